@@ -0,0 +1,82 @@
+//! Benchmarks culling bounding spheres and boxes against a camera frustum.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use game_engine::{
+    math::geometry::Frustum,
+    renderer::{Aabb, BoundingSphere, Camera},
+};
+use glam::Vec3;
+
+/// The half size of the cube the bounds are spread through, around the camera.
+const EXTENT: f32 = 200.0;
+
+/// Spreads the centers of `count` bounds evenly through a cube around the
+/// origin, so roughly a tenth of them are in view.
+fn centers(count: usize) -> Vec<Vec3> {
+    let side = (count as f32).cbrt().ceil() as usize;
+    let step = 2.0 * EXTENT / side as f32;
+    (0..count)
+        .map(|i| {
+            let (x, y, z) = (i % side, (i / side) % side, i / (side * side));
+            Vec3::new(x as f32, y as f32, z as f32) * step - Vec3::splat(EXTENT)
+        })
+        .collect()
+}
+
+fn frustum() -> Frustum {
+    let camera = Camera::new(Vec3::ZERO, 60.0, 16.0 / 9.0, 0.1, EXTENT);
+    Frustum::from_view_projection(camera.get_projection_matrix() * camera.get_view_matrix())
+}
+
+fn cull_spheres(c: &mut Criterion) {
+    let frustum = frustum();
+    let mut group = c.benchmark_group("culling/spheres");
+    for count in [10_000, 100_000, 1_000_000] {
+        let spheres: Vec<_> = centers(count)
+            .into_iter()
+            .map(|center| BoundingSphere::new(center, 1.0))
+            .collect();
+        let mut visible = Vec::with_capacity(count);
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &spheres,
+            |b, spheres| {
+                b.iter(|| {
+                    visible.clear();
+                    visible.extend(
+                        (0..spheres.len()).filter(|&i| frustum.intersects_sphere(&spheres[i])),
+                    );
+                    visible.len()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn cull_boxes(c: &mut Criterion) {
+    let frustum = frustum();
+    let mut group = c.benchmark_group("culling/boxes");
+    for count in [10_000, 100_000, 1_000_000] {
+        let boxes: Vec<_> = centers(count)
+            .into_iter()
+            .map(|center| Aabb::new(center - Vec3::ONE, center + Vec3::ONE))
+            .collect();
+        let mut visible = Vec::with_capacity(count);
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &boxes, |b, boxes| {
+            b.iter(|| {
+                visible.clear();
+                visible.extend((0..boxes.len()).filter(|&i| frustum.intersects_aabb(&boxes[i])));
+                visible.len()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cull_spheres, cull_boxes);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+//! Benchmarks generating a sphere of a million triangles and creating a mesh from it.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use game_engine::renderer::{
+    shape_builders::{shape_builder::ShapeBuilder, SphereBuilder},
+    Color, Mesh,
+};
+
+/// Rings and segments giving `2 * RINGS * SEGMENTS` = 1,000,000 triangles.
+const RINGS: u32 = 500;
+const SEGMENTS: u32 = 1_000;
+
+const TRIANGLES: u64 = 2 * RINGS as u64 * SEGMENTS as u64;
+
+fn generate_sphere(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mesh/sphere_1m_triangles");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(TRIANGLES));
+    group.bench_function("generate", |b| {
+        b.iter(|| SphereBuilder::new(1.0, RINGS, SEGMENTS, Color::WHITE).as_mesh())
+    });
+
+    let sphere = SphereBuilder::new(1.0, RINGS, SEGMENTS, Color::WHITE).as_mesh();
+    group.bench_function("create_mesh", |b| {
+        b.iter_batched(|| sphere.clone(), Mesh::new, BatchSize::LargeInput)
+    });
+    group.bench_function("create_mesh_with_bvh", |b| {
+        b.iter_batched(
+            || sphere.clone().with_bvh(true),
+            Mesh::new,
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, generate_sphere);
+criterion_main!(benches);
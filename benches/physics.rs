@@ -0,0 +1,41 @@
+//! Benchmarks integrating 100k rigid bodies.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use game_engine::physics::{rigid_body_system::RigidBodySystem, vector3::Vector3};
+
+const BODIES: usize = 100_000;
+
+const DT: f64 = 1.0 / 60.0;
+
+const GRAVITY: Vector3 = Vector3 {
+    x: 0.0,
+    y: -9.81,
+    z: 0.0,
+};
+
+/// Builds a system of bodies spread over a grid, pulled down by gravity.
+fn falling_bodies() -> RigidBodySystem {
+    let mut system = RigidBodySystem::with_capacity(BODIES);
+    for i in 0..BODIES {
+        let position = Vector3::new((i % 316) as f64, 10.0, (i / 316) as f64);
+        let velocity = Vector3::new(1.0, 0.0, (i % 7) as f64 * 0.1);
+        system.add(1.0 + (i % 3) as f64, position, velocity);
+    }
+    system.apply_force_to_all(GRAVITY);
+    system
+}
+
+fn integrate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("physics/step_100k_bodies");
+    group.throughput(Throughput::Elements(BODIES as u64));
+
+    let mut system = falling_bodies();
+    group.bench_function("verlet", |b| b.iter(|| system.update_verlet(DT)));
+
+    let mut system = falling_bodies();
+    group.bench_function("rk4", |b| b.iter(|| system.update_rk4(DT, |_, _| GRAVITY)));
+    group.finish();
+}
+
+criterion_group!(benches, integrate);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+//! Benchmarks building the render queue and sorting its draws into batches.
+
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use game_engine::renderer::{Color, DrawCommandBuilder, InstanceData, MaterialId, RenderQueue};
+use glam::{Mat4, Vec3};
+
+/// The number of distinct meshes and materials the draws are spread over.
+const MESHES: usize = 64;
+const MATERIALS: usize = 16;
+
+/// Records `count` draws in an order that interleaves meshes and materials,
+/// as a scene graph traversal would.
+fn fill_queue(queue: &mut RenderQueue, count: usize, instances: &[InstanceData]) {
+    for i in 0..count {
+        let mut command = DrawCommandBuilder::new_mesh((i * 7) % MESHES)
+            .with_material(MaterialId((i * 5) % MATERIALS))
+            .with_transform(Mat4::from_translation(Vec3::new(i as f32, 0.0, 0.0)));
+        if i % 16 == 0 {
+            command = command.with_instances(instances);
+        }
+        queue.add_draw_command(command);
+    }
+}
+
+fn instances() -> Vec<InstanceData> {
+    (0..32)
+        .map(|i| {
+            InstanceData::new(
+                Mat4::from_translation(Vec3::new(0.0, i as f32, 0.0)),
+                Color::new(1.0, 1.0, 1.0, 1.0),
+            )
+        })
+        .collect()
+}
+
+fn build(c: &mut Criterion) {
+    let instances = instances();
+    let mut group = c.benchmark_group("render_queue/build");
+    for count in [1_000, 10_000, 100_000] {
+        // Warms the queue up, so the benchmark measures the steady state of a
+        // queue reused between frames
+        let mut queue = RenderQueue::new();
+        fill_queue(&mut queue, count, &instances);
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| {
+                queue.clear();
+                fill_queue(&mut queue, count, &instances);
+                black_box(queue.len())
+            })
+        });
+    }
+    group.finish();
+}
+
+fn sort(c: &mut Criterion) {
+    let instances = instances();
+    let mut group = c.benchmark_group("render_queue/sort_batches");
+    for count in [1_000, 10_000, 100_000] {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter_batched_ref(
+                || {
+                    let mut queue = RenderQueue::new();
+                    fill_queue(&mut queue, count, &instances);
+                    queue
+                },
+                |queue| queue.sort_batches(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, build, sort);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+//! Benchmarks updating the world transforms of scene graphs.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use game_engine::renderer::{NodeId, SceneGraph};
+use glam::{Mat4, Quat, Vec3};
+
+/// The number of children of every interior node.
+const BRANCHING: usize = 8;
+
+/// Builds a scene of `count` nodes, each node's parent added before it so the
+/// tree is `BRANCHING` wide at every level.
+fn tree_scene(count: usize) -> SceneGraph {
+    let mut scene = SceneGraph::new();
+    let mut nodes: Vec<NodeId> = Vec::with_capacity(count);
+    for i in 0..count {
+        let parent = i.checked_sub(1).map(|i| nodes[i / BRANCHING]);
+        let transform = Mat4::from_scale_rotation_translation(
+            Vec3::ONE,
+            Quat::from_rotation_y(i as f32 * 0.01),
+            Vec3::new(i as f32 % 7.0, 1.0, 0.5),
+        );
+        nodes.push(scene.add_node(parent, transform).unwrap());
+    }
+    scene
+}
+
+fn update_world_transforms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scene_graph/update_world_transforms");
+    for count in [1_000, 10_000, 100_000] {
+        let mut scene = tree_scene(count);
+        // Sorts the nodes by depth once, as the first frame would
+        scene.update_world_transforms();
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| scene.update_world_transforms())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, update_world_transforms);
+criterion_main!(benches);
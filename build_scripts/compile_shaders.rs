@@ -1,9 +1,21 @@
 use std::path::Path;
 use std::process::Command;
 
+/// Returns the SDK the shaders are compiled against for the target platform.
+fn metal_sdk() -> &'static str {
+    let target = std::env::var("TARGET").unwrap_or_default();
+    match std::env::var("CARGO_CFG_TARGET_OS").as_deref() {
+        Ok("ios") if target.ends_with("-sim") || target.starts_with("x86_64") => "iphonesimulator",
+        Ok("ios") => "iphoneos",
+        _ => "macosx",
+    }
+}
+
 pub fn compile_metal_shaders() {
     println!("cargo:rerun-if-changed=src/metal_shaders");
 
+    let sdk = metal_sdk();
+
     let out_dir = std::env::var("OUT_DIR").unwrap();
     let shader_dir = Path::new("src/metal_shaders");
 
@@ -19,9 +31,11 @@ pub fn compile_metal_shaders() {
             let status = Command::new("xcrun")
                 .args([
                     "-sdk",
-                    "macosx",
+                    sdk,
                     "metal",
                     "-c",
+                    // Honors [[invariant]] positions, see VertexOut
+                    "-fpreserve-invariance",
                     path.to_str().unwrap(),
                     "-o",
                     &air_file,
@@ -38,7 +52,7 @@ pub fn compile_metal_shaders() {
     // Combine .air files into a single .metallib file
     let metallib_file = format!("{out_dir}/shaders.metallib");
     let mut command = Command::new("xcrun");
-    command.args(["-sdk", "macosx", "metallib"]);
+    command.args(["-sdk", sdk, "metallib"]);
 
     for entry in std::fs::read_dir(&out_dir).unwrap() {
         let entry = entry.unwrap();
@@ -0,0 +1,86 @@
+//! Clip module for audio.
+//!
+//! This module provides `AudioClip`, decoded audio held in memory. Clips are
+//! cheap to clone, so many sources can play the same clip at once.
+
+use super::error::AudioError;
+use std::{path::Path, sync::Arc, time::Duration};
+
+/// Decoded audio as interleaved samples from -1 to 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioClip {
+    samples: Arc<[f32]>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl AudioClip {
+    /// Creates a clip from interleaved samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` or `sample_rate` is zero.
+    pub fn from_samples(samples: impl Into<Arc<[f32]>>, channels: u16, sample_rate: u32) -> Self {
+        assert!(channels > 0, "An audio clip needs at least one channel");
+        assert!(sample_rate > 0, "An audio clip needs a sample rate");
+        Self {
+            samples: samples.into(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Loads and decodes a WAV or Ogg Vorbis file.
+    ///
+    /// # Returns
+    ///
+    /// The clip, or an error if the file cannot be read or decoded, or the
+    /// `audio` feature is not compiled in.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AudioError> {
+        let path = path.as_ref();
+        #[cfg(feature = "audio")]
+        {
+            use rodio::{Decoder, Source};
+            use std::{fs::File, io::BufReader};
+
+            let file = File::open(path).map_err(|source| AudioError::Io {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            let decoder =
+                Decoder::new(BufReader::new(file)).map_err(|error| AudioError::Decode {
+                    path: path.to_path_buf(),
+                    message: error.to_string(),
+                })?;
+            let (channels, sample_rate) = (decoder.channels(), decoder.sample_rate());
+            let samples: Vec<f32> = decoder.convert_samples().collect();
+            Ok(Self::from_samples(samples, channels, sample_rate))
+        }
+        #[cfg(not(feature = "audio"))]
+        {
+            let _ = path;
+            Err(AudioError::Unsupported)
+        }
+    }
+
+    /// Returns the interleaved samples.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// Returns the number of interleaved channels.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the number of sample frames per second.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns how long the clip plays at normal speed.
+    pub fn duration(&self) -> Duration {
+        let frames = self.samples.len() / self.channels as usize;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+}
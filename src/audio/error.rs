@@ -0,0 +1,48 @@
+//! Error module for audio.
+//!
+//! `AudioError` reports clips that cannot be loaded, output devices that
+//! cannot be opened and handles to sources that no longer exist. Errors
+//! caused by an I/O error expose it through `Error::source`.
+
+use super::source::SourceId;
+use std::{error::Error, fmt, io, path::PathBuf};
+
+/// Errors raised while loading and playing audio.
+#[derive(Debug)]
+pub enum AudioError {
+    /// Reading an audio file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// An audio file could not be decoded.
+    Decode { path: PathBuf, message: String },
+    /// Audio output needs the `audio` feature, which is not compiled in.
+    Unsupported,
+    /// No output device is available, or it could not be opened.
+    OutputFailed(String),
+    /// The source does not exist or was removed.
+    InvalidSource(SourceId),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::Io { path, .. } => write!(f, "Failed to read audio {}", path.display()),
+            AudioError::Decode { path, message } => {
+                write!(f, "Failed to decode audio {}: {message}", path.display())
+            }
+            AudioError::Unsupported => {
+                write!(f, "Audio output requires the `audio` feature")
+            }
+            AudioError::OutputFailed(msg) => write!(f, "Failed to open audio output: {msg}"),
+            AudioError::InvalidSource(id) => write!(f, "Audio source {} does not exist", id.0),
+        }
+    }
+}
+
+impl Error for AudioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AudioError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
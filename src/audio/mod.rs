@@ -0,0 +1,31 @@
+//! Audio Module
+//!
+//! This module provides audio playback with 3D positional sources. Sources
+//! play decoded clips from scene nodes or fixed positions, attenuated by
+//! distance, panned by direction and pitch-shifted by the doppler effect,
+//! as heard by a listener that follows the camera. Playback on an output
+//! device needs the `audio` feature; without it, sources are still tracked
+//! and spatialized.
+//!
+//! Key Components:
+//!
+//! - `clip`: Holds decoded audio in memory, shared by the sources playing it.
+//! - `error`: Defines the errors raised while loading and playing audio.
+//! - `output`: Plays sources on the default output device, with the `audio` feature.
+//! - `source`: Describes a clip played from a node, a position or the listener.
+//! - `spatial`: Computes the gain, pan and pitch of a source relative to the listener.
+//! - `system`: Owns the sources and spatializes them every frame.
+
+mod clip;
+mod error;
+#[cfg(feature = "audio")]
+mod output;
+mod source;
+mod spatial;
+mod system;
+
+pub use clip::AudioClip;
+pub use error::AudioError;
+pub use source::{AudioSource, Emitter, SourceId};
+pub use spatial::{Attenuation, Doppler, Listener, SpatialParams};
+pub use system::AudioSystem;
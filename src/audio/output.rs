@@ -0,0 +1,144 @@
+//! Output module for audio.
+//!
+//! This module plays sources on the default output device through rodio.
+//! Every playing source has a `Voice`, whose gain, stereo pan and speed
+//! follow the spatialization the `AudioSystem` computes each frame. Spatial
+//! sources are mixed down to mono before they are panned.
+
+use super::{clip::AudioClip, error::AudioError, source::Emitter, spatial::SpatialParams};
+use rodio::{source::ChannelVolume, OutputStream, OutputStreamHandle, Sink, Source};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// How often a playing voice picks up new channel gains.
+const GAIN_UPDATE_PERIOD: Duration = Duration::from_millis(5);
+
+/// The default output device.
+pub struct AudioOutput {
+    // Dropping the stream stops all playback
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+}
+
+impl AudioOutput {
+    /// Opens the default output device.
+    pub fn open() -> Result<Self, AudioError> {
+        let (stream, handle) =
+            OutputStream::try_default().map_err(|e| AudioError::OutputFailed(e.to_string()))?;
+        Ok(Self {
+            _stream: stream,
+            handle,
+        })
+    }
+
+    /// Starts playing a clip.
+    ///
+    /// # Arguments
+    ///
+    /// * `clip` - The clip to play.
+    /// * `emitter` - Where the clip is heard from, which decides whether it is panned.
+    /// * `looping` - Whether the clip restarts when it ends.
+    pub fn play(
+        &self,
+        clip: &AudioClip,
+        emitter: Emitter,
+        looping: bool,
+    ) -> Result<Voice, AudioError> {
+        let sink =
+            Sink::try_new(&self.handle).map_err(|e| AudioError::OutputFailed(e.to_string()))?;
+        let source = ClipSource {
+            clip: clip.clone(),
+            position: 0,
+            looping,
+        };
+        let gains = Arc::new(Mutex::new([0.0; 2]));
+        let spatial = emitter != Emitter::Listener;
+        if spatial {
+            let shared = Arc::clone(&gains);
+            let panned = ChannelVolume::new(source, vec![0.0; 2]).periodic_access(
+                GAIN_UPDATE_PERIOD,
+                move |panned| {
+                    let [left, right] = *shared.lock().unwrap();
+                    panned.set_volume(0, left);
+                    panned.set_volume(1, right);
+                },
+            );
+            sink.append(panned);
+        } else {
+            sink.append(source);
+        }
+        Ok(Voice {
+            sink,
+            gains,
+            spatial,
+        })
+    }
+}
+
+/// A clip playing on the output device.
+pub struct Voice {
+    sink: Sink,
+    /// The gains of the left and right channels of a spatial voice.
+    gains: Arc<Mutex<[f32; 2]>>,
+    spatial: bool,
+}
+
+impl Voice {
+    /// Applies the spatialization of the voice's source.
+    pub fn apply(&self, params: &SpatialParams) {
+        if self.spatial {
+            *self.gains.lock().unwrap() = params.channel_gains();
+        } else {
+            self.sink.set_volume(params.gain);
+        }
+        self.sink.set_speed(params.pitch);
+    }
+
+    /// Returns `true` once the clip has played to its end.
+    pub fn is_finished(&self) -> bool {
+        self.sink.empty()
+    }
+}
+
+/// Plays a clip from memory.
+struct ClipSource {
+    clip: AudioClip,
+    position: usize,
+    looping: bool,
+}
+
+impl Iterator for ClipSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let samples = self.clip.samples();
+        if self.position >= samples.len() {
+            if !self.looping || samples.is_empty() {
+                return None;
+            }
+            self.position = 0;
+        }
+        self.position += 1;
+        Some(samples[self.position - 1])
+    }
+}
+
+impl Source for ClipSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.clip.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.clip.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        (!self.looping).then(|| self.clip.duration())
+    }
+}
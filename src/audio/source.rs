@@ -0,0 +1,88 @@
+//! Source module for audio.
+//!
+//! This module provides `AudioSource`, a clip played from a scene node, a
+//! fixed world position or the listener itself, with its own volume, pitch,
+//! looping and distance attenuation.
+
+use super::{clip::AudioClip, spatial::Attenuation};
+use crate::renderer::NodeId;
+use glam::Vec3;
+
+/// Where a source is heard from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emitter {
+    /// The origin of a scene node, following it as it moves.
+    Node(NodeId),
+    /// A fixed world-space position.
+    Position(Vec3),
+    /// The listener, for music and interface sounds that are not spatialized.
+    Listener,
+}
+
+impl From<NodeId> for Emitter {
+    fn from(id: NodeId) -> Self {
+        Emitter::Node(id)
+    }
+}
+
+impl From<Vec3> for Emitter {
+    fn from(position: Vec3) -> Self {
+        Emitter::Position(position)
+    }
+}
+
+/// Identifies a source added to an `AudioSystem`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SourceId(pub usize);
+
+/// A clip played in the scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioSource {
+    pub emitter: Emitter,
+    pub clip: AudioClip,
+    /// The gain before attenuation, from 0.
+    pub volume: f32,
+    /// The playback speed before the doppler shift.
+    pub pitch: f32,
+    /// Whether the clip restarts when it ends.
+    pub looping: bool,
+    pub attenuation: Attenuation,
+}
+
+impl AudioSource {
+    /// Creates a new `AudioSource` at full volume and normal speed, played once.
+    pub fn new(emitter: impl Into<Emitter>, clip: AudioClip) -> Self {
+        Self {
+            emitter: emitter.into(),
+            clip,
+            volume: 1.0,
+            pitch: 1.0,
+            looping: false,
+            attenuation: Attenuation::default(),
+        }
+    }
+
+    /// Sets the gain before attenuation.
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    /// Sets the playback speed before the doppler shift.
+    pub fn with_pitch(mut self, pitch: f32) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    /// Sets whether the clip restarts when it ends.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Sets how the gain falls off with distance from the listener.
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+}
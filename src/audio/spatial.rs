@@ -0,0 +1,258 @@
+//! Spatialization module for audio.
+//!
+//! This module turns the positions and velocities of a source and the
+//! listener into the gain, stereo pan and pitch the source plays with.
+//! Gain falls off with distance by an `Attenuation` model, the pan follows
+//! the source's direction in the listener's view space, and the pitch is
+//! shifted by the doppler effect of their relative motion.
+
+use glam::{Mat4, Vec3};
+use std::f32::consts::FRAC_PI_4;
+
+/// How a source's gain falls off with its distance from the listener.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attenuation {
+    /// The gain does not depend on distance.
+    None,
+    /// The gain falls linearly from 1 at the reference distance to 0 at the maximum distance.
+    Linear {
+        reference_distance: f32,
+        max_distance: f32,
+    },
+    /// The gain is inversely proportional to the distance beyond the
+    /// reference distance, and constant beyond the maximum distance.
+    Inverse {
+        reference_distance: f32,
+        max_distance: f32,
+        /// How quickly the gain falls, with 1 being physically accurate.
+        rolloff: f32,
+    },
+}
+
+impl Default for Attenuation {
+    /// Physically accurate falloff beyond 1 unit, down to 1% at 100 units.
+    fn default() -> Self {
+        Attenuation::Inverse {
+            reference_distance: 1.0,
+            max_distance: 100.0,
+            rolloff: 1.0,
+        }
+    }
+}
+
+impl Attenuation {
+    /// Returns the gain at a distance, from 0 to 1.
+    pub fn gain(&self, distance: f32) -> f32 {
+        match *self {
+            Attenuation::None => 1.0,
+            Attenuation::Linear {
+                reference_distance,
+                max_distance,
+            } => {
+                let range = max_distance - reference_distance;
+                if range <= 0.0 {
+                    return (distance <= reference_distance) as u32 as f32;
+                }
+                1.0 - ((distance - reference_distance) / range).clamp(0.0, 1.0)
+            }
+            Attenuation::Inverse {
+                reference_distance,
+                max_distance,
+                rolloff,
+            } => {
+                let distance =
+                    distance.clamp(reference_distance, max_distance.max(reference_distance));
+                let falloff = reference_distance + rolloff * (distance - reference_distance);
+                if falloff <= 0.0 {
+                    return 1.0;
+                }
+                (reference_distance / falloff).min(1.0)
+            }
+        }
+    }
+}
+
+/// How strongly relative motion shifts the pitch of sources.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Doppler {
+    /// The speed of sound in world units per second.
+    pub speed_of_sound: f32,
+    /// The factor applied to velocities, with 0 disabling the effect.
+    pub factor: f32,
+}
+
+impl Default for Doppler {
+    /// The speed of sound in air, with one world unit being a meter.
+    fn default() -> Self {
+        Self {
+            speed_of_sound: 343.0,
+            factor: 1.0,
+        }
+    }
+}
+
+impl Doppler {
+    /// Returns the factor the pitch of a source is shifted by.
+    ///
+    /// Speeds towards the other are clamped below the speed of sound, so
+    /// the shift stays finite.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The position and velocity of the source.
+    /// * `listener` - The position and velocity of the listener.
+    pub fn pitch(&self, source: (Vec3, Vec3), listener: (Vec3, Vec3)) -> f32 {
+        let direction = (listener.0 - source.0).normalize_or_zero();
+        if self.factor == 0.0 || direction == Vec3::ZERO {
+            return 1.0;
+        }
+        // The speeds of both along the line from the source to the listener
+        let limit = self.speed_of_sound * 0.99;
+        let source_speed = (source.1.dot(direction) * self.factor).min(limit);
+        let listener_speed = (listener.1.dot(direction) * self.factor).min(limit);
+        (self.speed_of_sound - listener_speed) / (self.speed_of_sound - source_speed)
+    }
+}
+
+/// The position, orientation and velocity sources are heard from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Listener {
+    /// The world to listener matrix, which is the view matrix of the listening camera.
+    pub view: Mat4,
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+/// How a source plays after spatialization.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpatialParams {
+    /// The gain, including the source's volume.
+    pub gain: f32,
+    /// The stereo position, from -1 for left to 1 for right.
+    pub pan: f32,
+    /// The playback speed, including the source's pitch.
+    pub pitch: f32,
+}
+
+impl Default for SpatialParams {
+    /// Unchanged playback, centered between the speakers.
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            pan: 0.0,
+            pitch: 1.0,
+        }
+    }
+}
+
+impl SpatialParams {
+    /// Returns the gains of the left and right channels at equal total power.
+    pub fn channel_gains(&self) -> [f32; 2] {
+        let angle = (self.pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+        [angle.cos() * self.gain, angle.sin() * self.gain]
+    }
+}
+
+/// Spatializes a source relative to the listener.
+///
+/// # Arguments
+///
+/// * `listener` - The listener.
+/// * `position` - The world-space position of the source.
+/// * `velocity` - The world-space velocity of the source.
+/// * `attenuation` - How the source's gain falls off with distance.
+/// * `doppler` - How relative motion shifts the source's pitch.
+pub fn spatialize(
+    listener: &Listener,
+    position: Vec3,
+    velocity: Vec3,
+    attenuation: &Attenuation,
+    doppler: &Doppler,
+) -> SpatialParams {
+    // The view space x axis points to the listener's right
+    let relative = listener.view.transform_point3(position);
+    let distance = relative.length();
+    let pan = if distance > 0.0 {
+        relative.x / distance
+    } else {
+        0.0
+    };
+    SpatialParams {
+        gain: attenuation.gain(distance),
+        pan,
+        pitch: doppler.pitch((position, velocity), (listener.position, listener.velocity)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spatialize, Attenuation, Doppler, Listener, SpatialParams};
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_attenuation() {
+        let inverse = Attenuation::default();
+        assert_eq!(inverse.gain(0.5), 1.0);
+        assert_eq!(inverse.gain(4.0), 0.25);
+        assert_eq!(inverse.gain(1000.0), 0.01);
+        let linear = Attenuation::Linear {
+            reference_distance: 2.0,
+            max_distance: 6.0,
+        };
+        assert_eq!(linear.gain(1.0), 1.0);
+        assert_eq!(linear.gain(4.0), 0.5);
+        assert_eq!(linear.gain(8.0), 0.0);
+        assert_eq!(Attenuation::None.gain(1000.0), 1.0);
+    }
+
+    #[test]
+    fn test_spatialize_pan_and_doppler() {
+        let listener = Listener {
+            view: Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y),
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+        };
+        let doppler = Doppler::default();
+        let right = spatialize(
+            &listener,
+            Vec3::X * 2.0,
+            Vec3::ZERO,
+            &Attenuation::None,
+            &doppler,
+        );
+        assert_eq!(right.pan, 1.0);
+        assert_eq!(right.pitch, 1.0);
+        let [left_gain, right_gain] = right.channel_gains();
+        assert!(left_gain.abs() < 1e-6 && (right_gain - 1.0).abs() < 1e-6);
+        let centered = SpatialParams::default().channel_gains();
+        assert!((centered[0] * centered[0] + centered[1] * centered[1] - 1.0).abs() < 1e-6);
+
+        // A source approaching at a tenth of the speed of sound plays higher,
+        // and lower while receding
+        let ahead = Vec3::NEG_Z * 10.0;
+        let approaching = spatialize(
+            &listener,
+            ahead,
+            Vec3::Z * 34.3,
+            &Attenuation::None,
+            &doppler,
+        );
+        assert!((approaching.pitch - 1.0 / 0.9).abs() < 1e-5);
+        let receding = spatialize(
+            &listener,
+            ahead,
+            Vec3::NEG_Z * 34.3,
+            &Attenuation::None,
+            &doppler,
+        );
+        assert!((receding.pitch - 1.0 / 1.1).abs() < 1e-5);
+        let disabled = Doppler {
+            factor: 0.0,
+            ..doppler
+        };
+        assert_eq!(
+            disabled.pitch((ahead, Vec3::Z * 34.3), (Vec3::ZERO, Vec3::ZERO)),
+            1.0
+        );
+    }
+}
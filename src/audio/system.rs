@@ -0,0 +1,322 @@
+//! Audio system module.
+//!
+//! This module provides `AudioSystem`, which owns the audio sources and
+//! spatializes them every frame. The listener follows the camera, and
+//! sources attached to scene nodes follow the nodes' world transforms, so
+//! `update` is called once per frame after the scene graph is updated.
+//! Without an output device the system still tracks and spatializes its
+//! sources, which keeps games and tests running where no audio is available.
+
+#[cfg(feature = "audio")]
+use super::output::{AudioOutput, Voice};
+use super::{
+    error::AudioError,
+    source::{AudioSource, Emitter, SourceId},
+    spatial::{spatialize, Doppler, Listener, SpatialParams},
+};
+use crate::renderer::{Camera, SceneGraph};
+use glam::Vec3;
+use log::warn;
+
+/// A source and its playback state.
+struct SourceSlot {
+    source: AudioSource,
+    /// The world-space position of the last update, for the source's velocity.
+    position: Option<Vec3>,
+    params: SpatialParams,
+    playing: bool,
+    /// Seconds of the clip played, which ends sources played without a device.
+    played: f32,
+    #[cfg(feature = "audio")]
+    voice: Option<Voice>,
+}
+
+/// Plays and spatializes audio sources.
+pub struct AudioSystem {
+    sources: Vec<Option<SourceSlot>>,
+    listener: Option<Listener>,
+    /// How strongly relative motion shifts the pitch of sources.
+    pub doppler: Doppler,
+    /// The gain applied to every source.
+    pub master_volume: f32,
+    #[cfg(feature = "audio")]
+    output: Option<AudioOutput>,
+}
+
+impl Default for AudioSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioSystem {
+    /// Creates a new `AudioSystem` without an output device.
+    ///
+    /// Sources are spatialized but not heard. Use `with_output` to play them.
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            listener: None,
+            doppler: Doppler::default(),
+            master_volume: 1.0,
+            #[cfg(feature = "audio")]
+            output: None,
+        }
+    }
+
+    /// Creates a new `AudioSystem` playing on the default output device.
+    ///
+    /// # Returns
+    ///
+    /// The system, or an error if no device can be opened or the `audio`
+    /// feature is not compiled in.
+    pub fn with_output() -> Result<Self, AudioError> {
+        #[cfg(feature = "audio")]
+        {
+            let output = AudioOutput::open()?;
+            log::debug!("Opened audio output");
+            Ok(Self {
+                output: Some(output),
+                ..Self::new()
+            })
+        }
+        #[cfg(not(feature = "audio"))]
+        Err(AudioError::Unsupported)
+    }
+
+    /// Adds a stopped source and returns its handle.
+    pub fn add_source(&mut self, source: AudioSource) -> SourceId {
+        self.sources.push(Some(SourceSlot {
+            source,
+            position: None,
+            params: SpatialParams::default(),
+            playing: false,
+            played: 0.0,
+            #[cfg(feature = "audio")]
+            voice: None,
+        }));
+        SourceId(self.sources.len() - 1)
+    }
+
+    /// Removes a source, stopping it, and returns `true` if it existed.
+    pub fn remove_source(&mut self, id: SourceId) -> bool {
+        self.sources
+            .get_mut(id.0)
+            .is_some_and(|slot| slot.take().is_some())
+    }
+
+    /// Returns a source for modification.
+    ///
+    /// Changes to the volume, pitch and attenuation apply from the next
+    /// update. Changes to the clip or looping apply when it is next played.
+    pub fn source_mut(&mut self, id: SourceId) -> Option<&mut AudioSource> {
+        self.slot_mut(id).ok().map(|slot| &mut slot.source)
+    }
+
+    /// Plays a source from the start, restarting it if it is playing.
+    pub fn play(&mut self, id: SourceId) -> Result<(), AudioError> {
+        #[cfg(feature = "audio")]
+        let output = self.output.as_ref();
+        let slot = Self::slot(&mut self.sources, id)?;
+        #[cfg(feature = "audio")]
+        {
+            slot.voice = output
+                .map(|output| {
+                    output.play(&slot.source.clip, slot.source.emitter, slot.source.looping)
+                })
+                .transpose()?;
+            if let Some(voice) = &slot.voice {
+                voice.apply(&slot.params);
+            }
+        }
+        slot.playing = true;
+        slot.played = 0.0;
+        Ok(())
+    }
+
+    /// Stops a source.
+    pub fn stop(&mut self, id: SourceId) -> Result<(), AudioError> {
+        let slot = self.slot_mut(id)?;
+        slot.playing = false;
+        #[cfg(feature = "audio")]
+        {
+            slot.voice = None;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the source is playing.
+    pub fn is_playing(&self, id: SourceId) -> bool {
+        self.sources
+            .get(id.0)
+            .and_then(Option::as_ref)
+            .is_some_and(|slot| slot.playing)
+    }
+
+    /// Returns how a source played at the last update.
+    pub fn spatial_params(&self, id: SourceId) -> Option<SpatialParams> {
+        self.sources.get(id.0)?.as_ref().map(|slot| slot.params)
+    }
+
+    /// Returns the listener as of the last update.
+    pub fn listener(&self) -> Option<&Listener> {
+        self.listener.as_ref()
+    }
+
+    /// Moves the listener to the camera and spatializes every source.
+    ///
+    /// Sources attached to a node that no longer exists are stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta_time` - The seconds since the last update, for velocities.
+    /// * `camera` - The camera the scene is heard from.
+    /// * `scene_graph` - The scene, with world transforms up to date.
+    pub fn update(&mut self, delta_time: f32, camera: &Camera, scene_graph: &SceneGraph) {
+        let velocity = |previous: Option<Vec3>, position: Vec3| match previous {
+            Some(previous) if delta_time > 0.0 => (position - previous) / delta_time,
+            _ => Vec3::ZERO,
+        };
+        let position = camera.position();
+        let listener = Listener {
+            view: camera.get_view_matrix(),
+            position,
+            velocity: velocity(self.listener.map(|listener| listener.position), position),
+        };
+        self.listener = Some(listener);
+
+        for slot in self.sources.iter_mut().flatten() {
+            let source = &slot.source;
+            let position = match source.emitter {
+                Emitter::Node(id) => scene_graph
+                    .world_transform(id)
+                    .map(|world| world.w_axis.truncate()),
+                Emitter::Position(position) => Some(position),
+                Emitter::Listener => Some(listener.position),
+            };
+            let Some(position) = position else {
+                if slot.playing {
+                    warn!("Stopping audio source of removed node");
+                }
+                slot.playing = false;
+                slot.position = None;
+                #[cfg(feature = "audio")]
+                {
+                    slot.voice = None;
+                }
+                continue;
+            };
+
+            let mut params = match source.emitter {
+                Emitter::Listener => SpatialParams::default(),
+                _ => spatialize(
+                    &listener,
+                    position,
+                    velocity(slot.position, position),
+                    &source.attenuation,
+                    &self.doppler,
+                ),
+            };
+            params.gain *= source.volume * self.master_volume;
+            params.pitch *= source.pitch;
+            slot.params = params;
+            slot.position = Some(position);
+
+            if !slot.playing {
+                continue;
+            }
+            slot.played += delta_time * params.pitch;
+            #[cfg(feature = "audio")]
+            if let Some(voice) = &slot.voice {
+                voice.apply(&params);
+                slot.playing = !voice.is_finished();
+                continue;
+            }
+            if !source.looping && slot.played >= source.clip.duration().as_secs_f32() {
+                slot.playing = false;
+            }
+        }
+    }
+
+    /// Returns a source's slot for modification.
+    fn slot_mut(&mut self, id: SourceId) -> Result<&mut SourceSlot, AudioError> {
+        Self::slot(&mut self.sources, id)
+    }
+
+    /// Returns a slot of a source list, borrowing only the list.
+    fn slot(
+        sources: &mut [Option<SourceSlot>],
+        id: SourceId,
+    ) -> Result<&mut SourceSlot, AudioError> {
+        sources
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .ok_or(AudioError::InvalidSource(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AudioSystem;
+    use crate::audio::{
+        clip::AudioClip,
+        error::AudioError,
+        source::{AudioSource, Emitter},
+        spatial::Attenuation,
+    };
+    use crate::renderer::{Camera, SceneGraph};
+    use glam::{Mat4, Vec3};
+
+    /// One second of silence.
+    fn clip() -> AudioClip {
+        AudioClip::from_samples(vec![0.0; 100], 1, 100)
+    }
+
+    #[test]
+    fn test_sources_follow_nodes() {
+        let mut scene_graph = SceneGraph::default();
+        let node = scene_graph
+            .add_node(None, Mat4::from_translation(Vec3::new(2.0, 0.0, 3.0)))
+            .unwrap();
+        scene_graph.update_world_transforms();
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 3.0), 45.0, 1.0, 0.1, 100.0);
+
+        let mut audio = AudioSystem::new();
+        let source = audio.add_source(AudioSource::new(node, clip()).with_volume(0.5));
+        let music = audio.add_source(AudioSource::new(Emitter::Listener, clip()));
+        audio.play(source).unwrap();
+        audio.update(0.5, &camera, &scene_graph);
+
+        // Two units to the right of the listener
+        let params = audio.spatial_params(source).unwrap();
+        assert_eq!(params.pan, 1.0);
+        assert_eq!(params.gain, 0.25);
+        assert_eq!(audio.spatial_params(music).unwrap().pan, 0.0);
+
+        // Moving away lowers the pitch, and the clip ends after a second
+        scene_graph
+            .set_translation(node, Vec3::new(3.0, 0.0, 3.0))
+            .unwrap();
+        scene_graph.update_world_transforms();
+        audio.update(0.5, &camera, &scene_graph);
+        assert!(audio.spatial_params(source).unwrap().pitch < 1.0);
+        assert!(audio.is_playing(source));
+        audio.update(0.1, &camera, &scene_graph);
+        assert!(!audio.is_playing(source));
+
+        // Sources of removed nodes stop
+        audio.play(source).unwrap();
+        scene_graph.remove_node(node).unwrap();
+        audio.update(0.1, &camera, &scene_graph);
+        assert!(!audio.is_playing(source));
+        assert!(audio.remove_source(source));
+        assert!(matches!(
+            audio.play(source),
+            Err(AudioError::InvalidSource(_))
+        ));
+        assert!(audio
+            .source_mut(music)
+            .map(|music| music.attenuation = Attenuation::None)
+            .is_some());
+    }
+}
@@ -0,0 +1,34 @@
+//! Game Engine
+//!
+//! This crate provides the engine's rendering core, physics, audio and logging
+//! as a library, and the demo application in `main.rs` built on top of it.
+//!
+//! Features:
+//!
+//! - `windowing` (default): Provides `RendererSystem`, which opens a winit window and
+//!   runs the event loop, and the key bindings and input replays it drives. Without it,
+//!   the crate does not depend on winit, and applications hosting their own event loop
+//!   create a `Renderer` for any window with a raw window handle, or for an `NSView` or
+//!   `CAMetalLayer` of a native app.
+//! - `audio`: Plays audio sources through rodio.
+//! - `parallel`: Updates scene graph transforms, particles and fluids on a rayon thread pool.
+//! - `ffmpeg`: Records frames to video files through `ffmpeg`.
+//! - `usd`: Imports USDA layers and USDZ packages as scenes.
+
+pub mod audio;
+pub mod logging;
+pub mod math;
+pub mod physics;
+pub mod renderer;
+
+#[cfg(debug_assertions)]
+#[macro_export]
+macro_rules! debug_trace {
+    ($($arg:tt)*) => ( log::trace!($($arg)*) );
+}
+
+#[cfg(not(debug_assertions))]
+#[macro_export]
+macro_rules! debug_trace {
+    ($($arg:tt)*) => {};
+}
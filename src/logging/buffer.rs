@@ -0,0 +1,164 @@
+//! Buffer module for logging.
+//!
+//! This module provides `LogBuffer`, a ring buffer of the most recent log
+//! entries. The logger pushes to it from any thread, and the oldest entries
+//! are dropped once it is full.
+
+use super::channel::LogChannel;
+use log::Level;
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Mutex, MutexGuard},
+};
+
+/// The number of entries a buffer keeps by default.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// A log record kept in a `LogBuffer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub level: Level,
+    pub channel: LogChannel,
+    /// The frame the record was logged in, see `set_frame`.
+    pub frame: u32,
+    pub message: String,
+}
+
+/// Formats the entry as a console line, e.g. `[WARN asset] message`.
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{} {}] {}", self.level, self.channel, self.message)
+    }
+}
+
+/// Keeps the most recent log entries.
+pub struct LogBuffer {
+    entries: Mutex<VecDeque<LogEntry>>,
+    capacity: usize,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    /// Creates a new, empty `LogBuffer` keeping up to `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Returns the number of entries the buffer keeps.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries in the buffer.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if the buffer has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Adds an entry, dropping the oldest if the buffer is full.
+    pub fn push(&self, entry: LogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.lock();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Returns the most recent entries at or above a level, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The maximum number of entries to return.
+    /// * `level` - The least severe level to return, e.g. `Level::Warn` for warnings and errors.
+    pub fn recent(&self, count: usize, level: Level) -> Vec<LogEntry> {
+        let entries = self.lock();
+        let mut recent: Vec<_> = entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.level <= level)
+            .take(count)
+            .cloned()
+            .collect();
+        recent.reverse();
+        recent
+    }
+
+    /// Returns the entries logged in a frame, oldest first.
+    pub fn frame(&self, frame: u32) -> Vec<LogEntry> {
+        self.lock()
+            .iter()
+            .filter(|entry| entry.frame == frame)
+            .cloned()
+            .collect()
+    }
+
+    /// Removes every entry.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Locks the entries, recovering them if a thread panicked while logging.
+    fn lock(&self) -> MutexGuard<'_, VecDeque<LogEntry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogBuffer, LogEntry};
+    use crate::logging::channel::LogChannel;
+    use log::Level;
+
+    fn entry(level: Level, frame: u32, message: &str) -> LogEntry {
+        LogEntry {
+            level,
+            channel: LogChannel::Render,
+            frame,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_buffer_keeps_recent_entries() {
+        let buffer = LogBuffer::new(3);
+        buffer.push(entry(Level::Warn, 0, "first"));
+        buffer.push(entry(Level::Info, 0, "second"));
+        buffer.push(entry(Level::Error, 1, "third"));
+        buffer.push(entry(Level::Debug, 1, "fourth"));
+        assert_eq!(buffer.len(), 3);
+
+        let messages = |entries: Vec<LogEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.message).collect()
+        };
+        assert_eq!(
+            messages(buffer.recent(5, Level::Trace)),
+            ["second", "third", "fourth"]
+        );
+        assert_eq!(messages(buffer.recent(1, Level::Info)), ["third"]);
+        assert_eq!(messages(buffer.frame(1)), ["third", "fourth"]);
+        assert_eq!(
+            buffer.recent(1, Level::Warn)[0].to_string(),
+            "[ERROR render] third"
+        );
+        buffer.clear();
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+//! Channel module for logging.
+//!
+//! This module provides `LogChannel`, the subsystem a log record belongs to.
+//! Records are assigned by the module they were logged from, so existing
+//! `log` calls need no changes. A record logged with a channel's name as its
+//! target, e.g. `warn!(target: "asset", ...)`, belongs to that channel
+//! wherever it was logged from.
+
+use std::fmt;
+
+/// The modules of each channel, relative to the crate root, most specific first.
+const MODULE_CHANNELS: &[(&str, LogChannel)] = &[
+    ("renderer::scene_graph", LogChannel::Scene),
+    ("renderer::editor", LogChannel::Scene),
+    ("renderer::labels", LogChannel::Scene),
+    ("renderer::trail", LogChannel::Scene),
+    ("renderer::mesh", LogChannel::Asset),
+    ("renderer::mesh_optimizer", LogChannel::Asset),
+    ("renderer::vertex_layout", LogChannel::Asset),
+    ("renderer::shape_builders", LogChannel::Asset),
+    ("renderer::recording", LogChannel::Asset),
+    ("renderer", LogChannel::Render),
+    ("physics", LogChannel::Physics),
+    ("audio", LogChannel::Audio),
+];
+
+/// A subsystem of the engine that log records are filtered by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LogChannel {
+    /// The backend, render passes and renderer settings.
+    Render,
+    /// The scene graph, the editor and objects placed in the scene.
+    Scene,
+    /// Loading, building and writing meshes and other assets.
+    Asset,
+    Physics,
+    Audio,
+    /// Everything else, including the application.
+    General,
+}
+
+impl LogChannel {
+    /// Every channel, in the order of their filter levels.
+    pub const ALL: [LogChannel; 6] = [
+        LogChannel::Render,
+        LogChannel::Scene,
+        LogChannel::Asset,
+        LogChannel::Physics,
+        LogChannel::Audio,
+        LogChannel::General,
+    ];
+
+    /// Returns the channel's name, which is also its log target.
+    pub fn name(self) -> &'static str {
+        match self {
+            LogChannel::Render => "render",
+            LogChannel::Scene => "scene",
+            LogChannel::Asset => "asset",
+            LogChannel::Physics => "physics",
+            LogChannel::Audio => "audio",
+            LogChannel::General => "general",
+        }
+    }
+
+    /// Returns the channel's position in `ALL`.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Returns the channel of a record's target.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - A channel name, or the module path the record was logged from.
+    pub fn from_target(target: &str) -> Self {
+        if let Some(channel) = Self::ALL
+            .into_iter()
+            .find(|channel| channel.name() == target)
+        {
+            return channel;
+        }
+        // Module paths start with the crate's name
+        let Some((_, path)) = target.split_once("::") else {
+            return LogChannel::General;
+        };
+        MODULE_CHANNELS
+            .iter()
+            .find(|(module, _)| {
+                path.strip_prefix(module)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(LogChannel::General, |&(_, channel)| channel)
+    }
+}
+
+impl fmt::Display for LogChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogChannel;
+
+    #[test]
+    fn test_channel_from_target() {
+        let channel = LogChannel::from_target;
+        assert_eq!(
+            channel("game_engine::renderer::render_core"),
+            LogChannel::Render
+        );
+        assert_eq!(
+            channel("game_engine::renderer::backend::metal::pipeline"),
+            LogChannel::Render
+        );
+        assert_eq!(
+            channel("game_engine::renderer::scene_graph"),
+            LogChannel::Scene
+        );
+        assert_eq!(
+            channel("game_engine::renderer::mesh_optimizer"),
+            LogChannel::Asset
+        );
+        assert_eq!(
+            channel("game_engine::renderer::shape_builders::shape_builder"),
+            LogChannel::Asset
+        );
+        assert_eq!(channel("game_engine::audio::system"), LogChannel::Audio);
+        assert_eq!(channel("game_engine"), LogChannel::General);
+        assert_eq!(channel("winit::platform_impl"), LogChannel::General);
+
+        // Channel names are explicit targets
+        assert_eq!(channel("physics"), LogChannel::Physics);
+        for channel in LogChannel::ALL {
+            assert_eq!(LogChannel::ALL[channel.index()], channel);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Logger module for logging.
+//!
+//! This module provides `EngineLogger`, the `log` implementation installed by
+//! the application. It filters records by the level of their channel, writes
+//! them to stderr through `env_logger` and keeps them in a `LogBuffer`.
+//! Channel levels are atomics, so they can be changed from any thread while
+//! the engine runs.
+
+use super::{
+    buffer::{LogBuffer, LogEntry, DEFAULT_CAPACITY},
+    channel::LogChannel,
+};
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::sync::{
+    atomic::{AtomicU32, AtomicUsize, Ordering},
+    OnceLock,
+};
+
+/// The installed logger.
+static LOGGER: OnceLock<&'static EngineLogger> = OnceLock::new();
+
+/// The frame new entries are tagged with.
+static FRAME: AtomicU32 = AtomicU32::new(0);
+
+/// Returns the installed logger, or `None` if `EngineLogger::init` was not called.
+pub fn logger() -> Option<&'static EngineLogger> {
+    LOGGER.get().copied()
+}
+
+/// Sets the frame new log entries are tagged with.
+///
+/// The renderer calls this at the start of every frame.
+pub fn set_frame(frame: u32) {
+    FRAME.store(frame, Ordering::Relaxed);
+}
+
+/// Filters log records by channel, writing them to stderr and a ring buffer.
+pub struct EngineLogger {
+    /// The level of every channel as a `LevelFilter`, indexed by `LogChannel::index`.
+    levels: [AtomicUsize; LogChannel::ALL.len()],
+    buffer: LogBuffer,
+    stderr: Option<env_logger::Logger>,
+}
+
+impl EngineLogger {
+    /// Creates a new `EngineLogger` writing to stderr and keeping 256 entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of every channel.
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            levels: std::array::from_fn(|_| AtomicUsize::new(level as usize)),
+            buffer: LogBuffer::new(DEFAULT_CAPACITY),
+            stderr: Some(stderr_logger()),
+        }
+    }
+
+    /// Sets the number of entries the buffer keeps.
+    pub fn with_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer = LogBuffer::new(capacity);
+        self
+    }
+
+    /// Sets whether records are written to stderr, besides the buffer.
+    pub fn with_stderr(mut self, stderr: bool) -> Self {
+        self.stderr = stderr.then(stderr_logger);
+        self
+    }
+
+    /// Sets the level of a channel.
+    pub fn with_channel_level(self, channel: LogChannel, level: LevelFilter) -> Self {
+        self.set_channel_level(channel, level);
+        self
+    }
+
+    /// Installs the logger as the `log` crate's logger.
+    ///
+    /// # Returns
+    ///
+    /// The installed logger, or an error if a logger is already installed.
+    pub fn init(self) -> Result<&'static EngineLogger, SetLoggerError> {
+        let logger: &'static EngineLogger = Box::leak(Box::new(self));
+        log::set_logger(logger)?;
+        // `log` accepts a single logger, so this is the first
+        let _ = LOGGER.set(logger);
+        logger.update_max_level();
+        Ok(logger)
+    }
+
+    /// Sets the least severe level logged on a channel.
+    pub fn set_channel_level(&self, channel: LogChannel, level: LevelFilter) {
+        self.levels[channel.index()].store(level as usize, Ordering::Relaxed);
+        if logger().is_some_and(|logger| std::ptr::eq(logger, self)) {
+            self.update_max_level();
+        }
+    }
+
+    /// Sets the least severe level logged on every channel.
+    pub fn set_level(&self, level: LevelFilter) {
+        for channel in LogChannel::ALL {
+            self.set_channel_level(channel, level);
+        }
+    }
+
+    /// Returns the least severe level logged on a channel.
+    pub fn channel_level(&self, channel: LogChannel) -> LevelFilter {
+        let level = self.levels[channel.index()].load(Ordering::Relaxed);
+        LevelFilter::iter()
+            .find(|filter| *filter as usize == level)
+            .unwrap_or(LevelFilter::Off)
+    }
+
+    /// Returns the buffer of recent entries.
+    pub fn buffer(&self) -> &LogBuffer {
+        &self.buffer
+    }
+
+    /// Lets `log` skip formatting records below every channel's level.
+    fn update_max_level(&self) {
+        let level = LogChannel::ALL
+            .into_iter()
+            .map(|channel| self.channel_level(channel))
+            .max()
+            .unwrap_or(LevelFilter::Off);
+        log::set_max_level(level);
+    }
+}
+
+/// Creates the logger writing records to stderr, which leaves filtering to the channels.
+fn stderr_logger() -> env_logger::Logger {
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Trace)
+        .build()
+}
+
+impl Log for EngineLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.channel_level(LogChannel::from_target(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        let channel = LogChannel::from_target(record.target());
+        if record.level() > self.channel_level(channel) {
+            return;
+        }
+        if let Some(stderr) = &self.stderr {
+            stderr.log(record);
+        }
+        self.buffer.push(LogEntry {
+            level: record.level(),
+            channel,
+            frame: FRAME.load(Ordering::Relaxed),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {
+        if let Some(stderr) = &self.stderr {
+            stderr.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EngineLogger;
+    use crate::logging::channel::LogChannel;
+    use log::{Level, LevelFilter, Log, Record};
+
+    fn log(logger: &EngineLogger, level: Level, target: &str, message: &str) {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{message}"))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_logger_filters_channels() {
+        let logger = EngineLogger::new(LevelFilter::Info)
+            .with_stderr(false)
+            .with_buffer_capacity(8)
+            .with_channel_level(LogChannel::Physics, LevelFilter::Error);
+        log(
+            &logger,
+            Level::Info,
+            "game_engine::renderer::camera",
+            "camera",
+        );
+        log(
+            &logger,
+            Level::Debug,
+            "game_engine::renderer::camera",
+            "hidden",
+        );
+        log(
+            &logger,
+            Level::Warn,
+            "game_engine::physics::physics_world",
+            "hidden",
+        );
+        log(&logger, Level::Error, "physics", "physics");
+
+        let entries = logger.buffer().recent(8, Level::Trace);
+        let messages: Vec<_> = entries.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!(messages, ["camera", "physics"]);
+        assert_eq!(entries[0].channel, LogChannel::Render);
+
+        // Levels change at runtime
+        logger.set_level(LevelFilter::Trace);
+        assert_eq!(
+            logger.channel_level(LogChannel::Physics),
+            LevelFilter::Trace
+        );
+        log(
+            &logger,
+            Level::Trace,
+            "game_engine::physics::physics_world",
+            "trace",
+        );
+        assert_eq!(logger.buffer().len(), 3);
+        logger.set_channel_level(LogChannel::General, LevelFilter::Off);
+        assert!(!logger.enabled(&log::Metadata::builder().target("game_engine").build()));
+    }
+}
@@ -0,0 +1,22 @@
+//! Logging Module
+//!
+//! This module provides the engine's logger. Every `log` record belongs to a
+//! channel, such as rendering or physics, chosen by the module it was logged
+//! from or by an explicit `target`, and each channel has a level that can be
+//! changed while the engine runs. Records that pass are written to stderr and
+//! kept in an in-memory ring buffer, tagged with the frame they were logged
+//! in, which the renderer's log console draws over the scene.
+//!
+//! Key Components:
+//!
+//! - `buffer`: Keeps the most recent log entries for in-app display.
+//! - `channel`: Defines the engine's log channels and maps records to them.
+//! - `logger`: Implements the logger that filters records by channel and feeds the buffer.
+
+mod buffer;
+mod channel;
+mod logger;
+
+pub use buffer::{LogBuffer, LogEntry};
+pub use channel::LogChannel;
+pub use logger::{logger, set_frame, EngineLogger};
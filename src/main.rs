@@ -1,23 +1,12 @@
-use env_logger::Builder;
+use game_engine::{
+    logging::EngineLogger,
+    renderer::{
+        shape_builders::shape_builder::ShapeBuilder, Color, RendererConfig, RendererSystem,
+    },
+};
 use glam::{Mat4, Quat, Vec3};
 use log::LevelFilter;
-use renderer::{shape_builders::shape_builder::ShapeBuilder, Color, RendererSystem};
-use std::time::Instant;
-
-mod physics;
-mod renderer;
-
-#[cfg(debug_assertions)]
-#[macro_export]
-macro_rules! debug_trace {
-    ($($arg:tt)*) => ( log::trace!($($arg)*) );
-}
-
-#[cfg(not(debug_assertions))]
-#[macro_export]
-macro_rules! debug_trace {
-    ($($arg:tt)*) => {};
-}
+use std::path::Path;
 
 // fn create_infinite_ground(size: f32, divisions: u32) -> Vec<(Vec3, Color)> {
 //     let mut vertices = Vec::new();
@@ -37,10 +26,26 @@ macro_rules! debug_trace {
 // }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    Builder::new().filter_level(LevelFilter::Debug).init();
-
-    let mut renderer_system = RendererSystem::new(800, 600, "Metal Renderer")?;
-    let start_time = Instant::now();
+    EngineLogger::new(LevelFilter::Debug).init()?;
+
+    // Deployments tune the engine through engine.toml, when it exists
+    let config_path = Path::new("engine.toml");
+    let config = if config_path.exists() {
+        RendererConfig::from_file(config_path)?
+    } else {
+        RendererConfig::default()
+    };
+    let mut renderer_system = RendererSystem::builder().with_config(config).build()?;
+
+    // Record or play back a replay with --record-replay <path> or --play-replay <path>
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, path] = args.as_slice() {
+        match flag.as_str() {
+            "--record-replay" => renderer_system.record_replay(path),
+            "--play-replay" => renderer_system.play_replay(path)?,
+            _ => return Err(format!("Unknown argument: {flag}").into()),
+        }
+    }
 
     // Create the infinite ground
     // let ground_size = 1000.0;
@@ -48,7 +53,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // let ground_vertices = create_infinite_ground(ground_size, ground_divisions);
 
     renderer_system.set_render_callback(move |r| {
-        let elapsed = start_time.elapsed().as_secs_f32();
+        let elapsed = r.time().elapsed() as f32;
 
         // TODO: draw infinite ground with instance buffer, not uniform buffer.
         // Draw the infinite ground
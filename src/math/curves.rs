@@ -0,0 +1,514 @@
+//! Curves module.
+//!
+//! This module provides parametric curves evaluated over `t` from 0 to 1:
+//! Bézier curves of any degree, Catmull-Rom splines through a list of points
+//! and clamped uniform B-splines. Curves can be reparameterized by arc
+//! length, for constant-speed motion along them, and tessellated adaptively
+//! into polylines whose deviation from the curve stays under a tolerance.
+
+use crate::renderer::{
+    shape_builders::{shape_builder::vec3_color_to_vertex, MeshBuilder},
+    Color, PrimitiveType,
+};
+use glam::Vec3;
+
+/// The spans a curve is split into before adaptive subdivision, so that
+/// symmetric bends within a span are not mistaken for straight lines.
+const INITIAL_SPANS: usize = 8;
+
+/// The most times a span is halved while tessellating.
+const MAX_DEPTH: u32 = 12;
+
+/// A parametric curve over `t` from 0 to 1.
+pub trait Curve {
+    /// Returns the point of the curve at `t`, clamped to 0 to 1.
+    fn point(&self, t: f32) -> Vec3;
+
+    /// Returns the derivative of the curve at `t`, clamped to 0 to 1.
+    ///
+    /// The default implementation uses central differences.
+    fn tangent(&self, t: f32) -> Vec3 {
+        const STEP: f32 = 1e-3;
+        let (a, b) = ((t - STEP).max(0.0), (t + STEP).min(1.0));
+        (self.point(b) - self.point(a)) / (b - a)
+    }
+
+    /// Measures the curve for arc-length parameterization.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - The number of straight segments the curve is measured with.
+    fn arc_length(&self, samples: usize) -> ArcLength
+    where
+        Self: Sized,
+    {
+        ArcLength::new(self, samples)
+    }
+
+    /// Splits the curve into a polyline, halving spans until the midpoint of
+    /// every span is within the tolerance of its chord.
+    ///
+    /// # Arguments
+    ///
+    /// * `tolerance` - The largest distance between the polyline and the curve.
+    ///
+    /// # Returns
+    ///
+    /// The points of the polyline from `t = 0` to `t = 1`.
+    fn tessellate(&self, tolerance: f32) -> Vec<Vec3> {
+        let mut points = vec![self.point(0.0)];
+        let mut stack = Vec::new();
+        for span in 0..INITIAL_SPANS {
+            let t0 = span as f32 / INITIAL_SPANS as f32;
+            let t1 = (span + 1) as f32 / INITIAL_SPANS as f32;
+            stack.push((t0, t1, 0));
+            // The first half is pushed last, so points are emitted in order
+            while let Some((t0, t1, depth)) = stack.pop() {
+                let (start, end) = (self.point(t0), self.point(t1));
+                let middle = (t0 + t1) * 0.5;
+                if depth >= MAX_DEPTH
+                    || distance_to_segment(self.point(middle), start, end) <= tolerance
+                {
+                    points.push(end);
+                } else {
+                    stack.push((middle, t1, depth + 1));
+                    stack.push((t0, middle, depth + 1));
+                }
+            }
+        }
+        points
+    }
+
+    /// Tessellates the curve into a line strip mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `tolerance` - The largest distance between the line and the curve.
+    /// * `color` - The color of the line.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let path = CatmullRom::new(waypoints);
+    /// let mesh_id = renderer.add_mesh(path.to_mesh(0.01, Color::YELLOW));
+    /// ```
+    fn to_mesh(&self, tolerance: f32, color: Color) -> MeshBuilder {
+        let vertices = self
+            .tessellate(tolerance)
+            .into_iter()
+            .map(|point| vec3_color_to_vertex(point, color))
+            .collect();
+        MeshBuilder::new(vertices, PrimitiveType::LineStrip)
+    }
+}
+
+/// Returns the distance from a point to the segment between `start` and `end`.
+fn distance_to_segment(point: Vec3, start: Vec3, end: Vec3) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    let t = if length_squared > 0.0 {
+        ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance(start + segment * t)
+}
+
+/// Evaluates the Bézier curve with the given control points by de Casteljau's algorithm.
+fn de_casteljau(control_points: &[Vec3], t: f32) -> Vec3 {
+    let mut points = control_points.to_vec();
+    for level in 1..points.len() {
+        for index in 0..points.len() - level {
+            points[index] = points[index].lerp(points[index + 1], t);
+        }
+    }
+    points[0]
+}
+
+/// A Bézier curve of any degree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bezier {
+    /// The control points, the first and last of which the curve passes through.
+    pub control_points: Vec<Vec3>,
+}
+
+impl Bezier {
+    /// Creates a new `Bezier` of degree one less than the number of control points.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no control points.
+    pub fn new(control_points: Vec<Vec3>) -> Self {
+        assert!(
+            !control_points.is_empty(),
+            "A Bézier curve needs a control point"
+        );
+        Self { control_points }
+    }
+
+    /// Creates a new cubic `Bezier` from `start` to `end`.
+    pub fn cubic(start: Vec3, control_a: Vec3, control_b: Vec3, end: Vec3) -> Self {
+        Self::new(vec![start, control_a, control_b, end])
+    }
+}
+
+impl Curve for Bezier {
+    fn point(&self, t: f32) -> Vec3 {
+        de_casteljau(&self.control_points, t.clamp(0.0, 1.0))
+    }
+
+    fn tangent(&self, t: f32) -> Vec3 {
+        // The derivative is the Bézier curve of the scaled control point differences
+        let degree = self.control_points.len() - 1;
+        if degree == 0 {
+            return Vec3::ZERO;
+        }
+        let differences: Vec<Vec3> = self
+            .control_points
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]) * degree as f32)
+            .collect();
+        de_casteljau(&differences, t.clamp(0.0, 1.0))
+    }
+}
+
+/// A Catmull-Rom spline passing through every point.
+///
+/// Each pair of consecutive points is joined by one segment, spanning an
+/// equal range of `t`. Open splines extrapolate a point before the first and
+/// after the last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatmullRom {
+    pub points: Vec<Vec3>,
+    /// The knot exponent: 0 for uniform, 0.5 for centripetal and 1 for chordal splines.
+    pub alpha: f32,
+    /// Whether the last point joins back to the first.
+    pub closed: bool,
+}
+
+impl CatmullRom {
+    /// Creates a new open, centripetal `CatmullRom`.
+    ///
+    /// Centripetal splines never form cusps or self-intersections within a segment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no points.
+    pub fn new(points: Vec<Vec3>) -> Self {
+        assert!(!points.is_empty(), "A Catmull-Rom spline needs a point");
+        Self {
+            points,
+            alpha: 0.5,
+            closed: false,
+        }
+    }
+
+    /// Sets the knot exponent.
+    pub fn with_alpha(mut self, alpha: f32) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Sets whether the last point joins back to the first.
+    pub fn with_closed(mut self, closed: bool) -> Self {
+        self.closed = closed;
+        self
+    }
+
+    /// Returns the number of segments.
+    pub fn segments(&self) -> usize {
+        match self.points.len() {
+            1 => 0,
+            count if self.closed => count,
+            count => count - 1,
+        }
+    }
+
+    /// Returns a point by index, wrapping closed splines and extrapolating open ones.
+    fn control_point(&self, index: isize) -> Vec3 {
+        let count = self.points.len() as isize;
+        if self.closed {
+            return self.points[index.rem_euclid(count) as usize];
+        }
+        match index {
+            index if index < 0 => self.points[0] * 2.0 - self.points[1],
+            index if index >= count => {
+                self.points[count as usize - 1] * 2.0 - self.points[count as usize - 2]
+            }
+            index => self.points[index as usize],
+        }
+    }
+}
+
+impl Curve for CatmullRom {
+    fn point(&self, t: f32) -> Vec3 {
+        let segments = self.segments();
+        if segments == 0 {
+            return self.points[0];
+        }
+        let x = t.clamp(0.0, 1.0) * segments as f32;
+        let segment = (x as usize).min(segments - 1);
+        let u = x - segment as f32;
+        let [p0, p1, p2, p3] =
+            [-1, 0, 1, 2].map(|offset| self.control_point(segment as isize + offset));
+
+        // Barry and Goldman's pyramidal formulation, with knots spaced by
+        // the distance between points raised to alpha
+        let knot = |a: Vec3, b: Vec3| a.distance(b).powf(self.alpha).max(1e-6);
+        let t0 = 0.0;
+        let t1 = t0 + knot(p0, p1);
+        let t2 = t1 + knot(p1, p2);
+        let t3 = t2 + knot(p2, p3);
+        let t = t1 + (t2 - t1) * u;
+
+        let a1 = p0.lerp(p1, (t - t0) / (t1 - t0));
+        let a2 = p1.lerp(p2, (t - t1) / (t2 - t1));
+        let a3 = p2.lerp(p3, (t - t2) / (t3 - t2));
+        let b1 = a1.lerp(a2, (t - t0) / (t2 - t0));
+        let b2 = a2.lerp(a3, (t - t1) / (t3 - t1));
+        b1.lerp(b2, (t - t1) / (t2 - t1))
+    }
+}
+
+/// A clamped B-spline with uniform interior knots.
+///
+/// The curve starts at the first control point and ends at the last, and is
+/// as smooth as its degree allows everywhere else.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BSpline {
+    control_points: Vec<Vec3>,
+    degree: usize,
+    knots: Vec<f32>,
+}
+
+impl BSpline {
+    /// Creates a new `BSpline`.
+    ///
+    /// # Arguments
+    ///
+    /// * `control_points` - The control points.
+    /// * `degree` - The degree of the curve, lowered to one less than the
+    ///   number of control points if there are too few.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no control points.
+    pub fn new(control_points: Vec<Vec3>, degree: usize) -> Self {
+        assert!(
+            !control_points.is_empty(),
+            "A B-spline needs a control point"
+        );
+        let count = control_points.len();
+        let degree = degree.min(count - 1);
+        let interior = count - degree;
+        let knots = std::iter::repeat_n(0.0, degree)
+            .chain((0..=interior).map(|index| index as f32 / interior as f32))
+            .chain(std::iter::repeat_n(1.0, degree))
+            .collect();
+        Self {
+            control_points,
+            degree,
+            knots,
+        }
+    }
+
+    /// Creates a new cubic `BSpline`.
+    pub fn cubic(control_points: Vec<Vec3>) -> Self {
+        Self::new(control_points, 3)
+    }
+
+    /// Returns the control points.
+    pub fn control_points(&self) -> &[Vec3] {
+        &self.control_points
+    }
+
+    /// Returns the degree of the curve.
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
+}
+
+impl Curve for BSpline {
+    fn point(&self, t: f32) -> Vec3 {
+        let t = t.clamp(0.0, 1.0);
+        let degree = self.degree;
+        let last = self.control_points.len() - 1;
+        // The knot span containing t, with t = 1 in the last span
+        let span = (degree..=last)
+            .rev()
+            .find(|&span| self.knots[span] <= t)
+            .unwrap_or(degree);
+
+        // De Boor's algorithm
+        let mut points: Vec<Vec3> = (0..=degree)
+            .map(|index| self.control_points[index + span - degree])
+            .collect();
+        for level in 1..=degree {
+            for index in (level..=degree).rev() {
+                let knot = index + span - degree;
+                let denominator = self.knots[knot + 1 + degree - level] - self.knots[knot];
+                let weight = if denominator > 0.0 {
+                    (t - self.knots[knot]) / denominator
+                } else {
+                    0.0
+                };
+                points[index] = points[index - 1].lerp(points[index], weight);
+            }
+        }
+        points[degree]
+    }
+}
+
+/// A table mapping distance along a curve to its parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArcLength {
+    parameters: Vec<f32>,
+    distances: Vec<f32>,
+}
+
+impl ArcLength {
+    /// Measures a curve by summing the lengths of evenly spaced chords.
+    ///
+    /// # Arguments
+    ///
+    /// * `curve` - The curve to measure.
+    /// * `samples` - The number of chords, at least 1.
+    pub fn new(curve: &impl Curve, samples: usize) -> Self {
+        let samples = samples.max(1);
+        let mut parameters = Vec::with_capacity(samples + 1);
+        let mut distances = Vec::with_capacity(samples + 1);
+        let mut previous = curve.point(0.0);
+        let mut distance = 0.0;
+        for index in 0..=samples {
+            let t = index as f32 / samples as f32;
+            let point = curve.point(t);
+            distance += point.distance(previous);
+            previous = point;
+            parameters.push(t);
+            distances.push(distance);
+        }
+        Self {
+            parameters,
+            distances,
+        }
+    }
+
+    /// Returns the length of the curve.
+    pub fn length(&self) -> f32 {
+        self.distances[self.distances.len() - 1]
+    }
+
+    /// Returns the parameter at a distance along the curve, clamped to its length.
+    pub fn parameter(&self, distance: f32) -> f32 {
+        let distance = distance.clamp(0.0, self.length());
+        let index = self
+            .distances
+            .partition_point(|&sample| sample < distance)
+            .max(1);
+        let (d0, d1) = (self.distances[index - 1], self.distances[index]);
+        let (t0, t1) = (self.parameters[index - 1], self.parameters[index]);
+        if d1 > d0 {
+            t0 + (t1 - t0) * (distance - d0) / (d1 - d0)
+        } else {
+            t0
+        }
+    }
+
+    /// Returns points evenly spaced along the curve, including both ends.
+    ///
+    /// # Arguments
+    ///
+    /// * `curve` - The curve this table was measured from.
+    /// * `count` - The number of points, at least 2.
+    pub fn uniform_points(&self, curve: &impl Curve, count: usize) -> Vec<Vec3> {
+        let count = count.max(2);
+        (0..count)
+            .map(|index| {
+                let distance = self.length() * index as f32 / (count - 1) as f32;
+                curve.point(self.parameter(distance))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_to_segment, BSpline, Bezier, CatmullRom, Curve};
+    use glam::Vec3;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.distance(b) < 1e-4, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_curves_interpolate_their_points() {
+        let bezier = Bezier::cubic(Vec3::ZERO, Vec3::Y, Vec3::ONE.with_z(0.0), Vec3::X);
+        assert_near(bezier.point(0.5), Vec3::new(0.5, 0.75, 0.0));
+        assert_near(bezier.tangent(0.0), Vec3::Y * 3.0);
+
+        let points = vec![
+            Vec3::ZERO,
+            Vec3::new(1.0, 2.0, 0.0),
+            Vec3::new(3.0, 0.0, 1.0),
+        ];
+        let spline = CatmullRom::new(points.clone());
+        for (index, point) in points.iter().enumerate() {
+            assert_near(spline.point(index as f32 / 2.0), *point);
+        }
+        let closed = CatmullRom::new(points.clone()).with_closed(true);
+        assert_eq!(closed.segments(), 3);
+        assert_near(closed.point(1.0), points[0]);
+
+        // A clamped B-spline starts and ends at its control points, and
+        // one of degree 1 is the polyline through them
+        let cubic = BSpline::cubic(points.clone());
+        assert_eq!(cubic.degree(), 2);
+        assert_near(cubic.point(0.0), points[0]);
+        assert_near(cubic.point(1.0), points[2]);
+        let linear = BSpline::new(points.clone(), 1);
+        assert_near(linear.point(0.25), Vec3::new(0.5, 1.0, 0.0));
+        assert_near(linear.point(0.75), Vec3::new(2.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn test_arc_length_parameterization() {
+        // x = t², so constant speed needs t = √(distance)
+        let curve = Bezier::new(vec![Vec3::ZERO, Vec3::ZERO, Vec3::X]);
+        let arc_length = curve.arc_length(256);
+        assert!((arc_length.length() - 1.0).abs() < 1e-4);
+        assert!((arc_length.parameter(0.25) - 0.5).abs() < 1e-3);
+        assert_eq!(arc_length.parameter(2.0), 1.0);
+
+        let points = arc_length.uniform_points(&curve, 5);
+        for (index, point) in points.iter().enumerate() {
+            assert!((point.x - index as f32 * 0.25).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_tessellation() {
+        let line = Bezier::new(vec![Vec3::ZERO, Vec3::X]);
+        assert_eq!(line.tessellate(0.01).len(), 9);
+
+        let curve = Bezier::cubic(
+            Vec3::ZERO,
+            Vec3::Y * 4.0,
+            Vec3::new(4.0, -4.0, 0.0),
+            Vec3::X,
+        );
+        let coarse = curve.tessellate(0.1);
+        let fine = curve.tessellate(0.001);
+        assert!(fine.len() > coarse.len());
+        assert_eq!(fine[0], Vec3::ZERO);
+        assert_eq!(*fine.last().unwrap(), Vec3::X);
+        // Every point of the curve is near the polyline
+        for index in 0..=100 {
+            let point = curve.point(index as f32 / 100.0);
+            let distance = fine
+                .windows(2)
+                .map(|pair| distance_to_segment(point, pair[0], pair[1]))
+                .fold(f32::MAX, f32::min);
+            assert!(distance < 0.01);
+        }
+    }
+}
@@ -0,0 +1,498 @@
+//! Geometry queries module.
+//!
+//! This module provides the closest point, distance and overlap predicates
+//! shared by culling, physics and picking: closest points on segments,
+//! triangles, boxes and spheres, segment-triangle intersection, sphere and
+//! box overlap tests, ray and segment intersections with spheres, boxes and
+//! planes, and a view frustum tested against points, spheres and boxes.
+//! Boxes and spheres are treated as solids, so a point inside one is its own
+//! closest point. Closest points on meshes are found by
+//! `Mesh::closest_point`, which uses these queries per triangle.
+//!
+//! Queries the physics engine shares are generic over `GeometryVector`, so
+//! they run on glam's f32 `Vec3` and on the physics engine's f64 `Vector3`.
+
+use crate::renderer::{Aabb, BoundingSphere, Ray};
+use glam::{Mat4, Vec3, Vec4};
+use num_traits::{Float, Zero};
+use std::ops::{Add, Mul, Sub};
+
+/// A 3D vector the generic geometry queries run on.
+pub trait GeometryVector:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Self::Scalar, Output = Self>
+{
+    type Scalar: Float;
+
+    /// Returns the dot product of two vectors.
+    fn dot(self, other: Self) -> Self::Scalar;
+
+    /// Returns the x, y and z components.
+    fn to_array(self) -> [Self::Scalar; 3];
+
+    /// Creates a vector from its x, y and z components.
+    fn from_array(components: [Self::Scalar; 3]) -> Self;
+
+    /// Returns the length of the vector.
+    fn length(self) -> Self::Scalar {
+        self.dot(self).sqrt()
+    }
+}
+
+impl GeometryVector for Vec3 {
+    type Scalar = f32;
+
+    fn dot(self, other: Self) -> f32 {
+        Vec3::dot(self, other)
+    }
+
+    fn to_array(self) -> [f32; 3] {
+        Vec3::to_array(&self)
+    }
+
+    fn from_array(components: [f32; 3]) -> Self {
+        Vec3::from_array(components)
+    }
+}
+
+/// The point of a mesh closest to a query point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshPoint {
+    /// The closest point in world space.
+    pub point: Vec3,
+    /// The distance from the query point.
+    pub distance: f32,
+    /// The index of the triangle the point lies on.
+    pub triangle: usize,
+}
+
+/// Returns the point of a line segment closest to a point.
+pub fn closest_point_on_segment(point: Vec3, start: Vec3, end: Vec3) -> Vec3 {
+    let direction = end - start;
+    let length_squared = direction.length_squared();
+    if length_squared == 0.0 {
+        return start;
+    }
+    let t = ((point - start).dot(direction) / length_squared).clamp(0.0, 1.0);
+    start + direction * t
+}
+
+/// Returns the point of a triangle closest to a point.
+///
+/// The point is classified against the triangle's corner and edge regions,
+/// so only the nearest feature is projected onto.
+pub fn closest_point_on_triangle<V: GeometryVector>(point: V, [a, b, c]: [V; 3]) -> V {
+    let zero = V::Scalar::zero();
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= zero && d2 <= zero {
+        return a;
+    }
+
+    let bp = point - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= zero && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= zero && d1 >= zero && d3 <= zero {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = point - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= zero && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= zero && d2 >= zero && d6 <= zero {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= zero && d4 - d3 >= zero && d5 - d6 >= zero {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    // Inside the face; degenerate triangles fall back to their edges above
+    let denominator = va + vb + vc;
+    if denominator == zero {
+        return a;
+    }
+    a + ab * (vb / denominator) + ac * (vc / denominator)
+}
+
+/// Returns the point of a box closest to a point.
+pub fn closest_point_on_aabb(point: Vec3, aabb: &Aabb) -> Vec3 {
+    closest_point_on_box(point, aabb.min, aabb.max)
+}
+
+/// Returns the point of the box between two corners closest to a point.
+pub fn closest_point_on_box<V: GeometryVector>(point: V, min: V, max: V) -> V {
+    let (point, min, max) = (point.to_array(), min.to_array(), max.to_array());
+    V::from_array(std::array::from_fn(|axis| {
+        if point[axis] < min[axis] {
+            min[axis]
+        } else if point[axis] > max[axis] {
+            max[axis]
+        } else {
+            point[axis]
+        }
+    }))
+}
+
+/// Returns the point of a sphere closest to a point.
+pub fn closest_point_on_sphere(point: Vec3, sphere: &BoundingSphere) -> Vec3 {
+    let offset = point - sphere.center;
+    if offset.length_squared() <= sphere.radius * sphere.radius {
+        return point;
+    }
+    sphere.center + offset.normalize() * sphere.radius
+}
+
+/// Returns the squared distance from a point to a box, which is zero inside it.
+pub fn distance_squared_to_aabb(point: Vec3, aabb: &Aabb) -> f32 {
+    point.distance_squared(closest_point_on_aabb(point, aabb))
+}
+
+/// Returns the distance from a point to a sphere, which is zero inside it.
+pub fn distance_to_sphere(point: Vec3, sphere: &BoundingSphere) -> f32 {
+    signed_distance_to_sphere(point, sphere.center, sphere.radius).max(0.0)
+}
+
+/// Returns the distance from a point to a sphere's surface, negative inside it.
+pub fn signed_distance_to_sphere<V: GeometryVector>(
+    point: V,
+    center: V,
+    radius: V::Scalar,
+) -> V::Scalar {
+    (point - center).length() - radius
+}
+
+/// Returns the distance from a point to a plane, negative behind it.
+///
+/// # Arguments
+///
+/// * `point` - The point.
+/// * `plane_point` - Any point on the plane.
+/// * `normal` - The unit normal of the plane, pointing to its front.
+pub fn signed_distance_to_plane<V: GeometryVector>(
+    point: V,
+    plane_point: V,
+    normal: V,
+) -> V::Scalar {
+    (point - plane_point).dot(normal)
+}
+
+/// Intersects a line segment with a triangle, from either side.
+///
+/// # Returns
+///
+/// The fraction of the way from `start` to `end` of the hit and the
+/// barycentric weights of the triangle's corners at the hit, or `None` if
+/// the segment misses.
+pub fn intersect_segment_triangle(
+    start: Vec3,
+    end: Vec3,
+    triangle: [Vec3; 3],
+) -> Option<(f32, Vec3)> {
+    // An unnormalized ray measures distances in segment lengths
+    let ray = Ray {
+        origin: start,
+        direction: end - start,
+    };
+    ray.intersect_triangle(triangle)
+        .filter(|&(fraction, _)| fraction <= 1.0)
+}
+
+/// Intersects a ray with a sphere.
+///
+/// # Returns
+///
+/// The distance along the ray, in units of its direction's length, at which
+/// it enters the sphere, or `None` if it misses or starts inside.
+pub fn intersect_ray_sphere<V: GeometryVector>(
+    origin: V,
+    direction: V,
+    center: V,
+    radius: V::Scalar,
+) -> Option<V::Scalar> {
+    let zero = V::Scalar::zero();
+    let offset = origin - center;
+    let a = direction.dot(direction);
+    let b = offset.dot(direction);
+    let c = offset.dot(offset) - radius * radius;
+    // Starting inside, or outside and heading away
+    if a == zero || c <= zero || b >= zero {
+        return None;
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < zero {
+        return None;
+    }
+    Some((-b - discriminant.sqrt()) / a)
+}
+
+/// Intersects a ray with the box between two corners.
+///
+/// # Returns
+///
+/// The distance along the ray, in units of its direction's length, at which
+/// it enters the box and the axis of the face it enters through, or `None`
+/// if it misses or starts inside.
+pub fn intersect_ray_box<V: GeometryVector>(
+    origin: V,
+    direction: V,
+    min: V,
+    max: V,
+) -> Option<(V::Scalar, usize)> {
+    let zero = V::Scalar::zero();
+    let (origin, direction) = (origin.to_array(), direction.to_array());
+    let (min, max) = (min.to_array(), max.to_array());
+    let mut entry = V::Scalar::neg_infinity();
+    let mut exit = V::Scalar::infinity();
+    let mut entry_axis = None;
+    for axis in 0..3 {
+        if direction[axis] == zero {
+            // Parallel to the slab, so the ray stays outside or inside it
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t0 = (min[axis] - origin[axis]) / direction[axis];
+        let t1 = (max[axis] - origin[axis]) / direction[axis];
+        let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+        if near > entry {
+            entry = near;
+            entry_axis = Some(axis);
+        }
+        if far < exit {
+            exit = far;
+        }
+    }
+    let axis = entry_axis?;
+    (entry >= zero && entry <= exit).then_some((entry, axis))
+}
+
+/// Intersects a line segment with a plane, crossing it from front to back.
+///
+/// # Returns
+///
+/// The fraction of the way from `start` to `end` of the crossing, or `None`
+/// if the segment doesn't cross from the front of the plane to its back.
+pub fn intersect_segment_plane<V: GeometryVector>(
+    start: V,
+    end: V,
+    plane_point: V,
+    normal: V,
+) -> Option<V::Scalar> {
+    let zero = V::Scalar::zero();
+    let before = signed_distance_to_plane(start, plane_point, normal);
+    let after = signed_distance_to_plane(end, plane_point, normal);
+    (before > zero && after < zero).then(|| before / (before - after))
+}
+
+/// Returns `true` if two spheres overlap or touch.
+pub fn spheres_overlap(a: &BoundingSphere, b: &BoundingSphere) -> bool {
+    let radius = a.radius + b.radius;
+    a.center.distance_squared(b.center) <= radius * radius
+}
+
+/// Returns `true` if a sphere and a box overlap or touch.
+pub fn sphere_overlaps_aabb(sphere: &BoundingSphere, aabb: &Aabb) -> bool {
+    distance_squared_to_aabb(sphere.center, aabb) <= sphere.radius * sphere.radius
+}
+
+/// The volume a camera sees, bounded by six planes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// The left, right, bottom, top, near and far planes, facing inwards.
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the frustum of a view projection matrix.
+    ///
+    /// Planes are normalized and face inwards, so a point is inside the frustum
+    /// when `plane.xyz · point + plane.w >= 0` for every plane. Depth follows
+    /// Metal's [0, 1] clip space.
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let rows = view_projection.transpose();
+        let (x, y, z, w) = (rows.x_axis, rows.y_axis, rows.z_axis, rows.w_axis);
+        let planes =
+            [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+
+    /// Returns `true` if the point is inside or on the frustum.
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+
+    /// Returns `true` if a sphere is at least partly inside the frustum.
+    ///
+    /// Spheres near a corner of the frustum but outside it may pass, which
+    /// is conservative for culling. This is the test the GPU culling kernel
+    /// runs for every instance.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    /// Returns `true` if a box is at least partly inside the frustum.
+    ///
+    /// Like `intersects_sphere`, boxes near a corner of the frustum may pass.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        closest_point_on_aabb, closest_point_on_segment, closest_point_on_sphere,
+        closest_point_on_triangle, intersect_ray_box, intersect_ray_sphere,
+        intersect_segment_plane, intersect_segment_triangle, signed_distance_to_plane,
+        signed_distance_to_sphere, sphere_overlaps_aabb, spheres_overlap, Frustum,
+    };
+    use crate::renderer::{Aabb, BoundingSphere};
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_closest_points() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        // Above the face, beyond a corner and beyond an edge
+        let face = closest_point_on_triangle(Vec3::new(0.25, 0.25, 2.0), triangle);
+        assert_eq!(face, Vec3::new(0.25, 0.25, 0.0));
+        assert_eq!(
+            closest_point_on_triangle(Vec3::new(2.0, -1.0, 0.0), triangle),
+            Vec3::X
+        );
+        let edge = closest_point_on_triangle(Vec3::new(1.0, 1.0, 1.0), triangle);
+        assert!(edge.abs_diff_eq(Vec3::new(0.5, 0.5, 0.0), 1e-6));
+
+        assert_eq!(
+            closest_point_on_segment(Vec3::new(0.5, 3.0, 0.0), Vec3::ZERO, Vec3::X),
+            Vec3::new(0.5, 0.0, 0.0)
+        );
+        let aabb = Aabb::new(Vec3::ZERO, Vec3::ONE);
+        assert_eq!(
+            closest_point_on_aabb(Vec3::new(2.0, 0.5, -1.0), &aabb),
+            Vec3::new(1.0, 0.5, 0.0)
+        );
+        let sphere = BoundingSphere::new(Vec3::ZERO, 2.0);
+        assert_eq!(
+            closest_point_on_sphere(Vec3::X * 4.0, &sphere),
+            Vec3::X * 2.0
+        );
+        assert_eq!(closest_point_on_sphere(Vec3::X, &sphere), Vec3::X);
+    }
+
+    #[test]
+    fn test_intersection_and_overlap() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let start = Vec3::new(0.25, 0.25, 1.0);
+        let (fraction, _) = intersect_segment_triangle(start, -start, triangle).unwrap();
+        assert_eq!(fraction, 0.5);
+        assert!(intersect_segment_triangle(start, Vec3::new(0.25, 0.25, 0.5), triangle).is_none());
+
+        let aabb = Aabb::new(Vec3::ZERO, Vec3::ONE);
+        let sphere = BoundingSphere::new(Vec3::new(2.0, 0.5, 0.5), 1.0);
+        assert!(sphere_overlaps_aabb(&sphere, &aabb));
+        assert!(!sphere_overlaps_aabb(
+            &BoundingSphere {
+                radius: 0.9,
+                ..sphere
+            },
+            &aabb
+        ));
+        assert!(spheres_overlap(
+            &sphere,
+            &BoundingSphere::new(Vec3::splat(0.5), 1.0)
+        ));
+        assert!(!spheres_overlap(
+            &sphere,
+            &BoundingSphere::new(-Vec3::X, 1.0)
+        ));
+    }
+
+    #[test]
+    fn test_ray_queries() {
+        let origin = Vec3::new(-4.0, 0.5, 0.5);
+        assert_eq!(
+            intersect_ray_sphere(origin, Vec3::X * 2.0, Vec3::new(0.0, 0.5, 0.5), 1.0),
+            Some(1.5)
+        );
+        // Rays starting inside or heading away miss
+        assert_eq!(intersect_ray_sphere(origin, Vec3::X, origin, 1.0), None);
+        assert_eq!(
+            intersect_ray_sphere(origin, -Vec3::X, Vec3::ZERO, 1.0),
+            None
+        );
+
+        assert_eq!(
+            intersect_ray_box(origin, Vec3::X, Vec3::ZERO, Vec3::ONE),
+            Some((4.0, 0))
+        );
+        assert_eq!(
+            intersect_ray_box(Vec3::new(0.5, 3.0, 0.5), -Vec3::Y, Vec3::ZERO, Vec3::ONE),
+            Some((2.0, 1))
+        );
+        assert_eq!(
+            intersect_ray_box(origin, Vec3::Y, Vec3::ZERO, Vec3::ONE),
+            None
+        );
+        assert_eq!(
+            intersect_ray_box(Vec3::splat(0.5), Vec3::X, Vec3::ZERO, Vec3::ONE),
+            None
+        );
+
+        assert_eq!(signed_distance_to_sphere(Vec3::ZERO, Vec3::X, 2.0), -1.0);
+        assert_eq!(
+            signed_distance_to_plane(Vec3::Y * 3.0, Vec3::Y, Vec3::Y),
+            2.0
+        );
+        // Segments only cross planes from the front
+        assert_eq!(
+            intersect_segment_plane(Vec3::Y * 3.0, -Vec3::Y, Vec3::ZERO, Vec3::Y),
+            Some(0.75)
+        );
+        assert_eq!(
+            intersect_segment_plane(-Vec3::Y, Vec3::Y * 3.0, Vec3::ZERO, Vec3::Y),
+            None
+        );
+    }
+
+    #[test]
+    fn test_frustum() {
+        let view_projection = Mat4::perspective_rh(1.0, 1.0, 0.1, 100.0)
+            * Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let frustum = Frustum::from_view_projection(view_projection);
+        let sphere = |x, z, radius| BoundingSphere::new(Vec3::new(x, 0.0, z), radius);
+
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(frustum.intersects_sphere(&sphere(0.0, -10.0, 1.0)));
+        // Behind the camera and beyond the far plane
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, -110.0, 1.0)));
+        // Outside the left plane unless large enough to reach into the frustum
+        assert!(!frustum.intersects_sphere(&sphere(-20.0, -10.0, 1.0)));
+        assert!(frustum.intersects_sphere(&sphere(-20.0, -10.0, 20.0)));
+
+        let aabb = |min: Vec3| Aabb::new(min, min + Vec3::ONE);
+        assert!(frustum.intersects_aabb(&aabb(Vec3::new(-0.5, -0.5, -10.0))));
+        assert!(!frustum.intersects_aabb(&aabb(Vec3::new(-20.0, -0.5, -10.0))));
+        // A box straddling the left plane
+        assert!(frustum.intersects_aabb(&Aabb::new(
+            Vec3::new(-20.0, -0.5, -10.0),
+            Vec3::new(0.0, 0.5, -9.0)
+        )));
+    }
+}
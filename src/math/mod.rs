@@ -0,0 +1,14 @@
+//! Math Module
+//!
+//! This module provides math shared by the renderer and physics that glam
+//! does not cover.
+//!
+//! Key Components:
+//!
+//! - `curves`: Provides Bézier, Catmull-Rom and B-spline curves, arc-length parameterization and tessellation.
+//! - `geometry`: Provides closest point, distance and overlap queries shared by culling, physics and picking.
+//! - `transform`: Provides the translation, rotation and scale type used for local transforms.
+
+pub mod curves;
+pub mod geometry;
+pub mod transform;
@@ -0,0 +1,220 @@
+//! Transform module.
+//!
+//! This module provides `Transform`, a translation, rotation and scale kept
+//! apart rather than baked into a matrix. Keeping the parts makes
+//! interpolation and inversion cheap and exact, and avoids decomposing
+//! matrices wherever a position or orientation is needed. Transforms are
+//! applied as scale, then rotation, then translation.
+
+use glam::{Mat4, Quat, Vec3};
+use std::ops::Mul;
+
+/// A translation, rotation and scale, applied in reverse order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    /// The transform that leaves points unchanged.
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    /// Creates a new `Transform`.
+    pub fn new(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    /// Creates a new `Transform` that only translates.
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Creates a new `Transform` that only rotates.
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self {
+            rotation,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Creates a new `Transform` that only scales.
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self {
+            scale,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Decomposes an affine matrix without shear or perspective.
+    pub fn from_mat4(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    /// Returns the matrix applying the transform.
+    pub fn to_mat4(self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Sets the translation.
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    /// Sets the rotation.
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Sets the scale.
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Applies the transform to a point.
+    pub fn transform_point(self, point: Vec3) -> Vec3 {
+        self.rotation * (self.scale * point) + self.translation
+    }
+
+    /// Applies the transform to a direction, ignoring the translation.
+    pub fn transform_vector(self, vector: Vec3) -> Vec3 {
+        self.rotation * (self.scale * vector)
+    }
+
+    /// Returns the transform undoing this one.
+    ///
+    /// The inverse is exact for uniform scales. A rotated non-uniform scale
+    /// inverts to a shear, which a `Transform` cannot hold.
+    pub fn inverse(self) -> Self {
+        let rotation = self.rotation.inverse();
+        let scale = self.scale.recip();
+        Self {
+            translation: scale * (rotation * -self.translation),
+            rotation,
+            scale,
+        }
+    }
+
+    /// Interpolates linearly, normalizing the interpolated rotation.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The transform at `t = 1`.
+    /// * `t` - The interpolation factor.
+    pub fn lerp(self, other: Transform, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.lerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    /// Interpolates linearly, rotating at a constant angular speed.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The transform at `t = 1`.
+    /// * `t` - The interpolation factor.
+    pub fn slerp(self, other: Transform, t: f32) -> Self {
+        Self {
+            rotation: self.rotation.slerp(other.rotation, t),
+            ..self.lerp(other, t)
+        }
+    }
+}
+
+/// Composes two transforms, applying `rhs` first.
+///
+/// Like `inverse`, composition is exact unless a non-uniform scale is
+/// followed by a rotation.
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform {
+            translation: self.transform_point(rhs.translation),
+            rotation: (self.rotation * rhs.rotation).normalize(),
+            scale: self.scale * rhs.scale,
+        }
+    }
+}
+
+impl From<Mat4> for Transform {
+    fn from(matrix: Mat4) -> Self {
+        Self::from_mat4(matrix)
+    }
+}
+
+impl From<Transform> for Mat4 {
+    fn from(transform: Transform) -> Self {
+        transform.to_mat4()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transform;
+    use glam::{Mat4, Quat, Vec3};
+
+    fn assert_near(a: Mat4, b: Mat4) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn test_transform_matches_matrices() {
+        let a = Transform::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Quat::from_rotation_y(0.7),
+            Vec3::splat(2.0),
+        );
+        let b = Transform::new(Vec3::X, Quat::from_rotation_x(-0.3), Vec3::splat(0.5));
+        let point = Vec3::new(0.5, -1.0, 2.0);
+
+        assert_near((a * b).to_mat4(), a.to_mat4() * b.to_mat4());
+        assert_near(a.inverse().to_mat4(), a.to_mat4().inverse());
+        assert_near((a * a.inverse()).to_mat4(), Mat4::IDENTITY);
+        assert!(a
+            .transform_point(point)
+            .abs_diff_eq(a.to_mat4().transform_point3(point), 1e-5));
+        assert_near(Transform::from(a.to_mat4()).to_mat4(), a.to_mat4());
+    }
+
+    #[test]
+    fn test_transform_interpolation() {
+        let a = Transform::from_translation(Vec3::ZERO);
+        let b = Transform::new(Vec3::X * 2.0, Quat::from_rotation_z(1.5), Vec3::splat(3.0));
+        let middle = a.slerp(b, 0.5);
+        assert_eq!(middle.translation, Vec3::X);
+        assert_eq!(middle.scale, Vec3::splat(2.0));
+        assert!((middle.rotation.angle_between(Quat::IDENTITY) - 0.75).abs() < 1e-5);
+        // Normalized linear interpolation agrees at the ends and keeps a unit rotation
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert!(a.lerp(b, 0.5).rotation.is_normalized());
+    }
+}
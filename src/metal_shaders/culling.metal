@@ -0,0 +1,134 @@
+#include <metal_stdlib>
+using namespace metal;
+
+#include "shader_types.h"
+
+constant bool occlusion_culling [[function_constant(0)]];
+
+constexpr sampler pyramidSampler(filter::nearest, mip_filter::nearest, address::clamp_to_edge);
+
+static float read_depth(depth2d<float, access::read> source, uint2 position)
+{
+    return source.read(position);
+}
+
+static float read_depth(texture2d<float, access::read> source, uint2 position)
+{
+    return source.read(position).r;
+}
+
+// Returns the farthest source depth under an output texel, covering the
+// extra row or column of odd-sized sources
+template <typename Source>
+static float farthest_depth(Source source, uint2 position, uint2 outputSize)
+{
+    uint2 sourceSize = uint2(source.get_width(), source.get_height());
+    uint2 start = position * sourceSize / outputSize;
+    uint2 end = min(((position + 1) * sourceSize + outputSize - 1) / outputSize, sourceSize);
+    float farthest = 0.0;
+    for (uint y = start.y; y < end.y; y++) {
+        for (uint x = start.x; x < end.x; x++) {
+            farthest = max(farthest, read_depth(source, uint2(x, y)));
+        }
+    }
+    return farthest;
+}
+
+// Reduces the depth buffer into the first level of the depth pyramid
+kernel void depth_pyramid_first(
+    depth2d<float, access::read> depth [[texture(0)]],
+    texture2d<float, access::write> output [[texture(1)]],
+    uint2 position [[thread_position_in_grid]]
+) {
+    uint2 outputSize = uint2(output.get_width(), output.get_height());
+    if (any(position >= outputSize)) {
+        return;
+    }
+    output.write(float4(farthest_depth(depth, position, outputSize)), position);
+}
+
+// Reduces one level of the depth pyramid into the next
+kernel void depth_pyramid_downsample(
+    texture2d<float, access::read> source [[texture(0)]],
+    texture2d<float, access::write> output [[texture(1)]],
+    uint2 position [[thread_position_in_grid]]
+) {
+    uint2 outputSize = uint2(output.get_width(), output.get_height());
+    if (any(position >= outputSize)) {
+        return;
+    }
+    output.write(float4(farthest_depth(source, position, outputSize)), position);
+}
+
+// Returns true if a world-space sphere was hidden behind the previous frame's depth
+static bool is_occluded(
+    constant CullingConstants &culling,
+    float3 center,
+    float radius,
+    texture2d<float> pyramid
+) {
+    // The screen rectangle and nearest depth of the sphere's bounding box
+    float2 minUv = float2(1.0);
+    float2 maxUv = float2(0.0);
+    float nearestDepth = 1.0;
+    for (uint i = 0; i < 8; i++) {
+        float3 offset = float3(i & 1 ? 1.0 : -1.0, i & 2 ? 1.0 : -1.0, i & 4 ? 1.0 : -1.0);
+        float4 clip = culling.occlusionViewProjection * float4(center + offset * radius, 1.0);
+        // Boxes reaching behind the camera cover the whole screen
+        if (clip.w <= 0.0) {
+            return false;
+        }
+        float3 ndc = clip.xyz / clip.w;
+        float2 uv = ndc.xy * float2(0.5, -0.5) + 0.5;
+        minUv = min(minUv, uv);
+        maxUv = max(maxUv, uv);
+        nearestDepth = min(nearestDepth, ndc.z);
+    }
+    minUv = saturate(minUv);
+    maxUv = saturate(maxUv);
+
+    // At this level the rectangle covers at most two texels on each axis
+    float2 size = (maxUv - minUv) * float2(pyramid.get_width(), pyramid.get_height());
+    float lod = min(ceil(log2(max(max(size.x, size.y), 1.0))),
+                    float(pyramid.get_num_mip_levels() - 1));
+    float farthest = max(
+        max(pyramid.sample(pyramidSampler, minUv, level(lod)).r,
+            pyramid.sample(pyramidSampler, float2(maxUv.x, minUv.y), level(lod)).r),
+        max(pyramid.sample(pyramidSampler, float2(minUv.x, maxUv.y), level(lod)).r,
+            pyramid.sample(pyramidSampler, maxUv, level(lod)).r));
+    return nearestDepth > farthest;
+}
+
+// Tests every instance of a draw and compacts the visible ones, counting
+// them into the instance count of the draw's indirect arguments
+kernel void cull_instances(
+    constant CullingConstants &culling [[buffer(0)]],
+    device const InstanceData *instances [[buffer(1)]],
+    device InstanceData *visibleInstances [[buffer(2)]],
+    device atomic_uint *visibleCount [[buffer(3)]],
+    texture2d<float> depthPyramid [[texture(0), function_constant(occlusion_culling)]],
+    uint id [[thread_position_in_grid]]
+) {
+    if (id >= culling.instanceCount) {
+        return;
+    }
+
+    InstanceData instance = instances[id];
+    float4x4 model = culling.modelMatrix * instance.modelMatrix;
+    float3 center = (model * float4(float3(culling.boundsCenter), 1.0)).xyz;
+    float scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+    float radius = culling.boundsRadius * scale;
+
+    for (uint i = 0; i < 6; i++) {
+        float4 plane = culling.frustumPlanes[i];
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return;
+        }
+    }
+    if (occlusion_culling && is_occluded(culling, center, radius, depthPyramid)) {
+        return;
+    }
+
+    uint slot = atomic_fetch_add_explicit(visibleCount, 1, memory_order_relaxed);
+    visibleInstances[slot] = instance;
+}
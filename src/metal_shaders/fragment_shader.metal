@@ -2,7 +2,154 @@
 using namespace metal;
 
 #include "shader_types.h"
+#include "shader_features.h"
 
-fragment float4 fragment_main(VertexOut in [[stage_in]]) {
-    return in.color;
-}
\ No newline at end of file
+// Must match the argument indices encoded by `MaterialTable`
+struct MaterialArguments {
+    texture2d<float> baseColorTexture [[id(0)]];
+    sampler baseColorSampler [[id(1)]];
+    float4 baseColor [[id(2)]];
+    texture2d<float> reflectionTexture [[id(3)]];
+    float reflectivity [[id(4)]];
+};
+
+// Must match the discriminants of `DebugView`
+constant uint DEBUG_VIEW_NORMALS = 1;
+constant uint DEBUG_VIEW_DEPTH = 2;
+constant uint DEBUG_VIEW_DRAW_CALL = 4;
+
+// Maps an integer to a distinct, saturated color
+static float3 id_color(uint id) {
+    uint hash = id * 2654435761u;
+    float hue = float(hash >> 8) / float(1u << 24);
+    float3 rgb = saturate(abs(fmod(hue * 6.0 + float3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0);
+    return mix(float3(0.2), rgb, 0.9);
+}
+
+// Decodes an sRGB value to linear, so the sRGB target stores the debug value itself
+static float3 srgb_to_linear(float3 srgb) {
+    return select(pow((srgb + 0.055) / 1.055, 2.4), srgb / 12.92, srgb <= 0.04045);
+}
+
+// Returns the view-space distance of a fragment, undoing the perspective divide
+static float view_depth(float depth, constant FrameConstants &frame) {
+    float near = frame.nearPlane;
+    float far = frame.farPlane;
+    return near * far / (far - depth * (far - near));
+}
+
+// Returns the ambient light reaching a fragment, blending from the ground
+// color for surfaces facing down to the sky color for surfaces facing up
+static float3 ambient_light(VertexOut in, constant FrameConstants &frame) {
+    float3 normal = in.normal;
+    if (length_squared(normal) < 1e-12) {
+        // Without vertex normals, the face normal from the screen-space
+        // derivatives, or straight up for lines and points
+        normal = cross(dfdx(in.worldPosition), dfdy(in.worldPosition));
+        if (length_squared(normal) < 1e-20) {
+            normal = float3(0.0, 1.0, 0.0);
+        }
+    }
+    normal = normalize(normal);
+    // Back faces are lit from the side they are seen from
+    if (dot(normal, float3(frame.cameraPosition) - in.worldPosition) < 0.0) {
+        normal = -normal;
+    }
+    return mix(float3(frame.ambientGroundColor), float3(frame.ambientSkyColor),
+               normal.y * 0.5 + 0.5);
+}
+
+// The regular shading of a fragment
+static float4 base_color(
+    VertexOut in,
+    constant FrameConstants &frame,
+    constant MaterialArguments &material
+) {
+    // Geometry without texture coordinates skips the base color texture
+    float4 texel = is_textured
+        ? material.baseColorTexture.sample(material.baseColorSampler, in.texCoord)
+        : float4(1.0);
+    float4 materialColor = is_base_color_specialized ? specialized_base_color : material.baseColor;
+    float4 color = in.color * materialColor * texel;
+    color.rgb *= ambient_light(in, frame);
+
+    // Planar reflections are drawn from the same camera, so the fragment's
+    // position on screen is where its reflection lies in the texture
+    float reflectivity =
+        is_reflectivity_specialized ? specialized_reflectivity : material.reflectivity;
+    if (reflectivity > 0.0) {
+        float2 uv = in.clipPosition.xy / in.clipPosition.w * float2(0.5, -0.5) + 0.5;
+        float4 reflection = material.reflectionTexture.sample(material.baseColorSampler, uv);
+        color.rgb = mix(color.rgb, reflection.rgb, reflectivity);
+    }
+    return color;
+}
+
+// Must match the attachments of the weighted blended pipeline variants
+struct WeightedBlendedOut {
+    float4 accumulation [[color(0)]];
+    float revealage [[color(1)]];
+};
+
+// Accumulates a transparent fragment for weighted blended order-independent
+// transparency. Nearer and more opaque fragments weigh more, approximating
+// the order a sorted blend would composite them in (McGuire and Bavoil 2013)
+fragment WeightedBlendedOut fragment_weighted_blended(
+    VertexOut in [[stage_in]],
+    constant FrameConstants &frame [[buffer(0)]],
+    constant MaterialArguments *materials [[buffer(1)]],
+    constant uint &materialIndex [[buffer(2)]]
+) {
+    float4 color = base_color(in, frame, materials[materialIndex]);
+    float alpha = saturate(color.a);
+    float depth = view_depth(in.position.z, frame);
+    float weight = alpha * clamp(10.0 / (1e-5 + pow(depth / 5.0, 2.0) + pow(depth / 200.0, 6.0)),
+                                 1e-2, 3e3);
+
+    WeightedBlendedOut out;
+    out.accumulation = float4(color.rgb * alpha, alpha) * weight;
+    out.revealage = alpha;
+    return out;
+}
+
+fragment float4 fragment_main(
+    VertexOut in [[stage_in]],
+    constant FrameConstants &frame [[buffer(0)]],
+    constant MaterialArguments *materials [[buffer(1)]],
+    constant uint &materialIndex [[buffer(2)]],
+    constant uint &debugView [[buffer(4)]],
+    constant uint &drawIndex [[buffer(5)]]
+) {
+    constant MaterialArguments &material = materials[materialIndex];
+
+    if (debugView == DEBUG_VIEW_NORMALS) {
+        // Face normal from the screen-space derivatives of the world position
+        float3 normal = normalize(cross(dfdx(in.worldPosition), dfdy(in.worldPosition)));
+        return float4(srgb_to_linear(normal * 0.5 + 0.5), 1.0);
+    }
+    if (debugView == DEBUG_VIEW_DEPTH) {
+        float near = frame.nearPlane;
+        float far = frame.farPlane;
+        float viewDepth = view_depth(in.position.z, frame);
+        return float4(srgb_to_linear(float3((viewDepth - near) / (far - near))), 1.0);
+    }
+    if (debugView == DEBUG_VIEW_DRAW_CALL) {
+        return float4(srgb_to_linear(id_color(drawIndex)), 1.0);
+    }
+
+    return base_color(in, frame, material);
+}
+
+// Fills the scaled redraw of an outlined object with a solid color
+fragment float4 fragment_outline(
+    VertexOut in [[stage_in]],
+    constant float4 &outlineColor [[buffer(3)]]
+) {
+    return outlineColor;
+}
+
+// Adds a small tint per shaded fragment; additive blending turns the sum into
+// a heatmap of how many times each pixel was shaded
+fragment float4 fragment_overdraw(VertexOut in [[stage_in]]) {
+    return float4(0.1, 0.04, 0.01, 1.0);
+}
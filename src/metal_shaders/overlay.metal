@@ -0,0 +1,69 @@
+#include <metal_stdlib>
+using namespace metal;
+
+#include "shader_types.h"
+
+// The pixels sampled around an occlusion point are within this distance on both axes
+constant int OVERLAY_OCCLUSION_RADIUS = 1;
+
+struct OverlayOut
+{
+    float4 position [[position]];
+    float2 texCoord;
+    float4 color;
+};
+
+// Returns the fraction of the pixels around a point that are not in front of it
+float overlay_visibility(float4 occlusion, depth2d<float> depth) {
+    float2 size = float2(depth.get_width(), depth.get_height());
+    int2 center = int2((float2(occlusion.x, -occlusion.y) * 0.5 + 0.5) * size);
+    float visible = 0.0;
+    float samples = 0.0;
+    for (int y = -OVERLAY_OCCLUSION_RADIUS; y <= OVERLAY_OCCLUSION_RADIUS; y++) {
+        for (int x = -OVERLAY_OCCLUSION_RADIUS; x <= OVERLAY_OCCLUSION_RADIUS; x++) {
+            int2 pixel = center + int2(x, y);
+            // Pixels off screen hide nothing
+            if (any(pixel < 0) || any(pixel >= int2(size))) {
+                continue;
+            }
+            visible += depth.read(uint2(pixel)) >= occlusion.z ? 1.0 : 0.0;
+            samples += 1.0;
+        }
+    }
+    return samples > 0.0 ? visible / samples : 1.0;
+}
+
+// Projects canvas pixels, with y pointing down, orthographically onto the
+// screen, fading vertices whose occlusion point is hidden by the scene
+vertex OverlayOut overlay_vertex(
+    uint vertexId [[vertex_id]],
+    const device OverlayVertex *vertices [[buffer(0)]],
+    constant float2 &canvasSize [[buffer(1)]],
+    depth2d<float> depth [[texture(0)]]
+) {
+    OverlayVertex in = vertices[vertexId];
+    float2 ndc = in.position / canvasSize * 2.0 - 1.0;
+
+    OverlayOut out;
+    out.position = float4(ndc.x, -ndc.y, 0.0, 1.0);
+    out.texCoord = in.texCoord;
+    out.color = in.color;
+    if (in.occlusion.w < 1.0) {
+        out.color.a *= mix(in.occlusion.w, 1.0, overlay_visibility(in.occlusion, depth));
+    }
+    return out;
+}
+
+// Multiplies the vertex color with the batch's texture, if it has one
+fragment float4 overlay_fragment(
+    OverlayOut in [[stage_in]],
+    texture2d<float> texture [[texture(0)]],
+    constant uint &textured [[buffer(0)]]
+) {
+    constexpr sampler overlaySampler(filter::linear, address::clamp_to_edge);
+    float4 color = in.color;
+    if (textured != 0) {
+        color *= texture.sample(overlaySampler, in.texCoord);
+    }
+    return color;
+}
@@ -0,0 +1,368 @@
+#include <metal_stdlib>
+using namespace metal;
+
+#include "shader_types.h"
+
+// Both outputs of the temporal resolve: the presented color and the next history
+struct TemporalOut
+{
+    float4 color [[color(0)]];
+    float4 history [[color(1)]];
+};
+
+constexpr sampler pointSampler(filter::nearest, address::clamp_to_edge);
+constexpr sampler linearSampler(filter::linear, address::clamp_to_edge);
+
+// A lens flare element, with its corner in [-1, 1] on both axes
+struct FlareOut
+{
+    float4 position [[position]];
+    float2 local;
+    float4 color;
+    uint shape [[flat]];
+};
+
+// Both outputs of the atmosphere pass, blended as scene * transmittance + inscattered
+struct AtmosphereOut
+{
+    float4 inscattered [[color(0), index(0)]];
+    float4 transmittance [[color(0), index(1)]];
+};
+
+// FXAA tuning, using the defaults of the original FXAA implementation
+constant float FXAA_EDGE_THRESHOLD = 0.125;
+constant float FXAA_EDGE_THRESHOLD_MIN = 0.0312;
+constant float FXAA_REDUCE_MUL = 1.0 / 8.0;
+constant float FXAA_REDUCE_MIN = 1.0 / 128.0;
+constant float FXAA_SPAN_MAX = 8.0;
+
+// Must match the discriminants of `FlareShape`
+constant uint FLARE_SHAPE_DISC = 1;
+constant uint FLARE_SHAPE_RING = 2;
+constant uint FLARE_SHAPE_STARBURST = 3;
+
+// Depth samples per axis tested around a flare's light
+constant int FLARE_OCCLUSION_SAMPLES = 5;
+
+// Ray-march steps towards the sun from every atmosphere sample
+constant int ATMOSPHERE_LIGHT_SAMPLES = 8;
+
+// Stars are scattered over a grid of cells around the sky, at most one per cell
+constant float STAR_GRID_SIZE = 120.0;
+constant float STAR_DENSITY = 0.08;
+constant float STAR_RADIUS = 0.08;
+
+// The sun disc is far brighter than the sky around it
+constant float SUN_DISC_BRIGHTNESS = 20.0;
+
+// Mie extinction is slightly higher than its scattering, due to absorption
+constant float MIE_EXTINCTION_RATIO = 1.1;
+
+// FXAA's thresholds are tuned for perceptual luma, which the square root of
+// the linear scene's luma approximates
+static float luma(float3 color) {
+    return sqrt(dot(color, float3(0.299, 0.587, 0.114)));
+}
+
+// A single triangle covering the screen, generated from the vertex index
+vertex FullscreenOut fullscreen_vertex(uint vertexID [[vertex_id]]) {
+    float2 uv = float2((vertexID << 1) & 2, vertexID & 2);
+    FullscreenOut out;
+    out.position = float4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Presents the scene unchanged, for frames that are only post-processed for
+// effects. Scaled scenes are filtered to the drawable's size
+fragment float4 copy_fragment(
+    FullscreenOut in [[stage_in]],
+    texture2d<float> scene [[texture(0)]]
+) {
+    return float4(scene.sample(linearSampler, in.uv).rgb, 1.0);
+}
+
+fragment float4 fxaa_fragment(
+    FullscreenOut in [[stage_in]],
+    texture2d<float> scene [[texture(0)]]
+) {
+    float2 texel = 1.0 / float2(scene.get_width(), scene.get_height());
+    float3 center = scene.sample(pointSampler, in.uv).rgb;
+    float lumaM = luma(center);
+    float lumaNW = luma(scene.sample(pointSampler, in.uv + float2(-1.0, -1.0) * texel).rgb);
+    float lumaNE = luma(scene.sample(pointSampler, in.uv + float2(1.0, -1.0) * texel).rgb);
+    float lumaSW = luma(scene.sample(pointSampler, in.uv + float2(-1.0, 1.0) * texel).rgb);
+    float lumaSE = luma(scene.sample(pointSampler, in.uv + float2(1.0, 1.0) * texel).rgb);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+    if (lumaMax - lumaMin < max(FXAA_EDGE_THRESHOLD_MIN, lumaMax * FXAA_EDGE_THRESHOLD)) {
+        return float4(center, 1.0);
+    }
+
+    // Blur along the edge, perpendicular to the luma gradient
+    float2 direction = float2(
+        (lumaSW + lumaSE) - (lumaNW + lumaNE),
+        (lumaNW + lumaSW) - (lumaNE + lumaSE)
+    );
+    float reduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, -FXAA_SPAN_MAX, FXAA_SPAN_MAX) * texel;
+
+    float3 inner = 0.5 * (
+        scene.sample(linearSampler, in.uv + direction * (1.0 / 3.0 - 0.5)).rgb +
+        scene.sample(linearSampler, in.uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    float3 outer = inner * 0.5 + 0.25 * (
+        scene.sample(linearSampler, in.uv - direction * 0.5).rgb +
+        scene.sample(linearSampler, in.uv + direction * 0.5).rgb);
+
+    // The wider blur crossed another edge if it left the local luma range
+    float lumaOuter = luma(outer);
+    if (lumaOuter < lumaMin || lumaOuter > lumaMax) {
+        return float4(inner, 1.0);
+    }
+    return float4(outer, 1.0);
+}
+
+fragment TemporalOut taa_resolve_fragment(
+    FullscreenOut in [[stage_in]],
+    constant TemporalConstants &temporal [[buffer(0)]],
+    texture2d<float> scene [[texture(0)]],
+    texture2d<float> history [[texture(1)]],
+    depth2d<float> depth [[texture(2)]]
+) {
+    float2 texel = 1.0 / float2(scene.get_width(), scene.get_height());
+    float3 current = scene.sample(pointSampler, in.uv).rgb;
+    float3 resolved = current;
+
+    if (temporal.historyValid != 0) {
+        // The history is clamped to the neighbourhood to reject disoccluded colors
+        float3 minColor = current;
+        float3 maxColor = current;
+        for (int y = -1; y <= 1; y++) {
+            for (int x = -1; x <= 1; x++) {
+                float3 color = scene.sample(pointSampler, in.uv + float2(x, y) * texel).rgb;
+                minColor = min(minColor, color);
+                maxColor = max(maxColor, color);
+            }
+        }
+
+        // Reconstruct the unjittered clip position and find it in the previous frame
+        float2 ndc = float2(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0) - temporal.jitter;
+        float4 previous = temporal.reprojection * float4(ndc, depth.sample(pointSampler, in.uv), 1.0);
+        float2 previousNdc = previous.xy / previous.w;
+        float2 previousUv = float2(previousNdc.x, -previousNdc.y) * 0.5 + 0.5;
+
+        if (all(previousUv >= 0.0) && all(previousUv <= 1.0)) {
+            float3 past = clamp(history.sample(linearSampler, previousUv).rgb, minColor, maxColor);
+            resolved = mix(current, past, temporal.historyWeight);
+        }
+    }
+
+    TemporalOut out;
+    out.color = float4(resolved, 1.0);
+    out.history = out.color;
+    return out;
+}
+
+// Places a flare element quad, dimmed by how much of the area around the light is visible
+vertex FlareOut flare_vertex(
+    uint vertexID [[vertex_id]],
+    uint instanceID [[instance_id]],
+    constant FlareConstants &flare [[buffer(0)]],
+    constant FlareElement *elements [[buffer(1)]],
+    depth2d<float> depth [[texture(0)]]
+) {
+    // Samples off screen count as occluded, so the flare fades out at the edges
+    float2 size = float2(depth.get_width(), depth.get_height());
+    float2 radius = flare.occlusionRadius * float2(1.0 / flare.aspectRatio, 1.0);
+    float visible = 0.0;
+    for (int y = 0; y < FLARE_OCCLUSION_SAMPLES; y++) {
+        for (int x = 0; x < FLARE_OCCLUSION_SAMPLES; x++) {
+            float2 offset = float2(x, y) / float(FLARE_OCCLUSION_SAMPLES - 1) * 2.0 - 1.0;
+            float2 ndc = flare.lightPosition + offset * radius;
+            if (all(abs(ndc) < 1.0)) {
+                float2 uv = float2(ndc.x, -ndc.y) * 0.5 + 0.5;
+                visible += depth.read(uint2(uv * size)) >= flare.lightDepth ? 1.0 : 0.0;
+            }
+        }
+    }
+    visible /= float(FLARE_OCCLUSION_SAMPLES * FLARE_OCCLUSION_SAMPLES);
+
+    FlareElement element = elements[instanceID];
+    float2 corner = float2(vertexID & 1, vertexID >> 1) * 2.0 - 1.0;
+    FlareOut out;
+    out.position = float4(element.center + corner * element.size, 0.0, 1.0);
+    out.local = corner;
+    out.color = element.color * visible * flare.intensity;
+    out.shape = element.shape;
+    return out;
+}
+
+// Draws a procedural flare shape, added onto the presented frame
+fragment float4 flare_fragment(FlareOut in [[stage_in]]) {
+    float radius = length(in.local);
+    float coverage;
+    if (in.shape == FLARE_SHAPE_DISC) {
+        coverage = 1.0 - smoothstep(0.8, 1.0, radius);
+    } else if (in.shape == FLARE_SHAPE_RING) {
+        coverage = 1.0 - smoothstep(0.0, 0.12, abs(radius - 0.85));
+    } else if (in.shape == FLARE_SHAPE_STARBURST) {
+        float glow = pow(saturate(1.0 - radius), 3.0);
+        float streaks = pow(saturate(1.0 - abs(in.local.x * in.local.y) * 40.0), 8.0);
+        coverage = max(glow, streaks * saturate(1.0 - radius));
+    } else {
+        coverage = pow(saturate(1.0 - radius), 2.0);
+    }
+    return float4(in.color.rgb * in.color.a * coverage, 0.0);
+}
+
+// The distances along a ray to where it enters and leaves a sphere, or (-1, -1) if it misses
+static float2 ray_sphere(float3 origin, float3 direction, float3 center, float radius) {
+    float3 offset = origin - center;
+    float b = dot(offset, direction);
+    float c = dot(offset, offset) - radius * radius;
+    float discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return float2(-1.0);
+    }
+    float root = sqrt(discriminant);
+    return float2(-b - root, -b + root);
+}
+
+// The Rayleigh and Mie densities at a point, relative to the surface
+static float2 atmosphere_density(constant AtmosphereConstants &atmosphere, float3 position) {
+    float altitude = max(distance(position, float3(atmosphere.planetCenter)) - atmosphere.planetRadius, 0.0);
+    return exp(-altitude / float2(atmosphere.rayleighScaleHeight, atmosphere.mieScaleHeight));
+}
+
+// Single scattering of sunlight along each pixel's view ray, up to the scene depth
+fragment AtmosphereOut atmosphere_fragment(
+    FullscreenOut in [[stage_in]],
+    constant AtmosphereConstants &atmosphere [[buffer(0)]],
+    depth2d<float> depth [[texture(0)]]
+) {
+    AtmosphereOut out;
+    out.inscattered = float4(0.0);
+    out.transmittance = float4(1.0);
+
+    float3 camera = float3(atmosphere.cameraPosition);
+    float3 center = float3(atmosphere.planetCenter);
+    float sceneDepth = depth.sample(pointSampler, in.uv);
+    float2 ndc = float2(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    float4 world = atmosphere.inverseViewProjection * float4(ndc, sceneDepth, 1.0);
+    float3 direction = normalize(world.xyz / world.w - camera);
+    // Pixels without geometry extend to infinity, or to the planet's surface
+    float sceneDistance = sceneDepth < 1.0 ? distance(world.xyz / world.w, camera) : INFINITY;
+    float2 ground = ray_sphere(camera, direction, center, atmosphere.planetRadius);
+    if (sceneDepth >= 1.0 && ground.x > 0.0) {
+        sceneDistance = ground.x;
+    }
+
+    float2 shell = ray_sphere(camera, direction, center, atmosphere.atmosphereRadius);
+    float start = max(shell.x, 0.0);
+    float end = min(shell.y, sceneDistance);
+    if (end <= start) {
+        return out;
+    }
+
+    float3 sun = float3(atmosphere.sunDirection);
+    float3 rayleigh = float3(atmosphere.rayleighScattering);
+    float mie = atmosphere.mieScattering;
+    float3 mieExtinction = float3(mie * MIE_EXTINCTION_RATIO);
+
+    float step = (end - start) / float(atmosphere.sampleCount);
+    float2 opticalDepth = float2(0.0);
+    float3 rayleighSum = float3(0.0);
+    float3 mieSum = float3(0.0);
+    for (uint i = 0; i < atmosphere.sampleCount; i++) {
+        float3 position = camera + direction * (start + (float(i) + 0.5) * step);
+        float2 density = atmosphere_density(atmosphere, position) * step;
+        opticalDepth += density;
+
+        // Samples in the planet's shadow receive no sunlight
+        if (ray_sphere(position, sun, center, atmosphere.planetRadius).x > 0.0) {
+            continue;
+        }
+        float lightLength = ray_sphere(position, sun, center, atmosphere.atmosphereRadius).y;
+        float lightStep = lightLength / float(ATMOSPHERE_LIGHT_SAMPLES);
+        float2 lightDepth = float2(0.0);
+        for (int j = 0; j < ATMOSPHERE_LIGHT_SAMPLES; j++) {
+            float3 lightPosition = position + sun * ((float(j) + 0.5) * lightStep);
+            lightDepth += atmosphere_density(atmosphere, lightPosition) * lightStep;
+        }
+
+        float2 total = opticalDepth + lightDepth;
+        float3 attenuation = exp(-(rayleigh * total.x + mieExtinction * total.y));
+        rayleighSum += density.x * attenuation;
+        mieSum += density.y * attenuation;
+    }
+
+    float mu = dot(direction, sun);
+    float g = atmosphere.mieAnisotropy;
+    float rayleighPhase = 3.0 / (16.0 * M_PI_F) * (1.0 + mu * mu);
+    // Cornette-Shanks phase function
+    float miePhase = 3.0 / (8.0 * M_PI_F) * ((1.0 - g * g) * (1.0 + mu * mu))
+        / ((2.0 + g * g) * pow(1.0 + g * g - 2.0 * g * mu, 1.5));
+
+    float3 inscattered = atmosphere.sunIntensity
+        * (rayleighSum * rayleigh * rayleighPhase + mieSum * mie * miePhase);
+    out.inscattered = float4(inscattered, 0.0);
+    out.transmittance = float4(exp(-(rayleigh * opticalDepth.x + mieExtinction * opticalDepth.y)), 1.0);
+    return out;
+}
+
+// A pseudo-random value in [0, 1) for every integer cell
+static float hash(float3 cell) {
+    return fract(sin(dot(cell, float3(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// The brightness of the star in a direction's cell, if the cell has one
+static float star(float3 direction) {
+    float3 position = direction * STAR_GRID_SIZE;
+    float3 cell = floor(position);
+    if (hash(cell) > STAR_DENSITY) {
+        return 0.0;
+    }
+    // Stars stay inside their cell, so neighbouring cells never need sampling
+    float3 center = cell + 0.25 + 0.5 * float3(hash(cell + 1.0), hash(cell + 2.0), hash(cell + 3.0));
+    float brightness = 0.3 + 0.7 * hash(cell + 4.0);
+    return brightness * (1.0 - smoothstep(0.0, STAR_RADIUS, distance(position, center)));
+}
+
+// Fills pixels without geometry with the sky gradient, sun disc and stars
+fragment float4 sky_fragment(
+    FullscreenOut in [[stage_in]],
+    constant SkyConstants &sky [[buffer(0)]],
+    depth2d<float> depth [[texture(0)]]
+) {
+    if (depth.sample(pointSampler, in.uv) < 1.0) {
+        discard_fragment();
+    }
+
+    float2 ndc = float2(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    float4 near = sky.inverseViewProjection * float4(ndc, 0.0, 1.0);
+    float4 far = sky.inverseViewProjection * float4(ndc, 1.0, 1.0);
+    float3 direction = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    // Below the horizon, the sky fades to a darker horizon color
+    float height = direction.y;
+    float3 horizon = float3(sky.horizonColor);
+    float3 color = height >= 0.0
+        ? mix(horizon, float3(sky.zenithColor), sqrt(height))
+        : horizon * (1.0 - 0.5 * saturate(-height * 4.0));
+
+    float3 sun = float3(sky.sunDirection);
+    float cosAngle = dot(direction, sun);
+    float disc = smoothstep(cos(sky.sunAngularRadius * 1.2), cos(sky.sunAngularRadius), cosAngle);
+    float glow = pow(saturate(cosAngle), 256.0) * 0.5;
+    color += float3(sky.sunColor) * (disc * SUN_DISC_BRIGHTNESS + glow);
+
+    if (sky.starBrightness > 0.0) {
+        float3 starDirection = (sky.starRotation * float4(direction, 0.0)).xyz;
+        float fade = smoothstep(0.0, 0.15, height);
+        color += star(starDirection) * sky.starBrightness * fade;
+    }
+
+    return float4(color, 1.0);
+}
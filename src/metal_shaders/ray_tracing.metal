@@ -0,0 +1,39 @@
+#include <metal_stdlib>
+#include <metal_raytracing>
+using namespace metal;
+using namespace raytracing;
+
+#include "shader_types.h"
+
+constexpr sampler depthSampler(filter::nearest, address::clamp_to_edge);
+
+// Darkens pixels whose surface cannot see the light, multiplied onto the scene
+fragment float4 ray_traced_shadow_fragment(
+    FullscreenOut in [[stage_in]],
+    constant ShadowRayConstants &shadow [[buffer(0)]],
+    instance_acceleration_structure scene [[buffer(1)]],
+    depth2d<float> depth [[texture(0)]]
+) {
+    float sceneDepth = depth.sample(depthSampler, in.uv);
+    if (sceneDepth >= 1.0) {
+        discard_fragment();
+    }
+
+    float2 ndc = float2(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    float4 world = shadow.inverseViewProjection * float4(ndc, sceneDepth, 1.0);
+    float3 light = float3(shadow.lightDirection);
+
+    ray shadowRay;
+    shadowRay.origin = world.xyz / world.w + light * shadow.bias;
+    shadowRay.direction = light;
+    shadowRay.min_distance = 0.0;
+    shadowRay.max_distance = shadow.maxDistance;
+
+    // Any hit blocks the light, so the closest one is never searched for
+    intersector<instancing> occlusion;
+    occlusion.accept_any_intersection(true);
+    if (occlusion.intersect(shadowRay, scene).type == intersection_type::none) {
+        discard_fragment();
+    }
+    return float4(float3(1.0 - shadow.strength), 1.0);
+}
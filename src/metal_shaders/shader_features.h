@@ -0,0 +1,20 @@
+#pragma once
+
+// Shader features, compiled into specialized functions per pipeline. The
+// indices must match the bits of `ShaderFeatures`
+constant bool is_instanced [[function_constant(0)]];
+// Whether the vertex layout has each attribute, see `VertexLayout`
+constant bool use_vertex_color [[function_constant(1)]];
+constant bool has_vertex_normal [[function_constant(2)]];
+// Texture coordinates are read and the base color texture sampled
+constant bool is_textured [[function_constant(3)]];
+// Reserved until vertex layouts carry tangents and joints
+constant bool has_normal_map [[function_constant(4)]];
+constant bool is_skinned [[function_constant(5)]];
+
+// Material parameters specialized into constants, see `MaterialConstants`.
+// Each is read from the material table unless its constant is defined
+constant float4 specialized_base_color [[function_constant(6)]];
+constant bool is_base_color_specialized = is_function_constant_defined(specialized_base_color);
+constant float specialized_reflectivity [[function_constant(7)]];
+constant bool is_reflectivity_specialized = is_function_constant_defined(specialized_reflectivity);
@@ -4,16 +4,165 @@
 #include <metal_stdlib>
 using namespace metal;
 
-struct VertexIn
+struct VertexOut
 {
-    float3 position [[attribute(0)]];
-    float4 color [[attribute(1)]];
+    // Invariant, so the depth pre-pass and shaded draws compute equal depth
+    float4 position [[position, invariant]];
+    float4 color;
+    float3 worldPosition;
+    float3 normal;
+    float2 texCoord;
+    // The position before the perspective divide, which planar reflections are sampled at
+    float4 clipPosition;
 };
 
-struct VertexOut
+// Per-instance data, bound as an array to vertex buffer 2 of instanced draws
+struct InstanceData
+{
+    float4x4 modelMatrix;
+    float4 color;
+};
+
+// The output of the fullscreen triangle shared by post-process passes
+struct FullscreenOut
 {
     float4 position [[position]];
+    float2 uv;
+};
+
+// Per-frame constants, bound to vertex buffer 3 and fragment buffer 0
+struct FrameConstants
+{
+    packed_float3 cameraPosition;
+    float time;
+    float2 viewportSize;
+    float nearPlane;
+    float farPlane;
+    float deltaTime;
+    uint frameIndex;
+    packed_float3 ambientSkyColor;
+    packed_float3 ambientGroundColor;
+};
+
+// Temporal anti-aliasing constants, bound to fragment buffer 0 of the resolve pass
+struct TemporalConstants
+{
+    float4x4 reprojection;
+    float2 jitter;
+    float historyWeight;
+    uint historyValid;
+};
+
+// Lens flare constants, bound to vertex buffer 0 of the flare pass
+struct FlareConstants
+{
+    float2 lightPosition;
+    float lightDepth;
+    float occlusionRadius;
+    float aspectRatio;
+    float intensity;
+};
+
+// A flare element laid out on screen, bound as an array to vertex buffer 1 of the flare pass
+struct FlareElement
+{
+    float2 center;
+    float2 size;
     float4 color;
+    uint shape;
+};
+
+// A 2D canvas vertex in pixels, bound as an array to vertex buffer 0 of the overlay pass
+struct OverlayVertex
+{
+    float2 position;
+    float2 texCoord;
+    float4 color;
+    // A point in normalized device coordinates with its depth, and the
+    // opacity while the point is hidden, which is 1 for untested vertices
+    float4 occlusion;
+};
+
+// Atmospheric scattering constants, bound to fragment buffer 0 of the atmosphere pass
+struct AtmosphereConstants
+{
+    float4x4 inverseViewProjection;
+    packed_float3 cameraPosition;
+    float atmosphereRadius;
+    packed_float3 planetCenter;
+    float planetRadius;
+    packed_float3 rayleighScattering;
+    float rayleighScaleHeight;
+    packed_float3 sunDirection;
+    float sunIntensity;
+    float mieScattering;
+    float mieScaleHeight;
+    float mieAnisotropy;
+    uint sampleCount;
+};
+
+// Procedural sky constants, bound to fragment buffer 0 of the sky pass
+struct SkyConstants
+{
+    float4x4 inverseViewProjection;
+    float4x4 starRotation;
+    packed_float3 sunDirection;
+    float sunAngularRadius;
+    packed_float3 sunColor;
+    float starBrightness;
+    packed_float3 zenithColor;
+    float _padding;
+    packed_float3 horizonColor;
+    float _padding2;
+};
+
+// Ray-traced shadow constants, bound to fragment buffer 0 of the shadow pass
+struct ShadowRayConstants
+{
+    float4x4 inverseViewProjection;
+    packed_float3 lightDirection;
+    float strength;
+    float maxDistance;
+    float bias;
+};
+
+// Cascaded shadow map constants, bound to fragment buffer 0 of the shadow map pass
+#define MAX_SHADOW_CASCADES 4
+struct ShadowMapConstants
+{
+    float4x4 inverseViewProjection;
+    float4x4 view;
+    float4x4 cascadeViewProjections[MAX_SHADOW_CASCADES];
+    float cascadeSplits[MAX_SHADOW_CASCADES];
+    uint cascadeCount;
+    float strength;
+    float bias;
+    float blend;
+};
+
+// Point or spot light shadow constants, bound to fragment buffer 0 of the light shadow pass
+struct LightShadowConstants
+{
+    float4x4 inverseViewProjection;
+    float4x4 faceViewProjections[6];
+    packed_float3 position;
+    float range;
+    uint faceCount;
+    float strength;
+    float bias;
+    float padding;
+};
+
+// Constants of one instanced draw, bound to buffer 0 of the culling kernel
+struct CullingConstants
+{
+    float4x4 modelMatrix;
+    float4x4 occlusionViewProjection;
+    float4 frustumPlanes[6];
+    packed_float3 boundsCenter;
+    float boundsRadius;
+    uint instanceCount;
+    uint occlusion;
 };
 
 #endif /* ShaderTypes_h */
\ No newline at end of file
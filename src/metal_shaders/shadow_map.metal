@@ -0,0 +1,147 @@
+#include <metal_stdlib>
+using namespace metal;
+
+#include "shader_types.h"
+
+constexpr sampler sceneDepthSampler(filter::nearest, address::clamp_to_edge);
+// Bilinear comparisons smooth each tap of the filter between texels
+constexpr sampler shadowSampler(filter::linear, address::clamp_to_edge, compare_func::less_equal);
+
+// Returns how lit a world position is in one layer of a shadow map array,
+// drawn with the given view projection, from 0 in shadow to 1
+static float shadow_light(
+    depth2d_array<float> shadowMaps,
+    float4x4 viewProjection,
+    float3 world,
+    uint layer,
+    float bias
+) {
+    float4 clip = viewProjection * float4(world, 1.0);
+    float3 ndc = clip.xyz / clip.w;
+    float2 uv = float2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (clip.w <= 0.0 || any(uv < 0.0) || any(uv > 1.0) || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    // 3x3 percentage-closer filter
+    float2 texel = 1.0 / float2(shadowMaps.get_width(), shadowMaps.get_height());
+    float lit = 0.0;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            lit += shadowMaps.sample_compare(
+                shadowSampler, uv + float2(x, y) * texel, layer, ndc.z - bias);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Returns how lit a world position is in one cascade, from 0 in shadow to 1
+static float cascade_light(
+    constant ShadowMapConstants &shadow,
+    depth2d_array<float> shadowMaps,
+    float3 world,
+    uint cascade
+) {
+    return shadow_light(
+        shadowMaps, shadow.cascadeViewProjections[cascade], world, cascade, shadow.bias);
+}
+
+// Returns the cube face a direction points through, in the order of CubeFace::ALL
+static uint cube_face(float3 direction) {
+    float3 a = abs(direction);
+    if (a.x >= a.y && a.x >= a.z) {
+        return direction.x >= 0.0 ? 0 : 1;
+    }
+    if (a.y >= a.z) {
+        return direction.y >= 0.0 ? 2 : 3;
+    }
+    return direction.z >= 0.0 ? 4 : 5;
+}
+
+// Darkens pixels whose surface is hidden from the light in the shadow maps,
+// multiplied onto the scene
+fragment float4 shadow_map_fragment(
+    FullscreenOut in [[stage_in]],
+    constant ShadowMapConstants &shadow [[buffer(0)]],
+    depth2d<float> depth [[texture(0)]],
+    depth2d_array<float> shadowMaps [[texture(1)]]
+) {
+    float sceneDepth = depth.sample(sceneDepthSampler, in.uv);
+    if (sceneDepth >= 1.0) {
+        discard_fragment();
+    }
+
+    float2 ndc = float2(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    float4 world = shadow.inverseViewProjection * float4(ndc, sceneDepth, 1.0);
+    world /= world.w;
+    float viewDepth = -(shadow.view * world).z;
+
+    uint cascade = 0;
+    while (cascade < shadow.cascadeCount && viewDepth > shadow.cascadeSplits[cascade]) {
+        cascade++;
+    }
+    if (cascade == shadow.cascadeCount) {
+        discard_fragment();
+    }
+    float lit = cascade_light(shadow, shadowMaps, world.xyz, cascade);
+
+    // Near its far end, a cascade fades into the next, or the last one into
+    // no shadow, hiding the seam
+    float start = cascade == 0 ? 0.0 : shadow.cascadeSplits[cascade - 1];
+    float end = shadow.cascadeSplits[cascade];
+    float fade = (end - viewDepth) / max((end - start) * shadow.blend, 1e-5);
+    if (fade < 1.0) {
+        float next = cascade + 1 < shadow.cascadeCount
+            ? cascade_light(shadow, shadowMaps, world.xyz, cascade + 1)
+            : 1.0;
+        lit = mix(next, lit, saturate(fade));
+    }
+
+    if (lit >= 1.0) {
+        discard_fragment();
+    }
+    return float4(float3(1.0 - shadow.strength * (1.0 - lit)), 1.0);
+}
+
+// Darkens pixels within a point or spot light's reach whose surface is hidden
+// from the light, multiplied onto the scene. A spot light has one shadow map
+// and a point light one per cube face.
+fragment float4 light_shadow_fragment(
+    FullscreenOut in [[stage_in]],
+    constant LightShadowConstants &shadow [[buffer(0)]],
+    depth2d<float> depth [[texture(0)]],
+    depth2d_array<float> shadowMaps [[texture(1)]]
+) {
+    float sceneDepth = depth.sample(sceneDepthSampler, in.uv);
+    if (sceneDepth >= 1.0) {
+        discard_fragment();
+    }
+
+    float2 ndc = float2(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    float4 world = shadow.inverseViewProjection * float4(ndc, sceneDepth, 1.0);
+    world /= world.w;
+    float3 toSurface = world.xyz - float3(shadow.position);
+    float distance = length(toSurface);
+    if (distance >= shadow.range) {
+        discard_fragment();
+    }
+
+    uint face = shadow.faceCount > 1 ? cube_face(toSurface) : 0;
+    float4 clip = shadow.faceViewProjections[face] * world;
+    float2 light = clip.xy / clip.w;
+    // Outside a spot light's cone
+    if (clip.w <= 0.0 || any(abs(light) > 1.0)) {
+        discard_fragment();
+    }
+    float lit = shadow_light(
+        shadowMaps, shadow.faceViewProjections[face], world.xyz, face, shadow.bias);
+
+    // Shadows fade out towards the end of the light's reach
+    float fade = saturate((shadow.range - distance) / (shadow.range * 0.1));
+    lit = mix(1.0, lit, fade);
+
+    if (lit >= 1.0) {
+        discard_fragment();
+    }
+    return float4(float3(1.0 - shadow.strength * (1.0 - lit)), 1.0);
+}
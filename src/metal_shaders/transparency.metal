@@ -0,0 +1,27 @@
+#include <metal_stdlib>
+using namespace metal;
+
+#include "shader_types.h"
+
+// Composites the weighted average color of the accumulated transparent
+// fragments over the scene, covering it by one minus the revealage
+fragment float4 weighted_blended_composite_fragment(
+    FullscreenOut in [[stage_in]],
+    texture2d<float, access::read> accumulation [[texture(0)]],
+    texture2d<float, access::read> revealage [[texture(1)]]
+) {
+    uint2 position = uint2(in.position.xy);
+    float reveal = revealage.read(position).r;
+    // Pixels without transparent fragments are fully revealed
+    if (reveal >= 1.0) {
+        discard_fragment();
+    }
+
+    float4 sum = accumulation.read(position);
+    // Sums beyond the half float range fall back to their alpha
+    if (any(isinf(sum.rgb))) {
+        sum.rgb = float3(sum.a);
+    }
+    float3 average = sum.rgb / max(sum.a, 1e-5);
+    return float4(average, 1.0 - reveal);
+}
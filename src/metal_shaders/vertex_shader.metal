@@ -2,39 +2,78 @@
 using namespace metal;
 
 #include "shader_types.h"
+#include "shader_features.h"
 
-constant bool is_instanced [[function_constant(0)]];
-constant bool use_vertex_color [[function_constant(1)]];
+struct VertexIn
+{
+    float3 position [[attribute(0)]];
+    float4 color [[attribute(1), function_constant(use_vertex_color)]];
+    float3 normal [[attribute(2), function_constant(has_vertex_normal)]];
+    float2 texCoord [[attribute(3), function_constant(is_textured)]];
+};
 
 struct Uniforms {
     float4x4 viewProjectionMatrix;
     float4x4 modelMatrix;
 };
 
-struct InstanceData {
-    float4x4 modelMatrix;
-    float4 color;
-};
-
 vertex VertexOut vertex_main(
     VertexIn vertexIn [[stage_in]],
     constant Uniforms &uniforms [[buffer(1)]],
     constant InstanceData *instanceData [[buffer(2)]],
+    constant FrameConstants &frame [[buffer(3)]],
     uint vertexID [[vertex_id]],
     uint instanceID [[instance_id]]
 ) {
     VertexOut out;
 
-    float4x4 modelMatrix;
+    float4x4 modelMatrix = uniforms.modelMatrix;
+    float4 color = use_vertex_color ? vertexIn.color : float4(1.0);
+
+    // Instances are placed relative to the draw's transform and tint its vertex colors
     if (is_instanced) {
-        modelMatrix = instanceData[instanceID].modelMatrix;
-    } else {
-        modelMatrix = uniforms.modelMatrix;
+        modelMatrix = modelMatrix * instanceData[instanceID].modelMatrix;
+        color *= instanceData[instanceID].color;
     }
 
     float4 worldPosition = modelMatrix * float4(vertexIn.position, 1.0);
     out.position = uniforms.viewProjectionMatrix * worldPosition;
-    out.color = use_vertex_color ? vertexIn.color : (is_instanced ? instanceData[instanceID].color : float4(1.0));
+    out.clipPosition = out.position;
+    out.color = color;
+    out.worldPosition = worldPosition.xyz;
+    out.normal = has_vertex_normal ? (modelMatrix * float4(vertexIn.normal, 0.0)).xyz : float3(0.0);
+    out.texCoord = is_textured ? vertexIn.texCoord : float2(0.0);
+
+    return out;
+}
+
+// Draws any vertex layout while its specialized pipeline compiles, reading
+// only the position at the start of each vertex
+vertex VertexOut vertex_fallback(
+    const device uchar *vertices [[buffer(0)]],
+    constant Uniforms &uniforms [[buffer(1)]],
+    constant InstanceData *instanceData [[buffer(2)]],
+    constant uint &vertexStride [[buffer(6)]],
+    uint vertexID [[vertex_id]],
+    uint instanceID [[instance_id]]
+) {
+    VertexOut out;
+
+    float4x4 modelMatrix = uniforms.modelMatrix;
+    float4 color = float4(1.0);
+    if (is_instanced) {
+        modelMatrix = modelMatrix * instanceData[instanceID].modelMatrix;
+        color *= instanceData[instanceID].color;
+    }
+
+    float3 position = float3(*(const device packed_float3 *)(vertices + vertexID * vertexStride));
+    float4 worldPosition = modelMatrix * float4(position, 1.0);
+    out.position = uniforms.viewProjectionMatrix * worldPosition;
+    out.clipPosition = out.position;
+    out.color = color;
+    out.worldPosition = worldPosition.xyz;
+    out.normal = float3(0.0);
+    out.texCoord = float2(0.0);
 
     return out;
 }
@@ -0,0 +1,344 @@
+//! Character controller module for the physics engine.
+//!
+//! This module provides `CharacterController`, a kinematic capsule moved by
+//! sweeping it through a `RigidBodySystem` rather than by forces. Each move
+//! slides along the surfaces it hits, climbs ledges up to a step height while
+//! on the ground, and falls under gravity, which can be turned off for ships
+//! flying through space. The controller doesn't push bodies; it treats them
+//! and the system's colliders as obstacles.
+//!
+//! The capsule is swept as a row of spheres along its axis, spaced at most a
+//! radius apart, so it may touch sharp edges between two of them slightly late.
+
+use super::collider::CollisionFilter;
+use super::floating_origin::FloatingOrigin;
+use super::rigid_body_system::{RigidBodySystem, SweepHit};
+use super::vector3::Vector3;
+use crate::renderer::{NodeId, SceneError, SceneGraph};
+use glam::Quat;
+
+/// The most surfaces a move slides along before stopping.
+const MAX_SLIDES: usize = 4;
+
+/// The distances below which motion is treated as zero.
+const EPSILON: f64 = 1e-9;
+
+/// A kinematic capsule that walks, falls and flies through a rigid body system.
+#[derive(Clone, Debug)]
+pub struct CharacterController {
+    /// The center of the capsule.
+    pub position: Vector3,
+    /// The velocity of the last move, including falling.
+    pub velocity: Vector3,
+    /// The orientation applied to driven scene nodes, e.g. the facing of a
+    /// character or the attitude of a ship.
+    pub orientation: Quat,
+    /// The radius of the capsule.
+    pub radius: f64,
+    /// The height of the capsule from end to end, at least twice the radius.
+    pub height: f64,
+    /// The unit direction the capsule's axis points along, away from the ground.
+    pub up: Vector3,
+    /// The acceleration the controller falls with, or `None` to fly.
+    pub gravity: Option<Vector3>,
+    /// The tallest ledge the controller climbs without jumping.
+    pub step_height: f64,
+    /// The cosine of the steepest slope the controller stands on.
+    pub max_slope_cos: f64,
+    /// The distance the capsule is kept off the surfaces it touches.
+    pub skin_width: f64,
+    /// The groups the capsule belongs to and collides with.
+    pub filter: CollisionFilter,
+    grounded: bool,
+}
+
+impl CharacterController {
+    /// Creates an upright controller under Earth gravity, climbing steps up
+    /// to a third of its height and standing on slopes up to 45 degrees.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The center of the capsule.
+    /// * `radius` - The radius of the capsule.
+    /// * `height` - The height of the capsule from end to end.
+    pub fn new(position: Vector3, radius: f64, height: f64) -> Self {
+        Self {
+            position,
+            velocity: Vector3::zero(),
+            orientation: Quat::IDENTITY,
+            radius,
+            height: height.max(radius * 2.0),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            gravity: Some(Vector3::new(0.0, -9.81, 0.0)),
+            step_height: height / 3.0,
+            max_slope_cos: std::f64::consts::FRAC_1_SQRT_2,
+            skin_width: 0.01,
+            filter: CollisionFilter::default(),
+            grounded: false,
+        }
+    }
+
+    /// Sets the acceleration the controller falls with, or `None` to fly.
+    pub fn with_gravity(mut self, gravity: Option<Vector3>) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Sets the tallest ledge the controller climbs without jumping.
+    pub fn with_step_height(mut self, step_height: f64) -> Self {
+        self.step_height = step_height.max(0.0);
+        self
+    }
+
+    /// Sets the groups the capsule belongs to and collides with.
+    pub fn with_filter(mut self, filter: CollisionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Returns `true` if the controller stood on walkable ground after its last move.
+    pub fn is_grounded(&self) -> bool {
+        self.grounded
+    }
+
+    /// Launches the controller upward if it stands on the ground.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the controller jumped.
+    pub fn jump(&mut self, speed: f64) -> bool {
+        if !self.grounded {
+            return false;
+        }
+        self.velocity = self.horizontal(self.velocity) + self.up * speed;
+        self.grounded = false;
+        true
+    }
+
+    /// Moves the controller, sliding along whatever it hits.
+    ///
+    /// # Arguments
+    ///
+    /// * `system` - The bodies and colliders the controller moves among.
+    /// * `desired_velocity` - The velocity the controller tries to move at.
+    ///   Under gravity only its horizontal part is used, and the controller
+    ///   keeps falling or jumping; while flying it is used as is.
+    /// * `dt` - The time step in seconds.
+    pub fn move_and_slide(&mut self, system: &RigidBodySystem, desired_velocity: Vector3, dt: f64) {
+        if dt <= 0.0 {
+            return;
+        }
+        self.depenetrate(system);
+
+        self.velocity = match self.gravity {
+            Some(gravity) => {
+                let mut vertical = self.velocity.dot(self.up);
+                if self.grounded {
+                    vertical = vertical.max(0.0);
+                }
+                self.horizontal(desired_velocity) + self.up * vertical + gravity * dt
+            }
+            None => desired_velocity,
+        };
+
+        let start = self.position;
+        let motion = self.velocity * dt;
+        let mut end = self.slide(system, start, motion);
+
+        // Climb ledges the slide was stopped by
+        let desired = self.horizontal(motion).magnitude();
+        let achieved = self.horizontal(end - start).magnitude();
+        if self.grounded && self.step_height > 0.0 && achieved + EPSILON < desired {
+            if let Some(stepped) = self.step(system, start, self.horizontal(motion)) {
+                if self.horizontal(stepped - start).magnitude() > achieved {
+                    end = stepped;
+                }
+            }
+        }
+        self.position = end;
+        self.grounded = self.gravity.is_some() && self.ground(system, end).is_some();
+    }
+
+    /// Places a root scene node at the controller's position and orientation,
+    /// converted to render space by a floating origin.
+    pub fn drive_node(
+        &self,
+        origin: &FloatingOrigin,
+        scene_graph: &mut SceneGraph,
+        node: NodeId,
+    ) -> Result<(), SceneError> {
+        origin.drive_node(scene_graph, node, self.position, self.orientation)
+    }
+
+    /// Returns the part of a vector across the up axis.
+    fn horizontal(&self, vector: Vector3) -> Vector3 {
+        vector - self.up * vector.dot(self.up)
+    }
+
+    /// Returns the offsets along the axis of the spheres the capsule is swept as.
+    fn sphere_offsets(&self) -> impl Iterator<Item = f64> {
+        let half = (self.height * 0.5 - self.radius).max(0.0);
+        let count = ((2.0 * half / self.radius.max(EPSILON)).ceil() as usize).max(1) + 1;
+        (0..count).map(move |k| -half + 2.0 * half * k as f64 / (count - 1) as f64)
+    }
+
+    /// Sweeps the capsule along a motion, returning the first hit.
+    fn cast(&self, system: &RigidBodySystem, from: Vector3, motion: Vector3) -> Option<SweepHit> {
+        if motion.magnitude() < EPSILON {
+            return None;
+        }
+        self.sphere_offsets()
+            .filter_map(|offset| {
+                let center = from + self.up * offset;
+                system.sweep_sphere(center, center + motion, self.radius, self.filter)
+            })
+            .min_by(|a, b| a.time.total_cmp(&b.time))
+    }
+
+    /// Moves the capsule along a motion until it touches something, keeping
+    /// the skin width off the surface.
+    fn advance(
+        &self,
+        system: &RigidBodySystem,
+        from: Vector3,
+        motion: Vector3,
+    ) -> (Vector3, Option<SweepHit>) {
+        let Some(hit) = self.cast(system, from, motion) else {
+            return (from + motion, None);
+        };
+        let length = motion.magnitude();
+        let travel = (hit.time * length - self.skin_width).max(0.0);
+        (from + motion * (travel / length), Some(hit))
+    }
+
+    /// Moves the capsule along a motion, sliding along the surfaces it hits
+    /// and losing the velocity into them.
+    fn slide(&mut self, system: &RigidBodySystem, from: Vector3, motion: Vector3) -> Vector3 {
+        let mut position = from;
+        let mut remaining = motion;
+        for _ in 0..MAX_SLIDES {
+            let (end, hit) = self.advance(system, position, remaining);
+            remaining -= end - position;
+            position = end;
+            let Some(hit) = hit else {
+                break;
+            };
+            remaining -= hit.normal * remaining.dot(hit.normal);
+            let into = self.velocity.dot(hit.normal);
+            if into < 0.0 {
+                self.velocity -= hit.normal * into;
+            }
+            if remaining.magnitude() < EPSILON {
+                break;
+            }
+        }
+        position
+    }
+
+    /// Tries to climb a ledge by rising the step height, moving forward and
+    /// settling back down.
+    ///
+    /// # Returns
+    ///
+    /// Where the controller lands, or `None` if it wouldn't land on walkable ground.
+    fn step(&self, system: &RigidBodySystem, from: Vector3, forward: Vector3) -> Option<Vector3> {
+        let (raised, _) = self.advance(system, from, self.up * self.step_height);
+        let (moved, _) = self.advance(system, raised, forward);
+        let drop = (raised - from).dot(self.up) + self.skin_width;
+        let (landed, ground) = self.advance(system, moved, self.up * -drop);
+        ground
+            .filter(|ground| self.is_walkable(ground))
+            .map(|_| landed)
+    }
+
+    /// Returns the walkable ground just below the capsule at a position, if any.
+    fn ground(&self, system: &RigidBodySystem, position: Vector3) -> Option<SweepHit> {
+        self.cast(system, position, self.up * (-2.0 * self.skin_width))
+            .filter(|hit| self.is_walkable(hit))
+    }
+
+    /// Returns `true` if a surface is flat enough to stand on.
+    fn is_walkable(&self, hit: &SweepHit) -> bool {
+        hit.normal.dot(self.up) >= self.max_slope_cos
+    }
+
+    /// Pushes the capsule out of anything it overlaps, e.g. a body that moved into it.
+    fn depenetrate(&mut self, system: &RigidBodySystem) {
+        for offset in self.sphere_offsets() {
+            let center = self.position + self.up * offset;
+            let push = system
+                .overlap_sphere(center, self.radius, self.filter)
+                .fold(Vector3::zero(), |push, (_, normal, depth)| {
+                    push + normal * (depth + self.skin_width)
+                });
+            self.position += push;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CharacterController;
+    use crate::physics::collider::{Collider, ColliderShape};
+    use crate::physics::rigid_body_system::RigidBodySystem;
+    use crate::physics::vector3::Vector3;
+
+    fn level() -> RigidBodySystem {
+        let mut system = RigidBodySystem::new();
+        system.add_collider(Collider::new(
+            ColliderShape::Plane {
+                normal: Vector3::new(0.0, 1.0, 0.0),
+            },
+            Vector3::zero(),
+        ));
+        // A low ledge, then a wall
+        system.add_collider(Collider::new(
+            ColliderShape::Box {
+                half_extents: Vector3::new(1.0, 0.15, 5.0),
+            },
+            Vector3::new(3.0, 0.15, 0.0),
+        ));
+        system.add_collider(Collider::new(
+            ColliderShape::Box {
+                half_extents: Vector3::new(0.5, 5.0, 5.0),
+            },
+            Vector3::new(8.0, 5.0, 0.0),
+        ));
+        system
+    }
+
+    #[test]
+    fn test_controller_walks_steps_and_slides() {
+        let system = level();
+        let mut controller = CharacterController::new(Vector3::new(0.0, 2.0, 0.0), 0.3, 1.8);
+
+        // Falls onto the floor and stands there
+        for _ in 0..120 {
+            controller.move_and_slide(&system, Vector3::zero(), 1.0 / 60.0);
+        }
+        assert!(controller.is_grounded());
+        assert!((controller.position.y - 0.9).abs() < 0.05);
+
+        // Climbs the ledge, then slides along the wall it walks into diagonally
+        for _ in 0..240 {
+            controller.move_and_slide(&system, Vector3::new(3.0, 0.0, 1.0), 1.0 / 60.0);
+        }
+        assert!(controller.position.x < 7.5 - 0.3 + 1e-6);
+        assert!(controller.position.x > 7.0);
+        assert!(controller.position.z > 3.0);
+        assert!(controller.is_grounded());
+    }
+
+    #[test]
+    fn test_controller_flies_without_gravity() {
+        let system = level();
+        let mut ship =
+            CharacterController::new(Vector3::new(0.0, 3.0, 0.0), 0.5, 1.0).with_gravity(None);
+        for _ in 0..60 {
+            ship.move_and_slide(&system, Vector3::new(0.0, 0.0, -2.0), 1.0 / 60.0);
+        }
+        assert!((ship.position.y - 3.0).abs() < 1e-9);
+        assert!((ship.position.z + 2.0).abs() < 1e-6);
+        assert!(!ship.is_grounded());
+    }
+}
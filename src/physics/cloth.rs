@@ -0,0 +1,504 @@
+//! Cloth module for the physics engine.
+//!
+//! This module provides `Cloth`, a position-based dynamics solver for
+//! triangle meshes such as flags, sails and membranes. Every vertex is a
+//! particle moved by gravity and wind; distance constraints along the mesh's
+//! edges keep it from stretching, and bending constraints across pairs of
+//! triangles sharing an edge keep it from folding. Vertices are pushed out of
+//! sphere and plane colliders after every solver iteration, and pinned
+//! vertices stay where they are put, e.g. along a flagpole.
+//!
+//! Positions are kept in `f32` so they can be handed straight to a
+//! `DynamicMesh` for rendering every step.
+
+use super::determinism::Determinism;
+use glam::{Mat4, Vec2, Vec3};
+use std::collections::HashMap;
+
+/// The lengths below which distances are treated as zero.
+const EPSILON: f32 = 1e-6;
+
+/// A shape cloth vertices are kept out of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClothCollider {
+    /// The half-space behind a plane, whose points satisfy `normal · p < distance`.
+    Plane { normal: Vec3, distance: f32 },
+    /// A solid sphere.
+    Sphere { center: Vec3, radius: f32 },
+}
+
+impl ClothCollider {
+    /// Returns where a point is pushed to, keeping `thickness` away from the
+    /// collider's surface, or `None` if it lies outside.
+    fn resolve(&self, point: Vec3, thickness: f32) -> Option<Vec3> {
+        match *self {
+            ClothCollider::Plane { normal, distance } => {
+                let normal = normal.normalize_or_zero();
+                let depth = normal.dot(point) - distance - thickness;
+                (depth < 0.0).then(|| point - normal * depth)
+            }
+            ClothCollider::Sphere { center, radius } => {
+                let offset = point - center;
+                let radius = radius + thickness;
+                let distance = offset.length();
+                if distance >= radius {
+                    return None;
+                }
+                let direction = if distance < EPSILON {
+                    Vec3::Y
+                } else {
+                    offset / distance
+                };
+                Some(center + direction * radius)
+            }
+        }
+    }
+}
+
+/// Keeps two vertices at their rest distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DistanceConstraint {
+    a: usize,
+    b: usize,
+    rest_length: f32,
+}
+
+/// A triangle mesh simulated with position-based dynamics.
+#[derive(Clone, Debug)]
+pub struct Cloth {
+    positions: Vec<Vec3>,
+    previous_positions: Vec<Vec3>,
+    inverse_masses: Vec<f32>,
+    pinned: Vec<bool>,
+    triangles: Vec<[u32; 3]>,
+    stretch_constraints: Vec<DistanceConstraint>,
+    bend_constraints: Vec<DistanceConstraint>,
+    /// The acceleration applied to every free vertex.
+    pub gravity: Vec3,
+    /// The velocity of the air. Each triangle is pushed along its normal in
+    /// proportion to its area and the air's velocity relative to it.
+    pub wind: Vec3,
+    /// The fraction of their velocity vertices lose per second.
+    pub damping: f32,
+    /// How strongly edges resist stretching, from 0 to 1.
+    pub stretch_stiffness: f32,
+    /// How strongly pairs of triangles resist folding, from 0 to 1.
+    pub bend_stiffness: f32,
+    /// The number of solver iterations per step. More iterations make the
+    /// cloth stiffer and collisions more accurate.
+    pub iterations: usize,
+    /// The distance vertices keep from colliders.
+    pub thickness: f32,
+    /// The shapes vertices are kept out of.
+    pub colliders: Vec<ClothCollider>,
+    /// How strictly steps are kept reproducible. Deterministic steps damp
+    /// linearly and don't rescale stiffness by the iteration count, avoiding
+    /// `powf`, so stiffness depends on `iterations`.
+    pub determinism: Determinism,
+}
+
+impl Cloth {
+    /// Creates cloth from a triangle mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `positions` - The rest positions of the vertices.
+    /// * `triangles` - The vertex indices of the triangles.
+    /// * `mass` - The total mass of the cloth, divided evenly between its vertices.
+    ///
+    /// # Returns
+    ///
+    /// The cloth at rest, with a distance constraint along every edge and a
+    /// bending constraint across every edge shared by two triangles.
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[u32; 3]>, mass: f32) -> Self {
+        let inverse_mass = if mass > 0.0 {
+            positions.len() as f32 / mass
+        } else {
+            0.0
+        };
+
+        // The vertex opposite each edge in the first triangle it was found in
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut stretch_constraints = Vec::new();
+        let mut bend_constraints = Vec::new();
+        let constraint = |a: usize, b: usize| DistanceConstraint {
+            a,
+            b,
+            rest_length: positions[a].distance(positions[b]),
+        };
+        for triangle in &triangles {
+            for corner in 0..3 {
+                let a = triangle[corner] as usize;
+                let b = triangle[(corner + 1) % 3] as usize;
+                let opposite = triangle[(corner + 2) % 3] as usize;
+                match edges.get(&(a.min(b), a.max(b))) {
+                    Some(&other) => bend_constraints.push(constraint(other, opposite)),
+                    None => {
+                        edges.insert((a.min(b), a.max(b)), opposite);
+                        stretch_constraints.push(constraint(a, b));
+                    }
+                }
+            }
+        }
+
+        Self {
+            previous_positions: positions.clone(),
+            inverse_masses: vec![inverse_mass; positions.len()],
+            pinned: vec![false; positions.len()],
+            positions,
+            triangles,
+            stretch_constraints,
+            bend_constraints,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            wind: Vec3::ZERO,
+            damping: 0.1,
+            stretch_stiffness: 1.0,
+            bend_stiffness: 0.1,
+            iterations: 8,
+            thickness: 0.01,
+            colliders: Vec::new(),
+            determinism: Determinism::Off,
+        }
+    }
+
+    /// Creates a rectangular sheet of cloth in the XY plane.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The number of cells along X.
+    /// * `rows` - The number of cells along Y.
+    /// * `size` - The width and height of the sheet, which spans from the origin to `size`.
+    /// * `mass` - The total mass of the sheet.
+    ///
+    /// # Returns
+    ///
+    /// The sheet, whose vertex at `column` and `row` has the index
+    /// `row * (columns + 1) + column`.
+    pub fn grid(columns: usize, rows: usize, size: Vec2, mass: f32) -> Self {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        let cell = size / Vec2::new(columns as f32, rows as f32);
+        let positions = (0..=rows)
+            .flat_map(|row| {
+                (0..=columns)
+                    .map(move |column| Vec3::new(column as f32 * cell.x, row as f32 * cell.y, 0.0))
+            })
+            .collect();
+        let stride = columns as u32 + 1;
+        let triangles = (0..rows as u32)
+            .flat_map(|row| {
+                (0..columns as u32).flat_map(move |column| {
+                    let corner = row * stride + column;
+                    [
+                        [corner, corner + 1, corner + stride + 1],
+                        [corner, corner + stride + 1, corner + stride],
+                    ]
+                })
+            })
+            .collect();
+        Self::new(positions, triangles, mass)
+    }
+
+    /// Moves the cloth at rest by a transform, e.g. to stand a grid upright at a flagpole.
+    pub fn with_transform(mut self, transform: Mat4) -> Self {
+        for position in &mut self.positions {
+            *position = transform.transform_point3(*position);
+        }
+        self.previous_positions.clone_from(&self.positions);
+        self
+    }
+
+    /// Adds a shape the cloth's vertices are kept out of.
+    pub fn with_collider(mut self, collider: ClothCollider) -> Self {
+        self.colliders.push(collider);
+        self
+    }
+
+    /// Returns the number of vertices.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns `true` if the cloth has no vertices.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Returns the positions of the vertices.
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    /// Returns the vertex indices of the triangles.
+    pub fn triangles(&self) -> &[[u32; 3]] {
+        &self.triangles
+    }
+
+    /// Returns the area-weighted normals of the vertices.
+    pub fn normals(&self) -> Vec<Vec3> {
+        vertex_normals(&self.positions, &self.triangles)
+    }
+
+    /// Returns the velocity of a vertex over the last step.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the vertex.
+    /// * `dt` - The duration of the last step in seconds.
+    pub fn velocity(&self, index: usize, dt: f32) -> Vec3 {
+        if dt <= 0.0 {
+            return Vec3::ZERO;
+        }
+        (self.positions[index] - self.previous_positions[index]) / dt
+    }
+
+    /// Pins a vertex in place, so the solver never moves it.
+    pub fn pin(&mut self, index: usize) {
+        self.pinned[index] = true;
+    }
+
+    /// Releases a pinned vertex.
+    pub fn unpin(&mut self, index: usize) {
+        self.pinned[index] = false;
+    }
+
+    /// Returns `true` if a vertex is pinned.
+    pub fn is_pinned(&self, index: usize) -> bool {
+        self.pinned[index]
+    }
+
+    /// Moves a vertex, e.g. to drag a pinned corner along with a mast.
+    ///
+    /// The vertex keeps the velocity it had, so a free vertex is teleported
+    /// rather than flung.
+    pub fn set_position(&mut self, index: usize, position: Vec3) {
+        let offset = position - self.positions[index];
+        self.positions[index] = position;
+        self.previous_positions[index] += offset;
+    }
+
+    /// Advances the simulation.
+    ///
+    /// # Arguments
+    ///
+    /// * `dt` - The time step in seconds. Steps of a fixed, small length
+    ///   keep the cloth stable.
+    pub fn step(&mut self, dt: f32) {
+        if dt <= 0.0 || self.positions.is_empty() {
+            return;
+        }
+
+        let weights: Vec<f32> = (0..self.positions.len())
+            .map(|index| self.inverse_mass(index))
+            .collect();
+        let accelerations = self.external_accelerations(dt);
+        let deterministic = self.determinism.is_deterministic();
+        let retained = if deterministic {
+            (1.0 - self.damping * dt).clamp(0.0, 1.0)
+        } else {
+            (1.0 - self.damping).clamp(0.0, 1.0).powf(dt)
+        };
+        for (((position, previous), acceleration), weight) in self
+            .positions
+            .iter_mut()
+            .zip(&mut self.previous_positions)
+            .zip(accelerations)
+            .zip(&weights)
+        {
+            if *weight == 0.0 {
+                *previous = *position;
+                continue;
+            }
+            let velocity = (*position - *previous) / dt * retained + acceleration * dt;
+            *previous = *position;
+            *position += velocity * dt;
+        }
+
+        // Scale stiffness so the cloth behaves the same at any iteration count
+        let iterations = self.iterations.max(1);
+        let per_iteration = |stiffness: f32| {
+            let stiffness = stiffness.clamp(0.0, 1.0);
+            if deterministic {
+                return stiffness;
+            }
+            1.0 - (1.0 - stiffness).powf(1.0 / iterations as f32)
+        };
+        let stretch_stiffness = per_iteration(self.stretch_stiffness);
+        let bend_stiffness = per_iteration(self.bend_stiffness);
+        for _ in 0..iterations {
+            solve_distances(
+                &mut self.positions,
+                &weights,
+                &self.stretch_constraints,
+                stretch_stiffness,
+            );
+            solve_distances(
+                &mut self.positions,
+                &weights,
+                &self.bend_constraints,
+                bend_stiffness,
+            );
+            self.collide();
+        }
+    }
+
+    /// Returns the inverse mass of a vertex, zero if it is pinned.
+    fn inverse_mass(&self, index: usize) -> f32 {
+        if self.pinned[index] {
+            0.0
+        } else {
+            self.inverse_masses[index]
+        }
+    }
+
+    /// Returns the acceleration of every vertex from gravity and wind.
+    fn external_accelerations(&self, dt: f32) -> Vec<Vec3> {
+        let mut accelerations = vec![self.gravity; self.positions.len()];
+        if self.wind == Vec3::ZERO {
+            return accelerations;
+        }
+        for triangle in &self.triangles {
+            let [a, b, c] = triangle.map(|index| index as usize);
+            let (pa, pb, pc) = (self.positions[a], self.positions[b], self.positions[c]);
+            // The cross product's length is twice the triangle's area
+            let area_normal = (pb - pa).cross(pc - pa) * 0.5;
+            let Some(normal) = area_normal.try_normalize() else {
+                continue;
+            };
+            let velocity =
+                (self.velocity(a, dt) + self.velocity(b, dt) + self.velocity(c, dt)) / 3.0;
+            let force = normal * normal.dot(self.wind - velocity) * area_normal.length();
+            for index in [a, b, c] {
+                accelerations[index] += force / 3.0 * self.inverse_masses[index];
+            }
+        }
+        accelerations
+    }
+
+    /// Pushes free vertices out of the colliders.
+    fn collide(&mut self) {
+        for index in 0..self.positions.len() {
+            if self.inverse_mass(index) == 0.0 {
+                continue;
+            }
+            for collider in &self.colliders {
+                if let Some(position) = collider.resolve(self.positions[index], self.thickness) {
+                    self.positions[index] = position;
+                }
+            }
+        }
+    }
+}
+
+/// Moves the vertices of distance constraints toward their rest distances.
+///
+/// # Arguments
+///
+/// * `positions` - The positions of the vertices.
+/// * `weights` - The inverse masses of the vertices, zero for pinned vertices.
+/// * `constraints` - The constraints to solve.
+/// * `stiffness` - The fraction of each constraint's error corrected, from 0 to 1.
+fn solve_distances(
+    positions: &mut [Vec3],
+    weights: &[f32],
+    constraints: &[DistanceConstraint],
+    stiffness: f32,
+) {
+    if stiffness <= 0.0 {
+        return;
+    }
+    for constraint in constraints {
+        let (a, b) = (constraint.a, constraint.b);
+        let total_weight = weights[a] + weights[b];
+        let delta = positions[b] - positions[a];
+        let length = delta.length();
+        if total_weight == 0.0 || length < EPSILON {
+            continue;
+        }
+        let correction =
+            delta * ((length - constraint.rest_length) / (length * total_weight) * stiffness);
+        positions[a] += correction * weights[a];
+        positions[b] -= correction * weights[b];
+    }
+}
+
+/// Returns the area-weighted normals of a triangle mesh's vertices.
+///
+/// # Arguments
+///
+/// * `positions` - The positions of the vertices.
+/// * `triangles` - The vertex indices of the triangles.
+///
+/// # Returns
+///
+/// One unit normal per vertex, or zero for vertices in no triangle.
+pub fn vertex_normals(positions: &[Vec3], triangles: &[[u32; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in triangles {
+        let [a, b, c] = triangle.map(|index| index as usize);
+        // Unnormalized, so larger triangles weigh more
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        for index in [a, b, c] {
+            normals[index] += normal;
+        }
+    }
+    for normal in &mut normals {
+        *normal = normal.normalize_or_zero();
+    }
+    normals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cloth, ClothCollider};
+    use glam::{Vec2, Vec3};
+
+    #[test]
+    fn test_cloth_grid_constraints() {
+        let cloth = Cloth::grid(2, 1, Vec2::new(2.0, 1.0), 1.0);
+        assert_eq!(cloth.len(), 6);
+        assert_eq!(cloth.triangles().len(), 4);
+        // Nine unique edges, of which two diagonals and the middle edge are shared
+        assert_eq!(cloth.stretch_constraints.len(), 9);
+        assert_eq!(cloth.bend_constraints.len(), 3);
+        assert!(cloth
+            .normals()
+            .iter()
+            .all(|normal| normal.abs_diff_eq(Vec3::Z, 1e-6)));
+    }
+
+    #[test]
+    fn test_cloth_hangs_and_collides() {
+        // A horizontal sheet pinned along one edge, draped over a sphere
+        let mut cloth = Cloth::grid(8, 8, Vec2::splat(2.0), 1.0)
+            .with_transform(glam::Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2))
+            .with_collider(ClothCollider::Sphere {
+                center: Vec3::new(1.0, -1.0, 0.0),
+                radius: 0.5,
+            })
+            .with_collider(ClothCollider::Plane {
+                normal: Vec3::Y,
+                distance: -1.6,
+            });
+        for column in 0..=8 {
+            cloth.pin(column);
+        }
+        let pinned: Vec<Vec3> = cloth.positions()[..9].to_vec();
+        for _ in 0..240 {
+            cloth.step(1.0 / 60.0);
+        }
+
+        assert_eq!(&cloth.positions()[..9], pinned.as_slice());
+        let far_edge = cloth.positions()[cloth.len() - 5];
+        assert!(far_edge.y < -0.5, "cloth fell to {far_edge}");
+        for position in cloth.positions() {
+            assert!(position.distance(Vec3::new(1.0, -1.0, 0.0)) > 0.5);
+            assert!(position.y > -1.6);
+        }
+        // Edges stay close to their rest length
+        for constraint in &cloth.stretch_constraints {
+            let length = cloth.positions()[constraint.a].distance(cloth.positions()[constraint.b]);
+            assert!((length - constraint.rest_length).abs() < 0.1 * constraint.rest_length);
+        }
+    }
+}
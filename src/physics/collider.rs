@@ -0,0 +1,315 @@
+//! Collider module for the physics engine.
+//!
+//! This module provides the static `Collider`s bodies of a `RigidBodySystem`
+//! bounce off. Bodies are spheres, so every shape answers two questions: how
+//! deep a sphere overlaps it, for the discrete test after each step, and when
+//! a sphere moving along a segment first touches it, for continuous collision
+//! detection. Sweeping lets fast bodies hit thin colliders they would
+//! otherwise pass through between two steps.
+//!
+//! Terrain and level geometry collide as `Heightfield`s and `TriangleMesh`es,
+//! shared between colliders behind an `Arc`. `Renderer::mesh_colliders`
+//! creates triangle mesh colliders for the scene nodes whose meshes are
+//! flagged as collidable.
+//!
+//! Bodies and colliders carry a `CollisionFilter` of the groups they belong
+//! to and the groups they collide with, so e.g. debris can ignore other
+//! debris. Trigger colliders report the bodies overlapping them instead of
+//! pushing them out.
+
+use super::heightfield::Heightfield;
+use super::triangle_mesh::TriangleMesh;
+use super::vector3::Vector3;
+use crate::math::geometry::{
+    closest_point_on_box, intersect_ray_box, intersect_ray_sphere, intersect_segment_plane,
+    signed_distance_to_plane, signed_distance_to_sphere, GeometryVector,
+};
+use std::sync::Arc;
+
+/// The groups a body or collider belongs to and the groups it collides with.
+///
+/// Two objects collide only if each one's mask contains a group of the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CollisionFilter {
+    /// The groups the object belongs to, one per bit.
+    pub groups: u32,
+    /// The groups the object collides with, one per bit.
+    pub mask: u32,
+}
+
+impl CollisionFilter {
+    /// The group objects belong to unless told otherwise.
+    pub const DEFAULT_GROUP: u32 = 1 << 0;
+
+    /// Creates a filter.
+    pub const fn new(groups: u32, mask: u32) -> Self {
+        Self { groups, mask }
+    }
+
+    /// Returns `true` if objects with the two filters collide.
+    pub fn collides_with(self, other: CollisionFilter) -> bool {
+        self.groups & other.mask != 0 && other.groups & self.mask != 0
+    }
+}
+
+impl Default for CollisionFilter {
+    /// In the default group, colliding with every group.
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_GROUP, u32::MAX)
+    }
+}
+
+/// The shape of a collider, placed at the collider's position.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColliderShape {
+    /// A solid sphere.
+    Sphere { radius: f64 },
+    /// The half-space behind a plane through the position, with bodies kept
+    /// on the side the normal points to.
+    Plane { normal: Vector3 },
+    /// An axis-aligned box, such as a wall or a floor.
+    Box { half_extents: Vector3 },
+    /// Heights on a grid from the position along +x and +z, such as terrain,
+    /// solid below the surface.
+    Heightfield(Arc<Heightfield>),
+    /// Static triangles around the position, such as level geometry or an
+    /// imported model.
+    TriangleMesh(Arc<TriangleMesh>),
+}
+
+/// Where a moving sphere first touches a collider.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// The fraction of the sweep travelled before the contact, from 0 to 1.
+    pub time: f64,
+    /// The collider's surface normal at the contact.
+    pub normal: Vector3,
+}
+
+/// A static shape bodies collide with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    pub position: Vector3,
+    /// The fraction of their approaching speed bodies keep when bouncing off.
+    pub restitution: f64,
+    /// The groups the collider belongs to and collides with.
+    pub filter: CollisionFilter,
+    /// Whether the collider only reports overlapping bodies, without pushing them out.
+    pub trigger: bool,
+}
+
+impl Collider {
+    /// Creates a collider that bodies bounce off at half their speed.
+    pub fn new(shape: ColliderShape, position: Vector3) -> Self {
+        Self {
+            shape,
+            position,
+            restitution: 0.5,
+            filter: CollisionFilter::default(),
+            trigger: false,
+        }
+    }
+
+    /// Sets the groups the collider belongs to and collides with.
+    pub fn with_filter(mut self, filter: CollisionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Makes the collider a trigger, which reports overlapping bodies without pushing them out.
+    pub fn with_trigger(mut self, trigger: bool) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// Sets the fraction of their approaching speed bodies keep when bouncing off.
+    pub fn with_restitution(mut self, restitution: f64) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    /// Finds how a sphere overlaps the collider.
+    ///
+    /// # Arguments
+    ///
+    /// * `center` - The center of the sphere.
+    /// * `radius` - The radius of the sphere.
+    ///
+    /// # Returns
+    ///
+    /// The direction to push the sphere out along and the distance to push
+    /// it, or `None` if the sphere doesn't overlap the collider.
+    pub fn penetration(&self, center: Vector3, radius: f64) -> Option<(Vector3, f64)> {
+        let offset = center - self.position;
+        match &self.shape {
+            &ColliderShape::Sphere {
+                radius: collider_radius,
+            } => {
+                let depth =
+                    radius - signed_distance_to_sphere(center, self.position, collider_radius);
+                if depth <= 0.0 {
+                    return None;
+                }
+                let normal = if offset != Vector3::zero() {
+                    offset.normalize()
+                } else {
+                    Vector3::new(0.0, 1.0, 0.0)
+                };
+                Some((normal, depth))
+            }
+            &ColliderShape::Plane { normal } => {
+                let normal = normal.normalize();
+                let depth = radius - signed_distance_to_plane(center, self.position, normal);
+                (depth > 0.0).then_some((normal, depth))
+            }
+            &ColliderShape::Box { half_extents } => {
+                let closest =
+                    closest_point_on_box(offset, Vector3::zero() - half_extents, half_extents);
+                let outside = offset - closest;
+                let distance = outside.magnitude();
+                if distance > 0.0 {
+                    return (distance < radius).then_some((outside / distance, radius - distance));
+                }
+
+                // The center is inside, so leave through the nearest face
+                let faces = [
+                    (
+                        half_extents.x - offset.x.abs(),
+                        Vector3::new(offset.x.signum(), 0.0, 0.0),
+                    ),
+                    (
+                        half_extents.y - offset.y.abs(),
+                        Vector3::new(0.0, offset.y.signum(), 0.0),
+                    ),
+                    (
+                        half_extents.z - offset.z.abs(),
+                        Vector3::new(0.0, 0.0, offset.z.signum()),
+                    ),
+                ];
+                let (distance, normal) = faces
+                    .into_iter()
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .unwrap_or((0.0, Vector3::new(0.0, 1.0, 0.0)));
+                Some((normal, distance + radius))
+            }
+            ColliderShape::Heightfield(heightfield) => heightfield.penetration(offset, radius),
+            ColliderShape::TriangleMesh(mesh) => mesh.penetration(offset, radius),
+        }
+    }
+
+    /// Finds where a sphere moving along a segment first touches the collider.
+    ///
+    /// Boxes are swept as boxes grown by the radius, so spheres hitting their
+    /// edges and corners touch slightly early.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The center of the sphere before moving.
+    /// * `end` - The center of the sphere after moving.
+    /// * `radius` - The radius of the sphere.
+    ///
+    /// # Returns
+    ///
+    /// The first contact, or `None` if the sphere misses the collider or
+    /// already overlaps it at the start.
+    pub fn sweep(&self, start: Vector3, end: Vector3, radius: f64) -> Option<Contact> {
+        let origin = start - self.position;
+        let motion = end - start;
+        match &self.shape {
+            &ColliderShape::Sphere {
+                radius: collider_radius,
+            } => {
+                let time = intersect_ray_sphere(
+                    origin,
+                    motion,
+                    Vector3::zero(),
+                    collider_radius + radius,
+                )?;
+                (time <= 1.0).then(|| Contact {
+                    time,
+                    normal: (origin + motion * time).normalize(),
+                })
+            }
+            &ColliderShape::Plane { normal } => {
+                // The center crosses the plane pushed out by the radius
+                let normal = normal.normalize();
+                let time =
+                    intersect_segment_plane(origin, origin + motion, normal * radius, normal)?;
+                Some(Contact { time, normal })
+            }
+            &ColliderShape::Box { half_extents } => {
+                let extents = half_extents + Vector3::new(radius, radius, radius);
+                let (time, axis) =
+                    intersect_ray_box(origin, motion, Vector3::zero() - extents, extents)?;
+                if time > 1.0 {
+                    return None;
+                }
+                let mut normal = [0.0; 3];
+                normal[axis] = -motion.to_array()[axis].signum();
+                Some(Contact {
+                    time,
+                    normal: Vector3::from_array(normal),
+                })
+            }
+            ColliderShape::Heightfield(heightfield) => {
+                heightfield.sweep(origin, origin + motion, radius)
+            }
+            ColliderShape::TriangleMesh(mesh) => mesh.sweep(origin, origin + motion, radius),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Collider, ColliderShape, CollisionFilter};
+    use crate::physics::vector3::Vector3;
+
+    #[test]
+    fn test_collider_sweeps() {
+        let start = Vector3::new(-10.0, 0.0, 0.0);
+        let end = Vector3::new(10.0, 0.0, 0.0);
+
+        // A thin wall is hit a radius before its face
+        let wall = Collider::new(
+            ColliderShape::Box {
+                half_extents: Vector3::new(0.01, 5.0, 5.0),
+            },
+            Vector3::zero(),
+        );
+        let contact = wall.sweep(start, end, 0.5).unwrap();
+        assert!((contact.time - (10.0 - 0.51) / 20.0).abs() < 1e-12);
+        assert_eq!(contact.normal, Vector3::new(-1.0, 0.0, 0.0));
+        assert!(wall.penetration(start, 0.5).is_none());
+        assert!(wall.penetration(Vector3::zero(), 0.5).is_some());
+
+        let sphere = Collider::new(ColliderShape::Sphere { radius: 1.0 }, Vector3::zero());
+        let contact = sphere.sweep(start, end, 1.0).unwrap();
+        assert!((contact.time - 0.4).abs() < 1e-12);
+        assert!(sphere
+            .sweep(start, Vector3::new(-5.0, 0.0, 0.0), 1.0)
+            .is_none());
+
+        // Sweeps only count crossings from the plane's front
+        let plane = Collider::new(
+            ColliderShape::Plane {
+                normal: Vector3::new(1.0, 0.0, 0.0),
+            },
+            Vector3::zero(),
+        );
+        assert!(plane.sweep(start, end, 0.0).is_none());
+        let contact = plane.sweep(end, start, 0.0).unwrap();
+        assert!((contact.time - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_collision_filters() {
+        const DEBRIS: u32 = 1 << 1;
+        let debris = CollisionFilter::new(DEBRIS, !DEBRIS);
+        assert!(!debris.collides_with(debris));
+        assert!(debris.collides_with(CollisionFilter::default()));
+        // Both sides must accept each other
+        let ghost = CollisionFilter::new(CollisionFilter::DEFAULT_GROUP, 0);
+        assert!(!ghost.collides_with(CollisionFilter::default()));
+    }
+}
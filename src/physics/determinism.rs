@@ -0,0 +1,172 @@
+//! Determinism module for the physics engine.
+//!
+//! This module provides `Determinism`, the modes a simulation steps in, and
+//! `Fixed`, the fixed-point number deterministic rigid bodies are integrated
+//! with. Simulations that must replay identically, such as recorded runs or
+//! future lockstep networking, step in one of the deterministic modes.
+//!
+//! IEEE 754 addition, subtraction, multiplication, division and square roots
+//! are correctly rounded, so they give the same bits on every platform.
+//! Transcendental functions such as `powf` come from the platform's math
+//! library and may differ in the last bit, so deterministic steps avoid them.
+//! No solver sums across threads: with the `parallel` feature, every
+//! particle's values are summed over its neighbours in a fixed order by a
+//! single task, so parallel and serial steps give identical results.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// How strictly a simulation keeps its steps reproducible.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Determinism {
+    /// Steps use whatever math is fastest, and may differ slightly between
+    /// platforms.
+    #[default]
+    Off,
+    /// Steps visit bodies and constraints in a fixed order and use only
+    /// correctly rounded float operations, so they replay identically on
+    /// platforms with IEEE 754 floats.
+    Strict,
+    /// As `Strict`, and rigid bodies are integrated in 32.32 fixed point,
+    /// with positions and velocities snapped to the fixed-point grid after
+    /// every step.
+    FixedPoint,
+}
+
+impl Determinism {
+    /// Returns `true` if steps must replay identically.
+    pub fn is_deterministic(self) -> bool {
+        self != Determinism::Off
+    }
+}
+
+/// A signed 32.32 fixed-point number.
+///
+/// Arithmetic is exact integer arithmetic, rounding toward negative infinity
+/// after multiplication and division, so results are identical everywhere.
+/// Values range over roughly ±2.1 billion, in steps of about 2.3e-10, and
+/// results beyond the range saturate at its ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    /// The number of bits after the binary point.
+    pub const FRACTION_BITS: u32 = 32;
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << Self::FRACTION_BITS);
+
+    /// Creates a number from its raw bits.
+    pub const fn from_bits(bits: i64) -> Self {
+        Fixed(bits)
+    }
+
+    /// Returns the raw bits of the number.
+    pub const fn to_bits(self) -> i64 {
+        self.0
+    }
+
+    /// Rounds a float to the nearest fixed-point number, saturating at the ends of the range.
+    pub fn from_f64(value: f64) -> Self {
+        Fixed((value * Self::ONE.0 as f64).round() as i64)
+    }
+
+    /// Returns the number as a float, which holds it exactly within ±2^21.
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Self::ONE.0 as f64
+    }
+
+    /// Snaps a float to the nearest value a fixed-point number holds.
+    pub fn snap(value: f64) -> f64 {
+        Self::from_f64(value).to_f64()
+    }
+
+    /// Clamps a wide intermediate result to the range.
+    fn saturate(value: i128) -> Self {
+        Fixed(value.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Fixed(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Fixed(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Fixed::saturate((self.0 as i128 * other.0 as i128) >> Self::FRACTION_BITS)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+
+    /// Divides, returning zero when dividing by zero.
+    fn div(self, other: Self) -> Self {
+        if other.0 == 0 {
+            return Fixed::ZERO;
+        }
+        Fixed::saturate(((self.0 as i128) << Self::FRACTION_BITS).div_euclid(other.0 as i128))
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fixed;
+
+    #[test]
+    fn test_fixed_arithmetic() {
+        let a = Fixed::from_f64(2.5);
+        let b = Fixed::from_f64(-0.25);
+        assert_eq!((a + b).to_f64(), 2.25);
+        assert_eq!((a - b).to_f64(), 2.75);
+        assert_eq!((a * b).to_f64(), -0.625);
+        assert_eq!((a / b).to_f64(), -10.0);
+        assert_eq!((-a).to_f64(), -2.5);
+        assert_eq!(a / Fixed::ZERO, Fixed::ZERO);
+
+        // Values off the grid round to the nearest step
+        let third = Fixed::ONE / Fixed::from_f64(3.0);
+        assert!((third.to_f64() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(Fixed::snap(third.to_f64()), third.to_f64());
+    }
+
+    #[test]
+    fn test_fixed_saturation() {
+        let max = Fixed::from_bits(i64::MAX);
+        let min = Fixed::from_bits(i64::MIN);
+        assert_eq!(max + Fixed::ONE, max);
+        assert_eq!(min - Fixed::ONE, min);
+        assert_eq!(-min, max);
+        assert_eq!(max * Fixed::from_f64(2.0), max);
+        assert_eq!(max * Fixed::from_f64(-2.0), min);
+        assert_eq!(max / Fixed::from_f64(0.5), max);
+        assert_eq!(min / Fixed::from_f64(0.25), min);
+        assert_eq!(Fixed::from_f64(1e12), max);
+        assert_eq!(Fixed::from_f64(-1e12), min);
+
+        // Results within the range are exact
+        let big = Fixed::from_f64(1e9);
+        assert_eq!((big + big - big).to_f64(), 1e9);
+        assert_eq!((big / Fixed::from_f64(4.0)).to_f64(), 2.5e8);
+    }
+}
@@ -0,0 +1,496 @@
+//! Diagnostics module for the physics engine.
+//!
+//! This module provides `Diagnostics`, which a `RigidBodySystem` samples
+//! after every step: the total kinetic and potential energy of its bodies and
+//! their total linear and angular momentum. Comparing each sample with the
+//! first shows how far an integrator drifts from the quantities an isolated,
+//! conservative system keeps, e.g. to choose between Verlet and RK4 for a
+//! simulation, and a warning is logged when a drift exceeds its tolerance.
+//!
+//! The forces applied to bodies are constant, so their potential energy is
+//! known. The potential energy of generated forces, such as springs or
+//! gravity between bodies, comes from a function given to the diagnostics;
+//! generated forces without one, such as drag, show up as energy drift.
+//! Momentum is only conserved without external forces, so only energy drift
+//! is watched unless told otherwise.
+
+use super::forces::BodyState;
+use super::vector3::Vector3;
+use log::warn;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// The number of samples kept unless told otherwise.
+const DEFAULT_HISTORY_LENGTH: usize = 1024;
+
+/// Returns the potential energy of a system's bodies.
+pub type PotentialFn = dyn Fn(&[BodyState]) -> f64;
+
+/// The energy and momentum of a system's bodies after a step.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EnergySample {
+    /// The number of steps taken before the sample.
+    pub step: u64,
+    /// The simulated time before the sample.
+    pub time: f64,
+    pub kinetic: f64,
+    pub potential: f64,
+    pub linear_momentum: Vector3,
+    /// The angular momentum about the origin.
+    pub angular_momentum: Vector3,
+}
+
+impl EnergySample {
+    /// Returns the total energy.
+    pub fn total(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+}
+
+/// A quantity an isolated, conservative system keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Conserved {
+    Energy,
+    LinearMomentum,
+    AngularMomentum,
+}
+
+/// A drift past its tolerance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriftWarning {
+    /// The step the drift first exceeded the tolerance after.
+    pub step: u64,
+    pub quantity: Conserved,
+    /// The relative drift at that step.
+    pub drift: f64,
+}
+
+/// The first sample and the scales drifts are measured relative to.
+#[derive(Clone, Copy, Debug)]
+struct Baseline {
+    sample: EnergySample,
+    energy_scale: f64,
+    momentum_scale: f64,
+    angular_momentum_scale: f64,
+}
+
+/// Tracks the energy and momentum of a system step by step.
+pub struct Diagnostics {
+    /// The relative energy drift warned about, or `None` to not watch energy.
+    pub energy_tolerance: Option<f64>,
+    /// The relative linear momentum drift warned about, or `None` to not watch it.
+    pub momentum_tolerance: Option<f64>,
+    /// The relative angular momentum drift warned about, or `None` to not watch it.
+    pub angular_momentum_tolerance: Option<f64>,
+    /// The most recent samples kept.
+    pub history_length: usize,
+    potential: Option<Box<PotentialFn>>,
+    baseline: Option<Baseline>,
+    history: VecDeque<EnergySample>,
+    max_drift: [f64; 3],
+    warned: [bool; 3],
+    warnings: Vec<DriftWarning>,
+    steps: u64,
+    time: f64,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Diagnostics {
+    /// Creates diagnostics that warn when energy drifts by more than 0.1%.
+    pub fn new() -> Self {
+        Self {
+            energy_tolerance: Some(1e-3),
+            momentum_tolerance: None,
+            angular_momentum_tolerance: None,
+            history_length: DEFAULT_HISTORY_LENGTH,
+            potential: None,
+            baseline: None,
+            history: VecDeque::new(),
+            max_drift: [0.0; 3],
+            warned: [false; 3],
+            warnings: Vec::new(),
+            steps: 0,
+            time: 0.0,
+        }
+    }
+
+    /// Sets the relative drift warned about for a quantity, or `None` to not watch it.
+    pub fn with_tolerance(mut self, quantity: Conserved, tolerance: Option<f64>) -> Self {
+        match quantity {
+            Conserved::Energy => self.energy_tolerance = tolerance,
+            Conserved::LinearMomentum => self.momentum_tolerance = tolerance,
+            Conserved::AngularMomentum => self.angular_momentum_tolerance = tolerance,
+        }
+        self
+    }
+
+    /// Sets the function giving the potential energy of generated forces,
+    /// on top of that of the forces applied to bodies.
+    pub fn with_potential(mut self, potential: impl Fn(&[BodyState]) -> f64 + 'static) -> Self {
+        self.potential = Some(Box::new(potential));
+        self
+    }
+
+    /// Sets the number of recent samples kept.
+    pub fn with_history_length(mut self, history_length: usize) -> Self {
+        self.history_length = history_length;
+        self
+    }
+
+    /// Measures the energy and momentum of bodies.
+    ///
+    /// # Arguments
+    ///
+    /// * `bodies` - The state of every body.
+    /// * `forces` - The constant force applied to each body.
+    pub fn measure(&self, bodies: &[BodyState], forces: &[Vector3]) -> EnergySample {
+        let mut sample = EnergySample {
+            step: self.steps,
+            time: self.time,
+            ..Default::default()
+        };
+        for (body, &force) in bodies.iter().zip(forces) {
+            let momentum = body.velocity * body.mass;
+            sample.kinetic += 0.5 * body.mass * body.velocity.dot(body.velocity);
+            sample.potential -= force.dot(body.position);
+            sample.linear_momentum += momentum;
+            sample.angular_momentum += body.position.cross(momentum);
+        }
+        if let Some(potential) = &self.potential {
+            sample.potential += potential(bodies);
+        }
+        sample
+    }
+
+    /// Records the bodies' state after a step, warning about drifts past their tolerances.
+    ///
+    /// # Arguments
+    ///
+    /// * `bodies` - The state of every body.
+    /// * `forces` - The constant force applied to each body.
+    /// * `dt` - The length of the step, or zero for the state before the first step.
+    pub fn record(&mut self, bodies: &[BodyState], forces: &[Vector3], dt: f64) {
+        if dt > 0.0 {
+            self.steps += 1;
+            self.time += dt;
+        }
+        let sample = self.measure(bodies, forces);
+        if self.baseline.is_none() {
+            let magnitudes = |value: fn(&BodyState) -> f64| bodies.iter().map(value).sum::<f64>();
+            self.baseline = Some(Baseline {
+                sample,
+                energy_scale: sample.kinetic.abs() + sample.potential.abs(),
+                momentum_scale: magnitudes(|body| body.mass * body.velocity.magnitude()),
+                angular_momentum_scale: magnitudes(|body| {
+                    body.position.cross(body.velocity * body.mass).magnitude()
+                }),
+            });
+        }
+
+        for quantity in [
+            Conserved::Energy,
+            Conserved::LinearMomentum,
+            Conserved::AngularMomentum,
+        ] {
+            let drift = self.drift_of(quantity, &sample);
+            let slot = quantity as usize;
+            self.max_drift[slot] = self.max_drift[slot].max(drift);
+            let Some(tolerance) = self.tolerance(quantity) else {
+                continue;
+            };
+            // Warn once each time the drift crosses the tolerance
+            if drift <= tolerance {
+                self.warned[slot] = false;
+            } else if !self.warned[slot] {
+                self.warned[slot] = true;
+                warn!(
+                    "{quantity:?} drifted by {:.3e} after {} steps, past the tolerance of {tolerance:.1e}",
+                    drift, sample.step
+                );
+                self.warnings.push(DriftWarning {
+                    step: sample.step,
+                    quantity,
+                    drift,
+                });
+            }
+        }
+
+        if self.history_length > 0 {
+            if self.history.len() == self.history_length {
+                self.history.pop_front();
+            }
+            self.history.push_back(sample);
+        }
+    }
+
+    /// Returns the tolerance of a quantity, if it is watched.
+    pub fn tolerance(&self, quantity: Conserved) -> Option<f64> {
+        match quantity {
+            Conserved::Energy => self.energy_tolerance,
+            Conserved::LinearMomentum => self.momentum_tolerance,
+            Conserved::AngularMomentum => self.angular_momentum_tolerance,
+        }
+    }
+
+    /// Returns the change of a quantity since the first sample, relative to
+    /// its scale then: the total magnitude of the energies, momenta or
+    /// angular momenta of the bodies, so cancelling values don't inflate it.
+    fn drift_of(&self, quantity: Conserved, sample: &EnergySample) -> f64 {
+        let Some(baseline) = &self.baseline else {
+            return 0.0;
+        };
+        let first = &baseline.sample;
+        let (change, scale) = match quantity {
+            Conserved::Energy => (
+                (sample.total() - first.total()).abs(),
+                baseline.energy_scale,
+            ),
+            Conserved::LinearMomentum => (
+                (sample.linear_momentum - first.linear_momentum).magnitude(),
+                baseline.momentum_scale,
+            ),
+            Conserved::AngularMomentum => (
+                (sample.angular_momentum - first.angular_momentum).magnitude(),
+                baseline.angular_momentum_scale,
+            ),
+        };
+        if scale > 0.0 {
+            change / scale
+        } else {
+            change
+        }
+    }
+
+    /// Returns the current relative drift of a quantity.
+    pub fn drift(&self, quantity: Conserved) -> f64 {
+        self.latest()
+            .map_or(0.0, |sample| self.drift_of(quantity, sample))
+    }
+
+    /// Returns the largest relative drift of a quantity so far.
+    pub fn max_drift(&self, quantity: Conserved) -> f64 {
+        self.max_drift[quantity as usize]
+    }
+
+    /// Returns the first sample.
+    pub fn baseline(&self) -> Option<&EnergySample> {
+        self.baseline.as_ref().map(|baseline| &baseline.sample)
+    }
+
+    /// Returns the most recent sample.
+    pub fn latest(&self) -> Option<&EnergySample> {
+        self.history.back()
+    }
+
+    /// Returns the recent samples, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &EnergySample> + '_ {
+        self.history.iter()
+    }
+
+    /// Returns every drift past its tolerance so far.
+    pub fn warnings(&self) -> &[DriftWarning] {
+        &self.warnings
+    }
+
+    /// Forgets every sample and warning, so the next sample becomes the baseline.
+    pub fn reset(&mut self) {
+        self.baseline = None;
+        self.history.clear();
+        self.max_drift = [0.0; 3];
+        self.warned = [false; 3];
+        self.warnings.clear();
+        self.steps = 0;
+        self.time = 0.0;
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Diagnostics")
+            .field("energy_tolerance", &self.energy_tolerance)
+            .field("momentum_tolerance", &self.momentum_tolerance)
+            .field(
+                "angular_momentum_tolerance",
+                &self.angular_momentum_tolerance,
+            )
+            .field("steps", &self.steps)
+            .field("warnings", &self.warnings.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Conserved, Diagnostics};
+    use crate::physics::forces::BodyState;
+    use crate::physics::rigid_body_system::RigidBodySystem;
+    use crate::physics::vector3::Vector3;
+
+    const STIFFNESS: f64 = 4.0;
+
+    /// A body on a spring to the origin, as a generated force with a potential.
+    fn oscillator() -> RigidBodySystem {
+        let mut system = RigidBodySystem::new();
+        system.add(
+            1.0,
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.5, 0.0),
+        );
+        system.add_force_generator(|body: &BodyState| body.position * -STIFFNESS);
+        system.set_diagnostics(Some(Diagnostics::new().with_potential(|bodies| {
+            bodies
+                .iter()
+                .map(|body| 0.5 * STIFFNESS * body.position.dot(body.position))
+                .sum()
+        })));
+        system
+    }
+
+    #[test]
+    fn test_diagnostics_track_drift() {
+        let mut system = oscillator();
+        let baseline = *system.diagnostics().unwrap().baseline().unwrap();
+        assert_eq!(baseline.total(), 0.125 + 2.0);
+        assert_eq!(baseline.angular_momentum, Vector3::new(0.0, 0.0, 0.5));
+
+        // Verlet keeps the energy of the oscillator within a tight band
+        for _ in 0..600 {
+            system.update_verlet(0.01);
+        }
+        let diagnostics = system.diagnostics().unwrap();
+        assert_eq!(diagnostics.latest().unwrap().step, 600);
+        assert!(diagnostics.max_drift(Conserved::Energy) < 1e-3);
+        assert!(diagnostics.drift(Conserved::AngularMomentum) < 1e-3);
+        assert!(diagnostics.warnings().is_empty());
+
+        // The spring pulls on the body, so its momentum isn't conserved
+        let mut system = oscillator();
+        let diagnostics = Diagnostics::new()
+            .with_tolerance(Conserved::Energy, None)
+            .with_tolerance(Conserved::LinearMomentum, Some(0.1))
+            .with_history_length(10);
+        system.set_diagnostics(Some(diagnostics));
+        for _ in 0..100 {
+            system.update_verlet(0.01);
+        }
+        let diagnostics = system.diagnostics().unwrap();
+        assert_eq!(diagnostics.history().count(), 10);
+        let warning = diagnostics.warnings()[0];
+        assert_eq!(warning.quantity, Conserved::LinearMomentum);
+        assert!(warning.drift > 0.1);
+    }
+
+    /// A body of a given mass, position and velocity.
+    fn body(mass: f64, position: Vector3, velocity: Vector3) -> BodyState {
+        BodyState {
+            index: 0,
+            position,
+            velocity,
+            mass,
+            radius: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_diagnostics_measure() {
+        let bodies = [
+            body(
+                2.0,
+                Vector3::new(0.0, 1.0, 0.0),
+                Vector3::new(1.0, 0.0, 0.0),
+            ),
+            body(
+                1.0,
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, -2.0),
+            ),
+        ];
+        let forces = [Vector3::new(0.0, -10.0, 0.0), Vector3::new(0.0, 0.0, 0.0)];
+        let sample = Diagnostics::new().measure(&bodies, &forces);
+        assert_eq!(sample.kinetic, 3.0);
+        // Lifting against the applied force stores energy
+        assert_eq!(sample.potential, 10.0);
+        assert_eq!(sample.linear_momentum, Vector3::new(2.0, 0.0, -2.0));
+        assert_eq!(sample.angular_momentum, Vector3::new(0.0, 2.0, -2.0));
+
+        let sample = Diagnostics::new()
+            .with_potential(|bodies| bodies.len() as f64 * 2.5)
+            .measure(&bodies, &forces);
+        assert_eq!(sample.potential, 15.0);
+        assert_eq!(sample.total(), 18.0);
+    }
+
+    #[test]
+    fn test_diagnostics_warn_once_per_crossing() {
+        let mut diagnostics = Diagnostics::new().with_tolerance(Conserved::Energy, Some(0.1));
+        let at_speed = |speed: f64| {
+            [body(
+                1.0,
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(speed, 0.0, 0.0),
+            )]
+        };
+        let forces = [Vector3::new(0.0, 0.0, 0.0)];
+        diagnostics.record(&at_speed(1.0), &forces, 0.0);
+        for speed in [2.0, 2.0, 1.0, 2.0] {
+            diagnostics.record(&at_speed(speed), &forces, 0.5);
+        }
+
+        // The drift warns when it crosses the tolerance, not every step past it
+        let steps: Vec<_> = diagnostics
+            .warnings()
+            .iter()
+            .map(|warning| warning.step)
+            .collect();
+        assert_eq!(steps, [1, 4]);
+        assert_eq!(diagnostics.warnings()[0].drift, 3.0);
+        assert_eq!(diagnostics.max_drift(Conserved::Energy), 3.0);
+        assert_eq!(diagnostics.latest().unwrap().time, 2.0);
+        // Unwatched quantities are measured but never warned about
+        assert_eq!(diagnostics.max_drift(Conserved::LinearMomentum), 1.0);
+        assert!(diagnostics
+            .warnings()
+            .iter()
+            .all(|warning| warning.quantity == Conserved::Energy));
+    }
+
+    #[test]
+    fn test_diagnostics_absolute_drift_and_reset() {
+        let forces = [Vector3::new(0.0, 0.0, 0.0)];
+        let at_rest = [body(
+            1.0,
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 0.0),
+        )];
+        let moving = [body(
+            1.0,
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.5, 0.0, 0.0),
+        )];
+        let mut diagnostics = Diagnostics::new().with_history_length(0);
+        diagnostics.record(&at_rest, &forces, 0.0);
+        diagnostics.record(&moving, &forces, 0.1);
+        // Without history there is no latest sample to measure the current drift of
+        assert!(diagnostics.latest().is_none());
+        assert_eq!(diagnostics.drift(Conserved::LinearMomentum), 0.0);
+        // A baseline of zero leaves the drift absolute
+        assert_eq!(diagnostics.max_drift(Conserved::LinearMomentum), 0.5);
+        assert_eq!(diagnostics.max_drift(Conserved::Energy), 0.125);
+        assert_eq!(diagnostics.warnings().len(), 1);
+
+        diagnostics.reset();
+        assert!(diagnostics.baseline().is_none());
+        assert!(diagnostics.warnings().is_empty());
+        assert_eq!(diagnostics.max_drift(Conserved::LinearMomentum), 0.0);
+        diagnostics.history_length = 4;
+        diagnostics.record(&moving, &forces, 0.0);
+        let baseline = diagnostics.baseline().unwrap();
+        assert_eq!((baseline.step, baseline.time), (0, 0.0));
+        assert_eq!(diagnostics.drift(Conserved::Energy), 0.0);
+    }
+}
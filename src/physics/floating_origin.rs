@@ -0,0 +1,163 @@
+//! Floating origin module for the physics engine.
+//!
+//! This module provides `FloatingOrigin`, the bridge between the f64 world
+//! space physics runs in and the f32 space the renderer draws in. Render space
+//! is world space shifted so that its origin sits near the camera: positions
+//! are made relative to the origin while still in f64, and only the small
+//! difference is rounded to f32. Objects near the camera therefore keep full
+//! precision however far from the world origin the scene is, where f32 world
+//! coordinates would have jittered by metres.
+//!
+//! The origin follows the camera in jumps. Once the camera strays further
+//! than the rebase distance, the origin moves to it, and everything placed in
+//! render space must be shifted by the same amount. Nodes driven through the
+//! origin every frame follow automatically; `rebase_scene` shifts the rest.
+
+use super::vector3::Vector3;
+use crate::math::transform::Transform;
+use crate::renderer::{NodeId, SceneError, SceneGraph};
+use glam::{Quat, Vec3};
+
+/// How far the camera strays from the origin before the origin moves to it,
+/// leaving positions near the camera precise to about a tenth of a millimetre.
+pub const DEFAULT_REBASE_DISTANCE: f64 = 2048.0;
+
+/// Converts between f64 world space and the camera-relative f32 space rendered in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloatingOrigin {
+    origin: Vector3,
+    /// How far the camera strays from the origin before the origin moves to it.
+    pub rebase_distance: f64,
+}
+
+impl Default for FloatingOrigin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FloatingOrigin {
+    /// Creates a floating origin at the world origin.
+    pub fn new() -> Self {
+        Self {
+            origin: Vector3::zero(),
+            rebase_distance: DEFAULT_REBASE_DISTANCE,
+        }
+    }
+
+    /// Sets how far the camera strays from the origin before the origin moves to it.
+    pub fn with_rebase_distance(mut self, rebase_distance: f64) -> Self {
+        self.rebase_distance = rebase_distance;
+        self
+    }
+
+    /// Returns the world-space position of the render-space origin.
+    pub fn origin(&self) -> Vector3 {
+        self.origin
+    }
+
+    /// Moves the render-space origin, rounded to whole units so that shifts
+    /// between origins are exact in f32.
+    ///
+    /// # Returns
+    ///
+    /// How far the origin moved, to pass to `rebase_scene`.
+    pub fn set_origin(&mut self, origin: Vector3) -> Vector3 {
+        let origin = Vector3::new(origin.x.round(), origin.y.round(), origin.z.round());
+        let shift = origin - self.origin;
+        self.origin = origin;
+        shift
+    }
+
+    /// Moves the origin to the camera if the camera has strayed further than
+    /// the rebase distance from it. Call this once per frame, before
+    /// converting positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The world-space position of the camera.
+    ///
+    /// # Returns
+    ///
+    /// How far the origin moved, or `None` if it stayed.
+    pub fn recenter(&mut self, camera: Vector3) -> Option<Vector3> {
+        ((camera - self.origin).magnitude() > self.rebase_distance).then(|| self.set_origin(camera))
+    }
+
+    /// Converts a world-space position to render space.
+    pub fn to_render(&self, position: Vector3) -> Vec3 {
+        let relative = position - self.origin;
+        Vec3::new(relative.x as f32, relative.y as f32, relative.z as f32)
+    }
+
+    /// Converts a render-space position, such as a picked point, to world space.
+    pub fn to_world(&self, position: Vec3) -> Vector3 {
+        self.origin + Vector3::from(position)
+    }
+
+    /// Converts a world-space placement to a render-space transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The world-space position.
+    /// * `rotation` - The orientation, which is the same in both spaces.
+    /// * `scale` - The scale, which is the same in both spaces.
+    pub fn to_render_transform(&self, position: Vector3, rotation: Quat, scale: Vec3) -> Transform {
+        Transform::new(self.to_render(position), rotation, scale)
+    }
+
+    /// Places a root scene node at a world-space position and orientation.
+    pub fn drive_node(
+        &self,
+        scene_graph: &mut SceneGraph,
+        node: NodeId,
+        position: Vector3,
+        rotation: Quat,
+    ) -> Result<(), SceneError> {
+        scene_graph.set_translation(node, self.to_render(position))?;
+        scene_graph.set_rotation(node, rotation)
+    }
+
+    /// Shifts every root node of a scene after the origin moved, so that
+    /// nodes not driven every frame stay put in world space.
+    ///
+    /// # Arguments
+    ///
+    /// * `scene_graph` - The scene graph placed in render space.
+    /// * `shift` - How far the origin moved, as returned by `recenter` or `set_origin`.
+    pub fn rebase_scene(&self, scene_graph: &mut SceneGraph, shift: Vector3) {
+        let shift = Vec3::new(shift.x as f32, shift.y as f32, shift.z as f32);
+        let roots: Vec<NodeId> = scene_graph
+            .nodes()
+            .filter(|&node| scene_graph.parent(node).is_none())
+            .collect();
+        for node in roots {
+            if let Some(transform) = scene_graph.local_transform(node) {
+                let _ = scene_graph.set_translation(node, transform.translation - shift);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FloatingOrigin;
+    use crate::physics::vector3::Vector3;
+    use glam::Vec3;
+
+    #[test]
+    fn test_floating_origin_keeps_precision() {
+        // A millimetre apart, a billion metres out, where f32 steps are 64 metres
+        let camera = Vector3::new(1.0e9, 0.0, -3.0e8);
+        let object = camera + Vector3::new(0.001, 2.0, 0.0);
+        let mut origin = FloatingOrigin::new();
+        let shift = origin.recenter(camera).unwrap();
+        assert_eq!(shift, Vector3::new(1.0e9, 0.0, -3.0e8));
+        assert!(origin.recenter(object).is_none());
+
+        let relative = origin.to_render(object) - origin.to_render(camera);
+        assert!(relative.abs_diff_eq(Vec3::new(0.001, 2.0, 0.0), 1e-6));
+        let back = origin.to_world(origin.to_render(object));
+        assert!((back - object).magnitude() < 1e-6);
+    }
+}
@@ -0,0 +1,476 @@
+//! Fluid module for the physics engine.
+//!
+//! This module provides `Fluid`, a smoothed particle hydrodynamics (SPH)
+//! solver. Every particle carries a share of the fluid's mass; its density is
+//! estimated from the particles within the smoothing radius, which are found
+//! through a spatial hash, and pressure pushes particles from dense regions
+//! toward sparse ones while viscosity evens out their velocities.
+//!
+//! Besides uniform gravity, particles are pulled by point attractors with
+//! inverse-square falloff, so a disk of particles given orbital velocities
+//! around an attractor swirls like an accretion disk, and a loose cloud
+//! collapses under it like a gas cloud. With the `parallel` feature,
+//! densities and accelerations are computed on a rayon thread pool.
+
+use glam::{IVec3, Vec3};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// The golden angle in radians, spacing particles evenly around disks.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// A point pulling fluid particles toward it, such as a star or black hole.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FluidAttractor {
+    /// The position of the attractor.
+    pub position: Vec3,
+    /// The gravitational parameter, the acceleration at a distance of one.
+    pub strength: f32,
+    /// The distance within which the pull stops growing, avoiding the singularity at the center.
+    pub softening: f32,
+}
+
+impl FluidAttractor {
+    /// Returns the acceleration of a particle at a position.
+    fn acceleration(&self, position: Vec3) -> Vec3 {
+        let offset = self.position - position;
+        let distance_squared = offset.length_squared() + self.softening * self.softening;
+        if distance_squared <= 0.0 {
+            return Vec3::ZERO;
+        }
+        offset * (self.strength / (distance_squared * distance_squared.sqrt()))
+    }
+}
+
+/// A particle fluid simulated with smoothed particle hydrodynamics.
+#[derive(Clone, Debug)]
+pub struct Fluid {
+    positions: Vec<Vec3>,
+    velocities: Vec<Vec3>,
+    densities: Vec<f32>,
+    /// The mass of every particle.
+    pub particle_mass: f32,
+    /// The distance over which particles interact.
+    pub smoothing_radius: f32,
+    /// The density the fluid settles at.
+    pub rest_density: f32,
+    /// How strongly pressure resists compression. Higher values make the
+    /// fluid less compressible, but need smaller time steps.
+    pub stiffness: f32,
+    /// How strongly neighbouring particles' velocities are evened out.
+    pub viscosity: f32,
+    /// The acceleration applied to every particle.
+    pub gravity: Vec3,
+    /// The points pulling the particles toward them.
+    pub attractors: Vec<FluidAttractor>,
+    /// The minimum and maximum corners of the box particles are kept in, or
+    /// `None` for unbounded fluid.
+    pub bounds: Option<(Vec3, Vec3)>,
+    /// The fraction of their speed particles keep when bouncing off the bounds.
+    pub restitution: f32,
+}
+
+impl Default for Fluid {
+    /// Water-like fluid under Earth gravity, with particles 0.1 apart.
+    fn default() -> Self {
+        Self {
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            densities: Vec::new(),
+            particle_mass: 1.0,
+            smoothing_radius: 0.2,
+            rest_density: 1000.0,
+            stiffness: 20.0,
+            viscosity: 0.05,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            attractors: Vec::new(),
+            bounds: None,
+            restitution: 0.3,
+        }
+    }
+}
+
+impl Fluid {
+    /// Creates an empty fluid.
+    ///
+    /// # Arguments
+    ///
+    /// * `spacing` - The distance between particles at rest. The particle
+    ///   mass is chosen so that particles this far apart have the rest
+    ///   density, and the smoothing radius is twice the spacing.
+    /// * `rest_density` - The density the fluid settles at.
+    pub fn new(spacing: f32, rest_density: f32) -> Self {
+        Self {
+            particle_mass: rest_density * spacing.powi(3),
+            smoothing_radius: spacing * 2.0,
+            rest_density,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the acceleration applied to every particle.
+    pub fn with_gravity(mut self, gravity: Vec3) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Adds a point pulling the particles toward it.
+    pub fn with_attractor(mut self, attractor: FluidAttractor) -> Self {
+        self.attractors.push(attractor);
+        self
+    }
+
+    /// Keeps the particles within a box.
+    pub fn with_bounds(mut self, min: Vec3, max: Vec3) -> Self {
+        self.bounds = Some((min.min(max), min.max(max)));
+        self
+    }
+
+    /// Returns the number of particles.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns `true` if the fluid has no particles.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Returns the positions of the particles.
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    /// Returns the velocities of the particles.
+    pub fn velocities(&self) -> &[Vec3] {
+        &self.velocities
+    }
+
+    /// Returns the densities of the particles as of the last step, or the
+    /// rest density for particles added since.
+    pub fn densities(&self) -> &[f32] {
+        &self.densities
+    }
+
+    /// Adds a particle.
+    ///
+    /// # Returns
+    ///
+    /// The index of the new particle.
+    pub fn add_particle(&mut self, position: Vec3, velocity: Vec3) -> usize {
+        self.positions.push(position);
+        self.velocities.push(velocity);
+        self.densities.push(self.rest_density);
+        self.positions.len() - 1
+    }
+
+    /// Fills a box with particles at rest, e.g. for a dam break.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - The minimum corner of the box.
+    /// * `max` - The maximum corner of the box.
+    /// * `spacing` - The distance between neighbouring particles.
+    pub fn add_block(&mut self, min: Vec3, max: Vec3, spacing: f32) {
+        if spacing <= 0.0 {
+            return;
+        }
+        let counts = ((max - min) / spacing).floor().max(Vec3::ZERO).as_uvec3() + 1;
+        for z in 0..counts.z {
+            for y in 0..counts.y {
+                for x in 0..counts.x {
+                    let offset = Vec3::new(x as f32, y as f32, z as f32) * spacing;
+                    self.add_particle(min + offset, Vec3::ZERO);
+                }
+            }
+        }
+    }
+
+    /// Adds a disk of particles in circular orbits around an attractor, as
+    /// for an accretion disk or a protoplanetary disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `attractor` - The attractor the particles orbit, in the XZ plane through its position.
+    /// * `inner_radius` - The radius of the disk's inner edge.
+    /// * `outer_radius` - The radius of the disk's outer edge.
+    /// * `count` - The number of particles, spread evenly over the disk's area.
+    pub fn add_disk(
+        &mut self,
+        attractor: &FluidAttractor,
+        inner_radius: f32,
+        outer_radius: f32,
+        count: usize,
+    ) {
+        for index in 0..count {
+            // Uniform in area between the two radii
+            let t = (index as f32 + 0.5) / count as f32;
+            let radius = (inner_radius * inner_radius
+                + t * (outer_radius * outer_radius - inner_radius * inner_radius))
+                .sqrt();
+            let angle = index as f32 * GOLDEN_ANGLE;
+            let direction = Vec3::new(angle.cos(), 0.0, angle.sin());
+            let speed = (attractor.strength / radius).sqrt();
+            self.add_particle(
+                attractor.position + direction * radius,
+                Vec3::new(-direction.z, 0.0, direction.x) * speed,
+            );
+        }
+    }
+
+    /// Removes every particle.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.velocities.clear();
+        self.densities.clear();
+    }
+
+    /// Advances the simulation.
+    ///
+    /// # Arguments
+    ///
+    /// * `dt` - The time step in seconds. Stiffer fluids need smaller steps
+    ///   to stay stable.
+    pub fn step(&mut self, dt: f32) {
+        if dt <= 0.0 || self.positions.is_empty() {
+            return;
+        }
+
+        let grid = SpatialHash::new(&self.positions, self.smoothing_radius);
+        let kernels = Kernels::new(self.smoothing_radius);
+        let positions = &self.positions;
+        let mass = self.particle_mass;
+        self.densities = map_particles(positions.len(), |index| {
+            let position = positions[index];
+            grid.neighbours(position)
+                .map(|other| kernels.poly6((position - positions[other]).length_squared()))
+                .sum::<f32>()
+                * mass
+        });
+
+        let accelerations = map_particles(positions.len(), |index| {
+            self.acceleration(index, &grid, &kernels)
+        });
+        for ((position, velocity), acceleration) in self
+            .positions
+            .iter_mut()
+            .zip(&mut self.velocities)
+            .zip(accelerations)
+        {
+            *velocity += acceleration * dt;
+            *position += *velocity * dt;
+        }
+
+        if let Some((min, max)) = self.bounds {
+            for (position, velocity) in self.positions.iter_mut().zip(&mut self.velocities) {
+                for axis in 0..3 {
+                    if position[axis] < min[axis] {
+                        position[axis] = min[axis];
+                        velocity[axis] = velocity[axis].abs() * self.restitution;
+                    } else if position[axis] > max[axis] {
+                        position[axis] = max[axis];
+                        velocity[axis] = -velocity[axis].abs() * self.restitution;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the pressure of a particle, which only pushes particles apart.
+    fn pressure(&self, density: f32) -> f32 {
+        (self.stiffness * (density - self.rest_density)).max(0.0)
+    }
+
+    /// Returns the acceleration of a particle from pressure, viscosity,
+    /// gravity and the attractors.
+    fn acceleration(&self, index: usize, grid: &SpatialHash, kernels: &Kernels) -> Vec3 {
+        let position = self.positions[index];
+        let velocity = self.velocities[index];
+        let density = self.densities[index];
+        let pressure = self.pressure(density);
+
+        let mut pressure_force = Vec3::ZERO;
+        let mut viscosity_force = Vec3::ZERO;
+        for other in grid.neighbours(position) {
+            if other == index {
+                continue;
+            }
+            let offset = position - self.positions[other];
+            let distance = offset.length();
+            if distance >= self.smoothing_radius {
+                continue;
+            }
+            let other_density = self.densities[other];
+            if other_density <= 0.0 {
+                continue;
+            }
+            // Coincident particles are pushed apart in an arbitrary direction
+            let direction = offset.try_normalize().unwrap_or(Vec3::Y);
+            let shared_pressure = (pressure + self.pressure(other_density)) / (2.0 * other_density);
+            pressure_force += direction * (shared_pressure * kernels.spiky_gradient(distance));
+            viscosity_force += (self.velocities[other] - velocity)
+                * (kernels.viscosity_laplacian(distance) / other_density);
+        }
+
+        let mut acceleration = self.gravity;
+        if density > 0.0 {
+            acceleration += (pressure_force + viscosity_force * self.viscosity)
+                * (self.particle_mass / density);
+        }
+        for attractor in &self.attractors {
+            acceleration += attractor.acceleration(position);
+        }
+        acceleration
+    }
+}
+
+/// The smoothing kernels of Müller et al. for a smoothing radius.
+struct Kernels {
+    radius: f32,
+    poly6: f32,
+    spiky: f32,
+}
+
+impl Kernels {
+    fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            poly6: 315.0 / (64.0 * PI * radius.powi(9)),
+            spiky: 45.0 / (PI * radius.powi(6)),
+        }
+    }
+
+    /// Weighs neighbours for density, by their squared distance.
+    fn poly6(&self, distance_squared: f32) -> f32 {
+        let difference = self.radius * self.radius - distance_squared;
+        if difference <= 0.0 {
+            return 0.0;
+        }
+        self.poly6 * difference.powi(3)
+    }
+
+    /// Returns the magnitude of the pressure kernel's gradient, which stays
+    /// steep as neighbours close in, so particles don't clump.
+    fn spiky_gradient(&self, distance: f32) -> f32 {
+        let difference = self.radius - distance;
+        if difference <= 0.0 {
+            return 0.0;
+        }
+        self.spiky * difference * difference
+    }
+
+    /// Returns the Laplacian of the viscosity kernel.
+    fn viscosity_laplacian(&self, distance: f32) -> f32 {
+        (self.spiky * (self.radius - distance)).max(0.0)
+    }
+}
+
+/// Particle indices bucketed by cells the size of the smoothing radius, so
+/// a particle's neighbours lie in the 27 cells around it.
+struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec3, Vec<usize>>,
+}
+
+impl SpatialHash {
+    fn new(positions: &[Vec3], cell_size: f32) -> Self {
+        let cell_size = cell_size.max(f32::EPSILON);
+        let mut cells: HashMap<IVec3, Vec<usize>> = HashMap::new();
+        for (index, &position) in positions.iter().enumerate() {
+            cells
+                .entry((position / cell_size).floor().as_ivec3())
+                .or_default()
+                .push(index);
+        }
+        Self { cell_size, cells }
+    }
+
+    /// Returns the particles in the cells around a position, including any at the position.
+    fn neighbours(&self, position: Vec3) -> impl Iterator<Item = usize> + '_ {
+        let cell = (position / self.cell_size).floor().as_ivec3();
+        (-1..=1)
+            .flat_map(move |z| {
+                (-1..=1).flat_map(move |y| (-1..=1).map(move |x| IVec3::new(x, y, z)))
+            })
+            .filter_map(move |offset| self.cells.get(&(cell + offset)))
+            .flatten()
+            .copied()
+    }
+}
+
+/// Computes a value for every particle, on the rayon thread pool with the `parallel` feature.
+fn map_particles<T: Send>(count: usize, value: impl Fn(usize) -> T + Send + Sync) -> Vec<T> {
+    #[cfg(feature = "parallel")]
+    let values = (0..count).into_par_iter().map(value).collect();
+    #[cfg(not(feature = "parallel"))]
+    let values = (0..count).map(value).collect();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fluid, FluidAttractor};
+    use glam::Vec3;
+
+    #[test]
+    fn test_fluid_settles_within_bounds() {
+        let spacing = 0.1;
+        let mut fluid =
+            Fluid::new(spacing, 1000.0).with_bounds(Vec3::ZERO, Vec3::new(1.0, 2.0, 0.5));
+        fluid.add_block(Vec3::ZERO, Vec3::new(0.3, 0.6, 0.3), spacing);
+        assert_eq!(fluid.len(), 4 * 7 * 4);
+        for _ in 0..300 {
+            fluid.step(1.0 / 240.0);
+        }
+
+        for (&position, &density) in fluid.positions().iter().zip(fluid.densities()) {
+            assert!(position.cmpge(Vec3::ZERO).all());
+            assert!(position.cmple(Vec3::new(1.0, 2.0, 0.5)).all());
+            assert!(density.is_finite() && density > 0.0);
+        }
+        // The column collapses and spreads along the floor
+        let top = fluid
+            .positions()
+            .iter()
+            .map(|position| position.y)
+            .fold(0.0, f32::max);
+        let width = fluid
+            .positions()
+            .iter()
+            .map(|position| position.x)
+            .fold(0.0, f32::max);
+        assert!(top < 0.6, "column still {top} high");
+        assert!(width > 0.3, "column only {width} wide");
+    }
+
+    #[test]
+    fn test_fluid_disk_orbits_attractor() {
+        let star = FluidAttractor {
+            position: Vec3::new(0.0, 1.0, 0.0),
+            strength: 4.0,
+            softening: 0.01,
+        };
+        let mut fluid = Fluid::new(0.1, 1.0)
+            .with_gravity(Vec3::ZERO)
+            .with_attractor(star);
+        fluid.stiffness = 0.0;
+        fluid.add_disk(&star, 1.0, 2.0, 32);
+        let radii: Vec<f32> = fluid
+            .positions()
+            .iter()
+            .map(|position| position.distance(star.position))
+            .collect();
+        assert!(radii.iter().all(|&radius| (1.0..=2.0).contains(&radius)));
+
+        // A quarter orbit of the innermost particle keeps every radius
+        for _ in 0..200 {
+            fluid.step(1.0 / 256.0);
+        }
+        for (position, radius) in fluid.positions().iter().zip(radii) {
+            assert!((position.distance(star.position) - radius).abs() < 0.05 * radius);
+            assert!((position.y - 1.0).abs() < 1e-4);
+        }
+    }
+}
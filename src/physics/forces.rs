@@ -0,0 +1,322 @@
+//! Force generators module for the physics engine.
+//!
+//! This module provides `ForceGenerator`s, which a `RigidBodySystem` asks for
+//! a force on every body whenever it evaluates forces during a step, from
+//! the body's current position and velocity. Unlike forces applied with
+//! `apply_force`, generated forces follow the body as it moves within a step.
+//!
+//! `Buoyancy` floats bodies in water whose surface height is sampled from a
+//! function, such as an ocean's waves, and damps their motion through it.
+//! `Drag` slows bodies moving through air whose density is sampled from a
+//! function, such as a planet's exponential atmosphere, so objects entering
+//! it from space decelerate as the air thickens.
+//!
+//! Bodies are spheres of their collision radius: they displace the part of
+//! the sphere under water and present its cross-section to the flow. Bodies
+//! without a radius are points, which neither float nor feel drag.
+
+use super::vector3::Vector3;
+use crate::renderer::Atmosphere;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// The density of fresh water, in kilograms per cubic metre.
+pub const WATER_DENSITY: f64 = 1000.0;
+
+/// The density of air at sea level on Earth, in kilograms per cubic metre.
+pub const SEA_LEVEL_AIR_DENSITY: f64 = 1.225;
+
+/// The drag coefficient of a sphere over a wide range of speeds.
+pub const SPHERE_DRAG_COEFFICIENT: f64 = 0.47;
+
+/// The state of a body a force is generated for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BodyState {
+    /// The index of the body in its system.
+    pub index: usize,
+    pub position: Vector3,
+    pub velocity: Vector3,
+    pub mass: f64,
+    /// The radius of the sphere the body collides as, or zero for a point.
+    pub radius: f64,
+}
+
+/// Generates a force on bodies from their state, every time a step evaluates forces.
+///
+/// Closures taking a `&BodyState` and returning the force are generators too.
+pub trait ForceGenerator {
+    /// Returns the force on a body.
+    fn force(&self, body: &BodyState) -> Vector3;
+}
+
+impl<F: Fn(&BodyState) -> Vector3> ForceGenerator for F {
+    fn force(&self, body: &BodyState) -> Vector3 {
+        self(body)
+    }
+}
+
+/// Returns the force of quadratic drag on a sphere moving through a fluid.
+fn quadratic_drag(velocity: Vector3, radius: f64, density: f64, drag_coefficient: f64) -> Vector3 {
+    let area = PI * radius * radius;
+    velocity * (-0.5 * density * drag_coefficient * area * velocity.magnitude())
+}
+
+/// Floats bodies in water and drags them through it.
+pub struct Buoyancy {
+    surface: Box<dyn Fn(f64, f64) -> f64>,
+    /// The density of the water.
+    pub density: f64,
+    /// The acceleration of gravity, which buoyancy pushes against. Water
+    /// surfaces are horizontal, so gravity should point down the y axis.
+    pub gravity: Vector3,
+    /// The drag coefficient of bodies moving through the water.
+    pub drag_coefficient: f64,
+}
+
+impl Buoyancy {
+    /// Creates buoyancy in fresh water under Earth gravity.
+    ///
+    /// # Arguments
+    ///
+    /// * `surface` - Returns the height of the water surface above a point
+    ///   on the XZ plane, e.g. sampled from an ocean's waves.
+    pub fn new(surface: impl Fn(f64, f64) -> f64 + 'static) -> Self {
+        Self {
+            surface: Box::new(surface),
+            density: WATER_DENSITY,
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            drag_coefficient: SPHERE_DRAG_COEFFICIENT,
+        }
+    }
+
+    /// Creates buoyancy in calm water, whose surface is flat at a height.
+    pub fn flat(level: f64) -> Self {
+        Self::new(move |_, _| level)
+    }
+
+    /// Sets the density of the water, e.g. about 1025 for sea water.
+    pub fn with_density(mut self, density: f64) -> Self {
+        self.density = density;
+        self
+    }
+
+    /// Sets the acceleration of gravity, which buoyancy pushes against.
+    pub fn with_gravity(mut self, gravity: Vector3) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Sets the drag coefficient of bodies moving through the water.
+    pub fn with_drag_coefficient(mut self, drag_coefficient: f64) -> Self {
+        self.drag_coefficient = drag_coefficient;
+        self
+    }
+
+    /// Returns the volume of a body under the water.
+    pub fn submerged_volume(&self, position: Vector3, radius: f64) -> f64 {
+        let surface = (self.surface)(position.x, position.z);
+        let depth = (surface - (position.y - radius)).clamp(0.0, 2.0 * radius);
+        // The volume of the spherical cap below the surface
+        PI * depth * depth * (3.0 * radius - depth) / 3.0
+    }
+}
+
+impl ForceGenerator for Buoyancy {
+    fn force(&self, body: &BodyState) -> Vector3 {
+        if body.radius <= 0.0 {
+            return Vector3::zero();
+        }
+        let volume = self.submerged_volume(body.position, body.radius);
+        if volume == 0.0 {
+            return Vector3::zero();
+        }
+        let fraction = volume / (4.0 / 3.0 * PI * body.radius.powi(3));
+        let lift = self.gravity * (-self.density * volume);
+        let drag = quadratic_drag(
+            body.velocity,
+            body.radius,
+            self.density,
+            self.drag_coefficient,
+        );
+        lift + drag * fraction
+    }
+}
+
+impl fmt::Debug for Buoyancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buoyancy")
+            .field("density", &self.density)
+            .field("gravity", &self.gravity)
+            .field("drag_coefficient", &self.drag_coefficient)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Slows bodies moving through air, or any fluid whose density varies in space.
+pub struct Drag {
+    density: Box<dyn Fn(Vector3) -> f64>,
+    /// The drag coefficient of the bodies.
+    pub drag_coefficient: f64,
+}
+
+impl Drag {
+    /// Creates drag on spheres.
+    ///
+    /// # Arguments
+    ///
+    /// * `density` - Returns the density of the fluid at a point.
+    pub fn new(density: impl Fn(Vector3) -> f64 + 'static) -> Self {
+        Self {
+            density: Box::new(density),
+            drag_coefficient: SPHERE_DRAG_COEFFICIENT,
+        }
+    }
+
+    /// Creates drag through a fluid of the same density everywhere.
+    pub fn uniform(density: f64) -> Self {
+        Self::new(move |_| density)
+    }
+
+    /// Creates drag through a planet's atmosphere, whose density falls by a
+    /// factor of e every scale height above the surface.
+    ///
+    /// # Arguments
+    ///
+    /// * `center` - The center of the planet.
+    /// * `planet_radius` - The radius of the planet's surface.
+    /// * `surface_density` - The density of the air at the surface.
+    /// * `scale_height` - The altitude over which the density falls by a factor of e.
+    pub fn exponential(
+        center: Vector3,
+        planet_radius: f64,
+        surface_density: f64,
+        scale_height: f64,
+    ) -> Self {
+        Self::new(move |position| {
+            let altitude = ((position - center).magnitude() - planet_radius).max(0.0);
+            surface_density * (-altitude / scale_height).exp()
+        })
+    }
+
+    /// Creates drag through the atmosphere the renderer draws, thinning with
+    /// its Rayleigh scale height and ending at its top.
+    ///
+    /// # Arguments
+    ///
+    /// * `atmosphere` - The atmosphere, in world units.
+    /// * `surface_density` - The density of the air at the surface.
+    pub fn from_atmosphere(atmosphere: &Atmosphere, surface_density: f64) -> Self {
+        let center = Vector3::from(atmosphere.planet_center);
+        let planet_radius = atmosphere.planet_radius as f64;
+        let top = atmosphere.atmosphere_radius as f64;
+        let scale_height = atmosphere.rayleigh_scale_height as f64;
+        Self::new(move |position| {
+            let distance = (position - center).magnitude();
+            if distance > top {
+                return 0.0;
+            }
+            let altitude = (distance - planet_radius).max(0.0);
+            surface_density * (-altitude / scale_height).exp()
+        })
+    }
+
+    /// Sets the drag coefficient of the bodies.
+    pub fn with_drag_coefficient(mut self, drag_coefficient: f64) -> Self {
+        self.drag_coefficient = drag_coefficient;
+        self
+    }
+
+    /// Returns the density of the fluid at a point.
+    pub fn density(&self, position: Vector3) -> f64 {
+        (self.density)(position)
+    }
+}
+
+impl ForceGenerator for Drag {
+    fn force(&self, body: &BodyState) -> Vector3 {
+        if body.radius <= 0.0 {
+            return Vector3::zero();
+        }
+        let density = self.density(body.position);
+        quadratic_drag(body.velocity, body.radius, density, self.drag_coefficient)
+    }
+}
+
+impl fmt::Debug for Drag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drag")
+            .field("drag_coefficient", &self.drag_coefficient)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BodyState, Buoyancy, Drag, ForceGenerator, WATER_DENSITY};
+    use crate::physics::rigid_body_system::RigidBodySystem;
+    use crate::physics::vector3::Vector3;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_buoyancy_floats_half_submerged() {
+        // Half as dense as water, so it floats with its center at the surface
+        let volume = 4.0 / 3.0 * PI;
+        let mass = WATER_DENSITY * volume * 0.5;
+        let water = Buoyancy::flat(0.0);
+        let at_rest = BodyState {
+            index: 0,
+            position: Vector3::zero(),
+            velocity: Vector3::zero(),
+            mass,
+            radius: 1.0,
+        };
+        let lift = water.force(&at_rest);
+        assert!((lift.y - 9.81 * mass).abs() < 1e-6);
+
+        // Dropped in, it bobs about the surface as the water damps it
+        let mut system = RigidBodySystem::new();
+        let ship = system.add(mass, Vector3::new(0.0, 3.0, 0.0), Vector3::zero());
+        system.set_radius(ship, 1.0);
+        system.apply_force(ship, Vector3::new(0.0, -9.81 * mass, 0.0));
+        system.add_force_generator(water);
+        let mut lowest = f64::INFINITY;
+        for step in 0..1800 {
+            system.update_verlet(1.0 / 60.0);
+            if step >= 1200 {
+                lowest = lowest.min(system.position(ship).y);
+            }
+        }
+        assert!(lowest > -0.25);
+        assert!(system.position(ship).y.abs() < 0.25);
+    }
+
+    #[test]
+    fn test_drag_decelerates_through_atmosphere() {
+        // Without gravity, quadratic drag in uniform air slows a body as 1 / (1 + kv0t)
+        let mut system = RigidBodySystem::new();
+        let body = system.add(1.0, Vector3::zero(), Vector3::new(100.0, 0.0, 0.0));
+        system.set_radius(body, 0.1);
+        system.add_force_generator(Drag::uniform(1.2));
+        for _ in 0..1000 {
+            system.update_verlet(0.001);
+        }
+        let k = 0.5 * 1.2 * 0.47 * PI * 0.01;
+        let expected = 100.0 / (1.0 + k * 100.0);
+        let speed = system.velocity(body).x;
+        assert!((speed - expected).abs() / expected < 0.01);
+
+        // Thin air high up barely slows a falling probe
+        let atmosphere = Drag::exponential(Vector3::zero(), 0.0, 1.2, 8.0);
+        assert_eq!(atmosphere.density(Vector3::zero()), 1.2);
+        assert!(atmosphere.density(Vector3::new(0.0, 80.0, 0.0)) < 1e-4);
+        let mut system = RigidBodySystem::new();
+        let probe = system.add(1.0, Vector3::new(0.0, 100.0, 0.0), Vector3::zero());
+        system.set_radius(probe, 0.1);
+        system.add_force_generator(atmosphere);
+        system.apply_force(probe, Vector3::new(0.0, -9.81, 0.0));
+        for _ in 0..60 {
+            system.update_verlet(1.0 / 60.0);
+        }
+        assert!((system.velocity(probe).y + 9.81).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,256 @@
+//! Heightfield module for the physics engine.
+//!
+//! This module provides `Heightfield`, a grid of heights over the XZ plane
+//! such as terrain, which spheres collide with as the two triangles of every
+//! cell. Unlike a triangle mesh, everything below the surface is solid, so a
+//! sphere that sinks through it is pushed back up rather than out the bottom.
+//! The cells under a query are found directly from the grid, so heightfields
+//! need no BVH.
+
+use super::collider::Contact;
+use super::triangle_mesh::{earlier, sweep_triangle, triangle_normal, triangle_penetration};
+use super::vector3::Vector3;
+use std::fmt;
+
+/// Heights sampled on a regular grid, from the collider's position along +x and +z.
+#[derive(Clone, PartialEq)]
+pub struct Heightfield {
+    columns: usize,
+    rows: usize,
+    spacing: f64,
+    heights: Vec<f64>,
+}
+
+impl Heightfield {
+    /// Creates a heightfield. Missing heights are zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The number of samples along x, at least 2.
+    /// * `rows` - The number of samples along z, at least 2.
+    /// * `spacing` - The distance between neighbouring samples.
+    /// * `heights` - The heights, row by row, relative to the collider.
+    pub fn new(columns: usize, rows: usize, spacing: f64, mut heights: Vec<f64>) -> Self {
+        let (columns, rows) = (columns.max(2), rows.max(2));
+        heights.resize(columns * rows, 0.0);
+        Self {
+            columns,
+            rows,
+            spacing,
+            heights,
+        }
+    }
+
+    /// Creates a heightfield by sampling a function of the column and row.
+    pub fn from_fn(
+        columns: usize,
+        rows: usize,
+        spacing: f64,
+        height: impl Fn(usize, usize) -> f64,
+    ) -> Self {
+        let heights = (0..rows.max(2))
+            .flat_map(|row| (0..columns.max(2)).map(move |column| (column, row)))
+            .map(|(column, row)| height(column, row))
+            .collect();
+        Self::new(columns, rows, spacing, heights)
+    }
+
+    /// Returns the number of samples along x.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Returns the number of samples along z.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the distance between neighbouring samples.
+    pub fn spacing(&self) -> f64 {
+        self.spacing
+    }
+
+    /// Returns the height sampled at a column and row.
+    pub fn sample(&self, column: usize, row: usize) -> f64 {
+        self.heights[row * self.columns + column]
+    }
+
+    /// Returns the height of the surface above a point, relative to the
+    /// collider, or `None` if the point is outside the grid.
+    pub fn height_at(&self, x: f64, z: f64) -> Option<f64> {
+        let (column, row) = self.cell(x, z)?;
+        let (u, v) = (
+            x / self.spacing - column as f64,
+            z / self.spacing - row as f64,
+        );
+        let h00 = self.sample(column, row);
+        let h10 = self.sample(column + 1, row);
+        let h01 = self.sample(column, row + 1);
+        let h11 = self.sample(column + 1, row + 1);
+        Some(if u + v <= 1.0 {
+            h00 + (h10 - h00) * u + (h01 - h00) * v
+        } else {
+            h11 + (h01 - h11) * (1.0 - u) + (h10 - h11) * (1.0 - v)
+        })
+    }
+
+    /// Returns the cell containing a point, or `None` if it is outside the grid.
+    fn cell(&self, x: f64, z: f64) -> Option<(usize, usize)> {
+        let (u, v) = (x / self.spacing, z / self.spacing);
+        if !(0.0..=(self.columns - 1) as f64).contains(&u)
+            || !(0.0..=(self.rows - 1) as f64).contains(&v)
+        {
+            return None;
+        }
+        Some((
+            (u as usize).min(self.columns - 2),
+            (v as usize).min(self.rows - 2),
+        ))
+    }
+
+    /// Returns the corners of a cell's two triangles, wound to face up.
+    fn cell_triangles(&self, column: usize, row: usize) -> [[Vector3; 3]; 2] {
+        let corner = |c: usize, r: usize| {
+            Vector3::new(
+                c as f64 * self.spacing,
+                self.sample(c, r),
+                r as f64 * self.spacing,
+            )
+        };
+        let (p00, p10) = (corner(column, row), corner(column + 1, row));
+        let (p01, p11) = (corner(column, row + 1), corner(column + 1, row + 1));
+        [[p00, p01, p10], [p10, p01, p11]]
+    }
+
+    /// Visits the triangles of the cells under a box's extent on the XZ plane.
+    fn candidates(&self, min: Vector3, max: Vector3, mut visit: impl FnMut([Vector3; 3])) {
+        let last_cell = |samples: usize| (samples - 2) as f64;
+        let range = |low: f64, high: f64, samples: usize| {
+            let low = (low / self.spacing).floor().max(0.0);
+            let high = (high / self.spacing).floor().min(last_cell(samples));
+            (low <= high).then_some(low as usize..=high as usize)
+        };
+        let (Some(columns), Some(rows)) = (
+            range(min.x, max.x, self.columns),
+            range(min.z, max.z, self.rows),
+        ) else {
+            return;
+        };
+        for row in rows {
+            for column in columns.clone() {
+                self.cell_triangles(column, row)
+                    .into_iter()
+                    .for_each(&mut visit);
+            }
+        }
+    }
+
+    /// Finds how a sphere overlaps the heightfield, pushing it up out of
+    /// the ground if its center sank below the surface.
+    ///
+    /// # Returns
+    ///
+    /// The direction to push the sphere out along and the distance to push
+    /// it, or `None` if the sphere doesn't overlap the heightfield.
+    pub fn penetration(&self, center: Vector3, radius: f64) -> Option<(Vector3, f64)> {
+        if let Some((column, row)) = self.cell(center.x, center.z) {
+            let surface = self.height_at(center.x, center.z).unwrap_or(center.y);
+            if center.y < surface {
+                let (u, v) = (
+                    center.x / self.spacing - column as f64,
+                    center.z / self.spacing - row as f64,
+                );
+                let triangles = self.cell_triangles(column, row);
+                let normal = triangle_normal(triangles[usize::from(u + v > 1.0)]);
+                return Some((normal, (surface - center.y) * normal.y + radius));
+            }
+        }
+
+        let reach = Vector3::new(radius, radius, radius);
+        let mut deepest: Option<(Vector3, f64)> = None;
+        self.candidates(center - reach, center + reach, |corners| {
+            let Some((normal, depth)) = triangle_penetration(center, radius, corners) else {
+                return;
+            };
+            if deepest.is_none_or(|(_, deepest)| depth > deepest) {
+                deepest = Some((normal, depth));
+            }
+        });
+        deepest
+    }
+
+    /// Finds where a sphere moving along a segment first touches the surface
+    /// from above.
+    ///
+    /// # Returns
+    ///
+    /// The first contact, or `None` if the sphere misses the surface or
+    /// starts below it.
+    pub fn sweep(&self, start: Vector3, end: Vector3, radius: f64) -> Option<Contact> {
+        if self
+            .height_at(start.x, start.z)
+            .is_some_and(|surface| start.y < surface)
+        {
+            return None;
+        }
+        let reach = Vector3::new(radius, radius, radius);
+        let min = Vector3::new(start.x.min(end.x), start.y.min(end.y), start.z.min(end.z));
+        let max = Vector3::new(start.x.max(end.x), start.y.max(end.y), start.z.max(end.z));
+        let mut first: Option<Contact> = None;
+        self.candidates(min - reach, max + reach, |corners| {
+            first = earlier(first, sweep_triangle(start, end - start, radius, corners));
+        });
+        first
+    }
+}
+
+impl fmt::Debug for Heightfield {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heightfield")
+            .field("columns", &self.columns)
+            .field("rows", &self.rows)
+            .field("spacing", &self.spacing)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Heightfield;
+    use crate::physics::vector3::Vector3;
+
+    #[test]
+    fn test_heightfield_queries() {
+        // A slope rising one unit per unit along x
+        let slope = Heightfield::from_fn(5, 5, 1.0, |column, _| column as f64);
+        assert_eq!(slope.height_at(2.5, 1.25), Some(2.5));
+        assert_eq!(slope.height_at(-0.5, 1.0), None);
+
+        // A sphere buried under the surface is pushed up and out
+        let normal = Vector3::new(-1.0, 1.0, 0.0).normalize();
+        let (push, depth) = slope.penetration(Vector3::new(2.0, 1.0, 2.0), 0.5).unwrap();
+        assert!((push - normal).magnitude() < 1e-9);
+        assert!((depth - (0.5f64.sqrt() + 0.5)).abs() < 1e-9);
+        assert!(slope
+            .penetration(Vector3::new(2.0, 3.0, 2.0), 0.5)
+            .is_none());
+
+        // Dropping onto the slope, and starting under it
+        let contact = slope
+            .sweep(
+                Vector3::new(2.0, 10.0, 2.0),
+                Vector3::new(2.0, -10.0, 2.0),
+                0.0,
+            )
+            .unwrap();
+        assert!((contact.time - 0.4).abs() < 1e-9);
+        assert!((contact.normal - normal).magnitude() < 1e-9);
+        assert!(slope
+            .sweep(
+                Vector3::new(2.0, 1.0, 2.0),
+                Vector3::new(2.0, 5.0, 2.0),
+                0.0
+            )
+            .is_none());
+    }
+}
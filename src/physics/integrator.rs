@@ -0,0 +1,231 @@
+//! Integrator module for the physics engine.
+//!
+//! This module provides `Integrator`, the method a `RigidBodySystem` advances
+//! its bodies with in `step`, and the adaptive Dormand-Prince 5(4) scheme.
+//! Symplectic integrators keep the energy of conservative systems within a
+//! band for as long as they run, which suits games and orbits over long
+//! spans; Runge-Kutta integrators are more accurate over a short span but let
+//! energy slowly drift. `Diagnostics` measure that drift.
+//!
+//! The adaptive integrator halves its substep when the error estimate is too
+//! large and doubles it when the error is far below the tolerance, rather
+//! than scaling it by a root of the error, so its steps involve no
+//! transcendental functions and stay deterministic.
+
+use super::vector3::Vector3;
+
+/// The position and velocity of a body.
+pub(crate) type State = (Vector3, Vector3);
+
+/// The most substeps an adaptive step accepts, however small the error forces
+/// them to be. The last one covers whatever is left of the step.
+const MAX_SUBSTEPS: usize = 4096;
+
+/// How a rigid body system advances its bodies over a step.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Integrator {
+    /// Updates velocities by the accelerations, then positions by the new
+    /// velocities. First order and the cheapest, with one force evaluation,
+    /// but symplectic, so energy oscillates rather than drifts.
+    SemiImplicitEuler,
+    /// Velocity Verlet. Second order and symplectic, with two force
+    /// evaluations, and steady under distance constraints.
+    #[default]
+    VelocityVerlet,
+    /// Classic fourth-order Runge-Kutta, with four force evaluations.
+    Rk4,
+    /// Dormand-Prince 5(4), which splits each step into substeps whose
+    /// estimated error relative to the state stays under the tolerance.
+    Rk45 { tolerance: f64 },
+}
+
+/// The nodes of the Dormand-Prince stages within a step, as fractions of it.
+const NODES: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+
+/// The weights of earlier stages in each Dormand-Prince stage.
+const STAGE_WEIGHTS: [[f64; 6]; 7] = [
+    [0.0; 6],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+
+/// The weights of the stages in the difference between the fifth- and
+/// fourth-order solutions, which estimates the error.
+const ERROR_WEIGHTS: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+/// Takes one Dormand-Prince step.
+///
+/// # Arguments
+///
+/// * `states` - The state of every body.
+/// * `dt` - The length of the step.
+/// * `tolerance` - The error allowed relative to the size of the state.
+/// * `accelerations` - Returns the acceleration of every body in a state.
+///
+/// # Returns
+///
+/// The fifth-order solution and its error relative to the tolerance, which
+/// is at most 1 if the step should be accepted.
+fn dormand_prince_step(
+    states: &[State],
+    dt: f64,
+    tolerance: f64,
+    accelerations: &impl Fn(&[State]) -> Vec<Vector3>,
+) -> (Vec<State>, f64) {
+    let mut derivatives: Vec<Vec<State>> = Vec::with_capacity(NODES.len());
+    let mut stage_states = states.to_vec();
+    for weights in &STAGE_WEIGHTS {
+        for (body, stage) in stage_states.iter_mut().enumerate() {
+            let (mut position, mut velocity) = states[body];
+            for (derivative, &weight) in derivatives.iter().zip(weights) {
+                position += derivative[body].0 * (weight * dt);
+                velocity += derivative[body].1 * (weight * dt);
+            }
+            *stage = (position, velocity);
+        }
+        let stage_accelerations = accelerations(&stage_states);
+        derivatives.push(
+            stage_states
+                .iter()
+                .zip(stage_accelerations)
+                .map(|(&(_, velocity), acceleration)| (velocity, acceleration))
+                .collect(),
+        );
+    }
+
+    // The last stage is evaluated at the fifth-order solution
+    let mut error: f64 = 0.0;
+    for (body, &(position, velocity)) in stage_states.iter().enumerate() {
+        let (mut position_error, mut velocity_error) = (Vector3::zero(), Vector3::zero());
+        for (derivative, &weight) in derivatives.iter().zip(&ERROR_WEIGHTS) {
+            position_error += derivative[body].0 * (weight * dt);
+            velocity_error += derivative[body].1 * (weight * dt);
+        }
+        error = error
+            .max(position_error.magnitude() / (tolerance * (1.0 + position.magnitude())))
+            .max(velocity_error.magnitude() / (tolerance * (1.0 + velocity.magnitude())));
+    }
+    (stage_states, error)
+}
+
+/// Advances bodies over a step in adaptive Dormand-Prince substeps.
+///
+/// # Arguments
+///
+/// * `states` - The state of every body.
+/// * `dt` - The length of the step.
+/// * `tolerance` - The error allowed per substep relative to the size of the state.
+/// * `substep` - The substep to try first, e.g. the last one accepted.
+/// * `accelerations` - Returns the acceleration of every body in a state.
+///
+/// # Returns
+///
+/// The states after the step and the substep to try first next time.
+pub(crate) fn integrate_adaptive(
+    states: &[State],
+    dt: f64,
+    tolerance: f64,
+    substep: f64,
+    accelerations: impl Fn(&[State]) -> Vec<Vector3>,
+) -> (Vec<State>, f64) {
+    let mut states = states.to_vec();
+    let mut substep = if substep > 0.0 { substep.min(dt) } else { dt };
+    let mut remaining = dt;
+    let mut accepted = 0;
+    while remaining > 0.0 {
+        let last = accepted + 1 == MAX_SUBSTEPS;
+        let length = if last {
+            remaining
+        } else {
+            substep.min(remaining)
+        };
+        let (next, error) = dormand_prince_step(&states, length, tolerance, &accelerations);
+        // Accept steps at the smallest length allowed, to always make progress
+        if error > 1.0 && length > dt / MAX_SUBSTEPS as f64 && !last {
+            substep = length * 0.5;
+            continue;
+        }
+        states = next;
+        remaining -= length;
+        accepted += 1;
+        // The error shrinks with the fifth power of the substep
+        if error < 1.0 / 32.0 {
+            substep = length * 2.0;
+        }
+    }
+    (states, substep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::integrate_adaptive;
+    use crate::physics::vector3::Vector3;
+
+    #[test]
+    fn test_adaptive_integration_matches_oscillator() {
+        // x'' = -x, so x = cos t
+        let start = [(Vector3::new(1.0, 0.0, 0.0), Vector3::zero())];
+        let accelerations = |states: &[(Vector3, Vector3)]| {
+            states
+                .iter()
+                .map(|&(position, _)| position * -1.0)
+                .collect()
+        };
+        let (end, substep) = integrate_adaptive(&start, 10.0, 1e-10, 0.0, accelerations);
+        assert!((end[0].0.x - 10f64.cos()).abs() < 1e-7);
+        assert!((end[0].1.x + 10f64.sin()).abs() < 1e-7);
+        assert!(substep < 10.0);
+
+        // A looser tolerance takes fewer, longer substeps
+        let (_, loose) = integrate_adaptive(&start, 10.0, 1e-4, 0.0, accelerations);
+        assert!(loose > substep);
+    }
+
+    #[test]
+    fn test_adaptive_integration_covers_stiff_step() {
+        // A stiff spring keeps the substeps at their smallest, and a body
+        // moving at a constant velocity measures how much of the step was taken
+        let start = [
+            (Vector3::new(1.0, 0.0, 0.0), Vector3::zero()),
+            (Vector3::zero(), Vector3::new(1.0, 0.0, 0.0)),
+        ];
+        let accelerations =
+            |states: &[(Vector3, Vector3)]| vec![states[0].0 * -1e6, Vector3::zero()];
+        let (end, _) = integrate_adaptive(&start, 1.0, 1e-6, 0.0, accelerations);
+        assert!((end[1].0.x - 1.0).abs() < 1e-12);
+        assert!(end[0].0.magnitude() <= 1.0 + 1e-6);
+    }
+}
@@ -1,3 +1,14 @@
+pub mod character_controller;
+pub mod cloth;
+pub mod collider;
+pub mod determinism;
+pub mod diagnostics;
+pub mod floating_origin;
+pub mod fluid;
+pub mod forces;
+pub mod heightfield;
+pub mod integrator;
 pub mod physics_world;
 pub mod rigid_body_system;
-mod vector3;
+pub mod triangle_mesh;
+pub mod vector3;
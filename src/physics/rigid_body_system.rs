@@ -1,13 +1,92 @@
+use super::collider::{Collider, ColliderShape, CollisionFilter, Contact};
+use super::determinism::{Determinism, Fixed};
+use super::diagnostics::Diagnostics;
+use super::forces::{BodyState, ForceGenerator};
+use super::integrator::{integrate_adaptive, Integrator, State};
 use super::vector3::Vector3;
+use std::fmt;
 
+/// The most times a swept body bounces within one step.
+const MAX_SWEEPS: usize = 4;
+
+/// The distance a swept body is kept off the surface it hit, so the next
+/// sweep doesn't start touching it.
+const CONTACT_OFFSET: f64 = 1e-9;
+
+/// The passes over the distance constraints each step, each bringing the
+/// bodies closer to satisfying all of them at once.
+const CONSTRAINT_ITERATIONS: usize = 8;
+
+/// Something a body can collide with or a ray can hit.
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CollisionObject {
+    /// The body with the index.
+    Body(usize),
+    /// The static collider with the index.
+    Collider(usize),
+}
+
+/// Called with a body and an object it is about to collide with, after their
+/// collision filters allowed the pair. Returning `false` skips the contact.
+pub type PairFilter = dyn Fn(usize, CollisionObject) -> bool;
+
+/// A body overlapping a trigger collider at the end of a step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TriggerEvent {
+    pub body: usize,
+    pub collider: usize,
+}
+
+/// The first body or collider a swept sphere touches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    pub object: CollisionObject,
+    /// The fraction of the sweep travelled before the hit, from 0 to 1.
+    pub time: f64,
+    /// The surface normal at the hit.
+    pub normal: Vector3,
+}
+
+/// Keeps two bodies a fixed distance apart, like a massless rod between them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DistanceConstraint {
+    pub a: usize,
+    pub b: usize,
+    pub length: f64,
+}
+
+/// The first body or collider along a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub object: CollisionObject,
+    /// The distance along the ray to the hit.
+    pub distance: f64,
+    /// The surface normal at the hit.
+    pub normal: Vector3,
+}
+
+#[derive(Default)]
 pub struct RigidBodySystem {
     masses: Vec<f64>,
     positions: Vec<Vector3>,
     velocities: Vec<Vector3>,
     accelerations: Vec<Vector3>,
     forces: Vec<Vector3>,
+    radii: Vec<f64>,
+    continuous: Vec<bool>,
+    colliders: Vec<Collider>,
+    ccd_threshold: f64,
+    determinism: Determinism,
+    filters: Vec<CollisionFilter>,
+    body_restitution: f64,
+    pair_filter: Option<Box<PairFilter>>,
+    trigger_events: Vec<TriggerEvent>,
+    force_generators: Vec<Box<dyn ForceGenerator>>,
+    diagnostics: Option<Diagnostics>,
+    integrator: Integrator,
+    adaptive_substep: f64,
+    constraints: Vec<DistanceConstraint>,
 }
 
 impl RigidBodySystem {
@@ -19,6 +98,20 @@ impl RigidBodySystem {
             velocities: Vec::new(),
             accelerations: Vec::new(),
             forces: Vec::new(),
+            radii: Vec::new(),
+            continuous: Vec::new(),
+            colliders: Vec::new(),
+            ccd_threshold: 0.0,
+            determinism: Determinism::Off,
+            filters: Vec::new(),
+            body_restitution: 0.5,
+            pair_filter: None,
+            trigger_events: Vec::new(),
+            force_generators: Vec::new(),
+            diagnostics: None,
+            integrator: Integrator::VelocityVerlet,
+            adaptive_substep: 0.0,
+            constraints: Vec::new(),
         }
     }
 
@@ -30,6 +123,20 @@ impl RigidBodySystem {
             velocities: Vec::with_capacity(capacity),
             accelerations: Vec::with_capacity(capacity),
             forces: Vec::with_capacity(capacity),
+            radii: Vec::with_capacity(capacity),
+            continuous: Vec::with_capacity(capacity),
+            colliders: Vec::new(),
+            ccd_threshold: 0.0,
+            determinism: Determinism::Off,
+            filters: Vec::with_capacity(capacity),
+            body_restitution: 0.5,
+            pair_filter: None,
+            trigger_events: Vec::new(),
+            force_generators: Vec::new(),
+            diagnostics: None,
+            integrator: Integrator::VelocityVerlet,
+            adaptive_substep: 0.0,
+            constraints: Vec::new(),
         }
     }
 
@@ -41,14 +148,348 @@ impl RigidBodySystem {
         self.velocities.push(velocity);
         self.accelerations.push(Vector3::zero());
         self.forces.push(Vector3::zero());
+        self.radii.push(0.0);
+        self.continuous.push(false);
+        self.filters.push(CollisionFilter::default());
         index
     }
 
+    /// Sets the groups a body belongs to and collides with.
+    pub fn set_filter(&mut self, index: usize, filter: CollisionFilter) {
+        self.filters[index] = filter;
+    }
+
+    /// Sets the fraction of their approaching speed colliding bodies keep.
+    pub fn set_body_restitution(&mut self, restitution: f64) {
+        self.body_restitution = restitution;
+    }
+
+    /// Sets the callback deciding, pair by pair, whether bodies collide with
+    /// what their filters allow, or `None` to collide with all of it.
+    pub fn set_pair_filter(&mut self, filter: Option<Box<PairFilter>>) {
+        self.pair_filter = filter;
+    }
+
+    /// Returns the bodies that overlapped trigger colliders at the end of the last step.
+    pub fn trigger_events(&self) -> &[TriggerEvent] {
+        &self.trigger_events
+    }
+
+    /// Casts a ray against the bodies with a radius and the colliders that
+    /// aren't triggers.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The start of the ray.
+    /// * `direction` - The direction of the ray.
+    /// * `max_distance` - The length of the ray.
+    /// * `mask` - The groups the ray hits, one per bit.
+    ///
+    /// # Returns
+    ///
+    /// The nearest hit, or `None` if the ray hits nothing in the groups.
+    /// Objects the ray starts inside are not hit.
+    pub fn raycast(
+        &self,
+        origin: Vector3,
+        direction: Vector3,
+        max_distance: f64,
+        mask: u32,
+    ) -> Option<RaycastHit> {
+        let end = origin + direction.normalize() * max_distance;
+        self.obstacles(|filter| filter.groups & mask != 0)
+            .filter_map(|(object, collider)| {
+                let contact = collider.sweep(origin, end, 0.0)?;
+                Some(RaycastHit {
+                    object,
+                    distance: contact.time * max_distance,
+                    normal: contact.normal,
+                })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Sweeps a sphere along a segment against the bodies with a radius and
+    /// the colliders that aren't triggers, as for kinematic controllers.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The center of the sphere before moving.
+    /// * `end` - The center of the sphere after moving.
+    /// * `radius` - The radius of the sphere.
+    /// * `filter` - The groups the sphere belongs to and collides with.
+    ///
+    /// # Returns
+    ///
+    /// The first hit, or `None` if the sphere reaches the end unobstructed.
+    /// Objects the sphere starts inside are not hit.
+    pub fn sweep_sphere(
+        &self,
+        start: Vector3,
+        end: Vector3,
+        radius: f64,
+        filter: CollisionFilter,
+    ) -> Option<SweepHit> {
+        self.obstacles(|other| filter.collides_with(other))
+            .filter_map(|(object, collider)| {
+                let Contact { time, normal } = collider.sweep(start, end, radius)?;
+                Some(SweepHit {
+                    object,
+                    time,
+                    normal,
+                })
+            })
+            .min_by(|a, b| a.time.total_cmp(&b.time))
+    }
+
+    /// Finds the bodies with a radius and colliders that aren't triggers
+    /// overlapping a sphere.
+    ///
+    /// # Returns
+    ///
+    /// Each overlapping object, with the direction to push the sphere out
+    /// along and the distance to push it.
+    pub fn overlap_sphere(
+        &self,
+        center: Vector3,
+        radius: f64,
+        filter: CollisionFilter,
+    ) -> impl Iterator<Item = (CollisionObject, Vector3, f64)> + '_ {
+        self.obstacles(move |other| filter.collides_with(other))
+            .filter_map(move |(object, collider)| {
+                let (normal, depth) = collider.penetration(center, radius)?;
+                Some((object, normal, depth))
+            })
+    }
+
+    /// Returns the bodies with a radius, as sphere colliders, and the
+    /// colliders that aren't triggers whose filters a test accepts.
+    fn obstacles<'a>(
+        &'a self,
+        accepts: impl Fn(CollisionFilter) -> bool + Clone + 'a,
+    ) -> impl Iterator<Item = (CollisionObject, Collider)> + 'a {
+        let accepts_body = accepts.clone();
+        let bodies = (0..self.masses.len())
+            .filter(move |&i| self.radii[i] > 0.0 && accepts_body(self.filters[i]))
+            .map(|i| {
+                let shape = ColliderShape::Sphere {
+                    radius: self.radii[i],
+                };
+                (
+                    CollisionObject::Body(i),
+                    Collider::new(shape, self.positions[i]),
+                )
+            });
+        let colliders = self
+            .colliders
+            .iter()
+            .enumerate()
+            .filter(move |(_, collider)| !collider.trigger && accepts(collider.filter))
+            .map(|(i, collider)| (CollisionObject::Collider(i), collider.clone()));
+        bodies.chain(colliders)
+    }
+
+    /// Sets the radius of the sphere a body collides as. Bodies start as points.
+    pub fn set_radius(&mut self, index: usize, radius: f64) {
+        self.radii[index] = radius.max(0.0);
+    }
+
+    /// Opts a body in or out of continuous collision detection, which sweeps
+    /// it along its motion so it can't pass through thin colliders within a
+    /// step. Sweeping costs more than the discrete test, so only fast bodies
+    /// such as projectiles should opt in.
+    pub fn set_continuous(&mut self, index: usize, continuous: bool) {
+        self.continuous[index] = continuous;
+    }
+
+    /// Sets the speed above which bodies opted into continuous collision
+    /// detection are swept. Slower bodies use the discrete test.
+    pub fn set_ccd_threshold(&mut self, speed: f64) {
+        self.ccd_threshold = speed.max(0.0);
+    }
+
+    /// Sets how strictly steps are kept reproducible. Bodies and colliders
+    /// are always visited in the order they were added.
+    pub fn set_determinism(&mut self, determinism: Determinism) {
+        self.determinism = determinism;
+        self.snap_to_fixed_point();
+    }
+
+    /// Returns how strictly steps are kept reproducible.
+    pub fn determinism(&self) -> Determinism {
+        self.determinism
+    }
+
+    /// Returns a hash of the bodies' positions and velocities, which
+    /// deterministic simulations compare to detect when replicas diverge.
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a over the exact bits of the state
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for vector in self.positions.iter().chain(&self.velocities) {
+            for value in [vector.x, vector.y, vector.z] {
+                for byte in value.to_bits().to_le_bytes() {
+                    hash = (hash ^ byte as u64).wrapping_mul(PRIME);
+                }
+            }
+        }
+        hash
+    }
+
+    /// Adds a static collider bodies bounce off, returning its index.
+    pub fn add_collider(&mut self, collider: Collider) -> usize {
+        self.colliders.push(collider);
+        self.colliders.len() - 1
+    }
+
+    /// Returns the static colliders.
+    pub fn colliders(&self) -> &[Collider] {
+        &self.colliders
+    }
+
+    /// Adds a generator of forces on every body, such as buoyancy or drag,
+    /// which steps evaluate alongside the forces applied to bodies.
+    pub fn add_force_generator(&mut self, generator: impl ForceGenerator + 'static) -> usize {
+        self.force_generators.push(Box::new(generator));
+        self.force_generators.len() - 1
+    }
+
+    /// Removes every force generator.
+    pub fn clear_force_generators(&mut self) {
+        self.force_generators.clear();
+    }
+
+    /// Attaches diagnostics that sample the energy and momentum of the
+    /// bodies after every step, taking the current state as their baseline,
+    /// or detaches them with `None`.
+    pub fn set_diagnostics(&mut self, diagnostics: Option<Diagnostics>) {
+        self.diagnostics = diagnostics;
+        self.record_diagnostics(0.0);
+    }
+
+    /// Returns the attached diagnostics.
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    /// Sets the integrator `step` advances the bodies with.
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+        self.adaptive_substep = 0.0;
+    }
+
+    /// Returns the integrator `step` advances the bodies with.
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    /// Keeps two bodies a fixed distance apart. Steps correct the positions
+    /// and velocities of constrained bodies after integrating them, in
+    /// proportion to their inverse masses.
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - The index of the first body.
+    /// * `b` - The index of the second body.
+    /// * `length` - The distance to keep between the bodies.
+    ///
+    /// # Returns
+    ///
+    /// The index of the constraint.
+    pub fn add_distance_constraint(&mut self, a: usize, b: usize, length: f64) -> usize {
+        self.constraints.push(DistanceConstraint { a, b, length });
+        self.constraints.len() - 1
+    }
+
+    /// Returns the distance constraints between bodies.
+    pub fn distance_constraints(&self) -> &[DistanceConstraint] {
+        &self.constraints
+    }
+
+    /// Removes every distance constraint.
+    pub fn clear_distance_constraints(&mut self) {
+        self.constraints.clear();
+    }
+
+    /// Advances the bodies over a step with the system's integrator, under
+    /// the applied and generated forces.
+    pub fn step(&mut self, dt: f64) {
+        match self.integrator {
+            Integrator::SemiImplicitEuler => self.update_semi_implicit_euler(dt),
+            Integrator::VelocityVerlet => self.update_verlet(dt),
+            Integrator::Rk4 => {
+                let previous_positions = self.positions.clone();
+                let states = self.rk4_states(dt, |i, position, velocity| {
+                    self.total_force(i, position, velocity)
+                });
+                self.set_states(states);
+                self.finish_step(&previous_positions, dt);
+            }
+            Integrator::Rk45 { tolerance } => self.update_rk45(dt, tolerance),
+        }
+    }
+
+    /// Returns the state of every body.
+    fn body_states(&self) -> Vec<BodyState> {
+        (0..self.masses.len())
+            .map(|index| BodyState {
+                index,
+                position: self.positions[index],
+                velocity: self.velocities[index],
+                mass: self.masses[index],
+                radius: self.radii[index],
+            })
+            .collect()
+    }
+
+    /// Records the state after a step of `dt` in the attached diagnostics.
+    fn record_diagnostics(&mut self, dt: f64) {
+        let Some(mut diagnostics) = self.diagnostics.take() else {
+            return;
+        };
+        diagnostics.record(&self.body_states(), &self.forces, dt);
+        self.diagnostics = Some(diagnostics);
+    }
+
+    /// Returns the sum of the generated forces on a body in a state.
+    fn generated_force(&self, index: usize, position: Vector3, velocity: Vector3) -> Vector3 {
+        let body = BodyState {
+            index,
+            position,
+            velocity,
+            mass: self.masses[index],
+            radius: self.radii[index],
+        };
+        self.force_generators
+            .iter()
+            .fold(Vector3::zero(), |sum, generator| {
+                sum + generator.force(&body)
+            })
+    }
+
+    /// Returns the total force on a body in a state.
+    fn total_force(&self, index: usize, position: Vector3, velocity: Vector3) -> Vector3 {
+        self.forces[index] + self.generated_force(index, position, velocity)
+    }
+
     #[allow(dead_code)]
     pub fn update_verlet(&mut self, dt: f64) {
+        let previous_positions = self.positions.clone();
         for i in 0..self.masses.len() {
             // Calculate acceleration from continuous force
-            self.accelerations[i] = self.forces[i] / self.masses[i];
+            self.accelerations[i] =
+                self.total_force(i, self.positions[i], self.velocities[i]) / self.masses[i];
+
+            if self.determinism == Determinism::FixedPoint {
+                (self.positions[i], self.velocities[i]) = fixed_point_verlet(
+                    self.positions[i],
+                    self.velocities[i],
+                    self.accelerations[i],
+                    dt,
+                );
+                self.accelerations[i] = Vector3::zero();
+                continue;
+            }
 
             // Update position using Verlet integration
             // x = x + vt + a*0.5*t^2
@@ -59,7 +500,8 @@ impl RigidBodySystem {
             let old_acceleration = self.accelerations[i];
 
             // Calculate new acceleration
-            self.accelerations[i] = self.forces[i] / self.masses[i];
+            self.accelerations[i] =
+                self.total_force(i, self.positions[i], self.velocities[i]) / self.masses[i];
 
             // Update velocity using average acceleration
             // v = v + 1/2(a_0+a)* t
@@ -68,38 +510,295 @@ impl RigidBodySystem {
             // Reset forces for next iteration
             self.accelerations[i] = Vector3::zero();
         }
+        self.finish_step(&previous_positions, dt);
+    }
+
+    /// Advances the bodies with semi-implicit Euler integration.
+    fn update_semi_implicit_euler(&mut self, dt: f64) {
+        let previous_positions = self.positions.clone();
+        for i in 0..self.masses.len() {
+            let acceleration =
+                self.total_force(i, self.positions[i], self.velocities[i]) / self.masses[i];
+            self.velocities[i] += acceleration * dt;
+            self.positions[i] += self.velocities[i] * dt;
+        }
+        self.finish_step(&previous_positions, dt);
+    }
+
+    /// Advances the bodies with adaptive Dormand-Prince integration,
+    /// starting from the substep the last step ended with.
+    fn update_rk45(&mut self, dt: f64, tolerance: f64) {
+        let previous_positions = self.positions.clone();
+        let states: Vec<State> = self
+            .positions
+            .iter()
+            .copied()
+            .zip(self.velocities.iter().copied())
+            .collect();
+        let (states, substep) =
+            integrate_adaptive(&states, dt, tolerance, self.adaptive_substep, |states| {
+                states
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &(position, velocity))| {
+                        self.total_force(i, position, velocity) / self.masses[i]
+                    })
+                    .collect()
+            });
+        self.adaptive_substep = substep;
+        self.set_states(states);
+        self.finish_step(&previous_positions, dt);
     }
 
     #[allow(dead_code)]
     pub fn update_rk4(&mut self, dt: f64, force_func: impl Fn(&Vector3, &Vector3) -> Vector3) {
-        for i in 0..self.masses.len() {
-            let k1v = force_func(&self.positions[i], &self.velocities[i]) / self.masses[i] * dt;
-            let k1r = self.velocities[i] * dt;
+        let previous_positions = self.positions.clone();
+        let states = self.rk4_states(dt, |i, position, velocity| {
+            force_func(&position, &velocity) + self.generated_force(i, position, velocity)
+        });
+        self.set_states(states);
+        self.finish_step(&previous_positions, dt);
+    }
+
+    /// Returns the state of every body after a step of classic Runge-Kutta
+    /// integration under a force, which is given a body's index, position
+    /// and velocity.
+    fn rk4_states(
+        &self,
+        dt: f64,
+        force_func: impl Fn(usize, Vector3, Vector3) -> Vector3,
+    ) -> Vec<State> {
+        (0..self.masses.len())
+            .map(|i| {
+                let (position, velocity) = (self.positions[i], self.velocities[i]);
+                let acceleration =
+                    |position, velocity| force_func(i, position, velocity) / self.masses[i];
+                let k1v = acceleration(position, velocity) * dt;
+                let k1r = velocity * dt;
+
+                let k2v = acceleration(position + k1r * 0.5, velocity + k1v * 0.5) * dt;
+                let k2r = (velocity + k1v * 0.5) * dt;
 
-            let k2v = force_func(
-                &(self.positions[i] + k1r * 0.5),
-                &(self.velocities[i] + k1v * 0.5),
-            ) / self.masses[i]
-                * dt;
-            let k2r = (self.velocities[i] + k1v * 0.5) * dt;
+                let k3v = acceleration(position + k2r * 0.5, velocity + k2v * 0.5) * dt;
+                let k3r = (velocity + k2v * 0.5) * dt;
 
-            let k3v = force_func(
-                &(self.positions[i] + k2r * 0.5),
-                &(self.velocities[i] + k2v * 0.5),
-            ) / self.masses[i]
-                * dt;
-            let k3r = (self.velocities[i] + k2v * 0.5) * dt;
+                let k4v = acceleration(position + k3r, velocity + k3v) * dt;
+                let k4r = (velocity + k3v) * dt;
 
-            let k4v = force_func(&(self.positions[i] + k3r), &(self.velocities[i] + k3v))
-                / self.masses[i]
-                * dt;
-            let k4r = (self.velocities[i] + k3v) * dt;
+                (
+                    position + (k1r + k2r * 2.0 + k3r * 2.0 + k4r) * (1.0 / 6.0),
+                    velocity + (k1v + k2v * 2.0 + k3v * 2.0 + k4v) * (1.0 / 6.0),
+                )
+            })
+            .collect()
+    }
+
+    /// Sets the position and velocity of every body.
+    fn set_states(&mut self, states: Vec<State>) {
+        for (i, (position, velocity)) in states.into_iter().enumerate() {
+            self.positions[i] = position;
+            self.velocities[i] = velocity;
+        }
+    }
+
+    /// Enforces constraints and resolves collisions after the bodies were
+    /// integrated over a step, then records diagnostics.
+    fn finish_step(&mut self, previous_positions: &[Vector3], dt: f64) {
+        self.enforce_constraints(previous_positions, dt);
+        self.collide(previous_positions, dt);
+        self.snap_to_fixed_point();
+        self.record_diagnostics(dt);
+    }
 
-            self.velocities[i] += (k1v + k2v * 2.0 + k3v * 2.0 + k4v) * (1.0 / 6.0);
-            self.velocities[i] += (k1r + k2r * 2.0 + k3r * 2.0 + k4r) * (1.0 / 6.0);
+    /// Moves constrained bodies back to their distances along the
+    /// constraints' directions at the start of the step, as in SHAKE, adding
+    /// the corrections to their velocities over the step, then removes the
+    /// part of their relative velocities that would stretch or compress the
+    /// constraints.
+    fn enforce_constraints(&mut self, previous_positions: &[Vector3], dt: f64) {
+        if self.constraints.is_empty() || dt <= 0.0 {
+            return;
+        }
+        for _ in 0..CONSTRAINT_ITERATIONS {
+            for constraint in 0..self.constraints.len() {
+                let DistanceConstraint { a, b, length } = self.constraints[constraint];
+                let total_weight = inverse_mass(self.masses[a]) + inverse_mass(self.masses[b]);
+                let direction = previous_positions[b] - previous_positions[a];
+                let offset = self.positions[b] - self.positions[a];
+                let alignment = 2.0 * total_weight * offset.dot(direction);
+                if alignment == 0.0 {
+                    continue;
+                }
+                // Newton's step towards |offset| = length along the direction
+                let correction = direction * ((offset.dot(offset) - length * length) / alignment);
+                self.move_constrained(a, correction * inverse_mass(self.masses[a]), dt);
+                self.move_constrained(b, correction * -inverse_mass(self.masses[b]), dt);
+            }
+        }
+        for &DistanceConstraint { a, b, .. } in &self.constraints {
+            let (weight_a, weight_b) = (inverse_mass(self.masses[a]), inverse_mass(self.masses[b]));
+            let offset = self.positions[b] - self.positions[a];
+            let distance = offset.magnitude();
+            if weight_a + weight_b == 0.0 || distance == 0.0 {
+                continue;
+            }
+            let normal = offset / distance;
+            let stretch = (self.velocities[b] - self.velocities[a]).dot(normal);
+            let impulse = normal * (stretch / (weight_a + weight_b));
+            self.velocities[a] += impulse * weight_a;
+            self.velocities[b] -= impulse * weight_b;
         }
     }
 
+    /// Moves a constrained body, changing its velocity to cover the move over a step.
+    fn move_constrained(&mut self, index: usize, offset: Vector3, dt: f64) {
+        self.positions[index] += offset;
+        self.velocities[index] += offset / dt;
+    }
+
+    /// Snaps positions and velocities to the fixed-point grid in fixed-point mode.
+    fn snap_to_fixed_point(&mut self) {
+        if self.determinism != Determinism::FixedPoint {
+            return;
+        }
+        for vector in self.positions.iter_mut().chain(&mut self.velocities) {
+            *vector = Vector3::new(
+                Fixed::snap(vector.x),
+                Fixed::snap(vector.y),
+                Fixed::snap(vector.z),
+            );
+        }
+    }
+
+    /// Returns `true` if a body collides with a collider, by their filters
+    /// and the pair filter.
+    fn collides_with_collider(&self, body: usize, index: usize) -> bool {
+        if !self.filters[body].collides_with(self.colliders[index].filter) {
+            return false;
+        }
+        match &self.pair_filter {
+            Some(filter) => filter(body, CollisionObject::Collider(index)),
+            None => true,
+        }
+    }
+
+    /// Bounces bodies that moved into colliders or each other during a
+    /// step, sweeping fast bodies opted into continuous collision detection
+    /// along their motion, and records trigger overlaps.
+    fn collide(&mut self, previous_positions: &[Vector3], dt: f64) {
+        self.trigger_events.clear();
+        self.collide_bodies();
+        if self.colliders.is_empty() {
+            return;
+        }
+        for (i, &previous_position) in previous_positions.iter().enumerate() {
+            if self.continuous[i] && self.velocities[i].magnitude() > self.ccd_threshold {
+                self.sweep(i, previous_position, dt);
+            }
+
+            // Push out of anything the body still overlaps
+            for (index, collider) in self.colliders.iter().enumerate() {
+                if !self.collides_with_collider(i, index) {
+                    continue;
+                }
+                let Some((normal, depth)) = collider.penetration(self.positions[i], self.radii[i])
+                else {
+                    continue;
+                };
+                if collider.trigger {
+                    self.trigger_events.push(TriggerEvent {
+                        body: i,
+                        collider: index,
+                    });
+                    continue;
+                }
+                self.positions[i] += normal * depth;
+                self.velocities[i] = bounce(self.velocities[i], normal, collider.restitution);
+            }
+        }
+    }
+
+    /// Separates overlapping pairs of bodies with a radius, in proportion to
+    /// their inverse masses. Every pair is tested, which suits up to a few
+    /// hundred colliding bodies.
+    fn collide_bodies(&mut self) {
+        let count = self.masses.len();
+        for a in 0..count {
+            if self.radii[a] == 0.0 {
+                continue;
+            }
+            for b in a + 1..count {
+                if self.radii[b] == 0.0 || !self.filters[a].collides_with(self.filters[b]) {
+                    continue;
+                }
+                let offset = self.positions[b] - self.positions[a];
+                let distance = offset.magnitude();
+                let depth = self.radii[a] + self.radii[b] - distance;
+                if depth <= 0.0 {
+                    continue;
+                }
+                if let Some(filter) = &self.pair_filter {
+                    if !filter(a, CollisionObject::Body(b)) {
+                        continue;
+                    }
+                }
+
+                let (weight_a, weight_b) =
+                    (inverse_mass(self.masses[a]), inverse_mass(self.masses[b]));
+                let total_weight = weight_a + weight_b;
+                if total_weight == 0.0 {
+                    continue;
+                }
+                let normal = if distance > 0.0 {
+                    offset / distance
+                } else {
+                    Vector3::new(0.0, 1.0, 0.0)
+                };
+                let correction = normal * (depth / total_weight);
+                self.positions[a] -= correction * weight_a;
+                self.positions[b] += correction * weight_b;
+
+                let approach = (self.velocities[b] - self.velocities[a]).dot(normal);
+                if approach < 0.0 {
+                    let impulse =
+                        normal * (-(1.0 + self.body_restitution) * approach / total_weight);
+                    self.velocities[a] -= impulse * weight_a;
+                    self.velocities[b] += impulse * weight_b;
+                }
+            }
+        }
+    }
+
+    /// Moves a body from its previous position along its motion, bouncing
+    /// off the first collider in its way and continuing with the rest of the step.
+    fn sweep(&mut self, i: usize, previous_position: Vector3, dt: f64) {
+        let radius = self.radii[i];
+        let mut start = previous_position;
+        let mut end = self.positions[i];
+        let mut remaining = dt;
+        for _ in 0..MAX_SWEEPS {
+            let hit = self
+                .colliders
+                .iter()
+                .enumerate()
+                .filter(|&(index, collider)| {
+                    !collider.trigger && self.collides_with_collider(i, index)
+                })
+                .filter_map(|(_, collider)| Some((collider.sweep(start, end, radius)?, collider)))
+                .min_by(|(a, _), (b, _)| a.time.total_cmp(&b.time));
+            let Some((Contact { time, normal }, collider)) = hit else {
+                self.positions[i] = end;
+                return;
+            };
+            self.velocities[i] = bounce(self.velocities[i], normal, collider.restitution);
+            remaining *= 1.0 - time;
+            start = start + (end - start) * time + normal * CONTACT_OFFSET;
+            end = start + self.velocities[i] * remaining;
+        }
+        // Out of bounces, so stop at the last contact
+        self.positions[i] = start;
+    }
+
     #[allow(dead_code)]
     pub fn apply_force(&mut self, index: usize, force: Vector3) {
         self.forces[index] += force;
@@ -132,4 +831,260 @@ impl RigidBodySystem {
     pub fn len(&self) -> usize {
         self.masses.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.masses.is_empty()
+    }
+}
+
+impl fmt::Debug for RigidBodySystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RigidBodySystem")
+            .field("bodies", &self.masses.len())
+            .field("colliders", &self.colliders)
+            .field("determinism", &self.determinism)
+            .field("pair_filter", &self.pair_filter.is_some())
+            .field("force_generators", &self.force_generators.len())
+            .field("diagnostics", &self.diagnostics)
+            .field("integrator", &self.integrator)
+            .field("constraints", &self.constraints.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Integrates a body over a step in fixed point, returning its new position and velocity.
+fn fixed_point_verlet(
+    position: Vector3,
+    velocity: Vector3,
+    acceleration: Vector3,
+    dt: f64,
+) -> (Vector3, Vector3) {
+    let dt = Fixed::from_f64(dt);
+    let half_dt_squared = Fixed::from_f64(0.5) * dt * dt;
+    let axis = |x: f64, v: f64, a: f64| {
+        let (x, v, a) = (Fixed::from_f64(x), Fixed::from_f64(v), Fixed::from_f64(a));
+        (
+            (x + v * dt + a * half_dt_squared).to_f64(),
+            (v + a * dt).to_f64(),
+        )
+    };
+    let (x, vx) = axis(position.x, velocity.x, acceleration.x);
+    let (y, vy) = axis(position.y, velocity.y, acceleration.y);
+    let (z, vz) = axis(position.z, velocity.z, acceleration.z);
+    (Vector3::new(x, y, z), Vector3::new(vx, vy, vz))
+}
+
+/// Returns the inverse of a mass, or zero for an immovable body without mass.
+fn inverse_mass(mass: f64) -> f64 {
+    if mass > 0.0 {
+        1.0 / mass
+    } else {
+        0.0
+    }
+}
+
+/// Reflects the part of a velocity moving into a surface, scaled by the restitution.
+fn bounce(velocity: Vector3, normal: Vector3, restitution: f64) -> Vector3 {
+    let approach = velocity.dot(normal);
+    if approach >= 0.0 {
+        return velocity;
+    }
+    velocity - normal * (approach * (1.0 + restitution))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RigidBodySystem;
+    use super::{CollisionObject, TriggerEvent};
+    use crate::physics::collider::{Collider, ColliderShape, CollisionFilter};
+    use crate::physics::determinism::{Determinism, Fixed};
+    use crate::physics::forces::BodyState;
+    use crate::physics::integrator::Integrator;
+    use crate::physics::vector3::Vector3;
+
+    fn projectile(continuous: bool) -> RigidBodySystem {
+        let mut system = RigidBodySystem::new();
+        system.add_collider(Collider::new(
+            ColliderShape::Box {
+                half_extents: Vector3::new(0.05, 10.0, 10.0),
+            },
+            Vector3::new(5.0, 0.0, 0.0),
+        ));
+        let bullet = system.add(0.01, Vector3::zero(), Vector3::new(600.0, 0.0, 0.0));
+        system.set_radius(bullet, 0.1);
+        system.set_continuous(bullet, continuous);
+        system.set_ccd_threshold(100.0);
+        system
+    }
+
+    #[test]
+    fn test_continuous_collision_stops_tunneling() {
+        // One step carries the bullet from one side of the wall to the other
+        let mut discrete = projectile(false);
+        discrete.update_verlet(1.0 / 60.0);
+        assert!(discrete.position(0).x > 5.0);
+
+        let mut swept = projectile(true);
+        swept.update_verlet(1.0 / 60.0);
+        assert!(swept.position(0).x < 4.85);
+        assert!((swept.velocity(0).x + 300.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fixed_point_steps_replay_identically() {
+        let run = |determinism| {
+            let mut system = projectile(true);
+            system.set_determinism(determinism);
+            system.add(
+                2.0,
+                Vector3::new(0.1, 0.2, 0.3),
+                Vector3::new(-1.0, 0.5, 0.0),
+            );
+            system.apply_force(1, Vector3::new(0.0, -19.62, 0.0));
+            for _ in 0..120 {
+                system.update_verlet(1.0 / 60.0);
+            }
+            system
+        };
+        let on_grid = |system: &RigidBodySystem| {
+            (0..system.len()).all(|i| {
+                let (position, velocity) = (system.position(i), system.velocity(i));
+                [position, velocity]
+                    .iter()
+                    .flat_map(|v| [v.x, v.y, v.z])
+                    .all(|value| Fixed::snap(value) == value)
+            })
+        };
+
+        // Float steps leave the state between grid points, fixed-point steps don't
+        let float = run(Determinism::Off);
+        assert!(!on_grid(&float));
+        let fixed = run(Determinism::FixedPoint);
+        assert!(on_grid(&fixed));
+        assert!(fixed.position(1).y < 0.2);
+
+        // Checksums of fixed-point runs are the same on every platform
+        assert_eq!(fixed.checksum(), 0x491a_2e16_0e99_3cc7);
+    }
+
+    #[test]
+    fn test_collision_filters_and_triggers() {
+        const DEBRIS: u32 = 1 << 1;
+        let mut system = RigidBodySystem::new();
+        let floor = system.add_collider(Collider::new(
+            ColliderShape::Plane {
+                normal: Vector3::new(0.0, 1.0, 0.0),
+            },
+            Vector3::zero(),
+        ));
+        let trigger = system.add_collider(
+            Collider::new(
+                ColliderShape::Sphere { radius: 1.0 },
+                Vector3::new(0.0, 0.5, 0.0),
+            )
+            .with_trigger(true),
+        );
+
+        // Two overlapping pieces of debris pass through each other, not the floor
+        for x in [0.0, 0.1] {
+            let body = system.add(1.0, Vector3::new(x, 0.4, 0.0), Vector3::zero());
+            system.set_radius(body, 0.5);
+            system.set_filter(body, CollisionFilter::new(DEBRIS, !DEBRIS));
+        }
+        system.update_verlet(1.0 / 60.0);
+        assert_eq!(system.position(0).x, 0.0);
+        assert_eq!(system.position(1).x, 0.1);
+        assert_eq!(system.position(0).y, 0.5);
+        assert_eq!(
+            system.trigger_events(),
+            [
+                TriggerEvent {
+                    body: 0,
+                    collider: trigger
+                },
+                TriggerEvent {
+                    body: 1,
+                    collider: trigger
+                }
+            ]
+        );
+
+        // Rays skip triggers and groups outside their mask
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let origin = Vector3::new(0.0, 5.0, 0.0);
+        let hit = system.raycast(origin, down, 10.0, DEBRIS).unwrap();
+        assert_eq!(hit.object, CollisionObject::Body(0));
+        assert!((hit.distance - 4.0).abs() < 1e-9);
+        let hit = system
+            .raycast(origin, down, 10.0, CollisionFilter::DEFAULT_GROUP)
+            .unwrap();
+        assert_eq!(hit.object, CollisionObject::Collider(floor));
+
+        // The pair filter can veto contacts the groups allow
+        system.set_filter(1, CollisionFilter::default());
+        system.set_pair_filter(Some(Box::new(|_, object| {
+            !matches!(object, CollisionObject::Body(_))
+        })));
+        system.update_verlet(1.0 / 60.0);
+        assert_eq!(system.position(1).x, 0.1);
+        system.set_pair_filter(None);
+        system.update_verlet(1.0 / 60.0);
+        assert!(system.position(1).x > 0.1);
+    }
+
+    #[test]
+    fn test_integrators_follow_oscillator() {
+        // x'' = -4x, so x = cos 2t
+        let error_after = |integrator: Integrator, dt: f64| {
+            let mut system = RigidBodySystem::new();
+            system.add(1.0, Vector3::new(1.0, 0.0, 0.0), Vector3::zero());
+            system.add_force_generator(|body: &BodyState| body.position * -4.0);
+            system.set_integrator(integrator);
+            let steps = (10.0 / dt).round() as usize;
+            for _ in 0..steps {
+                system.step(dt);
+            }
+            (system.position(0).x - 20f64.cos()).abs()
+        };
+        assert!(error_after(Integrator::SemiImplicitEuler, 0.05) < 0.1);
+        assert!(error_after(Integrator::VelocityVerlet, 0.05) < 0.02);
+        assert!(error_after(Integrator::Rk4, 0.05) < 1e-4);
+        assert!(error_after(Integrator::Rk45 { tolerance: 1e-9 }, 0.5) < 1e-6);
+
+        // Runge-Kutta is exact for constant acceleration
+        let mut system = RigidBodySystem::new();
+        system.add(2.0, Vector3::zero(), Vector3::new(1.0, 0.0, 0.0));
+        system.update_rk4(1.0, |_, _| Vector3::new(0.0, -20.0, 0.0));
+        assert!((system.position(0) - Vector3::new(1.0, -5.0, 0.0)).magnitude() < 1e-12);
+        assert!((system.velocity(0) - Vector3::new(1.0, -10.0, 0.0)).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn test_distance_constraint_holds_spinning_pair() {
+        // Unconstrained, the bodies would fly apart along their tangents
+        for integrator in [Integrator::SemiImplicitEuler, Integrator::VelocityVerlet] {
+            let mut system = RigidBodySystem::new();
+            let a = system.add(
+                1.0,
+                Vector3::new(-1.0, 0.0, 0.0),
+                Vector3::new(0.0, -1.0, 0.0),
+            );
+            let b = system.add(
+                1.0,
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+            );
+            system.add_distance_constraint(a, b, 2.0);
+            system.set_integrator(integrator);
+            for _ in 0..600 {
+                system.step(1.0 / 60.0);
+            }
+            let (pa, pb) = (system.position(a), system.position(b));
+            assert!(((pb - pa).magnitude() - 2.0).abs() < 1e-6);
+            assert!((pa + pb).magnitude() < 1e-9);
+            let relative = system.velocity(b) - system.velocity(a);
+            assert!(relative.dot(pb - pa).abs() < 1e-6);
+            assert!((relative.magnitude() - 2.0).abs() < 0.05);
+        }
+    }
 }
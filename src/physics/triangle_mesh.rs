@@ -0,0 +1,364 @@
+//! Triangle mesh module for the physics engine.
+//!
+//! This module provides `TriangleMesh`, the static triangles of a level or an
+//! imported model that spheres collide with, and the sphere-triangle tests
+//! shared with heightfields. Triangles are solid from both sides and have no
+//! inside, so a sphere that ends up behind one is pushed back out the side it
+//! is nearest to.
+//!
+//! A mesh's triangles are found through the renderer's bounding volume
+//! hierarchy (BVH), built in f32 over the mesh's local coordinates. Queries
+//! visit only the triangles whose BVH leaves overlap the query's bounding
+//! box, so large meshes cost little more to collide with than small ones.
+
+use super::collider::Contact;
+use super::vector3::Vector3;
+use crate::math::geometry::closest_point_on_triangle;
+use crate::renderer::{Aabb, Bvh, Mesh};
+use glam::{Mat4, Vec3};
+use std::fmt;
+
+/// How far query boxes are grown before being tested against the BVH, so
+/// that rounding to f32 never drops a triangle.
+const QUERY_PADDING: f32 = 1e-3;
+
+/// Static triangles spheres collide with, in the space of their collider.
+#[derive(Clone, PartialEq)]
+pub struct TriangleMesh {
+    vertices: Vec<Vector3>,
+    triangles: Vec<[u32; 3]>,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    /// Creates a mesh and builds its BVH. Triangles with a vertex index out
+    /// of range are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertices` - The positions of the vertices, relative to the collider.
+    /// * `triangles` - The vertex indices of each triangle.
+    pub fn new(vertices: Vec<Vector3>, mut triangles: Vec<[u32; 3]>) -> Self {
+        triangles.retain(|triangle| {
+            triangle
+                .iter()
+                .all(|&index| (index as usize) < vertices.len())
+        });
+        let to_vec3 = |v: Vector3| Vec3::new(v.x as f32, v.y as f32, v.z as f32);
+        let corners: Vec<[Vec3; 3]> = triangles
+            .iter()
+            .map(|triangle| triangle.map(|index| to_vec3(vertices[index as usize])))
+            .collect();
+        Self {
+            bvh: Bvh::new(&corners),
+            vertices,
+            triangles,
+        }
+    }
+
+    /// Creates a mesh from the triangles of a renderer mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh` - The mesh. Points and lines have no triangles.
+    /// * `transform` - The transform baked into the vertices, e.g. a scene
+    ///   node's rotation and scale.
+    pub fn from_mesh(mesh: &Mesh, transform: &Mat4) -> Self {
+        let vertices = mesh
+            .vertices
+            .iter()
+            .map(|vertex| Vector3::from(transform.transform_point3(Vec3::from(vertex.position))))
+            .collect();
+        let triangles = (0..mesh.triangle_count())
+            .filter_map(|triangle| mesh.triangle(triangle))
+            .map(|corners| corners.map(|index| index as u32))
+            .collect();
+        Self::new(vertices, triangles)
+    }
+
+    /// Returns the number of triangles.
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Returns `true` if the mesh has no triangles.
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    /// Returns the corners of a triangle.
+    pub fn corners(&self, triangle: usize) -> [Vector3; 3] {
+        self.triangles[triangle].map(|index| self.vertices[index as usize])
+    }
+
+    /// Visits the triangles that may overlap a box.
+    fn candidates(&self, min: Vector3, max: Vector3, mut visit: impl FnMut([Vector3; 3])) {
+        let bounds = Aabb::new(
+            Vec3::new(min.x as f32, min.y as f32, min.z as f32) - QUERY_PADDING,
+            Vec3::new(max.x as f32, max.y as f32, max.z as f32) + QUERY_PADDING,
+        );
+        self.bvh
+            .overlapping(&bounds, |triangle| visit(self.corners(triangle)));
+    }
+
+    /// Finds how a sphere overlaps the mesh, by its deepest triangle.
+    ///
+    /// # Returns
+    ///
+    /// The direction to push the sphere out along and the distance to push
+    /// it, or `None` if the sphere doesn't overlap the mesh.
+    pub fn penetration(&self, center: Vector3, radius: f64) -> Option<(Vector3, f64)> {
+        let reach = Vector3::new(radius, radius, radius);
+        let mut deepest: Option<(Vector3, f64)> = None;
+        self.candidates(center - reach, center + reach, |corners| {
+            let Some((normal, depth)) = triangle_penetration(center, radius, corners) else {
+                return;
+            };
+            if deepest.is_none_or(|(_, deepest)| depth > deepest) {
+                deepest = Some((normal, depth));
+            }
+        });
+        deepest
+    }
+
+    /// Finds where a sphere moving along a segment first touches the mesh.
+    ///
+    /// # Returns
+    ///
+    /// The first contact, or `None` if the sphere misses the mesh.
+    pub fn sweep(&self, start: Vector3, end: Vector3, radius: f64) -> Option<Contact> {
+        let reach = Vector3::new(radius, radius, radius);
+        let min = Vector3::new(start.x.min(end.x), start.y.min(end.y), start.z.min(end.z));
+        let max = Vector3::new(start.x.max(end.x), start.y.max(end.y), start.z.max(end.z));
+        let mut first: Option<Contact> = None;
+        self.candidates(min - reach, max + reach, |corners| {
+            first = earlier(first, sweep_triangle(start, end - start, radius, corners));
+        });
+        first
+    }
+}
+
+impl fmt::Debug for TriangleMesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TriangleMesh")
+            .field("vertices", &self.vertices.len())
+            .field("triangles", &self.triangles.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns the earlier of two contacts.
+pub(crate) fn earlier(a: Option<Contact>, b: Option<Contact>) -> Option<Contact> {
+    match (a, b) {
+        (Some(a), Some(b)) if b.time < a.time => Some(b),
+        (Some(a), _) => Some(a),
+        (None, b) => b,
+    }
+}
+
+/// Returns the unit normal of a triangle by its winding, or zero if it is degenerate.
+pub(crate) fn triangle_normal([a, b, c]: [Vector3; 3]) -> Vector3 {
+    (b - a).cross(c - a).normalize()
+}
+
+/// Finds how a sphere overlaps a triangle.
+///
+/// # Returns
+///
+/// The direction from the triangle to the sphere's center and the distance
+/// to push the sphere along it, or `None` if they don't overlap.
+pub(crate) fn triangle_penetration(
+    center: Vector3,
+    radius: f64,
+    corners: [Vector3; 3],
+) -> Option<(Vector3, f64)> {
+    let offset = center - closest_point_on_triangle(center, corners);
+    let distance = offset.magnitude();
+    if distance >= radius {
+        return None;
+    }
+    let normal = if distance > 0.0 {
+        offset / distance
+    } else {
+        triangle_normal(corners)
+    };
+    Some((normal, radius - distance))
+}
+
+/// Finds where a sphere moving from `start` by `motion` first touches a
+/// triangle, on its face, an edge or a corner.
+///
+/// # Returns
+///
+/// The first contact, or `None` if the sphere misses the triangle or already
+/// overlaps it at the start.
+pub(crate) fn sweep_triangle(
+    start: Vector3,
+    motion: Vector3,
+    radius: f64,
+    corners: [Vector3; 3],
+) -> Option<Contact> {
+    if (start - closest_point_on_triangle(start, corners)).magnitude() < radius {
+        return None;
+    }
+    let mut time: Option<f64> = None;
+    let mut consider = |t: f64| {
+        if (0.0..=1.0).contains(&t) && time.is_none_or(|time| t < time) {
+            time = Some(t);
+        }
+    };
+
+    // The face, from whichever side the sphere starts on
+    let normal = triangle_normal(corners);
+    let height = (start - corners[0]).dot(normal);
+    let facing = if height >= 0.0 { normal } else { normal * -1.0 };
+    let approach = motion.dot(facing);
+    if approach < 0.0 {
+        let t = (height.abs() - radius) / -approach;
+        let touch = start + motion * t - facing * radius;
+        if (touch - closest_point_on_triangle(touch, corners)).magnitude() <= 1e-9 {
+            consider(t);
+        }
+    }
+
+    // The edges, as cylinders, and the corners, as spheres
+    for k in 0..3 {
+        let (a, b) = (corners[k], corners[(k + 1) % 3]);
+        if let Some(t) = sweep_segment(start, motion, radius, a, b) {
+            consider(t);
+        }
+        if let Some(t) = sweep_point(start, motion, radius, a) {
+            consider(t);
+        }
+    }
+
+    let time = time?;
+    let center = start + motion * time;
+    let offset = center - closest_point_on_triangle(center, corners);
+    let distance = offset.magnitude();
+    // Rays touch the triangle itself, so fall back to the face's normal
+    let normal = if distance > 1e-9 {
+        offset / distance
+    } else {
+        facing
+    };
+    Some(Contact { time, normal })
+}
+
+/// Returns when a sphere moving from `start` by `motion` first touches a point.
+fn sweep_point(start: Vector3, motion: Vector3, radius: f64, point: Vector3) -> Option<f64> {
+    let origin = start - point;
+    let a = motion.dot(motion);
+    let b = origin.dot(motion);
+    let c = origin.dot(origin) - radius * radius;
+    let discriminant = b * b - a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    Some((-b - discriminant.sqrt()) / a)
+}
+
+/// Returns when a sphere moving from `start` by `motion` first touches the
+/// segment between `a` and `b`, excluding its ends.
+fn sweep_segment(
+    start: Vector3,
+    motion: Vector3,
+    radius: f64,
+    a: Vector3,
+    b: Vector3,
+) -> Option<f64> {
+    let edge = b - a;
+    let length_squared = edge.dot(edge);
+    if length_squared == 0.0 {
+        return None;
+    }
+    let across = |v: Vector3| v - edge * (v.dot(edge) / length_squared);
+    let origin = start - a;
+    let (origin_across, motion_across) = (across(origin), across(motion));
+    let qa = motion_across.dot(motion_across);
+    let qb = origin_across.dot(motion_across);
+    let qc = origin_across.dot(origin_across) - radius * radius;
+    let discriminant = qb * qb - qa * qc;
+    if qa == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let time = (-qb - discriminant.sqrt()) / qa;
+    let along = (origin + motion * time).dot(edge) / length_squared;
+    (0.0..=1.0).contains(&along).then_some(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{triangle_penetration, TriangleMesh};
+    use crate::physics::vector3::Vector3;
+
+    /// A ramp rising along +x, from 0 at x = 0 to 1 at x = 1, and a wall at x = 2.
+    fn ramp() -> TriangleMesh {
+        let vertices = vec![
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(1.0, 1.0, -1.0),
+            Vector3::new(2.0, 0.0, -1.0),
+            Vector3::new(2.0, 0.0, 1.0),
+            Vector3::new(2.0, 3.0, 1.0),
+            Vector3::new(2.0, 3.0, -1.0),
+        ];
+        TriangleMesh::new(
+            vertices,
+            vec![[0, 1, 2], [0, 2, 3], [4, 6, 5], [4, 7, 6], [0, 1, 99]],
+        )
+    }
+
+    #[test]
+    fn test_triangle_mesh_queries() {
+        let mesh = ramp();
+        assert_eq!(mesh.len(), 4);
+
+        // A sphere resting on the ramp is pushed out along its normal
+        let up_ramp = Vector3::new(-1.0, 1.0, 0.0).normalize();
+        let center = Vector3::new(0.5, 0.5, 0.0) + up_ramp * 0.2;
+        let (normal, depth) = mesh.penetration(center, 0.3).unwrap();
+        assert!((normal - up_ramp).magnitude() < 1e-9);
+        assert!((depth - 0.1).abs() < 1e-9);
+        assert!(mesh.penetration(center, 0.1).is_none());
+
+        // Falling onto the face, then flying into the wall's edge
+        let contact = mesh
+            .sweep(
+                Vector3::new(0.5, 3.0, 0.0),
+                Vector3::new(0.5, -3.0, 0.0),
+                0.0,
+            )
+            .unwrap();
+        assert!((contact.time - 2.5 / 6.0).abs() < 1e-9);
+        assert!((contact.normal - up_ramp).magnitude() < 1e-9);
+        let contact = mesh
+            .sweep(
+                Vector3::new(1.5, 3.2, 0.0),
+                Vector3::new(2.5, 3.2, 0.0),
+                0.5,
+            )
+            .unwrap();
+        let center = Vector3::new(1.5 + contact.time, 3.2, 0.0);
+        assert!(((center - Vector3::new(2.0, 3.0, 0.0)).magnitude() - 0.5).abs() < 1e-9);
+        assert!(contact.normal.x < 0.0 && contact.normal.y > 0.0);
+    }
+
+    #[test]
+    fn test_degenerate_triangles() {
+        // Collinear and collapsed triangles act as their edges and corners
+        let center = Vector3::new(0.5, 0.2, 0.0);
+        let line = [
+            Vector3::zero(),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(2.0, 0.0, 0.0),
+        ];
+        let (normal, depth) = triangle_penetration(center, 0.5, line).unwrap();
+        assert!((normal - Vector3::new(0.0, 1.0, 0.0)).magnitude() < 1e-12);
+        assert!((depth - 0.3).abs() < 1e-12);
+        let (normal, depth) = triangle_penetration(center, 1.0, [Vector3::zero(); 3]).unwrap();
+        assert!((normal - center.normalize()).magnitude() < 1e-12);
+        assert!((depth - (1.0 - center.magnitude())).abs() < 1e-12);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::math::geometry::GeometryVector;
+use glam::Vec3;
 use std::cmp::PartialEq;
-use std::ops::{Add, AddAssign, Div, Mul, Sub};
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Vector3 {
     pub x: f64,
     pub y: f64,
@@ -32,6 +34,18 @@ impl Vector3 {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
 
+    pub fn dot(&self, other: Vector3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: Vector3) -> Self {
+        Vector3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
     #[allow(dead_code)]
     pub fn normalize(&self) -> Self {
         let mag = self.magnitude();
@@ -86,10 +100,41 @@ impl AddAssign for Vector3 {
     }
 }
 
+impl SubAssign for Vector3 {
+    fn sub_assign(&mut self, other: Self) {
+        self.x -= other.x;
+        self.y -= other.y;
+        self.z -= other.z;
+    }
+}
+
+/// Lets physics run the shared geometry queries in f64
+impl GeometryVector for Vector3 {
+    type Scalar = f64;
+
+    fn dot(self, other: Self) -> f64 {
+        Vector3::dot(&self, other)
+    }
+
+    fn to_array(self) -> [f64; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    fn from_array([x, y, z]: [f64; 3]) -> Self {
+        Vector3::new(x, y, z)
+    }
+}
+
+/// Widen a render-space vector, which is exact
+impl From<Vec3> for Vector3 {
+    fn from(vector: Vec3) -> Self {
+        Vector3::new(vector.x as f64, vector.y as f64, vector.z as f64)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::physics::vector3::Vector3;
-    use std::f64::EPSILON;
 
     #[test]
     fn test_vector3_operations() {
@@ -116,15 +161,18 @@ mod tests {
         let divided = v1 / 2.0;
         assert_eq!(divided, Vector3::new(0.5, 1.0, 1.5));
 
+        // Test dot product
+        assert_eq!(v1.dot(v2), 32.0);
+
         // Test magnitude
         let mag = v1.magnitude();
-        assert!((mag - 3.7416573867739413).abs() < EPSILON);
+        assert!((mag - 3.7416573867739413).abs() < f64::EPSILON);
 
         // Test normalize
         let normalized = v1.normalize();
-        assert!((normalized.magnitude() - 1.0).abs() < EPSILON);
-        assert!((normalized.x - 0.2672612419124244).abs() < EPSILON);
-        assert!((normalized.y - 0.5345224838248488).abs() < EPSILON);
-        assert!((normalized.z - 0.8017837257372732).abs() < EPSILON);
+        assert!((normalized.magnitude() - 1.0).abs() < f64::EPSILON);
+        assert!((normalized.x - 0.2672612419124244).abs() < f64::EPSILON);
+        assert!((normalized.y - 0.5345224838248488).abs() < f64::EPSILON);
+        assert!((normalized.z - 0.8017837257372732).abs() < f64::EPSILON);
     }
 }
@@ -0,0 +1,92 @@
+//! Ambient light module for the renderer.
+//!
+//! This module provides `AmbientLight`, the scene-level light reaching every
+//! surface regardless of direct lights, so surfaces facing away from them are
+//! not left pure black. Ambient light is a flat color, a hemisphere blending
+//! from a ground color below to a sky color above, or the irradiance of the
+//! procedural sky, following its time of day.
+//!
+//! Every mode resolves to the sky and ground colors of a hemisphere, bound
+//! with the frame constants. Fragments multiply their color by the hemisphere
+//! color along their normal, so the default flat white leaves colors as they
+//! are.
+
+use super::{common::Color, sky::Sky};
+use glam::Vec3;
+
+/// The ambient light reaching every surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AmbientLight {
+    /// The same light from every direction.
+    Flat(Color),
+    /// Light blending from the ground color for surfaces facing down to the
+    /// sky color for surfaces facing up.
+    Hemisphere { sky: Color, ground: Color },
+    /// The irradiance of the renderer's sky, scaled by the intensity, or the
+    /// default flat light while no sky is set.
+    Sky { intensity: f32 },
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        AmbientLight::Flat(Color::WHITE)
+    }
+}
+
+impl AmbientLight {
+    /// Resolves the ambient light into the colors of a hemisphere.
+    ///
+    /// # Arguments
+    ///
+    /// * `sky` - The renderer's sky, if set.
+    ///
+    /// # Returns
+    ///
+    /// The linear light reaching surfaces facing up and facing down.
+    pub fn hemisphere_colors(&self, sky: Option<&Sky>) -> (Vec3, Vec3) {
+        let rgb = |color: Color| Vec3::new(color.r, color.g, color.b);
+        match *self {
+            AmbientLight::Flat(color) => (rgb(color), rgb(color)),
+            AmbientLight::Hemisphere { sky, ground } => (rgb(sky), rgb(ground)),
+            AmbientLight::Sky { intensity } => match sky {
+                Some(sky) => {
+                    let (up, down) = sky.hemisphere_irradiance();
+                    (up * intensity, down * intensity)
+                }
+                None => AmbientLight::default().hemisphere_colors(None),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AmbientLight;
+    use crate::renderer::{common::Color, sky::Sky};
+    use glam::Vec3;
+
+    #[test]
+    fn test_hemisphere_colors() {
+        assert_eq!(
+            AmbientLight::default().hemisphere_colors(None),
+            (Vec3::ONE, Vec3::ONE)
+        );
+        let hemisphere = AmbientLight::Hemisphere {
+            sky: Color::new(0.4, 0.5, 0.6, 1.0),
+            ground: Color::new(0.1, 0.1, 0.0, 1.0),
+        };
+        assert_eq!(
+            hemisphere.hemisphere_colors(None),
+            (Vec3::new(0.4, 0.5, 0.6), Vec3::new(0.1, 0.1, 0.0))
+        );
+
+        // The sky lights surfaces facing up more than those facing down, and
+        // much less at night
+        let ambient = AmbientLight::Sky { intensity: 2.0 };
+        let (up, down) = ambient.hemisphere_colors(Some(&Sky::new(12.0)));
+        assert!(up.length() > down.length());
+        let (night, _) = ambient.hemisphere_colors(Some(&Sky::new(0.0)));
+        assert!(night.max_element() < up.min_element() * 0.2);
+        assert_eq!(ambient.hemisphere_colors(None), (Vec3::ONE, Vec3::ONE));
+    }
+}
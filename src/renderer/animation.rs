@@ -0,0 +1,502 @@
+//! Animation module for the renderer.
+//!
+//! This module provides `AnimationClip`, keyframed local transforms of scene
+//! nodes, and `AnimationStateMachine`, which decides which clip plays. Each
+//! state of the machine plays a clip; transitions between states fire when
+//! their conditions on the machine's parameters hold, cross-fading the clips
+//! over a blend duration. The application sets parameters such as a speed or
+//! a jump trigger, advances the machine every frame and applies its pose to
+//! the scene graph, reacting to the events fired as states are entered and
+//! exited.
+
+use super::scene_graph::{NodeId, SceneGraph};
+use crate::math::transform::Transform;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+
+/// A keyframe of a node's local transform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    /// The time of the keyframe in seconds from the start of the clip.
+    pub time: f32,
+    pub transform: Transform,
+}
+
+/// The keyframes of one node in a clip, sorted by time.
+#[derive(Clone, Debug, PartialEq)]
+struct NodeTrack {
+    node: NodeId,
+    keyframes: Vec<Keyframe>,
+}
+
+impl NodeTrack {
+    /// Interpolates the keyframes around a time, holding the first and last beyond them.
+    fn sample(&self, time: f32) -> Option<Transform> {
+        let next = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        match (
+            self.keyframes.get(next.wrapping_sub(1)),
+            self.keyframes.get(next),
+        ) {
+            (Some(a), Some(b)) => {
+                let t = (time - a.time) / (b.time - a.time);
+                Some(a.transform.slerp(b.transform, t))
+            }
+            (Some(keyframe), None) | (None, Some(keyframe)) => Some(keyframe.transform),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Keyframed local transforms of scene nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationClip {
+    tracks: Vec<NodeTrack>,
+    duration: f32,
+    looping: bool,
+}
+
+impl AnimationClip {
+    /// Creates a new, empty `AnimationClip`.
+    ///
+    /// # Arguments
+    ///
+    /// * `looping` - Whether the clip wraps around at its end rather than holding its last pose.
+    pub fn new(looping: bool) -> Self {
+        AnimationClip {
+            tracks: Vec::new(),
+            duration: 0.0,
+            looping,
+        }
+    }
+
+    /// Adds the keyframes of a node, replacing any it had.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node the keyframes move.
+    /// * `keyframes` - The times in seconds and local transforms, in any order.
+    pub fn with_track(mut self, node: NodeId, keyframes: &[(f32, Transform)]) -> Self {
+        let mut keyframes: Vec<_> = keyframes
+            .iter()
+            .map(|&(time, transform)| Keyframe { time, transform })
+            .collect();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self.tracks.retain(|track| track.node != node);
+        self.tracks.push(NodeTrack { node, keyframes });
+        self.duration = self
+            .tracks
+            .iter()
+            .filter_map(|track| track.keyframes.last())
+            .fold(0.0, |duration, keyframe| keyframe.time.max(duration));
+        self
+    }
+
+    /// Returns the time of the clip's last keyframe in seconds.
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Returns `true` if the clip wraps around at its end.
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Returns the local transform of every node in the clip at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time in seconds, wrapped into the clip if it loops.
+    pub fn sample(&self, time: f32) -> Vec<(NodeId, Transform)> {
+        let time = if self.looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        };
+        self.tracks
+            .iter()
+            .filter_map(|track| Some((track.node, track.sample(time)?)))
+            .collect()
+    }
+}
+
+/// A handle to a state of an `AnimationStateMachine`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StateId(pub usize);
+
+/// A state of an `AnimationStateMachine`, playing a clip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationState {
+    pub name: String,
+    pub clip: AnimationClip,
+    /// The rate the clip plays at, 1 for its authored speed.
+    pub speed: f32,
+}
+
+impl AnimationState {
+    /// Creates a new `AnimationState` playing a clip at its authored speed.
+    pub fn new(name: &str, clip: AnimationClip) -> Self {
+        AnimationState {
+            name: name.to_string(),
+            clip,
+            speed: 1.0,
+        }
+    }
+
+    /// Sets the rate the clip plays at.
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+}
+
+/// A test of the machine's parameters. Parameters that were never set are
+/// 0, `false` and not triggered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// A float parameter is greater than a value.
+    Greater(String, f32),
+    /// A float parameter is less than a value.
+    Less(String, f32),
+    /// A bool parameter has a value.
+    Bool(String, bool),
+    /// A trigger parameter was set. Triggers are consumed by the transition they fire.
+    Trigger(String),
+}
+
+/// A change of state, fired when its conditions hold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    /// The state the transition leaves, or `None` to leave any other state.
+    pub from: Option<StateId>,
+    pub to: StateId,
+    /// The seconds over which the clips cross-fade.
+    pub blend: f32,
+    /// The conditions, all of which must hold.
+    pub conditions: Vec<Condition>,
+    /// The playback of the current clip, in clip lengths, before the
+    /// transition can fire, e.g. 1 to finish a clip first.
+    pub exit_time: Option<f32>,
+}
+
+impl Transition {
+    /// Creates a new `Transition` between two states, firing unconditionally.
+    pub fn new(from: StateId, to: StateId, blend: f32) -> Self {
+        Transition {
+            from: Some(from),
+            to,
+            blend,
+            conditions: Vec::new(),
+            exit_time: None,
+        }
+    }
+
+    /// Creates a new `Transition` from any other state, firing unconditionally.
+    pub fn from_any(to: StateId, blend: f32) -> Self {
+        Transition {
+            from: None,
+            ..Self::new(to, to, blend)
+        }
+    }
+
+    /// Adds a condition that must hold for the transition to fire.
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Sets the playback of the current clip, in clip lengths, before the transition can fire.
+    pub fn with_exit_time(mut self, exit_time: f32) -> Self {
+        self.exit_time = Some(exit_time);
+        self
+    }
+}
+
+/// A state change reported by `AnimationStateMachine::update`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationEvent {
+    Exited(StateId),
+    Entered(StateId),
+}
+
+/// A playing state and its clip time in seconds.
+#[derive(Clone, Copy, Debug)]
+struct Playback {
+    state: StateId,
+    time: f32,
+}
+
+/// A cross-fade from the state being left.
+#[derive(Clone, Copy, Debug)]
+struct Blend {
+    from: Playback,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Chooses and blends the clips of animation states from parameter-driven transitions.
+#[derive(Debug, Default)]
+pub struct AnimationStateMachine {
+    states: Vec<AnimationState>,
+    transitions: Vec<Transition>,
+    floats: HashMap<String, f32>,
+    bools: HashMap<String, bool>,
+    triggers: HashSet<String>,
+    /// The playing state, or `None` until the first update.
+    current: Option<Playback>,
+    blend: Option<Blend>,
+}
+
+impl AnimationStateMachine {
+    /// Creates a new `AnimationStateMachine` without states.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a state. The first state added is entered on the first update.
+    pub fn add_state(&mut self, state: AnimationState) -> StateId {
+        debug!("Adding animation state \"{}\"", state.name);
+        self.states.push(state);
+        StateId(self.states.len() - 1)
+    }
+
+    /// Adds a transition. Transitions are tested in the order they were added.
+    pub fn add_transition(&mut self, transition: Transition) {
+        self.transitions.push(transition);
+    }
+
+    /// Returns a state by its ID.
+    pub fn state(&self, id: StateId) -> Option<&AnimationState> {
+        self.states.get(id.0)
+    }
+
+    /// Returns the playing state, or `None` before the first update.
+    pub fn current_state(&self) -> Option<StateId> {
+        self.current.map(|playback| playback.state)
+    }
+
+    /// Returns `true` while cross-fading from a previous state.
+    pub fn is_blending(&self) -> bool {
+        self.blend.is_some()
+    }
+
+    /// Sets a float parameter.
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.floats.insert(name.to_string(), value);
+    }
+
+    /// Sets a bool parameter.
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.bools.insert(name.to_string(), value);
+    }
+
+    /// Sets a trigger parameter, which stays set until a transition consumes it.
+    pub fn set_trigger(&mut self, name: &str) {
+        self.triggers.insert(name.to_string());
+    }
+
+    /// Clears a trigger parameter that no transition consumed.
+    pub fn reset_trigger(&mut self, name: &str) {
+        self.triggers.remove(name);
+    }
+
+    /// Advances the clips and fires the first transition whose conditions hold.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta` - The seconds since the last update.
+    ///
+    /// # Returns
+    ///
+    /// The states exited and entered during the update, in order.
+    pub fn update(&mut self, delta: f32) -> Vec<AnimationEvent> {
+        let mut events = Vec::new();
+        let Some(mut current) = self.current else {
+            if self.states.is_empty() {
+                return events;
+            }
+            self.current = Some(Playback {
+                state: StateId(0),
+                time: 0.0,
+            });
+            events.push(AnimationEvent::Entered(StateId(0)));
+            return events;
+        };
+
+        current.time += delta * self.states[current.state.0].speed;
+        if let Some(blend) = &mut self.blend {
+            blend.from.time += delta * self.states[blend.from.state.0].speed;
+            blend.elapsed += delta;
+            if blend.elapsed >= blend.duration {
+                self.blend = None;
+            }
+        }
+        self.current = Some(current);
+
+        if let Some(transition) = self
+            .transitions
+            .iter()
+            .find(|transition| self.can_fire(transition, current))
+            .cloned()
+        {
+            for condition in &transition.conditions {
+                if let Condition::Trigger(name) = condition {
+                    self.triggers.remove(name);
+                }
+            }
+            debug!(
+                "Animation state \"{}\" -> \"{}\"",
+                self.states[current.state.0].name, self.states[transition.to.0].name
+            );
+            events.push(AnimationEvent::Exited(current.state));
+            events.push(AnimationEvent::Entered(transition.to));
+            self.blend = (transition.blend > 0.0).then_some(Blend {
+                from: current,
+                elapsed: 0.0,
+                duration: transition.blend,
+            });
+            self.current = Some(Playback {
+                state: transition.to,
+                time: 0.0,
+            });
+        }
+        events
+    }
+
+    /// Sets the local transforms of the nodes the playing clips animate,
+    /// cross-fading from the state being left.
+    ///
+    /// Nodes that were removed from the graph are skipped.
+    pub fn apply(&self, graph: &mut SceneGraph) {
+        let Some(current) = self.current else {
+            return;
+        };
+        let mut pose = self.states[current.state.0].clip.sample(current.time);
+        if let Some(blend) = &self.blend {
+            let weight = (blend.elapsed / blend.duration).clamp(0.0, 1.0);
+            let from: HashMap<_, _> = self.states[blend.from.state.0]
+                .clip
+                .sample(blend.from.time)
+                .into_iter()
+                .collect();
+            for (node, transform) in &mut pose {
+                if let Some(&previous) = from.get(node) {
+                    *transform = previous.slerp(*transform, weight);
+                }
+            }
+        }
+        for (node, transform) in pose {
+            if graph.set_local_transform(node, transform).is_err() {
+                warn!("Animated scene node {} does not exist", node.0);
+            }
+        }
+    }
+
+    /// Returns `true` if a transition leaves the playing state and its conditions hold.
+    fn can_fire(&self, transition: &Transition, current: Playback) -> bool {
+        let leaves = match transition.from {
+            Some(from) => from == current.state,
+            None => transition.to != current.state,
+        };
+        if !leaves || transition.to.0 >= self.states.len() {
+            return false;
+        }
+        if let Some(exit_time) = transition.exit_time {
+            let duration = self.states[current.state.0].clip.duration();
+            if duration > 0.0 && current.time / duration < exit_time {
+                return false;
+            }
+        }
+        transition
+            .conditions
+            .iter()
+            .all(|condition| match condition {
+                Condition::Greater(name, value) => self.float(name) > *value,
+                Condition::Less(name, value) => self.float(name) < *value,
+                Condition::Bool(name, value) => {
+                    self.bools.get(name).copied().unwrap_or(false) == *value
+                }
+                Condition::Trigger(name) => self.triggers.contains(name),
+            })
+    }
+
+    fn float(&self, name: &str) -> f32 {
+        self.floats.get(name).copied().unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AnimationClip, AnimationEvent, AnimationState, AnimationStateMachine, Condition, StateId,
+        Transition,
+    };
+    use crate::{
+        math::transform::Transform,
+        renderer::scene_graph::{NodeId, SceneGraph},
+    };
+    use glam::{Mat4, Vec3};
+
+    fn at(x: f32) -> Transform {
+        Transform::from_translation(Vec3::new(x, 0.0, 0.0))
+    }
+
+    #[test]
+    fn test_animation_clip_sample() {
+        let node = NodeId(0);
+        let clip = AnimationClip::new(true).with_track(node, &[(2.0, at(4.0)), (0.0, at(0.0))]);
+        assert_eq!(clip.duration(), 2.0);
+        assert_eq!(clip.sample(0.5), [(node, at(1.0))]);
+        // Looping clips wrap around, others hold their last pose
+        assert_eq!(clip.sample(2.5), [(node, at(1.0))]);
+        let clip = AnimationClip {
+            looping: false,
+            ..clip
+        };
+        assert_eq!(clip.sample(2.5), [(node, at(4.0))]);
+    }
+
+    #[test]
+    fn test_animation_state_machine() {
+        let mut graph = SceneGraph::new();
+        let node = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let pose = |x| AnimationClip::new(true).with_track(node, &[(0.0, at(x)), (1.0, at(x))]);
+
+        let mut machine = AnimationStateMachine::new();
+        let idle = machine.add_state(AnimationState::new("idle", pose(0.0)));
+        let walk = machine.add_state(AnimationState::new("walk", pose(10.0)));
+        let jump = machine.add_state(AnimationState::new("jump", AnimationClip::new(false)));
+        machine.add_transition(
+            Transition::new(idle, walk, 1.0).when(Condition::Greater("speed".into(), 0.5)),
+        );
+        machine.add_transition(
+            Transition::from_any(jump, 0.0).when(Condition::Trigger("jump".into())),
+        );
+        machine.add_transition(Transition::new(jump, idle, 0.0).with_exit_time(1.0));
+
+        assert_eq!(machine.update(0.1), [AnimationEvent::Entered(idle)]);
+        assert!(machine.update(0.1).is_empty());
+
+        // Walking cross-fades from idle over a second
+        machine.set_float("speed", 1.0);
+        assert_eq!(
+            machine.update(0.1),
+            [AnimationEvent::Exited(idle), AnimationEvent::Entered(walk)]
+        );
+        machine.update(0.25);
+        machine.apply(&mut graph);
+        assert_eq!(graph.local_transform(node).unwrap().translation.x, 2.5);
+        machine.update(1.0);
+        assert!(!machine.is_blending());
+
+        // Triggers are consumed, and the jump returns to idle once its clip ends
+        machine.set_trigger("jump");
+        assert_eq!(machine.update(0.1)[1], AnimationEvent::Entered(jump));
+        assert_eq!(
+            machine.update(0.1),
+            [AnimationEvent::Exited(jump), AnimationEvent::Entered(idle)]
+        );
+        assert_eq!(machine.current_state(), Some(StateId(0)));
+    }
+}
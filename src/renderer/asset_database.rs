@@ -0,0 +1,644 @@
+//! Asset database module for the renderer.
+//!
+//! This module provides `AssetDatabase`, which gives every imported asset a
+//! stable `AssetGuid` and records its source path, import settings and the
+//! assets it depends on, e.g. a scene on its meshes and a mesh on its
+//! textures. Assets refer to each other by GUID, so renaming or moving a
+//! source file only updates the database and never breaks its dependents.
+//! When a source changes, `reimport_order` lists it and everything depending
+//! on it, dependencies first, so reimports cascade in the right order.
+//!
+//! The database is saved as TOML, one table per asset, with source paths
+//! relative to the asset root:
+//!
+//! ```text
+//! # game_engine asset database v2
+//! [[asset]]
+//! guid = "6f1c2a9e0d3b4c5a8e7f6d5c4b3a2910"
+//! kind = "mesh"
+//! path = "models/ship.obj"
+//! dependencies = ["0a1b2c3d4e5f60718293a4b5c6d7e8f9"]
+//!
+//! [asset.settings]
+//! scale = "0.01"
+//! ```
+//!
+//! Setting names and values are quoted as needed, so any text survives a
+//! save and load. Source paths must be valid UTF-8 to be saved.
+
+use super::error::AssetDatabaseError;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, BTreeSet, HashMap},
+    fmt, fs,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+/// The first line of every asset database file.
+const DATABASE_HEADER: &str = "# game_engine asset database v2";
+
+/// A globally unique, stable identifier of an asset.
+///
+/// GUIDs are written as 32 hex digits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct AssetGuid(pub u128);
+
+impl AssetGuid {
+    /// Generates a new random GUID.
+    pub fn generate() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        // Randomly keyed hashers make GUIDs differ between processes too
+        let half = |salt: u8| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u8(salt);
+            hasher.write_u64(count);
+            hasher.write_u128(nanos);
+            hasher.finish() as u128
+        };
+        AssetGuid(half(0) << 64 | half(1))
+    }
+}
+
+impl fmt::Display for AssetGuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for AssetGuid {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text.len() != 32 {
+            return Err(format!("expected 32 hex digits in GUID \"{text}\""));
+        }
+        u128::from_str_radix(text, 16)
+            .map(AssetGuid)
+            .map_err(|_| format!("invalid GUID \"{text}\""))
+    }
+}
+
+impl From<AssetGuid> for String {
+    fn from(guid: AssetGuid) -> Self {
+        guid.to_string()
+    }
+}
+
+impl TryFrom<String> for AssetGuid {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+/// The type of an asset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetKind {
+    Mesh,
+    Texture,
+    Material,
+    Scene,
+    ParticleEffect,
+    Audio,
+    Other,
+}
+
+/// An asset known to the database.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetRecord {
+    pub guid: AssetGuid,
+    pub kind: AssetKind,
+    /// The source file, relative to the asset root.
+    pub path: PathBuf,
+    /// The assets this asset depends on.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub dependencies: BTreeSet<AssetGuid>,
+    /// The options the asset is imported with, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub settings: BTreeMap<String, String>,
+}
+
+/// The contents of an asset database file.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatabaseFile<Record> {
+    #[serde(default = "Vec::new", rename = "asset")]
+    assets: Vec<Record>,
+}
+
+/// The assets of a project by GUID, with their sources and dependencies.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AssetDatabase {
+    assets: BTreeMap<AssetGuid, AssetRecord>,
+    paths: HashMap<PathBuf, AssetGuid>,
+}
+
+impl AssetDatabase {
+    /// Creates a new, empty `AssetDatabase`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a source file as an asset, or returns its GUID if it is
+    /// already registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The source file, relative to the asset root.
+    /// * `kind` - The type of the asset.
+    pub fn import(&mut self, path: impl Into<PathBuf>, kind: AssetKind) -> AssetGuid {
+        let path = path.into();
+        if let Some(&guid) = self.paths.get(&path) {
+            return guid;
+        }
+        let mut guid = AssetGuid::generate();
+        while self.assets.contains_key(&guid) {
+            guid = AssetGuid::generate();
+        }
+        debug!("Imported {} as {kind:?} asset {guid}", path.display());
+        self.insert(AssetRecord {
+            guid,
+            kind,
+            path,
+            settings: BTreeMap::new(),
+            dependencies: BTreeSet::new(),
+        });
+        guid
+    }
+
+    fn insert(&mut self, record: AssetRecord) {
+        self.paths.insert(record.path.clone(), record.guid);
+        self.assets.insert(record.guid, record);
+    }
+
+    /// Returns an asset.
+    pub fn get(&self, guid: AssetGuid) -> Option<&AssetRecord> {
+        self.assets.get(&guid)
+    }
+
+    /// Returns the asset imported from a source file.
+    pub fn guid(&self, path: impl AsRef<Path>) -> Option<AssetGuid> {
+        self.paths.get(path.as_ref()).copied()
+    }
+
+    /// Returns the source file of an asset, relative to the asset root.
+    pub fn path(&self, guid: AssetGuid) -> Option<&Path> {
+        self.assets.get(&guid).map(|record| record.path.as_path())
+    }
+
+    /// Returns every asset, ordered by GUID.
+    pub fn assets(&self) -> impl Iterator<Item = &AssetRecord> + '_ {
+        self.assets.values()
+    }
+
+    /// Returns the number of assets.
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    /// Returns `true` if the database has no assets.
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Moves an asset to a new source path, keeping its GUID.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `AssetDatabaseError::UnknownAsset`, or
+    /// `AssetDatabaseError::DuplicatePath` if another asset has the path.
+    pub fn rename(
+        &mut self,
+        guid: AssetGuid,
+        path: impl Into<PathBuf>,
+    ) -> Result<(), AssetDatabaseError> {
+        let path = path.into();
+        if self.paths.get(&path).is_some_and(|&other| other != guid) {
+            return Err(AssetDatabaseError::DuplicatePath(path));
+        }
+        let record = self
+            .assets
+            .get_mut(&guid)
+            .ok_or(AssetDatabaseError::UnknownAsset(guid))?;
+        self.paths.remove(&record.path);
+        info!(
+            "Renamed asset {guid} from {} to {}",
+            record.path.display(),
+            path.display()
+        );
+        record.path = path.clone();
+        self.paths.insert(path, guid);
+        Ok(())
+    }
+
+    /// Removes an asset that nothing depends on.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the removed asset, `AssetDatabaseError::UnknownAsset`,
+    /// or `AssetDatabaseError::HasDependents` if other assets depend on it.
+    pub fn remove(&mut self, guid: AssetGuid) -> Result<AssetRecord, AssetDatabaseError> {
+        if !self.assets.contains_key(&guid) {
+            return Err(AssetDatabaseError::UnknownAsset(guid));
+        }
+        let dependents = self.dependents(guid);
+        if !dependents.is_empty() {
+            return Err(AssetDatabaseError::HasDependents {
+                asset: guid,
+                dependents,
+            });
+        }
+        let record = self
+            .assets
+            .remove(&guid)
+            .ok_or(AssetDatabaseError::UnknownAsset(guid))?;
+        self.paths.remove(&record.path);
+        Ok(record)
+    }
+
+    /// Sets an import setting of an asset.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the replaced value, or `AssetDatabaseError::UnknownAsset`.
+    pub fn set_setting(
+        &mut self,
+        guid: AssetGuid,
+        name: &str,
+        value: impl Into<String>,
+    ) -> Result<Option<String>, AssetDatabaseError> {
+        let record = self
+            .assets
+            .get_mut(&guid)
+            .ok_or(AssetDatabaseError::UnknownAsset(guid))?;
+        Ok(record.settings.insert(name.to_string(), value.into()))
+    }
+
+    /// Returns an import setting of an asset.
+    pub fn setting(&self, guid: AssetGuid, name: &str) -> Option<&str> {
+        self.assets
+            .get(&guid)?
+            .settings
+            .get(name)
+            .map(String::as_str)
+    }
+
+    /// Records that an asset depends on another.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `AssetDatabaseError::UnknownAsset` if
+    /// either asset doesn't exist, or `AssetDatabaseError::CyclicDependency`
+    /// if the dependency already depends on the asset.
+    pub fn add_dependency(
+        &mut self,
+        asset: AssetGuid,
+        dependency: AssetGuid,
+    ) -> Result<(), AssetDatabaseError> {
+        for guid in [asset, dependency] {
+            if !self.assets.contains_key(&guid) {
+                return Err(AssetDatabaseError::UnknownAsset(guid));
+            }
+        }
+        if asset == dependency || self.depends_on(dependency, asset) {
+            return Err(AssetDatabaseError::CyclicDependency { asset, dependency });
+        }
+        if let Some(record) = self.assets.get_mut(&asset) {
+            record.dependencies.insert(dependency);
+        }
+        Ok(())
+    }
+
+    /// Removes the record that an asset depends on another, returning
+    /// `true` if it existed.
+    pub fn remove_dependency(&mut self, asset: AssetGuid, dependency: AssetGuid) -> bool {
+        self.assets
+            .get_mut(&asset)
+            .is_some_and(|record| record.dependencies.remove(&dependency))
+    }
+
+    /// Returns `true` if an asset depends on another, directly or through other assets.
+    pub fn depends_on(&self, asset: AssetGuid, dependency: AssetGuid) -> bool {
+        let mut stack = vec![asset];
+        let mut visited = BTreeSet::new();
+        while let Some(guid) = stack.pop() {
+            let Some(record) = self.assets.get(&guid) else {
+                continue;
+            };
+            for &next in &record.dependencies {
+                if next == dependency {
+                    return true;
+                }
+                if visited.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the assets depending directly on an asset.
+    pub fn dependents(&self, guid: AssetGuid) -> Vec<AssetGuid> {
+        self.assets
+            .values()
+            .filter(|record| record.dependencies.contains(&guid))
+            .map(|record| record.guid)
+            .collect()
+    }
+
+    /// Returns the assets to reimport after sources changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `changed` - The assets whose sources or settings changed.
+    ///
+    /// # Returns
+    ///
+    /// The changed assets and every asset depending on them, directly or
+    /// not, with each asset after the assets it depends on.
+    pub fn reimport_order(&self, changed: &[AssetGuid]) -> Vec<AssetGuid> {
+        let mut dependents: HashMap<AssetGuid, Vec<AssetGuid>> = HashMap::new();
+        for record in self.assets.values() {
+            for &dependency in &record.dependencies {
+                dependents.entry(dependency).or_default().push(record.guid);
+            }
+        }
+        // Every asset reachable from the changed ones
+        let mut affected = BTreeSet::new();
+        let mut stack: Vec<AssetGuid> = changed
+            .iter()
+            .copied()
+            .filter(|guid| self.assets.contains_key(guid))
+            .collect();
+        while let Some(guid) = stack.pop() {
+            if affected.insert(guid) {
+                stack.extend(dependents.get(&guid).into_iter().flatten());
+            }
+        }
+
+        // Depth-first, placing each asset after its affected dependencies
+        let mut order = Vec::with_capacity(affected.len());
+        let mut placed = BTreeSet::new();
+        for &guid in &affected {
+            self.place_after_dependencies(guid, &affected, &mut placed, &mut order);
+        }
+        order
+    }
+
+    fn place_after_dependencies(
+        &self,
+        guid: AssetGuid,
+        affected: &BTreeSet<AssetGuid>,
+        placed: &mut BTreeSet<AssetGuid>,
+        order: &mut Vec<AssetGuid>,
+    ) {
+        if !placed.insert(guid) {
+            return;
+        }
+        for &dependency in &self.assets[&guid].dependencies {
+            if affected.contains(&dependency) {
+                self.place_after_dependencies(dependency, affected, placed, order);
+            }
+        }
+        order.push(guid);
+    }
+
+    /// Loads a database saved with `save`.
+    pub fn load(path: &Path) -> Result<Self, AssetDatabaseError> {
+        let text = fs::read_to_string(path).map_err(|source| AssetDatabaseError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let database: AssetDatabase = text.parse()?;
+        info!(
+            "Loaded asset database {} with {} assets",
+            path.display(),
+            database.len()
+        );
+        Ok(database)
+    }
+
+    /// Returns the database as the TOML written by `save`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the text, or `AssetDatabaseError::NonUtf8Path`
+    /// if a source path isn't valid UTF-8 and couldn't be found again after
+    /// loading.
+    pub fn to_toml(&self) -> Result<String, AssetDatabaseError> {
+        if let Some(record) = self.assets().find(|record| record.path.to_str().is_none()) {
+            return Err(AssetDatabaseError::NonUtf8Path(record.path.clone()));
+        }
+        let file = DatabaseFile {
+            assets: self.assets().collect(),
+        };
+        let text = toml::to_string(&file)
+            .map_err(|error| AssetDatabaseError::Serialize(error.to_string()))?;
+        Ok(format!("{DATABASE_HEADER}\n{text}"))
+    }
+
+    /// Saves the database as TOML.
+    pub fn save(&self, path: &Path) -> Result<(), AssetDatabaseError> {
+        fs::write(path, self.to_toml()?).map_err(|source| AssetDatabaseError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        info!(
+            "Saved asset database {} with {} assets",
+            path.display(),
+            self.len()
+        );
+        Ok(())
+    }
+}
+
+impl FromStr for AssetDatabase {
+    type Err = AssetDatabaseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let file: DatabaseFile<AssetRecord> =
+            toml::from_str(text).map_err(|error| AssetDatabaseError::Parse {
+                line: error
+                    .span()
+                    .map_or(1, |span| text[..span.start].matches('\n').count() + 1),
+                message: error.message().to_string(),
+            })?;
+
+        let mut database = AssetDatabase::new();
+        let mut dependencies = Vec::new();
+        for mut record in file.assets {
+            if database.assets.contains_key(&record.guid) {
+                return Err(AssetDatabaseError::DuplicateAsset(record.guid));
+            }
+            if database.paths.contains_key(&record.path) {
+                return Err(AssetDatabaseError::DuplicatePath(record.path));
+            }
+            // Dependencies may name assets listed after them
+            let guid = record.guid;
+            dependencies.extend(
+                std::mem::take(&mut record.dependencies)
+                    .into_iter()
+                    .map(|dependency| (guid, dependency)),
+            );
+            database.insert(record);
+        }
+        for (asset, dependency) in dependencies {
+            database.add_dependency(asset, dependency)?;
+        }
+        Ok(database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AssetDatabase, AssetGuid, AssetKind};
+    use crate::renderer::error::AssetDatabaseError;
+
+    #[test]
+    fn test_asset_database_rename_and_round_trip() {
+        let mut database = AssetDatabase::new();
+        let texture = database.import("textures/hull.png", AssetKind::Texture);
+        let mesh = database.import("models/ship.obj", AssetKind::Mesh);
+        assert_ne!(texture, mesh);
+        assert_eq!(database.import("models/ship.obj", AssetKind::Mesh), mesh);
+        database.add_dependency(mesh, texture).unwrap();
+        database.set_setting(mesh, "scale", "0.01").unwrap();
+
+        // Renaming keeps the GUID, so dependents still find the asset
+        database.rename(texture, "textures/hull paint.png").unwrap();
+        assert_eq!(database.guid("textures/hull paint.png"), Some(texture));
+        assert_eq!(database.guid("textures/hull.png"), None);
+        assert!(database.get(mesh).unwrap().dependencies.contains(&texture));
+        assert!(matches!(
+            database.rename(mesh, "textures/hull paint.png"),
+            Err(AssetDatabaseError::DuplicatePath(_))
+        ));
+
+        let loaded: AssetDatabase = database.to_toml().unwrap().parse().unwrap();
+        assert_eq!(loaded, database);
+        assert_eq!(loaded.setting(mesh, "scale"), Some("0.01"));
+        assert_eq!(
+            loaded.path(texture).unwrap().to_str(),
+            Some("textures/hull paint.png")
+        );
+        assert_eq!(AssetGuid(0xAB).to_string().parse(), Ok(AssetGuid(0xAB)));
+    }
+
+    #[test]
+    fn test_asset_database_dependencies() {
+        let mut database = AssetDatabase::new();
+        let texture = database.import("hull.png", AssetKind::Texture);
+        let material = database.import("hull.material", AssetKind::Material);
+        let mesh = database.import("ship.obj", AssetKind::Mesh);
+        let scene = database.import("level.scene", AssetKind::Scene);
+        let unrelated = database.import("music.ogg", AssetKind::Audio);
+        database.add_dependency(scene, mesh).unwrap();
+        database.add_dependency(mesh, material).unwrap();
+        database.add_dependency(material, texture).unwrap();
+        database.add_dependency(scene, texture).unwrap();
+
+        assert!(database.depends_on(scene, texture));
+        assert!(matches!(
+            database.add_dependency(texture, scene),
+            Err(AssetDatabaseError::CyclicDependency { .. })
+        ));
+        assert!(matches!(
+            database.remove(texture),
+            Err(AssetDatabaseError::HasDependents { .. })
+        ));
+
+        // Changing the texture reimports everything built from it, in order
+        assert_eq!(
+            database.reimport_order(&[texture]),
+            [texture, material, mesh, scene]
+        );
+        assert_eq!(database.reimport_order(&[mesh]), [mesh, scene]);
+        assert!(database.remove(unrelated).is_ok());
+        assert_eq!(database.len(), 4);
+    }
+
+    #[test]
+    fn test_asset_database_settings_and_paths_round_trip() {
+        let mut database = AssetDatabase::new();
+        let mesh = database.import("models/big ship\n.obj", AssetKind::Mesh);
+        let sound = database.import("audio/ünïcode #1.ogg", AssetKind::Audio);
+        database.add_dependency(mesh, sound).unwrap();
+        let settings = [
+            ("scale", "0.01"),
+            ("up axis", " z "),
+            ("", ""),
+            ("line\nbreak", "a = \"b\"\n[c]"),
+            ("# not a comment", "\\"),
+        ];
+        for (name, value) in settings {
+            database.set_setting(mesh, name, value).unwrap();
+        }
+
+        let text = database.to_toml().unwrap();
+        assert!(text.starts_with("# game_engine asset database v2\n"));
+        let loaded: AssetDatabase = text.parse().unwrap();
+        assert_eq!(loaded, database);
+        for (name, value) in settings {
+            assert_eq!(loaded.setting(mesh, name), Some(value));
+        }
+        assert_eq!(loaded.guid("audio/ünïcode #1.ogg"), Some(sound));
+        assert_eq!(loaded.guid("models/big ship\n.obj"), Some(mesh));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_asset_database_rejects_non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf};
+
+        let mut database = AssetDatabase::new();
+        let path = PathBuf::from(OsStr::from_bytes(b"models/ship\xff.obj"));
+        database.import(path.clone(), AssetKind::Mesh);
+        assert!(matches!(
+            database.to_toml(),
+            Err(AssetDatabaseError::NonUtf8Path(bad)) if bad == path
+        ));
+    }
+
+    #[test]
+    fn test_asset_database_parse_errors() {
+        let guid = "0123456789abcdef0123456789abcdef";
+        let error = format!("[[asset]]\nguid = \"{guid}\"\nkind = \"spaceship\"\npath = \"a\"")
+            .parse::<AssetDatabase>()
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid asset database on line 3: unknown variant `spaceship`"));
+        let error = "[[asset]]\nguid = \"12\"\nkind = \"mesh\"\npath = \"a\""
+            .parse::<AssetDatabase>()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid asset database on line 2: expected 32 hex digits in GUID \"12\""
+        );
+
+        let twice = format!("[[asset]]\nguid = \"{guid}\"\nkind = \"mesh\"\npath = \"a\"\n");
+        assert!(matches!(
+            twice.repeat(2).parse::<AssetDatabase>(),
+            Err(AssetDatabaseError::DuplicateAsset(_))
+        ));
+        let dangling = format!("{twice}dependencies = [\"{}\"]", "f".repeat(32));
+        assert!(matches!(
+            dangling.parse::<AssetDatabase>(),
+            Err(AssetDatabaseError::UnknownAsset(AssetGuid(u128::MAX)))
+        ));
+        assert!("".parse::<AssetDatabase>().unwrap().is_empty());
+    }
+}
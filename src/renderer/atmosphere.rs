@@ -0,0 +1,167 @@
+//! Atmosphere module for the renderer.
+//!
+//! This module provides `Atmosphere`, an analytic single-scattering model of
+//! a planet's atmosphere in the style of O'Neil and Nishita. The backend
+//! ray-marches every pixel from the camera through the atmosphere shell up to
+//! the scene depth, so the same pass draws the glowing shell around a planet
+//! seen from space, the sky when the camera is inside the atmosphere, and
+//! aerial perspective over distant geometry.
+
+use glam::{Mat4, Vec3};
+
+/// Rayleigh scattering coefficients of Earth's air at sea level, per kilometer.
+const EARTH_RAYLEIGH_SCATTERING: Vec3 = Vec3::new(5.8e-3, 13.5e-3, 33.1e-3);
+
+/// The default ray-march steps per pixel.
+const DEFAULT_SAMPLE_COUNT: u32 = 16;
+
+/// A planet's atmosphere, lit by a sun.
+///
+/// Distances are in world units and scattering coefficients per world unit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Atmosphere {
+    pub planet_center: Vec3,
+    /// The radius of the planet's surface.
+    pub planet_radius: f32,
+    /// The radius of the top of the atmosphere, beyond which nothing scatters.
+    pub atmosphere_radius: f32,
+    /// The Rayleigh scattering coefficients at the surface, per color channel.
+    pub rayleigh_scattering: Vec3,
+    /// The altitude over which Rayleigh scattering density falls by a factor of e.
+    pub rayleigh_scale_height: f32,
+    /// The Mie scattering coefficient at the surface.
+    pub mie_scattering: f32,
+    /// The altitude over which Mie scattering density falls by a factor of e.
+    pub mie_scale_height: f32,
+    /// How strongly Mie scattering favors the forward direction, in (-1, 1).
+    pub mie_anisotropy: f32,
+    /// The world-space direction towards the sun.
+    pub sun_direction: Vec3,
+    pub sun_intensity: f32,
+    /// The ray-march steps per pixel, trading quality for speed.
+    pub sample_count: u32,
+}
+
+impl Atmosphere {
+    /// Creates an Earth-like atmosphere.
+    ///
+    /// # Arguments
+    ///
+    /// * `planet_center` - The world-space center of the planet.
+    /// * `units_per_kilometer` - The world units in a kilometer, scaling the planet to the scene.
+    pub fn earth(planet_center: Vec3, units_per_kilometer: f32) -> Self {
+        Self {
+            planet_center,
+            planet_radius: 6360.0 * units_per_kilometer,
+            atmosphere_radius: 6420.0 * units_per_kilometer,
+            rayleigh_scattering: EARTH_RAYLEIGH_SCATTERING / units_per_kilometer,
+            rayleigh_scale_height: 8.0 * units_per_kilometer,
+            mie_scattering: 21e-3 / units_per_kilometer,
+            mie_scale_height: 1.2 * units_per_kilometer,
+            mie_anisotropy: 0.76,
+            sun_direction: Vec3::new(0.0, 1.0, 0.0),
+            sun_intensity: 20.0,
+            sample_count: DEFAULT_SAMPLE_COUNT,
+        }
+    }
+
+    /// Sets the direction towards the sun.
+    pub fn with_sun(mut self, direction: Vec3, intensity: f32) -> Self {
+        self.sun_direction = direction;
+        self.sun_intensity = intensity;
+        self
+    }
+
+    /// Returns `true` if a point is inside the atmosphere shell or the planet.
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.distance(self.planet_center) < self.atmosphere_radius
+    }
+
+    /// Builds the constants of the atmosphere pass for a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    /// * `camera_position` - The world-space position of the camera.
+    pub fn constants(&self, view_projection: Mat4, camera_position: Vec3) -> AtmosphereConstants {
+        AtmosphereConstants {
+            inverse_view_projection: view_projection.inverse(),
+            camera_position: camera_position.to_array(),
+            atmosphere_radius: self.atmosphere_radius,
+            planet_center: self.planet_center.to_array(),
+            planet_radius: self.planet_radius,
+            rayleigh_scattering: self.rayleigh_scattering.to_array(),
+            rayleigh_scale_height: self.rayleigh_scale_height,
+            sun_direction: self.sun_direction.normalize_or_zero().to_array(),
+            sun_intensity: self.sun_intensity,
+            mie_scattering: self.mie_scattering,
+            mie_scale_height: self.mie_scale_height,
+            mie_anisotropy: self.mie_anisotropy.clamp(-0.999, 0.999),
+            sample_count: self.sample_count.max(1),
+        }
+    }
+}
+
+/// The per-frame constants of the atmosphere pass.
+///
+/// The layout matches the `AtmosphereConstants` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtmosphereConstants {
+    pub inverse_view_projection: Mat4,
+    pub camera_position: [f32; 3],
+    pub atmosphere_radius: f32,
+    pub planet_center: [f32; 3],
+    pub planet_radius: f32,
+    pub rayleigh_scattering: [f32; 3],
+    pub rayleigh_scale_height: f32,
+    pub sun_direction: [f32; 3],
+    pub sun_intensity: f32,
+    pub mie_scattering: f32,
+    pub mie_scale_height: f32,
+    pub mie_anisotropy: f32,
+    pub sample_count: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Atmosphere, AtmosphereConstants};
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_atmosphere_constants_layout() {
+        // Must match the struct in shader_types.h
+        assert_eq!(std::mem::size_of::<AtmosphereConstants>(), 144);
+    }
+
+    #[test]
+    fn test_earth_scales_with_units() {
+        let kilometers = Atmosphere::earth(Vec3::ZERO, 1.0);
+        let meters = Atmosphere::earth(Vec3::ZERO, 1000.0);
+        assert_eq!(meters.planet_radius, kilometers.planet_radius * 1000.0);
+        assert!((meters.mie_scattering * 1000.0 - kilometers.mie_scattering).abs() < 1e-9);
+
+        // The optical depth through the whole shell is independent of units
+        let optical_depth = |atmosphere: &Atmosphere| {
+            atmosphere.rayleigh_scattering.z * atmosphere.rayleigh_scale_height
+        };
+        assert!((optical_depth(&meters) - optical_depth(&kilometers)).abs() < 1e-6);
+
+        let surface = Vec3::new(0.0, 6361.0, 0.0);
+        assert!(kilometers.contains(surface));
+        assert!(!kilometers.contains(surface * 2.0));
+    }
+
+    #[test]
+    fn test_constants_invert_view_projection() {
+        let view_projection = Mat4::perspective_rh(1.0, 1.5, 0.1, 100.0);
+        let constants = Atmosphere::earth(Vec3::ZERO, 1.0)
+            .with_sun(Vec3::new(0.0, 2.0, 0.0), 10.0)
+            .constants(view_projection, Vec3::ONE);
+        assert!(
+            (constants.inverse_view_projection * view_projection).abs_diff_eq(Mat4::IDENTITY, 1e-5)
+        );
+        assert_eq!(constants.sun_direction, [0.0, 1.0, 0.0]);
+        assert_eq!(constants.camera_position, [1.0; 3]);
+    }
+}
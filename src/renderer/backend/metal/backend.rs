@@ -1,41 +1,154 @@
 //! Metal backend for the renderer.
 //!
 //! This module provides the implementation of the Metal graphics backend,
-//! which is responsible for rendering using the Metal API on macOS platforms.
+//! which is responsible for rendering using the Metal API on macOS and iOS.
 //!
 //! It includes the main `MetalBackend` struct and associated implementations
 //! for handling rendering operations, buffer management, and pipeline state creation.
 
-use super::buffer_manager::BufferManager;
-use super::pipeline::{create_default_pipeline_descriptor, RenderPipelineCache};
+use super::buffer_manager::{as_bytes, BufferBinding, BufferManager};
+use super::frame_readback::FrameReadback;
+use super::gpu_culling::{CulledInstances, GpuCuller};
+use super::material_table::MaterialTable;
+use super::overlay::Overlay;
+use super::pipeline::{
+    create_default_pipeline_descriptors, CachedPipeline, DepthStencilCache, PipelineVariant,
+    RenderPipelineCache, COLOR_PIXEL_FORMAT, FALLBACK_STRIDE_INDEX,
+};
+use super::post_process::PostProcess;
+use super::ray_tracing::RayTracing;
+use super::shadow_map::{attach_shadow_layer, ShadowMaps};
+use super::static_mesh::StaticMeshes;
 use super::texture_manager::TextureManager;
+use super::transparency::WeightedBlended;
+use crate::renderer::atmosphere::AtmosphereConstants;
 use crate::renderer::backend::GraphicsBackend;
-use crate::renderer::common::{BackendDrawCommand, RendererError, TextureId, Uniforms, Vertex};
+use crate::renderer::canvas::CanvasFrame;
+use crate::renderer::common::{
+    BackendDrawCommand, CaptureDestination, FrameConstants, RenderTarget, TemporalConstants,
+    TextureId, Uniforms, Vertex,
+};
+use crate::renderer::config::{AntiAliasing, Transparency};
+use crate::renderer::debug_view::DebugView;
+use crate::renderer::error::{AssetError, BackendError, RendererError};
+use crate::renderer::gpu_culling::CullingConstants;
+use crate::renderer::lens_flare::LensFlareFrame;
+use crate::renderer::lights::LightShadowFrame;
+use crate::renderer::material_manager::{Material, MaterialConstants, MaterialId};
+use crate::renderer::memory_report::GpuMemoryReport;
+use crate::renderer::ray_tracing::RayTracingFrame;
+use crate::renderer::recording::FrameImage;
+use crate::renderer::render_queue::GeometryView;
+use crate::renderer::render_state::{BlendMode, Outline, RenderState, StencilState};
+use crate::renderer::shadow_map::{ShadowMapFrame, ShadowTarget};
+use crate::renderer::sky::SkyConstants;
+use crate::renderer::texture_upload::TextureUpload;
+use crate::renderer::vertex_layout::{PackedVertices, VertexLayout};
+use crate::renderer::viewport::Viewport;
 use crate::renderer::InstanceData;
 use cocoa::base::id as cocoa_id;
-use core_graphics::display::CGSize;
+use core_graphics::{
+    display::CGSize,
+    geometry::{CGPoint, CGRect},
+};
+use glam::{Mat4, UVec2, Vec3};
 use log::{debug, info, trace, warn};
 use metal::{
-    foreign_types::ForeignTypeRef, BufferRef, DepthStencilState, MTLRegion, MTLViewport,
-    MetalDrawableRef, RenderCommandEncoderRef, RenderPassDescriptorRef, RenderPipelineDescriptor,
-    TextureDescriptor, TextureRef,
+    foreign_types::ForeignTypeRef, BufferRef, CaptureDescriptor, CaptureManager, DepthStencilState,
+    MTLCaptureDestination, MTLClearColor, MTLCullMode, MTLLoadAction, MTLPixelFormat, MTLRegion,
+    MTLRenderStages, MTLResourceUsage, MTLScissorRect, MTLStorageMode, MTLTextureUsage,
+    MTLViewport, MTLWinding, RenderCommandEncoderRef, RenderPassDescriptorRef,
+    RenderPipelineDescriptor, Texture, TextureDescriptor, TextureRef,
 };
 use metal::{
     objc::{msg_send, sel, sel_impl},
-    CommandQueue, Device, MetalLayer,
+    CommandBufferRef, CommandQueue, Device, MetalDrawableRef, MetalLayer, MetalLayerRef,
 };
 use raw_window_handle::HasWindowHandle;
-use winit::window::Window;
+use std::{ffi::c_void, ptr::NonNull};
 
 /// Represents the Metal backend for rendering.
 pub struct MetalBackend {
+    device: Device,
     command_queue: CommandQueue,
     render_pipeline_cache: RenderPipelineCache,
     buffer_manager: BufferManager,
+    /// The private buffers of static meshes, drawn without per-draw uploads.
+    static_meshes: StaticMeshes,
     texture_manager: TextureManager,
+    material_table: MaterialTable,
+    material_index: u32,
+    material_id: MaterialId,
+    /// The specialized parameters of each material, which its draws' pipelines are specialized to.
+    material_constants: Vec<MaterialConstants>,
     layer: MetalLayer,
-    depth_stencil_state: DepthStencilState,
+    /// Whether the layer is a sublayer of a UIKit view, whose frame follows the drawable size.
+    layer_is_sublayer: bool,
+    /// The shortest time each drawable is shown for, pacing frames below the refresh rate.
+    minimum_frame_duration: Option<f64>,
+    depth_stencil_cache: DepthStencilCache,
+    render_state: RenderState,
+    outline: Option<Outline>,
+    uniforms: Option<Uniforms>,
+    /// The layout of the vertices in the vertex buffer.
+    vertex_layout: VertexLayout,
+    debug_view: DebugView,
+    /// The index of the next draw call within the current frame.
+    draw_index: u32,
     wireframe_mode: bool,
+    /// Copies presented frames to the CPU while recording.
+    frame_readback: Option<FrameReadback>,
+    /// Renders draws offscreen and resolves anti-aliasing and post-process effects while enabled.
+    post_process: PostProcess,
+    ray_tracing: RayTracing,
+    shadow_maps: ShadowMaps,
+    gpu_culler: GpuCuller,
+    weighted_blended: WeightedBlended,
+    overlay: Overlay,
+    depth_prepass: bool,
+    /// The rectangle of the frame draws render into.
+    viewport: Viewport,
+    /// Whether the depth pre-pass wrote depth this frame, which later draws load and test equal to.
+    depth_prepassed: bool,
+    /// The texture draws render into instead of the frame, if any.
+    render_target: Option<RenderTarget>,
+    /// Whether a draw has cleared the render target since it was set.
+    render_target_started: bool,
+    /// The depth of draws into render targets, sized to the last target drawn into.
+    render_target_depth: Option<Texture>,
+}
+
+/// The stencil value marking pixels covered by an outlined object.
+const OUTLINE_STENCIL_REFERENCE: u32 = 1;
+
+/// The dark gray background of every frame, in linear color.
+const CLEAR_COLOR: MTLClearColor = MTLClearColor {
+    red: 0.01,
+    green: 0.01,
+    blue: 0.01,
+    alpha: 1.0,
+};
+
+/// Schedules a drawable's presentation once a command buffer completes.
+///
+/// # Arguments
+///
+/// * `command_buffer` - The command buffer rendering into the drawable.
+/// * `drawable` - The drawable to present.
+/// * `minimum_duration` - The shortest time the previous drawable is shown
+///   for, or `None` to present at the next refresh.
+fn present_drawable(
+    command_buffer: &CommandBufferRef,
+    drawable: &MetalDrawableRef,
+    minimum_duration: Option<f64>,
+) {
+    match minimum_duration {
+        Some(duration) => unsafe {
+            let () = msg_send![command_buffer.as_ptr(), presentDrawable: drawable.as_ptr()
+                afterMinimumDuration: duration];
+        },
+        None => command_buffer.present_drawable(drawable),
+    }
 }
 
 impl MetalBackend {
@@ -43,105 +156,382 @@ impl MetalBackend {
     ///
     /// # Arguments
     ///
-    /// * `window` - The window to which the Metal layer will be attached.
+    /// * `window` - The window to which the Metal layer will be attached, e.g. a winit window.
+    /// * `size` - The size of the window's drawable area in physical pixels.
     ///
     /// # Returns
     ///
     /// Returns a Result containing the `MetalBackend` instance or a `RendererError`.
-    pub fn new(window: &Window) -> Result<Self, RendererError> {
-        let device = Device::system_default().ok_or(RendererError::DeviceNotFound)?;
+    pub fn new(window: &impl HasWindowHandle, size: UVec2) -> Result<Self, RendererError> {
+        match window.window_handle()?.as_raw() {
+            // The handle keeps the view alive while the window exists
+            raw_window_handle::RawWindowHandle::AppKit(handle) => unsafe {
+                Self::from_ns_view(handle.ns_view, size)
+            },
+            raw_window_handle::RawWindowHandle::UiKit(handle) => unsafe {
+                Self::from_ui_view(handle.ui_view, size)
+            },
+            _ => {
+                warn!("Unsupported platform for Metal rendering");
+                Err(BackendError::UnsupportedPlatform.into())
+            }
+        }
+    }
+
+    /// Creates a new `MetalBackend` drawing into an existing view.
+    ///
+    /// The view is made layer-backed by a new Metal layer, replacing its
+    /// current layer.
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The `NSView` to draw into, e.g. one embedded in an AppKit or SwiftUI app.
+    /// * `size` - The size of the view's drawable area in physical pixels.
+    ///
+    /// # Safety
+    ///
+    /// `view` must point to a valid `NSView`, and be used on the main thread.
+    pub unsafe fn from_ns_view(view: NonNull<c_void>, size: UVec2) -> Result<Self, RendererError> {
+        let backend = Self::with_layer(MetalLayer::new(), size)?;
+        let ns_view = view.as_ptr() as cocoa_id;
+        let () = msg_send![ns_view, setLayer:backend.layer.as_ref()];
+        let () = msg_send![ns_view, setWantsLayer:true];
+        Ok(backend)
+    }
+
+    /// Creates a new `MetalBackend` drawing into a UIKit view.
+    ///
+    /// UIKit views cannot replace their layer, so a new Metal layer is added
+    /// as a sublayer covering the view, at the view's display scale.
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The `UIView` to draw into.
+    /// * `size` - The size of the view's drawable area in physical pixels.
+    ///
+    /// # Safety
+    ///
+    /// `view` must point to a valid `UIView`, and be used on the main thread.
+    unsafe fn from_ui_view(view: NonNull<c_void>, size: UVec2) -> Result<Self, RendererError> {
+        let mut backend = Self::with_layer(MetalLayer::new(), size)?;
+        let ui_view = view.as_ptr() as cocoa_id;
+        let view_layer: cocoa_id = msg_send![ui_view, layer];
+        let bounds: CGRect = msg_send![ui_view, bounds];
+        let scale_factor: f64 = msg_send![ui_view, contentScaleFactor];
+
+        let layer: &MetalLayerRef = &backend.layer;
+        layer.set_contents_scale(scale_factor);
+        let () = msg_send![layer, setFrame: bounds];
+        let () = msg_send![view_layer, addSublayer: layer];
+        backend.layer_is_sublayer = true;
+        debug!("Metal layer added to UIKit view at scale factor {scale_factor}");
+        Ok(backend)
+    }
+
+    /// Creates a new `MetalBackend` drawing into an existing Metal layer.
+    ///
+    /// The layer stays owned by the caller, e.g. a `MTKView` or a layer-hosting
+    /// view, and is retained by the backend. Its device and pixel format are
+    /// set to those the backend renders with.
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The `CAMetalLayer` to present to.
+    /// * `size` - The size of the layer's drawable area in physical pixels.
+    ///
+    /// # Safety
+    ///
+    /// `layer` must point to a valid `CAMetalLayer`.
+    pub unsafe fn from_metal_layer(
+        layer: NonNull<c_void>,
+        size: UVec2,
+    ) -> Result<Self, RendererError> {
+        let layer = MetalLayerRef::from_ptr(layer.as_ptr().cast()).to_owned();
+        Self::with_layer(layer, size)
+    }
+
+    /// Creates a new `MetalBackend` presenting to a Metal layer.
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The layer to configure and present to.
+    /// * `size` - The size of the layer's drawable area in physical pixels.
+    fn with_layer(layer: MetalLayer, size: UVec2) -> Result<Self, RendererError> {
+        let device = Device::system_default().ok_or(BackendError::DeviceNotFound)?;
         info!("Metal device initialized");
 
         let command_queue = device.new_command_queue();
         let mut render_pipeline_cache = RenderPipelineCache::new(&device)?;
         let buffer_manager = BufferManager::new(&device)?;
+        let static_meshes = StaticMeshes::new(&device);
         let texture_manager = TextureManager::new(&device);
+        let material_table = MaterialTable::new(&device);
+        let post_process = PostProcess::new(&device)?;
+        let ray_tracing = RayTracing::new(&device)?;
+        let shadow_maps = ShadowMaps::new(&device)?;
+        let gpu_culler = GpuCuller::new(&device)?;
+        let weighted_blended = WeightedBlended::new(&device)?;
+        let overlay = Overlay::new(&device)?;
+
+        let pipeline_descriptors = create_default_pipeline_descriptors(&device)?;
+        let depth_stencil_cache = DepthStencilCache::new(&device);
+        for (variant, descriptor) in &pipeline_descriptors {
+            render_pipeline_cache.create_pipeline_state(
+                *variant,
+                &VertexLayout::default(),
+                descriptor,
+            )?;
+        }
+
+        layer.set_device(&device);
+        layer.set_pixel_format(COLOR_PIXEL_FORMAT);
+        layer.set_presents_with_transaction(false);
 
-        let (default_pipeline_descriptor, depth_stencil_state) =
-            create_default_pipeline_descriptor(&device)?;
-        render_pipeline_cache.create_pipeline_state(&default_pipeline_descriptor)?;
+        let physical_metal_size = CGSize::new(size.x as f64, size.y as f64);
+        layer.set_drawable_size(physical_metal_size);
 
-        let layer = Self::create_metal_layer_for_window(window, &device)?;
+        debug!(
+            "Setting Metal layer drawable size to: {:?}",
+            physical_metal_size
+        );
 
         info!("MetalBackend initialized successfully");
         Ok(MetalBackend {
+            device,
             command_queue,
             render_pipeline_cache,
             buffer_manager,
+            static_meshes,
             texture_manager,
+            material_table,
+            material_index: 0,
+            material_id: MaterialId::DEFAULT,
+            material_constants: Vec::new(),
             layer,
-            depth_stencil_state,
+            layer_is_sublayer: false,
+            minimum_frame_duration: None,
+            depth_stencil_cache,
+            render_state: RenderState::default(),
+            outline: None,
+            uniforms: None,
+            vertex_layout: VertexLayout::default(),
+            debug_view: DebugView::default(),
+            draw_index: 0,
             wireframe_mode: false,
+            frame_readback: None,
+            post_process,
+            ray_tracing,
+            shadow_maps,
+            gpu_culler,
+            weighted_blended,
+            overlay,
+            depth_prepass: false,
+            viewport: Viewport::FULL,
+            depth_prepassed: false,
+            render_target: None,
+            render_target_started: false,
+            render_target_depth: None,
         })
     }
 
-    /// Creates a Metal Layer for the given window.
-    ///
-    /// # Arguments
-    ///
-    /// * `window` - The window to which the Metal layer will be attached.
-    /// * `device` - The Metal device.
-    ///
-    /// # Returns
-    ///
-    /// Returns a Result containing the `MetalLayer` or a `RendererError`.
-    fn create_metal_layer_for_window(
-        window: &Window,
-        device: &Device,
-    ) -> Result<MetalLayer, RendererError> {
-        match window.window_handle()?.as_raw() {
-            raw_window_handle::RawWindowHandle::AppKit(handle) => {
-                let ns_view = handle.ns_view.as_ptr() as cocoa_id;
-                let layer = MetalLayer::new();
+    /// Creates a viewport covering a rectangle of the given render target
+    fn create_viewport(texture: &TextureRef, viewport: Viewport) -> MTLViewport {
+        let (origin, size) =
+            viewport.to_pixels(UVec2::new(texture.width() as u32, texture.height() as u32));
+        MTLViewport {
+            originX: origin.x as f64,
+            originY: origin.y as f64,
+            width: size.x as f64,
+            height: size.y as f64,
+            znear: 0.0,
+            zfar: 1.0,
+        }
+    }
 
-                layer.set_device(device);
-                layer.set_pixel_format(metal::MTLPixelFormat::BGRA8Unorm);
-                layer.set_presents_with_transaction(false);
+    /// Renders offscreen while passes outside post-processing need the frame's scene or depth.
+    fn update_offscreen(&mut self) {
+        self.post_process.require_offscreen(
+            self.ray_tracing.is_enabled()
+                || self.shadow_maps.is_enabled()
+                || self.gpu_culler.is_pyramid_enabled()
+                || self.weighted_blended.is_enabled()
+                || self.overlay.is_enabled()
+                || self.depth_prepass,
+        );
+    }
 
-                let size = window.inner_size();
-                let scale_factor = window.scale_factor();
+    /// Binds the buffers and material table of a shaded draw, and counts the draw.
+    fn bind_draw_resources(&mut self, render_pass: &RenderPass) {
+        // Set vertex and uniform buffers
+        render_pass.set_vertex_buffer(0, Some(self.buffer_manager.bound_vertex_buffer()), 0);
+        render_pass.bind_vertex_data(1, self.buffer_manager.uniform_binding());
+        render_pass.set_vertex_buffer(3, Some(&self.buffer_manager.frame_constants_buffer), 0);
+        render_pass.set_fragment_buffer(0, Some(&self.buffer_manager.frame_constants_buffer), 0);
+        trace!("Vertex, uniform and frame constant buffers set");
 
-                let physical_metal_size = CGSize::new(size.width as f64, size.height as f64);
-                layer.set_drawable_size(physical_metal_size);
+        // Bind the material table and select this draw's entry
+        render_pass.use_textures(self.material_table.resident_textures());
+        render_pass.set_fragment_buffer(1, Some(self.material_table.buffer()), 0);
+        render_pass.set_fragment_bytes(2, &self.material_index.to_ne_bytes());
+        trace!(
+            "Material table bound with material index {}",
+            self.material_index
+        );
+        render_pass.set_fragment_bytes(4, &(self.debug_view as u32).to_ne_bytes());
+        render_pass.set_fragment_bytes(5, &self.draw_index.to_ne_bytes());
+        self.draw_index += 1;
+    }
 
-                debug!(
-                    "Setting Metal layer drawable size to: {:?} and scale factor is: {:?}",
-                    physical_metal_size, scale_factor
-                );
+    /// Draws a draw command into a render target instead of the frame.
+    ///
+    /// Each draw encodes its own pass, like draws into the frame, loading
+    /// what earlier draws into the target left. Transparent draws blend in
+    /// order, and draws into mirrored targets flip their front faces.
+    fn draw_into_target(
+        &mut self,
+        draw_command: BackendDrawCommand,
+        target: RenderTarget,
+    ) -> Result<(), RendererError> {
+        let texture = self
+            .texture_manager
+            .get_texture(target.texture)
+            .ok_or(AssetError::InvalidTexture(target.texture))?
+            .to_owned();
+        let depth_texture = self.ensure_render_target_depth(&texture);
+        let load_action = if std::mem::replace(&mut self.render_target_started, true) {
+            MTLLoadAction::Load
+        } else {
+            MTLLoadAction::Clear
+        };
 
-                unsafe {
-                    let () = msg_send![ns_view, setLayer:layer.as_ref()];
-                    let () = msg_send![ns_view, setWantsLayer:true];
-                }
+        let descriptor = metal::RenderPassDescriptor::new();
+        let color_attachment = descriptor.color_attachments().object_at(0).unwrap();
+        color_attachment.set_texture(Some(&texture));
+        color_attachment.set_load_action(load_action);
+        color_attachment.set_clear_color(CLEAR_COLOR);
+        color_attachment.set_store_action(metal::MTLStoreAction::Store);
+        let depth_attachment = descriptor.depth_attachment().unwrap();
+        depth_attachment.set_texture(Some(&depth_texture));
+        depth_attachment.set_load_action(load_action);
+        depth_attachment.set_clear_depth(1.0);
+        depth_attachment.set_store_action(metal::MTLStoreAction::Store);
+        let stencil_attachment = descriptor.stencil_attachment().unwrap();
+        stencil_attachment.set_texture(Some(&depth_texture));
+        stencil_attachment.set_load_action(MTLLoadAction::Clear);
+        stencil_attachment.set_store_action(metal::MTLStoreAction::DontCare);
 
-                Ok(layer)
-            }
-            _ => {
-                warn!("Unsupported platform for Metal rendering");
-                Err(RendererError::UnsupportedPlatform)
-            }
+        let command_buffer = self.command_queue.new_command_buffer().to_owned();
+        let culled = self.gpu_culler.encode_cull(&command_buffer, &draw_command);
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
+        let mut render_pass =
+            RenderPass::new(encoder, Self::create_viewport(&texture, Viewport::FULL));
+        render_pass.set_mirrored(target.mirrored);
+
+        let render_state = self.render_state;
+        render_pass.set_depth_stencil_state(self.depth_stencil_cache.get(
+            render_state.depth_test,
+            false,
+            render_state.depth_write,
+            render_state.stencil,
+        ));
+        render_pass.set_render_state(&render_state);
+        render_pass.set_wireframe_mode(self.wireframe_mode);
+
+        let mut variant = PipelineVariant::for_draw_command(&draw_command);
+        if render_state.blend_mode == BlendMode::Additive {
+            variant = variant.additive();
+        } else if render_state.is_transparent() {
+            variant = variant.transparent(false);
         }
+        let pipeline_state = self.render_pipeline_cache.get_pipeline_state(
+            variant,
+            &self.vertex_layout,
+            self.current_material_constants(),
+        )?;
+        render_pass.set_pipeline(pipeline_state);
+
+        self.bind_draw_resources(&render_pass);
+        render_pass.draw(draw_command, &self.buffer_manager, culled.as_ref());
+        render_pass.end();
+        command_buffer.commit();
+        Ok(())
     }
 
-    /// Creates a viewport for the given drawable
-    fn create_viewport(&self, drawable: &MetalDrawableRef) -> MTLViewport {
-        let texture = drawable.texture();
-        MTLViewport {
-            originX: 0.0,
-            originY: 0.0,
-            width: texture.width() as f64,
-            height: texture.height() as f64,
-            znear: 0.0,
-            zfar: 1.0,
+    /// Returns the specialized parameters of the material selected for draws.
+    fn current_material_constants(&self) -> MaterialConstants {
+        self.material_constants
+            .get(self.material_id.0)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the depth texture of draws into a render target, resized to the target.
+    fn ensure_render_target_depth(&mut self, target: &TextureRef) -> Texture {
+        let (width, height) = (target.width(), target.height());
+        if let Some(depth) = self
+            .render_target_depth
+            .as_ref()
+            .filter(|depth| depth.width() == width && depth.height() == height)
+        {
+            return depth.clone();
         }
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width);
+        descriptor.set_height(height);
+        descriptor.set_pixel_format(MTLPixelFormat::Depth32Float_Stencil8);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget);
+        let depth = self.device.new_texture(&descriptor);
+        trace!("Created render target depth texture: {width}x{height}");
+        self.render_target_depth = Some(depth.clone());
+        depth
     }
 
-    /// Toggles the wireframe mode.
+    /// Redraws the last draw command scaled up in the outline color.
     ///
-    /// This method switches between filled and wireframe rendering modes
-    pub fn toggle_wireframe_mode(&mut self) {
-        self.wireframe_mode = !self.wireframe_mode;
-        info!("Wireframe mode toggled: {}", self.wireframe_mode);
+    /// Only pixels not marked in the stencil buffer by the object itself are
+    /// filled, leaving a border around the object.
+    fn draw_outline(
+        &mut self,
+        render_pass: &mut RenderPass,
+        draw_command: BackendDrawCommand,
+        outline: Outline,
+        uniforms: Uniforms,
+        culled: Option<&CulledInstances>,
+    ) -> Result<(), RendererError> {
+        let variant = PipelineVariant::for_draw_command(&draw_command).outline();
+        let pipeline_state = self.render_pipeline_cache.get_pipeline_state(
+            variant,
+            &self.vertex_layout,
+            MaterialConstants::default(),
+        )?;
+        render_pass.set_pipeline(pipeline_state);
+
+        let render_state = RenderState::OVERLAY
+            .with_stencil(StencilState::NOT_EQUAL_REFERENCE, OUTLINE_STENCIL_REFERENCE);
+        render_pass.set_depth_stencil_state(self.depth_stencil_cache.get(
+            render_state.depth_test,
+            false,
+            render_state.depth_write,
+            render_state.stencil,
+        ));
+        render_pass.set_render_state(&render_state);
+
+        let outline_uniforms = Uniforms {
+            model_matrix: uniforms.model_matrix
+                * Mat4::from_scale(Vec3::splat(1.0 + outline.width)),
+            ..uniforms
+        };
+        render_pass.bind_vertex_data(
+            1,
+            BufferBinding::Bytes(as_bytes(std::slice::from_ref(&outline_uniforms))),
+        );
+        let color: [f32; 4] = outline.color.into();
+        render_pass.set_fragment_bytes(3, as_bytes(&color));
+
+        trace!("Drawing outline: {:?}", outline);
+        render_pass.draw(draw_command, &self.buffer_manager, culled);
+        Ok(())
     }
 }
 
@@ -156,24 +546,66 @@ impl GraphicsBackend for MetalBackend {
     ///
     /// Returns a Result indicating success or a `RendererError`.
     fn draw(&mut self, draw_command: BackendDrawCommand) -> Result<(), RendererError> {
+        if let Some(target) = self.render_target {
+            return self.draw_into_target(draw_command, target);
+        }
         let descriptor = metal::RenderPassDescriptor::new();
+        // Alpha-blended draws accumulate apart from the scene while weighted
+        // blended, except in debug views, which show them like opaque draws
+        let weighted_blended = self.weighted_blended.is_enabled()
+            && self.render_state.blend_mode == BlendMode::Alpha
+            && self.debug_view == DebugView::Shaded;
+        // Outlines are drawn into the scene texture, so only around draws into it
+        let outline = self.outline.filter(|_| !weighted_blended);
 
-        let drawable = self
-            .layer
-            .next_drawable()
-            .ok_or(RendererError::DrawFailed("No next drawable".to_string()))?;
-
-        let texture = drawable.texture();
+        // Owned handles keep `self` free for the outline pass below. While
+        // anti-aliasing is enabled, draws render offscreen and `end_frame` presents
+        let drawable = if self.post_process.is_enabled() {
+            None
+        } else {
+            Some(
+                self.layer
+                    .next_drawable()
+                    .ok_or(BackendError::NoDrawable)?
+                    .to_owned(),
+            )
+        };
+        let (texture, load_action) = match &drawable {
+            Some(drawable) => (drawable.texture().to_owned(), MTLLoadAction::Clear),
+            // Depth is only cleared if no opaque draw has rendered this frame
+            None if weighted_blended => {
+                let size = self.layer.drawable_size();
+                let (width, height) = self
+                    .post_process
+                    .scene_size(size.width as u64, size.height as u64);
+                let texture = self.weighted_blended.begin_draw(descriptor, width, height);
+                let load_action = if self.post_process.is_frame_started() {
+                    MTLLoadAction::Load
+                } else {
+                    MTLLoadAction::Clear
+                };
+                (texture, load_action)
+            }
+            None => {
+                let size = self.layer.drawable_size();
+                let (scene, load_action) = self
+                    .post_process
+                    .begin_draw(size.width as u64, size.height as u64);
+                (scene.to_owned(), load_action)
+            }
+        };
 
         // Update depth texture if needed
         let texture_size = CGSize::new(texture.width() as f64, texture.height() as f64);
         self.buffer_manager.ensure_depth_texture(texture_size);
 
-        let color_attachment = descriptor.color_attachments().object_at(0).unwrap();
-        color_attachment.set_texture(Some(texture));
-        color_attachment.set_load_action(metal::MTLLoadAction::Clear);
-        color_attachment.set_clear_color(metal::MTLClearColor::new(0.1, 0.1, 0.1, 1.0)); // Dark gray background
-        color_attachment.set_store_action(metal::MTLStoreAction::Store);
+        if !weighted_blended {
+            let color_attachment = descriptor.color_attachments().object_at(0).unwrap();
+            color_attachment.set_texture(Some(&texture));
+            color_attachment.set_load_action(load_action);
+            color_attachment.set_clear_color(CLEAR_COLOR);
+            color_attachment.set_store_action(metal::MTLStoreAction::Store);
+        }
 
         // Set up depth attachment
         let depth_attachment = descriptor.depth_attachment().unwrap();
@@ -183,35 +615,167 @@ impl GraphicsBackend for MetalBackend {
                 .as_ref()
                 .map(|t| t as &TextureRef),
         );
-        depth_attachment.set_load_action(metal::MTLLoadAction::Clear);
+        // Depth written by the depth pre-pass is kept for the draws it was written for
+        depth_attachment.set_load_action(if self.depth_prepassed {
+            MTLLoadAction::Load
+        } else {
+            load_action
+        });
         depth_attachment.set_clear_depth(1.0);
         depth_attachment.set_store_action(metal::MTLStoreAction::Store);
 
-        let command_buffer = self.command_queue.new_command_buffer();
+        // The stencil lives in the depth texture and is only needed within the pass
+        let stencil_attachment = descriptor.stencil_attachment().unwrap();
+        stencil_attachment.set_texture(
+            self.buffer_manager
+                .depth_texture
+                .as_ref()
+                .map(|t| t as &TextureRef),
+        );
+        stencil_attachment.set_load_action(metal::MTLLoadAction::Clear);
+        stencil_attachment.set_clear_stencil(0);
+        stencil_attachment.set_store_action(metal::MTLStoreAction::DontCare);
+
+        let command_buffer = self.command_queue.new_command_buffer().to_owned();
+        // Culling runs ahead of the render pass on the same command buffer
+        let culled = self.gpu_culler.encode_cull(&command_buffer, &draw_command);
         let encoder = command_buffer.new_render_command_encoder(descriptor);
 
-        let viewport = self.create_viewport(drawable);
+        let viewport = Self::create_viewport(&texture, self.viewport);
         let mut render_pass = RenderPass::new(encoder, viewport);
 
-        render_pass.set_depth_stencil_state(&self.depth_stencil_state);
+        // Outlined objects mark their pixels in the stencil buffer
+        let mut render_state = self.render_state;
+        if outline.is_some() {
+            render_state =
+                render_state.with_stencil(StencilState::WRITE_REFERENCE, OUTLINE_STENCIL_REFERENCE);
+        }
+        // Overdraw counts every fragment, including hidden ones
+        if self.debug_view == DebugView::Overdraw {
+            render_state = render_state.with_depth_test(false).with_depth_write(false);
+        }
+        render_pass.set_depth_stencil_state(self.depth_stencil_cache.get(
+            render_state.depth_test,
+            self.depth_prepassed,
+            render_state.depth_write,
+            render_state.stencil,
+        ));
+        render_pass.set_render_state(&render_state);
         render_pass.set_wireframe_mode(self.wireframe_mode);
 
-        // Set the pipeline state
-        let pipeline_state = self
-            .render_pipeline_cache
-            .get_pipeline_state()
-            .ok_or(RendererError::InvalidPipelineId)?;
+        // Select the pipeline variant matching the draw command
+        let mut variant = PipelineVariant::for_draw_command(&draw_command);
+        if self.debug_view == DebugView::Overdraw {
+            variant = variant.overdraw();
+        } else if self.render_state.blend_mode == BlendMode::Additive {
+            variant = variant.additive();
+        } else if self.render_state.is_transparent() {
+            variant = variant.transparent(weighted_blended);
+        }
+        let pipeline_state = self.render_pipeline_cache.get_pipeline_state(
+            variant,
+            &self.vertex_layout,
+            self.current_material_constants(),
+        )?;
         render_pass.set_pipeline(pipeline_state);
 
-        // Set vertex and uniform buffers
-        render_pass.set_vertex_buffer(0, Some(&self.buffer_manager.vertex_buffer), 0);
-        render_pass.set_vertex_buffer(1, Some(&self.buffer_manager.uniform_buffer), 0);
-        trace!("Vertex and uniform buffers set");
-
-        render_pass.draw(draw_command, &self.buffer_manager);
+        self.bind_draw_resources(&render_pass);
+        render_pass.draw(draw_command, &self.buffer_manager, culled.as_ref());
+        if let (Some(outline), Some(uniforms)) = (outline, self.uniforms) {
+            self.draw_outline(
+                &mut render_pass,
+                draw_command,
+                outline,
+                uniforms,
+                culled.as_ref(),
+            )?;
+        }
         render_pass.end();
 
-        command_buffer.present_drawable(drawable);
+        if let Some(drawable) = &drawable {
+            if let Some(frame_readback) = &mut self.frame_readback {
+                frame_readback.encode_copy(&command_buffer, &texture);
+            }
+            present_drawable(&command_buffer, drawable, self.minimum_frame_duration);
+        }
+        command_buffer.commit();
+
+        Ok(())
+    }
+
+    /// Draws only the depth of a draw command into the scene's depth texture.
+    ///
+    /// The vertex buffer must hold position-only vertices. The first depth
+    /// draw of a frame clears the depth texture, and later draws of the frame,
+    /// shaded or not, keep it. Depth is only drawn while the depth pre-pass is
+    /// enabled and the frame renders offscreen; wireframe frames skip it, since
+    /// the filled depth would hide lines behind the front faces.
+    ///
+    /// # Arguments
+    ///
+    /// * `draw_command` - The draw command whose depth to draw.
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result indicating success or a `RendererError`.
+    fn draw_depth(&mut self, draw_command: BackendDrawCommand) -> Result<(), RendererError> {
+        if !self.depth_prepass
+            || !self.post_process.is_enabled()
+            || self.wireframe_mode
+            || self.render_target.is_some()
+        {
+            return Ok(());
+        }
+
+        let size = self.layer.drawable_size();
+        let (width, height) = self
+            .post_process
+            .scene_size(size.width as u64, size.height as u64);
+        self.buffer_manager
+            .ensure_depth_texture(CGSize::new(width as f64, height as f64));
+        let depth_texture = self.buffer_manager.depth_texture.clone().unwrap();
+
+        let descriptor = metal::RenderPassDescriptor::new();
+        let depth_attachment = descriptor.depth_attachment().unwrap();
+        depth_attachment.set_texture(Some(&depth_texture));
+        depth_attachment.set_load_action(if self.depth_prepassed {
+            MTLLoadAction::Load
+        } else {
+            MTLLoadAction::Clear
+        });
+        depth_attachment.set_clear_depth(1.0);
+        depth_attachment.set_store_action(metal::MTLStoreAction::Store);
+        let stencil_attachment = descriptor.stencil_attachment().unwrap();
+        stencil_attachment.set_texture(Some(&depth_texture));
+        stencil_attachment.set_load_action(MTLLoadAction::DontCare);
+        stencil_attachment.set_store_action(metal::MTLStoreAction::DontCare);
+        self.depth_prepassed = true;
+
+        let command_buffer = self.command_queue.new_command_buffer().to_owned();
+        let culled = self.gpu_culler.encode_cull(&command_buffer, &draw_command);
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
+        let mut render_pass = RenderPass::new(
+            encoder,
+            Self::create_viewport(&depth_texture, self.viewport),
+        );
+
+        // The same cull mode and depth bias as the shaded draw, so both produce equal depth
+        render_pass.set_depth_stencil_state(self.depth_stencil_cache.get(true, false, true, None));
+        render_pass.set_render_state(&self.render_state);
+
+        let variant = PipelineVariant::for_draw_command(&draw_command).depth_only();
+        let pipeline_state = self.render_pipeline_cache.get_pipeline_state(
+            variant,
+            &self.vertex_layout,
+            MaterialConstants::default(),
+        )?;
+        render_pass.set_pipeline(pipeline_state);
+
+        render_pass.set_vertex_buffer(0, Some(self.buffer_manager.bound_vertex_buffer()), 0);
+        render_pass.bind_vertex_data(1, self.buffer_manager.uniform_binding());
+        render_pass.set_vertex_buffer(3, Some(&self.buffer_manager.frame_constants_buffer), 0);
+        render_pass.draw(draw_command, &self.buffer_manager, culled.as_ref());
+        render_pass.end();
         command_buffer.commit();
 
         Ok(())
@@ -228,7 +792,30 @@ impl GraphicsBackend for MetalBackend {
     /// A `Result` indicating success or a `RendererError`.
     fn update_vertex_buffer(&mut self, vertices: &[Vertex]) -> Result<(), RendererError> {
         trace!("Updating vertex buffer with {} vertices", vertices.len());
-        self.buffer_manager.update_vertex_buffer(vertices)
+        self.vertex_layout = VertexLayout::default();
+        Ok(self.buffer_manager.update_vertex_buffer(vertices)?)
+    }
+
+    /// Updates the vertex buffer with vertices packed in a custom layout.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertices` - The packed vertices to upload.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `RendererError`.
+    fn update_packed_vertex_buffer(
+        &mut self,
+        vertices: &PackedVertices,
+    ) -> Result<(), RendererError> {
+        trace!(
+            "Updating vertex buffer with {} vertices of layout {:?}",
+            vertices.len(),
+            vertices.layout
+        );
+        self.vertex_layout = vertices.layout;
+        Ok(self.buffer_manager.update_packed_vertex_buffer(vertices)?)
     }
 
     /// Updates the index buffer with new index data.
@@ -242,11 +829,53 @@ impl GraphicsBackend for MetalBackend {
     /// A `Result` indicating success or a `RendererError`.
     fn update_index_buffer(&mut self, indices: &[u32]) -> Result<(), RendererError> {
         trace!("Updating index buffer with {} indices", indices.len());
-        self.buffer_manager.update_index_buffer(indices)
+        Ok(self.buffer_manager.update_index_buffer(indices)?)
+    }
+
+    /// Uploads a static mesh's geometry into private buffers through staging buffers.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh` - The index of the mesh in `MeshStorage`.
+    /// * `geometry` - The mesh's geometry.
+    fn upload_static_mesh(&mut self, mesh: usize, geometry: GeometryView<'_>) {
+        self.static_meshes
+            .upload(&self.command_queue, mesh, &geometry);
+    }
+
+    /// Binds a static mesh's private buffers in place of the shared vertex and index buffers.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh` - The index of the mesh in `MeshStorage`.
+    /// * `positions` - Whether to bind the position-only vertices of depth-only draws.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the mesh's buffers are resident and bound.
+    fn bind_static_mesh(&mut self, mesh: usize, positions: bool) -> bool {
+        let Some(resident) = self.static_meshes.get(mesh) else {
+            return false;
+        };
+        let (vertices, layout) = if positions {
+            match &resident.positions {
+                Some(positions) => (positions, VertexLayout::position()),
+                None => return false,
+            }
+        } else {
+            (&resident.vertices, resident.layout)
+        };
+        trace!("Binding static mesh {mesh}");
+        self.vertex_layout = layout;
+        self.buffer_manager
+            .bind_static(vertices, resident.indices.as_ref());
+        true
     }
 
     /// Updates the instance buffer with new instance data.
     ///
+    /// Instances of culled draws are uploaded for the culling kernel instead.
+    ///
     /// # Arguments
     ///
     /// * `instances` - A new instance data to upload.
@@ -255,11 +884,15 @@ impl GraphicsBackend for MetalBackend {
     ///
     /// A `Result` indicating success of a `RendererError`.
     fn update_instance_buffer(&mut self, instances: &[InstanceData]) -> Result<(), RendererError> {
+        if self.gpu_culler.is_active() {
+            self.gpu_culler.upload_instances(instances);
+            return Ok(());
+        }
         trace!(
             "Updating instance buffer with {} instances",
             instances.len()
         );
-        self.buffer_manager.update_instance_buffer(instances)
+        Ok(self.buffer_manager.update_instance_buffer(instances)?)
     }
 
     /// Updates the uniform buffer with new uniform data.
@@ -273,7 +906,567 @@ impl GraphicsBackend for MetalBackend {
     /// A `Result` indicating success or a `RendererError`.
     fn update_uniform_buffer(&mut self, uniforms: &Uniforms) -> Result<(), RendererError> {
         trace!("Updating uniform buffer");
-        self.buffer_manager.update_uniform_buffer(uniforms)
+        self.uniforms = Some(*uniforms);
+        Ok(self.buffer_manager.update_uniform_buffer(uniforms)?)
+    }
+
+    /// Updates the frame constants buffer with new per-frame data.
+    ///
+    /// # Arguments
+    ///
+    /// * `constants` - The new frame constants to upload.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `RendererError`.
+    fn update_frame_constants(&mut self, constants: &FrameConstants) -> Result<(), RendererError> {
+        trace!("Updating frame constants buffer");
+        // Frame constants are updated once per frame, before any draws
+        self.draw_index = 0;
+        Ok(self
+            .buffer_manager
+            .update_frame_constants_buffer(constants)?)
+    }
+
+    /// Re-encodes the material table with new material data.
+    ///
+    /// # Arguments
+    ///
+    /// * `materials` - All materials, indexed by `MaterialId`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `RendererError`.
+    fn update_materials(&mut self, materials: &[Material]) -> Result<(), RendererError> {
+        trace!("Updating material table with {} materials", materials.len());
+        self.material_constants = materials.iter().map(Material::constants).collect();
+        Ok(self
+            .material_table
+            .update(materials, &self.texture_manager)?)
+    }
+
+    /// Selects the material used by subsequent draws.
+    ///
+    /// # Arguments
+    ///
+    /// * `material_id` - The ID of the material to use.
+    fn set_material(&mut self, material_id: MaterialId) {
+        self.material_index = self.material_table.shader_index(material_id.0);
+        self.material_id = material_id;
+    }
+
+    /// Sets the depth, culling, depth bias and blend state used by subsequent draws.
+    ///
+    /// # Arguments
+    ///
+    /// * `render_state` - The render state to use.
+    fn set_render_state(&mut self, render_state: RenderState) {
+        self.render_state = render_state;
+    }
+
+    /// Sets the outline drawn around subsequent draws, or `None` for no outline.
+    ///
+    /// # Arguments
+    ///
+    /// * `outline` - The outline to draw.
+    fn set_outline(&mut self, outline: Option<Outline>) {
+        self.outline = outline;
+    }
+
+    /// Sets the culling of subsequent instanced draws, or `None` to draw every instance.
+    ///
+    /// Must be called before the draw's instances are uploaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `culling` - The culling constants of the draw.
+    fn set_instance_culling(&mut self, culling: Option<&CullingConstants>) {
+        self.gpu_culler.set_constants(culling);
+    }
+
+    /// Enables or disables building a depth pyramid at the end of every frame.
+    ///
+    /// The pyramid reads the whole frame's depth, so draws render offscreen while it is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to build the depth pyramid for occlusion culling.
+    fn set_depth_pyramid(&mut self, enabled: bool) {
+        self.gpu_culler.set_depth_pyramid(enabled);
+        self.update_offscreen();
+    }
+
+    /// Enables or disables drawing depth-only passes ahead of shaded draws.
+    ///
+    /// The pre-pass shares its depth with the frame's later draws, so draws
+    /// render offscreen while it is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether `draw_depth` draws into the scene's depth.
+    fn set_depth_prepass(&mut self, enabled: bool) {
+        self.depth_prepass = enabled;
+        self.update_offscreen();
+    }
+
+    /// Sets what the fragment shader outputs for subsequent draws.
+    ///
+    /// # Arguments
+    ///
+    /// * `debug_view` - The debug view, or `DebugView::Shaded` for regular shading.
+    fn set_debug_view(&mut self, debug_view: DebugView) {
+        self.debug_view = debug_view;
+    }
+
+    /// Toggles the wireframe mode.
+    ///
+    /// This method switches between filled and wireframe rendering modes
+    fn toggle_wireframe_mode(&mut self) {
+        self.wireframe_mode = !self.wireframe_mode;
+        info!("Wireframe mode toggled: {}", self.wireframe_mode);
+    }
+
+    /// Selects the post-process anti-aliasing of subsequent frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `anti_aliasing` - The anti-aliasing, or `AntiAliasing::None` to draw into the drawable.
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.post_process.set_anti_aliasing(anti_aliasing);
+    }
+
+    /// Selects how subsequent transparent draws are composited.
+    ///
+    /// Weighted blended transparency composites over the whole frame, so draws
+    /// render offscreen while it is selected.
+    ///
+    /// # Arguments
+    ///
+    /// * `transparency` - The transparency, or `Transparency::Ordered` to blend draws in order.
+    fn set_transparency(&mut self, transparency: Transparency) {
+        self.weighted_blended
+            .set_enabled(transparency == Transparency::WeightedBlended);
+        self.update_offscreen();
+    }
+
+    /// Sets the resolution subsequent frames are rendered at, relative to the drawable.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale` - The render scale, or 1 to render at the drawable's size.
+    fn set_render_scale(&mut self, scale: f32) {
+        self.post_process.set_render_scale(scale);
+    }
+
+    /// Sets whether drawables are presented in sync with the display's refresh.
+    ///
+    /// Without vsync, frames are presented as soon as they finish, which can tear.
+    fn set_vsync(&mut self, enabled: bool) {
+        self.layer.set_display_sync_enabled(enabled);
+    }
+
+    /// Paces presented drawables to a frame rate, such as 60 Hz on a 120 Hz
+    /// ProMotion display, by showing each for at least one frame's duration.
+    fn set_frame_rate(&mut self, frame_rate: Option<f32>) {
+        self.minimum_frame_duration = frame_rate.map(|rate| 1.0 / rate as f64);
+    }
+
+    /// Sets the layer's drawable size, which the frame's render targets follow.
+    fn resize(&mut self, width: u32, height: u32) {
+        self.layer
+            .set_drawable_size(CGSize::new(width as f64, height as f64));
+        if self.layer_is_sublayer {
+            // Sublayers are not resized with their view, and their frame is in points
+            let scale_factor = self.layer.contents_scale();
+            let frame = CGRect::new(
+                &CGPoint::new(0.0, 0.0),
+                &CGSize::new(width as f64 / scale_factor, height as f64 / scale_factor),
+            );
+            let layer: &MetalLayerRef = &self.layer;
+            unsafe {
+                let () = msg_send![layer, setFrame: frame];
+            }
+        }
+        debug!("Metal layer drawable size set to {width}x{height}");
+    }
+
+    /// Sets the layer's contents scale, so the drawable maps to the display's
+    /// pixels rather than being scaled by the compositor.
+    fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.layer.set_contents_scale(scale_factor);
+    }
+
+    /// Sets the rectangle of the render target later draws render into.
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    /// Creates a texture in the drawable's format, which draws render into
+    /// and materials sample.
+    fn create_render_target(&mut self, width: u32, height: u32) -> TextureId {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width.max(1) as u64);
+        descriptor.set_height(height.max(1) as u64);
+        descriptor.set_pixel_format(COLOR_PIXEL_FORMAT);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+        debug!("Creating {width}x{height} render target");
+        self.texture_manager.create_texture(&descriptor)
+    }
+
+    /// Renders later draws into a render target, or into the frame with `None`.
+    fn set_render_target(&mut self, target: Option<RenderTarget>) {
+        self.render_target = target;
+        self.render_target_started = false;
+    }
+
+    /// Sets the reprojection and jitter used to resolve the current frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `constants` - The temporal constants of the frame.
+    fn set_temporal_constants(&mut self, constants: &TemporalConstants) {
+        self.post_process.set_temporal_constants(*constants);
+    }
+
+    /// Sets the lens flare drawn over the current frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `lens_flare` - The flare laid out for this frame, or `None` for no flare.
+    fn set_lens_flare(&mut self, lens_flare: Option<&LensFlareFrame>) {
+        self.post_process.set_lens_flare(lens_flare);
+    }
+
+    /// Sets the atmosphere blended over the current frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `atmosphere` - The atmosphere constants of this frame, or `None` for no atmosphere.
+    fn set_atmosphere(&mut self, atmosphere: Option<&AtmosphereConstants>) {
+        self.post_process.set_atmosphere(atmosphere);
+    }
+
+    /// Sets the sky drawn behind the current frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `sky` - The sky constants of this frame, or `None` for no sky.
+    fn set_sky(&mut self, sky: Option<&SkyConstants>) {
+        self.post_process.set_sky(sky);
+    }
+
+    /// Sets the canvas drawn over the resolved frame.
+    ///
+    /// Must be called before the frame's first draw, since it decides
+    /// whether draws render offscreen.
+    ///
+    /// # Arguments
+    ///
+    /// * `overlay` - The canvas of this frame, or `None` for no overlay.
+    fn set_overlay(&mut self, overlay: Option<&CanvasFrame>) {
+        self.overlay.set_frame(overlay);
+        self.update_offscreen();
+    }
+
+    /// Resolves anti-aliasing and post-process effects into the drawable and presents it.
+    ///
+    /// Without either, every draw presents on its own and this does nothing.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `RendererError`.
+    fn end_frame(&mut self) -> Result<(), RendererError> {
+        self.depth_prepassed = false;
+        self.viewport = Viewport::FULL;
+        if !self.post_process.is_enabled() {
+            return Ok(());
+        }
+
+        let drawable = self
+            .layer
+            .next_drawable()
+            .ok_or(BackendError::NoDrawable)?
+            .to_owned();
+        let texture = drawable.texture();
+        // Depth matches the scene, which may be scaled from the drawable
+        let (width, height) = self
+            .post_process
+            .scene_size(texture.width(), texture.height());
+        self.buffer_manager
+            .ensure_depth_texture(CGSize::new(width as f64, height as f64));
+        let depth_texture = self.buffer_manager.depth_texture.as_ref().unwrap();
+
+        let command_buffer = self.command_queue.new_command_buffer().to_owned();
+        let ray_tracing = &self.ray_tracing;
+        let shadow_maps = &mut self.shadow_maps;
+        let weighted_blended = &mut self.weighted_blended;
+        self.post_process.encode_resolve(
+            &command_buffer,
+            texture,
+            depth_texture,
+            CLEAR_COLOR,
+            |command_buffer, scene, depth| {
+                shadow_maps.encode_shadows(command_buffer, scene, depth);
+                ray_tracing.encode_shadows(command_buffer, scene, depth);
+                // Shadows darken the opaque surfaces seen through transparent ones
+                weighted_blended.encode_composite(command_buffer, scene);
+            },
+        );
+        self.overlay.encode(
+            &command_buffer,
+            texture,
+            depth_texture,
+            &self.texture_manager,
+        );
+        // After the resolve, which clears the depth of frames without draws
+        self.gpu_culler
+            .encode_depth_pyramid(&command_buffer, depth_texture);
+        if let Some(frame_readback) = &mut self.frame_readback {
+            frame_readback.encode_copy(&command_buffer, texture);
+        }
+        present_drawable(&command_buffer, &drawable, self.minimum_frame_duration);
+        command_buffer.commit();
+        Ok(())
+    }
+
+    /// Returns `true` if the device supports ray tracing.
+    fn supports_ray_tracing(&self) -> bool {
+        self.ray_tracing.is_supported()
+    }
+
+    /// Builds the acceleration structure of a stored mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh` - The index of the mesh in `MeshStorage`.
+    /// * `geometry` - The mesh's geometry.
+    fn build_ray_tracing_mesh(&mut self, mesh: usize, geometry: GeometryView<'_>) {
+        if self.ray_tracing.is_supported() {
+            self.ray_tracing
+                .build_mesh(&self.command_queue, mesh, &geometry);
+        }
+    }
+
+    /// Sets the ray-traced scene and shadows of the current frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The shadow casters and constants of this frame, or `None` for no shadows.
+    fn set_ray_tracing_frame(&mut self, frame: Option<&RayTracingFrame>) {
+        self.ray_tracing.set_frame(frame);
+        self.update_offscreen();
+    }
+
+    /// Sets the cascaded shadow maps of the current frame.
+    ///
+    /// Shadows are resolved from the depth buffer, so while they are set
+    /// the frame renders offscreen.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The shadow constants of this frame, or `None` for no shadows.
+    fn set_shadow_maps(&mut self, frame: Option<&ShadowMapFrame>) {
+        self.shadow_maps.set_frame(frame);
+        self.update_offscreen();
+    }
+
+    /// Sets the shadow maps of this frame's point and spot lights.
+    ///
+    /// Like cascaded shadows, light shadows are resolved from the depth
+    /// buffer, so while any are set the frame renders offscreen.
+    ///
+    /// # Arguments
+    ///
+    /// * `lights` - The shadow constants of each shadowed light, or none to release them.
+    fn set_light_shadows(&mut self, lights: &[LightShadowFrame]) {
+        self.shadow_maps.set_lights(lights);
+        self.update_offscreen();
+    }
+
+    /// Draws only the depth of a draw command into a cascade's or light's shadow map.
+    ///
+    /// The vertex buffer must hold position-only vertices and the uniforms
+    /// the target's view projection. The first draw into a cascade or light
+    /// face in a frame clears its shadow map.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The cascade or light face whose shadow map to draw into.
+    /// * `draw_command` - The draw command whose depth to draw.
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result indicating success or a `RendererError`.
+    fn draw_shadow_caster(
+        &mut self,
+        target: ShadowTarget,
+        draw_command: BackendDrawCommand,
+    ) -> Result<(), RendererError> {
+        let Some((texture, layer, load_action)) = self.shadow_maps.begin_draw(target) else {
+            return Ok(());
+        };
+        let descriptor = metal::RenderPassDescriptor::new();
+        attach_shadow_layer(descriptor, &texture, layer, load_action);
+
+        let command_buffer = self.command_queue.new_command_buffer().to_owned();
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
+        let mut render_pass =
+            RenderPass::new(encoder, Self::create_viewport(&texture, Viewport::FULL));
+        render_pass.set_depth_stencil_state(self.depth_stencil_cache.get(true, false, true, None));
+        render_pass.set_render_state(&self.render_state);
+
+        let variant = PipelineVariant::for_draw_command(&draw_command).depth_only();
+        let pipeline_state = self.render_pipeline_cache.get_pipeline_state(
+            variant,
+            &self.vertex_layout,
+            MaterialConstants::default(),
+        )?;
+        render_pass.set_pipeline(pipeline_state);
+
+        render_pass.set_vertex_buffer(0, Some(self.buffer_manager.bound_vertex_buffer()), 0);
+        render_pass.bind_vertex_data(1, self.buffer_manager.uniform_binding());
+        render_pass.set_vertex_buffer(3, Some(&self.buffer_manager.frame_constants_buffer), 0);
+        render_pass.draw(draw_command, &self.buffer_manager, None);
+        render_pass.end();
+        command_buffer.commit();
+
+        Ok(())
+    }
+
+    /// Enables or disables copying presented frames back to the CPU.
+    ///
+    /// Drawables can only be copied when the layer is not framebuffer-only,
+    /// which may cost some performance, so this is disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to copy presented frames.
+    fn set_frame_readback(&mut self, enabled: bool) {
+        self.layer.set_framebuffer_only(!enabled);
+        self.frame_readback = enabled.then(|| FrameReadback::new(&self.device));
+        debug!("Frame readback set to: {}", enabled);
+    }
+
+    /// Collects the frames whose copies have completed on the GPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - Whether to wait for frames still in flight.
+    ///
+    /// # Returns
+    ///
+    /// The completed frames, oldest first, or none if readback is disabled.
+    fn finish_frame_readback(&mut self, wait: bool) -> Vec<FrameImage> {
+        self.frame_readback
+            .as_mut()
+            .map(|frame_readback| frame_readback.finish_frame(wait))
+            .unwrap_or_default()
+    }
+
+    /// Starts capturing all GPU work submitted to the command queue.
+    ///
+    /// Outside of Xcode, captures require the `METAL_CAPTURE_ENABLED=1`
+    /// environment variable to be set before launch.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where the capture is sent when it ends.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `BackendError::CaptureFailed`.
+    fn begin_frame_capture(
+        &mut self,
+        destination: &CaptureDestination,
+    ) -> Result<(), RendererError> {
+        let capture_manager = CaptureManager::shared();
+        if capture_manager.is_capturing() {
+            return Err(BackendError::CaptureFailed(
+                "A capture is already in progress".to_string(),
+            )
+            .into());
+        }
+
+        let descriptor = CaptureDescriptor::new();
+        descriptor.set_capture_command_queue(&self.command_queue);
+        let metal_destination = match destination {
+            CaptureDestination::DeveloperTools => MTLCaptureDestination::DeveloperTools,
+            CaptureDestination::TraceFile(path) => {
+                descriptor.set_output_url(path);
+                MTLCaptureDestination::GpuTraceDocument
+            }
+        };
+        if !capture_manager.supports_destination(metal_destination) {
+            return Err(BackendError::CaptureFailed(format!(
+                "{:?} is not supported; attach Xcode or set METAL_CAPTURE_ENABLED=1",
+                metal_destination
+            ))
+            .into());
+        }
+        descriptor.set_destination(metal_destination);
+
+        capture_manager
+            .start_capture(&descriptor)
+            .map_err(BackendError::CaptureFailed)?;
+        info!("GPU frame capture started: {:?}", destination);
+        Ok(())
+    }
+
+    /// Stops the capture started by `begin_frame_capture`, if any.
+    fn end_frame_capture(&mut self) {
+        let capture_manager = CaptureManager::shared();
+        if capture_manager.is_capturing() {
+            capture_manager.stop_capture();
+            info!("GPU frame capture finished");
+        }
+    }
+
+    /// Gathers the GPU memory held by the backend, per resource category.
+    ///
+    /// Drawables are owned by the layer, so their size is estimated from the
+    /// drawable size and the maximum drawable count.
+    ///
+    /// # Returns
+    ///
+    /// A `GpuMemoryReport` describing current allocations.
+    fn gpu_memory_report(&self) -> GpuMemoryReport {
+        let buffers = &self.buffer_manager;
+        let drawable_size = self.layer.drawable_size();
+        let drawable_bytes = (drawable_size.width * drawable_size.height) as u64
+            * 4 // BGRA8Unorm_sRGB
+            * self.layer.maximum_drawable_count();
+        let depth_bytes = buffers
+            .depth_texture
+            .as_ref()
+            .map_or(0, |texture| texture.allocated_size());
+
+        let (static_vertex_bytes, static_index_bytes) = self.static_meshes.allocated_bytes();
+
+        GpuMemoryReport {
+            vertex_bytes: buffers.vertex_buffer.allocated_size() + static_vertex_bytes,
+            index_bytes: buffers.index_buffer.allocated_size() + static_index_bytes,
+            uniform_bytes: buffers.uniform_buffer.allocated_size()
+                + buffers.frame_constants_buffer.allocated_size()
+                + self.material_table.allocated_bytes(),
+            instance_bytes: buffers.instance_buffer.allocated_size(),
+            texture_bytes: self.texture_manager.allocated_bytes(),
+            render_target_bytes: depth_bytes
+                + drawable_bytes
+                + self.post_process.allocated_bytes()
+                + self.gpu_culler.allocated_bytes()
+                + self.weighted_blended.allocated_bytes()
+                + self.shadow_maps.allocated_bytes()
+                + self
+                    .render_target_depth
+                    .as_ref()
+                    .map_or(0, |texture| texture.allocated_size()),
+            acceleration_structure_bytes: self.ray_tracing.allocated_bytes(),
+            heap_reserved_bytes: buffers.memory_stats().reserved_bytes,
+            device_allocated_bytes: self.device.current_allocated_size(),
+            recommended_working_set_bytes: self.device.recommended_max_working_set_size(),
+            has_unified_memory: self.device.has_unified_memory(),
+        }
     }
 
     /// Creates a new texture.
@@ -324,7 +1517,22 @@ impl GraphicsBackend for MetalBackend {
             data,
             bytes_per_row,
             bytes_per_image,
-        )
+        )?;
+        Ok(())
+    }
+
+    fn upload_texture(&mut self, upload: TextureUpload) -> Result<(), RendererError> {
+        trace!("Queueing upload into texture: {:?}", upload.texture);
+        self.texture_manager.upload_texture(upload)?;
+        Ok(())
+    }
+
+    fn poll_texture_uploads(&mut self) -> Vec<TextureId> {
+        self.texture_manager.poll_uploads()
+    }
+
+    fn pending_texture_uploads(&self) -> usize {
+        self.texture_manager.pending_uploads()
     }
 
     fn create_render_pipeline_state(
@@ -332,16 +1540,31 @@ impl GraphicsBackend for MetalBackend {
         descriptor: &RenderPipelineDescriptor,
     ) -> Result<(), RendererError> {
         debug!("Creating new render pipeline state");
-        self.render_pipeline_cache.create_pipeline_state(descriptor)
+        self.render_pipeline_cache.create_pipeline_state(
+            PipelineVariant::Default,
+            &VertexLayout::default(),
+            descriptor,
+        )?;
+        Ok(())
+    }
+
+    fn set_async_pipelines(&mut self, enabled: bool) {
+        self.render_pipeline_cache.set_asynchronous(enabled);
+    }
+
+    fn warm_up_pipelines(&mut self, layouts: &[VertexLayout]) -> Result<(), RendererError> {
+        self.render_pipeline_cache.warm_up(layouts)?;
+        Ok(())
+    }
+
+    fn pending_pipelines(&self) -> usize {
+        self.render_pipeline_cache.pending_count()
     }
 
     // TODO: Use render pass for batch calling
     #[allow(unused_variables)]
     fn render_pass(&mut self, descriptor: &RenderPassDescriptorRef) -> Result<(), RendererError> {
-        let drawable = self
-            .layer
-            .next_drawable()
-            .ok_or(RendererError::DrawFailed("No next drawable".to_string()))?;
+        let drawable = self.layer.next_drawable().ok_or(BackendError::NoDrawable)?;
 
         // let command_buffer = self.command_queue.new_command_buffer();
         // let encoder = command_buffer.new_render_command_encoder(descriptor);
@@ -373,9 +1596,16 @@ impl<'a> RenderPass<'a> {
         RenderPass { encoder, viewport }
     }
 
-    /// Sets the render pipeline state.
-    pub fn set_pipeline(&mut self, pipeline: &metal::RenderPipelineState) {
-        self.encoder.set_render_pipeline_state(pipeline);
+    /// Sets the render pipeline state, binding the vertex stride a fallback pipeline reads.
+    pub fn set_pipeline(&mut self, pipeline: CachedPipeline) {
+        self.encoder.set_render_pipeline_state(pipeline.state);
+        if let Some(stride) = pipeline.fallback_stride {
+            self.encoder.set_vertex_bytes(
+                FALLBACK_STRIDE_INDEX,
+                std::mem::size_of::<u32>() as u64,
+                &stride as *const u32 as *const std::ffi::c_void,
+            );
+        }
     }
 
     /// Sets a vertex buffer.
@@ -383,11 +1613,72 @@ impl<'a> RenderPass<'a> {
         self.encoder.set_vertex_buffer(index, buffer, offset);
     }
 
+    /// Binds per-draw vertex data from either a buffer or inline bytes.
+    pub fn bind_vertex_data(&self, index: u64, binding: BufferBinding) {
+        match binding {
+            BufferBinding::Buffer(buffer) => self.encoder.set_vertex_buffer(index, Some(buffer), 0),
+            BufferBinding::Bytes(bytes) => self.encoder.set_vertex_bytes(
+                index,
+                bytes.len() as u64,
+                bytes.as_ptr() as *const std::ffi::c_void,
+            ),
+        }
+    }
+
+    /// Sets a fragment buffer.
+    pub fn set_fragment_buffer(&self, index: u64, buffer: Option<&BufferRef>, offset: u64) {
+        self.encoder.set_fragment_buffer(index, buffer, offset);
+    }
+
+    /// Sets inline fragment data.
+    pub fn set_fragment_bytes(&self, index: u64, bytes: &[u8]) {
+        self.encoder.set_fragment_bytes(
+            index,
+            bytes.len() as u64,
+            bytes.as_ptr() as *const std::ffi::c_void,
+        );
+    }
+
+    /// Makes textures referenced through argument buffers resident for the fragment stage.
+    pub fn use_textures<'t>(&self, textures: impl Iterator<Item = &'t metal::Texture>) {
+        for texture in textures {
+            self.encoder.use_resource_at(
+                texture,
+                MTLResourceUsage::Read | MTLResourceUsage::Sample,
+                MTLRenderStages::Fragment,
+            );
+        }
+    }
+
     /// Sets the depth stencil state.
     pub fn set_depth_stencil_state(&mut self, state: &DepthStencilState) {
         self.encoder.set_depth_stencil_state(state);
     }
 
+    /// Sets the cull mode and depth bias of a render state.
+    ///
+    /// The depth and stencil tests are applied through the depth stencil state.
+    pub fn set_render_state(&mut self, render_state: &RenderState) {
+        self.encoder
+            .set_cull_mode(MTLCullMode::from(render_state.cull_mode));
+        let bias = render_state.depth_bias;
+        self.encoder
+            .set_depth_bias(bias.constant, bias.slope_scale, bias.clamp);
+        self.encoder
+            .set_stencil_reference_value(render_state.stencil_reference);
+        trace!("Render state set to: {:?}", render_state);
+    }
+
+    /// Flips which triangles face the camera, for draws mirrored by their view projection.
+    pub fn set_mirrored(&mut self, mirrored: bool) {
+        // Metal's default winding, which mirroring turns counter-clockwise
+        self.encoder.set_front_facing_winding(if mirrored {
+            MTLWinding::CounterClockwise
+        } else {
+            MTLWinding::Clockwise
+        });
+    }
+
     /// Sets the wireframe mode for rendering.
     pub fn set_wireframe_mode(&mut self, wireframe: bool) {
         unsafe {
@@ -404,8 +1695,22 @@ impl<'a> RenderPass<'a> {
     }
 
     /// Executes the draw command.
-    fn draw(&mut self, draw_command: BackendDrawCommand, buffer_manager: &BufferManager) {
+    ///
+    /// Culled instanced draws read their visible instances and instance count from the GPU.
+    fn draw(
+        &mut self,
+        draw_command: BackendDrawCommand,
+        buffer_manager: &BufferManager,
+        culled: Option<&CulledInstances>,
+    ) {
         self.encoder.set_viewport(self.viewport);
+        // Draws of split-screen views stay within their rectangle of the target
+        self.encoder.set_scissor_rect(MTLScissorRect {
+            x: self.viewport.originX as u64,
+            y: self.viewport.originY as u64,
+            width: self.viewport.width as u64,
+            height: self.viewport.height as u64,
+        });
 
         match draw_command {
             BackendDrawCommand::Basic {
@@ -439,7 +1744,7 @@ impl<'a> RenderPass<'a> {
                     primitive_type.into(),
                     index_count,
                     index_type.into(),
-                    &buffer_manager.index_buffer,
+                    buffer_manager.bound_index_buffer(),
                     index_buffer_offset,
                 );
             }
@@ -456,8 +1761,17 @@ impl<'a> RenderPass<'a> {
                     vertex_count,
                     instance_count
                 );
-                self.encoder
-                    .set_vertex_buffer(2, Some(&buffer_manager.instance_buffer), 0);
+                if let Some(culled) = culled {
+                    self.encoder
+                        .set_vertex_buffer(2, Some(&culled.instances), 0);
+                    self.encoder.draw_primitives_indirect(
+                        primitive_type.into(),
+                        &culled.arguments,
+                        0,
+                    );
+                    return;
+                }
+                self.bind_vertex_data(2, buffer_manager.instance_binding());
                 self.encoder.draw_primitives_instanced(
                     primitive_type.into(),
                     vertex_start,
@@ -474,13 +1788,25 @@ impl<'a> RenderPass<'a> {
             } => {
                 trace!("Drawing indexed instanced primitives: type={:?}, count={}, index_type={:?}, offset={}, instances={}", 
                         primitive_type, index_count, index_type, index_buffer_offset, instance_count);
-                self.encoder
-                    .set_vertex_buffer(2, Some(&buffer_manager.instance_buffer), 0);
+                if let Some(culled) = culled {
+                    self.encoder
+                        .set_vertex_buffer(2, Some(&culled.instances), 0);
+                    self.encoder.draw_indexed_primitives_indirect(
+                        primitive_type.into(),
+                        index_type.into(),
+                        buffer_manager.bound_index_buffer(),
+                        index_buffer_offset,
+                        &culled.arguments,
+                        0,
+                    );
+                    return;
+                }
+                self.bind_vertex_data(2, buffer_manager.instance_binding());
                 self.encoder.draw_indexed_primitives_instanced(
                     primitive_type.into(),
                     index_count,
                     index_type.into(),
-                    &buffer_manager.index_buffer,
+                    buffer_manager.bound_index_buffer(),
                     index_buffer_offset,
                     instance_count,
                 );
@@ -506,7 +1832,7 @@ mod tests {
         let command_queue = device.new_command_queue();
         let command_buffer = command_queue.new_command_buffer();
         let descriptor = metal::RenderPassDescriptor::new();
-        let encoder = command_buffer.new_render_command_encoder(&descriptor);
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
         let viewport = MTLViewport {
             originX: 0.0,
             originY: 0.0,
@@ -1,18 +1,22 @@
 //! Metal buffer management module.
 //!
 //! This module provides functionality to create and manage Metal buffers for vertex,
-//! index, uniform, and instance data, as well as depth textures.
+//! index, uniform, frame constant, and instance data, as well as depth textures.
+//! Buffers are sub-allocated from GPU heaps through the `MemoryManager`.
+//! Small per-draw payloads (uniforms, short instance arrays) are kept on the CPU
+//! and bound inline with `set_vertex_bytes` instead of rewriting a shared buffer.
 
+use super::memory_manager::{MemoryManager, MemoryStats};
 use crate::renderer::{
-    common::{Uniforms, Vertex},
+    common::{FrameConstants, Uniforms, Vertex},
+    error::BackendError,
     render_queue::InstanceData,
-    RendererError,
+    vertex_layout::PackedVertices,
 };
 use core_graphics::display::CGSize;
-use glam::Mat4;
 use log::{debug, trace, warn};
 use metal::{
-    Buffer, Device, MTLPixelFormat, MTLResourceOptions, MTLStorageMode, MTLTextureUsage, Texture,
+    Buffer, BufferRef, Device, MTLPixelFormat, MTLStorageMode, MTLTextureUsage, Texture,
     TextureDescriptor,
 };
 
@@ -21,16 +25,46 @@ const MAX_VERTICES: usize = 65_536; // 2^16
 const MAX_INDICES: usize = 196_608; // 65536 * 3
 const MAX_INSTANCES: usize = 4_096;
 
-/// Manages Metal buffers for vertex, index, uniform, and instance data.
+/// Payloads smaller than this are bound inline with `set_vertex_bytes`.
+pub const MAX_INLINE_BYTES: usize = 4_096;
+
+/// Describes where the data for a shader buffer slot comes from.
+pub enum BufferBinding<'a> {
+    /// Bind a Metal buffer.
+    Buffer(&'a BufferRef),
+    /// Copy the bytes directly into the command stream.
+    Bytes(&'a [u8]),
+}
+
+/// Returns `true` if `count` items of `T` are small enough to be bound inline.
+fn fits_inline<T>(count: usize) -> bool {
+    count * std::mem::size_of::<T>() < MAX_INLINE_BYTES
+}
+
+/// Reinterprets a slice of plain data as raw bytes.
+pub fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+/// Manages Metal buffers for vertex, index, uniform, frame constant, and instance data.
 pub struct BufferManager {
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
     pub instance_buffer: Buffer,
     pub uniform_buffer: Buffer,
+    pub frame_constants_buffer: Buffer,
     pub depth_texture: Option<Texture>,
+    /// The private vertex buffer of the bound static mesh, read by draws instead of `vertex_buffer`.
+    static_vertices: Option<Buffer>,
+    /// The private index buffer of the bound static mesh, read by draws instead of `index_buffer`.
+    static_indices: Option<Buffer>,
     vertex_count: usize,
     index_count: usize,
     instance_count: usize,
+    inline_uniforms: Option<Uniforms>,
+    inline_instances: Vec<InstanceData>,
+    instances_inline: bool,
+    memory: MemoryManager,
     device: Device,
 }
 
@@ -43,47 +77,67 @@ impl BufferManager {
     ///
     /// # Returns
     ///
-    /// A `Result` containing the new `BufferManager` or a `RendererError`.
-    pub fn new(device: &Device) -> Result<Self, RendererError> {
+    /// A `Result` containing the new `BufferManager` or a `BackendError`.
+    pub fn new(device: &Device) -> Result<Self, BackendError> {
         debug!("Creating new BufferManager");
+        let mut memory = MemoryManager::new(device);
         let vertex_buffer = Self::create_buffer(
-            device,
+            &mut memory,
             MAX_VERTICES,
             std::mem::size_of::<Vertex>(),
             "Vertex",
-        );
-        let index_buffer =
-            Self::create_buffer(device, MAX_INDICES, std::mem::size_of::<u32>(), "Index");
+        )?;
+        let index_buffer = Self::create_buffer(
+            &mut memory,
+            MAX_INDICES,
+            std::mem::size_of::<u32>(),
+            "Index",
+        )?;
         let instance_buffer = Self::create_buffer(
-            device,
+            &mut memory,
             MAX_INSTANCES,
             std::mem::size_of::<InstanceData>(),
             "Instance",
-        );
-        let uniform_buffer = Self::create_buffer(device, 1, std::mem::size_of::<Mat4>(), "Uniform");
+        )?;
+        let uniform_buffer =
+            Self::create_buffer(&mut memory, 1, std::mem::size_of::<Uniforms>(), "Uniform")?;
+        let frame_constants_buffer = Self::create_buffer(
+            &mut memory,
+            1,
+            std::mem::size_of::<FrameConstants>(),
+            "FrameConstants",
+        )?;
 
         Ok(BufferManager {
             vertex_buffer,
             index_buffer,
             uniform_buffer,
+            frame_constants_buffer,
             instance_buffer,
             depth_texture: None,
+            static_vertices: None,
+            static_indices: None,
             vertex_count: 0,
             index_count: 0,
             instance_count: 0,
+            inline_uniforms: None,
+            inline_instances: Vec::new(),
+            instances_inline: false,
+            memory,
             device: device.clone(),
         })
     }
 
-    /// Creates a Metal buffer with the specified size and options.
-    fn create_buffer(device: &Device, count: usize, stride: usize, name: &str) -> Buffer {
-        let buffer = device.new_buffer(
-            (count * stride) as u64,
-            MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeShared,
-        );
-        buffer.set_label(name);
+    /// Allocates a Metal buffer with the specified size from the memory manager's heaps.
+    fn create_buffer(
+        memory: &mut MemoryManager,
+        count: usize,
+        stride: usize,
+        name: &str,
+    ) -> Result<Buffer, BackendError> {
+        let allocation = memory.allocate((count * stride) as u64, name)?;
         debug!("Created {name} buffer: size = {} bytes", count * stride);
-        buffer
+        Ok(allocation.buffer)
     }
 
     /// Generic method to update a buffer with new data.
@@ -93,7 +147,7 @@ impl BufferManager {
         data: &[T],
         max_count: usize,
         buffer_type: &str,
-    ) -> Result<usize, RendererError> {
+    ) -> Result<usize, BackendError> {
         if data.len() > max_count {
             warn!(
                 "{} buffer overflow: {} items exceed maximum of {}",
@@ -101,7 +155,11 @@ impl BufferManager {
                 data.len(),
                 max_count
             );
-            return Err(RendererError::BufferOverflow);
+            return Err(BackendError::BufferOverflow {
+                buffer: buffer_type.to_string(),
+                count: data.len(),
+                capacity: max_count,
+            });
         }
 
         unsafe {
@@ -121,13 +179,49 @@ impl BufferManager {
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or a `RendererError`.
-    pub fn update_vertex_buffer(&mut self, vertices: &[Vertex]) -> Result<(), RendererError> {
+    /// A `Result` indicating success or a `BackendError`.
+    pub fn update_vertex_buffer(&mut self, vertices: &[Vertex]) -> Result<(), BackendError> {
+        self.unbind_static();
         self.vertex_count =
             self.update_buffer(&self.vertex_buffer, vertices, MAX_VERTICES, "vertex")?;
         Ok(())
     }
 
+    /// Updates the vertex buffer with vertices packed in a custom layout.
+    ///
+    /// The vertex buffer holds as many bytes as `MAX_VERTICES` default
+    /// vertices, so layouts with a smaller stride fit more vertices.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertices` - The packed vertices to update the buffer with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `BackendError`.
+    pub fn update_packed_vertex_buffer(
+        &mut self,
+        vertices: &PackedVertices,
+    ) -> Result<(), BackendError> {
+        self.unbind_static();
+        let capacity = MAX_VERTICES * std::mem::size_of::<Vertex>() / vertices.layout.stride();
+        if vertices.len() > capacity {
+            warn!(
+                "vertex buffer overflow: {} packed vertices exceed maximum of {}",
+                vertices.len(),
+                capacity
+            );
+            return Err(BackendError::BufferOverflow {
+                buffer: "vertex".to_string(),
+                count: vertices.len(),
+                capacity,
+            });
+        }
+        self.update_buffer(&self.vertex_buffer, &vertices.data, usize::MAX, "vertex")?;
+        self.vertex_count = vertices.len();
+        Ok(())
+    }
+
     /// Updates the index buffer with new index data.
     ///
     /// # Arguments
@@ -136,25 +230,38 @@ impl BufferManager {
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or a `RendererError`.
-    pub fn update_index_buffer(&mut self, indices: &[u32]) -> Result<(), RendererError> {
+    /// A `Result` indicating success or a `BackendError`.
+    pub fn update_index_buffer(&mut self, indices: &[u32]) -> Result<(), BackendError> {
+        self.static_indices = None;
         self.index_count = self.update_buffer(&self.index_buffer, indices, MAX_INDICES, "index")?;
         Ok(())
     }
 
     /// Updates the instance buffer with new instance data.
     ///
+    /// Instance arrays small enough to be bound inline are kept on the CPU
+    /// instead of being written to the shared instance buffer.
+    ///
     /// # Arguments
     ///
     /// * `instances` - A slice of instance data to update the buffer with.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success of a `RendererError`.
+    /// A `Result` indicating success of a `BackendError`.
     pub fn update_instance_buffer(
         &mut self,
         instances: &[InstanceData],
-    ) -> Result<(), RendererError> {
+    ) -> Result<(), BackendError> {
+        self.instances_inline = fits_inline::<InstanceData>(instances.len());
+        if self.instances_inline {
+            trace!("Binding {} instances inline", instances.len());
+            self.inline_instances.clear();
+            self.inline_instances.extend_from_slice(instances);
+            self.instance_count = instances.len();
+            return Ok(());
+        }
+
         self.instance_count =
             self.update_buffer(&self.instance_buffer, instances, MAX_INSTANCES, "instance")?;
         Ok(())
@@ -162,16 +269,25 @@ impl BufferManager {
 
     /// Updates the uniform buffer with new uniform data.
     ///
+    /// Uniforms small enough to be bound inline are kept on the CPU, which
+    /// avoids the `did_modify_range` cost and lets every draw carry its own copy.
+    ///
     /// # Arguments
     ///
     /// * `uniforms` - A reference to the uniform data to update the buffer with.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or a `RendererError`.
-    // TODO: Make a uniform buffer pool to allow multiple objects in a scene
-    pub fn update_uniform_buffer(&mut self, uniforms: &Uniforms) -> Result<(), RendererError> {
+    /// A `Result` indicating success or a `BackendError`.
+    pub fn update_uniform_buffer(&mut self, uniforms: &Uniforms) -> Result<(), BackendError> {
+        if fits_inline::<Uniforms>(1) {
+            trace!("Binding uniforms inline");
+            self.inline_uniforms = Some(*uniforms);
+            return Ok(());
+        }
+
         trace!("Updating uniform buffer");
+        self.inline_uniforms = None;
         unsafe {
             let dest: *mut Uniforms = self.uniform_buffer.contents() as *mut Uniforms;
             *dest = *uniforms;
@@ -184,6 +300,34 @@ impl BufferManager {
         Ok(())
     }
 
+    /// Updates the frame constants buffer with new per-frame data.
+    ///
+    /// # Arguments
+    ///
+    /// * `constants` - A reference to the frame constants to update the buffer with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `BackendError`.
+    pub fn update_frame_constants_buffer(
+        &mut self,
+        constants: &FrameConstants,
+    ) -> Result<(), BackendError> {
+        trace!("Updating frame constants buffer");
+        unsafe {
+            let dest: *mut FrameConstants =
+                self.frame_constants_buffer.contents() as *mut FrameConstants;
+            *dest = *constants;
+        }
+        self.frame_constants_buffer
+            .did_modify_range(metal::NSRange {
+                location: 0,
+                length: std::mem::size_of::<FrameConstants>() as u64,
+            });
+
+        Ok(())
+    }
+
     /// Updates the depth texture with a new size.
     ///
     /// # Arguments
@@ -193,9 +337,10 @@ impl BufferManager {
         let descriptor = TextureDescriptor::new();
         descriptor.set_width(size.width as u64);
         descriptor.set_height(size.height as u64);
-        descriptor.set_pixel_format(MTLPixelFormat::Depth32Float);
+        descriptor.set_pixel_format(MTLPixelFormat::Depth32Float_Stencil8);
         descriptor.set_storage_mode(MTLStorageMode::Private);
-        descriptor.set_usage(MTLTextureUsage::RenderTarget);
+        // Temporal anti-aliasing reads depth to reproject the history
+        descriptor.set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
 
         self.depth_texture = Some(self.device.new_texture(&descriptor));
         trace!("Created depth texture: {}x{}", size.width, size.height);
@@ -207,7 +352,7 @@ impl BufferManager {
     ///
     /// * `size` -  The required size for the depth texture.
     pub fn ensure_depth_texture(&mut self, size: CGSize) {
-        let update_needed = self.depth_texture.as_ref().map_or(true, |texture| {
+        let update_needed = self.depth_texture.as_ref().is_none_or(|texture| {
             texture.width() != size.width as u64 || texture.height() != size.height as u64
         });
 
@@ -216,6 +361,58 @@ impl BufferManager {
         }
     }
 
+    /// Returns the binding for the most recently updated uniforms.
+    pub fn uniform_binding(&self) -> BufferBinding<'_> {
+        match &self.inline_uniforms {
+            Some(uniforms) => BufferBinding::Bytes(as_bytes(std::slice::from_ref(uniforms))),
+            None => BufferBinding::Buffer(&self.uniform_buffer),
+        }
+    }
+
+    /// Returns the binding for the most recently updated instance data.
+    pub fn instance_binding(&self) -> BufferBinding<'_> {
+        if self.instances_inline {
+            BufferBinding::Bytes(as_bytes(&self.inline_instances))
+        } else {
+            BufferBinding::Buffer(&self.instance_buffer)
+        }
+    }
+
+    /// Binds the private buffers of a static mesh, which draws read until the
+    /// next vertex buffer update.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertices` - The mesh's vertex buffer.
+    /// * `indices` - The mesh's index buffer, if it is indexed.
+    pub fn bind_static(&mut self, vertices: &Buffer, indices: Option<&Buffer>) {
+        self.static_vertices = Some(vertices.clone());
+        self.static_indices = indices.cloned();
+    }
+
+    /// Returns to reading the shared vertex and index buffers.
+    fn unbind_static(&mut self) {
+        self.static_vertices = None;
+        self.static_indices = None;
+    }
+
+    /// Returns the buffer draws read their vertices from.
+    pub fn bound_vertex_buffer(&self) -> &BufferRef {
+        self.static_vertices
+            .as_deref()
+            .unwrap_or(&self.vertex_buffer)
+    }
+
+    /// Returns the buffer indexed draws read their indices from.
+    pub fn bound_index_buffer(&self) -> &BufferRef {
+        self.static_indices.as_deref().unwrap_or(&self.index_buffer)
+    }
+
+    /// Returns heap usage statistics for the managed buffers.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.memory.stats()
+    }
+
     #[allow(dead_code)]
     pub fn get_vertex_count(&self) -> usize {
         self.vertex_count
@@ -234,8 +431,12 @@ impl BufferManager {
 
 #[cfg(test)]
 mod tests {
-    use super::{BufferManager, MAX_INDICES, MAX_VERTICES};
-    use crate::renderer::{common::Vertex, RendererError};
+    use super::{as_bytes, fits_inline, BufferManager, MAX_INDICES, MAX_INSTANCES, MAX_VERTICES};
+    use crate::renderer::{
+        common::{Uniforms, Vertex},
+        error::BackendError,
+        InstanceData,
+    };
     use core::f32;
     use metal::Device;
 
@@ -321,11 +522,27 @@ mod tests {
 
         assert!(matches!(
             buffer_manager.update_vertex_buffer(&too_many_vertices),
-            Err(RendererError::BufferOverflow)
+            Err(BackendError::BufferOverflow { .. })
         ));
         assert!(matches!(
             buffer_manager.update_index_buffer(&too_many_indices),
-            Err(RendererError::BufferOverflow)
+            Err(BackendError::BufferOverflow { .. })
         ));
     }
+
+    #[test]
+    fn test_fits_inline() {
+        assert!(fits_inline::<Uniforms>(1));
+        assert!(fits_inline::<InstanceData>(51));
+        assert!(!fits_inline::<InstanceData>(52));
+        assert!(!fits_inline::<InstanceData>(MAX_INSTANCES));
+    }
+
+    #[test]
+    fn test_as_bytes() {
+        let data = [1u32, 2u32];
+        let bytes = as_bytes(&data);
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(&bytes[0..4], &1u32.to_ne_bytes());
+    }
 }
@@ -0,0 +1,162 @@
+//! Metal frame readback module.
+//!
+//! This module copies presented drawables into CPU-visible buffers for frame
+//! recording. Copies are encoded into the command buffer that renders the
+//! frame and are read once the GPU has completed it, so the CPU never waits
+//! for the frame it just submitted.
+
+use crate::renderer::recording::FrameImage;
+use log::{debug, warn};
+use metal::{
+    Buffer, CommandBuffer, CommandBufferRef, Device, MTLBlitOption, MTLCommandBufferStatus,
+    MTLOrigin, MTLResourceOptions, MTLSize, TextureRef,
+};
+use std::collections::VecDeque;
+
+/// Frames that may be in flight before the CPU waits for the oldest one.
+const MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+/// A frame copy that may still be executing on the GPU.
+struct PendingFrame {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    command_buffer: CommandBuffer,
+}
+
+/// Copies presented frames back to the CPU without stalling the GPU.
+pub struct FrameReadback {
+    device: Device,
+    current: Option<PendingFrame>,
+    in_flight: VecDeque<PendingFrame>,
+    free_buffers: Vec<Buffer>,
+}
+
+impl FrameReadback {
+    /// Creates a new `FrameReadback` with no buffers allocated.
+    pub fn new(device: &Device) -> Self {
+        FrameReadback {
+            device: device.clone(),
+            current: None,
+            in_flight: VecDeque::new(),
+            free_buffers: Vec::new(),
+        }
+    }
+
+    /// Encodes a copy of the drawable texture into the current frame's buffer.
+    ///
+    /// Every pass of a frame presents, so later copies replace earlier ones
+    /// and the frame keeps what was presented last.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer rendering into the texture.
+    /// * `texture` - The drawable texture, which must not be framebuffer-only.
+    pub fn encode_copy(&mut self, command_buffer: &CommandBufferRef, texture: &TextureRef) {
+        let (width, height) = (texture.width() as u32, texture.height() as u32);
+        let bytes_per_row = width as u64 * 4;
+        let length = bytes_per_row * height as u64;
+
+        let buffer = match self.current.take() {
+            Some(frame) if frame.buffer.length() >= length => frame.buffer,
+            Some(frame) => {
+                self.free_buffers.push(frame.buffer);
+                self.take_buffer(length)
+            }
+            None => self.take_buffer(length),
+        };
+
+        let blit = command_buffer.new_blit_command_encoder();
+        blit.copy_from_texture_to_buffer(
+            texture,
+            0,
+            0,
+            MTLOrigin { x: 0, y: 0, z: 0 },
+            MTLSize {
+                width: width as u64,
+                height: height as u64,
+                depth: 1,
+            },
+            &buffer,
+            0,
+            bytes_per_row,
+            length,
+            MTLBlitOption::empty(),
+        );
+        blit.end_encoding();
+
+        self.current = Some(PendingFrame {
+            buffer,
+            width,
+            height,
+            command_buffer: command_buffer.to_owned(),
+        });
+    }
+
+    /// Ends the current frame and collects frames the GPU has finished copying.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - Whether to wait for all frames in flight, e.g. when recording stops.
+    ///
+    /// # Returns
+    ///
+    /// The completed frames, oldest first.
+    pub fn finish_frame(&mut self, wait: bool) -> Vec<FrameImage> {
+        if let Some(frame) = self.current.take() {
+            self.in_flight.push_back(frame);
+        }
+
+        let mut frames = Vec::new();
+        while let Some(frame) = self.in_flight.front() {
+            let must_wait = wait || self.in_flight.len() > MAX_FRAMES_IN_FLIGHT;
+            let status = frame.command_buffer.status();
+            if must_wait && status != MTLCommandBufferStatus::Completed {
+                frame.command_buffer.wait_until_completed();
+            } else if status != MTLCommandBufferStatus::Completed
+                && status != MTLCommandBufferStatus::Error
+            {
+                break;
+            }
+
+            let frame = self.in_flight.pop_front().unwrap();
+            if frame.command_buffer.status() == MTLCommandBufferStatus::Completed {
+                frames.push(Self::read_frame(&frame));
+            } else {
+                warn!("Frame readback failed, dropping frame");
+            }
+            self.free_buffers.push(frame.buffer);
+        }
+        frames
+    }
+
+    /// Returns a free buffer of at least `length` bytes, allocating one if needed.
+    fn take_buffer(&mut self, length: u64) -> Buffer {
+        if let Some(index) = self
+            .free_buffers
+            .iter()
+            .position(|buffer| buffer.length() >= length)
+        {
+            return self.free_buffers.swap_remove(index);
+        }
+
+        debug!("Allocating {} byte frame readback buffer", length);
+        let buffer = self
+            .device
+            .new_buffer(length.max(1), MTLResourceOptions::StorageModeShared);
+        buffer.set_label("FrameReadback");
+        buffer
+    }
+
+    fn read_frame(frame: &PendingFrame) -> FrameImage {
+        let length = frame.width as usize * frame.height as usize * 4;
+        // The GPU has completed the copy into the shared buffer
+        let pixels =
+            unsafe { std::slice::from_raw_parts(frame.buffer.contents() as *const u8, length) };
+        FrameImage {
+            width: frame.width,
+            height: frame.height,
+            pixels: pixels.to_vec(),
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Metal GPU culling module.
+//!
+//! This module culls the instances of instanced draws in a compute kernel.
+//! The instances of a culled draw are uploaded to their own buffer, and the
+//! kernel compacts the visible ones into a second buffer while counting them
+//! into the draw's indirect arguments, so the draw never waits on the CPU.
+//! While occlusion culling is enabled, every frame ends by reducing the depth
+//! buffer into a pyramid of farthest depths, which the next frame's kernels
+//! test instances against.
+
+use super::pipeline::load_metal_shader_library;
+use crate::renderer::{
+    common::BackendDrawCommand, error::PipelineError, gpu_culling::CullingConstants,
+    render_queue::InstanceData,
+};
+use log::{debug, trace};
+use metal::{
+    Buffer, CommandBufferRef, ComputePipelineState, Device, FunctionConstantValues, Library,
+    MTLDataType, MTLDrawIndexedPrimitivesIndirectArguments, MTLDrawPrimitivesIndirectArguments,
+    MTLPixelFormat, MTLResourceOptions, MTLSize, MTLStorageMode, MTLTextureType, MTLTextureUsage,
+    NSRange, Texture, TextureDescriptor, TextureRef,
+};
+use std::ffi::c_void;
+
+/// The threads per threadgroup of the depth pyramid kernels, along each axis.
+const PYRAMID_THREADGROUP_SIZE: u64 = 8;
+
+/// The visible instances of a culled draw and its indirect arguments.
+pub struct CulledInstances {
+    pub instances: Buffer,
+    /// The draw's indirect arguments, whose instance count the kernel fills in.
+    pub arguments: Buffer,
+}
+
+/// A mip chain of the farthest depth under every texel.
+struct DepthPyramid {
+    texture: Texture,
+    /// A view of every mip level, for writing one level while reading the previous.
+    levels: Vec<Texture>,
+    /// The size of the depth texture the pyramid reduces.
+    depth_size: (u64, u64),
+    /// Whether the pyramid holds a frame's depth yet.
+    valid: bool,
+}
+
+/// Culls instanced draws and builds the depth pyramid for occlusion culling.
+pub struct GpuCuller {
+    device: Device,
+    frustum_pipeline: ComputePipelineState,
+    occlusion_pipeline: ComputePipelineState,
+    pyramid_first_pipeline: ComputePipelineState,
+    pyramid_downsample_pipeline: ComputePipelineState,
+    /// The constants of the next draw, or `None` if it is not culled.
+    constants: Option<CullingConstants>,
+    /// The instances of the next draw, uploaded while it is culled.
+    instances: Option<Buffer>,
+    pyramid_enabled: bool,
+    depth_pyramid: Option<DepthPyramid>,
+}
+
+impl GpuCuller {
+    /// Creates a new `GpuCuller` with culling and the depth pyramid disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `GpuCuller` or a `PipelineError`.
+    pub fn new(device: &Device) -> Result<Self, PipelineError> {
+        let library = load_metal_shader_library(device)?;
+        let cull_pipeline = |occlusion: bool| {
+            let constants = FunctionConstantValues::new();
+            constants.set_constant_value_at_index(
+                &occlusion as *const bool as *const c_void,
+                MTLDataType::Bool,
+                0,
+            );
+            create_compute_pipeline(device, &library, "cull_instances", Some(constants))
+        };
+
+        Ok(GpuCuller {
+            device: device.clone(),
+            frustum_pipeline: cull_pipeline(false)?,
+            occlusion_pipeline: cull_pipeline(true)?,
+            pyramid_first_pipeline: create_compute_pipeline(
+                device,
+                &library,
+                "depth_pyramid_first",
+                None,
+            )?,
+            pyramid_downsample_pipeline: create_compute_pipeline(
+                device,
+                &library,
+                "depth_pyramid_downsample",
+                None,
+            )?,
+            constants: None,
+            instances: None,
+            pyramid_enabled: false,
+            depth_pyramid: None,
+        })
+    }
+
+    /// Sets the culling constants of subsequent draws, or `None` to draw every instance.
+    pub fn set_constants(&mut self, constants: Option<&CullingConstants>) {
+        self.constants = constants.copied();
+        self.instances = None;
+    }
+
+    /// Returns `true` if the next draw's instances are culled.
+    pub fn is_active(&self) -> bool {
+        self.constants.is_some()
+    }
+
+    /// Uploads the instances of the next culled draw.
+    ///
+    /// Every draw gets its own buffer, since earlier draws of the frame may
+    /// still be reading theirs.
+    pub fn upload_instances(&mut self, instances: &[InstanceData]) {
+        trace!("Uploading {} instances for culling", instances.len());
+        self.instances = Some(self.device.new_buffer_with_data(
+            instances.as_ptr() as *const c_void,
+            std::mem::size_of_val(instances).max(1) as u64,
+            MTLResourceOptions::StorageModeShared,
+        ));
+    }
+
+    /// Encodes the culling of the uploaded instances ahead of an instanced draw.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer of the draw.
+    /// * `draw_command` - The draw, giving the non-instance indirect arguments.
+    ///
+    /// # Returns
+    ///
+    /// The visible instances and indirect arguments to draw with, or `None`
+    /// if the draw is not culled.
+    pub fn encode_cull(
+        &self,
+        command_buffer: &CommandBufferRef,
+        draw_command: &BackendDrawCommand,
+    ) -> Option<CulledInstances> {
+        let (Some(constants), Some(instances)) = (&self.constants, &self.instances) else {
+            return None;
+        };
+        let arguments = match *draw_command {
+            BackendDrawCommand::Instanced {
+                vertex_start,
+                vertex_count,
+                ..
+            } => self.new_arguments(&MTLDrawPrimitivesIndirectArguments {
+                vertexCount: vertex_count as u32,
+                instanceCount: 0,
+                vertexStart: vertex_start as u32,
+                baseInstance: 0,
+            }),
+            // The index buffer offset is passed to the draw itself
+            BackendDrawCommand::IndexedInstanced { index_count, .. } => {
+                self.new_arguments(&MTLDrawIndexedPrimitivesIndirectArguments {
+                    indexCount: index_count as u32,
+                    instanceCount: 0,
+                    indexStart: 0,
+                    baseVertex: 0,
+                    baseInstance: 0,
+                })
+            }
+            _ => return None,
+        };
+        let visible = self
+            .device
+            .new_buffer(instances.length(), MTLResourceOptions::StorageModePrivate);
+
+        let pyramid = self
+            .depth_pyramid
+            .as_ref()
+            .filter(|pyramid| constants.occlusion != 0 && pyramid.valid);
+        let pipeline = match pyramid {
+            Some(_) => &self.occlusion_pipeline,
+            None => &self.frustum_pipeline,
+        };
+
+        let encoder = command_buffer.new_compute_command_encoder();
+        encoder.set_compute_pipeline_state(pipeline);
+        encoder.set_bytes(
+            0,
+            std::mem::size_of::<CullingConstants>() as u64,
+            constants as *const CullingConstants as *const c_void,
+        );
+        encoder.set_buffer(1, Some(instances), 0);
+        encoder.set_buffer(2, Some(&visible), 0);
+        // The instance count is the second field of both argument layouts
+        encoder.set_buffer(3, Some(&arguments), std::mem::size_of::<u32>() as u64);
+        if let Some(pyramid) = pyramid {
+            encoder.set_texture(0, Some(&pyramid.texture));
+        }
+        encoder.dispatch_threads(
+            MTLSize::new(constants.instance_count as u64, 1, 1),
+            MTLSize::new(pipeline.thread_execution_width(), 1, 1),
+        );
+        encoder.end_encoding();
+        trace!(
+            "Encoded culling of {} instances, occlusion: {}",
+            constants.instance_count,
+            pyramid.is_some()
+        );
+
+        Some(CulledInstances {
+            instances: visible,
+            arguments,
+        })
+    }
+
+    /// Enables or disables building the depth pyramid at the end of every frame.
+    pub fn set_depth_pyramid(&mut self, enabled: bool) {
+        self.pyramid_enabled = enabled;
+        if !enabled {
+            self.depth_pyramid = None;
+        }
+    }
+
+    /// Returns `true` if every frame builds a depth pyramid.
+    pub fn is_pyramid_enabled(&self) -> bool {
+        self.pyramid_enabled
+    }
+
+    /// Reduces a frame's depth into the depth pyramid, level by level.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer ending the frame.
+    /// * `depth` - The depth texture of the frame.
+    pub fn encode_depth_pyramid(&mut self, command_buffer: &CommandBufferRef, depth: &TextureRef) {
+        if !self.pyramid_enabled {
+            return;
+        }
+        let depth_size = (depth.width(), depth.height());
+        if self
+            .depth_pyramid
+            .as_ref()
+            .is_none_or(|pyramid| pyramid.depth_size != depth_size)
+        {
+            self.depth_pyramid = Some(self.new_depth_pyramid(depth_size));
+        }
+        let pyramid = self.depth_pyramid.as_mut().unwrap();
+
+        let encoder = command_buffer.new_compute_command_encoder();
+        let mut source: &TextureRef = depth;
+        for (index, level) in pyramid.levels.iter().enumerate() {
+            let pipeline = if index == 0 {
+                &self.pyramid_first_pipeline
+            } else {
+                &self.pyramid_downsample_pipeline
+            };
+            encoder.set_compute_pipeline_state(pipeline);
+            encoder.set_texture(0, Some(source));
+            encoder.set_texture(1, Some(level));
+            encoder.dispatch_threads(
+                MTLSize::new(level.width(), level.height(), 1),
+                MTLSize::new(PYRAMID_THREADGROUP_SIZE, PYRAMID_THREADGROUP_SIZE, 1),
+            );
+            source = level;
+        }
+        encoder.end_encoding();
+        pyramid.valid = true;
+    }
+
+    /// Returns the bytes allocated for the depth pyramid.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.depth_pyramid
+            .as_ref()
+            .map_or(0, |pyramid| pyramid.texture.allocated_size())
+    }
+
+    /// Creates a buffer holding a draw's indirect arguments.
+    fn new_arguments<T>(&self, arguments: &T) -> Buffer {
+        self.device.new_buffer_with_data(
+            arguments as *const T as *const c_void,
+            std::mem::size_of::<T>() as u64,
+            MTLResourceOptions::StorageModeShared,
+        )
+    }
+
+    /// Creates a depth pyramid at half the depth texture's size, down to a single texel.
+    fn new_depth_pyramid(&self, depth_size: (u64, u64)) -> DepthPyramid {
+        let (width, height) = ((depth_size.0 / 2).max(1), (depth_size.1 / 2).max(1));
+        let level_count = 64 - width.max(height).leading_zeros() as u64;
+
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width);
+        descriptor.set_height(height);
+        descriptor.set_mipmap_level_count(level_count);
+        descriptor.set_pixel_format(MTLPixelFormat::R32Float);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(
+            MTLTextureUsage::ShaderRead
+                | MTLTextureUsage::ShaderWrite
+                | MTLTextureUsage::PixelFormatView,
+        );
+        let texture = self.device.new_texture(&descriptor);
+        texture.set_label("DepthPyramid");
+
+        let levels = (0..level_count)
+            .map(|level| {
+                texture.new_texture_view_from_slice(
+                    MTLPixelFormat::R32Float,
+                    MTLTextureType::D2,
+                    NSRange::new(level, 1),
+                    NSRange::new(0, 1),
+                )
+            })
+            .collect();
+        debug!(
+            "Created depth pyramid: {}x{} with {} levels",
+            width, height, level_count
+        );
+
+        DepthPyramid {
+            texture,
+            levels,
+            depth_size,
+            valid: false,
+        }
+    }
+}
+
+/// Creates a compute pipeline from a kernel in the shader library.
+fn create_compute_pipeline(
+    device: &Device,
+    library: &Library,
+    name: &str,
+    constants: Option<FunctionConstantValues>,
+) -> Result<ComputePipelineState, PipelineError> {
+    let function =
+        library
+            .get_function(name, constants)
+            .map_err(|_| PipelineError::FunctionNotFound {
+                function: name.to_string(),
+                pipeline: name.to_string(),
+            })?;
+    device
+        .new_compute_pipeline_state_with_function(&function)
+        .map_err(|message| PipelineError::CreationFailed {
+            pipeline: name.to_string(),
+            message,
+        })
+}
@@ -0,0 +1,241 @@
+//! Metal material table module.
+//!
+//! This module encodes every material of the frame into a single Metal
+//! argument buffer. The fragment shader receives the whole table once per
+//! pass and indexes it with the material index of each draw, so switching
+//! materials costs a few inline bytes instead of rebinding textures, samplers
+//! and parameters.
+
+use super::texture_manager::TextureManager;
+use crate::renderer::{common::TextureId, error::AssetError, material_manager::Material};
+use log::{debug, trace, warn};
+use metal::{
+    ArgumentDescriptor, ArgumentEncoder, Array, Buffer, Device, MTLArgumentAccess,
+    MTLArgumentBuffersTier, MTLDataType, MTLPixelFormat, MTLRegion, MTLResourceOptions,
+    MTLSamplerMinMagFilter, MTLTextureType, SamplerDescriptor, SamplerState, Texture,
+    TextureDescriptor,
+};
+
+// Argument indices, matching the [[id(n)]] attributes of `MaterialArguments`
+const BASE_COLOR_TEXTURE_INDEX: u64 = 0;
+const BASE_COLOR_SAMPLER_INDEX: u64 = 1;
+const BASE_COLOR_INDEX: u64 = 2;
+const REFLECTION_TEXTURE_INDEX: u64 = 3;
+const REFLECTIVITY_INDEX: u64 = 4;
+
+const INITIAL_CAPACITY: usize = 64;
+
+/// Encodes all materials of a frame into one argument buffer.
+pub struct MaterialTable {
+    device: Device,
+    encoder: ArgumentEncoder,
+    buffer: Buffer,
+    sampler: SamplerState,
+    placeholder_texture: Texture,
+    resident_textures: Vec<Texture>,
+    element_stride: u64,
+    capacity: usize,
+    count: usize,
+    supports_indexing: bool,
+}
+
+impl MaterialTable {
+    /// Creates a new `MaterialTable` with room for an initial set of materials.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device used to create the argument buffer.
+    pub fn new(device: &Device) -> Self {
+        debug!("Creating new MaterialTable");
+        let encoder = Self::create_argument_encoder(device);
+        let element_stride = Self::element_stride(&encoder);
+
+        let supports_indexing = device.argument_buffers_support() == MTLArgumentBuffersTier::Tier2;
+        if !supports_indexing {
+            warn!("Argument buffer tier 2 unavailable: all draws use the default material");
+        }
+
+        MaterialTable {
+            device: device.clone(),
+            buffer: Self::create_buffer(device, INITIAL_CAPACITY, element_stride),
+            encoder,
+            sampler: Self::create_sampler(device),
+            placeholder_texture: Self::create_placeholder_texture(device),
+            resident_textures: Vec::new(),
+            element_stride,
+            capacity: INITIAL_CAPACITY,
+            count: 0,
+            supports_indexing,
+        }
+    }
+
+    fn create_argument_encoder(device: &Device) -> ArgumentEncoder {
+        let texture = ArgumentDescriptor::new();
+        texture.set_index(BASE_COLOR_TEXTURE_INDEX);
+        texture.set_data_type(MTLDataType::Texture);
+        texture.set_texture_type(MTLTextureType::D2);
+        texture.set_access(MTLArgumentAccess::ReadOnly);
+
+        let sampler = ArgumentDescriptor::new();
+        sampler.set_index(BASE_COLOR_SAMPLER_INDEX);
+        sampler.set_data_type(MTLDataType::Sampler);
+
+        let base_color = ArgumentDescriptor::new();
+        base_color.set_index(BASE_COLOR_INDEX);
+        base_color.set_data_type(MTLDataType::Float4);
+
+        let reflection_texture = ArgumentDescriptor::new();
+        reflection_texture.set_index(REFLECTION_TEXTURE_INDEX);
+        reflection_texture.set_data_type(MTLDataType::Texture);
+        reflection_texture.set_texture_type(MTLTextureType::D2);
+        reflection_texture.set_access(MTLArgumentAccess::ReadOnly);
+
+        let reflectivity = ArgumentDescriptor::new();
+        reflectivity.set_index(REFLECTIVITY_INDEX);
+        reflectivity.set_data_type(MTLDataType::Float);
+
+        device.new_argument_encoder(Array::from_slice(&[
+            texture,
+            sampler,
+            base_color,
+            reflection_texture,
+            reflectivity,
+        ]))
+    }
+
+    /// Returns the distance between consecutive materials in the argument buffer.
+    fn element_stride(encoder: &ArgumentEncoder) -> u64 {
+        let alignment = encoder.alignment().max(1);
+        encoder.encoded_length().div_ceil(alignment) * alignment
+    }
+
+    fn create_buffer(device: &Device, capacity: usize, element_stride: u64) -> Buffer {
+        let buffer = device.new_buffer(
+            capacity as u64 * element_stride,
+            MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeShared,
+        );
+        buffer.set_label("MaterialTable");
+        buffer
+    }
+
+    fn create_sampler(device: &Device) -> SamplerState {
+        let descriptor = SamplerDescriptor::new();
+        descriptor.set_min_filter(MTLSamplerMinMagFilter::Linear);
+        descriptor.set_mag_filter(MTLSamplerMinMagFilter::Linear);
+        device.new_sampler(&descriptor)
+    }
+
+    /// Creates the 1x1 white texture bound for materials without a texture.
+    fn create_placeholder_texture(device: &Device) -> Texture {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(1);
+        descriptor.set_height(1);
+        descriptor.set_pixel_format(MTLPixelFormat::RGBA8Unorm);
+
+        let texture = device.new_texture(&descriptor);
+        let white = [255u8; 4];
+        texture.replace_region(
+            MTLRegion::new_2d(0, 0, 1, 1),
+            0,
+            white.as_ptr() as *const std::ffi::c_void,
+            4,
+        );
+        texture
+    }
+
+    /// Re-encodes the argument buffer from the given materials.
+    ///
+    /// # Arguments
+    ///
+    /// * `materials` - All materials, indexed by `MaterialId`.
+    /// * `texture_manager` - Resolves the texture IDs referenced by materials.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `AssetError::InvalidTexture`.
+    pub fn update(
+        &mut self,
+        materials: &[Material],
+        texture_manager: &TextureManager,
+    ) -> Result<(), AssetError> {
+        if materials.len() > self.capacity {
+            self.capacity = materials.len().next_power_of_two();
+            self.buffer = Self::create_buffer(&self.device, self.capacity, self.element_stride);
+            debug!("Grew MaterialTable to {} materials", self.capacity);
+        }
+
+        self.resident_textures.clear();
+        for (index, material) in materials.iter().enumerate() {
+            let texture = self.resolve_texture(material.texture_id, texture_manager)?;
+            let reflection_texture =
+                self.resolve_texture(material.reflection_texture, texture_manager)?;
+
+            self.encoder
+                .set_argument_buffer(&self.buffer, index as u64 * self.element_stride);
+            self.encoder.set_texture(BASE_COLOR_TEXTURE_INDEX, &texture);
+            self.encoder
+                .set_sampler_state(BASE_COLOR_SAMPLER_INDEX, &self.sampler);
+            self.encoder
+                .set_texture(REFLECTION_TEXTURE_INDEX, &reflection_texture);
+
+            let base_color: [f32; 4] = material.base_color.into();
+            unsafe {
+                let dest = self.encoder.constant_data(BASE_COLOR_INDEX) as *mut [f32; 4];
+                *dest = base_color;
+                let dest = self.encoder.constant_data(REFLECTIVITY_INDEX) as *mut f32;
+                *dest = material.reflectivity;
+            }
+        }
+
+        self.count = materials.len();
+        trace!("Encoded {} materials into the argument buffer", self.count);
+        Ok(())
+    }
+
+    /// Returns the texture of a material, making it resident, or the
+    /// placeholder without one or while its uploads are in flight.
+    fn resolve_texture(
+        &mut self,
+        id: Option<TextureId>,
+        texture_manager: &TextureManager,
+    ) -> Result<Texture, AssetError> {
+        let Some(id) = id else {
+            return Ok(self.placeholder_texture.clone());
+        };
+        let texture = texture_manager
+            .get_texture(id)
+            .ok_or(AssetError::InvalidTexture(id))?;
+        if texture_manager.is_uploading(id) {
+            return Ok(self.placeholder_texture.clone());
+        }
+        self.resident_textures.push(texture.clone());
+        Ok(texture.clone())
+    }
+
+    /// Returns the argument buffer holding the encoded materials.
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Returns the number of bytes allocated for the argument buffer.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.buffer.allocated_size()
+    }
+
+    /// Returns every texture referenced by the argument buffer.
+    ///
+    /// These must be made resident with `use_resource` before drawing, since
+    /// Metal does not track resources referenced indirectly.
+    pub fn resident_textures(&self) -> impl Iterator<Item = &Texture> {
+        std::iter::once(&self.placeholder_texture).chain(&self.resident_textures)
+    }
+
+    /// Maps a material index to the index used by the shader.
+    pub fn shader_index(&self, material_index: usize) -> u32 {
+        if self.supports_indexing && material_index < self.count {
+            material_index as u32
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Metal GPU memory management module.
+//!
+//! This module sub-allocates buffers from large placement `MTLHeap`s instead
+//! of creating an individual `MTLBuffer` for every resource. Each heap is
+//! managed by a first-fit free list that coalesces neighbouring free ranges,
+//! and the manager tracks the total reservation against a memory budget.
+
+use crate::renderer::error::BackendError;
+use log::{debug, trace, warn};
+use metal::{
+    Buffer, Device, Heap, HeapDescriptor, MTLHazardTrackingMode, MTLHeapType, MTLResourceOptions,
+    MTLStorageMode,
+};
+
+/// Default size of each heap block.
+const DEFAULT_HEAP_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Resource options shared by the heaps and every buffer placed in them.
+const HEAP_RESOURCE_OPTIONS: MTLResourceOptions = MTLResourceOptions::from_bits_truncate(
+    MTLResourceOptions::CPUCacheModeDefaultCache.bits()
+        | MTLResourceOptions::StorageModeShared.bits()
+        | MTLResourceOptions::HazardTrackingModeTracked.bits(),
+);
+
+/// A first-fit free list managing byte ranges within a fixed-size block.
+#[derive(Debug)]
+pub struct FreeListAllocator {
+    size: u64,
+    used: u64,
+    // Free `(offset, size)` ranges, sorted by offset and never adjacent
+    free_ranges: Vec<(u64, u64)>,
+}
+
+impl FreeListAllocator {
+    /// Creates a new `FreeListAllocator` with a single free range of `size` bytes.
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            used: 0,
+            free_ranges: vec![(0, size)],
+        }
+    }
+
+    /// Reserves a range of `size` bytes whose offset is a multiple of `alignment`.
+    ///
+    /// # Returns
+    ///
+    /// The offset of the range, or `None` if no free range is large enough.
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let alignment = alignment.max(1);
+        let (index, offset) =
+            self.free_ranges
+                .iter()
+                .enumerate()
+                .find_map(|(index, &(start, length))| {
+                    let offset = start.div_ceil(alignment) * alignment;
+                    (offset + size <= start + length).then_some((index, offset))
+                })?;
+
+        let (start, length) = self.free_ranges.remove(index);
+        let end = start + length;
+        let mut insert_at = index;
+        if offset > start {
+            self.free_ranges.insert(insert_at, (start, offset - start));
+            insert_at += 1;
+        }
+        if offset + size < end {
+            self.free_ranges
+                .insert(insert_at, (offset + size, end - offset - size));
+        }
+
+        self.used += size;
+        Some(offset)
+    }
+
+    /// Returns a previously allocated range to the free list.
+    pub fn free(&mut self, offset: u64, size: u64) {
+        let index = self
+            .free_ranges
+            .partition_point(|&(start, _)| start < offset);
+        self.free_ranges.insert(index, (offset, size));
+        self.used -= size;
+
+        // Coalesce with the following range, then the preceding one
+        if index + 1 < self.free_ranges.len() {
+            let (next_start, next_length) = self.free_ranges[index + 1];
+            if offset + size == next_start {
+                self.free_ranges[index].1 += next_length;
+                self.free_ranges.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let (previous_start, previous_length) = self.free_ranges[index - 1];
+            if previous_start + previous_length == offset {
+                self.free_ranges[index - 1].1 += self.free_ranges[index].1;
+                self.free_ranges.remove(index);
+            }
+        }
+    }
+
+    /// Returns the total size of the managed block in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the number of allocated bytes, excluding alignment padding.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Returns the size of the largest free range in bytes.
+    pub fn largest_free_range(&self) -> u64 {
+        self.free_ranges
+            .iter()
+            .map(|&(_, length)| length)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of separate free ranges.
+    #[cfg(test)]
+    pub fn free_range_count(&self) -> usize {
+        self.free_ranges.len()
+    }
+
+    /// Returns `true` if nothing is allocated from the block.
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.used == 0
+    }
+}
+
+/// A buffer sub-allocated from one of the `MemoryManager`'s heaps.
+// The range is only read by `free`, as the backend's buffers live as long as it does
+#[allow(dead_code)]
+pub struct Allocation {
+    pub buffer: Buffer,
+    heap_index: usize,
+    offset: u64,
+    size: u64,
+}
+
+/// Summary of the `MemoryManager`'s heap usage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryStats {
+    pub heap_count: usize,
+    pub reserved_bytes: u64,
+    pub used_bytes: u64,
+    pub budget_bytes: u64,
+    pub largest_free_range: u64,
+    /// Share of free memory outside the largest free range in each heap,
+    /// from 0 (no fragmentation) to 1.
+    pub fragmentation: f32,
+}
+
+struct HeapBlock {
+    heap: Heap,
+    allocator: FreeListAllocator,
+}
+
+/// Sub-allocates GPU buffers from large placement heaps.
+pub struct MemoryManager {
+    device: Device,
+    heaps: Vec<HeapBlock>,
+    heap_size: u64,
+    budget: u64,
+}
+
+impl MemoryManager {
+    /// Creates a new `MemoryManager`.
+    ///
+    /// The budget defaults to the device's recommended working set size.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device used to create heaps.
+    pub fn new(device: &Device) -> Self {
+        let budget = device.recommended_max_working_set_size();
+        debug!("Creating new MemoryManager with a budget of {budget} bytes");
+        MemoryManager {
+            device: device.clone(),
+            heaps: Vec::new(),
+            heap_size: DEFAULT_HEAP_SIZE,
+            budget,
+        }
+    }
+
+    /// Sets the maximum number of bytes the manager may reserve in heaps.
+    #[allow(dead_code)]
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+    }
+
+    /// Allocates a buffer of `length` bytes from a heap.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The size of the buffer in bytes.
+    /// * `label` - The debug label of the buffer.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Allocation` or `BackendError::OutOfMemory`.
+    pub fn allocate(&mut self, length: u64, label: &str) -> Result<Allocation, BackendError> {
+        let out_of_memory = || BackendError::OutOfMemory {
+            buffer: label.to_string(),
+            requested: length,
+        };
+        let size_and_align = self
+            .device
+            .heap_buffer_size_and_align(length.max(1), HEAP_RESOURCE_OPTIONS);
+        let (size, align) = (size_and_align.size, size_and_align.align);
+
+        let placement = self
+            .heaps
+            .iter_mut()
+            .enumerate()
+            .find_map(|(index, block)| block.allocator.allocate(size, align).map(|o| (index, o)));
+
+        let (heap_index, offset) = match placement {
+            Some(placement) => placement,
+            None => {
+                let heap_index = self.create_heap(size).ok_or_else(out_of_memory)?;
+                let offset = self.heaps[heap_index]
+                    .allocator
+                    .allocate(size, align)
+                    .ok_or_else(out_of_memory)?;
+                (heap_index, offset)
+            }
+        };
+
+        let block = &mut self.heaps[heap_index];
+        let Some(buffer) =
+            block
+                .heap
+                .new_buffer_with_offset(length.max(1), HEAP_RESOURCE_OPTIONS, offset)
+        else {
+            block.allocator.free(offset, size);
+            return Err(out_of_memory());
+        };
+        buffer.set_label(label);
+
+        trace!("Allocated {label} buffer: {size} bytes at offset {offset} in heap {heap_index}");
+        Ok(Allocation {
+            buffer,
+            heap_index,
+            offset,
+            size,
+        })
+    }
+
+    /// Returns an allocation's range to its heap.
+    #[allow(dead_code)]
+    pub fn free(&mut self, allocation: Allocation) {
+        trace!(
+            "Freeing {} bytes at offset {} in heap {}",
+            allocation.size,
+            allocation.offset,
+            allocation.heap_index
+        );
+        self.heaps[allocation.heap_index]
+            .allocator
+            .free(allocation.offset, allocation.size);
+    }
+
+    /// Creates a heap large enough for `min_size` bytes, respecting the budget.
+    ///
+    /// # Returns
+    ///
+    /// The index of the new heap, or `None` if it would exceed the budget.
+    fn create_heap(&mut self, min_size: u64) -> Option<usize> {
+        let size = self.heap_size.max(min_size);
+        let reserved = self.reserved_bytes();
+        if reserved + size > self.budget {
+            warn!(
+                "GPU memory budget exceeded: {} reserved + {} requested > {} budget",
+                reserved, size, self.budget
+            );
+            return None;
+        }
+
+        let descriptor = HeapDescriptor::new();
+        descriptor.set_heap_type(MTLHeapType::Placement);
+        descriptor.set_storage_mode(MTLStorageMode::Shared);
+        descriptor.set_hazard_tracking_mode(MTLHazardTrackingMode::Tracked);
+        descriptor.set_size(size);
+
+        let heap = self.device.new_heap(&descriptor);
+        heap.set_label(&format!("Heap {}", self.heaps.len()));
+        debug!(
+            "Created {size} byte heap ({} heaps total)",
+            self.heaps.len() + 1
+        );
+
+        self.heaps.push(HeapBlock {
+            heap,
+            allocator: FreeListAllocator::new(size),
+        });
+        Some(self.heaps.len() - 1)
+    }
+
+    fn reserved_bytes(&self) -> u64 {
+        self.heaps.iter().map(|block| block.allocator.size()).sum()
+    }
+
+    /// Returns current heap usage and fragmentation statistics.
+    pub fn stats(&self) -> MemoryStats {
+        let reserved_bytes = self.reserved_bytes();
+        let used_bytes = self.heaps.iter().map(|block| block.allocator.used()).sum();
+        let free_bytes = reserved_bytes - used_bytes;
+        let largest_free_ranges: u64 = self
+            .heaps
+            .iter()
+            .map(|block| block.allocator.largest_free_range())
+            .sum();
+
+        MemoryStats {
+            heap_count: self.heaps.len(),
+            reserved_bytes,
+            used_bytes,
+            budget_bytes: self.budget,
+            largest_free_range: self
+                .heaps
+                .iter()
+                .map(|block| block.allocator.largest_free_range())
+                .max()
+                .unwrap_or(0),
+            fragmentation: if free_bytes > 0 {
+                1.0 - largest_free_ranges as f32 / free_bytes as f32
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FreeListAllocator;
+
+    #[test]
+    fn test_free_list_allocate_aligned() {
+        let mut allocator = FreeListAllocator::new(1024);
+        assert_eq!(allocator.allocate(10, 1), Some(0));
+        assert_eq!(allocator.allocate(16, 256), Some(256));
+        assert_eq!(allocator.used(), 26);
+        // The padding before the aligned range stays free
+        assert_eq!(allocator.free_range_count(), 2);
+        assert_eq!(allocator.allocate(2048, 1), None);
+    }
+
+    #[test]
+    fn test_free_list_coalesces() {
+        let mut allocator = FreeListAllocator::new(300);
+        let a = allocator.allocate(100, 1).unwrap();
+        let b = allocator.allocate(100, 1).unwrap();
+        let c = allocator.allocate(100, 1).unwrap();
+        assert_eq!(allocator.largest_free_range(), 0);
+
+        allocator.free(a, 100);
+        allocator.free(c, 100);
+        assert_eq!(allocator.free_range_count(), 2);
+        assert_eq!(allocator.largest_free_range(), 100);
+
+        allocator.free(b, 100);
+        assert_eq!(allocator.free_range_count(), 1);
+        assert_eq!(allocator.largest_free_range(), 300);
+        assert!(allocator.is_empty());
+    }
+}
@@ -7,12 +7,34 @@
 //! Key components:
 //! - `backend`: Implements the core Metal backend functionality.
 //! - `buffer_management`: Handles creation and management of Metal buffers.
+//! - `frame_readback`: Copies presented frames back to the CPU for recording.
+//! - `gpu_culling`: Culls instanced draws in a compute kernel and builds the depth pyramid.
+//! - `material_table`: Encodes materials into an argument buffer for bindless access.
+//! - `memory_manager`: Sub-allocates buffers from large placement heaps.
+//! - `overlay`: Draws the 2D canvas over the resolved frame.
 //! - `pipeline`: Manages creation and caching of render pipeline states.
+//! - `post_process`: Resolves FXAA, temporal anti-aliasing, the sky, atmospheric scattering, lens flares and render scaling into the drawable.
+//! - `ray_tracing`: Builds acceleration structures and traces shadows where supported.
+//! - `shadow_map`: Draws cascaded shadow maps and darkens the scene with them.
+//! - `static_mesh`: Uploads the geometry of static meshes into private buffers through staging buffers.
 //! - `texture_manager`: Handles creation and management of Metal textures.
+//! - `texture_upload`: Uploads texture pixels through staging buffers on a loader thread and transfer queue.
+//! - `transparency`: Accumulates and composites weighted blended transparent draws.
 
 mod backend;
 mod buffer_manager;
+mod frame_readback;
+mod gpu_culling;
+mod material_table;
+mod memory_manager;
+mod overlay;
 mod pipeline;
+mod post_process;
+mod ray_tracing;
+mod shadow_map;
+mod static_mesh;
 mod texture_manager;
+mod texture_upload;
+mod transparency;
 
 pub use self::backend::MetalBackend;
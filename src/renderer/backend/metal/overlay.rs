@@ -0,0 +1,147 @@
+//! Metal canvas overlay module.
+//!
+//! This module draws the 2D canvas over the resolved frame. The canvas's
+//! triangles are in pixels and projected orthographically in the vertex
+//! shader, blended over the drawable by their alpha, one draw per run of
+//! triangles sharing a texture. The overlay is drawn after anti-aliasing and
+//! lens flares at the drawable's resolution, so it stays sharp at any render
+//! scale and never enters the temporal history. Vertices with an occlusion
+//! point fade where the frame's depth is in front of it, like lens flares.
+
+use super::buffer_manager::as_bytes;
+use super::pipeline::{load_metal_shader_library, COLOR_PIXEL_FORMAT};
+use super::post_process::{create_pipeline, Blend};
+use super::texture_manager::TextureManager;
+use crate::renderer::{canvas::CanvasFrame, error::PipelineError};
+use log::warn;
+use metal::{
+    CommandBufferRef, Device, MTLIndexType, MTLLoadAction, MTLPrimitiveType, MTLResourceOptions,
+    MTLStoreAction, RenderPassDescriptor, RenderPipelineState, TextureRef,
+};
+
+/// Draws the canvas over the drawable.
+pub struct Overlay {
+    device: Device,
+    pipeline: RenderPipelineState,
+    frame: Option<CanvasFrame>,
+}
+
+impl Overlay {
+    /// Creates a new `Overlay` without a canvas.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Overlay` or a `PipelineError`.
+    pub fn new(device: &Device) -> Result<Self, PipelineError> {
+        let library = load_metal_shader_library(device)?;
+        Ok(Overlay {
+            device: device.clone(),
+            pipeline: create_pipeline(
+                device,
+                &library,
+                ("overlay_vertex", "overlay_fragment"),
+                &[COLOR_PIXEL_FORMAT],
+                Blend::Over,
+            )?,
+            frame: None,
+        })
+    }
+
+    /// Sets the canvas drawn over the current frame, or `None` for no overlay.
+    pub fn set_frame(&mut self, frame: Option<&CanvasFrame>) {
+        match (frame, &mut self.frame) {
+            (Some(frame), Some(current)) => current.clone_from(frame),
+            (frame, current) => *current = frame.cloned(),
+        }
+    }
+
+    /// Returns `true` if a canvas is drawn over every frame.
+    pub fn is_enabled(&self) -> bool {
+        self.frame.is_some()
+    }
+
+    /// Draws the canvas over the resolved target.
+    ///
+    /// Vertices and indices are copied into new buffers every frame, which
+    /// Metal keeps alive until the command buffer completes.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer presenting the target.
+    /// * `target` - The drawable texture to draw over.
+    /// * `depth` - The depth texture of the frame, which occlusion points are tested against.
+    /// * `texture_manager` - The textures of textured batches.
+    pub fn encode(
+        &self,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+        depth: &TextureRef,
+        texture_manager: &TextureManager,
+    ) {
+        let Some(frame) = &self.frame else {
+            return;
+        };
+        if frame.indices.is_empty() {
+            return;
+        }
+
+        let new_buffer = |bytes: &[u8]| {
+            self.device.new_buffer_with_data(
+                bytes.as_ptr() as *const std::ffi::c_void,
+                bytes.len() as u64,
+                MTLResourceOptions::StorageModeShared,
+            )
+        };
+        let vertex_buffer = new_buffer(as_bytes(&frame.vertices));
+        let index_buffer = new_buffer(as_bytes(&frame.indices));
+
+        let descriptor = RenderPassDescriptor::new();
+        let attachment = descriptor.color_attachments().object_at(0).unwrap();
+        attachment.set_texture(Some(target));
+        attachment.set_load_action(MTLLoadAction::Load);
+        attachment.set_store_action(MTLStoreAction::Store);
+
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
+        encoder.set_render_pipeline_state(&self.pipeline);
+        encoder.set_vertex_buffer(0, Some(&vertex_buffer), 0);
+        encoder.set_vertex_texture(0, Some(depth));
+        let size = as_bytes(&frame.size);
+        encoder.set_vertex_bytes(
+            1,
+            size.len() as u64,
+            size.as_ptr() as *const std::ffi::c_void,
+        );
+
+        for batch in &frame.batches {
+            let texture = match batch.texture {
+                Some(id) => match texture_manager.get_texture(id) {
+                    Some(texture) => Some(texture),
+                    None => {
+                        warn!("Skipping canvas batch with missing texture {:?}", id);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let textured = texture.is_some() as u32;
+            encoder.set_fragment_texture(0, texture.map(|texture| texture as &TextureRef));
+            encoder.set_fragment_bytes(
+                0,
+                std::mem::size_of::<u32>() as u64,
+                &textured as *const u32 as *const std::ffi::c_void,
+            );
+            encoder.draw_indexed_primitives(
+                MTLPrimitiveType::Triangle,
+                batch.index_count as u64,
+                MTLIndexType::UInt32,
+                &index_buffer,
+                (batch.index_offset * std::mem::size_of::<u32>()) as u64,
+            );
+        }
+        encoder.end_encoding();
+    }
+}
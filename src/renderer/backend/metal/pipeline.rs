@@ -2,23 +2,523 @@
 //!
 //! This module provides functionality to create and manage Metal rendering pipelines,
 //! including pipeline state caching and default pipeline descriptor creation.
+//!
+//! Shaders are specialized into permutations by `ShaderFeatures`, a set of
+//! features each compiled in through a Metal function constant. The
+//! `ShaderLibrary` compiles the functions of each permutation on first use,
+//! and pipeline states are cached per variant, feature set and vertex layout.
+//! Materials' specialized parameters are compiled in through further function
+//! constants, so materials with distinct specialized values get their own
+//! permutations.
 
-use crate::renderer::RendererError;
-use log::{debug, error, info, trace};
+use super::transparency::{ACCUMULATION_PIXEL_FORMAT, REVEALAGE_PIXEL_FORMAT};
+use crate::renderer::{
+    common::BackendDrawCommand,
+    error::PipelineError,
+    material_manager::MaterialConstants,
+    render_state::StencilState,
+    vertex_layout::{VertexFormat, VertexLayout, VertexSemantic},
+};
+use log::{debug, error, info, trace, warn};
 use metal::{
-    DepthStencilDescriptor, DepthStencilState, Device, MTLDataType, MTLPixelFormat,
-    MTLVertexFormat, RenderPipelineDescriptor, RenderPipelineState,
+    DepthStencilDescriptor, DepthStencilState, Device, Function, FunctionConstantValues, Library,
+    MTLBlendFactor, MTLBlendOperation, MTLDataType, MTLPixelFormat, MTLVertexFormat,
+    RenderPipelineColorAttachmentDescriptorRef, RenderPipelineDescriptor, RenderPipelineState,
+    StencilDescriptor,
 };
-use std::ffi::c_void;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    fmt,
+    ops::BitOr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+/// The format of the drawable and the scene texture.
+///
+/// Shaders output linear color, which the hardware encodes to sRGB on write
+/// and decodes on read, so blending and filtering happen in linear space.
+pub const COLOR_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm_sRGB;
+
+/// The function constant index of the specialized base color, following the shader features.
+const SPECIALIZED_BASE_COLOR_INDEX: u64 = 6;
+/// The function constant index of the specialized reflectivity.
+const SPECIALIZED_REFLECTIVITY_INDEX: u64 = 7;
+
+/// The number of specialized material permutations past which a warning is
+/// logged, as specialized values likely change too often.
+const SPECIALIZED_PERMUTATION_WARNING: usize = 64;
+
+/// A set of shader features, each compiled into specialized shader functions.
+///
+/// The bit at index `i` sets `function_constant(i)` of the default shaders,
+/// declared in `shader_features.h`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures(pub u32);
+
+impl ShaderFeatures {
+    pub const NONE: ShaderFeatures = ShaderFeatures(0);
+    /// Reads per-instance model matrices and colors from the instance buffer.
+    pub const INSTANCED: ShaderFeatures = ShaderFeatures(1 << 0);
+    /// Reads vertex colors.
+    pub const VERTEX_COLOR: ShaderFeatures = ShaderFeatures(1 << 1);
+    /// Reads vertex normals.
+    pub const VERTEX_NORMAL: ShaderFeatures = ShaderFeatures(1 << 2);
+    /// Reads texture coordinates and samples the base color texture.
+    pub const TEXTURED: ShaderFeatures = ShaderFeatures(1 << 3);
+    /// Perturbs normals by a normal map. No vertex layout carries tangents
+    /// yet, so no permutation sets it.
+    #[allow(dead_code)]
+    pub const NORMAL_MAP: ShaderFeatures = ShaderFeatures(1 << 4);
+    /// Blends vertices by joint matrices. No vertex layout carries joints
+    /// yet, so no permutation sets it.
+    #[allow(dead_code)]
+    pub const SKINNED: ShaderFeatures = ShaderFeatures(1 << 5);
+
+    /// The name of each feature, by bit index.
+    const NAMES: [&'static str; 6] = [
+        "INSTANCED",
+        "VERTEX_COLOR",
+        "VERTEX_NORMAL",
+        "TEXTURED",
+        "NORMAL_MAP",
+        "SKINNED",
+    ];
+
+    /// Selects the features of a variant drawing vertices of a layout.
+    pub fn for_pipeline(variant: PipelineVariant, layout: &VertexLayout) -> Self {
+        let mut features = ShaderFeatures::NONE;
+        for (enabled, feature) in [
+            (variant.is_instanced(), ShaderFeatures::INSTANCED),
+            (
+                layout.has(VertexSemantic::Color),
+                ShaderFeatures::VERTEX_COLOR,
+            ),
+            (
+                layout.has(VertexSemantic::Normal),
+                ShaderFeatures::VERTEX_NORMAL,
+            ),
+            (
+                layout.has(VertexSemantic::TexCoord),
+                ShaderFeatures::TEXTURED,
+            ),
+        ] {
+            if enabled {
+                features = features | feature;
+            }
+        }
+        features
+    }
+
+    /// Returns `true` if every feature of `other` is in this set.
+    #[cfg(test)]
+    pub fn contains(self, other: ShaderFeatures) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the function constant values specializing shaders to these
+    /// features and a material's specialized parameters.
+    ///
+    /// Parameters not specialized are left undefined, so the shaders read
+    /// them from the material table.
+    fn function_constants(self, constants: &MaterialConstants) -> FunctionConstantValues {
+        let function_constants = FunctionConstantValues::new();
+        if let Some(base_color) = &constants.base_color {
+            function_constants.set_constant_value_at_index(
+                base_color.as_ptr() as *const c_void,
+                MTLDataType::Float4,
+                SPECIALIZED_BASE_COLOR_INDEX,
+            );
+        }
+        if let Some(reflectivity) = &constants.reflectivity {
+            function_constants.set_constant_value_at_index(
+                reflectivity as *const f32 as *const c_void,
+                MTLDataType::Float,
+                SPECIALIZED_REFLECTIVITY_INDEX,
+            );
+        }
+        for index in 0..Self::NAMES.len() {
+            let value = self.0 & (1 << index) != 0;
+            function_constants.set_constant_value_at_index(
+                &value as *const bool as *const c_void,
+                MTLDataType::Bool,
+                index as u64,
+            );
+        }
+        function_constants
+    }
+}
+
+impl BitOr for ShaderFeatures {
+    type Output = ShaderFeatures;
+
+    fn bitor(self, other: ShaderFeatures) -> ShaderFeatures {
+        ShaderFeatures(self.0 | other.0)
+    }
+}
+
+impl fmt::Debug for ShaderFeatures {
+    /// Formats the features by name, e.g. `ShaderFeatures(INSTANCED | TEXTURED)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = Self::NAMES
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.0 & (1 << index) != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            write!(f, "ShaderFeatures(NONE)")
+        } else {
+            write!(f, "ShaderFeatures({})", names.join(" | "))
+        }
+    }
+}
+
+/// The shader library and the functions specialized from it.
+///
+/// Each function is compiled on first use for each set of shader features
+/// and material constants and cached, so every permutation compiles once.
+pub struct ShaderLibrary {
+    library: Library,
+    functions: HashMap<(&'static str, ShaderFeatures, MaterialConstants), Function>,
+}
+
+impl ShaderLibrary {
+    /// Loads the shader library compiled by the build script.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - A reference to the Metal device.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `ShaderLibrary` or a `PipelineError`.
+    pub fn load(device: &Device) -> Result<Self, PipelineError> {
+        Ok(ShaderLibrary {
+            library: load_metal_shader_library(device)?,
+            functions: HashMap::new(),
+        })
+    }
+
+    /// Returns a function specialized to a set of shader features, compiling it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the function in the library.
+    /// * `features` - The features to specialize the function to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Function` or a `PipelineError`.
+    pub fn function(
+        &mut self,
+        name: &'static str,
+        features: ShaderFeatures,
+    ) -> Result<Function, PipelineError> {
+        self.specialized_function(name, features, MaterialConstants::default())
+    }
+
+    /// Returns a function specialized to a set of shader features and a
+    /// material's specialized parameters, compiling it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the function in the library.
+    /// * `features` - The features to specialize the function to.
+    /// * `constants` - The material parameter values to compile in.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Function` or a `PipelineError`.
+    pub fn specialized_function(
+        &mut self,
+        name: &'static str,
+        features: ShaderFeatures,
+        constants: MaterialConstants,
+    ) -> Result<Function, PipelineError> {
+        if let Some(function) = self.functions.get(&(name, features, constants)) {
+            return Ok(function.clone());
+        }
+        debug!("Compiling {name} for {features:?}");
+        let function = self
+            .library
+            .get_function(name, Some(features.function_constants(&constants)))
+            .map_err(|e| {
+                error!("Failed to specialize {name}: {e}");
+                PipelineError::FunctionNotFound {
+                    function: name.to_string(),
+                    pipeline: format!("{features:?}"),
+                }
+            })?;
+        self.functions
+            .insert((name, features, constants), function.clone());
+        Ok(function)
+    }
+}
+
+/// Identifies a specialization of the default shaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PipelineVariant {
+    /// Reads the model matrix from the uniform buffer.
+    Default,
+    /// Reads per-instance model matrices and colors from the instance buffer.
+    Instanced,
+    /// Fills the geometry with the outline color.
+    Outline,
+    /// Fills instanced geometry with the outline color.
+    OutlineInstanced,
+    /// Adds a constant tint per fragment to visualize overdraw.
+    Overdraw,
+    /// Adds a constant tint per fragment of instanced geometry.
+    OverdrawInstanced,
+    /// Blends the shaded geometry over the scene by its alpha.
+    Transparent,
+    /// Blends shaded instanced geometry over the scene by its alpha.
+    TransparentInstanced,
+    /// Adds the shaded geometry to the scene, scaled by its alpha.
+    Additive,
+    /// Adds shaded instanced geometry to the scene, scaled by its alpha.
+    AdditiveInstanced,
+    /// Accumulates the shaded geometry into the weighted blended transparency targets.
+    WeightedBlended,
+    /// Accumulates shaded instanced geometry into the weighted blended transparency targets.
+    WeightedBlendedInstanced,
+    /// Writes only the depth of position-only geometry, for the depth pre-pass.
+    DepthOnly,
+    /// Writes only the depth of position-only instanced geometry.
+    DepthOnlyInstanced,
+}
+
+impl PipelineVariant {
+    /// All variants created at backend initialization.
+    ///
+    /// Depth-only variants read position-only vertices, so they are created on first use.
+    pub const ALL: [PipelineVariant; 12] = [
+        PipelineVariant::Default,
+        PipelineVariant::Instanced,
+        PipelineVariant::Outline,
+        PipelineVariant::OutlineInstanced,
+        PipelineVariant::Overdraw,
+        PipelineVariant::OverdrawInstanced,
+        PipelineVariant::Transparent,
+        PipelineVariant::TransparentInstanced,
+        PipelineVariant::Additive,
+        PipelineVariant::AdditiveInstanced,
+        PipelineVariant::WeightedBlended,
+        PipelineVariant::WeightedBlendedInstanced,
+    ];
+
+    /// Selects the variant required to execute a draw command.
+    pub fn for_draw_command(draw_command: &BackendDrawCommand) -> Self {
+        if draw_command.is_instanced() {
+            PipelineVariant::Instanced
+        } else {
+            PipelineVariant::Default
+        }
+    }
+
+    /// Returns the variant that draws the same geometry as a solid outline.
+    pub fn outline(self) -> Self {
+        if self.is_instanced() {
+            PipelineVariant::OutlineInstanced
+        } else {
+            PipelineVariant::Outline
+        }
+    }
+
+    /// Returns the variant that draws the same geometry as an overdraw tint.
+    pub fn overdraw(self) -> Self {
+        if self.is_instanced() {
+            PipelineVariant::OverdrawInstanced
+        } else {
+            PipelineVariant::Overdraw
+        }
+    }
+
+    /// Returns the variant that draws only the depth of the same geometry.
+    pub fn depth_only(self) -> Self {
+        if self.is_instanced() {
+            PipelineVariant::DepthOnlyInstanced
+        } else {
+            PipelineVariant::DepthOnly
+        }
+    }
+
+    /// Returns the variant that draws the same geometry transparently.
+    ///
+    /// # Arguments
+    ///
+    /// * `weighted_blended` - Whether to accumulate into the weighted blended
+    ///   transparency targets rather than blend over the scene.
+    pub fn transparent(self, weighted_blended: bool) -> Self {
+        match (self.is_instanced(), weighted_blended) {
+            (false, false) => PipelineVariant::Transparent,
+            (true, false) => PipelineVariant::TransparentInstanced,
+            (false, true) => PipelineVariant::WeightedBlended,
+            (true, true) => PipelineVariant::WeightedBlendedInstanced,
+        }
+    }
+
+    /// Returns the variant that adds the same geometry to the scene, e.g. for glowing particles.
+    pub fn additive(self) -> Self {
+        if self.is_instanced() {
+            PipelineVariant::AdditiveInstanced
+        } else {
+            PipelineVariant::Additive
+        }
+    }
+
+    fn is_instanced(&self) -> bool {
+        matches!(
+            self,
+            PipelineVariant::Instanced
+                | PipelineVariant::OutlineInstanced
+                | PipelineVariant::OverdrawInstanced
+                | PipelineVariant::TransparentInstanced
+                | PipelineVariant::AdditiveInstanced
+                | PipelineVariant::WeightedBlendedInstanced
+                | PipelineVariant::DepthOnlyInstanced
+        )
+    }
+
+    fn is_overdraw(&self) -> bool {
+        matches!(
+            self,
+            PipelineVariant::Overdraw | PipelineVariant::OverdrawInstanced
+        )
+    }
+
+    fn is_additive(&self) -> bool {
+        matches!(
+            self,
+            PipelineVariant::Additive | PipelineVariant::AdditiveInstanced
+        )
+    }
+
+    fn is_transparent(&self) -> bool {
+        matches!(
+            self,
+            PipelineVariant::Transparent | PipelineVariant::TransparentInstanced
+        )
+    }
+
+    fn is_weighted_blended(&self) -> bool {
+        matches!(
+            self,
+            PipelineVariant::WeightedBlended | PipelineVariant::WeightedBlendedInstanced
+        )
+    }
+
+    /// Returns `true` if the variant shades with the material, so it is
+    /// specialized to the material's constants.
+    fn reads_material(&self) -> bool {
+        matches!(
+            self.fragment_function_name(),
+            Some("fragment_main" | "fragment_weighted_blended")
+        )
+    }
+
+    /// Returns the name of the variant's fragment function, or `None` if it writes no color.
+    fn fragment_function_name(&self) -> Option<&'static str> {
+        match self {
+            PipelineVariant::Default
+            | PipelineVariant::Instanced
+            | PipelineVariant::Transparent
+            | PipelineVariant::TransparentInstanced
+            | PipelineVariant::Additive
+            | PipelineVariant::AdditiveInstanced => Some("fragment_main"),
+            PipelineVariant::Outline | PipelineVariant::OutlineInstanced => {
+                Some("fragment_outline")
+            }
+            PipelineVariant::Overdraw | PipelineVariant::OverdrawInstanced => {
+                Some("fragment_overdraw")
+            }
+            PipelineVariant::WeightedBlended | PipelineVariant::WeightedBlendedInstanced => {
+                Some("fragment_weighted_blended")
+            }
+            PipelineVariant::DepthOnly | PipelineVariant::DepthOnlyInstanced => None,
+        }
+    }
+}
+
+/// The variant, shader features, vertex layout and material constants a
+/// pipeline state is cached under.
+type PipelineKey = (
+    PipelineVariant,
+    ShaderFeatures,
+    VertexLayout,
+    MaterialConstants,
+);
+
+/// A pipeline state compiled in the background, or the error that stopped it.
+type CompiledPipeline = (PipelineKey, Result<RenderPipelineState, PipelineError>);
+
+/// The vertex buffer index fallback pipelines read the vertex stride from.
+pub const FALLBACK_STRIDE_INDEX: u64 = 6;
+
+/// A pipeline state to draw with.
+pub struct CachedPipeline<'a> {
+    pub state: &'a RenderPipelineState,
+    /// The vertex stride a fallback pipeline reads positions with, or `None`
+    /// if the state is the draw's own permutation.
+    pub fallback_stride: Option<u32>,
+}
+
+/// Compiles pipeline states on a background thread.
+struct PipelineCompiler {
+    jobs: Sender<PipelineKey>,
+    compiled: Receiver<CompiledPipeline>,
+}
+
+impl PipelineCompiler {
+    /// Starts the compiler thread, which loads its own shader library on its first job.
+    fn spawn(device: &Device) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<PipelineKey>();
+        let (compiled_sender, compiled) = mpsc::channel();
+        let device = device.clone();
+        thread::spawn(move || {
+            let mut library = None;
+            for key in job_receiver {
+                let result = compile_pipeline_state(&device, &mut library, key);
+                if compiled_sender.send((key, result)).is_err() {
+                    break;
+                }
+            }
+        });
+        PipelineCompiler { jobs, compiled }
+    }
+}
 
 /// Manages the caching of Metal render pipeline states.
+///
+/// Pipeline states are cached per variant, shader features, vertex layout
+/// and material constants. The default layout's states are created up
+/// front, and other permutations' on first use. With asynchronous compilation, a new
+/// permutation compiles on a background thread while its draws use the
+/// variant's fallback pipeline, which reads only positions from any vertex
+/// layout, so first uses never stall the frame. Permutations specialized to
+/// material constants draw with the unspecialized permutation instead, which
+/// reads the same values from the material table.
 pub struct RenderPipelineCache {
     device: Device,
-    pipeline_state: Option<RenderPipelineState>,
+    /// The shader library, loaded when a pipeline state is first created on demand.
+    library: Option<ShaderLibrary>,
+    pipeline_states: HashMap<PipelineKey, RenderPipelineState>,
+    /// The layout-independent pipeline of each variant, drawing while permutations compile.
+    fallbacks: HashMap<PipelineVariant, RenderPipelineState>,
+    /// The background compiler, or `None` to compile permutations on first use.
+    compiler: Option<PipelineCompiler>,
+    /// Permutations queued on the compiler.
+    pending: HashSet<PipelineKey>,
+    /// Permutations that failed to compile in the background, drawn with their fallback.
+    failed: HashSet<PipelineKey>,
+    /// The distinct material constants permutations have been specialized to.
+    specializations: HashSet<MaterialConstants>,
 }
 
 impl RenderPipelineCache {
-    /// Creates a new `RenderPipelineCache` instance.
+    /// Creates a new `RenderPipelineCache` instance, compiling asynchronously.
     ///
     /// # Arguments
     ///
@@ -26,52 +526,308 @@ impl RenderPipelineCache {
     ///
     /// # Returns
     ///
-    /// A `Result` containing the new `RenderPipelineCache` or a `RendererError`.
-    pub fn new(device: &Device) -> Result<Self, RendererError> {
+    /// A `Result` containing the new `RenderPipelineCache` or a `PipelineError`.
+    pub fn new(device: &Device) -> Result<Self, PipelineError> {
         Ok(RenderPipelineCache {
             device: device.clone(),
-            pipeline_state: None,
+            library: None,
+            pipeline_states: HashMap::new(),
+            fallbacks: HashMap::new(),
+            compiler: Some(PipelineCompiler::spawn(device)),
+            pending: HashSet::new(),
+            failed: HashSet::new(),
+            specializations: HashSet::new(),
         })
     }
 
-    /// Creates and caches a new pipeline state.
+    /// Enables or disables compiling new permutations in the background.
+    ///
+    /// Disabling waits for the permutations already queued.
+    pub fn set_asynchronous(&mut self, enabled: bool) {
+        if enabled == self.compiler.is_some() {
+            return;
+        }
+        if enabled {
+            self.compiler = Some(PipelineCompiler::spawn(&self.device));
+        } else {
+            self.receive_compiled(true);
+            self.compiler = None;
+        }
+        debug!("Asynchronous pipeline compilation set to: {enabled}");
+    }
+
+    /// Returns the number of permutations still compiling in the background.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Creates and caches a new pipeline state for a variant.
     ///
     /// # Arguments
     ///
+    /// * `variant` - The variant the pipeline state is cached under.
+    /// * `layout` - The vertex layout the pipeline state is cached under.
     /// * `descriptor` - A reference to the `RenderPipelineDescriptor`.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or a `RendererError`.
+    /// A `Result` indicating success or a `PipelineError`.
     pub fn create_pipeline_state(
         &mut self,
+        variant: PipelineVariant,
+        layout: &VertexLayout,
         descriptor: &RenderPipelineDescriptor,
-    ) -> Result<(), RendererError> {
-        debug!("Creating new pipeline state");
-        let pipeline_state = self
-            .device
-            .new_render_pipeline_state(descriptor)
-            .map_err(|e| {
-                error!("Failed to create pipeline state: {e}");
-                RendererError::PipelineCreationFailed(e.to_string())
-            })?;
-
-        self.pipeline_state = Some(pipeline_state);
+    ) -> Result<(), PipelineError> {
+        debug!("Creating new pipeline state for {:?} variant", variant);
+        let pipeline_state = new_pipeline_state(&self.device, variant, descriptor)?;
+        let features = ShaderFeatures::for_pipeline(variant, layout);
+        self.pipeline_states.insert(
+            (variant, features, *layout, MaterialConstants::default()),
+            pipeline_state,
+        );
         info!("New pipeline state created and cached");
         Ok(())
     }
 
-    /// Retrieves the cached pipeline state.
+    /// Compiles the permutations of every variant drawing vertices of the
+    /// given layouts, in the background while compiling asynchronously.
+    ///
+    /// # Arguments
+    ///
+    /// * `layouts` - The vertex layouts of the meshes to be drawn.
     ///
     /// # Returns
     ///
-    /// An `Option` containing a reference to the `RenderPipelineState` if available.
-    pub fn get_pipeline_state(&self) -> Option<&RenderPipelineState> {
-        self.pipeline_state.as_ref()
+    /// A `Result` indicating success or the `PipelineError` of a synchronous compile.
+    pub fn warm_up(&mut self, layouts: &[VertexLayout]) -> Result<(), PipelineError> {
+        let mut keys: Vec<_> = layouts
+            .iter()
+            .flat_map(|layout| PipelineVariant::ALL.map(|variant| (variant, *layout)))
+            .collect();
+        // Depth-only draws read position-only vertices, whatever the mesh's layout
+        for variant in [
+            PipelineVariant::DepthOnly,
+            PipelineVariant::DepthOnlyInstanced,
+        ] {
+            keys.push((variant, VertexLayout::position()));
+        }
+        for (variant, layout) in keys {
+            let features = ShaderFeatures::for_pipeline(variant, &layout);
+            self.request((variant, features, layout, MaterialConstants::default()))?;
+        }
+        info!(
+            "Warming up pipelines for {} vertex layouts, {} compiling in the background",
+            layouts.len(),
+            self.pending.len()
+        );
+        Ok(())
     }
+
+    /// Retrieves the pipeline state for a variant, vertex layout and material constants.
+    ///
+    /// A permutation not yet compiled is compiled on the spot, or with
+    /// asynchronous compilation queued, drawing with the unspecialized
+    /// permutation or else the variant's fallback pipeline until it is ready.
+    ///
+    /// # Arguments
+    ///
+    /// * `variant` - The variant to look up.
+    /// * `layout` - The vertex layout of the draw.
+    /// * `constants` - The specialized parameters of the draw's material,
+    ///   ignored by variants that don't shade with the material.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `CachedPipeline` to draw with or a `PipelineError`.
+    pub fn get_pipeline_state(
+        &mut self,
+        variant: PipelineVariant,
+        layout: &VertexLayout,
+        constants: MaterialConstants,
+    ) -> Result<CachedPipeline<'_>, PipelineError> {
+        let features = ShaderFeatures::for_pipeline(variant, layout);
+        let unspecialized = (variant, features, *layout, MaterialConstants::default());
+        let mut keys = vec![unspecialized];
+        if variant.reads_material() && !constants.is_empty() {
+            keys.insert(0, (variant, features, *layout, constants));
+        }
+        if !self.pipeline_states.contains_key(&keys[0]) {
+            self.receive_compiled(false);
+            for key in &keys {
+                self.request(*key)?;
+            }
+        }
+        if let Some(key) = keys
+            .iter()
+            .find(|key| self.pipeline_states.contains_key(key))
+        {
+            return Ok(CachedPipeline {
+                state: &self.pipeline_states[key],
+                fallback_stride: None,
+            });
+        }
+
+        if !self.fallbacks.contains_key(&variant) {
+            debug!("Creating fallback pipeline state for {variant:?} variant");
+            let library = match &mut self.library {
+                Some(library) => library,
+                None => self.library.insert(ShaderLibrary::load(&self.device)?),
+            };
+            let descriptor = create_fallback_pipeline_descriptor(library, variant)?;
+            let state = new_pipeline_state(&self.device, variant, &descriptor)?;
+            self.fallbacks.insert(variant, state);
+        }
+        Ok(CachedPipeline {
+            state: &self.fallbacks[&variant],
+            fallback_stride: Some(layout.stride() as u32),
+        })
+    }
+
+    /// Compiles a permutation, or queues it on the background compiler.
+    fn request(&mut self, key: PipelineKey) -> Result<(), PipelineError> {
+        if self.pipeline_states.contains_key(&key)
+            || self.pending.contains(&key)
+            || self.failed.contains(&key)
+        {
+            return Ok(());
+        }
+        let (variant, _, layout, constants) = key;
+        if !constants.is_empty()
+            && self.specializations.insert(constants)
+            && self.specializations.len() == SPECIALIZED_PERMUTATION_WARNING
+        {
+            warn!(
+                "Materials have been specialized to {} distinct values; specialize only \
+                 parameters that rarely change",
+                SPECIALIZED_PERMUTATION_WARNING
+            );
+        }
+        if let Some(compiler) = &self.compiler {
+            if compiler.jobs.send(key).is_ok() {
+                debug!("Queued {variant:?} pipeline state for vertex layout {layout:?}");
+                self.pending.insert(key);
+                return Ok(());
+            }
+            warn!("The pipeline compiler thread has stopped, compiling on first use");
+            self.compiler = None;
+        }
+        debug!("Creating {variant:?} pipeline state for vertex layout {layout:?}");
+        let state = compile_pipeline_state(&self.device, &mut self.library, key)?;
+        self.pipeline_states.insert(key, state);
+        Ok(())
+    }
+
+    /// Caches the permutations the background compiler has finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - Whether to wait for every queued permutation.
+    fn receive_compiled(&mut self, wait: bool) {
+        let Some(compiler) = &self.compiler else {
+            return;
+        };
+        while !self.pending.is_empty() {
+            let received = if wait {
+                compiler.compiled.recv().ok()
+            } else {
+                compiler.compiled.try_recv().ok()
+            };
+            let Some((key, result)) = received else {
+                break;
+            };
+            self.pending.remove(&key);
+            match result {
+                Ok(state) => {
+                    trace!("Pipeline state compiled in the background: {:?}", key.0);
+                    self.pipeline_states.insert(key, state);
+                }
+                Err(e) => {
+                    error!("Failed to compile {:?} pipeline state: {e}", key.0);
+                    self.failed.insert(key);
+                }
+            }
+        }
+    }
+}
+
+/// Creates a pipeline state from a descriptor.
+fn new_pipeline_state(
+    device: &Device,
+    variant: PipelineVariant,
+    descriptor: &RenderPipelineDescriptor,
+) -> Result<RenderPipelineState, PipelineError> {
+    device.new_render_pipeline_state(descriptor).map_err(|e| {
+        error!("Failed to create pipeline state: {e}");
+        PipelineError::CreationFailed {
+            pipeline: format!("{variant:?}"),
+            message: e.to_string(),
+        }
+    })
 }
 
-/// Creates a default render pipeline descriptor and a depth stencil state.
+/// Compiles the pipeline state of a permutation, loading the shader library if needed.
+fn compile_pipeline_state(
+    device: &Device,
+    library: &mut Option<ShaderLibrary>,
+    (variant, _, layout, constants): PipelineKey,
+) -> Result<RenderPipelineState, PipelineError> {
+    let library = match library {
+        Some(library) => library,
+        None => library.insert(ShaderLibrary::load(device)?),
+    };
+    let descriptor = create_variant_pipeline_descriptor(library, variant, &layout, constants)?;
+    new_pipeline_state(device, variant, &descriptor)
+}
+
+/// Manages the caching of Metal depth stencil states.
+///
+/// States are created on first use for each combination of depth test, depth
+/// comparison, depth write and stencil operations, so per-draw render state
+/// changes never allocate after warm-up.
+pub struct DepthStencilCache {
+    device: Device,
+    states: HashMap<(bool, bool, bool, Option<StencilState>), DepthStencilState>,
+}
+
+impl DepthStencilCache {
+    /// Creates a new, empty `DepthStencilCache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - A reference to the Metal device.
+    pub fn new(device: &Device) -> Self {
+        DepthStencilCache {
+            device: device.clone(),
+            states: HashMap::new(),
+        }
+    }
+
+    /// Retrieves the depth stencil state for a depth configuration, creating it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth_test` - Whether fragments are tested against the depth buffer.
+    /// * `accept_equal` - Whether fragments at the stored depth pass the test,
+    ///   as needed after a depth pre-pass wrote the same depth.
+    /// * `depth_write` - Whether fragments write to the depth buffer.
+    /// * `stencil` - The stencil operations, or `None` to disable the stencil test.
+    pub fn get(
+        &mut self,
+        depth_test: bool,
+        accept_equal: bool,
+        depth_write: bool,
+        stencil: Option<StencilState>,
+    ) -> &DepthStencilState {
+        let device = &self.device;
+        self.states
+            .entry((depth_test, accept_equal, depth_write, stencil))
+            .or_insert_with(|| {
+                create_depth_stencil_state(device, depth_test, accept_equal, depth_write, stencil)
+            })
+    }
+}
+
+/// Creates the default render pipeline descriptors for every variant.
 ///
 /// # Arguments
 ///
@@ -79,172 +835,403 @@ impl RenderPipelineCache {
 ///
 /// # Returns
 ///
-/// A `Result` containing a tuple of `(RenderPipelineDescriptor, DepthStencilState)` or a `RendererError`.
-pub fn create_default_pipeline_descriptor(
+/// A `Result` containing the descriptors keyed by variant, or a `PipelineError`.
+pub fn create_default_pipeline_descriptors(
     device: &Device,
-) -> Result<(RenderPipelineDescriptor, DepthStencilState), RendererError> {
-    debug!("Creating default pipeline descriptor");
+) -> Result<Vec<(PipelineVariant, RenderPipelineDescriptor)>, PipelineError> {
+    debug!("Creating default pipeline descriptors");
 
-    let library = load_metal_shader_library(device)?;
-    let (vertex_function, fragment_function) = create_shader_functions(&library)?;
-    let pipeline_descriptor = create_pipeline_descriptor(&vertex_function, &fragment_function);
-    let depth_stencil_state = create_depth_stencil_state(device);
+    let mut library = ShaderLibrary::load(device)?;
+    let mut descriptors = Vec::with_capacity(PipelineVariant::ALL.len());
 
-    setup_vertex_descriptor(&pipeline_descriptor);
+    for variant in PipelineVariant::ALL {
+        let pipeline_descriptor = create_variant_pipeline_descriptor(
+            &mut library,
+            variant,
+            &VertexLayout::default(),
+            MaterialConstants::default(),
+        )?;
+        descriptors.push((variant, pipeline_descriptor));
+    }
+
+    info!("Default pipeline descriptors created");
+    Ok(descriptors)
+}
 
-    // Create the render pipeline state
-    info!("Render pipeline state created");
-    Ok((pipeline_descriptor, depth_stencil_state))
+/// Creates the render pipeline descriptor of a variant drawing vertices of a
+/// layout with a material's specialized parameters.
+fn create_variant_pipeline_descriptor(
+    library: &mut ShaderLibrary,
+    variant: PipelineVariant,
+    layout: &VertexLayout,
+    constants: MaterialConstants,
+) -> Result<RenderPipelineDescriptor, PipelineError> {
+    let (vertex_function, fragment_function) =
+        create_shader_functions(library, variant, layout, constants)?;
+    let pipeline_descriptor =
+        create_pipeline_descriptor(&vertex_function, fragment_function.as_ref(), variant);
+    setup_vertex_descriptor(&pipeline_descriptor, layout);
+    Ok(pipeline_descriptor)
+}
+
+/// Creates the fallback pipeline descriptor of a variant, drawing any vertex layout.
+///
+/// The fallback vertex function reads positions from the raw vertex buffer
+/// with the stride bound at `FALLBACK_STRIDE_INDEX`, so the pipeline has no
+/// vertex descriptor, and draws are shaded without colors, normals or
+/// texture coordinates.
+fn create_fallback_pipeline_descriptor(
+    library: &mut ShaderLibrary,
+    variant: PipelineVariant,
+) -> Result<RenderPipelineDescriptor, PipelineError> {
+    let features = if variant.is_instanced() {
+        ShaderFeatures::INSTANCED
+    } else {
+        ShaderFeatures::NONE
+    };
+    let named = |result: Result<Function, PipelineError>, name: &str| {
+        result.map_err(|_| PipelineError::FunctionNotFound {
+            function: name.to_string(),
+            pipeline: format!("{variant:?}"),
+        })
+    };
+    let vertex_function = named(
+        library.function("vertex_fallback", features),
+        "vertex_fallback",
+    )?;
+    let fragment_function = variant
+        .fragment_function_name()
+        .map(|name| named(library.function(name, features), name))
+        .transpose()?;
+    Ok(create_pipeline_descriptor(
+        &vertex_function,
+        fragment_function.as_ref(),
+        variant,
+    ))
 }
 
-fn load_metal_shader_library(device: &Device) -> Result<metal::Library, RendererError> {
+/// Loads the shader library compiled by the build script.
+///
+/// iOS apps cannot read the build's output directory, so the library is
+/// embedded in the binary there.
+#[cfg(target_os = "ios")]
+pub fn load_metal_shader_library(device: &Device) -> Result<metal::Library, PipelineError> {
+    debug!("Loading embedded shaders");
+
+    let data = include_bytes!(env!("METAL_SHADER_LIB"));
+    device.new_library_with_data(data).map_err(|e| {
+        error!("Failed to load shader library: {e}");
+        PipelineError::ShaderLibraryLoadFailed {
+            path: env!("METAL_SHADER_LIB").to_string(),
+            message: e,
+        }
+    })
+}
+
+/// Loads the shader library compiled by the build script.
+#[cfg(not(target_os = "ios"))]
+pub fn load_metal_shader_library(device: &Device) -> Result<metal::Library, PipelineError> {
     debug!("Loading pre-compiled shaders");
 
     // Create compilation options
     let shader_lib_path = std::env::var("METAL_SHADER_LIB").map_err(|e| {
         error!("Failed to get shader lib path: {e}");
-        RendererError::ShaderCompilationFailed(format!("Failed to get shader lib path: {e}"))
+        PipelineError::ShaderLibraryPathMissing(e)
     })?;
 
-    device.new_library_with_file(shader_lib_path).map_err(|e| {
+    device.new_library_with_file(&shader_lib_path).map_err(|e| {
         error!("Failed to load shader library: {e}");
-        RendererError::ShaderCompilationFailed(format!("Failed to load shader library: {e}"))
+        PipelineError::ShaderLibraryLoadFailed {
+            path: shader_lib_path,
+            message: e,
+        }
     })
 }
 
 fn create_shader_functions(
-    library: &metal::Library,
-) -> Result<(metal::Function, metal::Function), RendererError> {
-    debug!("Creating shader functions");
-
-    // Create function constants for shader compilation
-    // These constants are used to configure the shader behavior
-    let function_constants = metal::FunctionConstantValues::new();
-
-    // Set function constants for instancing and vertex color usage
-    // These values correspond to the function_constant(0) and function_constant(1) in the shader code
-    let is_instanced = false;
-    let use_vertex_color = true;
-    function_constants.set_constant_value_at_index(
-        &is_instanced as *const bool as *const c_void,
-        MTLDataType::Bool,
-        0,
-    );
-    function_constants.set_constant_value_at_index(
-        &use_vertex_color as *const bool as *const c_void,
-        MTLDataType::Bool,
-        1,
-    );
+    library: &mut ShaderLibrary,
+    variant: PipelineVariant,
+    layout: &VertexLayout,
+    constants: MaterialConstants,
+) -> Result<(Function, Option<Function>), PipelineError> {
+    debug!("Creating shader functions for {:?} variant", variant);
 
-    // Compile the vertex and fragment shaders
-    let vertex_function = library
-        .get_function("vertex_main", Some(function_constants))
-        .map_err(|_| RendererError::ShaderFunctionNotFound("vertex_main".to_string()))?;
-    let fragment_function = library
-        .get_function("fragment_main", None)
-        .map_err(|_| RendererError::ShaderFunctionNotFound("fragment_main".to_string()))?;
-
-    let function_names: Vec<String> = library
-        .function_names()
-        .into_iter()
-        .map(String::from)
-        .collect();
-    debug!(
-        "Shaders loaded successfully. Available functions:\n - {}",
-        function_names.join("\n - ")
-    );
+    // Both stages are specialized to the same features, so their interfaces match
+    let features = ShaderFeatures::for_pipeline(variant, layout);
+    let named = |result: Result<Function, PipelineError>, name: &str| {
+        result.map_err(|_| PipelineError::FunctionNotFound {
+            function: name.to_string(),
+            pipeline: format!("{variant:?}"),
+        })
+    };
+    let vertex_function = named(library.function("vertex_main", features), "vertex_main")?;
+    // Only the fragment stage reads the material
+    let fragment_function = variant
+        .fragment_function_name()
+        .map(|name| {
+            named(
+                library.specialized_function(name, features, constants),
+                name,
+            )
+        })
+        .transpose()?;
 
     Ok((vertex_function, fragment_function))
 }
 
 fn create_pipeline_descriptor(
-    vertex_function: &metal::Function,
-    fragment_function: &metal::Function,
+    vertex_function: &Function,
+    fragment_function: Option<&Function>,
+    variant: PipelineVariant,
 ) -> RenderPipelineDescriptor {
     debug!("Creating pipeline descriptor");
     let pipeline_descriptor = metal::RenderPipelineDescriptor::new();
     pipeline_descriptor.set_vertex_function(Some(vertex_function));
-    pipeline_descriptor.set_fragment_function(Some(fragment_function));
+    pipeline_descriptor.set_fragment_function(fragment_function.map(|function| function as &_));
+
+    // Add depth and stencil attachments, which share one texture
+    pipeline_descriptor.set_depth_attachment_pixel_format(MTLPixelFormat::Depth32Float_Stencil8);
+    pipeline_descriptor.set_stencil_attachment_pixel_format(MTLPixelFormat::Depth32Float_Stencil8);
+
+    // Depth-only variants rasterize without shading or color attachments
+    if fragment_function.is_none() {
+        return pipeline_descriptor;
+    }
 
     // Setup color attachments
     let attachment = pipeline_descriptor
         .color_attachments()
         .object_at(0)
         .unwrap();
-    attachment.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+    attachment.set_pixel_format(COLOR_PIXEL_FORMAT);
 
-    // Add depth attachment
-    pipeline_descriptor.set_depth_attachment_pixel_format(MTLPixelFormat::Depth32Float);
+    // Overdraw accumulates one tint per shaded fragment
+    if variant.is_overdraw() {
+        set_blend_factors(attachment, MTLBlendFactor::One, MTLBlendFactor::One);
+    }
+    if variant.is_additive() {
+        set_blend_factors(attachment, MTLBlendFactor::SourceAlpha, MTLBlendFactor::One);
+    }
+    if variant.is_transparent() {
+        set_blend_factors(
+            attachment,
+            MTLBlendFactor::SourceAlpha,
+            MTLBlendFactor::OneMinusSourceAlpha,
+        );
+    }
+    // Weighted blended transparency sums weighted colors into the first
+    // target and multiplies one minus every alpha into the second
+    if variant.is_weighted_blended() {
+        attachment.set_pixel_format(ACCUMULATION_PIXEL_FORMAT);
+        set_blend_factors(attachment, MTLBlendFactor::One, MTLBlendFactor::One);
+        let revealage = pipeline_descriptor
+            .color_attachments()
+            .object_at(1)
+            .unwrap();
+        revealage.set_pixel_format(REVEALAGE_PIXEL_FORMAT);
+        set_blend_factors(
+            revealage,
+            MTLBlendFactor::Zero,
+            MTLBlendFactor::OneMinusSourceColor,
+        );
+    }
 
     pipeline_descriptor
 }
 
-fn create_depth_stencil_state(device: &Device) -> DepthStencilState {
-    debug!("Creating depth stencil state");
+fn set_blend_factors(
+    attachment: &RenderPipelineColorAttachmentDescriptorRef,
+    source: MTLBlendFactor,
+    destination: MTLBlendFactor,
+) {
+    attachment.set_blending_enabled(true);
+    attachment.set_rgb_blend_operation(MTLBlendOperation::Add);
+    attachment.set_alpha_blend_operation(MTLBlendOperation::Add);
+    attachment.set_source_rgb_blend_factor(source);
+    attachment.set_destination_rgb_blend_factor(destination);
+    attachment.set_source_alpha_blend_factor(source);
+    attachment.set_destination_alpha_blend_factor(destination);
+}
 
-    // Enable depth testing
+fn create_depth_stencil_state(
+    device: &Device,
+    depth_test: bool,
+    accept_equal: bool,
+    depth_write: bool,
+    stencil: Option<StencilState>,
+) -> DepthStencilState {
+    debug!(
+        "Creating depth stencil state: test={depth_test}, equal={accept_equal}, write={depth_write}, stencil={:?}",
+        stencil
+    );
+
+    // Without depth testing every fragment passes, but may still write depth
     let depth_stencil_descriptor = DepthStencilDescriptor::new();
-    depth_stencil_descriptor.set_depth_compare_function(metal::MTLCompareFunction::Less);
-    depth_stencil_descriptor.set_depth_write_enabled(true);
+    depth_stencil_descriptor.set_depth_compare_function(match (depth_test, accept_equal) {
+        (false, _) => metal::MTLCompareFunction::Always,
+        (true, false) => metal::MTLCompareFunction::Less,
+        (true, true) => metal::MTLCompareFunction::LessEqual,
+    });
+    depth_stencil_descriptor.set_depth_write_enabled(depth_write);
+
+    if let Some(stencil) = stencil {
+        let stencil_descriptor = StencilDescriptor::new();
+        stencil_descriptor.set_stencil_compare_function(stencil.compare.into());
+        stencil_descriptor.set_stencil_failure_operation(stencil.fail_op.into());
+        stencil_descriptor.set_depth_failure_operation(stencil.depth_fail_op.into());
+        stencil_descriptor.set_depth_stencil_pass_operation(stencil.pass_op.into());
+        stencil_descriptor.set_read_mask(stencil.read_mask);
+        stencil_descriptor.set_write_mask(stencil.write_mask);
+        depth_stencil_descriptor.set_front_face_stencil(Some(&stencil_descriptor));
+        depth_stencil_descriptor.set_back_face_stencil(Some(&stencil_descriptor));
+    }
 
     device.new_depth_stencil_state(&depth_stencil_descriptor)
 }
 
-fn setup_vertex_descriptor(pipeline_descriptor: &RenderPipelineDescriptor) {
+fn setup_vertex_descriptor(pipeline_descriptor: &RenderPipelineDescriptor, layout: &VertexLayout) {
     debug!("Setting up vertex descriptor");
     let vertex_descriptor = metal::VertexDescriptor::new();
 
-    // Position attribute
-    let position_attr = vertex_descriptor.attributes().object_at(0).unwrap();
-    position_attr.set_format(MTLVertexFormat::Float3);
-    position_attr.set_offset(0);
-    position_attr.set_buffer_index(0);
-
-    // Color attribute
-    let color_attr = vertex_descriptor.attributes().object_at(1).unwrap();
-    color_attr.set_format(MTLVertexFormat::Float4);
-    color_attr.set_offset(12); // 3 floats
-    color_attr.set_buffer_index(0);
+    // Every attribute is read from vertex buffer 0 at its semantic's attribute index
+    for (semantic, attribute) in layout.attributes() {
+        let descriptor = vertex_descriptor
+            .attributes()
+            .object_at(semantic.attribute_index() as u64)
+            .unwrap();
+        descriptor.set_format(vertex_format(attribute.format));
+        descriptor.set_offset(attribute.offset as u64);
+        descriptor.set_buffer_index(0);
+        trace!(
+            "Vertex attribute {:?}: format={:?}, offset={}",
+            semantic,
+            attribute.format,
+            attribute.offset
+        );
+    }
 
     // Vertex buffer layout
     vertex_descriptor
         .layouts()
         .object_at(0)
         .unwrap()
-        .set_stride(28); // 7 floats per vertex (3 for position, 4 for color)
-
-    trace!(
-        "Vertex descriptor details:\n
-            Position: format={:?}, offset={}, buffer_index={}\n 
-            Color: format={:?}, offset={}, buffer_index={}\n,
-            Stride: {}",
-        position_attr.format(),
-        position_attr.offset(),
-        position_attr.buffer_index(),
-        color_attr.format(),
-        color_attr.offset(),
-        color_attr.buffer_index(),
-        vertex_descriptor.layouts().object_at(0).unwrap().stride()
-    );
+        .set_stride(layout.stride() as u64);
+    trace!("Vertex stride: {}", layout.stride());
 
     pipeline_descriptor.set_vertex_descriptor(Some(vertex_descriptor));
 
     debug!("Vertex descriptor set up successfully");
 }
 
+/// Returns the Metal format of a vertex attribute format.
+fn vertex_format(format: VertexFormat) -> MTLVertexFormat {
+    match format {
+        VertexFormat::Float => MTLVertexFormat::Float,
+        VertexFormat::Float2 => MTLVertexFormat::Float2,
+        VertexFormat::Float3 => MTLVertexFormat::Float3,
+        VertexFormat::Float4 => MTLVertexFormat::Float4,
+        VertexFormat::UChar4Normalized => MTLVertexFormat::UChar4Normalized,
+        VertexFormat::Char4Normalized => MTLVertexFormat::Char4Normalized,
+        VertexFormat::Half2 => MTLVertexFormat::Half2,
+        VertexFormat::Half4 => MTLVertexFormat::Half4,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::renderer::backend::metal::pipeline::create_default_pipeline_descriptor;
+    use crate::renderer::{
+        backend::metal::pipeline::{
+            create_default_pipeline_descriptors, PipelineVariant, ShaderFeatures,
+        },
+        common::{BackendDrawCommand, PrimitiveType},
+        vertex_layout::VertexLayout,
+    };
     use metal::Device;
 
     #[test]
     fn test_create_default_pipeline_descriptor() {
         let device = Device::system_default().expect("No Metal device found");
-        let result = create_default_pipeline_descriptor(&device);
+        let result = create_default_pipeline_descriptors(&device);
         assert!(
             result.is_ok(),
             "Failed to create render pipeline: {:?}",
             result.err()
         );
+
+        let descriptors = result.unwrap();
+        assert_eq!(descriptors.len(), PipelineVariant::ALL.len());
+    }
+
+    #[test]
+    fn test_pipeline_variant_for_draw_command() {
+        let basic = BackendDrawCommand::Basic {
+            primitive_type: PrimitiveType::Triangle,
+            vertex_start: 0,
+            vertex_count: 3,
+        };
+        let instanced = BackendDrawCommand::Instanced {
+            primitive_type: PrimitiveType::Triangle,
+            vertex_start: 0,
+            vertex_count: 3,
+            instance_count: 10,
+        };
+
+        assert_eq!(
+            PipelineVariant::for_draw_command(&basic),
+            PipelineVariant::Default
+        );
+        assert_eq!(
+            PipelineVariant::for_draw_command(&instanced),
+            PipelineVariant::Instanced
+        );
+        assert_eq!(
+            PipelineVariant::for_draw_command(&instanced).outline(),
+            PipelineVariant::OutlineInstanced
+        );
+        assert_eq!(
+            PipelineVariant::for_draw_command(&basic).overdraw(),
+            PipelineVariant::Overdraw
+        );
+        assert_eq!(
+            PipelineVariant::for_draw_command(&basic).transparent(false),
+            PipelineVariant::Transparent
+        );
+        assert_eq!(
+            PipelineVariant::for_draw_command(&instanced).transparent(true),
+            PipelineVariant::WeightedBlendedInstanced
+        );
+        assert_eq!(
+            PipelineVariant::for_draw_command(&instanced).depth_only(),
+            PipelineVariant::DepthOnlyInstanced
+        );
+    }
+
+    #[test]
+    fn test_shader_features_for_pipeline() {
+        let features = ShaderFeatures::for_pipeline(
+            PipelineVariant::Instanced,
+            &VertexLayout::position_color(),
+        );
+        assert_eq!(
+            features,
+            ShaderFeatures::INSTANCED | ShaderFeatures::VERTEX_COLOR
+        );
+        assert!(!features.contains(ShaderFeatures::TEXTURED));
+
+        let features = ShaderFeatures::for_pipeline(
+            PipelineVariant::Transparent,
+            &VertexLayout::position_normal_uv(),
+        );
+        assert_eq!(
+            format!("{features:?}"),
+            "ShaderFeatures(VERTEX_NORMAL | TEXTURED)"
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                ShaderFeatures::for_pipeline(PipelineVariant::DepthOnly, &VertexLayout::position())
+            ),
+            "ShaderFeatures(NONE)"
+        );
     }
 }
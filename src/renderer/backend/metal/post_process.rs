@@ -0,0 +1,579 @@
+//! Metal post-process module.
+//!
+//! This module resolves anti-aliasing and draws the sky, atmospheric
+//! scattering and lens flares. While any is enabled, draws render into an
+//! offscreen scene texture instead of the drawable. At the end of the frame,
+//! the sky fills the background of the scene texture and the atmosphere is
+//! blended over it, then a fullscreen pass writes the anti-aliased scene to
+//! the drawable. Temporal anti-aliasing also writes the
+//! result to a history texture, which the next frame blends with. Lens flares
+//! are added on top afterwards, so they never enter the history. Frames with
+//! a render scale other than 1 render at the scaled size and are filtered to
+//! the drawable's size by the final pass.
+
+use super::buffer_manager::as_bytes;
+use super::pipeline::{load_metal_shader_library, COLOR_PIXEL_FORMAT};
+use crate::renderer::{
+    atmosphere::AtmosphereConstants, common::TemporalConstants, config::AntiAliasing,
+    error::PipelineError, lens_flare::LensFlareFrame, render_scale::scaled_size, sky::SkyConstants,
+};
+use log::{debug, error};
+use metal::{
+    CommandBufferRef, Device, Library, MTLBlendFactor, MTLBlendOperation, MTLClearColor,
+    MTLLoadAction, MTLPixelFormat, MTLPrimitiveType, MTLStorageMode, MTLStoreAction,
+    MTLTextureUsage, RenderPassDescriptor, RenderPipelineDescriptor, RenderPipelineState, Texture,
+    TextureDescriptor, TextureRef,
+};
+
+/// The format of the history textures, which keeps precision across blends.
+const HISTORY_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA16Float;
+
+/// The most flare elements drawn, keeping them within Metal's 4 KB limit for inline bytes.
+const MAX_FLARE_ELEMENTS: usize = 64;
+
+/// Renders frames offscreen and resolves them into the drawable.
+pub struct PostProcess {
+    device: Device,
+    anti_aliasing: AntiAliasing,
+    copy_pipeline: RenderPipelineState,
+    fxaa_pipeline: RenderPipelineState,
+    taa_pipeline: RenderPipelineState,
+    flare_pipeline: RenderPipelineState,
+    atmosphere_pipeline: RenderPipelineState,
+    sky_pipeline: RenderPipelineState,
+    lens_flare: Option<LensFlareFrame>,
+    atmosphere: Option<AtmosphereConstants>,
+    sky: Option<SkyConstants>,
+    /// Whether passes encoded outside post-processing need the scene texture.
+    offscreen_required: bool,
+    /// The size of the scene texture relative to the drawable.
+    render_scale: f32,
+    scene_color: Option<Texture>,
+    /// The previous frame's history is read while the current frame's is written.
+    history: Vec<Texture>,
+    history_index: usize,
+    history_valid: bool,
+    temporal: Option<TemporalConstants>,
+    /// Whether a draw has rendered into the scene texture this frame.
+    frame_started: bool,
+}
+
+impl PostProcess {
+    /// Creates a new `PostProcess` with anti-aliasing and all effects disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `PostProcess` or a `PipelineError`.
+    pub fn new(device: &Device) -> Result<Self, PipelineError> {
+        let library = load_metal_shader_library(device)?;
+        let drawable_format = [COLOR_PIXEL_FORMAT];
+        let copy_pipeline = create_pipeline(
+            device,
+            &library,
+            ("fullscreen_vertex", "copy_fragment"),
+            &drawable_format,
+            Blend::Replace,
+        )?;
+        let fxaa_pipeline = create_pipeline(
+            device,
+            &library,
+            ("fullscreen_vertex", "fxaa_fragment"),
+            &drawable_format,
+            Blend::Replace,
+        )?;
+        let taa_pipeline = create_pipeline(
+            device,
+            &library,
+            ("fullscreen_vertex", "taa_resolve_fragment"),
+            &[COLOR_PIXEL_FORMAT, HISTORY_PIXEL_FORMAT],
+            Blend::Replace,
+        )?;
+        let flare_pipeline = create_pipeline(
+            device,
+            &library,
+            ("flare_vertex", "flare_fragment"),
+            &drawable_format,
+            Blend::Additive,
+        )?;
+        let atmosphere_pipeline = create_pipeline(
+            device,
+            &library,
+            ("fullscreen_vertex", "atmosphere_fragment"),
+            &drawable_format,
+            Blend::Transmittance,
+        )?;
+        let sky_pipeline = create_pipeline(
+            device,
+            &library,
+            ("fullscreen_vertex", "sky_fragment"),
+            &drawable_format,
+            Blend::Replace,
+        )?;
+
+        Ok(PostProcess {
+            device: device.clone(),
+            anti_aliasing: AntiAliasing::None,
+            copy_pipeline,
+            fxaa_pipeline,
+            taa_pipeline,
+            flare_pipeline,
+            atmosphere_pipeline,
+            sky_pipeline,
+            lens_flare: None,
+            atmosphere: None,
+            sky: None,
+            offscreen_required: false,
+            render_scale: 1.0,
+            scene_color: None,
+            history: Vec::new(),
+            history_index: 0,
+            history_valid: false,
+            temporal: None,
+            frame_started: false,
+        })
+    }
+
+    /// Returns `true` if draws render offscreen.
+    pub fn is_enabled(&self) -> bool {
+        self.anti_aliasing.is_enabled()
+            || self.lens_flare.is_some()
+            || self.atmosphere.is_some()
+            || self.sky.is_some()
+            || self.offscreen_required
+            || self.render_scale != 1.0
+    }
+
+    /// Sets the size of the scene texture relative to the drawable.
+    ///
+    /// Discards the temporal history, which no longer matches the scene.
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale;
+        if !self.is_enabled() {
+            self.scene_color = None;
+        }
+        self.history_valid = false;
+    }
+
+    /// Returns the size of the scene texture for a drawable.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the drawable in pixels.
+    /// * `height` - The height of the drawable in pixels.
+    pub fn scene_size(&self, width: u64, height: u64) -> (u64, u64) {
+        scaled_size(width, height, self.render_scale)
+    }
+
+    /// Selects the anti-aliasing, releasing textures it no longer needs.
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+        if !self.is_enabled() {
+            self.scene_color = None;
+        }
+        if anti_aliasing != AntiAliasing::Taa {
+            self.history.clear();
+            self.temporal = None;
+        }
+        self.history_valid = false;
+        self.frame_started = false;
+    }
+
+    /// Sets the lens flare drawn over the current frame, or `None` for no flare.
+    ///
+    /// Must be called before the frame's first draw, since it decides
+    /// whether draws render offscreen.
+    pub fn set_lens_flare(&mut self, lens_flare: Option<&LensFlareFrame>) {
+        match (lens_flare, &mut self.lens_flare) {
+            (Some(lens_flare), Some(current)) => current.clone_from(lens_flare),
+            (lens_flare, current) => *current = lens_flare.cloned(),
+        }
+        if !self.is_enabled() {
+            self.scene_color = None;
+        }
+    }
+
+    /// Sets the atmosphere blended over the current frame, or `None` for no atmosphere.
+    ///
+    /// Must be called before the frame's first draw, since it decides
+    /// whether draws render offscreen.
+    pub fn set_atmosphere(&mut self, atmosphere: Option<&AtmosphereConstants>) {
+        self.atmosphere = atmosphere.copied();
+        if !self.is_enabled() {
+            self.scene_color = None;
+        }
+    }
+
+    /// Sets the sky drawn behind the current frame, or `None` for no sky.
+    ///
+    /// Must be called before the frame's first draw, since it decides
+    /// whether draws render offscreen.
+    pub fn set_sky(&mut self, sky: Option<&SkyConstants>) {
+        self.sky = sky.copied();
+        if !self.is_enabled() {
+            self.scene_color = None;
+        }
+    }
+
+    /// Forces draws offscreen for passes encoded outside post-processing,
+    /// such as ray-traced shadows and the depth pyramid of occlusion culling.
+    pub fn require_offscreen(&mut self, required: bool) {
+        self.offscreen_required = required;
+        if !self.is_enabled() {
+            self.scene_color = None;
+        }
+    }
+
+    /// Sets the constants of the next temporal resolve.
+    pub fn set_temporal_constants(&mut self, constants: TemporalConstants) {
+        self.temporal = Some(constants);
+    }
+
+    /// Returns the scene texture a draw renders into, and how to load it.
+    ///
+    /// The first draw of a frame clears the scene texture and later draws
+    /// keep what earlier draws rendered. The depth attachment follows the
+    /// same load action.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the drawable in pixels.
+    /// * `height` - The height of the drawable in pixels.
+    pub fn begin_draw(&mut self, width: u64, height: u64) -> (&TextureRef, MTLLoadAction) {
+        let load_action = if std::mem::replace(&mut self.frame_started, true) {
+            MTLLoadAction::Load
+        } else {
+            MTLLoadAction::Clear
+        };
+        let (width, height) = self.scene_size(width, height);
+        (self.ensure_scene_color(width, height), load_action)
+    }
+
+    /// Returns `true` if a draw has rendered into the current frame's scene texture.
+    pub fn is_frame_started(&self) -> bool {
+        self.frame_started
+    }
+
+    /// Encodes the sky, atmosphere, anti-aliasing resolve and lens flare of the current frame into a target.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer presenting the target.
+    /// * `target` - The drawable texture to write.
+    /// * `depth` - The depth texture of the frame, for temporal reprojection, the sky, the atmosphere and flare occlusion.
+    /// * `clear_color` - The color of frames without any draws.
+    /// * `scene_pass` - Encodes further passes over the scene and depth textures
+    ///   after the sky, before the atmosphere and resolve.
+    pub fn encode_resolve<F>(
+        &mut self,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+        depth: &TextureRef,
+        clear_color: MTLClearColor,
+        scene_pass: F,
+    ) where
+        F: FnOnce(&CommandBufferRef, &TextureRef, &TextureRef),
+    {
+        let (width, height) = (target.width(), target.height());
+        let drawn = std::mem::take(&mut self.frame_started);
+        let (scene_width, scene_height) = self.scene_size(width, height);
+        let scene = self
+            .ensure_scene_color(scene_width, scene_height)
+            .to_owned();
+        if !drawn {
+            let descriptor = RenderPassDescriptor::new();
+            let attachment = descriptor.color_attachments().object_at(0).unwrap();
+            attachment.set_texture(Some(&scene));
+            attachment.set_load_action(MTLLoadAction::Clear);
+            attachment.set_clear_color(clear_color);
+            attachment.set_store_action(MTLStoreAction::Store);
+            // The sky and atmosphere read depth, which must not be left from an earlier frame
+            let depth_attachment = descriptor.depth_attachment().unwrap();
+            depth_attachment.set_texture(Some(depth));
+            depth_attachment.set_load_action(MTLLoadAction::Clear);
+            depth_attachment.set_clear_depth(1.0);
+            depth_attachment.set_store_action(MTLStoreAction::Store);
+            command_buffer
+                .new_render_command_encoder(descriptor)
+                .end_encoding();
+        }
+        if let Some(sky) = &self.sky {
+            encode_scene_pass(command_buffer, &scene, depth, &self.sky_pipeline, sky);
+        }
+        scene_pass(command_buffer, &scene, depth);
+        // Scattering happens before the anti-aliasing resolve, so temporal
+        // anti-aliasing smooths its ray-march noise along with the scene
+        if let Some(atmosphere) = &self.atmosphere {
+            encode_scene_pass(
+                command_buffer,
+                &scene,
+                depth,
+                &self.atmosphere_pipeline,
+                atmosphere,
+            );
+        }
+
+        let descriptor = RenderPassDescriptor::new();
+        let attachment = descriptor.color_attachments().object_at(0).unwrap();
+        attachment.set_texture(Some(target));
+        attachment.set_load_action(MTLLoadAction::DontCare);
+        attachment.set_store_action(MTLStoreAction::Store);
+
+        let temporal = match self.anti_aliasing {
+            AntiAliasing::Taa => {
+                self.ensure_history(width, height);
+                let mut constants = self.temporal.take().unwrap_or(TemporalConstants {
+                    reprojection: glam::Mat4::IDENTITY,
+                    jitter: [0.0; 2],
+                    history_weight: 0.0,
+                    history_valid: 0,
+                });
+                // Stale depth cannot reproject an empty frame
+                if !self.history_valid || !drawn {
+                    constants.history_valid = 0;
+                }
+                let write_index = 1 - self.history_index;
+                let history_attachment = descriptor.color_attachments().object_at(1).unwrap();
+                history_attachment.set_texture(Some(&self.history[write_index]));
+                history_attachment.set_load_action(MTLLoadAction::DontCare);
+                history_attachment.set_store_action(MTLStoreAction::Store);
+                Some(constants)
+            }
+            _ => None,
+        };
+
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
+        encoder.set_fragment_texture(0, Some(&scene));
+        match temporal {
+            Some(constants) => {
+                encoder.set_render_pipeline_state(&self.taa_pipeline);
+                encoder.set_fragment_bytes(
+                    0,
+                    std::mem::size_of::<TemporalConstants>() as u64,
+                    &constants as *const TemporalConstants as *const std::ffi::c_void,
+                );
+                encoder.set_fragment_texture(1, Some(&self.history[self.history_index]));
+                encoder.set_fragment_texture(2, Some(depth));
+                self.history_index = 1 - self.history_index;
+                self.history_valid = true;
+            }
+            None if self.anti_aliasing == AntiAliasing::Fxaa => {
+                encoder.set_render_pipeline_state(&self.fxaa_pipeline)
+            }
+            None => encoder.set_render_pipeline_state(&self.copy_pipeline),
+        }
+        encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 3);
+        encoder.end_encoding();
+
+        self.encode_lens_flare(command_buffer, target, depth);
+    }
+
+    /// Adds the lens flare elements onto the resolved target.
+    ///
+    /// Every element is a quad that tests the light's occlusion against the
+    /// depth texture in its vertex shader.
+    fn encode_lens_flare(
+        &self,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+        depth: &TextureRef,
+    ) {
+        let Some(lens_flare) = self.lens_flare.as_ref() else {
+            return;
+        };
+        if lens_flare.elements.is_empty() {
+            return;
+        }
+
+        let descriptor = RenderPassDescriptor::new();
+        let attachment = descriptor.color_attachments().object_at(0).unwrap();
+        attachment.set_texture(Some(target));
+        attachment.set_load_action(MTLLoadAction::Load);
+        attachment.set_store_action(MTLStoreAction::Store);
+
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
+        encoder.set_render_pipeline_state(&self.flare_pipeline);
+        let constants = as_bytes(std::slice::from_ref(&lens_flare.constants));
+        encoder.set_vertex_bytes(
+            0,
+            constants.len() as u64,
+            constants.as_ptr() as *const std::ffi::c_void,
+        );
+        let elements = &lens_flare.elements[..lens_flare.elements.len().min(MAX_FLARE_ELEMENTS)];
+        let element_count = elements.len() as u64;
+        let elements = as_bytes(elements);
+        encoder.set_vertex_bytes(
+            1,
+            elements.len() as u64,
+            elements.as_ptr() as *const std::ffi::c_void,
+        );
+        encoder.set_vertex_texture(0, Some(depth));
+        encoder.draw_primitives_instanced(MTLPrimitiveType::TriangleStrip, 0, 4, element_count);
+        encoder.end_encoding();
+    }
+
+    /// Returns the bytes allocated for the scene and history textures.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.scene_color
+            .iter()
+            .chain(&self.history)
+            .map(|texture| texture.allocated_size())
+            .sum()
+    }
+
+    fn ensure_scene_color(&mut self, width: u64, height: u64) -> &TextureRef {
+        let resize = self
+            .scene_color
+            .as_ref()
+            .is_none_or(|texture| texture.width() != width || texture.height() != height);
+        if resize {
+            self.scene_color =
+                Some(self.new_render_target(width, height, COLOR_PIXEL_FORMAT, "SceneColor"));
+        }
+        self.scene_color.as_ref().unwrap()
+    }
+
+    fn ensure_history(&mut self, width: u64, height: u64) {
+        let resize = self
+            .history
+            .first()
+            .is_none_or(|texture| texture.width() != width || texture.height() != height);
+        if resize {
+            self.history = (0..2)
+                .map(|_| self.new_render_target(width, height, HISTORY_PIXEL_FORMAT, "TaaHistory"))
+                .collect();
+            self.history_valid = false;
+        }
+    }
+
+    fn new_render_target(
+        &self,
+        width: u64,
+        height: u64,
+        pixel_format: MTLPixelFormat,
+        label: &str,
+    ) -> Texture {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width);
+        descriptor.set_height(height);
+        descriptor.set_pixel_format(pixel_format);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+
+        let texture = self.device.new_texture(&descriptor);
+        texture.set_label(label);
+        debug!("Created {} texture: {}x{}", label, width, height);
+        texture
+    }
+}
+
+/// Encodes a fullscreen pass over the scene texture that reads the frame's depth.
+///
+/// # Arguments
+///
+/// * `command_buffer` - The command buffer of the resolve.
+/// * `scene` - The scene texture, loaded and blended onto by the pipeline.
+/// * `depth` - The depth texture of the frame, bound to fragment texture 0.
+/// * `pipeline` - The pipeline of the pass.
+/// * `constants` - The constants bound to fragment buffer 0.
+fn encode_scene_pass<T>(
+    command_buffer: &CommandBufferRef,
+    scene: &TextureRef,
+    depth: &TextureRef,
+    pipeline: &RenderPipelineState,
+    constants: &T,
+) {
+    let descriptor = RenderPassDescriptor::new();
+    let attachment = descriptor.color_attachments().object_at(0).unwrap();
+    attachment.set_texture(Some(scene));
+    attachment.set_load_action(MTLLoadAction::Load);
+    attachment.set_store_action(MTLStoreAction::Store);
+
+    let encoder = command_buffer.new_render_command_encoder(descriptor);
+    encoder.set_render_pipeline_state(pipeline);
+    encoder.set_fragment_bytes(
+        0,
+        std::mem::size_of::<T>() as u64,
+        constants as *const T as *const std::ffi::c_void,
+    );
+    encoder.set_fragment_texture(0, Some(depth));
+    encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 3);
+    encoder.end_encoding();
+}
+
+/// How a pipeline combines its output with the first color attachment.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Blend {
+    /// The output replaces the attachment.
+    Replace,
+    /// The output is added onto the attachment.
+    Additive,
+    /// The attachment is multiplied by the second output and the first is added.
+    Transmittance,
+    /// The attachment is multiplied by the output.
+    Multiply,
+    /// The output is blended over the attachment by its alpha.
+    Over,
+}
+
+/// Creates a pipeline writing to color attachments of the given formats.
+pub fn create_pipeline(
+    device: &Device,
+    library: &Library,
+    (vertex_function_name, fragment_function_name): (&str, &str),
+    pixel_formats: &[MTLPixelFormat],
+    blend: Blend,
+) -> Result<RenderPipelineState, PipelineError> {
+    let function = |name: &str| {
+        library
+            .get_function(name, None)
+            .map_err(|_| PipelineError::FunctionNotFound {
+                function: name.to_string(),
+                pipeline: fragment_function_name.to_string(),
+            })
+    };
+
+    let vertex_function = function(vertex_function_name)?;
+    let fragment_function = function(fragment_function_name)?;
+
+    let descriptor = RenderPipelineDescriptor::new();
+    descriptor.set_vertex_function(Some(&vertex_function));
+    descriptor.set_fragment_function(Some(&fragment_function));
+    for (index, pixel_format) in pixel_formats.iter().enumerate() {
+        descriptor
+            .color_attachments()
+            .object_at(index as u64)
+            .unwrap()
+            .set_pixel_format(*pixel_format);
+    }
+    if blend != Blend::Replace {
+        let (source_factor, destination_factor) = match blend {
+            Blend::Transmittance => (MTLBlendFactor::One, MTLBlendFactor::Source1Color),
+            Blend::Multiply => (MTLBlendFactor::Zero, MTLBlendFactor::SourceColor),
+            Blend::Over => (
+                MTLBlendFactor::SourceAlpha,
+                MTLBlendFactor::OneMinusSourceAlpha,
+            ),
+            _ => (MTLBlendFactor::One, MTLBlendFactor::One),
+        };
+        let attachment = descriptor.color_attachments().object_at(0).unwrap();
+        attachment.set_blending_enabled(true);
+        attachment.set_rgb_blend_operation(MTLBlendOperation::Add);
+        attachment.set_alpha_blend_operation(MTLBlendOperation::Add);
+        attachment.set_source_rgb_blend_factor(source_factor);
+        attachment.set_destination_rgb_blend_factor(destination_factor);
+        attachment.set_source_alpha_blend_factor(MTLBlendFactor::Zero);
+        attachment.set_destination_alpha_blend_factor(MTLBlendFactor::One);
+    }
+
+    device.new_render_pipeline_state(&descriptor).map_err(|e| {
+        error!("Failed to create pipeline state: {e}");
+        PipelineError::CreationFailed {
+            pipeline: fragment_function_name.to_string(),
+            message: e,
+        }
+    })
+}
@@ -0,0 +1,256 @@
+//! Metal ray tracing module.
+//!
+//! This module builds a primitive acceleration structure for every stored mesh
+//! with triangles, and every frame an instance acceleration structure over
+//! that frame's shadow casters. A fullscreen pass then traces one shadow ray
+//! per pixel from the depth buffer and multiplies the result onto the scene
+//! texture. Nothing is built on devices without ray tracing support.
+
+use super::pipeline::{load_metal_shader_library, COLOR_PIXEL_FORMAT};
+use super::post_process::{create_pipeline, Blend};
+use crate::renderer::{
+    common::Vertex,
+    error::PipelineError,
+    ray_tracing::{triangle_indices, RayTracingFrame, ShadowRayConstants},
+    render_queue::GeometryView,
+};
+use log::{debug, info};
+use metal::{
+    AccelerationStructure, AccelerationStructureDescriptorRef,
+    AccelerationStructureTriangleGeometryDescriptor, Array, CommandBufferRef, CommandQueue, Device,
+    InstanceAccelerationStructureDescriptor, MTLAccelerationStructureInstanceDescriptor,
+    MTLAccelerationStructureInstanceOptions, MTLAttributeFormat, MTLIndexType, MTLLoadAction,
+    MTLPrimitiveType, MTLRenderStages, MTLResourceOptions, MTLResourceUsage, MTLStoreAction,
+    PrimitiveAccelerationStructureDescriptor, RenderPassDescriptor, RenderPipelineState,
+    TextureRef,
+};
+use std::collections::HashMap;
+
+/// Builds acceleration structures and traces ray-traced effects.
+pub struct RayTracing {
+    device: Device,
+    /// The shadow pipeline, or `None` if the device cannot ray trace.
+    shadow_pipeline: Option<RenderPipelineState>,
+    /// The acceleration structure of each mesh, or `None` for meshes without triangles.
+    primitive_structures: Vec<Option<AccelerationStructure>>,
+    frame: Option<RayTracingFrame>,
+}
+
+impl RayTracing {
+    /// Creates a new `RayTracing`, querying the device for support.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device to build acceleration structures on.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `RayTracing` or a `PipelineError`.
+    pub fn new(device: &Device) -> Result<Self, PipelineError> {
+        let shadow_pipeline = if device.supports_raytracing() {
+            let library = load_metal_shader_library(device)?;
+            Some(create_pipeline(
+                device,
+                &library,
+                ("fullscreen_vertex", "ray_traced_shadow_fragment"),
+                &[COLOR_PIXEL_FORMAT],
+                Blend::Multiply,
+            )?)
+        } else {
+            None
+        };
+        info!("Ray tracing supported: {}", shadow_pipeline.is_some());
+
+        Ok(RayTracing {
+            device: device.clone(),
+            shadow_pipeline,
+            primitive_structures: Vec::new(),
+            frame: None,
+        })
+    }
+
+    /// Returns `true` if the device supports ray tracing.
+    pub fn is_supported(&self) -> bool {
+        self.shadow_pipeline.is_some()
+    }
+
+    /// Returns `true` if a ray-traced effect is drawn this frame.
+    pub fn is_enabled(&self) -> bool {
+        self.frame.is_some()
+    }
+
+    /// Builds the acceleration structure of a stored mesh.
+    ///
+    /// Meshes of points or lines are skipped, since rays cannot hit them.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_queue` - The queue the build is committed to, ahead of later frames.
+    /// * `mesh` - The index of the mesh in `MeshStorage`.
+    /// * `geometry` - The mesh's geometry.
+    pub fn build_mesh(
+        &mut self,
+        command_queue: &CommandQueue,
+        mesh: usize,
+        geometry: &GeometryView<'_>,
+    ) {
+        if self.primitive_structures.len() <= mesh {
+            self.primitive_structures.resize(mesh + 1, None);
+        }
+        let Some(indices) = triangle_indices(geometry).filter(|indices| !indices.is_empty()) else {
+            return;
+        };
+
+        let vertices = geometry.vertices;
+        let vertex_buffer = self.device.new_buffer_with_data(
+            vertices.as_ptr() as *const std::ffi::c_void,
+            std::mem::size_of_val(vertices) as u64,
+            MTLResourceOptions::StorageModeShared,
+        );
+        let index_buffer = self.device.new_buffer_with_data(
+            indices.as_ptr() as *const std::ffi::c_void,
+            std::mem::size_of_val(indices.as_slice()) as u64,
+            MTLResourceOptions::StorageModeShared,
+        );
+
+        // Positions are the first field of every vertex
+        let triangles = AccelerationStructureTriangleGeometryDescriptor::descriptor();
+        triangles.set_vertex_buffer(Some(&vertex_buffer));
+        triangles.set_vertex_stride(std::mem::size_of::<Vertex>() as u64);
+        triangles.set_vertex_format(MTLAttributeFormat::Float3);
+        triangles.set_index_buffer(Some(&index_buffer));
+        triangles.set_index_type(MTLIndexType::UInt32);
+        triangles.set_triangle_count((indices.len() / 3) as u64);
+        triangles.set_opaque(true);
+
+        let descriptor = PrimitiveAccelerationStructureDescriptor::descriptor();
+        descriptor.set_geometry_descriptors(Array::from_owned_slice(&[From::from(triangles)]));
+
+        let command_buffer = command_queue.new_command_buffer();
+        let structure = self.encode_build(command_buffer, &descriptor);
+        command_buffer.commit();
+        debug!(
+            "Built acceleration structure for mesh {} with {} triangles",
+            mesh,
+            indices.len() / 3
+        );
+        self.primitive_structures[mesh] = Some(structure);
+    }
+
+    /// Sets the instances and shadow constants of the current frame, or `None` for no shadows.
+    pub fn set_frame(&mut self, frame: Option<&RayTracingFrame>) {
+        match (frame, &mut self.frame) {
+            (Some(frame), Some(current)) => current.clone_from(frame),
+            (frame, current) => *current = frame.cloned(),
+        }
+    }
+
+    /// Builds this frame's instance acceleration structure and multiplies
+    /// traced shadows onto the scene texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer of the resolve.
+    /// * `scene` - The scene texture to darken.
+    /// * `depth` - The depth texture of the frame, giving the surface under each pixel.
+    pub fn encode_shadows(
+        &self,
+        command_buffer: &CommandBufferRef,
+        scene: &TextureRef,
+        depth: &TextureRef,
+    ) {
+        let (Some(pipeline), Some(frame)) = (&self.shadow_pipeline, &self.frame) else {
+            return;
+        };
+
+        // The instance structure lists only the meshes placed this frame
+        let mut structures: Vec<AccelerationStructure> = Vec::new();
+        let mut structure_indices: HashMap<usize, u32> = HashMap::new();
+        let mut descriptors = Vec::with_capacity(frame.instances.len());
+        for instance in &frame.instances {
+            let Some(Some(structure)) = self.primitive_structures.get(instance.mesh) else {
+                continue;
+            };
+            let index = *structure_indices.entry(instance.mesh).or_insert_with(|| {
+                structures.push(structure.clone());
+                structures.len() as u32 - 1
+            });
+            let columns = instance.transform.to_cols_array_2d();
+            descriptors.push(MTLAccelerationStructureInstanceDescriptor {
+                transformation_matrix: columns.map(|column| [column[0], column[1], column[2]]),
+                options: MTLAccelerationStructureInstanceOptions::Opaque,
+                mask: u32::MAX,
+                intersection_function_table_offset: 0,
+                acceleration_structure_index: index,
+            });
+        }
+        if descriptors.is_empty() {
+            return;
+        }
+
+        let instance_buffer = self.device.new_buffer_with_data(
+            descriptors.as_ptr() as *const std::ffi::c_void,
+            std::mem::size_of_val(descriptors.as_slice()) as u64,
+            MTLResourceOptions::StorageModeShared,
+        );
+        let descriptor = InstanceAccelerationStructureDescriptor::descriptor();
+        descriptor.set_instanced_acceleration_structures(Array::from_owned_slice(&structures));
+        descriptor.set_instance_count(descriptors.len() as u64);
+        descriptor.set_instance_descriptor_buffer(&instance_buffer);
+        let scene_structure = self.encode_build(command_buffer, &descriptor);
+
+        let pass = RenderPassDescriptor::new();
+        let attachment = pass.color_attachments().object_at(0).unwrap();
+        attachment.set_texture(Some(scene));
+        attachment.set_load_action(MTLLoadAction::Load);
+        attachment.set_store_action(MTLStoreAction::Store);
+
+        let encoder = command_buffer.new_render_command_encoder(pass);
+        encoder.set_render_pipeline_state(pipeline);
+        encoder.set_fragment_bytes(
+            0,
+            std::mem::size_of::<ShadowRayConstants>() as u64,
+            &frame.constants as *const ShadowRayConstants as *const std::ffi::c_void,
+        );
+        encoder.set_fragment_acceleration_structure(1, Some(&scene_structure));
+        encoder.set_fragment_texture(0, Some(depth));
+        // Instanced structures are referenced indirectly and must be made resident
+        for structure in &structures {
+            encoder.use_resource_at(structure, MTLResourceUsage::Read, MTLRenderStages::Fragment);
+        }
+        encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 3);
+        encoder.end_encoding();
+    }
+
+    /// Returns the bytes allocated for the mesh acceleration structures.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.primitive_structures
+            .iter()
+            .flatten()
+            .map(|structure| structure.allocated_size())
+            .sum()
+    }
+
+    /// Encodes the build of an acceleration structure into a command buffer.
+    fn encode_build(
+        &self,
+        command_buffer: &CommandBufferRef,
+        descriptor: &AccelerationStructureDescriptorRef,
+    ) -> AccelerationStructure {
+        let sizes = self
+            .device
+            .acceleration_structure_sizes_with_descriptor(descriptor);
+        let structure = self
+            .device
+            .new_acceleration_structure_with_size(sizes.acceleration_structure_size);
+        let scratch = self.device.new_buffer(
+            sizes.build_scratch_buffer_size,
+            MTLResourceOptions::StorageModePrivate,
+        );
+
+        let encoder = command_buffer.new_acceleration_structure_command_encoder();
+        encoder.build_acceleration_structure(&structure, descriptor, &scratch, 0);
+        encoder.end_encoding();
+        structure
+    }
+}
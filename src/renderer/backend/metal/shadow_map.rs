@@ -0,0 +1,353 @@
+//! Metal shadow map module.
+//!
+//! This module holds the cascaded shadow maps of a frame in one depth texture
+//! array, a layer per cascade, which shadow-casting draws render their depth
+//! into. At the end of the frame a fullscreen pass finds the surface under
+//! each pixel from the depth buffer, filters the shadow map of its cascade,
+//! and multiplies the result onto the scene texture.
+//!
+//! Point and spot lights with shadows get a texture array each, with a layer
+//! per cube face of a point light and a single layer for a spot light, and
+//! are resolved the same way, one fullscreen pass per light.
+
+use super::pipeline::{load_metal_shader_library, COLOR_PIXEL_FORMAT};
+use super::post_process::{create_pipeline, Blend};
+use crate::renderer::{
+    error::PipelineError,
+    lights::LightShadowFrame,
+    shadow_map::{ShadowMapFrame, ShadowTarget},
+};
+use log::debug;
+use metal::{
+    CommandBufferRef, Device, MTLLoadAction, MTLPixelFormat, MTLPrimitiveType, MTLStorageMode,
+    MTLStoreAction, MTLTextureType, MTLTextureUsage, RenderPassDescriptor, RenderPassDescriptorRef,
+    RenderPipelineState, Texture, TextureDescriptor, TextureRef,
+};
+
+/// Attaches a layer of a shadow map array as the depth and stencil of a render pass.
+///
+/// # Arguments
+///
+/// * `descriptor` - The render pass to attach to.
+/// * `texture` - The shadow map texture array.
+/// * `layer` - The layer of the array attached, a cascade or cube face.
+/// * `load_action` - Whether the pass clears or keeps the shadow map.
+pub fn attach_shadow_layer(
+    descriptor: &RenderPassDescriptorRef,
+    texture: &TextureRef,
+    layer: u32,
+    load_action: MTLLoadAction,
+) {
+    let depth_attachment = descriptor.depth_attachment().unwrap();
+    depth_attachment.set_texture(Some(texture));
+    depth_attachment.set_slice(layer as u64);
+    depth_attachment.set_load_action(load_action);
+    depth_attachment.set_clear_depth(1.0);
+    depth_attachment.set_store_action(MTLStoreAction::Store);
+    // The depth-only pipelines also have a stencil attachment, unused here
+    let stencil_attachment = descriptor.stencil_attachment().unwrap();
+    stencil_attachment.set_texture(Some(texture));
+    stencil_attachment.set_slice(layer as u64);
+    stencil_attachment.set_load_action(MTLLoadAction::DontCare);
+    stencil_attachment.set_store_action(MTLStoreAction::DontCare);
+}
+
+/// The shadow map of a point or spot light.
+struct LightShadowMap {
+    frame: LightShadowFrame,
+    /// The depth texture array of the light's faces.
+    texture: Texture,
+    /// A bit per face whose shadow map has been drawn into this frame.
+    started_faces: u32,
+}
+
+/// Draws cascaded and light shadow maps and darkens the scene with them.
+pub struct ShadowMaps {
+    device: Device,
+    resolve_pipeline: RenderPipelineState,
+    light_resolve_pipeline: RenderPipelineState,
+    /// The depth texture array of the cascades, allocated while shadows are set.
+    texture: Option<Texture>,
+    frame: Option<ShadowMapFrame>,
+    /// A bit per cascade whose shadow map has been drawn into this frame.
+    started_cascades: u32,
+    /// The shadow maps of this frame's point and spot lights.
+    lights: Vec<LightShadowMap>,
+}
+
+impl ShadowMaps {
+    /// Creates new `ShadowMaps`, without shadows until a frame is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `ShadowMaps` or a `PipelineError`.
+    pub fn new(device: &Device) -> Result<Self, PipelineError> {
+        let library = load_metal_shader_library(device)?;
+        Ok(ShadowMaps {
+            device: device.clone(),
+            resolve_pipeline: create_pipeline(
+                device,
+                &library,
+                ("fullscreen_vertex", "shadow_map_fragment"),
+                &[COLOR_PIXEL_FORMAT],
+                Blend::Multiply,
+            )?,
+            light_resolve_pipeline: create_pipeline(
+                device,
+                &library,
+                ("fullscreen_vertex", "light_shadow_fragment"),
+                &[COLOR_PIXEL_FORMAT],
+                Blend::Multiply,
+            )?,
+            texture: None,
+            frame: None,
+            started_cascades: 0,
+            lights: Vec::new(),
+        })
+    }
+
+    /// Returns `true` if this frame has shadows.
+    pub fn is_enabled(&self) -> bool {
+        self.frame.is_some() || !self.lights.is_empty()
+    }
+
+    /// Sets the shadow constants of the current frame, or `None` for no
+    /// shadows, releasing the shadow maps.
+    pub fn set_frame(&mut self, frame: Option<&ShadowMapFrame>) {
+        self.frame = frame.copied();
+        self.started_cascades = 0;
+        if self.frame.is_none() {
+            self.texture = None;
+        }
+    }
+
+    /// Sets the shadows of the current frame's point and spot lights,
+    /// releasing the shadow maps of lights no longer shadowed.
+    ///
+    /// Shadow maps are reused by position in the list while their
+    /// resolution and face count match.
+    pub fn set_lights(&mut self, lights: &[LightShadowFrame]) {
+        self.lights.truncate(lights.len());
+        for (index, frame) in lights.iter().enumerate() {
+            let resolution = frame.resolution.max(1) as u64;
+            let faces = frame.constants.face_count.max(1) as u64;
+            let texture = match self.lights.get(index) {
+                Some(light)
+                    if light.texture.width() == resolution
+                        && light.texture.array_length() == faces =>
+                {
+                    light.texture.clone()
+                }
+                _ => self.new_texture("LightShadowMap", resolution, faces),
+            };
+            let shadow_map = LightShadowMap {
+                frame: *frame,
+                texture,
+                started_faces: 0,
+            };
+            match self.lights.get_mut(index) {
+                Some(light) => *light = shadow_map,
+                None => self.lights.push(shadow_map),
+            }
+        }
+    }
+
+    /// Returns the shadow map texture array, its layer and the load action of
+    /// a draw into a cascade or light.
+    ///
+    /// The first draw into a layer in a frame clears its shadow map and
+    /// later draws keep what earlier draws left.
+    ///
+    /// # Returns
+    ///
+    /// The texture array, layer and load action, or `None` if the target
+    /// has no shadow map this frame.
+    pub fn begin_draw(&mut self, target: ShadowTarget) -> Option<(Texture, u32, MTLLoadAction)> {
+        let (texture, layer, started) = match target {
+            ShadowTarget::Cascade(cascade) => {
+                let frame = self.frame?;
+                if cascade >= frame.constants.cascade_count {
+                    return None;
+                }
+                let texture = self.ensure_texture(&frame);
+                (texture, cascade, &mut self.started_cascades)
+            }
+            ShadowTarget::Light { index, face } => {
+                let light = self.lights.get_mut(index as usize)?;
+                if face >= light.frame.constants.face_count {
+                    return None;
+                }
+                (light.texture.clone(), face, &mut light.started_faces)
+            }
+        };
+        let load_action = if *started & (1 << layer) != 0 {
+            MTLLoadAction::Load
+        } else {
+            MTLLoadAction::Clear
+        };
+        *started |= 1 << layer;
+        Some((texture, layer, load_action))
+    }
+
+    /// Multiplies this frame's shadows onto the scene texture.
+    ///
+    /// Cascades and light faces nothing was drawn into this frame are
+    /// cleared first, so they shadow nothing rather than keep last frame's
+    /// casters.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer of the resolve.
+    /// * `scene` - The scene texture to darken.
+    /// * `depth` - The depth texture of the frame, giving the surface under each pixel.
+    pub fn encode_shadows(
+        &mut self,
+        command_buffer: &CommandBufferRef,
+        scene: &TextureRef,
+        depth: &TextureRef,
+    ) {
+        if let Some(frame) = self.frame {
+            let texture = self.ensure_texture(&frame);
+            clear_unstarted(
+                command_buffer,
+                &texture,
+                frame.constants.cascade_count,
+                self.started_cascades,
+            );
+            self.started_cascades = 0;
+            encode_resolve(
+                command_buffer,
+                &self.resolve_pipeline,
+                &frame.constants,
+                scene,
+                depth,
+                &texture,
+            );
+        }
+
+        for light in &mut self.lights {
+            clear_unstarted(
+                command_buffer,
+                &light.texture,
+                light.frame.constants.face_count,
+                light.started_faces,
+            );
+            light.started_faces = 0;
+            encode_resolve(
+                command_buffer,
+                &self.light_resolve_pipeline,
+                &light.frame.constants,
+                scene,
+                depth,
+                &light.texture,
+            );
+        }
+    }
+
+    /// Returns the bytes allocated for the shadow maps.
+    pub fn allocated_bytes(&self) -> u64 {
+        let lights: u64 = self
+            .lights
+            .iter()
+            .map(|light| light.texture.allocated_size())
+            .sum();
+        self.texture
+            .as_ref()
+            .map_or(0, |texture| texture.allocated_size())
+            + lights
+    }
+
+    /// Returns the texture array, reallocated if the resolution or cascade count changed.
+    fn ensure_texture(&mut self, frame: &ShadowMapFrame) -> Texture {
+        let resolution = frame.resolution.max(1) as u64;
+        let layers = frame.constants.cascade_count.max(1) as u64;
+        if let Some(texture) = self
+            .texture
+            .as_ref()
+            .filter(|texture| texture.width() == resolution && texture.array_length() == layers)
+        {
+            return texture.clone();
+        }
+
+        let texture = self.new_texture("ShadowMaps", resolution, layers);
+        self.started_cascades = 0;
+        self.texture = Some(texture.clone());
+        texture
+    }
+
+    /// Creates a depth texture array of square shadow maps.
+    fn new_texture(&self, label: &str, resolution: u64, layers: u64) -> Texture {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_texture_type(MTLTextureType::D2Array);
+        descriptor.set_width(resolution);
+        descriptor.set_height(resolution);
+        descriptor.set_array_length(layers);
+        // The format of the depth-only pipelines the casters are drawn with
+        descriptor.set_pixel_format(MTLPixelFormat::Depth32Float_Stencil8);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+        let texture = self.device.new_texture(&descriptor);
+        texture.set_label(label);
+        debug!("Created {label}: {layers} layers of {resolution}x{resolution}");
+        texture
+    }
+}
+
+/// Clears the layers of a shadow map array whose bit is not set in `started`.
+fn clear_unstarted(
+    command_buffer: &CommandBufferRef,
+    texture: &TextureRef,
+    layers: u32,
+    started: u32,
+) {
+    for layer in 0..layers {
+        if started & (1 << layer) == 0 {
+            let descriptor = RenderPassDescriptor::new();
+            attach_shadow_layer(descriptor, texture, layer, MTLLoadAction::Clear);
+            command_buffer
+                .new_render_command_encoder(descriptor)
+                .end_encoding();
+        }
+    }
+}
+
+/// Encodes a fullscreen pass multiplying a shadow map's shadows onto the scene.
+///
+/// # Arguments
+///
+/// * `command_buffer` - The command buffer of the resolve.
+/// * `pipeline` - The resolve pipeline of the shadow map's kind.
+/// * `constants` - The shadow constants, bound to fragment buffer 0.
+/// * `scene` - The scene texture to darken.
+/// * `depth` - The depth texture of the frame.
+/// * `shadow_maps` - The shadow map texture array.
+fn encode_resolve<T>(
+    command_buffer: &CommandBufferRef,
+    pipeline: &RenderPipelineState,
+    constants: &T,
+    scene: &TextureRef,
+    depth: &TextureRef,
+    shadow_maps: &TextureRef,
+) {
+    let descriptor = RenderPassDescriptor::new();
+    let attachment = descriptor.color_attachments().object_at(0).unwrap();
+    attachment.set_texture(Some(scene));
+    attachment.set_load_action(MTLLoadAction::Load);
+    attachment.set_store_action(MTLStoreAction::Store);
+
+    let encoder = command_buffer.new_render_command_encoder(descriptor);
+    encoder.set_render_pipeline_state(pipeline);
+    encoder.set_fragment_bytes(
+        0,
+        std::mem::size_of::<T>() as u64,
+        constants as *const T as *const std::ffi::c_void,
+    );
+    encoder.set_fragment_texture(0, Some(depth));
+    encoder.set_fragment_texture(1, Some(shadow_maps));
+    encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 3);
+    encoder.end_encoding();
+}
@@ -0,0 +1,157 @@
+//! Metal static mesh module.
+//!
+//! This module keeps the geometry of static meshes resident on the GPU. Each
+//! stream of a mesh, its shaded vertices, its position-only vertices and its
+//! indices, is written into a shared-storage staging buffer once and
+//! blit-copied into a private-storage buffer, which the GPU reads at full
+//! bandwidth but the CPU cannot touch. The copies are committed on the render
+//! command queue, so every draw submitted afterwards reads completed buffers.
+
+use super::buffer_manager::as_bytes;
+use crate::renderer::{render_queue::GeometryView, vertex_layout::VertexLayout};
+use log::{debug, warn};
+use metal::{BlitCommandEncoderRef, Buffer, CommandQueue, Device, MTLResourceOptions};
+use std::{collections::HashMap, ffi::c_void};
+
+/// The private buffers of a static mesh.
+pub struct StaticMesh {
+    pub vertices: Buffer,
+    /// The layout of the vertices in `vertices`.
+    pub layout: VertexLayout,
+    /// The position-only vertices, read by depth-only draws, if the mesh has them.
+    pub positions: Option<Buffer>,
+    pub indices: Option<Buffer>,
+}
+
+/// Uploads and holds the private buffers of static meshes.
+pub struct StaticMeshes {
+    device: Device,
+    /// The buffers of each uploaded mesh, by its index in `MeshStorage`.
+    meshes: HashMap<usize, StaticMesh>,
+}
+
+impl StaticMeshes {
+    /// Creates new `StaticMeshes`, holding no meshes.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device the buffers are allocated on.
+    pub fn new(device: &Device) -> Self {
+        StaticMeshes {
+            device: device.clone(),
+            meshes: HashMap::new(),
+        }
+    }
+
+    /// Copies a mesh's geometry into private buffers, replacing any it had.
+    ///
+    /// Meshes without vertices are not uploaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_queue` - The queue the copies are committed to, ahead of later draws.
+    /// * `mesh` - The index of the mesh in `MeshStorage`.
+    /// * `geometry` - The mesh's geometry.
+    pub fn upload(
+        &mut self,
+        command_queue: &CommandQueue,
+        mesh: usize,
+        geometry: &GeometryView<'_>,
+    ) {
+        let (vertex_bytes, layout) = match geometry.packed_vertices {
+            Some(packed_vertices) => (packed_vertices.data.as_slice(), packed_vertices.layout),
+            None => (as_bytes(geometry.vertices), VertexLayout::default()),
+        };
+        if vertex_bytes.is_empty() {
+            warn!("Static mesh {mesh} has no vertices, drawing it from the shared buffers");
+            return;
+        }
+
+        let command_buffer = command_queue.new_command_buffer();
+        command_buffer.set_label("StaticMeshUpload");
+        let blit = command_buffer.new_blit_command_encoder();
+        let copy = |bytes: &[u8], label: &str| self.copy_to_private(blit, bytes, label);
+        let vertices = copy(vertex_bytes, "StaticVertex");
+        let positions = geometry
+            .positions
+            .and_then(|positions| copy(&positions.data, "StaticPosition"));
+        let indices = geometry
+            .indices
+            .and_then(|indices| copy(as_bytes(indices), "StaticIndex"));
+        blit.end_encoding();
+        // The staging buffers are retained by the command buffer until it completes
+        command_buffer.commit();
+
+        let Some(vertices) = vertices else {
+            return;
+        };
+        debug!(
+            "Uploaded static mesh {mesh}: {} vertex bytes, {} index bytes",
+            vertices.length(),
+            indices.as_ref().map_or(0, |indices| indices.length())
+        );
+        self.meshes.insert(
+            mesh,
+            StaticMesh {
+                vertices,
+                layout,
+                positions,
+                indices,
+            },
+        );
+    }
+
+    /// Returns the private buffers of an uploaded mesh.
+    pub fn get(&self, mesh: usize) -> Option<&StaticMesh> {
+        self.meshes.get(&mesh)
+    }
+
+    /// Returns the bytes allocated for the vertex and index buffers of every mesh.
+    pub fn allocated_bytes(&self) -> (u64, u64) {
+        self.meshes
+            .values()
+            .fold((0, 0), |(vertex_bytes, index_bytes), mesh| {
+                let positions = mesh
+                    .positions
+                    .as_ref()
+                    .map_or(0, |buffer| buffer.allocated_size());
+                let indices = mesh
+                    .indices
+                    .as_ref()
+                    .map_or(0, |buffer| buffer.allocated_size());
+                (
+                    vertex_bytes + mesh.vertices.allocated_size() + positions,
+                    index_bytes + indices,
+                )
+            })
+    }
+
+    /// Encodes a copy of some bytes through a staging buffer into a new private buffer.
+    ///
+    /// # Returns
+    ///
+    /// The private buffer, or `None` if there are no bytes to copy.
+    fn copy_to_private(
+        &self,
+        blit: &BlitCommandEncoderRef,
+        bytes: &[u8],
+        label: &str,
+    ) -> Option<Buffer> {
+        if bytes.is_empty() {
+            return None;
+        }
+        let length = bytes.len() as u64;
+        let staging = self.device.new_buffer_with_data(
+            bytes.as_ptr() as *const c_void,
+            length,
+            MTLResourceOptions::StorageModeShared,
+        );
+        staging.set_label("StaticMeshStaging");
+        let buffer = self
+            .device
+            .new_buffer(length, MTLResourceOptions::StorageModePrivate);
+        buffer.set_label(label);
+        blit.copy_from_buffer(&staging, 0, &buffer, 0, length);
+        Some(buffer)
+    }
+}
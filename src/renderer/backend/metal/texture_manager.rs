@@ -2,11 +2,13 @@ use std::num::NonZeroU32;
 
 use metal::{Device, MTLRegion, Texture, TextureDescriptor};
 
-use crate::renderer::{common::TextureId, RendererError};
+use super::texture_upload::TextureUploader;
+use crate::renderer::{common::TextureId, error::AssetError, texture_upload::TextureUpload};
 
 pub struct TextureManager {
     device: Device,
     textures: Vec<Option<Texture>>,
+    uploader: TextureUploader,
 }
 
 impl TextureManager {
@@ -14,6 +16,7 @@ impl TextureManager {
         TextureManager {
             device: device.clone(),
             textures: Vec::new(),
+            uploader: TextureUploader::new(device),
         }
     }
 
@@ -24,6 +27,45 @@ impl TextureManager {
         id
     }
 
+    pub fn get_texture(&self, id: TextureId) -> Option<&Texture> {
+        self.textures
+            .get(id.0.get() as usize - 1)
+            .and_then(|texture| texture.as_ref())
+    }
+
+    /// Queues an upload into a texture in the background.
+    pub fn upload_texture(&mut self, upload: TextureUpload) -> Result<(), AssetError> {
+        let texture = self
+            .get_texture(upload.texture)
+            .ok_or(AssetError::InvalidTexture(upload.texture))?
+            .clone();
+        self.uploader.upload(texture, upload);
+        Ok(())
+    }
+
+    /// Returns `true` if a texture has background uploads still in flight.
+    pub fn is_uploading(&self, id: TextureId) -> bool {
+        self.uploader.is_pending(id)
+    }
+
+    /// Returns the textures whose background uploads completed since the last poll.
+    pub fn poll_uploads(&mut self) -> Vec<TextureId> {
+        self.uploader.poll()
+    }
+
+    /// Returns the number of textures with background uploads still in flight.
+    pub fn pending_uploads(&self) -> usize {
+        self.uploader.pending_count()
+    }
+
+    pub fn allocated_bytes(&self) -> u64 {
+        self.textures
+            .iter()
+            .flatten()
+            .map(|texture| texture.allocated_size())
+            .sum()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn update_texture(
         &self,
@@ -34,7 +76,7 @@ impl TextureManager {
         bytes: &[u8],
         bytes_per_row: u64,
         bytes_per_image: u64,
-    ) -> Result<(), RendererError> {
+    ) -> Result<(), AssetError> {
         if let Some(Some(texture)) = self.textures.get(id.0.get() as usize - 1) {
             texture.replace_region_in_slice(
                 region,
@@ -46,7 +88,7 @@ impl TextureManager {
             );
             Ok(())
         } else {
-            Err(RendererError::InvalidTextureId)
+            Err(AssetError::InvalidTexture(id))
         }
     }
 }
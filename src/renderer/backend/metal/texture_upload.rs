@@ -0,0 +1,188 @@
+//! Metal texture upload module.
+//!
+//! This module uploads texture pixels off the render thread. A loader thread
+//! produces each upload's pixels, fills a shared-storage staging buffer with
+//! them and blit-encodes the copy into the texture, typically private
+//! storage, on a dedicated transfer command queue. Each upload's command
+//! buffer then signals a shared event with the upload's ticket. The event
+//! fences the transfer queue: the render thread polls its signaled value to
+//! learn which textures are complete, never waiting on the loader or the GPU.
+
+use crate::renderer::{common::TextureId, texture_upload::TextureUpload};
+use log::{debug, error, trace};
+use metal::{CommandQueue, Device, MTLBlitOption, MTLResourceOptions, SharedEvent, Texture};
+use objc::rc::autoreleasepool;
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+/// An upload queued on the loader thread.
+struct UploadJob {
+    ticket: u64,
+    texture: Texture,
+    upload: TextureUpload,
+}
+
+/// Uploads texture pixels in the background through staging buffers.
+pub struct TextureUploader {
+    jobs: Sender<UploadJob>,
+    /// The textures and messages of uploads that failed to load.
+    failures: Receiver<(TextureId, String)>,
+    /// Signaled by the transfer queue with each upload's ticket once its copy completes.
+    fence: SharedEvent,
+    next_ticket: u64,
+    /// The ticket of each texture's latest upload, until it completes.
+    pending: HashMap<TextureId, u64>,
+}
+
+impl TextureUploader {
+    /// Creates a new `TextureUploader`, starting its loader thread and transfer queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device the textures belong to.
+    pub fn new(device: &Device) -> Self {
+        let fence = device.new_shared_event();
+        fence.set_signaled_value(0);
+        let transfer_queue = device.new_command_queue();
+        transfer_queue.set_label("TransferQueue");
+
+        let (jobs, job_receiver) = mpsc::channel::<UploadJob>();
+        let (failure_sender, failures) = mpsc::channel();
+        let (loader_device, loader_fence) = (device.clone(), fence.clone());
+        thread::spawn(move || {
+            for job in job_receiver {
+                let texture_id = job.upload.texture;
+                let result = autoreleasepool(|| {
+                    encode_upload(&loader_device, &transfer_queue, &loader_fence, job)
+                });
+                if let Err(message) = result {
+                    if failure_sender.send((texture_id, message)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        debug!("Texture uploader started");
+
+        TextureUploader {
+            jobs,
+            failures,
+            fence,
+            next_ticket: 1,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Queues an upload into a texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `texture` - The texture of `upload.texture`.
+    /// * `upload` - The pixels and region to upload.
+    pub fn upload(&mut self, texture: Texture, upload: TextureUpload) {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        let id = upload.texture;
+        trace!("Queued upload {ticket} into texture {:?}", id);
+        if self
+            .jobs
+            .send(UploadJob {
+                ticket,
+                texture,
+                upload,
+            })
+            .is_ok()
+        {
+            self.pending.insert(id, ticket);
+        } else {
+            error!("The texture loader thread has stopped, dropping the upload");
+        }
+    }
+
+    /// Returns `true` if a texture has uploads still in flight.
+    pub fn is_pending(&self, id: TextureId) -> bool {
+        self.pending.contains_key(&id)
+    }
+
+    /// Returns the number of textures with uploads still in flight.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Logs failed uploads and returns the textures whose uploads completed since the last poll.
+    pub fn poll(&mut self) -> Vec<TextureId> {
+        for (id, message) in self.failures.try_iter() {
+            error!("Failed to upload texture {}: {message}", id.0);
+        }
+        let signaled = self.fence.signaled_value();
+        let completed: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, &ticket)| ticket <= signaled)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &completed {
+            self.pending.remove(id);
+        }
+        if !completed.is_empty() {
+            debug!("{} texture uploads completed", completed.len());
+        }
+        completed
+    }
+}
+
+/// Copies an upload's pixels into its texture on the transfer queue and signals its ticket.
+///
+/// The ticket is signaled even if the upload fails to load, as the fence's
+/// value only moves forward and later uploads wait behind it.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a message describing why the upload failed.
+fn encode_upload(
+    device: &Device,
+    transfer_queue: &CommandQueue,
+    fence: &SharedEvent,
+    job: UploadJob,
+) -> Result<(), String> {
+    let UploadJob {
+        ticket,
+        texture,
+        upload,
+    } = job;
+    let (region, mipmap_level, slice) = (upload.region, upload.mipmap_level, upload.slice);
+    let (bytes_per_row, bytes_per_image) = (upload.bytes_per_row, upload.bytes_per_image);
+    let command_buffer = transfer_queue.new_command_buffer();
+    command_buffer.set_label("TextureUpload");
+
+    let result = upload.load().map(|bytes| {
+        let staging = device.new_buffer_with_data(
+            bytes.as_ptr() as *const c_void,
+            bytes.len() as u64,
+            MTLResourceOptions::StorageModeShared,
+        );
+        staging.set_label("TextureStaging");
+        let blit = command_buffer.new_blit_command_encoder();
+        blit.copy_from_buffer_to_texture(
+            &staging,
+            0,
+            bytes_per_row,
+            bytes_per_image,
+            region.size,
+            &texture,
+            slice,
+            mipmap_level,
+            region.origin,
+            MTLBlitOption::empty(),
+        );
+        blit.end_encoding();
+    });
+
+    command_buffer.encode_signal_event(fence, ticket);
+    command_buffer.commit();
+    result
+}
@@ -0,0 +1,202 @@
+//! Metal order-independent transparency module.
+//!
+//! This module implements weighted blended order-independent transparency.
+//! Transparent draws render into two targets instead of the scene texture:
+//! an accumulation target summing every fragment's premultiplied color and
+//! alpha by a depth-based weight, and a revealage target multiplying how much
+//! of the scene remains visible. At the end of the frame a fullscreen pass
+//! composites the weighted average color over the scene, so the result does
+//! not depend on the order transparent draws were submitted in.
+
+use super::pipeline::{load_metal_shader_library, COLOR_PIXEL_FORMAT};
+use super::post_process::{create_pipeline, Blend};
+use crate::renderer::error::PipelineError;
+use log::debug;
+use metal::{
+    CommandBufferRef, Device, MTLClearColor, MTLLoadAction, MTLPixelFormat, MTLPrimitiveType,
+    MTLStorageMode, MTLStoreAction, MTLTextureUsage, RenderPassDescriptor, RenderPassDescriptorRef,
+    RenderPipelineState, Texture, TextureDescriptor, TextureRef,
+};
+
+/// The format of the accumulated weighted color and alpha.
+pub const ACCUMULATION_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::RGBA16Float;
+
+/// The format of the product of one minus every fragment's alpha.
+pub const REVEALAGE_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::R16Float;
+
+/// The accumulation and revealage targets of weighted blended transparency.
+struct Targets {
+    accumulation: Texture,
+    revealage: Texture,
+}
+
+/// Accumulates transparent draws and composites them over the scene.
+pub struct WeightedBlended {
+    device: Device,
+    composite_pipeline: RenderPipelineState,
+    enabled: bool,
+    targets: Option<Targets>,
+    /// Whether a transparent draw has accumulated into the targets this frame.
+    frame_started: bool,
+}
+
+impl WeightedBlended {
+    /// Creates a new, disabled `WeightedBlended`.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The Metal device.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `WeightedBlended` or a `PipelineError`.
+    pub fn new(device: &Device) -> Result<Self, PipelineError> {
+        let library = load_metal_shader_library(device)?;
+        Ok(WeightedBlended {
+            device: device.clone(),
+            composite_pipeline: create_pipeline(
+                device,
+                &library,
+                ("fullscreen_vertex", "weighted_blended_composite_fragment"),
+                &[COLOR_PIXEL_FORMAT],
+                Blend::Over,
+            )?,
+            enabled: false,
+            targets: None,
+            frame_started: false,
+        })
+    }
+
+    /// Enables or disables accumulating transparent draws, releasing the targets when disabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.targets = None;
+            self.frame_started = false;
+        }
+    }
+
+    /// Returns `true` if transparent draws are accumulated.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Attaches the accumulation and revealage targets to a transparent draw's pass.
+    ///
+    /// The first transparent draw of a frame clears both targets and later
+    /// draws keep what earlier draws accumulated.
+    ///
+    /// # Arguments
+    ///
+    /// * `descriptor` - The render pass of the draw, whose first two color attachments are set.
+    /// * `width` - The width of the scene in pixels.
+    /// * `height` - The height of the scene in pixels.
+    ///
+    /// # Returns
+    ///
+    /// The accumulation target, giving the size of the pass.
+    pub fn begin_draw(
+        &mut self,
+        descriptor: &RenderPassDescriptorRef,
+        width: u64,
+        height: u64,
+    ) -> Texture {
+        let load_action = if std::mem::replace(&mut self.frame_started, true) {
+            MTLLoadAction::Load
+        } else {
+            MTLLoadAction::Clear
+        };
+        let targets = self.ensure_targets(width, height);
+
+        // Nothing accumulated yet, and the scene fully revealed
+        for (index, texture, clear) in [
+            (0, &targets.accumulation, 0.0),
+            (1, &targets.revealage, 1.0),
+        ] {
+            let attachment = descriptor.color_attachments().object_at(index).unwrap();
+            attachment.set_texture(Some(texture));
+            attachment.set_load_action(load_action);
+            attachment.set_clear_color(MTLClearColor::new(clear, clear, clear, clear));
+            attachment.set_store_action(MTLStoreAction::Store);
+        }
+        targets.accumulation.clone()
+    }
+
+    /// Composites this frame's transparent draws over the scene texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_buffer` - The command buffer of the resolve.
+    /// * `scene` - The scene texture to blend onto.
+    pub fn encode_composite(&mut self, command_buffer: &CommandBufferRef, scene: &TextureRef) {
+        if !std::mem::take(&mut self.frame_started) {
+            return;
+        }
+        let Some(targets) = &self.targets else {
+            return;
+        };
+
+        let descriptor = RenderPassDescriptor::new();
+        let attachment = descriptor.color_attachments().object_at(0).unwrap();
+        attachment.set_texture(Some(scene));
+        attachment.set_load_action(MTLLoadAction::Load);
+        attachment.set_store_action(MTLStoreAction::Store);
+
+        let encoder = command_buffer.new_render_command_encoder(descriptor);
+        encoder.set_render_pipeline_state(&self.composite_pipeline);
+        encoder.set_fragment_texture(0, Some(&targets.accumulation));
+        encoder.set_fragment_texture(1, Some(&targets.revealage));
+        encoder.draw_primitives(MTLPrimitiveType::Triangle, 0, 3);
+        encoder.end_encoding();
+    }
+
+    /// Returns the bytes allocated for the accumulation and revealage targets.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.targets.as_ref().map_or(0, |targets| {
+            targets.accumulation.allocated_size() + targets.revealage.allocated_size()
+        })
+    }
+
+    fn ensure_targets(&mut self, width: u64, height: u64) -> &Targets {
+        let resize = self.targets.as_ref().is_none_or(|targets| {
+            targets.accumulation.width() != width || targets.accumulation.height() != height
+        });
+        if resize {
+            self.targets = Some(Targets {
+                accumulation: self.new_render_target(
+                    width,
+                    height,
+                    ACCUMULATION_PIXEL_FORMAT,
+                    "TransparencyAccumulation",
+                ),
+                revealage: self.new_render_target(
+                    width,
+                    height,
+                    REVEALAGE_PIXEL_FORMAT,
+                    "TransparencyRevealage",
+                ),
+            });
+        }
+        self.targets.as_ref().unwrap()
+    }
+
+    fn new_render_target(
+        &self,
+        width: u64,
+        height: u64,
+        pixel_format: MTLPixelFormat,
+        label: &str,
+    ) -> Texture {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width);
+        descriptor.set_height(height);
+        descriptor.set_pixel_format(pixel_format);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+
+        let texture = self.device.new_texture(&descriptor);
+        texture.set_label(label);
+        debug!("Created {} texture: {}x{}", label, width, height);
+        texture
+    }
+}
@@ -5,20 +5,57 @@
 //! re-exports the specific backend implementations.
 //!
 //! The `GraphicsBackend` trait defines methods for:
-//! - Rendering operations
-//! - Buffer management (vertex, index, uniform, and instance buffers)
-//! - Texture creation and updates
-//! - Render pipeline state creation
+//! - Rendering operations, including depth-only draws for the depth pre-pass
+//! - Buffer management (vertex, index, uniform, frame constant, and instance buffers)
+//!   and GPU-resident buffers of static meshes
+//! - Texture creation and updates, and background texture uploads
+//! - Material table updates and per-draw material selection
+//! - Per-draw render state (depth, culling, depth bias and stencil) and outlines
+//! - The rectangle of the frame draws render into, for split-screen views
+//! - Render target textures draws render into instead of the frame, for planar reflections
+//! - GPU culling of instanced draws and the depth pyramid for occlusion culling
+//! - Wireframe mode, debug view selection and GPU frame captures
+//! - Vsync, frame pacing, and the size and scale factor of presented frames
+//! - Post-process anti-aliasing, sky, atmospheric scattering, lens flares and the end of each frame
+//! - The 2D canvas overlay drawn over each frame
+//! - Ray tracing capability queries, acceleration structures and ray-traced shadows
+//! - Cascaded shadow maps, point and spot light shadow maps, and the depth-only draws of shadow casters
+//! - Frame readback for recording
+//! - GPU memory usage reporting
+//! - Render pipeline state creation, asynchronous compilation and warm-up
 //!
 //! Implementations of this trait allow the renderer to work with different
-//! graphics APIs in a unified manner.
+//! graphics APIs in a unified manner. `NullBackend` records calls instead of
+//! rendering, so the renderer can be tested without a GPU.
 
 pub mod metal;
+pub mod null;
 pub mod vulkan;
 
 use super::{
-    common::{BackendDrawCommand, RendererError, TextureId, Uniforms, Vertex},
-    render_queue::InstanceData,
+    atmosphere::AtmosphereConstants,
+    canvas::CanvasFrame,
+    common::{
+        BackendDrawCommand, CaptureDestination, FrameConstants, RenderTarget, TemporalConstants,
+        TextureId, Uniforms, Vertex,
+    },
+    config::{AntiAliasing, Transparency},
+    debug_view::DebugView,
+    error::RendererError,
+    gpu_culling::CullingConstants,
+    lens_flare::LensFlareFrame,
+    lights::LightShadowFrame,
+    material_manager::{Material, MaterialId},
+    memory_report::GpuMemoryReport,
+    ray_tracing::RayTracingFrame,
+    recording::FrameImage,
+    render_queue::{GeometryView, InstanceData},
+    render_state::{Outline, RenderState},
+    shadow_map::{ShadowMapFrame, ShadowTarget},
+    sky::SkyConstants,
+    texture_upload::TextureUpload,
+    vertex_layout::{PackedVertices, VertexLayout},
+    viewport::Viewport,
 };
 use ::metal::{MTLRegion, RenderPassDescriptorRef, RenderPipelineDescriptor, TextureDescriptor};
 
@@ -31,12 +68,100 @@ pub trait GraphicsBackend {
     #[allow(dead_code)]
     fn render_pass(&mut self, descriptor: &RenderPassDescriptorRef) -> Result<(), RendererError>;
     fn draw(&mut self, draw_command: BackendDrawCommand) -> Result<(), RendererError>;
+    /// Draws only the depth of a draw command, reading position-only vertices.
+    fn draw_depth(&mut self, draw_command: BackendDrawCommand) -> Result<(), RendererError>;
 
     fn update_vertex_buffer(&mut self, vertices: &[Vertex]) -> Result<(), RendererError>;
+    /// Uploads vertices packed in a custom layout, which subsequent draws read until
+    /// the next vertex buffer update.
+    fn update_packed_vertex_buffer(
+        &mut self,
+        vertices: &PackedVertices,
+    ) -> Result<(), RendererError>;
     fn update_index_buffer(&mut self, indices: &[u32]) -> Result<(), RendererError>;
+    /// Uploads a static mesh's geometry once into GPU-resident buffers, which
+    /// `bind_static_mesh` binds in place of per-draw buffer updates.
+    fn upload_static_mesh(&mut self, mesh: usize, geometry: GeometryView<'_>);
+    /// Binds a static mesh's resident vertex and index buffers, or its
+    /// position-only vertices if `positions` is set, which subsequent draws
+    /// read until the next vertex buffer update. Returns `false` if the mesh
+    /// is not resident and its geometry must be uploaded per draw.
+    fn bind_static_mesh(&mut self, mesh: usize, positions: bool) -> bool;
     fn update_uniform_buffer(&mut self, uniforms: &Uniforms) -> Result<(), RendererError>;
+    fn update_frame_constants(&mut self, constants: &FrameConstants) -> Result<(), RendererError>;
     fn update_instance_buffer(&mut self, instances: &[InstanceData]) -> Result<(), RendererError>;
 
+    fn update_materials(&mut self, materials: &[Material]) -> Result<(), RendererError>;
+    fn set_material(&mut self, material_id: MaterialId);
+    fn set_render_state(&mut self, render_state: RenderState);
+    fn set_outline(&mut self, outline: Option<Outline>);
+    fn set_instance_culling(&mut self, culling: Option<&CullingConstants>);
+    fn set_depth_pyramid(&mut self, enabled: bool);
+    fn set_depth_prepass(&mut self, enabled: bool);
+    fn set_debug_view(&mut self, debug_view: DebugView);
+    fn toggle_wireframe_mode(&mut self);
+
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing);
+    fn set_transparency(&mut self, transparency: Transparency);
+    fn set_render_scale(&mut self, scale: f32);
+    /// Sets whether frames are presented in sync with the display's refresh.
+    fn set_vsync(&mut self, enabled: bool);
+    /// Paces presented frames to a frame rate in hertz, or presents them as
+    /// soon as they finish with `None`.
+    fn set_frame_rate(&mut self, frame_rate: Option<f32>);
+    /// Sets the size of the frames presented, in physical pixels.
+    fn resize(&mut self, width: u32, height: u32);
+    /// Sets the number of physical pixels per logical point of the display.
+    fn set_scale_factor(&mut self, scale_factor: f64);
+    /// Sets the rectangle of the frame later draws render into, until the end of the frame.
+    fn set_viewport(&mut self, viewport: Viewport);
+    /// Creates a texture of the given size that draws can render into and materials sample.
+    fn create_render_target(&mut self, width: u32, height: u32) -> TextureId;
+    /// Renders later draws into a render target, or into the frame again with `None`.
+    ///
+    /// Draws into a render target skip post-processing, outlines and the
+    /// depth pre-pass. The first draw after setting a target clears it.
+    fn set_render_target(&mut self, target: Option<RenderTarget>);
+    fn set_temporal_constants(&mut self, constants: &TemporalConstants);
+    fn set_lens_flare(&mut self, lens_flare: Option<&LensFlareFrame>);
+    fn set_atmosphere(&mut self, atmosphere: Option<&AtmosphereConstants>);
+    fn set_sky(&mut self, sky: Option<&SkyConstants>);
+    /// Sets the canvas drawn over the resolved frame, or `None` for no overlay.
+    fn set_overlay(&mut self, overlay: Option<&CanvasFrame>);
+    fn end_frame(&mut self) -> Result<(), RendererError>;
+
+    fn supports_ray_tracing(&self) -> bool;
+    fn build_ray_tracing_mesh(&mut self, mesh: usize, geometry: GeometryView<'_>);
+    fn set_ray_tracing_frame(&mut self, frame: Option<&RayTracingFrame>);
+
+    /// Sets the cascaded shadow maps of the current frame, or `None` for no shadows.
+    ///
+    /// Shadow maps are cleared with every frame set, before casters are drawn.
+    fn set_shadow_maps(&mut self, frame: Option<&ShadowMapFrame>);
+    /// Sets the shadow maps of the current frame's point and spot lights,
+    /// releasing them when empty.
+    ///
+    /// Shadow maps are cleared with every frame set, before casters are drawn.
+    fn set_light_shadows(&mut self, lights: &[LightShadowFrame]);
+    /// Draws only the depth of a draw command into a cascade's or light's
+    /// shadow map, reading position-only vertices.
+    fn draw_shadow_caster(
+        &mut self,
+        target: ShadowTarget,
+        draw_command: BackendDrawCommand,
+    ) -> Result<(), RendererError>;
+
+    fn begin_frame_capture(
+        &mut self,
+        destination: &CaptureDestination,
+    ) -> Result<(), RendererError>;
+    fn end_frame_capture(&mut self);
+
+    fn set_frame_readback(&mut self, enabled: bool);
+    fn finish_frame_readback(&mut self, wait: bool) -> Vec<FrameImage>;
+
+    fn gpu_memory_report(&self) -> GpuMemoryReport;
+
     #[allow(dead_code)]
     fn create_texture(&mut self, descriptor: &TextureDescriptor) -> TextureId;
 
@@ -52,10 +177,25 @@ pub trait GraphicsBackend {
         bytes_per_row: u64,
         bytes_per_image: u64,
     ) -> Result<(), RendererError>;
+    /// Queues an upload into a texture, copied in the background without
+    /// stalling the render thread. Materials show a placeholder in place of
+    /// the texture until its uploads complete.
+    fn upload_texture(&mut self, upload: TextureUpload) -> Result<(), RendererError>;
+    /// Returns the textures whose background uploads completed since the last call.
+    fn poll_texture_uploads(&mut self) -> Vec<TextureId>;
+    /// Returns the number of textures with background uploads still in flight.
+    fn pending_texture_uploads(&self) -> usize;
 
     #[allow(dead_code)]
     fn create_render_pipeline_state(
         &mut self,
         descriptor: &RenderPipelineDescriptor,
     ) -> Result<(), RendererError>;
+    /// Sets whether new pipeline permutations compile in the background,
+    /// drawing with a fallback pipeline until they are ready.
+    fn set_async_pipelines(&mut self, enabled: bool);
+    /// Compiles the pipeline permutations drawing vertices of the given layouts ahead of use.
+    fn warm_up_pipelines(&mut self, layouts: &[VertexLayout]) -> Result<(), RendererError>;
+    /// Returns the number of pipeline permutations still compiling.
+    fn pending_pipelines(&self) -> usize;
 }
@@ -0,0 +1,504 @@
+//! Null backend implementation for the renderer.
+//!
+//! `NullBackend` implements `GraphicsBackend` without touching the GPU. Every
+//! call is recorded as a `BackendCall` instead, so the render path (culling,
+//! sorting and queue building) can be run and inspected in tests and on
+//! machines without Metal.
+
+use crate::renderer::{
+    atmosphere::AtmosphereConstants,
+    backend::GraphicsBackend,
+    canvas::CanvasFrame,
+    common::{
+        BackendDrawCommand, CaptureDestination, FrameConstants, RenderTarget, TemporalConstants,
+        TextureId, Uniforms, Vertex,
+    },
+    config::{AntiAliasing, Transparency},
+    debug_view::DebugView,
+    gpu_culling::CullingConstants,
+    lens_flare::LensFlareFrame,
+    lights::LightShadowFrame,
+    material_manager::{Material, MaterialId},
+    memory_report::GpuMemoryReport,
+    ray_tracing::RayTracingFrame,
+    recording::FrameImage,
+    render_queue::GeometryView,
+    render_state::{Outline, RenderState},
+    shadow_map::{ShadowMapFrame, ShadowTarget},
+    sky::SkyConstants,
+    texture_upload::TextureUpload,
+    vertex_layout::{PackedVertices, VertexLayout},
+    viewport::Viewport,
+    InstanceData, RendererError,
+};
+use metal::{MTLRegion, RenderPassDescriptorRef, RenderPipelineDescriptor, TextureDescriptor};
+use std::{collections::HashMap, num::NonZeroU32};
+
+/// A call made to a `NullBackend`, with the data needed to check it.
+///
+/// Buffer updates record their contents where they are small, and their
+/// element count otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendCall {
+    RenderPass,
+    Draw(BackendDrawCommand),
+    DrawDepth(BackendDrawCommand),
+    UpdateVertexBuffer { count: usize },
+    UpdatePackedVertexBuffer { layout: VertexLayout, count: usize },
+    UpdateIndexBuffer { count: usize },
+    UploadStaticMesh(usize),
+    BindStaticMesh { mesh: usize, positions: bool },
+    UpdateUniformBuffer(Uniforms),
+    UpdateFrameConstants(FrameConstants),
+    UpdateInstanceBuffer { count: usize },
+    UpdateMaterials { count: usize },
+    SetMaterial(MaterialId),
+    SetRenderState(RenderState),
+    SetOutline(Option<Outline>),
+    SetInstanceCulling(Option<CullingConstants>),
+    SetDepthPyramid(bool),
+    SetDepthPrepass(bool),
+    SetDebugView(DebugView),
+    ToggleWireframeMode,
+    SetAntiAliasing(AntiAliasing),
+    SetTransparency(Transparency),
+    SetRenderScale(f32),
+    SetVsync(bool),
+    SetFrameRate(Option<f32>),
+    Resize(u32, u32),
+    SetScaleFactor(f64),
+    SetViewport(Viewport),
+    CreateRenderTarget(TextureId, u32, u32),
+    SetRenderTarget(Option<RenderTarget>),
+    SetTemporalConstants(TemporalConstants),
+    SetLensFlare(Option<LensFlareFrame>),
+    SetAtmosphere(Option<AtmosphereConstants>),
+    SetSky(Option<SkyConstants>),
+    SetOverlay(Option<CanvasFrame>),
+    EndFrame,
+    BuildRayTracingMesh(usize),
+    SetRayTracingFrame(Option<RayTracingFrame>),
+    SetShadowMaps(Option<ShadowMapFrame>),
+    SetLightShadows(Vec<LightShadowFrame>),
+    DrawShadowCaster(ShadowTarget, BackendDrawCommand),
+    BeginFrameCapture(CaptureDestination),
+    EndFrameCapture,
+    SetFrameReadback(bool),
+    FinishFrameReadback { wait: bool },
+    CreateTexture(TextureId),
+    UpdateTexture(TextureId),
+    UploadTexture(TextureId),
+    CreateRenderPipelineState,
+    SetAsyncPipelines(bool),
+    WarmUpPipelines(Vec<VertexLayout>),
+}
+
+/// A graphics backend that records calls instead of rendering.
+#[derive(Default)]
+pub struct NullBackend {
+    calls: Vec<BackendCall>,
+    texture_count: u32,
+    ray_tracing: bool,
+    size: (u32, u32),
+    frame_readback: bool,
+    /// Blank frames of the frame size, "copied back" at the end of each frame while readback is on.
+    frames: Vec<FrameImage>,
+    /// Textures uploaded since the last poll, whose uploads complete immediately.
+    uploaded: Vec<TextureId>,
+    /// The vertex layout of each uploaded static mesh.
+    static_meshes: HashMap<usize, VertexLayout>,
+}
+
+impl NullBackend {
+    /// Creates a new `NullBackend` with no recorded calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the backend reports ray tracing support.
+    pub fn set_ray_tracing_supported(&mut self, supported: bool) {
+        self.ray_tracing = supported;
+    }
+
+    /// Returns every call recorded since creation or the last `clear`.
+    pub fn calls(&self) -> &[BackendCall] {
+        &self.calls
+    }
+
+    /// Returns the recorded draw commands, in submission order.
+    pub fn draws(&self) -> impl Iterator<Item = &BackendDrawCommand> {
+        self.calls.iter().filter_map(|call| match call {
+            BackendCall::Draw(draw_command) => Some(draw_command),
+            _ => None,
+        })
+    }
+
+    /// Returns the material selected for each draw, in submission order.
+    pub fn draw_materials(&self) -> Vec<MaterialId> {
+        let mut material_id = MaterialId(0);
+        let mut materials = Vec::new();
+        for call in &self.calls {
+            match call {
+                BackendCall::SetMaterial(id) => material_id = *id,
+                BackendCall::Draw(_) => materials.push(material_id),
+                _ => {}
+            }
+        }
+        materials
+    }
+
+    /// Returns the number of draws that would switch pipeline states.
+    ///
+    /// The pipeline depends on whether a draw is instanced and outlined, and
+    /// on its render state and vertex layout, like in the Metal backend.
+    pub fn pipeline_switches(&self) -> usize {
+        let mut state = (false, None, RenderState::default(), VertexLayout::default());
+        let mut current = None;
+        let mut switches = 0;
+        for call in &self.calls {
+            match call {
+                BackendCall::SetOutline(outline) => state.1 = *outline,
+                BackendCall::SetRenderState(render_state) => state.2 = *render_state,
+                BackendCall::UpdateVertexBuffer { .. } => state.3 = VertexLayout::default(),
+                BackendCall::UpdatePackedVertexBuffer { layout, .. } => state.3 = *layout,
+                BackendCall::BindStaticMesh { mesh, positions } => {
+                    state.3 = if *positions {
+                        VertexLayout::position()
+                    } else {
+                        self.static_meshes[mesh]
+                    }
+                }
+                BackendCall::Draw(draw_command) => {
+                    state.0 = draw_command.is_instanced();
+                    if current != Some(state) {
+                        current = Some(state);
+                        switches += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        switches
+    }
+
+    /// Forgets all recorded calls.
+    pub fn clear(&mut self) {
+        self.calls.clear();
+    }
+}
+
+impl GraphicsBackend for NullBackend {
+    fn render_pass(&mut self, _descriptor: &RenderPassDescriptorRef) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::RenderPass);
+        Ok(())
+    }
+
+    fn draw(&mut self, draw_command: BackendDrawCommand) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::Draw(draw_command));
+        Ok(())
+    }
+
+    fn draw_depth(&mut self, draw_command: BackendDrawCommand) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::DrawDepth(draw_command));
+        Ok(())
+    }
+
+    fn update_vertex_buffer(&mut self, vertices: &[Vertex]) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UpdateVertexBuffer {
+            count: vertices.len(),
+        });
+        Ok(())
+    }
+
+    fn update_packed_vertex_buffer(
+        &mut self,
+        vertices: &PackedVertices,
+    ) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UpdatePackedVertexBuffer {
+            layout: vertices.layout,
+            count: vertices.len(),
+        });
+        Ok(())
+    }
+
+    fn update_index_buffer(&mut self, indices: &[u32]) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UpdateIndexBuffer {
+            count: indices.len(),
+        });
+        Ok(())
+    }
+
+    fn upload_static_mesh(&mut self, mesh: usize, geometry: GeometryView<'_>) {
+        self.calls.push(BackendCall::UploadStaticMesh(mesh));
+        let layout = geometry
+            .packed_vertices
+            .map_or(VertexLayout::default(), |packed_vertices| {
+                packed_vertices.layout
+            });
+        self.static_meshes.insert(mesh, layout);
+    }
+
+    fn bind_static_mesh(&mut self, mesh: usize, positions: bool) -> bool {
+        if !self.static_meshes.contains_key(&mesh) {
+            return false;
+        }
+        self.calls
+            .push(BackendCall::BindStaticMesh { mesh, positions });
+        true
+    }
+
+    fn update_uniform_buffer(&mut self, uniforms: &Uniforms) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UpdateUniformBuffer(*uniforms));
+        Ok(())
+    }
+
+    fn update_frame_constants(&mut self, constants: &FrameConstants) -> Result<(), RendererError> {
+        self.calls
+            .push(BackendCall::UpdateFrameConstants(*constants));
+        Ok(())
+    }
+
+    fn update_instance_buffer(&mut self, instances: &[InstanceData]) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UpdateInstanceBuffer {
+            count: instances.len(),
+        });
+        Ok(())
+    }
+
+    fn update_materials(&mut self, materials: &[Material]) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UpdateMaterials {
+            count: materials.len(),
+        });
+        Ok(())
+    }
+
+    fn set_material(&mut self, material_id: MaterialId) {
+        self.calls.push(BackendCall::SetMaterial(material_id));
+    }
+
+    fn set_render_state(&mut self, render_state: RenderState) {
+        self.calls.push(BackendCall::SetRenderState(render_state));
+    }
+
+    fn set_outline(&mut self, outline: Option<Outline>) {
+        self.calls.push(BackendCall::SetOutline(outline));
+    }
+
+    fn set_instance_culling(&mut self, culling: Option<&CullingConstants>) {
+        self.calls
+            .push(BackendCall::SetInstanceCulling(culling.copied()));
+    }
+
+    fn set_depth_pyramid(&mut self, enabled: bool) {
+        self.calls.push(BackendCall::SetDepthPyramid(enabled));
+    }
+
+    fn set_depth_prepass(&mut self, enabled: bool) {
+        self.calls.push(BackendCall::SetDepthPrepass(enabled));
+    }
+
+    fn set_debug_view(&mut self, debug_view: DebugView) {
+        self.calls.push(BackendCall::SetDebugView(debug_view));
+    }
+
+    fn toggle_wireframe_mode(&mut self) {
+        self.calls.push(BackendCall::ToggleWireframeMode);
+    }
+
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.calls.push(BackendCall::SetAntiAliasing(anti_aliasing));
+    }
+
+    fn set_transparency(&mut self, transparency: Transparency) {
+        self.calls.push(BackendCall::SetTransparency(transparency));
+    }
+
+    fn set_render_scale(&mut self, scale: f32) {
+        self.calls.push(BackendCall::SetRenderScale(scale));
+    }
+
+    fn set_vsync(&mut self, enabled: bool) {
+        self.calls.push(BackendCall::SetVsync(enabled));
+    }
+
+    fn set_frame_rate(&mut self, frame_rate: Option<f32>) {
+        self.calls.push(BackendCall::SetFrameRate(frame_rate));
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.size = (width, height);
+        self.calls.push(BackendCall::Resize(width, height));
+    }
+
+    fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.calls.push(BackendCall::SetScaleFactor(scale_factor));
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.calls.push(BackendCall::SetViewport(viewport));
+    }
+
+    fn create_render_target(&mut self, width: u32, height: u32) -> TextureId {
+        self.texture_count += 1;
+        let id = TextureId(NonZeroU32::new(self.texture_count).unwrap());
+        self.calls
+            .push(BackendCall::CreateRenderTarget(id, width, height));
+        id
+    }
+
+    fn set_render_target(&mut self, target: Option<RenderTarget>) {
+        self.calls.push(BackendCall::SetRenderTarget(target));
+    }
+
+    fn set_temporal_constants(&mut self, constants: &TemporalConstants) {
+        self.calls
+            .push(BackendCall::SetTemporalConstants(*constants));
+    }
+
+    fn set_lens_flare(&mut self, lens_flare: Option<&LensFlareFrame>) {
+        self.calls
+            .push(BackendCall::SetLensFlare(lens_flare.cloned()));
+    }
+
+    fn set_atmosphere(&mut self, atmosphere: Option<&AtmosphereConstants>) {
+        self.calls
+            .push(BackendCall::SetAtmosphere(atmosphere.copied()));
+    }
+
+    fn set_sky(&mut self, sky: Option<&SkyConstants>) {
+        self.calls.push(BackendCall::SetSky(sky.copied()));
+    }
+
+    fn set_overlay(&mut self, overlay: Option<&CanvasFrame>) {
+        self.calls.push(BackendCall::SetOverlay(overlay.cloned()));
+    }
+
+    fn end_frame(&mut self) -> Result<(), RendererError> {
+        if self.frame_readback {
+            let (width, height) = self.size;
+            self.frames.push(FrameImage {
+                width,
+                height,
+                pixels: vec![0; width as usize * height as usize * 4],
+            });
+        }
+        self.calls.push(BackendCall::EndFrame);
+        Ok(())
+    }
+
+    fn supports_ray_tracing(&self) -> bool {
+        self.ray_tracing
+    }
+
+    fn build_ray_tracing_mesh(&mut self, mesh: usize, _geometry: GeometryView<'_>) {
+        self.calls.push(BackendCall::BuildRayTracingMesh(mesh));
+    }
+
+    fn set_ray_tracing_frame(&mut self, frame: Option<&RayTracingFrame>) {
+        self.calls
+            .push(BackendCall::SetRayTracingFrame(frame.cloned()));
+    }
+
+    fn set_shadow_maps(&mut self, frame: Option<&ShadowMapFrame>) {
+        self.calls.push(BackendCall::SetShadowMaps(frame.copied()));
+    }
+
+    fn set_light_shadows(&mut self, lights: &[LightShadowFrame]) {
+        self.calls
+            .push(BackendCall::SetLightShadows(lights.to_vec()));
+    }
+
+    fn draw_shadow_caster(
+        &mut self,
+        target: ShadowTarget,
+        draw_command: BackendDrawCommand,
+    ) -> Result<(), RendererError> {
+        self.calls
+            .push(BackendCall::DrawShadowCaster(target, draw_command));
+        Ok(())
+    }
+
+    fn begin_frame_capture(
+        &mut self,
+        destination: &CaptureDestination,
+    ) -> Result<(), RendererError> {
+        self.calls
+            .push(BackendCall::BeginFrameCapture(destination.clone()));
+        Ok(())
+    }
+
+    fn end_frame_capture(&mut self) {
+        self.calls.push(BackendCall::EndFrameCapture);
+    }
+
+    fn set_frame_readback(&mut self, enabled: bool) {
+        self.frame_readback = enabled;
+        self.calls.push(BackendCall::SetFrameReadback(enabled));
+    }
+
+    fn finish_frame_readback(&mut self, wait: bool) -> Vec<FrameImage> {
+        self.calls.push(BackendCall::FinishFrameReadback { wait });
+        std::mem::take(&mut self.frames)
+    }
+
+    fn gpu_memory_report(&self) -> GpuMemoryReport {
+        GpuMemoryReport::default()
+    }
+
+    fn create_texture(&mut self, _descriptor: &TextureDescriptor) -> TextureId {
+        self.texture_count += 1;
+        let id = TextureId(NonZeroU32::new(self.texture_count).unwrap());
+        self.calls.push(BackendCall::CreateTexture(id));
+        id
+    }
+
+    fn update_texture(
+        &mut self,
+        id: TextureId,
+        _region: MTLRegion,
+        _mipmap_level: u64,
+        _slice: u64,
+        _bytes: &[u8],
+        _bytes_per_row: u64,
+        _bytes_per_image: u64,
+    ) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UpdateTexture(id));
+        Ok(())
+    }
+
+    fn upload_texture(&mut self, upload: TextureUpload) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::UploadTexture(upload.texture));
+        self.uploaded.push(upload.texture);
+        Ok(())
+    }
+
+    fn poll_texture_uploads(&mut self) -> Vec<TextureId> {
+        std::mem::take(&mut self.uploaded)
+    }
+
+    fn pending_texture_uploads(&self) -> usize {
+        0
+    }
+
+    fn create_render_pipeline_state(
+        &mut self,
+        _descriptor: &RenderPipelineDescriptor,
+    ) -> Result<(), RendererError> {
+        self.calls.push(BackendCall::CreateRenderPipelineState);
+        Ok(())
+    }
+
+    fn set_async_pipelines(&mut self, enabled: bool) {
+        self.calls.push(BackendCall::SetAsyncPipelines(enabled));
+    }
+
+    fn warm_up_pipelines(&mut self, layouts: &[VertexLayout]) -> Result<(), RendererError> {
+        self.calls
+            .push(BackendCall::WarmUpPipelines(layouts.to_vec()));
+        Ok(())
+    }
+
+    fn pending_pipelines(&self) -> usize {
+        0
+    }
+}
@@ -1,6 +1,27 @@
 use crate::renderer::{
+    atmosphere::AtmosphereConstants,
     backend::GraphicsBackend,
-    common::{BackendDrawCommand, TextureId, Uniforms, Vertex},
+    canvas::CanvasFrame,
+    common::{
+        BackendDrawCommand, CaptureDestination, FrameConstants, RenderTarget, TemporalConstants,
+        TextureId, Uniforms, Vertex,
+    },
+    config::{AntiAliasing, Transparency},
+    debug_view::DebugView,
+    gpu_culling::CullingConstants,
+    lens_flare::LensFlareFrame,
+    lights::LightShadowFrame,
+    material_manager::{Material, MaterialId},
+    memory_report::GpuMemoryReport,
+    ray_tracing::RayTracingFrame,
+    recording::FrameImage,
+    render_queue::GeometryView,
+    render_state::{Outline, RenderState},
+    shadow_map::{ShadowMapFrame, ShadowTarget},
+    sky::SkyConstants,
+    texture_upload::TextureUpload,
+    vertex_layout::{PackedVertices, VertexLayout},
+    viewport::Viewport,
     InstanceData, RendererError,
 };
 
@@ -22,26 +43,236 @@ impl GraphicsBackend for VulkanBackend {
         unimplemented!()
     }
 
+    #[allow(unused_variables)]
+    fn draw_depth(&mut self, draw_command: BackendDrawCommand) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
     #[allow(unused_variables)]
     fn update_vertex_buffer(&mut self, vertices: &[Vertex]) -> Result<(), RendererError> {
         unimplemented!()
     }
 
+    #[allow(unused_variables)]
+    fn update_packed_vertex_buffer(
+        &mut self,
+        vertices: &PackedVertices,
+    ) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
     #[allow(unused_variables)]
     fn update_index_buffer(&mut self, indices: &[u32]) -> Result<(), RendererError> {
         unimplemented!()
     }
 
+    #[allow(unused_variables)]
+    fn upload_static_mesh(&mut self, mesh: usize, geometry: GeometryView<'_>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn bind_static_mesh(&mut self, mesh: usize, positions: bool) -> bool {
+        unimplemented!()
+    }
+
     #[allow(unused_variables)]
     fn update_uniform_buffer(&mut self, uniforms: &Uniforms) -> Result<(), RendererError> {
         unimplemented!()
     }
 
+    #[allow(unused_variables)]
+    fn update_frame_constants(&mut self, constants: &FrameConstants) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
     #[allow(unused_variables)]
     fn update_instance_buffer(&mut self, instances: &[InstanceData]) -> Result<(), RendererError> {
         unimplemented!()
     }
 
+    #[allow(unused_variables)]
+    fn update_materials(&mut self, materials: &[Material]) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_material(&mut self, material_id: MaterialId) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_render_state(&mut self, render_state: RenderState) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_outline(&mut self, outline: Option<Outline>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_instance_culling(&mut self, culling: Option<&CullingConstants>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_depth_pyramid(&mut self, enabled: bool) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_depth_prepass(&mut self, enabled: bool) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_debug_view(&mut self, debug_view: DebugView) {
+        unimplemented!()
+    }
+
+    fn toggle_wireframe_mode(&mut self) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_transparency(&mut self, transparency: Transparency) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_render_scale(&mut self, scale: f32) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_vsync(&mut self, enabled: bool) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_frame_rate(&mut self, frame_rate: Option<f32>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn resize(&mut self, width: u32, height: u32) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_scale_factor(&mut self, scale_factor: f64) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_viewport(&mut self, viewport: Viewport) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn create_render_target(&mut self, width: u32, height: u32) -> TextureId {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_render_target(&mut self, target: Option<RenderTarget>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_temporal_constants(&mut self, constants: &TemporalConstants) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_lens_flare(&mut self, lens_flare: Option<&LensFlareFrame>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_atmosphere(&mut self, atmosphere: Option<&AtmosphereConstants>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_sky(&mut self, sky: Option<&SkyConstants>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_overlay(&mut self, overlay: Option<&CanvasFrame>) {
+        unimplemented!()
+    }
+
+    fn end_frame(&mut self) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
+    fn supports_ray_tracing(&self) -> bool {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn build_ray_tracing_mesh(&mut self, mesh: usize, geometry: GeometryView<'_>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_ray_tracing_frame(&mut self, frame: Option<&RayTracingFrame>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_shadow_maps(&mut self, frame: Option<&ShadowMapFrame>) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_light_shadows(&mut self, lights: &[LightShadowFrame]) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn draw_shadow_caster(
+        &mut self,
+        target: ShadowTarget,
+        draw_command: BackendDrawCommand,
+    ) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn begin_frame_capture(
+        &mut self,
+        destination: &CaptureDestination,
+    ) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
+    fn end_frame_capture(&mut self) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn set_frame_readback(&mut self, enabled: bool) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn finish_frame_readback(&mut self, wait: bool) -> Vec<FrameImage> {
+        unimplemented!()
+    }
+
+    fn gpu_memory_report(&self) -> GpuMemoryReport {
+        unimplemented!()
+    }
+
     #[allow(unused_variables)]
     fn create_texture(&mut self, descriptor: &metal::TextureDescriptor) -> TextureId {
         unimplemented!()
@@ -61,6 +292,19 @@ impl GraphicsBackend for VulkanBackend {
         unimplemented!()
     }
 
+    #[allow(unused_variables)]
+    fn upload_texture(&mut self, upload: TextureUpload) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
+    fn poll_texture_uploads(&mut self) -> Vec<TextureId> {
+        unimplemented!()
+    }
+
+    fn pending_texture_uploads(&self) -> usize {
+        unimplemented!()
+    }
+
     #[allow(unused_variables)]
     fn create_render_pipeline_state(
         &mut self,
@@ -69,6 +313,20 @@ impl GraphicsBackend for VulkanBackend {
         unimplemented!()
     }
 
+    #[allow(unused_variables)]
+    fn set_async_pipelines(&mut self, enabled: bool) {
+        unimplemented!()
+    }
+
+    #[allow(unused_variables)]
+    fn warm_up_pipelines(&mut self, layouts: &[VertexLayout]) -> Result<(), RendererError> {
+        unimplemented!()
+    }
+
+    fn pending_pipelines(&self) -> usize {
+        unimplemented!()
+    }
+
     #[allow(unused_variables)]
     fn render_pass(
         &mut self,
@@ -0,0 +1,233 @@
+//! Bounding volume module for the renderer.
+//!
+//! This module provides axis-aligned bounding boxes and bounding spheres.
+//! Meshes compute both when they are created, and they can be transformed
+//! into world space for culling, picking and physics queries, or drawn as
+//! wireframe boxes for debugging.
+
+use super::common::Vertex;
+use super::Color;
+use glam::{Mat4, Vec3};
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Creates a new `Aabb` from its minimum and maximum corners.
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Computes the smallest box containing all points.
+    ///
+    /// # Returns
+    ///
+    /// The bounding box, or `None` if there are no points.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Aabb::new(first, first), |aabb, point| Aabb {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        }))
+    }
+
+    /// Computes the bounding box of a set of vertices.
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Self> {
+        Self::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)))
+    }
+
+    /// Returns the center of the box.
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Returns half the size of the box along each axis.
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Returns the eight corners of the box.
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+        ]
+    }
+
+    /// Returns the box enclosing this box after it is transformed by `matrix`.
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        // Project the half extents onto each axis of the transformed box
+        let center = matrix.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        let extent = matrix.x_axis.truncate().abs() * half_extents.x
+            + matrix.y_axis.truncate().abs() * half_extents.y
+            + matrix.z_axis.truncate().abs() * half_extents.z;
+        Aabb::new(center - extent, center + extent)
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// Returns `true` if the point lies inside or on the box.
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// Returns `true` if the boxes overlap.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// Appends the twelve edges of the box as line-list vertices.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The color of the lines.
+    /// * `vertices` - The vertices to append to.
+    pub fn append_wireframe(&self, color: Color, vertices: &mut Vec<Vertex>) {
+        const EDGES: [(usize, usize); 12] = [
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 0),
+            (4, 5),
+            (5, 6),
+            (6, 7),
+            (7, 4),
+            (0, 4),
+            (1, 5),
+            (2, 6),
+            (3, 7),
+        ];
+
+        let corners = self.corners();
+        let color = color.into();
+        for (start, end) in EDGES {
+            vertices.push(Vertex {
+                position: corners[start].to_array(),
+                color,
+            });
+            vertices.push(Vertex {
+                position: corners[end].to_array(),
+                color,
+            });
+        }
+    }
+}
+
+/// A sphere enclosing a set of points.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Creates a new `BoundingSphere`.
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// Computes a sphere centered on the bounding box of the vertices.
+    ///
+    /// This is not the minimal enclosing sphere, but it is never larger than
+    /// the sphere around the bounding box.
+    ///
+    /// # Returns
+    ///
+    /// The bounding sphere, or `None` if there are no vertices.
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Self> {
+        let center = Aabb::from_vertices(vertices)?.center();
+        let radius_squared = vertices
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position).distance_squared(center))
+            .fold(0.0, f32::max);
+        Some(BoundingSphere {
+            center,
+            radius: radius_squared.sqrt(),
+        })
+    }
+
+    /// Returns the sphere enclosing this sphere after it is transformed by `matrix`.
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        let max_scale = matrix
+            .x_axis
+            .truncate()
+            .length()
+            .max(matrix.y_axis.truncate().length())
+            .max(matrix.z_axis.truncate().length());
+        BoundingSphere {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * max_scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aabb, BoundingSphere};
+    use crate::renderer::{common::Vertex, Color};
+    use glam::{Mat4, Quat, Vec3};
+
+    fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+        Vertex {
+            position: [x, y, z],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_aabb_from_vertices() {
+        let vertices = [vertex(-1.0, 0.0, 2.0), vertex(3.0, -2.0, 0.0)];
+        let aabb = Aabb::from_vertices(&vertices).unwrap();
+        assert_eq!(aabb.min, Vec3::new(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, Vec3::new(3.0, 0.0, 2.0));
+        assert!(aabb.contains_point(Vec3::new(0.0, -1.0, 1.0)));
+        assert!(Aabb::from_vertices(&[]).is_none());
+
+        let mut lines = Vec::new();
+        aabb.append_wireframe(Color::new(1.0, 1.0, 1.0, 1.0), &mut lines);
+        assert_eq!(lines.len(), 24);
+    }
+
+    #[test]
+    fn test_aabb_transformed() {
+        let aabb = Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let moved = aabb.transformed(&Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0)));
+        assert_eq!(moved.center(), Vec3::new(5.0, 0.0, 0.0));
+        assert_eq!(moved.half_extents(), Vec3::ONE);
+
+        // A 45 degree rotation widens the box by sqrt(2) in the rotated plane
+        let rotated = aabb.transformed(&Mat4::from_quat(Quat::from_rotation_y(
+            std::f32::consts::FRAC_PI_4,
+        )));
+        assert!((rotated.max.x - std::f32::consts::SQRT_2).abs() < 1e-5);
+        assert!((rotated.max.y - 1.0).abs() < 1e-5);
+        assert!(rotated.intersects(&aabb));
+    }
+
+    #[test]
+    fn test_bounding_sphere() {
+        let vertices = [vertex(-1.0, 0.0, 0.0), vertex(1.0, 0.0, 0.0)];
+        let sphere = BoundingSphere::from_vertices(&vertices).unwrap();
+        assert_eq!(sphere.center, Vec3::ZERO);
+        assert_eq!(sphere.radius, 1.0);
+
+        let scaled = sphere.transformed(&Mat4::from_scale(Vec3::new(1.0, 3.0, 1.0)));
+        assert_eq!(scaled.radius, 3.0);
+    }
+}
@@ -3,7 +3,8 @@
 //! This module provides a camera implementation for 3D rendering,
 //! including functionality for movement, rotation, and projection.
 
-use glam::{Mat4, Quat, Vec3};
+use super::{common::LayerMask, raycast::Ray};
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3};
 use log::{debug, trace};
 
 /// Represents a 3D camera with position, orientation, and projection properties.
@@ -16,6 +17,7 @@ pub struct Camera {
     far: f32,
     movement_speed: f32,
     mouse_sensitivity: f32,
+    cull_mask: LayerMask,
 }
 
 impl Camera {
@@ -43,6 +45,7 @@ impl Camera {
             far,
             movement_speed: 0.5,
             mouse_sensitivity: 0.001,
+            cull_mask: LayerMask::ALL,
         }
     }
 
@@ -75,6 +78,73 @@ impl Camera {
         proj_matrix
     }
 
+    /// Returns the world-space position of the camera.
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Returns the near clipping plane distance.
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    /// Returns the far clipping plane distance.
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    /// Returns the layers this camera draws.
+    pub fn cull_mask(&self) -> LayerMask {
+        self.cull_mask
+    }
+
+    /// Sets the layers this camera draws.
+    ///
+    /// Geometry whose layers do not intersect the mask is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `cull_mask` - The layers to draw.
+    pub fn set_cull_mask(&mut self, cull_mask: LayerMask) {
+        self.cull_mask = cull_mask;
+        debug!("Camera cull mask set to: {:#010x}", cull_mask.0);
+    }
+
+    /// Turns the camera to look in a direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction to look in, which must not be parallel to `up`.
+    /// * `up` - The direction of the top of the view.
+    pub fn look_to(&mut self, direction: Vec3, up: Vec3) {
+        let forward = direction.normalize();
+        let right = forward.cross(up).normalize();
+        // The camera looks along its -Z axis with +Y up
+        let rotation = Mat3::from_cols(right, right.cross(forward), -forward);
+        self.orientation = Quat::from_mat3(&rotation).normalize();
+        debug!("Camera looking to: {:?}", forward);
+    }
+
+    /// Returns the ray from the camera through a point on the screen, for picking.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel` - The point in pixels from the top left of the viewport.
+    /// * `viewport` - The size of the viewport in pixels.
+    ///
+    /// # Returns
+    ///
+    /// A ray starting at the camera, with distances along it in world units.
+    pub fn screen_ray(&self, pixel: Vec2, viewport: Vec2) -> Ray {
+        let ndc = Vec2::new(
+            pixel.x / viewport.x * 2.0 - 1.0,
+            1.0 - pixel.y / viewport.y * 2.0,
+        );
+        let inverse = (self.get_projection_matrix() * self.get_view_matrix()).inverse();
+        let far = inverse.project_point3(ndc.extend(1.0));
+        Ray::new(self.position, far - self.position)
+    }
+
     /// Process keyboard input to move the camera
     ///
     /// # Arguments
@@ -131,6 +201,11 @@ impl Camera {
         debug!("Camera FOV adjusted to : {}", self.fov);
     }
 
+    /// Returns the aspect ratio of the camera's viewport.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
     /// Sets the aspect ratio of the camera's viewport.
     ///
     /// # Arguments
@@ -154,7 +229,7 @@ pub enum CameraMovement {
 
 #[cfg(test)]
 mod tests {
-    use crate::renderer::{camera::CameraMovement, Camera};
+    use crate::renderer::{camera::CameraMovement, common::LayerMask, Camera};
     use glam::{Mat3, Mat4, Vec3};
     use std::panic;
 
@@ -328,4 +403,14 @@ mod tests {
         let forward = -camera.orientation * Vec3::Z;
         assert!(forward.x < 0.0); // Camera should have rotated to the left
     }
+
+    #[test]
+    fn test_camera_cull_mask() {
+        let mut camera = Camera::new(Vec3::ZERO, 45.0, 1.0, 0.1, 100.0);
+        assert_eq!(camera.cull_mask(), LayerMask::ALL);
+
+        camera.set_cull_mask(LayerMask::DEFAULT | LayerMask::GIZMO);
+        assert!(camera.cull_mask().intersects(LayerMask::GIZMO));
+        assert!(!camera.cull_mask().intersects(LayerMask::UI));
+    }
 }
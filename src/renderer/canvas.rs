@@ -0,0 +1,604 @@
+//! Canvas module for the renderer.
+//!
+//! This module provides `Canvas`, a 2D drawing layer over the 3D scene for
+//! HUDs, crosshairs and plots. Rectangles, lines and textured quads are
+//! placed relative to an anchor on the screen, in pixels or in coordinates
+//! normalized to the screen's size, and tessellated into triangles as they
+//! are drawn. Text uses the built-in bitmap font. The canvas is cleared
+//! after every frame. The backend draws it with an orthographic projection
+//! after the scene is resolved, so anti-aliasing and render scaling leave it
+//! sharp. Items can fade where a point of the scene is hidden, which the
+//! backend tests against the frame's depth on the GPU.
+//!
+//! Positions are in physical pixels. The canvas carries the display's scale
+//! factor, e.g. 2 on Retina displays, by which the built-in overlays scale
+//! their sizes so they look the same on every display.
+
+use super::{
+    common::TextureId,
+    font::{glyph_runs, text_size, GLYPH_ADVANCE, LINE_ADVANCE},
+    Color,
+};
+use glam::{Vec2, Vec3};
+
+/// The point of the screen, or of a rectangle, that positions are measured from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Returns the anchor's position within a rectangle of unit size, with y pointing down.
+    pub fn factor(self) -> Vec2 {
+        match self {
+            Anchor::TopLeft => Vec2::new(0.0, 0.0),
+            Anchor::Top => Vec2::new(0.5, 0.0),
+            Anchor::TopRight => Vec2::new(1.0, 0.0),
+            Anchor::Left => Vec2::new(0.0, 0.5),
+            Anchor::Center => Vec2::new(0.5, 0.5),
+            Anchor::Right => Vec2::new(1.0, 0.5),
+            Anchor::BottomLeft => Vec2::new(0.0, 1.0),
+            Anchor::Bottom => Vec2::new(0.5, 1.0),
+            Anchor::BottomRight => Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+/// The units of canvas positions and sizes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Units {
+    /// Physical pixels.
+    #[default]
+    Pixels,
+    /// Fractions of the screen's width and height.
+    Normalized,
+}
+
+impl Units {
+    fn scale(self, screen: Vec2) -> Vec2 {
+        match self {
+            Units::Pixels => Vec2::ONE,
+            Units::Normalized => screen,
+        }
+    }
+}
+
+/// A position on the canvas, offset from an anchor of the screen with y pointing down.
+///
+/// # Example
+///
+/// ```ignore
+/// // 16 pixels in from the bottom-right corner
+/// CanvasPoint::pixels(-16.0, -16.0).anchored(Anchor::BottomRight)
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CanvasPoint {
+    pub anchor: Anchor,
+    pub offset: Vec2,
+    pub units: Units,
+}
+
+impl CanvasPoint {
+    /// Creates a position in pixels from the top-left corner of the screen.
+    pub fn pixels(x: f32, y: f32) -> Self {
+        Self {
+            anchor: Anchor::TopLeft,
+            offset: Vec2::new(x, y),
+            units: Units::Pixels,
+        }
+    }
+
+    /// Creates a position in fractions of the screen from its top-left corner.
+    pub fn normalized(x: f32, y: f32) -> Self {
+        Self {
+            anchor: Anchor::TopLeft,
+            offset: Vec2::new(x, y),
+            units: Units::Normalized,
+        }
+    }
+
+    /// Measures the offset from another anchor of the screen.
+    ///
+    /// Rectangles and quads placed at the position are aligned by the same
+    /// anchor, so a rectangle anchored to the bottom-right corner extends up
+    /// and to the left of its position.
+    pub fn anchored(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// Returns the position in pixels on a screen of the given size.
+    pub fn resolve(&self, screen: Vec2) -> Vec2 {
+        self.anchor.factor() * screen + self.offset * self.units.scale(screen)
+    }
+}
+
+/// The size of a rectangle or quad on the canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CanvasSize {
+    pub size: Vec2,
+    pub units: Units,
+}
+
+impl CanvasSize {
+    /// Creates a size in pixels.
+    pub fn pixels(width: f32, height: f32) -> Self {
+        Self {
+            size: Vec2::new(width, height),
+            units: Units::Pixels,
+        }
+    }
+
+    /// Creates a size in fractions of the screen's width and height.
+    pub fn normalized(width: f32, height: f32) -> Self {
+        Self {
+            size: Vec2::new(width, height),
+            units: Units::Normalized,
+        }
+    }
+
+    /// Returns the size in pixels on a screen of the given size.
+    pub fn resolve(&self, screen: Vec2) -> Vec2 {
+        self.size * self.units.scale(screen)
+    }
+}
+
+/// A point of the scene whose visibility fades canvas items.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Occlusion {
+    /// The point in normalized device coordinates, with its depth as z.
+    pub point: Vec3,
+    /// The opacity of items while the point is hidden, from 0 to 1.
+    pub occluded_opacity: f32,
+}
+
+/// A canvas vertex in pixels.
+///
+/// The layout matches the `OverlayVertex` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayVertex {
+    pub position: [f32; 2],
+    pub tex_coord: [f32; 2],
+    pub color: [f32; 4],
+    /// The tested point and the opacity while it is hidden, which is 1 for untested vertices.
+    pub occlusion: [f32; 4],
+}
+
+/// A run of canvas triangles sharing a texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayBatch {
+    /// The texture multiplied with the vertex colors, or `None` for flat colors.
+    pub texture: Option<TextureId>,
+    pub index_offset: usize,
+    pub index_count: usize,
+}
+
+/// The canvas tessellated for one frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CanvasFrame {
+    /// The size of the screen in pixels, which vertices are projected from.
+    pub size: [f32; 2],
+    pub vertices: Vec<OverlayVertex>,
+    pub indices: Vec<u32>,
+    pub batches: Vec<OverlayBatch>,
+}
+
+/// A 2D drawing layer rendered over the scene.
+///
+/// # Example
+///
+/// ```ignore
+/// let canvas = renderer.canvas();
+/// let center = CanvasPoint::normalized(0.0, 0.0).anchored(Anchor::Center);
+/// canvas.rect(center, CanvasSize::pixels(2.0, 16.0), Color::WHITE);
+/// canvas.rect(center, CanvasSize::pixels(16.0, 2.0), Color::WHITE);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Canvas {
+    frame: CanvasFrame,
+    occlusion: Option<Occlusion>,
+    /// Physical pixels per logical point.
+    scale_factor: f32,
+}
+
+impl Canvas {
+    /// Creates a new, empty `Canvas` for a screen of the given size in pixels.
+    pub fn new(size: Vec2) -> Self {
+        let mut canvas = Self {
+            scale_factor: 1.0,
+            ..Self::default()
+        };
+        canvas.set_size(size);
+        canvas
+    }
+
+    /// Returns the number of physical pixels per logical point of the display.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// Sets the number of physical pixels per logical point of the display.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor.max(f32::EPSILON);
+    }
+
+    /// Returns the size of the screen in pixels.
+    pub fn size(&self) -> Vec2 {
+        Vec2::from(self.frame.size)
+    }
+
+    /// Sets the size of the screen that later positions are resolved against.
+    pub fn set_size(&mut self, size: Vec2) {
+        self.frame.size = size.max(Vec2::ONE).to_array();
+    }
+
+    /// Returns `true` if nothing was drawn since the canvas was last cleared.
+    pub fn is_empty(&self) -> bool {
+        self.frame.indices.is_empty()
+    }
+
+    /// Removes everything drawn and stops fading items by occlusion.
+    pub fn clear(&mut self) {
+        self.frame.vertices.clear();
+        self.frame.indices.clear();
+        self.frame.batches.clear();
+        self.occlusion = None;
+    }
+
+    /// Fades items drawn afterwards while a point of the scene is hidden, or stops with `None`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// canvas.set_occlusion(Some(Occlusion { point: ndc, occluded_opacity: 0.25 }));
+    /// canvas.text(position, "Mars", 2.0, Color::WHITE);
+    /// canvas.set_occlusion(None);
+    /// ```
+    pub fn set_occlusion(&mut self, occlusion: Option<Occlusion>) {
+        self.occlusion = occlusion;
+    }
+
+    /// Returns the triangles drawn so far.
+    pub fn frame(&self) -> &CanvasFrame {
+        &self.frame
+    }
+
+    /// Fills a rectangle.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position of the rectangle's corner, edge or center matching the position's anchor.
+    /// * `size` - The size of the rectangle.
+    /// * `color` - The fill color.
+    pub fn rect(&mut self, position: CanvasPoint, size: CanvasSize, color: Color) {
+        let (min, max) = self.resolve_rect(position, size);
+        self.push_rect(min, max, [Vec2::ZERO, Vec2::ONE], color, None);
+    }
+
+    /// Outlines a rectangle with lines drawn inside its edges.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position of the rectangle, see `rect`.
+    /// * `size` - The size of the rectangle.
+    /// * `thickness` - The width of the lines in pixels.
+    /// * `color` - The line color.
+    pub fn rect_outline(
+        &mut self,
+        position: CanvasPoint,
+        size: CanvasSize,
+        thickness: f32,
+        color: Color,
+    ) {
+        let (min, max) = self.resolve_rect(position, size);
+        let thickness = thickness
+            .min((max.x - min.x) * 0.5)
+            .min((max.y - min.y) * 0.5);
+        let uv = [Vec2::ZERO, Vec2::ONE];
+        self.push_rect(min, Vec2::new(max.x, min.y + thickness), uv, color, None);
+        self.push_rect(Vec2::new(min.x, max.y - thickness), max, uv, color, None);
+        let (top, bottom) = (min.y + thickness, max.y - thickness);
+        self.push_rect(
+            Vec2::new(min.x, top),
+            Vec2::new(min.x + thickness, bottom),
+            uv,
+            color,
+            None,
+        );
+        self.push_rect(
+            Vec2::new(max.x - thickness, top),
+            Vec2::new(max.x, bottom),
+            uv,
+            color,
+            None,
+        );
+    }
+
+    /// Draws a straight line.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The start of the line.
+    /// * `to` - The end of the line.
+    /// * `thickness` - The width of the line in pixels.
+    /// * `color` - The line color.
+    pub fn line(&mut self, from: CanvasPoint, to: CanvasPoint, thickness: f32, color: Color) {
+        let screen = self.size();
+        self.push_segment(from.resolve(screen), to.resolve(screen), thickness, color);
+    }
+
+    /// Draws connected straight lines through a series of points.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - The points to connect, in order.
+    /// * `thickness` - The width of the lines in pixels.
+    /// * `color` - The line color.
+    pub fn polyline(&mut self, points: &[CanvasPoint], thickness: f32, color: Color) {
+        let screen = self.size();
+        for segment in points.windows(2) {
+            let (from, to) = (segment[0].resolve(screen), segment[1].resolve(screen));
+            self.push_segment(from, to, thickness, color);
+        }
+    }
+
+    /// Draws a texture stretched over a rectangle.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position of the rectangle, see `rect`.
+    /// * `size` - The size of the rectangle.
+    /// * `texture` - The texture to draw.
+    /// * `tint` - The color the texture is multiplied with, white to draw it unchanged.
+    pub fn image(
+        &mut self,
+        position: CanvasPoint,
+        size: CanvasSize,
+        texture: TextureId,
+        tint: Color,
+    ) {
+        self.image_region(position, size, texture, [Vec2::ZERO, Vec2::ONE], tint);
+    }
+
+    /// Draws part of a texture stretched over a rectangle, e.g. one glyph of an atlas.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position of the rectangle, see `rect`.
+    /// * `size` - The size of the rectangle.
+    /// * `texture` - The texture to draw.
+    /// * `tex_coords` - The top-left and bottom-right texture coordinates of the region.
+    /// * `tint` - The color the texture is multiplied with.
+    pub fn image_region(
+        &mut self,
+        position: CanvasPoint,
+        size: CanvasSize,
+        texture: TextureId,
+        tex_coords: [Vec2; 2],
+        tint: Color,
+    ) {
+        let (min, max) = self.resolve_rect(position, size);
+        self.push_rect(min, max, tex_coords, tint, Some(texture));
+    }
+
+    /// Draws text in the built-in bitmap font, with lines separated by `\n`.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position of the text's bounds, see `rect`.
+    /// * `text` - The text to draw. Characters outside printable ASCII draw as `?`.
+    /// * `pixel_size` - The size of a font pixel in screen pixels; glyphs are 5 by 8 font pixels.
+    /// * `color` - The text color.
+    pub fn text(&mut self, position: CanvasPoint, text: &str, pixel_size: f32, color: Color) {
+        let size = text_size(text) * pixel_size;
+        let (min, _) = self.resolve_rect(position, CanvasSize::pixels(size.x, size.y));
+        for (line_index, line) in text.lines().enumerate() {
+            let top = min.y + line_index as f32 * LINE_ADVANCE * pixel_size;
+            for (index, character) in line.chars().enumerate() {
+                let left = min.x + index as f32 * GLYPH_ADVANCE * pixel_size;
+                for (column, row, length) in glyph_runs(character) {
+                    let run_min = Vec2::new(
+                        left + column as f32 * pixel_size,
+                        top + row as f32 * pixel_size,
+                    );
+                    let run_size = Vec2::new(pixel_size, length as f32 * pixel_size);
+                    self.push_rect(run_min, run_min + run_size, [Vec2::ZERO; 2], color, None);
+                }
+            }
+        }
+    }
+
+    /// Returns the top-left and bottom-right corners of a rectangle in pixels.
+    fn resolve_rect(&self, position: CanvasPoint, size: CanvasSize) -> (Vec2, Vec2) {
+        let screen = self.size();
+        let size = size.resolve(screen);
+        let min = position.resolve(screen) - size * position.anchor.factor();
+        (min, min + size)
+    }
+
+    fn push_rect(
+        &mut self,
+        min: Vec2,
+        max: Vec2,
+        [uv_min, uv_max]: [Vec2; 2],
+        color: Color,
+        texture: Option<TextureId>,
+    ) {
+        self.push_quad(
+            [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)],
+            [
+                uv_min,
+                Vec2::new(uv_max.x, uv_min.y),
+                uv_max,
+                Vec2::new(uv_min.x, uv_max.y),
+            ],
+            color,
+            texture,
+        );
+    }
+
+    fn push_segment(&mut self, from: Vec2, to: Vec2, thickness: f32, color: Color) {
+        let Some(direction) = (to - from).try_normalize() else {
+            return;
+        };
+        let side = direction.perp() * thickness * 0.5;
+        self.push_quad(
+            [from - side, to - side, to + side, from + side],
+            [Vec2::ZERO; 4],
+            color,
+            None,
+        );
+    }
+
+    /// Appends two triangles, continuing the last batch if it has the same texture.
+    fn push_quad(
+        &mut self,
+        corners: [Vec2; 4],
+        tex_coords: [Vec2; 4],
+        color: Color,
+        texture: Option<TextureId>,
+    ) {
+        let occlusion = match self.occlusion {
+            Some(Occlusion {
+                point,
+                occluded_opacity,
+            }) => point.extend(occluded_opacity.clamp(0.0, 1.0)).to_array(),
+            None => [0.0, 0.0, 0.0, 1.0],
+        };
+        let frame = &mut self.frame;
+        let first = frame.vertices.len() as u32;
+        frame
+            .vertices
+            .extend(
+                corners
+                    .iter()
+                    .zip(tex_coords)
+                    .map(|(corner, tex_coord)| OverlayVertex {
+                        position: corner.to_array(),
+                        tex_coord: tex_coord.to_array(),
+                        color: color.into(),
+                        occlusion,
+                    }),
+            );
+        frame
+            .indices
+            .extend([0, 1, 2, 0, 2, 3].map(|index| first + index));
+
+        match frame.batches.last_mut() {
+            Some(batch) if batch.texture == texture => batch.index_count += 6,
+            _ => frame.batches.push(OverlayBatch {
+                texture,
+                index_offset: frame.indices.len() - 6,
+                index_count: 6,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anchor, Canvas, CanvasPoint, CanvasSize, OverlayVertex};
+    use crate::renderer::{common::TextureId, Color};
+    use glam::Vec2;
+    use std::num::NonZeroU32;
+
+    fn positions(canvas: &Canvas) -> Vec<[f32; 2]> {
+        canvas
+            .frame()
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position)
+            .collect()
+    }
+
+    #[test]
+    fn test_overlay_vertex_layout() {
+        // Must match the struct in shader_types.h
+        assert_eq!(std::mem::size_of::<OverlayVertex>(), 48);
+    }
+
+    #[test]
+    fn test_canvas_anchors() {
+        let screen = Vec2::new(800.0, 600.0);
+        assert_eq!(
+            CanvasPoint::pixels(10.0, 20.0).resolve(screen),
+            Vec2::new(10.0, 20.0)
+        );
+        assert_eq!(
+            CanvasPoint::normalized(0.5, 0.25).resolve(screen),
+            Vec2::new(400.0, 150.0)
+        );
+        assert_eq!(
+            CanvasPoint::pixels(-10.0, -10.0)
+                .anchored(Anchor::BottomRight)
+                .resolve(screen),
+            Vec2::new(790.0, 590.0)
+        );
+
+        // Rectangles are aligned by their position's anchor
+        let mut canvas = Canvas::new(screen);
+        canvas.rect(
+            CanvasPoint::pixels(-10.0, -10.0).anchored(Anchor::BottomRight),
+            CanvasSize::pixels(100.0, 50.0),
+            Color::WHITE,
+        );
+        canvas.rect(
+            CanvasPoint::default().anchored(Anchor::Center),
+            CanvasSize::normalized(0.5, 0.5),
+            Color::WHITE,
+        );
+        assert_eq!(
+            positions(&canvas),
+            [
+                [690.0, 540.0],
+                [790.0, 540.0],
+                [790.0, 590.0],
+                [690.0, 590.0],
+                [200.0, 150.0],
+                [600.0, 150.0],
+                [600.0, 450.0],
+                [200.0, 450.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canvas_batches_by_texture() {
+        let texture = TextureId(NonZeroU32::new(1).unwrap());
+        let mut canvas = Canvas::new(Vec2::new(100.0, 100.0));
+        let (origin, size) = (CanvasPoint::default(), CanvasSize::pixels(10.0, 10.0));
+        canvas.rect(origin, size, Color::RED);
+        canvas.line(origin, CanvasPoint::pixels(50.0, 0.0), 2.0, Color::GREEN);
+        canvas.image(origin, size, texture, Color::WHITE);
+        canvas.image(origin, size, texture, Color::WHITE);
+        canvas.rect_outline(origin, size, 1.0, Color::BLUE);
+
+        let frame = canvas.frame();
+        let batches: Vec<_> = frame
+            .batches
+            .iter()
+            .map(|batch| (batch.texture, batch.index_offset, batch.index_count))
+            .collect();
+        assert_eq!(
+            batches,
+            [(None, 0, 12), (Some(texture), 12, 12), (None, 24, 24)]
+        );
+        // The line extends half its thickness to both sides
+        assert_eq!(frame.vertices[4].position, [0.0, -1.0]);
+        assert_eq!(frame.vertices[6].position, [50.0, 1.0]);
+
+        canvas.clear();
+        assert!(canvas.is_empty());
+        assert!(canvas.frame().batches.is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+//! Colormap module for the renderer.
+//!
+//! This module provides built-in scientific colormaps for mapping scalar
+//! values onto colors, so simulation data (temperatures, densities,
+//! velocities, ...) can be visualized directly on mesh vertices.
+
+use super::Color;
+
+/// Control points for viridis, sampled at 9 evenly spaced positions.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [0x44, 0x01, 0x54],
+    [0x47, 0x2d, 0x7b],
+    [0x3b, 0x52, 0x8b],
+    [0x2c, 0x72, 0x8e],
+    [0x21, 0x91, 0x8c],
+    [0x28, 0xae, 0x80],
+    [0x5e, 0xc9, 0x62],
+    [0xad, 0xdc, 0x30],
+    [0xfd, 0xe7, 0x25],
+];
+
+/// Control points for plasma, sampled at 9 evenly spaced positions.
+const PLASMA: [[u8; 3]; 9] = [
+    [0x0d, 0x08, 0x87],
+    [0x4c, 0x02, 0xa1],
+    [0x7e, 0x03, 0xa8],
+    [0xa9, 0x23, 0x95],
+    [0xcc, 0x47, 0x78],
+    [0xe5, 0x6b, 0x5d],
+    [0xf8, 0x94, 0x41],
+    [0xfd, 0xc3, 0x28],
+    [0xf0, 0xf9, 0x21],
+];
+
+/// Control points for Moreland's diverging cool-warm map, sampled at 9 evenly spaced positions.
+const COOLWARM: [[u8; 3]; 9] = [
+    [59, 76, 192],
+    [98, 130, 234],
+    [141, 176, 254],
+    [184, 208, 249],
+    [221, 221, 221],
+    [245, 196, 173],
+    [244, 154, 123],
+    [222, 96, 77],
+    [180, 4, 38],
+];
+
+/// Built-in colormaps for data-driven coloring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    /// Perceptually uniform, sequential (dark purple to yellow).
+    Viridis,
+    /// Perceptually uniform, sequential (dark blue to yellow).
+    Plasma,
+    /// Diverging (blue through gray to red), for data centered on a midpoint.
+    Coolwarm,
+}
+
+impl Colormap {
+    /// Samples the colormap at a normalized position.
+    ///
+    /// # Arguments
+    ///
+    /// * `t` - The position in the colormap, clamped to `[0, 1]`.
+    ///
+    /// # Returns
+    ///
+    /// The interpolated, fully opaque color.
+    pub fn sample(&self, t: f32) -> Color {
+        let table = self.control_points();
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+
+        let scaled = t * (table.len() - 1) as f32;
+        let index = (scaled.floor() as usize).min(table.len() - 2);
+        let fraction = scaled - index as f32;
+
+        let lower = table[index];
+        let upper = table[index + 1];
+        let channel =
+            |i: usize| (lower[i] as f32 + (upper[i] as f32 - lower[i] as f32) * fraction) / 255.0;
+
+        // The control points are sRGB, and are interpolated as published
+        Color::from_srgb(channel(0), channel(1), channel(2), 1.0)
+    }
+
+    /// Maps a scalar value within a range onto the colormap.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The scalar value to map.
+    /// * `range` - The `(min, max)` values mapped to the ends of the colormap.
+    ///
+    /// # Returns
+    ///
+    /// The color for the value. Values outside the range are clamped.
+    pub fn map(&self, value: f32, range: (f32, f32)) -> Color {
+        let (min, max) = range;
+        let extent = max - min;
+        let t = if extent.abs() > f32::EPSILON {
+            (value - min) / extent
+        } else {
+            0.5
+        };
+        self.sample(t)
+    }
+
+    /// Computes the `(min, max)` range of a set of scalar values, ignoring NaNs.
+    ///
+    /// # Returns
+    ///
+    /// The range, or `(0.0, 1.0)` if there are no finite values.
+    pub fn auto_range(values: &[f32]) -> (f32, f32) {
+        values
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold(None, |range, &v| match range {
+                None => Some((v, v)),
+                Some((min, max)) => Some((f32::min(min, v), f32::max(max, v))),
+            })
+            .unwrap_or((0.0, 1.0))
+    }
+
+    fn control_points(&self) -> &'static [[u8; 3]] {
+        match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Plasma => &PLASMA,
+            Colormap::Coolwarm => &COOLWARM,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Colormap;
+    use crate::renderer::Color;
+
+    fn color_approx_eq(a: Color, b: Color, epsilon: f32) -> bool {
+        (a.r - b.r).abs() < epsilon
+            && (a.g - b.g).abs() < epsilon
+            && (a.b - b.b).abs() < epsilon
+            && (a.a - b.a).abs() < epsilon
+    }
+
+    #[test]
+    fn test_colormap_endpoints() {
+        let start = Colormap::Viridis.sample(0.0);
+        let end = Colormap::Viridis.sample(1.0);
+        assert!(color_approx_eq(
+            start,
+            Color::from_srgb_u8(68, 1, 84, 255),
+            1e-6
+        ));
+        assert!(color_approx_eq(
+            end,
+            Color::from_srgb_u8(253, 231, 37, 255),
+            1e-6
+        ));
+    }
+
+    #[test]
+    fn test_colormap_clamps_out_of_range() {
+        assert_eq!(Colormap::Plasma.sample(-1.0), Colormap::Plasma.sample(0.0));
+        assert_eq!(Colormap::Plasma.sample(2.0), Colormap::Plasma.sample(1.0));
+        assert_eq!(
+            Colormap::Plasma.sample(f32::NAN),
+            Colormap::Plasma.sample(0.0)
+        );
+    }
+
+    #[test]
+    fn test_colormap_map_midpoint() {
+        let mid = Colormap::Coolwarm.map(5.0, (0.0, 10.0));
+        assert!(color_approx_eq(
+            mid,
+            Color::from_srgb_u8(221, 221, 221, 255),
+            1e-6
+        ));
+
+        // A degenerate range maps everything to the middle of the colormap
+        assert_eq!(
+            Colormap::Coolwarm.map(3.0, (3.0, 3.0)),
+            Colormap::Coolwarm.sample(0.5)
+        );
+    }
+
+    #[test]
+    fn test_auto_range() {
+        assert_eq!(
+            Colormap::auto_range(&[3.0, -1.0, f32::NAN, 7.5]),
+            (-1.0, 7.5)
+        );
+        assert_eq!(Colormap::auto_range(&[]), (0.0, 1.0));
+    }
+}
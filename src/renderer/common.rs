@@ -2,14 +2,12 @@
 //!
 //! This module provides various common types, enums, and structures used
 //! throughout the renderer, including color representations, vertex definitions,
-//! and error type.
+//! and GPU capture destinations.
 
-use core::fmt;
+use super::error::ColorError;
 use glam::Mat4;
-use log::error;
 use metal::{MTLIndexType, MTLPrimitiveType};
-use raw_window_handle::HandleError;
-use std::num::NonZeroU32;
+use std::{num::NonZeroU32, path::PathBuf, str::FromStr};
 
 /// Represents a texture ID.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -38,7 +36,7 @@ impl From<PrimitiveType> for MTLPrimitiveType {
 }
 
 /// Represents different index types for rendering.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum IndexType {
     UInt16,
     UInt32,
@@ -54,6 +52,7 @@ impl From<IndexType> for MTLIndexType {
 }
 
 /// Represents a draw command for the backend.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BackendDrawCommand {
     Basic {
         primitive_type: PrimitiveType,
@@ -81,7 +80,23 @@ pub enum BackendDrawCommand {
     },
 }
 
+impl BackendDrawCommand {
+    /// Returns `true` if the command draws multiple instances from the instance buffer.
+    pub fn is_instanced(&self) -> bool {
+        matches!(
+            self,
+            BackendDrawCommand::Instanced { .. } | BackendDrawCommand::IndexedInstanced { .. }
+        )
+    }
+}
+
 /// Represents a color with red, green, blue, and alpha components.
+///
+/// Components are in linear space, where shading and blending happen, and
+/// are encoded to sRGB when written to the screen. Colors picked in an image
+/// editor or from a web palette are sRGB and should be created with
+/// `Color::from_srgb` or `Color::from_srgb_u8`. Alpha is always linear.
+#[repr(C)]
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Color {
     pub r: f32,
@@ -91,10 +106,232 @@ pub struct Color {
 }
 
 impl Color {
-    /// Creates a new Color instance.
-    pub fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+    /// Creates a new Color instance from linear components.
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
         Self { r, g, b, a }
     }
+
+    /// Creates a new Color from sRGB-encoded components in [0, 1].
+    pub fn from_srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a)
+    }
+
+    /// Creates a new Color from 8-bit sRGB-encoded components, e.g. `(255, 136, 0, 255)`.
+    pub fn from_srgb_u8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        let unit = |component: u8| component as f32 / 255.0;
+        Self::from_srgb(unit(r), unit(g), unit(b), unit(a))
+    }
+
+    /// Returns the sRGB-encoded components, as shown on screen.
+    pub fn to_srgb(self) -> [f32; 4] {
+        [
+            linear_to_srgb(self.r),
+            linear_to_srgb(self.g),
+            linear_to_srgb(self.b),
+            self.a,
+        ]
+    }
+
+    /// Returns the 8-bit sRGB-encoded components, rounded and clamped to [0, 255].
+    pub fn to_srgb_u8(self) -> [u8; 4] {
+        self.to_srgb()
+            .map(|component| (component.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    /// Parses an sRGB hex color, e.g. `"#ff8800"`.
+    ///
+    /// The leading `#` is optional, and `rgb`, `rgba`, `rrggbb` and `rrggbbaa`
+    /// digits are accepted. Colors without alpha digits are fully opaque.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the color or a `ColorError`.
+    pub fn from_hex(hex: &str) -> Result<Self, ColorError> {
+        let invalid = || ColorError::InvalidHex(hex.to_string());
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        // Short forms repeat every digit, so `f80` is `ff8800`
+        let (width, scale) = match digits.len() {
+            3 | 4 => (1, 17),
+            6 | 8 => (2, 1),
+            _ => return Err(invalid()),
+        };
+        let component = |index: usize| {
+            digits
+                .get(index * width..(index + 1) * width)
+                .map_or(255, |digits| {
+                    u8::from_str_radix(digits, 16).unwrap() * scale
+                })
+        };
+        Ok(Self::from_srgb_u8(
+            component(0),
+            component(1),
+            component(2),
+            component(3),
+        ))
+    }
+
+    /// Returns the sRGB hex code of the color, with alpha digits only if it is not opaque.
+    pub fn to_hex(self) -> String {
+        let [r, g, b, a] = self.to_srgb_u8();
+        if a == 255 {
+            format!("#{r:02x}{g:02x}{b:02x}")
+        } else {
+            format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+        }
+    }
+
+    /// Creates a new Color from hue, saturation and value.
+    ///
+    /// Like color pickers, the model describes the sRGB-encoded color.
+    ///
+    /// # Arguments
+    ///
+    /// * `hue` - The hue in degrees, wrapped to [0, 360).
+    /// * `saturation` - The saturation in [0, 1].
+    /// * `value` - The value in [0, 1].
+    /// * `alpha` - The alpha in [0, 1].
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32, alpha: f32) -> Self {
+        let channel = |n: f32| {
+            let k = (n + hue / 60.0).rem_euclid(6.0);
+            value - value * saturation * k.min(4.0 - k).clamp(0.0, 1.0)
+        };
+        Self::from_srgb(channel(5.0), channel(3.0), channel(1.0), alpha)
+    }
+
+    /// Returns the hue in degrees, saturation, value and alpha of the sRGB-encoded color.
+    pub fn to_hsv(self) -> [f32; 4] {
+        let [r, g, b, a] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let chroma = max - r.min(g).min(b);
+        let saturation = if max > 0.0 { chroma / max } else { 0.0 };
+        [hue(r, g, b, max, chroma), saturation, max, a]
+    }
+
+    /// Creates a new Color from hue, saturation and lightness.
+    ///
+    /// Like color pickers, the model describes the sRGB-encoded color.
+    ///
+    /// # Arguments
+    ///
+    /// * `hue` - The hue in degrees, wrapped to [0, 360).
+    /// * `saturation` - The saturation in [0, 1].
+    /// * `lightness` - The lightness in [0, 1].
+    /// * `alpha` - The alpha in [0, 1].
+    pub fn from_hsl(hue: f32, saturation: f32, lightness: f32, alpha: f32) -> Self {
+        let amplitude = saturation * lightness.min(1.0 - lightness);
+        let channel = |n: f32| {
+            let k = (n + hue / 30.0).rem_euclid(12.0);
+            lightness - amplitude * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+        };
+        Self::from_srgb(channel(0.0), channel(8.0), channel(4.0), alpha)
+    }
+
+    /// Returns the hue in degrees, saturation, lightness and alpha of the sRGB-encoded color.
+    pub fn to_hsl(self) -> [f32; 4] {
+        let [r, g, b, a] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let chroma = max - min;
+        let lightness = (max + min) / 2.0;
+        let saturation = if chroma > 0.0 {
+            chroma / (1.0 - (2.0 * lightness - 1.0).abs())
+        } else {
+            0.0
+        };
+        [hue(r, g, b, max, chroma), saturation, lightness, a]
+    }
+
+    /// Returns the same color with a different alpha.
+    pub fn with_alpha(self, a: f32) -> Self {
+        Self { a, ..self }
+    }
+
+    /// Interpolates linearly between two colors in linear space.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The color at `t = 1`.
+    /// * `t` - The interpolation factor, not clamped.
+    pub fn lerp(self, other: Color, t: f32) -> Self {
+        Self::new(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+            self.a + (other.a - self.a) * t,
+        )
+    }
+
+    /// Returns the color with its red, green and blue multiplied by its alpha.
+    pub fn premultiply(self) -> Self {
+        Self::new(self.r * self.a, self.g * self.a, self.b * self.a, self.a)
+    }
+
+    /// Undoes `premultiply`, leaving fully transparent colors black.
+    pub fn unpremultiply(self) -> Self {
+        if self.a <= 0.0 {
+            return Self::TRANSPARENT;
+        }
+        Self::new(self.r / self.a, self.g / self.a, self.b / self.a, self.a)
+    }
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+    /// Mid gray, shown as sRGB 0.5.
+    pub const GRAY: Color = Color::new(0.214_041_14, 0.214_041_14, 0.214_041_14, 1.0);
+    pub const RED: Color = Color::new(1.0, 0.0, 0.0, 1.0);
+    pub const GREEN: Color = Color::new(0.0, 1.0, 0.0, 1.0);
+    pub const BLUE: Color = Color::new(0.0, 0.0, 1.0, 1.0);
+    pub const YELLOW: Color = Color::new(1.0, 1.0, 0.0, 1.0);
+    pub const CYAN: Color = Color::new(0.0, 1.0, 1.0, 1.0);
+    pub const MAGENTA: Color = Color::new(1.0, 0.0, 1.0, 1.0);
+}
+
+impl FromStr for Color {
+    type Err = ColorError;
+
+    /// Parses an sRGB hex color, see `Color::from_hex`.
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(hex)
+    }
+}
+
+/// Returns the hue in degrees of sRGB-encoded components, given their maximum and chroma.
+fn hue(r: f32, g: f32, b: f32, max: f32, chroma: f32) -> f32 {
+    if chroma <= 0.0 {
+        return 0.0;
+    }
+    let sector = if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (sector * 60.0).rem_euclid(360.0)
+}
+
+/// Decodes an sRGB-encoded component into linear space.
+pub fn srgb_to_linear(component: f32) -> f32 {
+    if component <= 0.04045 {
+        component / 12.92
+    } else {
+        ((component + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear component into sRGB space.
+pub fn linear_to_srgb(component: f32) -> f32 {
+    if component <= 0.0031308 {
+        component * 12.92
+    } else {
+        1.055 * component.powf(1.0 / 2.4) - 0.055
+    }
 }
 
 impl From<Color> for [f32; 4] {
@@ -103,6 +340,75 @@ impl From<Color> for [f32; 4] {
     }
 }
 
+/// A 32-bit set of visibility layers.
+///
+/// Scene nodes and draws belong to one or more layers, and a camera only
+/// draws geometry whose layers intersect its cull mask.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayerMask(pub u32);
+
+impl LayerMask {
+    pub const NONE: LayerMask = LayerMask(0);
+    pub const ALL: LayerMask = LayerMask(u32::MAX);
+    pub const DEFAULT: LayerMask = LayerMask(1 << 0);
+    pub const UI: LayerMask = LayerMask(1 << 1);
+    pub const GIZMO: LayerMask = LayerMask(1 << 2);
+    pub const DEBUG: LayerMask = LayerMask(1 << 3);
+    /// Geometry on this layer is rendered into shadow maps.
+    pub const SHADOW_CASTER: LayerMask = LayerMask(1 << 4);
+
+    /// Returns the mask containing only the layer at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is 32 or greater.
+    pub fn layer(index: u32) -> Self {
+        assert!(index < 32, "Layer index {index} out of range");
+        LayerMask(1 << index)
+    }
+
+    /// Returns `true` if the masks share at least one layer.
+    pub fn intersects(self, other: LayerMask) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns `true` if every layer of `other` is in this mask.
+    pub fn contains(self, other: LayerMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for LayerMask {
+    /// Regular scene geometry is visible and casts shadows.
+    fn default() -> Self {
+        LayerMask::DEFAULT | LayerMask::SHADOW_CASTER
+    }
+}
+
+impl std::ops::BitOr for LayerMask {
+    type Output = LayerMask;
+
+    fn bitor(self, rhs: LayerMask) -> LayerMask {
+        LayerMask(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitAnd for LayerMask {
+    type Output = LayerMask;
+
+    fn bitand(self, rhs: LayerMask) -> LayerMask {
+        LayerMask(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::Not for LayerMask {
+    type Output = LayerMask;
+
+    fn not(self) -> LayerMask {
+        LayerMask(!self.0)
+    }
+}
+
 /// Represents a vertex with position and color.
 #[repr(C)]
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -122,79 +428,64 @@ impl Default for Vertex {
 
 /// Represents uniform data for rendering.
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Uniforms {
     pub view_projection_matrix: Mat4,
     pub model_matrix: Mat4,
 }
 
-/// Represents possible errors that can occur in the renderer.
-#[derive(Debug)]
-pub enum RendererError {
-    DeviceNotFound,
-    ShaderCompilationFailed(String),
-    ShaderFunctionNotFound(String),
-    PipelineCreationFailed(String),
-    DrawFailed(String),
-    WindowCreationFailed(String),
-    EventLoopError(String),
-    WindowHandleError(String),
-    BufferOverflow,
-    InvalidTextureId,
-    InvalidPipelineId,
-    InvalidMeshId,
-    UnsupportedPlatform,
-}
-
-impl fmt::Display for RendererError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            RendererError::DeviceNotFound => write!(f, "Metal device not found"),
-            RendererError::ShaderCompilationFailed(msg) => {
-                write!(f, "Shader compilation failed: {msg}")
-            }
-            RendererError::ShaderFunctionNotFound(name) => {
-                write!(f, "Shader function not found: {name}")
-            }
-            RendererError::PipelineCreationFailed(msg) => {
-                write!(f, "Pipeline creation failed: {msg}")
-            }
-            RendererError::DrawFailed(msg) => write!(f, "Draw operation failed: {msg}"),
-            RendererError::WindowCreationFailed(msg) => {
-                write!(f, "Window creation with winit failed: {msg}")
-            }
-            RendererError::EventLoopError(msg) => {
-                write!(f, "Winit event loop error: {msg}")
-            }
-            RendererError::WindowHandleError(msg) => {
-                write!(f, "Winit window handle error: {msg}")
-            }
-            RendererError::BufferOverflow => {
-                write!(f, "Buffer overflow")
-            }
-            RendererError::InvalidTextureId => {
-                write!(f, "Invalid texture Id")
-            }
-            RendererError::InvalidPipelineId => {
-                write!(f, "Invalid pipeline Id")
-            }
-            RendererError::InvalidMeshId => {
-                write!(f, "Invalid mesh Id")
-            }
-            RendererError::UnsupportedPlatform => {
-                write!(f, "Unsupported platform")
-            }
-        }
-    }
+/// Represents per-frame constants shared by all draws in a frame.
+///
+/// The layout matches the `FrameConstants` struct in `shader_types.h`, which
+/// is bound once per pass to vertex buffer 3 and fragment buffer 0.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameConstants {
+    pub camera_position: [f32; 3],
+    pub time: f32,
+    pub viewport_size: [f32; 2],
+    pub near: f32,
+    pub far: f32,
+    pub delta_time: f32,
+    pub frame_index: u32,
+    /// The ambient light reaching surfaces facing up.
+    pub ambient_sky_color: [f32; 3],
+    /// The ambient light reaching surfaces facing down.
+    pub ambient_ground_color: [f32; 3],
 }
 
-impl std::error::Error for RendererError {}
+/// Represents the constants used to resolve a frame with temporal anti-aliasing.
+///
+/// The layout matches the `TemporalConstants` struct in `shader_types.h`,
+/// which is bound to fragment buffer 0 of the resolve pass.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TemporalConstants {
+    /// Maps this frame's unjittered clip space to the previous frame's.
+    pub reprojection: Mat4,
+    /// The projection jitter of this frame in normalized device coordinates.
+    pub jitter: [f32; 2],
+    /// How much of the history is kept, between 0 and 1.
+    pub history_weight: f32,
+    /// Whether the history holds a previous frame, as 0 or 1.
+    pub history_valid: u32,
+}
 
-impl From<HandleError> for RendererError {
-    fn from(error: HandleError) -> Self {
-        error!("Window handle error: {}", error);
-        RendererError::WindowHandleError(error.to_string())
-    }
+/// Where a GPU frame capture is sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureDestination {
+    /// Opens the capture in Xcode, which must be attached to the process.
+    DeveloperTools,
+    /// Writes the capture to a `.gputrace` file that Xcode can open later.
+    TraceFile(PathBuf),
+}
+
+/// A texture draws render into instead of the frame, such as a planar reflection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderTarget {
+    pub texture: TextureId,
+    /// Whether draws are mirrored, which flips the winding of their triangles.
+    pub mirrored: bool,
 }
 
 #[cfg(test)]
@@ -203,7 +494,9 @@ mod tests {
 
     use crate::renderer::common::{IndexType, PrimitiveType};
 
-    use super::{Color, Vertex};
+    use super::{
+        linear_to_srgb, srgb_to_linear, Color, FrameConstants, LayerMask, TemporalConstants, Vertex,
+    };
 
     #[test]
     fn test_color_creation() {
@@ -221,6 +514,66 @@ mod tests {
         assert_eq!(array, [0.1, 0.2, 0.3, 0.4]);
     }
 
+    #[test]
+    fn test_color_srgb_conversion() {
+        let color = Color::from_srgb_u8(255, 136, 0, 128);
+        assert_eq!(color.r, 1.0);
+        assert!((color.g - 0.2462).abs() < 1e-4);
+        assert_eq!(color.b, 0.0);
+        assert!((color.a - 128.0 / 255.0).abs() < 1e-6);
+        assert_eq!(color.to_srgb_u8(), [255, 136, 0, 128]);
+
+        // Mid gray on screen is far darker in linear space
+        assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-4);
+        for value in [0.0, 0.002, 0.3, 0.75, 1.0] {
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_color_hex() {
+        let color = Color::from_hex("#ff8800").unwrap();
+        assert_eq!(color, Color::from_srgb_u8(255, 136, 0, 255));
+        assert_eq!("f80".parse::<Color>().unwrap(), color);
+        assert_eq!(Color::from_hex("#ff880080").unwrap().to_hex(), "#ff880080");
+        assert_eq!(color.to_hex(), "#ff8800");
+
+        for hex in ["", "#ff", "#ff880", "#gg8800", "#+f8800"] {
+            assert!(Color::from_hex(hex).is_err(), "{hex} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_color_hsv_hsl() {
+        let orange = Color::from_hsv(32.0, 1.0, 1.0, 1.0);
+        assert_eq!(orange.to_srgb_u8(), [255, 136, 0, 255]);
+        let [hue, saturation, value, _] = orange.to_hsv();
+        assert!((hue - 32.0).abs() < 1e-3);
+        assert!((saturation - 1.0).abs() < 1e-5 && (value - 1.0).abs() < 1e-5);
+
+        let hsl = Color::from_hsl(210.0, 0.5, 0.25, 1.0);
+        assert_eq!(hsl.to_srgb_u8(), [32, 64, 96, 255]);
+        let [hue, saturation, lightness, _] = hsl.to_hsl();
+        assert!((hue - 210.0).abs() < 1e-3);
+        assert!((saturation - 0.5).abs() < 1e-4 && (lightness - 0.25).abs() < 1e-4);
+
+        assert_eq!(Color::GRAY.to_hsv()[1], 0.0);
+    }
+
+    #[test]
+    fn test_color_blending() {
+        let mid = Color::BLACK.lerp(Color::WHITE.with_alpha(0.0), 0.5);
+        assert_eq!(mid, Color::new(0.5, 0.5, 0.5, 0.5));
+
+        let premultiplied = Color::new(1.0, 0.5, 0.0, 0.5).premultiply();
+        assert_eq!(premultiplied, Color::new(0.5, 0.25, 0.0, 0.5));
+        assert_eq!(
+            premultiplied.unpremultiply(),
+            Color::new(1.0, 0.5, 0.0, 0.5)
+        );
+        assert_eq!(Color::TRANSPARENT.unpremultiply(), Color::TRANSPARENT);
+    }
+
     #[test]
     fn test_vertex_default() {
         let vertex = Vertex::default();
@@ -228,6 +581,14 @@ mod tests {
         assert_eq!(vertex.color, [1.0, 1.0, 1.0, 1.0]);
     }
 
+    #[test]
+    fn test_frame_constants_layout() {
+        // Must match the `FrameConstants` struct in shader_types.h
+        assert_eq!(std::mem::size_of::<FrameConstants>(), 64);
+        // Must match the `TemporalConstants` struct in shader_types.h
+        assert_eq!(std::mem::size_of::<TemporalConstants>(), 80);
+    }
+
     #[test]
     fn test_primitive_type_conversion() {
         assert_eq!(
@@ -249,4 +610,17 @@ mod tests {
         assert_eq!(MTLIndexType::from(IndexType::UInt16), MTLIndexType::UInt16);
         assert_eq!(MTLIndexType::from(IndexType::UInt32), MTLIndexType::UInt32);
     }
+
+    #[test]
+    fn test_layer_mask() {
+        let mask = LayerMask::DEFAULT | LayerMask::GIZMO;
+        assert!(mask.intersects(LayerMask::GIZMO));
+        assert!(!mask.intersects(LayerMask::UI));
+        assert!(mask.contains(LayerMask::DEFAULT));
+        assert!(!mask.contains(LayerMask::DEFAULT | LayerMask::UI));
+        assert!(LayerMask::ALL.contains(mask));
+        assert!(!(mask & !LayerMask::GIZMO).intersects(LayerMask::GIZMO));
+        assert_eq!(LayerMask::layer(2), LayerMask::GIZMO);
+        assert!(LayerMask::default().intersects(LayerMask::SHADOW_CASTER));
+    }
 }
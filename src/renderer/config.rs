@@ -0,0 +1,256 @@
+//! Config module for the renderer.
+//!
+//! This module provides `RendererConfig`, the options a renderer is created
+//! with, `AntiAliasing`, the post-process anti-aliasing applied to every
+//! frame, and `Transparency`, how alpha-blended draws are composited. The
+//! window a `RendererSystem` opens is described by `WindowConfig`, and the
+//! directories files are read from and written to by `AssetPaths`.
+//! `RendererConfig::apply` hands a config's options to a renderer.
+
+use super::backend::GraphicsBackend;
+use super::config_file::ConfigFile;
+use super::display_link::FrameRateRange;
+#[cfg(feature = "windowing")]
+use super::input::InputBindings;
+use super::render_core::Renderer;
+use super::render_scale::RenderScale;
+use super::upload_scheduler::DEFAULT_UPLOAD_BUDGET;
+use glam::Vec2;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The number of jitter offsets cycled through by temporal anti-aliasing.
+const TAA_SAMPLE_COUNT: u32 = 8;
+
+/// The post-process anti-aliasing applied to every frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AntiAliasing {
+    /// Frames are presented as rendered.
+    #[default]
+    None,
+    /// Fast approximate anti-aliasing, which blurs along edges found by
+    /// their contrast in luma. Cheap, but softens texture detail.
+    Fxaa,
+    /// Temporal anti-aliasing, which offsets the projection by a different
+    /// sub-pixel jitter every frame and blends each frame with the previous
+    /// ones, reprojected through the depth buffer.
+    Taa,
+}
+
+impl AntiAliasing {
+    /// Returns `true` if frames are rendered offscreen and resolved before presenting.
+    pub fn is_enabled(&self) -> bool {
+        *self != AntiAliasing::None
+    }
+
+    /// Returns the sub-pixel offset applied to the projection of a frame.
+    ///
+    /// Temporal anti-aliasing cycles through a Halton (2, 3) sequence, which
+    /// covers the pixel evenly. Other modes are not jittered.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_index` - The index of the frame.
+    ///
+    /// # Returns
+    ///
+    /// The offset in pixels, between -0.5 and 0.5 on both axes.
+    pub fn jitter(&self, frame_index: u32) -> Vec2 {
+        if *self != AntiAliasing::Taa {
+            return Vec2::ZERO;
+        }
+        // The sequence starts at 1, since index 0 is the origin on both axes
+        let index = frame_index % TAA_SAMPLE_COUNT + 1;
+        Vec2::new(halton(index, 2), halton(index, 3)) - 0.5
+    }
+}
+
+/// How draws with `BlendMode::Alpha` are composited over the scene.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transparency {
+    /// Transparent draws are blended in submission order after every opaque
+    /// draw. Correct only when they are submitted back to front and do not
+    /// intersect.
+    #[default]
+    Ordered,
+    /// Weighted blended order-independent transparency, which accumulates
+    /// every transparent fragment weighted by its alpha and depth, then
+    /// composites the weighted average over the scene. Correct for
+    /// intersecting and unsorted geometry, but approximates the ordering of
+    /// layers with similar depth and renders frames offscreen.
+    WeightedBlended,
+}
+
+/// The window a `RendererSystem` opens.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// The width of the window in logical pixels.
+    pub width: u32,
+    /// The height of the window in logical pixels.
+    pub height: u32,
+    pub title: String,
+    pub resizable: bool,
+}
+
+impl Default for WindowConfig {
+    /// An 800 by 600 resizable window.
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            title: "Metal Renderer".to_string(),
+            resizable: true,
+        }
+    }
+}
+
+/// The directories the engine reads assets from and writes captures to.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AssetPaths {
+    /// The directory relative asset paths are resolved against.
+    pub root: PathBuf,
+    /// The directory frame recordings and GPU captures are written to.
+    pub captures: PathBuf,
+}
+
+impl Default for AssetPaths {
+    /// The working directory for both.
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("."),
+            captures: PathBuf::from("."),
+        }
+    }
+}
+
+impl AssetPaths {
+    /// Returns the path of an asset, resolving relative paths against the root.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Returns the path of a capture file in the captures directory.
+    pub fn capture(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.captures.join(name)
+    }
+}
+
+/// The options a renderer is created with.
+///
+/// Options can be loaded from a settings file with `RendererConfig::from_file`,
+/// or deserialized from the same layout with serde.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(from = "ConfigFile")]
+pub struct RendererConfig {
+    /// The window a `RendererSystem` opens. Renderers drawing into an
+    /// existing window ignore it.
+    pub window: WindowConfig,
+    /// The anti-aliasing applied to every frame.
+    pub anti_aliasing: AntiAliasing,
+    /// How alpha-blended draws are composited.
+    pub transparency: Transparency,
+    /// The resolution the scene is rendered at, relative to the window.
+    pub render_scale: RenderScale,
+    /// Whether opaque meshes draw their depth from positions alone before shading.
+    pub depth_prepass: bool,
+    /// Whether frames are presented in sync with the display's refresh.
+    pub vsync: bool,
+    /// The frame rates to present at, or `None` to present at the display's refresh rate.
+    pub frame_rate: Option<FrameRateRange>,
+    /// Whether new pipeline permutations compile in the background rather than on first use.
+    pub async_pipelines: bool,
+    /// The bytes of mesh and texture uploads submitted per frame, or `None`
+    /// to submit every upload as soon as it is made.
+    pub upload_budget: Option<u64>,
+    /// The keys bound to the renderer's actions.
+    #[cfg(feature = "windowing")]
+    pub input: InputBindings,
+    pub assets: AssetPaths,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            window: WindowConfig::default(),
+            anti_aliasing: AntiAliasing::default(),
+            transparency: Transparency::default(),
+            render_scale: RenderScale::default(),
+            depth_prepass: false,
+            vsync: true,
+            frame_rate: None,
+            async_pipelines: true,
+            upload_budget: Some(DEFAULT_UPLOAD_BUDGET),
+            #[cfg(feature = "windowing")]
+            input: InputBindings::default(),
+            assets: AssetPaths::default(),
+        }
+    }
+}
+
+impl RendererConfig {
+    /// Applies the options to a renderer, besides the window's.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer to configure.
+    pub fn apply<B: GraphicsBackend>(&self, renderer: &mut Renderer<B>) {
+        renderer.set_anti_aliasing(self.anti_aliasing);
+        renderer.set_transparency(self.transparency);
+        renderer.set_render_scale(self.render_scale);
+        renderer.set_depth_prepass(self.depth_prepass);
+        renderer.set_vsync(self.vsync);
+        renderer.set_frame_rate_range(self.frame_rate);
+        renderer.set_async_pipelines(self.async_pipelines);
+        renderer.set_upload_budget(self.upload_budget);
+        #[cfg(feature = "windowing")]
+        {
+            *renderer.input_bindings_mut() = self.input.clone();
+        }
+        renderer.set_asset_paths(self.assets.clone());
+    }
+}
+
+/// Returns element `index` of the Halton sequence in `base`, in [0, 1).
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{halton, AntiAliasing, TAA_SAMPLE_COUNT};
+    use glam::Vec2;
+
+    #[test]
+    fn test_halton_sequence() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((halton(5, 3) - 7.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_jitter() {
+        assert_eq!(AntiAliasing::Fxaa.jitter(3), Vec2::ZERO);
+
+        let taa = AntiAliasing::Taa;
+        assert_eq!(taa.jitter(0), Vec2::new(0.0, 1.0 / 3.0 - 0.5));
+        assert_eq!(taa.jitter(TAA_SAMPLE_COUNT), taa.jitter(0));
+        for frame_index in 0..TAA_SAMPLE_COUNT {
+            let jitter = taa.jitter(frame_index);
+            assert!(jitter.abs().max_element() <= 0.5);
+            assert_ne!(jitter, taa.jitter(frame_index + 1));
+        }
+    }
+}
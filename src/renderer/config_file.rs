@@ -0,0 +1,435 @@
+//! Config file module for the renderer.
+//!
+//! This module loads a `RendererConfig` from a settings file such as
+//! `engine.toml`, so deployments can be tuned without recompiling. The file
+//! is TOML, read through serde into `ConfigFile`, whose sections mirror the
+//! layout below and are then folded into a `RendererConfig`. Settings left
+//! out keep their defaults. Unknown sections and keys are logged and skipped,
+//! so a file can carry settings of newer versions.
+//!
+//! ```toml
+//! [window]
+//! width = 1280
+//! height = 720
+//! title = "Demo"
+//! resizable = false
+//!
+//! [graphics]
+//! anti_aliasing = "taa"        # "none", "fxaa" or "taa"
+//! transparency = "ordered"     # or "weighted_blended"
+//! render_scale = 0.75          # or "dynamic"
+//! target_frame_time_ms = 16.6  # held by the dynamic render scale
+//! depth_prepass = true
+//! vsync = false
+//! max_frame_rate = 60          # e.g. 60 Hz on a 120 Hz ProMotion display
+//! async_pipelines = false      # compile pipelines on first use
+//! upload_budget_mb = 8         # mesh and texture uploads per frame, or "unlimited"
+//!
+//! [input]
+//! move_forward = "ArrowUp"     # actions bound to winit key code names
+//!
+//! [assets]
+//! root = "assets"
+//! captures = "captures"
+//! ```
+//!
+//! The renderer has no multisampling, so `msaa` only accepts 1; edges are
+//! smoothed by `anti_aliasing` instead. Key bindings need the `windowing`
+//! feature, without which the `[input]` section is skipped.
+
+#[cfg(feature = "windowing")]
+use super::input::{key_from_name, Action};
+use super::{
+    config::{AntiAliasing, AssetPaths, RendererConfig, Transparency, WindowConfig},
+    display_link::FrameRateRange,
+    error::ConfigError,
+    render_scale::{DynamicResolution, RenderScale},
+};
+use log::{info, warn};
+use serde::Deserialize;
+#[cfg(feature = "windowing")]
+use std::collections::BTreeMap;
+use std::{fs, path::Path, str::FromStr};
+#[cfg(feature = "windowing")]
+use winit::keyboard::KeyCode;
+
+/// The target frame time of a dynamic render scale without `target_frame_time_ms`.
+const DEFAULT_TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+
+/// The sections of a settings file, before they are folded into a `RendererConfig`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    window: WindowConfig,
+    graphics: GraphicsSection,
+    /// Action names bound to winit key code names.
+    #[cfg(feature = "windowing")]
+    input: BTreeMap<String, KeyName>,
+    assets: AssetPaths,
+}
+
+/// The `[graphics]` section of a settings file.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct GraphicsSection {
+    anti_aliasing: AntiAliasing,
+    transparency: Transparency,
+    render_scale: Option<RenderScaleSetting>,
+    target_frame_time_ms: Option<Positive>,
+    depth_prepass: bool,
+    vsync: bool,
+    max_frame_rate: Option<Positive>,
+    async_pipelines: bool,
+    upload_budget_mb: Option<UploadBudgetSetting>,
+    /// Only validated, since any accepted value leaves rendering unchanged.
+    msaa: Option<Msaa>,
+}
+
+impl Default for GraphicsSection {
+    fn default() -> Self {
+        let config = RendererConfig::default();
+        Self {
+            anti_aliasing: config.anti_aliasing,
+            transparency: config.transparency,
+            render_scale: None,
+            target_frame_time_ms: None,
+            depth_prepass: config.depth_prepass,
+            vsync: config.vsync,
+            max_frame_rate: None,
+            async_pipelines: config.async_pipelines,
+            upload_budget_mb: None,
+            msaa: None,
+        }
+    }
+}
+
+/// A number above zero.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "f64")]
+struct Positive(f32);
+
+impl TryFrom<f64> for Positive {
+    type Error = String;
+
+    fn try_from(number: f64) -> Result<Self, Self::Error> {
+        if number > 0.0 && number.is_finite() {
+            Ok(Positive(number as f32))
+        } else {
+            Err(format!("expected a positive number, found {number}"))
+        }
+    }
+}
+
+/// A setting that is either a number or one of a few names.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrName {
+    Number(f64),
+    Name(String),
+}
+
+impl NumberOrName {
+    /// Returns the number if it is positive, or an error naming the accepted values.
+    fn positive(self, name: &str) -> Result<f32, String> {
+        let expected = || format!("expected a positive number or \"{name}\"");
+        match self {
+            NumberOrName::Number(number) => Positive::try_from(number)
+                .map(|positive| positive.0)
+                .map_err(|_| expected()),
+            NumberOrName::Name(_) => Err(expected()),
+        }
+    }
+}
+
+/// The `render_scale` setting: a fixed scale or `"dynamic"`.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "NumberOrName")]
+enum RenderScaleSetting {
+    Fixed(f32),
+    Dynamic,
+}
+
+impl TryFrom<NumberOrName> for RenderScaleSetting {
+    type Error = String;
+
+    fn try_from(setting: NumberOrName) -> Result<Self, Self::Error> {
+        match setting {
+            NumberOrName::Name(name) if name == "dynamic" => Ok(RenderScaleSetting::Dynamic),
+            setting => setting.positive("dynamic").map(RenderScaleSetting::Fixed),
+        }
+    }
+}
+
+/// The `upload_budget_mb` setting: megabytes per frame or `"unlimited"`.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "NumberOrName")]
+enum UploadBudgetSetting {
+    Bytes(u64),
+    Unlimited,
+}
+
+impl TryFrom<NumberOrName> for UploadBudgetSetting {
+    type Error = String;
+
+    fn try_from(setting: NumberOrName) -> Result<Self, Self::Error> {
+        match setting {
+            NumberOrName::Name(name) if name == "unlimited" => Ok(UploadBudgetSetting::Unlimited),
+            setting => setting
+                .positive("unlimited")
+                .map(|megabytes| UploadBudgetSetting::Bytes((megabytes * 1024.0 * 1024.0) as u64)),
+        }
+    }
+}
+
+/// The `msaa` setting, which only accepts 1 since the renderer has no multisampling.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "u32")]
+struct Msaa;
+
+impl TryFrom<u32> for Msaa {
+    type Error = &'static str;
+
+    fn try_from(samples: u32) -> Result<Self, Self::Error> {
+        match samples {
+            1 => Ok(Msaa),
+            _ => Err("multisampling is not supported, use anti_aliasing"),
+        }
+    }
+}
+
+/// A winit key code name, such as `"ArrowUp"`.
+#[cfg(feature = "windowing")]
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct KeyName(KeyCode);
+
+#[cfg(feature = "windowing")]
+impl TryFrom<String> for KeyName {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        key_from_name(&name)
+            .map(KeyName)
+            .ok_or_else(|| format!("unknown key \"{name}\""))
+    }
+}
+
+impl From<ConfigFile> for RendererConfig {
+    fn from(file: ConfigFile) -> Self {
+        let graphics = file.graphics;
+        let target_frame_time = graphics
+            .target_frame_time_ms
+            .map(|Positive(milliseconds)| milliseconds / 1000.0);
+        let defaults = RendererConfig::default();
+        let render_scale = match graphics.render_scale {
+            Some(RenderScaleSetting::Dynamic) => RenderScale::Dynamic(DynamicResolution::new(
+                target_frame_time.unwrap_or(DEFAULT_TARGET_FRAME_TIME),
+            )),
+            setting => {
+                if target_frame_time.is_some() {
+                    warn!("Ignoring target_frame_time_ms, since the render scale is not dynamic");
+                }
+                match setting {
+                    Some(RenderScaleSetting::Fixed(scale)) => RenderScale::Fixed(scale),
+                    _ => defaults.render_scale,
+                }
+            }
+        };
+
+        #[cfg(feature = "windowing")]
+        let mut input = defaults.input;
+        #[cfg(feature = "windowing")]
+        for (name, KeyName(key)) in file.input {
+            match Action::from_name(&name) {
+                Some(action) => input.bind(action, key),
+                None => warn!("Skipping unknown action \"{name}\""),
+            }
+        }
+
+        RendererConfig {
+            window: file.window,
+            anti_aliasing: graphics.anti_aliasing,
+            transparency: graphics.transparency,
+            render_scale,
+            depth_prepass: graphics.depth_prepass,
+            vsync: graphics.vsync,
+            frame_rate: graphics
+                .max_frame_rate
+                .map(|Positive(rate)| FrameRateRange::new(0.0, rate, rate)),
+            async_pipelines: graphics.async_pipelines,
+            upload_budget: match graphics.upload_budget_mb {
+                Some(UploadBudgetSetting::Bytes(bytes)) => Some(bytes),
+                Some(UploadBudgetSetting::Unlimited) => None,
+                None => defaults.upload_budget,
+            },
+            #[cfg(feature = "windowing")]
+            input,
+            assets: file.assets,
+        }
+    }
+}
+
+impl RendererConfig {
+    /// Loads options from a settings file, see the module documentation.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The settings file, e.g. `engine.toml`.
+    ///
+    /// # Returns
+    ///
+    /// The default options overridden by the file's settings, or an error if
+    /// the file cannot be read or a setting is malformed.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let config = text.parse()?;
+        info!("Loaded settings {}", path.display());
+        Ok(config)
+    }
+}
+
+impl FromStr for RendererConfig {
+    type Err = ConfigError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let deserializer = toml::Deserializer::new(text);
+        serde_ignored::deserialize(deserializer, |setting| {
+            warn!("Skipping unknown setting \"{setting}\"")
+        })
+        .map_err(|error: toml::de::Error| ConfigError::Parse {
+            line: error
+                .span()
+                .map_or(1, |span| text[..span.start].matches('\n').count() + 1),
+            message: error.message().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "windowing")]
+    use crate::renderer::input::Action;
+    use crate::renderer::{
+        config::{AntiAliasing, RendererConfig, Transparency},
+        display_link::FrameRateRange,
+        render_scale::{DynamicResolution, RenderScale},
+    };
+    use std::path::PathBuf;
+    #[cfg(feature = "windowing")]
+    use winit::keyboard::KeyCode;
+
+    #[test]
+    fn test_parse_config() {
+        let config: RendererConfig = r#"
+            # Deployment settings
+            [window]
+            width = 1280
+            height = 720
+            title = "Demo # 1"  # the title keeps its hash
+            resizable = false
+
+            [graphics]
+            anti_aliasing = "taa"
+            transparency = "weighted_blended"
+            render_scale = "dynamic"
+            target_frame_time_ms = 20
+            vsync = false
+            max_frame_rate = 60
+            async_pipelines = false
+            upload_budget_mb = 8
+            msaa = 1
+            bloom = { radius = 2, passes = [1, 2] }
+
+            [assets]
+            root = "assets"
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.window.width, 1280);
+        assert_eq!(config.window.title, "Demo # 1");
+        assert!(!config.window.resizable);
+        assert_eq!(config.anti_aliasing, AntiAliasing::Taa);
+        assert_eq!(config.transparency, Transparency::WeightedBlended);
+        assert_eq!(
+            config.render_scale,
+            RenderScale::Dynamic(DynamicResolution::new(0.02))
+        );
+        assert!(!config.vsync && !config.depth_prepass && !config.async_pipelines);
+        assert_eq!(config.upload_budget, Some(8 * 1024 * 1024));
+        assert_eq!(
+            config.frame_rate,
+            Some(FrameRateRange::new(0.0, 60.0, 60.0))
+        );
+        assert_eq!(
+            config.assets.resolve("a.png"),
+            PathBuf::from("assets/a.png")
+        );
+        assert_eq!(config.assets.capture("f.png"), PathBuf::from("./f.png"));
+
+        let config: RendererConfig = "window = { width = 640 }\ngraphics.render_scale = 0.5"
+            .parse()
+            .unwrap();
+        assert_eq!((config.window.width, config.window.height), (640, 600));
+        assert_eq!(config.render_scale, RenderScale::Fixed(0.5));
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        let error = |text: &str| text.parse::<RendererConfig>().unwrap_err().to_string();
+        assert_eq!(
+            error("[window]\n\nwidth = \"wide\""),
+            "Invalid settings on line 3: invalid type: string \"wide\", expected u32"
+        );
+        assert_eq!(
+            error("[graphics]\nanti_aliasing = \"msaa\""),
+            "Invalid settings on line 2: unknown variant `msaa`, expected one of `none`, `fxaa`, `taa`"
+        );
+        assert_eq!(
+            error("[graphics]\nrender_scale = \"fast\""),
+            "Invalid settings on line 2: expected a positive number or \"dynamic\""
+        );
+        assert_eq!(
+            error("[graphics]\nupload_budget_mb = 0"),
+            "Invalid settings on line 2: expected a positive number or \"unlimited\""
+        );
+        assert_eq!(
+            error("[graphics]\nmsaa = 4"),
+            "Invalid settings on line 2: multisampling is not supported, use anti_aliasing"
+        );
+        assert!(error("[window").starts_with("Invalid settings on line 1: "));
+        assert!(error("[window]\nwidth 800").starts_with("Invalid settings on line 2: "));
+    }
+
+    #[test]
+    #[cfg(feature = "windowing")]
+    fn test_parse_input_bindings() {
+        let config: RendererConfig = r#"
+            [input]
+            move_forward = "ArrowUp"
+            toggle_bounds = "KeyW"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            config.input.key(Action::MoveForward),
+            Some(KeyCode::ArrowUp)
+        );
+        assert_eq!(
+            config.input.action(KeyCode::KeyW),
+            Some(Action::ToggleBounds)
+        );
+
+        let error = "[input]\nmove_up = \"Up\""
+            .parse::<RendererConfig>()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid settings on line 2: unknown key \"Up\""
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Transform constraints module for the renderer.
+//!
+//! This module provides the `Constraint`s a `SceneGraph` applies to nodes'
+//! world transforms after each hierarchy update: turning a node toward a
+//! target, such as a turret toward its enemy or a label toward the camera,
+//! copying another node's position or rotation, and keeping a node within a
+//! distance of a target.
+//!
+//! Constraints are solved in dependency order, so a node constrained to
+//! another sees that node's constrained transform, and the descendants of a
+//! constrained node follow it. A node's constraints apply in the order they
+//! were added. Constraints whose source node was removed do nothing.
+
+use super::scene_graph::NodeId;
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+/// The lengths below which directions and distances are treated as zero.
+const EPSILON: f32 = 1e-6;
+
+/// What a constraint points at or measures against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConstraintTarget {
+    /// The world position of a node.
+    Node(NodeId),
+    /// A position in world space.
+    Point(Vec3),
+    /// The position of the camera, as set with `SceneGraph::set_camera_position`.
+    Camera,
+}
+
+/// A rule overriding part of a node's world transform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constraint {
+    /// Turns the node's -Z axis toward the target, keeping its +Y axis as
+    /// close to `up` as possible, as for cameras and spot lights.
+    LookAt { target: ConstraintTarget, up: Vec3 },
+    /// Turns the node's +Z axis toward the camera, so quads built facing +Z
+    /// face the viewer. With an axis, the node only turns around it, as for
+    /// trees or signposts standing upright.
+    Billboard { axis: Option<Vec3> },
+    /// Moves the node to the world position of another node, plus a world-space offset.
+    CopyPosition { source: NodeId, offset: Vec3 },
+    /// Sets the node's world rotation to that of another node.
+    CopyRotation { source: NodeId },
+    /// Moves the node along the line to the target until its distance lies
+    /// within `min..=max`.
+    DistanceLimit {
+        target: ConstraintTarget,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl Constraint {
+    /// Creates a constraint turning a node's -Z axis toward a node, with +Y up.
+    pub fn look_at(target: NodeId) -> Self {
+        Constraint::LookAt {
+            target: ConstraintTarget::Node(target),
+            up: Vec3::Y,
+        }
+    }
+
+    /// Creates a constraint turning a node's +Z axis toward the camera.
+    pub fn billboard() -> Self {
+        Constraint::Billboard { axis: None }
+    }
+
+    /// Returns the node whose transform the constraint reads, if any.
+    pub fn source(&self) -> Option<NodeId> {
+        match *self {
+            Constraint::LookAt {
+                target: ConstraintTarget::Node(source),
+                ..
+            }
+            | Constraint::DistanceLimit {
+                target: ConstraintTarget::Node(source),
+                ..
+            }
+            | Constraint::CopyPosition { source, .. }
+            | Constraint::CopyRotation { source } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Returns the constraint reading another node in place of its source,
+    /// or unchanged if it has no source.
+    pub fn with_source(mut self, node: NodeId) -> Self {
+        match &mut self {
+            Constraint::LookAt {
+                target: ConstraintTarget::Node(source),
+                ..
+            }
+            | Constraint::DistanceLimit {
+                target: ConstraintTarget::Node(source),
+                ..
+            }
+            | Constraint::CopyPosition { source, .. }
+            | Constraint::CopyRotation { source } => *source = node,
+            _ => {}
+        }
+        self
+    }
+
+    /// Applies the constraint to a world matrix, keeping the parts it doesn't override.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The node's world matrix.
+    /// * `camera` - The position of the camera.
+    /// * `source` - The world matrix of the constraint's source node, if it has one and it exists.
+    ///
+    /// # Returns
+    ///
+    /// The constrained world matrix, or `world` if the source is missing or
+    /// the constraint's direction is undefined.
+    pub fn apply(&self, world: Mat4, camera: Vec3, source: Option<Mat4>) -> Mat4 {
+        let (scale, rotation, translation) = world.to_scale_rotation_translation();
+        let target = |target: ConstraintTarget| match target {
+            ConstraintTarget::Node(_) => source.map(|source| source.w_axis.truncate()),
+            ConstraintTarget::Point(point) => Some(point),
+            ConstraintTarget::Camera => Some(camera),
+        };
+
+        let (rotation, translation) = match *self {
+            Constraint::LookAt { target: to, up } => {
+                let Some(position) = target(to) else {
+                    return world;
+                };
+                match look_rotation(position - translation, up) {
+                    Some(rotation) => (rotation, translation),
+                    None => return world,
+                }
+            }
+            Constraint::Billboard { axis } => {
+                let mut toward = camera - translation;
+                let up = match axis {
+                    Some(axis) => {
+                        let axis = axis.normalize_or_zero();
+                        toward -= axis * toward.dot(axis);
+                        axis
+                    }
+                    None => Vec3::Y,
+                };
+                // Facing -Z away from the camera turns +Z toward it
+                match look_rotation(-toward, up) {
+                    Some(rotation) => (rotation, translation),
+                    None => return world,
+                }
+            }
+            Constraint::CopyPosition { offset, .. } => match source {
+                Some(source) => (rotation, source.w_axis.truncate() + offset),
+                None => return world,
+            },
+            Constraint::CopyRotation { .. } => match source {
+                Some(source) => (source.to_scale_rotation_translation().1, translation),
+                None => return world,
+            },
+            Constraint::DistanceLimit {
+                target: to,
+                min,
+                max,
+            } => {
+                let Some(position) = target(to) else {
+                    return world;
+                };
+                let offset = translation - position;
+                let distance = offset.length();
+                if distance < EPSILON {
+                    return world;
+                }
+                let limited = distance.clamp(min, max.max(min));
+                (rotation, position + offset * (limited / distance))
+            }
+        };
+        Mat4::from_scale_rotation_translation(scale, rotation, translation)
+    }
+}
+
+/// Returns the rotation turning -Z toward a direction with +Y as close to `up`
+/// as possible, or `None` if the direction is zero.
+fn look_rotation(forward: Vec3, up: Vec3) -> Option<Quat> {
+    let forward = forward.try_normalize()?;
+    let right = forward
+        .cross(up)
+        .try_normalize()
+        .unwrap_or_else(|| forward.any_orthonormal_vector());
+    let up = right.cross(forward);
+    Some(Quat::from_mat3(&Mat3::from_cols(right, up, -forward)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Constraint, ConstraintTarget};
+    use crate::renderer::scene_graph::NodeId;
+    use glam::{Mat4, Quat, Vec3};
+
+    fn approx_eq(a: Vec3, b: Vec3) -> bool {
+        a.abs_diff_eq(b, 1e-5)
+    }
+
+    #[test]
+    fn test_constraint_apply() {
+        let world = Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0));
+        let camera = Vec3::new(1.0, 0.0, 5.0);
+
+        // Looking at a point turns -Z toward it
+        let look_at = Constraint::LookAt {
+            target: ConstraintTarget::Point(Vec3::new(4.0, 0.0, 0.0)),
+            up: Vec3::Y,
+        };
+        let looked = look_at.apply(world, camera, None);
+        assert!(approx_eq(looked.transform_vector3(Vec3::NEG_Z), Vec3::X));
+        assert!(approx_eq(looked.w_axis.truncate(), Vec3::X));
+
+        // Billboards turn +Z toward the camera
+        let billboard = Constraint::billboard().apply(world, camera, None);
+        assert!(approx_eq(billboard.transform_vector3(Vec3::Z), Vec3::Z));
+
+        // Copies read the source, and do nothing without one
+        let source =
+            Mat4::from_rotation_translation(Quat::from_rotation_y(1.0), Vec3::new(0.0, 2.0, 0.0));
+        let copy = Constraint::CopyPosition {
+            source: NodeId(0),
+            offset: Vec3::Y,
+        };
+        assert!(approx_eq(
+            copy.apply(world, camera, Some(source)).w_axis.truncate(),
+            Vec3::new(0.0, 3.0, 0.0)
+        ));
+        assert_eq!(copy.apply(world, camera, None), world);
+
+        // Distance limits pull the node toward the target
+        let limit = Constraint::DistanceLimit {
+            target: ConstraintTarget::Camera,
+            min: 0.0,
+            max: 2.0,
+        };
+        assert!(approx_eq(
+            limit.apply(world, camera, None).w_axis.truncate(),
+            Vec3::new(1.0, 0.0, 3.0)
+        ));
+    }
+}
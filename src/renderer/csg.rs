@@ -0,0 +1,712 @@
+//! Constructive solid geometry module for the renderer.
+//!
+//! This module provides `Csg`, a solid built from the triangles of a closed
+//! mesh that can be combined with others by union, subtraction and
+//! intersection. Solids are clipped against each other with binary space
+//! partitioning trees, splitting polygons where the surfaces cross, so the
+//! result is again a closed surface. Vertex colors and attributes are
+//! interpolated along split edges. Results are returned as unindexed
+//! triangle lists, which the mesh optimizer welds when they are added.
+//!
+//! Both operands must be closed and consistently wound; open meshes have no
+//! inside, and combining them gives undefined results.
+
+use super::{
+    common::{PrimitiveType, Vertex},
+    error::CsgError,
+    shape_builders::MeshBuilder,
+    vertex_layout::{VertexLayout, VertexSemantic, VertexStream},
+};
+use glam::{Mat3, Vec3, Vec4};
+
+/// The distance within which a point counts as lying on a plane.
+const PLANE_EPSILON: f32 = 1e-5;
+
+/// Which side of a plane a point or polygon lies on, as bit flags.
+const COPLANAR: u8 = 0;
+const FRONT: u8 = 1;
+const BACK: u8 = 2;
+const SPANNING: u8 = FRONT | BACK;
+
+/// A polygon vertex with its color and the values of its other attributes.
+#[derive(Clone, Debug)]
+struct CsgVertex {
+    position: Vec3,
+    color: Vec4,
+    /// The attribute values, in the order of the solid's stream semantics.
+    values: Vec<[f32; 4]>,
+}
+
+impl CsgVertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(other.position, t),
+            color: self.color.lerp(other.color, t),
+            values: self
+                .values
+                .iter()
+                .zip(&other.values)
+                .map(|(&a, &b)| Vec4::from(a).lerp(Vec4::from(b), t).into())
+                .collect(),
+        }
+    }
+}
+
+/// An oriented plane, with points in front of it on the side its normal faces.
+#[derive(Clone, Copy, Debug)]
+struct Plane {
+    normal: Vec3,
+    distance: f32,
+}
+
+/// The pieces of polygons split by a plane.
+#[derive(Default)]
+struct Split {
+    coplanar_front: Vec<Polygon>,
+    coplanar_back: Vec<Polygon>,
+    front: Vec<Polygon>,
+    back: Vec<Polygon>,
+}
+
+impl Plane {
+    /// Returns the plane of a counter-clockwise triangle, or `None` if it is degenerate.
+    fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Option<Self> {
+        let normal = (b - a).cross(c - a).try_normalize()?;
+        Some(Self {
+            normal,
+            distance: normal.dot(a),
+        })
+    }
+
+    fn flip(&mut self) {
+        self.normal = -self.normal;
+        self.distance = -self.distance;
+    }
+
+    fn side(&self, point: Vec3) -> u8 {
+        let distance = self.normal.dot(point) - self.distance;
+        if distance < -PLANE_EPSILON {
+            BACK
+        } else if distance > PLANE_EPSILON {
+            FRONT
+        } else {
+            COPLANAR
+        }
+    }
+
+    /// Sorts a polygon by its side of the plane, splitting it if it spans the plane.
+    fn split(&self, polygon: Polygon, split: &mut Split) {
+        let sides: Vec<u8> = polygon
+            .vertices
+            .iter()
+            .map(|vertex| self.side(vertex.position))
+            .collect();
+
+        match sides
+            .iter()
+            .fold(COPLANAR, |polygon_side, side| polygon_side | side)
+        {
+            COPLANAR if self.normal.dot(polygon.plane.normal) > 0.0 => {
+                split.coplanar_front.push(polygon)
+            }
+            COPLANAR => split.coplanar_back.push(polygon),
+            FRONT => split.front.push(polygon),
+            BACK => split.back.push(polygon),
+            _ => {
+                let count = polygon.vertices.len();
+                let mut front = Vec::with_capacity(count + 1);
+                let mut back = Vec::with_capacity(count + 1);
+                for i in 0..count {
+                    let j = (i + 1) % count;
+                    let (vertex, next) = (&polygon.vertices[i], &polygon.vertices[j]);
+                    if sides[i] != BACK {
+                        front.push(vertex.clone());
+                    }
+                    if sides[i] != FRONT {
+                        back.push(vertex.clone());
+                    }
+                    if sides[i] | sides[j] == SPANNING {
+                        let t = (self.distance - self.normal.dot(vertex.position))
+                            / self.normal.dot(next.position - vertex.position);
+                        let crossing = vertex.lerp(next, t);
+                        front.push(crossing.clone());
+                        back.push(crossing);
+                    }
+                }
+                // Pieces keep the plane of the whole polygon, which slivers could not recompute
+                if front.len() >= 3 {
+                    split.front.push(Polygon {
+                        vertices: front,
+                        plane: polygon.plane,
+                    });
+                }
+                if back.len() >= 3 {
+                    split.back.push(Polygon {
+                        vertices: back,
+                        plane: polygon.plane,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// A convex, planar polygon with counter-clockwise vertices.
+#[derive(Clone, Debug)]
+struct Polygon {
+    vertices: Vec<CsgVertex>,
+    plane: Plane,
+}
+
+impl Polygon {
+    /// Turns the polygon to face the other way.
+    ///
+    /// # Arguments
+    ///
+    /// * `normal_slot` - The index of the normal in the vertices' values, if they have one.
+    fn flip(&mut self, normal_slot: Option<usize>) {
+        self.vertices.reverse();
+        if let Some(slot) = normal_slot {
+            for vertex in &mut self.vertices {
+                vertex.values[slot] = (-Vec4::from(vertex.values[slot])).into();
+            }
+        }
+        self.plane.flip();
+    }
+}
+
+/// A node of a BSP tree: a splitting plane, the polygons lying in it and its subtrees.
+struct BspNode {
+    plane: Plane,
+    polygons: Vec<Polygon>,
+    front: Option<usize>,
+    back: Option<usize>,
+}
+
+/// A binary space partitioning tree of polygons, stored flat with the root first.
+///
+/// Trees are built and walked with explicit stacks, so meshes whose
+/// planes partition poorly cannot overflow the call stack.
+#[derive(Default)]
+struct Bsp {
+    nodes: Vec<BspNode>,
+}
+
+impl Bsp {
+    fn new(polygons: Vec<Polygon>) -> Self {
+        let mut bsp = Self::default();
+        bsp.build(polygons);
+        bsp
+    }
+
+    fn push_node(&mut self, plane: Plane) -> usize {
+        self.nodes.push(BspNode {
+            plane,
+            polygons: Vec::new(),
+            front: None,
+            back: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Adds polygons to the tree, splitting them by the planes of existing nodes.
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+        if self.nodes.is_empty() {
+            self.push_node(polygons[0].plane);
+        }
+
+        let mut stack = vec![(0, polygons)];
+        while let Some((index, polygons)) = stack.pop() {
+            let plane = self.nodes[index].plane;
+            let mut split = Split::default();
+            for polygon in polygons {
+                plane.split(polygon, &mut split);
+            }
+            let node = &mut self.nodes[index];
+            node.polygons.append(&mut split.coplanar_front);
+            node.polygons.append(&mut split.coplanar_back);
+
+            if !split.front.is_empty() {
+                let child = match self.nodes[index].front {
+                    Some(child) => child,
+                    None => {
+                        let child = self.push_node(split.front[0].plane);
+                        self.nodes[index].front = Some(child);
+                        child
+                    }
+                };
+                stack.push((child, split.front));
+            }
+            if !split.back.is_empty() {
+                let child = match self.nodes[index].back {
+                    Some(child) => child,
+                    None => {
+                        let child = self.push_node(split.back[0].plane);
+                        self.nodes[index].back = Some(child);
+                        child
+                    }
+                };
+                stack.push((child, split.back));
+            }
+        }
+    }
+
+    /// Turns the solid inside out.
+    fn invert(&mut self, normal_slot: Option<usize>) {
+        for node in &mut self.nodes {
+            for polygon in &mut node.polygons {
+                polygon.flip(normal_slot);
+            }
+            node.plane.flip();
+            std::mem::swap(&mut node.front, &mut node.back);
+        }
+    }
+
+    /// Removes the parts of polygons inside the solid.
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        if self.nodes.is_empty() {
+            return polygons;
+        }
+
+        let mut clipped = Vec::new();
+        let mut stack = vec![(0, polygons)];
+        while let Some((index, polygons)) = stack.pop() {
+            let node = &self.nodes[index];
+            let mut split = Split::default();
+            for polygon in polygons {
+                node.plane.split(polygon, &mut split);
+            }
+            split.front.append(&mut split.coplanar_front);
+            split.back.append(&mut split.coplanar_back);
+
+            match node.front {
+                Some(child) => stack.push((child, split.front)),
+                None => clipped.append(&mut split.front),
+            }
+            // Polygons behind a leaf are inside the solid
+            if let Some(child) = node.back {
+                stack.push((child, split.back));
+            }
+        }
+        clipped
+    }
+
+    /// Removes the parts of this tree's polygons inside another tree's solid.
+    fn clip_to(&mut self, other: &Bsp) {
+        for node in &mut self.nodes {
+            node.polygons = other.clip_polygons(std::mem::take(&mut node.polygons));
+        }
+    }
+
+    fn into_polygons(self) -> Vec<Polygon> {
+        self.nodes
+            .into_iter()
+            .flat_map(|node| node.polygons)
+            .collect()
+    }
+}
+
+/// A closed solid that can be combined with others by union, subtraction and intersection.
+///
+/// # Example
+///
+/// ```ignore
+/// let tunnel = Csg::from_mesh(&block)?.subtract(&Csg::from_mesh(&bore)?);
+/// renderer.add_mesh(tunnel.to_mesh());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Csg {
+    polygons: Vec<Polygon>,
+    /// The layout results are packed in, giving the attributes carried through operations.
+    layout: VertexLayout,
+}
+
+impl Csg {
+    /// Creates a solid from the triangles of a closed mesh.
+    ///
+    /// The mesh's transform is applied, so solids are combined where their
+    /// meshes would be drawn. Degenerate triangles are dropped, and vertices
+    /// without normals take the normal of their triangle.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh` - A closed, consistently wound triangle list.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the solid or a `CsgError` if the mesh is not a well-formed triangle list.
+    pub fn from_mesh(mesh: &MeshBuilder) -> Result<Self, CsgError> {
+        let data = &mesh.data;
+        if data.primitive_type != PrimitiveType::Triangle {
+            return Err(CsgError::NotTriangleList(data.primitive_type));
+        }
+        let vertex_count = data.vertices.len();
+        let indices = match &data.indices {
+            Some(indices) => indices.clone(),
+            None => (0..vertex_count as u32).collect(),
+        };
+        if indices.len() % 3 != 0 || indices.iter().any(|&i| i as usize >= vertex_count) {
+            return Err(CsgError::MalformedIndices);
+        }
+
+        let semantics = stream_semantics(&mesh.layout);
+        let streams: Vec<Option<&VertexStream>> = semantics
+            .iter()
+            .map(|&semantic| {
+                mesh.streams
+                    .iter()
+                    .find(|stream| stream.semantic == semantic)
+            })
+            .collect();
+        let normal_slot = normal_slot(&semantics);
+
+        let transform = data.transform;
+        let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+        // Mirroring transforms turn counter-clockwise triangles clockwise
+        let mirrored = transform.determinant() < 0.0;
+
+        let mut polygons = Vec::with_capacity(indices.len() / 3);
+        for triangle in indices.chunks_exact(3) {
+            let mut corners = [triangle[0], triangle[1], triangle[2]];
+            if mirrored {
+                corners.swap(1, 2);
+            }
+            let positions = corners.map(|i| {
+                transform.transform_point3(Vec3::from(data.vertices[i as usize].position))
+            });
+            let Some(plane) = Plane::from_points(positions[0], positions[1], positions[2]) else {
+                continue;
+            };
+
+            let vertices = corners
+                .iter()
+                .zip(positions)
+                .map(|(&i, position)| {
+                    let i = i as usize;
+                    let values = semantics
+                        .iter()
+                        .zip(&streams)
+                        .enumerate()
+                        .map(|(slot, (semantic, stream))| {
+                            let value = stream.and_then(|stream| stream.values.get(i).copied());
+                            match value {
+                                Some(value) if Some(slot) == normal_slot => (normal_matrix
+                                    * Vec3::from_slice(&value))
+                                .normalize_or_zero()
+                                .extend(0.0)
+                                .into(),
+                                Some(value) => value,
+                                None if Some(slot) == normal_slot => {
+                                    plane.normal.extend(0.0).into()
+                                }
+                                None => semantic.default_value(),
+                            }
+                        })
+                        .collect();
+                    CsgVertex {
+                        position,
+                        color: Vec4::from(data.vertices[i].color),
+                        values,
+                    }
+                })
+                .collect();
+            polygons.push(Polygon { vertices, plane });
+        }
+
+        Ok(Self {
+            polygons,
+            layout: mesh.layout,
+        })
+    }
+
+    /// Returns `true` if the solid has no volume left, e.g. after subtracting it from itself.
+    pub fn is_empty(&self) -> bool {
+        self.polygons.is_empty()
+    }
+
+    /// Returns the space covered by either solid.
+    pub fn union(&self, other: &Csg) -> Csg {
+        let (layout, a, b) = self.aligned(other);
+        let normal_slot = normal_slot(&stream_semantics(&layout));
+        let (mut a, mut b) = (Bsp::new(a), Bsp::new(b));
+
+        a.clip_to(&b);
+        b.clip_to(&a);
+        // Drop the faces of `b` coplanar with faces of `a`, which `a` keeps
+        b.invert(normal_slot);
+        b.clip_to(&a);
+        b.invert(normal_slot);
+        a.build(b.into_polygons());
+
+        Csg {
+            polygons: a.into_polygons(),
+            layout,
+        }
+    }
+
+    /// Returns the space covered by this solid but not the other.
+    pub fn subtract(&self, other: &Csg) -> Csg {
+        let (layout, a, b) = self.aligned(other);
+        let normal_slot = normal_slot(&stream_semantics(&layout));
+        let (mut a, mut b) = (Bsp::new(a), Bsp::new(b));
+
+        a.invert(normal_slot);
+        a.clip_to(&b);
+        b.clip_to(&a);
+        b.invert(normal_slot);
+        b.clip_to(&a);
+        b.invert(normal_slot);
+        a.build(b.into_polygons());
+        a.invert(normal_slot);
+
+        Csg {
+            polygons: a.into_polygons(),
+            layout,
+        }
+    }
+
+    /// Returns the space covered by both solids.
+    pub fn intersect(&self, other: &Csg) -> Csg {
+        let (layout, a, b) = self.aligned(other);
+        let normal_slot = normal_slot(&stream_semantics(&layout));
+        let (mut a, mut b) = (Bsp::new(a), Bsp::new(b));
+
+        a.invert(normal_slot);
+        b.clip_to(&a);
+        b.invert(normal_slot);
+        a.clip_to(&b);
+        b.clip_to(&a);
+        a.build(b.into_polygons());
+        a.invert(normal_slot);
+
+        Csg {
+            polygons: a.into_polygons(),
+            layout,
+        }
+    }
+
+    /// Triangulates the solid into an unindexed triangle list.
+    ///
+    /// The mesh has an identity transform, as the operands' transforms are
+    /// already applied, and the layout of the operands' combined attributes.
+    pub fn to_mesh(&self) -> MeshBuilder {
+        let semantics = stream_semantics(&self.layout);
+        let normal_slot = normal_slot(&semantics);
+        let mut vertices = Vec::new();
+        let mut streams: Vec<VertexStream> = semantics
+            .iter()
+            .map(|&semantic| VertexStream {
+                semantic,
+                values: Vec::new(),
+            })
+            .collect();
+
+        for polygon in &self.polygons {
+            let fan = &polygon.vertices;
+            for i in 1..fan.len() - 1 {
+                for vertex in [&fan[0], &fan[i], &fan[i + 1]] {
+                    vertices.push(Vertex {
+                        position: vertex.position.to_array(),
+                        color: vertex.color.to_array(),
+                    });
+                    for (slot, (stream, &value)) in
+                        streams.iter_mut().zip(&vertex.values).enumerate()
+                    {
+                        // Interpolated normals are shorter than unit length
+                        stream.values.push(if Some(slot) == normal_slot {
+                            Vec3::from_slice(&value)
+                                .normalize_or_zero()
+                                .extend(0.0)
+                                .into()
+                        } else {
+                            value
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut mesh = MeshBuilder::new(vertices, PrimitiveType::Triangle).with_layout(self.layout);
+        mesh.streams = streams;
+        mesh
+    }
+
+    /// Returns the layout of both solids' attributes and both solids' polygons carrying them.
+    fn aligned(&self, other: &Csg) -> (VertexLayout, Vec<Polygon>, Vec<Polygon>) {
+        let layout = other
+            .layout
+            .attributes()
+            .filter(|(semantic, _)| !self.layout.has(*semantic))
+            .fold(self.layout, |layout, (semantic, attribute)| {
+                layout.with_attribute(semantic, attribute.format)
+            });
+        (
+            layout,
+            self.polygons_with_layout(&layout),
+            other.polygons_with_layout(&layout),
+        )
+    }
+
+    /// Returns the solid's polygons with the attribute values of another layout.
+    fn polygons_with_layout(&self, layout: &VertexLayout) -> Vec<Polygon> {
+        if *layout == self.layout {
+            return self.polygons.clone();
+        }
+        let old_semantics = stream_semantics(&self.layout);
+        let slots: Vec<(VertexSemantic, Option<usize>)> = stream_semantics(layout)
+            .into_iter()
+            .map(|semantic| {
+                let slot = old_semantics.iter().position(|&old| old == semantic);
+                (semantic, slot)
+            })
+            .collect();
+
+        self.polygons
+            .iter()
+            .map(|polygon| Polygon {
+                vertices: polygon
+                    .vertices
+                    .iter()
+                    .map(|vertex| CsgVertex {
+                        values: slots
+                            .iter()
+                            .map(|&(semantic, slot)| match slot {
+                                Some(slot) => vertex.values[slot],
+                                None if semantic == VertexSemantic::Normal => {
+                                    polygon.plane.normal.extend(0.0).into()
+                                }
+                                None => semantic.default_value(),
+                            })
+                            .collect(),
+                        ..vertex.clone()
+                    })
+                    .collect(),
+                plane: polygon.plane,
+            })
+            .collect()
+    }
+}
+
+/// Returns the semantics of a layout carried as vertex values, every one but position and color.
+fn stream_semantics(layout: &VertexLayout) -> Vec<VertexSemantic> {
+    layout
+        .attributes()
+        .map(|(semantic, _)| semantic)
+        .filter(|semantic| !matches!(semantic, VertexSemantic::Position | VertexSemantic::Color))
+        .collect()
+}
+
+fn normal_slot(semantics: &[VertexSemantic]) -> Option<usize> {
+    semantics
+        .iter()
+        .position(|&semantic| semantic == VertexSemantic::Normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Csg;
+    use crate::renderer::{
+        common::{PrimitiveType, Vertex},
+        error::CsgError,
+        shape_builders::MeshBuilder,
+        vertex_layout::{VertexFormat, VertexLayout, VertexSemantic},
+    };
+    use glam::{Mat4, Vec3};
+
+    /// A unit cube with its minimum corner at `min`, wound counter-clockwise from outside.
+    fn cube(min: Vec3) -> MeshBuilder {
+        let vertices = (0..8)
+            .map(|corner| Vertex {
+                position: [
+                    (corner & 1) as f32,
+                    (corner >> 1 & 1) as f32,
+                    (corner >> 2 & 1) as f32,
+                ],
+                color: [1.0; 4],
+            })
+            .collect();
+        MeshBuilder::new(vertices, PrimitiveType::Triangle)
+            .with_indices(vec![
+                0, 2, 3, 0, 3, 1, // -z
+                4, 5, 7, 4, 7, 6, // +z
+                0, 1, 5, 0, 5, 4, // -y
+                2, 6, 7, 2, 7, 3, // +y
+                0, 4, 6, 0, 6, 2, // -x
+                1, 3, 7, 1, 7, 5, // +x
+            ])
+            .with_transform(Mat4::from_translation(min))
+    }
+
+    /// Returns the volume enclosed by a triangle list with an identity transform.
+    fn volume(mesh: &MeshBuilder) -> f32 {
+        mesh.data
+            .vertices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(triangle[i].position));
+                a.dot(b.cross(c)) / 6.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_csg_operations() {
+        let a = Csg::from_mesh(&cube(Vec3::ZERO)).unwrap();
+        let b = Csg::from_mesh(&cube(Vec3::splat(0.5))).unwrap();
+        assert!((volume(&a.to_mesh()) - 1.0).abs() < 1e-5);
+
+        assert!((volume(&a.union(&b).to_mesh()) - 1.875).abs() < 1e-4);
+        assert!((volume(&a.subtract(&b).to_mesh()) - 0.875).abs() < 1e-4);
+        assert!((volume(&a.intersect(&b).to_mesh()) - 0.125).abs() < 1e-4);
+
+        assert!(a.subtract(&a).is_empty());
+        let far = Csg::from_mesh(&cube(Vec3::splat(5.0))).unwrap();
+        assert!(a.intersect(&far).is_empty());
+        assert!((volume(&a.union(&far).to_mesh()) - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_csg_carries_attributes() {
+        let lit = cube(Vec3::ZERO).with_layout(
+            VertexLayout::position_color()
+                .with_attribute(VertexSemantic::Normal, VertexFormat::Float3),
+        );
+        let result = Csg::from_mesh(&lit)
+            .unwrap()
+            .subtract(&Csg::from_mesh(&cube(Vec3::splat(0.5))).unwrap())
+            .to_mesh();
+
+        assert!(result.layout.has(VertexSemantic::Normal));
+        let normals = &result.streams[0];
+        assert_eq!(normals.semantic, VertexSemantic::Normal);
+        assert_eq!(normals.values.len(), result.data.vertices.len());
+        // Faces without normals take their plane's, including the carved-out faces
+        for (triangle, normals) in result
+            .data
+            .vertices
+            .chunks_exact(3)
+            .zip(normals.values.chunks_exact(3))
+        {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(triangle[i].position));
+            let face = (b - a).cross(c - a).normalize();
+            assert!(Vec3::from_slice(&normals[0]).dot(face) > 0.999);
+        }
+    }
+
+    #[test]
+    fn test_csg_rejects_malformed_meshes() {
+        let lines = MeshBuilder::new(vec![Vertex::default(); 2], PrimitiveType::Line);
+        assert_eq!(
+            Csg::from_mesh(&lines).unwrap_err(),
+            CsgError::NotTriangleList(PrimitiveType::Line)
+        );
+        let out_of_range = cube(Vec3::ZERO).with_indices(vec![0, 1, 8]);
+        assert_eq!(
+            Csg::from_mesh(&out_of_range).unwrap_err(),
+            CsgError::MalformedIndices
+        );
+    }
+}
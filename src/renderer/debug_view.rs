@@ -0,0 +1,216 @@
+//! Debug view module for the renderer.
+//!
+//! This module provides the `DebugView` shading modes, which replace the
+//! material color with a visualization of the geometry, overdraw or
+//! batching, and helpers that draw per-vertex normals as line segments.
+//!
+//! Vertices do not carry normals, tangents or texture coordinates yet, so
+//! normals are derived from the triangles sharing each vertex. Tangent lines
+//! and a UV view can be added once vertices carry texture coordinates.
+
+use super::{
+    common::{PrimitiveType, Vertex},
+    render_queue::GeometryView,
+};
+use glam::{Mat4, Vec3};
+
+/// Selects what the fragment shader outputs.
+///
+/// The discriminants must match the `DEBUG_VIEW_*` constants in the fragment shader.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    /// Regular material shading.
+    #[default]
+    Shaded = 0,
+    /// Face normals, mapped from [-1, 1] to RGB.
+    Normals = 1,
+    /// Linear depth between the near and far planes, from black to white.
+    Depth = 2,
+    /// Brightens each pixel every time it is shaded, ignoring depth, to
+    /// reveal overdraw.
+    Overdraw = 3,
+    /// Colors each draw call differently, to reveal how geometry is batched.
+    DrawCall = 4,
+}
+
+impl DebugView {
+    /// Returns the next view in the cycle, wrapping back to `Shaded`.
+    pub fn next(self) -> Self {
+        match self {
+            DebugView::Shaded => DebugView::Normals,
+            DebugView::Normals => DebugView::Depth,
+            DebugView::Depth => DebugView::Overdraw,
+            DebugView::Overdraw => DebugView::DrawCall,
+            DebugView::DrawCall => DebugView::Shaded,
+        }
+    }
+}
+
+/// Computes a normal per vertex by averaging the normals of adjacent triangles.
+///
+/// Triangles are weighted by area, and counter-clockwise triangles face the
+/// viewer. Vertices of non-triangle geometry get zero normals.
+pub fn vertex_normals(geometry: &GeometryView) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; geometry.vertices.len()];
+    let index_count = geometry
+        .indices
+        .map_or(geometry.vertices.len(), |indices| indices.len());
+    let index = |i: usize| geometry.indices.map_or(i, |indices| indices[i] as usize);
+
+    let triangles: Box<dyn Iterator<Item = [usize; 3]>> = match geometry.primitive_type {
+        PrimitiveType::Triangle => Box::new(
+            (0..index_count / 3).map(|t| [index(3 * t), index(3 * t + 1), index(3 * t + 2)]),
+        ),
+        // Every other strip triangle is wound clockwise
+        PrimitiveType::TriangleStrip => Box::new((0..index_count.saturating_sub(2)).map(|t| {
+            if t % 2 == 0 {
+                [index(t), index(t + 1), index(t + 2)]
+            } else {
+                [index(t + 1), index(t), index(t + 2)]
+            }
+        })),
+        _ => return normals,
+    };
+
+    for [a, b, c] in triangles {
+        let (Some(va), Some(vb), Some(vc)) = (
+            geometry.vertices.get(a),
+            geometry.vertices.get(b),
+            geometry.vertices.get(c),
+        ) else {
+            continue;
+        };
+        let (pa, pb, pc) = (
+            Vec3::from(va.position),
+            Vec3::from(vb.position),
+            Vec3::from(vc.position),
+        );
+        let face_normal = (pb - pa).cross(pc - pa);
+        normals[a] += face_normal;
+        normals[b] += face_normal;
+        normals[c] += face_normal;
+    }
+
+    normals
+        .iter()
+        .map(|normal| normal.normalize_or_zero())
+        .collect()
+}
+
+/// Appends a world-space line segment along the normal of every vertex.
+///
+/// Lines are colored by direction, like the `Normals` debug view.
+///
+/// # Arguments
+///
+/// * `geometry` - The geometry whose normals to draw.
+/// * `transform` - The model matrix of the geometry.
+/// * `length` - The length of each line in world units.
+/// * `lines` - The line-list vertices to append to.
+pub fn append_normal_lines(
+    geometry: &GeometryView,
+    transform: &Mat4,
+    length: f32,
+    lines: &mut Vec<Vertex>,
+) {
+    let normal_matrix = transform.inverse().transpose();
+    for (vertex, normal) in geometry.vertices.iter().zip(vertex_normals(geometry)) {
+        if normal == Vec3::ZERO {
+            continue;
+        }
+
+        let start = transform.transform_point3(Vec3::from(vertex.position));
+        let direction = normal_matrix.transform_vector3(normal).normalize_or_zero();
+        let color = (direction * 0.5 + 0.5).extend(1.0).to_array();
+        lines.push(Vertex {
+            position: start.to_array(),
+            color,
+        });
+        lines.push(Vertex {
+            position: (start + direction * length).to_array(),
+            color,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_normal_lines, vertex_normals, DebugView};
+    use crate::renderer::{
+        common::{PrimitiveType, Vertex},
+        render_queue::GeometryView,
+    };
+    use glam::{Mat4, Vec3};
+
+    fn quad() -> Vec<Vertex> {
+        [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ]
+        .into_iter()
+        .map(|position| Vertex {
+            position,
+            ..Default::default()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_vertex_normals_indexed() {
+        let vertices = quad();
+        let indices = [0, 1, 2, 0, 2, 3];
+        let geometry = GeometryView {
+            vertices: &vertices,
+            indices: Some(&indices),
+            primitive_type: PrimitiveType::Triangle,
+            packed_vertices: None,
+            positions: None,
+        };
+        assert!(vertex_normals(&geometry)
+            .iter()
+            .all(|&normal| normal == Vec3::Z));
+
+        let mut lines = Vec::new();
+        let transform = Mat4::from_rotation_x(std::f32::consts::FRAC_PI_2);
+        append_normal_lines(&geometry, &transform, 0.5, &mut lines);
+        assert_eq!(lines.len(), 8);
+        let end = Vec3::from(lines[1].position) - Vec3::from(lines[0].position);
+        assert!((end - Vec3::new(0.0, -0.5, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_vertex_normals_strip_and_lines() {
+        let vertices = quad();
+        let order = [vertices[0], vertices[1], vertices[3], vertices[2]];
+        let strip = GeometryView {
+            vertices: &order,
+            indices: None,
+            primitive_type: PrimitiveType::TriangleStrip,
+            packed_vertices: None,
+            positions: None,
+        };
+        assert!(vertex_normals(&strip)
+            .iter()
+            .all(|&normal| normal == Vec3::Z));
+
+        let lines = GeometryView {
+            primitive_type: PrimitiveType::Line,
+            ..strip
+        };
+        assert!(vertex_normals(&lines)
+            .iter()
+            .all(|&normal| normal == Vec3::ZERO));
+    }
+
+    #[test]
+    fn test_debug_view_cycle() {
+        let mut view = DebugView::default();
+        for _ in 0..5 {
+            view = view.next();
+        }
+        assert_eq!(view, DebugView::Shaded);
+    }
+}
@@ -0,0 +1,343 @@
+//! Display link module for the renderer.
+//!
+//! This module provides `DisplayLink`, which calls back once per refresh of
+//! the display, on a thread of its own, with the time the next frame will be
+//! shown. Rendering when it fires keeps frames in step with the display,
+//! rather than rendering as fast as the event loop spins, and `DisplayFrame`
+//! carries the frame's timestamp and the display's refresh rate. Display
+//! links are provided by Core Video on macOS; elsewhere `DisplayLink::new`
+//! fails with `BackendError::UnsupportedPlatform`.
+//!
+//! Variable refresh rate displays, such as ProMotion screens refreshing at
+//! up to 120 Hz, report their highest rate through `max_refresh_rate`. A
+//! `FrameRateRange` asks for a lower rate, e.g. 60 Hz to save power, which
+//! frames are paced to at a whole fraction of the refresh rate.
+
+use super::error::BackendError;
+use log::debug;
+use std::ffi::c_void;
+
+/// Receives the frames of a display link, on the display link's thread.
+type FrameCallback = dyn Fn(DisplayFrame) + Send;
+
+/// The most refreshes a frame is shown for when pacing to a lower frame rate.
+const MAX_REFRESHES_PER_FRAME: u32 = 8;
+
+/// A refresh of the display, as reported by a display link.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayFrame {
+    /// When the frame will be shown, in seconds on the display's clock.
+    pub timestamp: f64,
+    /// The time between refreshes of the display in seconds.
+    pub refresh_period: f64,
+}
+
+impl DisplayFrame {
+    /// Creates a new `DisplayFrame` from times in units of a video time scale.
+    ///
+    /// # Returns
+    ///
+    /// The frame, or `None` if the time scale is not positive, as for
+    /// timestamps without video times.
+    pub fn from_video_time(video_time: i64, refresh_period: i64, time_scale: i32) -> Option<Self> {
+        (time_scale > 0).then(|| {
+            let time_scale = time_scale as f64;
+            DisplayFrame {
+                timestamp: video_time as f64 / time_scale,
+                refresh_period: refresh_period as f64 / time_scale,
+            }
+        })
+    }
+
+    /// Returns the refresh rate of the display in hertz.
+    pub fn refresh_rate(&self) -> f64 {
+        1.0 / self.refresh_period
+    }
+}
+
+/// The frame rates to present at, like Core Animation's `CAFrameRateRange`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameRateRange {
+    /// The lowest acceptable frame rate in hertz.
+    pub minimum: f32,
+    /// The highest acceptable frame rate in hertz.
+    pub maximum: f32,
+    /// The frame rate to present at when the display allows it, in hertz.
+    pub preferred: f32,
+}
+
+impl FrameRateRange {
+    /// Creates a new `FrameRateRange`, clamping the preferred rate into the range.
+    pub fn new(minimum: f32, maximum: f32, preferred: f32) -> Self {
+        let maximum = maximum.max(minimum);
+        FrameRateRange {
+            minimum,
+            maximum,
+            preferred: preferred.clamp(minimum, maximum),
+        }
+    }
+
+    /// Creates a new `FrameRateRange` holding a single frame rate.
+    pub fn fixed(rate: f32) -> Self {
+        Self::new(rate, rate, rate)
+    }
+
+    /// Returns the frame rate to present at on a display.
+    ///
+    /// Frames are shown for a whole number of refreshes, so the rate is the
+    /// fraction of the refresh rate closest to the preferred rate within the
+    /// range, or the preferred rate if no fraction lies within it.
+    ///
+    /// # Arguments
+    ///
+    /// * `refresh_rate` - The display's refresh rate in hertz.
+    pub fn frame_rate(&self, refresh_rate: f32) -> f32 {
+        (1..=MAX_REFRESHES_PER_FRAME)
+            .map(|refreshes| refresh_rate / refreshes as f32)
+            .filter(|rate| (self.minimum..=self.maximum).contains(rate))
+            .min_by(|a, b| {
+                (a - self.preferred)
+                    .abs()
+                    .total_cmp(&(b - self.preferred).abs())
+            })
+            .unwrap_or(self.preferred)
+    }
+}
+
+/// Returns the highest refresh rate of the main display in hertz.
+///
+/// ProMotion displays vary their refresh rate up to this rate, while others
+/// always refresh at it. Returns `None` where the rate is unknown, e.g.
+/// before macOS 12 or on platforms other than macOS and iOS.
+pub fn max_refresh_rate() -> Option<f64> {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    unsafe {
+        use metal::objc::{
+            class, msg_send,
+            runtime::{Object, BOOL, NO},
+            sel, sel_impl,
+        };
+
+        #[cfg(target_os = "macos")]
+        let screen: *mut Object = msg_send![class!(NSScreen), mainScreen];
+        #[cfg(target_os = "ios")]
+        let screen: *mut Object = msg_send![class!(UIScreen), mainScreen];
+        if screen.is_null() {
+            return None;
+        }
+        let supported: BOOL = msg_send![screen, respondsToSelector: sel!(maximumFramesPerSecond)];
+        if supported == NO {
+            return None;
+        }
+        let rate: isize = msg_send![screen, maximumFramesPerSecond];
+        (rate > 0).then_some(rate as f64)
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    None
+}
+
+/// Calls back once per refresh of the display until dropped.
+pub struct DisplayLink {
+    /// The Core Video display link.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    link: *mut c_void,
+    /// The boxed `FrameCallback` the display link passes to `output_callback`.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    callback: *mut Box<FrameCallback>,
+}
+
+impl DisplayLink {
+    /// Creates and starts a display link following the active displays.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Called on the display link's thread for every refresh.
+    ///
+    /// # Returns
+    ///
+    /// The running `DisplayLink`, or a `BackendError` if the platform has no
+    /// display link or Core Video fails to create one.
+    #[cfg(target_os = "macos")]
+    pub fn new<F>(callback: F) -> Result<Self, BackendError>
+    where
+        F: Fn(DisplayFrame) + Send + 'static,
+    {
+        let callback: Box<Box<FrameCallback>> = Box::new(Box::new(callback));
+        let callback = Box::into_raw(callback);
+        let mut link = std::ptr::null_mut();
+        unsafe {
+            let status = core_video::CVDisplayLinkCreateWithActiveCGDisplays(&mut link);
+            if status != core_video::SUCCESS {
+                drop(Box::from_raw(callback));
+                return Err(BackendError::DisplayLinkFailed(status));
+            }
+            // From here on, dropping the display link releases the callback
+            let display_link = DisplayLink { link, callback };
+            let status = core_video::CVDisplayLinkSetOutputCallback(
+                link,
+                core_video::output_callback,
+                callback.cast(),
+            );
+            if status != core_video::SUCCESS {
+                return Err(BackendError::DisplayLinkFailed(status));
+            }
+            let status = core_video::CVDisplayLinkStart(link);
+            if status != core_video::SUCCESS {
+                return Err(BackendError::DisplayLinkFailed(status));
+            }
+            debug!("Display link started");
+            Ok(display_link)
+        }
+    }
+
+    /// Creates and starts a display link following the active displays.
+    ///
+    /// # Returns
+    ///
+    /// `BackendError::UnsupportedPlatform`, since display links are only
+    /// provided on macOS.
+    #[cfg(not(target_os = "macos"))]
+    pub fn new<F>(_callback: F) -> Result<Self, BackendError>
+    where
+        F: Fn(DisplayFrame) + Send + 'static,
+    {
+        Err(BackendError::UnsupportedPlatform)
+    }
+
+    /// Stops calling back until resumed, e.g. while the window is hidden.
+    pub fn pause(&self) {
+        #[cfg(target_os = "macos")]
+        unsafe {
+            core_video::CVDisplayLinkStop(self.link);
+        }
+        debug!("Display link paused");
+    }
+
+    /// Calls back once per refresh again after a pause.
+    pub fn resume(&self) {
+        #[cfg(target_os = "macos")]
+        unsafe {
+            core_video::CVDisplayLinkStart(self.link);
+        }
+        debug!("Display link resumed");
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for DisplayLink {
+    fn drop(&mut self) {
+        unsafe {
+            // Stopping waits for a running callback, so the callback can be freed after
+            core_video::CVDisplayLinkStop(self.link);
+            core_video::CVDisplayLinkRelease(self.link);
+            drop(Box::from_raw(self.callback));
+        }
+        debug!("Display link stopped");
+    }
+}
+
+/// Bindings to the display link functions of Core Video.
+#[cfg(target_os = "macos")]
+mod core_video {
+    use super::{DisplayFrame, FrameCallback};
+    use std::ffi::c_void;
+
+    pub type CVReturn = i32;
+
+    pub const SUCCESS: CVReturn = 0;
+
+    #[repr(C)]
+    pub struct CVSMPTETime {
+        subframes: i16,
+        subframe_divisor: i16,
+        counter: u32,
+        kind: u32,
+        flags: u32,
+        hours: i16,
+        minutes: i16,
+        seconds: i16,
+        frames: i16,
+    }
+
+    #[repr(C)]
+    pub struct CVTimeStamp {
+        version: u32,
+        video_time_scale: i32,
+        video_time: i64,
+        host_time: u64,
+        rate_scalar: f64,
+        video_refresh_period: i64,
+        smpte_time: CVSMPTETime,
+        flags: u64,
+        reserved: u64,
+    }
+
+    pub type CVDisplayLinkOutputCallback = extern "C" fn(
+        link: *mut c_void,
+        now: *const CVTimeStamp,
+        output_time: *const CVTimeStamp,
+        flags_in: u64,
+        flags_out: *mut u64,
+        context: *mut c_void,
+    ) -> CVReturn;
+
+    #[link(name = "CoreVideo", kind = "framework")]
+    extern "C" {
+        pub fn CVDisplayLinkCreateWithActiveCGDisplays(link: *mut *mut c_void) -> CVReturn;
+        pub fn CVDisplayLinkSetOutputCallback(
+            link: *mut c_void,
+            callback: CVDisplayLinkOutputCallback,
+            context: *mut c_void,
+        ) -> CVReturn;
+        pub fn CVDisplayLinkStart(link: *mut c_void) -> CVReturn;
+        pub fn CVDisplayLinkStop(link: *mut c_void) -> CVReturn;
+        pub fn CVDisplayLinkRelease(link: *mut c_void);
+    }
+
+    /// Passes the time the next frame is shown to the display link's `FrameCallback`.
+    pub extern "C" fn output_callback(
+        _link: *mut c_void,
+        _now: *const CVTimeStamp,
+        output_time: *const CVTimeStamp,
+        _flags_in: u64,
+        _flags_out: *mut u64,
+        context: *mut c_void,
+    ) -> CVReturn {
+        let (callback, output_time) =
+            unsafe { (&*(context as *const Box<FrameCallback>), &*output_time) };
+        if let Some(frame) = DisplayFrame::from_video_time(
+            output_time.video_time,
+            output_time.video_refresh_period,
+            output_time.video_time_scale,
+        ) {
+            callback(frame);
+        }
+        SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DisplayFrame, FrameRateRange};
+
+    #[test]
+    fn test_display_frame_from_video_time() {
+        let frame = DisplayFrame::from_video_time(1_200_000, 10_000, 1_200_000).unwrap();
+        assert_eq!(frame.timestamp, 1.0);
+        assert!((frame.refresh_rate() - 120.0).abs() < 1e-9);
+        assert_eq!(DisplayFrame::from_video_time(1, 1, 0), None);
+    }
+
+    #[test]
+    fn test_frame_rate_range() {
+        // Frames last whole refreshes of a 120 Hz display
+        let range = FrameRateRange::new(30.0, 120.0, 55.0);
+        assert_eq!(range.frame_rate(120.0), 60.0);
+        assert_eq!(range.frame_rate(60.0), 60.0);
+        assert_eq!(FrameRateRange::fixed(120.0).frame_rate(120.0), 120.0);
+
+        // Without a fraction in range, frames are paced to the preferred rate
+        assert_eq!(FrameRateRange::fixed(50.0).frame_rate(120.0), 50.0);
+        assert_eq!(FrameRateRange::new(30.0, 60.0, 90.0).preferred, 60.0);
+    }
+}
@@ -0,0 +1,211 @@
+//! Dynamic mesh module for the renderer.
+//!
+//! This module provides `DynamicMesh`, triangle geometry whose vertices move
+//! every frame, such as a `Cloth` from the physics module. Meshes stored in the
+//! renderer are never modified, so dynamic meshes are kept apart and drawn as
+//! transient geometry each frame instead. Transient vertices carry only a
+//! position and a color, so a dynamic mesh shades its color by its normals
+//! against a fixed light direction, lighting both sides of thin surfaces.
+
+use super::{
+    common::{LayerMask, Vertex},
+    Color,
+};
+use crate::physics::cloth::{vertex_normals, Cloth};
+use glam::{Mat4, Vec3};
+
+/// The share of a dynamic mesh's color kept on faces turned away from the light.
+const AMBIENT: f32 = 0.35;
+
+/// Identifies a dynamic mesh added to the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DynamicMeshId(pub usize);
+
+/// Triangle geometry whose vertex positions are replaced every frame.
+#[derive(Clone, Debug)]
+pub struct DynamicMesh {
+    /// The base color of the surface.
+    pub color: Color,
+    /// The direction light travels in, or `None` to draw the color unshaded.
+    pub light_direction: Option<Vec3>,
+    /// The transform from the positions' space to world space.
+    pub transform: Mat4,
+    /// The visibility layers the mesh belongs to.
+    pub layers: LayerMask,
+    positions: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+}
+
+impl DynamicMesh {
+    /// Creates a dynamic mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `positions` - The initial positions of the vertices.
+    /// * `triangles` - The vertex indices of the triangles, which never change.
+    /// * `color` - The base color of the surface.
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[u32; 3]>, color: Color) -> Self {
+        Self {
+            color,
+            light_direction: Some(Vec3::new(-0.3, -1.0, -0.5).normalize()),
+            transform: Mat4::IDENTITY,
+            layers: LayerMask::DEFAULT,
+            positions,
+            triangles,
+        }
+    }
+
+    /// Creates a dynamic mesh drawing a cloth, whose positions are then set after every step.
+    pub fn from_cloth(cloth: &Cloth, color: Color) -> Self {
+        Self::new(
+            cloth.positions().to_vec(),
+            cloth.triangles().to_vec(),
+            color,
+        )
+    }
+
+    /// Sets the direction light travels in, or `None` to draw the color unshaded.
+    pub fn with_light_direction(mut self, light_direction: Option<Vec3>) -> Self {
+        self.light_direction = light_direction.map(Vec3::normalize_or_zero);
+        self
+    }
+
+    /// Sets the transform from the positions' space to world space.
+    pub fn with_transform(mut self, transform: Mat4) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Sets the visibility layers the mesh belongs to.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Returns the positions of the vertices.
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    /// Returns the vertex indices of the triangles.
+    pub fn triangles(&self) -> &[[u32; 3]] {
+        &self.triangles
+    }
+
+    /// Replaces the positions of the vertices, e.g. after a simulation step.
+    ///
+    /// Positions beyond the mesh's vertex count are ignored, and missing
+    /// positions keep their previous value.
+    pub fn set_positions(&mut self, positions: &[Vec3]) {
+        let count = positions.len().min(self.positions.len());
+        self.positions[..count].copy_from_slice(&positions[..count]);
+    }
+
+    /// Returns the indices of the triangles as a flat list.
+    pub(crate) fn indices(&self) -> Vec<u32> {
+        self.triangles.iter().flatten().copied().collect()
+    }
+
+    /// Appends the shaded vertices of the mesh.
+    pub(crate) fn append_vertices(&self, vertices: &mut Vec<Vertex>) {
+        let color = [self.color.r, self.color.g, self.color.b];
+        let Some(light_direction) = self.light_direction else {
+            vertices.extend(self.positions.iter().map(|position| Vertex {
+                position: position.to_array(),
+                color: self.color.into(),
+            }));
+            return;
+        };
+
+        let normals = vertex_normals(&self.positions, &self.triangles);
+        let light_direction = self
+            .transform
+            .inverse()
+            .transform_vector3(light_direction)
+            .normalize_or_zero();
+        vertices.extend(
+            self.positions
+                .iter()
+                .zip(normals)
+                .map(|(position, normal)| {
+                    // Both sides of the surface face the light
+                    let diffuse = normal.dot(light_direction).abs();
+                    let shade = AMBIENT + (1.0 - AMBIENT) * diffuse;
+                    Vertex {
+                        position: position.to_array(),
+                        color: [
+                            color[0] * shade,
+                            color[1] * shade,
+                            color[2] * shade,
+                            self.color.a,
+                        ],
+                    }
+                }),
+        );
+    }
+}
+
+/// The dynamic meshes of a renderer, indexed by `DynamicMeshId`.
+#[derive(Clone, Debug, Default)]
+pub struct DynamicMeshes {
+    meshes: Vec<Option<DynamicMesh>>,
+}
+
+impl DynamicMeshes {
+    /// Adds a dynamic mesh and returns its handle.
+    pub fn add(&mut self, mesh: DynamicMesh) -> DynamicMeshId {
+        self.meshes.push(Some(mesh));
+        DynamicMeshId(self.meshes.len() - 1)
+    }
+
+    /// Removes a dynamic mesh, returning `true` if it existed.
+    pub fn remove(&mut self, id: DynamicMeshId) -> bool {
+        self.meshes
+            .get_mut(id.0)
+            .is_some_and(|mesh| mesh.take().is_some())
+    }
+
+    /// Returns a dynamic mesh for modification, e.g. to set its positions.
+    pub fn get_mut(&mut self, id: DynamicMeshId) -> Option<&mut DynamicMesh> {
+        self.meshes.get_mut(id.0)?.as_mut()
+    }
+
+    /// Returns `true` if there are no dynamic meshes.
+    pub fn is_empty(&self) -> bool {
+        self.meshes.iter().all(Option::is_none)
+    }
+
+    /// Returns the dynamic meshes.
+    pub fn iter(&self) -> impl Iterator<Item = &DynamicMesh> + '_ {
+        self.meshes.iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DynamicMesh;
+    use crate::renderer::Color;
+    use glam::Vec3;
+
+    #[test]
+    fn test_dynamic_mesh_shading() {
+        let mut mesh = DynamicMesh::new(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Z],
+            vec![[0, 1, 2]],
+            Color::WHITE,
+        )
+        .with_light_direction(Some(Vec3::NEG_Y));
+        let mut vertices = Vec::new();
+        mesh.append_vertices(&mut vertices);
+        assert_eq!(vertices.len(), 3);
+        assert_eq!(vertices[0].color, [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(mesh.indices(), vec![0, 1, 2]);
+
+        // Turning the triangle edge-on to the light leaves only the ambient share
+        mesh.set_positions(&[Vec3::ZERO, Vec3::X, Vec3::Y]);
+        vertices.clear();
+        mesh.append_vertices(&mut vertices);
+        assert_eq!(vertices[2].position, [0.0, 1.0, 0.0]);
+        assert!((vertices[2].color[0] - super::AMBIENT).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,387 @@
+//! Editor mode module for the renderer.
+//!
+//! This module provides the `EditorMode`, an optional in-engine editing mode
+//! for the scene graph. While enabled, nodes are selected through a
+//! `Selection`, which highlights them with outlines; the primary selection's
+//! transform, material and parent can be edited. The hierarchy is exposed as
+//! a flattened tree for display.
+//!
+//! The renderer drives the editor from the keyboard and mouse: `F1` toggles
+//! the mode, releasing the cursor from the camera while it is enabled.
+//! Clicking a node selects it and Shift-clicking toggles it. `Tab` selects
+//! the next node, `Enter` picks the node at the center of the screen,
+//! `Insert` duplicates the selection's subtree, `Escape` clears the
+//! selection, the arrow keys move the selection in the XZ plane and
+//! `PageUp`/`PageDown` move it vertically.
+//!
+//! Edits are saved as a scene file with `save_scene` and loaded back with
+//! `load_scene`, both through `SceneDocument`.
+//!
+//! While enabled, the renderer also draws gizmos for its lights, with the
+//! parts chosen by `set_light_gizmos`.
+//!
+//! TODO: add translate/rotate gizmos, and move the inspector and hierarchy
+//! into an egui side panel.
+
+use super::{
+    error::{SceneError, SceneFileError},
+    light_gizmos::LightGizmos,
+    material_manager::MaterialId,
+    render_state::Outline,
+    scene_file::SceneDocument,
+    scene_graph::{NodeId, SceneGraph},
+    selection::{Selection, SelectionMode},
+};
+use crate::math::transform::Transform;
+use glam::Vec3;
+use log::{debug, info};
+use std::{collections::HashMap, path::Path};
+
+/// A row of the hierarchy tree view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HierarchyEntry {
+    pub id: NodeId,
+    /// The number of ancestors of the node.
+    pub depth: usize,
+    pub selected: bool,
+}
+
+/// Selection and editing state for the scene editor.
+pub struct EditorMode {
+    enabled: bool,
+    selection: Selection,
+    light_gizmos: LightGizmos,
+}
+
+impl EditorMode {
+    /// Creates a new, disabled `EditorMode`.
+    pub fn new() -> Self {
+        debug!("Creating new EditorMode");
+        Self {
+            enabled: false,
+            selection: Selection::new(),
+            light_gizmos: LightGizmos::default(),
+        }
+    }
+
+    /// Returns `true` if the editor is active.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turns the editor on or off, clearing the selection when turned off.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - The scene graph being edited.
+    pub fn toggle(&mut self, graph: &mut SceneGraph) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.clear_selection(graph);
+        }
+        info!("Editor mode toggled: {}", self.enabled);
+    }
+
+    /// Returns the primary selected node, which edits apply to.
+    pub fn selected(&self) -> Option<NodeId> {
+        self.selection.primary()
+    }
+
+    /// Returns the selected and hovered nodes.
+    pub fn selection(&self) -> &Selection {
+        &self.selection
+    }
+
+    /// Returns the selected and hovered nodes for modification.
+    pub fn selection_mut(&mut self) -> &mut Selection {
+        &mut self.selection
+    }
+
+    /// Sets the outline used to highlight the selection.
+    pub fn set_highlight(&mut self, graph: &mut SceneGraph, highlight: Outline) {
+        self.selection.set_selection_outline(graph, highlight);
+    }
+
+    /// Returns the parts of the light gizmos drawn while the editor is active.
+    pub fn light_gizmos(&self) -> LightGizmos {
+        self.light_gizmos
+    }
+
+    /// Sets the parts of the light gizmos drawn while the editor is active.
+    pub fn set_light_gizmos(&mut self, light_gizmos: LightGizmos) {
+        self.light_gizmos = light_gizmos;
+        debug!("Editor light gizmos: {:?}", light_gizmos);
+    }
+
+    /// Selects only a node, moving the highlight from the previous selection.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - The scene graph being edited.
+    /// * `id` - The node to select, or `None` to clear the selection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `SceneError::InvalidNode`.
+    pub fn select(&mut self, graph: &mut SceneGraph, id: Option<NodeId>) -> Result<(), SceneError> {
+        self.selection.select(graph, id, SelectionMode::Replace)
+    }
+
+    /// Clears the selection.
+    pub fn clear_selection(&mut self, graph: &mut SceneGraph) {
+        self.selection.clear(graph);
+    }
+
+    /// Selects the node following the current selection in hierarchy order.
+    ///
+    /// Wraps around to the first node, and selects nothing if the graph is empty.
+    pub fn select_next(&mut self, graph: &mut SceneGraph) {
+        let hierarchy = self.hierarchy(graph);
+        let primary = self.selected();
+        let next = match hierarchy.iter().position(|entry| Some(entry.id) == primary) {
+            Some(position) => hierarchy.get((position + 1) % hierarchy.len()),
+            None => hierarchy.first(),
+        };
+        let _ = self.select(graph, next.map(|entry| entry.id));
+    }
+
+    /// Returns the selected node's transform relative to its parent.
+    pub fn selected_transform(&self, graph: &SceneGraph) -> Option<Transform> {
+        graph.local_transform(self.selected()?)
+    }
+
+    /// Replaces the selected node's transform relative to its parent.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `SceneError::NoSelection` if
+    /// nothing is selected.
+    pub fn set_selected_transform(
+        &self,
+        graph: &mut SceneGraph,
+        transform: impl Into<Transform>,
+    ) -> Result<(), SceneError> {
+        let id = self.selected().ok_or(SceneError::NoSelection)?;
+        graph.set_local_transform(id, transform)
+    }
+
+    /// Moves the selected node by `delta` in its parent's space.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `SceneError::NoSelection` if
+    /// nothing is selected.
+    pub fn translate_selected(
+        &self,
+        graph: &mut SceneGraph,
+        delta: Vec3,
+    ) -> Result<(), SceneError> {
+        let id = self.selected().ok_or(SceneError::NoSelection)?;
+        let translation = graph
+            .local_transform(id)
+            .ok_or(SceneError::InvalidNode(id))?
+            .translation;
+        graph.set_translation(id, translation + delta)
+    }
+
+    /// Changes the material, and thereby the color, of the selected node.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `SceneError::NoSelection` if
+    /// nothing is selected.
+    pub fn set_selected_material(
+        &self,
+        graph: &mut SceneGraph,
+        material_id: MaterialId,
+    ) -> Result<(), SceneError> {
+        let id = self.selected().ok_or(SceneError::NoSelection)?;
+        graph.set_material(id, material_id)
+    }
+
+    /// Moves the selected node under a new parent.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `SceneError::NoSelection` if nothing
+    /// is selected, or the error of `SceneGraph::set_parent`.
+    pub fn reparent_selected(
+        &self,
+        graph: &mut SceneGraph,
+        parent: Option<NodeId>,
+    ) -> Result<(), SceneError> {
+        let id = self.selected().ok_or(SceneError::NoSelection)?;
+        graph.set_parent(id, parent)
+    }
+
+    /// Copies the selected node and its descendants, and selects the copy.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the copy, or `SceneError::NoSelection` if
+    /// nothing is selected.
+    pub fn duplicate_selected(&mut self, graph: &mut SceneGraph) -> Result<NodeId, SceneError> {
+        let id = self.selected().ok_or(SceneError::NoSelection)?;
+        let copy = graph.duplicate(id, true)?;
+        self.select(graph, Some(copy))?;
+        Ok(copy)
+    }
+
+    /// Flattens the scene graph into tree view rows.
+    ///
+    /// Nodes follow their parents depth-first, and siblings are ordered by ID.
+    pub fn hierarchy(&self, graph: &SceneGraph) -> Vec<HierarchyEntry> {
+        graph
+            .tree_order()
+            .into_iter()
+            .map(|(id, depth)| HierarchyEntry {
+                id,
+                depth,
+                selected: self.selection.is_selected(id),
+            })
+            .collect()
+    }
+
+    /// Saves every node of the edited scene graph as a scene file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `SceneFileError`.
+    pub fn save_scene(&self, graph: &SceneGraph, path: &Path) -> Result<(), SceneFileError> {
+        SceneDocument::capture(graph).save(path)
+    }
+
+    /// Replaces the nodes of the edited scene graph with those of a scene
+    /// file saved with `save_scene`, clearing the selection.
+    ///
+    /// Nodes get new IDs when loaded. The graph is left unchanged if the
+    /// file can't be read or its hierarchy is broken.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the graph node created for each node of the
+    /// file, or a `SceneFileError`.
+    pub fn load_scene(
+        &mut self,
+        graph: &mut SceneGraph,
+        path: &Path,
+    ) -> Result<HashMap<NodeId, NodeId>, SceneFileError> {
+        let document = SceneDocument::load(path)?;
+        document.hierarchy_order()?;
+
+        self.clear_selection(graph);
+        let roots: Vec<NodeId> = graph
+            .nodes()
+            .filter(|&id| graph.parent(id).is_none())
+            .collect();
+        for root in roots {
+            let _ = graph.remove_node(root);
+        }
+        document.instantiate(graph, None)
+    }
+}
+
+impl Default for EditorMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EditorMode;
+    use crate::renderer::scene_graph::SceneGraph;
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_editor_selection_highlight() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let b = graph.add_node(Some(a), Mat4::IDENTITY).unwrap();
+
+        let mut editor = EditorMode::new();
+        editor.toggle(&mut graph);
+        editor.select_next(&mut graph);
+        assert_eq!(editor.selected(), Some(a));
+        assert!(graph.outline(a).is_some());
+
+        editor.select_next(&mut graph);
+        assert_eq!(editor.selected(), Some(b));
+        assert!(graph.outline(a).is_none());
+        assert!(graph.outline(b).is_some());
+
+        // Disabling the editor removes the highlight
+        editor.toggle(&mut graph);
+        assert_eq!(editor.selected(), None);
+        assert!(graph.outline(b).is_none());
+    }
+
+    #[test]
+    fn test_editor_hierarchy_and_edits() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let other_root = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let child = graph.add_node(Some(root), Mat4::IDENTITY).unwrap();
+
+        let mut editor = EditorMode::new();
+        let rows: Vec<_> = editor
+            .hierarchy(&graph)
+            .iter()
+            .map(|entry| (entry.id, entry.depth))
+            .collect();
+        assert_eq!(rows, vec![(root, 0), (child, 1), (other_root, 0)]);
+
+        assert!(editor.translate_selected(&mut graph, Vec3::X).is_err());
+        editor.select(&mut graph, Some(child)).unwrap();
+        editor.translate_selected(&mut graph, Vec3::X).unwrap();
+        editor
+            .reparent_selected(&mut graph, Some(other_root))
+            .unwrap();
+
+        assert_eq!(graph.parent(child), Some(other_root));
+        assert_eq!(
+            editor.selected_transform(&graph).unwrap().translation,
+            Vec3::X
+        );
+
+        // The copy takes over the selection and its highlight
+        let copy = editor.duplicate_selected(&mut graph).unwrap();
+        assert_eq!(editor.selected(), Some(copy));
+        assert_eq!(graph.parent(copy), Some(other_root));
+        assert!(graph.outline(child).is_none());
+        assert!(graph.outline(copy).is_some());
+    }
+
+    #[test]
+    fn test_editor_save_and_load_scene() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let child = graph
+            .add_node(Some(root), Mat4::from_translation(Vec3::X))
+            .unwrap();
+        graph.set_name(child, Some("door")).unwrap();
+
+        let mut editor = EditorMode::new();
+        let path = std::env::temp_dir().join(format!("editor-{}.scene", std::process::id()));
+        editor.save_scene(&graph, &path).unwrap();
+
+        // Loading replaces the edited nodes and clears the selection
+        editor.select(&mut graph, Some(child)).unwrap();
+        graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let created = editor.load_scene(&mut graph, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(graph.len(), 2);
+        assert_eq!(editor.selected(), None);
+        assert!(graph.outline(created[&child]).is_none());
+        assert_eq!(graph.parent(created[&child]), Some(created[&root]));
+        assert_eq!(graph.find_by_name("door"), Some(created[&child]));
+        assert_eq!(
+            graph.local_transform(created[&child]).unwrap().translation,
+            Vec3::X
+        );
+
+        // A missing file leaves the graph as it was
+        assert!(editor.load_scene(&mut graph, &path).is_err());
+        assert_eq!(graph.len(), 2);
+    }
+}
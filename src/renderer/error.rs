@@ -0,0 +1,802 @@
+//! Error module for the renderer.
+//!
+//! Every subsystem reports failures with its own error type, carrying the
+//! context needed to find the cause: `SceneError` names the scene node,
+//! `AssetError` the mesh, material or texture handle, `PipelineError` the
+//! shader library, function or pipeline, `BackendError` the GPU resource
+//! or platform object, `RecordingError` the recording output, and
+//! `ReplayError` the replay file and line, `ParticleError` the particle
+//! effect file and line, and `SceneFileError` the scene or patch file and
+//! line or the node a patch refers to, and `AssetDatabaseError` the asset
+//! database file and line or the asset GUID. `RendererError` transparently wraps them for APIs
+//! spanning several subsystems. `ColorError` is returned on its own when a
+//! color string is malformed, `CsgError` when meshes cannot be combined,
+//! `ConfigError` when a settings file cannot be loaded,
+//! `ImportSettingsError` when an asset's import settings are invalid,
+//! `ImportError` when a model file such as an STL or PLY cannot be imported,
+//! and `ExportError` when a mesh or scene cannot be exported.
+//! Errors caused by a library error, such as a winit or environment error,
+//! expose it through `Error::source`.
+//!
+//! Errors that should not stop rendering are reported with
+//! `Renderer::report_error`, which hands them to the renderer's error
+//! handler. `RendererSystem` delivers them through the event loop to its
+//! error callback.
+
+use super::{
+    asset_database::AssetGuid,
+    common::{PrimitiveType, TextureId},
+    material_manager::MaterialId,
+    render_queue::GeometryHandle,
+    scene_graph::NodeId,
+};
+use log::error;
+use raw_window_handle::HandleError;
+use std::{error::Error, fmt, io, path::PathBuf};
+#[cfg(feature = "windowing")]
+use winit::error::{EventLoopError, OsError};
+
+/// Errors raised by the scene graph and the editor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneError {
+    /// The node does not exist or was removed.
+    InvalidNode(NodeId),
+    /// The new parent of a node is the node itself or one of its descendants.
+    CyclicParent { node: NodeId, parent: NodeId },
+    /// The source of a node's constraint is the node itself, or depends on it
+    /// through its ancestors or constraints.
+    CyclicConstraint { node: NodeId, source: NodeId },
+    /// The node has no mesh, or its mesh has no socket of the name.
+    MissingSocket { node: NodeId, socket: String },
+    /// An editor operation needs a selected node, but nothing is selected.
+    NoSelection,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::InvalidNode(id) => write!(f, "Scene node {} does not exist", id.0),
+            SceneError::CyclicParent { node, parent } => write!(
+                f,
+                "Scene node {} cannot be parented to its descendant {}",
+                node.0, parent.0
+            ),
+            SceneError::CyclicConstraint { node, source } => write!(
+                f,
+                "Scene node {} cannot be constrained to node {}, which depends on it",
+                node.0, source.0
+            ),
+            SceneError::MissingSocket { node, socket } => write!(
+                f,
+                "Scene node {} has no mesh with a socket named \"{socket}\"",
+                node.0
+            ),
+            SceneError::NoSelection => write!(f, "No scene node is selected"),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+/// Errors raised when parsing colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorError {
+    /// The string is not a hex color of 3, 4, 6 or 8 digits.
+    InvalidHex(String),
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorError::InvalidHex(hex) => write!(f, "Invalid hex color \"{hex}\""),
+        }
+    }
+}
+
+impl Error for ColorError {}
+
+/// Errors raised when combining meshes with constructive solid geometry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsgError {
+    /// The mesh is not a triangle list.
+    NotTriangleList(PrimitiveType),
+    /// The mesh's indices are out of range or not whole triangles.
+    MalformedIndices,
+}
+
+impl fmt::Display for CsgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsgError::NotTriangleList(primitive_type) => {
+                write!(f, "CSG needs a triangle list, not {primitive_type:?}")
+            }
+            CsgError::MalformedIndices => {
+                write!(f, "Mesh indices are out of range or not whole triangles")
+            }
+        }
+    }
+}
+
+impl Error for CsgError {}
+
+/// Errors raised when looking up meshes, materials and textures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum AssetError {
+    /// A mesh or the transient geometry of a draw does not exist.
+    InvalidGeometry(GeometryHandle),
+    InvalidMaterial(MaterialId),
+    InvalidTexture(TextureId),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::InvalidGeometry(GeometryHandle::Mesh(id)) => {
+                write!(f, "Mesh {id} does not exist")
+            }
+            AssetError::InvalidGeometry(GeometryHandle::Transient(id)) => {
+                write!(f, "Transient geometry {id} does not exist")
+            }
+            AssetError::InvalidMaterial(id) => write!(f, "Material {} does not exist", id.0),
+            AssetError::InvalidTexture(id) => write!(f, "Texture {} does not exist", id.0),
+        }
+    }
+}
+
+impl Error for AssetError {}
+
+/// Errors raised while loading shaders and creating pipeline states.
+#[derive(Debug)]
+pub enum PipelineError {
+    /// The `METAL_SHADER_LIB` environment variable is not set.
+    ShaderLibraryPathMissing(std::env::VarError),
+    /// The shader library could not be loaded from `path`.
+    ShaderLibraryLoadFailed { path: String, message: String },
+    /// The shader library has no function with this name.
+    FunctionNotFound { function: String, pipeline: String },
+    /// The device rejected the pipeline descriptor.
+    CreationFailed { pipeline: String, message: String },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::ShaderLibraryPathMissing(_) => {
+                write!(f, "Failed to get shader lib path from METAL_SHADER_LIB")
+            }
+            PipelineError::ShaderLibraryLoadFailed { path, message } => {
+                write!(f, "Failed to load shader library {path}: {message}")
+            }
+            PipelineError::FunctionNotFound { function, pipeline } => {
+                write!(
+                    f,
+                    "Shader function {function} not found for {pipeline} pipeline"
+                )
+            }
+            PipelineError::CreationFailed { pipeline, message } => {
+                write!(f, "Failed to create {pipeline} pipeline: {message}")
+            }
+        }
+    }
+}
+
+impl Error for PipelineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PipelineError::ShaderLibraryPathMissing(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Errors raised by the graphics backend, the window and the event loop.
+#[derive(Debug)]
+pub enum BackendError {
+    DeviceNotFound,
+    UnsupportedPlatform,
+    /// The layer had no drawable available to render into.
+    NoDrawable,
+    /// More items were written to a buffer than it can hold.
+    BufferOverflow {
+        buffer: String,
+        count: usize,
+        capacity: usize,
+    },
+    /// Allocating the buffer would exceed the GPU memory budget.
+    OutOfMemory {
+        buffer: String,
+        requested: u64,
+    },
+    CaptureFailed(String),
+    /// A ray-traced effect was requested on a device without ray tracing.
+    RayTracingUnsupported,
+    /// Core Video failed to create or start a display link, with this status.
+    DisplayLinkFailed(i32),
+    #[cfg(feature = "windowing")]
+    WindowCreationFailed(OsError),
+    #[cfg(feature = "windowing")]
+    EventLoopFailed(EventLoopError),
+    WindowHandle(HandleError),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::DeviceNotFound => write!(f, "Metal device not found"),
+            BackendError::UnsupportedPlatform => write!(f, "Unsupported platform"),
+            BackendError::NoDrawable => write!(f, "No next drawable"),
+            BackendError::BufferOverflow {
+                buffer,
+                count,
+                capacity,
+            } => write!(
+                f,
+                "{buffer} buffer overflow: {count} items exceed maximum of {capacity}"
+            ),
+            BackendError::OutOfMemory { buffer, requested } => write!(
+                f,
+                "GPU memory budget exhausted allocating {requested} bytes for {buffer} buffer"
+            ),
+            BackendError::CaptureFailed(msg) => write!(f, "GPU frame capture failed: {msg}"),
+            BackendError::RayTracingUnsupported => {
+                write!(f, "Ray tracing is not supported by the device")
+            }
+            BackendError::DisplayLinkFailed(status) => {
+                write!(f, "Display link failed with Core Video status {status}")
+            }
+            #[cfg(feature = "windowing")]
+            BackendError::WindowCreationFailed(_) => write!(f, "Window creation with winit failed"),
+            #[cfg(feature = "windowing")]
+            BackendError::EventLoopFailed(_) => write!(f, "Winit event loop error"),
+            BackendError::WindowHandle(_) => write!(f, "Window handle error"),
+        }
+    }
+}
+
+impl Error for BackendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "windowing")]
+            BackendError::WindowCreationFailed(e) => Some(e),
+            #[cfg(feature = "windowing")]
+            BackendError::EventLoopFailed(e) => Some(e),
+            BackendError::WindowHandle(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<HandleError> for BackendError {
+    fn from(error: HandleError) -> Self {
+        error!("Window handle error: {}", error);
+        BackendError::WindowHandle(error)
+    }
+}
+
+/// Errors raised while recording frames to disk.
+#[derive(Debug)]
+pub enum RecordingError {
+    /// A recording is already in progress.
+    AlreadyRecording,
+    /// The output path has no known format, or its encoder is not compiled in.
+    UnsupportedFormat(PathBuf),
+    /// Writing the recording output failed.
+    Io { path: PathBuf, source: io::Error },
+    /// The frame encoder stopped before all frames were written.
+    EncoderFailed(String),
+    /// The backend copied back no frame for a capture.
+    ReadbackFailed,
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::AlreadyRecording => write!(f, "A recording is already in progress"),
+            RecordingError::UnsupportedFormat(path) => {
+                write!(f, "Unsupported recording format for {}", path.display())
+            }
+            RecordingError::Io { path, .. } => {
+                write!(f, "Failed to write recording to {}", path.display())
+            }
+            RecordingError::EncoderFailed(msg) => write!(f, "Frame encoder failed: {msg}"),
+            RecordingError::ReadbackFailed => write!(f, "No frame was copied back from the GPU"),
+        }
+    }
+}
+
+impl Error for RecordingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RecordingError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Errors raised while loading and saving replays.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// Reading or writing the replay file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A line of the replay file is malformed.
+    Parse { line: usize, message: String },
+    /// The replay could not be written as RON.
+    Serialize(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io { path, .. } => {
+                write!(f, "Failed to access replay {}", path.display())
+            }
+            ReplayError::Parse { line, message } => {
+                write!(f, "Invalid replay on line {line}: {message}")
+            }
+            ReplayError::Serialize(message) => write!(f, "Failed to write replay: {message}"),
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReplayError::Io { source, .. } => Some(source),
+            ReplayError::Parse { .. } | ReplayError::Serialize(_) => None,
+        }
+    }
+}
+
+/// Errors raised while loading a particle effect file.
+#[derive(Debug)]
+pub enum ParticleError {
+    /// Reading the particle file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A value of the particle file is malformed or invalid.
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ParticleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParticleError::Io { path, .. } => {
+                write!(f, "Failed to read particle effect {}", path.display())
+            }
+            ParticleError::Parse { line, message } => {
+                write!(f, "Invalid particle effect on line {line}: {message}")
+            }
+        }
+    }
+}
+
+impl Error for ParticleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParticleError::Io { source, .. } => Some(source),
+            ParticleError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Errors raised while loading, saving and patching scene files.
+#[derive(Debug)]
+pub enum SceneFileError {
+    /// Reading or writing the scene or patch file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A line of the scene or patch file is malformed.
+    Parse { line: usize, message: String },
+    /// A patch changes or removes a node the scene doesn't have.
+    MissingNode(NodeId),
+    /// A patch adds a node the scene already has.
+    DuplicateNode(NodeId),
+    /// A node's parent is missing from the scene or is one of its descendants.
+    InvalidParent { node: NodeId, parent: NodeId },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, .. } => {
+                write!(f, "Failed to access scene file {}", path.display())
+            }
+            SceneFileError::Parse { line, message } => {
+                write!(f, "Invalid scene file on line {line}: {message}")
+            }
+            SceneFileError::MissingNode(node) => {
+                write!(f, "Scene has no node with ID {}", node.0)
+            }
+            SceneFileError::DuplicateNode(node) => {
+                write!(f, "Scene already has a node with ID {}", node.0)
+            }
+            SceneFileError::InvalidParent { node, parent } => write!(
+                f,
+                "Node {} has parent {}, which is missing or a descendant",
+                node.0, parent.0
+            ),
+        }
+    }
+}
+
+impl Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneFileError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Errors raised while loading, saving and editing the asset database.
+#[derive(Debug)]
+pub enum AssetDatabaseError {
+    /// Reading or writing the database file failed.
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A line of the database file is malformed.
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// Writing the database as TOML failed.
+    Serialize(String),
+    /// A source path isn't valid UTF-8, so it can't be saved.
+    NonUtf8Path(PathBuf),
+    UnknownAsset(AssetGuid),
+    /// The database file lists an asset twice.
+    DuplicateAsset(AssetGuid),
+    /// Another asset already has the source path.
+    DuplicatePath(PathBuf),
+    /// The dependency already depends on the asset, directly or not.
+    CyclicDependency {
+        asset: AssetGuid,
+        dependency: AssetGuid,
+    },
+    /// The asset can't be removed while other assets depend on it.
+    HasDependents {
+        asset: AssetGuid,
+        dependents: Vec<AssetGuid>,
+    },
+}
+
+impl fmt::Display for AssetDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetDatabaseError::Io { path, .. } => {
+                write!(f, "Failed to access asset database {}", path.display())
+            }
+            AssetDatabaseError::Parse { line, message } => {
+                write!(f, "Invalid asset database on line {line}: {message}")
+            }
+            AssetDatabaseError::Serialize(message) => {
+                write!(f, "Failed to write asset database: {message}")
+            }
+            AssetDatabaseError::NonUtf8Path(path) => write!(
+                f,
+                "Asset path {} is not valid UTF-8 and cannot be saved",
+                path.display()
+            ),
+            AssetDatabaseError::UnknownAsset(guid) => write!(f, "Asset {guid} does not exist"),
+            AssetDatabaseError::DuplicateAsset(guid) => {
+                write!(f, "Asset {guid} appears twice")
+            }
+            AssetDatabaseError::DuplicatePath(path) => {
+                write!(f, "An asset already has the path {}", path.display())
+            }
+            AssetDatabaseError::CyclicDependency { asset, dependency } => write!(
+                f,
+                "Asset {asset} cannot depend on {dependency}, which depends on it"
+            ),
+            AssetDatabaseError::HasDependents { asset, dependents } => write!(
+                f,
+                "Asset {asset} cannot be removed while {} assets depend on it",
+                dependents.len()
+            ),
+        }
+    }
+}
+
+impl Error for AssetDatabaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AssetDatabaseError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Errors raised while loading a settings file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Reading the settings file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A line of the settings file is malformed or has an invalid value.
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, .. } => {
+                write!(f, "Failed to read settings {}", path.display())
+            }
+            ConfigError::Parse { line, message } => {
+                write!(f, "Invalid settings on line {line}: {message}")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Errors raised while reading import settings.
+#[derive(Debug)]
+pub enum ImportSettingsError {
+    /// Reading or writing the settings file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A line of the settings file is malformed or has an invalid value.
+    Parse { line: usize, message: String },
+    /// An asset record's setting has an invalid value.
+    InvalidValue { name: String, message: String },
+    /// The settings could not be written as TOML.
+    Serialize(String),
+}
+
+impl fmt::Display for ImportSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportSettingsError::Io { path, .. } => {
+                write!(f, "Failed to access import settings {}", path.display())
+            }
+            ImportSettingsError::Parse { line, message } => {
+                write!(f, "Invalid import settings on line {line}: {message}")
+            }
+            ImportSettingsError::InvalidValue { name, message } => {
+                write!(f, "Invalid import setting {name}: {message}")
+            }
+            ImportSettingsError::Serialize(message) => {
+                write!(f, "Failed to write import settings: {message}")
+            }
+        }
+    }
+}
+
+impl Error for ImportSettingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImportSettingsError::Io { source, .. } => Some(source),
+            ImportSettingsError::Parse { .. }
+            | ImportSettingsError::InvalidValue { .. }
+            | ImportSettingsError::Serialize(_) => None,
+        }
+    }
+}
+
+/// Errors raised while importing a model file.
+#[derive(Debug)]
+pub enum ImportError {
+    /// Reading the model file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// The model file's contents are malformed.
+    Malformed(String),
+    /// The model file is of a format or version that can't be read.
+    Unsupported(String),
+    /// The model has no vertex property of the name.
+    MissingProperty(String),
+    /// The model's import settings are invalid.
+    Settings(ImportSettingsError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io { path, .. } => write!(f, "Failed to read model {}", path.display()),
+            ImportError::Malformed(message) => write!(f, "Malformed model: {message}"),
+            ImportError::Unsupported(message) => write!(f, "Unsupported model: {message}"),
+            ImportError::MissingProperty(name) => {
+                write!(f, "Model has no vertex property \"{name}\"")
+            }
+            ImportError::Settings(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImportError::Io { source, .. } => Some(source),
+            ImportError::Settings(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImportSettingsError> for ImportError {
+    fn from(error: ImportSettingsError) -> Self {
+        ImportError::Settings(error)
+    }
+}
+
+/// Errors raised while exporting a mesh or scene.
+#[derive(Debug)]
+pub enum ExportError {
+    /// Writing the exported file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// The mesh does not exist.
+    MissingMesh(usize),
+    /// The scene's hierarchy is broken.
+    InvalidScene(SceneFileError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io { path, .. } => write!(f, "Failed to write export {}", path.display()),
+            ExportError::MissingMesh(id) => write!(f, "Mesh {id} does not exist"),
+            ExportError::InvalidScene(error) => write!(f, "Failed to export scene: {error}"),
+        }
+    }
+}
+
+impl Error for ExportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExportError::Io { source, .. } => Some(source),
+            ExportError::InvalidScene(error) => Some(error),
+            ExportError::MissingMesh(_) => None,
+        }
+    }
+}
+
+/// Represents possible errors that can occur in the renderer.
+#[derive(Debug)]
+pub enum RendererError {
+    Scene(SceneError),
+    Asset(AssetError),
+    Pipeline(PipelineError),
+    Backend(BackendError),
+    Recording(RecordingError),
+    Replay(ReplayError),
+    Particle(ParticleError),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::Scene(e) => e.fmt(f),
+            RendererError::Asset(e) => e.fmt(f),
+            RendererError::Pipeline(e) => e.fmt(f),
+            RendererError::Backend(e) => e.fmt(f),
+            RendererError::Recording(e) => e.fmt(f),
+            RendererError::Replay(e) => e.fmt(f),
+            RendererError::Particle(e) => e.fmt(f),
+        }
+    }
+}
+
+impl RendererError {
+    /// Returns the subsystem error wrapped by this error.
+    pub fn inner(&self) -> &(dyn Error + 'static) {
+        match self {
+            RendererError::Scene(e) => e,
+            RendererError::Asset(e) => e,
+            RendererError::Pipeline(e) => e,
+            RendererError::Backend(e) => e,
+            RendererError::Recording(e) => e,
+            RendererError::Replay(e) => e,
+            RendererError::Particle(e) => e,
+        }
+    }
+}
+
+// Transparent: the wrapped error's own source is the first cause
+impl Error for RendererError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RendererError::Scene(e) => e.source(),
+            RendererError::Asset(e) => e.source(),
+            RendererError::Pipeline(e) => e.source(),
+            RendererError::Backend(e) => e.source(),
+            RendererError::Recording(e) => e.source(),
+            RendererError::Replay(e) => e.source(),
+            RendererError::Particle(e) => e.source(),
+        }
+    }
+}
+
+impl From<SceneError> for RendererError {
+    fn from(error: SceneError) -> Self {
+        RendererError::Scene(error)
+    }
+}
+
+impl From<AssetError> for RendererError {
+    fn from(error: AssetError) -> Self {
+        RendererError::Asset(error)
+    }
+}
+
+impl From<PipelineError> for RendererError {
+    fn from(error: PipelineError) -> Self {
+        RendererError::Pipeline(error)
+    }
+}
+
+impl From<BackendError> for RendererError {
+    fn from(error: BackendError) -> Self {
+        RendererError::Backend(error)
+    }
+}
+
+impl From<RecordingError> for RendererError {
+    fn from(error: RecordingError) -> Self {
+        RendererError::Recording(error)
+    }
+}
+
+impl From<ReplayError> for RendererError {
+    fn from(error: ReplayError) -> Self {
+        RendererError::Replay(error)
+    }
+}
+
+impl From<ParticleError> for RendererError {
+    fn from(error: ParticleError) -> Self {
+        RendererError::Particle(error)
+    }
+}
+
+impl From<HandleError> for RendererError {
+    fn from(error: HandleError) -> Self {
+        RendererError::Backend(error.into())
+    }
+}
+
+/// Formats an error followed by each of its sources, e.g. for logging.
+pub fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_chain, AssetError, PipelineError, RendererError, SceneError};
+    use crate::renderer::{material_manager::MaterialId, scene_graph::NodeId};
+    use std::error::Error;
+
+    #[test]
+    fn test_renderer_error_source_chain() {
+        let error: RendererError = SceneError::InvalidNode(NodeId(4)).into();
+        assert!(matches!(
+            error.inner().downcast_ref::<SceneError>(),
+            Some(SceneError::InvalidNode(NodeId(4)))
+        ));
+        assert!(error.source().is_none());
+        assert_eq!(error_chain(&error), "Scene node 4 does not exist");
+
+        let error = RendererError::from(AssetError::InvalidMaterial(MaterialId(2)));
+        assert_eq!(error.to_string(), "Material 2 does not exist");
+
+        let error: RendererError =
+            PipelineError::ShaderLibraryPathMissing(std::env::VarError::NotPresent).into();
+        assert_eq!(
+            error_chain(&error),
+            "Failed to get shader lib path from METAL_SHADER_LIB: \
+             environment variable not found"
+        );
+    }
+}
@@ -0,0 +1,571 @@
+//! Export module for the renderer.
+//!
+//! This module writes meshes as Wavefront OBJ and scenes as glTF 2.0, so
+//! procedurally generated content such as planets, CSG results and terrain
+//! can be inspected and reused in Blender and other tools.
+//!
+//! OBJ files hold one mesh's positions, vertex colors, texture coordinates
+//! and normals, with colors in sRGB after the position as Blender and MeshLab
+//! read them. glTF files hold the scene graph's hierarchy, with each node's
+//! name and local transform, the meshes the nodes draw and the base colors
+//! of their materials. The geometry is embedded as a base64 data URI, so the
+//! export is a single `.gltf` file. Textures are not exported.
+
+use super::{
+    common::PrimitiveType,
+    error::ExportError,
+    material_manager::{Material, MaterialId},
+    mesh::{Mesh, MeshStorage},
+    scene_file::SceneDocument,
+    vertex_layout::VertexSemantic,
+    Color,
+};
+use crate::math::transform::Transform;
+use glam::{Quat, Vec3};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+};
+
+/// The glTF component type of unsigned 32-bit integers.
+const GLTF_UNSIGNED_INT: u32 = 5125;
+
+/// The glTF component type of 32-bit floats.
+const GLTF_FLOAT: u32 = 5126;
+
+/// The glTF buffer view target of vertex attributes.
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+
+/// The glTF buffer view target of indices.
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Writes a mesh as the text of a Wavefront OBJ file.
+///
+/// Texture coordinates are flipped vertically, as OBJ puts their origin at
+/// the bottom left.
+pub fn write_obj(mesh: &Mesh) -> String {
+    let mut obj = String::from("# game_engine mesh\n");
+    let packed = mesh.packed_vertices.as_ref();
+    let tex_coords = packed.and_then(|packed| packed.unpack(VertexSemantic::TexCoord));
+    let normals = packed.and_then(|packed| packed.unpack(VertexSemantic::Normal));
+    let colored = mesh.vertices.iter().any(|vertex| vertex.color != [1.0; 4]);
+
+    for vertex in &mesh.vertices {
+        let [x, y, z] = vertex.position;
+        if colored {
+            let [r, g, b, a] = vertex.color;
+            let [r, g, b, _] = Color::new(r, g, b, a).to_srgb();
+            let _ = writeln!(obj, "v {x} {y} {z} {r} {g} {b}");
+        } else {
+            let _ = writeln!(obj, "v {x} {y} {z}");
+        }
+    }
+    for [u, v, _, _] in tex_coords.iter().flatten() {
+        let _ = writeln!(obj, "vt {u} {}", 1.0 - v);
+    }
+    for [x, y, z, _] in normals.iter().flatten() {
+        let _ = writeln!(obj, "vn {x} {y} {z}");
+    }
+
+    // OBJ indices start at one, and every attribute shares the vertex's index
+    let corner = |index: usize| match (&tex_coords, &normals) {
+        (Some(_), Some(_)) => format!("{0}/{0}/{0}", index + 1),
+        (Some(_), None) => format!("{0}/{0}", index + 1),
+        (None, Some(_)) => format!("{0}//{0}", index + 1),
+        (None, None) => (index + 1).to_string(),
+    };
+    let indices: Vec<usize> = match &mesh.indices {
+        Some(indices) => indices.iter().map(|&i| i as usize).collect(),
+        None => (0..mesh.vertices.len()).collect(),
+    };
+    match mesh.primitive_type {
+        PrimitiveType::Triangle | PrimitiveType::TriangleStrip => {
+            for triangle in (0..mesh.triangle_count()).filter_map(|t| mesh.triangle(t)) {
+                let [a, b, c] = triangle.map(corner);
+                let _ = writeln!(obj, "f {a} {b} {c}");
+            }
+        }
+        PrimitiveType::Line => {
+            for line in indices.chunks_exact(2) {
+                let _ = writeln!(obj, "l {} {}", line[0] + 1, line[1] + 1);
+            }
+        }
+        PrimitiveType::LineStrip if indices.len() >= 2 => {
+            let strip: Vec<String> = indices.iter().map(|i| (i + 1).to_string()).collect();
+            let _ = writeln!(obj, "l {}", strip.join(" "));
+        }
+        PrimitiveType::LineStrip => {}
+        PrimitiveType::Point => {
+            for index in indices {
+                let _ = writeln!(obj, "p {}", index + 1);
+            }
+        }
+    }
+    obj
+}
+
+/// Writes the nodes of a scene as the text of a glTF 2.0 file.
+///
+/// Each combination of mesh and material drawn by the scene becomes a glTF
+/// mesh, sharing the geometry of the mesh. Nodes keep their hierarchy,
+/// names and local transforms.
+///
+/// # Arguments
+///
+/// * `document` - The scene's nodes, e.g. captured from its scene graph.
+/// * `meshes` - The meshes the nodes draw.
+/// * `materials` - The materials by ID.
+///
+/// # Returns
+///
+/// A `Result` containing the glTF JSON, `ExportError::InvalidScene` if the
+/// document's hierarchy is broken, or `ExportError::MissingMesh` if a node
+/// draws a mesh that isn't stored.
+pub fn write_gltf(
+    document: &SceneDocument,
+    meshes: &MeshStorage,
+    materials: &[Material],
+) -> Result<String, ExportError> {
+    let order = document
+        .hierarchy_order()
+        .map_err(ExportError::InvalidScene)?;
+    let mut gltf = GltfWriter::default();
+    let index_of: HashMap<_, _> = order.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); order.len()];
+    let mut roots = Vec::new();
+    let mut nodes = Vec::with_capacity(order.len());
+    for (index, &id) in order.iter().enumerate() {
+        let Some(node) = document.get(id) else {
+            continue;
+        };
+        match node.parent {
+            Some(parent) => children[index_of[&parent]].push(index),
+            None => roots.push(index),
+        }
+        let mesh = match node.mesh_id {
+            Some(mesh_id) => {
+                let mesh = meshes
+                    .get_mesh(mesh_id)
+                    .ok_or(ExportError::MissingMesh(mesh_id))?;
+                Some(gltf.mesh(mesh_id, mesh, node.material_id, materials))
+            }
+            None => None,
+        };
+        nodes.push((node.name.clone(), node.transform, mesh));
+    }
+
+    let mut json = String::new();
+    let _ = write!(
+        json,
+        "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"game_engine\"}},\
+         \"scene\":0,\"scenes\":[{{\"nodes\":{}}}],\"nodes\":[",
+        JsonList(&roots)
+    );
+    for (index, (name, transform, mesh)) in nodes.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        json.push('{');
+        let mut fields = Vec::new();
+        if let Some(name) = name {
+            fields.push(format!("\"name\":{}", JsonString(name)));
+        }
+        fields.extend(transform_fields(transform));
+        if let Some(mesh) = mesh {
+            fields.push(format!("\"mesh\":{mesh}"));
+        }
+        if !children[index].is_empty() {
+            fields.push(format!("\"children\":{}", JsonList(&children[index])));
+        }
+        json.push_str(&fields.join(","));
+        json.push('}');
+    }
+    json.push(']');
+    gltf.finish(&mut json);
+    json.push('}');
+    Ok(json)
+}
+
+/// Returns the glTF fields of a node's transform, leaving out identity parts.
+fn transform_fields(transform: &Transform) -> Vec<String> {
+    let mut fields = Vec::new();
+    if transform.translation != Vec3::ZERO {
+        fields.push(format!(
+            "\"translation\":{}",
+            JsonList(&transform.translation.to_array().map(JsonNumber))
+        ));
+    }
+    if transform.rotation != Quat::IDENTITY {
+        fields.push(format!(
+            "\"rotation\":{}",
+            JsonList(&transform.rotation.to_array().map(JsonNumber))
+        ));
+    }
+    if transform.scale != Vec3::ONE {
+        fields.push(format!(
+            "\"scale\":{}",
+            JsonList(&transform.scale.to_array().map(JsonNumber))
+        ));
+    }
+    fields
+}
+
+/// The meshes, materials and binary data of a glTF file being written.
+#[derive(Default)]
+struct GltfWriter {
+    buffer: Vec<u8>,
+    buffer_views: Vec<String>,
+    accessors: Vec<String>,
+    /// The JSON of each glTF mesh.
+    meshes: Vec<String>,
+    /// The glTF mesh of each mesh and material drawn.
+    mesh_indices: HashMap<(usize, MaterialId), usize>,
+    /// The JSON of a primitive's attributes and indices for each mesh.
+    geometry: HashMap<usize, String>,
+    materials: Vec<String>,
+    material_indices: HashMap<MaterialId, usize>,
+}
+
+impl GltfWriter {
+    /// Returns the glTF mesh drawing a mesh with a material, adding it if needed.
+    fn mesh(
+        &mut self,
+        mesh_id: usize,
+        mesh: &Mesh,
+        material_id: MaterialId,
+        materials: &[Material],
+    ) -> usize {
+        if let Some(&index) = self.mesh_indices.get(&(mesh_id, material_id)) {
+            return index;
+        }
+        if !self.geometry.contains_key(&mesh_id) {
+            let geometry = self.geometry_json(mesh);
+            self.geometry.insert(mesh_id, geometry);
+        }
+        let mode = match mesh.primitive_type {
+            PrimitiveType::Point => 0,
+            PrimitiveType::Line => 1,
+            PrimitiveType::LineStrip => 3,
+            PrimitiveType::Triangle => 4,
+            PrimitiveType::TriangleStrip => 5,
+        };
+        let material = self.material(material_id, materials);
+        let index = self.meshes.len();
+        self.meshes.push(format!(
+            "{{\"primitives\":[{{{},\"mode\":{mode},\"material\":{material}}}]}}",
+            self.geometry[&mesh_id]
+        ));
+        self.mesh_indices.insert((mesh_id, material_id), index);
+        index
+    }
+
+    /// Returns the glTF material of a material, adding it if needed.
+    fn material(&mut self, material_id: MaterialId, materials: &[Material]) -> usize {
+        if let Some(&index) = self.material_indices.get(&material_id) {
+            return index;
+        }
+        let color = materials
+            .get(material_id.0)
+            .map_or(Color::WHITE, |material| material.base_color);
+        let alpha_mode = if color.a < 1.0 { "BLEND" } else { "OPAQUE" };
+        let index = self.materials.len();
+        self.materials.push(format!(
+            "{{\"name\":\"material {}\",\"pbrMetallicRoughness\":{{\"baseColorFactor\":{},\
+             \"metallicFactor\":0,\"roughnessFactor\":1}},\"alphaMode\":\"{alpha_mode}\"}}",
+            material_id.0,
+            JsonList(&[color.r, color.g, color.b, color.a].map(JsonNumber))
+        ));
+        self.material_indices.insert(material_id, index);
+        index
+    }
+
+    /// Writes a mesh's geometry to the buffer, returning the JSON of its
+    /// `attributes` and `indices`.
+    fn geometry_json(&mut self, mesh: &Mesh) -> String {
+        let positions: Vec<[f32; 4]> = mesh
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let [x, y, z] = vertex.position;
+                [x, y, z, 0.0]
+            })
+            .collect();
+        let mut attributes = vec![format!(
+            "\"POSITION\":{}",
+            self.vertex_accessor(&positions, 3, true)
+        )];
+        let packed = mesh.packed_vertices.as_ref();
+        if let Some(normals) = packed.and_then(|packed| packed.unpack(VertexSemantic::Normal)) {
+            attributes.push(format!(
+                "\"NORMAL\":{}",
+                self.vertex_accessor(&normals, 3, false)
+            ));
+        }
+        if let Some(tex_coords) = packed.and_then(|packed| packed.unpack(VertexSemantic::TexCoord))
+        {
+            attributes.push(format!(
+                "\"TEXCOORD_0\":{}",
+                self.vertex_accessor(&tex_coords, 2, false)
+            ));
+        }
+        if mesh.vertices.iter().any(|vertex| vertex.color != [1.0; 4]) {
+            let colors: Vec<[f32; 4]> = mesh.vertices.iter().map(|vertex| vertex.color).collect();
+            attributes.push(format!(
+                "\"COLOR_0\":{}",
+                self.vertex_accessor(&colors, 4, false)
+            ));
+        }
+
+        let mut json = format!("\"attributes\":{{{}}}", attributes.join(","));
+        if let Some(indices) = &mesh.indices {
+            let view = self.buffer_view(
+                indices.iter().flat_map(|i| i.to_le_bytes()),
+                GLTF_ELEMENT_ARRAY_BUFFER,
+            );
+            let accessor = self.accessors.len();
+            self.accessors.push(format!(
+                "{{\"bufferView\":{view},\"componentType\":{GLTF_UNSIGNED_INT},\
+                 \"count\":{},\"type\":\"SCALAR\"}}",
+                indices.len()
+            ));
+            let _ = write!(json, ",\"indices\":{accessor}");
+        }
+        json
+    }
+
+    /// Writes one float attribute value per vertex, returning its accessor.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values, of which the leading `components` are written.
+    /// * `components` - The number of components of the glTF type.
+    /// * `bounds` - Whether the accessor has its values' `min` and `max`,
+    ///   which glTF requires of positions.
+    fn vertex_accessor(&mut self, values: &[[f32; 4]], components: usize, bounds: bool) -> usize {
+        let bytes = values
+            .iter()
+            .flat_map(|value| value[..components].to_vec())
+            .flat_map(f32::to_le_bytes);
+        let view = self.buffer_view(bytes, GLTF_ARRAY_BUFFER);
+        let kind = match components {
+            2 => "VEC2",
+            3 => "VEC3",
+            _ => "VEC4",
+        };
+        let mut accessor = format!(
+            "{{\"bufferView\":{view},\"componentType\":{GLTF_FLOAT},\"count\":{},\"type\":\"{kind}\"",
+            values.len()
+        );
+        if bounds && !values.is_empty() {
+            let (mut min, mut max) = (values[0], values[0]);
+            for value in values {
+                for i in 0..components {
+                    min[i] = min[i].min(value[i]);
+                    max[i] = max[i].max(value[i]);
+                }
+            }
+            let _ = write!(
+                accessor,
+                ",\"min\":{},\"max\":{}",
+                JsonList(
+                    &min[..components]
+                        .iter()
+                        .copied()
+                        .map(JsonNumber)
+                        .collect::<Vec<_>>()
+                ),
+                JsonList(
+                    &max[..components]
+                        .iter()
+                        .copied()
+                        .map(JsonNumber)
+                        .collect::<Vec<_>>()
+                )
+            );
+        }
+        accessor.push('}');
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    /// Appends bytes to the buffer, 4-byte aligned, returning their buffer view.
+    fn buffer_view(&mut self, bytes: impl IntoIterator<Item = u8>, target: u32) -> usize {
+        let offset = self.buffer.len();
+        self.buffer.extend(bytes);
+        let length = self.buffer.len() - offset;
+        self.buffer.resize(self.buffer.len().next_multiple_of(4), 0);
+        self.buffer_views.push(format!(
+            "{{\"buffer\":0,\"byteOffset\":{offset},\"byteLength\":{length},\"target\":{target}}}"
+        ));
+        self.buffer_views.len() - 1
+    }
+
+    /// Appends the meshes, materials, accessors and buffer to the JSON.
+    fn finish(self, json: &mut String) {
+        for (name, items) in [
+            ("meshes", &self.meshes),
+            ("materials", &self.materials),
+            ("accessors", &self.accessors),
+            ("bufferViews", &self.buffer_views),
+        ] {
+            if !items.is_empty() {
+                let _ = write!(json, ",\"{name}\":[{}]", items.join(","));
+            }
+        }
+        if !self.buffer.is_empty() {
+            let _ = write!(
+                json,
+                ",\"buffers\":[{{\"byteLength\":{},\
+                 \"uri\":\"data:application/octet-stream;base64,{}\"}}]",
+                self.buffer.len(),
+                base64(&self.buffer)
+            );
+        }
+    }
+}
+
+/// Encodes bytes as standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// A float written as a JSON number, with non-finite values written as zero.
+#[derive(Clone, Copy)]
+struct JsonNumber(f32);
+
+impl fmt::Display for JsonNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_finite() {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "0")
+        }
+    }
+}
+
+/// A string written as a quoted, escaped JSON string.
+struct JsonString<'a>(&'a str);
+
+impl fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// Values written as a JSON array.
+struct JsonList<'a, T>(&'a [T]);
+
+impl<T: fmt::Display> fmt::Display for JsonList<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('[')?;
+        for (index, value) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{value}")?;
+        }
+        f.write_char(']')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, write_gltf, write_obj};
+    use crate::renderer::{
+        common::{PrimitiveType, Vertex},
+        material_manager::{Material, MaterialId},
+        mesh::{Mesh, MeshStorage},
+        scene_file::SceneDocument,
+        scene_graph::SceneGraph,
+        shape_builders::MeshBuilder,
+        vertex_layout::VertexLayout,
+        Color,
+    };
+    use glam::{Mat4, Vec2, Vec3};
+
+    fn triangle() -> MeshBuilder {
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].map(|position| Vertex {
+            position,
+            ..Vertex::default()
+        });
+        MeshBuilder::new(vertices.to_vec(), PrimitiveType::Triangle)
+            .with_indices(vec![0, 1, 2])
+            .with_optimization(None)
+    }
+
+    #[test]
+    fn test_write_obj() {
+        let plain = Mesh::new(triangle());
+        assert_eq!(
+            write_obj(&plain),
+            "# game_engine mesh\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n"
+        );
+
+        let textured = Mesh::new(
+            triangle()
+                .with_layout(VertexLayout::position_normal_uv())
+                .with_normals(&[Vec3::Z; 3])
+                .with_tex_coords(&[Vec2::ZERO, Vec2::X, Vec2::Y]),
+        );
+        let obj = write_obj(&textured);
+        assert!(obj.contains("vt 1 1\n"));
+        assert!(obj.contains("vn 0 0 1\n"));
+        assert!(obj.ends_with("f 1/1/1 2/2/2 3/3/3\n"));
+    }
+
+    #[test]
+    fn test_write_gltf() {
+        let mut meshes = MeshStorage::new();
+        let mesh_id = meshes.add_mesh(triangle());
+        let materials = [Material::new(Color::WHITE), Material::new(Color::RED)];
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        graph.set_name(root, Some("ship \"one\"")).unwrap();
+        let child = graph
+            .add_node(Some(root), Mat4::from_translation(Vec3::X))
+            .unwrap();
+        graph.set_mesh(child, Some(mesh_id), MaterialId(1)).unwrap();
+        let other = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        graph.set_mesh(other, Some(mesh_id), MaterialId(1)).unwrap();
+
+        let json = write_gltf(&SceneDocument::capture(&graph), &meshes, &materials).unwrap();
+        assert!(json.starts_with("{\"asset\":{\"version\":\"2.0\""));
+        assert!(json.contains("\"scenes\":[{\"nodes\":[0,2]}]"));
+        assert!(json.contains(
+            "{\"name\":\"ship \\\"one\\\"\",\"children\":[1]},{\"translation\":[1,0,0],\"mesh\":0}"
+        ));
+        // Both nodes share one glTF mesh, material and set of accessors
+        assert_eq!(json.matches("\"primitives\"").count(), 1);
+        assert!(json.contains("\"baseColorFactor\":[1,0,0,1]"));
+        assert!(json.contains("\"min\":[0,0,0],\"max\":[1,1,0]"));
+        // Three positions and three indices
+        assert!(json.contains("\"byteLength\":48,"));
+
+        graph.set_mesh(other, Some(7), MaterialId(0)).unwrap();
+        assert!(write_gltf(&SceneDocument::capture(&graph), &meshes, &materials).is_err());
+        assert_eq!(base64(b"glTF!"), "Z2xURiE=");
+    }
+}
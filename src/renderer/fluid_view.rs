@@ -0,0 +1,232 @@
+//! Fluid view module for the renderer.
+//!
+//! This module provides `FluidView`, which draws the particles of a `Fluid`
+//! from the physics module as instanced spheres colored by their density
+//! through a colormap. Drawn translucent and additively blended, large
+//! overlapping spheres blend into gas clouds and accretion disks whose dense
+//! regions glow brightest; drawn opaque, small spheres show liquids.
+//!
+//! The particles are copied into the view after every simulation step, and
+//! every view shares one low-poly sphere mesh created by the renderer.
+
+use super::{
+    colormap::Colormap,
+    common::{Color, LayerMask},
+    render_queue::InstanceData,
+    render_state::{BlendMode, RenderState},
+    shape_builders::{shape_builder::ShapeBuilder, MeshBuilder, SphereBuilder},
+};
+use crate::physics::fluid::Fluid;
+use glam::{Mat4, Vec3};
+
+/// The number of rings between the poles of the shared sphere mesh.
+const SPHERE_RINGS: u32 = 6;
+
+/// The number of segments around the shared sphere mesh.
+const SPHERE_SEGMENTS: u32 = 10;
+
+/// Identifies a fluid view added to the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FluidViewId(pub usize);
+
+/// Draws fluid particles as spheres colored by density.
+#[derive(Clone, Debug)]
+pub struct FluidView {
+    /// The radius of the sphere drawn for each particle.
+    pub radius: f32,
+    /// The colormap densities are mapped through.
+    pub colormap: Colormap,
+    /// The densities mapped to the ends of the colormap, or `None` to use the
+    /// range of the current densities.
+    pub density_range: Option<(f32, f32)>,
+    /// The opacity of the spheres, from 0 to 1.
+    pub opacity: f32,
+    /// How the spheres are blended with what's behind them. Additive
+    /// spheres brighten where particles crowd together.
+    pub blend: BlendMode,
+    /// The transform from the fluid's space to world space.
+    pub transform: Mat4,
+    /// The visibility layers the view belongs to.
+    pub layers: LayerMask,
+    positions: Vec<Vec3>,
+    densities: Vec<f32>,
+}
+
+impl FluidView {
+    /// Creates an opaque view of a fluid's current particles.
+    ///
+    /// # Arguments
+    ///
+    /// * `fluid` - The fluid to draw.
+    /// * `radius` - The radius of the sphere drawn for each particle.
+    pub fn new(fluid: &Fluid, radius: f32) -> Self {
+        let mut view = Self {
+            radius,
+            colormap: Colormap::Plasma,
+            density_range: None,
+            opacity: 1.0,
+            blend: BlendMode::Alpha,
+            transform: Mat4::IDENTITY,
+            layers: LayerMask::DEFAULT,
+            positions: Vec::new(),
+            densities: Vec::new(),
+        };
+        view.update(fluid);
+        view
+    }
+
+    /// Creates a translucent, additively blended view, for glowing gas.
+    ///
+    /// # Arguments
+    ///
+    /// * `fluid` - The fluid to draw.
+    /// * `radius` - The radius of the sphere drawn for each particle,
+    ///   usually a few times the particles' spacing so they overlap.
+    /// * `opacity` - The opacity of each sphere.
+    pub fn gas(fluid: &Fluid, radius: f32, opacity: f32) -> Self {
+        Self {
+            opacity,
+            blend: BlendMode::Additive,
+            ..Self::new(fluid, radius)
+        }
+    }
+
+    /// Sets the colormap densities are mapped through.
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+
+    /// Sets the densities mapped to the ends of the colormap.
+    pub fn with_density_range(mut self, range: (f32, f32)) -> Self {
+        self.density_range = Some(range);
+        self
+    }
+
+    /// Sets the transform from the fluid's space to world space.
+    pub fn with_transform(mut self, transform: Mat4) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Sets the visibility layers the view belongs to.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Copies the current positions and densities of a fluid's particles.
+    pub fn update(&mut self, fluid: &Fluid) {
+        self.positions.clear();
+        self.positions.extend_from_slice(fluid.positions());
+        self.densities.clear();
+        self.densities.extend_from_slice(fluid.densities());
+    }
+
+    /// Returns the number of particles drawn.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns `true` if there are no particles to draw.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Returns the render state the spheres are drawn with.
+    pub(crate) fn render_state(&self) -> RenderState {
+        match self.blend {
+            BlendMode::Additive => RenderState::ADDITIVE,
+            _ if self.opacity < 1.0 => RenderState::TRANSPARENT,
+            _ => RenderState::OPAQUE,
+        }
+    }
+
+    /// Appends one sphere instance per particle.
+    pub(crate) fn append_instances(&self, instances: &mut Vec<InstanceData>) {
+        let range = self
+            .density_range
+            .unwrap_or_else(|| Colormap::auto_range(&self.densities));
+        let scale = Mat4::from_scale(Vec3::splat(self.radius));
+        instances.extend(self.positions.iter().zip(&self.densities).map(
+            |(&position, &density)| {
+                let mut color = self.colormap.map(density, range);
+                color.a = self.opacity;
+                InstanceData::new(
+                    self.transform * Mat4::from_translation(position) * scale,
+                    color,
+                )
+            },
+        ));
+    }
+}
+
+/// Builds the unit sphere every fluid view's particles are drawn with.
+pub(crate) fn sphere_mesh() -> MeshBuilder {
+    SphereBuilder::new(1.0, SPHERE_RINGS, SPHERE_SEGMENTS, Color::WHITE).as_mesh()
+}
+
+/// The fluid views of a renderer, indexed by `FluidViewId`.
+#[derive(Clone, Debug, Default)]
+pub struct FluidViews {
+    views: Vec<Option<FluidView>>,
+}
+
+impl FluidViews {
+    /// Adds a fluid view and returns its handle.
+    pub fn add(&mut self, view: FluidView) -> FluidViewId {
+        self.views.push(Some(view));
+        FluidViewId(self.views.len() - 1)
+    }
+
+    /// Removes a fluid view, returning `true` if it existed.
+    pub fn remove(&mut self, id: FluidViewId) -> bool {
+        self.views
+            .get_mut(id.0)
+            .is_some_and(|view| view.take().is_some())
+    }
+
+    /// Returns a fluid view for modification, e.g. to update its particles.
+    pub fn get_mut(&mut self, id: FluidViewId) -> Option<&mut FluidView> {
+        self.views.get_mut(id.0)?.as_mut()
+    }
+
+    /// Returns `true` if there are no fluid views.
+    pub fn is_empty(&self) -> bool {
+        self.views.iter().all(Option::is_none)
+    }
+
+    /// Returns the fluid views.
+    pub fn iter(&self) -> impl Iterator<Item = &FluidView> + '_ {
+        self.views.iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FluidView;
+    use crate::physics::fluid::Fluid;
+    use crate::renderer::{colormap::Colormap, render_state::RenderState};
+    use glam::Vec3;
+
+    #[test]
+    fn test_fluid_view_instances() {
+        let mut fluid = Fluid::new(0.1, 1000.0);
+        fluid.add_particle(Vec3::ZERO, Vec3::ZERO);
+        fluid.add_particle(Vec3::X, Vec3::ZERO);
+        fluid.step(0.01);
+
+        let view = FluidView::gas(&fluid, 0.5, 0.25).with_density_range((0.0, 1.0));
+        assert_eq!(view.render_state(), RenderState::ADDITIVE);
+        let mut instances = Vec::new();
+        view.append_instances(&mut instances);
+        assert_eq!(instances.len(), 2);
+        let (scale, _, translation) = instances[1].model_matrix.to_scale_rotation_translation();
+        assert!(scale.abs_diff_eq(Vec3::splat(0.5), 1e-6));
+        assert!(translation.abs_diff_eq(fluid.positions()[1], 1e-6));
+        // Colors follow the density, with the view's opacity
+        let mut expected = Colormap::Plasma.map(fluid.densities()[0], (0.0, 1.0));
+        expected.a = 0.25;
+        assert_eq!(instances[0].color, expected);
+    }
+}
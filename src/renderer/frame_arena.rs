@@ -0,0 +1,234 @@
+//! Frame arena module for the renderer.
+//!
+//! This module provides the `FrameArena`, a bump allocator for transient
+//! per-frame data such as instance arrays, transient geometry and scratch
+//! buffers. Allocations are copied into one growing block of memory and
+//! addressed through typed `ArenaSlice` handles; resetting the arena at the
+//! end of a frame releases everything at once while keeping the memory for
+//! the next frame.
+
+use log::debug;
+use std::{fmt, marker::PhantomData};
+
+/// The largest alignment the arena supports, matching `Mat4` and `InstanceData`.
+const MAX_ALIGN: usize = 16;
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct Block([u8; MAX_ALIGN]);
+
+/// A typed handle to a slice allocated in a `FrameArena`.
+///
+/// Handles are only valid until the arena is reset.
+pub struct ArenaSlice<T> {
+    offset: usize,
+    len: usize,
+    generation: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T> ArenaSlice<T> {
+    /// Returns the number of elements in the slice.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the slice has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> Clone for ArenaSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ArenaSlice<T> {}
+
+impl<T> fmt::Debug for ArenaSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArenaSlice")
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+/// A bump allocator for data that only lives for a single frame.
+pub struct FrameArena {
+    blocks: Vec<Block>,
+    offset: usize,
+    peak: usize,
+    generation: u32,
+}
+
+impl FrameArena {
+    /// Creates a new, empty `FrameArena`.
+    pub fn new() -> Self {
+        debug!("Creating new FrameArena");
+        Self {
+            blocks: Vec::new(),
+            offset: 0,
+            peak: 0,
+            generation: 0,
+        }
+    }
+
+    /// Copies `data` into the arena.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The elements to copy. Their alignment must not exceed 16 bytes.
+    ///
+    /// # Returns
+    ///
+    /// A handle to the copied slice, valid until the next `reset`.
+    pub fn alloc_slice<T: Copy>(&mut self, data: &[T]) -> ArenaSlice<T> {
+        assert!(
+            std::mem::align_of::<T>() <= MAX_ALIGN,
+            "FrameArena supports alignments up to {MAX_ALIGN} bytes"
+        );
+
+        let start = self.offset.next_multiple_of(std::mem::align_of::<T>());
+        let end = start + std::mem::size_of_val(data);
+        self.reserve(end);
+
+        // SAFETY: `reserve` grew the blocks to at least `end` bytes, and
+        // `data` cannot overlap the arena's memory since `self` is borrowed
+        // mutably.
+        unsafe {
+            let dest = (self.blocks.as_mut_ptr() as *mut u8).add(start);
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                dest,
+                std::mem::size_of_val(data),
+            );
+        }
+
+        self.offset = end;
+        ArenaSlice {
+            offset: start,
+            len: data.len(),
+            generation: self.generation,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Grows the arena so that it holds at least `bytes` bytes.
+    fn reserve(&mut self, bytes: usize) {
+        let blocks = bytes.div_ceil(MAX_ALIGN);
+        if blocks > self.blocks.len() {
+            let new_len = blocks.next_power_of_two();
+            debug!("Growing FrameArena to {} bytes", new_len * MAX_ALIGN);
+            self.blocks.resize(new_len, Block([0; MAX_ALIGN]));
+        }
+    }
+
+    /// Returns the contents of a slice allocated in this frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle was allocated before the last `reset`, or lies
+    /// outside this arena's allocations.
+    pub fn get<T: Copy>(&self, slice: ArenaSlice<T>) -> &[T] {
+        assert_eq!(
+            slice.generation, self.generation,
+            "ArenaSlice used after its FrameArena was reset"
+        );
+        assert!(
+            slice.offset + slice.len * std::mem::size_of::<T>() <= self.offset,
+            "ArenaSlice used with a different FrameArena"
+        );
+
+        // SAFETY: The slice lies within the bytes allocated this frame, which
+        // `alloc_slice` initialized from `T`s at an offset aligned for `T`.
+        // The blocks don't move while `self` is borrowed.
+        unsafe {
+            let data = (self.blocks.as_ptr() as *const u8).add(slice.offset) as *const T;
+            std::slice::from_raw_parts(data, slice.len)
+        }
+    }
+
+    /// Releases every allocation while keeping the memory for the next frame.
+    pub fn reset(&mut self) {
+        self.peak = self.peak.max(self.offset);
+        self.offset = 0;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Returns the number of bytes allocated since the last reset.
+    pub fn used_bytes(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of bytes the arena can hold without growing.
+    pub fn capacity_bytes(&self) -> usize {
+        self.blocks.len() * MAX_ALIGN
+    }
+
+    /// Returns the largest number of bytes used by any frame so far.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak.max(self.offset)
+    }
+}
+
+impl Default for FrameArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameArena;
+    use glam::Mat4;
+
+    #[test]
+    fn test_frame_arena_alloc_and_get() {
+        let mut arena = FrameArena::new();
+        let bytes = arena.alloc_slice(&[1u8, 2, 3]);
+        let matrices = arena.alloc_slice(&[Mat4::IDENTITY, Mat4::ZERO]);
+        let empty = arena.alloc_slice::<u32>(&[]);
+
+        assert_eq!(arena.get(bytes), &[1, 2, 3]);
+        assert_eq!(arena.get(matrices), &[Mat4::IDENTITY, Mat4::ZERO]);
+        assert!(arena.get(empty).is_empty());
+        // The matrices are aligned past the three bytes
+        assert_eq!(arena.used_bytes(), 16 + 2 * 64);
+    }
+
+    #[test]
+    fn test_frame_arena_reset_keeps_capacity() {
+        let mut arena = FrameArena::new();
+        arena.alloc_slice(&[0u32; 100]);
+        let capacity = arena.capacity_bytes();
+
+        arena.reset();
+        assert_eq!(arena.used_bytes(), 0);
+        assert_eq!(arena.capacity_bytes(), capacity);
+        assert_eq!(arena.peak_bytes(), 400);
+
+        arena.alloc_slice(&[0u32; 50]);
+        assert_eq!(arena.capacity_bytes(), capacity);
+    }
+
+    #[test]
+    #[should_panic(expected = "reset")]
+    fn test_frame_arena_stale_handle() {
+        let mut arena = FrameArena::new();
+        let slice = arena.alloc_slice(&[1u32]);
+        arena.reset();
+        arena.get(slice);
+    }
+
+    #[test]
+    #[should_panic(expected = "different FrameArena")]
+    fn test_frame_arena_foreign_handle() {
+        let mut other = FrameArena::new();
+        let slice = other.alloc_slice(&[1u32, 2]);
+        FrameArena::new().get(slice);
+    }
+}
@@ -0,0 +1,136 @@
+//! GPU culling module for the renderer.
+//!
+//! This module provides `GpuCulling`, which moves the culling of instanced
+//! mesh draws to the GPU. For every such draw, the renderer passes the mesh's
+//! bounding sphere, the draw's transform and the camera's frustum planes to
+//! the backend, where a compute kernel tests every instance and compacts the
+//! survivors into the instance buffer and the instance count of an indirect
+//! draw. With occlusion culling enabled, instances are also tested against a
+//! depth pyramid built from the previous frame's depth buffer, so an instance
+//! hidden last frame may appear one frame late when the camera moves quickly.
+
+use super::bounds::BoundingSphere;
+use crate::math::geometry::Frustum;
+use glam::{Mat4, Vec4};
+
+/// The fewest instances a draw needs before culling them on the GPU pays off.
+const DEFAULT_MIN_INSTANCES: usize = 64;
+
+/// Culls the instances of instanced mesh draws on the GPU.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpuCulling {
+    /// Whether instances hidden behind last frame's depth are culled too.
+    pub occlusion: bool,
+    /// The fewest instances a draw needs to be culled, since small draws cost more to cull than to draw.
+    pub min_instances: usize,
+}
+
+impl Default for GpuCulling {
+    /// Frustum culling of draws with at least 64 instances.
+    fn default() -> Self {
+        Self {
+            occlusion: false,
+            min_instances: DEFAULT_MIN_INSTANCES,
+        }
+    }
+}
+
+impl GpuCulling {
+    /// Enables or disables occlusion culling against the previous frame's depth.
+    pub fn with_occlusion(mut self, occlusion: bool) -> Self {
+        self.occlusion = occlusion;
+        self
+    }
+
+    /// Sets the fewest instances a draw needs to be culled.
+    pub fn with_min_instances(mut self, min_instances: usize) -> Self {
+        self.min_instances = min_instances;
+        self
+    }
+
+    /// Returns `true` if a draw with this many instances is culled on the GPU.
+    pub fn culls(&self, instance_count: usize) -> bool {
+        instance_count >= self.min_instances.max(1)
+    }
+
+    /// Builds the constants of the culling kernel for a draw.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    /// * `occlusion_view_projection` - The view projection matrix of the previous
+    ///   frame, whose depth the occlusion test reads, or `None` to skip the test.
+    /// * `model_matrix` - The draw's transform, applied before every instance's own.
+    /// * `bounds` - The mesh's bounding sphere in model space.
+    /// * `instance_count` - The number of instances to cull.
+    pub fn constants(
+        &self,
+        view_projection: Mat4,
+        occlusion_view_projection: Option<Mat4>,
+        model_matrix: Mat4,
+        bounds: BoundingSphere,
+        instance_count: usize,
+    ) -> CullingConstants {
+        let occlusion = occlusion_view_projection.filter(|_| self.occlusion);
+        CullingConstants {
+            model_matrix,
+            occlusion_view_projection: occlusion.unwrap_or(Mat4::IDENTITY),
+            frustum_planes: Frustum::from_view_projection(view_projection).planes,
+            bounds_center: bounds.center.to_array(),
+            bounds_radius: bounds.radius,
+            instance_count: instance_count as u32,
+            occlusion: occlusion.is_some() as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// The constants of the culling kernel for one draw.
+///
+/// The layout matches the `CullingConstants` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CullingConstants {
+    pub model_matrix: Mat4,
+    pub occlusion_view_projection: Mat4,
+    /// The left, right, bottom, top, near and far planes, facing inwards.
+    pub frustum_planes: [Vec4; 6],
+    pub bounds_center: [f32; 3],
+    pub bounds_radius: f32,
+    pub instance_count: u32,
+    /// Whether the occlusion test runs, as a boolean.
+    pub occlusion: u32,
+    _padding: [u32; 2],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CullingConstants, GpuCulling};
+    use crate::renderer::bounds::BoundingSphere;
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_culling_constants_layout() {
+        // Must match the struct in shader_types.h
+        assert_eq!(std::mem::size_of::<CullingConstants>(), 256);
+    }
+
+    #[test]
+    fn test_occlusion_needs_previous_frame() {
+        let culling = GpuCulling::default().with_occlusion(true);
+        let bounds = BoundingSphere::new(Vec3::ZERO, 1.0);
+        let first = culling.constants(Mat4::IDENTITY, None, Mat4::IDENTITY, bounds, 100);
+        let later = culling.constants(
+            Mat4::IDENTITY,
+            Some(Mat4::IDENTITY),
+            Mat4::IDENTITY,
+            bounds,
+            100,
+        );
+        assert_eq!(first.occlusion, 0);
+        assert_eq!(later.occlusion, 1);
+
+        assert!(culling.culls(64));
+        assert!(!culling.culls(63));
+    }
+}
@@ -0,0 +1,750 @@
+//! Import settings module for the renderer.
+//!
+//! This module provides `ImportSettings`, the per-asset options model
+//! importers apply to the meshes they read, since raw files rarely match the
+//! engine's conventions: a uniform scale, e.g. 0.01 for files authored in
+//! centimeters, conversion from Z-up tools to the engine's Y-up, generated
+//! normals and tangents, mesh optimization, and merging meshes that share a
+//! material into one.
+//!
+//! Settings are kept in a sidecar file next to the source, named after it
+//! with `.import` appended, so they travel with the file in version control:
+//!
+//! ```text
+//! # models/ship.obj.import
+//! scale = 0.01
+//! up_axis = "z"
+//! generate_normals = true
+//! generate_tangents = false
+//! optimize = true
+//! merge_by_material = true
+//! ```
+//!
+//! The sidecar is TOML, read and written through the settings' serde
+//! implementations. Settings left out keep their defaults, and unknown ones
+//! are logged and skipped, so files can carry settings of newer versions.
+//! The same names are used for the settings of an `AssetRecord`. Sources
+//! without a sidecar are imported with the defaults.
+
+use super::{
+    common::PrimitiveType,
+    error::ImportSettingsError,
+    material_manager::MaterialId,
+    mesh_optimizer::MeshOptimization,
+    shape_builders::MeshBuilder,
+    vertex_layout::{VertexFormat, VertexLayout, VertexSemantic, VertexStream},
+};
+use glam::{Mat3, Mat4, Vec2, Vec3};
+use log::{info, warn};
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::FRAC_PI_2,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The attribute generated tangents are stored in, with the handedness of
+/// the bitangent in the fourth component. Only custom shaders read it.
+pub const TANGENT_SEMANTIC: VertexSemantic = VertexSemantic::Custom(0);
+
+/// The extension appended to a source path to name its settings file.
+const SIDECAR_EXTENSION: &str = "import";
+
+/// The axis pointing up in a source file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpAxis {
+    /// The engine's convention, used by glTF.
+    #[default]
+    Y,
+    /// Used by Blender, 3ds Max and most CAD tools.
+    Z,
+}
+
+impl UpAxis {
+    /// Returns the name of the axis in settings.
+    pub fn name(self) -> &'static str {
+        match self {
+            UpAxis::Y => "y",
+            UpAxis::Z => "z",
+        }
+    }
+}
+
+impl FromStr for UpAxis {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "y" => Ok(UpAxis::Y),
+            "z" => Ok(UpAxis::Z),
+            _ => Err(format!(
+                "unknown up axis \"{text}\", expected \"y\" or \"z\""
+            )),
+        }
+    }
+}
+
+/// Reads a finite number greater than 0.
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match f32::deserialize(deserializer)? {
+        number if number > 0.0 && number.is_finite() => Ok(number),
+        number => Err(D::Error::custom(format!(
+            "expected a positive number, found {number}"
+        ))),
+    }
+}
+
+/// Writes a number in its shortest form, rather than the digits of the
+/// `f64` TOML widens it to, e.g. 0.009999999776482582 for 0.01.
+fn shortest<S: Serializer>(number: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    let number: f64 = number.to_string().parse().map_err(S::Error::custom)?;
+    serializer.serialize_f64(number)
+}
+
+/// The options applied to the meshes of an imported model.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportSettings {
+    /// The uniform scale from source units to meters.
+    #[serde(deserialize_with = "positive", serialize_with = "shortest")]
+    pub scale: f32,
+    /// The axis pointing up in the source.
+    pub up_axis: UpAxis,
+    /// Whether smooth normals are generated for meshes without them.
+    pub generate_normals: bool,
+    /// Whether tangents are generated for meshes with normals and texture coordinates.
+    pub generate_tangents: bool,
+    /// Whether meshes are welded and optimized when added, see `MeshOptimization`.
+    pub optimize: bool,
+    /// Whether triangle meshes sharing a material and layout are merged into one.
+    pub merge_by_material: bool,
+}
+
+impl Default for ImportSettings {
+    /// Keeps the source's scale and Y-up axis, generates missing normals
+    /// and optimizes meshes, without tangents or merging.
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            up_axis: UpAxis::Y,
+            generate_normals: true,
+            generate_tangents: false,
+            optimize: true,
+            merge_by_material: false,
+        }
+    }
+}
+
+impl ImportSettings {
+    /// Sets the uniform scale from source units to meters.
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale.max(f32::EPSILON);
+        self
+    }
+
+    /// Sets the axis pointing up in the source.
+    pub fn with_up_axis(mut self, up_axis: UpAxis) -> Self {
+        self.up_axis = up_axis;
+        self
+    }
+
+    /// Enables or disables generating normals for meshes without them.
+    pub fn with_generated_normals(mut self, generate_normals: bool) -> Self {
+        self.generate_normals = generate_normals;
+        self
+    }
+
+    /// Enables or disables generating tangents.
+    pub fn with_generated_tangents(mut self, generate_tangents: bool) -> Self {
+        self.generate_tangents = generate_tangents;
+        self
+    }
+
+    /// Enables or disables optimizing meshes when they are added.
+    pub fn with_optimization(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Enables or disables merging meshes that share a material.
+    pub fn with_merge_by_material(mut self, merge_by_material: bool) -> Self {
+        self.merge_by_material = merge_by_material;
+        self
+    }
+
+    /// Returns the path of the settings file of a source file.
+    pub fn sidecar_path(source: &Path) -> PathBuf {
+        let mut path = source.as_os_str().to_owned();
+        path.push(".");
+        path.push(SIDECAR_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Loads the settings of a source file from its sidecar.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the settings, which are the defaults if the
+    /// source has no sidecar, or an `ImportSettingsError` if it can't be
+    /// read or parsed.
+    pub fn load_for(source: &Path) -> Result<Self, ImportSettingsError> {
+        let path = Self::sidecar_path(source);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(source) => return Err(ImportSettingsError::Io { path, source }),
+        };
+        let settings = text.parse()?;
+        info!("Loaded import settings {}", path.display());
+        Ok(settings)
+    }
+
+    /// Saves the settings to the sidecar of a source file.
+    pub fn save_for(&self, source: &Path) -> Result<(), ImportSettingsError> {
+        let path = Self::sidecar_path(source);
+        fs::write(&path, self.to_toml()?).map_err(|source| ImportSettingsError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        info!("Saved import settings {}", path.display());
+        Ok(())
+    }
+
+    /// Writes the settings as the TOML of a sidecar.
+    pub fn to_toml(&self) -> Result<String, ImportSettingsError> {
+        toml::to_string(self).map_err(|error| ImportSettingsError::Serialize(error.to_string()))
+    }
+
+    /// Reads settings from the settings of an `AssetRecord`.
+    ///
+    /// Settings left out keep their defaults, and names that aren't import
+    /// settings are ignored.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the settings, or `ImportSettingsError::InvalidValue`.
+    pub fn from_settings(settings: &BTreeMap<String, String>) -> Result<Self, ImportSettingsError> {
+        let mut import_settings = Self::default();
+        for (name, value) in settings {
+            import_settings.set(name, value).map_err(|message| {
+                ImportSettingsError::InvalidValue {
+                    name: name.clone(),
+                    message,
+                }
+            })?;
+        }
+        Ok(import_settings)
+    }
+
+    /// Returns the settings by name, for storing in an `AssetRecord`.
+    pub fn to_settings(&self) -> BTreeMap<String, String> {
+        let values = [
+            ("scale", self.scale.to_string()),
+            ("up_axis", self.up_axis.name().to_string()),
+            ("generate_normals", self.generate_normals.to_string()),
+            ("generate_tangents", self.generate_tangents.to_string()),
+            ("optimize", self.optimize.to_string()),
+            ("merge_by_material", self.merge_by_material.to_string()),
+        ];
+        values
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    /// Sets a setting by name from its text.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the name is an import setting, or why
+    /// the value is invalid.
+    fn set(&mut self, name: &str, value: &str) -> Result<bool, String> {
+        let flag = |value: &str| {
+            value
+                .parse::<bool>()
+                .map_err(|_| format!("expected true or false, found \"{value}\""))
+        };
+        match name {
+            "scale" => match value.parse::<f32>() {
+                Ok(scale) if scale > 0.0 && scale.is_finite() => self.scale = scale,
+                _ => return Err(format!("expected a positive number, found \"{value}\"")),
+            },
+            "up_axis" => self.up_axis = value.parse()?,
+            "generate_normals" => self.generate_normals = flag(value)?,
+            "generate_tangents" => self.generate_tangents = flag(value)?,
+            "optimize" => self.optimize = flag(value)?,
+            "merge_by_material" => self.merge_by_material = flag(value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Returns the rotation from the source's axes to the engine's.
+    fn rotation(&self) -> Mat3 {
+        match self.up_axis {
+            UpAxis::Y => Mat3::IDENTITY,
+            // Z-up becomes Y-up and Y-forward becomes -Z-forward, keeping handedness
+            UpAxis::Z => Mat3::from_rotation_x(-FRAC_PI_2),
+        }
+    }
+
+    /// Returns the matrix converting positions from the source's axes and units.
+    fn conversion(&self) -> Mat4 {
+        Mat4::from_mat3(self.rotation() * self.scale)
+    }
+
+    /// Converts a transform read from the source, such as a node's local
+    /// transform, to the engine's axes and units.
+    ///
+    /// Converting every transform of a hierarchy, and its meshes with
+    /// `apply`, gives the same result as converting the whole model.
+    pub fn convert_transform(&self, transform: Mat4) -> Mat4 {
+        let conversion = self.conversion();
+        conversion * transform * conversion.inverse()
+    }
+
+    /// Applies the settings to the meshes of an imported model.
+    ///
+    /// Meshes are converted to the engine's axes and scale, including their
+    /// transforms and sockets, then normals and tangents are generated and
+    /// meshes merged as enabled. Normals and tangents are only generated for
+    /// triangle lists, and added to the mesh's layout if it lacks them.
+    ///
+    /// # Arguments
+    ///
+    /// * `meshes` - The meshes as read from the source.
+    ///
+    /// # Returns
+    ///
+    /// The meshes to add to the renderer.
+    pub fn apply(&self, meshes: Vec<MeshBuilder>) -> Vec<MeshBuilder> {
+        let rotation = self.rotation();
+        let conversion = self.conversion();
+        let mut meshes: Vec<MeshBuilder> = meshes
+            .into_iter()
+            .map(|mut mesh| {
+                if conversion != Mat4::IDENTITY {
+                    for vertex in &mut mesh.data.vertices {
+                        vertex.position = conversion
+                            .transform_point3(Vec3::from(vertex.position))
+                            .into();
+                    }
+                    for stream in &mut mesh.streams {
+                        if matches!(stream.semantic, VertexSemantic::Normal) {
+                            rotate_values(&mut stream.values, rotation);
+                        }
+                    }
+                    mesh.data.transform = self.convert_transform(mesh.data.transform);
+                    for socket in &mut mesh.sockets {
+                        socket.transform = self.convert_transform(socket.transform);
+                    }
+                }
+                if self.generate_normals {
+                    generate_normals(&mut mesh);
+                }
+                if self.generate_tangents {
+                    generate_tangents(&mut mesh);
+                }
+                mesh
+            })
+            .collect();
+
+        if self.merge_by_material {
+            meshes = merge_by_material(meshes);
+        }
+        for mesh in &mut meshes {
+            mesh.optimization = self.optimize.then(MeshOptimization::default);
+        }
+        meshes
+    }
+}
+
+impl FromStr for ImportSettings {
+    type Err = ImportSettingsError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let deserializer = toml::Deserializer::new(text);
+        serde_ignored::deserialize(deserializer, |setting| {
+            warn!("Skipping unknown import setting \"{setting}\"")
+        })
+        .map_err(|error: toml::de::Error| ImportSettingsError::Parse {
+            line: error
+                .span()
+                .map_or(1, |span| text[..span.start].matches('\n').count() + 1),
+            message: error.message().to_string(),
+        })
+    }
+}
+
+/// Rotates the direction held in the first three components of each value.
+fn rotate_values(values: &mut [[f32; 4]], rotation: Mat3) {
+    for value in values {
+        let direction = rotation * Vec3::new(value[0], value[1], value[2]);
+        value[..3].copy_from_slice(&direction.to_array());
+    }
+}
+
+/// Returns the vertex indices of a triangle list mesh's triangles, or
+/// `None` for other primitives and malformed indices.
+fn triangles(mesh: &MeshBuilder) -> Option<Vec<[usize; 3]>> {
+    let data = &mesh.data;
+    if data.primitive_type != PrimitiveType::Triangle {
+        return None;
+    }
+    let indices: Vec<usize> = match &data.indices {
+        Some(indices) => indices.iter().map(|&i| i as usize).collect(),
+        None => (0..data.vertices.len()).collect(),
+    };
+    if !indices.len().is_multiple_of(3) || indices.iter().any(|&i| i >= data.vertices.len()) {
+        return None;
+    }
+    Some(
+        indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect(),
+    )
+}
+
+/// Returns the values of a mesh's attribute, if it has one for every vertex.
+fn stream(mesh: &MeshBuilder, semantic: VertexSemantic) -> Option<&[[f32; 4]]> {
+    mesh.streams
+        .iter()
+        .find(|stream| stream.semantic == semantic)
+        .filter(|stream| stream.values.len() == mesh.data.vertices.len())
+        .map(|stream| stream.values.as_slice())
+}
+
+/// Sets a mesh's attribute, adding it to the layout if it lacks it.
+fn set_stream(
+    mesh: &mut MeshBuilder,
+    semantic: VertexSemantic,
+    format: VertexFormat,
+    values: Vec<[f32; 4]>,
+) {
+    if !mesh.layout.has(semantic) {
+        mesh.layout = mesh.layout.with_attribute(semantic, format);
+    }
+    mesh.streams.retain(|stream| stream.semantic != semantic);
+    mesh.streams.push(VertexStream { semantic, values });
+}
+
+/// Gives a triangle mesh without normals smooth normals, weighting the
+/// faces around each vertex by their area.
+fn generate_normals(mesh: &mut MeshBuilder) {
+    if stream(mesh, VertexSemantic::Normal).is_some() {
+        return;
+    }
+    let Some(triangles) = triangles(mesh) else {
+        return;
+    };
+    let positions: Vec<Vec3> = mesh
+        .data
+        .vertices
+        .iter()
+        .map(|vertex| Vec3::from(vertex.position))
+        .collect();
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for [a, b, c] in triangles {
+        // The cross product's length is twice the triangle's area
+        let face = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        for vertex in [a, b, c] {
+            normals[vertex] += face;
+        }
+    }
+    let values = normals
+        .iter()
+        .map(|normal| normal.normalize_or_zero().extend(0.0).into())
+        .collect();
+    set_stream(mesh, VertexSemantic::Normal, VertexFormat::Float3, values);
+}
+
+/// Gives a triangle mesh with normals and texture coordinates tangents
+/// along the direction its U coordinate increases.
+fn generate_tangents(mesh: &mut MeshBuilder) {
+    let (Some(normals), Some(tex_coords), Some(triangles)) = (
+        stream(mesh, VertexSemantic::Normal),
+        stream(mesh, VertexSemantic::TexCoord),
+        triangles(mesh),
+    ) else {
+        return;
+    };
+    let vertices = &mesh.data.vertices;
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+    for [a, b, c] in triangles {
+        let position = |i: usize| Vec3::from(vertices[i].position);
+        let uv = |i: usize| Vec2::new(tex_coords[i][0], tex_coords[i][1]);
+        let (edge1, edge2) = (position(b) - position(a), position(c) - position(a));
+        let (duv1, duv2) = (uv(b) - uv(a), uv(c) - uv(a));
+        let determinant = duv1.perp_dot(duv2);
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+        for vertex in [a, b, c] {
+            tangents[vertex] += tangent;
+            bitangents[vertex] += bitangent;
+        }
+    }
+    let values = normals
+        .iter()
+        .zip(tangents.iter().zip(&bitangents))
+        .map(|(normal, (&tangent, &bitangent))| {
+            let normal = Vec3::new(normal[0], normal[1], normal[2]);
+            // Gram-Schmidt orthogonalize against the normal
+            let tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            tangent.extend(handedness).into()
+        })
+        .collect();
+    set_stream(mesh, TANGENT_SEMANTIC, VertexFormat::Float4, values);
+}
+
+/// Merges the triangle meshes sharing a material and layout into one,
+/// baking their transforms into their vertices. Instanced meshes and other
+/// primitives are kept as they are.
+fn merge_by_material(meshes: Vec<MeshBuilder>) -> Vec<MeshBuilder> {
+    let mut merged: Vec<MeshBuilder> = Vec::new();
+    let mut groups: HashMap<(MaterialId, usize), usize> = HashMap::new();
+    let mut layouts: Vec<VertexLayout> = Vec::new();
+    for mut mesh in meshes {
+        if mesh.data.instances.is_some() || triangles(&mesh).is_none() {
+            merged.push(mesh);
+            continue;
+        }
+        bake_transform(&mut mesh);
+        let layout = match layouts.iter().position(|&layout| layout == mesh.layout) {
+            Some(layout) => layout,
+            None => {
+                layouts.push(mesh.layout);
+                layouts.len() - 1
+            }
+        };
+        match groups.get(&(mesh.data.material_id, layout)) {
+            Some(&index) => append(&mut merged[index], mesh),
+            None => {
+                groups.insert((mesh.data.material_id, layout), merged.len());
+                merged.push(mesh);
+            }
+        }
+    }
+    merged
+}
+
+/// Moves a mesh's transform into its vertices, normals, tangents and sockets.
+fn bake_transform(mesh: &mut MeshBuilder) {
+    let transform = mesh.data.transform;
+    if transform == Mat4::IDENTITY {
+        return;
+    }
+    for vertex in &mut mesh.data.vertices {
+        vertex.position = transform
+            .transform_point3(Vec3::from(vertex.position))
+            .into();
+    }
+    let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+    for stream in &mut mesh.streams {
+        let matrix = match stream.semantic {
+            VertexSemantic::Normal => normal_matrix,
+            semantic if semantic == TANGENT_SEMANTIC => Mat3::from_mat4(transform),
+            _ => continue,
+        };
+        for value in &mut stream.values {
+            let direction = (matrix * Vec3::new(value[0], value[1], value[2])).normalize_or_zero();
+            value[..3].copy_from_slice(&direction.to_array());
+        }
+    }
+    for socket in &mut mesh.sockets {
+        socket.transform = transform * socket.transform;
+    }
+    mesh.data.transform = Mat4::IDENTITY;
+}
+
+/// Appends the triangles and sockets of a mesh with the same layout.
+fn append(mesh: &mut MeshBuilder, other: MeshBuilder) {
+    let (count, other_count) = (mesh.data.vertices.len(), other.data.vertices.len());
+    let own_indices = mesh
+        .data
+        .indices
+        .take()
+        .unwrap_or_else(|| (0..count as u32).collect());
+    let other_indices = other
+        .data
+        .indices
+        .unwrap_or_else(|| (0..other_count as u32).collect());
+    mesh.data.indices = Some(
+        own_indices
+            .into_iter()
+            .chain(other_indices.into_iter().map(|i| i + count as u32))
+            .collect(),
+    );
+    mesh.data.vertices.extend(other.data.vertices);
+
+    // Attributes only one of the meshes has get default values in the other
+    let mut semantics: Vec<VertexSemantic> = Vec::new();
+    for stream in mesh.streams.iter().chain(&other.streams) {
+        if !semantics.contains(&stream.semantic) {
+            semantics.push(stream.semantic);
+        }
+    }
+    let values = |streams: &[VertexStream], semantic: VertexSemantic, len: usize| {
+        streams
+            .iter()
+            .find(|stream| stream.semantic == semantic && stream.values.len() == len)
+            .map_or_else(
+                || vec![semantic.default_value(); len],
+                |stream| stream.values.clone(),
+            )
+    };
+    mesh.streams = semantics
+        .into_iter()
+        .map(|semantic| {
+            let mut combined = values(&mesh.streams, semantic, count);
+            combined.extend(values(&other.streams, semantic, other_count));
+            VertexStream {
+                semantic,
+                values: combined,
+            }
+        })
+        .collect();
+
+    for socket in other.sockets {
+        if !mesh
+            .sockets
+            .iter()
+            .any(|existing| existing.name == socket.name)
+        {
+            mesh.sockets.push(socket);
+        }
+    }
+    mesh.bvh |= other.bvh;
+    mesh.collidable |= other.collidable;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportSettings, UpAxis, TANGENT_SEMANTIC};
+    use crate::renderer::{
+        common::{PrimitiveType, Vertex},
+        error::ImportSettingsError,
+        material_manager::MaterialId,
+        shape_builders::MeshBuilder,
+        vertex_layout::VertexSemantic,
+    };
+    use glam::{Mat4, Vec2, Vec3};
+    use std::path::Path;
+
+    fn quad(material: usize) -> MeshBuilder {
+        let vertices = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ]
+        .map(|position| Vertex {
+            position,
+            ..Vertex::default()
+        });
+        MeshBuilder::new(vertices.to_vec(), PrimitiveType::Triangle)
+            .with_indices(vec![0, 1, 2, 0, 2, 3])
+            .with_material(MaterialId(material))
+    }
+
+    #[test]
+    fn test_import_settings_round_trip() {
+        let settings = ImportSettings::default()
+            .with_scale(0.01)
+            .with_up_axis(UpAxis::Z)
+            .with_merge_by_material(true);
+        let text = settings.to_toml().unwrap();
+        assert!(text.starts_with("scale = 0.01\nup_axis = \"z\"\n"));
+        let parsed: ImportSettings = text.parse().unwrap();
+        assert_eq!(parsed, settings);
+        assert_eq!(
+            ImportSettings::from_settings(&settings.to_settings()).unwrap(),
+            settings
+        );
+
+        // Left out settings keep their defaults, and unknown ones are skipped
+        let text = "# exported by a newer version\nscale = 2 # meters\ncompression = \"bc7\"";
+        let parsed: ImportSettings = text.parse().unwrap();
+        assert_eq!(parsed, ImportSettings::default().with_scale(2.0));
+        assert!(matches!(
+            "scale = 1\nup_axis = \"x\"".parse::<ImportSettings>(),
+            Err(ImportSettingsError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            "scale = -1".parse::<ImportSettings>(),
+            Err(ImportSettingsError::Parse { line: 1, .. })
+        ));
+
+        let source = std::env::temp_dir().join(format!("import-{}.obj", std::process::id()));
+        assert_eq!(
+            ImportSettings::sidecar_path(Path::new("models/ship.obj")),
+            Path::new("models/ship.obj.import")
+        );
+        assert_eq!(
+            ImportSettings::load_for(&source).unwrap(),
+            ImportSettings::default()
+        );
+        settings.save_for(&source).unwrap();
+        assert_eq!(ImportSettings::load_for(&source).unwrap(), settings);
+        std::fs::remove_file(ImportSettings::sidecar_path(&source)).unwrap();
+    }
+
+    #[test]
+    fn test_import_settings_apply() {
+        let settings = ImportSettings::default()
+            .with_scale(2.0)
+            .with_up_axis(UpAxis::Z)
+            .with_generated_tangents(true)
+            .with_merge_by_material(true)
+            .with_optimization(false);
+        let tex_coords = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y];
+        let meshes = vec![
+            quad(1).with_tex_coords(&tex_coords),
+            quad(2),
+            quad(1)
+                .with_tex_coords(&tex_coords)
+                .with_transform(Mat4::from_translation(Vec3::Z)),
+        ];
+        let meshes = settings.apply(meshes);
+        assert_eq!(meshes.len(), 2);
+        assert!(meshes.iter().all(|mesh| mesh.optimization.is_none()));
+
+        // The Z-up quad facing +Z now faces +Y, twice as large
+        let merged = &meshes[0];
+        assert_eq!(merged.data.vertices.len(), 8);
+        assert_eq!(merged.data.indices.as_ref().unwrap().len(), 12);
+        assert!(
+            (Vec3::from(merged.data.vertices[2].position) - Vec3::new(2.0, 0.0, -2.0)).length()
+                < 1e-5
+        );
+        // The second quad's transform was converted and baked, raising it by 2
+        assert!((merged.data.vertices[4].position[1] - 2.0).abs() < 1e-5);
+        assert_eq!(merged.data.transform, Mat4::IDENTITY);
+        let stream = |semantic| {
+            merged
+                .streams
+                .iter()
+                .find(|stream| stream.semantic == semantic)
+                .unwrap()
+        };
+        let normal = stream(VertexSemantic::Normal).values[0];
+        assert!((Vec3::new(normal[0], normal[1], normal[2]) - Vec3::Y).length() < 1e-5);
+        assert!(merged.layout.has(VertexSemantic::Normal));
+        let tangent = stream(TANGENT_SEMANTIC).values[5];
+        assert!((Vec3::new(tangent[0], tangent[1], tangent[2]) - Vec3::X).length() < 1e-5);
+        // Without texture coordinates, the other quad has no tangents
+        assert!(!meshes[1].layout.has(TANGENT_SEMANTIC));
+    }
+}
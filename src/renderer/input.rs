@@ -0,0 +1,362 @@
+//! Input module for the renderer.
+//!
+//! This module provides `InputBindings`, the keys that trigger the renderer's
+//! actions, such as camera movement and debug toggles. Each action is bound
+//! to at most one key and each key to at most one action. Keys are named by
+//! their winit key code, e.g. `"KeyW"`, `"Space"` or `"F3"`. The editor's
+//! keys are fixed, and take precedence over actions bound to them while the
+//! editor is enabled.
+
+use super::camera::CameraMovement;
+use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+use std::fmt;
+use winit::keyboard::KeyCode;
+
+/// Keys the editor handles while it is enabled, and `F1`, which toggles it.
+pub const EDITOR_KEYS: [KeyCode; 11] = [
+    KeyCode::F1,
+    KeyCode::Tab,
+    KeyCode::Enter,
+    KeyCode::Insert,
+    KeyCode::Escape,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+];
+
+/// Keys that can be named in bindings, config files and replays.
+const KEYS: [KeyCode; 80] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Tab,
+    KeyCode::Escape,
+    KeyCode::Backspace,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::Period,
+    KeyCode::Comma,
+    KeyCode::Slash,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Backslash,
+    KeyCode::Backquote,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::Insert,
+    KeyCode::Delete,
+];
+
+/// Returns the key with a winit key code name, e.g. `"KeyW"`.
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.into_iter().find(|key| format!("{key:?}") == name)
+}
+
+/// Serializes a key by its winit key code name, for `#[serde(with = "key_name")]`.
+pub mod key_name {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(key: &KeyCode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{key:?}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        key_from_name(&name).ok_or_else(|| D::Error::custom(format!("unknown key \"{name}\"")))
+    }
+}
+
+/// Something the renderer does when a key is pressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ToggleWireframe,
+    ToggleBounds,
+    ToggleNormals,
+    CycleDebugView,
+    ToggleProfiler,
+    ToggleLogConsole,
+    TogglePause,
+    /// Advances one frame while paused.
+    StepFrame,
+    /// Halves the time scale.
+    SlowDown,
+    /// Doubles the time scale.
+    SpeedUp,
+    /// Starts or stops recording frames.
+    ToggleRecording,
+    /// Captures the next frame for the GPU debugger.
+    CaptureFrame,
+}
+
+impl Action {
+    /// Every action, in the order of `InputBindings`.
+    pub const ALL: [Action; 18] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::ToggleWireframe,
+        Action::ToggleBounds,
+        Action::ToggleNormals,
+        Action::CycleDebugView,
+        Action::ToggleProfiler,
+        Action::ToggleLogConsole,
+        Action::TogglePause,
+        Action::StepFrame,
+        Action::SlowDown,
+        Action::SpeedUp,
+        Action::ToggleRecording,
+        Action::CaptureFrame,
+    ];
+
+    /// Returns the action's name in config files, e.g. `"move_forward"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::ToggleWireframe => "toggle_wireframe",
+            Action::ToggleBounds => "toggle_bounds",
+            Action::ToggleNormals => "toggle_normals",
+            Action::CycleDebugView => "cycle_debug_view",
+            Action::ToggleProfiler => "toggle_profiler",
+            Action::ToggleLogConsole => "toggle_log_console",
+            Action::TogglePause => "toggle_pause",
+            Action::StepFrame => "step_frame",
+            Action::SlowDown => "slow_down",
+            Action::SpeedUp => "speed_up",
+            Action::ToggleRecording => "toggle_recording",
+            Action::CaptureFrame => "capture_frame",
+        }
+    }
+
+    /// Returns the action with a name, see `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// Returns the key the action is bound to by default.
+    pub fn default_key(self) -> KeyCode {
+        match self {
+            Action::MoveForward => KeyCode::KeyW,
+            Action::MoveBackward => KeyCode::KeyS,
+            Action::MoveLeft => KeyCode::KeyA,
+            Action::MoveRight => KeyCode::KeyD,
+            Action::MoveUp => KeyCode::Space,
+            Action::MoveDown => KeyCode::ShiftLeft,
+            Action::ToggleWireframe => KeyCode::KeyV,
+            Action::ToggleBounds => KeyCode::KeyB,
+            Action::ToggleNormals => KeyCode::KeyN,
+            Action::CycleDebugView => KeyCode::F2,
+            Action::ToggleProfiler => KeyCode::F3,
+            Action::ToggleLogConsole => KeyCode::F4,
+            Action::TogglePause => KeyCode::KeyP,
+            Action::StepFrame => KeyCode::Period,
+            Action::SlowDown => KeyCode::Minus,
+            Action::SpeedUp => KeyCode::Equal,
+            Action::ToggleRecording => KeyCode::F10,
+            Action::CaptureFrame => KeyCode::F12,
+        }
+    }
+
+    /// Returns the direction the action moves the camera in, or `None` if
+    /// it doesn't move the camera.
+    pub fn camera_movement(self) -> Option<CameraMovement> {
+        match self {
+            Action::MoveForward => Some(CameraMovement::Forward),
+            Action::MoveBackward => Some(CameraMovement::Backward),
+            Action::MoveLeft => Some(CameraMovement::Left),
+            Action::MoveRight => Some(CameraMovement::Right),
+            Action::MoveUp => Some(CameraMovement::Up),
+            Action::MoveDown => Some(CameraMovement::Down),
+            _ => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The keys bound to the renderer's actions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputBindings {
+    /// The key of every action, indexed by its position in `Action::ALL`.
+    keys: [Option<KeyCode>; Action::ALL.len()],
+}
+
+impl Default for InputBindings {
+    /// Binds every action to its default key.
+    fn default() -> Self {
+        Self {
+            keys: Action::ALL.map(|action| Some(action.default_key())),
+        }
+    }
+}
+
+impl InputBindings {
+    /// Creates new `InputBindings` with no action bound.
+    pub fn unbound() -> Self {
+        Self {
+            keys: [None; Action::ALL.len()],
+        }
+    }
+
+    /// Binds an action to a key, unbinding the action the key was bound to.
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        if let Some(previous) = self.action(key) {
+            self.keys[previous.index()] = None;
+        }
+        self.keys[action.index()] = Some(key);
+    }
+
+    /// Unbinds an action, so no key triggers it.
+    pub fn unbind(&mut self, action: Action) {
+        self.keys[action.index()] = None;
+    }
+
+    /// Returns the key an action is bound to.
+    pub fn key(&self, action: Action) -> Option<KeyCode> {
+        self.keys[action.index()]
+    }
+
+    /// Returns the action a key is bound to.
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| self.keys[action.index()] == Some(key))
+    }
+
+    /// Returns `true` if the key triggers an action or is handled by the editor.
+    pub fn handles(&self, key: KeyCode) -> bool {
+        EDITOR_KEYS.contains(&key) || self.action(key).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key_from_name, Action, InputBindings};
+    use crate::renderer::camera::CameraMovement;
+    use winit::keyboard::KeyCode;
+
+    #[test]
+    fn test_bindings() {
+        let mut bindings = InputBindings::default();
+        assert_eq!(bindings.action(KeyCode::KeyW), Some(Action::MoveForward));
+        assert_eq!(bindings.key(Action::ToggleProfiler), Some(KeyCode::F3));
+        assert!(bindings.handles(KeyCode::Tab));
+        assert!(!bindings.handles(KeyCode::KeyQ));
+
+        // Binding a bound key moves it to the new action
+        bindings.bind(Action::ToggleBounds, KeyCode::KeyW);
+        assert_eq!(bindings.action(KeyCode::KeyW), Some(Action::ToggleBounds));
+        assert_eq!(bindings.key(Action::MoveForward), None);
+        assert!(!bindings.handles(KeyCode::KeyB));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(key_from_name("KeyW"), Some(KeyCode::KeyW));
+        assert_eq!(key_from_name("ArrowUp"), Some(KeyCode::ArrowUp));
+        assert_eq!(key_from_name("W"), None);
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+            assert!(key_from_name(&format!("{:?}", action.default_key())).is_some());
+        }
+    }
+
+    #[test]
+    fn test_camera_movement() {
+        assert!(matches!(
+            Action::MoveUp.camera_movement(),
+            Some(CameraMovement::Up)
+        ));
+        let moving = Action::ALL
+            .into_iter()
+            .filter(|action| action.camera_movement().is_some())
+            .count();
+        assert_eq!(moving, 6);
+        assert!(Action::SpeedUp.camera_movement().is_none());
+    }
+}
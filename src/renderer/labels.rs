@@ -0,0 +1,339 @@
+//! Labels module for the renderer.
+//!
+//! This module provides text labels attached to scene nodes or world
+//! positions. Every frame the labels are projected onto the screen and drawn
+//! on the canvas, optionally scaled by their distance from the camera and
+//! connected to their target by a leader line. Labels fade while their
+//! target is hidden by the scene, which the overlay tests against the frame's
+//! depth on the GPU.
+
+use super::{
+    canvas::{Canvas, CanvasPoint, CanvasSize, Occlusion},
+    font::text_size,
+    scene_graph::NodeId,
+    Color,
+};
+use glam::{Mat4, Vec2, Vec3, Vec4Swizzles};
+
+/// What a label is attached to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LabelTarget {
+    /// The center of a node's bounding sphere, or its origin if it has no mesh.
+    Node(NodeId),
+    /// A fixed world-space position.
+    Position(Vec3),
+}
+
+impl From<NodeId> for LabelTarget {
+    fn from(id: NodeId) -> Self {
+        LabelTarget::Node(id)
+    }
+}
+
+impl From<Vec3> for LabelTarget {
+    fn from(position: Vec3) -> Self {
+        LabelTarget::Position(position)
+    }
+}
+
+/// Identifies a label added to the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LabelId(pub usize);
+
+/// Describes how a label is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LabelStyle {
+    pub color: Color,
+    /// The color of the box behind the text, or `None` for no box.
+    pub background: Option<Color>,
+    /// The size of a font pixel in points at the reference distance.
+    pub pixel_size: f32,
+    /// The distance at which the label has its pixel size, or `None` for a constant size.
+    pub reference_distance: Option<f32>,
+    /// The smallest and largest factors distance scaling may apply.
+    pub scale_range: (f32, f32),
+    /// The offset of the text from the projected target in points, with y pointing down.
+    pub offset: Vec2,
+    /// Whether a line connects an offset label to its target.
+    pub leader_line: bool,
+    /// The opacity of the label while its target is hidden, from 0 to 1.
+    pub occluded_opacity: f32,
+}
+
+impl Default for LabelStyle {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            background: Some(Color::BLACK.with_alpha(0.5)),
+            pixel_size: 2.0,
+            reference_distance: None,
+            scale_range: (0.5, 2.0),
+            offset: Vec2::ZERO,
+            leader_line: true,
+            occluded_opacity: 0.25,
+        }
+    }
+}
+
+impl LabelStyle {
+    /// Sets the color of the text and leader line.
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the color of the box behind the text, or `None` for no box.
+    pub fn with_background(mut self, background: Option<Color>) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Sets the size of a font pixel in screen pixels.
+    pub fn with_pixel_size(mut self, pixel_size: f32) -> Self {
+        self.pixel_size = pixel_size;
+        self
+    }
+
+    /// Scales the label inversely with its distance from the camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference_distance` - The distance at which the label has its pixel size.
+    /// * `min_scale` - The smallest factor applied to far labels.
+    /// * `max_scale` - The largest factor applied to near labels.
+    pub fn with_distance_scaling(
+        mut self,
+        reference_distance: f32,
+        min_scale: f32,
+        max_scale: f32,
+    ) -> Self {
+        self.reference_distance = Some(reference_distance);
+        self.scale_range = (min_scale, max_scale);
+        self
+    }
+
+    /// Sets the offset of the text from its target in points, with y pointing down.
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets whether a line connects an offset label to its target.
+    pub fn with_leader_line(mut self, leader_line: bool) -> Self {
+        self.leader_line = leader_line;
+        self
+    }
+
+    /// Sets the opacity of the label while its target is hidden.
+    pub fn with_occluded_opacity(mut self, occluded_opacity: f32) -> Self {
+        self.occluded_opacity = occluded_opacity;
+        self
+    }
+
+    /// Returns the factor applied to the pixel size at a distance from the camera.
+    fn scale(&self, distance: f32) -> f32 {
+        match self.reference_distance {
+            Some(reference) if distance > 0.0 => {
+                let (min, max) = self.scale_range;
+                (reference / distance).clamp(min, max.max(min))
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// A text label attached to the scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub target: LabelTarget,
+    pub text: String,
+    pub style: LabelStyle,
+}
+
+/// The labels of a renderer, indexed by `LabelId`.
+#[derive(Clone, Debug, Default)]
+pub struct Labels {
+    labels: Vec<Option<Label>>,
+}
+
+impl Labels {
+    /// Adds a label and returns its handle.
+    pub fn add(&mut self, label: Label) -> LabelId {
+        self.labels.push(Some(label));
+        LabelId(self.labels.len() - 1)
+    }
+
+    /// Removes a label, returning `true` if it existed.
+    pub fn remove(&mut self, id: LabelId) -> bool {
+        self.labels
+            .get_mut(id.0)
+            .is_some_and(|label| label.take().is_some())
+    }
+
+    /// Returns a label for modification.
+    pub fn get_mut(&mut self, id: LabelId) -> Option<&mut Label> {
+        self.labels.get_mut(id.0)?.as_mut()
+    }
+
+    /// Returns `true` if there are no labels.
+    pub fn is_empty(&self) -> bool {
+        self.labels.iter().all(Option::is_none)
+    }
+
+    /// Projects the labels and draws them on the canvas.
+    ///
+    /// Labels whose target is behind the camera or off screen are skipped.
+    /// Each label fades by the visibility of the point of its target's
+    /// bounding sphere nearest the camera, which is not tested while the
+    /// camera is inside the sphere.
+    ///
+    /// # Arguments
+    ///
+    /// * `canvas` - The canvas to draw on.
+    /// * `view_projection` - The unjittered view projection matrix of the frame.
+    /// * `camera_position` - The world-space position of the camera.
+    /// * `resolve` - Returns the world-space center and radius of a target, or
+    ///   `None` if it no longer exists.
+    pub fn layout(
+        &self,
+        canvas: &mut Canvas,
+        view_projection: Mat4,
+        camera_position: Vec3,
+        resolve: impl Fn(LabelTarget) -> Option<(Vec3, f32)>,
+    ) {
+        let screen = canvas.size();
+        let scale_factor = canvas.scale_factor();
+        let project = |position: Vec3| {
+            let clip = view_projection * position.extend(1.0);
+            (clip.w > 0.0).then(|| clip.xyz() / clip.w)
+        };
+
+        for label in self.labels.iter().flatten() {
+            let Some((center, radius)) = resolve(label.target) else {
+                continue;
+            };
+            let Some(ndc) = project(center) else {
+                continue;
+            };
+            if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 {
+                continue;
+            }
+            let anchor = Vec2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5) * screen;
+
+            let style = &label.style;
+            let to_camera = camera_position - center;
+            let distance = to_camera.length();
+            let occlusion = (distance > radius)
+                .then(|| project(center + to_camera / distance * radius))
+                .flatten()
+                .map(|point| Occlusion {
+                    point,
+                    occluded_opacity: style.occluded_opacity,
+                });
+            canvas.set_occlusion(occlusion);
+
+            let pixel_size = style.pixel_size * style.scale(distance) * scale_factor;
+            let offset = style.offset * scale_factor;
+            let padding = Vec2::splat(pixel_size * 2.0);
+            let text = text_size(&label.text) * pixel_size;
+            let size = text + padding * 2.0;
+            // Offset labels extend away from their target, others are centered on it
+            let pivot = Vec2::select(
+                offset.cmpeq(Vec2::ZERO),
+                Vec2::splat(0.5),
+                0.5 - offset.signum() * 0.5,
+            );
+            let position = anchor + offset;
+            let min = position - size * pivot;
+
+            if style.leader_line && offset != Vec2::ZERO {
+                canvas.line(
+                    CanvasPoint::pixels(anchor.x, anchor.y),
+                    CanvasPoint::pixels(position.x, position.y),
+                    scale_factor,
+                    style.color,
+                );
+            }
+            if let Some(background) = style.background {
+                canvas.rect(
+                    CanvasPoint::pixels(min.x, min.y),
+                    CanvasSize::pixels(size.x, size.y),
+                    background,
+                );
+            }
+            let text_min = min + padding;
+            canvas.text(
+                CanvasPoint::pixels(text_min.x, text_min.y),
+                &label.text,
+                pixel_size,
+                style.color,
+            );
+        }
+        canvas.set_occlusion(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Label, LabelStyle, LabelTarget, Labels};
+    use crate::renderer::{canvas::Canvas, scene_graph::NodeId};
+    use glam::{Mat4, Vec2, Vec3};
+
+    fn label(target: impl Into<LabelTarget>, style: LabelStyle) -> Label {
+        Label {
+            target: target.into(),
+            text: "A".to_string(),
+            style: style.with_background(None),
+        }
+    }
+
+    fn layout(labels: &Labels) -> Canvas {
+        let mut canvas = Canvas::new(Vec2::new(100.0, 100.0));
+        let camera = Vec3::new(0.0, 0.0, -10.0);
+        labels.layout(&mut canvas, Mat4::IDENTITY, camera, |target| match target {
+            LabelTarget::Position(position) => Some((position, 0.0)),
+            LabelTarget::Node(_) => None,
+        });
+        canvas
+    }
+
+    #[test]
+    fn test_labels_layout() {
+        let mut labels = Labels::default();
+        labels.add(label(Vec3::new(0.0, 0.0, 0.5), LabelStyle::default()));
+        // Off screen, unresolved and removed labels are skipped
+        labels.add(label(Vec3::new(2.0, 0.0, 0.5), LabelStyle::default()));
+        labels.add(label(NodeId(7), LabelStyle::default()));
+        let removed = labels.add(label(Vec3::ZERO, LabelStyle::default()));
+        assert!(labels.remove(removed));
+        assert!(!labels.remove(removed));
+
+        let canvas = layout(&labels);
+        let vertices = &canvas.frame().vertices;
+        // 'A' at pixel size 2 is 10 by 16 pixels, padded by 4 and centered on
+        // the target, and its first run starts two font pixels down
+        assert_eq!(vertices[0].position, [45.0, 46.0]);
+        assert_eq!(vertices[0].occlusion, [0.0, 0.0, 0.5, 0.25]);
+        assert!(vertices
+            .iter()
+            .all(|vertex| (45.0..=55.0).contains(&vertex.position[0])));
+    }
+
+    #[test]
+    fn test_labels_offset_and_distance_scaling() {
+        let mut labels = Labels::default();
+        let style = LabelStyle::default()
+            .with_offset(Vec2::new(10.0, 0.0))
+            .with_distance_scaling(5.0, 0.5, 2.0);
+        labels.add(label(Vec3::new(0.0, 0.0, 0.5), style));
+
+        let canvas = layout(&labels);
+        let vertices = &canvas.frame().vertices;
+        // The leader line runs from the target to the offset position
+        assert_eq!(vertices[0].position, [50.0, 49.5]);
+        assert_eq!(vertices[2].position, [60.0, 50.5]);
+        // At about twice the reference distance the pixel size is halved,
+        // and the box extends right from the offset position
+        assert_eq!(vertices[4].position, [62.0, 48.0]);
+    }
+}
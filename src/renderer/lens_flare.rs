@@ -0,0 +1,263 @@
+//! Lens flare module for the renderer.
+//!
+//! This module provides the `LensFlare` effect: a chain of glows, discs and
+//! rings drawn along the screen-space axis from a light through the center of
+//! the screen. The backend tests the light against the depth buffer around its
+//! projected position on the GPU, so the flare fades as the light is occluded
+//! or leaves the screen, without reading depth back to the CPU.
+
+use super::Color;
+use glam::{Mat4, Vec3, Vec4Swizzles};
+
+/// The light a lens flare originates from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlareLight {
+    /// A light at a world-space position.
+    Point(Vec3),
+    /// A light infinitely far away in a world-space direction, such as a sun.
+    /// It is only visible where nothing was drawn.
+    Directional(Vec3),
+}
+
+/// The procedural shape of a flare element.
+///
+/// The discriminants must match the `FLARE_SHAPE_*` constants in the post-process shader.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlareShape {
+    /// A soft glow falling off from the center.
+    #[default]
+    Glow = 0,
+    /// A disc with a soft edge.
+    Disc = 1,
+    /// A thin ring.
+    Ring = 2,
+    /// A glow with thin horizontal and vertical streaks, like sun glare.
+    Starburst = 3,
+}
+
+/// One element of a lens flare chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlareElement {
+    /// The position along the flare axis: 0 at the light, 1 at the screen
+    /// center, and beyond 1 mirrored to the other side.
+    pub axis_offset: f32,
+    /// The radius as a fraction of the screen height.
+    pub size: f32,
+    pub color: Color,
+    pub shape: FlareShape,
+}
+
+impl FlareElement {
+    /// Creates a new `FlareElement`.
+    pub fn new(axis_offset: f32, size: f32, color: Color, shape: FlareShape) -> Self {
+        Self {
+            axis_offset,
+            size,
+            color,
+            shape,
+        }
+    }
+}
+
+/// A lens flare originating from a light.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LensFlare {
+    pub light: FlareLight,
+    pub elements: Vec<FlareElement>,
+    /// Scales the brightness of every element.
+    pub intensity: f32,
+    /// The radius around the light tested for occlusion, as a fraction of the screen height.
+    pub occlusion_radius: f32,
+}
+
+impl LensFlare {
+    /// Creates a new `LensFlare` without elements.
+    ///
+    /// # Arguments
+    ///
+    /// * `light` - The light the flare originates from.
+    pub fn new(light: FlareLight) -> Self {
+        Self {
+            light,
+            elements: Vec::new(),
+            intensity: 1.0,
+            occlusion_radius: 0.01,
+        }
+    }
+
+    /// Creates a sun glare with a starburst at the sun and a chain of ghosts
+    /// across the screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The world-space direction towards the sun.
+    pub fn sun(direction: Vec3) -> Self {
+        let warm = |a| Color::new(1.0, 0.9, 0.7, a);
+        let cool = |a| Color::new(0.6, 0.8, 1.0, a);
+        Self::new(FlareLight::Directional(direction))
+            .with_element(FlareElement::new(
+                0.0,
+                0.25,
+                warm(0.8),
+                FlareShape::Starburst,
+            ))
+            .with_element(FlareElement::new(0.0, 0.06, warm(1.0), FlareShape::Disc))
+            .with_element(FlareElement::new(0.4, 0.03, cool(0.25), FlareShape::Disc))
+            .with_element(FlareElement::new(0.8, 0.08, warm(0.15), FlareShape::Ring))
+            .with_element(FlareElement::new(1.3, 0.05, cool(0.2), FlareShape::Disc))
+            .with_element(FlareElement::new(1.7, 0.12, cool(0.1), FlareShape::Ring))
+            .with_element(FlareElement::new(2.0, 0.04, warm(0.3), FlareShape::Glow))
+    }
+
+    /// Adds an element to the flare chain.
+    pub fn with_element(mut self, element: FlareElement) -> Self {
+        self.elements.push(element);
+        self
+    }
+
+    /// Sets the brightness of the flare.
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Projects the light and lays out the flare elements for a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The unjittered view projection matrix of the frame.
+    /// * `aspect_ratio` - The width of the viewport divided by its height.
+    ///
+    /// # Returns
+    ///
+    /// The constants and elements to draw, with no elements if the light is
+    /// behind the camera.
+    pub fn frame(&self, view_projection: Mat4, aspect_ratio: f32) -> LensFlareFrame {
+        let clip = match self.light {
+            FlareLight::Point(position) => view_projection * position.extend(1.0),
+            FlareLight::Directional(direction) => {
+                view_projection * direction.normalize_or_zero().extend(0.0)
+            }
+        };
+        if clip.w <= 0.0 {
+            return LensFlareFrame::default();
+        }
+
+        let light = clip.xy() / clip.w;
+        // Directional lights sit on the far plane, behind everything drawn
+        let light_depth = match self.light {
+            FlareLight::Point(_) => (clip.z / clip.w).clamp(0.0, 1.0),
+            FlareLight::Directional(_) => 1.0,
+        };
+
+        // Sizes are relative to the screen height, so x is scaled down on wide screens
+        let elements = self
+            .elements
+            .iter()
+            .map(|element| FlareElementData {
+                center: (light * (1.0 - element.axis_offset)).to_array(),
+                size: [element.size * 2.0 / aspect_ratio, element.size * 2.0],
+                color: element.color.into(),
+                shape: element.shape as u32,
+                _padding: [0; 3],
+            })
+            .collect();
+
+        LensFlareFrame {
+            constants: FlareConstants {
+                light_position: light.to_array(),
+                light_depth,
+                occlusion_radius: self.occlusion_radius * 2.0,
+                aspect_ratio,
+                intensity: self.intensity,
+            },
+            elements,
+        }
+    }
+}
+
+/// The per-frame constants of a lens flare.
+///
+/// The layout matches the `FlareConstants` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FlareConstants {
+    /// The projected light in normalized device coordinates.
+    pub light_position: [f32; 2],
+    /// The depth of the light, compared against the depth buffer.
+    pub light_depth: f32,
+    /// The radius tested for occlusion, in normalized device coordinates of the height.
+    pub occlusion_radius: f32,
+    pub aspect_ratio: f32,
+    pub intensity: f32,
+}
+
+/// A flare element laid out on screen.
+///
+/// The layout matches the `FlareElement` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlareElementData {
+    /// The center in normalized device coordinates.
+    pub center: [f32; 2],
+    /// The half extents in normalized device coordinates.
+    pub size: [f32; 2],
+    pub color: [f32; 4],
+    pub shape: u32,
+    _padding: [u32; 3],
+}
+
+/// A lens flare laid out for one frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LensFlareFrame {
+    pub constants: FlareConstants,
+    pub elements: Vec<FlareElementData>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlareConstants, FlareElementData, FlareLight, LensFlare};
+    use glam::{Mat4, Vec3};
+
+    fn view_projection() -> Mat4 {
+        Mat4::perspective_rh(60f32.to_radians(), 1.0, 0.1, 100.0)
+            * Mat4::look_at_rh(Vec3::ZERO, -Vec3::Z, Vec3::Y)
+    }
+
+    #[test]
+    fn test_flare_layout() {
+        // Must match the structs in shader_types.h
+        assert_eq!(std::mem::size_of::<FlareConstants>(), 24);
+        assert_eq!(std::mem::size_of::<FlareElementData>(), 48);
+    }
+
+    #[test]
+    fn test_flare_elements_follow_axis() {
+        let flare = LensFlare::sun(Vec3::new(0.2, 0.1, -1.0));
+        let frame = flare.frame(view_projection(), 1.0);
+        assert_eq!(frame.elements.len(), flare.elements.len());
+        assert_eq!(frame.constants.light_depth, 1.0);
+
+        let light = frame.constants.light_position;
+        assert!(light[0] > 0.0 && light[1] > 0.0);
+        assert_eq!(frame.elements[0].center, light);
+        // Elements past the screen center are mirrored to the other side
+        let mirrored = frame.elements.last().unwrap().center;
+        assert!((mirrored[0] + light[0]).abs() < 1e-6);
+        assert!((mirrored[1] + light[1]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_flare_behind_camera() {
+        let flare = LensFlare::sun(Vec3::Z);
+        assert!(flare.frame(view_projection(), 1.0).elements.is_empty());
+
+        let flare = LensFlare::sun(Vec3::ZERO);
+        assert!(flare.frame(view_projection(), 1.0).elements.is_empty());
+
+        let flare = LensFlare::new(FlareLight::Point(Vec3::new(0.0, 0.0, -10.0)));
+        let frame = flare.frame(view_projection(), 1.0);
+        assert!(frame.constants.light_depth > 0.0 && frame.constants.light_depth < 1.0);
+    }
+}
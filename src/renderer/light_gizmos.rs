@@ -0,0 +1,369 @@
+//! Light gizmo module for the renderer.
+//!
+//! This module builds the editor's visualization of lights as debug lines: a
+//! camera-facing icon at every light, an arrow along the light of directional
+//! lights, a wireframe sphere of a point light's radius, a spot light's cone,
+//! and the frusta shadows are rendered in. Icons and arrows keep their size
+//! on screen, while radii and cones are drawn at their size in the world.
+//!
+//! While editor mode is enabled, the renderer draws the gizmos of the lights
+//! it has on the `GIZMO` layer: the sky's sun, the shadow light, lens flare
+//! lights and the cascades of cascaded shadows. Directional lights have no
+//! position, so their gizmos are drawn at the world origin. `LightGizmos`
+//! chooses which parts of the gizmos the editor draws.
+
+use super::{
+    camera::Camera,
+    common::{Color, Vertex},
+};
+use glam::{Mat4, Vec3};
+use std::f32::consts::TAU;
+
+/// The radius of light icons, as a fraction of their distance from the camera.
+const ICON_SCALE: f32 = 0.02;
+/// The length of directional light arrows, in icon radii.
+const ARROW_LENGTH: f32 = 6.0;
+/// The number of line segments circles are drawn with.
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// Which parts of the light gizmos the editor draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightGizmos {
+    /// Camera-facing icons at the positions of lights.
+    pub icons: bool,
+    /// Directional light arrows, point light radii and spot light cones.
+    pub volumes: bool,
+    /// The frusta shadows are rendered in.
+    pub shadow_frusta: bool,
+}
+
+impl Default for LightGizmos {
+    fn default() -> Self {
+        LightGizmos {
+            icons: true,
+            volumes: true,
+            shadow_frusta: true,
+        }
+    }
+}
+
+impl LightGizmos {
+    /// Draws no light gizmos.
+    pub const NONE: LightGizmos = LightGizmos {
+        icons: false,
+        volumes: false,
+        shadow_frusta: false,
+    };
+
+    /// Returns `true` if no part of the gizmos is drawn.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::NONE
+    }
+}
+
+/// The shape of a light, as visualized by its gizmo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightShape {
+    /// A light infinitely far away, such as a sun.
+    Directional {
+        /// The world-space direction towards the light.
+        direction: Vec3,
+    },
+    /// A light shining in every direction from a position.
+    Point {
+        position: Vec3,
+        /// The distance the light reaches, or 0 if unbounded.
+        radius: f32,
+    },
+    /// A light shining in a cone from a position.
+    Spot {
+        position: Vec3,
+        /// The world-space direction the light shines in.
+        direction: Vec3,
+        /// The distance the light reaches.
+        range: f32,
+        /// The angle between the cone's axis and its edge in radians.
+        angle: f32,
+    },
+}
+
+/// The gizmo of a light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightGizmo {
+    pub shape: LightShape,
+    pub color: Color,
+}
+
+impl LightGizmo {
+    /// Creates a new `LightGizmo`.
+    pub fn new(shape: LightShape, color: Color) -> Self {
+        LightGizmo { shape, color }
+    }
+
+    /// Returns the position the gizmo's icon is drawn at.
+    pub fn position(&self) -> Vec3 {
+        match self.shape {
+            LightShape::Directional { .. } => Vec3::ZERO,
+            LightShape::Point { position, .. } | LightShape::Spot { position, .. } => position,
+        }
+    }
+
+    /// Appends the gizmo as line-list vertices.
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The parts of the gizmo to draw.
+    /// * `camera` - The camera the gizmo is seen from, which icons face.
+    /// * `lines` - The vertices to append to.
+    pub fn append_lines(&self, parts: LightGizmos, camera: &Camera, lines: &mut Vec<Vertex>) {
+        let position = self.position();
+        let camera_to_world = camera.get_view_matrix().inverse();
+        let (right, up) = (
+            camera_to_world.x_axis.truncate(),
+            camera_to_world.y_axis.truncate(),
+        );
+        let size = position.distance(camera.position()) * ICON_SCALE;
+
+        if parts.icons {
+            append_circle(position, right, up, size, self.color, lines);
+            match self.shape {
+                // Rays around the circle, like a sun
+                LightShape::Directional { .. } => {
+                    for ray in 0..8 {
+                        let angle = ray as f32 / 8.0 * TAU;
+                        let outward = right * angle.cos() + up * angle.sin();
+                        append_line(
+                            position + outward * size * 1.4,
+                            position + outward * size * 2.0,
+                            self.color,
+                            lines,
+                        );
+                    }
+                }
+                // A cross inside the circle, like a bulb's filament
+                LightShape::Point { .. } | LightShape::Spot { .. } => {
+                    let (right, up) = (right * size * 0.5, up * size * 0.5);
+                    append_line(position - right, position + right, self.color, lines);
+                    append_line(position - up, position + up, self.color, lines);
+                }
+            }
+        }
+
+        if parts.volumes {
+            match self.shape {
+                LightShape::Directional { direction } => {
+                    append_arrow(
+                        position,
+                        -direction.normalize_or_zero(),
+                        size * ARROW_LENGTH,
+                        self.color,
+                        lines,
+                    );
+                }
+                LightShape::Point { position, radius } if radius > 0.0 => {
+                    append_circle(position, Vec3::X, Vec3::Y, radius, self.color, lines);
+                    append_circle(position, Vec3::Y, Vec3::Z, radius, self.color, lines);
+                    append_circle(position, Vec3::Z, Vec3::X, radius, self.color, lines);
+                }
+                LightShape::Point { .. } => {}
+                LightShape::Spot {
+                    position,
+                    direction,
+                    range,
+                    angle,
+                } => append_cone(position, direction, range, angle, self.color, lines),
+            }
+        }
+    }
+}
+
+/// Appends the twelve edges of a frustum as line-list vertices.
+///
+/// # Arguments
+///
+/// * `view_projection` - The view projection matrix of the frustum, with depths from 0 to 1.
+/// * `color` - The color of the lines.
+/// * `lines` - The vertices to append to.
+pub fn append_frustum_lines(view_projection: Mat4, color: Color, lines: &mut Vec<Vertex>) {
+    let clip_to_world = view_projection.inverse();
+    let corner = |x: f32, y: f32, z: f32| clip_to_world.project_point3(Vec3::new(x, y, z));
+    let near = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| corner(x, y, 0.0));
+    let far = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| corner(x, y, 1.0));
+    for index in 0..4 {
+        let next = (index + 1) % 4;
+        append_line(near[index], near[next], color, lines);
+        append_line(far[index], far[next], color, lines);
+        append_line(near[index], far[index], color, lines);
+    }
+}
+
+/// Appends a line from `start` to `end`.
+fn append_line(start: Vec3, end: Vec3, color: Color, lines: &mut Vec<Vertex>) {
+    let color = color.into();
+    lines.push(Vertex {
+        position: start.to_array(),
+        color,
+    });
+    lines.push(Vertex {
+        position: end.to_array(),
+        color,
+    });
+}
+
+/// Appends a circle in the plane spanned by two perpendicular unit axes.
+fn append_circle(
+    center: Vec3,
+    axis_u: Vec3,
+    axis_v: Vec3,
+    radius: f32,
+    color: Color,
+    lines: &mut Vec<Vertex>,
+) {
+    let point = |segment: usize| {
+        let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+        center + (axis_u * angle.cos() + axis_v * angle.sin()) * radius
+    };
+    for segment in 0..CIRCLE_SEGMENTS {
+        append_line(point(segment), point(segment + 1), color, lines);
+    }
+}
+
+/// Appends an arrow ending at `head`, pointing along a unit direction.
+fn append_arrow(head: Vec3, direction: Vec3, length: f32, color: Color, lines: &mut Vec<Vertex>) {
+    if direction == Vec3::ZERO {
+        return;
+    }
+    append_line(head - direction * length, head, color, lines);
+    let (axis_u, axis_v) = direction.any_orthonormal_pair();
+    let barb_length = length * 0.2;
+    for barb in [axis_u, -axis_u, axis_v, -axis_v] {
+        let tail = head - direction * barb_length + barb * barb_length * 0.5;
+        append_line(tail, head, color, lines);
+    }
+}
+
+/// Appends a cone from its apex, with the circle at its base and four edges.
+fn append_cone(
+    apex: Vec3,
+    direction: Vec3,
+    range: f32,
+    angle: f32,
+    color: Color,
+    lines: &mut Vec<Vertex>,
+) {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return;
+    }
+    let (axis_u, axis_v) = direction.any_orthonormal_pair();
+    let center = apex + direction * range * angle.cos();
+    let radius = range * angle.sin();
+    append_circle(center, axis_u, axis_v, radius, color, lines);
+    for edge in [axis_u, -axis_u, axis_v, -axis_v] {
+        append_line(apex, center + edge * radius, color, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_frustum_lines, LightGizmo, LightGizmos, LightShape};
+    use crate::renderer::{
+        camera::Camera,
+        common::{Color, Vertex},
+    };
+    use glam::{Mat4, Vec3};
+
+    fn positions(lines: &[Vertex]) -> Vec<Vec3> {
+        lines
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position))
+            .collect()
+    }
+
+    #[test]
+    fn test_light_gizmo_parts() {
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 10.0), 60.0, 1.0, 0.1, 100.0);
+        let point = LightGizmo::new(
+            LightShape::Point {
+                position: Vec3::new(1.0, 2.0, 3.0),
+                radius: 2.0,
+            },
+            Color::YELLOW,
+        );
+
+        let mut icon = Vec::new();
+        let icon_only = LightGizmos {
+            volumes: false,
+            ..LightGizmos::default()
+        };
+        point.append_lines(icon_only, &camera, &mut icon);
+        // The icon faces the camera, so it lies in a plane of constant depth
+        assert!(!icon.is_empty() && icon.len() % 2 == 0);
+        assert!(positions(&icon).iter().all(|p| (p.z - 3.0).abs() < 1e-5));
+
+        // The radius is drawn as three great circles of the light's sphere
+        let mut radius = Vec::new();
+        let volumes_only = LightGizmos {
+            volumes: true,
+            ..LightGizmos::NONE
+        };
+        point.append_lines(volumes_only, &camera, &mut radius);
+        assert!(!radius.is_empty());
+        for position in positions(&radius) {
+            assert!((position.distance(Vec3::new(1.0, 2.0, 3.0)) - 2.0).abs() < 1e-5);
+        }
+
+        let mut nothing = Vec::new();
+        point.append_lines(LightGizmos::NONE, &camera, &mut nothing);
+        assert!(nothing.is_empty());
+    }
+
+    #[test]
+    fn test_spot_and_directional_volumes() {
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 10.0), 60.0, 1.0, 0.1, 100.0);
+        let volumes_only = LightGizmos {
+            volumes: true,
+            ..LightGizmos::NONE
+        };
+
+        // Every point of the cone lies within its range and angle
+        let spot = LightGizmo::new(
+            LightShape::Spot {
+                position: Vec3::ZERO,
+                direction: Vec3::NEG_Y,
+                range: 4.0,
+                angle: 0.5,
+            },
+            Color::WHITE,
+        );
+        let mut cone = Vec::new();
+        spot.append_lines(volumes_only, &camera, &mut cone);
+        for position in positions(&cone) {
+            assert!(position.length() <= 4.0 + 1e-5);
+            if position != Vec3::ZERO {
+                assert!(position.angle_between(Vec3::NEG_Y) <= 0.5 + 1e-5);
+            }
+        }
+
+        // The arrow points away from the sun, ending at the origin
+        let sun = LightGizmo::new(LightShape::Directional { direction: Vec3::Y }, Color::WHITE);
+        let mut arrow = Vec::new();
+        sun.append_lines(volumes_only, &camera, &mut arrow);
+        let shaft = positions(&arrow[..2]);
+        assert!(shaft[0].y > 0.0 && shaft[0].x == 0.0);
+        assert_eq!(shaft[1], Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_frustum_lines() {
+        let projection = Mat4::orthographic_rh(-1.0, 1.0, -2.0, 2.0, 1.0, 5.0);
+        let mut lines = Vec::new();
+        append_frustum_lines(projection, Color::WHITE, &mut lines);
+        assert_eq!(lines.len(), 24);
+        for position in positions(&lines) {
+            assert!((position.x.abs() - 1.0).abs() < 1e-5);
+            assert!((position.y.abs() - 2.0).abs() < 1e-5);
+            assert!(position.z == -1.0 || position.z == -5.0);
+        }
+    }
+}
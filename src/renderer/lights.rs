@@ -0,0 +1,467 @@
+//! Light module for the renderer.
+//!
+//! This module provides point and spot lights and the `LightManager` holding
+//! them, indexed by `LightId`. Every light has its own shadow settings: shadows
+//! are off until enabled, and each shadowed light has a shadow map of its own
+//! resolution. A spot light's shadow map is drawn with a single perspective
+//! projection covering its cone, and a point light's as a depth cube map of
+//! six 90° faces, in the order of `CubeFace::ALL`. Like the sun's cascades,
+//! shadows darken the frame where a surface within the light's reach is
+//! hidden from it.
+//!
+//! Every frame, `LightManager::draw_shadows` sets the shadow maps of the
+//! shadowed lights on the backend and draws the shadow casters into them.
+//!
+//! Surfaces are not shaded by these lights yet; besides their shadows,
+//! lights show as gizmos in editor mode.
+
+use super::{
+    backend::GraphicsBackend,
+    bounds::BoundingSphere,
+    common::Color,
+    light_gizmos::{LightGizmo, LightShape},
+    panorama::CubeFace,
+    shadow_map::{ShadowCasters, ShadowTarget},
+    RendererError,
+};
+use crate::profile_scope;
+use glam::{Mat4, Vec3};
+use std::f32::consts::FRAC_PI_2;
+
+/// The near plane distance of light shadow map projections.
+const SHADOW_NEAR: f32 = 0.05;
+
+/// The shadow settings of a light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightShadow {
+    /// Whether the light casts shadows.
+    pub enabled: bool,
+    /// The width and height of the light's shadow map, or of each cube face, in texels.
+    pub resolution: u32,
+    /// How much shadowed pixels are darkened, from 0 for none to 1 for black.
+    pub strength: f32,
+    /// The depth offset of shadow map comparisons, avoiding self-shadowing.
+    pub bias: f32,
+}
+
+impl Default for LightShadow {
+    fn default() -> Self {
+        LightShadow {
+            enabled: false,
+            resolution: 512,
+            strength: 0.6,
+            bias: 0.0005,
+        }
+    }
+}
+
+/// A light shining in every direction from a position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// The distance the light reaches.
+    pub radius: f32,
+    pub color: Color,
+    pub intensity: f32,
+    pub shadow: LightShadow,
+}
+
+impl PointLight {
+    /// Creates a new white `PointLight` without shadows.
+    pub fn new(position: Vec3, radius: f32) -> Self {
+        PointLight {
+            position,
+            radius,
+            color: Color::WHITE,
+            intensity: 1.0,
+            shadow: LightShadow::default(),
+        }
+    }
+
+    /// Sets the light's color.
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Enables shadows with a shadow map of the given resolution per cube face.
+    pub fn with_shadows(mut self, resolution: u32) -> Self {
+        self.shadow.enabled = true;
+        self.shadow.resolution = resolution.max(1);
+        self
+    }
+}
+
+/// A light shining in a cone from a position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    /// The direction the light shines in.
+    pub direction: Vec3,
+    /// The distance the light reaches.
+    pub range: f32,
+    /// The angle between the cone's axis and its edge in radians.
+    pub angle: f32,
+    pub color: Color,
+    pub intensity: f32,
+    pub shadow: LightShadow,
+}
+
+impl SpotLight {
+    /// Creates a new white `SpotLight` without shadows.
+    pub fn new(position: Vec3, direction: Vec3, range: f32, angle: f32) -> Self {
+        SpotLight {
+            position,
+            direction,
+            range,
+            angle,
+            color: Color::WHITE,
+            intensity: 1.0,
+            shadow: LightShadow::default(),
+        }
+    }
+
+    /// Sets the light's color.
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Enables shadows with a shadow map of the given resolution.
+    pub fn with_shadows(mut self, resolution: u32) -> Self {
+        self.shadow.enabled = true;
+        self.shadow.resolution = resolution.max(1);
+        self
+    }
+}
+
+/// A point or spot light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
+
+impl Light {
+    /// Returns the light's position.
+    pub fn position(&self) -> Vec3 {
+        match self {
+            Light::Point(light) => light.position,
+            Light::Spot(light) => light.position,
+        }
+    }
+
+    /// Returns the distance the light reaches.
+    pub fn range(&self) -> f32 {
+        match self {
+            Light::Point(light) => light.radius,
+            Light::Spot(light) => light.range,
+        }
+    }
+
+    /// Returns the light's shadow settings.
+    pub fn shadow(&self) -> &LightShadow {
+        match self {
+            Light::Point(light) => &light.shadow,
+            Light::Spot(light) => &light.shadow,
+        }
+    }
+
+    /// Returns the light's shadow settings for modification.
+    pub fn shadow_mut(&mut self) -> &mut LightShadow {
+        match self {
+            Light::Point(light) => &mut light.shadow,
+            Light::Spot(light) => &mut light.shadow,
+        }
+    }
+
+    /// Returns the light's editor gizmo.
+    pub fn gizmo(&self) -> LightGizmo {
+        match *self {
+            Light::Point(light) => LightGizmo::new(
+                LightShape::Point {
+                    position: light.position,
+                    radius: light.radius,
+                },
+                light.color,
+            ),
+            Light::Spot(light) => LightGizmo::new(
+                LightShape::Spot {
+                    position: light.position,
+                    direction: light.direction,
+                    range: light.range,
+                    angle: light.angle,
+                },
+                light.color,
+            ),
+        }
+    }
+
+    /// Returns the view projection matrices the light's shadow map is drawn
+    /// with: one for a spot light, and one per cube face for a point light.
+    pub fn shadow_view_projections(&self) -> Vec<Mat4> {
+        match *self {
+            Light::Point(light) => CubeFace::ALL
+                .iter()
+                .map(|face| {
+                    let camera = face.camera(light.position, SHADOW_NEAR, light.radius);
+                    camera.get_projection_matrix() * camera.get_view_matrix()
+                })
+                .collect(),
+            Light::Spot(light) => {
+                let direction = light.direction.normalize_or_zero();
+                if direction == Vec3::ZERO {
+                    return Vec::new();
+                }
+                let up = if direction.y.abs() > 0.99 {
+                    Vec3::Z
+                } else {
+                    Vec3::Y
+                };
+                // The cone's edge touches the sides of the square frustum
+                let fov = (light.angle * 2.0).clamp(0.01, FRAC_PI_2 * 1.9);
+                let projection = Mat4::perspective_rh(fov, 1.0, SHADOW_NEAR, light.range);
+                vec![projection * Mat4::look_to_rh(light.position, direction, up)]
+            }
+        }
+    }
+
+    /// Builds the shadow constants of a frame for the light.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    ///
+    /// # Returns
+    ///
+    /// The shadow map frame, or `None` if the light casts no shadows.
+    pub fn shadow_frame(&self, view_projection: Mat4) -> Option<LightShadowFrame> {
+        let shadow = self.shadow();
+        if !shadow.enabled {
+            return None;
+        }
+        let face_view_projections = self.shadow_view_projections();
+        if face_view_projections.is_empty() {
+            return None;
+        }
+        let mut faces = [Mat4::IDENTITY; 6];
+        faces[..face_view_projections.len()].copy_from_slice(&face_view_projections);
+        Some(LightShadowFrame {
+            constants: LightShadowConstants {
+                inverse_view_projection: view_projection.inverse(),
+                face_view_projections: faces,
+                position: self.position().to_array(),
+                range: self.range(),
+                face_count: face_view_projections.len() as u32,
+                strength: shadow.strength.clamp(0.0, 1.0),
+                bias: shadow.bias,
+                _padding: 0.0,
+            },
+            resolution: shadow.resolution.max(1),
+        })
+    }
+}
+
+/// The per-frame constants of a light's shadow pass.
+///
+/// The layout matches the `LightShadowConstants` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightShadowConstants {
+    pub inverse_view_projection: Mat4,
+    /// The view projection of every face, one for spot lights and six for point lights.
+    pub face_view_projections: [Mat4; 6],
+    pub position: [f32; 3],
+    pub range: f32,
+    pub face_count: u32,
+    pub strength: f32,
+    pub bias: f32,
+    _padding: f32,
+}
+
+/// A light's shadow map and shadow constants of one frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightShadowFrame {
+    pub constants: LightShadowConstants,
+    /// The width and height of the shadow map, or of each cube face, in texels.
+    pub resolution: u32,
+}
+
+/// Identifies a light added to the light manager.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LightId(pub usize);
+
+/// The point and spot lights of a renderer, indexed by `LightId`.
+#[derive(Default)]
+pub struct LightManager {
+    lights: Vec<Option<Light>>,
+    /// Whether light shadows are set on the backend, to release them once
+    /// no light casts shadows.
+    shadows_set: bool,
+}
+
+impl LightManager {
+    /// Adds a light and returns its handle.
+    pub fn add(&mut self, light: impl Into<Light>) -> LightId {
+        self.lights.push(Some(light.into()));
+        LightId(self.lights.len() - 1)
+    }
+
+    /// Removes a light, returning `true` if it existed.
+    pub fn remove(&mut self, id: LightId) -> bool {
+        self.lights
+            .get_mut(id.0)
+            .is_some_and(|light| light.take().is_some())
+    }
+
+    /// Returns a light.
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.lights.get(id.0)?.as_ref()
+    }
+
+    /// Returns a light for modification.
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.lights.get_mut(id.0)?.as_mut()
+    }
+
+    /// Enables or disables a light's shadows, returning `true` if the light exists.
+    pub fn set_shadows_enabled(&mut self, id: LightId, enabled: bool) -> bool {
+        self.get_mut(id)
+            .map(|light| light.shadow_mut().enabled = enabled)
+            .is_some()
+    }
+
+    /// Sets the resolution of a light's shadow map, returning `true` if the light exists.
+    pub fn set_shadow_resolution(&mut self, id: LightId, resolution: u32) -> bool {
+        self.get_mut(id)
+            .map(|light| light.shadow_mut().resolution = resolution.max(1))
+            .is_some()
+    }
+
+    /// Returns `true` if there are no lights.
+    pub fn is_empty(&self) -> bool {
+        self.lights.iter().all(Option::is_none)
+    }
+
+    /// Returns the lights in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Light> {
+        self.lights.iter().flatten()
+    }
+
+    /// Sets the shadow maps of the lights with shadows enabled on the backend
+    /// and draws the depth of the shadow casters into every face of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - The backend to draw with.
+    /// * `casters` - The frame's shadow casters.
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    pub fn draw_shadows(
+        &mut self,
+        backend: &mut impl GraphicsBackend,
+        casters: &ShadowCasters,
+        view_projection: Mat4,
+    ) -> Result<(), RendererError> {
+        let lights: Vec<_> = self
+            .iter()
+            .filter_map(|light| Some((*light, light.shadow_frame(view_projection)?)))
+            .collect();
+        if lights.is_empty() {
+            if std::mem::take(&mut self.shadows_set) {
+                backend.set_light_shadows(&[]);
+            }
+            return Ok(());
+        }
+        profile_scope!("light_shadows");
+        let frames: Vec<_> = lights.iter().map(|(_, frame)| *frame).collect();
+        backend.set_light_shadows(&frames);
+        self.shadows_set = true;
+
+        for (index, (light, frame)) in lights.iter().enumerate() {
+            let reach = BoundingSphere::new(light.position(), light.range());
+            for face in 0..frame.constants.face_count {
+                casters.draw(
+                    backend,
+                    ShadowTarget::Light {
+                        index: index as u32,
+                        face,
+                    },
+                    frame.constants.face_view_projections[face as usize],
+                    Some(reach),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Light, LightManager, PointLight, SpotLight};
+    use crate::renderer::panorama::CubeFace;
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_light_manager_shadow_settings() {
+        let mut lights = LightManager::default();
+        let lamp = lights.add(PointLight::new(Vec3::Y, 5.0));
+        let torch =
+            lights.add(SpotLight::new(Vec3::ZERO, Vec3::NEG_Z, 10.0, 0.4).with_shadows(1024));
+        assert!(lights
+            .get(lamp)
+            .unwrap()
+            .shadow_frame(Mat4::IDENTITY)
+            .is_none());
+
+        assert!(lights.set_shadows_enabled(lamp, true));
+        assert!(lights.set_shadow_resolution(lamp, 0));
+        let frame = lights
+            .get(lamp)
+            .unwrap()
+            .shadow_frame(Mat4::IDENTITY)
+            .unwrap();
+        assert_eq!((frame.constants.face_count, frame.resolution), (6, 1));
+        let frame = lights
+            .get(torch)
+            .unwrap()
+            .shadow_frame(Mat4::IDENTITY)
+            .unwrap();
+        assert_eq!((frame.constants.face_count, frame.resolution), (1, 1024));
+
+        assert!(lights.remove(lamp));
+        assert!(!lights.set_shadows_enabled(lamp, false));
+        assert_eq!(lights.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_shadow_view_projections() {
+        // Each cube face sees the points along its direction
+        let point = Light::Point(PointLight::new(Vec3::new(1.0, 2.0, 3.0), 10.0));
+        let faces = point.shadow_view_projections();
+        for (face, view_projection) in CubeFace::ALL.iter().zip(&faces) {
+            let seen =
+                view_projection.project_point3(Vec3::new(1.0, 2.0, 3.0) + face.direction() * 4.0);
+            assert!(seen.x.abs() < 1e-5 && seen.y.abs() < 1e-5);
+            assert!((0.0..1.0).contains(&seen.z));
+        }
+
+        // The spot light's cone fits within its frustum, and its range ends at the far plane
+        let spot = Light::Spot(SpotLight::new(Vec3::ZERO, Vec3::NEG_Y, 8.0, 0.5));
+        let view_projection = spot.shadow_view_projections()[0];
+        let edge = Vec3::new(0.5f32.sin(), -0.5f32.cos(), 0.0) * 4.0;
+        assert!(view_projection.project_point3(edge).x.abs() <= 1.0 + 1e-5);
+        assert!((view_projection.project_point3(Vec3::NEG_Y * 8.0).z - 1.0).abs() < 1e-5);
+    }
+}
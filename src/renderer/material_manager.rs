@@ -1,54 +1,340 @@
-// use super::common::{RenderPipelineId, TextureId};
-
-// pub struct MaterialId(usize);
-
-// pub struct UniformData {
-//     // Placeholder for uniform data
-//     // This could be a more complex structure depending on your needs
-//     data: Vec<u8>,
-// }
-
-// pub struct Material {
-//     pub pipeline_id: RenderPipelineId,
-//     pub texture_id: Option<TextureId>,
-//     pub uniform_data: UniformData,
-// }
-
-// pub struct MaterialManager {
-//     pipeline_ids: Vec<RenderPipelineId>,
-//     textures: Vec<Option<TextureId>>,
-//     uniforms: Vec<UniformData>,
-// }
-
-// impl MaterialManager {
-//     pub fn new() -> Self {
-//         MaterialManager {
-//             pipeline_ids: Vec::new(),
-//             textures: Vec::new(),
-//             uniforms: Vec::new(),
-//         }
-//     }
-
-//     pub fn create_material(
-//         &mut self,
-//         pipeline_id: RenderPipelineId,
-//         texture_id: Option<TextureId>,
-//         uniform_data: UniformData,
-//     ) -> MaterialId {
-//         let material_id = MaterialId(self.pipeline_ids.len());
-
-//         self.pipeline_ids.push(pipeline_id);
-//         self.textures.push(texture_id);
-//         self.uniforms.push(uniform_data);
-
-//         material_id
-//     }
-
-//     pub fn get(&self, id: MaterialId) -> Material {
-//         Material {
-//             pipeline_id: self.pipeline_ids[id.0],
-//             texture_id: self.textures[id.0],
-//             uniform_data: self.uniforms[id.0].clone(),
-//         }
-//     }
-// }
+//! Material management module for the renderer.
+//!
+//! This module provides the `Material` description and the `MaterialManager`,
+//! which owns every material used in a frame. The backend uploads the whole
+//! material table once (as a Metal argument buffer) and each draw selects its
+//! entry by `MaterialId`.
+//!
+//! Parameters that rarely change can instead be specialized, compiling their
+//! values into the shaders of the material's draws as constants rather than
+//! reading them from the table per pixel. Every distinct set of specialized
+//! values gets its own pipeline permutation, compiled when first drawn, so
+//! specialization trades pipeline count for per-pixel cost.
+
+use super::{common::TextureId, error::AssetError, render_state::RenderState, Color};
+use crate::debug_trace;
+use log::debug;
+use std::{
+    hash::{Hash, Hasher},
+    ops::BitOr,
+};
+
+/// Identifies a material registered with the `MaterialManager`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub usize);
+
+impl MaterialId {
+    /// The untextured white material every `MaterialManager` starts with.
+    pub const DEFAULT: MaterialId = MaterialId(0);
+}
+
+impl Default for MaterialId {
+    fn default() -> Self {
+        MaterialId::DEFAULT
+    }
+}
+
+/// A set of material parameters compiled into shaders as constants.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SpecializedParameters(pub u32);
+
+impl SpecializedParameters {
+    pub const NONE: SpecializedParameters = SpecializedParameters(0);
+    /// The base color, multiplied with vertex colors and the texture.
+    pub const BASE_COLOR: SpecializedParameters = SpecializedParameters(1 << 0);
+    /// The reflectivity, so materials without reflections skip the
+    /// reflection branch entirely.
+    pub const REFLECTIVITY: SpecializedParameters = SpecializedParameters(1 << 1);
+    pub const ALL: SpecializedParameters = SpecializedParameters(0b11);
+
+    /// Returns `true` if every parameter of `other` is in this set.
+    pub fn contains(self, other: SpecializedParameters) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for SpecializedParameters {
+    type Output = SpecializedParameters;
+
+    fn bitor(self, other: SpecializedParameters) -> SpecializedParameters {
+        SpecializedParameters(self.0 | other.0)
+    }
+}
+
+/// The values of a material's specialized parameters, which pipelines are
+/// specialized to.
+///
+/// Values compare and hash by their bits, so each distinct value is its own
+/// permutation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialConstants {
+    pub base_color: Option<[f32; 4]>,
+    pub reflectivity: Option<f32>,
+}
+
+impl MaterialConstants {
+    /// Returns `true` if no parameter is specialized.
+    pub fn is_empty(&self) -> bool {
+        self.base_color.is_none() && self.reflectivity.is_none()
+    }
+
+    /// Returns the bits of the values, which identify the permutation.
+    fn bits(&self) -> (Option<[u32; 4]>, Option<u32>) {
+        (
+            self.base_color.map(|color| color.map(f32::to_bits)),
+            self.reflectivity.map(f32::to_bits),
+        )
+    }
+}
+
+impl PartialEq for MaterialConstants {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits() == other.bits()
+    }
+}
+
+impl Eq for MaterialConstants {}
+
+impl Hash for MaterialConstants {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits().hash(state);
+    }
+}
+
+/// Describes the surface properties used when shading a draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub base_color: Color,
+    pub texture_id: Option<TextureId>,
+    /// The planar reflection texture blended over the base color, sampled at the fragment's screen position.
+    pub reflection_texture: Option<TextureId>,
+    /// How much of the reflection shows, from 0 for none to 1 for a perfect mirror.
+    pub reflectivity: f32,
+    /// The render state of draws using this material, unless overridden per draw.
+    pub render_state: RenderState,
+    /// The parameters compiled into the shaders of draws using this material.
+    pub specialized: SpecializedParameters,
+}
+
+impl Material {
+    /// Creates a new untextured `Material` with the given base color.
+    ///
+    /// The base color is multiplied with the vertex colors.
+    pub fn new(base_color: Color) -> Self {
+        Self {
+            base_color,
+            texture_id: None,
+            reflection_texture: None,
+            reflectivity: 0.0,
+            render_state: RenderState::default(),
+            specialized: SpecializedParameters::NONE,
+        }
+    }
+
+    /// Sets the base color texture of the material.
+    pub fn with_texture(mut self, texture_id: TextureId) -> Self {
+        self.texture_id = Some(texture_id);
+        self
+    }
+
+    /// Shows a planar reflection on the material, e.g. for water or a polished floor.
+    ///
+    /// # Arguments
+    ///
+    /// * `texture_id` - The reflection's texture, from `Renderer::reflection_texture`.
+    /// * `reflectivity` - How much of the reflection shows, from 0 to 1.
+    pub fn with_reflection(mut self, texture_id: TextureId, reflectivity: f32) -> Self {
+        self.reflection_texture = Some(texture_id);
+        self.reflectivity = reflectivity.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the render state of draws using the material.
+    pub fn with_render_state(mut self, render_state: RenderState) -> Self {
+        self.render_state = render_state;
+        self
+    }
+
+    /// Compiles parameters into the shaders of draws using the material,
+    /// for values that rarely change.
+    ///
+    /// Specialized parameters cost nothing per pixel to read and let the
+    /// shader compiler fold them away, e.g. skipping reflections entirely
+    /// at zero reflectivity. Changing a specialized value compiles a new
+    /// pipeline permutation, drawing with the unspecialized one until it is
+    /// ready.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - The parameters to specialize.
+    pub fn with_specialized(mut self, parameters: SpecializedParameters) -> Self {
+        self.specialized = parameters;
+        self
+    }
+
+    /// Returns the values of the material's specialized parameters.
+    pub fn constants(&self) -> MaterialConstants {
+        MaterialConstants {
+            base_color: self
+                .specialized
+                .contains(SpecializedParameters::BASE_COLOR)
+                .then(|| self.base_color.into()),
+            reflectivity: self
+                .specialized
+                .contains(SpecializedParameters::REFLECTIVITY)
+                .then_some(self.reflectivity),
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new(Color::new(1.0, 1.0, 1.0, 1.0))
+    }
+}
+
+/// Stores all materials and tracks whether the backend copy is stale.
+pub struct MaterialManager {
+    materials: Vec<Material>,
+    dirty: bool,
+}
+
+impl MaterialManager {
+    /// Creates a new `MaterialManager` containing only the default material.
+    pub fn new() -> Self {
+        debug!("Creating new MaterialManager");
+        Self {
+            materials: vec![Material::default()],
+            dirty: true,
+        }
+    }
+
+    /// Registers a new material.
+    ///
+    /// # Arguments
+    ///
+    /// * `material` - The material to add.
+    ///
+    /// # Returns
+    ///
+    /// The `MaterialId` of the new material.
+    pub fn create_material(&mut self, material: Material) -> MaterialId {
+        let id = MaterialId(self.materials.len());
+        self.materials.push(material);
+        self.dirty = true;
+        debug_trace!("Created material {:?}", id);
+        id
+    }
+
+    /// Replaces an existing material.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the material to replace.
+    /// * `material` - The new material description.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `AssetError::InvalidMaterial`.
+    pub fn update_material(
+        &mut self,
+        id: MaterialId,
+        material: Material,
+    ) -> Result<(), AssetError> {
+        let slot = self
+            .materials
+            .get_mut(id.0)
+            .ok_or(AssetError::InvalidMaterial(id))?;
+        *slot = material;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Retrieves a material by its ID.
+    pub fn get(&self, id: MaterialId) -> Option<&Material> {
+        self.materials.get(id.0)
+    }
+
+    /// Returns all materials, indexed by `MaterialId`.
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    /// Flags the materials for re-upload, e.g. once textures they reference finish uploading.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Returns `true` if materials changed since the last call, and clears the flag.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+}
+
+impl Default for MaterialManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Material, MaterialConstants, MaterialId, MaterialManager, SpecializedParameters};
+    use crate::renderer::{error::AssetError, Color};
+
+    #[test]
+    fn test_material_manager_default_material() {
+        let manager = MaterialManager::new();
+        assert_eq!(manager.materials().len(), 1);
+        assert_eq!(manager.get(MaterialId::DEFAULT), Some(&Material::default()));
+    }
+
+    #[test]
+    fn test_material_manager_create_and_update() {
+        let mut manager = MaterialManager::new();
+        assert!(manager.take_dirty());
+        assert!(!manager.take_dirty());
+
+        let red = Material::new(Color::new(1.0, 0.0, 0.0, 1.0));
+        let id = manager.create_material(red);
+        assert_eq!(id, MaterialId(1));
+        assert!(manager.take_dirty());
+
+        let blue = Material::new(Color::new(0.0, 0.0, 1.0, 1.0));
+        assert!(manager.update_material(id, blue).is_ok());
+        assert_eq!(manager.get(id), Some(&blue));
+        assert!(manager.take_dirty());
+
+        assert!(matches!(
+            manager.update_material(MaterialId(42), blue),
+            Err(AssetError::InvalidMaterial(MaterialId(42)))
+        ));
+    }
+
+    #[test]
+    fn test_material_constants() {
+        let material = Material::new(Color::new(1.0, 0.5, 0.0, 1.0));
+        assert!(material.constants().is_empty());
+
+        let specialized = material.with_specialized(SpecializedParameters::BASE_COLOR);
+        assert_eq!(
+            specialized.constants().base_color,
+            Some([1.0, 0.5, 0.0, 1.0])
+        );
+        assert_eq!(specialized.constants().reflectivity, None);
+
+        // Constants are equal by bits, so each distinct value is a permutation
+        let all = material.with_specialized(SpecializedParameters::ALL);
+        assert_eq!(all.constants(), all.constants());
+        assert_ne!(all.constants(), specialized.constants());
+        let negative_zero = MaterialConstants {
+            reflectivity: Some(-0.0),
+            ..Default::default()
+        };
+        let zero = MaterialConstants {
+            reflectivity: Some(0.0),
+            ..Default::default()
+        };
+        assert_ne!(negative_zero, zero);
+    }
+}
@@ -0,0 +1,160 @@
+//! GPU memory report module for the renderer.
+//!
+//! This module provides the `GpuMemoryReport`, a snapshot of how much GPU
+//! memory the renderer holds per resource category, compared against the
+//! device's recommended working set. On unified-memory Macs this memory is
+//! shared with the OS and other applications, so the report also flags when
+//! usage approaches the budget.
+
+use std::fmt;
+
+/// Fraction of the recommended working set above which a warning is raised.
+const NEAR_BUDGET_THRESHOLD: f32 = 0.8;
+
+/// A snapshot of the renderer's GPU memory usage, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GpuMemoryReport {
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    pub uniform_bytes: u64,
+    pub instance_bytes: u64,
+    pub texture_bytes: u64,
+    pub render_target_bytes: u64,
+    /// Bytes of ray tracing acceleration structures.
+    pub acceleration_structure_bytes: u64,
+    /// Bytes reserved in buffer heaps, including unallocated heap space.
+    pub heap_reserved_bytes: u64,
+    /// Bytes the device reports as allocated by this process.
+    pub device_allocated_bytes: u64,
+    /// The device's recommended maximum working set size.
+    pub recommended_working_set_bytes: u64,
+    pub has_unified_memory: bool,
+}
+
+impl GpuMemoryReport {
+    /// Returns the sum of all per-category allocations.
+    pub fn total_bytes(&self) -> u64 {
+        self.vertex_bytes
+            + self.index_bytes
+            + self.uniform_bytes
+            + self.instance_bytes
+            + self.texture_bytes
+            + self.render_target_bytes
+            + self.acceleration_structure_bytes
+    }
+
+    /// Returns device allocations as a fraction of the recommended working set.
+    pub fn budget_usage(&self) -> f32 {
+        if self.recommended_working_set_bytes == 0 {
+            return 0.0;
+        }
+        self.device_allocated_bytes as f32 / self.recommended_working_set_bytes as f32
+    }
+
+    /// Returns human-readable warnings when usage nears or exceeds the budget.
+    pub fn warnings(&self) -> Vec<String> {
+        let usage = self.budget_usage();
+        let mut warnings = Vec::new();
+
+        if usage > 1.0 {
+            warnings.push(format!(
+                "GPU allocations exceed the recommended working set ({:.0}%)",
+                usage * 100.0
+            ));
+        } else if usage >= NEAR_BUDGET_THRESHOLD {
+            warnings.push(format!(
+                "GPU allocations are nearing the recommended working set ({:.0}%)",
+                usage * 100.0
+            ));
+        }
+
+        if self.has_unified_memory && !warnings.is_empty() {
+            warnings.push("Memory is shared with the system on this device".to_string());
+        }
+        warnings
+    }
+}
+
+impl fmt::Display for GpuMemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MIB: f64 = 1024.0 * 1024.0;
+        let mib = |bytes: u64| bytes as f64 / MIB;
+
+        writeln!(f, "GPU memory report:")?;
+        writeln!(f, "  vertex:         {:>10.2} MiB", mib(self.vertex_bytes))?;
+        writeln!(f, "  index:          {:>10.2} MiB", mib(self.index_bytes))?;
+        writeln!(f, "  uniform:        {:>10.2} MiB", mib(self.uniform_bytes))?;
+        writeln!(
+            f,
+            "  instance:       {:>10.2} MiB",
+            mib(self.instance_bytes)
+        )?;
+        writeln!(f, "  textures:       {:>10.2} MiB", mib(self.texture_bytes))?;
+        writeln!(
+            f,
+            "  render targets: {:>10.2} MiB",
+            mib(self.render_target_bytes)
+        )?;
+        writeln!(
+            f,
+            "  accel structs:  {:>10.2} MiB",
+            mib(self.acceleration_structure_bytes)
+        )?;
+        writeln!(f, "  total:          {:>10.2} MiB", mib(self.total_bytes()))?;
+        writeln!(
+            f,
+            "  heaps reserved: {:>10.2} MiB",
+            mib(self.heap_reserved_bytes)
+        )?;
+        write!(
+            f,
+            "  device:         {:>10.2} / {:.2} MiB ({:.1}%)",
+            mib(self.device_allocated_bytes),
+            mib(self.recommended_working_set_bytes),
+            self.budget_usage() * 100.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GpuMemoryReport;
+
+    #[test]
+    fn test_memory_report_total() {
+        let report = GpuMemoryReport {
+            vertex_bytes: 1,
+            index_bytes: 2,
+            uniform_bytes: 3,
+            instance_bytes: 4,
+            texture_bytes: 5,
+            render_target_bytes: 6,
+            acceleration_structure_bytes: 7,
+            ..Default::default()
+        };
+        assert_eq!(report.total_bytes(), 28);
+    }
+
+    #[test]
+    fn test_memory_report_warnings() {
+        let mut report = GpuMemoryReport {
+            device_allocated_bytes: 500,
+            recommended_working_set_bytes: 1000,
+            ..Default::default()
+        };
+        assert!(report.warnings().is_empty());
+
+        report.device_allocated_bytes = 850;
+        assert_eq!(report.warnings().len(), 1);
+
+        report.device_allocated_bytes = 1200;
+        report.has_unified_memory = true;
+        let warnings = report.warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("exceed"));
+
+        // A device without a reported working set never warns
+        report.recommended_working_set_bytes = 0;
+        assert!(report.warnings().is_empty());
+    }
+}
@@ -4,22 +4,56 @@
 //! meshes, as well as storing them efficiently for use in rendering.
 
 use super::{
+    bounds::{Aabb, BoundingSphere},
     common::{PrimitiveType, Vertex},
+    error::ExportError,
+    export::write_obj,
+    raycast::{closest_triangle_point, nearest_hit, Bvh, Ray, RayHit},
+    render_queue::GeometryView,
     shape_builders::MeshBuilder,
+    vertex_layout::{PackedVertices, VertexLayout},
 };
-use crate::debug_trace;
-use log::{debug, trace};
+use crate::{debug_trace, math::geometry::MeshPoint};
+use glam::{Mat4, Vec3};
+use log::{debug, info, trace};
+use std::{fs, path::Path};
+
+/// A named attachment point on a mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Socket {
+    pub name: String,
+    /// The socket's transform in model space.
+    pub transform: Mat4,
+}
 
 /// Represents a mesh with vertices, indices, and associated Metal buffers.
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Option<Vec<u32>>,
     pub primitive_type: PrimitiveType,
+    /// The bounding box of the vertices in model space.
+    pub bounds: Aabb,
+    /// The bounding sphere of the vertices in model space.
+    pub bounding_sphere: BoundingSphere,
+    /// The vertices packed in the mesh's layout, unless it is the default layout of `Vertex`.
+    pub packed_vertices: Option<PackedVertices>,
+    /// The vertex positions alone, read by depth-only passes at 12 bytes per vertex.
+    pub positions: PackedVertices,
+    /// The bounding volume hierarchy over the triangles, if the builder asked for one.
+    pub bvh: Option<Bvh>,
+    /// Whether the geometry is uploaded once into GPU-resident buffers rather than every draw.
+    pub is_static: bool,
+    /// Whether scene nodes drawing the mesh become physics colliders.
+    pub collidable: bool,
+    /// The named attachment points scene nodes can be attached to.
+    pub sockets: Vec<Socket>,
 }
 
 impl Mesh {
     /// Create a new Mesh from a `MeshBuilder`.
     ///
+    /// The builder's geometry is optimized first, unless it opted out.
+    ///
     /// # Arguments
     ///
     /// * `mesh_builder` - The `MeshBuilder` containing mesh data.
@@ -29,10 +63,184 @@ impl Mesh {
     /// A new Mesh instance.
     pub fn new(mesh_builder: MeshBuilder) -> Self {
         debug_trace!("Creating new Mesh");
-        Mesh {
-            vertices: mesh_builder.data.vertices,
+        let mesh_builder = match mesh_builder.optimization {
+            Some(optimization) => optimization.apply(mesh_builder),
+            None => mesh_builder,
+        };
+        let vertices = mesh_builder.data.vertices;
+        let layout = mesh_builder.layout;
+        let packed_vertices = (layout != VertexLayout::default()).then(|| PackedVertices {
+            layout,
+            data: layout.pack(&vertices, &mesh_builder.streams),
+        });
+        let positions = PackedVertices {
+            layout: VertexLayout::position(),
+            data: VertexLayout::position().pack(&vertices, &mesh_builder.streams),
+        };
+        let mut mesh = Mesh {
+            bounds: Aabb::from_vertices(&vertices).unwrap_or_default(),
+            bounding_sphere: BoundingSphere::from_vertices(&vertices).unwrap_or_default(),
+            packed_vertices,
+            positions,
+            vertices,
             indices: mesh_builder.data.indices,
             primitive_type: mesh_builder.data.primitive_type,
+            bvh: None,
+            is_static: mesh_builder.is_static,
+            collidable: mesh_builder.collidable,
+            sockets: mesh_builder.sockets,
+        };
+        if mesh_builder.bvh {
+            let triangles: Vec<_> = (0..mesh.triangle_count())
+                .filter_map(|triangle| mesh.triangle_positions(triangle))
+                .collect();
+            mesh.bvh = Some(Bvh::new(&triangles));
+        }
+        mesh
+    }
+
+    /// Returns the number of triangles the mesh draws, which is zero for points and lines.
+    pub fn triangle_count(&self) -> usize {
+        let count = self.indices.as_ref().map_or(self.vertices.len(), Vec::len);
+        match self.primitive_type {
+            PrimitiveType::Triangle => count / 3,
+            PrimitiveType::TriangleStrip => count.saturating_sub(2),
+            _ => 0,
+        }
+    }
+
+    /// Returns the vertex indices of a triangle.
+    ///
+    /// Every other triangle of a strip has its first two vertices swapped,
+    /// so all triangles keep the winding of the first.
+    ///
+    /// # Returns
+    ///
+    /// The indices into `vertices`, or `None` if the mesh has no such triangle.
+    pub fn triangle(&self, triangle: usize) -> Option<[usize; 3]> {
+        if triangle >= self.triangle_count() {
+            return None;
+        }
+        let corners = match self.primitive_type {
+            PrimitiveType::TriangleStrip if triangle % 2 == 1 => {
+                [triangle + 1, triangle, triangle + 2]
+            }
+            PrimitiveType::TriangleStrip => [triangle, triangle + 1, triangle + 2],
+            _ => [triangle * 3, triangle * 3 + 1, triangle * 3 + 2],
+        };
+        Some(match &self.indices {
+            Some(indices) => corners.map(|corner| indices[corner] as usize),
+            None => corners,
+        })
+    }
+
+    /// Returns the model-space corners of a triangle.
+    fn triangle_positions(&self, triangle: usize) -> Option<[Vec3; 3]> {
+        let corners = self.triangle(triangle)?;
+        let positions = corners.map(|index| self.vertices.get(index).map(|vertex| vertex.position));
+        match positions {
+            [Some(a), Some(b), Some(c)] => Some([a, b, c].map(Vec3::from)),
+            _ => None,
+        }
+    }
+
+    /// Casts a ray against the mesh's triangles.
+    ///
+    /// The ray is tested against the bounding box first, then against the
+    /// triangles, using the BVH if the mesh has one. Triangles are hit from
+    /// either side.
+    ///
+    /// # Arguments
+    ///
+    /// * `ray` - The ray in world space.
+    /// * `transform` - The mesh's model to world matrix.
+    ///
+    /// # Returns
+    ///
+    /// The nearest hit, with its distance in units of the ray's direction,
+    /// or `None` if the ray misses.
+    pub fn raycast(&self, ray: &Ray, transform: &Mat4) -> Option<RayHit> {
+        let ray = ray.transformed(&transform.inverse());
+        ray.intersect_aabb(&self.bounds, f32::INFINITY)?;
+        let corners = |triangle| self.triangle_positions(triangle).unwrap_or_default();
+        match &self.bvh {
+            Some(bvh) => bvh.raycast(&ray, corners),
+            None => (0..self.triangle_count()).fold(None, |nearest, triangle| {
+                nearest_hit(nearest, &ray, triangle, corners(triangle))
+            }),
+        }
+    }
+
+    /// Finds the point of the mesh's triangles closest to a point.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The query point in world space.
+    /// * `transform` - The mesh's model to world matrix.
+    ///
+    /// # Returns
+    ///
+    /// The closest point in world space, or `None` if the mesh has no triangles.
+    pub fn closest_point(&self, point: Vec3, transform: &Mat4) -> Option<MeshPoint> {
+        let corners = |triangle| {
+            self.triangle_positions(triangle)
+                .unwrap_or_default()
+                .map(|corner| transform.transform_point3(corner))
+        };
+        match &self.bvh {
+            Some(bvh) => bvh.closest_point(point, transform, corners),
+            None => (0..self.triangle_count()).fold(None, |closest, triangle| {
+                closest_triangle_point(closest, point, triangle, corners(triangle))
+            }),
+        }
+    }
+
+    /// Returns the model-space transform of a named socket.
+    pub fn socket(&self, name: &str) -> Option<Mat4> {
+        self.sockets
+            .iter()
+            .find(|socket| socket.name == name)
+            .map(|socket| socket.transform)
+    }
+
+    /// Returns the bytes of geometry a static mesh uploads: its vertices, in
+    /// its layout, its positions and its indices.
+    pub fn upload_bytes(&self) -> u64 {
+        let vertex_bytes = match &self.packed_vertices {
+            Some(packed_vertices) => packed_vertices.data.len(),
+            None => std::mem::size_of_val(self.vertices.as_slice()),
+        };
+        let index_bytes = self.indices.as_deref().map_or(0, std::mem::size_of_val);
+        (vertex_bytes + self.positions.data.len() + index_bytes) as u64
+    }
+
+    /// Writes the mesh as a Wavefront OBJ file, e.g. to inspect generated
+    /// geometry in Blender.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `ExportError::Io`.
+    pub fn export_obj(&self, path: &Path) -> Result<(), ExportError> {
+        fs::write(path, write_obj(self)).map_err(|source| ExportError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        info!(
+            "Exported mesh with {} vertices to {}",
+            self.vertices.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Returns a borrowed view of the mesh geometry.
+    pub fn view(&self) -> GeometryView<'_> {
+        GeometryView {
+            vertices: &self.vertices,
+            indices: self.indices.as_deref(),
+            primitive_type: self.primitive_type,
+            packed_vertices: self.packed_vertices.as_ref(),
+            positions: Some(&self.positions),
         }
     }
 }
@@ -70,6 +278,11 @@ impl MeshStorage {
         index
     }
 
+    /// Returns the number of stored meshes.
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
     /// Retrieves a reference to a mesh by its index.
     ///
     /// # Arguments
@@ -95,8 +308,10 @@ mod tests {
     use super::{Mesh, MeshStorage};
     use crate::renderer::{
         common::{PrimitiveType, Vertex},
+        raycast::Ray,
         shape_builders::MeshBuilder,
     };
+    use glam::{Mat4, Vec3};
 
     fn create_test_mesh_builder() -> MeshBuilder {
         let vertices = vec![
@@ -124,6 +339,51 @@ mod tests {
         assert_eq!(mesh.vertices.len(), 3);
         assert_eq!(mesh.primitive_type, PrimitiveType::Triangle);
         assert!(mesh.indices.is_none());
+        assert_eq!(mesh.bounds.min, Vec3::new(-0.5, -0.5, 0.0));
+        assert_eq!(mesh.bounds.max, Vec3::new(0.5, 0.5, 0.0));
+
+        // Depth-only passes read the positions alone
+        assert_eq!(mesh.upload_bytes(), 3 * 28 + 3 * 12);
+        assert_eq!(mesh.positions.layout.stride(), 12);
+        assert_eq!(mesh.positions.len(), 3);
+        let y = f32::from_ne_bytes(mesh.positions.data[4..8].try_into().unwrap());
+        assert_eq!(y, 0.5);
+    }
+
+    #[test]
+    fn test_mesh_raycast() {
+        // A strip of two triangles covering the square from (0, 0) to (1, 1)
+        let vertices = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]
+            .into_iter()
+            .map(|[x, y]| Vertex {
+                position: [x, y, 0.0],
+                ..Default::default()
+            })
+            .collect();
+        let builder =
+            MeshBuilder::new(vertices, PrimitiveType::TriangleStrip).with_optimization(None);
+        let mesh = Mesh::new(builder.clone());
+        assert_eq!(mesh.triangle_count(), 2);
+        assert_eq!(mesh.triangle(1), Some([2, 1, 3]));
+        assert_eq!(mesh.triangle(2), None);
+
+        let transform = Mat4::from_translation(Vec3::Z) * Mat4::from_scale(Vec3::splat(2.0));
+        let ray = Ray::new(Vec3::new(1.5, 1.5, 5.0), -Vec3::Z);
+        let bvh_mesh = Mesh::new(builder.with_bvh(true));
+        assert!(bvh_mesh.bvh.is_some());
+        for mesh in [&mesh, &bvh_mesh] {
+            let hit = mesh.raycast(&ray, &transform).unwrap();
+            // Distances are in world units despite the scale
+            assert_eq!(hit.distance, 4.0);
+            assert_eq!(hit.triangle, 1);
+            assert!(mesh.raycast(&ray, &Mat4::IDENTITY).is_none());
+
+            let closest = mesh
+                .closest_point(Vec3::new(1.5, 5.0, 3.0), &transform)
+                .unwrap();
+            assert_eq!(closest.point, Vec3::new(1.5, 2.0, 1.0));
+            assert_eq!(closest.distance, 2.0_f32.hypot(3.0));
+        }
     }
 
     #[test]
@@ -0,0 +1,526 @@
+//! Mesh optimizer module for the renderer.
+//!
+//! This module provides `MeshOptimization`, the post-processing applied to a
+//! mesh's geometry when it is added to the renderer. Vertices equal within an
+//! epsilon are welded and the mesh is re-indexed, triangles are reordered so
+//! the GPU's post-transform vertex cache reuses recently shaded vertices, and
+//! vertices are reordered by first use so the vertex fetch reads memory
+//! mostly sequentially. Attributes can optionally be quantized to compact
+//! formats. Only triangle lists are optimized; a `MeshBuilder` opts out with
+//! `with_optimization(None)`.
+
+use super::{
+    common::{PrimitiveType, Vertex},
+    shape_builders::MeshBuilder,
+    vertex_layout::VertexStream,
+};
+use log::debug;
+use std::collections::{HashMap, VecDeque};
+
+/// The distance within which vertices are welded by default.
+const DEFAULT_WELD_EPSILON: f32 = 1e-6;
+
+/// The number of vertices the cache optimization models the GPU's cache holding.
+const VERTEX_CACHE_SIZE: usize = 32;
+
+/// How quickly the score of a cached vertex falls with its cache position.
+const CACHE_DECAY_POWER: f32 = 1.5;
+
+/// The score of the vertices of the last triangle, kept below the next
+/// positions so the following triangle does not reuse all of them at once.
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+
+/// How much vertices with few remaining triangles are preferred, so they
+/// are finished rather than left as isolated triangles.
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// The post-processing applied to a mesh's triangles when it is added.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshOptimization {
+    /// The distance within which vertices with equal attributes are welded, or `None` to keep duplicates.
+    pub weld_epsilon: Option<f32>,
+    /// Whether triangles are reordered for the post-transform vertex cache.
+    pub optimize_vertex_cache: bool,
+    /// Whether colors, normals and texture coordinates are stored in compact formats.
+    pub quantize: bool,
+}
+
+impl Default for MeshOptimization {
+    /// Welds exact duplicates and optimizes for the vertex cache, without quantizing.
+    fn default() -> Self {
+        Self {
+            weld_epsilon: Some(DEFAULT_WELD_EPSILON),
+            optimize_vertex_cache: true,
+            quantize: false,
+        }
+    }
+}
+
+impl MeshOptimization {
+    /// Sets the distance within which vertices are welded, or `None` to keep duplicates.
+    pub fn with_weld_epsilon(mut self, weld_epsilon: Option<f32>) -> Self {
+        self.weld_epsilon = weld_epsilon;
+        self
+    }
+
+    /// Enables or disables reordering triangles for the vertex cache.
+    pub fn with_vertex_cache_optimization(mut self, optimize_vertex_cache: bool) -> Self {
+        self.optimize_vertex_cache = optimize_vertex_cache;
+        self
+    }
+
+    /// Enables or disables quantizing attributes, see `VertexLayout::quantized`.
+    pub fn with_quantization(mut self, quantize: bool) -> Self {
+        self.quantize = quantize;
+        self
+    }
+
+    /// Optimizes the geometry of a mesh builder.
+    ///
+    /// Meshes that are not triangle lists, or whose indices are out of range
+    /// or not whole triangles, are left unchanged for validation to report.
+    /// Meshes without indices only gain them if welding merged vertices.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh_builder` - The mesh to optimize.
+    ///
+    /// # Returns
+    ///
+    /// The optimized mesh builder.
+    pub fn apply(&self, mut mesh_builder: MeshBuilder) -> MeshBuilder {
+        if self.quantize {
+            mesh_builder.layout = mesh_builder.layout.quantized();
+        }
+
+        let data = &mut mesh_builder.data;
+        let vertex_count = data.vertices.len();
+        if data.primitive_type != PrimitiveType::Triangle {
+            return mesh_builder;
+        }
+        if let Some(indices) = &data.indices {
+            if indices.len() % 3 != 0 || indices.iter().any(|&i| i as usize >= vertex_count) {
+                debug!("Skipping optimization of a mesh with malformed indices");
+                return mesh_builder;
+            }
+        }
+
+        if let Some(epsilon) = self.weld_epsilon {
+            let (remap, unique_count) =
+                weld_vertices(&data.vertices, &mesh_builder.streams, epsilon);
+            if unique_count < vertex_count {
+                let mut order = vec![0; unique_count];
+                for (old, &new) in remap.iter().enumerate().rev() {
+                    order[new as usize] = old as u32;
+                }
+                reorder_vertices(&mut data.vertices, &mut mesh_builder.streams, &order);
+                data.indices = Some(match data.indices.take() {
+                    Some(indices) => indices.iter().map(|&i| remap[i as usize]).collect(),
+                    None => remap,
+                });
+            }
+        }
+
+        if let Some(indices) = &mut data.indices {
+            if self.optimize_vertex_cache {
+                let before = average_cache_miss_ratio(indices, VERTEX_CACHE_SIZE);
+                *indices = optimize_vertex_cache(indices, data.vertices.len());
+                debug!(
+                    "Vertex cache misses per triangle: {:.3} -> {:.3}",
+                    before,
+                    average_cache_miss_ratio(indices, VERTEX_CACHE_SIZE)
+                );
+            }
+            let order = optimize_vertex_fetch(indices, data.vertices.len());
+            reorder_vertices(&mut data.vertices, &mut mesh_builder.streams, &order);
+        }
+
+        debug!(
+            "Optimized mesh: {} -> {} vertices",
+            vertex_count,
+            data.vertices.len()
+        );
+        mesh_builder
+    }
+}
+
+/// Finds the vertices equal to an earlier vertex within an epsilon.
+///
+/// Positions, colors and every stream's values are compared per component.
+/// Vertices are bucketed in a grid of `epsilon`-sized cells, so each is only
+/// compared with the vertices of its own and neighbouring cells.
+///
+/// # Arguments
+///
+/// * `vertices` - The vertices to weld.
+/// * `streams` - The other attributes of the vertices.
+/// * `epsilon` - The largest difference between equal components.
+///
+/// # Returns
+///
+/// The new index of every vertex, numbered in order of first occurrence,
+/// and the number of unique vertices.
+pub fn weld_vertices(
+    vertices: &[Vertex],
+    streams: &[VertexStream],
+    epsilon: f32,
+) -> (Vec<u32>, usize) {
+    let epsilon = epsilon.max(0.0);
+    let cell_size = epsilon.max(f32::MIN_POSITIVE);
+    let cell = |position: [f32; 3]| position.map(|x| (x / cell_size).floor() as i64);
+    let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() <= epsilon);
+    let equal = |a: usize, b: usize| {
+        close(&vertices[a].position, &vertices[b].position)
+            && close(&vertices[a].color, &vertices[b].color)
+            && streams.iter().all(|stream| {
+                let value = |index: usize| {
+                    stream
+                        .values
+                        .get(index)
+                        .copied()
+                        .unwrap_or(stream.semantic.default_value())
+                };
+                close(&value(a), &value(b))
+            })
+    };
+
+    // The first vertex of every unique vertex, by grid cell
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut remap = Vec::with_capacity(vertices.len());
+    let mut unique_count = 0;
+    for (index, vertex) in vertices.iter().enumerate() {
+        let [x, y, z] = cell(vertex.position);
+        let neighbours = (-1..=1).flat_map(|dx| {
+            (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [x + dx, y + dy, z + dz]))
+        });
+        let existing = neighbours
+            .filter_map(|key| cells.get(&key))
+            .flatten()
+            .find(|&&other| equal(index, other));
+        match existing {
+            Some(&other) => remap.push(remap[other]),
+            None => {
+                remap.push(unique_count as u32);
+                unique_count += 1;
+                cells.entry([x, y, z]).or_default().push(index);
+            }
+        }
+    }
+    (remap, unique_count)
+}
+
+/// Reorders triangles so consecutive triangles share recently used vertices.
+///
+/// This is Tom Forsyth's linear-speed vertex cache optimization: every
+/// vertex is scored by its position in a modeled LRU cache and by how many
+/// of its triangles remain, and the highest scoring triangle among those
+/// touching the cache is emitted next. The corners of every triangle keep
+/// their order, so winding is preserved.
+///
+/// # Arguments
+///
+/// * `indices` - The triangle list to reorder.
+/// * `vertex_count` - The number of vertices the indices refer to.
+///
+/// # Returns
+///
+/// The reordered triangle list.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let corners = |triangle: usize| &indices[triangle * 3..triangle * 3 + 3];
+
+    // The triangles of every vertex, with those not yet emitted first
+    let mut remaining = vec![0usize; vertex_count];
+    for &index in &indices[..triangle_count * 3] {
+        remaining[index as usize] += 1;
+    }
+    let mut offsets = vec![0; vertex_count + 1];
+    for vertex in 0..vertex_count {
+        offsets[vertex + 1] = offsets[vertex] + remaining[vertex];
+    }
+    let mut adjacency = vec![0; triangle_count * 3];
+    let mut fill = offsets.clone();
+    for triangle in 0..triangle_count {
+        for &vertex in corners(triangle) {
+            adjacency[fill[vertex as usize]] = triangle;
+            fill[vertex as usize] += 1;
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = remaining
+        .iter()
+        .map(|&remaining| vertex_score(None, remaining))
+        .collect();
+    let triangle_score = |vertex_scores: &[f32], triangle: usize| -> f32 {
+        corners(triangle)
+            .iter()
+            .map(|&vertex| vertex_scores[vertex as usize])
+            .sum()
+    };
+    let mut emitted = vec![false; triangle_count];
+    let mut next_unemitted = 0;
+    let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut result = Vec::with_capacity(triangle_count * 3);
+
+    let mut best = (0..triangle_count).max_by(|&a, &b| {
+        triangle_score(&vertex_scores, a).total_cmp(&triangle_score(&vertex_scores, b))
+    });
+    while let Some(triangle) = best {
+        emitted[triangle] = true;
+        result.extend_from_slice(corners(triangle));
+
+        // Remove the triangle from its vertices' remaining triangles
+        for &vertex in corners(triangle) {
+            let vertex = vertex as usize;
+            let live = &mut adjacency[offsets[vertex]..offsets[vertex] + remaining[vertex]];
+            if let Some(position) = live.iter().position(|&other| other == triangle) {
+                live.swap(position, live.len() - 1);
+                remaining[vertex] -= 1;
+            }
+        }
+
+        // Move the triangle's vertices to the front of the cache
+        let mut new_cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+        for &vertex in corners(triangle).iter().chain(&cache) {
+            if !new_cache.contains(&vertex) {
+                new_cache.push(vertex);
+            }
+        }
+        for &vertex in new_cache.iter().skip(VERTEX_CACHE_SIZE) {
+            cache_positions[vertex as usize] = None;
+        }
+        for (position, &vertex) in new_cache.iter().take(VERTEX_CACHE_SIZE).enumerate() {
+            cache_positions[vertex as usize] = Some(position);
+        }
+
+        // Rescore the vertices that moved, then pick the best of their triangles
+        for &vertex in &new_cache {
+            let vertex = vertex as usize;
+            vertex_scores[vertex] = vertex_score(cache_positions[vertex], remaining[vertex]);
+        }
+        best = None;
+        let mut best_score = f32::NEG_INFINITY;
+        for &vertex in &new_cache {
+            let vertex = vertex as usize;
+            for &other in &adjacency[offsets[vertex]..offsets[vertex] + remaining[vertex]] {
+                let score = triangle_score(&vertex_scores, other);
+                if score > best_score {
+                    best = Some(other);
+                    best_score = score;
+                }
+            }
+        }
+        new_cache.truncate(VERTEX_CACHE_SIZE);
+        cache = new_cache;
+
+        // Continue elsewhere once no cached vertex has triangles left
+        if best.is_none() {
+            while next_unemitted < triangle_count && emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            best = (next_unemitted < triangle_count).then_some(next_unemitted);
+        }
+    }
+    result
+}
+
+/// Returns how much emitting a triangle using a vertex is preferred.
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (VERTEX_CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+    };
+    cache_score + VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Renumbers vertices in the order the indices first use them.
+///
+/// # Arguments
+///
+/// * `indices` - The indices, rewritten to the new vertex numbers.
+/// * `vertex_count` - The number of vertices the indices refer to.
+///
+/// # Returns
+///
+/// The old index of every new vertex. Unused vertices are dropped.
+pub fn optimize_vertex_fetch(indices: &mut [u32], vertex_count: usize) -> Vec<u32> {
+    let mut remap = vec![None; vertex_count];
+    let mut order = Vec::with_capacity(vertex_count);
+    for index in indices.iter_mut() {
+        let new = *remap[*index as usize].get_or_insert_with(|| {
+            order.push(*index);
+            order.len() as u32 - 1
+        });
+        *index = new;
+    }
+    order
+}
+
+/// Returns the average number of vertices shaded per triangle with a FIFO vertex cache.
+///
+/// Lower is better: 0.5 is ideal for large grids and 3 means no reuse.
+///
+/// # Arguments
+///
+/// * `indices` - The triangle list to measure.
+/// * `cache_size` - The number of vertices the cache holds.
+pub fn average_cache_miss_ratio(indices: &[u32], cache_size: usize) -> f32 {
+    let mut cache = VecDeque::with_capacity(cache_size);
+    let mut misses = 0;
+    for &index in indices {
+        if !cache.contains(&index) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.pop_front();
+            }
+            cache.push_back(index);
+        }
+    }
+    misses as f32 / (indices.len() / 3).max(1) as f32
+}
+
+/// Keeps the vertices and stream values at `order`, in that order.
+fn reorder_vertices(vertices: &mut Vec<Vertex>, streams: &mut [VertexStream], order: &[u32]) {
+    *vertices = order.iter().map(|&old| vertices[old as usize]).collect();
+    for stream in streams {
+        let default = stream.semantic.default_value();
+        stream.values = order
+            .iter()
+            .map(|&old| stream.values.get(old as usize).copied().unwrap_or(default))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        average_cache_miss_ratio, optimize_vertex_cache, weld_vertices, MeshOptimization,
+        VERTEX_CACHE_SIZE,
+    };
+    use crate::renderer::{
+        common::{PrimitiveType, Vertex},
+        shape_builders::MeshBuilder,
+        VertexLayout,
+    };
+
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex {
+            position: [x, y, 0.0],
+            ..Default::default()
+        }
+    }
+
+    /// A grid of `size` by `size` quads, two triangles each.
+    fn grid(size: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = (0..=size)
+            .flat_map(|y| (0..=size).map(move |x| vertex(x as f32, y as f32)))
+            .collect();
+        let row = size + 1;
+        let indices = (0..size)
+            .flat_map(|y| (0..size).map(move |x| y * row + x))
+            .flat_map(|corner| {
+                [
+                    corner,
+                    corner + 1,
+                    corner + row,
+                    corner + 1,
+                    corner + row + 1,
+                    corner + row,
+                ]
+            })
+            .collect();
+        (vertices, indices)
+    }
+
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn test_weld_vertices() {
+        let vertices = [
+            vertex(0.0, 0.0),
+            vertex(1.0, 0.0),
+            vertex(1.0, 1e-7),
+            Vertex {
+                color: [1.0, 0.0, 0.0, 1.0],
+                ..vertex(1.0, 0.0)
+            },
+        ];
+        let (remap, unique_count) = weld_vertices(&vertices, &[], 1e-6);
+        // Close positions weld, different colors do not
+        assert_eq!(remap, [0, 1, 1, 2]);
+        assert_eq!(unique_count, 3);
+    }
+
+    #[test]
+    fn test_optimize_vertex_cache() {
+        let (vertices, indices) = grid(32);
+        // Scatter the triangles so the cache rarely hits
+        let triangle_count = indices.len() / 3;
+        let scattered: Vec<u32> = (0..triangle_count)
+            .flat_map(|triangle| {
+                let triangle = triangle * 769 % triangle_count;
+                indices[triangle * 3..triangle * 3 + 3].to_vec()
+            })
+            .collect();
+
+        let optimized = optimize_vertex_cache(&scattered, vertices.len());
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+        let before = average_cache_miss_ratio(&scattered, VERTEX_CACHE_SIZE);
+        let after = average_cache_miss_ratio(&optimized, VERTEX_CACHE_SIZE);
+        assert!(after < 0.8, "{before} -> {after}");
+        assert!(after < before);
+    }
+
+    #[test]
+    fn test_mesh_optimization() {
+        // A quad of two unindexed triangles shares two of its six vertices
+        let quad = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (0.0, 1.0),
+            (1.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+        ]
+        .map(|(x, y)| vertex(x, y))
+        .to_vec();
+        let optimized =
+            MeshOptimization::default().apply(MeshBuilder::new(quad, PrimitiveType::Triangle));
+        assert_eq!(optimized.data.vertices.len(), 4);
+        assert_eq!(optimized.data.indices.as_ref().unwrap().len(), 6);
+
+        // Unique vertices stay unindexed, and malformed indices untouched
+        let triangle = vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)];
+        let mesh = MeshBuilder::new(triangle.clone(), PrimitiveType::Triangle);
+        assert!(MeshOptimization::default()
+            .apply(mesh)
+            .data
+            .indices
+            .is_none());
+        let broken =
+            MeshBuilder::new(triangle, PrimitiveType::Triangle).with_indices(vec![2, 0, 7]);
+        let result = MeshOptimization::default().apply(broken);
+        assert_eq!(result.data.indices, Some(vec![2, 0, 7]));
+
+        let quantized = MeshOptimization::default()
+            .with_quantization(true)
+            .apply(MeshBuilder::new(Vec::new(), PrimitiveType::Point));
+        assert_eq!(quantized.layout, VertexLayout::default().quantized());
+    }
+}
@@ -0,0 +1,226 @@
+//! Minimap module for the renderer.
+//!
+//! This module provides `Minimap`, a top-down view of the scene in a corner
+//! of the screen. Every frame, before the main camera draws, the scene is
+//! drawn into the minimap's texture by an orthographic camera looking
+//! straight down, with north (-Z) at the top. The texture is then drawn on
+//! the canvas, with a wedge marking where the main camera is and what it
+//! sees. The minimap can show a fixed area or follow the main camera.
+
+use super::{
+    camera::Camera,
+    canvas::{Anchor, Canvas, CanvasPoint, CanvasSize},
+    common::{LayerMask, TextureId},
+    Color,
+};
+use glam::{Mat4, Vec2, Vec3};
+
+/// The resolution of minimap textures unless set.
+const DEFAULT_RESOLUTION: u32 = 512;
+
+/// A top-down view of the scene drawn in a corner of the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Minimap {
+    /// The point at the center of the map, or `None` to follow the main camera.
+    pub center: Option<Vec3>,
+    /// Half the width of the square area shown, in world units.
+    pub extent: f32,
+    /// The height above the center the map is drawn from. Geometry more
+    /// than this height above or below the center is not drawn.
+    pub altitude: f32,
+    /// The width and height of the minimap texture in pixels.
+    pub resolution: u32,
+    /// The layers drawn into the map.
+    pub cull_mask: LayerMask,
+    /// The corner or edge of the screen the map is placed in.
+    pub anchor: Anchor,
+    /// The width and height of the map on screen in points.
+    pub size: f32,
+    /// The space between the map and the edges of the screen in points.
+    pub margin: f32,
+    /// The color of the main camera's wedge, or `None` to hide it.
+    pub frustum_color: Option<Color>,
+    /// The color of the map's border, or `None` for no border.
+    pub border_color: Option<Color>,
+}
+
+impl Minimap {
+    /// Creates a new `Minimap` following the main camera in the top-right corner.
+    ///
+    /// # Arguments
+    ///
+    /// * `extent` - Half the width of the square area shown, in world units.
+    pub fn new(extent: f32) -> Self {
+        Minimap {
+            center: None,
+            extent: extent.max(f32::EPSILON),
+            altitude: extent.max(f32::EPSILON),
+            resolution: DEFAULT_RESOLUTION,
+            cull_mask: LayerMask::DEFAULT,
+            anchor: Anchor::TopRight,
+            size: 192.0,
+            margin: 8.0,
+            frustum_color: Some(Color::YELLOW),
+            border_color: Some(Color::WHITE),
+        }
+    }
+
+    /// Fixes the point at the center of the map.
+    pub fn with_center(mut self, center: Vec3) -> Self {
+        self.center = Some(center);
+        self
+    }
+
+    /// Sets the height above the center the map is drawn from.
+    pub fn with_altitude(mut self, altitude: f32) -> Self {
+        self.altitude = altitude.max(f32::EPSILON);
+        self
+    }
+
+    /// Sets the width and height of the minimap texture in pixels.
+    pub fn with_resolution(mut self, resolution: u32) -> Self {
+        self.resolution = resolution.max(1);
+        self
+    }
+
+    /// Sets the layers drawn into the map.
+    pub fn with_cull_mask(mut self, cull_mask: LayerMask) -> Self {
+        self.cull_mask = cull_mask;
+        self
+    }
+
+    /// Places the map on screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `anchor` - The corner or edge of the screen the map is placed in.
+    /// * `size` - The width and height of the map in points.
+    /// * `margin` - The space between the map and the edges of the screen in points.
+    pub fn with_placement(mut self, anchor: Anchor, size: f32, margin: f32) -> Self {
+        self.anchor = anchor;
+        self.size = size;
+        self.margin = margin;
+        self
+    }
+
+    /// Sets the color of the main camera's wedge, or `None` to hide it.
+    pub fn with_frustum_color(mut self, color: Option<Color>) -> Self {
+        self.frustum_color = color;
+        self
+    }
+
+    /// Sets the color of the map's border, or `None` for no border.
+    pub fn with_border_color(mut self, color: Option<Color>) -> Self {
+        self.border_color = color;
+        self
+    }
+
+    /// Returns the point at the center of the map for a main camera.
+    pub fn center(&self, camera: &Camera) -> Vec3 {
+        self.center.unwrap_or_else(|| camera.position())
+    }
+
+    /// Returns the view projection matrix drawing the map for a main camera.
+    pub fn view_projection(&self, camera: &Camera) -> Mat4 {
+        let center = self.center(camera);
+        let eye = center + Vec3::Y * self.altitude;
+        // North is up on the map
+        let view = Mat4::look_at_rh(eye, center, -Vec3::Z);
+        let extent = self.extent;
+        let projection =
+            Mat4::orthographic_rh(-extent, extent, -extent, extent, 0.0, self.altitude * 2.0);
+        projection * view
+    }
+
+    /// Returns the main camera's position and the ends of the lines along
+    /// the left and right edges of its view, flattened onto the ground and
+    /// reaching half the map's extent.
+    fn frustum_outline(&self, camera: &Camera) -> [Vec3; 3] {
+        let reach = self.extent * 0.5;
+        let edge = |x: f32| {
+            let direction = camera.screen_ray(Vec2::new(x, 0.5), Vec2::ONE).direction;
+            camera.position() + Vec3::new(direction.x, 0.0, direction.z) * reach
+        };
+        [edge(0.0), camera.position(), edge(1.0)]
+    }
+
+    /// Draws the map's texture on the canvas, with its border and the main camera's wedge.
+    ///
+    /// # Arguments
+    ///
+    /// * `canvas` - The canvas to draw on.
+    /// * `texture` - The texture the map was drawn into.
+    /// * `camera` - The main camera.
+    pub fn draw(&self, canvas: &mut Canvas, texture: TextureId, camera: &Camera) {
+        let scale_factor = canvas.scale_factor();
+        let (size, margin) = (self.size * scale_factor, self.margin * scale_factor);
+        // Inset from the edges the anchor touches
+        let factor = self.anchor.factor();
+        let offset = (Vec2::ONE - factor * 2.0) * margin;
+        let position = CanvasPoint::pixels(offset.x, offset.y).anchored(self.anchor);
+        let canvas_size = CanvasSize::pixels(size, size);
+        canvas.image(position, canvas_size, texture, Color::WHITE);
+        if let Some(color) = self.border_color {
+            canvas.rect_outline(position, canvas_size, scale_factor, color);
+        }
+
+        let Some(color) = self.frustum_color else {
+            return;
+        };
+        let min = position.resolve(canvas.size()) - factor * size;
+        let view_projection = self.view_projection(camera);
+        let points: Vec<CanvasPoint> = self
+            .frustum_outline(camera)
+            .iter()
+            .map(|&point| {
+                let ndc = view_projection.project_point3(point);
+                let pixel = min + Vec2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5) * size;
+                let pixel = pixel.clamp(min, min + size);
+                CanvasPoint::pixels(pixel.x, pixel.y)
+            })
+            .collect();
+        canvas.polyline(&points, scale_factor * 2.0, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Minimap;
+    use crate::renderer::{camera::Camera, canvas::Canvas, common::TextureId};
+    use glam::{Vec2, Vec3};
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn test_minimap_projection() {
+        let camera = Camera::new(Vec3::new(10.0, 2.0, 5.0), 90.0, 1.0, 0.1, 100.0);
+        let minimap = Minimap::new(20.0).with_altitude(50.0);
+
+        // The map follows the camera with north (-Z) up and east (+X) right
+        let view_projection = minimap.view_projection(&camera);
+        let ndc = |point: Vec3| view_projection.project_point3(point);
+        assert!(ndc(camera.position()).truncate().length() < 1e-6);
+        assert!((ndc(Vec3::new(30.0, 0.0, 5.0)).x - 1.0).abs() < 1e-6);
+        assert!((ndc(Vec3::new(10.0, 0.0, -15.0)).y - 1.0).abs() < 1e-6);
+        // Depth runs from the map's altitude above the center to as far below
+        assert!((ndc(camera.position()).z - 0.5).abs() < 1e-6);
+        let fixed = minimap.with_center(Vec3::ZERO).view_projection(&camera);
+        assert!(fixed.project_point3(Vec3::ZERO).truncate().length() < 1e-6);
+
+        // The camera looks north, so its wedge opens upwards on the map
+        let [left, apex, right] = minimap.frustum_outline(&camera);
+        assert_eq!(apex, camera.position());
+        assert!(left.x < apex.x && right.x > apex.x);
+        assert!(left.z < apex.z && right.z < apex.z);
+
+        let mut canvas = Canvas::new(Vec2::new(800.0, 600.0));
+        let texture = TextureId(NonZeroU32::new(3).unwrap());
+        minimap.draw(&mut canvas, texture, &camera);
+        let frame = canvas.frame();
+        assert_eq!(frame.batches[0].texture, Some(texture));
+        // Everything is within the map in the top-right corner
+        assert!(frame.vertices.iter().all(|vertex| {
+            let [x, y] = vertex.position;
+            (600.0..=792.0).contains(&x) && (8.0..=200.0).contains(&y)
+        }));
+    }
+}
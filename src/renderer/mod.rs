@@ -6,26 +6,244 @@
 //!
 //! Key Components:
 //!
+//! - `ambient`: Provides the flat, hemisphere or sky ambient light bound with every frame's constants.
+//! - `animation`: Plays keyframed node animation clips chosen by a parameter-driven state machine.
+//! - `asset_database`: Gives imported assets stable GUIDs and tracks their sources, settings and dependencies.
+//! - `atmosphere`: Provides analytic atmospheric scattering around planets and in the sky.
 //! - `backend`: Handles the low-level graphics API interactions (e.g., Metal, Vulkan).
+//! - `bounds`: Provides bounding boxes and spheres for meshes and scene nodes.
+//! - `canvas`: Provides the 2D overlay drawn over the scene for HUDs, crosshairs and plots.
 //! - `camera`: Provides a camera system for 3D scene navigation and projection.
+//! - `colormap`: Provides scientific colormaps for data-driven vertex coloring.
 //! - `common`: Contains common data structures and types used throughout the renderer.
-//! - `render_core`: Implements the core rendering logic and system management.
+//! - `config`: Defines the options a renderer is created with, such as anti-aliasing and transparency.
+//! - `config_file`: Loads renderer options from a settings file such as `engine.toml`.
+//! - `constraints`: Declares look-at, billboard, copy and distance constraints on scene nodes.
+//! - `csg`: Combines closed meshes with union, subtraction and intersection.
+//! - `debug_view`: Provides shader debug views and per-vertex normal lines.
+//! - `display_link`: Calls back once per display refresh, with the frame's timestamp and refresh rate, and paces frames on variable refresh rate displays.
+//! - `dynamic_mesh`: Draws triangle geometry whose vertices move every frame, such as simulated cloth.
+//! - `editor`: Provides an optional mode for selecting and editing scene graph nodes.
+//! - `error`: Defines the renderer error type and its per-subsystem errors.
+//! - `export`: Writes meshes as OBJ and scenes as glTF for use in other tools.
+//! - `fluid_view`: Draws the particles of a simulated fluid as instanced spheres colored by density.
+//! - `font`: Provides the built-in bitmap font the canvas draws text with.
+//! - `frame_arena`: Provides a bump allocator for transient per-frame data.
+//! - `import_settings`: Scales, reorients and post-processes imported meshes by per-asset settings.
+//! - `input`: Binds the renderer's actions, such as camera movement and debug toggles, to keys.
+//! - `gpu_culling`: Culls instanced draws against the frustum and last frame's depth on the GPU.
+//! - `labels`: Provides world-space text labels with occlusion fade, distance scaling and leader lines.
+//! - `lens_flare`: Provides lens flares and sun glare, occluded by the scene.
+//! - `light_gizmos`: Draws editor icons, arrows, radii, cones and shadow frusta for lights.
+//! - `lights`: Manages point and spot lights and their per-light shadow settings.
+//! - `material_manager`: Stores materials and tracks changes for the backend's material table.
+//! - `mesh_optimizer`: Welds, re-indexes, reorders and quantizes mesh geometry as meshes are added.
+//! - `memory_report`: Summarizes GPU memory usage against the device budget.
+//! - `node_data`: Holds the typed values applications attach to scene nodes.
+//! - `minimap`: Draws a top-down view of the scene into a screen corner, marking the main camera's view.
+//! - `palette`: Provides color palettes for coloring sets of objects.
+//! - `panorama`: Captures cube maps and 360° equirectangular panoramas of the scene.
+//! - `particle_file`: Loads particle effects from RON files and watches them for changes.
+//! - `particles`: Simulates CPU particle emitters and draws them as camera-facing quads.
+//! - `plots`: Draws line graphs of named time series, such as frame times or physics energy, on the canvas.
+//! - `ply`: Imports PLY meshes and point clouds, coloring them by scalar vertex properties.
+//! - `profiler`: Times named scopes into a per-frame tree with rolling averages and worst cases.
+//! - `ray_tracing`: Provides optional ray-traced shadows on devices that support them.
+//! - `raycast`: Casts rays against mesh triangles, optionally through a per-mesh BVH.
+//! - `recording`: Records presented frames to a PNG sequence or video.
+//! - `reflection`: Draws planar reflections of the scene, such as water, with an oblique mirrored camera.
+//! - `render_core`: Implements the core rendering logic.
 //! - `render_queue`: Handles the queuing and processing of draw commands.
+//! - `render_scale`: Scales the resolution the scene is rendered at, optionally to hold a frame time.
+//! - `render_state`: Describes per-draw depth, culling, bias, stencil and blend state, and outlines.
+//! - `replay`: Records and plays back input and frame timing for deterministic runs.
+//! - `scene_diff`: Computes, applies and merges patches between scene documents for incremental saves.
+//! - `scene_file`: Saves and loads snapshots of the scene graph's hierarchy as text.
+//! - `scene_graph`: Stores the transform hierarchy as flat, depth-sorted arrays.
+//! - `selection`: Tracks selected and hovered nodes picked on screen, outlines them and reports changes.
+//! - `shadow_map`: Provides cascaded shadow maps for directional lights such as the sun.
 //! - `shape_builders`: Offers utilities for creating various 3D shapes programmatically.
+//! - `sky`: Provides the procedural day/night sky and the sunlight it drives.
+//! - `stl`: Imports binary and ASCII STL meshes.
+//! - `streaming`: Loads and unloads chunks of the world around the camera on a background thread.
+//! - `system`: Runs a renderer in a winit window and event loop.
+//! - `texture_upload`: Describes texture pixels uploaded in the background, optionally produced by a loader.
+//! - `time`: Provides the pausable, scalable frame clock.
+//! - `touch`: Turns touch screen drags and pinches into the camera input of the mouse.
+//! - `trail`: Records the recent positions of nodes and bodies and draws them as fading lines or ribbons.
+//! - `upload_scheduler`: Spreads mesh and texture uploads over frames within a per-frame byte budget.
+//! - `usd`: Imports USDA layers and USDZ packages as node hierarchies with meshes.
+//! - `validation`: Checks draws for malformed geometry, transforms and handles.
+//! - `vertex_layout`: Describes the attributes and packing of mesh vertices.
+//! - `viewport`: Provides split-screen views drawing the scene from extra cameras into rectangles of the frame.
+//!
+//! `input`, `replay`, `system` and `touch` depend on winit and are only built with the
+//! `windowing` feature, and `usd` with the `usd` feature.
 //!
 //! This module abstracts away much of the complexity of 3D rendering, providing a
 //! high-level interface for creating and managing 3D scenes while maintaining
 //! flexibility for advanced usage.
 
+mod ambient;
+mod animation;
+mod asset_database;
+mod atmosphere;
 mod backend;
+mod bounds;
 mod camera;
+mod canvas;
+mod colormap;
 mod common;
+mod config;
+mod config_file;
+mod constraints;
+mod csg;
+mod debug_view;
+mod display_link;
+mod dynamic_mesh;
+mod editor;
+mod error;
+mod export;
+mod fluid_view;
+mod font;
+mod frame_arena;
+mod gpu_culling;
+mod import_settings;
+#[cfg(feature = "windowing")]
+mod input;
+mod labels;
+mod lens_flare;
+mod light_gizmos;
+mod lights;
+mod material_manager;
+mod memory_report;
 mod mesh;
+mod mesh_optimizer;
+mod minimap;
+mod node_data;
+mod palette;
+mod panorama;
+mod particle_file;
+mod particles;
+mod plots;
+mod ply;
+mod profiler;
+mod ray_tracing;
+mod raycast;
+mod recording;
+mod reflection;
 mod render_core;
 mod render_queue;
+mod render_scale;
+mod render_state;
+#[cfg(feature = "windowing")]
+mod replay;
+mod scene_diff;
+mod scene_file;
+mod scene_graph;
+mod selection;
+mod shadow_map;
 pub mod shape_builders;
+mod sky;
+mod stl;
+mod streaming;
+#[cfg(feature = "windowing")]
+mod system;
+mod texture_upload;
+mod time;
+#[cfg(feature = "windowing")]
+mod touch;
+mod trail;
+mod upload_scheduler;
+#[cfg(feature = "usd")]
+mod usd;
+mod validation;
+mod vertex_layout;
+mod viewport;
 
-pub use self::common::{Color, RendererError};
+pub use self::common::Color;
+pub use self::common::{CaptureDestination, LayerMask, PrimitiveType};
+pub use ambient::AmbientLight;
+pub use animation::{
+    AnimationClip, AnimationEvent, AnimationState, AnimationStateMachine, Condition, Keyframe,
+    StateId, Transition,
+};
+pub use asset_database::{AssetDatabase, AssetGuid, AssetKind, AssetRecord};
+pub use atmosphere::Atmosphere;
+pub use backend::{
+    metal::MetalBackend,
+    null::{BackendCall, NullBackend},
+    GraphicsBackend,
+};
+pub use bounds::{Aabb, BoundingSphere};
 pub use camera::Camera;
-pub use render_core::RendererSystem;
-pub use render_queue::{DrawCommandBuilder, InstanceData};
+pub use canvas::{Anchor, Canvas, CanvasPoint, CanvasSize, Occlusion, Units};
+pub use colormap::Colormap;
+pub use config::{AntiAliasing, AssetPaths, RendererConfig, Transparency, WindowConfig};
+pub use constraints::{Constraint, ConstraintTarget};
+pub use csg::Csg;
+pub use debug_view::DebugView;
+pub use display_link::{max_refresh_rate, DisplayFrame, DisplayLink, FrameRateRange};
+pub use dynamic_mesh::{DynamicMesh, DynamicMeshId};
+pub use error::RendererError;
+pub use error::{
+    AssetDatabaseError, AssetError, BackendError, ColorError, ConfigError, CsgError, ExportError,
+    ImportError, ImportSettingsError, ParticleError, PipelineError, RecordingError, ReplayError,
+    SceneError, SceneFileError,
+};
+pub use fluid_view::{FluidView, FluidViewId};
+pub use frame_arena::{ArenaSlice, FrameArena};
+pub use gpu_culling::GpuCulling;
+pub use import_settings::{ImportSettings, UpAxis, TANGENT_SEMANTIC};
+#[cfg(feature = "windowing")]
+pub use input::{Action, InputBindings};
+pub use labels::{Label, LabelId, LabelStyle, LabelTarget};
+pub use lens_flare::{FlareElement, FlareLight, FlareShape, LensFlare};
+pub use light_gizmos::{LightGizmo, LightGizmos, LightShape};
+pub use lights::{Light, LightId, LightManager, LightShadow, PointLight, SpotLight};
+pub use material_manager::{Material, MaterialId, SpecializedParameters};
+pub use memory_report::GpuMemoryReport;
+pub use mesh::{Mesh, Socket};
+pub use mesh_optimizer::MeshOptimization;
+pub use minimap::Minimap;
+pub use node_data::{NodeAddedCallback, NodeData, NodeRemovedCallback};
+pub use palette::Palette;
+pub use panorama::{CubeFace, CubeMap};
+pub use particle_file::ParticleFile;
+pub use particles::{
+    EmitterShape, EmitterSource, Interpolate, ParticleCurve, ParticleEffect, ParticleEmitter,
+    ParticleEmitterId,
+};
+pub use plots::{Plot, PlotStyle, Plots};
+pub use ply::{import_ply, PlyModel};
+pub use profiler::{ProfileScope, Profiler, ScopeTiming};
+pub use ray_tracing::RayTracedShadows;
+pub use raycast::{Bvh, Ray, RayHit};
+pub use recording::FrameImage;
+pub use reflection::{PlanarReflection, ReflectionId};
+pub use render_core::{ErrorHandler, Renderer};
+pub use render_queue::{DrawCommandBuilder, InstanceData, RenderQueue};
+pub use render_scale::{DynamicResolution, RenderScale};
+pub use render_state::{
+    BlendMode, CompareFunction, CullMode, DepthBias, Outline, RenderState, StencilOp, StencilState,
+};
+pub use scene_diff::{SceneChange, ScenePatch};
+pub use scene_file::{SceneDocument, SceneNode};
+pub use scene_graph::{NodeId, SceneGraph, SceneStats};
+pub use selection::{Selection, SelectionEvent, SelectionMode};
+pub use shadow_map::CascadedShadows;
+pub use sky::{Sky, SunLight};
+pub use stl::{import_stl, parse_stl};
+pub use streaming::{ChunkCoord, StreamEvent, WorldStreamer};
+#[cfg(feature = "windowing")]
+pub use system::{RendererEvent, RendererSystem, RendererSystemBuilder, WindowChange};
+pub use texture_upload::{TextureData, TextureUpload};
+pub use time::Time;
+#[cfg(feature = "windowing")]
+pub use touch::TouchGestures;
+pub use trail::{Trail, TrailId, TrailShape, TrailSource};
+pub use upload_scheduler::DEFAULT_UPLOAD_BUDGET;
+#[cfg(feature = "usd")]
+pub use usd::{UsdPrim, UsdScene};
+pub use vertex_layout::{VertexFormat, VertexLayout, VertexSemantic};
+pub use viewport::{View, ViewId, Viewport};
@@ -0,0 +1,163 @@
+//! Node data module for the renderer.
+//!
+//! This module provides `NodeData`, a map holding at most one value of each
+//! type, which the `SceneGraph` attaches to every node. Gameplay or
+//! visualization state, such as a body handle or a label, lives alongside the
+//! node and is removed with it, rather than in maps keyed by `NodeId` that
+//! the application keeps in sync by hand.
+//!
+//! The graph's lifecycle callbacks are called as nodes are added and removed;
+//! the removal callback receives the node's data to tear the state down.
+//!
+//! Values inserted with `insert_cloneable` are copied when their node is
+//! duplicated; other values, such as handles owning outside state, stay with
+//! the original node.
+
+use super::scene_graph::NodeId;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+};
+
+/// Called with each node added to a `SceneGraph`.
+pub type NodeAddedCallback = dyn FnMut(NodeId);
+
+/// Called with each node removed from a `SceneGraph` and the data it held.
+pub type NodeRemovedCallback = dyn FnMut(NodeId, NodeData);
+
+/// Copies a value attached with `NodeData::insert_cloneable`.
+type CloneFn = fn(&dyn Any) -> Box<dyn Any>;
+
+/// The values attached to a scene node, at most one of each type.
+#[derive(Default)]
+pub struct NodeData {
+    values: HashMap<TypeId, Box<dyn Any>>,
+    // Clone functions by type, for the values copied with their node
+    cloners: HashMap<TypeId, CloneFn>,
+}
+
+impl NodeData {
+    /// Attaches a value, replacing the value of the same type.
+    ///
+    /// # Returns
+    ///
+    /// The replaced value, if the node held one.
+    pub fn insert<T: Any>(&mut self, value: T) -> Option<T> {
+        self.cloners.remove(&TypeId::of::<T>());
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    /// Attaches a value that is copied when the node is duplicated,
+    /// replacing the value of the same type.
+    ///
+    /// # Returns
+    ///
+    /// The replaced value, if the node held one.
+    pub fn insert_cloneable<T: Any + Clone>(&mut self, value: T) -> Option<T> {
+        let replaced = self.insert(value);
+        self.cloners.insert(TypeId::of::<T>(), clone_value::<T>);
+        replaced
+    }
+
+    /// Returns the value of a type.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    /// Returns the value of a type mutably.
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+
+    /// Detaches and returns the value of a type.
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.cloners.remove(&TypeId::of::<T>());
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Returns `true` if a value of the type is attached.
+    pub fn contains<T: Any>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Returns the number of attached values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if no values are attached.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns a copy of the values attached with `insert_cloneable`,
+    /// leaving out the others.
+    pub fn duplicate(&self) -> NodeData {
+        let mut copy = NodeData::default();
+        for (&type_id, &clone) in &self.cloners {
+            if let Some(value) = self.values.get(&type_id) {
+                copy.values.insert(type_id, clone(value.as_ref()));
+                copy.cloners.insert(type_id, clone);
+            }
+        }
+        copy
+    }
+}
+
+fn clone_value<T: Any + Clone>(value: &dyn Any) -> Box<dyn Any> {
+    let value = value
+        .downcast_ref::<T>()
+        .expect("clone functions are stored under their value's type");
+    Box::new(value.clone())
+}
+
+impl fmt::Debug for NodeData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NodeData({} values)", self.values.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NodeData;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[test]
+    fn test_node_data_types() {
+        let mut data = NodeData::default();
+        assert_eq!(data.insert(Health(3)), None);
+        assert_eq!(data.insert("player"), None);
+        assert_eq!(data.insert(Health(5)), Some(Health(3)));
+        assert_eq!(data.len(), 2);
+
+        data.get_mut::<Health>().unwrap().0 -= 1;
+        assert_eq!(data.get::<Health>(), Some(&Health(4)));
+        assert_eq!(data.get::<&str>(), Some(&"player"));
+        assert_eq!(data.remove::<Health>(), Some(Health(4)));
+        assert!(!data.contains::<Health>() && data.contains::<&str>());
+    }
+
+    #[test]
+    fn test_node_data_duplicate() {
+        let mut data = NodeData::default();
+        data.insert_cloneable("ship");
+        data.insert(Health(3));
+        data.insert_cloneable(7_u32);
+        // Replacing a value with `insert` stops it from being copied
+        data.insert(8_u32);
+
+        let copy = data.duplicate();
+        assert_eq!(copy.len(), 1);
+        assert_eq!(copy.get::<&str>(), Some(&"ship"));
+        assert_eq!(copy.duplicate().get::<&str>(), Some(&"ship"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Palette module for the renderer.
+//!
+//! This module provides `Palette`, an ordered list of colors for coloring
+//! sets of objects, e.g. one color per body, team or data series. Indexing
+//! wraps around, so any number of objects can be colored from a palette, and
+//! sampling interpolates between neighbouring colors in linear space.
+
+use super::{error::ColorError, Color, Colormap};
+
+/// The Tableau 10 categorical palette, as sRGB hex codes.
+const CATEGORICAL: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+
+/// An ordered, non-empty list of colors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<Color>,
+}
+
+impl Palette {
+    /// Creates a new `Palette` from its colors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `colors` is empty.
+    pub fn new(colors: Vec<Color>) -> Self {
+        assert!(!colors.is_empty(), "A palette needs at least one color");
+        Self { colors }
+    }
+
+    /// Creates a new `Palette` from sRGB hex codes, e.g. `["#ff8800", "#0088ff"]`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the palette or the `ColorError` of the first malformed code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hexes` is empty.
+    pub fn from_hex(hexes: &[&str]) -> Result<Self, ColorError> {
+        let colors = hexes
+            .iter()
+            .map(|hex| Color::from_hex(hex))
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(colors))
+    }
+
+    /// Returns ten distinct colors for categorical data (Tableau 10).
+    pub fn categorical() -> Self {
+        Self::from_hex(&CATEGORICAL).unwrap()
+    }
+
+    /// Creates a palette of fully opaque colors with evenly spaced hues, starting at red.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of colors, at least one.
+    /// * `saturation` - The HSV saturation of every color in [0, 1].
+    /// * `value` - The HSV value of every color in [0, 1].
+    pub fn hues(count: usize, saturation: f32, value: f32) -> Self {
+        let count = count.max(1);
+        Self::new(
+            (0..count)
+                .map(|index| {
+                    let hue = index as f32 * 360.0 / count as f32;
+                    Color::from_hsv(hue, saturation, value, 1.0)
+                })
+                .collect(),
+        )
+    }
+
+    /// Creates a palette blending from one color to another in linear space.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The first color.
+    /// * `to` - The last color.
+    /// * `count` - The number of colors, at least two.
+    pub fn gradient(from: Color, to: Color, count: usize) -> Self {
+        let count = count.max(2);
+        Self::new(
+            (0..count)
+                .map(|index| from.lerp(to, index as f32 / (count - 1) as f32))
+                .collect(),
+        )
+    }
+
+    /// Creates a palette of evenly spaced samples of a colormap.
+    ///
+    /// # Arguments
+    ///
+    /// * `colormap` - The colormap to sample.
+    /// * `count` - The number of colors, at least two.
+    pub fn from_colormap(colormap: Colormap, count: usize) -> Self {
+        let count = count.max(2);
+        Self::new(
+            (0..count)
+                .map(|index| colormap.sample(index as f32 / (count - 1) as f32))
+                .collect(),
+        )
+    }
+
+    /// Returns the colors of the palette.
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Returns the color at `index`, wrapping around past the last color.
+    pub fn color(&self, index: usize) -> Color {
+        self.colors[index % self.colors.len()]
+    }
+
+    /// Samples the palette at a normalized position, interpolating in linear space.
+    ///
+    /// # Arguments
+    ///
+    /// * `t` - The position in the palette, clamped to `[0, 1]`.
+    pub fn sample(&self, t: f32) -> Color {
+        let last = self.colors.len() - 1;
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let scaled = t * last as f32;
+        let index = (scaled.floor() as usize).min(last.saturating_sub(1));
+        let upper = (index + 1).min(last);
+        self.colors[index].lerp(self.colors[upper], scaled - index as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Palette;
+    use crate::renderer::{Color, Colormap};
+
+    fn color_approx_eq(a: Color, b: Color) -> bool {
+        let a: [f32; 4] = a.into();
+        let b: [f32; 4] = b.into();
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5)
+    }
+
+    #[test]
+    fn test_palette_wraps_and_samples() {
+        let palette = Palette::new(vec![Color::BLACK, Color::WHITE]);
+        assert_eq!(palette.color(0), Color::BLACK);
+        assert_eq!(palette.color(3), Color::WHITE);
+
+        assert!(color_approx_eq(
+            palette.sample(0.25),
+            Color::new(0.25, 0.25, 0.25, 1.0)
+        ));
+        assert_eq!(palette.sample(-1.0), Color::BLACK);
+        assert_eq!(palette.sample(2.0), Color::WHITE);
+
+        let single = Palette::new(vec![Color::RED]);
+        assert_eq!(single.sample(0.7), Color::RED);
+    }
+
+    #[test]
+    fn test_palette_builders() {
+        assert_eq!(Palette::categorical().colors().len(), 10);
+        assert!(Palette::from_hex(&["#ff8800", "nope"]).is_err());
+
+        let hues = Palette::hues(3, 1.0, 1.0);
+        assert!(color_approx_eq(hues.color(0), Color::RED));
+        assert!(color_approx_eq(hues.color(1), Color::GREEN));
+        assert!(color_approx_eq(hues.color(2), Color::BLUE));
+
+        let gradient = Palette::gradient(Color::RED, Color::BLUE, 5);
+        assert_eq!(gradient.colors().len(), 5);
+        assert!(color_approx_eq(
+            gradient.color(2),
+            Color::new(0.5, 0.0, 0.5, 1.0)
+        ));
+
+        let viridis = Palette::from_colormap(Colormap::Viridis, 3);
+        assert_eq!(viridis.color(0), Colormap::Viridis.sample(0.0));
+        assert_eq!(viridis.color(2), Colormap::Viridis.sample(1.0));
+    }
+}
@@ -0,0 +1,240 @@
+//! Panorama module for the renderer.
+//!
+//! This module provides `CubeMap`, the six faces of the scene seen from a
+//! point, as captured by `Renderer::capture_panorama`, and its conversion to
+//! an equirectangular 360° image. Each face is rendered with a square 90°
+//! camera looking along one axis, so the faces meet without gaps. Cube maps
+//! can be used as skyboxes, and equirectangular images shared with panorama
+//! viewers.
+//!
+//! Faces are images as seen from the center, with the side faces upright
+//! (+Y up), the +Y face's top towards +Z and the -Y face's top towards -Z.
+
+use super::{camera::Camera, recording::FrameImage};
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// A face of a cube map, named by the axis it looks along.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    /// Every face, in the order of `CubeMap::faces`.
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    /// Returns the direction the face looks along.
+    pub fn direction(self) -> Vec3 {
+        match self {
+            CubeFace::PositiveX => Vec3::X,
+            CubeFace::NegativeX => Vec3::NEG_X,
+            CubeFace::PositiveY => Vec3::Y,
+            CubeFace::NegativeY => Vec3::NEG_Y,
+            CubeFace::PositiveZ => Vec3::Z,
+            CubeFace::NegativeZ => Vec3::NEG_Z,
+        }
+    }
+
+    /// Returns the direction of the top of the face's image.
+    pub fn up(self) -> Vec3 {
+        match self {
+            CubeFace::PositiveY => Vec3::Z,
+            CubeFace::NegativeY => Vec3::NEG_Z,
+            _ => Vec3::Y,
+        }
+    }
+
+    /// Returns the direction of the right of the face's image.
+    pub fn right(self) -> Vec3 {
+        self.direction().cross(self.up())
+    }
+
+    /// Returns the face a direction points through.
+    pub fn from_direction(direction: Vec3) -> Self {
+        let abs = direction.abs();
+        if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x >= 0.0 {
+                CubeFace::PositiveX
+            } else {
+                CubeFace::NegativeX
+            }
+        } else if abs.y >= abs.z {
+            if direction.y >= 0.0 {
+                CubeFace::PositiveY
+            } else {
+                CubeFace::NegativeY
+            }
+        } else if direction.z >= 0.0 {
+            CubeFace::PositiveZ
+        } else {
+            CubeFace::NegativeZ
+        }
+    }
+
+    /// Creates the square 90° camera rendering the face.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The center of the cube map.
+    /// * `near` - The near clipping plane distance.
+    /// * `far` - The far clipping plane distance.
+    pub fn camera(self, position: Vec3, near: f32, far: f32) -> Camera {
+        let mut camera = Camera::new(position, 90.0, 1.0, near, far);
+        camera.look_to(self.direction(), self.up());
+        camera
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The six square faces of the scene seen from a point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CubeMap {
+    /// The width and height of every face in pixels.
+    pub resolution: u32,
+    /// The faces, in the order of `CubeFace::ALL`.
+    pub faces: [FrameImage; 6],
+}
+
+impl CubeMap {
+    /// Returns the image of a face.
+    pub fn face(&self, face: CubeFace) -> &FrameImage {
+        &self.faces[face.index()]
+    }
+
+    /// Converts the cube map to an equirectangular 360° image.
+    ///
+    /// Longitude runs from left to right across the image, with -Z at its
+    /// center, and latitude from +Y at the top to -Y at the bottom.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the image in pixels; its height is half the width.
+    pub fn to_equirectangular(&self, width: u32) -> FrameImage {
+        let width = width.max(2);
+        let height = width / 2;
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+            for x in 0..width {
+                let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+                let direction = Vec3::new(
+                    longitude.sin() * latitude.cos(),
+                    latitude.sin(),
+                    -longitude.cos() * latitude.cos(),
+                );
+                pixels.extend_from_slice(&self.sample(direction));
+            }
+        }
+        FrameImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Returns the bilinearly filtered BGRA color seen in a direction.
+    fn sample(&self, direction: Vec3) -> [u8; 4] {
+        let face = CubeFace::from_direction(direction);
+        let image = self.face(face);
+        let depth = direction.dot(face.direction());
+        let ndc = Vec2::new(
+            direction.dot(face.right()) / depth,
+            direction.dot(face.up()) / depth,
+        );
+        // Rows run from top to bottom
+        let size = Vec2::new(image.width as f32, image.height as f32);
+        let pixel = (Vec2::new(ndc.x, -ndc.y) * 0.5 + 0.5) * size - 0.5;
+        let max = (size - 1.0).max(Vec2::ZERO);
+        let min_corner = pixel.floor().clamp(Vec2::ZERO, max);
+        let max_corner = (min_corner + 1.0).min(max);
+        let weight = (pixel - min_corner).clamp(Vec2::ZERO, Vec2::ONE);
+
+        let texel = |x: f32, y: f32| -> [f32; 4] {
+            let offset = (y as usize * image.width as usize + x as usize) * 4;
+            std::array::from_fn(|channel| {
+                image
+                    .pixels
+                    .get(offset + channel)
+                    .map_or(0.0, |&value| value as f32)
+            })
+        };
+        let lerp = |a: [f32; 4], b: [f32; 4], t: f32| -> [f32; 4] {
+            std::array::from_fn(|channel| a[channel] + (b[channel] - a[channel]) * t)
+        };
+        let top = lerp(
+            texel(min_corner.x, min_corner.y),
+            texel(max_corner.x, min_corner.y),
+            weight.x,
+        );
+        let bottom = lerp(
+            texel(min_corner.x, max_corner.y),
+            texel(max_corner.x, max_corner.y),
+            weight.x,
+        );
+        lerp(top, bottom, weight.y).map(|value| value.round() as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CubeFace, CubeMap};
+    use crate::renderer::recording::FrameImage;
+    use glam::{Vec3, Vec4};
+
+    /// Returns a face image of one color, its index in the blue channel.
+    fn solid_face(face: CubeFace, resolution: u32) -> FrameImage {
+        FrameImage {
+            width: resolution,
+            height: resolution,
+            pixels: [face as u8 * 40, 0, 0, 255].repeat((resolution * resolution) as usize),
+        }
+    }
+
+    #[test]
+    fn test_face_cameras() {
+        for face in CubeFace::ALL {
+            let camera = face.camera(Vec3::ZERO, 0.1, 10.0);
+            let view_projection = camera.get_projection_matrix() * camera.get_view_matrix();
+            // The face's direction projects to the center, and its up to the top
+            let center = view_projection * (face.direction() * 2.0).extend(1.0);
+            assert!((center.truncate() / center.w).truncate().length() < 1e-5);
+            let top = view_projection * (face.direction() + face.up()).extend(1.0);
+            assert!(((top / top.w) - Vec4::new(0.0, 1.0, top.z / top.w, 1.0)).length() < 1e-5);
+            assert_eq!(CubeFace::from_direction(face.direction()), face);
+        }
+    }
+
+    #[test]
+    fn test_equirectangular() {
+        let cube_map = CubeMap {
+            resolution: 4,
+            faces: CubeFace::ALL.map(|face| solid_face(face, 4)),
+        };
+        let image = cube_map.to_equirectangular(16);
+        assert_eq!((image.width, image.height), (16, 8));
+        let pixel = |x: usize, y: usize| image.pixels[(y * 16 + x) * 4];
+
+        // The center looks along -Z, the top row up and the bottom row down
+        assert_eq!(pixel(8, 4), CubeFace::NegativeZ as u8 * 40);
+        assert_eq!(pixel(0, 4), CubeFace::PositiveZ as u8 * 40);
+        assert_eq!(pixel(12, 4), CubeFace::PositiveX as u8 * 40);
+        assert_eq!(pixel(3, 0), CubeFace::PositiveY as u8 * 40);
+        assert_eq!(pixel(3, 7), CubeFace::NegativeY as u8 * 40);
+    }
+}
@@ -0,0 +1,396 @@
+//! Particle file module for the renderer.
+//!
+//! This module loads a `ParticleEffect` from a RON file such as
+//! `assets/effects/fire.ron`, so effects can be authored and tweaked without
+//! recompiling. The file holds one `ParticleEffect` struct, read through
+//! its serde `Deserialize` implementation with the `ron` crate; fields left
+//! out keep their defaults, and unknown fields are logged and skipped.
+//!
+//! ```ron
+//! ParticleEffect(
+//!     shape: Sphere(radius: 0.2),          // Point, Sphere(radius) or Box(half_extents)
+//!     rate: 40.0,                          // particles per second
+//!     burst: 10,                           // particles emitted at once on start
+//!     max_particles: 500,
+//!     lifetime: (0.8, 1.5),                // seconds, from shortest to longest
+//!     speed: (1.0, 2.0),
+//!     direction: (0.0, 1.0, 0.0),
+//!     spread: 15.0,                        // degrees from the direction
+//!     gravity: (0.0, 0.5, 0.0),
+//!     drag: 0.3,
+//!     bounce: 0.5,                         // speed kept when bouncing off colliders
+//!     kill_on_collision: false,
+//!     size: [(0.0, 0.1), (1.0, 0.5)],      // (lifetime fraction, width)
+//!     color: [(0.0, "#FFCC55"), (1.0, "#FF220000")],
+//!     texture: Some("flame"),              // registered with Renderer::set_particle_texture
+//!     blend: Additive,                     // Alpha or Additive
+//! )
+//! ```
+//!
+//! Colors are sRGB hex strings, as accepted by `Color::from_hex`, or tuples
+//! of three or four linear components. Sizes and colors are either a list
+//! of keys or one value held over the lifetime.
+//!
+//! `ParticleFile` watches a file's modification time, and `ParticleFiles`
+//! tracks the file of each emitter, so the renderer swaps in effects when
+//! they are saved.
+
+use super::{
+    error::ParticleError,
+    particles::{Interpolate, ParticleCurve, ParticleEffect, ParticleEmitterId, ParticleEmitters},
+    Color,
+};
+use glam::Vec3;
+use log::{info, warn};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the files of emitters are checked for changes.
+pub const PARTICLE_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Reads a number of at least 0.
+pub fn non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match f32::deserialize(deserializer)? {
+        number if number >= 0.0 => Ok(number),
+        number => Err(D::Error::custom(format!(
+            "expected a number of at least 0, found {number}"
+        ))),
+    }
+}
+
+/// Reads an angle in degrees, up to 180, as radians.
+pub fn degrees<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    Ok(non_negative(deserializer)?.min(180.0).to_radians())
+}
+
+/// Reads a range as a tuple of its bounds, which are at least 0.
+pub fn range<'de, D: Deserializer<'de>>(deserializer: D) -> Result<(f32, f32), D::Error> {
+    match <(f32, f32)>::deserialize(deserializer)? {
+        (min, _) if min < 0.0 => Err(D::Error::custom("expected bounds of at least 0")),
+        (min, max) if min <= max => Ok((min, max)),
+        _ => Err(D::Error::custom("expected the lower bound first")),
+    }
+}
+
+/// Reads a vector from a tuple of its components.
+pub fn vector<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3, D::Error> {
+    let (x, y, z) = <(f32, f32, f32)>::deserialize(deserializer)?;
+    Ok(Vec3::new(x, y, z))
+}
+
+/// Reads the half extents of a box, ignoring the signs of their components.
+pub fn half_extents<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3, D::Error> {
+    Ok(vector(deserializer)?.abs())
+}
+
+/// A curve as written in a particle file.
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "a value or a list of (lifetime fraction, value) keys"
+)]
+enum CurveSetting<T> {
+    Constant(T),
+    Keys(Vec<(f32, T)>),
+}
+
+impl<T> CurveSetting<T> {
+    /// Converts the values of the curve, then builds it.
+    fn build<U: Interpolate, E: serde::de::Error>(
+        self,
+        value: impl Fn(T) -> Result<U, E>,
+    ) -> Result<ParticleCurve<U>, E> {
+        let keys = match self {
+            CurveSetting::Constant(constant) => {
+                return Ok(ParticleCurve::constant(value(constant)?))
+            }
+            CurveSetting::Keys(keys) => keys,
+        };
+        let keys = keys
+            .into_iter()
+            .map(|(time, key)| Ok((time, value(key)?)))
+            .collect::<Result<Vec<_>, E>>()?;
+        ParticleCurve::new(keys).ok_or_else(|| E::custom("expected at least one key"))
+    }
+}
+
+/// Reads a curve of sizes, which are at least 0.
+pub fn size_curve<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ParticleCurve<f32>, D::Error> {
+    CurveSetting::<f32>::deserialize(deserializer)?.build(|size| match size {
+        size if size >= 0.0 => Ok(size),
+        size => Err(D::Error::custom(format!(
+            "expected a size of at least 0, found {size}"
+        ))),
+    })
+}
+
+/// A color as written in a particle file.
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a hex string or a tuple of 3 or 4 components")]
+enum ColorSetting {
+    Hex(String),
+    Rgb(f32, f32, f32),
+    Rgba(f32, f32, f32, f32),
+}
+
+/// Reads a curve of colors.
+pub fn color_curve<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ParticleCurve<Color>, D::Error> {
+    CurveSetting::<ColorSetting>::deserialize(deserializer)?.build(|color| match color {
+        ColorSetting::Hex(hex) => Color::from_hex(&hex).map_err(D::Error::custom),
+        ColorSetting::Rgb(r, g, b) => Ok(Color::new(r, g, b, 1.0)),
+        ColorSetting::Rgba(r, g, b, a) => Ok(Color::new(r, g, b, a)),
+    })
+}
+
+impl ParticleEffect {
+    /// Loads an effect from a particle file, see the module documentation.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The particle file, e.g. `assets/effects/fire.ron`.
+    ///
+    /// # Returns
+    ///
+    /// The effect, or an error if the file cannot be read or is malformed.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ParticleError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| ParticleError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let effect = text.parse()?;
+        info!("Loaded particle effect {}", path.display());
+        Ok(effect)
+    }
+}
+
+impl FromStr for ParticleEffect {
+    type Err = ParticleError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parse_error = |error: ron::error::SpannedError| ParticleError::Parse {
+            line: error.span.start.line,
+            message: error.code.to_string(),
+        };
+        let mut deserializer = ron::Deserializer::from_str(text).map_err(parse_error)?;
+        let effect = serde_ignored::deserialize(&mut deserializer, |field| {
+            warn!("Skipping unknown particle effect field {field}")
+        })
+        .and_then(|effect| deserializer.end().map(|()| effect))
+        .map_err(|error| parse_error(deserializer.span_error(error)))?;
+        Ok(effect)
+    }
+}
+
+/// A particle file and the modification time it was last loaded at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticleFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ParticleFile {
+    /// Loads the effect of a particle file and starts watching it.
+    ///
+    /// # Returns
+    ///
+    /// The watched file and its effect, or an error if it cannot be loaded.
+    pub fn load(path: PathBuf) -> Result<(Self, ParticleEffect), ParticleError> {
+        let modified = modified(&path);
+        let effect = ParticleEffect::from_file(&path)?;
+        Ok((Self { path, modified }, effect))
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reloads the effect if the file was modified since it was last loaded.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the file is unchanged, or the result of loading it. A
+    /// failed load is not retried until the file changes again.
+    pub fn reload(&mut self) -> Option<Result<ParticleEffect, ParticleError>> {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(ParticleEffect::from_file(&self.path))
+    }
+}
+
+/// Returns the modification time of a file, or `None` if it cannot be read.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// The particle files emitters were loaded from, checked for changes as frames render.
+#[derive(Debug)]
+pub struct ParticleFiles {
+    files: HashMap<ParticleEmitterId, ParticleFile>,
+    /// When the files were last checked for changes.
+    pub(crate) checked: Instant,
+}
+
+impl Default for ParticleFiles {
+    fn default() -> Self {
+        Self {
+            files: HashMap::new(),
+            checked: Instant::now(),
+        }
+    }
+}
+
+impl ParticleFiles {
+    /// Watches the file an emitter's effect was loaded from.
+    pub fn watch(&mut self, id: ParticleEmitterId, file: ParticleFile) {
+        self.files.insert(id, file);
+    }
+
+    /// Stops watching the file of an emitter.
+    pub fn unwatch(&mut self, id: ParticleEmitterId) {
+        self.files.remove(&id);
+    }
+
+    /// Swaps the effects of emitters whose files changed, at most once per
+    /// `PARTICLE_RELOAD_INTERVAL`, keeping the previous effect of a file
+    /// that fails to load.
+    ///
+    /// # Returns
+    ///
+    /// The errors of the files that failed to load.
+    pub fn reload(&mut self, emitters: &mut ParticleEmitters) -> Vec<ParticleError> {
+        let mut errors = Vec::new();
+        if self.checked.elapsed() < PARTICLE_RELOAD_INTERVAL {
+            return errors;
+        }
+        self.checked = Instant::now();
+        for (&id, file) in &mut self.files {
+            match file.reload() {
+                Some(Ok(effect)) => {
+                    if let Some(emitter) = emitters.get_mut(id) {
+                        emitter.set_effect(effect);
+                    }
+                    info!("Reloaded particle effect {}", file.path().display());
+                }
+                Some(Err(e)) => {
+                    warn!(
+                        "Keeping the previous particle effect of {}",
+                        file.path().display()
+                    );
+                    errors.push(e);
+                }
+                None => {}
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParticleFile;
+    use crate::renderer::{
+        error::ParticleError,
+        particles::{EmitterShape, ParticleEffect},
+        render_state::BlendMode,
+        Color,
+    };
+    use glam::Vec3;
+    use std::fs;
+
+    #[test]
+    fn test_particle_effect_parse() {
+        let effect: ParticleEffect = r##"
+            // Sparks
+            ParticleEffect(
+                shape: Box(half_extents: (1.0, 0.0, -2)),
+                rate: 25,
+                burst: 4,
+                lifetime: (0.5, 1.5),
+                direction: (0.0, 0.0, 1.0),
+                spread: 90,
+                size: 0.25,
+                color: [(1.0, (1.0, 0.0, 0.0, 0.0)), (0.0, "#FFFFFF")],
+                texture: Some("spark"),
+                blend: Additive,
+                kill_on_collision: true,
+                future_field: [1, 2],
+            )
+        "##
+        .parse()
+        .unwrap();
+        assert_eq!(
+            effect.shape,
+            EmitterShape::Box {
+                half_extents: Vec3::new(1.0, 0.0, 2.0)
+            }
+        );
+        assert_eq!((effect.rate, effect.burst), (25.0, 4));
+        assert_eq!(effect.lifetime, (0.5, 1.5));
+        assert!((effect.spread - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert_eq!(effect.size.sample(0.5), 0.25);
+        assert_eq!(
+            effect.color.sample(0.0),
+            Color::from_hex("#FFFFFF").unwrap()
+        );
+        assert_eq!(effect.color.sample(1.0), Color::RED.with_alpha(0.0));
+        assert_eq!(effect.texture.as_deref(), Some("spark"));
+        assert_eq!(effect.blend, BlendMode::Additive);
+        assert!(effect.kill_on_collision);
+        // Left out fields keep their defaults
+        assert_eq!(
+            effect.max_particles,
+            ParticleEffect::default().max_particles
+        );
+    }
+
+    #[test]
+    fn test_particle_effect_parse_errors() {
+        let line = |text: &str| match text.parse::<ParticleEffect>() {
+            Err(ParticleError::Parse { line, .. }) => line,
+            result => panic!("expected a parse error, got {result:?}"),
+        };
+        assert_eq!(line("ParticleEffect(\n  rate: \"fast\",\n)"), 2);
+        assert_eq!(line("(\n  lifetime: (2.0, 1.0)\n)"), 2);
+        assert_eq!(line("(\n  blend: Alpha\n  rate: 1.0\n)"), 3);
+        assert_eq!(line("(\n  texture: Some(\"smoke)\n)"), 2);
+        assert_eq!(line("Effect()"), 1);
+        assert_eq!(line("(rate: 1.0"), 1);
+    }
+
+    #[test]
+    fn test_particle_file_reload() {
+        let path = std::env::temp_dir().join(format!("particles-{}.ron", std::process::id()));
+        fs::write(&path, "(rate: 5.0)").unwrap();
+        let (mut file, effect) = ParticleFile::load(path.clone()).unwrap();
+        assert_eq!(effect.rate, 5.0);
+        assert!(file.reload().is_none());
+
+        // A changed modification time triggers a reload
+        file.modified = None;
+        fs::write(&path, "(rate: \"x\")").unwrap();
+        assert!(matches!(
+            file.reload(),
+            Some(Err(ParticleError::Parse { .. }))
+        ));
+        assert!(file.reload().is_none());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(file.reload(), Some(Err(ParticleError::Io { .. }))));
+    }
+}
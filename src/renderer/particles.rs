@@ -0,0 +1,829 @@
+//! Particles module for the renderer.
+//!
+//! This module provides CPU particle emitters for effects such as fire,
+//! smoke and sparks. A `ParticleEffect` describes an effect as data: the
+//! shape particles are emitted from, their rate, lifetime, speed and
+//! direction, the forces acting on them, their size and color over their
+//! lifetime, and their texture and blend mode. Effects are usually authored
+//! in files, see `particle_file`, so they can be tweaked while the
+//! application runs.
+//!
+//! A `ParticleEmitter` plays an effect at a scene node or a fixed position.
+//! Particles are drawn as camera-facing quads, one instanced draw per
+//! emitter, and their textures are referred to by the names registered with
+//! `Renderer::set_particle_texture`.
+//!
+//! Particles are simulated on the CPU, so effects run on every backend and
+//! can collide with colliders the application places, reporting each hit to
+//! a callback. Each attribute of an emitter's particles is stored in its own
+//! array of SIMD vectors, integrated in batches; with the `parallel`
+//! feature, the batches are spread over the rayon thread pool.
+
+use super::{
+    common::{LayerMask, TextureId},
+    material_manager::MaterialId,
+    particle_file,
+    render_queue::InstanceData,
+    render_state::BlendMode,
+    scene_graph::NodeId,
+    Color,
+};
+use glam::{Mat4, Quat, Vec3, Vec3A};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::{f32::consts::TAU, fmt};
+
+/// The particles integrated together, by one task with the `parallel` feature.
+const BATCH_SIZE: usize = 1024;
+
+/// A value that can be blended along a `ParticleCurve`.
+pub trait Interpolate: Copy {
+    /// Blends from this value to another, with `t` from 0 to 1.
+    fn interpolate(self, other: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Color {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+/// A value over a particle's lifetime, blended linearly between keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticleCurve<T> {
+    /// The keys as pairs of the lifetime fraction, from 0 to 1, and the value.
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Interpolate> ParticleCurve<T> {
+    /// Creates a curve holding the same value over the whole lifetime.
+    pub fn constant(value: T) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    /// Creates a curve through keys, which are sorted by their lifetime fraction.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - Pairs of the lifetime fraction, from 0 to 1, and the value.
+    ///
+    /// # Returns
+    ///
+    /// The curve, or `None` if there are no keys.
+    pub fn new(mut keys: Vec<(f32, T)>) -> Option<Self> {
+        if keys.is_empty() {
+            return None;
+        }
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Some(Self { keys })
+    }
+
+    /// Returns the keys, sorted by their lifetime fraction.
+    pub fn keys(&self) -> &[(f32, T)] {
+        &self.keys
+    }
+
+    /// Returns the value at a lifetime fraction, holding the first and last keys beyond them.
+    pub fn sample(&self, t: f32) -> T {
+        let next = self.keys.partition_point(|&(time, _)| time <= t);
+        match (self.keys.get(next.wrapping_sub(1)), self.keys.get(next)) {
+            (Some(&(from_time, from)), Some(&(to_time, to))) => {
+                let span = to_time - from_time;
+                let u = if span > 0.0 {
+                    (t - from_time) / span
+                } else {
+                    1.0
+                };
+                from.interpolate(to, u)
+            }
+            (Some(&(_, value)), None) | (None, Some(&(_, value))) => value,
+            (None, None) => unreachable!("Curves have at least one key"),
+        }
+    }
+}
+
+/// The volume new particles are placed in, around the emitter's origin.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum EmitterShape {
+    /// Every particle starts at the origin.
+    #[default]
+    Point,
+    /// Particles start anywhere within a sphere.
+    Sphere {
+        #[serde(deserialize_with = "particle_file::non_negative")]
+        radius: f32,
+    },
+    /// Particles start anywhere within a box.
+    Box {
+        #[serde(deserialize_with = "particle_file::half_extents")]
+        half_extents: Vec3,
+    },
+}
+
+/// The data describing a particle effect.
+///
+/// Effects deserialize from the layout of a particle file, see `particle_file`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ParticleEffect {
+    pub shape: EmitterShape,
+    /// The particles emitted per second.
+    #[serde(deserialize_with = "particle_file::non_negative")]
+    pub rate: f32,
+    /// The particles emitted at once when the emitter starts.
+    pub burst: u32,
+    /// The most particles alive at once.
+    pub max_particles: usize,
+    /// The shortest and longest lifetimes in seconds.
+    #[serde(deserialize_with = "particle_file::range")]
+    pub lifetime: (f32, f32),
+    /// The slowest and fastest initial speeds.
+    #[serde(deserialize_with = "particle_file::range")]
+    pub speed: (f32, f32),
+    /// The direction particles are emitted in, in the emitter's space.
+    #[serde(deserialize_with = "particle_file::vector")]
+    pub direction: Vec3,
+    /// The angle in radians particles deviate from `direction` by at most,
+    /// written in degrees in particle files.
+    #[serde(deserialize_with = "particle_file::degrees")]
+    pub spread: f32,
+    /// The acceleration applied to every particle, in world space.
+    #[serde(deserialize_with = "particle_file::vector")]
+    pub gravity: Vec3,
+    /// The fraction of its velocity a particle loses per second.
+    #[serde(deserialize_with = "particle_file::non_negative")]
+    pub drag: f32,
+    /// The fraction of its speed into a collider a particle bounces back with.
+    #[serde(deserialize_with = "particle_file::non_negative")]
+    pub bounce: f32,
+    /// Whether particles die when they hit a collider, rather than bounce.
+    pub kill_on_collision: bool,
+    /// The world-space width of particles over their lifetime.
+    #[serde(deserialize_with = "particle_file::size_curve")]
+    pub size: ParticleCurve<f32>,
+    /// The color of particles over their lifetime, multiplied with the texture.
+    #[serde(deserialize_with = "particle_file::color_curve")]
+    pub color: ParticleCurve<Color>,
+    /// The name of the texture, as registered with `Renderer::set_particle_texture`.
+    pub texture: Option<String>,
+    /// How particles are combined with the scene; opaque effects are drawn alpha-blended.
+    pub blend: BlendMode,
+}
+
+impl Default for ParticleEffect {
+    fn default() -> Self {
+        Self {
+            shape: EmitterShape::Point,
+            rate: 10.0,
+            burst: 0,
+            max_particles: 1000,
+            lifetime: (1.0, 1.0),
+            speed: (1.0, 1.0),
+            direction: Vec3::Y,
+            spread: 0.0,
+            gravity: Vec3::ZERO,
+            drag: 0.0,
+            bounce: 0.5,
+            kill_on_collision: false,
+            size: ParticleCurve::constant(0.1),
+            color: ParticleCurve::constant(Color::WHITE),
+            texture: None,
+            blend: BlendMode::Alpha,
+        }
+    }
+}
+
+/// Where an emitter's particles are emitted from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmitterSource {
+    /// The world transform of a node, followed as it moves.
+    Node(NodeId),
+    /// A fixed position in world space.
+    Position(Vec3),
+}
+
+/// Identifies a particle emitter added to the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ParticleEmitterId(pub usize);
+
+/// A surface particles collide with, in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParticleCollider {
+    /// The plane of points whose dot product with `normal` is `distance`.
+    /// Particles are kept on the side the normal faces.
+    Plane { normal: Vec3, distance: f32 },
+    /// A sphere particles are kept out of.
+    Sphere { center: Vec3, radius: f32 },
+}
+
+impl ParticleCollider {
+    /// Returns the closest point on the surface and its normal if a position
+    /// lies behind or inside the collider.
+    fn contact(&self, position: Vec3A) -> Option<(Vec3A, Vec3A)> {
+        match *self {
+            ParticleCollider::Plane { normal, distance } => {
+                let normal = Vec3A::from(normal).normalize_or_zero();
+                let depth = distance - position.dot(normal);
+                (depth > 0.0).then(|| (position + normal * depth, normal))
+            }
+            ParticleCollider::Sphere { center, radius } => {
+                let offset = position - Vec3A::from(center);
+                let distance_squared = offset.length_squared();
+                if distance_squared >= radius * radius {
+                    return None;
+                }
+                let normal = offset.try_normalize().unwrap_or(Vec3A::Y);
+                Some((Vec3A::from(center) + normal * radius, normal))
+            }
+        }
+    }
+}
+
+/// A particle hitting a collider.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticleCollision {
+    /// The index of the collider in the emitter's colliders.
+    pub collider: usize,
+    /// Where the particle hit, on the collider's surface.
+    pub position: Vec3,
+    /// The collider's surface normal at the hit.
+    pub normal: Vec3,
+    /// The velocity of the particle as it hit.
+    pub velocity: Vec3,
+}
+
+/// Called with each collision of an emitter's particles, after the update that moved them.
+pub type ParticleCollisionCallback = dyn FnMut(&ParticleCollision);
+
+/// The live particles of an emitter, one array per attribute.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Particles {
+    positions: Vec<Vec3A>,
+    velocities: Vec<Vec3A>,
+    ages: Vec<f32>,
+    lifetimes: Vec<f32>,
+}
+
+impl Particles {
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn push(&mut self, position: Vec3A, velocity: Vec3A, lifetime: f32) {
+        self.positions.push(position);
+        self.velocities.push(velocity);
+        self.ages.push(0.0);
+        self.lifetimes.push(lifetime);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.positions.truncate(len);
+        self.velocities.truncate(len);
+        self.ages.truncate(len);
+        self.lifetimes.truncate(len);
+    }
+
+    /// Removes the particles that outlived their lifetime, moving the last
+    /// particles into their places.
+    fn remove_expired(&mut self) {
+        let mut index = 0;
+        while index < self.len() {
+            if self.ages[index] < self.lifetimes[index] {
+                index += 1;
+                continue;
+            }
+            self.positions.swap_remove(index);
+            self.velocities.swap_remove(index);
+            self.ages.swap_remove(index);
+            self.lifetimes.swap_remove(index);
+        }
+    }
+}
+
+/// The settings shared by every batch of an update.
+struct Step<'a> {
+    delta_time: f32,
+    gravity: Vec3A,
+    damping: f32,
+    colliders: &'a [ParticleCollider],
+    bounce: f32,
+    kill_on_collision: bool,
+}
+
+impl Step<'_> {
+    /// Ages and moves a batch of particles, resolving their collisions.
+    ///
+    /// # Arguments
+    ///
+    /// * `positions`, `velocities`, `ages`, `lifetimes` - The batch's attributes, of equal length.
+    /// * `collisions` - The collisions to append to.
+    fn integrate(
+        &self,
+        positions: &mut [Vec3A],
+        velocities: &mut [Vec3A],
+        ages: &mut [f32],
+        lifetimes: &[f32],
+        collisions: &mut Vec<ParticleCollision>,
+    ) {
+        let acceleration = self.gravity * self.delta_time;
+        for (((position, velocity), age), &lifetime) in positions
+            .iter_mut()
+            .zip(velocities.iter_mut())
+            .zip(ages.iter_mut())
+            .zip(lifetimes)
+        {
+            *age += self.delta_time;
+            *velocity = (*velocity + acceleration) * self.damping;
+            *position += *velocity * self.delta_time;
+
+            for (index, collider) in self.colliders.iter().enumerate() {
+                let Some((surface, normal)) = collider.contact(*position) else {
+                    continue;
+                };
+                collisions.push(ParticleCollision {
+                    collider: index,
+                    position: surface.into(),
+                    normal: normal.into(),
+                    velocity: (*velocity).into(),
+                });
+                *position = surface;
+                let approach = velocity.dot(normal);
+                if approach < 0.0 {
+                    *velocity -= normal * approach * (1.0 + self.bounce);
+                }
+                if self.kill_on_collision {
+                    *age = lifetime;
+                }
+            }
+        }
+    }
+}
+
+/// A xorshift generator, so emitters play the same way every run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Random(u32);
+
+impl Random {
+    /// Returns a number in `0..1`.
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Returns a number between the bounds of a range.
+    fn range(&mut self, (min, max): (f32, f32)) -> f32 {
+        min + (max - min) * self.next()
+    }
+
+    /// Returns a point in a unit sphere.
+    fn in_unit_sphere(&mut self) -> Vec3 {
+        loop {
+            let point = Vec3::new(self.next(), self.next(), self.next()) * 2.0 - Vec3::ONE;
+            if point.length_squared() <= 1.0 {
+                return point;
+            }
+        }
+    }
+}
+
+/// Plays a `ParticleEffect` from a node or position.
+pub struct ParticleEmitter {
+    pub source: EmitterSource,
+    /// Whether new particles are emitted; live particles play out either way.
+    pub emitting: bool,
+    pub layers: LayerMask,
+    /// The surfaces particles collide with, in world space.
+    pub colliders: Vec<ParticleCollider>,
+    effect: ParticleEffect,
+    particles: Particles,
+    /// The fraction of a particle carried over between updates.
+    pending: f32,
+    burst_pending: bool,
+    random: Random,
+    on_collision: Option<Box<ParticleCollisionCallback>>,
+    /// The material particles are drawn with, resolved from the effect's texture.
+    material: Option<MaterialId>,
+    /// The texture the material was resolved from.
+    texture: Option<TextureId>,
+}
+
+impl ParticleEmitter {
+    /// Creates a new `ParticleEmitter`, emitting its effect's burst on its first update.
+    ///
+    /// # Arguments
+    ///
+    /// * `effect` - The effect played.
+    /// * `source` - Where particles are emitted from.
+    pub fn new(effect: ParticleEffect, source: EmitterSource) -> Self {
+        Self {
+            source,
+            emitting: true,
+            layers: LayerMask::DEFAULT,
+            colliders: Vec::new(),
+            effect,
+            particles: Particles::default(),
+            pending: 0.0,
+            burst_pending: true,
+            random: Random(0x9E37_79B9),
+            on_collision: None,
+            material: None,
+            texture: None,
+        }
+    }
+
+    /// Sets the layers the particles are drawn on.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Adds a surface particles collide with.
+    pub fn with_collider(mut self, collider: ParticleCollider) -> Self {
+        self.colliders.push(collider);
+        self
+    }
+
+    /// Sets the callback called with each collision of the emitter's
+    /// particles, e.g. to play a sound or spawn a decal where sparks land.
+    pub fn set_on_collision(&mut self, callback: impl FnMut(&ParticleCollision) + 'static) {
+        self.on_collision = Some(Box::new(callback));
+    }
+
+    /// Returns the effect played.
+    pub fn effect(&self) -> &ParticleEffect {
+        &self.effect
+    }
+
+    /// Replaces the effect, keeping live particles, e.g. when its file changes.
+    ///
+    /// Particles beyond the new effect's maximum are dropped, and the rest
+    /// take the new effect's forces, size and color.
+    pub fn set_effect(&mut self, effect: ParticleEffect) {
+        self.particles.truncate(effect.max_particles);
+        self.effect = effect;
+    }
+
+    /// Removes every particle and emits the burst again on the next update.
+    pub fn restart(&mut self) {
+        self.particles = Particles::default();
+        self.pending = 0.0;
+        self.burst_pending = true;
+    }
+
+    /// Returns the number of live particles.
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// Returns `true` if no particles are alive.
+    pub fn is_empty(&self) -> bool {
+        self.particles.len() == 0
+    }
+
+    /// Returns the material particles are drawn with, once the renderer resolved it.
+    pub(crate) fn material(&self) -> Option<MaterialId> {
+        self.material
+    }
+
+    /// Sets the material particles are drawn with and the texture it shows.
+    pub(crate) fn set_material(&mut self, material: MaterialId, texture: Option<TextureId>) {
+        self.material = Some(material);
+        self.texture = texture;
+    }
+
+    /// Returns the texture the material was resolved from.
+    pub(crate) fn texture(&self) -> Option<TextureId> {
+        self.texture
+    }
+
+    /// Ages, moves and expires particles, then emits new ones.
+    ///
+    /// Collisions are reported to the callback once every batch is integrated.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta_time` - The seconds of scaled time since the last update.
+    /// * `origin` - The world transform particles are emitted from, or
+    ///   `None` to emit none, e.g. while the source node is missing.
+    pub fn update(&mut self, delta_time: f32, origin: Option<Mat4>) {
+        let effect = &self.effect;
+        let step = Step {
+            delta_time,
+            gravity: effect.gravity.into(),
+            damping: (1.0 - effect.drag * delta_time).max(0.0),
+            colliders: &self.colliders,
+            bounce: effect.bounce,
+            kill_on_collision: effect.kill_on_collision,
+        };
+        let particles = &mut self.particles;
+
+        #[cfg(not(feature = "parallel"))]
+        let collisions = {
+            let mut collisions = Vec::new();
+            for (((positions, velocities), ages), lifetimes) in particles
+                .positions
+                .chunks_mut(BATCH_SIZE)
+                .zip(particles.velocities.chunks_mut(BATCH_SIZE))
+                .zip(particles.ages.chunks_mut(BATCH_SIZE))
+                .zip(particles.lifetimes.chunks(BATCH_SIZE))
+            {
+                step.integrate(positions, velocities, ages, lifetimes, &mut collisions);
+            }
+            collisions
+        };
+
+        #[cfg(feature = "parallel")]
+        let collisions: Vec<_> = particles
+            .positions
+            .par_chunks_mut(BATCH_SIZE)
+            .zip(particles.velocities.par_chunks_mut(BATCH_SIZE))
+            .zip(particles.ages.par_chunks_mut(BATCH_SIZE))
+            .zip(particles.lifetimes.par_chunks(BATCH_SIZE))
+            .flat_map_iter(|(((positions, velocities), ages), lifetimes)| {
+                let mut collisions = Vec::new();
+                step.integrate(positions, velocities, ages, lifetimes, &mut collisions);
+                collisions
+            })
+            .collect();
+
+        particles.remove_expired();
+        if let Some(callback) = &mut self.on_collision {
+            collisions.iter().for_each(callback);
+        }
+
+        let Some(origin) = origin else {
+            return;
+        };
+        let mut count = 0;
+        if self.burst_pending {
+            self.burst_pending = false;
+            count += effect.burst as usize;
+        }
+        if self.emitting {
+            self.pending += effect.rate.max(0.0) * delta_time;
+            count += self.pending as usize;
+            self.pending = self.pending.fract();
+        }
+        let count = count.min(effect.max_particles.saturating_sub(self.particles.len()));
+        for _ in 0..count {
+            self.spawn(origin);
+        }
+    }
+
+    /// Emits a particle at a random place in the emitter's shape.
+    fn spawn(&mut self, origin: Mat4) {
+        let effect = &self.effect;
+        let random = &mut self.random;
+        let offset = match effect.shape {
+            EmitterShape::Point => Vec3::ZERO,
+            EmitterShape::Sphere { radius } => random.in_unit_sphere() * radius,
+            EmitterShape::Box { half_extents } => {
+                (Vec3::new(random.next(), random.next(), random.next()) * 2.0 - Vec3::ONE)
+                    * half_extents
+            }
+        };
+
+        // Directions are uniform over the cone's cap around the emission direction
+        let cos_theta = 1.0 - random.next() * (1.0 - effect.spread.cos());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = random.next() * TAU;
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let axis = effect.direction.normalize_or(Vec3::Y);
+        let direction = origin
+            .transform_vector3(Quat::from_rotation_arc(Vec3::Z, axis) * local)
+            .normalize_or_zero();
+
+        let velocity = direction * random.range(effect.speed);
+        let lifetime = random.range(effect.lifetime).max(f32::EPSILON);
+        self.particles.push(
+            origin.transform_point3(offset).into(),
+            velocity.into(),
+            lifetime,
+        );
+    }
+
+    /// Appends a camera-facing quad instance for every particle.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera_rotation` - The rotation of the camera, which quads built facing +Z take.
+    /// * `instances` - The instances to append to.
+    pub fn append_instances(&self, camera_rotation: Quat, instances: &mut Vec<InstanceData>) {
+        let particles = &self.particles;
+        let lifetimes = particles.ages.iter().zip(&particles.lifetimes);
+        instances.extend(particles.positions.iter().zip(lifetimes).map(
+            |(&position, (age, lifetime))| {
+                let t = age / lifetime;
+                InstanceData {
+                    model_matrix: Mat4::from_scale_rotation_translation(
+                        Vec3::splat(self.effect.size.sample(t)),
+                        camera_rotation,
+                        position.into(),
+                    ),
+                    color: self.effect.color.sample(t),
+                }
+            },
+        ));
+    }
+}
+
+impl fmt::Debug for ParticleEmitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParticleEmitter")
+            .field("source", &self.source)
+            .field("emitting", &self.emitting)
+            .field("particles", &self.particles.len())
+            .field("effect", &self.effect)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The particle emitters of a renderer, indexed by `ParticleEmitterId`.
+#[derive(Debug, Default)]
+pub struct ParticleEmitters {
+    emitters: Vec<Option<ParticleEmitter>>,
+}
+
+impl ParticleEmitters {
+    /// Adds an emitter and returns its handle.
+    pub fn add(&mut self, emitter: ParticleEmitter) -> ParticleEmitterId {
+        self.emitters.push(Some(emitter));
+        ParticleEmitterId(self.emitters.len() - 1)
+    }
+
+    /// Removes an emitter, returning `true` if it existed.
+    pub fn remove(&mut self, id: ParticleEmitterId) -> bool {
+        self.emitters
+            .get_mut(id.0)
+            .is_some_and(|emitter| emitter.take().is_some())
+    }
+
+    /// Returns an emitter.
+    pub fn get(&self, id: ParticleEmitterId) -> Option<&ParticleEmitter> {
+        self.emitters.get(id.0)?.as_ref()
+    }
+
+    /// Returns an emitter for modification.
+    pub fn get_mut(&mut self, id: ParticleEmitterId) -> Option<&mut ParticleEmitter> {
+        self.emitters.get_mut(id.0)?.as_mut()
+    }
+
+    /// Returns `true` if there are no emitters.
+    pub fn is_empty(&self) -> bool {
+        self.emitters.iter().all(Option::is_none)
+    }
+
+    /// Returns the emitters.
+    pub fn iter(&self) -> impl Iterator<Item = &ParticleEmitter> + '_ {
+        self.emitters.iter().flatten()
+    }
+
+    /// Returns the emitters and their handles for modification.
+    pub fn iter_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (ParticleEmitterId, &mut ParticleEmitter)> + '_ {
+        self.emitters
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, emitter)| Some((ParticleEmitterId(index), emitter.as_mut()?)))
+    }
+
+    /// Updates every emitter.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta_time` - The seconds of scaled time since the last update.
+    /// * `node_transform` - Returns the world transform of a node, or `None`
+    ///   if it no longer exists.
+    pub fn update(&mut self, delta_time: f32, node_transform: impl Fn(NodeId) -> Option<Mat4>) {
+        for emitter in self.emitters.iter_mut().flatten() {
+            let origin = match emitter.source {
+                EmitterSource::Node(id) => node_transform(id),
+                EmitterSource::Position(position) => Some(Mat4::from_translation(position)),
+            };
+            emitter.update(delta_time, origin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        EmitterShape, EmitterSource, ParticleCollider, ParticleCurve, ParticleEffect,
+        ParticleEmitter,
+    };
+    use crate::renderer::Color;
+    use glam::{Mat4, Quat, Vec3, Vec3A};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_particle_curve_sample() {
+        let curve = ParticleCurve::new(vec![(1.0, 4.0), (0.0, 0.0), (0.5, 1.0)]).unwrap();
+        assert_eq!(curve.sample(-1.0), 0.0);
+        assert_eq!(curve.sample(0.25), 0.5);
+        assert_eq!(curve.sample(0.75), 2.5);
+        assert_eq!(curve.sample(2.0), 4.0);
+        assert_eq!(ParticleCurve::constant(Color::RED).sample(0.5), Color::RED);
+        assert!(ParticleCurve::<f32>::new(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_particle_emitter_update() {
+        let effect = ParticleEffect {
+            shape: EmitterShape::Sphere { radius: 0.5 },
+            rate: 10.0,
+            burst: 3,
+            max_particles: 8,
+            lifetime: (1.0, 1.0),
+            speed: (2.0, 2.0),
+            direction: Vec3::Z,
+            size: ParticleCurve::new(vec![(0.0, 1.0), (1.0, 3.0)]).unwrap(),
+            ..ParticleEffect::default()
+        };
+        let origin = Mat4::from_translation(Vec3::new(0.0, 5.0, 0.0));
+        let mut emitter = ParticleEmitter::new(effect, EmitterSource::Position(Vec3::ZERO));
+
+        // The burst and a tenth of a second of particles
+        emitter.update(0.1, Some(origin));
+        assert_eq!(emitter.len(), 4);
+        let particles = &emitter.particles;
+        for (position, velocity) in particles.positions.iter().zip(&particles.velocities) {
+            assert!(position.distance(Vec3A::new(0.0, 5.0, 0.0)) <= 0.5);
+            assert!((*velocity - Vec3A::Z * 2.0).length() < 1e-5);
+        }
+
+        // Particles move, grow over their lifetime, and stop at the maximum
+        emitter.update(0.5, Some(origin));
+        assert_eq!(emitter.len(), 8);
+        let mut instances = Vec::new();
+        emitter.append_instances(Quat::IDENTITY, &mut instances);
+        let scale = instances[0].model_matrix.x_axis.length();
+        assert!((scale - 2.0).abs() < 1e-5);
+
+        // Without an origin nothing is emitted, and particles expire
+        emitter.update(1.0, None);
+        assert!(emitter.is_empty());
+        emitter.restart();
+        emitter.update(0.0, Some(origin));
+        assert_eq!(emitter.len(), 3);
+    }
+
+    #[test]
+    fn test_particle_emitter_collisions() {
+        let effect = ParticleEffect {
+            rate: 0.0,
+            burst: 2,
+            lifetime: (10.0, 10.0),
+            speed: (4.0, 4.0),
+            direction: Vec3::NEG_Y,
+            bounce: 0.5,
+            ..ParticleEffect::default()
+        };
+        let ground = ParticleCollider::Plane {
+            normal: Vec3::Y,
+            distance: 0.0,
+        };
+        let mut emitter = ParticleEmitter::new(effect.clone(), EmitterSource::Position(Vec3::Y))
+            .with_collider(ground);
+        let collisions = Rc::new(RefCell::new(Vec::new()));
+        let received = Rc::clone(&collisions);
+        emitter.set_on_collision(move |collision| received.borrow_mut().push(*collision));
+        emitter.update(0.0, Some(Mat4::from_translation(Vec3::Y)));
+
+        // Both particles land on the plane and bounce back at half speed
+        emitter.update(0.5, None);
+        assert_eq!(collisions.borrow().len(), 2);
+        let collision = collisions.borrow()[0];
+        assert_eq!((collision.collider, collision.normal), (0, Vec3::Y));
+        assert_eq!(collision.position, Vec3::ZERO);
+        assert_eq!(collision.velocity, Vec3::NEG_Y * 4.0);
+        assert_eq!(emitter.particles.velocities[0], Vec3A::Y * 2.0);
+
+        // Particles inside a sphere are pushed to its surface, and can die on impact
+        let mut emitter = ParticleEmitter::new(
+            ParticleEffect {
+                kill_on_collision: true,
+                ..effect
+            },
+            EmitterSource::Position(Vec3::ZERO),
+        )
+        .with_collider(ParticleCollider::Sphere {
+            center: Vec3::new(0.0, -3.0, 0.0),
+            radius: 2.0,
+        });
+        emitter.update(0.0, Some(Mat4::IDENTITY));
+        emitter.update(0.5, None);
+        assert!(emitter.is_empty());
+    }
+}
@@ -0,0 +1,427 @@
+//! Plots module for the renderer.
+//!
+//! This module provides line graphs of named time series, such as frame
+//! times, the energy of a physics system or any other metric, drawn on the
+//! canvas over every frame. Each series keeps its most recent values in a
+//! ring buffer, and its graph is scaled to the range of the values it holds
+//! unless given a fixed range. Graphs are stacked down the top-right corner
+//! of the screen, each showing the series' name, latest value and range.
+
+use super::{
+    canvas::{Canvas, CanvasPoint, CanvasSize},
+    font::{text_size, LINE_ADVANCE},
+    Color, Palette,
+};
+use glam::Vec2;
+use std::collections::VecDeque;
+
+/// The values a series keeps by default.
+const DEFAULT_CAPACITY: usize = 240;
+
+/// The size of a graph in points.
+const PLOT_SIZE: Vec2 = Vec2::new(240.0, 64.0);
+
+/// The space between graphs and around the stack in points.
+const MARGIN: f32 = 8.0;
+
+/// The size of a font pixel of the graphs' text in points.
+const PIXEL_SIZE: f32 = 1.0;
+
+/// Describes how a series is kept and drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlotStyle {
+    /// The color of the line, or `None` for the next color of the categorical palette.
+    pub color: Option<Color>,
+    /// The values at the bottom and top of the graph, or `None` to fit the values.
+    pub range: Option<(f32, f32)>,
+    /// The number of most recent values kept.
+    pub capacity: usize,
+}
+
+impl Default for PlotStyle {
+    fn default() -> Self {
+        Self {
+            color: None,
+            range: None,
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+}
+
+impl PlotStyle {
+    /// Sets the color of the line.
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Fixes the values at the bottom and top of the graph.
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Sets the number of most recent values kept.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(2);
+        self
+    }
+}
+
+/// A named series of values, oldest first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plot {
+    name: String,
+    style: PlotStyle,
+    values: VecDeque<f32>,
+}
+
+impl Plot {
+    /// Creates an empty series.
+    pub fn new(name: impl Into<String>, style: PlotStyle) -> Self {
+        Self {
+            name: name.into(),
+            style,
+            values: VecDeque::with_capacity(style.capacity),
+        }
+    }
+
+    /// Returns the name of the series.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns how the series is kept and drawn.
+    pub fn style(&self) -> &PlotStyle {
+        &self.style
+    }
+
+    /// Sets how the series is kept and drawn, dropping the oldest values
+    /// beyond its capacity.
+    pub fn set_style(&mut self, style: PlotStyle) {
+        self.style = style;
+        self.trim();
+    }
+
+    /// Appends a value, dropping the oldest value if the series is full.
+    /// Values that aren't finite are skipped.
+    pub fn push(&mut self, value: f32) {
+        if !value.is_finite() {
+            return;
+        }
+        self.values.push_back(value);
+        self.trim();
+    }
+
+    /// Returns the values, oldest first.
+    pub fn values(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        self.values.iter().copied()
+    }
+
+    /// Returns the most recent value.
+    pub fn latest(&self) -> Option<f32> {
+        self.values.back().copied()
+    }
+
+    /// Returns the smallest and largest values.
+    pub fn min_max(&self) -> Option<(f32, f32)> {
+        self.values().fold(None, |range, value| match range {
+            Some((min, max)) => Some((value.min(min), value.max(max))),
+            None => Some((value, value)),
+        })
+    }
+
+    /// Returns the mean of the values.
+    pub fn average(&self) -> Option<f32> {
+        (!self.values.is_empty()).then(|| self.values().sum::<f32>() / self.values.len() as f32)
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the series has no values.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Removes every value.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Returns the values at the bottom and top of the graph, which are
+    /// apart even when every value is the same.
+    fn range(&self) -> (f32, f32) {
+        let (min, max) = self.style.range.or(self.min_max()).unwrap_or((0.0, 1.0));
+        if max > min {
+            return (min, max);
+        }
+        let padding = (min.abs() * 0.5).max(0.5);
+        (min - padding, max + padding)
+    }
+
+    fn trim(&mut self) {
+        while self.values.len() > self.style.capacity.max(2) {
+            self.values.pop_front();
+        }
+    }
+}
+
+/// The plotted series of a renderer, in the order they were first recorded.
+#[derive(Clone, Debug)]
+pub struct Plots {
+    plots: Vec<Plot>,
+    palette: Palette,
+}
+
+impl Default for Plots {
+    fn default() -> Self {
+        Self {
+            plots: Vec::new(),
+            palette: Palette::categorical(),
+        }
+    }
+}
+
+impl Plots {
+    /// Appends a value to a series, adding it with the default style if it doesn't exist.
+    pub fn record(&mut self, name: &str, value: f32) {
+        match self.get_mut(name) {
+            Some(plot) => plot.push(value),
+            None => {
+                let mut plot = Plot::new(name, PlotStyle::default());
+                plot.push(value);
+                self.plots.push(plot);
+            }
+        }
+    }
+
+    /// Sets how a series is kept and drawn, adding it if it doesn't exist.
+    pub fn set_style(&mut self, name: &str, style: PlotStyle) {
+        match self.get_mut(name) {
+            Some(plot) => plot.set_style(style),
+            None => self.plots.push(Plot::new(name, style)),
+        }
+    }
+
+    /// Returns a series by its name.
+    pub fn get(&self, name: &str) -> Option<&Plot> {
+        self.plots.iter().find(|plot| plot.name == name)
+    }
+
+    /// Returns a series by its name for modification.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Plot> {
+        self.plots.iter_mut().find(|plot| plot.name == name)
+    }
+
+    /// Returns every series, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Plot> {
+        self.plots.iter()
+    }
+
+    /// Removes a series, returning `true` if it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.plots.len();
+        self.plots.retain(|plot| plot.name != name);
+        self.plots.len() != count
+    }
+
+    /// Removes every series.
+    pub fn clear(&mut self) {
+        self.plots.clear();
+    }
+
+    /// Returns `true` if there are no series.
+    pub fn is_empty(&self) -> bool {
+        self.plots.is_empty()
+    }
+
+    /// Draws a graph of every series, stacked down the top-right corner of the canvas.
+    pub fn draw(&self, canvas: &mut Canvas) {
+        let scale_factor = canvas.scale_factor();
+        let (size, margin) = (PLOT_SIZE * scale_factor, MARGIN * scale_factor);
+        let pixel_size = PIXEL_SIZE * scale_factor;
+        let line_height = LINE_ADVANCE * pixel_size;
+        let left = canvas.size().x - margin - size.x;
+        for (index, plot) in self.plots.iter().enumerate() {
+            let top = margin + index as f32 * (size.y + margin);
+            let color = plot
+                .style
+                .color
+                .unwrap_or_else(|| self.palette.color(index));
+            canvas.rect(
+                CanvasPoint::pixels(left, top),
+                CanvasSize::pixels(size.x, size.y),
+                Color::BLACK.with_alpha(0.6),
+            );
+
+            // The line fills the graph below its title, newest value on the right
+            let (min, max) = plot.range();
+            let graph_top = top + line_height + pixel_size * 2.0;
+            let graph_height = top + size.y - pixel_size * 2.0 - graph_top;
+            let step = size.x / (plot.style.capacity.max(2) - 1) as f32;
+            let first = plot.style.capacity.max(2) - plot.len();
+            let points: Vec<CanvasPoint> = plot
+                .values()
+                .enumerate()
+                .map(|(i, value)| {
+                    let height = ((value - min) / (max - min)).clamp(0.0, 1.0) * graph_height;
+                    CanvasPoint::pixels(
+                        left + (first + i) as f32 * step,
+                        graph_top + graph_height - height,
+                    )
+                })
+                .collect();
+            canvas.polyline(&points, scale_factor, color);
+
+            let title = match plot.latest() {
+                Some(latest) => format!("{} {}", plot.name, format_value(latest)),
+                None => plot.name.clone(),
+            };
+            let text_left = left + pixel_size * 2.0;
+            canvas.text(
+                CanvasPoint::pixels(text_left, top + pixel_size * 2.0),
+                &title,
+                pixel_size,
+                color,
+            );
+            let bounds = format!("{}..{}", format_value(min), format_value(max));
+            let bounds_width = text_size(&bounds).x * pixel_size;
+            canvas.text(
+                CanvasPoint::pixels(
+                    left + size.x - pixel_size * 2.0 - bounds_width,
+                    top + pixel_size * 2.0,
+                ),
+                &bounds,
+                pixel_size,
+                Color::GRAY,
+            );
+        }
+    }
+}
+
+/// Formats a value with about four significant digits.
+fn format_value(value: f32) -> String {
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-3..1e5).contains(&magnitude) {
+        format!("{value:.2e}")
+    } else if magnitude >= 100.0 {
+        format!("{value:.0}")
+    } else {
+        format!("{value:.3}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_value, Plot, PlotStyle, Plots};
+    use crate::renderer::canvas::Canvas;
+    use glam::Vec2;
+
+    #[test]
+    fn test_plots_keep_recent_values() {
+        let mut plots = Plots::default();
+        plots.set_style("energy", PlotStyle::default().with_capacity(3));
+        for value in [1.0, 2.0, f32::NAN, 3.0, 4.0] {
+            plots.record("energy", value);
+        }
+        plots.record("frame", 16.0);
+
+        let energy = plots.get("energy").unwrap();
+        assert_eq!(energy.values().collect::<Vec<_>>(), [2.0, 3.0, 4.0]);
+        assert_eq!(energy.latest(), Some(4.0));
+        assert_eq!(energy.min_max(), Some((2.0, 4.0)));
+        assert_eq!(energy.average(), Some(3.0));
+        // A flat series is drawn in the middle of its graph
+        assert_eq!(plots.get("frame").unwrap().range(), (8.0, 24.0));
+        assert_eq!(
+            plots.iter().map(|plot| plot.name()).collect::<Vec<_>>(),
+            ["energy", "frame"]
+        );
+
+        let mut canvas = Canvas::new(Vec2::new(800.0, 600.0));
+        plots.draw(&mut canvas);
+        assert!(!canvas.is_empty());
+        // Graphs stay within the top-right corner
+        assert!(canvas
+            .frame()
+            .vertices
+            .iter()
+            .all(|vertex| vertex.position[0] >= 800.0 - 8.0 - 240.0 - 1.0));
+
+        assert!(plots.remove("energy"));
+        assert!(!plots.remove("energy"));
+        assert_eq!(format_value(0.000_012), "1.20e-5");
+        assert_eq!(format_value(1234.4), "1234");
+    }
+
+    #[test]
+    fn test_plot_range_of_empty_series() {
+        let mut plot = Plot::new("empty", PlotStyle::default());
+        assert_eq!(plot.min_max(), None);
+        assert_eq!(plot.average(), None);
+        assert_eq!(plot.range(), (0.0, 1.0));
+
+        // A fixed range applies before any value is recorded
+        plot.set_style(PlotStyle::default().with_range(-2.0, 2.0));
+        assert_eq!(plot.range(), (-2.0, 2.0));
+
+        // An empty series still draws its graph and title
+        let mut plots = Plots::default();
+        plots.set_style("empty", PlotStyle::default());
+        let mut canvas = Canvas::new(Vec2::new(800.0, 600.0));
+        plots.draw(&mut canvas);
+        assert!(!canvas.is_empty());
+    }
+
+    #[test]
+    fn test_plot_range_of_constant_series() {
+        let range = |value: f32| {
+            let mut plot = Plot::new("constant", PlotStyle::default());
+            for _ in 0..4 {
+                plot.push(value);
+            }
+            plot.range()
+        };
+        assert_eq!(range(0.0), (-0.5, 0.5));
+        assert_eq!(range(0.25), (-0.25, 0.75));
+        assert_eq!(range(-10.0), (-15.0, -5.0));
+
+        // A fixed range without extent is padded like a constant series
+        let mut plot = Plot::new("fixed", PlotStyle::default().with_range(3.0, 3.0));
+        plot.push(100.0);
+        assert_eq!(plot.range(), (1.5, 4.5));
+    }
+
+    #[test]
+    fn test_plot_range_skips_nan() {
+        let mut plot = Plot::new("nan", PlotStyle::default());
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            plot.push(value);
+        }
+        assert!(plot.is_empty());
+        assert_eq!(plot.range(), (0.0, 1.0));
+
+        for value in [f32::NAN, -1.0, f32::NAN, 3.0] {
+            plot.push(value);
+        }
+        assert_eq!(plot.values().collect::<Vec<_>>(), [-1.0, 3.0]);
+        assert_eq!(plot.range(), (-1.0, 3.0));
+        assert_eq!(plot.average(), Some(1.0));
+    }
+
+    #[test]
+    fn test_format_value_labels() {
+        assert_eq!(format_value(0.0), "0.000");
+        assert_eq!(format_value(0.5), "0.500");
+        assert_eq!(format_value(-42.125), "-42.125");
+        assert_eq!(format_value(100.0), "100");
+        assert_eq!(format_value(99_999.0), "99999");
+        assert_eq!(format_value(100_000.0), "1.00e5");
+        assert_eq!(format_value(-2.5e6), "-2.50e6");
+        assert_eq!(format_value(0.001), "0.001");
+        assert_eq!(format_value(0.000_1), "1.00e-4");
+    }
+}
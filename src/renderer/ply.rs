@@ -0,0 +1,683 @@
+//! PLY module for the renderer.
+//!
+//! This module imports PLY files, the polygon and point cloud format written
+//! by scanners and scientific tools such as ParaView, MeshLab and Open3D.
+//! ASCII, little-endian and big-endian binary files are read. A header
+//! declares the elements of the file and their properties:
+//!
+//! ```text
+//! ply
+//! format ascii 1.0
+//! element vertex 3
+//! property float x
+//! property float y
+//! property float z
+//! property uchar red
+//! property uchar green
+//! property uchar blue
+//! property float temperature
+//! element face 1
+//! property list uchar int vertex_indices
+//! end_header
+//! 0 0 0 255 0 0 280.5
+//! 1 0 0 0 255 0 301.2
+//! 0 1 0 0 0 255 295
+//! 3 0 1 2
+//! ```
+//!
+//! Vertex positions, colors, normals and texture coordinates are read into
+//! the mesh. Every other scalar vertex property, such as a temperature or
+//! density, is kept by name so it can color the mesh through a `Colormap`
+//! with `PlyModel::color_by`. Faces are triangulated as fans, and files
+//! without faces become point clouds. Other elements are skipped.
+
+use super::{
+    colormap::Colormap,
+    common::{PrimitiveType, Vertex},
+    error::ImportError,
+    import_settings::ImportSettings,
+    shape_builders::MeshBuilder,
+    Color,
+};
+use glam::{Vec2, Vec3};
+use log::{debug, info};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+/// The vertex properties read into the mesh rather than kept as scalars.
+const MESH_PROPERTIES: [&str; 16] = [
+    "x",
+    "y",
+    "z",
+    "nx",
+    "ny",
+    "nz",
+    "red",
+    "green",
+    "blue",
+    "alpha",
+    "u",
+    "v",
+    "s",
+    "t",
+    "texture_u",
+    "texture_v",
+];
+
+/// The encoding of a PLY file's body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// The type of a PLY property value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::UInt8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::UInt16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::UInt32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    /// Returns the largest value of integer types, which colors are scaled by.
+    fn max_integer(self) -> Option<f64> {
+        match self {
+            ScalarType::UInt8 => Some(u8::MAX as f64),
+            ScalarType::UInt16 => Some(u16::MAX as f64),
+            ScalarType::Float32 | ScalarType::Float64 => None,
+            ScalarType::Int8 => Some(i8::MAX as f64),
+            ScalarType::Int16 => Some(i16::MAX as f64),
+            ScalarType::Int32 => Some(i32::MAX as f64),
+            ScalarType::UInt32 => Some(u32::MAX as f64),
+        }
+    }
+}
+
+/// A property of an element: a value, or a list of values preceded by their count.
+#[derive(Clone, Debug)]
+enum Property {
+    Scalar(String, ScalarType),
+    List(String, ScalarType, ScalarType),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+/// An element declared in the header, e.g. `vertex` or `face`.
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// Returns the fewest bytes one item of the element takes in the body.
+    fn min_size(&self, format: Format) -> usize {
+        self.properties
+            .iter()
+            .map(|property| match (format, property) {
+                // Every ASCII value is at least one character
+                (Format::Ascii, _) => 1,
+                (_, Property::Scalar(_, scalar_type)) => scalar_type.size(),
+                // An empty list is only its count
+                (_, Property::List(_, count_type, _)) => count_type.size(),
+            })
+            .sum()
+    }
+}
+
+/// Reads values from a PLY file's body.
+struct Reader<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    /// Reads the next value of a type.
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, ImportError> {
+        if self.format == Format::Ascii {
+            let start = self.offset
+                + self.bytes[self.offset..]
+                    .iter()
+                    .take_while(|byte| byte.is_ascii_whitespace())
+                    .count();
+            let end = start
+                + self.bytes[start..]
+                    .iter()
+                    .take_while(|byte| !byte.is_ascii_whitespace())
+                    .count();
+            self.offset = end;
+            let token = std::str::from_utf8(&self.bytes[start..end]).unwrap_or_default();
+            return token.parse().map_err(|_| {
+                ImportError::Malformed(if token.is_empty() {
+                    "PLY file ends early".to_string()
+                } else {
+                    format!("invalid PLY value \"{token}\"")
+                })
+            });
+        }
+
+        let size = scalar_type.size();
+        let mut chunk = [0; 8];
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + size)
+            .ok_or_else(|| ImportError::Malformed("PLY file ends early".to_string()))?;
+        chunk[..size].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            chunk[..size].reverse();
+        }
+        self.offset += size;
+        Ok(match scalar_type {
+            ScalarType::Int8 => chunk[0] as i8 as f64,
+            ScalarType::UInt8 => chunk[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes(chunk[..2].try_into().unwrap()) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes(chunk[..2].try_into().unwrap()) as f64,
+            ScalarType::Int32 => i32::from_le_bytes(chunk[..4].try_into().unwrap()) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes(chunk[..4].try_into().unwrap()) as f64,
+            ScalarType::Float32 => f32::from_le_bytes(chunk[..4].try_into().unwrap()) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(chunk[..8].try_into().unwrap()),
+        })
+    }
+}
+
+/// A mesh or point cloud read from a PLY file, with its scalar vertex properties.
+#[derive(Clone)]
+pub struct PlyModel {
+    mesh: MeshBuilder,
+    scalars: BTreeMap<String, Vec<f32>>,
+}
+
+impl PlyModel {
+    /// Loads a PLY file.
+    pub fn load(path: &Path) -> Result<Self, ImportError> {
+        let bytes = fs::read(path).map_err(|source| ImportError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let model = Self::parse(&bytes)?;
+        info!(
+            "Loaded PLY {} with {} vertices",
+            path.display(),
+            model.mesh.data.vertices.len()
+        );
+        Ok(model)
+    }
+
+    /// Parses the contents of a PLY file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the model, `ImportError::Unsupported` if the
+    /// file isn't a PLY file or has no vertex positions, or
+    /// `ImportError::Malformed`.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ImportError> {
+        let (format, elements, body) = parse_header(bytes)?;
+        let mut reader = Reader {
+            format,
+            bytes: body,
+            offset: 0,
+        };
+
+        let mut columns: HashMap<String, (ScalarType, Vec<f64>)> = HashMap::new();
+        let mut vertex_count = 0;
+        let mut indices = Vec::new();
+        for element in &elements {
+            // Counts come from the header, so check them before allocating
+            let remaining = reader.bytes.len() - reader.offset;
+            if element
+                .count
+                .checked_mul(element.min_size(format))
+                .is_none_or(|size| size > remaining)
+            {
+                return Err(ImportError::Malformed(format!(
+                    "PLY file is too short for {} {} elements",
+                    element.count, element.name
+                )));
+            }
+
+            let is_vertex = element.name == "vertex";
+            let is_face = element.name == "face";
+            if is_vertex {
+                vertex_count = element.count;
+                for property in &element.properties {
+                    if let Property::Scalar(name, scalar_type) = property {
+                        columns.insert(
+                            name.clone(),
+                            (*scalar_type, Vec::with_capacity(vertex_count)),
+                        );
+                    }
+                }
+            }
+            let items = if element.properties.is_empty() {
+                0
+            } else {
+                element.count
+            };
+            for _ in 0..items {
+                for property in &element.properties {
+                    match property {
+                        Property::Scalar(name, scalar_type) => {
+                            let value = reader.read(*scalar_type)?;
+                            if is_vertex {
+                                columns.get_mut(name).unwrap().1.push(value);
+                            }
+                        }
+                        Property::List(name, count_type, item_type) => {
+                            let count = reader.read(*count_type)? as usize;
+                            let mut polygon = Vec::new();
+                            for _ in 0..count {
+                                polygon.push(reader.read(*item_type)? as u32);
+                            }
+                            if is_face && matches!(name.as_str(), "vertex_indices" | "vertex_index")
+                            {
+                                // Triangulate the polygon as a fan
+                                for i in 1..count.saturating_sub(1) {
+                                    indices.extend([polygon[0], polygon[i], polygon[i + 1]]);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            if !is_vertex && !is_face {
+                debug!("Skipped {} PLY {} elements", element.count, element.name);
+            }
+        }
+        if let Some(&index) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+            return Err(ImportError::Malformed(format!(
+                "PLY face refers to vertex {index} of {vertex_count}"
+            )));
+        }
+        // A second vertex element can leave columns of different lengths
+        if let Some((name, (_, values))) = columns
+            .iter()
+            .find(|(_, (_, values))| values.len() != vertex_count)
+        {
+            return Err(ImportError::Malformed(format!(
+                "PLY vertex property \"{name}\" has {} of {vertex_count} values",
+                values.len()
+            )));
+        }
+
+        let column = |name: &str| columns.get(name).map(|(_, values)| values);
+        let (Some(x), Some(y), Some(z)) = (column("x"), column("y"), column("z")) else {
+            return Err(ImportError::Unsupported(
+                "PLY file has no vertex positions".to_string(),
+            ));
+        };
+        let mut vertices: Vec<Vertex> = (0..vertex_count)
+            .map(|i| Vertex {
+                position: [x[i] as f32, y[i] as f32, z[i] as f32],
+                ..Vertex::default()
+            })
+            .collect();
+
+        // Integer colors span their type's range, float colors [0, 1], both in sRGB
+        if let (Some(red), Some(green), Some(blue)) = (
+            columns.get("red"),
+            columns.get("green"),
+            columns.get("blue"),
+        ) {
+            let channel = |(scalar_type, values): &(ScalarType, Vec<f64>), i: usize| {
+                (values[i] / scalar_type.max_integer().unwrap_or(1.0)) as f32
+            };
+            for (i, vertex) in vertices.iter_mut().enumerate() {
+                let alpha = columns.get("alpha").map_or(1.0, |alpha| channel(alpha, i));
+                vertex.color =
+                    Color::from_srgb(channel(red, i), channel(green, i), channel(blue, i), alpha)
+                        .into();
+            }
+        }
+
+        let primitive_type = if indices.is_empty() {
+            PrimitiveType::Point
+        } else {
+            PrimitiveType::Triangle
+        };
+        let mut mesh = MeshBuilder::new(vertices, primitive_type);
+        if !indices.is_empty() {
+            mesh = mesh.with_indices(indices);
+        }
+        if let (Some(nx), Some(ny), Some(nz)) = (column("nx"), column("ny"), column("nz")) {
+            let normals: Vec<Vec3> = (0..vertex_count)
+                .map(|i| Vec3::new(nx[i] as f32, ny[i] as f32, nz[i] as f32))
+                .collect();
+            mesh = mesh.with_normals(&normals);
+        }
+        let tex_coords = [("u", "v"), ("s", "t"), ("texture_u", "texture_v")]
+            .into_iter()
+            .find_map(|(u, v)| column(u).zip(column(v)));
+        if let Some((u, v)) = tex_coords {
+            let tex_coords: Vec<Vec2> = (0..vertex_count)
+                .map(|i| Vec2::new(u[i] as f32, v[i] as f32))
+                .collect();
+            mesh = mesh.with_tex_coords(&tex_coords);
+        }
+
+        let scalars = columns
+            .into_iter()
+            .filter(|(name, _)| !MESH_PROPERTIES.contains(&name.as_str()))
+            .map(|(name, (_, values))| (name, values.into_iter().map(|v| v as f32).collect()))
+            .collect();
+        Ok(Self { mesh, scalars })
+    }
+
+    /// Returns the mesh, or point cloud if the file has no faces.
+    pub fn mesh(&self) -> &MeshBuilder {
+        &self.mesh
+    }
+
+    /// Returns the names of the scalar vertex properties, in order.
+    pub fn scalar_names(&self) -> impl Iterator<Item = &str> {
+        self.scalars.keys().map(String::as_str)
+    }
+
+    /// Returns the values of a scalar vertex property, one per vertex.
+    pub fn scalar(&self, name: &str) -> Option<&[f32]> {
+        self.scalars.get(name).map(Vec::as_slice)
+    }
+
+    /// Colors the vertices by mapping a scalar property through a colormap.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the property.
+    /// * `colormap` - The colormap to map the values through.
+    /// * `range` - The `(min, max)` values mapped to the ends of the colormap,
+    ///   or `None` to use the range of the values.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `ImportError::MissingProperty`.
+    pub fn color_by(
+        &mut self,
+        name: &str,
+        colormap: Colormap,
+        range: Option<(f32, f32)>,
+    ) -> Result<(), ImportError> {
+        let values = self
+            .scalars
+            .get(name)
+            .ok_or_else(|| ImportError::MissingProperty(name.to_string()))?;
+        let range = range.unwrap_or_else(|| Colormap::auto_range(values));
+        for (vertex, &value) in self.mesh.data.vertices.iter_mut().zip(values) {
+            vertex.color = colormap.map(value, range).into();
+        }
+        Ok(())
+    }
+
+    /// Returns the meshes to add to the renderer, with import settings applied.
+    pub fn into_meshes(self, settings: &ImportSettings) -> Vec<MeshBuilder> {
+        settings.apply(vec![self.mesh])
+    }
+}
+
+/// Imports a PLY file, applying the import settings of its sidecar.
+///
+/// Scalar vertex properties are dropped; load a `PlyModel` to color the
+/// mesh by one.
+///
+/// # Returns
+///
+/// A `Result` containing the file's mesh, or an `ImportError` if the file
+/// or its settings can't be read.
+pub fn import_ply(path: &Path) -> Result<Vec<MeshBuilder>, ImportError> {
+    let model = PlyModel::load(path)?;
+    Ok(model.into_meshes(&ImportSettings::load_for(path)?))
+}
+
+/// Parses a PLY header.
+///
+/// # Returns
+///
+/// A `Result` containing the body's format, the elements in the order they
+/// are stored, and the body.
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), ImportError> {
+    if !bytes.starts_with(b"ply") {
+        return Err(ImportError::Unsupported("not a PLY file".to_string()));
+    }
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or_else(|| ImportError::Malformed("PLY header has no end_header".to_string()))?;
+    let header = String::from_utf8_lossy(&bytes[..end]);
+    // The body starts after the end of the end_header line
+    let body_start = bytes[end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |newline| end + newline + 1);
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for (index, line) in header.lines().enumerate().skip(1) {
+        let error = |message: String| {
+            ImportError::Malformed(format!("PLY header line {}: {message}", index + 1))
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let scalar_type = |name: &str| {
+            ScalarType::parse(name).ok_or_else(|| error(format!("unknown type \"{name}\"")))
+        };
+        match fields.as_slice() {
+            ["format", encoding, version] => {
+                if *version != "1.0" {
+                    return Err(ImportError::Unsupported(format!("PLY version {version}")));
+                }
+                format = Some(match *encoding {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(error(format!("unknown format \"{encoding}\""))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("invalid count \"{count}\"")))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let property = Property::List(
+                    name.to_string(),
+                    scalar_type(count_type)?,
+                    scalar_type(item_type)?,
+                );
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["property", scalar, name] => {
+                let property = Property::Scalar(name.to_string(), scalar_type(scalar)?);
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?;
+                if element
+                    .properties
+                    .iter()
+                    .any(|p| p.name() == property.name())
+                {
+                    return Err(error(format!("duplicate property \"{name}\"")));
+                }
+                element.properties.push(property);
+            }
+            ["comment" | "obj_info", ..] | [] => {}
+            _ => return Err(error(format!("unexpected \"{line}\""))),
+        }
+    }
+    let format =
+        format.ok_or_else(|| ImportError::Malformed("PLY header has no format".to_string()))?;
+    Ok((format, elements, &bytes[body_start..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PlyModel;
+    use crate::renderer::{colormap::Colormap, common::PrimitiveType, error::ImportError, Color};
+
+    const ASCII: &str = "ply\nformat ascii 1.0\ncomment from a simulation\n\
+        element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        property float temperature\nelement face 1\nproperty list uchar int vertex_indices\n\
+        end_header\n0 0 0 255 0 0 10\n1 0 0 0 255 0 20\n1 1 0 0 0 255 30\n0 1 0 255 255 255 40\n\
+        4 0 1 2 3\n";
+
+    #[test]
+    fn test_parse_ply() {
+        let mut model = PlyModel::parse(ASCII.as_bytes()).unwrap();
+        let mesh = model.mesh();
+        assert_eq!(mesh.data.primitive_type, PrimitiveType::Triangle);
+        // The quad is triangulated as a fan
+        assert_eq!(mesh.data.indices.as_deref(), Some(&[0, 1, 2, 0, 2, 3][..]));
+        assert_eq!(mesh.data.vertices[2].position, [1.0, 1.0, 0.0]);
+        assert_eq!(
+            mesh.data.vertices[1].color,
+            <[f32; 4]>::from(Color::from_srgb_u8(0, 255, 0, 255))
+        );
+        assert_eq!(model.scalar_names().collect::<Vec<_>>(), ["temperature"]);
+
+        model
+            .color_by("temperature", Colormap::Viridis, None)
+            .unwrap();
+        let colors: Vec<[f32; 4]> = model.mesh().data.vertices.iter().map(|v| v.color).collect();
+        assert_eq!(colors[0], <[f32; 4]>::from(Colormap::Viridis.sample(0.0)));
+        assert_eq!(colors[3], <[f32; 4]>::from(Colormap::Viridis.sample(1.0)));
+        assert!(matches!(
+            model.color_by("pressure", Colormap::Viridis, None),
+            Err(ImportError::MissingProperty(_))
+        ));
+
+        // A big-endian point cloud with doubles and an extra element
+        let mut binary = b"ply\nformat binary_big_endian 1.0\nelement vertex 2\n\
+            property double x\nproperty double y\nproperty double z\nproperty short density\n\
+            element camera 1\nproperty float focal\nend_header\n"
+            .to_vec();
+        for (position, density) in [([1.0f64, 2.0, 3.0], -5i16), ([4.0, 5.0, 6.0], 7)] {
+            for value in position {
+                binary.extend(value.to_be_bytes());
+            }
+            binary.extend(density.to_be_bytes());
+        }
+        binary.extend(35.0f32.to_be_bytes());
+        let cloud = PlyModel::parse(&binary).unwrap();
+        assert_eq!(cloud.mesh().data.primitive_type, PrimitiveType::Point);
+        assert_eq!(cloud.mesh().data.vertices[1].position, [4.0, 5.0, 6.0]);
+        assert_eq!(cloud.scalar("density"), Some(&[-5.0, 7.0][..]));
+
+        binary.truncate(binary.len() - 6);
+        assert!(matches!(
+            PlyModel::parse(&binary),
+            Err(ImportError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_ply_errors() {
+        let malformed =
+            |bytes: &[u8]| matches!(PlyModel::parse(bytes), Err(ImportError::Malformed(_)));
+        let header = "ply\nformat ascii 1.0\nelement vertex 1\n\
+            property float x\nproperty float y\nproperty float z\n";
+
+        // Truncated bodies
+        assert!(malformed(&ASCII.as_bytes()[..ASCII.len() - 4]));
+        assert!(malformed(format!("{header}end_header\n0 0").as_bytes()));
+        assert!(malformed(
+            b"ply\nformat binary_little_endian 1.0\nelement vertex 1\n\
+            property float x\nproperty float y\nproperty float z\nend_header\n\0\0\0\0"
+        ));
+
+        // Counts larger than the file can hold are rejected before allocating
+        for count in ["1000000000", "18446744073709551615"] {
+            let oversized = header.replace("vertex 1", &format!("vertex {count}"));
+            assert!(malformed(
+                format!("{oversized}end_header\n0 0 0\n").as_bytes()
+            ));
+        }
+        let mut list = b"ply\nformat binary_little_endian 1.0\nelement vertex 1\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uint int vertex_indices\nend_header\n"
+            .to_vec();
+        list.extend([0; 12]);
+        list.extend(u32::MAX.to_le_bytes());
+        assert!(malformed(&list));
+        // Elements without properties take no space, whatever their count
+        let empty = format!("{header}element marker 18446744073709551615\nend_header\n0 0 0\n");
+        let model = PlyModel::parse(empty.as_bytes()).unwrap();
+        assert_eq!(model.mesh().data.vertices.len(), 1);
+
+        // A second vertex element leaves the positions shorter than the vertices
+        let second =
+            format!("{header}element vertex 2\nproperty float w\nend_header\n0 0 0\n1\n2\n");
+        assert!(malformed(second.as_bytes()));
+        // Faces may only refer to declared vertices
+        let face = "element face 1\nproperty list uchar int vertex_indices\n";
+        assert!(malformed(
+            format!("{header}{face}end_header\n0 0 0\n3 0 1 2\n").as_bytes()
+        ));
+
+        // Malformed headers
+        for bad in [
+            "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n",
+            "ply\nelement vertex 0\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex -3\nend_header\n",
+            "ply\nformat ascii 1.0\nproperty float x\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex 0\nproperty half x\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex 0\nproperty float x\nproperty float x\nend_header\n",
+            "ply\nformat utf8 1.0\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex\nend_header\n",
+        ] {
+            assert!(malformed(bad.as_bytes()), "{bad}");
+        }
+        assert!(matches!(
+            PlyModel::parse(b"solid cube\n"),
+            Err(ImportError::Unsupported(_))
+        ));
+        assert!(matches!(
+            PlyModel::parse(b"ply\nformat ascii 2.0\nend_header\n"),
+            Err(ImportError::Unsupported(_))
+        ));
+    }
+}
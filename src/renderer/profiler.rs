@@ -0,0 +1,457 @@
+//! Profiler module for the renderer.
+//!
+//! This module provides a hierarchical CPU profiler fed by `profile_scope!`.
+//! Each scope records the time until the end of the enclosing block, nested
+//! under the scopes open when it started. `Profiler::end_frame` collects the
+//! scopes recorded since the previous frame into a tree, merging repeated
+//! scopes under the same parent, and tracks each scope's average and worst
+//! time over a window of recent frames. The renderer ends a frame at the
+//! start of every `render`, so scopes in the render callback count towards
+//! the frame they prepare.
+//!
+//! Scopes are recorded per thread and only while profiling is enabled, so
+//! disabled scopes cost a thread-local flag check.
+
+use super::{
+    canvas::{Canvas, CanvasPoint, CanvasSize},
+    font::text_size,
+    Color,
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The frames averages and worst cases are taken over by default.
+const DEFAULT_WINDOW: usize = 120;
+
+/// The size of a font pixel of the overlay in points.
+const OVERLAY_PIXEL_SIZE: f32 = 2.0;
+
+/// Times the rest of the enclosing block as a named profiler scope.
+///
+/// # Example
+///
+/// ```ignore
+/// {
+///     profile_scope!("cull");
+///     cull(&mut scene);
+/// }
+/// ```
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::renderer::ProfileScope::new($name);
+    };
+}
+
+/// A scope recorded on the current thread.
+struct ScopeEvent {
+    name: &'static str,
+    depth: usize,
+    start: Instant,
+    /// The time until the scope ended, or `None` while it is open.
+    duration: Option<Duration>,
+}
+
+/// The scopes recorded on a thread since the last frame ended.
+#[derive(Default)]
+struct Recorder {
+    enabled: bool,
+    events: Vec<ScopeEvent>,
+    /// The indices of the open scopes in `events`, innermost last.
+    open: Vec<usize>,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Recorder> = RefCell::new(Recorder::default());
+}
+
+/// Records a scope until it is dropped, usually through `profile_scope!`.
+pub struct ProfileScope {
+    /// The index of the scope's event, or `None` if profiling is disabled.
+    index: Option<usize>,
+}
+
+impl ProfileScope {
+    /// Opens a scope on the current thread.
+    pub fn new(name: &'static str) -> Self {
+        let index = RECORDER.with_borrow_mut(|recorder| {
+            recorder.enabled.then(|| {
+                let index = recorder.events.len();
+                recorder.events.push(ScopeEvent {
+                    name,
+                    depth: recorder.open.len(),
+                    start: Instant::now(),
+                    duration: None,
+                });
+                recorder.open.push(index);
+                index
+            })
+        });
+        Self { index }
+    }
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        let Some(index) = self.index else {
+            return;
+        };
+        RECORDER.with_borrow_mut(|recorder| {
+            // The event moves if a frame ended while the scope was open
+            let index = match recorder.open.pop() {
+                Some(open) => open,
+                None => index,
+            };
+            if let Some(event) = recorder.events.get_mut(index) {
+                event.duration = Some(event.start.elapsed());
+            }
+        });
+    }
+}
+
+/// The timing of a scope in the last frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeTiming {
+    pub name: &'static str,
+    /// The names of the scope and its ancestors from the root, separated by `/`.
+    pub path: String,
+    /// The number of ancestors.
+    pub depth: usize,
+    /// The total time of the scope in the frame.
+    pub duration: Duration,
+    /// The number of times the scope ran in the frame.
+    pub calls: u32,
+    /// The average time per frame over the window, counting frames it did not run as zero.
+    pub average: Duration,
+    /// The longest time in a frame over the window.
+    pub worst: Duration,
+}
+
+/// Collects profiler scopes into a per-frame tree of timings.
+pub struct Profiler {
+    /// The last frame's scopes, each followed by its descendants.
+    scopes: Vec<ScopeTiming>,
+    /// The times of every recent scope in the frames of the window, newest last.
+    history: HashMap<String, VecDeque<Duration>>,
+    window: usize,
+    last_frame: Option<Instant>,
+    frame_time: Duration,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// Creates a new `Profiler` averaging over 120 frames.
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            history: HashMap::new(),
+            window: DEFAULT_WINDOW,
+            last_frame: None,
+            frame_time: Duration::ZERO,
+        }
+    }
+
+    /// Enables or disables recording scopes on the current thread.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        RECORDER.with_borrow_mut(|recorder| recorder.enabled = enabled);
+    }
+
+    /// Returns `true` if scopes are recorded on the current thread.
+    pub fn is_enabled(&self) -> bool {
+        RECORDER.with_borrow(|recorder| recorder.enabled)
+    }
+
+    /// Sets the number of frames averages and worst cases are taken over.
+    pub fn set_window(&mut self, frames: usize) {
+        self.window = frames.max(1);
+        for history in self.history.values_mut() {
+            while history.len() > self.window {
+                history.pop_front();
+            }
+        }
+    }
+
+    /// Returns the last frame's scopes, each followed by its descendants.
+    pub fn scopes(&self) -> &[ScopeTiming] {
+        &self.scopes
+    }
+
+    /// Returns a scope of the last frame by its path, e.g. `"render/draw"`.
+    pub fn scope(&self, path: &str) -> Option<&ScopeTiming> {
+        self.scopes.iter().find(|scope| scope.path == path)
+    }
+
+    /// Returns the time between the last two frames.
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+
+    /// Forgets the averages and worst cases.
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// Collects the scopes recorded on this thread since the last frame.
+    ///
+    /// Scopes still open are carried over to the next frame.
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        self.frame_time = self
+            .last_frame
+            .map_or(Duration::ZERO, |last_frame| now - last_frame);
+        self.last_frame = Some(now);
+
+        let events = RECORDER.with_borrow_mut(|recorder| {
+            let mut events = std::mem::take(&mut recorder.events);
+            // Open scopes keep their ancestors, so they move to the front in order
+            for (new_index, index) in recorder.open.iter_mut().enumerate() {
+                let event = &events[*index];
+                recorder.events.push(ScopeEvent {
+                    name: event.name,
+                    depth: event.depth,
+                    start: event.start,
+                    duration: None,
+                });
+                *index = new_index;
+            }
+            events.retain(|event| event.duration.is_some());
+            events
+        });
+
+        // Merge repeated scopes under the same parent, in the order they first ran
+        let mut merged: Vec<(ScopeTiming, Option<usize>)> = Vec::new();
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut ancestors: Vec<usize> = Vec::new();
+        for event in &events {
+            ancestors.truncate(event.depth);
+            // A scope whose parent was still open last frame is a root
+            let parent = ancestors.last().copied();
+            let path = match parent {
+                Some(parent) => format!("{}/{}", merged[parent].0.path, event.name),
+                None => event.name.to_string(),
+            };
+            let index = *indices.entry(path.clone()).or_insert_with(|| {
+                merged.push((
+                    ScopeTiming {
+                        name: event.name,
+                        path,
+                        depth: ancestors.len(),
+                        duration: Duration::ZERO,
+                        calls: 0,
+                        average: Duration::ZERO,
+                        worst: Duration::ZERO,
+                    },
+                    parent,
+                ));
+                merged.len() - 1
+            });
+            merged[index].0.duration += event.duration.unwrap_or_default();
+            merged[index].0.calls += 1;
+            ancestors.push(index);
+        }
+
+        // Record this frame's times, counting recent scopes that did not run as zero
+        for history in self.history.values_mut() {
+            history.push_back(Duration::ZERO);
+        }
+        for (scope, _) in &merged {
+            let history = self.history.entry(scope.path.clone()).or_default();
+            match history.back_mut() {
+                Some(last) => *last = scope.duration,
+                None => history.push_back(scope.duration),
+            }
+        }
+        let window = self.window;
+        self.history.retain(|_, history| {
+            while history.len() > window {
+                history.pop_front();
+            }
+            history.iter().any(|duration| !duration.is_zero())
+        });
+        for (scope, _) in &mut merged {
+            if let Some(history) = self.history.get(&scope.path) {
+                scope.average = history.iter().sum::<Duration>() / history.len() as u32;
+                scope.worst = history.iter().copied().max().unwrap_or_default();
+            }
+        }
+
+        // Order the tree depth first, so every scope is followed by its descendants
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); merged.len()];
+        let mut stack = Vec::new();
+        for (index, (_, parent)) in merged.iter().enumerate().rev() {
+            match parent {
+                Some(parent) => children[*parent].push(index),
+                None => stack.push(index),
+            }
+        }
+        let mut order = Vec::with_capacity(merged.len());
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            stack.extend(children[index].iter().copied());
+        }
+        let mut merged: Vec<Option<ScopeTiming>> =
+            merged.into_iter().map(|(scope, _)| Some(scope)).collect();
+        self.scopes = order
+            .into_iter()
+            .filter_map(|index| merged[index].take())
+            .collect();
+    }
+
+    /// Draws the last frame's scopes as a table in the top-left corner of the canvas.
+    pub fn draw(&self, canvas: &mut Canvas) {
+        let text = self.to_string();
+        let pixel_size = OVERLAY_PIXEL_SIZE * canvas.scale_factor();
+        let padding = pixel_size * 2.0;
+        let size = text_size(&text) * pixel_size + padding * 2.0;
+        canvas.rect(
+            CanvasPoint::pixels(0.0, 0.0),
+            CanvasSize::pixels(size.x, size.y),
+            Color::BLACK.with_alpha(0.6),
+        );
+        canvas.text(
+            CanvasPoint::pixels(padding, padding),
+            &text,
+            pixel_size,
+            Color::WHITE,
+        );
+    }
+}
+
+/// Formats the last frame's scopes as an indented table of milliseconds.
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let milliseconds = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let width = self
+            .scopes
+            .iter()
+            .map(|scope| scope.depth * 2 + scope.name.len())
+            .max()
+            .unwrap_or(0)
+            .max(5);
+        write!(
+            f,
+            "{:width$} {:>7} {:>7} {:>7} {:>5}",
+            "frame", "ms", "avg", "max", "calls"
+        )?;
+        write!(f, "\n{:width$} {:>7.2}", "", milliseconds(self.frame_time))?;
+        for scope in &self.scopes {
+            let name = format!("{:indent$}{}", "", scope.name, indent = scope.depth * 2);
+            write!(
+                f,
+                "\n{name:width$} {:>7.2} {:>7.2} {:>7.2} {:>5}",
+                milliseconds(scope.duration),
+                milliseconds(scope.average),
+                milliseconds(scope.worst),
+                scope.calls
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profiler;
+    use std::time::Duration;
+
+    #[test]
+    fn test_profiler_builds_tree() {
+        let mut profiler = Profiler::new();
+        profiler.set_enabled(true);
+        {
+            profile_scope!("update");
+            for _ in 0..3 {
+                profile_scope!("physics");
+            }
+        }
+        {
+            profile_scope!("render");
+            {
+                profile_scope!("cull");
+            }
+            profile_scope!("draw");
+        }
+        // Repeated roots merge too, and their new children stay beneath them
+        {
+            profile_scope!("update");
+            profile_scope!("audio");
+        }
+        profiler.end_frame();
+
+        let paths: Vec<_> = profiler
+            .scopes()
+            .iter()
+            .map(|scope| scope.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "update",
+                "update/physics",
+                "update/audio",
+                "render",
+                "render/cull",
+                "render/draw"
+            ]
+        );
+        let update = profiler.scope("update").unwrap();
+        assert_eq!((update.calls, update.depth), (2, 0));
+        assert_eq!(profiler.scope("update/physics").unwrap().calls, 3);
+        let render = profiler.scope("render").unwrap();
+        assert!(render.duration >= profiler.scope("render/cull").unwrap().duration);
+        assert!(profiler
+            .to_string()
+            .lines()
+            .nth(3)
+            .unwrap()
+            .starts_with("  physics"));
+        profiler.set_enabled(false);
+    }
+
+    #[test]
+    fn test_profiler_rolling_statistics() {
+        let mut profiler = Profiler::new();
+        profiler.set_enabled(true);
+        profiler.set_window(2);
+        for milliseconds in [Some(20), None, Some(1)] {
+            if let Some(milliseconds) = milliseconds {
+                profile_scope!("work");
+                std::thread::sleep(Duration::from_millis(milliseconds));
+            }
+            profiler.end_frame();
+            // The scope is listed only in frames it ran in
+            assert_eq!(profiler.scope("work").is_some(), milliseconds.is_some());
+        }
+
+        // The 20 ms frame left the window of two, and the frame without the scope counts as zero
+        let work = profiler.scope("work").unwrap();
+        assert!(work.worst >= Duration::from_millis(1));
+        assert!(work.worst < Duration::from_millis(20));
+        assert_eq!(work.average, work.duration / 2);
+
+        // Scopes left open are carried over to the next frame
+        {
+            profile_scope!("open");
+            profiler.end_frame();
+            assert!(profiler.scope("open").is_none());
+        }
+        profiler.end_frame();
+        assert_eq!(profiler.scope("open").unwrap().calls, 1);
+
+        profiler.set_enabled(false);
+        {
+            profile_scope!("disabled");
+        }
+        profiler.end_frame();
+        assert!(profiler.scopes().is_empty());
+    }
+}
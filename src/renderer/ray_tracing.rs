@@ -0,0 +1,192 @@
+//! Ray tracing module for the renderer.
+//!
+//! This module provides `RayTracedShadows`, hard shadows from a directional
+//! light traced against acceleration structures built from `MeshStorage`.
+//! The renderer gathers the shadow-casting mesh instances of every frame and
+//! the backend traces one shadow ray per pixel from the depth buffer,
+//! darkening the raster frame where the light is blocked. Ray tracing is
+//! optional and only available on devices that report support for it.
+
+use super::{
+    common::{LayerMask, PrimitiveType},
+    render_queue::GeometryView,
+};
+use glam::{Mat4, Vec3};
+
+/// Hard shadows cast by a directional light, traced per pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayTracedShadows {
+    /// The world-space direction towards the light.
+    pub light_direction: Vec3,
+    /// How much shadowed pixels are darkened, from 0 for none to 1 for black.
+    pub strength: f32,
+    /// The farthest an occluder can be from a shadowed point.
+    pub max_distance: f32,
+    /// How far rays start from the surface, avoiding self-shadowing.
+    pub bias: f32,
+}
+
+impl Default for RayTracedShadows {
+    fn default() -> Self {
+        Self {
+            light_direction: Vec3::Y,
+            strength: 0.6,
+            max_distance: 1000.0,
+            bias: 0.01,
+        }
+    }
+}
+
+impl RayTracedShadows {
+    /// Creates new `RayTracedShadows` cast by a light in the given direction.
+    pub fn new(light_direction: Vec3) -> Self {
+        Self {
+            light_direction,
+            ..Self::default()
+        }
+    }
+
+    /// Sets how much shadowed pixels are darkened.
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    /// Builds the constants of the shadow pass for a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    pub fn constants(&self, view_projection: Mat4) -> ShadowRayConstants {
+        ShadowRayConstants {
+            inverse_view_projection: view_projection.inverse(),
+            light_direction: self.light_direction.normalize_or_zero().to_array(),
+            strength: self.strength.clamp(0.0, 1.0),
+            max_distance: self.max_distance,
+            bias: self.bias,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// The per-frame constants of the ray-traced shadow pass.
+///
+/// The layout matches the `ShadowRayConstants` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowRayConstants {
+    pub inverse_view_projection: Mat4,
+    pub light_direction: [f32; 3],
+    pub strength: f32,
+    pub max_distance: f32,
+    pub bias: f32,
+    _padding: [f32; 2],
+}
+
+/// A placement of a stored mesh in the ray-traced scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayTracingInstance {
+    /// The index of the mesh in `MeshStorage`.
+    pub mesh: usize,
+    pub transform: Mat4,
+}
+
+/// The ray-traced scene and shadow constants of one frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RayTracingFrame {
+    pub constants: ShadowRayConstants,
+    pub instances: Vec<RayTracingInstance>,
+}
+
+/// Returns `true` if draws on the given layers occlude ray-traced light.
+pub fn casts_shadows(layers: LayerMask) -> bool {
+    layers.intersects(LayerMask::SHADOW_CASTER)
+}
+
+/// Lists the triangles of a geometry as indices, for building an acceleration structure.
+///
+/// # Returns
+///
+/// Three indices per triangle, or `None` for points and lines, which rays cannot hit.
+pub fn triangle_indices(geometry: &GeometryView<'_>) -> Option<Vec<u32>> {
+    let indices: Vec<u32> = match geometry.indices {
+        Some(indices) => indices.to_vec(),
+        None => (0..geometry.vertices.len() as u32).collect(),
+    };
+    match geometry.primitive_type {
+        PrimitiveType::Triangle => Some(indices[..indices.len() - indices.len() % 3].to_vec()),
+        // Every other strip triangle is flipped to keep a consistent winding
+        PrimitiveType::TriangleStrip => Some(
+            indices
+                .windows(3)
+                .enumerate()
+                .flat_map(|(i, w)| {
+                    if i % 2 == 0 {
+                        [w[0], w[1], w[2]]
+                    } else {
+                        [w[1], w[0], w[2]]
+                    }
+                })
+                .collect(),
+        ),
+        PrimitiveType::Point | PrimitiveType::Line | PrimitiveType::LineStrip => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{triangle_indices, RayTracedShadows, ShadowRayConstants};
+    use crate::renderer::{
+        common::{PrimitiveType, Vertex},
+        render_queue::GeometryView,
+    };
+    use glam::{Mat4, Vec3};
+
+    fn geometry(
+        vertices: &[Vertex],
+        indices: Option<&'static [u32]>,
+        primitive_type: PrimitiveType,
+    ) -> Option<Vec<u32>> {
+        triangle_indices(&GeometryView {
+            vertices,
+            indices,
+            primitive_type,
+            packed_vertices: None,
+            positions: None,
+        })
+    }
+
+    #[test]
+    fn test_shadow_constants_layout() {
+        // Must match the struct in shader_types.h
+        assert_eq!(std::mem::size_of::<ShadowRayConstants>(), 96);
+
+        let constants = RayTracedShadows::new(Vec3::new(0.0, 0.0, 2.0))
+            .with_strength(2.0)
+            .constants(Mat4::IDENTITY);
+        assert_eq!(constants.light_direction, [0.0, 0.0, 1.0]);
+        assert_eq!(constants.strength, 1.0);
+    }
+
+    #[test]
+    fn test_triangle_indices() {
+        let vertices = [Vertex::default(); 5];
+        assert_eq!(
+            geometry(&vertices, None, PrimitiveType::Triangle),
+            Some(vec![0, 1, 2])
+        );
+        assert_eq!(
+            geometry(&vertices[..4], None, PrimitiveType::TriangleStrip),
+            Some(vec![0, 1, 2, 2, 1, 3])
+        );
+        assert_eq!(
+            geometry(
+                &vertices,
+                Some(&[4, 3, 2, 1, 0, 4]),
+                PrimitiveType::Triangle
+            ),
+            Some(vec![4, 3, 2, 1, 0, 4])
+        );
+        assert_eq!(geometry(&vertices, None, PrimitiveType::Line), None);
+    }
+}
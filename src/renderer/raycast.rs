@@ -0,0 +1,403 @@
+//! Raycast module for the renderer.
+//!
+//! This module provides rays and triangle-accurate ray casts against meshes,
+//! for picking, hit detection and measurement. A cast is tested against the
+//! mesh's bounding box first, then against every triangle, or only the
+//! triangles in the boxes a mesh's optional bounding volume hierarchy (BVH)
+//! leads it to. Meshes opt into a BVH with `MeshBuilder::with_bvh`, which
+//! pays off for meshes with many triangles that are cast against often.
+
+use super::bounds::Aabb;
+use crate::math::geometry::{closest_point_on_triangle, distance_squared_to_aabb, MeshPoint};
+use glam::{Mat4, Vec3};
+
+/// The most triangles in a BVH leaf.
+const LEAF_SIZE: usize = 4;
+
+/// A half-line from an origin along a direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// The direction, which is unit length for rays created with `new`.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// Creates a new `Ray`, normalizing the direction so distances are in world units.
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize_or_zero(),
+        }
+    }
+
+    /// Returns the point at a distance along the ray.
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Returns the ray transformed by a matrix, keeping distances along it.
+    ///
+    /// The direction is not renormalized, so a point at a distance along the
+    /// transformed ray is the transformed point at that distance.
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        Self {
+            origin: matrix.transform_point3(self.origin),
+            direction: matrix.transform_vector3(self.direction),
+        }
+    }
+
+    /// Returns the distance at which the ray enters a box, if it does so
+    /// before `max_distance`, or zero if it starts inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb, max_distance: f32) -> Option<f32> {
+        // Slab test; an infinite reciprocal keeps parallel axes working
+        let inverse = self.direction.recip();
+        let t0 = (aabb.min - self.origin) * inverse;
+        let t1 = (aabb.max - self.origin) * inverse;
+        let near = t0.min(t1).max_element().max(0.0);
+        let far = t0.max(t1).min_element().min(max_distance);
+        (near <= far).then_some(near)
+    }
+
+    /// Intersects the ray with a triangle, from either side.
+    ///
+    /// # Returns
+    ///
+    /// The distance to the hit and the barycentric weights of the triangle's
+    /// corners at the hit, or `None` if the ray misses.
+    pub fn intersect_triangle(&self, [a, b, c]: [Vec3; 3]) -> Option<(f32, Vec3)> {
+        // Möller-Trumbore
+        let (edge_ab, edge_ac) = (b - a, c - a);
+        let p = self.direction.cross(edge_ac);
+        let determinant = edge_ab.dot(p);
+        if determinant.abs() < f32::EPSILON * edge_ab.length() * edge_ac.length() {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let offset = self.origin - a;
+        let u = offset.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = offset.cross(edge_ab);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = edge_ac.dot(q) * inverse;
+        (distance >= 0.0).then_some((distance, Vec3::new(1.0 - u - v, u, v)))
+    }
+}
+
+/// A ray's hit on a mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// The distance along the ray, in units of its direction's length.
+    pub distance: f32,
+    /// The index of the triangle hit, in the order the mesh's primitives draw them.
+    pub triangle: usize,
+    /// The weights of the triangle's three vertices at the hit.
+    pub barycentrics: Vec3,
+}
+
+/// A node of a BVH, which is a leaf if it has triangles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BvhNode {
+    bounds: Aabb,
+    /// The first triangle of a leaf, or the left child of an inner node,
+    /// whose right child follows it.
+    first: u32,
+    count: u32,
+}
+
+/// A bounding volume hierarchy over the triangles of a mesh.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Triangle indices, ordered so every leaf's triangles are contiguous.
+    triangles: Vec<u32>,
+}
+
+impl Bvh {
+    /// Builds a BVH by splitting triangles at the median of their centroids
+    /// along the longest axis, until leaves are small.
+    ///
+    /// # Arguments
+    ///
+    /// * `triangles` - The corners of every triangle, by triangle index.
+    pub fn new(triangles: &[[Vec3; 3]]) -> Self {
+        let bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|corners| Aabb::from_points(*corners).unwrap())
+            .collect();
+        let mut order: Vec<u32> = (0..triangles.len() as u32).collect();
+        let mut nodes = Vec::new();
+        if triangles.is_empty() {
+            return Self::default();
+        }
+
+        nodes.push(BvhNode {
+            bounds: Aabb::default(),
+            first: 0,
+            count: 0,
+        });
+        let mut stack = vec![(0, 0..order.len())];
+        while let Some((node, range)) = stack.pop() {
+            let slice = &mut order[range.clone()];
+            let node_bounds = slice
+                .iter()
+                .map(|&triangle| bounds[triangle as usize])
+                .reduce(|a, b| a.union(&b))
+                .unwrap();
+            nodes[node].bounds = node_bounds;
+            if slice.len() <= LEAF_SIZE {
+                nodes[node].first = range.start as u32;
+                nodes[node].count = slice.len() as u32;
+                continue;
+            }
+
+            let centroids = Aabb::from_points(
+                slice
+                    .iter()
+                    .map(|&triangle| bounds[triangle as usize].center()),
+            )
+            .unwrap();
+            let extent = centroids.max - centroids.min;
+            let axis = if extent.x >= extent.y.max(extent.z) {
+                0
+            } else if extent.y >= extent.z {
+                1
+            } else {
+                2
+            };
+            let middle = slice.len() / 2;
+            slice.select_nth_unstable_by(middle, |&a, &b| {
+                let a = bounds[a as usize].center()[axis];
+                let b = bounds[b as usize].center()[axis];
+                a.total_cmp(&b)
+            });
+
+            let left = nodes.len();
+            nodes[node].first = left as u32;
+            let child = BvhNode {
+                bounds: Aabb::default(),
+                first: 0,
+                count: 0,
+            };
+            nodes.extend([child; 2]);
+            stack.push((left, range.start..range.start + middle));
+            stack.push((left + 1, range.start + middle..range.end));
+        }
+
+        Self {
+            nodes,
+            triangles: order,
+        }
+    }
+
+    /// Returns the number of nodes in the hierarchy.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the hierarchy has no triangles.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Finds the nearest hit, visiting only nodes the ray passes through
+    /// closer than the nearest hit so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `ray` - The ray, in the space the BVH was built in.
+    /// * `corners` - Returns the corners of a triangle by index.
+    pub fn raycast(&self, ray: &Ray, corners: impl Fn(usize) -> [Vec3; 3]) -> Option<RayHit> {
+        let mut nearest: Option<RayHit> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            let max_distance = nearest.map_or(f32::INFINITY, |hit| hit.distance);
+            if ray.intersect_aabb(&node.bounds, max_distance).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first as usize, node.first as usize + 1]);
+                continue;
+            }
+            let range = node.first as usize..(node.first + node.count) as usize;
+            for &triangle in &self.triangles[range] {
+                nearest = nearest_hit(nearest, ray, triangle as usize, corners(triangle as usize));
+            }
+        }
+        nearest
+    }
+
+    /// Visits the triangles in the leaves whose boxes overlap a box, which
+    /// may include triangles outside the box itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `bounds` - The box, in the space the BVH was built in.
+    /// * `visit` - Called with the index of each triangle.
+    pub fn overlapping(&self, bounds: &Aabb, mut visit: impl FnMut(usize)) {
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            if !node.bounds.intersects(bounds) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first as usize, node.first as usize + 1]);
+                continue;
+            }
+            let range = node.first as usize..(node.first + node.count) as usize;
+            for &triangle in &self.triangles[range] {
+                visit(triangle as usize);
+            }
+        }
+    }
+
+    /// Finds the point of the triangles closest to a point, visiting only
+    /// nodes whose box is closer than the closest point so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The query point in world space.
+    /// * `transform` - The model to world matrix of the triangles.
+    /// * `corners` - Returns the world-space corners of a triangle by index.
+    pub fn closest_point(
+        &self,
+        point: Vec3,
+        transform: &Mat4,
+        corners: impl Fn(usize) -> [Vec3; 3],
+    ) -> Option<MeshPoint> {
+        let mut closest: Option<MeshPoint> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            // The transformed box encloses the node, so it is never further away
+            let bounds = node.bounds.transformed(transform);
+            if closest.is_some_and(|closest| {
+                distance_squared_to_aabb(point, &bounds) >= closest.distance * closest.distance
+            }) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first as usize, node.first as usize + 1]);
+                continue;
+            }
+            let range = node.first as usize..(node.first + node.count) as usize;
+            for &triangle in &self.triangles[range] {
+                closest = closest_triangle_point(
+                    closest,
+                    point,
+                    triangle as usize,
+                    corners(triangle as usize),
+                );
+            }
+        }
+        closest
+    }
+}
+
+/// Returns the closer of a previous closest point and the closest point of a triangle.
+pub fn closest_triangle_point(
+    closest: Option<MeshPoint>,
+    point: Vec3,
+    triangle: usize,
+    corners: [Vec3; 3],
+) -> Option<MeshPoint> {
+    let candidate = closest_point_on_triangle(point, corners);
+    let distance = point.distance(candidate);
+    if closest.is_some_and(|closest| closest.distance <= distance) {
+        return closest;
+    }
+    Some(MeshPoint {
+        point: candidate,
+        distance,
+        triangle,
+    })
+}
+
+/// Returns the nearer of a previous hit and the ray's hit on a triangle.
+pub fn nearest_hit(
+    nearest: Option<RayHit>,
+    ray: &Ray,
+    triangle: usize,
+    corners: [Vec3; 3],
+) -> Option<RayHit> {
+    match ray.intersect_triangle(corners) {
+        Some((distance, barycentrics))
+            if !nearest.is_some_and(|nearest| nearest.distance <= distance) =>
+        {
+            Some(RayHit {
+                distance,
+                triangle,
+                barycentrics,
+            })
+        }
+        _ => nearest,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bvh, Ray};
+    use crate::renderer::bounds::Aabb;
+    use glam::Vec3;
+
+    /// A row of unit triangles in the xy plane, one per x.
+    fn triangles(count: usize) -> Vec<[Vec3; 3]> {
+        (0..count)
+            .map(|index| {
+                let x = index as f32;
+                [
+                    Vec3::new(x, 0.0, 0.0),
+                    Vec3::new(x + 1.0, 0.0, 0.0),
+                    Vec3::new(x, 1.0, 0.0),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ray_intersections() {
+        let ray = Ray::new(Vec3::new(0.25, 0.25, 5.0), -Vec3::Z * 2.0);
+        assert_eq!(ray.direction, -Vec3::Z);
+        let (distance, barycentrics) = ray.intersect_triangle(triangles(1)[0]).unwrap();
+        assert_eq!(distance, 5.0);
+        assert_eq!(barycentrics, Vec3::new(0.5, 0.25, 0.25));
+        assert!(Ray::new(Vec3::new(0.75, 0.75, 5.0), -Vec3::Z)
+            .intersect_triangle(triangles(1)[0])
+            .is_none());
+
+        let aabb = Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+        assert_eq!(ray.intersect_aabb(&aabb, f32::INFINITY), Some(4.0));
+        assert_eq!(ray.intersect_aabb(&aabb, 3.0), None);
+        // Parallel to the y and z slabs, starting inside them
+        let inside = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(inside.intersect_aabb(&aabb, f32::INFINITY), Some(4.0));
+    }
+
+    #[test]
+    fn test_bvh_matches_brute_force() {
+        let triangles = triangles(37);
+        let bvh = Bvh::new(&triangles);
+        assert!(bvh.len() > 1);
+        for index in 0..40 {
+            let ray = Ray::new(Vec3::new(index as f32 + 0.2, 0.3, 1.0), -Vec3::Z);
+            let hit = bvh.raycast(&ray, |triangle| triangles[triangle]);
+            assert_eq!(hit.map(|hit| hit.triangle), (index < 37).then_some(index));
+        }
+        assert!(Bvh::new(&[])
+            .raycast(&Ray::new(Vec3::ZERO, Vec3::X), |_| unreachable!())
+            .is_none());
+    }
+}
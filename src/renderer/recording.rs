@@ -0,0 +1,418 @@
+//! Frame recording module for the renderer.
+//!
+//! This module provides the `Recorder`, which writes presented frames to disk
+//! so simulations can be captured without an external screen recorder. The
+//! backend copies each frame to CPU memory without stalling the GPU, and the
+//! recorder encodes the frames on a worker thread.
+//!
+//! Frames are written as a numbered PNG sequence into a directory. With the
+//! `ffmpeg` feature, paths ending in `.mp4` are encoded to H.264 video by
+//! piping raw frames into an `ffmpeg` process, which must be on the `PATH`.
+//!
+//! PNGs are written without compression to keep encoding cheap enough for
+//! every frame, so sequences are large; compress them afterwards if needed.
+
+use super::error::RecordingError;
+use log::{debug, info};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// The largest payload of a stored deflate block.
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// A frame copied back from the GPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameImage {
+    pub width: u32,
+    pub height: u32,
+    /// sRGB encoded BGRA8 pixels, tightly packed, with rows from top to bottom.
+    pub pixels: Vec<u8>,
+}
+
+impl FrameImage {
+    /// Writes the frame to an uncompressed PNG file.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), RecordingError> {
+        let path = path.as_ref();
+        fs::write(path, encode_png(self)).map_err(|source| RecordingError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        info!(
+            "Saved {}x{} image to {}",
+            self.width,
+            self.height,
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Where and how frames are encoded.
+#[derive(Clone, Debug, PartialEq)]
+enum RecordingOutput {
+    /// A directory receiving `frame_00000.png`, `frame_00001.png`, ...
+    PngSequence(PathBuf),
+    /// A video file encoded by `ffmpeg`.
+    #[cfg(feature = "ffmpeg")]
+    Video(PathBuf),
+}
+
+impl RecordingOutput {
+    /// Selects the output format from the path's extension.
+    fn from_path(path: &Path) -> Result<Self, RecordingError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            None => Ok(RecordingOutput::PngSequence(path.to_path_buf())),
+            #[cfg(feature = "ffmpeg")]
+            Some("mp4") => Ok(RecordingOutput::Video(path.to_path_buf())),
+            Some(_) => Err(RecordingError::UnsupportedFormat(path.to_path_buf())),
+        }
+    }
+}
+
+/// Encodes frames to disk on a worker thread, at a fixed frame rate.
+pub struct Recorder {
+    sender: Sender<FrameImage>,
+    worker: JoinHandle<Result<usize, RecordingError>>,
+    frame_interval: Duration,
+    next_frame_time: Option<Instant>,
+}
+
+impl Recorder {
+    /// Starts a recording.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A directory for a PNG sequence, or an `.mp4` file with the `ffmpeg` feature.
+    /// * `fps` - The frame rate of the recording. Frames rendered faster are dropped.
+    ///
+    /// # Returns
+    ///
+    /// The recorder, or an error if the format is unsupported or the output
+    /// cannot be created.
+    pub fn start(path: &Path, fps: u32) -> Result<Self, RecordingError> {
+        let output = RecordingOutput::from_path(path)?;
+        match &output {
+            RecordingOutput::PngSequence(directory) => {
+                fs::create_dir_all(directory).map_err(|source| RecordingError::Io {
+                    path: directory.clone(),
+                    source,
+                })?;
+            }
+            #[cfg(feature = "ffmpeg")]
+            RecordingOutput::Video(_) => {}
+        }
+
+        let (sender, receiver) = mpsc::channel::<FrameImage>();
+        let worker = thread::spawn(move || {
+            let mut encoder = FrameEncoder::new(output, fps);
+            for frame in receiver {
+                encoder.encode(&frame)?;
+            }
+            encoder.finish()
+        });
+
+        info!("Recording started: {} at {} fps", path.display(), fps);
+        Ok(Recorder {
+            sender,
+            worker,
+            frame_interval: Duration::from_secs_f64(1.0 / fps.max(1) as f64),
+            next_frame_time: None,
+        })
+    }
+
+    /// Queues a frame for encoding if it is due at the recording frame rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to record.
+    /// * `now` - The time at which the frame was presented.
+    ///
+    /// # Returns
+    ///
+    /// An error if the encoder has stopped, e.g. after a write failure.
+    pub fn push(&mut self, frame: FrameImage, now: Instant) -> Result<(), RecordingError> {
+        if self.next_frame_time.is_some_and(|next| now < next) {
+            return Ok(());
+        }
+        // Frames arriving slower than the frame rate are all recorded
+        let next = self.next_frame_time.unwrap_or(now) + self.frame_interval;
+        self.next_frame_time = Some(next.max(now));
+
+        self.sender.send(frame).map_err(|_| {
+            RecordingError::EncoderFailed("the encoder thread has stopped".to_string())
+        })
+    }
+
+    /// Stops the recording once all queued frames are encoded.
+    ///
+    /// # Returns
+    ///
+    /// The number of frames written, or the error that stopped the encoder.
+    pub fn finish(self) -> Result<usize, RecordingError> {
+        drop(self.sender);
+        let frame_count = self.worker.join().map_err(|_| {
+            RecordingError::EncoderFailed("the encoder thread panicked".to_string())
+        })??;
+        info!("Recording finished: {} frames written", frame_count);
+        Ok(frame_count)
+    }
+}
+
+/// Writes frames in the recording's output format.
+struct FrameEncoder {
+    output: RecordingOutput,
+    #[cfg_attr(not(feature = "ffmpeg"), allow(dead_code))]
+    fps: u32,
+    frame_count: usize,
+    #[cfg(feature = "ffmpeg")]
+    ffmpeg: Option<(std::process::Child, u32, u32)>,
+}
+
+impl FrameEncoder {
+    fn new(output: RecordingOutput, fps: u32) -> Self {
+        FrameEncoder {
+            output,
+            fps,
+            frame_count: 0,
+            #[cfg(feature = "ffmpeg")]
+            ffmpeg: None,
+        }
+    }
+
+    fn encode(&mut self, frame: &FrameImage) -> Result<(), RecordingError> {
+        match &self.output {
+            RecordingOutput::PngSequence(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", self.frame_count));
+                fs::write(&path, encode_png(frame))
+                    .map_err(|source| RecordingError::Io { path, source })?;
+            }
+            #[cfg(feature = "ffmpeg")]
+            RecordingOutput::Video(path) => {
+                let path = path.clone();
+                if !self.write_video_frame(&path, frame)? {
+                    return Ok(());
+                }
+            }
+        }
+        debug!("Recorded frame {}", self.frame_count);
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Pipes a frame into `ffmpeg`, starting it on the first frame.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the frame was skipped because the window was resized.
+    #[cfg(feature = "ffmpeg")]
+    fn write_video_frame(
+        &mut self,
+        path: &Path,
+        frame: &FrameImage,
+    ) -> Result<bool, RecordingError> {
+        use log::warn;
+        use std::{
+            io::Write,
+            process::{Command, Stdio},
+        };
+
+        let io_error = |source| RecordingError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if self.ffmpeg.is_none() {
+            let child = Command::new("ffmpeg")
+                .args([
+                    "-y",
+                    "-loglevel",
+                    "error",
+                    "-f",
+                    "rawvideo",
+                    "-pix_fmt",
+                    "bgra",
+                ])
+                .args(["-video_size", &format!("{}x{}", frame.width, frame.height)])
+                .args(["-framerate", &self.fps.to_string(), "-i", "-"])
+                .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                .arg(path)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(io_error)?;
+            self.ffmpeg = Some((child, frame.width, frame.height));
+        }
+
+        let Some((child, width, height)) = &mut self.ffmpeg else {
+            unreachable!()
+        };
+        if (frame.width, frame.height) != (*width, *height) {
+            warn!(
+                "Skipping {}x{} frame in {}x{} video",
+                frame.width, frame.height, width, height
+            );
+            return Ok(false);
+        }
+        let stdin = child.stdin.as_mut().expect("ffmpeg stdin is piped");
+        stdin.write_all(&frame.pixels).map_err(io_error)?;
+        Ok(true)
+    }
+
+    fn finish(self) -> Result<usize, RecordingError> {
+        #[cfg(feature = "ffmpeg")]
+        if let (Some((mut child, _, _)), RecordingOutput::Video(path)) = (self.ffmpeg, &self.output)
+        {
+            // Closing stdin ends the input stream
+            drop(child.stdin.take());
+            let status = child.wait().map_err(|source| RecordingError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            if !status.success() {
+                return Err(RecordingError::EncoderFailed(format!(
+                    "ffmpeg exited with {status}"
+                )));
+            }
+        }
+        Ok(self.frame_count)
+    }
+}
+
+/// Encodes a frame as an uncompressed 8-bit RGBA PNG.
+fn encode_png(frame: &FrameImage) -> Vec<u8> {
+    let row_length = frame.width as usize * 4;
+
+    // Each row starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity((row_length + 1) * frame.height as usize);
+    for row in frame.pixels.chunks_exact(row_length.max(1)) {
+        raw.push(0);
+        for bgra in row.chunks_exact(4) {
+            raw.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&frame.width.to_be_bytes());
+    header.extend_from_slice(&frame.height.to_be_bytes());
+    // Bit depth 8, color type 6 (RGBA), default compression, filter and interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let block_count = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut stream = Vec::with_capacity(data.len() + 5 * block_count + 6);
+    stream.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(is_final as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MODULUS;
+        b = (b + a) % MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, encode_png, FrameImage, Recorder};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_encode_png() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+
+        let frame = FrameImage {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 0, 255, 128],
+        };
+        let png = encode_png(&frame);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(&png[png.len() - 4..], &0xae42_6082u32.to_be_bytes());
+
+        // The single stored block holds the filter byte and the RGBA pixels
+        let idat = 8 + 25 + 8;
+        let pixels = &png[idat + 2 + 5..idat + 2 + 5 + 9];
+        assert_eq!(pixels, &[0, 0, 0, 255, 255, 255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn test_recorder_writes_png_sequence() {
+        let directory = std::env::temp_dir().join(format!("recording_{}", std::process::id()));
+        let frame = FrameImage {
+            width: 1,
+            height: 1,
+            pixels: vec![0, 0, 0, 255],
+        };
+
+        // At 10 fps, a frame 50 ms after the first is dropped
+        let mut recorder = Recorder::start(&directory, 10).unwrap();
+        let start = Instant::now();
+        for offset in [0, 50, 100] {
+            recorder
+                .push(frame.clone(), start + Duration::from_millis(offset))
+                .unwrap();
+        }
+        assert_eq!(recorder.finish().unwrap(), 2);
+        assert!(directory.join("frame_00001.png").exists());
+        assert!(!directory.join("frame_00002.png").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(Recorder::start(&directory.with_extension("gif"), 30).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Planar reflection module for the renderer.
+//!
+//! This module provides `PlanarReflection`, a mirror plane such as a water
+//! surface or a polished floor. Every frame, before the main camera draws,
+//! the scene is drawn a second time mirrored across the plane into the
+//! reflection's texture, from the main camera's point of view. The mirrored
+//! camera's near plane is replaced with the reflection plane (an oblique
+//! projection), so geometry behind the mirror, e.g. below the water, cannot
+//! appear in the reflection. Materials show the reflection with
+//! `Material::with_reflection`, sampling the texture where the fragment
+//! lies on screen.
+//!
+//! Reflections are skipped while the camera is behind the plane, and are
+//! drawn for the main camera only, so split-screen views of a reflective
+//! surface show the main camera's reflection. The reflective surface itself
+//! should be on a layer outside the reflection's cull mask, since it cannot
+//! reflect itself.
+
+use super::{
+    camera::Camera,
+    common::{LayerMask, TextureId},
+};
+use glam::{Mat4, UVec2, Vec3, Vec4};
+
+/// The resolution of reflection textures unless set.
+const DEFAULT_RESOLUTION: UVec2 = UVec2::new(1024, 1024);
+
+/// A mirror plane whose reflection of the scene is drawn every frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlanarReflection {
+    /// The unit normal of the plane, pointing to the side that is reflected.
+    pub normal: Vec3,
+    /// The distance of the plane from the origin along its normal.
+    pub distance: f32,
+    /// The size of the reflection texture in pixels.
+    pub resolution: UVec2,
+    /// The layers drawn into the reflection.
+    pub cull_mask: LayerMask,
+}
+
+impl PlanarReflection {
+    /// Creates a new `PlanarReflection` through a point.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - A point on the plane.
+    /// * `normal` - The direction the plane faces, towards the reflected side.
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        let normal = normal.normalize();
+        PlanarReflection {
+            normal,
+            distance: normal.dot(point),
+            resolution: DEFAULT_RESOLUTION,
+            cull_mask: LayerMask::DEFAULT,
+        }
+    }
+
+    /// Sets the size of the reflection texture in pixels.
+    pub fn with_resolution(mut self, resolution: UVec2) -> Self {
+        self.resolution = resolution.max(UVec2::ONE);
+        self
+    }
+
+    /// Sets the layers drawn into the reflection.
+    pub fn with_cull_mask(mut self, cull_mask: LayerMask) -> Self {
+        self.cull_mask = cull_mask;
+        self
+    }
+
+    /// Returns the plane as `(normal, -distance)`, positive on the reflected side.
+    pub fn plane(&self) -> Vec4 {
+        self.normal.extend(-self.distance)
+    }
+
+    /// Returns the matrix mirroring points across the plane.
+    pub fn reflection_matrix(&self) -> Mat4 {
+        let n = self.normal;
+        Mat4::from_cols(
+            (Vec3::X - 2.0 * n.x * n).extend(0.0),
+            (Vec3::Y - 2.0 * n.y * n).extend(0.0),
+            (Vec3::Z - 2.0 * n.z * n).extend(0.0),
+            (2.0 * self.distance * n).extend(1.0),
+        )
+    }
+
+    /// Returns the view projection matrix drawing the reflection seen by a camera.
+    ///
+    /// The projection's near plane is the reflection plane, so only geometry
+    /// on the reflected side is drawn, at depths from 0 on the plane to 1 on
+    /// the camera's far plane (Lengyel 2005). Mirroring flips the winding of
+    /// triangles, which the backend undoes for reflection passes.
+    ///
+    /// # Returns
+    ///
+    /// The matrix, or `None` if the camera is not in front of the plane.
+    pub fn view_projection(&self, camera: &Camera) -> Option<Mat4> {
+        if self.plane().dot(camera.position().extend(1.0)) <= 0.0 {
+            return None;
+        }
+        let view = camera.get_view_matrix() * self.reflection_matrix();
+        let mut projection = camera.get_projection_matrix();
+
+        // The plane in the mirrored view space, with the mirrored camera behind it
+        let clip_plane = view.inverse().transpose() * self.plane();
+        let corner = projection.inverse()
+            * Vec4::new(clip_plane.x.signum(), clip_plane.y.signum(), 1.0, 1.0);
+        let near = clip_plane / clip_plane.dot(corner);
+        // Replace the row producing depth
+        projection.x_axis.z = near.x;
+        projection.y_axis.z = near.y;
+        projection.z_axis.z = near.z;
+        projection.w_axis.z = near.w;
+        Some(projection * view)
+    }
+}
+
+/// Identifies a planar reflection added to the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReflectionId(pub usize);
+
+/// A planar reflection and the texture it is drawn into.
+struct Reflection {
+    reflection: PlanarReflection,
+    texture: TextureId,
+}
+
+/// The planar reflections of a renderer, indexed by `ReflectionId`.
+#[derive(Default)]
+pub struct Reflections {
+    reflections: Vec<Option<Reflection>>,
+}
+
+impl Reflections {
+    /// Adds a reflection drawn into a texture and returns its handle.
+    pub fn add(&mut self, reflection: PlanarReflection, texture: TextureId) -> ReflectionId {
+        self.reflections.push(Some(Reflection {
+            reflection,
+            texture,
+        }));
+        ReflectionId(self.reflections.len() - 1)
+    }
+
+    /// Removes a reflection, returning its texture if it existed.
+    pub fn remove(&mut self, id: ReflectionId) -> Option<TextureId> {
+        let reflection = self.reflections.get_mut(id.0)?.take()?;
+        Some(reflection.texture)
+    }
+
+    /// Returns a reflection for modification.
+    pub fn get_mut(&mut self, id: ReflectionId) -> Option<&mut PlanarReflection> {
+        Some(&mut self.reflections.get_mut(id.0)?.as_mut()?.reflection)
+    }
+
+    /// Returns the texture a reflection is drawn into.
+    pub fn texture(&self, id: ReflectionId) -> Option<TextureId> {
+        Some(self.reflections.get(id.0)?.as_ref()?.texture)
+    }
+
+    /// Returns `true` if there are no reflections.
+    pub fn is_empty(&self) -> bool {
+        self.reflections.iter().all(Option::is_none)
+    }
+
+    /// Returns every reflection with its texture, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&PlanarReflection, TextureId)> {
+        self.reflections
+            .iter()
+            .flatten()
+            .map(|reflection| (&reflection.reflection, reflection.texture))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PlanarReflection;
+    use crate::renderer::camera::Camera;
+    use glam::Vec3;
+
+    #[test]
+    fn test_reflection_matrix() {
+        let water = PlanarReflection::new(Vec3::new(0.0, 2.0, 0.0), Vec3::Y);
+        assert_eq!(water.distance, 2.0);
+        let matrix = water.reflection_matrix();
+        assert!((matrix.determinant() + 1.0).abs() < 1e-6);
+        let mirrored = matrix.transform_point3(Vec3::new(1.0, 5.0, -3.0));
+        assert!(mirrored.abs_diff_eq(Vec3::new(1.0, -1.0, -3.0), 1e-6));
+    }
+
+    #[test]
+    fn test_oblique_view_projection() {
+        let water = PlanarReflection::new(Vec3::ZERO, Vec3::Y);
+        let camera = Camera::new(Vec3::new(0.0, 2.0, 5.0), 60.0, 1.0, 0.1, 100.0);
+        let view_projection = water.view_projection(&camera).unwrap();
+        let depth = |point: Vec3| view_projection.project_point3(point).z;
+
+        // Points on the plane lie on the near plane, points above it are drawn
+        // and points below it are clipped
+        assert!(depth(Vec3::new(0.0, 0.0, -5.0)).abs() < 1e-4);
+        assert!((0.0..1.0).contains(&depth(Vec3::new(0.0, 1.0, -5.0))));
+        assert!(depth(Vec3::new(0.0, -1.0, -5.0)) < 0.0);
+
+        // A point above the water appears where its mirror image below it is seen
+        let mirrored = view_projection.project_point3(Vec3::new(1.0, 1.0, -5.0));
+        let main = camera.get_projection_matrix() * camera.get_view_matrix();
+        let expected = main.project_point3(Vec3::new(1.0, -1.0, -5.0));
+        assert!(mirrored.truncate().abs_diff_eq(expected.truncate(), 1e-4));
+
+        let underwater = Camera::new(Vec3::new(0.0, -2.0, 5.0), 60.0, 1.0, 0.1, 100.0);
+        assert_eq!(water.view_projection(&underwater), None);
+    }
+}
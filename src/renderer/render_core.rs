@@ -1,425 +1,4401 @@
 use super::{
+    ambient::AmbientLight,
+    atmosphere::Atmosphere,
     backend::GraphicsBackend,
-    common::{BackendDrawCommand, IndexType, PrimitiveType, Uniforms, Vertex},
+    bounds::Aabb,
+    canvas::{Anchor, Canvas, CanvasPoint, CanvasSize},
+    common::{
+        CaptureDestination, FrameConstants, LayerMask, PrimitiveType, RenderTarget,
+        TemporalConstants, TextureId, Uniforms, Vertex,
+    },
+    config::{AntiAliasing, AssetPaths, RendererConfig, Transparency},
+    debug_view::{append_normal_lines, DebugView},
+    display_link::{max_refresh_rate, DisplayFrame, FrameRateRange},
+    dynamic_mesh::{DynamicMesh, DynamicMeshId, DynamicMeshes},
+    editor::EditorMode,
+    error::{error_chain, AssetError, BackendError, ExportError, RecordingError, SceneError},
+    export::write_gltf,
+    fluid_view::{sphere_mesh, FluidView, FluidViewId, FluidViews},
+    font::LINE_ADVANCE,
+    gpu_culling::GpuCulling,
+    labels::{Label, LabelId, LabelStyle, LabelTarget, Labels},
+    lens_flare::{FlareLight, LensFlare},
+    light_gizmos::{append_frustum_lines, LightGizmo, LightShape},
+    lights::{Light, LightManager},
+    material_manager::{Material, MaterialId, MaterialManager},
+    memory_report::GpuMemoryReport,
     mesh::{Mesh, MeshStorage},
-    render_queue::DrawCommand,
+    minimap::Minimap,
+    panorama::{CubeFace, CubeMap},
+    particle_file::{ParticleFile, ParticleFiles},
+    particles::{EmitterSource, ParticleEmitter, ParticleEmitterId, ParticleEmitters},
+    plots::{Plot, PlotStyle, Plots},
+    profiler::Profiler,
+    ray_tracing::{casts_shadows, RayTracedShadows, RayTracingFrame, RayTracingInstance},
+    raycast::{Ray, RayHit},
+    recording::{FrameImage, Recorder},
+    reflection::{PlanarReflection, ReflectionId, Reflections},
+    render_queue::{DrawCommandBuilder, GeometryHandle},
+    render_scale::{scaled_size, RenderScale, RenderScaler},
+    render_state::{BlendMode, RenderState},
+    scene_file::SceneDocument,
+    scene_graph::{NodeId, SceneGraph},
+    selection::{SelectionEvent, SelectionMode},
+    shadow_map::{CascadedShadows, ShadowCasters},
     shape_builders::{
         shape_builder::{vec3_color_to_vertex, ShapeData},
         MeshBuilder, TriangleBuilder,
     },
+    sky::{Sky, SunCallback, SunLight},
+    texture_upload::TextureUpload,
+    time::Time,
+    trail::{Trail, TrailId, Trails},
+    upload_scheduler::{UploadScheduler, DEFAULT_UPLOAD_BUDGET},
+    validation::{validate_draw, ValidationError},
+    vertex_layout::{VertexFormat, VertexLayout, VertexSemantic},
+    viewport::{View, ViewId, Viewport, Views},
     Camera, Color, RendererError,
 };
+#[cfg(feature = "windowing")]
+use super::{
+    input::{Action, InputBindings},
+    replay::InputEvent,
+};
 use crate::{
-    debug_trace,
-    renderer::{backend::metal::MetalBackend, camera::CameraMovement, render_queue::RenderQueue},
+    debug_trace, logging,
+    physics::{
+        collider::{Collider, ColliderShape},
+        floating_origin::FloatingOrigin,
+        triangle_mesh::TriangleMesh,
+    },
+    profile_scope,
+    renderer::{backend::metal::MetalBackend, render_queue::RenderQueue},
 };
-use glam::Vec3;
-use log::info;
-use std::{cell::RefCell, mem, rc::Rc, time::Instant};
-use winit::{
-    dpi::PhysicalSize,
-    event::{Event, KeyEvent, MouseScrollDelta, WindowEvent},
-    event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowBuilder},
+use glam::{Mat4, Quat, UVec2, Vec2, Vec3};
+use log::{debug, error, info, warn, Level};
+use metal::{MTLPixelFormat, MTLStorageMode, MTLTextureUsage, TextureDescriptor};
+use raw_window_handle::HasWindowHandle;
+use std::{
+    collections::HashMap, ffi::c_void, fs, path::Path, ptr::NonNull, sync::Arc, time::Instant,
 };
+#[cfg(feature = "windowing")]
+use winit::keyboard::KeyCode;
 
-pub struct Renderer {
-    backend: MetalBackend,
+/// Renders the scene graph and immediate draws through a graphics backend.
+///
+/// The backend defaults to Metal. Tests can use `NullBackend` to check the
+/// render path without a GPU.
+pub struct Renderer<B: GraphicsBackend = MetalBackend> {
+    backend: B,
     mesh_storage: MeshStorage,
+    /// Holds back mesh and texture uploads beyond the per-frame budget.
+    upload_scheduler: UploadScheduler,
     render_queue: RenderQueue,
-    // TODO: implement Material Manager and Scene Graph
-    // material_manager: MaterialManager,
-    // scene_graph: SceneGraph,
-    window: Window,
+    material_manager: MaterialManager,
+    scene_graph: SceneGraph,
+    editor: EditorMode,
+    show_bounds: bool,
+    show_normals: bool,
+    debug_view: DebugView,
+    anti_aliasing: AntiAliasing,
+    render_scaler: RenderScaler,
+    /// The unjittered view projection of the last frame, for temporal reprojection.
+    previous_view_projection: Option<Mat4>,
+    lens_flare: Option<LensFlare>,
+    /// The 2D overlay drawn over the next frame.
+    canvas: Canvas,
+    labels: Labels,
+    plots: Plots,
+    trails: Trails,
+    dynamic_meshes: DynamicMeshes,
+    fluid_views: FluidViews,
+    /// The sphere fluid particles are drawn with, created with the first fluid view.
+    fluid_sphere: Option<usize>,
+    particle_emitters: ParticleEmitters,
+    /// The files of emitters added with `load_particle_emitter`, reloaded when they change.
+    particle_files: ParticleFiles,
+    particle_textures: HashMap<String, TextureId>,
+    /// The material particles are drawn with, per texture.
+    particle_materials: HashMap<Option<TextureId>, MaterialId>,
+    /// The quad particles are drawn as, added with the first emitter.
+    particle_quad: Option<usize>,
+    profiler: Profiler,
+    show_profiler: bool,
+    show_log_console: bool,
+    /// Whether the backend has the last frame's canvas, which an empty canvas removes.
+    overlay_drawn: bool,
+    atmosphere: Option<Atmosphere>,
+    sky: Option<Sky>,
+    ambient_light: AmbientLight,
+    gpu_culling: Option<GpuCulling>,
+    depth_prepass: bool,
+    vsync: bool,
+    /// The display refresh the current frame is shown at, if driven by a display link.
+    display_frame: Option<DisplayFrame>,
+    frame_rate_range: Option<FrameRateRange>,
+    /// The frame rate the backend paces presented frames to, chosen from `frame_rate_range`.
+    frame_rate: Option<f32>,
+    /// The view projection matrix of the last frame, whose depth occlusion culling tests against.
+    occlusion_view_projection: Option<Mat4>,
+    sun_callback: Option<Box<SunCallback>>,
+    ray_traced_shadows: Option<RayTracedShadows>,
+    cascaded_shadows: Option<CascadedShadows>,
+    lights: LightManager,
+    /// Meshes before this index have ray tracing acceleration structures.
+    ray_traced_mesh_count: usize,
+    pending_capture: Option<CaptureDestination>,
+    recorder: Option<Recorder>,
+    validation: bool,
+    validation_errors: Vec<ValidationError>,
+    error_handler: Option<Box<ErrorHandler>>,
+    #[cfg(feature = "windowing")]
+    input_bindings: InputBindings,
+    asset_paths: AssetPaths,
+    viewport_size: UVec2,
+    /// Physical pixels per logical point of the display the frames are shown on.
+    scale_factor: f64,
     camera: Camera,
-    last_frame_time: std::time::Instant,
+    /// Split-screen views drawn after the main camera, and the main camera's viewport.
+    views: Views,
+    /// Planar reflections drawn before the main camera.
+    reflections: Reflections,
+    /// The minimap and the texture it is drawn into.
+    minimap: Option<(Minimap, TextureId)>,
+    time: Time,
+    frame_index: u32,
 }
 
-#[derive(Clone, Copy, PartialEq)]
-pub struct ObjectId(pub usize);
+/// Receives the errors passed to `Renderer::report_error`.
+pub type ErrorHandler = dyn Fn(RendererError);
 
-impl Renderer {
-    // Create a new Renderer with the specified window dimensions and title
-    pub fn new(window: Window) -> Result<Self, RendererError> {
-        let backend = MetalBackend::new(&window)?;
+impl Renderer<MetalBackend> {
+    /// Creates a new `Renderer` drawing into a window.
+    ///
+    /// The window can come from winit or any other windowing library, so an
+    /// application can run its own event loop instead of `RendererSystem`.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The window to draw into.
+    /// * `size` - The size of the window's drawable area in physical pixels.
+    /// * `config` - The options the renderer is created with.
+    pub fn new(
+        window: &impl HasWindowHandle,
+        size: UVec2,
+        config: &RendererConfig,
+    ) -> Result<Self, RendererError> {
+        let backend = MetalBackend::new(window, size)?;
         // let device = backend.device().clone();
-        let size = window.inner_size();
+        let mut renderer = Self::with_backend(backend, size);
+        config.apply(&mut renderer);
+        Ok(renderer)
+    }
+
+    /// Creates a new `Renderer` drawing into a view of a native app.
+    ///
+    /// This embeds the renderer in an AppKit or SwiftUI view hierarchy, whose
+    /// app drives it with `render_frame` and `resize`.
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The `NSView` to draw into, which is given a new Metal layer.
+    /// * `size` - The size of the view's drawable area in physical pixels.
+    /// * `config` - The options the renderer is created with.
+    ///
+    /// # Safety
+    ///
+    /// `view` must point to a valid `NSView`, and be used on the main thread.
+    pub unsafe fn from_ns_view(
+        view: NonNull<c_void>,
+        size: UVec2,
+        config: &RendererConfig,
+    ) -> Result<Self, RendererError> {
+        let backend = MetalBackend::from_ns_view(view, size)?;
+        let mut renderer = Self::with_backend(backend, size);
+        config.apply(&mut renderer);
+        Ok(renderer)
+    }
+
+    /// Creates a new `Renderer` presenting to a Metal layer owned by a native app.
+    ///
+    /// This suits apps that manage the layer themselves, e.g. through a
+    /// `MTKView`, and drive the renderer with `render_frame` and `resize`.
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The `CAMetalLayer` to present to, which the renderer retains.
+    /// * `size` - The size of the layer's drawable area in physical pixels.
+    /// * `config` - The options the renderer is created with.
+    ///
+    /// # Safety
+    ///
+    /// `layer` must point to a valid `CAMetalLayer`.
+    pub unsafe fn from_metal_layer(
+        layer: NonNull<c_void>,
+        size: UVec2,
+        config: &RendererConfig,
+    ) -> Result<Self, RendererError> {
+        let backend = MetalBackend::from_metal_layer(layer, size)?;
+        let mut renderer = Self::with_backend(backend, size);
+        config.apply(&mut renderer);
+        Ok(renderer)
+    }
+}
 
+impl<B: GraphicsBackend> Renderer<B> {
+    /// Creates a new `Renderer` drawing through the given backend.
+    ///
+    /// Errors passed to `report_error` are logged until an error handler is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - The graphics backend to submit draws to.
+    /// * `size` - The size of the viewport in pixels.
+    pub fn with_backend(backend: B, size: UVec2) -> Self {
         let camera = Camera::new(
             Vec3::new(0.0, 0.0, 3.0),
             45.0,
-            size.width as f32 / size.height as f32,
+            size.x as f32 / size.y as f32,
             0.1,
             100.0,
         );
 
-        Ok(Renderer {
+        Renderer {
             backend,
             mesh_storage: MeshStorage::new(),
+            upload_scheduler: UploadScheduler::new(Some(DEFAULT_UPLOAD_BUDGET)),
             render_queue: RenderQueue::new(),
-            window,
+            material_manager: MaterialManager::new(),
+            scene_graph: SceneGraph::new(),
+            editor: EditorMode::new(),
+            show_bounds: false,
+            show_normals: false,
+            debug_view: DebugView::default(),
+            anti_aliasing: AntiAliasing::default(),
+            render_scaler: RenderScaler::new(RenderScale::default()),
+            previous_view_projection: None,
+            lens_flare: None,
+            canvas: Canvas::new(size.as_vec2()),
+            labels: Labels::default(),
+            plots: Plots::default(),
+            trails: Trails::default(),
+            dynamic_meshes: DynamicMeshes::default(),
+            fluid_views: FluidViews::default(),
+            fluid_sphere: None,
+            particle_emitters: ParticleEmitters::default(),
+            particle_files: ParticleFiles::default(),
+            particle_textures: HashMap::new(),
+            particle_materials: HashMap::new(),
+            particle_quad: None,
+            profiler: Profiler::new(),
+            show_profiler: false,
+            show_log_console: false,
+            overlay_drawn: false,
+            atmosphere: None,
+            sky: None,
+            ambient_light: AmbientLight::default(),
+            gpu_culling: None,
+            depth_prepass: false,
+            vsync: true,
+            display_frame: None,
+            frame_rate_range: None,
+            frame_rate: None,
+            occlusion_view_projection: None,
+            sun_callback: None,
+            ray_traced_shadows: None,
+            cascaded_shadows: None,
+            lights: LightManager::default(),
+            ray_traced_mesh_count: 0,
+            pending_capture: None,
+            recorder: None,
+            validation: cfg!(debug_assertions),
+            validation_errors: Vec::new(),
+            error_handler: None,
+            #[cfg(feature = "windowing")]
+            input_bindings: InputBindings::default(),
+            asset_paths: AssetPaths::default(),
+            viewport_size: size,
+            scale_factor: 1.0,
             camera,
-            last_frame_time: std::time::Instant::now(),
-        })
+            views: Views::default(),
+            reflections: Reflections::default(),
+            minimap: None,
+            time: Time::new(),
+            frame_index: 0,
+        }
+    }
+
+    /// Advances the clock to now and renders a frame.
+    ///
+    /// Apps driving the renderer from their own loop, e.g. a `MTKView` draw
+    /// callback or a display link, call this once per frame after queuing
+    /// the frame's draws. `RendererSystem` advances the clock itself, since
+    /// it can play back recorded frame times.
+    pub fn render_frame(&mut self) -> Result<(), RendererError> {
+        self.tick_time();
+        self.render()
+    }
+
+    /// Advances the clock to the next frame.
+    ///
+    /// While frames are driven by a display link under vsync, frames last
+    /// from one presentation time to the next; otherwise they last until now.
+    pub fn tick_time(&mut self) {
+        match self.display_frame.filter(|_| self.vsync) {
+            Some(frame) => self.time.tick_presented(Instant::now(), frame.timestamp),
+            None => self.time.tick(Instant::now()),
+        }
     }
 
     pub fn render(&mut self) -> Result<(), RendererError> {
-        // TODO: sort batches in an efficient manner
         // TODO: Implement Frustum Culling
 
+        // Scopes recorded since the last render, e.g. by the game's update, count towards this frame
+        self.profiler.end_frame();
+        profile_scope!("render");
+        logging::set_frame(self.frame_index);
+
         let render_start = Instant::now();
         debug_trace!("Starting render at {:?}", render_start);
 
+        // Nodes removed since the last frame can no longer be selected
+        self.editor
+            .selection_mut()
+            .retain_existing(&self.scene_graph);
+
+        if let Some(scale) = self.render_scaler.update(self.time.unscaled_delta()) {
+            debug!("Render scale adjusted to: {scale}");
+            self.backend.set_render_scale(scale);
+        }
+
         let view_projection_matrix =
             self.camera.get_projection_matrix() * self.camera.get_view_matrix();
 
-        // Implicitly clear the render queue by taking ownership of the draw commands
-        let draw_commands = mem::take(&mut self.render_queue.draw_commands);
-        debug_trace!("Clearing RenderQueue at {:?}", Instant::now());
-
-        for draw_command in draw_commands {
-            match &draw_command {
-                DrawCommand::Mesh {
-                    mesh_id, transform, ..
-                } => {
-                    if let Some(mesh) = self.mesh_storage.get_mesh(*mesh_id) {
-                        self.backend.update_vertex_buffer(&mesh.vertices)?;
-                        if let Some(indices) = &mesh.indices {
-                            self.backend.update_index_buffer(indices)?;
-                        }
+        // A failed capture is reported but never stops the frame from rendering
+        let capturing = match self.pending_capture.take() {
+            Some(destination) => match self.backend.begin_frame_capture(&destination) {
+                Ok(()) => true,
+                Err(e) => {
+                    self.report_error(e);
+                    false
+                }
+            },
+            None => false,
+        };
 
-                        let uniforms = Uniforms {
-                            view_projection_matrix,
-                            model_matrix: *transform,
-                        };
-                        self.backend.update_uniform_buffer(&uniforms).unwrap();
-                    } else {
-                        return Err(RendererError::InvalidMeshId);
+        let sun = self.update_sun();
+        if let Some(lens_flare) = &self.lens_flare {
+            let size = self.viewport_size;
+            let aspect_ratio = size.x as f32 / size.y as f32;
+            let mut frame = lens_flare.frame(view_projection_matrix, aspect_ratio);
+            if let (Some(sun), FlareLight::Directional(_)) = (sun, lens_flare.light) {
+                frame.constants.intensity *= sun.intensity;
+            }
+            self.backend.set_lens_flare(Some(&frame));
+        }
+        if !self.labels.is_empty() {
+            self.layout_labels(view_projection_matrix);
+        }
+        if self.show_profiler {
+            self.profiler.draw(&mut self.canvas);
+        }
+        if !self.plots.is_empty() {
+            self.plots.draw(&mut self.canvas);
+        }
+        if let Some((minimap, texture)) = &self.minimap {
+            minimap.draw(&mut self.canvas, *texture, &self.camera);
+        }
+        if self.show_log_console {
+            self.draw_log_console();
+        }
+        if !self.canvas.is_empty() {
+            self.backend.set_overlay(Some(self.canvas.frame()));
+            self.overlay_drawn = true;
+        } else if std::mem::take(&mut self.overlay_drawn) {
+            self.backend.set_overlay(None);
+        }
+
+        // Jitter before the frame index advances with the frame constants
+        let jittered_view_projection = self.apply_temporal_jitter(view_projection_matrix);
+        // The sky and atmosphere reconstruct view rays from the jittered depth buffer
+        if let Some(sky) = &self.sky {
+            self.backend
+                .set_sky(Some(&sky.constants(jittered_view_projection)));
+        }
+        if let Some(atmosphere) = &self.atmosphere {
+            let constants = atmosphere.constants(jittered_view_projection, self.camera.position());
+            self.backend.set_atmosphere(Some(&constants));
+        }
+        let frame_constants = self.create_frame_constants();
+        self.backend.update_frame_constants(&frame_constants)?;
+
+        // Particle materials are created ahead of the upload below
+        if !self.particle_emitters.is_empty() {
+            self.prepare_particles();
+        }
+
+        // Materials swap their placeholders for textures whose uploads completed
+        let errors = self.upload_scheduler.submit(
+            &mut self.backend,
+            &self.mesh_storage,
+            &mut self.material_manager,
+        );
+        for error in errors {
+            self.report_error(error);
+        }
+        if !self.backend.poll_texture_uploads().is_empty() {
+            self.material_manager.mark_dirty();
+        }
+        if self.material_manager.take_dirty() {
+            let materials = self
+                .upload_scheduler
+                .with_placeholders(self.material_manager.materials());
+            self.backend.update_materials(&materials)?;
+        }
+
+        self.submit_scene_graph();
+        self.upload_scheduler
+            .submit_placeholders(&mut self.render_queue, &self.mesh_storage);
+        if !self.trails.is_empty() {
+            self.submit_trails();
+        }
+        if !self.dynamic_meshes.is_empty() {
+            self.submit_dynamic_meshes();
+        }
+        if !self.fluid_views.is_empty() {
+            self.submit_fluid_views();
+        }
+        if !self.particle_emitters.is_empty() {
+            self.submit_particles();
+        }
+        if self.show_bounds || self.show_normals {
+            self.submit_debug_lines();
+        }
+        if self.editor.is_enabled() && !self.editor.light_gizmos().is_empty() {
+            self.submit_light_gizmos(sun);
+        }
+        self.render_queue.sort_batches();
+        self.submit_ray_tracing(jittered_view_projection, sun);
+        let result = self
+            .draw_shadow_maps(jittered_view_projection, sun)
+            .and_then(|()| self.draw_reflections())
+            .and_then(|()| self.draw_minimap())
+            .and_then(|()| self.draw_views(jittered_view_projection))
+            .and_then(|()| self.backend.end_frame());
+        if self.gpu_culling.is_some_and(|culling| culling.occlusion) {
+            self.occlusion_view_projection = Some(jittered_view_projection);
+        }
+        self.record_frames(false);
+
+        // Clear the queue even if a draw failed, keeping its pools for the next frame
+        self.render_queue.clear();
+        self.canvas.clear();
+        if capturing {
+            self.backend.end_frame_capture();
+        }
+        debug_trace!("Finished render at {:?}", Instant::now());
+        result
+    }
+
+    /// Draws the labels on the canvas at their targets' current positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The unjittered view projection matrix of the frame.
+    fn layout_labels(&mut self, view_projection: Mat4) {
+        profile_scope!("labels");
+        self.scene_graph.update_world_transforms();
+        let (scene_graph, mesh_storage) = (&self.scene_graph, &self.mesh_storage);
+        self.labels.layout(
+            &mut self.canvas,
+            view_projection,
+            self.camera.position(),
+            |target| match target {
+                LabelTarget::Node(id) => {
+                    match scene_graph.world_bounding_sphere(id, mesh_storage) {
+                        Some(sphere) => Some((sphere.center, sphere.radius)),
+                        None => Some((scene_graph.world_transform(id)?.w_axis.truncate(), 0.0)),
                     }
                 }
-                DrawCommand::Primitive {
-                    vertices,
-                    indices,
-                    transform,
-                    ..
-                } => {
-                    self.backend.update_vertex_buffer(vertices)?;
-                    if let Some(indices) = indices {
-                        self.backend.update_index_buffer(indices)?;
-                    }
+                LabelTarget::Position(position) => Some((position, 0.0)),
+            },
+        );
+    }
 
-                    let uniforms = Uniforms {
-                        view_projection_matrix,
-                        model_matrix: *transform,
-                    };
-                    self.backend.update_uniform_buffer(&uniforms)?;
-                }
+    /// Advances the sky's time of day and points sun-driven lights at the sun.
+    ///
+    /// The atmosphere, directional lens flares, ray-traced and cascaded
+    /// shadows follow the sun, and the sun callback receives its light.
+    ///
+    /// # Returns
+    ///
+    /// The sun's light, or `None` without a sky.
+    fn update_sun(&mut self) -> Option<SunLight> {
+        let sky = self.sky.as_mut()?;
+        sky.advance(self.time.delta());
+        let sun = sky.sun_light();
+
+        if let Some(atmosphere) = &mut self.atmosphere {
+            atmosphere.sun_direction = sun.direction;
+        }
+        if let Some(LensFlare {
+            light: FlareLight::Directional(direction),
+            ..
+        }) = &mut self.lens_flare
+        {
+            *direction = sun.direction;
+        }
+        if let Some(shadows) = &mut self.ray_traced_shadows {
+            shadows.light_direction = sun.direction;
+        }
+        if let Some(shadows) = &mut self.cascaded_shadows {
+            shadows.light_direction = sun.direction;
+        }
+        if let Some(callback) = &mut self.sun_callback {
+            callback(&sun);
+        }
+        Some(sun)
+    }
+
+    /// Offsets the projection by this frame's temporal anti-aliasing jitter,
+    /// and passes the reprojection to the previous frame to the backend.
+    ///
+    /// # Returns
+    ///
+    /// The view projection matrix to draw with, unchanged without temporal anti-aliasing.
+    fn apply_temporal_jitter(&mut self, view_projection_matrix: Mat4) -> Mat4 {
+        if self.anti_aliasing != AntiAliasing::Taa {
+            return view_projection_matrix;
+        }
+
+        // A pixel of the scene spans 2 / size in normalized device coordinates
+        let jitter = self.anti_aliasing.jitter(self.frame_index) * 2.0 / self.render_size();
+        let previous = self
+            .previous_view_projection
+            .replace(view_projection_matrix);
+        self.backend.set_temporal_constants(&TemporalConstants {
+            reprojection: previous.unwrap_or(view_projection_matrix)
+                * view_projection_matrix.inverse(),
+            jitter: jitter.to_array(),
+            history_weight: TAA_HISTORY_WEIGHT,
+            history_valid: previous.is_some() as u32,
+        });
+        Mat4::from_translation(jitter.extend(0.0)) * view_projection_matrix
+    }
+
+    /// Passes frames copied back by the backend to the recorder, if recording.
+    ///
+    /// A failed recording is stopped and reported without stopping rendering.
+    fn record_frames(&mut self, wait: bool) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        profile_scope!("recording");
+        let now = Instant::now();
+        let result = self
+            .backend
+            .finish_frame_readback(wait)
+            .into_iter()
+            .try_for_each(|frame| recorder.push(frame, now));
+        if result.is_err() {
+            if let Err(e) = self.stop_recording() {
+                self.report_error(e);
+            }
+        }
+    }
+
+    /// Updates world transforms and queues a draw for every scene node with a mesh.
+    fn submit_scene_graph(&mut self) {
+        if self.scene_graph.is_empty() {
+            return;
+        }
+        profile_scope!("scene_graph");
+
+        self.scene_graph.set_camera_position(self.camera.position());
+        self.scene_graph.update_world_transforms();
+        for node in self.scene_graph.mesh_nodes() {
+            let mut draw_command = DrawCommandBuilder::new_mesh(node.mesh_id)
+                .with_transform(*node.world)
+                .with_material(node.material_id)
+                .with_layers(node.layers)
+                .with_node(node.id);
+            if let Some(outline) = node.outline {
+                draw_command = draw_command.with_outline(outline);
+            }
+            self.render_queue.add_draw_command(draw_command);
+        }
+    }
+
+    /// Records the positions of node trails and queues a draw for every trail.
+    fn submit_trails(&mut self) {
+        profile_scope!("trails");
+        let time = self.time.elapsed();
+        let scene_graph = &self.scene_graph;
+        self.trails.update(time, |id| {
+            Some(scene_graph.world_transform(id)?.w_axis.truncate())
+        });
+
+        let camera_position = self.camera.position();
+        let mut vertices = Vec::new();
+        for trail in self.trails.iter() {
+            vertices.clear();
+            trail.append_vertices(camera_position, time, &mut vertices);
+            if vertices.is_empty() {
+                continue;
             }
+            self.render_queue.add_draw_command(
+                DrawCommandBuilder::new_primitive(&vertices, None, trail.primitive_type())
+                    .with_layers(trail.layers)
+                    .with_render_state(RenderState::TRANSPARENT),
+            );
+        }
+    }
 
-            if let Some(instance_data) = draw_command.instance_data() {
-                self.backend.update_instance_buffer(instance_data)?;
+    /// Queues a draw of the current geometry of every dynamic mesh.
+    fn submit_dynamic_meshes(&mut self) {
+        let mut vertices = Vec::new();
+        for mesh in self.dynamic_meshes.iter() {
+            vertices.clear();
+            mesh.append_vertices(&mut vertices);
+            if vertices.is_empty() {
+                continue;
             }
+            let indices = mesh.indices();
+            self.render_queue.add_draw_command(
+                DrawCommandBuilder::new_primitive(
+                    &vertices,
+                    Some(&indices),
+                    PrimitiveType::Triangle,
+                )
+                .with_transform(mesh.transform)
+                .with_layers(mesh.layers),
+            );
+        }
+    }
 
-            let backend_draw_command = self.create_backend_draw_command(&draw_command)?;
-            self.backend.draw(backend_draw_command)?;
+    /// Queues an instanced draw of the particles of every fluid view.
+    fn submit_fluid_views(&mut self) {
+        let sphere = match self.fluid_sphere {
+            Some(sphere) => sphere,
+            None => {
+                let sphere = self.add_mesh(sphere_mesh());
+                self.fluid_sphere = Some(sphere);
+                sphere
+            }
+        };
+        let mut instances = Vec::new();
+        for view in self.fluid_views.iter() {
+            instances.clear();
+            view.append_instances(&mut instances);
+            if instances.is_empty() {
+                continue;
+            }
+            self.render_queue.add_draw_command(
+                DrawCommandBuilder::new_mesh(sphere)
+                    .with_instances(&instances)
+                    .with_layers(view.layers)
+                    .with_render_state(view.render_state()),
+            );
         }
+    }
 
-        debug_trace!("Finished render at {:?}", Instant::now());
-        Ok(())
+    /// Reloads changed particle files, and resolves the quad and the
+    /// materials emitters are drawn with.
+    fn prepare_particles(&mut self) {
+        for error in self.particle_files.reload(&mut self.particle_emitters) {
+            self.report_error(error);
+        }
+
+        if self.particle_quad.is_none() {
+            let corners = [(-0.5, 0.5), (0.5, 0.5), (0.5, -0.5), (-0.5, -0.5)];
+            let vertices = corners
+                .iter()
+                .map(|&(x, y)| vec3_color_to_vertex(Vec3::new(x, y, 0.0), Color::WHITE))
+                .collect();
+            let layout = VertexLayout::position_color()
+                .with_attribute(VertexSemantic::TexCoord, VertexFormat::Float2);
+            let quad = MeshBuilder::new(vertices, PrimitiveType::Triangle)
+                .with_layout(layout)
+                .with_tex_coords(&[Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y])
+                .with_indices(vec![0, 2, 1, 0, 3, 2]);
+            self.particle_quad = Some(self.add_mesh(quad));
+        }
+
+        for (_, emitter) in self.particle_emitters.iter_mut() {
+            let name = emitter.effect().texture.as_deref();
+            let texture = name.and_then(|name| self.particle_textures.get(name).copied());
+            if emitter.material().is_some() && emitter.texture() == texture {
+                continue;
+            }
+            if let (Some(name), None) = (name, texture) {
+                warn!("Particle texture {name} is not registered, drawing untextured");
+            }
+            let material_manager = &mut self.material_manager;
+            let material = *self.particle_materials.entry(texture).or_insert_with(|| {
+                let material = Material::new(Color::WHITE);
+                material_manager.create_material(match texture {
+                    Some(texture) => material.with_texture(texture),
+                    None => material,
+                })
+            });
+            emitter.set_material(material, texture);
+        }
+    }
+
+    /// Simulates particle emitters and queues an instanced draw for every
+    /// emitter with live particles.
+    fn submit_particles(&mut self) {
+        profile_scope!("particles");
+        let scene_graph = &self.scene_graph;
+        self.particle_emitters
+            .update(self.time.delta(), |id| scene_graph.world_transform(id));
+
+        let Some(quad) = self.particle_quad else {
+            return;
+        };
+        let camera_rotation = Quat::from_mat4(&self.camera.get_view_matrix().inverse());
+        let mut instances = Vec::new();
+        for emitter in self.particle_emitters.iter() {
+            let Some(material) = emitter.material() else {
+                continue;
+            };
+            instances.clear();
+            emitter.append_instances(camera_rotation, &mut instances);
+            if instances.is_empty() {
+                continue;
+            }
+            let render_state = match emitter.effect().blend {
+                BlendMode::Additive => RenderState::ADDITIVE,
+                _ => RenderState::TRANSPARENT,
+            };
+            self.render_queue.add_draw_command(
+                DrawCommandBuilder::new_mesh(quad)
+                    .with_instances(&instances)
+                    .with_material(material)
+                    .with_layers(emitter.layers)
+                    .with_render_state(render_state),
+            );
+        }
+    }
+
+    /// Builds acceleration structures for new meshes and passes this frame's
+    /// shadow casters to the backend, while ray-traced shadows are enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    /// * `sun` - The sky's sunlight, fading shadows out at night.
+    fn submit_ray_tracing(&mut self, view_projection: Mat4, sun: Option<SunLight>) {
+        let Some(shadows) = self.ray_traced_shadows else {
+            return;
+        };
+        profile_scope!("ray_tracing");
+
+        // Meshes are never modified once stored, so each is built once
+        while self.ray_traced_mesh_count < self.mesh_storage.len() {
+            let index = self.ray_traced_mesh_count;
+            if let Some(mesh) = self.mesh_storage.get_mesh(index) {
+                self.backend.build_ray_tracing_mesh(index, mesh.view());
+            }
+            self.ray_traced_mesh_count += 1;
+        }
+
+        let mut constants = shadows.constants(view_projection);
+        if let Some(sun) = sun {
+            constants.strength *= sun.intensity;
+        }
+        let mut instances = Vec::new();
+        for item in self.render_queue.draw_items() {
+            let GeometryHandle::Mesh(mesh) = item.geometry else {
+                continue;
+            };
+            if !casts_shadows(item.layers) {
+                continue;
+            }
+            match item.instances {
+                Some(item_instances) => {
+                    instances.extend(item_instances.iter().map(|instance| RayTracingInstance {
+                        mesh,
+                        transform: *item.transform * instance.model_matrix,
+                    }))
+                }
+                None => instances.push(RayTracingInstance {
+                    mesh,
+                    transform: *item.transform,
+                }),
+            }
+        }
+        self.backend.set_ray_tracing_frame(Some(&RayTracingFrame {
+            constants,
+            instances,
+        }));
     }
 
-    fn create_backend_draw_command(
-        &self,
-        draw_command: &DrawCommand,
-    ) -> Result<BackendDrawCommand, RendererError> {
-        match draw_command {
-            DrawCommand::Mesh { mesh_id, .. } => {
-                if let Some(mesh) = self.mesh_storage.get_mesh(*mesh_id) {
-                    Ok(self.create_backend_draw_command_from_mesh(mesh, draw_command))
-                } else {
-                    Err(RendererError::InvalidMeshId)
+    /// Queues debug lines for every queued draw: wireframe bounding boxes
+    /// and per-vertex normals, as enabled.
+    fn submit_debug_lines(&mut self) {
+        const BOUNDS_COLOR: Color = Color {
+            r: 0.0,
+            g: 1.0,
+            b: 0.0,
+            a: 1.0,
+        };
+        const NORMAL_LENGTH: f32 = 0.1;
+        profile_scope!("debug_lines");
+
+        let mut lines: Vec<Vertex> = Vec::new();
+        let queue = &self.render_queue;
+        for item in queue.draw_items() {
+            let (geometry, bounds) = match item.geometry {
+                GeometryHandle::Mesh(mesh_id) => match self.mesh_storage.get_mesh(mesh_id) {
+                    Some(mesh) => (mesh.view(), mesh.bounds),
+                    None => continue,
+                },
+                GeometryHandle::Transient(id) => match queue.transient_geometry(id) {
+                    Some(geometry) => (
+                        geometry,
+                        Aabb::from_vertices(geometry.vertices).unwrap_or_default(),
+                    ),
+                    None => continue,
+                },
+            };
+
+            let single_instance = [*item.transform];
+            let instance_transforms: Vec<Mat4> = match item.instances {
+                Some(instances) => instances
+                    .iter()
+                    .map(|instance| *item.transform * instance.model_matrix)
+                    .collect(),
+                None => single_instance.to_vec(),
+            };
+
+            for transform in &instance_transforms {
+                if self.show_bounds {
+                    bounds
+                        .transformed(transform)
+                        .append_wireframe(BOUNDS_COLOR, &mut lines);
+                }
+                if self.show_normals {
+                    append_normal_lines(&geometry, transform, NORMAL_LENGTH, &mut lines);
                 }
             }
-            DrawCommand::Primitive {
-                vertices,
-                indices,
-                primitive_type,
-                ..
-            } => Ok(self.create_backend_draw_command_from_primitive(
-                vertices,
-                indices,
-                primitive_type,
-                draw_command,
+        }
+
+        if !lines.is_empty() {
+            self.render_queue.add_draw_command(
+                DrawCommandBuilder::new_primitive(&lines, None, PrimitiveType::Line)
+                    .with_layers(LayerMask::DEBUG),
+            );
+        }
+    }
+
+    /// Queues the editor's gizmos for the renderer's lights: the sun, or the
+    /// shadow light without a sky, the lens flare's light, the point and spot
+    /// lights, and the frusta of the shadow cascades and shadowed spot lights.
+    ///
+    /// # Arguments
+    ///
+    /// * `sun` - The sky's sunlight.
+    fn submit_light_gizmos(&mut self, sun: Option<SunLight>) {
+        profile_scope!("light_gizmos");
+        let mut gizmos = Vec::new();
+        if let Some(sun) = sun {
+            let color = Color::new(sun.color.x, sun.color.y, sun.color.z, 1.0);
+            gizmos.push(LightGizmo::new(
+                LightShape::Directional {
+                    direction: sun.direction,
+                },
+                color,
+            ));
+        } else if let Some(direction) = self
+            .ray_traced_shadows
+            .map(|shadows| shadows.light_direction)
+            .or(self.cascaded_shadows.map(|shadows| shadows.light_direction))
+        {
+            gizmos.push(LightGizmo::new(
+                LightShape::Directional { direction },
+                Color::WHITE,
+            ));
+        }
+        match self.lens_flare.as_ref().map(|lens_flare| lens_flare.light) {
+            Some(FlareLight::Point(position)) => gizmos.push(LightGizmo::new(
+                LightShape::Point {
+                    position,
+                    radius: 0.0,
+                },
+                Color::YELLOW,
             )),
+            // Sun-driven flares already have the sun's gizmo
+            Some(FlareLight::Directional(direction)) if gizmos.is_empty() => gizmos.push(
+                LightGizmo::new(LightShape::Directional { direction }, Color::YELLOW),
+            ),
+            _ => {}
+        }
+        gizmos.extend(self.lights.iter().map(|light| light.gizmo()));
+
+        let parts = self.editor.light_gizmos();
+        let mut lines = Vec::new();
+        for gizmo in &gizmos {
+            gizmo.append_lines(parts, &self.camera, &mut lines);
+        }
+        if let Some(shadows) = self.cascaded_shadows.filter(|_| parts.shadow_frusta) {
+            for cascade in shadows.cascades(&self.camera) {
+                append_frustum_lines(cascade.view_projection, Color::CYAN, &mut lines);
+            }
+        }
+        if parts.shadow_frusta {
+            // A point light's faces cover its whole volume, already drawn
+            for light in self.lights.iter() {
+                if matches!(light, Light::Spot(_)) && light.shadow().enabled {
+                    for view_projection in light.shadow_view_projections() {
+                        append_frustum_lines(view_projection, Color::CYAN, &mut lines);
+                    }
+                }
+            }
+        }
+        if !lines.is_empty() {
+            self.render_queue.add_draw_command(
+                DrawCommandBuilder::new_primitive(&lines, None, PrimitiveType::Line)
+                    .with_layers(LayerMask::GIZMO),
+            );
+        }
+    }
+
+    /// Submits every draw in the render queue to the backend.
+    ///
+    /// With validation enabled, every draw is checked first; draws with fatal
+    /// issues are skipped and all issues are logged and kept until the next frame.
+    /// With the depth pre-pass enabled, the depth of opaque mesh draws is drawn
+    /// from their positions alone before any draw is shaded.
+    /// Draws the queue for the main camera, then again for every split-screen view.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection_matrix` - The view projection matrix of the main camera.
+    fn draw_views(&mut self, view_projection_matrix: Mat4) -> Result<(), RendererError> {
+        let split = self.views.is_split();
+        if split {
+            self.backend.set_viewport(self.views.main_viewport());
+        }
+        self.draw_queue(view_projection_matrix, self.camera.cull_mask(), true)?;
+        if !split {
+            return Ok(());
+        }
+
+        for (viewport, view_projection, cull_mask) in self.views.passes() {
+            self.backend.set_viewport(viewport);
+            self.draw_queue(view_projection, cull_mask, false)?;
         }
+        Ok(())
+    }
+
+    /// Draws the depth of this frame's shadow casters into the shadow map of
+    /// every cascade, while cascaded shadows are set, and of every point and
+    /// spot light with shadows enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    /// * `sun` - The sky's sunlight, fading cascaded shadows out at night.
+    fn draw_shadow_maps(
+        &mut self,
+        view_projection: Mat4,
+        sun: Option<SunLight>,
+    ) -> Result<(), RendererError> {
+        let casters = ShadowCasters {
+            queue: &self.render_queue,
+            meshes: &self.mesh_storage,
+            materials: &self.material_manager,
+            uploads: &self.upload_scheduler,
+            instance_culling: self.gpu_culling.is_some(),
+        };
+        if let Some(shadows) = self.cascaded_shadows {
+            profile_scope!("shadow_maps");
+            shadows.draw(
+                &mut self.backend,
+                &casters,
+                &self.camera,
+                view_projection,
+                sun,
+            )?;
+        }
+
+        self.lights
+            .draw_shadows(&mut self.backend, &casters, view_projection)
+    }
+
+    /// Draws the queue mirrored into every planar reflection seen by the main camera.
+    fn draw_reflections(&mut self) -> Result<(), RendererError> {
+        if self.reflections.is_empty() {
+            return Ok(());
+        }
+        profile_scope!("reflections");
+        let passes: Vec<_> = self
+            .reflections
+            .iter()
+            .filter_map(|(reflection, texture)| {
+                let view_projection = reflection.view_projection(&self.camera)?;
+                Some((texture, view_projection, reflection.cull_mask))
+            })
+            .collect();
+        for (texture, view_projection, cull_mask) in passes {
+            self.backend.set_render_target(Some(RenderTarget {
+                texture,
+                mirrored: true,
+            }));
+            let result = self.draw_queue(view_projection, cull_mask, false);
+            self.backend.set_render_target(None);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Draws the queue from above into the minimap's texture.
+    fn draw_minimap(&mut self) -> Result<(), RendererError> {
+        let Some((minimap, texture)) = self.minimap else {
+            return Ok(());
+        };
+        profile_scope!("minimap");
+        self.backend.set_render_target(Some(RenderTarget {
+            texture,
+            mirrored: false,
+        }));
+        let view_projection = minimap.view_projection(&self.camera);
+        let result = self.draw_queue(view_projection, minimap.cull_mask, false);
+        self.backend.set_render_target(None);
+        result
     }
 
-    fn create_backend_draw_command_from_mesh(
-        &self,
-        mesh: &Mesh,
-        draw_command: &DrawCommand,
-    ) -> BackendDrawCommand {
-        if let Some(instance_data) = draw_command.instance_data() {
-            if mesh.indices.is_some() {
-                BackendDrawCommand::IndexedInstanced {
-                    primitive_type: mesh.primitive_type,
-                    index_count: mesh.indices.as_ref().unwrap().len() as u64,
-                    index_type: IndexType::UInt32,
-                    index_buffer_offset: 0,
-                    instance_count: instance_data.len() as u64,
+    /// Draws the queue from one camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection_matrix` - The camera's view projection matrix.
+    /// * `cull_mask` - The layers the camera draws.
+    /// * `main_view` - Whether the camera is the main camera, whose draws are
+    ///   validated and occlusion culled against the last frame.
+    fn draw_queue(
+        &mut self,
+        view_projection_matrix: Mat4,
+        cull_mask: LayerMask,
+        main_view: bool,
+    ) -> Result<(), RendererError> {
+        profile_scope!("draw");
+        let queue = &self.render_queue;
+        if main_view {
+            self.validation_errors.clear();
+        }
+
+        // Transparent draws follow every opaque draw, so they blend over the
+        // finished opaque scene and test against its complete depth
+        let material_manager = &self.material_manager;
+        let (opaque, transparent): (Vec<_>, Vec<_>) = queue
+            .draw_items()
+            .filter(|item| item.layers.intersects(cull_mask))
+            .enumerate()
+            .map(|(draw_index, item)| {
+                let render_state = item.render_state.unwrap_or_else(|| {
+                    material_manager
+                        .get(item.material_id)
+                        .map(|material| material.render_state)
+                        .unwrap_or_default()
+                });
+                (draw_index, item, render_state)
+            })
+            .partition(|(_, _, render_state)| !render_state.is_transparent());
+
+        let mut draws = Vec::new();
+        for (draw_index, item, render_state) in opaque.into_iter().chain(transparent) {
+            // Static meshes are drawn as their placeholder until their upload is released
+            if let GeometryHandle::Mesh(mesh_id) = item.geometry {
+                if self.upload_scheduler.is_mesh_queued(mesh_id) {
+                    continue;
                 }
-            } else {
-                BackendDrawCommand::Instanced {
-                    primitive_type: mesh.primitive_type,
-                    vertex_start: 0,
-                    vertex_count: mesh.vertices.len() as u64,
-                    instance_count: instance_data.len() as u64,
+            }
+            let geometry = match item.geometry {
+                GeometryHandle::Mesh(mesh_id) => {
+                    self.mesh_storage.get_mesh(mesh_id).map(Mesh::view)
+                }
+                GeometryHandle::Transient(id) => queue.transient_geometry(id),
+            };
+
+            if self.validation {
+                let material_exists = self.material_manager.get(item.material_id).is_some();
+                let mut fatal = false;
+                for issue in validate_draw(&item, geometry.as_ref(), material_exists) {
+                    fatal |= issue.is_fatal();
+                    // Views draw the same queue, whose issues are reported once
+                    if !main_view {
+                        continue;
+                    }
+                    let validation_error = ValidationError {
+                        draw_index,
+                        node: item.node,
+                        geometry: item.geometry,
+                        material_id: item.material_id,
+                        issue,
+                    };
+                    if validation_error.issue.is_fatal() {
+                        error!("{validation_error}, skipping draw");
+                    } else {
+                        warn!("{validation_error}");
+                    }
+                    self.validation_errors.push(validation_error);
+                }
+                if fatal {
+                    continue;
                 }
             }
-        } else if mesh.indices.is_some() {
-            BackendDrawCommand::Indexed {
-                primitive_type: mesh.primitive_type,
-                index_count: mesh.indices.as_ref().unwrap().len() as u64,
-                index_type: IndexType::UInt32,
-                index_buffer_offset: 0,
-            }
-        } else {
-            BackendDrawCommand::Basic {
-                primitive_type: mesh.primitive_type,
-                vertex_start: 0,
-                vertex_count: mesh.vertices.len() as u64,
-            }
-        }
-    }
-
-    pub fn create_backend_draw_command_from_primitive(
-        &self,
-        vertices: &[Vertex],
-        indices: &Option<Vec<u32>>,
-        primitive_type: &PrimitiveType,
-        draw_command: &DrawCommand,
-    ) -> BackendDrawCommand {
-        if let Some(instance_data) = draw_command.instance_data() {
-            if let Some(indices) = indices {
-                BackendDrawCommand::IndexedInstanced {
-                    primitive_type: *primitive_type,
-                    index_count: indices.len() as u64,
-                    index_type: IndexType::UInt32,
-                    index_buffer_offset: 0,
-                    instance_count: instance_data.len() as u64,
+            let geometry = geometry.ok_or(AssetError::InvalidGeometry(item.geometry))?;
+            draws.push((item, render_state, geometry));
+        }
+
+        if self.depth_prepass {
+            for (item, render_state, geometry) in &draws {
+                let Some(positions) = geometry.positions else {
+                    continue;
+                };
+                if !render_state.writes_depth_prepass() {
+                    continue;
                 }
-            } else {
-                BackendDrawCommand::Instanced {
-                    primitive_type: *primitive_type,
-                    vertex_start: 0,
-                    vertex_count: vertices.len() as u64,
-                    instance_count: instance_data.len() as u64,
+
+                let resident = bind_static_geometry(
+                    &mut self.backend,
+                    &self.mesh_storage,
+                    item.geometry,
+                    true,
+                );
+                if !resident {
+                    self.backend.update_packed_vertex_buffer(positions)?;
+                    if let Some(indices) = geometry.indices {
+                        self.backend.update_index_buffer(indices)?;
+                    }
+                }
+                self.backend.update_uniform_buffer(&Uniforms {
+                    view_projection_matrix,
+                    model_matrix: *item.transform,
+                })?;
+                if let Some(instances) = item.instances {
+                    // Culling is left to the shaded draw
+                    if self.gpu_culling.is_some() {
+                        self.backend.set_instance_culling(None);
+                    }
+                    self.backend.update_instance_buffer(instances)?;
                 }
+                self.backend.set_render_state(*render_state);
+                self.backend
+                    .draw_depth(geometry.draw_command(item.instances))?;
             }
-        } else if let Some(indices) = indices {
-            BackendDrawCommand::Indexed {
-                primitive_type: *primitive_type,
-                index_count: indices.len() as u64,
-                index_type: IndexType::UInt32,
-                index_buffer_offset: 0,
+        }
+
+        for (item, render_state, geometry) in draws {
+            // Static meshes draw from their resident buffers without copying
+            let resident =
+                bind_static_geometry(&mut self.backend, &self.mesh_storage, item.geometry, false);
+            if !resident {
+                match geometry.packed_vertices {
+                    Some(packed_vertices) => {
+                        self.backend.update_packed_vertex_buffer(packed_vertices)?
+                    }
+                    None => self.backend.update_vertex_buffer(geometry.vertices)?,
+                }
+                if let Some(indices) = geometry.indices {
+                    self.backend.update_index_buffer(indices)?;
+                }
             }
-        } else {
-            BackendDrawCommand::Basic {
-                primitive_type: *primitive_type,
-                vertex_start: 0,
-                vertex_count: vertices.len() as u64,
+
+            let uniforms = Uniforms {
+                view_projection_matrix,
+                model_matrix: *item.transform,
+            };
+            self.backend.update_uniform_buffer(&uniforms)?;
+
+            if let Some(instances) = item.instances {
+                // Only stored meshes have bounds without scanning their vertices
+                if let Some(gpu_culling) = self.gpu_culling {
+                    let culling = match (item.geometry, gpu_culling.culls(instances.len())) {
+                        (GeometryHandle::Mesh(mesh_id), true) => {
+                            self.mesh_storage.get_mesh(mesh_id).map(|mesh| {
+                                gpu_culling.constants(
+                                    view_projection_matrix,
+                                    self.occlusion_view_projection.filter(|_| main_view),
+                                    *item.transform,
+                                    mesh.bounding_sphere,
+                                    instances.len(),
+                                )
+                            })
+                        }
+                        _ => None,
+                    };
+                    self.backend.set_instance_culling(culling.as_ref());
+                }
+                self.backend.update_instance_buffer(instances)?;
             }
+
+            self.backend.set_material(item.material_id);
+            self.backend.set_render_state(render_state);
+            self.backend.set_outline(item.outline);
+            self.backend.draw(geometry.draw_command(item.instances))?;
         }
+        Ok(())
     }
 
-    pub fn add_mesh(&mut self, mesh_builder: MeshBuilder) -> usize {
-        self.mesh_storage.add_mesh(mesh_builder)
+    /// Returns the size the scene is rendered at, after the render scale.
+    fn render_size(&self) -> Vec2 {
+        let size = self.viewport_size;
+        let (width, height) = scaled_size(size.x as u64, size.y as u64, self.render_scaler.scale());
+        Vec2::new(width as f32, height as f32)
     }
 
-    pub fn draw_immediate(&mut self, draw_command: DrawCommand) {
-        self.render_queue.add_draw_command(draw_command);
+    /// Gathers the per-frame constants, using the scaled time of the frame.
+    fn create_frame_constants(&mut self) -> FrameConstants {
+        let (ambient_sky, ambient_ground) = self.ambient_light.hemisphere_colors(self.sky.as_ref());
+        let frame_constants = FrameConstants {
+            camera_position: self.camera.position().to_array(),
+            time: self.time.elapsed() as f32,
+            viewport_size: self.render_size().to_array(),
+            near: self.camera.near(),
+            far: self.camera.far(),
+            delta_time: self.time.delta(),
+            frame_index: self.frame_index,
+            ambient_sky_color: ambient_sky.to_array(),
+            ambient_ground_color: ambient_ground.to_array(),
+        };
+
+        self.frame_index = self.frame_index.wrapping_add(1);
+        frame_constants
     }
 
-    // TODO: implement resize in the backend
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        self.camera
-            .set_aspect_ratio(new_size.width as f32 / new_size.height as f32);
-        // TODO: Update the backend
-        // self.backend.resize(new_size);
+    /// Stores a mesh, queuing the upload of its geometry to the GPU if it is static.
+    pub fn add_mesh(&mut self, mesh_builder: MeshBuilder) -> usize {
+        let index = self.mesh_storage.add_mesh(mesh_builder);
+        if let Some(mesh) = self
+            .mesh_storage
+            .get_mesh(index)
+            .filter(|mesh| mesh.is_static)
+        {
+            self.upload_scheduler.queue_mesh(index, mesh.upload_bytes());
+        }
+        index
     }
 
-    // TODO: Find a way to tell rust these are exposed API methods so they should'nt be counted as dead code
+    /// Registers a new material for use in draw commands.
     #[allow(dead_code)]
-    pub fn create_triangle(
+    pub fn create_material(&mut self, material: Material) -> MaterialId {
+        self.material_manager.create_material(material)
+    }
+
+    /// Replaces an existing material.
+    pub fn update_material(
         &mut self,
-        v1: Vec3,
-        v2: Vec3,
-        v3: Vec3,
-        color: Color,
-    ) -> TriangleBuilder {
-        TriangleBuilder::new(v1, v2, v3, color)
+        id: MaterialId,
+        material: Material,
+    ) -> Result<(), RendererError> {
+        Ok(self.material_manager.update_material(id, material)?)
     }
 
-    pub fn create_shape(&mut self, vertices: Vec<(Vec3, Color)>) -> ShapeData {
-        let vertices = vertices
-            .into_iter()
-            .map(|(position, color)| vec3_color_to_vertex(position, color))
-            .collect();
-        ShapeData::new(vertices, PrimitiveType::Triangle)
+    /// Reports the GPU memory held by the renderer, per resource category.
+    ///
+    /// Logs a warning for every budget warning in the report.
+    pub fn gpu_memory_report(&self) -> GpuMemoryReport {
+        let report = self.backend.gpu_memory_report();
+        for warning in report.warnings() {
+            warn!("{warning}");
+        }
+        report
     }
-}
 
-pub type RenderCallback = dyn Fn(&mut Renderer) -> Result<(), RendererError>;
+    /// Returns the graphics backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
 
-pub struct RendererSystem {
-    renderer: Rc<RefCell<Renderer>>,
-    event_loop: EventLoop<()>,
-    render_callback: Box<RenderCallback>,
-}
+    /// Returns a stored mesh.
+    pub fn mesh(&self, mesh_id: usize) -> Option<&Mesh> {
+        self.mesh_storage.get_mesh(mesh_id)
+    }
+
+    /// Writes a stored mesh as a Wavefront OBJ file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `ExportError::MissingMesh` if the mesh
+    /// doesn't exist, or `ExportError::Io`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let planet = renderer.add_mesh(planet_builder);
+    /// renderer.export_obj(planet, Path::new("planet.obj"))?;
+    /// ```
+    pub fn export_obj(&self, mesh_id: usize, path: &Path) -> Result<(), ExportError> {
+        self.mesh_storage
+            .get_mesh(mesh_id)
+            .ok_or(ExportError::MissingMesh(mesh_id))?
+            .export_obj(path)
+    }
+
+    /// Writes the scene graph, with the meshes and materials its nodes draw,
+    /// as a glTF 2.0 file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an `ExportError`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// renderer.export_gltf(Path::new("scene.gltf"))?;
+    /// ```
+    pub fn export_gltf(&self, path: &Path) -> Result<(), ExportError> {
+        let document = SceneDocument::capture(&self.scene_graph);
+        let gltf = write_gltf(
+            &document,
+            &self.mesh_storage,
+            self.material_manager.materials(),
+        )?;
+        fs::write(path, gltf).map_err(|source| ExportError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        info!(
+            "Exported scene with {} nodes to {}",
+            document.len(),
+            path.display()
+        );
+        Ok(())
+    }
 
-impl RendererSystem {
-    pub fn new(width: u32, height: u32, title: &str) -> Result<Self, RendererError> {
-        let event_loop =
-            EventLoop::new().map_err(|e| RendererError::EventLoopError(e.to_string()))?;
+    /// Returns the scene graph.
+    pub fn scene_graph(&self) -> &SceneGraph {
+        &self.scene_graph
+    }
 
-        let window = WindowBuilder::new()
-            .with_title(title)
-            .with_inner_size(winit::dpi::LogicalSize::new(width, height))
-            .build(&event_loop)
-            .map_err(|e| RendererError::WindowCreationFailed(e.to_string()))?;
+    /// Returns the scene graph for modification.
+    ///
+    /// Nodes with a mesh are drawn every frame by `render`.
+    pub fn scene_graph_mut(&mut self) -> &mut SceneGraph {
+        &mut self.scene_graph
+    }
 
-        let renderer = Rc::new(RefCell::new(Renderer::new(window)?));
-        info!("Initializing renderer system with {width}x{height} window");
+    /// Returns the frame clock.
+    ///
+    /// Use its `delta` to advance animations and physics, so they follow
+    /// pausing, stepping and time scaling.
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
 
-        Ok(RendererSystem {
-            renderer,
-            event_loop,
-            render_callback: Box::new(|_| Ok(())), // Default no-op callback
-        })
+    /// Returns the frame clock for modification, e.g. to pause or scale it.
+    pub fn time_mut(&mut self) -> &mut Time {
+        &mut self.time
     }
 
-    pub fn set_render_callback<F>(&mut self, callback: F)
-    where
-        F: Fn(&mut Renderer) -> Result<(), RendererError> + 'static,
-    {
-        self.render_callback = Box::new(callback);
-    }
-
-    pub fn run(self) -> Result<(), RendererError> {
-        let window_size = self.renderer.borrow().window.inner_size();
-        let center_x = window_size.width as f64 / 2.0;
-        let center_y = window_size.height as f64 / 2.0;
-
-        // Enable mouse capture
-        self.renderer
-            .borrow()
-            .window
-            .set_cursor_grab(winit::window::CursorGrabMode::Confined)
-            .or(self
-                .renderer
-                .borrow()
-                .window
-                .set_cursor_grab(winit::window::CursorGrabMode::Locked))
-            .unwrap();
-        self.renderer.borrow().window.set_cursor_visible(false);
-
-        self.event_loop
-            .run(move |event, event_loop_window_target| {
-                match event {
-                    Event::WindowEvent { event, .. } => match event {
-                        WindowEvent::CloseRequested => event_loop_window_target.exit(),
-                        WindowEvent::Resized(new_size) => {
-                            self.renderer.borrow_mut().resize(new_size);
-                        }
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    physical_key,
-                                    state,
-                                    ..
-                                },
-                            ..
-                        } => {
-                            let mut renderer = self.renderer.borrow_mut();
-                            let mut delta_time = renderer.last_frame_time.elapsed().as_secs_f32();
-                            delta_time = delta_time.min(0.1); // Cap delta_time to 0.1 seconds
-                            renderer.last_frame_time = std::time::Instant::now();
-
-                            if let PhysicalKey::Code(key_code) = physical_key {
-                                use winit::event::ElementState;
-
-                                if state == ElementState::Pressed {
-                                    match key_code {
-                                        KeyCode::KeyW => renderer
-                                            .camera
-                                            .process_keyboard(CameraMovement::Forward, delta_time),
-                                        KeyCode::KeyS => renderer
-                                            .camera
-                                            .process_keyboard(CameraMovement::Backward, delta_time),
-                                        KeyCode::KeyA => renderer
-                                            .camera
-                                            .process_keyboard(CameraMovement::Left, delta_time),
-                                        KeyCode::KeyD => renderer
-                                            .camera
-                                            .process_keyboard(CameraMovement::Right, delta_time),
-                                        KeyCode::Space => renderer
-                                            .camera
-                                            .process_keyboard(CameraMovement::Up, delta_time),
-                                        KeyCode::ShiftLeft => renderer
-                                            .camera
-                                            .process_keyboard(CameraMovement::Down, delta_time),
-                                        KeyCode::KeyV => renderer.backend.toggle_wireframe_mode(),
-                                        _ => {}
-                                    }
-                                }
-                            }
-                        }
+    /// Returns the camera, e.g. to place the audio listener.
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
 
-                        WindowEvent::CursorMoved { position, .. } => {
-                            let mut renderer = self.renderer.borrow_mut();
-
-                            let delta_x = position.x - center_x;
-                            let delta_y = center_y - position.y; // Reversed since y-coordinates go from bottom to top
-                            renderer
-                                .camera
-                                .process_mouse_movement(delta_x as f32, delta_y as f32);
-
-                            // Reset cursor position to center
-                            renderer
-                                .window
-                                .set_cursor_position(winit::dpi::PhysicalPosition::new(
-                                    center_x, center_y,
-                                ))
-                                .unwrap();
-                        }
-                        WindowEvent::MouseWheel { delta, .. } => {
-                            let mut renderer = self.renderer.borrow_mut();
-                            match delta {
-                                MouseScrollDelta::LineDelta(_, y) => {
-                                    renderer.camera.process_mouse_scroll(y);
-                                }
-                                MouseScrollDelta::PixelDelta(position) => renderer
-                                    .camera
-                                    .process_mouse_scroll(position.y as f32 * 0.1),
-                            }
-                        }
-                        WindowEvent::RedrawRequested => {
-                            let mut renderer = self.renderer.borrow_mut();
+    /// Returns the camera for modification, e.g. to change its cull mask.
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
 
-                            // Draw objects
-                            if let Err(e) = (self.render_callback)(&mut renderer) {
-                                eprintln!("Error in render callback: {e:?}");
-                            }
-                        }
-                        _ => {}
-                    },
-                    Event::AboutToWait => {
-                        self.renderer.borrow().window.request_redraw();
-                    }
-                    _ => {}
-                }
-            })
-            .map_err(|e| RendererError::EventLoopError(e.to_string()))
+    /// Returns the keys bound to the renderer's actions.
+    #[cfg(feature = "windowing")]
+    pub fn input_bindings(&self) -> &InputBindings {
+        &self.input_bindings
+    }
+
+    /// Returns the keys bound to the renderer's actions for modification.
+    #[cfg(feature = "windowing")]
+    pub fn input_bindings_mut(&mut self) -> &mut InputBindings {
+        &mut self.input_bindings
+    }
+
+    /// Returns the directories assets are read from and captures written to.
+    pub fn asset_paths(&self) -> &AssetPaths {
+        &self.asset_paths
+    }
+
+    /// Sets the directories assets are read from and captures written to.
+    pub fn set_asset_paths(&mut self, asset_paths: AssetPaths) {
+        self.asset_paths = asset_paths;
+    }
+
+    /// Sets whether frames are presented in sync with the display's refresh.
+    pub fn set_vsync(&mut self, enabled: bool) {
+        self.vsync = enabled;
+        self.backend.set_vsync(enabled);
+        info!("Vsync enabled: {}", enabled);
+    }
+
+    /// Returns `true` if frames are presented in sync with the display's refresh.
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    /// Returns the display refresh the current frame is shown at.
+    ///
+    /// This is `None` unless frames are driven by a display link, see `set_display_frame`.
+    pub fn display_frame(&self) -> Option<DisplayFrame> {
+        self.display_frame
+    }
+
+    /// Sets the display refresh the next frame is shown at.
+    ///
+    /// `RendererSystem` calls this when its display link fires. Apps driving
+    /// the renderer from their own display link, e.g. a `CADisplayLink`, can
+    /// pass its timestamps.
+    pub fn set_display_frame(&mut self, frame: DisplayFrame) {
+        self.display_frame = Some(frame);
+        // The refresh rate changes when the window moves to another display
+        self.update_frame_rate();
+    }
+
+    /// Returns `true` if a display refresh is due a new frame.
+    ///
+    /// Every refresh is due one unless a frame rate range paces frames below
+    /// the refresh rate, in which case refreshes during the last frame's
+    /// duration are skipped.
+    pub fn frame_due(&self, frame: &DisplayFrame) -> bool {
+        match (self.display_frame, self.frame_rate) {
+            (Some(last), Some(frame_rate)) => {
+                let elapsed = frame.timestamp - last.timestamp;
+                elapsed >= 1.0 / frame_rate as f64 - frame.refresh_period / 2.0
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns the display's current refresh rate in hertz.
+    ///
+    /// This is only known while frames are driven by a display link.
+    pub fn refresh_rate(&self) -> Option<f64> {
+        self.display_frame.map(|frame| frame.refresh_rate())
+    }
+
+    /// Returns the highest refresh rate of the display in hertz, see `max_refresh_rate`.
+    pub fn max_refresh_rate(&self) -> Option<f64> {
+        max_refresh_rate()
+    }
+
+    /// Returns the frame rates frames are presented at, if limited.
+    pub fn frame_rate_range(&self) -> Option<FrameRateRange> {
+        self.frame_rate_range
+    }
+
+    /// Sets the frame rates to present at, e.g. a fixed 60 Hz on a 120 Hz
+    /// ProMotion display to save power, or `None` to present at the
+    /// display's refresh rate.
+    pub fn set_frame_rate_range(&mut self, range: Option<FrameRateRange>) {
+        self.frame_rate_range = range;
+        self.update_frame_rate();
+    }
+
+    /// Returns the frame rate presented frames are paced to, if any.
+    pub fn frame_rate(&self) -> Option<f32> {
+        self.frame_rate
+    }
+
+    /// Chooses the frame rate of the frame rate range on the current display.
+    fn update_frame_rate(&mut self) {
+        let refresh_rate = self.refresh_rate().or_else(max_refresh_rate);
+        let frame_rate = self.frame_rate_range.map(|range| {
+            refresh_rate.map_or(range.preferred, |rate| range.frame_rate(rate as f32))
+        });
+        if frame_rate != self.frame_rate {
+            self.frame_rate = frame_rate;
+            self.backend.set_frame_rate(frame_rate);
+            info!("Frame rate set to: {:?}", frame_rate);
+        }
+    }
+
+    /// Returns the profiler, with the timings of the last frame's `profile_scope!` scopes.
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    /// Returns the profiler for modification, e.g. to enable it without the overlay.
+    pub fn profiler_mut(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
+    /// Toggles drawing the profiler's timings over the frame.
+    ///
+    /// Showing the timings enables profiling, and hiding them disables it.
+    pub fn toggle_profiler(&mut self) {
+        self.show_profiler = !self.show_profiler;
+        self.profiler.set_enabled(self.show_profiler);
+        if !self.show_profiler {
+            self.profiler.reset();
+        }
+        info!("Profiler display toggled: {}", self.show_profiler);
+    }
+
+    /// Toggles drawing the most recent log entries over the frame.
+    ///
+    /// Entries at info level and above are shown, if the engine's logger is installed.
+    pub fn toggle_log_console(&mut self) {
+        self.show_log_console = !self.show_log_console;
+        info!("Log console toggled: {}", self.show_log_console);
+    }
+
+    /// Draws the most recent log entries in the bottom-left corner of the canvas.
+    fn draw_log_console(&mut self) {
+        const LINES: usize = 10;
+        const PIXEL_SIZE: f32 = 2.0;
+        const PADDING: f32 = 4.0;
+        let scale_factor = self.canvas.scale_factor();
+        let (pixel_size, padding) = (PIXEL_SIZE * scale_factor, PADDING * scale_factor);
+
+        let Some(logger) = logging::logger() else {
+            return;
+        };
+        let entries = logger.buffer().recent(LINES, Level::Info);
+        if entries.is_empty() {
+            return;
+        }
+        let line_height = LINE_ADVANCE * pixel_size;
+        self.canvas.rect(
+            CanvasPoint::pixels(0.0, 0.0).anchored(Anchor::BottomLeft),
+            CanvasSize::pixels(
+                self.canvas.size().x,
+                entries.len() as f32 * line_height + padding * 2.0,
+            ),
+            Color::BLACK.with_alpha(0.6),
+        );
+        for (index, entry) in entries.iter().rev().enumerate() {
+            let color = match entry.level {
+                Level::Error => Color::RED,
+                Level::Warn => Color::YELLOW,
+                _ => Color::WHITE,
+            };
+            self.canvas.text(
+                CanvasPoint::pixels(padding, -padding - index as f32 * line_height)
+                    .anchored(Anchor::BottomLeft),
+                &entry.to_string(),
+                pixel_size,
+                color,
+            );
+        }
+    }
+
+    /// Toggles between filled and wireframe rendering.
+    pub fn toggle_wireframe(&mut self) {
+        self.backend.toggle_wireframe_mode();
+    }
+
+    /// Toggles drawing the bounding box of every draw as a wireframe.
+    pub fn toggle_bounds(&mut self) {
+        self.show_bounds = !self.show_bounds;
+        info!("Bounding box display toggled: {}", self.show_bounds);
+    }
+
+    /// Toggles drawing the normal of every vertex as a line.
+    pub fn toggle_normals(&mut self) {
+        self.show_normals = !self.show_normals;
+        info!("Normal display toggled: {}", self.show_normals);
+    }
+
+    /// Sets what the fragment shader outputs, e.g. normals or depth.
+    pub fn set_debug_view(&mut self, debug_view: DebugView) {
+        self.debug_view = debug_view;
+        self.backend.set_debug_view(debug_view);
+        info!("Debug view set to: {:?}", debug_view);
+    }
+
+    /// Selects the post-process anti-aliasing applied to every frame.
+    ///
+    /// Switching modes discards the temporal history, so the first frames
+    /// after enabling temporal anti-aliasing are not blended.
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+        self.previous_view_projection = None;
+        self.backend.set_anti_aliasing(anti_aliasing);
+        info!("Anti-aliasing set to: {:?}", anti_aliasing);
+    }
+
+    /// Sets the resolution the scene is rendered at, relative to the window.
+    ///
+    /// Scales other than 1 render offscreen and are filtered to the window's
+    /// size when presented. A dynamic scale starts at its maximum and adapts
+    /// to the frame times measured from then on.
+    pub fn set_render_scale(&mut self, render_scale: RenderScale) {
+        self.render_scaler = RenderScaler::new(render_scale);
+        self.backend.set_render_scale(self.render_scaler.scale());
+        info!("Render scale set to: {:?}", render_scale);
+    }
+
+    /// Returns the scale the next frame is rendered at.
+    pub fn render_scale(&self) -> f32 {
+        self.render_scaler.scale()
+    }
+
+    /// Selects how draws with `BlendMode::Alpha` are composited.
+    ///
+    /// Transparent draws are always submitted after opaque ones. With
+    /// `Transparency::Ordered` they must also be submitted back to front;
+    /// `Transparency::WeightedBlended` removes that requirement.
+    pub fn set_transparency(&mut self, transparency: Transparency) {
+        self.backend.set_transparency(transparency);
+        info!("Transparency set to: {:?}", transparency);
+    }
+
+    /// Sets the lens flare drawn over every frame, or `None` to remove it.
+    ///
+    /// The flare is faded by the backend as its light is occluded by the
+    /// scene or leaves the screen.
+    pub fn set_lens_flare(&mut self, lens_flare: Option<LensFlare>) {
+        if lens_flare.is_none() {
+            self.backend.set_lens_flare(None);
+        }
+        self.lens_flare = lens_flare;
+        info!("Lens flare enabled: {}", self.lens_flare.is_some());
+    }
+
+    /// Returns the 2D canvas drawn over the next frame.
+    ///
+    /// Positions are resolved against the viewport's size in pixels. The
+    /// canvas is cleared after every frame, so HUDs are drawn again each frame.
+    pub fn canvas(&mut self) -> &mut Canvas {
+        &mut self.canvas
+    }
+
+    /// Adds a text label that follows a scene node or world position on screen.
+    ///
+    /// Labels are drawn on the canvas every frame until removed, fading while
+    /// their target is hidden by the scene.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The `NodeId` or world-space `Vec3` the label is attached to.
+    /// * `text` - The text of the label, with lines separated by `\n`.
+    /// * `style` - How the label is drawn, scaled and offset.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new label.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let style = LabelStyle::default()
+    ///     .with_offset(Vec2::new(40.0, -40.0))
+    ///     .with_distance_scaling(10.0, 0.5, 2.0);
+    /// renderer.add_label(planet, "Mars", style);
+    /// ```
+    pub fn add_label(
+        &mut self,
+        target: impl Into<LabelTarget>,
+        text: impl Into<String>,
+        style: LabelStyle,
+    ) -> LabelId {
+        self.labels.add(Label {
+            target: target.into(),
+            text: text.into(),
+            style,
+        })
+    }
+
+    /// Removes a label, returning `true` if it existed.
+    pub fn remove_label(&mut self, id: LabelId) -> bool {
+        self.labels.remove(id)
+    }
+
+    /// Returns a label for modification, e.g. to change its text.
+    pub fn label_mut(&mut self, id: LabelId) -> Option<&mut Label> {
+        self.labels.get_mut(id)
+    }
+
+    /// Appends a value to a plotted series, adding the series with the
+    /// default style if it doesn't exist.
+    ///
+    /// Every series is drawn as a line graph in the top-right corner of the
+    /// frame until removed, scaled to the range of its recent values.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the series, shown above its graph.
+    /// * `value` - The value to append. Values that aren't finite are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// renderer.plot("frame ms", time.unscaled_delta() * 1000.0);
+    /// renderer.plot("energy", diagnostics.latest().unwrap().total() as f32);
+    /// ```
+    pub fn plot(&mut self, name: &str, value: f32) {
+        self.plots.record(name, value);
+    }
+
+    /// Sets how a plotted series is kept and drawn, adding it if it doesn't exist.
+    pub fn set_plot_style(&mut self, name: &str, style: PlotStyle) {
+        self.plots.set_style(name, style);
+    }
+
+    /// Returns a plotted series by its name.
+    pub fn plot_series(&self, name: &str) -> Option<&Plot> {
+        self.plots.get(name)
+    }
+
+    /// Removes a plotted series, returning `true` if it existed.
+    pub fn remove_plot(&mut self, name: &str) -> bool {
+        self.plots.remove(name)
+    }
+
+    /// Removes every plotted series.
+    pub fn clear_plots(&mut self) {
+        self.plots.clear();
+    }
+
+    /// Adds a trail, which records its node's position every frame, or
+    /// positions pushed through `trail_mut` for manual trails.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new trail.
+    pub fn add_trail(&mut self, trail: Trail) -> TrailId {
+        self.trails.add(trail)
+    }
+
+    /// Removes a trail, returning `true` if it existed.
+    pub fn remove_trail(&mut self, id: TrailId) -> bool {
+        self.trails.remove(id)
+    }
+
+    /// Returns a trail for modification.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let position = bodies.position(index);
+    /// let time = renderer.time().elapsed();
+    /// if let Some(trail) = renderer.trail_mut(trail) {
+    ///     trail.push(Vec3::new(position.x as f32, position.y as f32, position.z as f32), time);
+    /// }
+    /// ```
+    pub fn trail_mut(&mut self, id: TrailId) -> Option<&mut Trail> {
+        self.trails.get_mut(id)
+    }
+
+    /// Adds a dynamic mesh, which is drawn with its current positions every frame.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new dynamic mesh.
+    pub fn add_dynamic_mesh(&mut self, mesh: DynamicMesh) -> DynamicMeshId {
+        self.dynamic_meshes.add(mesh)
+    }
+
+    /// Removes a dynamic mesh, returning `true` if it existed.
+    pub fn remove_dynamic_mesh(&mut self, id: DynamicMeshId) -> bool {
+        self.dynamic_meshes.remove(id)
+    }
+
+    /// Returns a dynamic mesh for modification.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// cloth.step(1.0 / 60.0);
+    /// if let Some(flag) = renderer.dynamic_mesh_mut(flag) {
+    ///     flag.set_positions(cloth.positions());
+    /// }
+    /// ```
+    pub fn dynamic_mesh_mut(&mut self, id: DynamicMeshId) -> Option<&mut DynamicMesh> {
+        self.dynamic_meshes.get_mut(id)
+    }
+
+    /// Adds a fluid view, which draws the particles last copied into it every frame.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new fluid view.
+    pub fn add_fluid_view(&mut self, view: FluidView) -> FluidViewId {
+        self.fluid_views.add(view)
+    }
+
+    /// Removes a fluid view, returning `true` if it existed.
+    pub fn remove_fluid_view(&mut self, id: FluidViewId) -> bool {
+        self.fluid_views.remove(id)
+    }
+
+    /// Returns a fluid view for modification.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// fluid.step(1.0 / 120.0);
+    /// if let Some(view) = renderer.fluid_view_mut(disk) {
+    ///     view.update(&fluid);
+    /// }
+    /// ```
+    pub fn fluid_view_mut(&mut self, id: FluidViewId) -> Option<&mut FluidView> {
+        self.fluid_views.get_mut(id)
+    }
+
+    /// Adds a particle emitter, which plays its effect from the next frame.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new emitter.
+    pub fn add_particle_emitter(&mut self, emitter: ParticleEmitter) -> ParticleEmitterId {
+        self.particle_emitters.add(emitter)
+    }
+
+    /// Loads a particle effect file and adds an emitter playing it.
+    ///
+    /// The file is checked for changes twice a second, and the emitter's
+    /// effect is replaced when it is saved. A file that fails to reload is
+    /// reported through the error handler, and the previous effect plays on.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The particle file, resolved against the asset root.
+    /// * `source` - Where particles are emitted from.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new emitter, or an error if the file cannot be loaded.
+    pub fn load_particle_emitter(
+        &mut self,
+        path: impl AsRef<Path>,
+        source: EmitterSource,
+    ) -> Result<ParticleEmitterId, RendererError> {
+        let (file, effect) = ParticleFile::load(self.asset_paths.resolve(path))?;
+        let id = self
+            .particle_emitters
+            .add(ParticleEmitter::new(effect, source));
+        self.particle_files.watch(id, file);
+        Ok(id)
+    }
+
+    /// Removes a particle emitter and its particles, returning `true` if it existed.
+    pub fn remove_particle_emitter(&mut self, id: ParticleEmitterId) -> bool {
+        self.particle_files.unwatch(id);
+        self.particle_emitters.remove(id)
+    }
+
+    /// Returns a particle emitter.
+    pub fn particle_emitter(&self, id: ParticleEmitterId) -> Option<&ParticleEmitter> {
+        self.particle_emitters.get(id)
+    }
+
+    /// Returns a particle emitter for modification, e.g. to stop emitting.
+    pub fn particle_emitter_mut(&mut self, id: ParticleEmitterId) -> Option<&mut ParticleEmitter> {
+        self.particle_emitters.get_mut(id)
+    }
+
+    /// Registers a texture under the name particle effects refer to it by.
+    ///
+    /// Emitters whose effect names the texture show it from the next frame,
+    /// including those that were drawn untextured before it was registered.
+    pub fn set_particle_texture(&mut self, name: impl Into<String>, texture: TextureId) {
+        self.particle_textures.insert(name.into(), texture);
+    }
+
+    /// Sets the atmosphere blended over every frame, or `None` to remove it.
+    pub fn set_atmosphere(&mut self, atmosphere: Option<Atmosphere>) {
+        if atmosphere.is_none() {
+            self.backend.set_atmosphere(None);
+        }
+        self.atmosphere = atmosphere;
+        info!("Atmosphere enabled: {}", self.atmosphere.is_some());
+    }
+
+    /// Sets the day/night sky drawn behind every frame, or `None` to remove it.
+    ///
+    /// While a sky is set, its time of day advances with the frame clock, and
+    /// the atmosphere and directional lens flares follow its sun.
+    pub fn set_sky(&mut self, sky: Option<Sky>) {
+        if sky.is_none() {
+            self.backend.set_sky(None);
+        }
+        self.sky = sky;
+        info!("Sky enabled: {}", self.sky.is_some());
+    }
+
+    /// Returns the day/night sky, if set.
+    pub fn sky_mut(&mut self) -> Option<&mut Sky> {
+        self.sky.as_mut()
+    }
+
+    /// Sets the ambient light reaching every surface, bound with each frame's constants.
+    ///
+    /// `AmbientLight::Sky` follows the sky's time of day while a sky is set.
+    pub fn set_ambient_light(&mut self, ambient_light: AmbientLight) {
+        self.ambient_light = ambient_light;
+        debug!("Ambient light set to: {:?}", ambient_light);
+    }
+
+    /// Returns the ambient light reaching every surface.
+    pub fn ambient_light(&self) -> AmbientLight {
+        self.ambient_light
+    }
+
+    /// Sets the callback receiving the sun's light every frame a sky is set.
+    ///
+    /// Use it to update directional lights, such as a shadow-casting sun,
+    /// from the time of day.
+    pub fn set_sun_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(&SunLight) + 'static,
+    {
+        self.sun_callback = Some(Box::new(callback));
+    }
+
+    /// Returns `true` if the backend's device supports ray tracing.
+    pub fn supports_ray_tracing(&self) -> bool {
+        self.backend.supports_ray_tracing()
+    }
+
+    /// Sets the ray-traced shadows composited into every frame, or `None` to remove them.
+    ///
+    /// Shadows are cast by stored meshes on the `SHADOW_CASTER` layer. While a
+    /// sky is set, they follow its sun.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, or a `BackendError` if the device cannot ray trace.
+    pub fn set_ray_traced_shadows(
+        &mut self,
+        shadows: Option<RayTracedShadows>,
+    ) -> Result<(), RendererError> {
+        if shadows.is_some() && !self.backend.supports_ray_tracing() {
+            return Err(BackendError::RayTracingUnsupported.into());
+        }
+        if shadows.is_none() {
+            self.backend.set_ray_tracing_frame(None);
+        }
+        self.ray_traced_shadows = shadows;
+        info!(
+            "Ray-traced shadows enabled: {}",
+            self.ray_traced_shadows.is_some()
+        );
+        Ok(())
+    }
+
+    /// Sets the cascaded shadow maps composited into every frame, or `None` to remove them.
+    ///
+    /// Shadows are cast by stored meshes on the `SHADOW_CASTER` layer. While a
+    /// sky is set, they follow its sun.
+    pub fn set_cascaded_shadows(&mut self, shadows: Option<CascadedShadows>) {
+        if shadows.is_none() {
+            self.backend.set_shadow_maps(None);
+        }
+        self.cascaded_shadows = shadows;
+        info!(
+            "Cascaded shadows enabled: {}",
+            self.cascaded_shadows.is_some()
+        );
+    }
+
+    /// Returns the cascaded shadows for modification, if set.
+    pub fn cascaded_shadows_mut(&mut self) -> Option<&mut CascadedShadows> {
+        self.cascaded_shadows.as_mut()
+    }
+
+    /// Returns the point and spot lights.
+    pub fn lights(&self) -> &LightManager {
+        &self.lights
+    }
+
+    /// Returns the point and spot lights for modification, such as adding
+    /// lights or enabling their shadows.
+    ///
+    /// Shadows are cast by stored meshes on the `SHADOW_CASTER` layer within
+    /// a light's reach.
+    pub fn lights_mut(&mut self) -> &mut LightManager {
+        &mut self.lights
+    }
+
+    /// Sets the GPU culling of instanced mesh draws, or `None` to draw every instance.
+    ///
+    /// With occlusion culling, every frame's depth is kept for the next
+    /// frame's tests, so frames render offscreen.
+    pub fn set_gpu_culling(&mut self, gpu_culling: Option<GpuCulling>) {
+        let occlusion = gpu_culling.is_some_and(|culling| culling.occlusion);
+        if gpu_culling.is_none() {
+            self.backend.set_instance_culling(None);
+        }
+        self.backend.set_depth_pyramid(occlusion);
+        self.gpu_culling = gpu_culling;
+        self.occlusion_view_projection = None;
+        info!("GPU culling set to: {:?}", self.gpu_culling);
+    }
+
+    /// Enables or disables the depth pre-pass.
+    ///
+    /// The pre-pass draws the depth of every opaque stored mesh from its
+    /// position-only vertex stream, reading 12 bytes per vertex, before any
+    /// draw is shaded. Shaded draws then only run the fragment shader for
+    /// visible surfaces, which pays off in scenes with heavy overdraw or
+    /// expensive materials. Frames render offscreen while it is enabled.
+    pub fn set_depth_prepass(&mut self, enabled: bool) {
+        self.depth_prepass = enabled;
+        self.backend.set_depth_prepass(enabled);
+        info!("Depth pre-pass enabled: {}", enabled);
+    }
+
+    /// Enables or disables compiling new pipeline permutations in the background.
+    ///
+    /// Asynchronous compilation is enabled by default. A draw whose vertex
+    /// layout or state needs a pipeline permutation not yet compiled draws
+    /// with a fallback pipeline, shading positions only, until the
+    /// permutation is ready, rather than stalling the frame while it
+    /// compiles. Disabled, permutations compile on first use.
+    pub fn set_async_pipelines(&mut self, enabled: bool) {
+        self.backend.set_async_pipelines(enabled);
+        info!("Asynchronous pipeline compilation enabled: {}", enabled);
+    }
+
+    /// Compiles the pipeline permutations of every variant drawing vertices
+    /// of the given layouts, e.g. at startup or behind a loading screen, so
+    /// the first draws of those layouts don't use the fallback pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `layouts` - The vertex layouts of the meshes to be drawn.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or the `RendererError` of a permutation
+    /// compiled on the spot.
+    pub fn warm_up_pipelines(&mut self, layouts: &[VertexLayout]) -> Result<(), RendererError> {
+        self.backend.warm_up_pipelines(layouts)
+    }
+
+    /// Returns the number of pipeline permutations still compiling in the background.
+    pub fn pending_pipelines(&self) -> usize {
+        self.backend.pending_pipelines()
+    }
+
+    /// Enables or disables draw validation.
+    ///
+    /// Validation is enabled by default in debug builds. It checks every draw
+    /// for out-of-range indices, degenerate triangles, broken transforms and
+    /// missing mesh or material handles before it reaches the backend.
+    pub fn set_validation(&mut self, enabled: bool) {
+        self.validation = enabled;
+        info!("Draw validation set to: {}", enabled);
+    }
+
+    /// Returns the validation issues found while drawing the last frame.
+    pub fn validation_errors(&self) -> &[ValidationError] {
+        &self.validation_errors
+    }
+
+    /// Reports an error that does not stop rendering.
+    ///
+    /// The error is passed to the error handler. `RendererSystem` sets one
+    /// delivering errors through the event loop to its error callback, which
+    /// logs them by default. Without an error handler, the error is logged
+    /// directly.
+    pub fn report_error(&self, error: impl Into<RendererError>) {
+        let error = error.into();
+        match &self.error_handler {
+            Some(handler) => handler(error),
+            None => error!("{}", error_chain(&error)),
+        }
+    }
+
+    /// Sets the handler receiving the errors passed to `report_error`.
+    pub fn set_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(RendererError) + 'static,
+    {
+        self.error_handler = Some(Box::new(handler));
+    }
+
+    /// Captures the GPU work of the next rendered frame.
+    ///
+    /// The capture can be inspected in Xcode without launching the engine
+    /// from it. Outside of Xcode, the `METAL_CAPTURE_ENABLED=1` environment
+    /// variable must be set before launch.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where the capture is sent, e.g. a `.gputrace` file.
+    pub fn capture_next_frame(&mut self, destination: CaptureDestination) {
+        info!(
+            "GPU capture requested for the next frame: {:?}",
+            destination
+        );
+        self.pending_capture = Some(destination);
+    }
+
+    /// Starts recording every presented frame.
+    ///
+    /// Frames are copied back from the GPU asynchronously and encoded on a
+    /// worker thread, so recording adds little to the frame time.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A directory for a PNG sequence, or an `.mp4` file with the `ffmpeg` feature.
+    /// * `fps` - The frame rate of the recording. Frames rendered faster are dropped.
+    pub fn start_recording(
+        &mut self,
+        path: impl AsRef<Path>,
+        fps: u32,
+    ) -> Result<(), RendererError> {
+        if self.recorder.is_some() {
+            return Err(RecordingError::AlreadyRecording.into());
+        }
+        self.recorder = Some(Recorder::start(path.as_ref(), fps)?);
+        self.backend.set_frame_readback(true);
+        Ok(())
+    }
+
+    /// Stops recording once all frames in flight are written.
+    ///
+    /// # Returns
+    ///
+    /// The number of frames written, or zero if nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Result<usize, RendererError> {
+        self.record_frames(true);
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(0);
+        };
+        self.backend.set_frame_readback(false);
+        Ok(recorder.finish()?)
+    }
+
+    /// Returns `true` while frames are being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Captures the scene around a point into the six faces of a cube map.
+    ///
+    /// Each face is rendered with a square 90° camera into a frame of the
+    /// face's resolution and copied back from the GPU, before the frame size,
+    /// camera and views are restored. Faces show the scene without labels or
+    /// overlays, and the clock does not advance, so the faces match. Use
+    /// `CubeMap::to_equirectangular` for a 360° panorama image.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let cube_map = renderer.capture_panorama(Vec3::new(0.0, 1.7, 0.0), 1024)?;
+    /// cube_map.to_equirectangular(4096).save_png("panorama.png")?;
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The center of the cube map.
+    /// * `resolution` - The width and height of every face in pixels.
+    pub fn capture_panorama(
+        &mut self,
+        position: Vec3,
+        resolution: u32,
+    ) -> Result<CubeMap, RendererError> {
+        let resolution = resolution.max(1);
+        let size = self.viewport_size;
+        let canvas = std::mem::replace(
+            &mut self.canvas,
+            Canvas::new(Vec2::splat(resolution as f32)),
+        );
+        let labels = std::mem::take(&mut self.labels);
+        let plots = std::mem::take(&mut self.plots);
+        let minimap = self.minimap.take();
+        let views = std::mem::take(&mut self.views);
+        let recorder = self.recorder.take();
+        let (show_profiler, show_log_console) = (self.show_profiler, self.show_log_console);
+        (self.show_profiler, self.show_log_console) = (false, false);
+        let mut face_camera =
+            CubeFace::PositiveX.camera(position, self.camera.near(), self.camera.far());
+        face_camera.set_cull_mask(self.camera.cull_mask());
+        let camera = std::mem::replace(&mut self.camera, face_camera);
+
+        self.resize(UVec2::splat(resolution));
+        self.backend.set_frame_readback(true);
+        let faces = self.render_cube_faces(position);
+
+        // Restore the camera and views before resizing, so their aspect ratios follow
+        self.backend.set_frame_readback(recorder.is_some());
+        self.camera = camera;
+        self.canvas = canvas;
+        self.labels = labels;
+        self.plots = plots;
+        self.minimap = minimap;
+        self.views = views;
+        self.recorder = recorder;
+        (self.show_profiler, self.show_log_console) = (show_profiler, show_log_console);
+        self.previous_view_projection = None;
+        self.resize(size);
+
+        let faces = faces?;
+        info!("Captured {resolution}x{resolution} cube map at {position:?}");
+        Ok(CubeMap { resolution, faces })
+    }
+
+    /// Renders and copies back every face of a cube map, in the order of `CubeFace::ALL`.
+    fn render_cube_faces(&mut self, position: Vec3) -> Result<[FrameImage; 6], RendererError> {
+        let mut faces = Vec::with_capacity(CubeFace::ALL.len());
+        for face in CubeFace::ALL {
+            let mut camera = face.camera(position, self.camera.near(), self.camera.far());
+            camera.set_cull_mask(self.camera.cull_mask());
+            self.camera = camera;
+            // Temporal anti-aliasing must not blend in another face
+            self.previous_view_projection = None;
+            self.render()?;
+            let frame = self
+                .backend
+                .finish_frame_readback(true)
+                .pop()
+                .ok_or(RecordingError::ReadbackFailed)?;
+            faces.push(frame);
+        }
+        Ok(faces.try_into().expect("one frame per cube face"))
+    }
+
+    /// Switches to the next debug view.
+    pub fn cycle_debug_view(&mut self) {
+        self.set_debug_view(self.debug_view.next());
+    }
+
+    /// Returns the world-space bounding box of a scene node's mesh.
+    ///
+    /// # Returns
+    ///
+    /// The bounding box as of the last rendered frame, or `None` if the node
+    /// does not exist or has no mesh.
+    pub fn node_world_bounds(&self, id: NodeId) -> Option<Aabb> {
+        self.scene_graph.world_bounds(id, &self.mesh_storage)
+    }
+
+    /// Attaches a scene node to a socket of another node's mesh.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or the errors of `SceneGraph::attach_to_socket`.
+    pub fn attach_to_socket(
+        &mut self,
+        id: NodeId,
+        parent: NodeId,
+        socket: &str,
+    ) -> Result<(), SceneError> {
+        self.scene_graph
+            .attach_to_socket(id, parent, socket, &self.mesh_storage)
+    }
+
+    /// Casts a ray against the triangles of every scene node's mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `ray` - The ray in world space, e.g. from `Camera::screen_ray`.
+    ///
+    /// # Returns
+    ///
+    /// The node hit nearest the ray's origin and the hit on its mesh, or
+    /// `None` if the ray misses every mesh.
+    pub fn raycast(&mut self, ray: &Ray) -> Option<(NodeId, RayHit)> {
+        self.scene_graph.update_world_transforms();
+        self.scene_graph
+            .mesh_nodes()
+            .filter_map(|node| {
+                let mesh = self.mesh_storage.get_mesh(node.mesh_id)?;
+                mesh.raycast(ray, node.world).map(|hit| (node.id, hit))
+            })
+            .min_by(|(_, a), (_, b)| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Picks the scene node drawn at a point on the screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel` - The point in physical pixels from the top-left of the viewport.
+    ///
+    /// # Returns
+    ///
+    /// The node whose mesh is nearest the camera at the point, or `None` if
+    /// no mesh is there.
+    pub fn pick(&mut self, pixel: Vec2) -> Option<NodeId> {
+        let ray = self.camera.screen_ray(pixel, self.viewport_size.as_vec2());
+        self.raycast(&ray).map(|(id, _)| id)
+    }
+
+    /// Hovers the scene node at a point on the screen, highlighting it.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel` - The pointer in physical pixels, or `None` if it left the viewport.
+    ///
+    /// # Returns
+    ///
+    /// The hovered node.
+    pub fn hover_at(&mut self, pixel: Option<Vec2>) -> Option<NodeId> {
+        let id = pixel.and_then(|pixel| self.pick(pixel));
+        self.editor
+            .selection_mut()
+            .set_hovered(&mut self.scene_graph, id);
+        id
+    }
+
+    /// Changes the selection by the scene node at a point on the screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel` - The point in physical pixels from the top-left of the viewport.
+    /// * `mode` - How the picked node changes the selection. Picking nothing
+    ///   with `SelectionMode::Replace` clears it.
+    ///
+    /// # Returns
+    ///
+    /// The picked node.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mode = if shift { SelectionMode::Toggle } else { SelectionMode::Replace };
+    /// renderer.select_at(cursor, mode);
+    /// for event in renderer.selection_events() {
+    ///     if let SelectionEvent::Selected(id) = event {
+    ///         inspector.show(id);
+    ///     }
+    /// }
+    /// ```
+    pub fn select_at(&mut self, pixel: Vec2, mode: SelectionMode) -> Option<NodeId> {
+        let id = self.pick(pixel);
+        // The picked node comes from the graph, so it is always valid
+        let _ = self
+            .editor
+            .selection_mut()
+            .select(&mut self.scene_graph, id, mode);
+        id
+    }
+
+    /// Returns the selection and hover changes since this was last called, oldest first.
+    pub fn selection_events(&mut self) -> Vec<SelectionEvent> {
+        self.editor.selection_mut().take_events()
+    }
+
+    /// Creates a static triangle mesh collider for every scene node drawing
+    /// a mesh flagged as collidable with `MeshBuilder::with_collidable`.
+    ///
+    /// Each node's rotation and scale are baked into its collider's
+    /// triangles, and its translation becomes the collider's position, so
+    /// colliders must be created again after collidable nodes move.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The floating origin the scene is placed relative to.
+    ///
+    /// # Returns
+    ///
+    /// The colliders in world space, to add to a `RigidBodySystem`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// for collider in renderer.mesh_colliders(&origin) {
+    ///     system.add_collider(collider);
+    /// }
+    /// ```
+    pub fn mesh_colliders(&mut self, origin: &FloatingOrigin) -> Vec<Collider> {
+        self.scene_graph.update_world_transforms();
+        self.scene_graph
+            .mesh_nodes()
+            .filter_map(|node| {
+                let mesh = self.mesh_storage.get_mesh(node.mesh_id)?;
+                if !mesh.collidable {
+                    return None;
+                }
+                let translation = node.world.w_axis.truncate();
+                let linear = Mat4::from_translation(-translation) * *node.world;
+                let triangles = TriangleMesh::from_mesh(mesh, &linear);
+                Some(Collider::new(
+                    ColliderShape::TriangleMesh(Arc::new(triangles)),
+                    origin.to_world(translation),
+                ))
+            })
+            .collect()
+    }
+
+    /// Returns the scene editor.
+    pub fn editor(&self) -> &EditorMode {
+        &self.editor
+    }
+
+    /// Returns the scene editor and the scene graph it edits.
+    pub fn editor_mut(&mut self) -> (&mut EditorMode, &mut SceneGraph) {
+        (&mut self.editor, &mut self.scene_graph)
+    }
+
+    /// Applies an input event to the camera, editor and key bindings.
+    ///
+    /// `RendererSystem` calls this for live input and for replayed input.
+    #[cfg(feature = "windowing")]
+    pub fn handle_input(&mut self, input: InputEvent) {
+        match input {
+            InputEvent::Key {
+                code: key_code,
+                pressed,
+                delta_time,
+            } => {
+                if !pressed || self.handle_editor_key(key_code) {
+                    return;
+                }
+                let Some(action) = self.input_bindings.action(key_code) else {
+                    return;
+                };
+                if let Some(movement) = action.camera_movement() {
+                    self.camera.process_keyboard(movement, delta_time);
+                    return;
+                }
+                match action {
+                    Action::ToggleWireframe => self.toggle_wireframe(),
+                    Action::ToggleBounds => self.toggle_bounds(),
+                    Action::ToggleNormals => self.toggle_normals(),
+                    Action::CycleDebugView => self.cycle_debug_view(),
+                    Action::ToggleProfiler => self.toggle_profiler(),
+                    Action::ToggleLogConsole => self.toggle_log_console(),
+                    Action::TogglePause => self.time.toggle_pause(),
+                    Action::StepFrame => self.time.step(),
+                    Action::SlowDown => self.time.slow_down(),
+                    Action::SpeedUp => self.time.speed_up(),
+                    Action::ToggleRecording => {
+                        let result = if self.is_recording() {
+                            self.stop_recording().map(drop)
+                        } else {
+                            let path = self
+                                .asset_paths
+                                .capture(format!("recording_{}", self.frame_index));
+                            self.start_recording(path, 60)
+                        };
+                        if let Err(e) = result {
+                            self.report_error(e);
+                        }
+                    }
+                    Action::CaptureFrame => {
+                        let path = self
+                            .asset_paths
+                            .capture(format!("frame_{}.gputrace", self.frame_index));
+                        self.capture_next_frame(CaptureDestination::TraceFile(path));
+                    }
+                    _ => {}
+                }
+            }
+            InputEvent::MouseMotion { delta_x, delta_y } => {
+                self.camera.process_mouse_movement(delta_x, delta_y);
+            }
+            InputEvent::Scroll { delta } => self.camera.process_mouse_scroll(delta),
+            InputEvent::Click { x, y, toggle } => {
+                if self.editor.is_enabled() {
+                    let mode = if toggle {
+                        SelectionMode::Toggle
+                    } else {
+                        SelectionMode::Replace
+                    };
+                    self.select_at(Vec2::new(x, y), mode);
+                }
+            }
+        }
+    }
+
+    /// Applies an editor key binding.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key was consumed by the editor.
+    #[cfg(feature = "windowing")]
+    fn handle_editor_key(&mut self, key_code: KeyCode) -> bool {
+        const NUDGE_DISTANCE: f32 = 0.1;
+
+        if key_code == KeyCode::F1 {
+            self.editor.toggle(&mut self.scene_graph);
+            return true;
+        }
+        if !self.editor.is_enabled() {
+            return false;
+        }
+
+        let direction = match key_code {
+            KeyCode::Tab => {
+                self.editor.select_next(&mut self.scene_graph);
+                return true;
+            }
+            KeyCode::Enter => {
+                // Picks without the mouse, e.g. on touch screens
+                let center = self.viewport_size.as_vec2() * 0.5;
+                self.select_at(center, SelectionMode::Replace);
+                return true;
+            }
+            KeyCode::Insert => {
+                if let Err(e) = self.editor.duplicate_selected(&mut self.scene_graph) {
+                    debug!("Nothing duplicated: {e}");
+                }
+                return true;
+            }
+            KeyCode::Escape => {
+                self.editor.clear_selection(&mut self.scene_graph);
+                return true;
+            }
+            KeyCode::ArrowLeft => -Vec3::X,
+            KeyCode::ArrowRight => Vec3::X,
+            KeyCode::ArrowUp => -Vec3::Z,
+            KeyCode::ArrowDown => Vec3::Z,
+            KeyCode::PageUp => Vec3::Y,
+            KeyCode::PageDown => -Vec3::Y,
+            _ => return false,
+        };
+        if self
+            .editor
+            .translate_selected(&mut self.scene_graph, direction * NUDGE_DISTANCE)
+            .is_err()
+        {
+            debug!("No node selected to move");
+        }
+        true
+    }
+
+    pub fn draw_immediate(&mut self, draw_command: DrawCommandBuilder) {
+        self.render_queue.add_draw_command(draw_command);
+    }
+
+    /// Resizes the viewport and the frames presented.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_size` - The size of the drawable area in physical pixels.
+    pub fn resize(&mut self, new_size: UVec2) {
+        self.viewport_size = new_size;
+        self.canvas.set_size(new_size.as_vec2());
+        self.camera
+            .set_aspect_ratio(self.views.main_viewport().aspect_ratio(new_size));
+        self.views.resize(new_size);
+        self.backend.resize(new_size.x, new_size.y);
+    }
+
+    /// Returns the rectangle of the frame the main camera draws into.
+    pub fn viewport(&self) -> Viewport {
+        self.views.main_viewport()
+    }
+
+    /// Sets the rectangle of the frame the main camera draws into, e.g. the
+    /// left half beside a split-screen view.
+    ///
+    /// The camera's aspect ratio follows the rectangle.
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.views.set_main_viewport(viewport);
+        self.camera
+            .set_aspect_ratio(viewport.aspect_ratio(self.viewport_size));
+    }
+
+    /// Adds a split-screen view, drawing the scene from another camera into
+    /// a rectangle of the frame after the main camera.
+    ///
+    /// The view's camera takes the rectangle's aspect ratio. Only the main
+    /// camera is moved by input; views are moved through `view_mut`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// renderer.set_viewport(Viewport::grid(2, 1, 0));
+    /// let top_down = Camera::new(Vec3::new(0.0, 20.0, 0.01), 45.0, 1.0, 0.1, 100.0);
+    /// renderer.add_view(top_down, Viewport::grid(2, 1, 1));
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new view.
+    pub fn add_view(&mut self, camera: Camera, viewport: Viewport) -> ViewId {
+        self.views
+            .add(View::new(camera, viewport, self.viewport_size))
+    }
+
+    /// Removes a split-screen view, returning `true` if it existed.
+    pub fn remove_view(&mut self, id: ViewId) -> bool {
+        self.views.remove(id)
+    }
+
+    /// Returns a split-screen view for modification, e.g. to move its camera.
+    pub fn view_mut(&mut self, id: ViewId) -> Option<&mut View> {
+        self.views.get_mut(id)
+    }
+
+    /// Adds a planar reflection, drawing the scene mirrored across a plane
+    /// into a texture before every frame.
+    ///
+    /// Show the reflection on the plane's surface with a material sampling
+    /// its texture, and keep the surface on a layer outside the reflection's
+    /// cull mask.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let reflection = renderer.add_planar_reflection(PlanarReflection::new(Vec3::ZERO, Vec3::Y));
+    /// let texture = renderer.reflection_texture(reflection).unwrap();
+    /// let water = renderer.create_material(Material::new(Color::BLUE).with_reflection(texture, 0.6));
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// The handle of the new reflection.
+    pub fn add_planar_reflection(&mut self, reflection: PlanarReflection) -> ReflectionId {
+        let resolution = reflection.resolution;
+        let texture = self
+            .backend
+            .create_render_target(resolution.x, resolution.y);
+        info!("Planar reflection added with a {resolution} texture");
+        self.reflections.add(reflection, texture)
+    }
+
+    /// Stops drawing a planar reflection, returning `true` if it existed.
+    ///
+    /// Its texture keeps its last reflection, for materials still sampling it.
+    pub fn remove_planar_reflection(&mut self, id: ReflectionId) -> bool {
+        self.reflections.remove(id).is_some()
+    }
+
+    /// Returns a planar reflection for modification, e.g. to move its plane.
+    ///
+    /// The resolution is fixed when the reflection is added.
+    pub fn planar_reflection_mut(&mut self, id: ReflectionId) -> Option<&mut PlanarReflection> {
+        self.reflections.get_mut(id)
+    }
+
+    /// Returns the texture a planar reflection is drawn into, for its materials.
+    pub fn reflection_texture(&self, id: ReflectionId) -> Option<TextureId> {
+        self.reflections.texture(id)
+    }
+
+    /// Sets the minimap drawn in a corner of every frame, or `None` to remove it.
+    ///
+    /// The scene is drawn into the minimap's texture from above before the
+    /// main camera draws. Its texture is kept while the resolution is unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let minimap = Minimap::new(200.0).with_placement(Anchor::BottomRight, 160.0, 16.0);
+    /// renderer.set_minimap(Some(minimap));
+    /// ```
+    pub fn set_minimap(&mut self, minimap: Option<Minimap>) {
+        let Some(minimap) = minimap else {
+            self.minimap = None;
+            info!("Minimap removed");
+            return;
+        };
+        let texture = match self.minimap {
+            Some((current, texture)) if current.resolution == minimap.resolution => texture,
+            _ => {
+                let resolution = minimap.resolution;
+                info!("Minimap added with a {resolution}x{resolution} texture");
+                self.backend.create_render_target(resolution, resolution)
+            }
+        };
+        self.minimap = Some((minimap, texture));
+    }
+
+    /// Returns the minimap for modification, e.g. to zoom it.
+    ///
+    /// The resolution is fixed when the minimap is set.
+    pub fn minimap_mut(&mut self) -> Option<&mut Minimap> {
+        self.minimap.as_mut().map(|(minimap, _)| minimap)
+    }
+
+    /// Returns the texture the minimap is drawn into.
+    pub fn minimap_texture(&self) -> Option<TextureId> {
+        self.minimap.map(|(_, texture)| texture)
+    }
+
+    /// Creates a 2D texture in private GPU storage for materials to sample,
+    /// filled by `upload_texture`.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the texture in pixels.
+    /// * `height` - The height of the texture in pixels.
+    /// * `pixel_format` - The format of the texture's pixels.
+    ///
+    /// # Returns
+    ///
+    /// The `TextureId` of the new texture.
+    pub fn create_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixel_format: MTLPixelFormat,
+    ) -> TextureId {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width.max(1) as u64);
+        descriptor.set_height(height.max(1) as u64);
+        descriptor.set_pixel_format(pixel_format);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::ShaderRead);
+        self.backend.create_texture(&descriptor)
+    }
+
+    /// Queues an upload of pixels into a texture.
+    ///
+    /// The pixels are copied, or produced by the upload's loader, on a
+    /// background thread and transferred on a separate queue, so large
+    /// textures load without stalling frames. Uploads are submitted within
+    /// the per-frame upload budget, and errors submitting them, such as a
+    /// texture that does not exist, are passed to `report_error`. Materials
+    /// referencing the texture show a white placeholder until its uploads
+    /// complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The texture, region and pixels to upload.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the upload was queued.
+    pub fn upload_texture(&mut self, upload: TextureUpload) -> Result<(), RendererError> {
+        self.upload_scheduler.queue_texture(upload);
+        Ok(())
+    }
+
+    /// Returns the number of textures with uploads still in flight.
+    pub fn pending_texture_uploads(&self) -> usize {
+        self.backend.pending_texture_uploads()
+    }
+
+    /// Sets the bytes of mesh and texture uploads submitted per frame, or
+    /// `None` to submit every upload in the frame after it is made.
+    ///
+    /// Uploads beyond the budget wait for later frames, so streaming in a
+    /// large scene doesn't stall a single frame. Until then, static meshes
+    /// are drawn as the wireframe of their bounds and textures as a white
+    /// placeholder. A frame always submits at least one upload, however
+    /// large.
+    pub fn set_upload_budget(&mut self, budget: Option<u64>) {
+        self.upload_scheduler.set_budget(budget);
+        info!("Upload budget set to {:?} bytes per frame", budget);
+    }
+
+    /// Returns the bytes of uploads submitted per frame, or `None` if there is no limit.
+    pub fn upload_budget(&self) -> Option<u64> {
+        self.upload_scheduler.budget()
+    }
+
+    /// Returns the number of mesh and texture uploads waiting for a later frame's budget.
+    pub fn queued_uploads(&self) -> usize {
+        self.upload_scheduler.len()
+    }
+
+    /// Returns the size of the viewport in physical pixels.
+    pub fn viewport_size(&self) -> UVec2 {
+        self.viewport_size
+    }
+
+    /// Returns the size of the viewport in logical points, as windows are sized.
+    pub fn logical_size(&self) -> Vec2 {
+        (self.viewport_size.as_dvec2() / self.scale_factor).as_vec2()
+    }
+
+    /// Returns the number of physical pixels per logical point of the display.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Sets the display's scale factor and resizes to the new physical size.
+    ///
+    /// Call this when the window moves between displays of different pixel
+    /// densities, e.g. from a Retina display at 2 to an external one at 1.
+    /// The canvas's built-in overlays are scaled to keep their size in points.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale_factor` - Physical pixels per logical point.
+    /// * `new_size` - The size of the drawable area in physical pixels at the new scale.
+    pub fn set_scale_factor(&mut self, scale_factor: f64, new_size: UVec2) {
+        self.scale_factor = scale_factor;
+        self.canvas.set_scale_factor(scale_factor as f32);
+        self.backend.set_scale_factor(scale_factor);
+        self.resize(new_size);
+        info!("Scale factor set to: {scale_factor}");
+    }
+
+    // TODO: Find a way to tell rust these are exposed API methods so they should'nt be counted as dead code
+    pub fn create_triangle(
+        &mut self,
+        v1: Vec3,
+        v2: Vec3,
+        v3: Vec3,
+        color: Color,
+    ) -> TriangleBuilder {
+        TriangleBuilder::new(v1, v2, v3, color)
+    }
+
+    pub fn create_shape(&mut self, vertices: Vec<(Vec3, Color)>) -> ShapeData {
+        let vertices = vertices
+            .into_iter()
+            .map(|(position, color)| vec3_color_to_vertex(position, color))
+            .collect();
+        ShapeData::new(vertices, PrimitiveType::Triangle)
+    }
+}
+
+/// Binds the resident buffers of a static mesh in place of uploading its geometry.
+///
+/// # Arguments
+///
+/// * `backend` - The backend the draw is submitted to.
+/// * `mesh_storage` - The stored meshes.
+/// * `geometry` - The geometry of the draw.
+/// * `positions` - Whether to bind the position-only vertices of depth-only draws.
+///
+/// # Returns
+///
+/// `true` if the buffers were bound, or `false` if the geometry must be uploaded.
+fn bind_static_geometry(
+    backend: &mut impl GraphicsBackend,
+    mesh_storage: &MeshStorage,
+    geometry: GeometryHandle,
+    positions: bool,
+) -> bool {
+    match geometry {
+        GeometryHandle::Mesh(mesh_id) => {
+            mesh_storage
+                .get_mesh(mesh_id)
+                .is_some_and(|mesh| mesh.is_static)
+                && backend.bind_static_mesh(mesh_id, positions)
+        }
+        GeometryHandle::Transient(_) => false,
+    }
+}
+
+/// How much of the reprojected history temporal anti-aliasing keeps per frame.
+const TAA_HISTORY_WEIGHT: f32 = 0.9;
+
+#[cfg(test)]
+mod tests {
+    use super::Renderer;
+    use crate::logging::{self, EngineLogger, LogChannel};
+    use crate::physics::{
+        cloth::Cloth, floating_origin::FloatingOrigin, fluid::Fluid, vector3::Vector3,
+    };
+    use crate::renderer::{
+        ambient::AmbientLight,
+        atmosphere::Atmosphere,
+        backend::null::{BackendCall, NullBackend},
+        canvas::{Anchor, CanvasPoint, CanvasSize},
+        common::{
+            BackendDrawCommand, CaptureDestination, LayerMask, PrimitiveType, RenderTarget,
+            TextureId, Uniforms, Vertex,
+        },
+        config::{AntiAliasing, Transparency},
+        display_link::{DisplayFrame, FrameRateRange},
+        dynamic_mesh::DynamicMesh,
+        error::{BackendError, RendererError},
+        fluid_view::FluidView,
+        gpu_culling::GpuCulling,
+        labels::LabelStyle,
+        lens_flare::LensFlare,
+        light_gizmos::LightGizmos,
+        lights::{LightId, PointLight, SpotLight},
+        material_manager::{Material, MaterialId},
+        minimap::Minimap,
+        particle_file::PARTICLE_RELOAD_INTERVAL,
+        particles::EmitterSource,
+        ray_tracing::RayTracedShadows,
+        reflection::PlanarReflection,
+        render_scale::RenderScale,
+        render_state::RenderState,
+        selection::{SelectionEvent, SelectionMode},
+        shadow_map::{CascadedShadows, ShadowTarget},
+        shape_builders::MeshBuilder,
+        sky::Sky,
+        texture_upload::TextureUpload,
+        trail::{Trail, TrailSource},
+        validation::ValidationIssue,
+        vertex_layout::VertexLayout,
+        viewport::Viewport,
+        Camera, Color, DrawCommandBuilder, InstanceData,
+    };
+    #[cfg(feature = "windowing")]
+    use crate::renderer::{input::Action, replay::InputEvent};
+    use glam::{Mat4, UVec2, Vec2, Vec3};
+    use log::{warn, Level, LevelFilter};
+    use std::{cell::RefCell, num::NonZeroU32, rc::Rc};
+    #[cfg(feature = "windowing")]
+    use winit::keyboard::KeyCode;
+
+    fn renderer() -> Renderer<NullBackend> {
+        Renderer::with_backend(NullBackend::new(), UVec2::new(800, 600))
+    }
+
+    fn triangle() -> MeshBuilder {
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+            .into_iter()
+            .map(|position| Vertex {
+                position,
+                ..Default::default()
+            })
+            .collect();
+        MeshBuilder::new(vertices, PrimitiveType::Triangle)
+    }
+
+    #[test]
+    fn test_render_culls_layers() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id).with_layers(LayerMask::UI));
+        renderer.camera_mut().set_cull_mask(LayerMask::DEFAULT);
+        renderer.render().unwrap();
+
+        assert_eq!(renderer.backend().draws().count(), 1);
+        assert!(renderer.backend().calls().iter().any(|call| matches!(
+            call,
+            BackendCall::UpdateFrameConstants(constants)
+                if constants.viewport_size == [800.0, 600.0]
+        )));
+    }
+
+    #[test]
+    fn test_render_sorts_by_material() {
+        let mut renderer = renderer();
+        let red = renderer.create_material(Material::new(Color::new(1.0, 0.0, 0.0, 1.0)));
+        let blue = renderer.create_material(Material::new(Color::new(0.0, 0.0, 1.0, 1.0)));
+        let mesh_id = renderer.add_mesh(triangle());
+        for material_id in [blue, red, blue] {
+            renderer
+                .draw_immediate(DrawCommandBuilder::new_mesh(mesh_id).with_material(material_id));
+        }
+        renderer.render().unwrap();
+
+        assert_eq!(renderer.backend().draw_materials(), vec![red, blue, blue]);
+        assert_eq!(renderer.backend().pipeline_switches(), 1);
+        assert!(renderer
+            .backend()
+            .calls()
+            .contains(&BackendCall::UpdateMaterials { count: 3 }));
+    }
+
+    #[test]
+    fn test_render_uploads_custom_vertex_layout() {
+        let mut renderer = renderer();
+        let layout = VertexLayout::position_normal_uv();
+        let lit = renderer.add_mesh(
+            triangle()
+                .with_layout(layout)
+                .with_normals(&[Vec3::Z; 3])
+                .with_tex_coords(&[Vec2::ZERO, Vec2::X, Vec2::Y]),
+        );
+        let plain = renderer.add_mesh(triangle());
+        for mesh_id in [lit, plain] {
+            renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+        }
+        renderer.render().unwrap();
+
+        let calls = renderer.backend().calls();
+        assert!(calls.contains(&BackendCall::UpdatePackedVertexBuffer { layout, count: 3 }));
+        assert!(calls.contains(&BackendCall::UpdateVertexBuffer { count: 3 }));
+        // The two layouts need different pipelines
+        assert_eq!(renderer.backend().pipeline_switches(), 2);
+    }
+
+    #[test]
+    fn test_render_depth_prepass_draws_positions_first() {
+        let mut renderer = renderer();
+        renderer.set_depth_prepass(true);
+        let mesh_id = renderer.add_mesh(triangle());
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+        renderer.draw_immediate(
+            DrawCommandBuilder::new_mesh(mesh_id).with_render_state(RenderState::TRANSPARENT),
+        );
+        renderer.render().unwrap();
+
+        // Only the opaque draw has its depth drawn, from positions alone, before any shading
+        let calls = renderer.backend().calls();
+        let depth_draws: Vec<_> = calls
+            .iter()
+            .enumerate()
+            .filter(|(_, call)| matches!(call, BackendCall::DrawDepth(_)))
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(depth_draws.len(), 1);
+        let first_draw = calls
+            .iter()
+            .position(|call| matches!(call, BackendCall::Draw(_)))
+            .unwrap();
+        assert!(depth_draws[0] < first_draw);
+        assert_eq!(
+            calls[depth_draws[0] - 3],
+            BackendCall::UpdatePackedVertexBuffer {
+                layout: VertexLayout::position(),
+                count: 3
+            }
+        );
+        assert_eq!(renderer.backend().draws().count(), 2);
+    }
+
+    #[test]
+    fn test_render_static_meshes_from_resident_buffers() {
+        let mut renderer = renderer();
+        renderer.set_depth_prepass(true);
+        let static_id = renderer.add_mesh(triangle().with_indices(vec![0, 1, 2]).with_static(true));
+        let dynamic_id = renderer.add_mesh(triangle());
+        assert_eq!(renderer.queued_uploads(), 1);
+
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(static_id));
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(dynamic_id));
+        renderer.render().unwrap();
+
+        // The static mesh is uploaded once, then binds its buffers for both
+        // passes, copying nothing per draw
+        let calls = renderer.backend().calls();
+        assert!(calls.contains(&BackendCall::UploadStaticMesh(static_id)));
+        let bound: Vec<_> = calls
+            .iter()
+            .filter(|call| matches!(call, BackendCall::BindStaticMesh { .. }))
+            .collect();
+        assert_eq!(
+            bound,
+            [
+                &BackendCall::BindStaticMesh {
+                    mesh: static_id,
+                    positions: true
+                },
+                &BackendCall::BindStaticMesh {
+                    mesh: static_id,
+                    positions: false
+                },
+            ]
+        );
+        assert!(!calls.contains(&BackendCall::UpdateIndexBuffer { count: 3 }));
+        assert!(calls.contains(&BackendCall::UpdateVertexBuffer { count: 3 }));
+        assert_eq!(renderer.backend().draws().count(), 2);
+    }
+
+    #[test]
+    fn test_render_draws_transparent_last() {
+        let mut renderer = renderer();
+        renderer.set_transparency(Transparency::WeightedBlended);
+        // Created first, so its draws sort before the opaque material's
+        let glass = renderer.create_material(
+            Material::new(Color::new(1.0, 1.0, 1.0, 0.3))
+                .with_render_state(RenderState::TRANSPARENT),
+        );
+        let opaque = renderer.create_material(Material::new(Color::new(1.0, 0.0, 0.0, 1.0)));
+        let mesh_id = renderer.add_mesh(triangle());
+        for material_id in [glass, opaque] {
+            renderer
+                .draw_immediate(DrawCommandBuilder::new_mesh(mesh_id).with_material(material_id));
+        }
+        renderer.draw_immediate(
+            DrawCommandBuilder::new_mesh(mesh_id)
+                .with_material(opaque)
+                .with_render_state(RenderState::TRANSPARENT),
+        );
+        renderer.render().unwrap();
+
+        assert_eq!(
+            renderer.backend().draw_materials(),
+            vec![opaque, glass, opaque]
+        );
+        assert!(renderer
+            .backend()
+            .calls()
+            .contains(&BackendCall::SetTransparency(Transparency::WeightedBlended)));
+    }
+
+    #[test]
+    fn test_render_scale() {
+        let mut renderer = renderer();
+        renderer.set_render_scale(RenderScale::Fixed(0.5));
+        renderer.render().unwrap();
+
+        assert_eq!(renderer.render_scale(), 0.5);
+        let calls = renderer.backend().calls();
+        assert!(calls.contains(&BackendCall::SetRenderScale(0.5)));
+        assert!(calls.iter().any(|call| matches!(
+            call,
+            BackendCall::UpdateFrameConstants(constants)
+                if constants.viewport_size == [400.0, 300.0]
+        )));
+    }
+
+    #[test]
+    fn test_render_skips_invalid_draws() {
+        let mut renderer = renderer();
+        renderer.set_validation(true);
+        let mesh_id = renderer.add_mesh(triangle());
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id + 1));
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+        renderer.render().unwrap();
+
+        assert_eq!(renderer.backend().draws().count(), 1);
+        assert_eq!(renderer.validation_errors().len(), 1);
+        assert_eq!(
+            renderer.validation_errors()[0].issue,
+            ValidationIssue::MissingMesh(mesh_id + 1)
+        );
+    }
+
+    #[test]
+    fn test_render_jitters_with_taa() {
+        let mut renderer = renderer();
+        renderer.set_anti_aliasing(AntiAliasing::Taa);
+        let mesh_id = renderer.add_mesh(triangle());
+        for _ in 0..2 {
+            renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+            renderer.render().unwrap();
+            assert_eq!(
+                renderer.backend().calls().last(),
+                Some(&BackendCall::EndFrame)
+            );
+        }
+        let view_projections: Vec<Mat4> = renderer
+            .backend()
+            .calls()
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::UpdateUniformBuffer(Uniforms {
+                    view_projection_matrix,
+                    ..
+                }) => Some(*view_projection_matrix),
+                _ => None,
+            })
+            .collect();
+
+        // The camera is still, so only the jitter differs between frames
+        assert_eq!(view_projections.len(), 2);
+        assert_ne!(view_projections[0], view_projections[1]);
+        let history_valid: Vec<u32> = renderer
+            .backend()
+            .calls()
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::SetTemporalConstants(constants) => Some(constants.history_valid),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(history_valid, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_render_lays_out_lens_flare() {
+        let mut renderer = renderer();
+        renderer.set_lens_flare(Some(LensFlare::sun(Vec3::new(0.1, 0.1, -1.0))));
+        renderer.render().unwrap();
+        renderer.set_lens_flare(None);
+
+        let flares: Vec<_> = renderer
+            .backend()
+            .calls()
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::SetLensFlare(frame) => Some(frame.as_ref()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(flares.len(), 2);
+        assert_eq!(flares[0].unwrap().elements.len(), 7);
+        assert!(flares[1].is_none());
+    }
+
+    #[test]
+    fn test_render_draws_canvas_overlay() {
+        let mut renderer = renderer();
+        renderer.canvas().rect(
+            CanvasPoint::normalized(0.0, 0.0).anchored(Anchor::Center),
+            CanvasSize::pixels(10.0, 10.0),
+            Color::WHITE,
+        );
+        renderer.render().unwrap();
+        // The canvas is cleared, so the next frames remove the overlay once
+        renderer.render().unwrap();
+        renderer.render().unwrap();
+
+        let overlays: Vec<_> = renderer
+            .backend()
+            .calls()
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::SetOverlay(frame) => Some(frame.as_ref()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(overlays.len(), 2);
+        let frame = overlays[0].unwrap();
+        assert_eq!(frame.size, [800.0, 600.0]);
+        assert_eq!(frame.vertices[0].position, [395.0, 295.0]);
+        assert!(overlays[1].is_none());
+        assert!(renderer.canvas().is_empty());
+    }
+
+    #[test]
+    fn test_render_draws_log_console() {
+        let mut renderer = renderer();
+        // The only test installing the logger, which other tests' records then pass through
+        let logger = logging::logger().unwrap_or_else(|| {
+            EngineLogger::new(LevelFilter::Info)
+                .with_stderr(false)
+                .init()
+                .unwrap()
+        });
+        warn!(target: "asset", "Missing texture");
+        assert!(logger
+            .buffer()
+            .recent(usize::MAX, Level::Warn)
+            .iter()
+            .any(|entry| entry.channel == LogChannel::Asset && entry.message == "Missing texture"));
+
+        renderer.toggle_log_console();
+        renderer.render().unwrap();
+        let overlay = renderer
+            .backend()
+            .calls()
+            .iter()
+            .find_map(|call| match call {
+                BackendCall::SetOverlay(frame) => frame.as_ref(),
+                _ => None,
+            });
+        assert!(overlay.is_some_and(|frame| !frame.vertices.is_empty()));
+    }
+
+    /// Returns the frame capture calls made so far, with whether each frame's
+    /// constants were updated while a capture was open.
+    fn capture_calls(renderer: &Renderer<NullBackend>) -> (Vec<BackendCall>, Vec<bool>) {
+        let mut captures = Vec::new();
+        let mut captured_frames = Vec::new();
+        let mut capturing = false;
+        for call in renderer.backend().calls() {
+            match call {
+                BackendCall::BeginFrameCapture(_) => capturing = true,
+                BackendCall::EndFrameCapture => capturing = false,
+                BackendCall::UpdateFrameConstants(_) => {
+                    captured_frames.push(capturing);
+                    continue;
+                }
+                _ => continue,
+            }
+            captures.push(call.clone());
+        }
+        (captures, captured_frames)
+    }
+
+    #[test]
+    fn test_capture_next_frame() {
+        let mut renderer = renderer();
+        renderer.render().unwrap();
+        assert!(capture_calls(&renderer).0.is_empty());
+
+        // Only the latest request is kept, and it is captured for one frame
+        let destination = CaptureDestination::TraceFile("frame.gputrace".into());
+        renderer.capture_next_frame(CaptureDestination::DeveloperTools);
+        renderer.capture_next_frame(destination.clone());
+        renderer.render().unwrap();
+        renderer.render().unwrap();
+        let (captures, captured_frames) = capture_calls(&renderer);
+        assert_eq!(
+            captures,
+            vec![
+                BackendCall::BeginFrameCapture(destination),
+                BackendCall::EndFrameCapture
+            ]
+        );
+        assert_eq!(captured_frames, vec![false, true, false]);
+
+        // The capture is armed again by the next request
+        renderer.capture_next_frame(CaptureDestination::DeveloperTools);
+        renderer.render().unwrap();
+        assert_eq!(capture_calls(&renderer).0.len(), 4);
+        assert_eq!(capture_calls(&renderer).1, vec![false, true, false, true]);
+    }
+
+    #[test]
+    #[cfg(feature = "windowing")]
+    fn test_capture_frame_hotkey() {
+        let mut renderer = renderer();
+        renderer.handle_input(InputEvent::Key {
+            code: KeyCode::F12,
+            pressed: true,
+            delta_time: 0.1,
+        });
+        renderer.render().unwrap();
+        renderer.render().unwrap();
+
+        let (captures, captured_frames) = capture_calls(&renderer);
+        assert!(matches!(
+            captures.as_slice(),
+            [
+                BackendCall::BeginFrameCapture(CaptureDestination::TraceFile(path)),
+                BackendCall::EndFrameCapture
+            ] if path.ends_with("frame_0.gputrace")
+        ));
+        assert_eq!(captured_frames, vec![true, false]);
+    }
+
+    #[test]
+    #[cfg(feature = "windowing")]
+    fn test_input_bindings() {
+        let mut renderer = renderer();
+        let press = |renderer: &mut Renderer<NullBackend>, code| {
+            renderer.handle_input(InputEvent::Key {
+                code,
+                pressed: true,
+                delta_time: 0.1,
+            })
+        };
+        press(&mut renderer, KeyCode::KeyP);
+        assert!(renderer.time().is_paused());
+
+        renderer
+            .input_bindings_mut()
+            .bind(Action::TogglePause, KeyCode::KeyQ);
+        press(&mut renderer, KeyCode::KeyP);
+        assert!(renderer.time().is_paused());
+        press(&mut renderer, KeyCode::KeyQ);
+        assert!(!renderer.time().is_paused());
+
+        // Arrow keys move the camera while the editor, which takes them, is disabled
+        renderer
+            .input_bindings_mut()
+            .bind(Action::MoveForward, KeyCode::ArrowUp);
+        press(&mut renderer, KeyCode::ArrowUp);
+        assert!(renderer.camera().position().z < 3.0);
+    }
+
+    #[test]
+    fn test_resize_and_render_frame() {
+        let mut renderer = renderer();
+        renderer.resize(UVec2::new(1024, 512));
+        renderer.render_frame().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        renderer.render_frame().unwrap();
+
+        // The clock advances between frames
+        assert!(renderer.time().elapsed() > 0.0);
+        let calls = renderer.backend().calls();
+        assert!(calls.contains(&BackendCall::Resize(1024, 512)));
+        assert!(calls.iter().any(|call| matches!(
+            call,
+            BackendCall::UpdateFrameConstants(constants)
+                if constants.viewport_size == [1024.0, 512.0]
+        )));
+    }
+
+    #[test]
+    fn test_scale_factor() {
+        let mut renderer = renderer();
+        renderer.set_scale_factor(2.0, UVec2::new(1600, 1200));
+        assert_eq!(renderer.viewport_size(), UVec2::new(1600, 1200));
+        assert_eq!(renderer.logical_size(), Vec2::new(800.0, 600.0));
+        assert_eq!(renderer.canvas().scale_factor(), 2.0);
+
+        // Moving to a display at 1 keeps the logical size
+        renderer.set_scale_factor(1.0, UVec2::new(800, 600));
+        assert_eq!(renderer.logical_size(), Vec2::new(800.0, 600.0));
+        let calls = renderer.backend().calls();
+        assert!(calls.contains(&BackendCall::SetScaleFactor(2.0)));
+        assert!(calls.ends_with(&[
+            BackendCall::SetScaleFactor(1.0),
+            BackendCall::Resize(800, 600)
+        ]));
+    }
+
+    #[test]
+    fn test_frame_rate_range_paces_display_frames() {
+        let mut renderer = renderer();
+        let frame = |timestamp| DisplayFrame {
+            timestamp,
+            refresh_period: 1.0 / 120.0,
+        };
+        renderer.set_frame_rate_range(Some(FrameRateRange::fixed(60.0)));
+        renderer.set_display_frame(frame(1.0));
+        assert_eq!(renderer.refresh_rate(), Some(120.0));
+        assert_eq!(renderer.frame_rate(), Some(60.0));
+        assert!(renderer
+            .backend()
+            .calls()
+            .contains(&BackendCall::SetFrameRate(Some(60.0))));
+
+        // Every other refresh of the 120 Hz display is skipped
+        assert!(!renderer.frame_due(&frame(1.0 + 1.0 / 120.0)));
+        assert!(renderer.frame_due(&frame(1.0 + 2.0 / 120.0)));
+
+        // Presentation times drive the clock
+        renderer.tick_time();
+        renderer.set_display_frame(frame(1.0 + 2.0 / 120.0));
+        renderer.tick_time();
+        assert!((renderer.time().delta() - 1.0 / 60.0).abs() < 1e-6);
+
+        renderer.set_frame_rate_range(None);
+        assert!(renderer.frame_due(&frame(1.0 + 3.0 / 120.0)));
+    }
+
+    #[test]
+    fn test_split_screen_views() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        let node = renderer
+            .scene_graph_mut()
+            .add_node(None, Mat4::IDENTITY)
+            .unwrap();
+        renderer
+            .scene_graph_mut()
+            .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+            .unwrap();
+        let (left, right) = (Viewport::grid(2, 1, 0), Viewport::grid(2, 1, 1));
+        renderer.set_viewport(left);
+        let camera = Camera::new(Vec3::new(0.0, 0.0, -3.0), 45.0, 1.0, 0.1, 100.0);
+        let view = renderer.add_view(camera, right);
+        assert_eq!(renderer.camera().aspect_ratio(), 400.0 / 600.0);
+        renderer.render().unwrap();
+
+        // Each view draws the queue into its rectangle with its own camera
+        let calls = renderer.backend().calls();
+        let viewports: Vec<_> = calls
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::SetViewport(viewport) => Some(*viewport),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(viewports, [left, right]);
+        let view_projections: Vec<_> = calls
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::UpdateUniformBuffer(uniforms) => Some(uniforms.view_projection_matrix),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(view_projections.len(), 2);
+        assert_ne!(view_projections[0], view_projections[1]);
+
+        // Resizing keeps the views' aspect ratios
+        renderer.resize(UVec2::new(1600, 600));
+        let aspect_ratio = renderer.view_mut(view).unwrap().camera.aspect_ratio();
+        assert_eq!(aspect_ratio, 800.0 / 600.0);
+        assert!(renderer.remove_view(view));
+    }
+
+    #[test]
+    fn test_planar_reflection_pass() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        let node = renderer
+            .scene_graph_mut()
+            .add_node(None, Mat4::IDENTITY)
+            .unwrap();
+        renderer
+            .scene_graph_mut()
+            .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+            .unwrap();
+        let water = PlanarReflection::new(Vec3::new(0.0, -1.0, 0.0), Vec3::Y)
+            .with_resolution(UVec2::new(256, 128));
+        let reflection = renderer.add_planar_reflection(water);
+        let texture = renderer.reflection_texture(reflection).unwrap();
+        assert!(renderer
+            .backend()
+            .calls()
+            .contains(&BackendCall::CreateRenderTarget(texture, 256, 128)));
+        renderer.render().unwrap();
+
+        // The mirrored scene is drawn into the texture before the frame
+        let target = RenderTarget {
+            texture,
+            mirrored: true,
+        };
+        let calls = renderer.backend().calls();
+        let start = calls
+            .iter()
+            .position(|call| *call == BackendCall::SetRenderTarget(Some(target)))
+            .unwrap();
+        let end = calls
+            .iter()
+            .position(|call| *call == BackendCall::SetRenderTarget(None))
+            .unwrap();
+        let is_draw = |call: &BackendCall| matches!(call, BackendCall::Draw(_));
+        assert_eq!(
+            calls[start..end]
+                .iter()
+                .filter(|call| is_draw(call))
+                .count(),
+            1
+        );
+        assert_eq!(calls[end..].iter().filter(|call| is_draw(call)).count(), 1);
+
+        // Nothing is reflected while the camera is below the water
+        let call_count = renderer.backend().calls().len();
+        renderer.planar_reflection_mut(reflection).unwrap().distance = 5.0;
+        renderer.render().unwrap();
+        assert!(!renderer.backend().calls()[call_count..]
+            .iter()
+            .any(|call| matches!(call, BackendCall::SetRenderTarget(_))));
+        assert!(renderer.remove_planar_reflection(reflection));
+    }
+
+    #[test]
+    fn test_capture_panorama() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        let node = renderer
+            .scene_graph_mut()
+            .add_node(None, Mat4::IDENTITY)
+            .unwrap();
+        renderer
+            .scene_graph_mut()
+            .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+            .unwrap();
+        renderer.add_view(
+            Camera::new(Vec3::ZERO, 45.0, 1.0, 0.1, 100.0),
+            Viewport::grid(2, 1, 1),
+        );
+        renderer.set_viewport(Viewport::grid(2, 1, 0));
+        renderer.toggle_log_console();
+        let cube_map = renderer.capture_panorama(Vec3::Y, 64).unwrap();
+        assert_eq!(cube_map.resolution, 64);
+        assert!(cube_map
+            .faces
+            .iter()
+            .all(|face| (face.width, face.height) == (64, 64)));
+
+        // Every face draws one viewport from its own camera, without the overlay
+        let calls = renderer.backend().calls();
+        let view_projections: Vec<_> = calls
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::UpdateUniformBuffer(uniforms) => Some(uniforms.view_projection_matrix),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(view_projections.len(), 6);
+        assert!(view_projections[1..]
+            .iter()
+            .all(|view_projection| *view_projection != view_projections[0]));
+        assert!(!calls
+            .iter()
+            .any(|call| matches!(call, BackendCall::SetOverlay(Some(_)))));
+        assert!(calls.ends_with(&[
+            BackendCall::SetFrameReadback(false),
+            BackendCall::Resize(800, 600)
+        ]));
+
+        // The camera and views are restored
+        assert_eq!(renderer.viewport_size(), UVec2::new(800, 600));
+        assert_eq!(renderer.viewport(), Viewport::grid(2, 1, 0));
+        assert_eq!(renderer.camera().aspect_ratio(), 400.0 / 600.0);
+        assert_eq!(cube_map.to_equirectangular(128).height, 64);
+    }
+
+    #[test]
+    fn test_render_profiles_frames() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        let node = renderer
+            .scene_graph_mut()
+            .add_node(None, Mat4::IDENTITY)
+            .unwrap();
+        renderer
+            .scene_graph_mut()
+            .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+            .unwrap();
+        renderer.toggle_profiler();
+        renderer.render().unwrap();
+        renderer.render().unwrap();
+
+        // Each frame's scopes are collected when the next frame starts
+        let profiler = renderer.profiler();
+        let render = profiler.scope("render").unwrap();
+        assert_eq!(render.calls, 1);
+        assert!(render.duration >= profiler.scope("render/draw").unwrap().duration);
+        assert!(profiler.scope("render/scene_graph").is_some());
+        let overlay = renderer
+            .backend()
+            .calls()
+            .iter()
+            .rev()
+            .find_map(|call| match call {
+                BackendCall::SetOverlay(frame) => frame.as_ref(),
+                _ => None,
+            });
+        assert!(overlay.is_some_and(|frame| !frame.vertices.is_empty()));
+
+        renderer.toggle_profiler();
+        assert!(!renderer.profiler().is_enabled());
+    }
+
+    #[test]
+    fn test_render_draws_minimap() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        let node = renderer
+            .scene_graph_mut()
+            .add_node(None, Mat4::IDENTITY)
+            .unwrap();
+        renderer
+            .scene_graph_mut()
+            .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+            .unwrap();
+        renderer.set_minimap(Some(Minimap::new(10.0).with_resolution(128)));
+        let texture = renderer.minimap_texture().unwrap();
+        assert!(renderer
+            .backend()
+            .calls()
+            .contains(&BackendCall::CreateRenderTarget(texture, 128, 128)));
+        renderer.render().unwrap();
+
+        // The scene is drawn into the texture, which the overlay shows
+        let calls = renderer.backend().calls();
+        let target = BackendCall::SetRenderTarget(Some(RenderTarget {
+            texture,
+            mirrored: false,
+        }));
+        let start = calls.iter().position(|call| *call == target).unwrap();
+        let end = start
+            + calls[start..]
+                .iter()
+                .position(|call| *call == BackendCall::SetRenderTarget(None))
+                .unwrap();
+        assert!(calls[start..end]
+            .iter()
+            .any(|call| matches!(call, BackendCall::Draw(_))));
+        let overlay = calls
+            .iter()
+            .find_map(|call| match call {
+                BackendCall::SetOverlay(frame) => frame.clone(),
+                _ => None,
+            })
+            .unwrap();
+        assert!(overlay
+            .batches
+            .iter()
+            .any(|batch| batch.texture == Some(texture)));
+
+        // Changing the view keeps the texture
+        renderer.set_minimap(Some(Minimap::new(50.0).with_resolution(128)));
+        assert_eq!(renderer.minimap_texture(), Some(texture));
+        renderer.set_minimap(None);
+        assert!(renderer.minimap_mut().is_none());
+    }
+
+    #[test]
+    fn test_render_draws_plots() {
+        let mut renderer = renderer();
+        for value in [1.0, 3.0, 2.0] {
+            renderer.plot("energy", value);
+        }
+        renderer.render().unwrap();
+        let last_overlay = |renderer: &Renderer<NullBackend>| {
+            renderer
+                .backend()
+                .calls()
+                .iter()
+                .rev()
+                .find_map(|call| match call {
+                    BackendCall::SetOverlay(frame) => Some(frame.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        assert!(last_overlay(&renderer).is_some_and(|frame| !frame.vertices.is_empty()));
+        assert_eq!(renderer.plot_series("energy").unwrap().latest(), Some(2.0));
+
+        // Removing the last series removes the overlay
+        assert!(renderer.remove_plot("energy"));
+        renderer.render().unwrap();
+        assert!(last_overlay(&renderer).is_none());
+    }
+
+    #[test]
+    fn test_render_draws_labels() {
+        let mut renderer = renderer();
+        let node = renderer
+            .scene_graph_mut()
+            .add_node(None, Mat4::IDENTITY)
+            .unwrap();
+        let style = LabelStyle::default().with_background(None);
+        let origin = renderer.add_label(node, "Origin", style);
+        // Behind the camera
+        let behind = renderer.add_label(Vec3::new(0.0, 0.0, 10.0), "Behind", style);
+        renderer.render().unwrap();
+
+        let frame = renderer
+            .backend()
+            .calls()
+            .iter()
+            .find_map(|call| match call {
+                BackendCall::SetOverlay(frame) => frame.clone(),
+                _ => None,
+            })
+            .unwrap();
+        // "Origin" is centered on the screen, 70 by 16 pixels with padding
+        let (min, max) = frame.vertices.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), vertex| {
+                let position = Vec2::from(vertex.position);
+                (min.min(position), max.max(position))
+            },
+        );
+        assert_eq!(min, Vec2::new(365.0, 292.0));
+        assert_eq!(max, Vec2::new(435.0, 308.0));
+        let occlusion = frame.vertices[0].occlusion;
+        assert_eq!(&occlusion[..2], &[0.0, 0.0]);
+        assert_eq!(occlusion[3], 0.25);
+
+        assert!(renderer.remove_label(behind));
+        assert!(!renderer.remove_label(behind));
+        assert!(renderer.label_mut(origin).is_some());
+    }
+
+    #[test]
+    fn test_render_draws_node_trails() {
+        let mut renderer = renderer();
+        let node = renderer
+            .scene_graph_mut()
+            .add_node(None, Mat4::IDENTITY)
+            .unwrap();
+        let trail = renderer.add_trail(Trail::new(TrailSource::Node(node), 2));
+        for step in 0..3 {
+            renderer
+                .scene_graph_mut()
+                .set_translation(node, Vec3::X * step as f32)
+                .unwrap();
+            renderer.render().unwrap();
+        }
+
+        // Drawn once two positions are recorded, then capped by the capacity
+        let trail_draws: Vec<_> = renderer
+            .backend()
+            .draws()
+            .filter_map(|draw| match *draw {
+                BackendDrawCommand::Basic {
+                    primitive_type: PrimitiveType::LineStrip,
+                    vertex_count,
+                    ..
+                } => Some(vertex_count),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(trail_draws, [2, 2]);
+        let positions: Vec<_> = renderer.trail_mut(trail).unwrap().positions().collect();
+        assert_eq!(positions, [Vec3::X, Vec3::X * 2.0]);
+        assert!(renderer.remove_trail(trail));
+    }
+
+    #[test]
+    fn test_render_draws_cloth_as_dynamic_mesh() {
+        let mut renderer = renderer();
+        let mut cloth = Cloth::grid(4, 4, Vec2::ONE, 1.0);
+        let flag = renderer.add_dynamic_mesh(DynamicMesh::from_cloth(&cloth, Color::RED));
+        for _ in 0..2 {
+            cloth.step(1.0 / 60.0);
+            renderer
+                .dynamic_mesh_mut(flag)
+                .unwrap()
+                .set_positions(cloth.positions());
+            renderer.render().unwrap();
+        }
+
+        let index_counts: Vec<_> = renderer
+            .backend()
+            .draws()
+            .filter_map(|draw| match *draw {
+                BackendDrawCommand::Indexed {
+                    primitive_type: PrimitiveType::Triangle,
+                    index_count,
+                    ..
+                } => Some(index_count),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(index_counts, [96, 96]);
+        assert!(renderer.remove_dynamic_mesh(flag));
+        assert!(renderer.dynamic_mesh_mut(flag).is_none());
+    }
+
+    #[test]
+    fn test_render_draws_fluid_as_instanced_spheres() {
+        let mut renderer = renderer();
+        let mut fluid = Fluid::new(0.1, 1000.0);
+        fluid.add_block(Vec3::ZERO, Vec3::splat(0.1), 0.1);
+        let view = renderer.add_fluid_view(FluidView::gas(&fluid, 0.2, 0.5));
+        for _ in 0..2 {
+            fluid.step(1.0 / 120.0);
+            renderer.fluid_view_mut(view).unwrap().update(&fluid);
+            renderer.render().unwrap();
+        }
+
+        let instance_counts: Vec<_> = renderer
+            .backend()
+            .draws()
+            .filter_map(|draw| match *draw {
+                BackendDrawCommand::IndexedInstanced { instance_count, .. } => Some(instance_count),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(instance_counts, [8, 8]);
+        assert!(renderer.remove_fluid_view(view));
+    }
+
+    #[test]
+    fn test_render_draws_and_reloads_particle_files() {
+        let path =
+            std::env::temp_dir().join(format!("render-particles-{}.ron", std::process::id()));
+        std::fs::write(&path, "(burst: 3, rate: 0.0, texture: Some(\"smoke\"))").unwrap();
+        let mut renderer = renderer();
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let received = Rc::clone(&errors);
+        renderer.set_error_handler(move |error| received.borrow_mut().push(error.to_string()));
+        let texture = TextureId(NonZeroU32::new(1).unwrap());
+        renderer.set_particle_texture("smoke", texture);
+        let emitter = renderer
+            .load_particle_emitter(&path, EmitterSource::Position(Vec3::ZERO))
+            .unwrap();
+        let particle_draws = |renderer: &Renderer<NullBackend>| -> Vec<_> {
+            renderer
+                .backend()
+                .draws()
+                .filter_map(|draw| match *draw {
+                    BackendDrawCommand::IndexedInstanced { instance_count, .. } => {
+                        Some(instance_count)
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // The burst is drawn as one instanced draw with the registered texture
+        renderer.render().unwrap();
+        assert_eq!(particle_draws(&renderer), [3]);
+        let material = renderer.particle_emitter_mut(emitter).unwrap().material();
+        assert_eq!(
+            renderer.particle_materials.get(&Some(texture)).copied(),
+            material
+        );
+
+        // Saving a malformed file keeps the effect and reports the error
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::fs::write(&path, "(burst: -1)").unwrap();
+        renderer.particle_files.checked -= PARTICLE_RELOAD_INTERVAL;
+        renderer.render().unwrap();
+        assert_eq!(errors.borrow().len(), 1);
+        assert!(errors.borrow()[0].contains("line 1"));
+
+        // Fixing it swaps the effect
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::fs::write(&path, "(burst: 5, max_particles: 4)").unwrap();
+        renderer.particle_files.checked -= PARTICLE_RELOAD_INTERVAL;
+        renderer.particle_emitter_mut(emitter).unwrap().restart();
+        renderer.render().unwrap();
+        assert_eq!(particle_draws(&renderer).last(), Some(&4));
+        assert!(renderer.remove_particle_emitter(emitter));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mesh_colliders_from_collidable_nodes() {
+        let mut renderer = renderer();
+        let collidable = renderer.add_mesh(triangle().with_collidable(true));
+        let decoration = renderer.add_mesh(triangle());
+        for mesh_id in [collidable, decoration] {
+            let scene_graph = renderer.scene_graph_mut();
+            let node = scene_graph
+                .add_node(None, Mat4::from_translation(Vec3::new(5.0, 0.0, -1.0)))
+                .unwrap();
+            scene_graph
+                .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+                .unwrap();
+        }
+
+        let mut origin = FloatingOrigin::new();
+        origin.set_origin(Vector3::new(1000.0, 0.0, 0.0));
+        let colliders = renderer.mesh_colliders(&origin);
+        assert_eq!(colliders.len(), 1);
+        assert_eq!(colliders[0].position, Vector3::new(1005.0, 0.0, -1.0));
+        let contact = colliders[0]
+            .sweep(
+                Vector3::new(1005.2, 0.2, 3.0),
+                Vector3::new(1005.2, 0.2, -5.0),
+                0.0,
+            )
+            .unwrap();
+        assert!((contact.time - 0.5).abs() < 1e-6);
+        assert_eq!(contact.normal, Vector3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_raycast_picks_nearest_node() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle().with_bvh(true));
+        let mut nodes = Vec::new();
+        for z in [-1.0, 0.0] {
+            let scene_graph = renderer.scene_graph_mut();
+            let node = scene_graph
+                .add_node(None, Mat4::from_translation(Vec3::new(-0.25, -0.25, z)))
+                .unwrap();
+            scene_graph
+                .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+                .unwrap();
+            nodes.push(node);
+        }
+
+        // The camera at (0, 0, 3) looks through the screen center at both triangles
+        let ray = renderer
+            .camera_mut()
+            .screen_ray(Vec2::new(400.0, 300.0), Vec2::new(800.0, 600.0));
+        let (node, hit) = renderer.raycast(&ray).unwrap();
+        assert_eq!(node, nodes[1]);
+        assert!((hit.distance - 3.0).abs() < 1e-5);
+        assert!(ray.at(hit.distance).abs_diff_eq(Vec3::ZERO, 1e-5));
+
+        let ray = renderer
+            .camera_mut()
+            .screen_ray(Vec2::ZERO, Vec2::new(800.0, 600.0));
+        assert!(renderer.raycast(&ray).is_none());
+    }
+
+    #[test]
+    fn test_pick_hover_and_select() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        let scene_graph = renderer.scene_graph_mut();
+        let node = scene_graph
+            .add_node(None, Mat4::from_translation(Vec3::new(-0.25, -0.25, 0.0)))
+            .unwrap();
+        scene_graph
+            .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+            .unwrap();
+
+        let center = Vec2::new(400.0, 300.0);
+        assert_eq!(renderer.pick(center), Some(node));
+        assert_eq!(renderer.hover_at(Some(center)), Some(node));
+        assert_eq!(
+            renderer.select_at(center, SelectionMode::Replace),
+            Some(node)
+        );
+        assert!(renderer.scene_graph().outline(node).is_some());
+        assert_eq!(
+            renderer.selection_events(),
+            [
+                SelectionEvent::Hovered(Some(node)),
+                SelectionEvent::Selected(node)
+            ]
+        );
+
+        // Clicking empty space clears the selection, and removed nodes are forgotten
+        renderer.select_at(Vec2::ZERO, SelectionMode::Replace);
+        assert_eq!(renderer.editor().selected(), None);
+        renderer.scene_graph_mut().remove_node(node).unwrap();
+        renderer.render().unwrap();
+        assert_eq!(renderer.editor().selection().hovered(), None);
+        assert_eq!(
+            renderer.selection_events(),
+            [
+                SelectionEvent::Deselected(node),
+                SelectionEvent::Hovered(None)
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "windowing")]
+    fn test_click_selects_in_editor() {
+        let mut renderer = renderer();
+        let mesh_id = renderer.add_mesh(triangle());
+        let scene_graph = renderer.scene_graph_mut();
+        let node = scene_graph
+            .add_node(None, Mat4::from_translation(Vec3::new(-0.25, -0.25, 0.0)))
+            .unwrap();
+        scene_graph
+            .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)
+            .unwrap();
+        let click = |renderer: &mut Renderer<NullBackend>, toggle| {
+            renderer.handle_input(InputEvent::Click {
+                x: 400.0,
+                y: 300.0,
+                toggle,
+            })
+        };
+
+        // Clicks only select while the editor is enabled
+        click(&mut renderer, false);
+        assert_eq!(renderer.editor().selected(), None);
+        renderer.handle_input(InputEvent::Key {
+            code: KeyCode::F1,
+            pressed: true,
+            delta_time: 0.1,
+        });
+        click(&mut renderer, false);
+        assert_eq!(renderer.editor().selected(), Some(node));
+        click(&mut renderer, true);
+        assert_eq!(renderer.editor().selected(), None);
+    }
+
+    #[test]
+    fn test_render_sets_atmosphere() {
+        let mut renderer = renderer();
+        renderer.set_atmosphere(Some(Atmosphere::earth(Vec3::new(0.0, -6360.0, 0.0), 1.0)));
+        renderer.render().unwrap();
+        renderer.set_atmosphere(None);
+
+        let atmospheres: Vec<_> = renderer
+            .backend()
+            .calls()
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::SetAtmosphere(constants) => Some(*constants),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(atmospheres.len(), 2);
+        let constants = atmospheres[0].unwrap();
+        assert_eq!(constants.planet_center, [0.0, -6360.0, 0.0]);
+        assert!(atmospheres[1].is_none());
+    }
+
+    #[test]
+    fn test_ambient_light_frame_constants() {
+        let mut renderer = renderer();
+        renderer.set_ambient_light(AmbientLight::Hemisphere {
+            sky: Color::new(0.5, 0.6, 0.7, 1.0),
+            ground: Color::new(0.2, 0.1, 0.0, 1.0),
+        });
+        renderer.render().unwrap();
+        assert!(renderer.backend().calls().iter().any(|call| matches!(
+            call,
+            BackendCall::UpdateFrameConstants(constants)
+                if constants.ambient_sky_color == [0.5, 0.6, 0.7]
+                    && constants.ambient_ground_color == [0.2, 0.1, 0.0]
+        )));
+
+        // Sky ambient light follows the sky's time of day
+        renderer.set_ambient_light(AmbientLight::Sky { intensity: 1.0 });
+        renderer.set_sky(Some(Sky::new(0.0)));
+        let start = renderer.backend().calls().len();
+        renderer.render().unwrap();
+        let night = renderer.backend().calls()[start..]
+            .iter()
+            .find_map(|call| match call {
+                BackendCall::UpdateFrameConstants(constants) => Some(constants.ambient_sky_color),
+                _ => None,
+            })
+            .unwrap();
+        assert!(night.iter().all(|&component| component < 0.1));
+    }
+
+    #[test]
+    fn test_texture_upload_refreshes_materials() {
+        let mut renderer = renderer();
+        let texture = TextureId(NonZeroU32::new(1).unwrap());
+        renderer.create_material(Material::default().with_texture(texture));
+        renderer.render().unwrap();
+
+        // Once the upload completes, the material table is re-encoded with the texture
+        let start = renderer.backend().calls().len();
+        renderer
+            .upload_texture(TextureUpload::new(texture, 4, 4, 16, vec![255; 64]))
+            .unwrap();
+        renderer.render().unwrap();
+        let calls = &renderer.backend().calls()[start..];
+        assert!(calls.contains(&BackendCall::UploadTexture(texture)));
+        assert!(calls.contains(&BackendCall::UpdateMaterials { count: 2 }));
+        assert_eq!(renderer.pending_texture_uploads(), 0);
+    }
+
+    #[test]
+    fn test_upload_budget_defers_uploads() {
+        let mut renderer = renderer();
+        renderer.set_upload_budget(Some(1));
+        let meshes = [0, 1].map(|_| renderer.add_mesh(triangle().with_static(true)));
+        let texture = TextureId(NonZeroU32::new(1).unwrap());
+        renderer
+            .upload_texture(TextureUpload::new(texture, 4, 4, 16, vec![255; 64]))
+            .unwrap();
+        assert_eq!(renderer.queued_uploads(), 3);
+
+        // Each frame submits one upload over the budget; the queued mesh is
+        // drawn as the lines of its bounds instead
+        let mut uploads = Vec::new();
+        for _ in 0..3 {
+            let start = renderer.backend().calls().len();
+            for mesh_id in meshes {
+                renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+            }
+            renderer.render().unwrap();
+            let calls = &renderer.backend().calls()[start..];
+            uploads.extend(
+                calls
+                    .iter()
+                    .filter(|call| {
+                        matches!(
+                            call,
+                            BackendCall::UploadStaticMesh(_) | BackendCall::UploadTexture(_)
+                        )
+                    })
+                    .cloned(),
+            );
+            let draws: Vec<_> = calls
+                .iter()
+                .filter_map(|call| match call {
+                    BackendCall::Draw(
+                        BackendDrawCommand::Basic { primitive_type, .. }
+                        | BackendDrawCommand::Indexed { primitive_type, .. },
+                    ) => Some(*primitive_type),
+                    _ => None,
+                })
+                .collect();
+            if uploads.len() == 1 {
+                assert_eq!(draws, [PrimitiveType::Triangle, PrimitiveType::Line]);
+            } else {
+                assert_eq!(draws, [PrimitiveType::Triangle, PrimitiveType::Triangle]);
+            }
+        }
+        assert_eq!(
+            uploads,
+            [
+                BackendCall::UploadStaticMesh(meshes[0]),
+                BackendCall::UploadStaticMesh(meshes[1]),
+                BackendCall::UploadTexture(texture),
+            ]
+        );
+        assert_eq!(renderer.queued_uploads(), 0);
+    }
+
+    #[test]
+    fn test_pipeline_warm_up() {
+        let mut renderer = renderer();
+        let layouts = [VertexLayout::default(), VertexLayout::position_normal_uv()];
+        renderer.set_async_pipelines(false);
+        renderer.warm_up_pipelines(&layouts).unwrap();
+        assert_eq!(
+            renderer.backend().calls(),
+            [
+                BackendCall::SetAsyncPipelines(false),
+                BackendCall::WarmUpPipelines(layouts.to_vec()),
+            ]
+        );
+        assert_eq!(renderer.pending_pipelines(), 0);
+    }
+
+    #[test]
+    fn test_sky_drives_sun_lights() {
+        let mut renderer = renderer();
+        let sun_directions = Rc::new(RefCell::new(Vec::new()));
+        let received = Rc::clone(&sun_directions);
+        renderer.set_sun_callback(move |sun| received.borrow_mut().push(sun.direction));
+        renderer.set_atmosphere(Some(Atmosphere::earth(Vec3::ZERO, 1.0)));
+        renderer.set_sky(Some(Sky::new(9.0)));
+        renderer.render().unwrap();
+
+        let sun = Sky::new(9.0).sun_direction();
+        assert_eq!(*sun_directions.borrow(), vec![sun]);
+        let calls = renderer.backend().calls();
+        assert!(calls
+            .iter()
+            .any(|call| matches!(call, BackendCall::SetSky(Some(_)))));
+        assert!(calls.iter().any(|call| matches!(
+            call,
+            BackendCall::SetAtmosphere(Some(constants)) if constants.sun_direction == sun.to_array()
+        )));
+    }
+
+    #[test]
+    fn test_editor_draws_light_gizmos() {
+        let mut renderer = renderer();
+        renderer.set_sky(Some(Sky::new(9.0)));
+        let line_draws = |renderer: &Renderer<NullBackend>| {
+            renderer
+                .backend()
+                .draws()
+                .filter(|draw| {
+                    matches!(
+                        draw,
+                        BackendDrawCommand::Basic {
+                            primitive_type: PrimitiveType::Line,
+                            ..
+                        }
+                    )
+                })
+                .count()
+        };
+        renderer.render().unwrap();
+        assert_eq!(line_draws(&renderer), 0);
+
+        // The sun's gizmo is drawn while the editor is enabled
+        let (editor, scene_graph) = renderer.editor_mut();
+        editor.toggle(scene_graph);
+        renderer.render().unwrap();
+        assert_eq!(line_draws(&renderer), 1);
+
+        renderer.editor_mut().0.set_light_gizmos(LightGizmos::NONE);
+        renderer.render().unwrap();
+        assert_eq!(line_draws(&renderer), 1);
+    }
+
+    #[test]
+    fn test_cascaded_shadow_maps() {
+        let mut renderer = renderer();
+        renderer.set_cascaded_shadows(Some(CascadedShadows::default().with_cascades(3)));
+        let mesh_id = renderer.add_mesh(triangle());
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id).with_layers(LayerMask::UI));
+        renderer.render().unwrap();
+
+        // The caster is drawn into every cascade before the frame is shaded
+        let calls = renderer.backend().calls();
+        assert!(calls.iter().any(|call| matches!(
+            call,
+            BackendCall::SetShadowMaps(Some(frame)) if frame.constants.cascade_count == 3
+        )));
+        let cascades: Vec<_> = calls
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::DrawShadowCaster(ShadowTarget::Cascade(cascade), _) => Some(*cascade),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(cascades, [0, 1, 2]);
+        let last_caster = calls
+            .iter()
+            .rposition(|call| matches!(call, BackendCall::DrawShadowCaster(..)))
+            .unwrap();
+        let first_draw = calls
+            .iter()
+            .position(|call| matches!(call, BackendCall::Draw(_)))
+            .unwrap();
+        assert!(last_caster < first_draw);
+
+        renderer.set_cascaded_shadows(None);
+        assert!(matches!(
+            renderer.backend().calls().last(),
+            Some(BackendCall::SetShadowMaps(None))
+        ));
+    }
+
+    #[test]
+    fn test_point_and_spot_light_shadows() {
+        let mut renderer = renderer();
+        let lamp = renderer
+            .lights_mut()
+            .add(PointLight::new(Vec3::Y, 5.0).with_shadows(256));
+        renderer.lights_mut().add(
+            SpotLight::new(Vec3::new(0.0, 0.0, 2.0), Vec3::NEG_Z, 10.0, 0.5).with_shadows(512),
+        );
+        // Out of reach of the caster, and without shadows
+        renderer
+            .lights_mut()
+            .add(PointLight::new(Vec3::new(100.0, 0.0, 0.0), 1.0).with_shadows(256));
+        renderer.lights_mut().add(PointLight::new(Vec3::ZERO, 5.0));
+        let mesh_id = renderer.add_mesh(triangle());
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+        renderer.render().unwrap();
+
+        let calls = renderer.backend().calls();
+        let frames = calls
+            .iter()
+            .find_map(|call| match call {
+                BackendCall::SetLightShadows(frames) => Some(frames),
+                _ => None,
+            })
+            .unwrap();
+        let faces: Vec<_> = frames
+            .iter()
+            .map(|frame| frame.constants.face_count)
+            .collect();
+        assert_eq!(faces, [6, 1, 6]);
+        assert_eq!(frames[1].resolution, 512);
+        let targets: Vec<_> = calls
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::DrawShadowCaster(target, _) => Some(*target),
+                _ => None,
+            })
+            .collect();
+        let mut expected: Vec<_> = (0..6)
+            .map(|face| ShadowTarget::Light { index: 0, face })
+            .collect();
+        expected.push(ShadowTarget::Light { index: 1, face: 0 });
+        assert_eq!(targets, expected);
+
+        // The shadow maps are released once, after the last light stops casting shadows
+        for light in [lamp, LightId(1), LightId(2)] {
+            renderer.lights_mut().set_shadows_enabled(light, false);
+        }
+        let start = renderer.backend().calls().len();
+        for _ in 0..2 {
+            renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+            renderer.render().unwrap();
+        }
+        let calls = &renderer.backend().calls()[start..];
+        let releases = calls
+            .iter()
+            .filter(
+                |call| matches!(call, BackendCall::SetLightShadows(frames) if frames.is_empty()),
+            )
+            .count();
+        assert_eq!(releases, 1);
+        assert!(!calls
+            .iter()
+            .any(|call| matches!(call, BackendCall::DrawShadowCaster(..))));
+    }
+
+    #[test]
+    fn test_gpu_culling_of_instanced_draws() {
+        let mut renderer = renderer();
+        renderer.set_gpu_culling(Some(GpuCulling::default().with_occlusion(true)));
+        let mesh_id = renderer.add_mesh(triangle());
+        let instances =
+            vec![InstanceData::new(Mat4::IDENTITY, Color::new(1.0, 1.0, 1.0, 1.0)); 100];
+        for _ in 0..2 {
+            renderer
+                .draw_immediate(DrawCommandBuilder::new_mesh(mesh_id).with_instances(&instances));
+            renderer.draw_immediate(
+                DrawCommandBuilder::new_mesh(mesh_id).with_instances(&instances[..10]),
+            );
+            renderer.render().unwrap();
+        }
+
+        let calls = renderer.backend().calls();
+        assert!(calls.contains(&BackendCall::SetDepthPyramid(true)));
+        let culling: Vec<_> = calls
+            .iter()
+            .filter_map(|call| match call {
+                BackendCall::SetInstanceCulling(culling) => Some(*culling),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(culling.len(), 4);
+        // Draws below the instance threshold are drawn whole
+        assert_eq!(
+            culling.iter().filter(|culling| culling.is_none()).count(),
+            2
+        );
+        let culled: Vec<_> = culling.iter().flatten().collect();
+        assert_eq!(culled[0].instance_count, 100);
+        // Occlusion waits for a frame of depth
+        assert_eq!(culled[0].occlusion, 0);
+        assert_eq!(culled[1].occlusion, 1);
+    }
+
+    #[test]
+    fn test_ray_traced_shadows() {
+        let mut renderer = renderer();
+        assert!(matches!(
+            renderer.set_ray_traced_shadows(Some(RayTracedShadows::default())),
+            Err(RendererError::Backend(BackendError::RayTracingUnsupported))
+        ));
+
+        let mut backend = NullBackend::new();
+        backend.set_ray_tracing_supported(true);
+        let mut renderer = Renderer::with_backend(backend, UVec2::new(800, 600));
+        renderer
+            .set_ray_traced_shadows(Some(RayTracedShadows::default()))
+            .unwrap();
+        let mesh_id = renderer.add_mesh(triangle());
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id));
+        renderer.draw_immediate(DrawCommandBuilder::new_mesh(mesh_id).with_layers(LayerMask::UI));
+        renderer.render().unwrap();
+        renderer.render().unwrap();
+
+        let calls = renderer.backend().calls();
+        let builds = calls
+            .iter()
+            .filter(|call| matches!(call, BackendCall::BuildRayTracingMesh(_)))
+            .count();
+        assert_eq!(builds, 1);
+        assert!(calls.iter().any(|call| matches!(
+            call,
+            BackendCall::SetRayTracingFrame(Some(frame)) if frame.instances.len() == 1
+        )));
     }
 }
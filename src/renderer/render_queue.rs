@@ -1,11 +1,20 @@
 //! Render Queue Module
 //!
 //! The module provides structures and implementations for managing draw commands
-//! in a rendering system. It includes types for instance data, draw commands,
-//! and a render queue to manage these commands efficiently.
+//! in a rendering system. Draws are recorded into a packed structure-of-arrays
+//! draw stream (geometry handle, material handle, transform index, sort key).
+//! Instance arrays and transient geometry are copied into a `FrameArena` that
+//! is reset with the queue, and the stream columns are cleared but never freed
+//! between frames, so once the queue has warmed up, recording draws does not
+//! allocate.
 
 use super::{
-    common::{PrimitiveType, Vertex},
+    common::{BackendDrawCommand, IndexType, LayerMask, PrimitiveType, Vertex},
+    frame_arena::{ArenaSlice, FrameArena},
+    material_manager::MaterialId,
+    render_state::{Outline, RenderState},
+    scene_graph::NodeId,
+    vertex_layout::PackedVertices,
     Color,
 };
 use crate::debug_trace;
@@ -13,6 +22,11 @@ use glam::Mat4;
 use log::{debug, trace};
 
 /// Represents instance-specific data for instanced rendering.
+///
+/// The layout matches the `InstanceData` struct in the vertex shader. The
+/// instance model matrix is applied after the draw command's transform, and
+/// the instance color tints the vertex colors.
+#[repr(C)]
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct InstanceData {
     pub model_matrix: Mat4,
@@ -35,52 +49,122 @@ impl InstanceData {
     }
 }
 
-/// Represents a draw command for the renderer.
-#[derive(Clone)]
-pub enum DrawCommand {
-    Mesh {
-        mesh_id: usize,
-        instance_data: Option<Vec<InstanceData>>,
-        transform: Mat4,
-    },
-    Primitive {
-        vertices: Vec<Vertex>,
-        indices: Option<Vec<u32>>,
-        primitive_type: PrimitiveType,
-        instance_data: Option<Vec<InstanceData>>,
-        transform: Mat4,
-    },
+/// Identifies the geometry rendered by a draw.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeometryHandle {
+    /// A mesh stored in `MeshStorage`.
+    Mesh(usize),
+    /// Geometry recorded into the queue for the current frame only.
+    Transient(u32),
 }
 
-impl DrawCommand {
-    /// Returns a reference to the instance data if present.
-    pub fn instance_data(&self) -> Option<&Vec<InstanceData>> {
+impl GeometryHandle {
+    /// Returns the low 32 bits of the sort key for this geometry.
+    fn sort_key(&self) -> u64 {
         match self {
-            DrawCommand::Mesh { instance_data, .. }
-            | DrawCommand::Primitive { instance_data, .. } => instance_data.as_ref(),
+            GeometryHandle::Mesh(id) => *id as u64 & 0x7FFF_FFFF,
+            GeometryHandle::Transient(id) => 0x8000_0000 | (*id as u64 & 0x7FFF_FFFF),
+        }
+    }
+}
+
+/// A borrowed view of the vertices and indices of some geometry.
+#[derive(Clone, Copy, Debug)]
+pub struct GeometryView<'a> {
+    pub vertices: &'a [Vertex],
+    pub indices: Option<&'a [u32]>,
+    pub primitive_type: PrimitiveType,
+    /// The vertices packed in a custom layout, uploaded instead of `vertices` when set.
+    pub packed_vertices: Option<&'a PackedVertices>,
+    /// The vertex positions alone, drawn by depth-only passes when set.
+    pub positions: Option<&'a PackedVertices>,
+}
+
+impl GeometryView<'_> {
+    /// Selects the backend draw call for the geometry and optional instances.
+    pub fn draw_command(&self, instances: Option<&[InstanceData]>) -> BackendDrawCommand {
+        let primitive_type = self.primitive_type;
+        match (self.indices, instances) {
+            (Some(indices), Some(instances)) => BackendDrawCommand::IndexedInstanced {
+                primitive_type,
+                index_count: indices.len() as u64,
+                index_type: IndexType::UInt32,
+                index_buffer_offset: 0,
+                instance_count: instances.len() as u64,
+            },
+            (None, Some(instances)) => BackendDrawCommand::Instanced {
+                primitive_type,
+                vertex_start: 0,
+                vertex_count: self.vertices.len() as u64,
+                instance_count: instances.len() as u64,
+            },
+            (Some(indices), None) => BackendDrawCommand::Indexed {
+                primitive_type,
+                index_count: indices.len() as u64,
+                index_type: IndexType::UInt32,
+                index_buffer_offset: 0,
+            },
+            (None, None) => BackendDrawCommand::Basic {
+                primitive_type,
+                vertex_start: 0,
+                vertex_count: self.vertices.len() as u64,
+            },
         }
     }
 }
 
-/// A builder for creating `DrawCommand's`.
-pub struct DrawCommandBuilder {
-    command: DrawCommand,
+/// Transient geometry stored in the queue's frame arena.
+#[derive(Clone, Copy, Debug)]
+struct TransientGeometry {
+    vertices: ArenaSlice<Vertex>,
+    indices: Option<ArenaSlice<u32>>,
+    primitive_type: PrimitiveType,
 }
 
-impl DrawCommandBuilder {
+/// A view of a single draw in the draw stream.
+#[derive(Clone, Copy, Debug)]
+pub struct DrawItem<'a> {
+    pub geometry: GeometryHandle,
+    pub material_id: MaterialId,
+    pub layers: LayerMask,
+    /// Overrides the material's render state when set.
+    pub render_state: Option<RenderState>,
+    pub outline: Option<Outline>,
+    /// The scene node the draw was submitted for, if any.
+    pub node: Option<NodeId>,
+    pub transform: &'a Mat4,
+    pub instances: Option<&'a [InstanceData]>,
+}
+
+/// The geometry a `DrawCommandBuilder` draws.
+enum GeometrySource<'a> {
+    Mesh(usize),
+    Primitive(GeometryView<'a>),
+}
+
+/// A builder for recording a draw into the `RenderQueue`.
+///
+/// The builder only borrows its data; it is copied into the queue's frame
+/// arena when the draw is added.
+pub struct DrawCommandBuilder<'a> {
+    source: GeometrySource<'a>,
+    instances: Option<&'a [InstanceData]>,
+    transform: Mat4,
+    material_id: MaterialId,
+    layers: LayerMask,
+    render_state: Option<RenderState>,
+    outline: Option<Outline>,
+    node: Option<NodeId>,
+}
+
+impl<'a> DrawCommandBuilder<'a> {
     /// Creates a new `DrawCommandBuilder` for a mesh.
     ///
     /// # Arguments
     ///
     /// * `mesh_id` - The ID of the mesh to draw.
     pub fn new_mesh(mesh_id: usize) -> Self {
-        Self {
-            command: DrawCommand::Mesh {
-                mesh_id,
-                instance_data: None,
-                transform: Mat4::IDENTITY,
-            },
-        }
+        Self::new(GeometrySource::Mesh(mesh_id))
     }
 
     /// Creates a new `DrawCommandBuilder` for a primitive.
@@ -91,18 +175,29 @@ impl DrawCommandBuilder {
     /// * `indices` - Optional indices for indexed rendering.
     /// * `primitive_type` - The type of primitive to draw.
     pub fn new_primitive(
-        vertices: Vec<Vertex>,
-        indices: Option<Vec<u32>>,
+        vertices: &'a [Vertex],
+        indices: Option<&'a [u32]>,
         primitive_type: PrimitiveType,
     ) -> Self {
+        Self::new(GeometrySource::Primitive(GeometryView {
+            vertices,
+            indices,
+            primitive_type,
+            packed_vertices: None,
+            positions: None,
+        }))
+    }
+
+    fn new(source: GeometrySource<'a>) -> Self {
         Self {
-            command: DrawCommand::Primitive {
-                vertices,
-                indices,
-                primitive_type,
-                instance_data: None,
-                transform: Mat4::IDENTITY,
-            },
+            source,
+            instances: None,
+            transform: Mat4::IDENTITY,
+            material_id: MaterialId::DEFAULT,
+            layers: LayerMask::default(),
+            render_state: None,
+            outline: None,
+            node: None,
         }
     }
 
@@ -110,16 +205,9 @@ impl DrawCommandBuilder {
     ///
     /// # Arguments
     ///
-    /// * `instance_data` - The instance data to add.
-    pub fn with_instances(mut self, instance_data: Vec<InstanceData>) -> Self {
-        match &mut self.command {
-            DrawCommand::Mesh {
-                instance_data: id, ..
-            } => *id = Some(instance_data),
-            DrawCommand::Primitive {
-                instance_data: id, ..
-            } => *id = Some(instance_data),
-        }
+    /// * `instances` - The instance data to add.
+    pub fn with_instances(mut self, instances: &'a [InstanceData]) -> Self {
+        self.instances = Some(instances);
         self
     }
 
@@ -129,25 +217,80 @@ impl DrawCommandBuilder {
     ///
     /// * `transform` - The transformation matrix to apply.
     pub fn with_transform(mut self, transform: Mat4) -> Self {
-        match &mut self.command {
-            DrawCommand::Mesh { transform: t, .. } => *t = transform,
-            DrawCommand::Primitive { transform: t, .. } => *t = transform,
-        }
+        self.transform = transform;
         self
     }
 
-    /// Builds the `DrawCommand`.
-    pub fn build(self) -> DrawCommand {
-        self.command
+    /// Sets the material for the draw command.
+    ///
+    /// # Arguments
+    ///
+    /// * `material_id` - The ID of the material to draw with.
+    pub fn with_material(mut self, material_id: MaterialId) -> Self {
+        self.material_id = material_id;
+        self
     }
-}
 
-// TODO: Add batch calling back
+    /// Sets the visibility layers of the draw command.
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - The layers the draw belongs to.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
 
-/// Manages a queue of draw commands for rendering.
+    /// Overrides the material's render state for the draw command.
+    ///
+    /// # Arguments
+    ///
+    /// * `render_state` - The depth, culling and depth bias state to draw with.
+    pub fn with_render_state(mut self, render_state: RenderState) -> Self {
+        self.render_state = Some(render_state);
+        self
+    }
+
+    /// Draws an outline around the draw command's geometry.
+    ///
+    /// # Arguments
+    ///
+    /// * `outline` - The color and width of the outline.
+    pub fn with_outline(mut self, outline: Outline) -> Self {
+        self.outline = Some(outline);
+        self
+    }
+
+    /// Records the scene node the draw command belongs to, for diagnostics.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The scene node being drawn.
+    pub fn with_node(mut self, node: NodeId) -> Self {
+        self.node = Some(node);
+        self
+    }
+}
+
+/// Manages the per-frame draw stream for rendering.
 #[derive(Default)]
 pub struct RenderQueue {
-    pub draw_commands: Vec<DrawCommand>,
+    // Draw stream, one entry per draw
+    geometries: Vec<GeometryHandle>,
+    material_ids: Vec<MaterialId>,
+    layers: Vec<LayerMask>,
+    render_states: Vec<Option<RenderState>>,
+    outlines: Vec<Option<Outline>>,
+    nodes: Vec<Option<NodeId>>,
+    transform_indices: Vec<u32>,
+    instances: Vec<Option<ArenaSlice<InstanceData>>>,
+    sort_keys: Vec<u64>,
+    order: Vec<u32>,
+
+    // Frame data referenced by the draw stream
+    transforms: Vec<Mat4>,
+    transient_geometry: Vec<TransientGeometry>,
+    arena: FrameArena,
 }
 
 impl RenderQueue {
@@ -157,40 +300,128 @@ impl RenderQueue {
         Self::default()
     }
 
-    /// Adds a draw command to the queue.
+    /// Records a draw into the queue, copying its data into the frame arena.
     ///
     /// # Arguments
     ///
     /// * `command` - The draw command to add.
-    pub fn add_draw_command(&mut self, command: DrawCommand) {
+    pub fn add_draw_command(&mut self, command: DrawCommandBuilder) {
         debug_trace!("Adding draw command to RenderQueue");
-        self.draw_commands.push(command);
+        let geometry = match command.source {
+            GeometrySource::Mesh(mesh_id) => GeometryHandle::Mesh(mesh_id),
+            GeometrySource::Primitive(view) => {
+                let vertices = self.arena.alloc_slice(view.vertices);
+                let indices = view.indices.map(|indices| self.arena.alloc_slice(indices));
+                self.transient_geometry.push(TransientGeometry {
+                    vertices,
+                    indices,
+                    primitive_type: view.primitive_type,
+                });
+                GeometryHandle::Transient(self.transient_geometry.len() as u32 - 1)
+            }
+        };
+
+        let instances = command
+            .instances
+            .map(|instances| self.arena.alloc_slice(instances));
+
+        self.transforms.push(command.transform);
+        self.order.push(self.geometries.len() as u32);
+        self.sort_keys
+            .push(((command.material_id.0 as u64) << 32) | geometry.sort_key());
+        self.geometries.push(geometry);
+        self.material_ids.push(command.material_id);
+        self.layers.push(command.layers);
+        self.render_states.push(command.render_state);
+        self.outlines.push(command.outline);
+        self.nodes.push(command.node);
+        self.transform_indices
+            .push(self.transforms.len() as u32 - 1);
+        self.instances.push(instances);
     }
 
-    /// Returns a slice of all draw commands in the queue.
+    /// Returns the number of draws in the queue.
     #[allow(dead_code)]
-    pub fn get_draw_commands(&self) -> &[DrawCommand] {
+    pub fn len(&self) -> usize {
+        self.geometries.len()
+    }
+
+    /// Returns `true` if the queue holds no draws.
+    pub fn is_empty(&self) -> bool {
+        self.geometries.is_empty()
+    }
+
+    /// Returns a view of the draw recorded at `index`.
+    pub fn draw_item(&self, index: usize) -> DrawItem<'_> {
+        DrawItem {
+            geometry: self.geometries[index],
+            material_id: self.material_ids[index],
+            layers: self.layers[index],
+            render_state: self.render_states[index],
+            outline: self.outlines[index],
+            node: self.nodes[index],
+            transform: &self.transforms[self.transform_indices[index] as usize],
+            instances: self.instances[index].map(|instances| self.arena.get(instances)),
+        }
+    }
+
+    /// Returns all draws in submission order, or sorted order after `sort_batches`.
+    pub fn draw_items(&self) -> impl Iterator<Item = DrawItem<'_>> {
         trace!("Retrieving draw commands from RenderQueue");
-        &self.draw_commands
+        self.order
+            .iter()
+            .map(|&index| self.draw_item(index as usize))
+    }
+
+    /// Returns the vertices and indices of transient geometry recorded this frame.
+    pub fn transient_geometry(&self, id: u32) -> Option<GeometryView<'_>> {
+        self.transient_geometry
+            .get(id as usize)
+            .map(|geometry| GeometryView {
+                vertices: self.arena.get(geometry.vertices),
+                indices: geometry.indices.map(|indices| self.arena.get(indices)),
+                primitive_type: geometry.primitive_type,
+                packed_vertices: None,
+                positions: None,
+            })
     }
 
-    /// Sorts the batches in the render queue.
+    /// Sorts the draw order by sort key, grouping draws by material, then geometry.
     ///
-    /// This method is currently unimplemented and will panic if called.
-    #[allow(dead_code)]
+    /// Draws with equal keys keep their submission order.
     pub fn sort_batches(&mut self) {
-        // TODO: sort batches by material
         // TODO: sort batches front to back
         // TODO: sort batches back to front
-        unimplemented!("Batch sorting is not yet implemented")
+        let sort_keys = &self.sort_keys;
+        self.order.sort_by_key(|&index| sort_keys[index as usize]);
+    }
+
+    /// Removes all draws while keeping the allocated capacity for the next frame.
+    pub fn clear(&mut self) {
+        debug_trace!("Clearing RenderQueue");
+        self.geometries.clear();
+        self.material_ids.clear();
+        self.layers.clear();
+        self.render_states.clear();
+        self.outlines.clear();
+        self.nodes.clear();
+        self.transform_indices.clear();
+        self.instances.clear();
+        self.sort_keys.clear();
+        self.order.clear();
+        self.transforms.clear();
+        self.transient_geometry.clear();
+        self.arena.reset();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{DrawCommand, DrawCommandBuilder, InstanceData, RenderQueue};
+    use super::{DrawCommandBuilder, GeometryHandle, InstanceData, RenderQueue};
     use crate::renderer::{
-        common::{PrimitiveType, Vertex},
+        common::{LayerMask, PrimitiveType, Vertex},
+        material_manager::MaterialId,
+        render_state::RenderState,
         Color,
     };
     use glam::{Mat4, Vec3};
@@ -198,58 +429,45 @@ mod tests {
     #[test]
     fn test_render_queue_new() {
         let queue = RenderQueue::new();
-        assert!(queue.draw_commands.is_empty());
+        assert!(queue.is_empty());
     }
 
     #[test]
     fn test_render_queue_add_draw_command() {
         let mut queue = RenderQueue::new();
-        let command = DrawCommand::Mesh {
-            mesh_id: 1,
-            instance_data: None,
-            transform: Mat4::IDENTITY,
-        };
-        queue.add_draw_command(command.clone());
-        assert_eq!(queue.draw_commands.len(), 1);
-        assert!(matches!(
-            queue.draw_commands[0],
-            DrawCommand::Mesh { mesh_id: 1, .. }
-        ))
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.draw_item(0).geometry, GeometryHandle::Mesh(1));
     }
 
     #[test]
-    fn test_render_queue_get_draw_commands() {
+    fn test_render_queue_draw_items() {
         let mut queue = RenderQueue::new();
-        queue.add_draw_command(DrawCommand::Mesh {
-            mesh_id: 1,
-            instance_data: None,
-            transform: Mat4::IDENTITY,
-        });
-        let commands = queue.get_draw_commands();
-        assert_eq!(commands.len(), 1);
-        assert!(matches!(commands[0], DrawCommand::Mesh { mesh_id: 1, .. }))
-    }
-
-    #[test]
-    fn test_draw_command_builder_new_mesh() {
-        let builder = DrawCommandBuilder::new_mesh(1);
-        let command = builder.build();
-        assert!(matches!(command, DrawCommand::Mesh { mesh_id: 1, .. }));
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1));
+        let items: Vec<_> = queue.draw_items().collect();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].geometry, GeometryHandle::Mesh(1));
+        assert_eq!(*items[0].transform, Mat4::IDENTITY);
+        assert!(items[0].instances.is_none());
     }
 
     #[test]
     fn test_draw_command_builder_new_primitive() {
-        let vertices = vec![Vertex::default()];
-        let builder =
-            DrawCommandBuilder::new_primitive(vertices.clone(), None, PrimitiveType::Triangle);
-        let command = builder.build();
-        assert!(matches!(
-            command,
-            DrawCommand::Primitive {
-                primitive_type: PrimitiveType::Triangle,
-                ..
-            }
+        let vertices = vec![Vertex::default(); 3];
+        let indices = vec![0, 1, 2];
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_primitive(
+            &vertices,
+            Some(&indices),
+            PrimitiveType::Triangle,
         ));
+
+        let item = queue.draw_item(0);
+        assert_eq!(item.geometry, GeometryHandle::Transient(0));
+        let geometry = queue.transient_geometry(0).unwrap();
+        assert_eq!(geometry.vertices, vertices.as_slice());
+        assert_eq!(geometry.indices, Some(indices.as_slice()));
+        assert_eq!(geometry.primitive_type, PrimitiveType::Triangle);
     }
 
     #[test]
@@ -258,18 +476,94 @@ mod tests {
             Mat4::IDENTITY,
             Color::new(1.0, 0.0, 0.0, 1.0),
         )];
-        let builder = DrawCommandBuilder::new_mesh(1).with_instances(instances.clone());
-        let command = builder.build();
-        assert!(
-            matches!(command, DrawCommand::Mesh { instance_data: Some(data), .. } if data == instances),
-        );
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1).with_instances(&instances));
+        assert_eq!(queue.draw_item(0).instances, Some(instances.as_slice()));
+    }
+
+    #[test]
+    fn test_instance_data_layout() {
+        // Must match `float4x4 modelMatrix; float4 color;` in the vertex shader
+        assert_eq!(std::mem::size_of::<InstanceData>(), 80);
+        assert_eq!(std::mem::align_of::<InstanceData>(), 16);
     }
 
     #[test]
     fn test_draw_command_builder_with_transform() {
         let transform = Mat4::from_scale(Vec3::new(2.0, 2.0, 2.0));
-        let builder = DrawCommandBuilder::new_mesh(1).with_transform(transform);
-        let command = builder.build();
-        assert!(matches!(command, DrawCommand::Mesh { transform: t, .. } if t == transform));
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1).with_transform(transform));
+        assert_eq!(*queue.draw_item(0).transform, transform);
+    }
+
+    #[test]
+    fn test_draw_command_builder_with_material() {
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1));
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1).with_material(MaterialId(3)));
+        assert_eq!(queue.draw_item(0).material_id, MaterialId::DEFAULT);
+        assert_eq!(queue.draw_item(1).material_id, MaterialId(3));
+    }
+
+    #[test]
+    fn test_draw_command_builder_with_layers() {
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1));
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1).with_layers(LayerMask::GIZMO));
+        assert_eq!(queue.draw_item(0).layers, LayerMask::default());
+        assert_eq!(queue.draw_item(1).layers, LayerMask::GIZMO);
+    }
+
+    #[test]
+    fn test_draw_command_builder_with_render_state() {
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1));
+        queue.add_draw_command(
+            DrawCommandBuilder::new_mesh(1).with_render_state(RenderState::OVERLAY),
+        );
+        assert_eq!(queue.draw_item(0).render_state, None);
+        assert_eq!(queue.draw_item(1).render_state, Some(RenderState::OVERLAY));
+    }
+
+    #[test]
+    fn test_render_queue_sort_batches() {
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(2).with_material(MaterialId(1)));
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(5));
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(1).with_material(MaterialId(1)));
+        queue.add_draw_command(DrawCommandBuilder::new_mesh(2));
+        queue.sort_batches();
+
+        let order: Vec<_> = queue
+            .draw_items()
+            .map(|item| (item.material_id, item.geometry))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (MaterialId(0), GeometryHandle::Mesh(2)),
+                (MaterialId(0), GeometryHandle::Mesh(5)),
+                (MaterialId(1), GeometryHandle::Mesh(1)),
+                (MaterialId(1), GeometryHandle::Mesh(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_queue_clear_keeps_capacity() {
+        let vertices = vec![Vertex::default(); 64];
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_primitive(
+            &vertices,
+            None,
+            PrimitiveType::Triangle,
+        ));
+        let capacity = queue.arena.capacity_bytes();
+
+        queue.clear();
+        assert!(queue.is_empty());
+        assert!(queue.transient_geometry(0).is_none());
+        assert_eq!(queue.arena.used_bytes(), 0);
+        assert_eq!(queue.arena.capacity_bytes(), capacity);
     }
 }
@@ -0,0 +1,214 @@
+//! Render scale module for the renderer.
+//!
+//! This module provides `RenderScale`, the resolution the 3D scene is drawn
+//! at relative to the window. Scaled frames render offscreen and are filtered
+//! to the window's size when presented, trading sharpness for speed below 1
+//! and speed for supersampling above it. `RenderScale::Dynamic` adjusts the
+//! scale between frames to hold a target frame time, stepping it down while
+//! frames run long and back up once they finish well within the target.
+
+/// The smallest scale the scene is rendered at.
+const MIN_SCALE: f32 = 0.25;
+
+/// The largest scale the scene is rendered at.
+const MAX_SCALE: f32 = 2.0;
+
+/// How much the dynamic scale changes at once. Coarse steps keep offscreen
+/// targets from being reallocated every frame.
+const SCALE_STEP: f32 = 0.05;
+
+/// How quickly the averaged frame time follows new frames, from 0 to 1.
+const FRAME_TIME_SMOOTHING: f32 = 0.1;
+
+/// Frames waited after a change before the dynamic scale changes again,
+/// so the averaged frame time reflects the new scale.
+const SETTLE_FRAMES: u32 = 30;
+
+/// The fraction of the target frame time a frame may overrun before the scale drops.
+const OVERRUN_TOLERANCE: f32 = 0.05;
+
+/// The fraction of the target frame time a frame must leave unused before the scale rises.
+const RAISE_HEADROOM: f32 = 0.15;
+
+/// The resolution the scene is rendered at, relative to the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderScale {
+    /// A constant scale, e.g. 0.5 for half the window's width and height.
+    Fixed(f32),
+    /// A scale adjusted every frame to hold a target frame time.
+    Dynamic(DynamicResolution),
+}
+
+impl Default for RenderScale {
+    /// The window's own resolution.
+    fn default() -> Self {
+        RenderScale::Fixed(1.0)
+    }
+}
+
+/// The target and range of a dynamically adjusted render scale.
+///
+/// Frame times are measured between frames on the CPU. Under vsync they
+/// cannot drop below the display's refresh interval, so a target equal to
+/// that interval only ever lowers the scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicResolution {
+    /// The frame time to hold, in seconds.
+    pub target_frame_time: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+}
+
+impl DynamicResolution {
+    /// Creates a new `DynamicResolution` scaling between half and full resolution.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_frame_time` - The frame time to hold, in seconds.
+    pub fn new(target_frame_time: f32) -> Self {
+        Self {
+            target_frame_time,
+            min_scale: 0.5,
+            max_scale: 1.0,
+        }
+    }
+
+    /// Sets the range the scale is adjusted within.
+    pub fn with_range(mut self, min_scale: f32, max_scale: f32) -> Self {
+        self.min_scale = min_scale;
+        self.max_scale = max_scale;
+        self
+    }
+}
+
+/// Tracks the current render scale and adjusts it in dynamic mode.
+#[derive(Clone, Debug)]
+pub struct RenderScaler {
+    mode: RenderScale,
+    scale: f32,
+    /// The exponential moving average of recent frame times, in seconds.
+    average_frame_time: Option<f32>,
+    frames_since_change: u32,
+}
+
+impl RenderScaler {
+    /// Creates a new `RenderScaler`, starting at the mode's largest scale.
+    pub fn new(mode: RenderScale) -> Self {
+        let scale = match mode {
+            RenderScale::Fixed(scale) => scale,
+            RenderScale::Dynamic(dynamic) => dynamic.max_scale,
+        };
+        Self {
+            mode,
+            scale: scale.clamp(MIN_SCALE, MAX_SCALE),
+            average_frame_time: None,
+            frames_since_change: 0,
+        }
+    }
+
+    /// Returns the scale the next frame is rendered at.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Records the duration of the last frame, adjusting the scale in dynamic mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_time` - The wall-clock duration of the last frame in seconds.
+    ///
+    /// # Returns
+    ///
+    /// The new scale if it changed, or `None` if it stayed the same.
+    pub fn update(&mut self, frame_time: f32) -> Option<f32> {
+        let RenderScale::Dynamic(dynamic) = self.mode else {
+            return None;
+        };
+        // The first frame has no duration
+        if frame_time <= 0.0 {
+            return None;
+        }
+        let average = match self.average_frame_time {
+            Some(average) => average + (frame_time - average) * FRAME_TIME_SMOOTHING,
+            None => frame_time,
+        };
+        self.average_frame_time = Some(average);
+        self.frames_since_change += 1;
+        if self.frames_since_change < SETTLE_FRAMES {
+            return None;
+        }
+
+        let target = dynamic.target_frame_time;
+        let step = if average > target * (1.0 + OVERRUN_TOLERANCE) {
+            -SCALE_STEP
+        } else if average < target * (1.0 - RAISE_HEADROOM) {
+            SCALE_STEP
+        } else {
+            return None;
+        };
+        let scale = (self.scale + step)
+            .clamp(dynamic.min_scale, dynamic.max_scale)
+            .clamp(MIN_SCALE, MAX_SCALE);
+        if scale == self.scale {
+            return None;
+        }
+        self.scale = scale;
+        self.frames_since_change = 0;
+        Some(scale)
+    }
+}
+
+/// Returns the size a window's scene is rendered at, at least one pixel on both axes.
+///
+/// # Arguments
+///
+/// * `width` - The width of the window in pixels.
+/// * `height` - The height of the window in pixels.
+/// * `scale` - The render scale.
+pub fn scaled_size(width: u64, height: u64, scale: f32) -> (u64, u64) {
+    let scale = |length: u64| ((length as f32 * scale).round() as u64).max(1);
+    (scale(width), scale(height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        scaled_size, DynamicResolution, RenderScale, RenderScaler, SCALE_STEP, SETTLE_FRAMES,
+    };
+
+    #[test]
+    fn test_fixed_render_scale() {
+        let mut scaler = RenderScaler::new(RenderScale::Fixed(0.5));
+        assert_eq!(scaler.scale(), 0.5);
+        assert_eq!(scaler.update(1.0), None);
+        assert_eq!(RenderScaler::new(RenderScale::Fixed(8.0)).scale(), 2.0);
+
+        assert_eq!(scaled_size(800, 600, 0.5), (400, 300));
+        assert_eq!(scaled_size(1, 1, 0.25), (1, 1));
+    }
+
+    #[test]
+    fn test_dynamic_render_scale() {
+        let dynamic = DynamicResolution::new(1.0 / 60.0).with_range(0.5, 1.0);
+        let mut scaler = RenderScaler::new(RenderScale::Dynamic(dynamic));
+        assert_eq!(scaler.scale(), 1.0);
+
+        // Slow frames lower the scale once the average has settled
+        for _ in 1..SETTLE_FRAMES {
+            assert_eq!(scaler.update(1.0 / 30.0), None);
+        }
+        assert_eq!(scaler.update(1.0 / 30.0), Some(1.0 - SCALE_STEP));
+
+        // Frames well within the target raise it again, up to the maximum
+        let mut scale = scaler.scale();
+        for _ in 0..SETTLE_FRAMES * 20 {
+            scale = scaler.update(1.0 / 120.0).unwrap_or(scale);
+        }
+        assert_eq!(scale, 1.0);
+
+        // Frames near the target keep the scale
+        for _ in 0..SETTLE_FRAMES * 2 {
+            assert_eq!(scaler.update(1.0 / 60.0), None);
+        }
+    }
+}
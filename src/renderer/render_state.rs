@@ -0,0 +1,400 @@
+//! Render state module for the renderer.
+//!
+//! This module provides the `RenderState`, the fixed-function state a draw is
+//! rendered with: depth testing, depth writes, face culling, depth bias,
+//! stencil operations and blending. Every material carries a render state, and individual
+//! draws can override it, e.g. for gizmos that render on top of the scene or
+//! double-sided foliage. It also provides the `Outline` highlight effect,
+//! which the backend renders with the stencil buffer.
+
+use super::Color;
+use metal::{MTLCompareFunction, MTLCullMode, MTLStencilOperation};
+use serde::Deserialize;
+
+/// Selects which faces are discarded before rasterization.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    /// Draws both front and back faces.
+    #[default]
+    None,
+    /// Discards front faces.
+    Front,
+    /// Discards back faces.
+    Back,
+}
+
+impl From<CullMode> for MTLCullMode {
+    fn from(cull_mode: CullMode) -> Self {
+        match cull_mode {
+            CullMode::None => MTLCullMode::None,
+            CullMode::Front => MTLCullMode::Front,
+            CullMode::Back => MTLCullMode::Back,
+        }
+    }
+}
+
+/// How a draw's fragments are combined with the pixels already drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum BlendMode {
+    /// Fragments replace the pixels beneath them.
+    #[default]
+    Opaque,
+    /// Fragments are blended over the pixels beneath them by their alpha.
+    /// Alpha-blended draws are submitted after every opaque draw.
+    Alpha,
+    /// Fragments scaled by their alpha are added to the pixels beneath them,
+    /// e.g. for fire, sparks and glows. Like alpha-blended draws, additive
+    /// draws are submitted after every opaque draw.
+    Additive,
+}
+
+/// A comparison between a fragment's value and the value stored in a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CompareFunction {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl From<CompareFunction> for MTLCompareFunction {
+    fn from(function: CompareFunction) -> Self {
+        match function {
+            CompareFunction::Never => MTLCompareFunction::Never,
+            CompareFunction::Less => MTLCompareFunction::Less,
+            CompareFunction::Equal => MTLCompareFunction::Equal,
+            CompareFunction::LessEqual => MTLCompareFunction::LessEqual,
+            CompareFunction::Greater => MTLCompareFunction::Greater,
+            CompareFunction::NotEqual => MTLCompareFunction::NotEqual,
+            CompareFunction::GreaterEqual => MTLCompareFunction::GreaterEqual,
+            CompareFunction::Always => MTLCompareFunction::Always,
+        }
+    }
+}
+
+/// An operation applied to the stencil buffer after a stencil or depth test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl From<StencilOp> for MTLStencilOperation {
+    fn from(op: StencilOp) -> Self {
+        match op {
+            StencilOp::Keep => MTLStencilOperation::Keep,
+            StencilOp::Zero => MTLStencilOperation::Zero,
+            StencilOp::Replace => MTLStencilOperation::Replace,
+            StencilOp::IncrementClamp => MTLStencilOperation::IncrementClamp,
+            StencilOp::DecrementClamp => MTLStencilOperation::DecrementClamp,
+            StencilOp::Invert => MTLStencilOperation::Invert,
+            StencilOp::IncrementWrap => MTLStencilOperation::IncrementWrap,
+            StencilOp::DecrementWrap => MTLStencilOperation::DecrementWrap,
+        }
+    }
+}
+
+/// Stencil test and update operations, applied to both front and back faces.
+///
+/// The value compared against is the render state's `stencil_reference`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StencilState {
+    pub compare: CompareFunction,
+    /// Applied when the stencil test fails.
+    pub fail_op: StencilOp,
+    /// Applied when the stencil test passes but the depth test fails.
+    pub depth_fail_op: StencilOp,
+    /// Applied when both the stencil and depth tests pass.
+    pub pass_op: StencilOp,
+    pub read_mask: u32,
+    pub write_mask: u32,
+}
+
+impl StencilState {
+    /// Writes the reference value wherever the draw covers a pixel.
+    pub const WRITE_REFERENCE: StencilState = StencilState {
+        compare: CompareFunction::Always,
+        fail_op: StencilOp::Keep,
+        depth_fail_op: StencilOp::Replace,
+        pass_op: StencilOp::Replace,
+        read_mask: 0xFF,
+        write_mask: 0xFF,
+    };
+
+    /// Only draws where the stencil buffer differs from the reference value.
+    pub const NOT_EQUAL_REFERENCE: StencilState = StencilState {
+        compare: CompareFunction::NotEqual,
+        fail_op: StencilOp::Keep,
+        depth_fail_op: StencilOp::Keep,
+        pass_op: StencilOp::Keep,
+        read_mask: 0xFF,
+        write_mask: 0,
+    };
+
+    /// Creates a new `StencilState` that tests without modifying the buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `compare` - Compares the reference value against the stored value.
+    pub fn new(compare: CompareFunction) -> Self {
+        Self {
+            compare,
+            fail_op: StencilOp::Keep,
+            depth_fail_op: StencilOp::Keep,
+            pass_op: StencilOp::Keep,
+            read_mask: 0xFF,
+            write_mask: 0xFF,
+        }
+    }
+
+    /// Sets the operations applied on stencil failure, depth failure and pass.
+    pub fn with_ops(
+        mut self,
+        fail_op: StencilOp,
+        depth_fail_op: StencilOp,
+        pass_op: StencilOp,
+    ) -> Self {
+        self.fail_op = fail_op;
+        self.depth_fail_op = depth_fail_op;
+        self.pass_op = pass_op;
+        self
+    }
+
+    /// Sets the masks applied when reading and writing stencil values.
+    pub fn with_masks(mut self, read_mask: u32, write_mask: u32) -> Self {
+        self.read_mask = read_mask;
+        self.write_mask = write_mask;
+        self
+    }
+}
+
+/// A depth offset applied to rasterized fragments, e.g. to avoid shadow acne.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DepthBias {
+    /// A constant offset added to every fragment's depth.
+    pub constant: f32,
+    /// An offset scaled by the slope of the polygon.
+    pub slope_scale: f32,
+    /// The largest absolute offset applied, or 0 for no clamp.
+    pub clamp: f32,
+}
+
+impl DepthBias {
+    /// Creates a new `DepthBias`.
+    ///
+    /// # Arguments
+    ///
+    /// * `constant` - A constant offset added to every fragment's depth.
+    /// * `slope_scale` - An offset scaled by the slope of the polygon.
+    /// * `clamp` - The largest absolute offset applied, or 0 for no clamp.
+    pub fn new(constant: f32, slope_scale: f32, clamp: f32) -> Self {
+        Self {
+            constant,
+            slope_scale,
+            clamp,
+        }
+    }
+}
+
+/// The fixed-function state used when rendering a draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub cull_mode: CullMode,
+    pub depth_bias: DepthBias,
+    /// The stencil test, or `None` to leave the stencil buffer untouched.
+    pub stencil: Option<StencilState>,
+    pub stencil_reference: u32,
+    pub blend_mode: BlendMode,
+}
+
+impl RenderState {
+    /// Depth tested and written, with no culling or bias.
+    pub const OPAQUE: RenderState = RenderState {
+        depth_test: true,
+        depth_write: true,
+        cull_mode: CullMode::None,
+        depth_bias: DepthBias {
+            constant: 0.0,
+            slope_scale: 0.0,
+            clamp: 0.0,
+        },
+        stencil: None,
+        stencil_reference: 0,
+        blend_mode: BlendMode::Opaque,
+    };
+
+    /// Drawn on top of everything rendered before it, without touching depth.
+    pub const OVERLAY: RenderState = RenderState {
+        depth_test: false,
+        depth_write: false,
+        ..RenderState::OPAQUE
+    };
+
+    /// Alpha-blended and depth tested, without writing depth, e.g. for glass.
+    pub const TRANSPARENT: RenderState = RenderState {
+        depth_write: false,
+        blend_mode: BlendMode::Alpha,
+        ..RenderState::OPAQUE
+    };
+
+    /// Added to the scene and depth tested, without writing depth, e.g. for sparks.
+    pub const ADDITIVE: RenderState = RenderState {
+        depth_write: false,
+        blend_mode: BlendMode::Additive,
+        ..RenderState::OPAQUE
+    };
+
+    /// Sets whether fragments are tested against the depth buffer.
+    pub fn with_depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    /// Sets whether fragments write to the depth buffer.
+    pub fn with_depth_write(mut self, depth_write: bool) -> Self {
+        self.depth_write = depth_write;
+        self
+    }
+
+    /// Sets which faces are culled.
+    pub fn with_cull_mode(mut self, cull_mode: CullMode) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Sets the depth bias.
+    pub fn with_depth_bias(mut self, depth_bias: DepthBias) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    /// Enables the stencil test.
+    ///
+    /// # Arguments
+    ///
+    /// * `stencil` - The stencil test and update operations.
+    /// * `reference` - The value compared against and written by the operations.
+    pub fn with_stencil(mut self, stencil: StencilState, reference: u32) -> Self {
+        self.stencil = Some(stencil);
+        self.stencil_reference = reference;
+        self
+    }
+
+    /// Sets how fragments are combined with the pixels already drawn.
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// Returns `true` if the draw is blended over the pixels beneath it.
+    pub fn is_transparent(&self) -> bool {
+        self.blend_mode != BlendMode::Opaque
+    }
+
+    /// Returns `true` if a depth pre-pass can write the draw's depth ahead of shading.
+    ///
+    /// Only opaque draws that test and write depth qualify. Stencil tested
+    /// draws are excluded, since the pre-pass would write depth where the
+    /// stencil test later discards them.
+    pub fn writes_depth_prepass(&self) -> bool {
+        !self.is_transparent() && self.depth_test && self.depth_write && self.stencil.is_none()
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        RenderState::OPAQUE
+    }
+}
+
+/// A solid-color outline drawn around an object, e.g. to highlight a selection.
+///
+/// The object is first drawn while marking its pixels in the stencil buffer,
+/// then redrawn scaled up about its local origin in the outline color
+/// wherever it was not marked. The outline is drawn on top of other geometry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outline {
+    pub color: Color,
+    /// The outline thickness as a fraction of the object's size.
+    pub width: f32,
+}
+
+impl Outline {
+    /// Creates a new `Outline`.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The color of the outline.
+    /// * `width` - The outline thickness as a fraction of the object's size.
+    pub fn new(color: Color, width: f32) -> Self {
+        Self { color, width }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BlendMode, CompareFunction, CullMode, DepthBias, RenderState, StencilOp, StencilState,
+    };
+
+    #[test]
+    fn test_render_state_builders() {
+        let state = RenderState::default()
+            .with_depth_write(false)
+            .with_cull_mode(CullMode::Back)
+            .with_depth_bias(DepthBias::new(1.0, 2.0, 0.01));
+
+        assert!(state.depth_test);
+        assert!(!state.depth_write);
+        assert_eq!(state.cull_mode, CullMode::Back);
+        assert_eq!(state.depth_bias.slope_scale, 2.0);
+
+        let overlay = RenderState::OVERLAY;
+        assert!(!overlay.depth_test);
+        assert!(!overlay.depth_write);
+        assert_eq!(overlay.cull_mode, CullMode::None);
+        assert!(!overlay.is_transparent());
+
+        let transparent = RenderState::TRANSPARENT;
+        assert!(transparent.depth_test);
+        assert!(!transparent.depth_write);
+        assert!(transparent.is_transparent());
+        assert_eq!(
+            RenderState::default()
+                .with_blend_mode(BlendMode::Alpha)
+                .blend_mode,
+            transparent.blend_mode
+        );
+        assert!(RenderState::ADDITIVE.is_transparent());
+        assert!(!RenderState::ADDITIVE.writes_depth_prepass());
+    }
+
+    #[test]
+    fn test_render_state_stencil() {
+        assert_eq!(RenderState::default().stencil, None);
+
+        let stencil = StencilState::new(CompareFunction::Equal)
+            .with_ops(StencilOp::Keep, StencilOp::Keep, StencilOp::IncrementClamp)
+            .with_masks(0x0F, 0x0F);
+        let state = RenderState::default().with_stencil(stencil, 2);
+        assert_eq!(state.stencil, Some(stencil));
+        assert_eq!(state.stencil_reference, 2);
+
+        let write = StencilState::WRITE_REFERENCE;
+        assert_eq!(write.pass_op, StencilOp::Replace);
+        let test = StencilState::NOT_EQUAL_REFERENCE;
+        assert_eq!(test.write_mask, 0);
+    }
+}
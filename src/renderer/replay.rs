@@ -0,0 +1,265 @@
+//! Replay module for the renderer.
+//!
+//! This module records the input events handled by the renderer and the
+//! duration of every frame, and plays them back in place of live input and
+//! the wall clock. Given the same starting scene, a replay reproduces a run
+//! exactly, which makes bugs reproducible and benchmark runs consistent.
+//!
+//! Replays are saved as RON through the events' serde implementations, one
+//! event per line. Floats are written in their shortest round-trip form, so
+//! playback sees bit-identical values.
+//! Only keys the renderer handles are recorded, since other keys have no
+//! effect. Keys are recorded rather than actions, so a replay should be
+//! played back with the bindings it was recorded with.
+//!
+//! ```ron
+//! // game_engine replay v2
+//! [
+//!     Frame(delta: 0.016666668),
+//!     Input(Key(code: "KeyW", pressed: true, delta_time: 0.1)),
+//!     Input(MouseMotion(delta_x: -2.5, delta_y: 1.0)),
+//!     Input(Scroll(delta: 1.0)),
+//! ]
+//! ```
+
+use super::{
+    error::ReplayError,
+    input::{key_name, InputBindings},
+};
+use log::info;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, str::FromStr};
+use winit::keyboard::KeyCode;
+
+/// The first line of every replay file.
+const REPLAY_HEADER: &str = "// game_engine replay v2";
+
+/// An input event as handled by the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum InputEvent {
+    /// A key was pressed or released.
+    ///
+    /// `delta_time` is the time since the previous key event, which scales
+    /// camera movement.
+    Key {
+        #[serde(with = "key_name")]
+        code: KeyCode,
+        pressed: bool,
+        delta_time: f32,
+    },
+    /// The mouse moved by this many pixels, with y pointing up.
+    MouseMotion { delta_x: f32, delta_y: f32 },
+    /// The mouse wheel scrolled by this many lines.
+    Scroll { delta: f32 },
+    /// The primary mouse button was pressed at a point in physical pixels
+    /// from the top-left of the window, with Shift held if `toggle` is set.
+    Click { x: f32, y: f32, toggle: bool },
+}
+
+impl InputEvent {
+    /// Returns `true` if the event can be stored in a replay.
+    ///
+    /// # Arguments
+    ///
+    /// * `bindings` - The key bindings the event is handled with.
+    pub fn is_replayable(&self, bindings: &InputBindings) -> bool {
+        match self {
+            InputEvent::Key { code, .. } => bindings.handles(*code),
+            _ => true,
+        }
+    }
+}
+
+/// An event in a replay.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum ReplayEvent {
+    Input(InputEvent),
+    /// A frame started, lasting `delta` unscaled seconds.
+    Frame {
+        delta: f32,
+    },
+}
+
+/// A recorded sequence of input events and frames.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    events: Vec<ReplayEvent>,
+    /// The index of the next event to play back.
+    position: usize,
+}
+
+impl Replay {
+    /// Creates a new empty `Replay` for recording.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a replay saved with `save`.
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let text = fs::read_to_string(path).map_err(|source| ReplayError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let replay: Replay = text.parse()?;
+        info!(
+            "Loaded replay {} with {} frames",
+            path.display(),
+            replay.frame_count()
+        );
+        Ok(replay)
+    }
+
+    /// Saves the replay as RON.
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        fs::write(path, self.to_ron()?).map_err(|source| ReplayError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        info!(
+            "Saved replay {} with {} frames",
+            path.display(),
+            self.frame_count()
+        );
+        Ok(())
+    }
+
+    /// Writes the replay as RON, one event per line.
+    pub fn to_ron(&self) -> Result<String, ReplayError> {
+        let config = PrettyConfig::new().depth_limit(1);
+        let events = ron::ser::to_string_pretty(&self.events, config)
+            .map_err(|error| ReplayError::Serialize(error.to_string()))?;
+        Ok(format!("{REPLAY_HEADER}\n{events}\n"))
+    }
+
+    /// Appends an input event, unless its key is not handled by the renderer.
+    pub fn record_input(&mut self, input: InputEvent, bindings: &InputBindings) {
+        if input.is_replayable(bindings) {
+            self.events.push(ReplayEvent::Input(input));
+        }
+    }
+
+    /// Appends the start of a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta` - The unscaled duration of the frame in seconds.
+    pub fn record_frame(&mut self, delta: f32) {
+        self.events.push(ReplayEvent::Frame { delta });
+    }
+
+    /// Returns the number of recorded frames.
+    pub fn frame_count(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| matches!(event, ReplayEvent::Frame { .. }))
+            .count()
+    }
+
+    /// Plays back the next frame.
+    ///
+    /// # Returns
+    ///
+    /// The input events to handle before the frame and the frame's unscaled
+    /// duration, or `None` once every frame has been played.
+    pub fn next_frame(&mut self) -> Option<(Vec<InputEvent>, f32)> {
+        let mut inputs = Vec::new();
+        while let Some(event) = self.events.get(self.position) {
+            self.position += 1;
+            match *event {
+                ReplayEvent::Input(input) => inputs.push(input),
+                ReplayEvent::Frame { delta } => return Some((inputs, delta)),
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for Replay {
+    type Err = ReplayError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text.lines().next().map(str::trim) != Some(REPLAY_HEADER) {
+            return Err(ReplayError::Parse {
+                line: 1,
+                message: format!("expected header \"{REPLAY_HEADER}\""),
+            });
+        }
+        let events = ron::from_str(text).map_err(|error| ReplayError::Parse {
+            line: error.span.start.line,
+            message: error.code.to_string(),
+        })?;
+        Ok(Self {
+            events,
+            position: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InputEvent, Replay};
+    use crate::renderer::{error::ReplayError, input::InputBindings};
+    use winit::keyboard::KeyCode;
+
+    #[test]
+    fn test_replay_round_trip() {
+        let bindings = InputBindings::default();
+        let mut replay = Replay::new();
+        replay.record_frame(0.1 + 0.2);
+        replay.record_input(
+            InputEvent::Key {
+                code: KeyCode::KeyW,
+                pressed: true,
+                delta_time: 1.0 / 3.0,
+            },
+            &bindings,
+        );
+        replay.record_input(
+            InputEvent::Key {
+                code: KeyCode::KeyQ,
+                pressed: true,
+                delta_time: 0.0,
+            },
+            &bindings,
+        );
+        replay.record_input(
+            InputEvent::MouseMotion {
+                delta_x: -2.5,
+                delta_y: f32::MIN_POSITIVE,
+            },
+            &bindings,
+        );
+        replay.record_frame(1.0 / 60.0);
+
+        let mut loaded: Replay = replay.to_ron().unwrap().parse().unwrap();
+        assert_eq!(loaded, replay);
+        assert_eq!(loaded.frame_count(), 2);
+
+        let (inputs, delta) = loaded.next_frame().unwrap();
+        assert!(inputs.is_empty());
+        assert_eq!(delta.to_bits(), (0.1f32 + 0.2).to_bits());
+        let (inputs, delta) = loaded.next_frame().unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(delta, 1.0 / 60.0);
+        assert!(loaded.next_frame().is_none());
+    }
+
+    #[test]
+    fn test_replay_parse_errors() {
+        assert!("[Frame(delta: 0.1)]".parse::<Replay>().is_err());
+        let error = "// game_engine replay v2\n[\n    Frame(delta: 0.1),\n    Input(Key(code: \"Q\", pressed: true, delta_time: 0.0)),\n]"
+            .parse::<Replay>()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid replay on line 4: unknown key \"Q\""
+        );
+        let error = "// game_engine replay v2\n[\n    Frame(delta: 0.1, speed: 2.0),\n]"
+            .parse::<Replay>()
+            .unwrap_err();
+        assert!(matches!(error, ReplayError::Parse { line: 3, .. }));
+    }
+}
@@ -0,0 +1,344 @@
+//! Scene diff module for the renderer.
+//!
+//! This module provides `ScenePatch`, the structural difference between two
+//! `SceneDocument`s as the nodes added, removed and modified. A patch applied
+//! to the document it was computed from gives the other, so a scene can be
+//! saved incrementally as a base document followed by the patches of each
+//! later save. Patches computed from the same base by different people are
+//! combined with `merge`, which reports the nodes both changed differently.
+//!
+//! Patches are saved as text in the format of scene files, with one change
+//! per line:
+//!
+//! ```text
+//! # game_engine scene patch v2
+//! remove 4
+//! add 7 0 0 1 0 0 0 0 1 1 1 1 3 2 1 "Right engine"
+//! modify 1 0 0 0.5 -2 0 0 0 1 1 1 1 3 2 1 "Left engine"
+//! ```
+//!
+//! Nodes are matched by ID, so patches are only meaningful between captures
+//! of the same scene graph, or documents loaded from the same base.
+
+use super::{
+    error::SceneFileError,
+    scene_file::{parse_lines, parse_node, NodeLine, SceneDocument, SceneNode},
+    scene_graph::NodeId,
+};
+use log::info;
+use std::{collections::BTreeMap, fmt, fs, path::Path, str::FromStr};
+
+/// The first line of every patch file.
+const PATCH_HEADER: &str = "# game_engine scene patch v2";
+
+/// A change of one node between two scene documents.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneChange {
+    Added(NodeId, SceneNode),
+    Removed(NodeId),
+    /// The node's parent, transform, mesh, material, layers or name changed
+    /// to those given.
+    Modified(NodeId, SceneNode),
+}
+
+impl SceneChange {
+    /// Returns the node changed.
+    pub fn node(&self) -> NodeId {
+        match *self {
+            SceneChange::Added(id, _) | SceneChange::Removed(id) | SceneChange::Modified(id, _) => {
+                id
+            }
+        }
+    }
+}
+
+/// The changes turning one scene document into another, ordered by node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScenePatch {
+    changes: Vec<SceneChange>,
+}
+
+impl ScenePatch {
+    /// Computes the changes turning `old` into `new`.
+    pub fn between(old: &SceneDocument, new: &SceneDocument) -> Self {
+        let mut changes: BTreeMap<NodeId, SceneChange> = BTreeMap::new();
+        for (id, node) in old.nodes() {
+            match new.get(id) {
+                None => {
+                    changes.insert(id, SceneChange::Removed(id));
+                }
+                Some(new_node) if new_node != node => {
+                    changes.insert(id, SceneChange::Modified(id, new_node.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        for (id, node) in new.nodes() {
+            if old.get(id).is_none() {
+                changes.insert(id, SceneChange::Added(id, node.clone()));
+            }
+        }
+        Self {
+            changes: changes.into_values().collect(),
+        }
+    }
+
+    /// Returns the changes, ordered by node.
+    pub fn changes(&self) -> &[SceneChange] {
+        &self.changes
+    }
+
+    /// Returns the change of a node.
+    pub fn change(&self, id: NodeId) -> Option<&SceneChange> {
+        self.changes.iter().find(|change| change.node() == id)
+    }
+
+    /// Returns `true` if the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies the changes to a document.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `SceneFileError::MissingNode` if a
+    /// modified or removed node doesn't exist, `SceneFileError::DuplicateNode`
+    /// if an added node already exists, or `SceneFileError::InvalidParent`
+    /// if the result has a broken hierarchy. The document is unchanged on
+    /// failure.
+    pub fn apply(&self, document: &mut SceneDocument) -> Result<(), SceneFileError> {
+        let mut patched = document.clone();
+        for change in &self.changes {
+            match change {
+                SceneChange::Added(id, node) => {
+                    if patched.get(*id).is_some() {
+                        return Err(SceneFileError::DuplicateNode(*id));
+                    }
+                    patched.insert(*id, node.clone());
+                }
+                SceneChange::Removed(id) => {
+                    patched
+                        .remove(*id)
+                        .ok_or(SceneFileError::MissingNode(*id))?;
+                }
+                SceneChange::Modified(id, node) => {
+                    if patched.insert(*id, node.clone()).is_none() {
+                        return Err(SceneFileError::MissingNode(*id));
+                    }
+                }
+            }
+        }
+        patched.hierarchy_order()?;
+        *document = patched;
+        Ok(())
+    }
+
+    /// Combines two patches computed from the same document.
+    ///
+    /// Changes to different nodes are kept, as are identical changes to the
+    /// same node.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the combined patch, or the nodes the patches
+    /// change differently.
+    pub fn merge(&self, other: &ScenePatch) -> Result<ScenePatch, Vec<NodeId>> {
+        let mut changes: BTreeMap<NodeId, SceneChange> = self
+            .changes
+            .iter()
+            .map(|change| (change.node(), change.clone()))
+            .collect();
+        let mut conflicts = Vec::new();
+        for change in &other.changes {
+            match changes.get(&change.node()) {
+                Some(existing) if existing != change => conflicts.push(change.node()),
+                Some(_) => {}
+                None => {
+                    changes.insert(change.node(), change.clone());
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            return Err(conflicts);
+        }
+        Ok(Self {
+            changes: changes.into_values().collect(),
+        })
+    }
+
+    /// Loads a patch saved with `save`.
+    pub fn load(path: &Path) -> Result<Self, SceneFileError> {
+        let text = fs::read_to_string(path).map_err(|source| SceneFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let patch: ScenePatch = text.parse()?;
+        info!(
+            "Loaded scene patch {} with {} changes",
+            path.display(),
+            patch.changes.len()
+        );
+        Ok(patch)
+    }
+
+    /// Saves the patch as text.
+    pub fn save(&self, path: &Path) -> Result<(), SceneFileError> {
+        fs::write(path, self.to_string()).map_err(|source| SceneFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        info!(
+            "Saved scene patch {} with {} changes",
+            path.display(),
+            self.changes.len()
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Display for ScenePatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{PATCH_HEADER}")?;
+        for change in &self.changes {
+            match change {
+                SceneChange::Added(id, node) => writeln!(f, "add {}", NodeLine(*id, node))?,
+                SceneChange::Removed(id) => writeln!(f, "remove {}", id.0)?,
+                SceneChange::Modified(id, node) => writeln!(f, "modify {}", NodeLine(*id, node))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ScenePatch {
+    type Err = SceneFileError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut changes: BTreeMap<NodeId, SceneChange> = BTreeMap::new();
+        for (line, kind, fields) in parse_lines(text, PATCH_HEADER)? {
+            let error = |message: String| SceneFileError::Parse { line, message };
+            let change = match kind {
+                "add" => {
+                    let (id, node) = parse_node(fields).map_err(error)?;
+                    SceneChange::Added(id, node)
+                }
+                "modify" => {
+                    let (id, node) = parse_node(fields).map_err(error)?;
+                    SceneChange::Modified(id, node)
+                }
+                "remove" => {
+                    let id = fields
+                        .parse::<usize>()
+                        .map_err(|_| error("expected a node ID".to_string()))?;
+                    SceneChange::Removed(NodeId(id))
+                }
+                _ => return Err(error(format!("unknown change \"{kind}\""))),
+            };
+            let id = change.node();
+            if changes.insert(id, change).is_some() {
+                return Err(error(format!("node {} changes twice", id.0)));
+            }
+        }
+        Ok(Self {
+            changes: changes.into_values().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SceneChange, ScenePatch};
+    use crate::renderer::{
+        error::SceneFileError,
+        material_manager::MaterialId,
+        scene_file::SceneDocument,
+        scene_graph::{NodeId, SceneGraph},
+    };
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_scene_patch_diff_and_apply() {
+        let mut graph = SceneGraph::new();
+        let ship = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let engine = graph.add_node(Some(ship), Mat4::IDENTITY).unwrap();
+        let cargo = graph.add_node(Some(ship), Mat4::IDENTITY).unwrap();
+        let base = SceneDocument::capture(&graph);
+
+        graph.set_translation(engine, Vec3::X).unwrap();
+        graph.remove_node(cargo).unwrap();
+        let turret = graph.add_node(Some(engine), Mat4::IDENTITY).unwrap();
+        graph.set_name(turret, Some(" turret\n2 ")).unwrap();
+        let edited = SceneDocument::capture(&graph);
+
+        let patch = ScenePatch::between(&base, &edited);
+        let kinds: Vec<_> = patch
+            .changes()
+            .iter()
+            .map(|change| match change {
+                SceneChange::Added(id, _) => ("add", *id),
+                SceneChange::Removed(id) => ("remove", *id),
+                SceneChange::Modified(id, _) => ("modify", *id),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [("modify", engine), ("remove", cargo), ("add", turret)]
+        );
+
+        // A patch saved after the base reproduces the edited scene
+        let loaded: ScenePatch = patch.to_string().parse().unwrap();
+        assert_eq!(loaded, patch);
+        let mut document = base.clone();
+        loaded.apply(&mut document).unwrap();
+        assert_eq!(document, edited);
+        assert!(ScenePatch::between(&document, &edited).is_empty());
+
+        // Applying it again fails without changing the document
+        assert!(matches!(
+            patch.apply(&mut document),
+            Err(SceneFileError::MissingNode(id)) if id == cargo
+        ));
+        assert_eq!(document, edited);
+        // Removing a parent but not its children breaks the hierarchy
+        let orphaning: ScenePatch = "# game_engine scene patch v2\nremove 0".parse().unwrap();
+        assert!(matches!(
+            orphaning.apply(&mut document),
+            Err(SceneFileError::InvalidParent { .. })
+        ));
+    }
+
+    #[test]
+    fn test_scene_patch_merge() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let b = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let base = SceneDocument::capture(&graph);
+
+        let mut first = base.clone();
+        let mut node = base.get(a).unwrap().clone();
+        node.material_id = MaterialId(1);
+        first.insert(a, node);
+        let mut second = base.clone();
+        let mut node = base.get(b).unwrap().clone();
+        node.name = Some("b".to_string());
+        second.insert(b, node);
+
+        let ours = ScenePatch::between(&base, &first);
+        let theirs = ScenePatch::between(&base, &second);
+        let merged = ours.merge(&theirs).unwrap();
+        let mut document = base.clone();
+        merged.apply(&mut document).unwrap();
+        assert_eq!(document.get(a).unwrap().material_id, MaterialId(1));
+        assert_eq!(document.get(b).unwrap().name.as_deref(), Some("b"));
+        assert_eq!(ours.merge(&ours), Ok(ours.clone()));
+
+        // Both changing the same node differently conflicts
+        let mut removed = base.clone();
+        removed.remove(a);
+        let conflicting = ScenePatch::between(&base, &removed);
+        assert_eq!(ours.merge(&conflicting), Err(vec![a]));
+        assert_eq!(merged.change(NodeId(7)), None);
+    }
+}
@@ -0,0 +1,539 @@
+//! Scene file module for the renderer.
+//!
+//! This module provides `SceneDocument`, a serializable snapshot of a
+//! `SceneGraph`'s hierarchy: every node's parent, local transform, mesh,
+//! material, layers and name. Documents are saved as text, one node per
+//! line, so they can be compared and merged by `ScenePatch` and by version
+//! control. Floats are written in their shortest round-trip form, so a saved
+//! scene loads bit-identical.
+//!
+//! ```text
+//! # game_engine scene v2
+//! # node <id> <parent> <translation> <rotation> <scale> <mesh> <material> <layers> [name]
+//! node 0 - 0 0 0 0 0 0 1 1 1 1 - 0 1 "Ship"
+//! node 1 0 0 0.5 -2 0 0 0 1 1 1 1 3 2 1 "Left engine"
+//! ```
+//!
+//! Nodes are keyed by the `NodeId` they had in the captured graph; `-`
+//! stands for no parent or no mesh. Names are quoted, with backslashes,
+//! quotes, tabs and line breaks escaped, so any name loads unchanged. Mesh and material IDs refer to the
+//! storage of the session the scene was captured in. Sockets, constraints,
+//! outlines and attached data are not saved.
+
+use super::{
+    common::LayerMask,
+    error::SceneFileError,
+    material_manager::MaterialId,
+    scene_graph::{NodeId, SceneGraph},
+};
+use crate::math::transform::Transform;
+use glam::{Quat, Vec3};
+use log::info;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+};
+
+/// The first line of every scene file.
+const SCENE_HEADER: &str = "# game_engine scene v2";
+
+/// The number of fields of a node before its name.
+const NODE_FIELDS: usize = 15;
+
+/// A node of a `SceneDocument`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneNode {
+    pub parent: Option<NodeId>,
+    /// The transform relative to the parent.
+    pub transform: Transform,
+    pub mesh_id: Option<usize>,
+    pub material_id: MaterialId,
+    pub layers: LayerMask,
+    pub name: Option<String>,
+}
+
+/// A snapshot of a scene graph's hierarchy, keyed by node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SceneDocument {
+    nodes: BTreeMap<NodeId, SceneNode>,
+}
+
+impl SceneDocument {
+    /// Creates a new, empty `SceneDocument`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captures every node of a scene graph.
+    pub fn capture(graph: &SceneGraph) -> Self {
+        let nodes = graph
+            .nodes()
+            .filter_map(|id| {
+                let node = SceneNode {
+                    parent: graph.parent(id),
+                    transform: graph.local_transform(id)?,
+                    mesh_id: graph.mesh(id),
+                    material_id: graph.material(id)?,
+                    layers: graph.layers(id)?,
+                    name: graph.name(id).map(str::to_string),
+                };
+                Some((id, node))
+            })
+            .collect();
+        Self { nodes }
+    }
+
+    /// Adds the document's nodes to a scene graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - The scene graph to add the nodes to.
+    /// * `parent` - The node the document's roots are added under, or `None`
+    ///   to add them as roots.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the graph node created for each document node,
+    /// `SceneFileError::MissingNode` if `parent` doesn't exist, or
+    /// `SceneFileError::InvalidParent` if the hierarchy is broken. Nothing is
+    /// added on failure.
+    pub fn instantiate(
+        &self,
+        graph: &mut SceneGraph,
+        parent: Option<NodeId>,
+    ) -> Result<HashMap<NodeId, NodeId>, SceneFileError> {
+        let order = self.hierarchy_order()?;
+        if let Some(parent) = parent.filter(|&parent| !graph.contains(parent)) {
+            return Err(SceneFileError::MissingNode(parent));
+        }
+        let mut created = HashMap::with_capacity(order.len());
+        for id in order {
+            let node = &self.nodes[&id];
+            let node_parent = match node.parent {
+                Some(node_parent) => Some(created[&node_parent]),
+                None => parent,
+            };
+            // The hierarchy and parent were checked, so adding cannot fail
+            let Ok(new) = graph.add_node(node_parent, node.transform) else {
+                continue;
+            };
+            let _ = graph.set_mesh(new, node.mesh_id, node.material_id);
+            let _ = graph.set_layers(new, node.layers);
+            let _ = graph.set_name(new, node.name.as_deref());
+            created.insert(id, new);
+        }
+        Ok(created)
+    }
+
+    /// Returns a node.
+    pub fn get(&self, id: NodeId) -> Option<&SceneNode> {
+        self.nodes.get(&id)
+    }
+
+    /// Returns every node, ordered by ID.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &SceneNode)> + '_ {
+        self.nodes.iter().map(|(&id, node)| (id, node))
+    }
+
+    /// Adds or replaces a node, returning the node it replaced.
+    pub fn insert(&mut self, id: NodeId, node: SceneNode) -> Option<SceneNode> {
+        self.nodes.insert(id, node)
+    }
+
+    /// Removes a node, leaving its children's parent dangling.
+    pub fn remove(&mut self, id: NodeId) -> Option<SceneNode> {
+        self.nodes.remove(&id)
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the document has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns every node with its parent before it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the nodes, or `SceneFileError::InvalidParent`
+    /// if a parent is missing or part of a cycle.
+    pub fn hierarchy_order(&self) -> Result<Vec<NodeId>, SceneFileError> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut placed: HashMap<NodeId, bool> = HashMap::with_capacity(self.nodes.len());
+        let mut chain = Vec::new();
+        for &start in self.nodes.keys() {
+            // Walk up to a root or a placed node, then place the chain top-down
+            let mut id = start;
+            while !placed.contains_key(&id) {
+                placed.insert(id, false);
+                chain.push(id);
+                let Some(parent) = self.nodes[&id].parent else {
+                    break;
+                };
+                // A parent visited on this walk but not placed is its own ancestor
+                if !self.nodes.contains_key(&parent) || placed.get(&parent) == Some(&false) {
+                    return Err(SceneFileError::InvalidParent { node: id, parent });
+                }
+                id = parent;
+            }
+            while let Some(id) = chain.pop() {
+                placed.insert(id, true);
+                order.push(id);
+            }
+        }
+        Ok(order)
+    }
+
+    /// Loads a scene saved with `save`.
+    pub fn load(path: &Path) -> Result<Self, SceneFileError> {
+        let text = fs::read_to_string(path).map_err(|source| SceneFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let document: SceneDocument = text.parse()?;
+        info!(
+            "Loaded scene {} with {} nodes",
+            path.display(),
+            document.len()
+        );
+        Ok(document)
+    }
+
+    /// Saves the scene as text.
+    pub fn save(&self, path: &Path) -> Result<(), SceneFileError> {
+        fs::write(path, self.to_string()).map_err(|source| SceneFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        info!("Saved scene {} with {} nodes", path.display(), self.len());
+        Ok(())
+    }
+}
+
+impl fmt::Display for SceneDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{SCENE_HEADER}")?;
+        for (&id, node) in &self.nodes {
+            writeln!(f, "node {}", NodeLine(id, node))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SceneDocument {
+    type Err = SceneFileError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut document = SceneDocument::new();
+        for (line, kind, fields) in parse_lines(text, SCENE_HEADER)? {
+            let error = |message: String| SceneFileError::Parse { line, message };
+            if kind != "node" {
+                return Err(error(format!("unknown entry \"{kind}\"")));
+            }
+            let (id, node) = parse_node(fields).map_err(error)?;
+            if document.nodes.insert(id, node).is_some() {
+                return Err(error(format!("node {} appears twice", id.0)));
+            }
+        }
+        Ok(document)
+    }
+}
+
+/// Formats a node's fields as a line of a scene or patch file.
+pub(crate) struct NodeLine<'a>(pub NodeId, pub &'a SceneNode);
+
+impl fmt::Display for NodeLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let NodeLine(id, node) = self;
+        let optional = |value: Option<usize>| value.map_or("-".to_string(), |v| v.to_string());
+        let Transform {
+            translation: t,
+            rotation: r,
+            scale: s,
+        } = node.transform;
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            id.0,
+            optional(node.parent.map(|parent| parent.0)),
+            t.x,
+            t.y,
+            t.z,
+            r.x,
+            r.y,
+            r.z,
+            r.w,
+            s.x,
+            s.y,
+            s.z,
+            optional(node.mesh_id),
+            node.material_id.0,
+            node.layers.0
+        )?;
+        if let Some(name) = &node.name {
+            f.write_str(" \"")?;
+            for c in name.chars() {
+                match c {
+                    '\\' => f.write_str("\\\\")?,
+                    '"' => f.write_str("\\\"")?,
+                    '\n' => f.write_str("\\n")?,
+                    '\r' => f.write_str("\\r")?,
+                    '\t' => f.write_str("\\t")?,
+                    c => write!(f, "{c}")?,
+                }
+            }
+            f.write_str("\"")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a quoted name, as written by `NodeLine`.
+fn parse_name(text: &str) -> Result<String, String> {
+    let mut chars = text
+        .strip_prefix('"')
+        .ok_or("expected a quoted name")?
+        .chars();
+    let mut name = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => break,
+            Some('\\') => name.push(match chars.next() {
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some(c) => return Err(format!("unknown escape \"\\{c}\" in the name")),
+                None => return Err("unterminated name".to_string()),
+            }),
+            Some(c) => name.push(c),
+            None => return Err("unterminated name".to_string()),
+        }
+    }
+    if !chars.as_str().is_empty() {
+        return Err("unexpected text after the name".to_string());
+    }
+    Ok(name)
+}
+
+/// Splits a scene or patch file into its entries after checking its header.
+///
+/// # Returns
+///
+/// The line number, kind and remaining fields of every non-empty line that
+/// isn't a comment.
+pub(crate) fn parse_lines<'a>(
+    text: &'a str,
+    header: &str,
+) -> Result<Vec<(usize, &'a str, &'a str)>, SceneFileError> {
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some(header) {
+        return Err(SceneFileError::Parse {
+            line: 1,
+            message: format!("expected header \"{header}\""),
+        });
+    }
+    Ok(lines
+        .filter_map(|(index, line)| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (kind, fields) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            Some((index + 1, kind, fields.trim_start()))
+        })
+        .collect())
+}
+
+/// Parses a node's fields, as written by `NodeLine`.
+pub(crate) fn parse_node(text: &str) -> Result<(NodeId, SceneNode), String> {
+    let mut rest = text.trim();
+    let mut fields = Vec::with_capacity(NODE_FIELDS);
+    while fields.len() < NODE_FIELDS && !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    if fields.len() < NODE_FIELDS {
+        return Err(format!(
+            "expected {NODE_FIELDS} fields, found {}",
+            fields.len()
+        ));
+    }
+
+    let integer = |field: usize| {
+        fields[field]
+            .parse::<usize>()
+            .map_err(|_| format!("expected an integer in field {}", field + 1))
+    };
+    let optional = |field: usize| match fields[field] {
+        "-" => Ok(None),
+        _ => integer(field).map(Some),
+    };
+    let float = |field: usize| {
+        fields[field]
+            .parse::<f32>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| format!("expected a number in field {}", field + 1))
+    };
+    let vector = |field: usize| {
+        Ok::<_, String>(Vec3::new(
+            float(field)?,
+            float(field + 1)?,
+            float(field + 2)?,
+        ))
+    };
+
+    let rotation = Quat::from_xyzw(float(5)?, float(6)?, float(7)?, float(8)?);
+    let layers = fields[14]
+        .parse::<u32>()
+        .map_err(|_| "expected a layer mask in field 15".to_string())?;
+    let node = SceneNode {
+        parent: optional(1)?.map(NodeId),
+        transform: Transform::new(vector(2)?, rotation, vector(9)?),
+        mesh_id: optional(12)?,
+        material_id: MaterialId(integer(13)?),
+        layers: LayerMask(layers),
+        name: match rest {
+            "" => None,
+            _ => Some(parse_name(rest)?),
+        },
+    };
+    Ok((NodeId(integer(0)?), node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SceneDocument;
+    use crate::renderer::{
+        common::LayerMask,
+        error::SceneFileError,
+        material_manager::MaterialId,
+        scene_graph::{NodeId, SceneGraph},
+    };
+    use glam::{Mat4, Quat, Vec3};
+
+    #[test]
+    fn test_scene_document_round_trip() {
+        let mut graph = SceneGraph::new();
+        let ship = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let engine = graph
+            .add_node(
+                Some(ship),
+                Mat4::from_rotation_translation(
+                    Quat::from_rotation_y(0.1 + 0.2),
+                    Vec3::new(1.0 / 3.0, -2.0, 0.5),
+                ),
+            )
+            .unwrap();
+        graph.set_mesh(engine, Some(3), MaterialId(2)).unwrap();
+        graph.set_layers(engine, LayerMask(5)).unwrap();
+        graph.set_name(engine, Some("Left  \"engine\"")).unwrap();
+
+        let document = SceneDocument::capture(&graph);
+        let text = document.to_string();
+        let loaded: SceneDocument = text.parse().unwrap();
+        assert_eq!(loaded, document);
+        assert_eq!(
+            loaded.get(engine).unwrap().name.as_deref(),
+            Some("Left  \"engine\"")
+        );
+
+        // Instantiating under a new parent recreates the hierarchy
+        let mut other = SceneGraph::new();
+        let holder = other.add_node(None, Mat4::IDENTITY).unwrap();
+        let created = loaded.instantiate(&mut other, Some(holder)).unwrap();
+        assert_eq!(other.parent(created[&ship]), Some(holder));
+        assert_eq!(other.parent(created[&engine]), Some(created[&ship]));
+        assert_eq!(other.mesh(created[&engine]), Some(3));
+        assert_eq!(
+            other.find_by_name("Left  \"engine\""),
+            Some(created[&engine])
+        );
+        assert_eq!(
+            other.local_transform(created[&engine]),
+            graph.local_transform(engine)
+        );
+    }
+
+    #[test]
+    fn test_scene_document_errors() {
+        let error = "# game_engine scene v2\nnode 0 - 0 0 0 0 0 0 1 1 1 x - 0 1"
+            .parse::<SceneDocument>()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid scene file on line 2: expected a number in field 12"
+        );
+        assert!("node 0".parse::<SceneDocument>().is_err());
+
+        // A cycle can't be instantiated
+        let cycle = "# game_engine scene v2\n\
+            node 0 1 0 0 0 0 0 0 1 1 1 1 - 0 1\n\
+            node 1 0 0 0 0 0 0 0 1 1 1 1 - 0 1"
+            .parse::<SceneDocument>()
+            .unwrap();
+        let mut graph = SceneGraph::new();
+        assert!(matches!(
+            cycle.instantiate(&mut graph, None),
+            Err(SceneFileError::InvalidParent { .. })
+        ));
+        assert!(graph.is_empty());
+        assert!(cycle.get(NodeId(1)).is_some());
+    }
+
+    #[test]
+    fn test_scene_document_names_round_trip() {
+        let mut graph = SceneGraph::new();
+        let names = [
+            " padded ",
+            "",
+            "a\nb",
+            "tab\there",
+            "back\\slash \"quoted\"\r",
+        ];
+        for name in names {
+            let node = graph.add_node(None, Mat4::IDENTITY).unwrap();
+            graph.set_name(node, Some(name)).unwrap();
+        }
+        graph.add_node(None, Mat4::IDENTITY).unwrap();
+
+        let document = SceneDocument::capture(&graph);
+        let text = document.to_string();
+        // Every node stays on its own line
+        assert_eq!(text.lines().count(), 1 + names.len() + 1);
+        let loaded: SceneDocument = text.parse().unwrap();
+        assert_eq!(loaded, document);
+        let loaded_names: Vec<_> = loaded.nodes().map(|(_, node)| node.name.clone()).collect();
+        let expected: Vec<_> = names
+            .iter()
+            .map(|name| Some(name.to_string()))
+            .chain([None])
+            .collect();
+        assert_eq!(loaded_names, expected);
+
+        for (line, message) in [
+            ("Ship", "expected a quoted name"),
+            ("\"Ship", "unterminated name"),
+            ("\"Ship\" x", "unexpected text after the name"),
+            ("\"S\\hip\"", "unknown escape \"\\h\" in the name"),
+        ] {
+            let error =
+                format!("# game_engine scene v2\nnode 0 - 0 0 0 0 0 0 1 1 1 1 - 0 1 {line}")
+                    .parse::<SceneDocument>()
+                    .unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid scene file on line 2: {message}")
+            );
+        }
+    }
+}
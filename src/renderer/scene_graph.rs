@@ -1,74 +1,1365 @@
-// use super::{material_manager::MaterialId, mesh_manager::MeshId, render_core::ObjectId};
-// use glam::{Mat4, Quat, Vec3};
-
-// pub struct SceneGraph {
-//     object_ids: Vec<ObjectId>,
-//     mesh_ids: Vec<MeshId>,
-//     material_ids: Vec<MaterialId>,
-//     positions: Vec<Vec3>,
-//     rotations: Vec<Quat>,
-//     scales: Vec<Vec3>,
-//     // colors: Vec<Color>,
-//     // pub mesh_indices: Vec<usize>,
-// }
-
-// impl SceneGraph {
-//     pub fn new() -> Self {
-//         SceneGraph {
-//             object_ids: Vec::new(),
-//             mesh_ids: Vec::new(),
-//             material_ids: Vec::new(),
-//             positions: Vec::new(),
-//             rotations: Vec::new(),
-//             scales: Vec::new(),
-//         }
-//     }
-
-//     pub fn add_object(
-//         &mut self,
-//         mesh_id: MeshId,
-//         material_id: MaterialId,
-//         transform: Mat4,
-//     ) -> ObjectId {
-//         let object_id = ObjectId(self.object_ids.len());
-
-//         let (scale, rotation, position) = transform.to_scale_rotation_translation();
-
-//         self.object_ids.push(object_id);
-//         self.mesh_ids.push(mesh_id);
-//         self.material_ids.push(material_id);
-//         self.positions.push(position);
-//         self.rotations.push(rotation);
-//         self.scales.push(scale);
-
-//         object_id
-//     }
-
-//     pub fn update_object(&mut self, object_id: ObjectId, transform: Mat4) {
-//         let index = self
-//             .object_ids
-//             .iter()
-//             .position(|&id| id == object_id)
-//             .unwrap();
-//         let (scale, rotation, position) = transform.to_scale_rotation_translation();
-
-//         self.positions[index] = position;
-//         self.rotations[index] = rotation;
-//         self.scales[index] = scale;
-//     }
-
-//     pub fn remove_object(&mut self, object_id: ObjectId) {
-//         let index = self
-//             .object_ids
-//             .iter()
-//             .position(|&id| id == object_id)
-//             .unwrap();
-
-//         self.object_ids.swap_remove(index);
-//         self.mesh_ids.swap_remove(index);
-//         self.material_ids.swap_remove(index);
-//         self.positions.swap_remove(index);
-//         self.rotations.swap_remove(index);
-//         self.scales.swap_remove(index);
-//     }
-// }
+//! Scene graph module for the renderer.
+//!
+//! This module provides the `SceneGraph`, a transform hierarchy stored as flat
+//! structure-of-arrays columns (local translation, rotation and scale, parent
+//! index, world matrix). Nodes are kept sorted by depth, so every parent
+//! precedes its children and all world transforms can be updated in a single
+//! linear pass. With the `parallel` feature, each depth level is updated in
+//! parallel with rayon.
+//!
+//! Nodes can be given names, and `stats` and `dump_tree` summarize and print
+//! the hierarchy for inspecting a constructed scene. Application state can be
+//! attached to nodes as `NodeData`, and callbacks observe nodes being added
+//! and removed. `duplicate` copies a node or a whole subtree, sharing meshes.
+//!
+//! `Constraint`s declared on nodes are solved after every hierarchy update.
+//! Nodes attached to a socket of their parent's mesh are placed relative to
+//! the socket rather than the parent's origin.
+
+use super::{
+    bounds::{Aabb, BoundingSphere},
+    common::LayerMask,
+    constraints::Constraint,
+    error::SceneError,
+    material_manager::MaterialId,
+    mesh::MeshStorage,
+    node_data::{NodeAddedCallback, NodeData, NodeRemovedCallback},
+    render_state::Outline,
+};
+use crate::{debug_trace, math::transform::Transform};
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use log::debug;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Marks a node without a parent in the `parents` column.
+const NO_PARENT: u32 = u32::MAX;
+
+/// A stable handle to a node in the `SceneGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub usize);
+
+/// A node with a mesh, as submitted for drawing.
+#[derive(Clone, Copy, Debug)]
+pub struct MeshNode<'a> {
+    pub id: NodeId,
+    pub mesh_id: usize,
+    pub material_id: MaterialId,
+    pub layers: LayerMask,
+    pub outline: Option<Outline>,
+    pub world: &'a Mat4,
+}
+
+/// A summary of the nodes of a `SceneGraph`, from `SceneGraph::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    pub nodes: usize,
+    pub roots: usize,
+    /// The most ancestors of any node, 0 if every node is a root.
+    pub max_depth: usize,
+    /// The number of nodes with a mesh.
+    pub mesh_nodes: usize,
+    /// The number of distinct meshes the nodes reference.
+    pub unique_meshes: usize,
+    /// The number of nodes with a mesh on a layer the camera draws.
+    pub visible: usize,
+}
+
+/// A transform hierarchy stored as flat, depth-sorted arrays.
+#[derive(Default)]
+pub struct SceneGraph {
+    // Node columns, sorted by depth so parents precede children
+    node_ids: Vec<NodeId>,
+    parents: Vec<u32>,
+    depths: Vec<u32>,
+    translations: Vec<Vec3>,
+    rotations: Vec<Quat>,
+    scales: Vec<Vec3>,
+    world_matrices: Vec<Mat4>,
+    mesh_ids: Vec<Option<usize>>,
+    material_ids: Vec<MaterialId>,
+    layers: Vec<LayerMask>,
+    outlines: Vec<Option<Outline>>,
+    // The model-space transform of the parent mesh's socket each node is attached to
+    socket_offsets: Vec<Option<Mat4>>,
+    // Names by ID, as few nodes are named
+    names: HashMap<NodeId, String>,
+    socket_names: HashMap<NodeId, String>,
+    // Attached values by ID, as most nodes hold none
+    data: HashMap<NodeId, NodeData>,
+    on_added: Option<Box<NodeAddedCallback>>,
+    on_removed: Option<Box<NodeRemovedCallback>>,
+    // Constraints by ID, applied in order after the hierarchy update
+    constraints: HashMap<NodeId, Vec<Constraint>>,
+    camera_position: Vec3,
+
+    // Maps `NodeId` to the node's current index in the columns
+    slots: Vec<Option<u32>>,
+    // Start index of every depth level, followed by the node count
+    level_starts: Vec<usize>,
+    needs_sort: bool,
+}
+
+impl SceneGraph {
+    /// Creates a new, empty `SceneGraph`.
+    pub fn new() -> Self {
+        debug!("Creating new SceneGraph");
+        Self::default()
+    }
+
+    /// Adds a node to the graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent` - The parent node, or `None` for a root node.
+    /// * `local_transform` - The transform of the node relative to its parent,
+    ///   as a `Transform` or a `Mat4` without shear.
+    ///
+    /// # Returns
+    ///
+    /// The `NodeId` of the new node, or `SceneError::InvalidNode` if the
+    /// parent does not exist.
+    pub fn add_node(
+        &mut self,
+        parent: Option<NodeId>,
+        local_transform: impl Into<Transform>,
+    ) -> Result<NodeId, SceneError> {
+        let (parent_index, depth) = match parent {
+            Some(parent) => {
+                let index = self.index(parent)?;
+                (index as u32, self.depths[index] + 1)
+            }
+            None => (NO_PARENT, 0),
+        };
+
+        let id = NodeId(self.slots.len());
+        let Transform {
+            translation,
+            rotation,
+            scale,
+        } = local_transform.into();
+
+        self.slots.push(Some(self.node_ids.len() as u32));
+        self.node_ids.push(id);
+        self.parents.push(parent_index);
+        self.depths.push(depth);
+        self.translations.push(translation);
+        self.rotations.push(rotation);
+        self.scales.push(scale);
+        self.world_matrices.push(Mat4::IDENTITY);
+        self.mesh_ids.push(None);
+        self.material_ids.push(MaterialId::DEFAULT);
+        self.layers.push(LayerMask::default());
+        self.outlines.push(None);
+        self.socket_offsets.push(None);
+
+        // Appending keeps parents before children, but not depth order
+        self.needs_sort = true;
+        debug_trace!("Added scene node {:?} with parent {:?}", id, parent);
+        if let Some(on_added) = &mut self.on_added {
+            on_added(id);
+        }
+        Ok(id)
+    }
+
+    /// Removes a node and all of its descendants.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `SceneError::InvalidNode`.
+    pub fn remove_node(&mut self, id: NodeId) -> Result<(), SceneError> {
+        if self.needs_sort {
+            self.sort_by_depth();
+        }
+        let index = self.index(id)?;
+        let removed = self.subtree_mask(index);
+
+        let keep: Vec<usize> = (0..self.len()).filter(|&i| !removed[i]).collect();
+        let removed_ids: Vec<NodeId> = (0..self.len())
+            .filter(|&i| removed[i])
+            .map(|i| self.node_ids[i])
+            .collect();
+        for node_id in &removed_ids {
+            self.slots[node_id.0] = None;
+            self.names.remove(node_id);
+            self.socket_names.remove(node_id);
+            self.constraints.remove(node_id);
+        }
+        self.apply_permutation(&keep);
+        debug_trace!("Removed scene node {:?}", id);
+
+        // Descendants are reported before their ancestors
+        for node_id in removed_ids.into_iter().rev() {
+            let data = self.data.remove(&node_id).unwrap_or_default();
+            if let Some(on_removed) = &mut self.on_removed {
+                on_removed(node_id, data);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies a node, and optionally its descendants, under the node's parent.
+    ///
+    /// Copies share the originals' meshes and materials, and keep their
+    /// local transforms, layers, names, sockets and constraints. Constraints
+    /// reading a copied node read its copy instead. Of the attached data,
+    /// only values inserted with `insert_cloneable_data` are copied. Outlines
+    /// are not copied, as they highlight particular nodes, e.g. the selection.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The node to copy.
+    /// * `recursive` - Whether to copy the node's descendants too.
+    ///
+    /// # Returns
+    ///
+    /// The `NodeId` of the copy of `id`, or `SceneError::InvalidNode`.
+    pub fn duplicate(&mut self, id: NodeId, recursive: bool) -> Result<NodeId, SceneError> {
+        if self.needs_sort {
+            self.sort_by_depth();
+        }
+        let index = self.index(id)?;
+        // Sorted by depth, so every original is copied after its parent
+        let originals: Vec<usize> = if recursive {
+            let subtree = self.subtree_mask(index);
+            (0..self.len()).filter(|&i| subtree[i]).collect()
+        } else {
+            vec![index]
+        };
+
+        // Adding nodes appends to the columns, so the originals' indices stay valid
+        let mut copies: HashMap<NodeId, NodeId> = HashMap::with_capacity(originals.len());
+        for &original in &originals {
+            let original_id = self.node_ids[original];
+            let parent = match self.parents[original] {
+                NO_PARENT => None,
+                parent => {
+                    let parent_id = self.node_ids[parent as usize];
+                    Some(*copies.get(&parent_id).unwrap_or(&parent_id))
+                }
+            };
+            let copy = self.add_node(parent, self.local(original))?;
+            let copy_index = self.len() - 1;
+            self.mesh_ids[copy_index] = self.mesh_ids[original];
+            self.material_ids[copy_index] = self.material_ids[original];
+            self.layers[copy_index] = self.layers[original];
+            self.socket_offsets[copy_index] = self.socket_offsets[original];
+            if let Some(name) = self.names.get(&original_id).cloned() {
+                self.names.insert(copy, name);
+            }
+            if let Some(socket) = self.socket_names.get(&original_id).cloned() {
+                self.socket_names.insert(copy, socket);
+            }
+            if let Some(data) = self.data.get(&original_id).map(NodeData::duplicate) {
+                if !data.is_empty() {
+                    self.data.insert(copy, data);
+                }
+            }
+            copies.insert(original_id, copy);
+        }
+
+        for (original_id, copy) in &copies {
+            let Some(constraints) = self.constraints.get(original_id) else {
+                continue;
+            };
+            let constraints = constraints
+                .iter()
+                .map(|constraint| match constraint.source() {
+                    Some(source) if copies.contains_key(&source) => {
+                        constraint.with_source(copies[&source])
+                    }
+                    _ => *constraint,
+                })
+                .collect();
+            self.constraints.insert(*copy, constraints);
+        }
+        debug!("Duplicated {} scene nodes from {:?}", copies.len(), id);
+        Ok(copies[&id])
+    }
+
+    /// Moves a node (with its subtree) under a new parent, detaching it from any socket.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `SceneError::InvalidNode` if either node
+    /// does not exist, or `SceneError::CyclicParent` if the new parent is part
+    /// of the node's subtree.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        let parent_index = match parent {
+            Some(parent) => {
+                let parent_index = self.index(parent)?;
+                if self.is_ancestor_or_self(index, parent_index) {
+                    return Err(SceneError::CyclicParent { node: id, parent });
+                }
+                parent_index as u32
+            }
+            None => NO_PARENT,
+        };
+
+        self.parents[index] = parent_index;
+        self.socket_offsets[index] = None;
+        self.socket_names.remove(&id);
+        self.recompute_depths();
+        self.needs_sort = true;
+        Ok(())
+    }
+
+    /// Moves a node (with its subtree) under a new parent, placed relative
+    /// to a socket of the parent's mesh.
+    ///
+    /// The socket's transform is read when the node is attached, so later
+    /// changes to the parent's mesh don't move the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The node to attach.
+    /// * `parent` - The node whose mesh has the socket.
+    /// * `socket` - The name of the socket.
+    /// * `meshes` - The storage holding the parent's mesh.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `SceneError::MissingSocket` if the
+    /// parent's mesh has no such socket, or the errors of `set_parent`.
+    pub fn attach_to_socket(
+        &mut self,
+        id: NodeId,
+        parent: NodeId,
+        socket: &str,
+        meshes: &MeshStorage,
+    ) -> Result<(), SceneError> {
+        let parent_index = self.index(parent)?;
+        let offset = self.mesh_ids[parent_index]
+            .and_then(|mesh_id| meshes.get_mesh(mesh_id))
+            .and_then(|mesh| mesh.socket(socket))
+            .ok_or_else(|| SceneError::MissingSocket {
+                node: parent,
+                socket: socket.to_string(),
+            })?;
+        self.set_parent(id, Some(parent))?;
+        let index = self.index(id)?;
+        self.socket_offsets[index] = Some(offset);
+        self.socket_names.insert(id, socket.to_string());
+        Ok(())
+    }
+
+    /// Returns the name of the parent's socket a node is attached to.
+    pub fn socket(&self, id: NodeId) -> Option<&str> {
+        self.socket_names.get(&id).map(String::as_str)
+    }
+
+    /// Marks the nodes in the subtree rooted at an index, which requires the
+    /// columns to be sorted by depth.
+    fn subtree_mask(&self, index: usize) -> Vec<bool> {
+        // Parents precede children, so one forward pass finds the whole subtree
+        let mut subtree = vec![false; self.len()];
+        subtree[index] = true;
+        for i in index + 1..self.len() {
+            let parent = self.parents[i];
+            if parent != NO_PARENT && subtree[parent as usize] {
+                subtree[i] = true;
+            }
+        }
+        subtree
+    }
+
+    /// Returns `true` if `ancestor` is `node` or one of its ancestors.
+    fn is_ancestor_or_self(&self, ancestor: usize, mut node: usize) -> bool {
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            match self.parents[node] {
+                NO_PARENT => return false,
+                parent => node = parent as usize,
+            }
+        }
+    }
+
+    /// Recomputes every depth by walking parent links, which stays correct
+    /// while the columns are out of order after a reparent.
+    fn recompute_depths(&mut self) {
+        const UNKNOWN: u32 = u32::MAX;
+        let mut depths = vec![UNKNOWN; self.len()];
+        let mut chain = Vec::new();
+        for start in 0..self.len() {
+            // Walk up to a root or a node whose depth is known...
+            let mut node = start;
+            while depths[node] == UNKNOWN {
+                chain.push(node);
+                match self.parents[node] {
+                    NO_PARENT => break,
+                    parent => node = parent as usize,
+                }
+            }
+            // ...then assign depths on the way back down
+            while let Some(node) = chain.pop() {
+                depths[node] = match self.parents[node] {
+                    NO_PARENT => 0,
+                    parent => depths[parent as usize] + 1,
+                };
+            }
+        }
+        self.depths = depths;
+    }
+
+    /// Stably sorts the columns by depth and records the depth level ranges.
+    fn sort_by_depth(&mut self) {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.sort_by_key(|&i| self.depths[i]);
+        self.apply_permutation(&order);
+        self.needs_sort = false;
+        debug_trace!(
+            "Sorted SceneGraph into {} levels",
+            self.level_starts.len().saturating_sub(1)
+        );
+    }
+
+    /// Rearranges every column so that new index `i` holds old index `order[i]`.
+    ///
+    /// Indices missing from `order` are dropped.
+    fn apply_permutation(&mut self, order: &[usize]) {
+        let mut new_index = vec![NO_PARENT; self.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new as u32;
+        }
+
+        fn permute<T: Copy>(column: &mut Vec<T>, order: &[usize]) {
+            *column = order.iter().map(|&i| column[i]).collect();
+        }
+
+        permute(&mut self.node_ids, order);
+        permute(&mut self.depths, order);
+        permute(&mut self.translations, order);
+        permute(&mut self.rotations, order);
+        permute(&mut self.scales, order);
+        permute(&mut self.world_matrices, order);
+        permute(&mut self.mesh_ids, order);
+        permute(&mut self.material_ids, order);
+        permute(&mut self.layers, order);
+        permute(&mut self.outlines, order);
+        permute(&mut self.socket_offsets, order);
+        self.parents = order
+            .iter()
+            .map(|&i| match self.parents[i] {
+                NO_PARENT => NO_PARENT,
+                parent => new_index[parent as usize],
+            })
+            .collect();
+
+        for (index, id) in self.node_ids.iter().enumerate() {
+            self.slots[id.0] = Some(index as u32);
+        }
+
+        self.level_starts.clear();
+        for (i, &depth) in self.depths.iter().enumerate() {
+            if i == 0 || depth != self.depths[i - 1] {
+                self.level_starts.push(i);
+            }
+        }
+        self.level_starts.push(self.len());
+    }
+
+    /// Recomputes the world matrix of every node from the local transforms.
+    pub fn update_world_transforms(&mut self) {
+        if self.needs_sort {
+            self.sort_by_depth();
+        }
+
+        #[cfg(not(feature = "parallel"))]
+        for i in 0..self.len() {
+            let local = self.local_matrix(i);
+            self.world_matrices[i] = match self.parents[i] {
+                NO_PARENT => local,
+                parent => self.world_matrices[parent as usize] * local,
+            };
+        }
+
+        #[cfg(feature = "parallel")]
+        for level in self.level_starts.windows(2) {
+            // Every parent lives in an earlier level, so it is already up to date
+            let (parents, current) = self.world_matrices.split_at_mut(level[0]);
+            let range = level[0]..level[1];
+            current[..range.len()]
+                .par_iter_mut()
+                .zip(&self.parents[range.clone()])
+                .zip(&self.translations[range.clone()])
+                .zip(&self.rotations[range.clone()])
+                .zip(&self.scales[range.clone()])
+                .zip(&self.socket_offsets[range])
+                .for_each(
+                    |(((((world, &parent), &translation), &rotation), &scale), &socket)| {
+                        let mut local = Transform::new(translation, rotation, scale).to_mat4();
+                        if let Some(socket) = socket {
+                            local = socket * local;
+                        }
+                        *world = match parent {
+                            NO_PARENT => local,
+                            parent => parents[parent as usize] * local,
+                        };
+                    },
+                );
+        }
+
+        self.solve_constraints();
+    }
+
+    /// Adds a constraint to a node, applied after its earlier constraints.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, `SceneError::InvalidNode` if the node
+    /// or the constraint's source does not exist, or
+    /// `SceneError::CyclicConstraint` if the source depends on the node.
+    pub fn add_constraint(&mut self, id: NodeId, constraint: Constraint) -> Result<(), SceneError> {
+        self.index(id)?;
+        if let Some(source) = constraint.source() {
+            self.index(source)?;
+            if self.depends_on(source, id) {
+                return Err(SceneError::CyclicConstraint { node: id, source });
+            }
+        }
+        self.constraints.entry(id).or_default().push(constraint);
+        Ok(())
+    }
+
+    /// Returns the constraints of a node, in the order they apply.
+    pub fn constraints(&self, id: NodeId) -> &[Constraint] {
+        self.constraints.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Removes every constraint of a node.
+    pub fn clear_constraints(&mut self, id: NodeId) {
+        self.constraints.remove(&id);
+    }
+
+    /// Sets the camera position that billboards and camera targets use.
+    pub fn set_camera_position(&mut self, position: Vec3) {
+        self.camera_position = position;
+    }
+
+    /// Returns the nodes a node's world transform is computed from: its
+    /// parent and the sources of its constraints.
+    fn dependencies(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.parent(id).into_iter().chain(
+            self.constraints(id)
+                .iter()
+                .filter_map(|constraint| constraint.source()),
+        )
+    }
+
+    /// Returns `true` if `node`'s world transform depends on `dependency`, or they are the same node.
+    fn depends_on(&self, node: NodeId, dependency: NodeId) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![node];
+        while let Some(id) = stack.pop() {
+            if id == dependency {
+                return true;
+            }
+            if visited.insert(id) {
+                stack.extend(self.dependencies(id));
+            }
+        }
+        false
+    }
+
+    /// Returns the constrained nodes ordered so every node follows the
+    /// constrained nodes it depends on.
+    ///
+    /// Cycles, which reparenting can create, are broken at the node where
+    /// the search finds them.
+    fn constraint_order(&self) -> Vec<NodeId> {
+        let mut roots: Vec<NodeId> = self.constraints.keys().copied().collect();
+        roots.sort_by_key(|id| id.0);
+
+        // `false` while a node's dependencies are being visited, `true` once done
+        let mut visited: HashMap<NodeId, bool> = HashMap::new();
+        let mut order = Vec::with_capacity(roots.len());
+        for root in roots {
+            let mut stack = vec![(root, false)];
+            while let Some((id, expanded)) = stack.pop() {
+                if expanded {
+                    visited.insert(id, true);
+                    if self.constraints.contains_key(&id) {
+                        order.push(id);
+                    }
+                    continue;
+                }
+                if visited.contains_key(&id) {
+                    continue;
+                }
+                visited.insert(id, false);
+                stack.push((id, true));
+                stack.extend(
+                    self.dependencies(id)
+                        .filter(|dependency| !visited.contains_key(dependency))
+                        .map(|dependency| (dependency, false)),
+                );
+            }
+        }
+        order
+    }
+
+    /// Applies every node's constraints to its world matrix and moves its descendants with it.
+    fn solve_constraints(&mut self) {
+        if self.constraints.is_empty() {
+            return;
+        }
+        for id in self.constraint_order() {
+            let Ok(index) = self.index(id) else {
+                continue;
+            };
+            let world = self.constraints[&id].iter().fold(
+                self.world_matrices[index],
+                |world, constraint| {
+                    let source = constraint
+                        .source()
+                        .and_then(|source| self.world_transform(source));
+                    constraint.apply(world, self.camera_position, source)
+                },
+            );
+            self.world_matrices[index] = world;
+            self.update_descendants(index);
+        }
+    }
+
+    /// Recomputes the world matrices of the descendants of the node at a column index.
+    ///
+    /// The columns must be sorted by depth, so descendants follow the node.
+    fn update_descendants(&mut self, index: usize) {
+        let mut moved = vec![false; self.len()];
+        moved[index] = true;
+        for i in index + 1..self.len() {
+            let parent = self.parents[i];
+            if parent != NO_PARENT && moved[parent as usize] {
+                moved[i] = true;
+                self.world_matrices[i] =
+                    self.world_matrices[parent as usize] * self.local_matrix(i);
+            }
+        }
+    }
+
+    /// Returns the column index of a node.
+    fn index(&self, id: NodeId) -> Result<usize, SceneError> {
+        self.slots
+            .get(id.0)
+            .copied()
+            .flatten()
+            .map(|index| index as usize)
+            .ok_or(SceneError::InvalidNode(id))
+    }
+
+    /// Returns the transform of the node at a column index.
+    fn local(&self, index: usize) -> Transform {
+        Transform::new(
+            self.translations[index],
+            self.rotations[index],
+            self.scales[index],
+        )
+    }
+
+    /// Returns the matrix of the node at a column index relative to its
+    /// parent, including the socket it is attached to.
+    fn local_matrix(&self, index: usize) -> Mat4 {
+        let local = self.local(index).to_mat4();
+        match self.socket_offsets[index] {
+            Some(socket) => socket * local,
+            None => local,
+        }
+    }
+
+    /// Sets the transform of a node relative to its parent, as a
+    /// `Transform` or a `Mat4` without shear.
+    pub fn set_local_transform(
+        &mut self,
+        id: NodeId,
+        transform: impl Into<Transform>,
+    ) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        let transform = transform.into();
+        self.translations[index] = transform.translation;
+        self.rotations[index] = transform.rotation;
+        self.scales[index] = transform.scale;
+        Ok(())
+    }
+
+    /// Sets the translation of a node relative to its parent.
+    pub fn set_translation(&mut self, id: NodeId, translation: Vec3) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        self.translations[index] = translation;
+        Ok(())
+    }
+
+    /// Sets the rotation of a node relative to its parent.
+    pub fn set_rotation(&mut self, id: NodeId, rotation: Quat) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        self.rotations[index] = rotation;
+        Ok(())
+    }
+
+    /// Sets the scale of a node relative to its parent.
+    pub fn set_scale(&mut self, id: NodeId, scale: Vec3) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        self.scales[index] = scale;
+        Ok(())
+    }
+
+    /// Attaches a mesh and material to a node, or detaches it with `None`.
+    pub fn set_mesh(
+        &mut self,
+        id: NodeId,
+        mesh_id: Option<usize>,
+        material_id: MaterialId,
+    ) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        self.mesh_ids[index] = mesh_id;
+        self.material_ids[index] = material_id;
+        Ok(())
+    }
+
+    /// Returns the mesh attached to a node.
+    pub fn mesh(&self, id: NodeId) -> Option<usize> {
+        self.index(id).ok().and_then(|index| self.mesh_ids[index])
+    }
+
+    /// Sets the visibility layers of a node.
+    ///
+    /// Layers are not inherited; each node is tested against a camera's
+    /// cull mask on its own.
+    pub fn set_layers(&mut self, id: NodeId, layers: LayerMask) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        self.layers[index] = layers;
+        Ok(())
+    }
+
+    /// Returns the visibility layers of a node.
+    pub fn layers(&self, id: NodeId) -> Option<LayerMask> {
+        self.index(id).ok().map(|index| self.layers[index])
+    }
+
+    /// Changes the material of a node's mesh.
+    pub fn set_material(&mut self, id: NodeId, material_id: MaterialId) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        self.material_ids[index] = material_id;
+        Ok(())
+    }
+
+    /// Returns the material of a node's mesh.
+    pub fn material(&self, id: NodeId) -> Option<MaterialId> {
+        self.index(id).ok().map(|index| self.material_ids[index])
+    }
+
+    /// Highlights a node's mesh with an outline, or removes it with `None`.
+    pub fn set_outline(&mut self, id: NodeId, outline: Option<Outline>) -> Result<(), SceneError> {
+        let index = self.index(id)?;
+        self.outlines[index] = outline;
+        Ok(())
+    }
+
+    /// Returns the outline of a node.
+    pub fn outline(&self, id: NodeId) -> Option<Outline> {
+        self.index(id).ok().and_then(|index| self.outlines[index])
+    }
+
+    /// Names a node, or clears its name with `None`.
+    pub fn set_name(&mut self, id: NodeId, name: Option<&str>) -> Result<(), SceneError> {
+        self.index(id)?;
+        match name {
+            Some(name) => self.names.insert(id, name.to_string()),
+            None => self.names.remove(&id),
+        };
+        Ok(())
+    }
+
+    /// Returns the name of a node, if it has one.
+    pub fn name(&self, id: NodeId) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+
+    /// Returns the first node with a name.
+    pub fn find_by_name(&self, name: &str) -> Option<NodeId> {
+        self.tree_order()
+            .into_iter()
+            .map(|(id, _)| id)
+            .find(|id| self.name(*id) == Some(name))
+    }
+
+    /// Attaches a value to a node, replacing its value of the same type.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the replaced value, if the node held one, or
+    /// `SceneError::InvalidNode`.
+    pub fn insert_data<T: Any>(&mut self, id: NodeId, value: T) -> Result<Option<T>, SceneError> {
+        self.index(id)?;
+        Ok(self.data.entry(id).or_default().insert(value))
+    }
+
+    /// Attaches a value to a node that is copied when the node is
+    /// duplicated, replacing its value of the same type.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the replaced value, if the node held one, or
+    /// `SceneError::InvalidNode`.
+    pub fn insert_cloneable_data<T: Any + Clone>(
+        &mut self,
+        id: NodeId,
+        value: T,
+    ) -> Result<Option<T>, SceneError> {
+        self.index(id)?;
+        Ok(self.data.entry(id).or_default().insert_cloneable(value))
+    }
+
+    /// Returns a node's value of a type.
+    pub fn get_data<T: Any>(&self, id: NodeId) -> Option<&T> {
+        self.data.get(&id)?.get()
+    }
+
+    /// Returns a node's value of a type mutably.
+    pub fn get_data_mut<T: Any>(&mut self, id: NodeId) -> Option<&mut T> {
+        self.data.get_mut(&id)?.get_mut()
+    }
+
+    /// Detaches and returns a node's value of a type.
+    pub fn remove_data<T: Any>(&mut self, id: NodeId) -> Option<T> {
+        let data = self.data.get_mut(&id)?;
+        let value = data.remove();
+        if data.is_empty() {
+            self.data.remove(&id);
+        }
+        value
+    }
+
+    /// Returns every value attached to a node.
+    pub fn node_data(&self, id: NodeId) -> Option<&NodeData> {
+        self.data.get(&id)
+    }
+
+    /// Sets the callback called with each node added to the graph.
+    pub fn set_on_node_added<F>(&mut self, callback: F)
+    where
+        F: FnMut(NodeId) + 'static,
+    {
+        self.on_added = Some(Box::new(callback));
+    }
+
+    /// Sets the callback called with each node removed from the graph.
+    ///
+    /// Removing a node also removes its descendants, which are reported
+    /// first. The callback receives the values that were attached to the
+    /// node, so state living outside the graph can be torn down with it.
+    pub fn set_on_node_removed<F>(&mut self, callback: F)
+    where
+        F: FnMut(NodeId, NodeData) + 'static,
+    {
+        self.on_removed = Some(Box::new(callback));
+    }
+
+    /// Returns the transform of a node relative to its parent.
+    pub fn local_transform(&self, id: NodeId) -> Option<Transform> {
+        self.index(id).ok().map(|index| self.local(index))
+    }
+
+    /// Returns the world matrix of a node as of the last `update_world_transforms`.
+    pub fn world_transform(&self, id: NodeId) -> Option<Mat4> {
+        self.index(id).ok().map(|index| self.world_matrices[index])
+    }
+
+    /// Returns the world-space bounding box of a node's mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The node to query.
+    /// * `meshes` - The storage holding the node's mesh.
+    ///
+    /// # Returns
+    ///
+    /// The bounding box as of the last `update_world_transforms`, or `None`
+    /// if the node does not exist or has no mesh.
+    pub fn world_bounds(&self, id: NodeId, meshes: &MeshStorage) -> Option<Aabb> {
+        let index = self.index(id).ok()?;
+        let mesh = meshes.get_mesh(self.mesh_ids[index]?)?;
+        Some(mesh.bounds.transformed(&self.world_matrices[index]))
+    }
+
+    /// Returns the world-space bounding sphere of a node's mesh.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The node to query.
+    /// * `meshes` - The storage holding the node's mesh.
+    ///
+    /// # Returns
+    ///
+    /// The bounding sphere as of the last `update_world_transforms`, or
+    /// `None` if the node does not exist or has no mesh.
+    pub fn world_bounding_sphere(
+        &self,
+        id: NodeId,
+        meshes: &MeshStorage,
+    ) -> Option<BoundingSphere> {
+        let index = self.index(id).ok()?;
+        let mesh = meshes.get_mesh(self.mesh_ids[index]?)?;
+        Some(
+            mesh.bounding_sphere
+                .transformed(&self.world_matrices[index]),
+        )
+    }
+
+    /// Returns the parent of a node.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        let index = self.index(id).ok()?;
+        match self.parents[index] {
+            NO_PARENT => None,
+            parent => Some(self.node_ids[parent as usize]),
+        }
+    }
+
+    /// Returns the direct children of a node.
+    pub fn children(&self, id: NodeId) -> Vec<NodeId> {
+        let Ok(index) = self.index(id) else {
+            return Vec::new();
+        };
+        self.parents
+            .iter()
+            .enumerate()
+            .filter(|(_, &parent)| parent == index as u32)
+            .map(|(child, _)| self.node_ids[child])
+            .collect()
+    }
+
+    /// Returns the IDs of all nodes, in no particular order.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.node_ids.iter().copied()
+    }
+
+    /// Returns `true` if the node exists.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.index(id).is_ok()
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.node_ids.len()
+    }
+
+    /// Returns `true` if the graph has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.node_ids.is_empty()
+    }
+
+    /// Returns every node in tree order with its number of ancestors.
+    ///
+    /// Nodes follow their parents depth-first, and siblings are ordered by ID.
+    pub fn tree_order(&self) -> Vec<(NodeId, usize)> {
+        let mut children: HashMap<u32, Vec<NodeId>> = HashMap::new();
+        for (&parent, &id) in self.parents.iter().zip(&self.node_ids) {
+            children.entry(parent).or_default().push(id);
+        }
+        for siblings in children.values_mut() {
+            siblings.sort_by_key(|id| std::cmp::Reverse(id.0));
+        }
+
+        let mut order = Vec::with_capacity(self.len());
+        let mut stack: Vec<(NodeId, usize)> = children
+            .remove(&NO_PARENT)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| (id, 0))
+            .collect();
+        while let Some((id, depth)) = stack.pop() {
+            order.push((id, depth));
+            let index = self.slots[id.0].unwrap_or(NO_PARENT);
+            if let Some(siblings) = children.get(&index) {
+                stack.extend(siblings.iter().map(|&child| (child, depth + 1)));
+            }
+        }
+        order
+    }
+
+    /// Summarizes the nodes of the graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `cull_mask` - The layers the camera draws, counting the visible nodes.
+    ///
+    /// # Returns
+    ///
+    /// The node, root, mesh and visible counts and the deepest nesting.
+    pub fn stats(&self, cull_mask: LayerMask) -> SceneStats {
+        let mesh_nodes: Vec<_> = self.mesh_nodes().collect();
+        SceneStats {
+            nodes: self.len(),
+            roots: self.parents.iter().filter(|&&p| p == NO_PARENT).count(),
+            max_depth: self.depths.iter().max().map_or(0, |&depth| depth as usize),
+            mesh_nodes: mesh_nodes.len(),
+            unique_meshes: mesh_nodes
+                .iter()
+                .map(|node| node.mesh_id)
+                .collect::<HashSet<_>>()
+                .len(),
+            visible: mesh_nodes
+                .iter()
+                .filter(|node| node.layers.intersects(cull_mask))
+                .count(),
+        }
+    }
+
+    /// Prints the hierarchy, one node per line in tree order, indented by depth.
+    ///
+    /// Each line holds the node's name and ID, its local translation,
+    /// rotation as XYZ Euler angles in degrees and scale, and its mesh and
+    /// material if it has a mesh.
+    pub fn dump_tree(&self) -> String {
+        let mut tree = String::new();
+        for (id, depth) in self.tree_order() {
+            let index = self.slots[id.0].unwrap_or_default() as usize;
+            let indent = "  ".repeat(depth);
+            let _ = match self.name(id) {
+                Some(name) => write!(tree, "{indent}{name} #{}", id.0),
+                None => write!(tree, "{indent}#{}", id.0),
+            };
+
+            let Transform {
+                translation,
+                rotation,
+                scale,
+            } = self.local(index);
+            let (x, y, z) = rotation.to_euler(EulerRot::XYZ);
+            // Adding zero turns negative zeros positive, keeping the output stable
+            let degrees = Vec3::new(x, y, z) * (180.0 / std::f32::consts::PI) + Vec3::ZERO;
+            let _ = write!(
+                tree,
+                " t=[{:.3}, {:.3}, {:.3}] r=[{:.1}, {:.1}, {:.1}] s=[{:.3}, {:.3}, {:.3}]",
+                translation.x,
+                translation.y,
+                translation.z,
+                degrees.x,
+                degrees.y,
+                degrees.z,
+                scale.x,
+                scale.y,
+                scale.z
+            );
+            if let Some(mesh_id) = self.mesh_ids[index] {
+                let _ = write!(
+                    tree,
+                    " mesh={mesh_id} material={}",
+                    self.material_ids[index].0
+                );
+            }
+            if let Some(socket) = self.socket(id) {
+                let _ = write!(tree, " socket={socket}");
+            }
+            tree.push('\n');
+        }
+        tree
+    }
+
+    /// Returns every node with a mesh.
+    pub fn mesh_nodes(&self) -> impl Iterator<Item = MeshNode<'_>> + '_ {
+        self.mesh_ids
+            .iter()
+            .enumerate()
+            .filter_map(|(index, mesh_id)| {
+                mesh_id.map(|mesh_id| MeshNode {
+                    id: self.node_ids[index],
+                    mesh_id,
+                    material_id: self.material_ids[index],
+                    layers: self.layers[index],
+                    outline: self.outlines[index],
+                    world: &self.world_matrices[index],
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeId, SceneGraph, SceneStats};
+    use crate::renderer::{
+        common::{LayerMask, PrimitiveType},
+        constraints::Constraint,
+        error::SceneError,
+        material_manager::MaterialId,
+        mesh::MeshStorage,
+        shape_builders::MeshBuilder,
+    };
+    use glam::{Mat4, Vec3};
+    use std::{cell::RefCell, rc::Rc};
+
+    fn translation(x: f32) -> Mat4 {
+        Mat4::from_translation(Vec3::new(x, 0.0, 0.0))
+    }
+
+    fn world_x(graph: &SceneGraph, id: NodeId) -> f32 {
+        graph.world_transform(id).unwrap().w_axis.x
+    }
+
+    #[test]
+    fn test_scene_graph_world_transforms() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(None, translation(1.0)).unwrap();
+        let child = graph.add_node(Some(root), translation(2.0)).unwrap();
+        let grandchild = graph.add_node(Some(child), translation(3.0)).unwrap();
+        let other_root = graph.add_node(None, translation(10.0)).unwrap();
+
+        graph.update_world_transforms();
+        assert_eq!(world_x(&graph, root), 1.0);
+        assert_eq!(world_x(&graph, child), 3.0);
+        assert_eq!(world_x(&graph, grandchild), 6.0);
+        assert_eq!(world_x(&graph, other_root), 10.0);
+        assert_eq!(graph.children(root), vec![child]);
+        assert_eq!(graph.parent(grandchild), Some(child));
+    }
+
+    #[test]
+    fn test_scene_graph_set_parent() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, translation(1.0)).unwrap();
+        let b = graph.add_node(None, translation(5.0)).unwrap();
+        let c = graph.add_node(Some(b), translation(1.0)).unwrap();
+
+        // Moving `b` under `a` must also move its child
+        graph.set_parent(b, Some(a)).unwrap();
+        graph.update_world_transforms();
+        assert_eq!(world_x(&graph, c), 7.0);
+
+        // A node cannot become a child of its own descendant
+        assert!(matches!(
+            graph.set_parent(a, Some(c)),
+            Err(SceneError::CyclicParent { .. })
+        ));
+    }
+
+    #[test]
+    fn test_scene_graph_remove_subtree() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let child = graph.add_node(Some(root), Mat4::IDENTITY).unwrap();
+        graph.add_node(Some(child), Mat4::IDENTITY).unwrap();
+        let sibling = graph.add_node(Some(root), translation(4.0)).unwrap();
+
+        graph.remove_node(child).unwrap();
+        assert_eq!(graph.len(), 2);
+        assert!(!graph.contains(child));
+        assert!(graph.set_translation(child, Vec3::ZERO).is_err());
+
+        graph.update_world_transforms();
+        assert_eq!(world_x(&graph, sibling), 4.0);
+    }
+
+    #[test]
+    fn test_scene_graph_duplicate_subtree() {
+        let mut graph = SceneGraph::new();
+        let parent = graph.add_node(None, translation(1.0)).unwrap();
+        let root = graph.add_node(Some(parent), translation(2.0)).unwrap();
+        let child = graph.add_node(Some(root), translation(3.0)).unwrap();
+        graph.set_mesh(child, Some(4), MaterialId(2)).unwrap();
+        graph.set_name(child, Some("wheel")).unwrap();
+        graph.insert_cloneable_data(child, "spinning").unwrap();
+        graph.insert_data(child, 5_u32).unwrap();
+        graph
+            .add_constraint(child, Constraint::look_at(root))
+            .unwrap();
+        graph
+            .add_constraint(root, Constraint::look_at(parent))
+            .unwrap();
+
+        let copy = graph.duplicate(root, true).unwrap();
+        assert_eq!(graph.len(), 5);
+        assert_eq!(graph.parent(copy), Some(parent));
+        let [child_copy] = graph.children(copy)[..] else {
+            panic!("expected one child");
+        };
+        graph.update_world_transforms();
+        assert_eq!(world_x(&graph, child_copy), world_x(&graph, child));
+        assert_eq!(
+            graph.mesh_nodes().filter(|node| node.mesh_id == 4).count(),
+            2
+        );
+        assert_eq!(graph.material(child_copy), Some(MaterialId(2)));
+        assert_eq!(graph.name(child_copy), Some("wheel"));
+        assert_eq!(graph.get_data::<&str>(child_copy), Some(&"spinning"));
+        assert_eq!(graph.get_data::<u32>(child_copy), None);
+        // Constraints within the subtree follow the copies
+        assert_eq!(graph.constraints(child_copy), [Constraint::look_at(copy)]);
+        assert_eq!(graph.constraints(copy), [Constraint::look_at(parent)]);
+
+        let single = graph.duplicate(root, false).unwrap();
+        assert!(graph.children(single).is_empty());
+        assert!(graph.duplicate(NodeId(42), true).is_err());
+    }
+
+    #[test]
+    fn test_scene_graph_stats_and_dump() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(None, translation(1.0)).unwrap();
+        let child = graph.add_node(Some(root), Mat4::IDENTITY).unwrap();
+        let ui = graph.add_node(Some(child), Mat4::IDENTITY).unwrap();
+        graph.add_node(None, Mat4::IDENTITY).unwrap();
+        graph.set_mesh(child, Some(3), MaterialId(1)).unwrap();
+        graph.set_mesh(ui, Some(3), MaterialId::DEFAULT).unwrap();
+        graph.set_layers(ui, LayerMask::UI).unwrap();
+        graph.set_name(root, Some("ship")).unwrap();
+        assert_eq!(graph.find_by_name("ship"), Some(root));
+
+        assert_eq!(
+            graph.stats(LayerMask::DEFAULT),
+            SceneStats {
+                nodes: 4,
+                roots: 2,
+                max_depth: 2,
+                mesh_nodes: 2,
+                unique_meshes: 1,
+                visible: 1,
+            }
+        );
+        let dump = graph.dump_tree();
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(
+            lines[0],
+            "ship #0 t=[1.000, 0.000, 0.000] r=[0.0, 0.0, 0.0] s=[1.000, 1.000, 1.000]"
+        );
+        assert!(lines[1].starts_with("  #1 ") && lines[1].ends_with(" mesh=3 material=1"));
+        assert!(lines[2].starts_with("    #2 "));
+        assert!(lines[3].starts_with("#3 "));
+
+        // Names are dropped with their nodes
+        graph.remove_node(root).unwrap();
+        assert_eq!(graph.find_by_name("ship"), None);
+        assert_eq!(graph.stats(LayerMask::ALL).max_depth, 0);
+    }
+
+    #[test]
+    fn test_scene_graph_data_and_callbacks() {
+        let mut graph = SceneGraph::new();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let added = events.clone();
+        graph.set_on_node_added(move |id| added.borrow_mut().push(format!("added {}", id.0)));
+        let removed = events.clone();
+        graph.set_on_node_removed(move |id, data| {
+            let label = data.get::<&str>().copied().unwrap_or("unlabeled");
+            removed
+                .borrow_mut()
+                .push(format!("removed {} {label}", id.0));
+        });
+
+        let root = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let child = graph.add_node(Some(root), Mat4::IDENTITY).unwrap();
+        graph.insert_data(root, "ship").unwrap();
+        graph.insert_data(child, "engine").unwrap();
+        graph.insert_data(child, 100_u32).unwrap();
+        *graph.get_data_mut::<u32>(child).unwrap() -= 40;
+        assert_eq!(graph.get_data::<u32>(child), Some(&60));
+        assert_eq!(graph.remove_data::<u32>(child), Some(60));
+        assert!(graph.insert_data(NodeId(9), 1_u32).is_err());
+
+        graph.remove_node(root).unwrap();
+        assert_eq!(
+            *events.borrow(),
+            ["added 0", "added 1", "removed 1 engine", "removed 0 ship"]
+        );
+        assert!(graph.node_data(root).is_none());
+    }
+
+    #[test]
+    fn test_scene_graph_constraints() {
+        let mut graph = SceneGraph::new();
+        let target = graph.add_node(None, translation(10.0)).unwrap();
+        // Added before its source's constraint, so it is solved out of insertion order
+        let follower = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let leader = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let child = graph.add_node(Some(leader), translation(1.0)).unwrap();
+        graph
+            .add_constraint(
+                follower,
+                Constraint::CopyPosition {
+                    source: child,
+                    offset: Vec3::Y,
+                },
+            )
+            .unwrap();
+        graph
+            .add_constraint(
+                leader,
+                Constraint::CopyPosition {
+                    source: target,
+                    offset: Vec3::ZERO,
+                },
+            )
+            .unwrap();
+
+        // The child moves with its constrained parent, and the follower sees both
+        graph.update_world_transforms();
+        assert_eq!(world_x(&graph, child), 11.0);
+        assert_eq!(
+            graph.world_transform(follower).unwrap().w_axis.truncate(),
+            Vec3::new(11.0, 1.0, 0.0)
+        );
+
+        // Constraining a node to something depending on it is rejected
+        assert_eq!(
+            graph.add_constraint(target, Constraint::look_at(follower)),
+            Err(SceneError::CyclicConstraint {
+                node: target,
+                source: follower,
+            })
+        );
+        assert!(graph
+            .add_constraint(leader, Constraint::look_at(child))
+            .is_err());
+        graph.remove_node(leader).unwrap();
+        graph.update_world_transforms();
+        assert_eq!(graph.constraints(follower).len(), 1);
+    }
+
+    #[test]
+    fn test_scene_graph_sockets() {
+        let mut meshes = MeshStorage::new();
+        let hull = meshes.add_mesh(
+            MeshBuilder::new(Vec::new(), PrimitiveType::Triangle)
+                .with_socket("thruster", translation(-2.0)),
+        );
+        let mut graph = SceneGraph::new();
+        let ship = graph.add_node(None, translation(10.0)).unwrap();
+        graph
+            .set_mesh(ship, Some(hull), MaterialId::DEFAULT)
+            .unwrap();
+        let flame = graph.add_node(None, translation(-0.5)).unwrap();
+
+        assert_eq!(
+            graph.attach_to_socket(flame, ship, "turret", &meshes),
+            Err(SceneError::MissingSocket {
+                node: ship,
+                socket: "turret".to_string(),
+            })
+        );
+        graph
+            .attach_to_socket(flame, ship, "thruster", &meshes)
+            .unwrap();
+        graph.update_world_transforms();
+        assert_eq!(world_x(&graph, flame), 7.5);
+        assert_eq!(graph.socket(flame), Some("thruster"));
+        assert!(graph
+            .dump_tree()
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with(" socket=thruster"));
+
+        // Reparenting detaches the node from the socket
+        graph.set_parent(flame, Some(ship)).unwrap();
+        graph.update_world_transforms();
+        assert_eq!(world_x(&graph, flame), 9.5);
+        assert_eq!(graph.socket(flame), None);
+    }
+}
@@ -0,0 +1,413 @@
+//! Selection module for the renderer.
+//!
+//! This module provides `Selection`, the set of selected scene nodes and the
+//! node under the pointer, shared by the editor, gizmos and tools. Nodes are
+//! usually chosen by picking, casting a ray from the camera through a point
+//! on the screen with `Renderer::pick`. Selected nodes are highlighted with
+//! an outline, and the hovered node with a fainter one, through the scene
+//! graph's per-node outlines, so the selection replaces any outline set on
+//! those nodes. Every change is reported as a `SelectionEvent`, which tools
+//! take after handling input to react to it.
+
+use super::{
+    error::SceneError,
+    render_state::Outline,
+    scene_graph::{NodeId, SceneGraph},
+    Color,
+};
+use log::debug;
+
+/// How picking or selecting nodes changes the selection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// Selects only the nodes, deselecting everything else.
+    #[default]
+    Replace,
+    /// Adds the nodes to the selection.
+    Add,
+    /// Selects the nodes that aren't selected and deselects the rest.
+    Toggle,
+    /// Deselects the nodes.
+    Remove,
+}
+
+/// A change of the selection or hover.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionEvent {
+    Selected(NodeId),
+    Deselected(NodeId),
+    /// The pointer moved onto another node, or off every node with `None`.
+    Hovered(Option<NodeId>),
+}
+
+/// The selected and hovered scene nodes.
+#[derive(Clone, Debug)]
+pub struct Selection {
+    /// The selected nodes, in the order they were selected.
+    selected: Vec<NodeId>,
+    hovered: Option<NodeId>,
+    selection_outline: Outline,
+    hover_outline: Outline,
+    events: Vec<SelectionEvent>,
+}
+
+impl Selection {
+    /// Creates a new, empty `Selection` with orange selection outlines.
+    pub fn new() -> Self {
+        let orange = Color::from_srgb_u8(255, 153, 0, 255);
+        Self {
+            selected: Vec::new(),
+            hovered: None,
+            selection_outline: Outline::new(orange, 0.05),
+            hover_outline: Outline::new(orange.with_alpha(0.5), 0.025),
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns the selected nodes, in the order they were selected.
+    pub fn selected(&self) -> &[NodeId] {
+        &self.selected
+    }
+
+    /// Returns the most recently selected node, which single-node edits apply to.
+    pub fn primary(&self) -> Option<NodeId> {
+        self.selected.last().copied()
+    }
+
+    /// Returns `true` if a node is selected.
+    pub fn is_selected(&self, id: NodeId) -> bool {
+        self.selected.contains(&id)
+    }
+
+    /// Returns the node under the pointer.
+    pub fn hovered(&self) -> Option<NodeId> {
+        self.hovered
+    }
+
+    /// Returns `true` if no node is selected.
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    /// Sets the outline highlighting selected nodes.
+    pub fn set_selection_outline(&mut self, graph: &mut SceneGraph, outline: Outline) {
+        self.selection_outline = outline;
+        self.refresh_all(graph);
+    }
+
+    /// Sets the outline highlighting the hovered node, if it isn't selected.
+    pub fn set_hover_outline(&mut self, graph: &mut SceneGraph, outline: Outline) {
+        self.hover_outline = outline;
+        self.refresh_all(graph);
+    }
+
+    /// Changes the selection by a picked node.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - The scene graph the nodes belong to.
+    /// * `id` - The picked node, or `None` if nothing was picked, which
+    ///   clears the selection when replacing it.
+    /// * `mode` - How the node changes the selection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `SceneError::InvalidNode`.
+    pub fn select(
+        &mut self,
+        graph: &mut SceneGraph,
+        id: Option<NodeId>,
+        mode: SelectionMode,
+    ) -> Result<(), SceneError> {
+        self.select_many(graph, id, mode)
+    }
+
+    /// Changes the selection by several nodes, e.g. those inside a box.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - The scene graph the nodes belong to.
+    /// * `ids` - The nodes.
+    /// * `mode` - How the nodes change the selection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or `SceneError::InvalidNode`, in which
+    /// case the selection is unchanged.
+    pub fn select_many(
+        &mut self,
+        graph: &mut SceneGraph,
+        ids: impl IntoIterator<Item = NodeId>,
+        mode: SelectionMode,
+    ) -> Result<(), SceneError> {
+        let ids: Vec<NodeId> = ids.into_iter().collect();
+        if let Some(&missing) = ids.iter().find(|&&id| !graph.contains(id)) {
+            return Err(SceneError::InvalidNode(missing));
+        }
+        if mode == SelectionMode::Replace {
+            let deselected: Vec<NodeId> = self
+                .selected
+                .iter()
+                .copied()
+                .filter(|id| !ids.contains(id))
+                .collect();
+            for id in deselected {
+                self.deselect(graph, id);
+            }
+        }
+        for id in ids {
+            match mode {
+                SelectionMode::Replace | SelectionMode::Add => self.add(graph, id),
+                SelectionMode::Toggle if self.is_selected(id) => self.deselect(graph, id),
+                SelectionMode::Toggle => self.add(graph, id),
+                SelectionMode::Remove => self.deselect(graph, id),
+            }
+        }
+        debug!("Selection: {:?}", self.selected);
+        Ok(())
+    }
+
+    /// Deselects every node.
+    pub fn clear(&mut self, graph: &mut SceneGraph) {
+        let _ = self.select_many(graph, [], SelectionMode::Replace);
+    }
+
+    /// Sets the node under the pointer, or `None` if it is over no node.
+    pub fn set_hovered(&mut self, graph: &mut SceneGraph, id: Option<NodeId>) {
+        let id = id.filter(|&id| graph.contains(id));
+        if id == self.hovered {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.hovered, id);
+        if let Some(previous) = previous {
+            self.refresh(graph, previous);
+        }
+        if let Some(id) = id {
+            self.refresh(graph, id);
+        }
+        self.events.push(SelectionEvent::Hovered(id));
+    }
+
+    /// Forgets selected and hovered nodes that were removed from the graph.
+    pub fn retain_existing(&mut self, graph: &SceneGraph) {
+        let events = &mut self.events;
+        self.selected.retain(|&id| {
+            let exists = graph.contains(id);
+            if !exists {
+                events.push(SelectionEvent::Deselected(id));
+            }
+            exists
+        });
+        if self.hovered.is_some_and(|id| !graph.contains(id)) {
+            self.hovered = None;
+            self.events.push(SelectionEvent::Hovered(None));
+        }
+    }
+
+    /// Returns the changes since the events were last taken, oldest first.
+    pub fn take_events(&mut self) -> Vec<SelectionEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn add(&mut self, graph: &mut SceneGraph, id: NodeId) {
+        // Reselecting a node makes it the primary selection
+        if let Some(position) = self.selected.iter().position(|&selected| selected == id) {
+            self.selected.remove(position);
+            self.selected.push(id);
+            return;
+        }
+        self.selected.push(id);
+        self.refresh(graph, id);
+        self.events.push(SelectionEvent::Selected(id));
+    }
+
+    fn deselect(&mut self, graph: &mut SceneGraph, id: NodeId) {
+        let count = self.selected.len();
+        self.selected.retain(|&selected| selected != id);
+        if self.selected.len() != count {
+            self.refresh(graph, id);
+            self.events.push(SelectionEvent::Deselected(id));
+        }
+    }
+
+    /// Sets a node's outline to match whether it is selected or hovered.
+    fn refresh(&self, graph: &mut SceneGraph, id: NodeId) {
+        let outline = if self.is_selected(id) {
+            Some(self.selection_outline)
+        } else if self.hovered == Some(id) {
+            Some(self.hover_outline)
+        } else {
+            None
+        };
+        // The node may have been removed from the graph
+        let _ = graph.set_outline(id, outline);
+    }
+
+    fn refresh_all(&self, graph: &mut SceneGraph) {
+        for &id in self.selected.iter().chain(&self.hovered) {
+            self.refresh(graph, id);
+        }
+    }
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Selection, SelectionEvent, SelectionMode};
+    use crate::renderer::{render_state::Outline, scene_graph::SceneGraph, Color};
+    use glam::Mat4;
+
+    #[test]
+    fn test_selection_modes_and_events() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let b = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let c = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let mut selection = Selection::new();
+
+        selection
+            .select(&mut graph, Some(a), SelectionMode::Replace)
+            .unwrap();
+        selection
+            .select_many(&mut graph, [b, c], SelectionMode::Add)
+            .unwrap();
+        selection
+            .select(&mut graph, Some(a), SelectionMode::Toggle)
+            .unwrap();
+        assert_eq!(selection.selected(), [b, c]);
+        assert_eq!(selection.primary(), Some(c));
+        assert!(graph.outline(a).is_none());
+        assert!(graph.outline(b).is_some());
+        assert_eq!(
+            selection.take_events(),
+            [
+                SelectionEvent::Selected(a),
+                SelectionEvent::Selected(b),
+                SelectionEvent::Selected(c),
+                SelectionEvent::Deselected(a),
+            ]
+        );
+
+        // Hovering an unselected node gives it the fainter outline
+        selection.set_hovered(&mut graph, Some(a));
+        let hover = graph.outline(a).unwrap();
+        assert!(hover.width < graph.outline(b).unwrap().width);
+        selection.set_hovered(&mut graph, Some(a));
+        assert_eq!(selection.take_events(), [SelectionEvent::Hovered(Some(a))]);
+
+        // Picking nothing clears the selection, but keeps the hover
+        selection
+            .select(&mut graph, None, SelectionMode::Replace)
+            .unwrap();
+        assert!(selection.is_empty());
+        assert!(graph.outline(c).is_none());
+        assert_eq!(graph.outline(a), Some(hover));
+
+        // Removed nodes are forgotten, and invalid nodes leave the selection unchanged
+        selection
+            .select(&mut graph, Some(b), SelectionMode::Add)
+            .unwrap();
+        graph.remove_node(b).unwrap();
+        graph.remove_node(a).unwrap();
+        selection.take_events();
+        selection.retain_existing(&graph);
+        assert_eq!(
+            selection.take_events(),
+            [SelectionEvent::Deselected(b), SelectionEvent::Hovered(None)]
+        );
+        assert!(selection
+            .select_many(&mut graph, [c, b], SelectionMode::Add)
+            .is_err());
+        assert!(selection.is_empty());
+    }
+
+    #[test]
+    fn test_selection_replace_and_remove() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let b = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let c = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let mut selection = Selection::new();
+        selection
+            .select_many(&mut graph, [a, b], SelectionMode::Replace)
+            .unwrap();
+        selection.take_events();
+
+        // Replacing keeps the nodes in both selections without reporting them
+        selection
+            .select_many(&mut graph, [b, c], SelectionMode::Replace)
+            .unwrap();
+        assert_eq!(selection.selected(), [b, c]);
+        assert_eq!(
+            selection.take_events(),
+            [SelectionEvent::Deselected(a), SelectionEvent::Selected(c)]
+        );
+
+        // Reselecting a node makes it the primary selection, quietly
+        selection
+            .select(&mut graph, Some(b), SelectionMode::Add)
+            .unwrap();
+        assert_eq!(selection.primary(), Some(b));
+        assert!(selection.take_events().is_empty());
+
+        // Removing a node that isn't selected does nothing
+        selection
+            .select_many(&mut graph, [a, b], SelectionMode::Remove)
+            .unwrap();
+        assert_eq!(selection.selected(), [c]);
+        assert!(graph.outline(b).is_none());
+        assert_eq!(selection.take_events(), [SelectionEvent::Deselected(b)]);
+
+        selection.clear(&mut graph);
+        assert!(selection.is_empty());
+        assert!(graph.outline(c).is_none());
+        assert_eq!(selection.take_events(), [SelectionEvent::Deselected(c)]);
+    }
+
+    #[test]
+    fn test_selection_outlines_and_hover() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let b = graph.add_node(None, Mat4::IDENTITY).unwrap();
+        let mut selection = Selection::new();
+        let selected = Outline::new(Color::new(0.0, 1.0, 0.0, 1.0), 0.1);
+        let hovered = Outline::new(Color::new(0.0, 0.0, 1.0, 1.0), 0.02);
+        selection.set_selection_outline(&mut graph, selected);
+        selection.set_hover_outline(&mut graph, hovered);
+
+        // A selected node keeps the selection outline while hovered
+        selection
+            .select(&mut graph, Some(a), SelectionMode::Replace)
+            .unwrap();
+        selection.set_hovered(&mut graph, Some(a));
+        assert_eq!(graph.outline(a), Some(selected));
+        selection
+            .select(&mut graph, Some(a), SelectionMode::Toggle)
+            .unwrap();
+        assert_eq!(graph.outline(a), Some(hovered));
+
+        // Changing the outlines restyles the nodes already highlighted
+        let wider = Outline::new(Color::new(0.0, 0.0, 1.0, 1.0), 0.04);
+        selection.set_hover_outline(&mut graph, wider);
+        assert_eq!(graph.outline(a), Some(wider));
+
+        // Moving the pointer off a node removes its outline
+        selection.set_hovered(&mut graph, Some(b));
+        assert!(graph.outline(a).is_none());
+        assert_eq!(graph.outline(b), Some(wider));
+        selection.set_hovered(&mut graph, None);
+        assert!(graph.outline(b).is_none());
+
+        // Hovering a node that doesn't exist is hovering nothing
+        graph.remove_node(b).unwrap();
+        selection.take_events();
+        selection.set_hovered(&mut graph, Some(b));
+        assert_eq!(selection.hovered(), None);
+        assert!(selection.take_events().is_empty());
+    }
+}
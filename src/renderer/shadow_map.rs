@@ -0,0 +1,460 @@
+//! Shadow map module for the renderer.
+//!
+//! This module provides `CascadedShadows`, shadows cast by a directional
+//! light such as the sun into cascaded shadow maps. The camera's view frustum
+//! is split along its depth into up to `MAX_CASCADES` slices, nearer slices
+//! being shorter, and each slice gets its own shadow map drawn from the
+//! light's point of view, so nearby shadows are sharp while distant ones
+//! still fit. The split distances blend between even and logarithmic splits
+//! by the split lambda.
+//!
+//! Every frame, the renderer draws the depth of the shadow-casting mesh draws
+//! into one layer of a texture array per cascade. The backend then picks the
+//! cascade of every pixel from its view depth, filters its shadow map, and
+//! darkens the frame where the light is blocked, blending between cascades
+//! across their seams. Cascades are fitted around bounding spheres of the
+//! frustum slices and snapped to whole shadow map texels, so shadow edges
+//! keep still as the camera turns and moves.
+//!
+//! `ShadowCasters` draws the depth of a frame's shadow-casting draws into one
+//! shadow map, for the cascades here and for the shadow maps of point and
+//! spot lights.
+
+use super::{
+    backend::GraphicsBackend,
+    bounds::BoundingSphere,
+    camera::Camera,
+    common::Uniforms,
+    material_manager::MaterialManager,
+    mesh::MeshStorage,
+    ray_tracing::casts_shadows,
+    render_queue::{GeometryHandle, RenderQueue},
+    sky::SunLight,
+    upload_scheduler::UploadScheduler,
+    RendererError,
+};
+use glam::{Mat4, Vec2, Vec3};
+
+/// The most cascades the shadow maps can be split into.
+pub const MAX_CASCADES: usize = 4;
+
+/// Shadows cast by a directional light into cascaded shadow maps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CascadedShadows {
+    /// The world-space direction towards the light.
+    pub light_direction: Vec3,
+    /// The number of cascades, from 1 to `MAX_CASCADES`.
+    pub cascade_count: u32,
+    /// How the splits are spaced, from 0 for even to 1 for logarithmic splits.
+    pub split_lambda: f32,
+    /// The width and height of each cascade's shadow map in texels.
+    pub resolution: u32,
+    /// The farthest view distance shadows are drawn at, capped by the camera's far plane.
+    pub max_distance: f32,
+    /// How far towards the light beyond a cascade occluders still cast shadows into it.
+    pub caster_distance: f32,
+    /// How much shadowed pixels are darkened, from 0 for none to 1 for black.
+    pub strength: f32,
+    /// The depth offset of shadow map comparisons, avoiding self-shadowing.
+    pub bias: f32,
+    /// The fraction of each cascade, at its far end, blended into the next.
+    pub blend: f32,
+}
+
+impl Default for CascadedShadows {
+    fn default() -> Self {
+        Self {
+            light_direction: Vec3::Y,
+            cascade_count: MAX_CASCADES as u32,
+            split_lambda: 0.75,
+            resolution: 2048,
+            max_distance: 200.0,
+            caster_distance: 100.0,
+            strength: 0.6,
+            bias: 0.001,
+            blend: 0.1,
+        }
+    }
+}
+
+impl CascadedShadows {
+    /// Creates new `CascadedShadows` cast by a light in the given direction.
+    pub fn new(light_direction: Vec3) -> Self {
+        Self {
+            light_direction,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the number of cascades, clamped from 1 to `MAX_CASCADES`.
+    pub fn with_cascades(mut self, cascade_count: u32) -> Self {
+        self.cascade_count = cascade_count.clamp(1, MAX_CASCADES as u32);
+        self
+    }
+
+    /// Sets how the splits are spaced, clamped from 0 for even to 1 for logarithmic splits.
+    pub fn with_split_lambda(mut self, split_lambda: f32) -> Self {
+        self.split_lambda = split_lambda.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the width and height of each cascade's shadow map in texels.
+    pub fn with_resolution(mut self, resolution: u32) -> Self {
+        self.resolution = resolution.max(1);
+        self
+    }
+
+    /// Sets the farthest view distance shadows are drawn at.
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    /// Sets how much shadowed pixels are darkened.
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    /// Returns the view distance each cascade ends at, nearest first.
+    ///
+    /// Each split is the practical split scheme's blend of an even and a
+    /// logarithmic split of the range by the split lambda.
+    ///
+    /// # Arguments
+    ///
+    /// * `near` - The camera's near plane distance, where the first cascade starts.
+    /// * `far` - The camera's far plane distance.
+    pub fn split_distances(&self, near: f32, far: f32) -> Vec<f32> {
+        let count = self.cascade_count.clamp(1, MAX_CASCADES as u32);
+        let far = far.min(self.max_distance).max(near);
+        let lambda = self.split_lambda.clamp(0.0, 1.0);
+        (1..=count)
+            .map(|cascade| {
+                let fraction = cascade as f32 / count as f32;
+                let logarithmic = near * (far / near).powf(fraction);
+                let even = near + (far - near) * fraction;
+                lambda * logarithmic + (1.0 - lambda) * even
+            })
+            .collect()
+    }
+
+    /// Fits the cascades around a camera's view frustum.
+    ///
+    /// # Returns
+    ///
+    /// The cascades, nearest first, or none without a light direction.
+    pub fn cascades(&self, camera: &Camera) -> Vec<ShadowCascade> {
+        let light = self.light_direction.normalize_or_zero();
+        if light == Vec3::ZERO {
+            return Vec::new();
+        }
+        let projection = camera.get_projection_matrix();
+        // The squared slope of the frustum's corner edges from its axis
+        let corner_slope =
+            Vec2::new(1.0 / projection.x_axis.x, 1.0 / projection.y_axis.y).length_squared();
+        let camera_to_world = camera.get_view_matrix().inverse();
+        let up = if light.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let light_rotation = Mat4::look_to_rh(Vec3::ZERO, -light, up);
+
+        let mut near = camera.near();
+        self.split_distances(camera.near(), camera.far())
+            .into_iter()
+            .map(|far| {
+                // The smallest sphere around the slice's corners lies on the view axis
+                let mut center = (near + far) * 0.5 * (1.0 + corner_slope);
+                if center > far {
+                    center = far;
+                }
+                let radius = ((far - center).powi(2) + far * far * corner_slope).sqrt();
+                // Rounded up so the projection keeps its size as the camera turns
+                let radius = (radius * 16.0).ceil() / 16.0;
+                near = far;
+
+                // Moved in whole texels, so the texels keep still as the camera moves
+                let texel = 2.0 * radius / self.resolution.max(1) as f32;
+                let center = light_rotation
+                    .transform_point3(camera_to_world.transform_point3(Vec3::NEG_Z * center));
+                let snapped = (center / texel).floor() * texel;
+                let snapped = Vec3::new(snapped.x, snapped.y, center.z);
+                let center = light_rotation.inverse().transform_point3(snapped);
+
+                let depth = radius + self.caster_distance.max(0.0);
+                let view = Mat4::look_to_rh(center + light * depth, -light, up);
+                let projection =
+                    Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, depth + radius);
+                ShadowCascade {
+                    view_projection: projection * view,
+                    far,
+                }
+            })
+            .collect()
+    }
+
+    /// Builds the constants of the shadow pass for a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    /// * `view` - The view matrix of the camera, giving the view depth of pixels.
+    /// * `cascades` - The cascades of the frame, nearest first.
+    pub fn constants(
+        &self,
+        view_projection: Mat4,
+        view: Mat4,
+        cascades: &[ShadowCascade],
+    ) -> ShadowMapConstants {
+        let mut cascade_view_projections = [Mat4::IDENTITY; MAX_CASCADES];
+        let mut cascade_splits = [0.0; MAX_CASCADES];
+        for (index, cascade) in cascades.iter().take(MAX_CASCADES).enumerate() {
+            cascade_view_projections[index] = cascade.view_projection;
+            cascade_splits[index] = cascade.far;
+        }
+        ShadowMapConstants {
+            inverse_view_projection: view_projection.inverse(),
+            view,
+            cascade_view_projections,
+            cascade_splits,
+            cascade_count: cascades.len().min(MAX_CASCADES) as u32,
+            strength: self.strength.clamp(0.0, 1.0),
+            bias: self.bias,
+            blend: self.blend.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Sets the frame's cascades on the backend and draws the depth of the
+    /// shadow casters into the shadow map of every cascade.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - The backend to draw with.
+    /// * `casters` - The frame's shadow casters.
+    /// * `camera` - The camera whose view frustum the cascades cover.
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    /// * `sun` - The sky's sunlight, fading the shadows out at night.
+    pub fn draw(
+        &self,
+        backend: &mut impl GraphicsBackend,
+        casters: &ShadowCasters,
+        camera: &Camera,
+        view_projection: Mat4,
+        sun: Option<SunLight>,
+    ) -> Result<(), RendererError> {
+        let cascades = self.cascades(camera);
+        let mut constants = self.constants(view_projection, camera.get_view_matrix(), &cascades);
+        if let Some(sun) = sun {
+            constants.strength *= sun.intensity;
+        }
+        backend.set_shadow_maps(Some(&ShadowMapFrame {
+            constants,
+            resolution: self.resolution,
+        }));
+
+        for (index, cascade) in cascades.iter().enumerate() {
+            casters.draw(
+                backend,
+                ShadowTarget::Cascade(index as u32),
+                cascade.view_projection,
+                None,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A slice of the view frustum and the shadow map projection covering it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowCascade {
+    /// The light's view projection matrix the cascade's shadow map is drawn with.
+    pub view_projection: Mat4,
+    /// The view distance the cascade ends at.
+    pub far: f32,
+}
+
+/// The per-frame constants of the shadow map pass.
+///
+/// The layout matches the `ShadowMapConstants` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowMapConstants {
+    pub inverse_view_projection: Mat4,
+    pub view: Mat4,
+    pub cascade_view_projections: [Mat4; MAX_CASCADES],
+    pub cascade_splits: [f32; MAX_CASCADES],
+    pub cascade_count: u32,
+    pub strength: f32,
+    pub bias: f32,
+    pub blend: f32,
+}
+
+/// The shadow maps and shadow constants of one frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowMapFrame {
+    pub constants: ShadowMapConstants,
+    /// The width and height of each cascade's shadow map in texels.
+    pub resolution: u32,
+}
+
+/// The shadow map a shadow caster is drawn into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowTarget {
+    /// A cascade of the directional light's shadow maps.
+    Cascade(u32),
+    /// A face of a point or spot light's shadow map, `index` being the
+    /// light's position in the frame's light shadows.
+    Light { index: u32, face: u32 },
+}
+
+/// The draws of a frame that cast shadows, and the stores they are drawn from.
+///
+/// Casters are the stored mesh draws on the `SHADOW_CASTER` layer that write
+/// depth, so transparent draws cast no shadows. Meshes still waiting to be
+/// uploaded are skipped.
+pub struct ShadowCasters<'a> {
+    pub queue: &'a RenderQueue,
+    pub meshes: &'a MeshStorage,
+    pub materials: &'a MaterialManager,
+    pub uploads: &'a UploadScheduler,
+    /// Whether instanced draws are culled on the GPU, which shadow maps skip.
+    pub instance_culling: bool,
+}
+
+impl ShadowCasters<'_> {
+    /// Draws the depth of the shadow casters into one shadow map.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - The backend to draw with.
+    /// * `target` - The cascade or light face whose shadow map to draw into.
+    /// * `view_projection` - The view projection matrix of the shadow map.
+    /// * `reach` - The sphere a light reaches, skipping uninstanced casters
+    ///   outside it, or `None` to draw every caster.
+    pub fn draw(
+        &self,
+        backend: &mut impl GraphicsBackend,
+        target: ShadowTarget,
+        view_projection: Mat4,
+        reach: Option<BoundingSphere>,
+    ) -> Result<(), RendererError> {
+        for item in self.queue.draw_items() {
+            let GeometryHandle::Mesh(mesh_id) = item.geometry else {
+                continue;
+            };
+            if !casts_shadows(item.layers) || self.uploads.is_mesh_queued(mesh_id) {
+                continue;
+            }
+            let render_state = item.render_state.unwrap_or_else(|| {
+                self.materials
+                    .get(item.material_id)
+                    .map(|material| material.render_state)
+                    .unwrap_or_default()
+            });
+            if !render_state.writes_depth_prepass() {
+                continue;
+            }
+            let Some(mesh) = self.meshes.get_mesh(mesh_id) else {
+                continue;
+            };
+            if let (Some(reach), None) = (reach, item.instances) {
+                let bounds = mesh.bounding_sphere.transformed(item.transform);
+                if bounds.center.distance(reach.center) > bounds.radius + reach.radius {
+                    continue;
+                }
+            }
+            let geometry = mesh.view();
+            let Some(positions) = geometry.positions else {
+                continue;
+            };
+
+            if !(mesh.is_static && backend.bind_static_mesh(mesh_id, true)) {
+                backend.update_packed_vertex_buffer(positions)?;
+                if let Some(indices) = geometry.indices {
+                    backend.update_index_buffer(indices)?;
+                }
+            }
+            backend.update_uniform_buffer(&Uniforms {
+                view_projection_matrix: view_projection,
+                model_matrix: *item.transform,
+            })?;
+            if let Some(instances) = item.instances {
+                if self.instance_culling {
+                    backend.set_instance_culling(None);
+                }
+                backend.update_instance_buffer(instances)?;
+            }
+            backend.set_render_state(render_state);
+            backend.draw_shadow_caster(target, geometry.draw_command(item.instances))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CascadedShadows, MAX_CASCADES};
+    use crate::renderer::camera::Camera;
+    use glam::{Vec3, Vec4};
+
+    #[test]
+    fn test_split_distances() {
+        let shadows = CascadedShadows::default().with_max_distance(100.0);
+        let even = shadows.with_split_lambda(0.0).split_distances(1.0, 1000.0);
+        assert_eq!(even, vec![25.75, 50.5, 75.25, 100.0]);
+        let logarithmic = shadows.with_split_lambda(1.0).split_distances(1.0, 1000.0);
+        for (split, expected) in logarithmic.iter().zip([10f32.sqrt(), 10.0, 1000f32.sqrt()]) {
+            assert!((split - expected).abs() < 1e-4);
+        }
+        assert_eq!(
+            shadows.with_cascades(9).split_distances(1.0, 10.0).len(),
+            MAX_CASCADES
+        );
+        assert_eq!(
+            shadows.with_cascades(0).split_distances(1.0, 10.0),
+            vec![10.0]
+        );
+    }
+
+    #[test]
+    fn test_cascades_cover_frustum_slices() {
+        let camera = Camera::new(Vec3::new(3.0, 2.0, 5.0), 60.0, 16.0 / 9.0, 0.1, 50.0);
+        let shadows = CascadedShadows::new(Vec3::new(0.3, 1.0, 0.2)).with_cascades(3);
+        let cascades = shadows.cascades(&camera);
+        assert_eq!(cascades.len(), 3);
+        assert_eq!(cascades[2].far, 50.0);
+
+        // Every corner of each slice lies within its cascade's shadow map, up
+        // to the texel the cascade may be snapped by
+        let camera_to_clip = camera.get_projection_matrix() * camera.get_view_matrix();
+        let clip_to_world = camera_to_clip.inverse();
+        let mut near = camera.near();
+        for cascade in &cascades {
+            for distance in [near, cascade.far] {
+                let depth = camera_to_clip
+                    .project_point3(Vec3::new(3.0, 2.0, 5.0 - distance))
+                    .z;
+                for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                    let corner = clip_to_world.project_point3(Vec3::new(x, y, depth));
+                    let shadow = cascade.view_projection.project_point3(corner);
+                    assert!(shadow.x.abs() <= 1.0 + 1e-3 && shadow.y.abs() <= 1.0 + 1e-3);
+                    assert!((0.0..=1.0).contains(&shadow.z));
+                }
+            }
+            near = cascade.far;
+        }
+
+        let constants = shadows.constants(camera_to_clip, camera.get_view_matrix(), &cascades);
+        assert_eq!(constants.cascade_count, 3);
+        assert_eq!(constants.cascade_splits[3], 0.0);
+        assert_eq!(
+            constants.cascade_view_projections[1] * Vec4::W,
+            cascades[1].view_projection * Vec4::W
+        );
+        assert!(CascadedShadows::new(Vec3::ZERO)
+            .cascades(&camera)
+            .is_empty());
+    }
+}
@@ -1,15 +1,20 @@
 //! This module provides various builder structures and traits for creating
 //! and manipulating geometric shapes within the rendering system. It includes
-//! implementations for general shape builder as well as specific shapes like triangles.
+//! implementations for general shape builder as well as specific shapes like triangles
+//! and spheres.
 //!
 //! Key components:
 //! - `shape_builder`: Provides the core shape building functionality and traits.
+//! - `sphere_builder`: Implements a builder for UV spheres of any resolution.
 //! - `triangle_builder`: Implements a specific builder for triangle shapes.
 //! - `MeshBuilder`: A builder for creating mesh objects.
+//! - `SphereBuilder`: A builder for UV spheres with per-vertex normals.
 //! - `TriangleBuilder`: A specialized builder for creating triangle primitives.
 
 pub mod shape_builder;
+pub mod sphere_builder;
 pub mod triangle_builder;
 
 pub use shape_builder::MeshBuilder;
+pub use sphere_builder::SphereBuilder;
 pub use triangle_builder::TriangleBuilder;
@@ -6,11 +6,19 @@
 //! `PrimitiveBuilder` and `MeshBuilder` structs for detailed shape customization.
 
 use crate::renderer::{
-    common::{PrimitiveType, Vertex},
+    backend::GraphicsBackend,
+    common::{LayerMask, PrimitiveType, Vertex},
+    csg::Csg,
+    error::CsgError,
+    material_manager::MaterialId,
+    mesh::Socket,
+    mesh_optimizer::MeshOptimization,
     render_core::Renderer,
-    Color, DrawCommandBuilder, InstanceData,
+    vertex_layout::{VertexLayout, VertexSemantic, VertexStream},
+    Color, Colormap, DrawCommandBuilder, InstanceData,
 };
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
+use log::warn;
 
 /// Trait for converting shapes into primitive or mesh builders.
 #[allow(clippy::wrong_self_convention)]
@@ -29,6 +37,8 @@ pub struct ShapeData {
     pub primitive_type: PrimitiveType,
     pub transform: Mat4,
     pub instances: Option<Vec<InstanceData>>,
+    pub material_id: MaterialId,
+    pub layers: LayerMask,
 }
 
 impl ShapeData {
@@ -40,6 +50,8 @@ impl ShapeData {
             primitive_type,
             transform: Mat4::IDENTITY,
             instances: None,
+            material_id: MaterialId::DEFAULT,
+            layers: LayerMask::default(),
         }
     }
 
@@ -47,7 +59,7 @@ impl ShapeData {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// .with_indices(vec![0, 1, 2])
     /// ```
     ///
@@ -60,7 +72,7 @@ impl ShapeData {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// .with_transform(Mat4::from_translation(Vec3::new(1.5, 0.0, 0.0)))
     /// ```
     fn with_transform(mut self, transform: Mat4) -> Self {
@@ -73,6 +85,46 @@ impl ShapeData {
         self.instances = Some(instances);
         self
     }
+
+    /// Sets the material the shape is drawn with.
+    fn with_material(mut self, material_id: MaterialId) -> Self {
+        self.material_id = material_id;
+        self
+    }
+
+    /// Sets the visibility layers the shape belongs to.
+    fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Colors the vertices by mapping per-vertex scalars through a colormap.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// .with_scalar_field(&temperatures, Colormap::Viridis, Some((0.0, 100.0)))
+    /// ```
+    fn with_scalar_field(
+        mut self,
+        values: &[f32],
+        colormap: Colormap,
+        range: Option<(f32, f32)>,
+    ) -> Self {
+        if values.len() != self.vertices.len() {
+            warn!(
+                "Scalar field has {} values but shape has {} vertices",
+                values.len(),
+                self.vertices.len()
+            );
+        }
+
+        let range = range.unwrap_or_else(|| Colormap::auto_range(values));
+        for (vertex, &value) in self.vertices.iter_mut().zip(values) {
+            vertex.color = colormap.map(value, range).into();
+        }
+        self
+    }
 }
 
 impl ShapeBuilder for ShapeData {
@@ -81,7 +133,16 @@ impl ShapeBuilder for ShapeData {
     }
 
     fn as_mesh(self) -> MeshBuilder {
-        MeshBuilder { data: self }
+        MeshBuilder {
+            data: self,
+            layout: VertexLayout::default(),
+            streams: Vec::new(),
+            optimization: Some(MeshOptimization::default()),
+            bvh: false,
+            is_static: false,
+            collidable: false,
+            sockets: Vec::new(),
+        }
     }
 }
 
@@ -89,7 +150,7 @@ impl ShapeBuilder for ShapeData {
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// renderer.create_triangle(
 ///     Vec3::new(0.0, 0.5, 0.0),
 ///     Vec3::new(-0.5, -0.5, 0.0),
@@ -117,7 +178,7 @@ impl PrimitiveBuilder {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// .with_indices(vec![0, 1, 2])
     /// ```
     ///
@@ -131,7 +192,7 @@ impl PrimitiveBuilder {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// .with_transform(Mat4::from_translation(Vec3::new(1.5, 0.0, 0.0)))
     /// ```
     #[allow(dead_code)]
@@ -147,21 +208,53 @@ impl PrimitiveBuilder {
         self
     }
 
+    /// Sets the material the shape is drawn with.
+    pub fn with_material(mut self, material_id: MaterialId) -> Self {
+        self.data = self.data.with_material(material_id);
+        self
+    }
+
+    /// Sets the visibility layers the shape belongs to.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.data = self.data.with_layers(layers);
+        self
+    }
+
+    /// Colors the vertices by mapping per-vertex scalars through a colormap.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - One scalar value per vertex.
+    /// * `colormap` - The colormap to map the values through.
+    /// * `range` - The `(min, max)` values mapped to the ends of the colormap,
+    ///   or `None` to use the range of `values`.
+    pub fn with_scalar_field(
+        mut self,
+        values: &[f32],
+        colormap: Colormap,
+        range: Option<(f32, f32)>,
+    ) -> Self {
+        self.data = self.data.with_scalar_field(values, colormap, range);
+        self
+    }
+
     /// Draws the primitive using the provided renderer.
     #[allow(dead_code)]
-    pub fn draw(self, renderer: &mut Renderer) {
+    pub fn draw<B: GraphicsBackend>(self, renderer: &mut Renderer<B>) {
         let mut draw_command = DrawCommandBuilder::new_primitive(
-            self.data.vertices,
-            self.data.indices,
+            &self.data.vertices,
+            self.data.indices.as_deref(),
             self.data.primitive_type,
         )
-        .with_transform(self.data.transform);
+        .with_transform(self.data.transform)
+        .with_material(self.data.material_id)
+        .with_layers(self.data.layers);
 
-        if let Some(instances) = self.data.instances {
+        if let Some(instances) = &self.data.instances {
             draw_command = draw_command.with_instances(instances);
         }
 
-        renderer.draw_immediate(draw_command.build());
+        renderer.draw_immediate(draw_command);
     }
 }
 
@@ -169,7 +262,7 @@ impl PrimitiveBuilder {
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// renderer.create_triangle(
 ///     Vec3::new(0.0, 0.5, 0.0),
 ///     Vec3::new(-0.5, -0.5, 0.0),
@@ -184,21 +277,163 @@ impl PrimitiveBuilder {
 #[derive(Clone)]
 pub struct MeshBuilder {
     pub data: ShapeData,
+    /// The layout the mesh's vertex buffer is packed in.
+    pub layout: VertexLayout,
+    /// The values of attributes other than the vertices' positions and colors.
+    pub streams: Vec<VertexStream>,
+    /// The optimization applied when the mesh is added, or `None` to keep the geometry as built.
+    pub optimization: Option<MeshOptimization>,
+    /// Whether the mesh builds a bounding volume hierarchy to speed up raycasts.
+    pub bvh: bool,
+    /// Whether the mesh's geometry is uploaded once into GPU-resident buffers.
+    pub is_static: bool,
+    /// Whether scene nodes drawing the mesh become physics colliders.
+    pub collidable: bool,
+    /// The named attachment points of the mesh.
+    pub sockets: Vec<Socket>,
 }
 
 impl MeshBuilder {
     /// Creates a new `MeshBuilder` with default values.
     pub fn new(vertices: Vec<Vertex>, primitive_type: PrimitiveType) -> Self {
-        Self {
-            data: ShapeData::new(vertices, primitive_type),
-        }
+        ShapeData::new(vertices, primitive_type).as_mesh()
     }
 
-    /// Adds indices to the primitive.
+    /// Sets the layout the mesh's vertex buffer is packed in.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// .with_layout(VertexLayout::position_normal_uv())
+    /// ```
+    pub fn with_layout(mut self, layout: VertexLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets the optimization applied when the mesh is added, or `None` to opt out.
+    ///
+    /// Meshes are welded and optimized for the vertex cache by default.
+    /// Opt out when vertex order matters, e.g. for vertices updated by index.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// .with_optimization(Some(MeshOptimization::default().with_quantization(true)))
+    /// ```
+    pub fn with_optimization(mut self, optimization: Option<MeshOptimization>) -> Self {
+        self.optimization = optimization;
+        self
+    }
+
+    /// Sets whether the mesh builds a bounding volume hierarchy when added.
+    ///
+    /// Raycasts against a mesh with a hierarchy only test the triangles near
+    /// the ray, which pays off for large meshes that are cast against often.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// .with_bvh(true)
+    /// ```
+    pub fn with_bvh(mut self, bvh: bool) -> Self {
+        self.bvh = bvh;
+        self
+    }
+
+    /// Sets whether the mesh is static, uploading its geometry once when added.
+    ///
+    /// Static meshes are copied into private GPU buffers, which the GPU reads
+    /// faster than the shared buffers other geometry is copied into every draw.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// .with_static(true)
+    /// ```
+    pub fn with_static(mut self, is_static: bool) -> Self {
+        self.is_static = is_static;
+        self
+    }
+
+    /// Sets whether scene nodes drawing the mesh become physics colliders.
+    ///
+    /// `Renderer::mesh_colliders` creates a triangle mesh collider for every
+    /// scene node drawing a collidable mesh, e.g. the level geometry of an
+    /// imported model.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// .with_collidable(true)
+    /// ```
+    pub fn with_collidable(mut self, collidable: bool) -> Self {
+        self.collidable = collidable;
+        self
+    }
+
+    /// Adds a named attachment point, replacing any socket of the same name.
+    ///
+    /// Scene nodes attached to the socket with `SceneGraph::attach_to_socket`
+    /// ride on this part of the model, e.g. a weapon in a hand or a thruster
+    /// on a hull.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name nodes are attached by.
+    /// * `transform` - The socket's transform in model space.
     ///
     /// # Example
     ///
+    /// ```ignore
+    /// .with_socket("muzzle", Mat4::from_translation(Vec3::new(0.0, 0.2, -1.5)))
     /// ```
+    pub fn with_socket(mut self, name: &str, transform: Mat4) -> Self {
+        self.sockets.retain(|socket| socket.name != name);
+        self.sockets.push(Socket {
+            name: name.to_string(),
+            transform,
+        });
+        self
+    }
+
+    /// Provides the values of an attribute, one per vertex.
+    ///
+    /// The attribute is only uploaded if the mesh's layout has it.
+    ///
+    /// # Arguments
+    ///
+    /// * `semantic` - The attribute the values are for.
+    /// * `values` - The values, with unused trailing components ignored.
+    pub fn with_attribute(mut self, semantic: VertexSemantic, values: Vec<[f32; 4]>) -> Self {
+        self.streams.retain(|stream| stream.semantic != semantic);
+        self.streams.push(VertexStream { semantic, values });
+        self
+    }
+
+    /// Provides the normal of every vertex.
+    pub fn with_normals(self, normals: &[Vec3]) -> Self {
+        let values = normals
+            .iter()
+            .map(|normal| normal.extend(0.0).into())
+            .collect();
+        self.with_attribute(VertexSemantic::Normal, values)
+    }
+
+    /// Provides the texture coordinates of every vertex.
+    pub fn with_tex_coords(self, tex_coords: &[Vec2]) -> Self {
+        let values = tex_coords
+            .iter()
+            .map(|tex_coord| [tex_coord.x, tex_coord.y, 0.0, 0.0])
+            .collect();
+        self.with_attribute(VertexSemantic::TexCoord, values)
+    }
+
+    /// Adds indices to the primitive.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
     /// .with_indices(vec![0, 1, 2])
     /// ```
     #[allow(dead_code)]
@@ -211,7 +446,7 @@ impl MeshBuilder {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// .with_transform(Mat4::from_translation(Vec3::new(1.5, 0.0, 0.0)))
     /// ```
     #[allow(dead_code)]
@@ -227,18 +462,85 @@ impl MeshBuilder {
         self
     }
 
+    /// Sets the material the shape is drawn with.
+    pub fn with_material(mut self, material_id: MaterialId) -> Self {
+        self.data = self.data.with_material(material_id);
+        self
+    }
+
+    /// Sets the visibility layers the shape belongs to.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.data = self.data.with_layers(layers);
+        self
+    }
+
+    /// Colors the vertices by mapping per-vertex scalars through a colormap.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - One scalar value per vertex.
+    /// * `colormap` - The colormap to map the values through.
+    /// * `range` - The `(min, max)` values mapped to the ends of the colormap,
+    ///   or `None` to use the range of `values`.
+    pub fn with_scalar_field(
+        mut self,
+        values: &[f32],
+        colormap: Colormap,
+        range: Option<(f32, f32)>,
+    ) -> Self {
+        self.data = self.data.with_scalar_field(values, colormap, range);
+        self
+    }
+
+    /// Returns the space covered by either mesh, see `Csg`.
+    ///
+    /// Both meshes must be closed triangle lists. The result keeps this
+    /// mesh's material and layers and has the meshes' transforms applied.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the combined mesh or a `CsgError` if either mesh is malformed.
+    pub fn union(&self, other: &MeshBuilder) -> Result<MeshBuilder, CsgError> {
+        self.combine(other, Csg::union)
+    }
+
+    /// Returns the space covered by this mesh but not the other, see `union`.
+    pub fn subtract(&self, other: &MeshBuilder) -> Result<MeshBuilder, CsgError> {
+        self.combine(other, Csg::subtract)
+    }
+
+    /// Returns the space covered by both meshes, see `union`.
+    pub fn intersect(&self, other: &MeshBuilder) -> Result<MeshBuilder, CsgError> {
+        self.combine(other, Csg::intersect)
+    }
+
+    fn combine(
+        &self,
+        other: &MeshBuilder,
+        operation: fn(&Csg, &Csg) -> Csg,
+    ) -> Result<MeshBuilder, CsgError> {
+        let solid = operation(&Csg::from_mesh(self)?, &Csg::from_mesh(other)?);
+        Ok(solid
+            .to_mesh()
+            .with_material(self.data.material_id)
+            .with_layers(self.data.layers)
+            .with_optimization(self.optimization))
+    }
+
     /// Draws the mesh using the provided renderer.
     #[allow(dead_code)]
-    pub fn draw(&self, renderer: &mut Renderer) {
+    pub fn draw<B: GraphicsBackend>(&self, renderer: &mut Renderer<B>) {
         let mesh_id = renderer.add_mesh(self.clone());
-        let mut draw_command =
-            DrawCommandBuilder::new_mesh(mesh_id).with_transform(self.data.transform);
+        let mut draw_command = DrawCommandBuilder::new_mesh(mesh_id)
+            .with_transform(self.data.transform)
+            .with_material(self.data.material_id)
+            .with_layers(self.data.layers);
 
         if let Some(instances) = &self.data.instances {
-            draw_command = draw_command.with_instances(instances.clone());
+            draw_command = draw_command.with_instances(instances);
         }
 
-        renderer.draw_immediate(draw_command.build());
+        renderer.draw_immediate(draw_command);
     }
 }
 
@@ -256,7 +558,7 @@ mod tests {
     use super::{vec3_color_to_vertex, MeshBuilder, PrimitiveBuilder};
     use crate::renderer::{
         common::{PrimitiveType, Vertex},
-        Color, InstanceData,
+        Color, Colormap, InstanceData,
     };
     use glam::{Mat4, Vec3};
 
@@ -328,4 +630,19 @@ mod tests {
         assert_eq!(vertex.position, [1.0, 2.0, 3.0]);
         assert_eq!(vertex.color, [0.1, 0.2, 0.3, 1.0]);
     }
+
+    #[test]
+    fn test_mesh_builder_with_scalar_field() {
+        let vertices = create_sample_triangle();
+        let builder = MeshBuilder::new(vertices, PrimitiveType::Triangle).with_scalar_field(
+            &[0.0, 5.0, 10.0],
+            Colormap::Viridis,
+            None,
+        );
+
+        let colors: Vec<[f32; 4]> = builder.data.vertices.iter().map(|v| v.color).collect();
+        assert_eq!(colors[0], <[f32; 4]>::from(Colormap::Viridis.sample(0.0)));
+        assert_eq!(colors[1], <[f32; 4]>::from(Colormap::Viridis.sample(0.5)));
+        assert_eq!(colors[2], <[f32; 4]>::from(Colormap::Viridis.sample(1.0)));
+    }
 }
@@ -0,0 +1,114 @@
+//! Sphere building module for the renderer.
+//!
+//! This module provides the `SphereBuilder` struct for creating UV spheres
+//! of any resolution, split into rings between the poles and segments around
+//! the axis.
+
+use super::{
+    shape_builder::{vec3_color_to_vertex, PrimitiveBuilder, ShapeBuilder},
+    MeshBuilder,
+};
+use crate::renderer::{
+    common::{PrimitiveType, Vertex},
+    vertex_layout::{VertexFormat, VertexLayout, VertexSemantic},
+    Color,
+};
+use glam::Vec3;
+use std::f32::consts::{PI, TAU};
+
+/// Builder for creating a UV sphere centered on the origin
+///
+/// The sphere has `2 * rings * segments` triangles, including the degenerate
+/// ones at the poles. Meshes built from it carry per-vertex normals.
+///
+/// # Example
+///
+/// ```ignore
+/// let sphere = SphereBuilder::new(1.0, 16, 32, Color::WHITE).as_mesh();
+/// let mesh_id = renderer.add_mesh(sphere);
+/// ```
+pub struct SphereBuilder {
+    vertices: Vec<Vertex>,
+    normals: Vec<Vec3>,
+    indices: Vec<u32>,
+}
+
+impl SphereBuilder {
+    pub fn new(radius: f32, rings: u32, segments: u32, color: Color) -> Self {
+        let rings = rings.max(2);
+        let segments = segments.max(3);
+
+        let vertex_count = ((rings + 1) * (segments + 1)) as usize;
+        let mut vertices = Vec::with_capacity(vertex_count);
+        let mut normals = Vec::with_capacity(vertex_count);
+        for ring in 0..=rings {
+            let polar = PI * ring as f32 / rings as f32;
+            for segment in 0..=segments {
+                let azimuth = TAU * segment as f32 / segments as f32;
+                let normal = Vec3::new(
+                    polar.sin() * azimuth.cos(),
+                    polar.cos(),
+                    polar.sin() * azimuth.sin(),
+                );
+                vertices.push(vec3_color_to_vertex(normal * radius, color));
+                normals.push(normal);
+            }
+        }
+
+        let stride = segments + 1;
+        let mut indices = Vec::with_capacity((rings * segments * 6) as usize);
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let corner = ring * stride + segment;
+                indices.extend([corner, corner + 1, corner + stride]);
+                indices.extend([corner + 1, corner + stride + 1, corner + stride]);
+            }
+        }
+
+        SphereBuilder {
+            vertices,
+            normals,
+            indices,
+        }
+    }
+}
+
+impl ShapeBuilder for SphereBuilder {
+    fn as_primitive(self) -> PrimitiveBuilder {
+        PrimitiveBuilder::new(self.vertices, PrimitiveType::Triangle).with_indices(self.indices)
+    }
+
+    fn as_mesh(self) -> MeshBuilder {
+        MeshBuilder::new(self.vertices, PrimitiveType::Triangle)
+            .with_layout(
+                VertexLayout::position_color()
+                    .with_attribute(VertexSemantic::Normal, VertexFormat::Float3),
+            )
+            .with_normals(&self.normals)
+            .with_indices(self.indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SphereBuilder;
+    use crate::renderer::Color;
+
+    #[test]
+    fn test_sphere_builder() {
+        let sphere = SphereBuilder::new(2.0, 4, 8, Color::WHITE);
+
+        assert_eq!(sphere.vertices.len(), 5 * 9);
+        assert_eq!(sphere.normals.len(), sphere.vertices.len());
+        assert_eq!(sphere.indices.len(), 2 * 4 * 8 * 3);
+        assert_eq!(sphere.vertices[0].position, [0.0, 2.0, 0.0]);
+        for vertex in &sphere.vertices {
+            let length = vertex.position.iter().map(|x| x * x).sum::<f32>().sqrt();
+            assert!((length - 2.0).abs() < 1e-5);
+        }
+        assert!(sphere
+            .indices
+            .iter()
+            .all(|&index| (index as usize) < sphere.vertices.len()));
+    }
+}
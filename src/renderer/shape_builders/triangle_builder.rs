@@ -18,7 +18,7 @@ use glam::Vec3;
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// let triangle = renderer.create_triangle(
 ///     Vec3::new(0.0, 0.5, 0.0),  // Top vertex
 ///     Vec3::new(-0.5, -0.5, 0.0), // Bottom-left vertex
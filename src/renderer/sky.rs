@@ -0,0 +1,257 @@
+//! Sky module for the renderer.
+//!
+//! This module provides `Sky`, a procedural day/night sky driven by the time
+//! of day. The sun follows its daily arc for a given latitude, and the sky
+//! fades from a daylight gradient with a sun disc, through sunset colors,
+//! into a rotating starfield at night. Every frame, the renderer passes the
+//! resulting `SunLight` to the atmosphere, directional lens flares and an
+//! optional callback, so lights follow the sun automatically.
+
+use glam::{Mat4, Quat, Vec3};
+use std::f32::consts::TAU;
+
+/// The hours in a day.
+const HOURS_PER_DAY: f32 = 24.0;
+
+/// Sky colors at noon, sunset and midnight, at the zenith and the horizon.
+const DAY_ZENITH: Vec3 = Vec3::new(0.25, 0.5, 0.9);
+const DAY_HORIZON: Vec3 = Vec3::new(0.7, 0.8, 0.95);
+const SUNSET_HORIZON: Vec3 = Vec3::new(0.9, 0.5, 0.3);
+const NIGHT_ZENITH: Vec3 = Vec3::new(0.0, 0.0, 0.02);
+const NIGHT_HORIZON: Vec3 = Vec3::new(0.02, 0.03, 0.06);
+
+/// Sunlight colors when the sun is high and when it touches the horizon.
+const NOON_SUN: Vec3 = Vec3::new(1.0, 0.97, 0.92);
+const HORIZON_SUN: Vec3 = Vec3::new(1.0, 0.45, 0.2);
+
+/// The directional light cast by the sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SunLight {
+    /// The world-space direction towards the sun.
+    pub direction: Vec3,
+    pub color: Vec3,
+    /// The light's strength, 0 once the sun has set.
+    pub intensity: f32,
+}
+
+/// A callback receiving the sun's light every frame a sky is set.
+pub type SunCallback = dyn FnMut(&SunLight);
+
+/// A procedural sky with a sun and stars.
+///
+/// The world's up axis is +Y, east is +X and north is -Z.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sky {
+    /// The hour of the day in [0, 24), with the sun highest at 12.
+    pub time_of_day: f32,
+    /// The real seconds a whole day takes as the frame clock advances, or 0 to hold the time.
+    pub day_length: f32,
+    /// The latitude of the observer in radians, tilting the sun's arc and the stars' pole.
+    pub latitude: f32,
+    /// The angle of the sun north of the celestial equator in radians, changing with the seasons.
+    pub declination: f32,
+    /// The angular radius of the sun disc in radians.
+    pub sun_angular_radius: f32,
+    /// Scales the brightness of the stars.
+    pub star_brightness: f32,
+}
+
+impl Default for Sky {
+    /// A mid-latitude sky at noon, with the time held.
+    fn default() -> Self {
+        Self {
+            time_of_day: 12.0,
+            day_length: 0.0,
+            latitude: 45f32.to_radians(),
+            declination: 0.0,
+            sun_angular_radius: 0.0047,
+            star_brightness: 1.0,
+        }
+    }
+}
+
+impl Sky {
+    /// Creates a new `Sky` at the given hour of the day.
+    pub fn new(time_of_day: f32) -> Self {
+        Self {
+            time_of_day: time_of_day.rem_euclid(HOURS_PER_DAY),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the real seconds a whole day takes.
+    pub fn with_day_length(mut self, day_length: f32) -> Self {
+        self.day_length = day_length;
+        self
+    }
+
+    /// Sets the latitude of the observer, in radians.
+    pub fn with_latitude(mut self, latitude: f32) -> Self {
+        self.latitude = latitude;
+        self
+    }
+
+    /// Advances the time of day, wrapping around at midnight.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta` - The scaled seconds since the last frame.
+    pub fn advance(&mut self, delta: f32) {
+        if self.day_length > 0.0 {
+            self.time_of_day = (self.time_of_day + delta / self.day_length * HOURS_PER_DAY)
+                .rem_euclid(HOURS_PER_DAY);
+        }
+    }
+
+    /// Returns the direction of the celestial pole, which the sky turns around.
+    fn pole(&self) -> Vec3 {
+        Vec3::new(0.0, self.latitude.sin(), -self.latitude.cos())
+    }
+
+    /// Returns the rotation of the sky since noon.
+    fn rotation(&self) -> Quat {
+        let hour_angle = (self.time_of_day - 12.0) / HOURS_PER_DAY * TAU;
+        Quat::from_axis_angle(self.pole(), -hour_angle)
+    }
+
+    /// Returns the world-space direction towards the sun.
+    pub fn sun_direction(&self) -> Vec3 {
+        let pole = self.pole();
+        // Where the celestial equator crosses the meridian, south of the zenith
+        let meridian = Vec3::X.cross(pole);
+        let noon = meridian * self.declination.cos() + pole * self.declination.sin();
+        (self.rotation() * noon).normalize()
+    }
+
+    /// Returns the light the sun casts at the current time of day.
+    pub fn sun_light(&self) -> SunLight {
+        let direction = self.sun_direction();
+        let elevation = direction.y;
+        SunLight {
+            direction,
+            color: HORIZON_SUN.lerp(NOON_SUN, smoothstep(0.0, 0.3, elevation)),
+            intensity: smoothstep(-0.05, 0.1, elevation),
+        }
+    }
+
+    /// Builds the constants of the sky pass for a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view projection matrix the frame is drawn with.
+    pub fn constants(&self, view_projection: Mat4) -> SkyConstants {
+        let sun = self.sun_light();
+        let daylight = smoothstep(-0.2, 0.1, sun.direction.y);
+        let (zenith_color, horizon_color) = self.gradient();
+
+        SkyConstants {
+            inverse_view_projection: view_projection.inverse(),
+            // Turns world directions back into the stars' fixed frame
+            star_rotation: Mat4::from_quat(self.rotation().inverse()),
+            sun_direction: sun.direction.to_array(),
+            sun_angular_radius: self.sun_angular_radius,
+            sun_color: (sun.color * sun.intensity).to_array(),
+            star_brightness: self.star_brightness * (1.0 - daylight),
+            zenith_color: zenith_color.to_array(),
+            _padding: 0.0,
+            horizon_color: horizon_color.to_array(),
+            _padding_2: 0.0,
+        }
+    }
+
+    /// Returns the irradiance the sky's gradient casts onto surfaces facing
+    /// straight up and straight down, without the sun's direct light.
+    ///
+    /// The values are the sky's radiance integrated over the upper and lower
+    /// hemispheres, weighted by the cosine to the surface normal.
+    pub fn hemisphere_irradiance(&self) -> (Vec3, Vec3) {
+        let (zenith, horizon) = self.gradient();
+        // Above the horizon the sky blends by the square root of the height,
+        // and below it darkens to half the horizon color
+        (horizon.lerp(zenith, 0.8), horizon * (49.0 / 96.0))
+    }
+
+    /// Returns the sky's colors at the zenith and the horizon.
+    fn gradient(&self) -> (Vec3, Vec3) {
+        let elevation = self.sun_direction().y;
+        let daylight = smoothstep(-0.2, 0.1, elevation);
+        let day_horizon = SUNSET_HORIZON.lerp(DAY_HORIZON, smoothstep(0.0, 0.3, elevation));
+        (
+            NIGHT_ZENITH.lerp(DAY_ZENITH, daylight),
+            NIGHT_HORIZON.lerp(day_horizon, daylight),
+        )
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// The per-frame constants of the sky pass.
+///
+/// The layout matches the `SkyConstants` struct in `shader_types.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkyConstants {
+    pub inverse_view_projection: Mat4,
+    pub star_rotation: Mat4,
+    pub sun_direction: [f32; 3],
+    pub sun_angular_radius: f32,
+    pub sun_color: [f32; 3],
+    pub star_brightness: f32,
+    pub zenith_color: [f32; 3],
+    _padding: f32,
+    pub horizon_color: [f32; 3],
+    _padding_2: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sky, SkyConstants};
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_sky_constants_layout() {
+        // Must match the struct in shader_types.h
+        assert_eq!(std::mem::size_of::<SkyConstants>(), 192);
+    }
+
+    #[test]
+    fn test_sun_follows_time_of_day() {
+        let sky = |hour| Sky::new(hour).with_latitude(0.0).sun_direction();
+        assert!(sky(12.0).abs_diff_eq(Vec3::Y, 1e-5));
+        assert!(sky(6.0).abs_diff_eq(Vec3::X, 1e-5));
+        assert!(sky(18.0).abs_diff_eq(-Vec3::X, 1e-5));
+        assert!(sky(0.0).abs_diff_eq(-Vec3::Y, 1e-5));
+
+        // North of the equator, the noon sun is in the south
+        let noon = Sky::new(12.0).sun_direction();
+        assert!(noon.y > 0.0 && noon.z > 0.0);
+    }
+
+    #[test]
+    fn test_sun_sets_at_night() {
+        let day = Sky::new(12.0);
+        let night = Sky::new(0.0);
+        assert_eq!(day.sun_light().intensity, 1.0);
+        assert_eq!(night.sun_light().intensity, 0.0);
+        assert_eq!(day.constants(Mat4::IDENTITY).star_brightness, 0.0);
+        assert_eq!(night.constants(Mat4::IDENTITY).star_brightness, 1.0);
+
+        // The sun reddens towards the horizon
+        let evening = Sky::new(17.5).sun_light();
+        assert!(evening.color.z < day.sun_light().color.z);
+    }
+
+    #[test]
+    fn test_advance_wraps_day() {
+        let mut sky = Sky::new(23.0).with_day_length(240.0);
+        sky.advance(20.0);
+        assert!((sky.time_of_day - 1.0).abs() < 1e-4);
+
+        let mut held = Sky::new(23.0);
+        held.advance(20.0);
+        assert_eq!(held.time_of_day, 23.0);
+    }
+}
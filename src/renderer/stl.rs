@@ -0,0 +1,242 @@
+//! STL module for the renderer.
+//!
+//! This module imports STL files, the triangle soups written by CAD tools,
+//! 3D scanners and scientific software. Both encodings are read: binary
+//! files, with an 80-byte header, a triangle count and 50 bytes per
+//! triangle, and ASCII files of `facet` blocks:
+//!
+//! ```text
+//! solid part
+//!   facet normal 0 0 1
+//!     outer loop
+//!       vertex 0 0 0
+//!       vertex 1 0 0
+//!       vertex 0 1 0
+//!     endloop
+//!   endfacet
+//! endsolid part
+//! ```
+//!
+//! Binary files may also start with `solid`, so a file is only read as
+//! ASCII if it has no NUL bytes and its size doesn't match its triangle
+//! count. STL has no colors,
+//! texture coordinates or shared vertices; each triangle gets its facet
+//! normal, computed from its vertices where the file leaves it zero, and
+//! identical vertices are welded when the mesh is optimized.
+
+use super::{
+    common::{PrimitiveType, Vertex},
+    error::ImportError,
+    import_settings::ImportSettings,
+    shape_builders::MeshBuilder,
+};
+use glam::Vec3;
+use log::info;
+use std::{fs, path::Path};
+
+/// The size of a binary STL header and triangle count.
+const BINARY_HEADER_SIZE: usize = 84;
+
+/// The size of a binary STL triangle: a normal, three vertices and an attribute.
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+/// Imports an STL file, applying the import settings of its sidecar.
+///
+/// # Returns
+///
+/// A `Result` containing the file's mesh, or an `ImportError` if the file
+/// or its settings can't be read.
+pub fn import_stl(path: &Path) -> Result<Vec<MeshBuilder>, ImportError> {
+    let bytes = fs::read(path).map_err(|source| ImportError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mesh = parse_stl(&bytes)?;
+    let settings = ImportSettings::load_for(path)?;
+    info!(
+        "Imported STL {} with {} triangles",
+        path.display(),
+        mesh.data.vertices.len() / 3
+    );
+    Ok(settings.apply(vec![mesh]))
+}
+
+/// Parses the contents of a binary or ASCII STL file.
+///
+/// # Returns
+///
+/// A `Result` containing a triangle list mesh with flat normals, or
+/// `ImportError::Malformed`.
+pub fn parse_stl(bytes: &[u8]) -> Result<MeshBuilder, ImportError> {
+    let triangles = if is_ascii(bytes) {
+        parse_ascii(bytes)?
+    } else {
+        parse_binary(bytes)?
+    };
+
+    let mut vertices = Vec::with_capacity(triangles.len() * 3);
+    let mut normals = Vec::with_capacity(triangles.len() * 3);
+    for (normal, corners) in triangles {
+        let normal = if normal.length_squared() > f32::EPSILON {
+            normal.normalize()
+        } else {
+            (corners[1] - corners[0])
+                .cross(corners[2] - corners[0])
+                .normalize_or_zero()
+        };
+        for corner in corners {
+            vertices.push(Vertex {
+                position: corner.into(),
+                ..Vertex::default()
+            });
+            normals.push(normal);
+        }
+    }
+    Ok(MeshBuilder::new(vertices, PrimitiveType::Triangle).with_normals(&normals))
+}
+
+/// Returns `true` if the file is ASCII: it starts with `solid`, has no NUL
+/// bytes, and its size doesn't match the triangle count a binary header
+/// would give.
+fn is_ascii(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(b"solid") || bytes.contains(&0) {
+        return false;
+    }
+    match bytes.get(80..BINARY_HEADER_SIZE) {
+        Some(count) => {
+            let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+            BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE != bytes.len()
+        }
+        None => true,
+    }
+}
+
+/// Reads the facet normal and vertices of every triangle of a binary file.
+fn parse_binary(bytes: &[u8]) -> Result<Vec<(Vec3, [Vec3; 3])>, ImportError> {
+    let count = bytes
+        .get(80..BINARY_HEADER_SIZE)
+        .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize)
+        .ok_or_else(|| ImportError::Malformed("STL file is too short".to_string()))?;
+    let body = &bytes[BINARY_HEADER_SIZE..];
+    if body.len() < count * BINARY_TRIANGLE_SIZE {
+        return Err(ImportError::Malformed(format!(
+            "STL file declares {count} triangles but holds {}",
+            body.len() / BINARY_TRIANGLE_SIZE
+        )));
+    }
+    Ok(body
+        .chunks_exact(BINARY_TRIANGLE_SIZE)
+        .take(count)
+        .map(|triangle| {
+            let vector = |index: usize| {
+                let float = |offset: usize| {
+                    let start = index * 12 + offset * 4;
+                    f32::from_le_bytes(triangle[start..start + 4].try_into().unwrap())
+                };
+                Vec3::new(float(0), float(1), float(2))
+            };
+            (vector(0), [vector(1), vector(2), vector(3)])
+        })
+        .collect())
+}
+
+/// Reads the facet normal and vertices of every triangle of an ASCII file.
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<(Vec3, [Vec3; 3])>, ImportError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| ImportError::Malformed("ASCII STL file is not UTF-8".to_string()))?;
+    let mut triangles = Vec::new();
+    let mut facet: Option<(Vec3, Vec<Vec3>)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let error =
+            |message: &str| ImportError::Malformed(format!("line {}: {message}", index + 1));
+        let mut fields = line.split_whitespace();
+        let vector = |fields: &mut std::str::SplitWhitespace| -> Result<Vec3, ImportError> {
+            let mut component = || {
+                fields
+                    .next()
+                    .and_then(|field| field.parse::<f32>().ok())
+                    .ok_or_else(|| error("expected three numbers"))
+            };
+            Ok(Vec3::new(component()?, component()?, component()?))
+        };
+        match fields.next() {
+            Some("facet") => {
+                if facet.is_some() {
+                    return Err(error("facet inside a facet"));
+                }
+                let normal = match fields.next() {
+                    Some("normal") => vector(&mut fields)?,
+                    _ => Vec3::ZERO,
+                };
+                facet = Some((normal, Vec::with_capacity(3)));
+            }
+            Some("vertex") => {
+                let vertex = vector(&mut fields)?;
+                facet
+                    .as_mut()
+                    .ok_or_else(|| error("vertex outside a facet"))?
+                    .1
+                    .push(vertex);
+            }
+            Some("endfacet") => {
+                let (normal, corners) = facet
+                    .take()
+                    .ok_or_else(|| error("endfacet without facet"))?;
+                let corners: [Vec3; 3] = corners
+                    .try_into()
+                    .map_err(|_| error("facet doesn't have three vertices"))?;
+                triangles.push((normal, corners));
+            }
+            Some("solid" | "endsolid" | "outer" | "endloop") | None => {}
+            Some(keyword) => return Err(error(&format!("unknown keyword \"{keyword}\""))),
+        }
+    }
+    if facet.is_some() {
+        return Err(ImportError::Malformed(
+            "STL file ends inside a facet".to_string(),
+        ));
+    }
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_stl;
+    use crate::renderer::{error::ImportError, vertex_layout::VertexSemantic};
+
+    #[test]
+    fn test_parse_ascii_and_binary_stl() {
+        let ascii = "solid part\n facet normal 0 0 0\n  outer loop\n   vertex 0 0 0\n   \
+                     vertex 1 0 0\n   vertex 0 1 0\n  endloop\n endfacet\nendsolid part\n";
+        let mut binary = b"solid exported as binary".to_vec();
+        binary.resize(80, 0);
+        binary.extend(1u32.to_le_bytes());
+        for value in [
+            0.0f32, 0.0, 2.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend(value.to_le_bytes());
+        }
+        binary.extend(0u16.to_le_bytes());
+
+        for bytes in [ascii.as_bytes(), &binary] {
+            let mesh = parse_stl(bytes).unwrap();
+            let positions: Vec<_> = mesh.data.vertices.iter().map(|v| v.position).collect();
+            assert_eq!(
+                positions,
+                [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+            );
+            // Zero normals are computed, and written ones normalized
+            let normals = &mesh.streams[0];
+            assert_eq!(normals.semantic, VertexSemantic::Normal);
+            assert_eq!(normals.values, vec![[0.0, 0.0, 1.0, 0.0]; 3]);
+        }
+
+        binary.truncate(100);
+        assert!(matches!(parse_stl(&binary), Err(ImportError::Malformed(_))));
+        let unfinished = "solid\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nendloop\nendfacet";
+        assert!(matches!(
+            parse_stl(unfinished.as_bytes()),
+            Err(ImportError::Malformed(message)) if message.starts_with("line 6")
+        ));
+    }
+}
@@ -0,0 +1,400 @@
+//! World streaming module for the renderer.
+//!
+//! This module provides `WorldStreamer`, which divides the world into a grid
+//! of cubic chunks, such as cells of objects or terrain tiles, and keeps the
+//! chunks around the camera loaded. Chunks are loaded on a background thread
+//! by a loader the application provides, nearest first, and unloaded once
+//! the camera moves away. Chunks load within one radius and unload beyond a
+//! larger one, so a camera moving back and forth across a chunk border
+//! doesn't load and unload it every frame.
+//!
+//! The streamer reports loads and unloads as `StreamEvent`s, and the
+//! application adds and removes the chunks' content, e.g. adding static
+//! meshes, whose uploads the renderer spreads over frames, and scene nodes.
+
+use glam::{IVec3, Vec3};
+use log::{debug, error};
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+/// The number of chunks loading at once by default.
+const DEFAULT_MAX_LOADING: usize = 4;
+
+/// The unload radius by default, relative to the load radius.
+const DEFAULT_UNLOAD_SCALE: f32 = 1.25;
+
+/// The coordinates of a chunk in the streaming grid, in chunks from the origin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkCoord(pub IVec3);
+
+/// A change to the loaded chunks, reported by `WorldStreamer::update`.
+#[derive(Debug, PartialEq)]
+pub enum StreamEvent<T> {
+    /// A chunk near the camera finished loading.
+    Loaded { chunk: ChunkCoord, content: T },
+    /// A loaded chunk is beyond the unload radius, and its content should be removed.
+    Unloaded(ChunkCoord),
+    /// A chunk's loader failed. The chunk is retried once the camera leaves and returns.
+    Failed { chunk: ChunkCoord, message: String },
+}
+
+/// The state of a chunk within the unload radius.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChunkState {
+    Loading,
+    Loaded,
+    Failed,
+}
+
+/// Loads and unloads the chunks of a world around the camera.
+pub struct WorldStreamer<T> {
+    chunk_size: f32,
+    load_radius: f32,
+    unload_radius: f32,
+    /// Whether chunks only span the XZ plane, with every chunk at a Y of 0.
+    flat: bool,
+    max_loading: usize,
+    jobs: Sender<ChunkCoord>,
+    loaded: Receiver<(ChunkCoord, Result<T, String>)>,
+    chunks: HashMap<ChunkCoord, ChunkState>,
+}
+
+impl<T: Send + 'static> WorldStreamer<T> {
+    /// Creates a new `WorldStreamer`, starting its loader thread.
+    ///
+    /// Chunks unload beyond 1.25 times the load radius unless set with
+    /// `with_unload_radius`.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk_size` - The edge length of a chunk in world units.
+    /// * `load_radius` - The distance from the camera within which chunks are loaded.
+    /// * `loader` - Produces a chunk's content, run on the loader thread.
+    pub fn new(
+        chunk_size: f32,
+        load_radius: f32,
+        mut loader: impl FnMut(ChunkCoord) -> Result<T, String> + Send + 'static,
+    ) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<ChunkCoord>();
+        let (loaded_sender, loaded) = mpsc::channel();
+        thread::spawn(move || {
+            for chunk in job_receiver {
+                if loaded_sender.send((chunk, loader(chunk))).is_err() {
+                    break;
+                }
+            }
+        });
+        debug!("World streamer started with {chunk_size} unit chunks");
+
+        let load_radius = load_radius.max(0.0);
+        WorldStreamer {
+            chunk_size: chunk_size.max(f32::EPSILON),
+            load_radius,
+            unload_radius: load_radius * DEFAULT_UNLOAD_SCALE,
+            flat: false,
+            max_loading: DEFAULT_MAX_LOADING,
+            jobs,
+            loaded,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Sets the distance beyond which loaded chunks unload, at least the load radius.
+    pub fn with_unload_radius(mut self, unload_radius: f32) -> Self {
+        self.unload_radius = unload_radius.max(self.load_radius);
+        self
+    }
+
+    /// Streams chunks across the XZ plane only, such as terrain tiles,
+    /// ignoring the camera's height.
+    pub fn flat(mut self) -> Self {
+        self.flat = true;
+        self
+    }
+
+    /// Sets the number of chunks loading at once, at least one.
+    ///
+    /// Fewer chunks in flight keep the loader working on the chunks nearest
+    /// a moving camera rather than a backlog it has left behind.
+    pub fn with_max_loading(mut self, max_loading: usize) -> Self {
+        self.max_loading = max_loading.max(1);
+        self
+    }
+
+    /// Returns the chunk containing a position.
+    pub fn chunk_at(&self, position: Vec3) -> ChunkCoord {
+        let mut coord = (position / self.chunk_size).floor().as_ivec3();
+        if self.flat {
+            coord.y = 0;
+        }
+        ChunkCoord(coord)
+    }
+
+    /// Returns `true` if a chunk's content has loaded and not unloaded since.
+    pub fn is_loaded(&self, chunk: ChunkCoord) -> bool {
+        self.chunks.get(&chunk) == Some(&ChunkState::Loaded)
+    }
+
+    /// Returns the number of loaded chunks.
+    pub fn loaded_count(&self) -> usize {
+        self.count(ChunkState::Loaded)
+    }
+
+    /// Returns the number of chunks on the loader thread.
+    pub fn loading_count(&self) -> usize {
+        self.count(ChunkState::Loading)
+    }
+
+    /// Streams chunks around the camera, typically called once per frame.
+    ///
+    /// Collects the chunks the loader finished since the last update,
+    /// unloads chunks beyond the unload radius and queues the nearest
+    /// unloaded chunks within the load radius.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The camera's position in world space.
+    ///
+    /// # Returns
+    ///
+    /// The chunks loaded, unloaded and failed since the last update.
+    pub fn update(&mut self, position: Vec3) -> Vec<StreamEvent<T>> {
+        let mut events = Vec::new();
+        let completed: Vec<_> = self.loaded.try_iter().collect();
+        for (chunk, result) in completed {
+            self.complete(chunk, result, &mut events);
+        }
+
+        let unload_radius = self.unload_radius;
+        let mut unloaded = Vec::new();
+        self.chunks.retain(|&chunk, state| {
+            let keep =
+                distance_to_chunk(position, chunk, self.chunk_size, self.flat) <= unload_radius;
+            if !keep && *state == ChunkState::Loaded {
+                unloaded.push(chunk);
+            }
+            keep
+        });
+        for chunk in unloaded {
+            debug!("Unloading chunk {:?}", chunk.0);
+            events.push(StreamEvent::Unloaded(chunk));
+        }
+
+        self.request_chunks(position);
+        events
+    }
+
+    /// Blocks until every chunk on the loader thread has loaded, e.g.
+    /// behind a loading screen before the first frame.
+    ///
+    /// # Returns
+    ///
+    /// The chunks loaded and failed, to be handled like those of `update`.
+    pub fn wait_for_loads(&mut self) -> Vec<StreamEvent<T>> {
+        let mut events = Vec::new();
+        while self.loading_count() > 0 {
+            let Ok((chunk, result)) = self.loaded.recv() else {
+                error!("The chunk loader thread has stopped");
+                break;
+            };
+            self.complete(chunk, result, &mut events);
+        }
+        events
+    }
+
+    /// Records a chunk the loader finished, reporting it unless it was
+    /// unloaded while loading.
+    fn complete(
+        &mut self,
+        chunk: ChunkCoord,
+        result: Result<T, String>,
+        events: &mut Vec<StreamEvent<T>>,
+    ) {
+        let Some(state) = self.chunks.get_mut(&chunk) else {
+            return;
+        };
+        if *state != ChunkState::Loading {
+            return;
+        }
+        match result {
+            Ok(content) => {
+                debug!("Loaded chunk {:?}", chunk.0);
+                *state = ChunkState::Loaded;
+                events.push(StreamEvent::Loaded { chunk, content });
+            }
+            Err(message) => {
+                *state = ChunkState::Failed;
+                events.push(StreamEvent::Failed { chunk, message });
+            }
+        }
+    }
+
+    /// Queues the nearest chunks within the load radius that have no state,
+    /// up to the number loading at once.
+    fn request_chunks(&mut self, position: Vec3) {
+        let free = self.max_loading.saturating_sub(self.loading_count());
+        if free == 0 {
+            return;
+        }
+
+        let reach = Vec3::splat(self.load_radius);
+        let (ChunkCoord(min), ChunkCoord(max)) = (
+            self.chunk_at(position - reach),
+            self.chunk_at(position + reach),
+        );
+        let mut candidates = Vec::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let chunk = ChunkCoord(IVec3::new(x, y, z));
+                    if self.chunks.contains_key(&chunk) {
+                        continue;
+                    }
+                    let distance = distance_to_chunk(position, chunk, self.chunk_size, self.flat);
+                    if distance <= self.load_radius {
+                        candidates.push((distance, chunk));
+                    }
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (_, chunk) in candidates.into_iter().take(free) {
+            if self.jobs.send(chunk).is_err() {
+                error!("The chunk loader thread has stopped, not loading chunks");
+                return;
+            }
+            self.chunks.insert(chunk, ChunkState::Loading);
+        }
+    }
+
+    fn count(&self, state: ChunkState) -> usize {
+        self.chunks
+            .values()
+            .filter(|&&other| other == state)
+            .count()
+    }
+}
+
+/// Returns the distance from a position to the nearest point of a chunk.
+///
+/// Flat chunks are measured across the XZ plane only.
+fn distance_to_chunk(position: Vec3, chunk: ChunkCoord, chunk_size: f32, flat: bool) -> f32 {
+    let min = chunk.0.as_vec3() * chunk_size;
+    let mut offset = position - position.clamp(min, min + chunk_size);
+    if flat {
+        offset.y = 0.0;
+    }
+    offset.length()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_to_chunk, ChunkCoord, StreamEvent, WorldStreamer};
+    use glam::{IVec3, Vec3};
+
+    fn loaded_chunks(events: &[StreamEvent<IVec3>]) -> Vec<IVec3> {
+        let mut chunks: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::Loaded { chunk, content } => {
+                    assert_eq!(chunk.0, *content);
+                    Some(*content)
+                }
+                _ => None,
+            })
+            .collect();
+        chunks.sort_by_key(|chunk| chunk.to_array());
+        chunks
+    }
+
+    #[test]
+    fn test_distance_to_chunk() {
+        let chunk = ChunkCoord(IVec3::new(1, 0, 0));
+        assert_eq!(
+            distance_to_chunk(Vec3::new(15.0, 5.0, 5.0), chunk, 10.0, false),
+            0.0
+        );
+        assert_eq!(
+            distance_to_chunk(Vec3::new(5.0, 5.0, 5.0), chunk, 10.0, false),
+            5.0
+        );
+        // Flat chunks ignore the height
+        assert_eq!(
+            distance_to_chunk(Vec3::new(15.0, 50.0, 5.0), chunk, 10.0, true),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_world_streamer_hysteresis() {
+        let mut streamer = WorldStreamer::new(10.0, 4.0, |chunk: ChunkCoord| Ok(chunk.0))
+            .with_unload_radius(8.0)
+            .flat();
+        assert_eq!(
+            streamer.chunk_at(Vec3::new(-0.5, 30.0, 12.0)).0,
+            IVec3::new(-1, 0, 1)
+        );
+
+        // Near a corner, the four chunks around it load
+        let corner = Vec3::new(9.0, 0.0, 9.0);
+        assert!(streamer.update(corner).is_empty());
+        assert_eq!(streamer.loading_count(), 4);
+        let events = streamer.wait_for_loads();
+        assert_eq!(
+            loaded_chunks(&events),
+            [
+                IVec3::new(0, 0, 0),
+                IVec3::new(0, 0, 1),
+                IVec3::new(1, 0, 0),
+                IVec3::new(1, 0, 1),
+            ]
+        );
+        assert_eq!(streamer.loaded_count(), 4);
+
+        // Moving into the next chunk keeps the old ones within the unload radius
+        let inside = Vec3::new(15.0, 0.0, 5.0);
+        assert!(streamer.update(inside).is_empty());
+        assert_eq!(streamer.loaded_count(), 4);
+
+        // Moving further unloads them
+        let far = Vec3::new(25.0, 0.0, 5.0);
+        let events = streamer.update(far);
+        let mut unloaded: Vec<_> = events
+            .iter()
+            .map(|event| match event {
+                StreamEvent::Unloaded(chunk) => chunk.0.to_array(),
+                event => panic!("unexpected {event:?}"),
+            })
+            .collect();
+        unloaded.sort();
+        assert_eq!(unloaded, [[0, 0, 0], [0, 0, 1]]);
+        assert!(streamer.is_loaded(ChunkCoord(IVec3::new(1, 0, 0))));
+        assert_eq!(
+            loaded_chunks(&streamer.wait_for_loads()),
+            [IVec3::new(2, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_world_streamer_failures() {
+        let mut streamer = WorldStreamer::new(10.0, 1.0, |chunk: ChunkCoord| {
+            Err::<(), _>(format!("no tile at {}", chunk.0))
+        });
+        streamer.update(Vec3::splat(5.0));
+        let events = streamer.wait_for_loads();
+        assert_eq!(
+            events,
+            [StreamEvent::Failed {
+                chunk: ChunkCoord(IVec3::ZERO),
+                message: "no tile at [0, 0, 0]".to_string(),
+            }]
+        );
+        // Failed chunks aren't retried while the camera stays near
+        streamer.update(Vec3::splat(5.0));
+        assert_eq!(streamer.loading_count(), 0);
+    }
+}
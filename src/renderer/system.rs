@@ -0,0 +1,659 @@
+//! System module for the renderer.
+//!
+//! This module provides `RendererSystem`, which opens a winit window, creates
+//! a `Renderer` drawing into it and runs the event loop: it feeds keyboard,
+//! mouse and touch input to the renderer, records or plays back replays, and
+//! calls the render callback every frame. The cursor is captured by the
+//! camera, and released for clicking nodes while the editor is enabled. Where a `DisplayLink` is available,
+//! frames are rendered when the display refreshes instead of whenever the
+//! event loop is idle. Rendering pauses while the window is minimized or
+//! covered. It is only built with the `windowing` feature; applications
+//! running their own event loop create a `Renderer` for their window
+//! directly.
+
+use super::{
+    config::RendererConfig,
+    display_link::{DisplayFrame, DisplayLink},
+    error::{error_chain, BackendError, ReplayError},
+    render_core::Renderer,
+    replay::{InputEvent, Replay},
+    touch::TouchGestures,
+    RendererError,
+};
+use glam::{UVec2, Vec2};
+use log::{error, info, warn};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Instant,
+};
+use winit::{
+    event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, Touch, WindowEvent},
+    event_loop::{EventLoop, EventLoopBuilder},
+    keyboard::PhysicalKey,
+    window::{CursorGrabMode, Window, WindowBuilder},
+};
+
+/// Custom events delivered through the renderer's event loop.
+#[derive(Debug)]
+pub enum RendererEvent {
+    /// An error that did not stop rendering, passed to the error callback.
+    Error(RendererError),
+    /// The display link fired, so the next frame can be rendered.
+    DisplayFrame(DisplayFrame),
+}
+
+/// Whether the event loop records or plays back a replay.
+#[derive(Default)]
+enum ReplayMode {
+    #[default]
+    Off,
+    Recording(PathBuf, Replay),
+    Playing(Replay),
+}
+
+impl ReplayMode {
+    /// Handles live input, recording it if needed. Live input is ignored
+    /// during playback.
+    fn handle_input(&mut self, renderer: &mut Renderer, input: InputEvent) {
+        match self {
+            ReplayMode::Off => {}
+            ReplayMode::Recording(_, replay) => {
+                replay.record_input(input, renderer.input_bindings())
+            }
+            ReplayMode::Playing(_) => return,
+        }
+        renderer.handle_input(input);
+    }
+
+    /// Advances the renderer's clock to the next frame, feeding the frame's
+    /// input first during playback.
+    fn advance_frame(&mut self, renderer: &mut Renderer) {
+        if let ReplayMode::Playing(replay) = self {
+            match replay.next_frame() {
+                Some((inputs, delta)) => {
+                    for input in inputs {
+                        renderer.handle_input(input);
+                    }
+                    renderer.time_mut().advance(delta);
+                    return;
+                }
+                None => {
+                    info!("Replay finished, resuming live input");
+                    *self = ReplayMode::Off;
+                }
+            }
+        }
+
+        renderer.tick_time();
+        if let ReplayMode::Recording(_, replay) = self {
+            replay.record_frame(renderer.time().unscaled_delta());
+        }
+    }
+
+    /// Saves the replay being recorded, if any.
+    fn finish(&mut self) -> Result<(), ReplayError> {
+        match std::mem::take(self) {
+            ReplayMode::Recording(path, replay) => replay.save(&path),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A change of the window's focus or visibility, passed to the window callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowChange {
+    /// The window gained keyboard focus.
+    Focused,
+    /// The window lost keyboard focus.
+    Unfocused,
+    /// The window was minimized or fully covered, so rendering paused.
+    Hidden,
+    /// The window became visible again, so rendering resumed.
+    Shown,
+}
+
+/// Tracks whether the window can be seen, which rendering is paused without.
+#[derive(Clone, Copy, Debug, Default)]
+struct Visibility {
+    /// Whether other windows cover the window, or it is on another space.
+    occluded: bool,
+    /// Whether the window is minimized, which some platforms report as a zero size.
+    minimized: bool,
+}
+
+impl Visibility {
+    /// Returns `true` if nothing of the window can be seen.
+    fn is_hidden(&self) -> bool {
+        self.occluded || self.minimized
+    }
+
+    /// Updates the visibility and returns the change, if the window was hidden or shown.
+    fn update(&mut self, occluded: bool, minimized: bool) -> Option<WindowChange> {
+        let was_hidden = self.is_hidden();
+        *self = Visibility {
+            occluded,
+            minimized,
+        };
+        match (was_hidden, self.is_hidden()) {
+            (false, true) => Some(WindowChange::Hidden),
+            (true, false) => Some(WindowChange::Shown),
+            _ => None,
+        }
+    }
+}
+
+/// Pauses or resumes frames when the window is hidden or shown, and passes the change on.
+fn window_changed(
+    change: WindowChange,
+    display_link: Option<&DisplayLink>,
+    window_callback: &WindowCallback,
+) {
+    info!("Window {change:?}");
+    match (change, display_link) {
+        (WindowChange::Hidden, Some(display_link)) => display_link.pause(),
+        (WindowChange::Shown, Some(display_link)) => display_link.resume(),
+        _ => {}
+    }
+    window_callback(change);
+}
+
+/// Captures the cursor for the camera, hiding it, or releases and shows it.
+fn capture_cursor(window: &Window, captured: bool) {
+    let result = if captured {
+        window
+            .set_cursor_grab(CursorGrabMode::Confined)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+    if let Err(e) = result {
+        warn!("Mouse capture is not supported: {e}");
+    }
+    window.set_cursor_visible(!captured);
+}
+
+/// Releases the cursor while the editor is enabled and captures it otherwise.
+fn sync_cursor(window: &Window, cursor_captured: &mut bool, renderer: &Renderer) {
+    let captured = !renderer.editor().is_enabled();
+    if captured != *cursor_captured {
+        *cursor_captured = captured;
+        capture_cursor(window, captured);
+    }
+}
+
+pub type RenderCallback = dyn Fn(&mut Renderer) -> Result<(), RendererError>;
+pub type ErrorCallback = dyn Fn(&RendererError);
+pub type WindowCallback = dyn Fn(WindowChange);
+
+pub struct RendererSystem {
+    renderer: Rc<RefCell<Renderer>>,
+    window: Window,
+    event_loop: EventLoop<RendererEvent>,
+    render_callback: Box<RenderCallback>,
+    error_callback: Box<ErrorCallback>,
+    window_callback: Box<WindowCallback>,
+    visibility: Visibility,
+    replay: ReplayMode,
+    /// When the last key event arrived, which scales camera movement.
+    last_key_time: Instant,
+    touches: TouchGestures,
+}
+
+/// Builds a `RendererSystem` from a `RendererConfig`.
+///
+/// # Example
+///
+/// ```ignore
+/// let config = RendererConfig::from_file("engine.toml")?;
+/// let renderer_system = RendererSystem::builder().with_config(config).build()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RendererSystemBuilder {
+    config: RendererConfig,
+}
+
+impl RendererSystemBuilder {
+    /// Creates a new `RendererSystemBuilder` with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets every option, including the window's, e.g. to those of a settings file.
+    pub fn with_config(mut self, config: RendererConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the size of the window in logical pixels.
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.config.window.width = width;
+        self.config.window.height = height;
+        self
+    }
+
+    /// Sets the title of the window.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.config.window.title = title.into();
+        self
+    }
+
+    /// Opens the window and creates the renderer drawing into it.
+    pub fn build(self) -> Result<RendererSystem, RendererError> {
+        let window_config = &self.config.window;
+        let event_loop = EventLoopBuilder::with_user_event()
+            .build()
+            .map_err(BackendError::EventLoopFailed)?;
+
+        let window = WindowBuilder::new()
+            .with_title(&window_config.title)
+            .with_inner_size(winit::dpi::LogicalSize::new(
+                window_config.width,
+                window_config.height,
+            ))
+            .with_resizable(window_config.resizable)
+            .build(&event_loop)
+            .map_err(BackendError::WindowCreationFailed)?;
+
+        let size = UVec2::new(window.inner_size().width, window.inner_size().height);
+        let mut renderer = Renderer::new(&window, size, &self.config)?;
+        renderer.set_scale_factor(window.scale_factor(), size);
+        let event_proxy = event_loop.create_proxy();
+        renderer.set_error_handler(move |error| {
+            if let Err(closed) = event_proxy.send_event(RendererEvent::Error(error)) {
+                // The event loop has exited, so nothing is left to deliver to
+                if let RendererEvent::Error(error) = closed.0 {
+                    warn!("Error reported after the event loop exited: {error}");
+                }
+            }
+        });
+        info!(
+            "Initializing renderer system with {}x{} window",
+            window_config.width, window_config.height
+        );
+
+        Ok(RendererSystem {
+            renderer: Rc::new(RefCell::new(renderer)),
+            window,
+            event_loop,
+            render_callback: Box::new(|_| Ok(())), // Default no-op callback
+            error_callback: Box::new(|e| error!("{}", error_chain(e))),
+            window_callback: Box::new(|_| {}),
+            visibility: Visibility::default(),
+            replay: ReplayMode::Off,
+            last_key_time: Instant::now(),
+            touches: TouchGestures::new(),
+        })
+    }
+}
+
+impl RendererSystem {
+    pub fn new(width: u32, height: u32, title: &str) -> Result<Self, RendererError> {
+        Self::builder()
+            .with_size(width, height)
+            .with_title(title)
+            .build()
+    }
+
+    /// Returns a builder for a `RendererSystem` with the default options.
+    pub fn builder() -> RendererSystemBuilder {
+        RendererSystemBuilder::new()
+    }
+
+    /// Creates a new `RendererSystem` whose renderer uses the given options.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the window in logical pixels.
+    /// * `height` - The height of the window in logical pixels.
+    /// * `title` - The title of the window.
+    /// * `config` - The options the renderer is created with.
+    pub fn with_config(
+        width: u32,
+        height: u32,
+        title: &str,
+        config: &RendererConfig,
+    ) -> Result<Self, RendererError> {
+        Self::builder()
+            .with_config(config.clone())
+            .with_size(width, height)
+            .with_title(title)
+            .build()
+    }
+
+    pub fn set_render_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&mut Renderer) -> Result<(), RendererError> + 'static,
+    {
+        self.render_callback = Box::new(callback);
+    }
+
+    /// Sets the callback receiving errors that did not stop rendering.
+    ///
+    /// This includes errors returned by the render callback and errors passed
+    /// to `Renderer::report_error`. By default, errors are logged.
+    pub fn set_error_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&RendererError) + 'static,
+    {
+        self.error_callback = Box::new(callback);
+    }
+
+    /// Sets the callback receiving changes of the window's focus and visibility.
+    ///
+    /// Rendering pauses while the window is hidden, i.e. minimized or fully
+    /// covered, so games can also pause their simulation and audio. On
+    /// macOS, the window is hidden according to `NSWindow`'s occlusion state.
+    pub fn set_window_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(WindowChange) + 'static,
+    {
+        self.window_callback = Box::new(callback);
+    }
+
+    /// Records input and frame timing from the start of `run`.
+    ///
+    /// The replay is saved when the event loop exits.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to save the replay to.
+    pub fn record_replay(&mut self, path: impl Into<PathBuf>) {
+        self.replay = ReplayMode::Recording(path.into(), Replay::new());
+    }
+
+    /// Plays back a replay from the start of `run`.
+    ///
+    /// Recorded input and frame durations replace live input and the wall
+    /// clock until the replay ends. Starting from the same scene, the run
+    /// reproduces the recorded one exactly.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A replay saved by `record_replay`.
+    pub fn play_replay(&mut self, path: impl AsRef<Path>) -> Result<(), RendererError> {
+        self.replay = ReplayMode::Playing(Replay::load(path.as_ref())?);
+        Ok(())
+    }
+
+    pub fn run(mut self) -> Result<(), RendererError> {
+        let window_size = self.window.inner_size();
+        let center_x = window_size.width as f64 / 2.0;
+        let center_y = window_size.height as f64 / 2.0;
+
+        // Enable mouse capture, which touch screens have no cursor for
+        let mut cursor_captured = true;
+        capture_cursor(&self.window, cursor_captured);
+        let mut cursor_position = Vec2::ZERO;
+        let mut shift = false;
+
+        // Render when the display refreshes, rather than as often as the event loop spins
+        let event_proxy = self.event_loop.create_proxy();
+        let display_link = match DisplayLink::new(move |frame| {
+            // Frames arriving after the event loop exited are dropped
+            let _ = event_proxy.send_event(RendererEvent::DisplayFrame(frame));
+        }) {
+            Ok(display_link) => Some(display_link),
+            Err(e) => {
+                info!("Rendering without a display link: {e}");
+                None
+            }
+        };
+
+        self.event_loop
+            .run(move |event, event_loop_window_target| {
+                match event {
+                    Event::WindowEvent { event, .. } => match event {
+                        WindowEvent::CloseRequested => event_loop_window_target.exit(),
+                        WindowEvent::Resized(new_size) => {
+                            let minimized = new_size.width == 0 || new_size.height == 0;
+                            let occluded = self.visibility.occluded;
+                            if let Some(change) = self.visibility.update(occluded, minimized) {
+                                window_changed(
+                                    change,
+                                    display_link.as_ref(),
+                                    &self.window_callback,
+                                );
+                            }
+                            if !minimized {
+                                self.renderer
+                                    .borrow_mut()
+                                    .resize(UVec2::new(new_size.width, new_size.height));
+                            }
+                        }
+                        WindowEvent::Occluded(occluded) => {
+                            let minimized = self.visibility.minimized;
+                            if let Some(change) = self.visibility.update(occluded, minimized) {
+                                window_changed(
+                                    change,
+                                    display_link.as_ref(),
+                                    &self.window_callback,
+                                );
+                            }
+                        }
+                        WindowEvent::Focused(focused) => {
+                            let change = if focused {
+                                WindowChange::Focused
+                            } else {
+                                WindowChange::Unfocused
+                            };
+                            window_changed(change, display_link.as_ref(), &self.window_callback);
+                        }
+                        // Moving between displays of different pixel densities
+                        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                            let size = self.window.inner_size();
+                            self.renderer.borrow_mut().set_scale_factor(
+                                scale_factor,
+                                UVec2::new(size.width, size.height),
+                            );
+                        }
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    physical_key,
+                                    state,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            let mut renderer = self.renderer.borrow_mut();
+                            let mut delta_time = self.last_key_time.elapsed().as_secs_f32();
+                            delta_time = delta_time.min(0.1); // Cap delta_time to 0.1 seconds
+                            self.last_key_time = Instant::now();
+
+                            if let PhysicalKey::Code(code) = physical_key {
+                                self.replay.handle_input(
+                                    &mut renderer,
+                                    InputEvent::Key {
+                                        code,
+                                        pressed: state == ElementState::Pressed,
+                                        delta_time,
+                                    },
+                                );
+                            }
+                            sync_cursor(&self.window, &mut cursor_captured, &renderer);
+                        }
+                        WindowEvent::ModifiersChanged(modifiers) => {
+                            shift = modifiers.state().shift_key();
+                        }
+
+                        // The editor released the cursor to click nodes
+                        WindowEvent::CursorMoved { position, .. } if !cursor_captured => {
+                            cursor_position = Vec2::new(position.x as f32, position.y as f32);
+                        }
+                        WindowEvent::MouseInput {
+                            state: ElementState::Pressed,
+                            button: MouseButton::Left,
+                            ..
+                        } if !cursor_captured => {
+                            let mut renderer = self.renderer.borrow_mut();
+                            self.replay.handle_input(
+                                &mut renderer,
+                                InputEvent::Click {
+                                    x: cursor_position.x,
+                                    y: cursor_position.y,
+                                    toggle: shift,
+                                },
+                            );
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            let mut renderer = self.renderer.borrow_mut();
+
+                            let delta_x = position.x - center_x;
+                            let delta_y = center_y - position.y; // Reversed since y-coordinates go from bottom to top
+                            self.replay.handle_input(
+                                &mut renderer,
+                                InputEvent::MouseMotion {
+                                    delta_x: delta_x as f32,
+                                    delta_y: delta_y as f32,
+                                },
+                            );
+
+                            // Reset cursor position to center
+                            self.window
+                                .set_cursor_position(winit::dpi::PhysicalPosition::new(
+                                    center_x, center_y,
+                                ))
+                                .unwrap();
+                        }
+                        WindowEvent::Touch(Touch {
+                            id,
+                            phase,
+                            location,
+                            ..
+                        }) => {
+                            let position = Vec2::new(location.x as f32, location.y as f32);
+                            if let Some(input) = self.touches.handle(id, phase, position) {
+                                let mut renderer = self.renderer.borrow_mut();
+                                self.replay.handle_input(&mut renderer, input);
+                            }
+                        }
+                        WindowEvent::MouseWheel { delta, .. } => {
+                            let mut renderer = self.renderer.borrow_mut();
+                            let delta = match delta {
+                                MouseScrollDelta::LineDelta(_, y) => y,
+                                MouseScrollDelta::PixelDelta(position) => position.y as f32 * 0.1,
+                            };
+                            self.replay
+                                .handle_input(&mut renderer, InputEvent::Scroll { delta });
+                        }
+                        // Hidden windows are not rendered to
+                        WindowEvent::RedrawRequested if !self.visibility.is_hidden() => {
+                            let mut renderer = self.renderer.borrow_mut();
+                            self.replay.advance_frame(&mut renderer);
+                            // Replayed input can toggle the editor too
+                            sync_cursor(&self.window, &mut cursor_captured, &renderer);
+
+                            // Draw objects
+                            if let Err(e) = (self.render_callback)(&mut renderer) {
+                                renderer.report_error(e);
+                            }
+                        }
+                        _ => {}
+                    },
+                    Event::UserEvent(RendererEvent::Error(error)) => {
+                        (self.error_callback)(&error);
+                    }
+                    Event::UserEvent(RendererEvent::DisplayFrame(frame)) => {
+                        let mut renderer = self.renderer.borrow_mut();
+                        if !self.visibility.is_hidden() && renderer.frame_due(&frame) {
+                            renderer.set_display_frame(frame);
+                            self.window.request_redraw();
+                        }
+                    }
+                    // Without a display link or vsync, frames render back to back
+                    Event::AboutToWait
+                        if !self.visibility.is_hidden()
+                            && (display_link.is_none() || !self.renderer.borrow().vsync()) =>
+                    {
+                        self.window.request_redraw();
+                    }
+                    Event::LoopExiting => {
+                        if let Err(e) = self.replay.finish() {
+                            (self.error_callback)(&e.into());
+                        }
+                    }
+                    _ => {}
+                }
+            })
+            .map_err(|e| BackendError::EventLoopFailed(e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{window_changed, RendererSystemBuilder, ReplayMode, Visibility, WindowChange};
+    use crate::renderer::{config::RendererConfig, replay::Replay};
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn test_visibility() {
+        let mut visibility = Visibility::default();
+        assert_eq!(visibility.update(true, false), Some(WindowChange::Hidden));
+
+        // Minimizing an occluded window leaves it hidden
+        assert_eq!(visibility.update(true, true), None);
+        assert_eq!(visibility.update(false, true), None);
+        assert!(visibility.is_hidden());
+        assert_eq!(visibility.update(false, false), Some(WindowChange::Shown));
+        assert_eq!(visibility.update(false, false), None);
+    }
+
+    #[test]
+    fn test_visibility_minimized() {
+        let mut visibility = Visibility::default();
+        assert!(!visibility.is_hidden());
+        assert_eq!(visibility.update(false, true), Some(WindowChange::Hidden));
+        assert!(visibility.is_hidden());
+        assert_eq!(visibility.update(true, false), None);
+        assert_eq!(visibility.update(false, false), Some(WindowChange::Shown));
+    }
+
+    #[test]
+    fn test_window_changed_without_display_link() {
+        let changes = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&changes);
+        let callback = move |change: WindowChange| {
+            assert_eq!(change, WindowChange::Hidden);
+            counter.set(counter.get() + 1);
+        };
+        window_changed(WindowChange::Hidden, None, &callback);
+        assert_eq!(changes.get(), 1);
+    }
+
+    #[test]
+    fn test_builder_options() {
+        let builder = RendererSystemBuilder::new()
+            .with_size(640, 480)
+            .with_title("Viewer");
+        assert_eq!(builder.config.window.width, 640);
+        assert_eq!(builder.config.window.height, 480);
+        assert_eq!(builder.config.window.title, "Viewer");
+
+        // A config replaces every option set before it
+        let builder = builder.with_config(RendererConfig::default());
+        assert_eq!(builder.config.window, RendererConfig::default().window);
+    }
+
+    #[test]
+    fn test_replay_finish() {
+        let path = std::env::temp_dir().join(format!("system-replay-{}.txt", std::process::id()));
+        let mut replay = Replay::new();
+        replay.record_frame(0.25);
+        replay.record_frame(0.5);
+
+        let mut mode = ReplayMode::Recording(path.clone(), replay.clone());
+        mode.finish().unwrap();
+        assert!(matches!(mode, ReplayMode::Off));
+        assert_eq!(Replay::load(&path).unwrap(), replay);
+        std::fs::remove_file(&path).unwrap();
+
+        // Only recordings are saved
+        let mut mode = ReplayMode::Playing(replay);
+        mode.finish().unwrap();
+        assert!(matches!(mode, ReplayMode::Off));
+        assert!(!path.exists());
+        assert!(ReplayMode::Off.finish().is_ok());
+    }
+}
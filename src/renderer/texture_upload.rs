@@ -0,0 +1,198 @@
+//! Texture upload module for the renderer.
+//!
+//! This module provides `TextureUpload`, the pixels of one region of a
+//! texture uploaded in the background rather than on the render thread. The
+//! pixels are either given up front or produced by a loader, such as reading
+//! and decoding a file, which runs on the backend's loader thread so large
+//! loads never stall a frame.
+//!
+//! Materials referencing a texture show a white placeholder until its
+//! uploads complete.
+
+use super::common::TextureId;
+use metal::{MTLOrigin, MTLRegion, MTLSize};
+use std::fmt;
+
+/// Produces the pixels of an upload on the loader thread, or a message describing why it failed.
+pub type TextureLoader = Box<dyn FnOnce() -> Result<Vec<u8>, String> + Send>;
+
+/// The pixels of an upload.
+pub enum TextureData {
+    Bytes(Vec<u8>),
+    /// Pixels produced on the loader thread.
+    Loader(TextureLoader),
+}
+
+impl fmt::Debug for TextureData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureData::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            TextureData::Loader(_) => write!(f, "Loader"),
+        }
+    }
+}
+
+/// The pixels of a region of a texture, uploaded in the background.
+#[derive(Debug)]
+pub struct TextureUpload {
+    pub texture: TextureId,
+    pub region: MTLRegion,
+    pub mipmap_level: u64,
+    pub slice: u64,
+    pub bytes_per_row: u64,
+    /// The bytes between the images of a 3D region, or 0 for a 2D region.
+    pub bytes_per_image: u64,
+    pub data: TextureData,
+}
+
+impl TextureUpload {
+    /// Creates an upload of the whole first mipmap level of a 2D texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `texture` - The texture to upload into.
+    /// * `width` - The width of the texture in pixels.
+    /// * `height` - The height of the texture in pixels.
+    /// * `bytes_per_row` - The number of bytes per row of pixels.
+    /// * `bytes` - The pixels, in the texture's pixel format.
+    pub fn new(
+        texture: TextureId,
+        width: u64,
+        height: u64,
+        bytes_per_row: u64,
+        bytes: Vec<u8>,
+    ) -> Self {
+        Self::with_data(
+            texture,
+            width,
+            height,
+            bytes_per_row,
+            TextureData::Bytes(bytes),
+        )
+    }
+
+    /// Creates an upload of the whole first mipmap level of a 2D texture,
+    /// whose pixels are produced on the loader thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `texture` - The texture to upload into.
+    /// * `width` - The width of the texture in pixels.
+    /// * `height` - The height of the texture in pixels.
+    /// * `bytes_per_row` - The number of bytes per row of pixels.
+    /// * `loader` - Produces the pixels, in the texture's pixel format.
+    pub fn from_loader(
+        texture: TextureId,
+        width: u64,
+        height: u64,
+        bytes_per_row: u64,
+        loader: impl FnOnce() -> Result<Vec<u8>, String> + Send + 'static,
+    ) -> Self {
+        Self::with_data(
+            texture,
+            width,
+            height,
+            bytes_per_row,
+            TextureData::Loader(Box::new(loader)),
+        )
+    }
+
+    fn with_data(
+        texture: TextureId,
+        width: u64,
+        height: u64,
+        bytes_per_row: u64,
+        data: TextureData,
+    ) -> Self {
+        TextureUpload {
+            texture,
+            region: MTLRegion {
+                origin: MTLOrigin { x: 0, y: 0, z: 0 },
+                size: MTLSize {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            },
+            mipmap_level: 0,
+            slice: 0,
+            bytes_per_row,
+            bytes_per_image: 0,
+            data,
+        }
+    }
+
+    /// Uploads into a region of the texture rather than the whole level.
+    pub fn with_region(mut self, region: MTLRegion, bytes_per_image: u64) -> Self {
+        self.region = region;
+        self.bytes_per_image = bytes_per_image;
+        self
+    }
+
+    /// Uploads into a mipmap level of the texture.
+    pub fn with_mipmap_level(mut self, mipmap_level: u64) -> Self {
+        self.mipmap_level = mipmap_level;
+        self
+    }
+
+    /// Uploads into a slice of an array or cube texture.
+    pub fn with_slice(mut self, slice: u64) -> Self {
+        self.slice = slice;
+        self
+    }
+
+    /// Returns the number of bytes the region's pixels take.
+    pub fn required_bytes(&self) -> u64 {
+        let size = self.region.size;
+        if size.depth > 1 {
+            self.bytes_per_image * size.depth
+        } else {
+            self.bytes_per_row * size.height
+        }
+    }
+
+    /// Produces the pixels of the upload, running its loader if it has one.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the pixels, or a message if the loader failed
+    /// or produced fewer bytes than the region takes.
+    pub fn load(self) -> Result<Vec<u8>, String> {
+        let required = self.required_bytes();
+        let bytes = match self.data {
+            TextureData::Bytes(bytes) => bytes,
+            TextureData::Loader(loader) => loader()?,
+        };
+        if (bytes.len() as u64) < required {
+            return Err(format!(
+                "{} bytes given for a region of {required} bytes",
+                bytes.len()
+            ));
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextureUpload;
+    use crate::renderer::common::TextureId;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn test_texture_upload_load() {
+        let texture = TextureId(NonZeroU32::new(1).unwrap());
+        let upload = TextureUpload::new(texture, 2, 2, 8, vec![255; 16]);
+        assert_eq!(upload.required_bytes(), 16);
+        assert_eq!(upload.load().unwrap().len(), 16);
+
+        // Loaders run when the upload is loaded, and short data is rejected
+        let upload = TextureUpload::from_loader(texture, 2, 2, 8, || Ok(vec![0; 8]));
+        assert_eq!(
+            upload.load(),
+            Err("8 bytes given for a region of 16 bytes".to_string())
+        );
+        let upload = TextureUpload::from_loader(texture, 2, 2, 8, || Err("missing".into()));
+        assert_eq!(upload.load(), Err("missing".to_string()));
+    }
+}
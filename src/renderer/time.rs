@@ -0,0 +1,282 @@
+//! Time module for the renderer.
+//!
+//! This module provides `Time`, the clock driving everything that animates:
+//! the render callback, shader time in the frame constants, and any physics
+//! stepped with its delta. It can be paused, stepped one frame at a time and
+//! scaled for slow motion or fast-forward. Camera input uses wall-clock time,
+//! so the scene can still be inspected while paused.
+
+use log::info;
+use std::time::Instant;
+
+/// Longer frames, e.g. after hitting a breakpoint, are treated as this long.
+const MAX_DELTA: f32 = 0.1;
+
+/// The default simulated duration of a single step while paused.
+const DEFAULT_STEP_DELTA: f32 = 1.0 / 60.0;
+
+/// The time scale range reachable with `slow_down` and `speed_up`.
+const MIN_TIME_SCALE: f32 = 1.0 / 64.0;
+const MAX_TIME_SCALE: f32 = 64.0;
+
+/// A pausable, scalable frame clock.
+#[derive(Clone, Debug)]
+pub struct Time {
+    scale: f32,
+    paused: bool,
+    step_requested: bool,
+    step_delta: f32,
+    delta: f32,
+    unscaled_delta: f32,
+    elapsed: f64,
+    unscaled_elapsed: f64,
+    last_tick: Option<Instant>,
+    /// The display's presentation time of the last frame, in seconds.
+    last_presentation: Option<f64>,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Time {
+    /// Creates a new running `Time` at normal speed.
+    pub fn new() -> Self {
+        Time {
+            scale: 1.0,
+            paused: false,
+            step_requested: false,
+            step_delta: DEFAULT_STEP_DELTA,
+            delta: 0.0,
+            unscaled_delta: 0.0,
+            elapsed: 0.0,
+            unscaled_elapsed: 0.0,
+            last_tick: None,
+            last_presentation: None,
+        }
+    }
+
+    /// Advances the clock to a new frame.
+    ///
+    /// `RendererSystem` ticks once per frame before the render callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time at which the frame starts.
+    pub fn tick(&mut self, now: Instant) {
+        let unscaled_delta = self
+            .last_tick
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last_tick = Some(now);
+        self.last_presentation = None;
+        self.advance(unscaled_delta);
+    }
+
+    /// Advances the clock to a new frame shown at a known presentation time.
+    ///
+    /// Frames driven by a display link last from one presentation to the
+    /// next, which is steadier than the time between ticks. Ticks without a
+    /// newer presentation time fall back to `now`.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time at which the frame starts.
+    /// * `presentation_time` - When the frame will be shown, in seconds on the display's clock.
+    pub fn tick_presented(&mut self, now: Instant, presentation_time: f64) {
+        let unscaled_delta = match (self.last_presentation, self.last_tick) {
+            (Some(last), _) if presentation_time > last => (presentation_time - last) as f32,
+            (_, last_tick) => last_tick.map_or(0.0, |last| now.duration_since(last).as_secs_f32()),
+        };
+        self.last_tick = Some(now);
+        self.last_presentation = Some(presentation_time);
+        self.advance(unscaled_delta);
+    }
+
+    /// Advances the clock to a new frame of a given duration.
+    ///
+    /// Replays use this to reproduce the frame durations of a recorded run.
+    ///
+    /// # Arguments
+    ///
+    /// * `unscaled_delta` - The wall-clock duration of the frame in seconds.
+    pub fn advance(&mut self, unscaled_delta: f32) {
+        self.unscaled_delta = unscaled_delta.min(MAX_DELTA);
+        self.unscaled_elapsed += self.unscaled_delta as f64;
+
+        self.delta = if !self.paused {
+            self.unscaled_delta * self.scale
+        } else if std::mem::take(&mut self.step_requested) {
+            self.step_delta * self.scale
+        } else {
+            0.0
+        };
+        self.elapsed += self.delta as f64;
+    }
+
+    /// Returns the scaled duration of the current frame in seconds, or zero while paused.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Returns the wall-clock duration of the current frame in seconds.
+    pub fn unscaled_delta(&self) -> f32 {
+        self.unscaled_delta
+    }
+
+    /// Returns the scaled time in seconds since the clock started.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Returns the wall-clock time in seconds since the clock started.
+    pub fn unscaled_elapsed(&self) -> f64 {
+        self.unscaled_elapsed
+    }
+
+    /// Returns the time scale, where 1 is normal speed.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets the time scale, e.g. 0.25 for slow motion or 4 for fast-forward.
+    ///
+    /// Negative scales are treated as zero.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+        info!("Time scale set to: {}", self.scale);
+    }
+
+    /// Halves the time scale, down to 1/64.
+    pub fn slow_down(&mut self) {
+        self.set_scale((self.scale * 0.5).max(MIN_TIME_SCALE));
+    }
+
+    /// Doubles the time scale, up to 64.
+    pub fn speed_up(&mut self) {
+        self.set_scale((self.scale * 2.0).min(MAX_TIME_SCALE));
+    }
+
+    /// Returns `true` if the clock is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses the clock, so `delta` is zero until it is resumed or stepped.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        info!("Time paused");
+    }
+
+    /// Resumes the clock after a pause.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.step_requested = false;
+        info!("Time resumed");
+    }
+
+    /// Pauses a running clock or resumes a paused one.
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Advances a paused clock by a single step on the next tick.
+    ///
+    /// The step lasts `step_delta` seconds times the time scale. Does
+    /// nothing while the clock is running.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.step_requested = true;
+        }
+    }
+
+    /// Sets the unscaled duration of a single step in seconds.
+    pub fn set_step_delta(&mut self, step_delta: f32) {
+        self.step_delta = step_delta.max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Time;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_time_scale_and_pause() {
+        let start = Instant::now();
+        let mut time = Time::new();
+        time.tick(start);
+        assert_eq!(time.delta(), 0.0);
+
+        time.set_scale(0.5);
+        time.tick(start + Duration::from_millis(40));
+        assert!((time.delta() - 0.02).abs() < 1e-6);
+        assert!((time.unscaled_delta() - 0.04).abs() < 1e-6);
+
+        time.pause();
+        time.tick(start + Duration::from_millis(80));
+        assert_eq!(time.delta(), 0.0);
+        assert!((time.elapsed() - 0.02).abs() < 1e-6);
+        assert!((time.unscaled_elapsed() - 0.08).abs() < 1e-6);
+
+        // Long frames are clamped
+        time.resume();
+        time.set_scale(1.0);
+        time.tick(start + Duration::from_secs(5));
+        assert!((time.delta() - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_time_slow_down_and_speed_up() {
+        let mut time = Time::new();
+        time.slow_down();
+        assert_eq!(time.scale(), 0.5);
+        for _ in 0..10 {
+            time.slow_down();
+        }
+        assert_eq!(time.scale(), 1.0 / 64.0);
+        for _ in 0..20 {
+            time.speed_up();
+        }
+        assert_eq!(time.scale(), 64.0);
+    }
+
+    #[test]
+    fn test_time_step() {
+        let start = Instant::now();
+        let mut time = Time::new();
+        time.set_step_delta(0.5);
+        time.step();
+        time.tick(start);
+        assert_eq!(time.delta(), 0.0);
+
+        time.pause();
+        time.step();
+        time.tick(start + Duration::from_millis(10));
+        assert_eq!(time.delta(), 0.5);
+        time.tick(start + Duration::from_millis(20));
+        assert_eq!(time.delta(), 0.0);
+        assert_eq!(time.elapsed(), 0.5);
+    }
+
+    #[test]
+    fn test_time_presented() {
+        let start = Instant::now();
+        let mut time = Time::new();
+        time.tick_presented(start, 10.0);
+        assert_eq!(time.delta(), 0.0);
+
+        // Deltas follow the presentation times rather than the ticks
+        time.tick_presented(start + Duration::from_millis(5), 10.0 + 1.0 / 120.0);
+        assert!((time.delta() - 1.0 / 120.0).abs() < 1e-6);
+
+        // A repeated presentation time falls back to the time between ticks
+        time.tick_presented(start + Duration::from_millis(15), 10.0 + 1.0 / 120.0);
+        assert!((time.delta() - 0.01).abs() < 1e-6);
+    }
+}
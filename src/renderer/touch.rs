@@ -0,0 +1,128 @@
+//! Touch module for the renderer.
+//!
+//! This module provides `TouchGestures`, which turns the touches of a touch
+//! screen, such as an iPhone's or iPad's, into the camera input of the
+//! mouse. Dragging one finger looks around like moving the mouse, and
+//! pinching two fingers zooms like scrolling. The resulting input events are
+//! recorded and played back in replays like any other.
+
+use super::replay::InputEvent;
+use glam::Vec2;
+use winit::event::TouchPhase;
+
+/// The scroll lines a pinch moves per pixel the fingers spread, as for trackpad scrolling.
+const PINCH_LINES_PER_PIXEL: f32 = 0.1;
+
+/// Tracks the fingers on a touch screen and recognizes camera gestures.
+#[derive(Clone, Debug, Default)]
+pub struct TouchGestures {
+    /// The id and position of every finger down, oldest first.
+    touches: Vec<(u64, Vec2)>,
+}
+
+impl TouchGestures {
+    /// Creates new `TouchGestures` with no finger down.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of fingers down.
+    pub fn touch_count(&self) -> usize {
+        self.touches.len()
+    }
+
+    /// Updates a finger and returns the input event of its gesture, if any.
+    ///
+    /// Only the two oldest fingers take part in gestures.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The finger's id, unique while it touches the screen.
+    /// * `phase` - Whether the finger touched, moved or left the screen.
+    /// * `position` - The finger's position in pixels, with y pointing down.
+    pub fn handle(&mut self, id: u64, phase: TouchPhase, position: Vec2) -> Option<InputEvent> {
+        let index = self.touches.iter().position(|&(touch, _)| touch == id);
+        match (phase, index) {
+            (TouchPhase::Started, None) => {
+                self.touches.push((id, position));
+                None
+            }
+            (TouchPhase::Moved, Some(index)) => {
+                let pinch_distance = self.pinch_distance();
+                let previous = std::mem::replace(&mut self.touches[index].1, position);
+                match (self.touches.len(), index) {
+                    (1, _) => Some(InputEvent::MouseMotion {
+                        delta_x: position.x - previous.x,
+                        delta_y: previous.y - position.y,
+                    }),
+                    (_, 0 | 1) => {
+                        let spread = self.pinch_distance()? - pinch_distance?;
+                        Some(InputEvent::Scroll {
+                            delta: spread * PINCH_LINES_PER_PIXEL,
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            (TouchPhase::Ended | TouchPhase::Cancelled, Some(index)) => {
+                self.touches.remove(index);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the distance between the two oldest fingers, if two are down.
+    fn pinch_distance(&self) -> Option<f32> {
+        match self.touches.as_slice() {
+            [(_, first), (_, second), ..] => Some(first.distance(*second)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TouchGestures;
+    use crate::renderer::replay::InputEvent;
+    use glam::Vec2;
+    use winit::event::TouchPhase;
+
+    #[test]
+    fn test_touch_gestures() {
+        let mut gestures = TouchGestures::new();
+        assert_eq!(
+            gestures.handle(1, TouchPhase::Started, Vec2::new(100.0, 100.0)),
+            None
+        );
+
+        // Dragging one finger looks around, with y pointing up
+        assert_eq!(
+            gestures.handle(1, TouchPhase::Moved, Vec2::new(110.0, 90.0)),
+            Some(InputEvent::MouseMotion {
+                delta_x: 10.0,
+                delta_y: 10.0,
+            })
+        );
+
+        // Spreading two fingers zooms in
+        gestures.handle(2, TouchPhase::Started, Vec2::new(210.0, 90.0));
+        assert_eq!(
+            gestures.handle(2, TouchPhase::Moved, Vec2::new(230.0, 90.0)),
+            Some(InputEvent::Scroll { delta: 2.0 })
+        );
+        gestures.handle(3, TouchPhase::Started, Vec2::ZERO);
+        assert_eq!(gestures.handle(3, TouchPhase::Moved, Vec2::ONE), None);
+
+        gestures.handle(1, TouchPhase::Ended, Vec2::new(110.0, 90.0));
+        gestures.handle(3, TouchPhase::Cancelled, Vec2::ONE);
+        assert_eq!(gestures.touch_count(), 1);
+        assert_eq!(
+            gestures.handle(2, TouchPhase::Moved, Vec2::new(230.0, 80.0)),
+            Some(InputEvent::MouseMotion {
+                delta_x: 0.0,
+                delta_y: 10.0,
+            })
+        );
+    }
+}
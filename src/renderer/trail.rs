@@ -0,0 +1,400 @@
+//! Trail module for the renderer.
+//!
+//! This module provides trails that record the recent positions of a scene
+//! node, or of anything whose positions are pushed by hand such as a physics
+//! body, and draw them as a polyline or camera-facing ribbon that fades from
+//! head to tail. Positions are kept in a fixed-capacity ring buffer, and
+//! optionally expire after a lifetime, so orbital paths, particle tracks and
+//! motion debugging cost the same every frame.
+
+use super::{
+    common::{LayerMask, PrimitiveType, Vertex},
+    scene_graph::NodeId,
+    Color,
+};
+use crate::math::curves::{CatmullRom, Curve};
+use glam::Vec3;
+use std::collections::VecDeque;
+
+/// Where a trail's positions come from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrailSource {
+    /// The world-space origin of a node, recorded every frame.
+    Node(NodeId),
+    /// Positions pushed with `Trail::push`, e.g. after every physics step.
+    Manual,
+}
+
+/// How a trail is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TrailShape {
+    /// A one pixel wide line strip.
+    #[default]
+    Line,
+    /// A strip facing the camera, with the given world-space width.
+    Ribbon { width: f32 },
+}
+
+/// Identifies a trail added to the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TrailId(pub usize);
+
+/// A recorded position and the scaled time it was recorded at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TrailPoint {
+    position: Vec3,
+    time: f64,
+}
+
+/// The recent path of a node or body, drawn fading from head to tail.
+///
+/// # Example
+///
+/// ```ignore
+/// let trail = Trail::new(TrailSource::Node(moon), 256)
+///     .with_color(Color::CYAN)
+///     .with_lifetime(10.0);
+/// renderer.add_trail(trail);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trail {
+    pub source: TrailSource,
+    pub shape: TrailShape,
+    /// The color of the newest position.
+    pub head_color: Color,
+    /// The color of the oldest position.
+    pub tail_color: Color,
+    /// The distance a position must move from the last recorded one to be recorded.
+    pub min_distance: f32,
+    /// The seconds of scaled time positions are kept for, or `None` to keep them until replaced.
+    pub lifetime: Option<f32>,
+    pub layers: LayerMask,
+    /// The segments drawn between two positions along a spline through them, or 1 for straight lines.
+    pub smoothing: usize,
+    points: VecDeque<TrailPoint>,
+    capacity: usize,
+}
+
+impl Trail {
+    /// Creates a new, empty white `Trail` drawn as a line.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the trail's positions come from.
+    /// * `capacity` - The most positions kept, replacing the oldest when full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is less than 2.
+    pub fn new(source: TrailSource, capacity: usize) -> Self {
+        assert!(capacity >= 2, "A trail needs at least 2 positions");
+        Self {
+            source,
+            shape: TrailShape::default(),
+            head_color: Color::WHITE,
+            tail_color: Color::WHITE.with_alpha(0.0),
+            min_distance: 0.01,
+            lifetime: None,
+            layers: LayerMask::DEFAULT,
+            smoothing: 1,
+            points: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Sets the color of the head, fading to transparent at the tail.
+    pub fn with_color(self, color: Color) -> Self {
+        self.with_colors(color, color.with_alpha(0.0))
+    }
+
+    /// Sets the colors blended from the newest to the oldest position.
+    pub fn with_colors(mut self, head_color: Color, tail_color: Color) -> Self {
+        self.head_color = head_color;
+        self.tail_color = tail_color;
+        self
+    }
+
+    /// Draws the trail as a camera-facing ribbon of the given world-space width.
+    pub fn with_ribbon(mut self, width: f32) -> Self {
+        self.shape = TrailShape::Ribbon { width };
+        self
+    }
+
+    /// Sets the distance a position must move to be recorded.
+    pub fn with_min_distance(mut self, min_distance: f32) -> Self {
+        self.min_distance = min_distance;
+        self
+    }
+
+    /// Draws a Catmull-Rom spline through the positions, splitting the span
+    /// between two positions into the given number of segments.
+    pub fn with_smoothing(mut self, subdivisions: usize) -> Self {
+        self.smoothing = subdivisions;
+        self
+    }
+
+    /// Drops positions older than the given seconds of scaled time.
+    pub fn with_lifetime(mut self, lifetime: f32) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    /// Sets the layers the trail is drawn on.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Records a position, replacing the oldest if the trail is full.
+    ///
+    /// Positions closer than the minimum distance to the last one are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The world-space position.
+    /// * `time` - The scaled time in seconds, as returned by `Time::elapsed`.
+    pub fn push(&mut self, position: Vec3, time: f64) {
+        if let Some(last) = self.points.back() {
+            if last.position.distance_squared(position) < self.min_distance * self.min_distance {
+                return;
+            }
+        }
+        if self.points.len() == self.capacity {
+            self.points.pop_front();
+        }
+        self.points.push_back(TrailPoint { position, time });
+    }
+
+    /// Drops positions that outlived the lifetime at the given scaled time.
+    pub fn expire(&mut self, time: f64) {
+        let Some(lifetime) = self.lifetime else {
+            return;
+        };
+        while self
+            .points
+            .front()
+            .is_some_and(|point| time - point.time > lifetime as f64)
+        {
+            self.points.pop_front();
+        }
+    }
+
+    /// Removes every recorded position.
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Returns the number of recorded positions.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Returns `true` if no positions are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Returns the recorded positions from oldest to newest.
+    pub fn positions(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.points.iter().map(|point| point.position)
+    }
+
+    /// Returns the primitive type of the trail's vertices.
+    pub fn primitive_type(&self) -> PrimitiveType {
+        match self.shape {
+            TrailShape::Line => PrimitiveType::LineStrip,
+            TrailShape::Ribbon { .. } => PrimitiveType::TriangleStrip,
+        }
+    }
+
+    /// Appends the trail's vertices, which are empty with fewer than 2 positions.
+    ///
+    /// Positions fade from head to tail by their age with a lifetime, and by
+    /// their order otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera_position` - The position ribbons face.
+    /// * `time` - The current scaled time in seconds.
+    /// * `vertices` - The vertices to append to.
+    pub fn append_vertices(&self, camera_position: Vec3, time: f64, vertices: &mut Vec<Vertex>) {
+        let count = self.points.len();
+        if count < 2 {
+            return;
+        }
+        let newest = (count - 1) as f32;
+        let fades = self
+            .points
+            .iter()
+            .enumerate()
+            .map(|(index, point)| match self.lifetime {
+                Some(lifetime) if lifetime > 0.0 => {
+                    ((time - point.time) as f32 / lifetime).min(1.0)
+                }
+                _ => (newest - index as f32) / newest,
+            });
+        let samples: Vec<(Vec3, f32)> = if self.smoothing > 1 {
+            let fades: Vec<f32> = fades.collect();
+            let spline = CatmullRom::new(self.positions().collect());
+            let sample_count = (count - 1) * self.smoothing + 1;
+            (0..sample_count)
+                .map(|index| {
+                    let (segment, step) = (index / self.smoothing, index % self.smoothing);
+                    let fade = match fades.get(segment + 1) {
+                        Some(next) => {
+                            let u = step as f32 / self.smoothing as f32;
+                            fades[segment] + (next - fades[segment]) * u
+                        }
+                        None => fades[segment],
+                    };
+                    let t = index as f32 / (sample_count - 1) as f32;
+                    (spline.point(t), fade)
+                })
+                .collect()
+        } else {
+            self.positions().zip(fades).collect()
+        };
+
+        for (index, &(position, fade)) in samples.iter().enumerate() {
+            let color = self.head_color.lerp(self.tail_color, fade).into();
+            match self.shape {
+                TrailShape::Line => vertices.push(Vertex {
+                    position: position.to_array(),
+                    color,
+                }),
+                TrailShape::Ribbon { width } => {
+                    let previous = samples[index.saturating_sub(1)].0;
+                    let next = samples[(index + 1).min(samples.len() - 1)].0;
+                    let side = (next - previous)
+                        .cross(camera_position - position)
+                        .normalize_or_zero()
+                        * width
+                        * 0.5;
+                    vertices.extend([position - side, position + side].map(|position| Vertex {
+                        position: position.to_array(),
+                        color,
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// The trails of a renderer, indexed by `TrailId`.
+#[derive(Clone, Debug, Default)]
+pub struct Trails {
+    trails: Vec<Option<Trail>>,
+}
+
+impl Trails {
+    /// Adds a trail and returns its handle.
+    pub fn add(&mut self, trail: Trail) -> TrailId {
+        self.trails.push(Some(trail));
+        TrailId(self.trails.len() - 1)
+    }
+
+    /// Removes a trail, returning `true` if it existed.
+    pub fn remove(&mut self, id: TrailId) -> bool {
+        self.trails
+            .get_mut(id.0)
+            .is_some_and(|trail| trail.take().is_some())
+    }
+
+    /// Returns a trail for modification, e.g. to push a body's position.
+    pub fn get_mut(&mut self, id: TrailId) -> Option<&mut Trail> {
+        self.trails.get_mut(id.0)?.as_mut()
+    }
+
+    /// Returns `true` if there are no trails.
+    pub fn is_empty(&self) -> bool {
+        self.trails.iter().all(Option::is_none)
+    }
+
+    /// Returns the trails.
+    pub fn iter(&self) -> impl Iterator<Item = &Trail> + '_ {
+        self.trails.iter().flatten()
+    }
+
+    /// Records the positions of node trails and expires old positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The current scaled time in seconds.
+    /// * `node_position` - Returns the world-space position of a node, or
+    ///   `None` if it no longer exists.
+    pub fn update(&mut self, time: f64, node_position: impl Fn(NodeId) -> Option<Vec3>) {
+        for trail in self.trails.iter_mut().flatten() {
+            if let TrailSource::Node(id) = trail.source {
+                if let Some(position) = node_position(id) {
+                    trail.push(position, time);
+                }
+            }
+            trail.expire(time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Trail, TrailSource};
+    use crate::renderer::Color;
+    use glam::Vec3;
+
+    #[test]
+    fn test_trail_ring_buffer_and_lifetime() {
+        let mut trail = Trail::new(TrailSource::Manual, 3).with_lifetime(2.5);
+        for step in 0..4 {
+            trail.push(Vec3::X * step as f32, step as f64);
+        }
+        // Too close to the last position
+        trail.push(Vec3::X * 3.001, 4.0);
+        assert_eq!(
+            trail.positions().collect::<Vec<_>>(),
+            [Vec3::X, Vec3::X * 2.0, Vec3::X * 3.0]
+        );
+
+        trail.expire(4.0);
+        assert_eq!(trail.len(), 2);
+        trail.expire(10.0);
+        assert!(trail.is_empty());
+    }
+
+    #[test]
+    fn test_trail_vertices_fade() {
+        let mut trail = Trail::new(TrailSource::Manual, 8).with_colors(Color::RED, Color::BLUE);
+        for step in 0..3 {
+            trail.push(Vec3::X * step as f32, 0.0);
+        }
+        let mut vertices = Vec::new();
+        trail.append_vertices(Vec3::Z * 10.0, 0.0, &mut vertices);
+        let colors: Vec<_> = vertices.iter().map(|vertex| vertex.color).collect();
+        assert_eq!(
+            colors,
+            [
+                [0.0, 0.0, 1.0, 1.0],
+                [0.5, 0.0, 0.5, 1.0],
+                [1.0, 0.0, 0.0, 1.0]
+            ]
+        );
+
+        // Ribbons face the camera with two vertices per position
+        vertices.clear();
+        trail
+            .with_ribbon(0.5)
+            .append_vertices(Vec3::Z * 10.0, 0.0, &mut vertices);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(vertices[2].position, [1.0, 0.25, 0.0]);
+        assert_eq!(vertices[3].position, [1.0, -0.25, 0.0]);
+
+        // Smoothed trails pass through every position
+        let mut trail = Trail::new(TrailSource::Manual, 8).with_smoothing(4);
+        for position in [Vec3::ZERO, Vec3::Y, Vec3::X] {
+            trail.push(position, 0.0);
+        }
+        vertices.clear();
+        trail.append_vertices(Vec3::Z * 10.0, 0.0, &mut vertices);
+        assert_eq!(vertices.len(), 9);
+        assert!(Vec3::from(vertices[4].position).distance(Vec3::Y) < 1e-5);
+        assert_eq!(vertices[8].color, [1.0; 4]);
+    }
+}
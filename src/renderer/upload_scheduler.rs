@@ -0,0 +1,357 @@
+//! Upload scheduler module for the renderer.
+//!
+//! This module provides `UploadScheduler`, which spreads the GPU uploads of
+//! static meshes and textures over frames. Each frame releases queued uploads
+//! in order until the next would exceed the per-frame byte budget, so
+//! streaming in a large scene costs a bounded slice of every frame instead of
+//! one long spike. The first upload of a frame is always released, so uploads
+//! larger than the budget still complete.
+//!
+//! Until their uploads are released, static meshes are drawn as the wireframe
+//! of their bounds, and materials show a white placeholder in place of their
+//! textures.
+
+use super::{
+    backend::GraphicsBackend,
+    common::{Color, LayerMask, PrimitiveType, TextureId, Vertex},
+    material_manager::{Material, MaterialManager},
+    mesh::MeshStorage,
+    render_queue::{DrawCommandBuilder, GeometryHandle, RenderQueue},
+    texture_upload::TextureUpload,
+    RendererError,
+};
+use glam::Mat4;
+use log::trace;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+/// The bytes uploaded per frame by default, enough for a 2048x2048 RGBA texture and its mipmaps.
+pub const DEFAULT_UPLOAD_BUDGET: u64 = 24 * 1024 * 1024;
+
+/// An upload released to the backend.
+#[derive(Debug)]
+pub enum ScheduledUpload {
+    /// The geometry of a static mesh, by its index in `MeshStorage`.
+    Mesh(usize),
+    Texture(TextureUpload),
+}
+
+/// A queued upload and the bytes it copies.
+#[derive(Debug)]
+struct QueuedUpload {
+    upload: ScheduledUpload,
+    bytes: u64,
+}
+
+/// Queues mesh and texture uploads and releases them within a per-frame byte budget.
+#[derive(Debug)]
+pub struct UploadScheduler {
+    /// The bytes released per frame, or `None` to release every upload at once.
+    budget: Option<u64>,
+    queue: VecDeque<QueuedUpload>,
+    /// The meshes with a queued upload.
+    meshes: HashSet<usize>,
+    /// The number of queued uploads of each texture.
+    textures: HashMap<TextureId, usize>,
+}
+
+impl UploadScheduler {
+    /// Creates a new `UploadScheduler` with nothing queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - The bytes released per frame, or `None` for no limit.
+    pub fn new(budget: Option<u64>) -> Self {
+        UploadScheduler {
+            budget,
+            queue: VecDeque::new(),
+            meshes: HashSet::new(),
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Returns the bytes released per frame, or `None` if there is no limit.
+    pub fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// Sets the bytes released per frame, or `None` for no limit.
+    pub fn set_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+    }
+
+    /// Queues the upload of a static mesh's geometry.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh` - The index of the mesh in `MeshStorage`.
+    /// * `bytes` - The bytes of geometry the upload copies.
+    pub fn queue_mesh(&mut self, mesh: usize, bytes: u64) {
+        self.meshes.insert(mesh);
+        self.queue.push_back(QueuedUpload {
+            upload: ScheduledUpload::Mesh(mesh),
+            bytes,
+        });
+    }
+
+    /// Queues an upload into a texture.
+    pub fn queue_texture(&mut self, upload: TextureUpload) {
+        *self.textures.entry(upload.texture).or_default() += 1;
+        self.queue.push_back(QueuedUpload {
+            bytes: upload.required_bytes(),
+            upload: ScheduledUpload::Texture(upload),
+        });
+    }
+
+    /// Returns `true` if a mesh's upload has not been released yet.
+    pub fn is_mesh_queued(&self, mesh: usize) -> bool {
+        self.meshes.contains(&mesh)
+    }
+
+    /// Returns `true` if a texture has uploads not released yet.
+    pub fn is_texture_queued(&self, texture: TextureId) -> bool {
+        self.textures.contains_key(&texture)
+    }
+
+    /// Returns the number of queued uploads.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Releases the uploads of a frame, in the order they were queued.
+    ///
+    /// Uploads are released until the next would take the frame over its
+    /// budget. The first is released regardless, so every upload completes.
+    ///
+    /// # Returns
+    ///
+    /// The uploads to submit to the backend this frame.
+    pub fn release(&mut self) -> Vec<ScheduledUpload> {
+        let mut released = Vec::new();
+        let mut spent = 0;
+        while let Some(queued) = self.queue.front() {
+            let over_budget = self
+                .budget
+                .is_some_and(|budget| spent + queued.bytes > budget);
+            if over_budget && !released.is_empty() {
+                break;
+            }
+            let Some(QueuedUpload { upload, bytes }) = self.queue.pop_front() else {
+                break;
+            };
+            spent += bytes;
+            match &upload {
+                ScheduledUpload::Mesh(mesh) => {
+                    self.meshes.remove(mesh);
+                }
+                ScheduledUpload::Texture(upload) => {
+                    if let Some(count) = self.textures.get_mut(&upload.texture) {
+                        *count -= 1;
+                        if *count == 0 {
+                            self.textures.remove(&upload.texture);
+                        }
+                    }
+                }
+            }
+            released.push(upload);
+        }
+        if !released.is_empty() {
+            trace!(
+                "Released {} uploads of {spent} bytes, {} deferred",
+                released.len(),
+                self.queue.len()
+            );
+        }
+        released
+    }
+
+    /// Releases the uploads of a frame and submits them to the backend.
+    ///
+    /// Materials are marked dirty once textures are released, so they are
+    /// re-sent without the placeholder.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - The backend to upload to.
+    /// * `meshes` - The meshes whose geometry is uploaded.
+    /// * `materials` - The materials showing placeholders for queued textures.
+    ///
+    /// # Returns
+    ///
+    /// The errors of uploads the backend rejected, such as uploads into a
+    /// texture that does not exist.
+    pub fn submit(
+        &mut self,
+        backend: &mut impl GraphicsBackend,
+        meshes: &MeshStorage,
+        materials: &mut MaterialManager,
+    ) -> Vec<RendererError> {
+        let mut errors = Vec::new();
+        let mut textures_released = false;
+        for upload in self.release() {
+            match upload {
+                ScheduledUpload::Mesh(index) => {
+                    if let Some(mesh) = meshes.get_mesh(index) {
+                        backend.upload_static_mesh(index, mesh.view());
+                    }
+                }
+                ScheduledUpload::Texture(upload) => {
+                    textures_released = true;
+                    if let Err(error) = backend.upload_texture(upload) {
+                        errors.push(error);
+                    }
+                }
+            }
+        }
+        if textures_released {
+            materials.mark_dirty();
+        }
+        errors
+    }
+
+    /// Queues the wireframe bounds of the queue's static meshes whose uploads
+    /// are still queued, drawn in place of the meshes until their geometry is
+    /// resident.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - The frame's render queue.
+    /// * `meshes` - The meshes drawn by the queue.
+    pub fn submit_placeholders(&self, queue: &mut RenderQueue, meshes: &MeshStorage) {
+        const PLACEHOLDER_COLOR: Color = Color::GRAY;
+
+        if self.meshes.is_empty() {
+            return;
+        }
+        let mut lines: Vec<Vertex> = Vec::new();
+        let mut layers = LayerMask::NONE;
+        for item in queue.draw_items() {
+            let GeometryHandle::Mesh(mesh_id) = item.geometry else {
+                continue;
+            };
+            if !self.is_mesh_queued(mesh_id) {
+                continue;
+            }
+            let Some(mesh) = meshes.get_mesh(mesh_id) else {
+                continue;
+            };
+            let mut append = |transform: &Mat4| {
+                mesh.bounds
+                    .transformed(transform)
+                    .append_wireframe(PLACEHOLDER_COLOR, &mut lines)
+            };
+            match item.instances {
+                Some(instances) => instances
+                    .iter()
+                    .for_each(|instance| append(&(*item.transform * instance.model_matrix))),
+                None => append(item.transform),
+            }
+            layers = layers | item.layers;
+        }
+
+        if !lines.is_empty() {
+            queue.add_draw_command(
+                DrawCommandBuilder::new_primitive(&lines, None, PrimitiveType::Line)
+                    .with_layers(layers),
+            );
+        }
+    }
+
+    /// Returns the materials with the white placeholder in place of textures
+    /// whose uploads are still queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `materials` - The materials to send to the backend.
+    ///
+    /// # Returns
+    ///
+    /// The materials, borrowed unchanged if no texture is queued.
+    pub fn with_placeholders<'a>(&self, materials: &'a [Material]) -> Cow<'a, [Material]> {
+        let queued = |material: &Material| {
+            material
+                .texture_id
+                .is_some_and(|texture| self.is_texture_queued(texture))
+        };
+        if !materials.iter().any(queued) {
+            return Cow::Borrowed(materials);
+        }
+        Cow::Owned(
+            materials
+                .iter()
+                .map(|material| Material {
+                    texture_id: material
+                        .texture_id
+                        .filter(|&texture| !self.is_texture_queued(texture)),
+                    ..*material
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScheduledUpload, UploadScheduler};
+    use crate::renderer::{
+        common::TextureId, material_manager::Material, texture_upload::TextureUpload,
+    };
+    use std::{borrow::Cow, num::NonZeroU32};
+
+    #[test]
+    fn test_upload_scheduler_budget() {
+        let texture = TextureId(NonZeroU32::new(1).unwrap());
+        let mut scheduler = UploadScheduler::new(Some(100));
+        scheduler.queue_mesh(0, 60);
+        scheduler.queue_mesh(1, 30);
+        scheduler.queue_texture(TextureUpload::new(texture, 4, 4, 16, vec![0; 64]));
+        scheduler.queue_mesh(2, 500);
+        assert!(scheduler.is_mesh_queued(1) && scheduler.is_texture_queued(texture));
+
+        // The texture would take the first frame over budget
+        let released = scheduler.release();
+        assert!(matches!(
+            released[..],
+            [ScheduledUpload::Mesh(0), ScheduledUpload::Mesh(1)]
+        ));
+        assert!(!scheduler.is_mesh_queued(1));
+        assert_eq!(scheduler.len(), 2);
+
+        // Uploads over the budget are still released alone
+        let released = scheduler.release();
+        assert!(matches!(released[..], [ScheduledUpload::Texture(_)]));
+        assert!(!scheduler.is_texture_queued(texture));
+        assert!(matches!(
+            scheduler.release()[..],
+            [ScheduledUpload::Mesh(2)]
+        ));
+        assert!(scheduler.release().is_empty());
+
+        // Without a budget everything is released at once
+        scheduler.set_budget(None);
+        scheduler.queue_mesh(3, 500);
+        scheduler.queue_mesh(4, 500);
+        assert_eq!(scheduler.release().len(), 2);
+    }
+
+    #[test]
+    fn test_upload_scheduler_placeholders() {
+        let texture = TextureId(NonZeroU32::new(1).unwrap());
+        let materials = [
+            Material::default(),
+            Material::default().with_texture(texture),
+        ];
+        let mut scheduler = UploadScheduler::new(None);
+        assert!(matches!(
+            scheduler.with_placeholders(&materials),
+            Cow::Borrowed(_)
+        ));
+
+        scheduler.queue_texture(TextureUpload::new(texture, 1, 1, 4, vec![0; 4]));
+        let placeholders = scheduler.with_placeholders(&materials);
+        assert_eq!(placeholders[0], materials[0]);
+        assert_eq!(placeholders[1].texture_id, None);
+    }
+}
@@ -0,0 +1,1101 @@
+//! USD module for the renderer.
+//!
+//! This module imports Universal Scene Description layers, the interchange
+//! format of Pixar's tools and, packaged as USDZ, of Apple's 3D frameworks
+//! and AR Quick Look. It is only built with the `usd` feature.
+//!
+//! `UsdScene` reads the text encoding, USDA, and USDZ packages whose root
+//! layer is USDA, stored or deflated, which are opened with the `zip` crate:
+//!
+//! ```text
+//! #usda 1.0
+//! (
+//!     upAxis = "Z"
+//!     metersPerUnit = 0.01
+//! )
+//!
+//! def Xform "Ship" {
+//!     double3 xformOp:translate = (0, 0, 120)
+//!     uniform token[] xformOpOrder = ["xformOp:translate"]
+//!
+//!     def Mesh "Hull" {
+//!         point3f[] points = [(0, 0, 0), (100, 0, 0), (0, 100, 0)]
+//!         int[] faceVertexCounts = [3]
+//!         int[] faceVertexIndices = [0, 1, 2]
+//!     }
+//! }
+//! ```
+//!
+//! Every defined prim becomes a node, with the local transform of its
+//! `xformOpOrder`, and every `Mesh` prim a triangulated mesh with its
+//! normals, `st` texture coordinates and display colors. Classes, overs,
+//! materials, shaders and geometry subsets are skipped, and references,
+//! payloads, variants and time samples are not resolved. Binary crate
+//! layers, `.usdc`, are unsupported.
+//!
+//! The stage's `upAxis` and `metersPerUnit` give the scene's import
+//! settings, which a sidecar next to the file overrides.
+
+use super::{
+    backend::GraphicsBackend,
+    common::{PrimitiveType, Vertex},
+    error::{ImportError, SceneError},
+    import_settings::{ImportSettings, UpAxis},
+    material_manager::MaterialId,
+    render_core::Renderer,
+    scene_graph::NodeId,
+    shape_builders::MeshBuilder,
+    Color,
+};
+use glam::{Mat4, Quat, Vec2, Vec3};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+};
+use zip::{result::ZipError, ZipArchive};
+
+/// The signature of a zip local file header, which starts a USDZ package.
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+
+/// The magic bytes starting a binary crate layer.
+const CRATE_MAGIC: &[u8] = b"PXR-USDC";
+
+/// A prim of an imported scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsdPrim {
+    /// The prim's name.
+    pub name: String,
+    /// The index of the parent prim, which comes before it, or `None` for a root prim.
+    pub parent: Option<usize>,
+    /// The transform relative to the parent, in the source's axes and units.
+    pub transform: Mat4,
+    /// The index of the prim's mesh in `UsdScene::meshes`.
+    pub mesh: Option<usize>,
+}
+
+/// A scene imported from a USDA layer or USDZ package.
+#[derive(Clone)]
+pub struct UsdScene {
+    /// The defined prims, with parents before their children.
+    pub prims: Vec<UsdPrim>,
+    /// The meshes of `Mesh` prims, in the source's axes and units.
+    pub meshes: Vec<MeshBuilder>,
+    /// The settings applied when the scene is instantiated.
+    pub settings: ImportSettings,
+}
+
+impl UsdScene {
+    /// Loads a `.usda` layer or `.usdz` package.
+    ///
+    /// The settings follow the stage's up axis and units unless the file
+    /// has a sidecar, see `ImportSettings::load_for`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the scene, or an `ImportError` if the file or
+    /// its settings can't be read.
+    pub fn load(path: &Path) -> Result<Self, ImportError> {
+        let bytes = fs::read(path).map_err(|source| ImportError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut scene = if bytes.starts_with(&ZIP_LOCAL_HEADER.to_le_bytes()) {
+            Self::parse_usdz(&bytes)?
+        } else {
+            Self::parse_usda(&bytes)?
+        };
+        if ImportSettings::sidecar_path(path).exists() {
+            scene.settings = ImportSettings::load_for(path)?;
+        }
+        info!(
+            "Imported USD {} with {} prims and {} meshes",
+            path.display(),
+            scene.prims.len(),
+            scene.meshes.len()
+        );
+        Ok(scene)
+    }
+
+    /// Parses the contents of a USDA layer.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the scene, `ImportError::Unsupported` for a
+    /// binary crate layer, or `ImportError::Malformed`.
+    pub fn parse_usda(bytes: &[u8]) -> Result<Self, ImportError> {
+        if bytes.starts_with(CRATE_MAGIC) {
+            return Err(ImportError::Unsupported(
+                "binary USD crate layers can't be read".to_string(),
+            ));
+        }
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| ImportError::Malformed("USDA layer is not UTF-8".to_string()))?;
+        if !text.trim_start().starts_with("#usda") {
+            return Err(ImportError::Unsupported("not a USDA layer".to_string()));
+        }
+
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let mut metadata = HashMap::new();
+        if parser.peek() == Some(&Token::Punct('(')) {
+            metadata = parser.metadata()?;
+        }
+        let mut specs = Vec::new();
+        while parser.peek().is_some() {
+            specs.push(parser.prim()?);
+        }
+
+        let up_axis = match metadata.get("upAxis") {
+            Some(Value::String(axis)) => axis.parse().unwrap_or_else(|message| {
+                warn!("USD layer has {message}, using Y");
+                UpAxis::Y
+            }),
+            _ => UpAxis::Y,
+        };
+        let meters_per_unit = match metadata.get("metersPerUnit") {
+            Some(Value::Number(meters)) => *meters as f32,
+            _ => 1.0,
+        };
+        let mut scene = UsdScene {
+            prims: Vec::new(),
+            meshes: Vec::new(),
+            settings: ImportSettings::default()
+                .with_up_axis(up_axis)
+                .with_scale(meters_per_unit),
+        };
+        for spec in &specs {
+            scene.add_prim(spec, None)?;
+        }
+        Ok(scene)
+    }
+
+    /// Parses the contents of a USDZ package, reading its root layer, which
+    /// is the package's first file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the scene, `ImportError::Unsupported` if the
+    /// root layer is a binary crate layer or compressed with a method other
+    /// than deflate, or `ImportError::Malformed`.
+    pub fn parse_usdz(bytes: &[u8]) -> Result<Self, ImportError> {
+        if !bytes.starts_with(&ZIP_LOCAL_HEADER.to_le_bytes()) {
+            return Err(ImportError::Unsupported("not a USDZ package".to_string()));
+        }
+        let zip_error = |error: ZipError| match error {
+            ZipError::UnsupportedArchive(message) => {
+                ImportError::Unsupported(format!("USDZ package is unsupported: {message}"))
+            }
+            error => ImportError::Malformed(format!("USDZ package is unreadable: {error}")),
+        };
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(zip_error)?;
+        let mut root = archive.by_index(0).map_err(zip_error)?;
+        if root.name().ends_with(".usdc") {
+            return Err(ImportError::Unsupported(
+                "USDZ root layer is a binary crate layer".to_string(),
+            ));
+        }
+        let mut layer = Vec::new();
+        root.read_to_end(&mut layer).map_err(|error| {
+            ImportError::Malformed(format!("USDZ root layer is unreadable: {error}"))
+        })?;
+        if layer.starts_with(CRATE_MAGIC) {
+            return Err(ImportError::Unsupported(
+                "USDZ root layer is a binary crate layer".to_string(),
+            ));
+        }
+        Self::parse_usda(&layer)
+    }
+
+    /// Adds the nodes and meshes of the scene to a renderer's scene graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer to add the scene to.
+    /// * `parent` - The node the root prims are attached to, or `None` to
+    ///   add them as root nodes.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the node of each prim, in the order of
+    /// `prims`, or `SceneError::InvalidNode` if the parent does not exist.
+    pub fn instantiate<B: GraphicsBackend>(
+        &self,
+        renderer: &mut Renderer<B>,
+        parent: Option<NodeId>,
+    ) -> Result<Vec<NodeId>, SceneError> {
+        // Meshes stay on their prims, so only the node transforms carry the hierarchy
+        let settings = self.settings.with_merge_by_material(false);
+        let mut nodes: Vec<NodeId> = Vec::with_capacity(self.prims.len());
+        for prim in &self.prims {
+            let node_parent = prim.parent.map(|index| nodes[index]).or(parent);
+            let transform = settings.convert_transform(prim.transform);
+            let node = renderer
+                .scene_graph_mut()
+                .add_node(node_parent, transform)?;
+            renderer
+                .scene_graph_mut()
+                .set_name(node, Some(&prim.name))?;
+            if let Some(mesh) = prim
+                .mesh
+                .and_then(|index| settings.apply(vec![self.meshes[index].clone()]).pop())
+            {
+                let mesh_id = renderer.add_mesh(mesh);
+                renderer
+                    .scene_graph_mut()
+                    .set_mesh(node, Some(mesh_id), MaterialId::DEFAULT)?;
+            }
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+
+    /// Adds a parsed prim and its descendants, skipping prims that aren't
+    /// part of the scene hierarchy.
+    fn add_prim(&mut self, spec: &PrimSpec, parent: Option<usize>) -> Result<(), ImportError> {
+        if spec.specifier != "def"
+            || matches!(
+                spec.type_name.as_deref(),
+                Some("Material" | "Shader" | "NodeGraph" | "GeomSubset")
+            )
+        {
+            return Ok(());
+        }
+        let mesh = match spec.type_name.as_deref() {
+            Some("Mesh") => {
+                self.meshes.push(mesh(spec)?);
+                Some(self.meshes.len() - 1)
+            }
+            _ => None,
+        };
+        self.prims.push(UsdPrim {
+            name: spec.name.clone(),
+            parent,
+            transform: local_transform(spec)?,
+            mesh,
+        });
+        let index = self.prims.len() - 1;
+        for child in &spec.children {
+            self.add_prim(child, Some(index))?;
+        }
+        Ok(())
+    }
+}
+
+/// A token of a USDA layer.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Punct(char),
+    String(String),
+    /// An asset path, `@path@`, or a prim or property path, `</path>`.
+    Path(String),
+    /// An identifier, keyword or number.
+    Word(String),
+}
+
+/// A value of an attribute or metadata field.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    String(String),
+    /// An array, `[...]`, or a tuple, `(...)`.
+    List(Vec<Value>),
+    /// A value that isn't read, such as a path, identifier or dictionary.
+    Other,
+}
+
+impl Value {
+    /// Appends the numbers of the value, flattening lists, or returns
+    /// `None` if it holds anything else.
+    fn flatten(&self, numbers: &mut Vec<f64>) -> Option<()> {
+        match self {
+            Value::Number(number) => numbers.push(*number),
+            Value::List(values) => {
+                for value in values {
+                    value.flatten(numbers)?;
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Returns the numbers of the value, flattening lists.
+    fn numbers(&self) -> Option<Vec<f64>> {
+        let mut numbers = Vec::new();
+        self.flatten(&mut numbers).map(|_| numbers)
+    }
+}
+
+/// An attribute of a prim.
+#[derive(Clone, Debug)]
+struct Attribute {
+    value: Value,
+    /// How a primvar's values map to the mesh, e.g. `"faceVarying"`.
+    interpolation: Option<String>,
+}
+
+/// A prim as written in a layer.
+#[derive(Clone, Debug)]
+struct PrimSpec {
+    /// `def`, `over` or `class`.
+    specifier: String,
+    type_name: Option<String>,
+    name: String,
+    attributes: HashMap<String, Attribute>,
+    children: Vec<PrimSpec>,
+}
+
+impl PrimSpec {
+    /// Returns the numbers of an attribute with a value.
+    fn numbers(&self, name: &str) -> Result<Option<Vec<f64>>, ImportError> {
+        match self.attributes.get(name) {
+            Some(attribute) if attribute.value != Value::Other => {
+                attribute.value.numbers().map(Some).ok_or_else(|| {
+                    ImportError::Malformed(format!(
+                        "attribute \"{name}\" of prim \"{}\" isn't numeric",
+                        self.name
+                    ))
+                })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the values of a primvar as vectors, with its indices and interpolation.
+    fn primvar<const N: usize>(&self, name: &str) -> Result<Option<Primvar<N>>, ImportError> {
+        let Some(numbers) = self.numbers(name)? else {
+            return Ok(None);
+        };
+        let values = numbers
+            .chunks_exact(N)
+            .map(|chunk| std::array::from_fn(|index| chunk[index] as f32))
+            .collect();
+        let indices = self
+            .numbers(&format!("{name}:indices"))?
+            .map(|indices| indices.into_iter().map(|index| index as usize).collect());
+        let interpolation = self.attributes[name]
+            .interpolation
+            .clone()
+            .unwrap_or_else(|| "constant".to_string());
+        Ok(Some(Primvar {
+            values,
+            indices,
+            interpolation,
+        }))
+    }
+}
+
+/// The values of a primvar.
+struct Primvar<const N: usize> {
+    values: Vec<[f32; N]>,
+    indices: Option<Vec<usize>>,
+    interpolation: String,
+}
+
+impl<const N: usize> Primvar<N> {
+    /// Returns the value for a face corner.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The index of the corner's point.
+    /// * `face` - The index of the corner's face.
+    /// * `corner` - The index of the corner among all faces' corners.
+    fn sample(&self, point: usize, face: usize, corner: usize) -> Option<[f32; N]> {
+        let element = match self.interpolation.as_str() {
+            "constant" => 0,
+            "uniform" => face,
+            "faceVarying" => corner,
+            _ => point,
+        };
+        let index = match &self.indices {
+            Some(indices) => *indices.get(element)?,
+            None => element,
+        };
+        self.values.get(index).copied()
+    }
+}
+
+/// Splits a layer into tokens, dropping comments.
+fn tokenize(text: &str) -> Result<Vec<Token>, ImportError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, character)) = chars.next() {
+        match character {
+            _ if character.is_whitespace() => {}
+            '#' => while chars.next_if(|&(_, next)| next != '\n').is_some() {},
+            '(' | ')' | '[' | ']' | '{' | '}' | '=' | ',' | ';' => {
+                tokens.push(Token::Punct(character));
+            }
+            '"' | '\'' => {
+                let triple = text[start..].starts_with(&character.to_string().repeat(3));
+                let delimiter = if triple {
+                    text[start..start + 3].to_string()
+                } else {
+                    character.to_string()
+                };
+                let body_start = start + delimiter.len();
+                let length = text[body_start..].find(&delimiter).ok_or_else(|| {
+                    ImportError::Malformed("USDA layer has an unterminated string".to_string())
+                })?;
+                let end = body_start + length + delimiter.len();
+                while chars.next_if(|&(index, _)| index < end).is_some() {}
+                tokens.push(Token::String(
+                    text[body_start..body_start + length].to_string(),
+                ));
+            }
+            '@' | '<' => {
+                let end = if character == '@' { '@' } else { '>' };
+                let length = text[start + 1..].find(end).ok_or_else(|| {
+                    ImportError::Malformed("USDA layer has an unterminated path".to_string())
+                })?;
+                while chars
+                    .next_if(|&(index, _)| index <= start + length + 1)
+                    .is_some()
+                {}
+                tokens.push(Token::Path(text[start + 1..start + 1 + length].to_string()));
+            }
+            _ => {
+                let mut end = start + character.len_utf8();
+                while let Some((index, next)) = chars.next_if(|&(_, next)| {
+                    !next.is_whitespace() && !"()[]{}=,;\"'#@<".contains(next)
+                }) {
+                    end = index + next.len_utf8();
+                }
+                tokens.push(Token::Word(text[start..end].to_string()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads prims, attributes and values from the tokens of a layer.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, ImportError> {
+        let token =
+            self.tokens.get(self.position).cloned().ok_or_else(|| {
+                ImportError::Malformed("USDA layer ends unexpectedly".to_string())
+            })?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn error(&self, expected: &str, found: &Token) -> ImportError {
+        ImportError::Malformed(format!(
+            "expected {expected} in USDA layer, found {found:?}"
+        ))
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), ImportError> {
+        match self.next()? {
+            Token::Punct(found) if found == punct => Ok(()),
+            token => Err(self.error(&format!("'{punct}'"), &token)),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, ImportError> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(self.error("a name", &token)),
+        }
+    }
+
+    /// Consumes the token if it is the punctuation.
+    fn accept(&mut self, punct: char) -> bool {
+        let accepted = self.peek() == Some(&Token::Punct(punct));
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    /// Skips a bracketed block whose opening bracket was consumed.
+    fn skip_block(&mut self, open: char, close: char) -> Result<(), ImportError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct(punct) if punct == open => depth += 1,
+                Token::Punct(punct) if punct == close => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a value: a number, string, array, tuple, or a value that isn't read.
+    fn value(&mut self) -> Result<Value, ImportError> {
+        match self.next()? {
+            Token::Punct(open @ ('[' | '(')) => {
+                let close = if open == '[' { ']' } else { ')' };
+                let mut values = Vec::new();
+                while !self.accept(close) {
+                    values.push(self.value()?);
+                    if !self.accept(',') {
+                        self.expect(close)?;
+                        break;
+                    }
+                }
+                Ok(Value::List(values))
+            }
+            Token::Punct('{') => {
+                self.skip_block('{', '}')?;
+                Ok(Value::Other)
+            }
+            Token::String(string) => Ok(Value::String(string)),
+            Token::Word(word) => Ok(word.parse().map(Value::Number).unwrap_or(Value::Other)),
+            Token::Path(_) => Ok(Value::Other),
+            token => Err(self.error("a value", &token)),
+        }
+    }
+
+    /// Reads a parenthesized metadata block of `name = value` fields and
+    /// documentation strings.
+    fn metadata(&mut self) -> Result<HashMap<String, Value>, ImportError> {
+        self.expect('(')?;
+        let mut fields = HashMap::new();
+        while !self.accept(')') {
+            match self.next()? {
+                Token::String(_) => {}
+                Token::Punct(';') => {}
+                Token::Word(mut name) => {
+                    if matches!(
+                        name.as_str(),
+                        "add" | "append" | "delete" | "prepend" | "reorder"
+                    ) {
+                        name = self.word()?;
+                    }
+                    self.expect('=')?;
+                    fields.insert(name, self.value()?);
+                }
+                token => return Err(self.error("a metadata field", &token)),
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Reads a prim, `specifier [Type] "name" [(metadata)] { ... }`, with its
+    /// attributes and children.
+    fn prim(&mut self) -> Result<PrimSpec, ImportError> {
+        let specifier = self.word()?;
+        if !matches!(specifier.as_str(), "def" | "over" | "class") {
+            return Err(ImportError::Malformed(format!(
+                "expected a prim in USDA layer, found \"{specifier}\""
+            )));
+        }
+        let type_name = match self.peek() {
+            Some(Token::Word(_)) => Some(self.word()?),
+            _ => None,
+        };
+        let name = match self.next()? {
+            Token::String(name) => name,
+            token => return Err(self.error("a prim name", &token)),
+        };
+        if self.peek() == Some(&Token::Punct('(')) {
+            self.metadata()?;
+        }
+        self.expect('{')?;
+
+        let mut prim = PrimSpec {
+            specifier,
+            type_name,
+            name,
+            attributes: HashMap::new(),
+            children: Vec::new(),
+        };
+        while !self.accept('}') {
+            match self.peek() {
+                Some(Token::Word(word)) if matches!(word.as_str(), "def" | "over" | "class") => {
+                    prim.children.push(self.prim()?);
+                }
+                Some(Token::Word(word)) if word == "variantSet" => {
+                    self.position += 1;
+                    self.next()?;
+                    self.expect('=')?;
+                    self.expect('{')?;
+                    self.skip_block('{', '}')?;
+                }
+                Some(Token::Word(word)) if word == "rel" => {
+                    self.position += 1;
+                    self.property(true)?;
+                }
+                Some(Token::Punct(';')) => self.position += 1,
+                _ => {
+                    if let Some((name, attribute)) = self.property(false)? {
+                        prim.attributes.insert(name, attribute);
+                    }
+                }
+            }
+        }
+        Ok(prim)
+    }
+
+    /// Reads an attribute, `[custom] [uniform] type[[]] name [= value]
+    /// [(metadata)]`, or the name and targets of a relationship.
+    ///
+    /// # Returns
+    ///
+    /// The attribute and its name, or `None` for relationships, time samples
+    /// and connections.
+    fn property(&mut self, relationship: bool) -> Result<Option<(String, Attribute)>, ImportError> {
+        let mut name = self.word()?;
+        if !relationship {
+            while matches!(name.as_str(), "custom" | "uniform" | "varying") {
+                name = self.word()?;
+            }
+            if self.accept('[') {
+                self.expect(']')?;
+            }
+            name = self.word()?;
+        }
+        let value = if self.accept('=') {
+            self.value()?
+        } else {
+            Value::Other
+        };
+        let interpolation = if self.peek() == Some(&Token::Punct('(')) {
+            match self.metadata()?.remove("interpolation") {
+                Some(Value::String(interpolation)) => Some(interpolation),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        if relationship || name.contains('.') {
+            return Ok(None);
+        }
+        Ok(Some((
+            name,
+            Attribute {
+                value,
+                interpolation,
+            },
+        )))
+    }
+}
+
+/// Composes a prim's transform from the ops of its `xformOpOrder`, the
+/// first op being outermost.
+fn local_transform(spec: &PrimSpec) -> Result<Mat4, ImportError> {
+    let order = match spec
+        .attributes
+        .get("xformOpOrder")
+        .map(|order| &order.value)
+    {
+        Some(Value::List(order)) => order,
+        _ => return Ok(Mat4::IDENTITY),
+    };
+    let mut transform = Mat4::IDENTITY;
+    for op in order {
+        let Value::String(op) = op else {
+            continue;
+        };
+        let (name, invert) = match op.strip_prefix("!invert!") {
+            Some(name) => (name, true),
+            None => (op.as_str(), false),
+        };
+        if name == "!resetXformStack!" {
+            continue;
+        }
+        let values = spec.numbers(name)?.ok_or_else(|| {
+            ImportError::Malformed(format!(
+                "prim \"{}\" has no value for transform op \"{name}\"",
+                spec.name
+            ))
+        })?;
+        let kind = name.split(':').nth(1).unwrap_or_default();
+        let axes = kind.strip_prefix("rotate").unwrap_or_default();
+        let expected = match kind {
+            "translate" | "scale" => 3,
+            "orient" => 4,
+            "transform" => 16,
+            _ if axes.len() == 1 || axes.len() == 3 => axes.len(),
+            _ => {
+                warn!(
+                    "Skipping unknown transform op \"{name}\" of prim \"{}\"",
+                    spec.name
+                );
+                continue;
+            }
+        };
+        if values.len() != expected {
+            return Err(ImportError::Malformed(format!(
+                "transform op \"{name}\" of prim \"{}\" has {} values",
+                spec.name,
+                values.len()
+            )));
+        }
+        let vector = || Vec3::new(values[0] as f32, values[1] as f32, values[2] as f32);
+        let matrix = match kind {
+            "translate" => Mat4::from_translation(vector()),
+            "scale" => Mat4::from_scale(vector()),
+            "orient" => Mat4::from_quat(
+                Quat::from_xyzw(
+                    values[1] as f32,
+                    values[2] as f32,
+                    values[3] as f32,
+                    values[0] as f32,
+                )
+                .normalize(),
+            ),
+            // Rows are written with the translation last, so they are glam's columns
+            "transform" => {
+                Mat4::from_cols_array(&std::array::from_fn(|index| values[index] as f32))
+            }
+            // Angles are in degrees, and the first axis named is applied first
+            _ => axes
+                .chars()
+                .zip(values.iter())
+                .fold(Mat4::IDENTITY, |matrix, (axis, &angle)| {
+                    axis_rotation(axis, angle as f32) * matrix
+                }),
+        };
+        transform *= if invert { matrix.inverse() } else { matrix };
+    }
+    Ok(transform)
+}
+
+/// Returns a rotation about the X, Y or Z axis by an angle in degrees.
+fn axis_rotation(axis: char, degrees: f32) -> Mat4 {
+    let radians = degrees.to_radians();
+    match axis {
+        'X' => Mat4::from_rotation_x(radians),
+        'Y' => Mat4::from_rotation_y(radians),
+        _ => Mat4::from_rotation_z(radians),
+    }
+}
+
+/// Builds the triangulated mesh of a `Mesh` prim, with a vertex per face
+/// corner so face-varying primvars are kept.
+fn mesh(spec: &PrimSpec) -> Result<MeshBuilder, ImportError> {
+    let malformed =
+        |message: &str| ImportError::Malformed(format!("mesh \"{}\" {message}", spec.name));
+    let indices = spec.numbers("faceVertexIndices")?.unwrap_or_default();
+    let required = |name: &str| match spec.numbers(name)? {
+        Some(values) => Ok(values),
+        // A mesh without faces needs neither points nor face counts
+        None if indices.is_empty() => Ok(Vec::new()),
+        None => Err(malformed(&format!("has faces but no {name}"))),
+    };
+    let points: Vec<Vec3> = required("points")?
+        .chunks_exact(3)
+        .map(|point| Vec3::new(point[0] as f32, point[1] as f32, point[2] as f32))
+        .collect();
+    let counts = required("faceVertexCounts")?;
+    if counts.iter().map(|&count| count as usize).sum::<usize>() != indices.len() {
+        return Err(malformed("has face counts that don't match its indices"));
+    }
+    let left_handed = matches!(
+        spec.attributes.get("orientation").map(|orientation| &orientation.value),
+        Some(Value::String(orientation)) if orientation == "leftHanded"
+    );
+
+    let normals = match spec.primvar::<3>("primvars:normals")? {
+        Some(normals) => Some(normals),
+        None => spec.primvar::<3>("normals")?.map(|mut normals| {
+            // Normals default to one per point rather than a constant
+            if spec.attributes["normals"].interpolation.is_none() {
+                normals.interpolation = "vertex".to_string();
+            }
+            normals
+        }),
+    };
+    let tex_coords = spec.primvar::<2>("primvars:st")?;
+    let colors = spec.primvar::<3>("primvars:displayColor")?;
+    let opacities = spec.primvar::<1>("primvars:displayOpacity")?;
+
+    let mut vertices = Vec::with_capacity(indices.len());
+    let mut vertex_normals = Vec::with_capacity(indices.len());
+    let mut vertex_tex_coords = Vec::with_capacity(indices.len());
+    let mut triangles = Vec::new();
+    let mut corner = 0;
+    for (face, &count) in counts.iter().enumerate() {
+        let count = count as usize;
+        let first = vertices.len() as u32;
+        for &point in &indices[corner..corner + count] {
+            let point = point as usize;
+            let position = *points
+                .get(point)
+                .ok_or_else(|| malformed(&format!("indexes missing point {point}")))?;
+            let [red, green, blue] = colors
+                .as_ref()
+                .and_then(|colors| colors.sample(point, face, corner))
+                .unwrap_or([1.0; 3]);
+            let [alpha] = opacities
+                .as_ref()
+                .and_then(|opacities| opacities.sample(point, face, corner))
+                .unwrap_or([1.0]);
+            vertices.push(Vertex {
+                position: position.into(),
+                color: Color::new(red, green, blue, alpha).into(),
+            });
+            if let Some(normals) = &normals {
+                let normal = normals.sample(point, face, corner).unwrap_or_default();
+                vertex_normals.push(Vec3::from(normal).normalize_or_zero());
+            }
+            if let Some(tex_coords) = &tex_coords {
+                let [u, v] = tex_coords.sample(point, face, corner).unwrap_or_default();
+                vertex_tex_coords.push(Vec2::new(u, 1.0 - v));
+            }
+            corner += 1;
+        }
+        for index in 1..count.saturating_sub(1) as u32 {
+            let (second, third) = if left_handed {
+                (index + 1, index)
+            } else {
+                (index, index + 1)
+            };
+            triangles.extend([first, first + second, first + third]);
+        }
+    }
+
+    let mut mesh = MeshBuilder::new(vertices, PrimitiveType::Triangle).with_indices(triangles);
+    if normals.is_some() {
+        mesh = mesh.with_normals(&vertex_normals);
+    }
+    if tex_coords.is_some() {
+        mesh = mesh.with_tex_coords(&vertex_tex_coords);
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, Parser, PrimSpec, UsdScene, Value};
+    use crate::renderer::{
+        error::ImportError, import_settings::UpAxis, vertex_layout::VertexSemantic,
+    };
+    use glam::{Mat4, Vec3};
+    use std::io::{Cursor, Write};
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    const LAYER: &str = r#"#usda 1.0
+(
+    """A quad on a rotated, scaled node."""
+    upAxis = "Z"
+    metersPerUnit = 0.01
+)
+
+class "Template" {}
+
+def Xform "Root" (
+    kind = "component"
+) {
+    double3 xformOp:translate = (1, 2, 3)
+    float xformOp:rotateZ = 90
+    float3 xformOp:scale = (2, 2, 2)
+    uniform token[] xformOpOrder = ["xformOp:translate", "xformOp:rotateZ", "xformOp:scale"]
+
+    def Mesh "Quad" {
+        point3f[] points = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0)]
+        int[] faceVertexCounts = [4]
+        int[] faceVertexIndices = [0, 1, 2, 3]
+        texCoord2f[] primvars:st = [(0, 0), (1, 1)] (
+            interpolation = "faceVarying"
+        )
+        int[] primvars:st:indices = [0, 1, 1, 0]
+        color3f[] primvars:displayColor = [(1, 0, 0)]
+        rel material:binding = </Materials/Red>
+    }
+
+    def Material "Red" {}
+}
+"#;
+
+    #[test]
+    fn test_parse_usda_scene() {
+        let scene = UsdScene::parse_usda(LAYER.as_bytes()).unwrap();
+        assert_eq!(scene.settings.up_axis, UpAxis::Z);
+        assert_eq!(scene.settings.scale, 0.01);
+
+        let names: Vec<_> = scene.prims.iter().map(|prim| prim.name.as_str()).collect();
+        assert_eq!(names, ["Root", "Quad"]);
+        assert_eq!(scene.prims[1].parent, Some(0));
+        assert_eq!(scene.prims[1].mesh, Some(0));
+        // Scaled, then rotated, then translated
+        let point = scene.prims[0]
+            .transform
+            .transform_point3(Vec3::new(1.0, 0.0, 0.0));
+        assert!(point.abs_diff_eq(Vec3::new(1.0, 4.0, 3.0), 1e-5));
+
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.data.vertices.len(), 4);
+        assert_eq!(mesh.data.indices, Some(vec![0, 1, 2, 0, 2, 3]));
+        assert_eq!(mesh.data.vertices[0].color, [1.0, 0.0, 0.0, 1.0]);
+        let tex_coords = mesh
+            .streams
+            .iter()
+            .find(|stream| stream.semantic == VertexSemantic::TexCoord)
+            .unwrap();
+        assert_eq!(tex_coords.values[1][..2], [1.0, 0.0]);
+        assert_eq!(tex_coords.values[3][..2], [0.0, 1.0]);
+        assert_eq!(Mat4::IDENTITY, scene.prims[1].transform);
+    }
+
+    /// Packages a root layer, and a texture after it, as a USDZ package.
+    fn usdz(layer: &[u8], name: &str, compression: CompressionMethod) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(compression);
+        writer.start_file(name, options).unwrap();
+        writer.write_all(layer).unwrap();
+        writer.start_file("texture.png", options).unwrap();
+        writer.write_all(b"not a layer").unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_parse_usdz_package() {
+        for compression in [CompressionMethod::Stored, CompressionMethod::Deflated] {
+            let package = usdz(LAYER.as_bytes(), "scene.usda", compression);
+            let scene = UsdScene::parse_usdz(&package).unwrap();
+            assert_eq!(scene.prims.len(), 2);
+            assert_eq!(scene.settings.up_axis, UpAxis::Z);
+        }
+
+        // Binary crate layers are recognized but can't be read
+        let mut crate_layer = b"PXR-USDC".to_vec();
+        crate_layer.extend([0; 16]);
+        let package = usdz(&crate_layer, "scene.usda", CompressionMethod::Deflated);
+        assert!(matches!(
+            UsdScene::parse_usdz(&package),
+            Err(ImportError::Unsupported(_))
+        ));
+        let package = usdz(LAYER.as_bytes(), "scene.usdc", CompressionMethod::Stored);
+        assert!(matches!(
+            UsdScene::parse_usdz(&package),
+            Err(ImportError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_usdz_errors() {
+        let mut package = usdz(LAYER.as_bytes(), "scene.usda", CompressionMethod::Deflated);
+        assert!(matches!(
+            UsdScene::parse_usdz(LAYER.as_bytes()),
+            Err(ImportError::Unsupported(_))
+        ));
+        // Without its central directory, the package can't be opened
+        package.truncate(40);
+        assert!(matches!(
+            UsdScene::parse_usdz(&package),
+            Err(ImportError::Malformed(_))
+        ));
+
+        let package = usdz(
+            b"def Xform \"A\" {}",
+            "scene.usda",
+            CompressionMethod::Stored,
+        );
+        assert!(matches!(
+            UsdScene::parse_usdz(&package),
+            Err(ImportError::Unsupported(_))
+        ));
+        let package = usdz(
+            b"#usda 1.0\ndef Xform \"A\" {",
+            "scene.usda",
+            CompressionMethod::Stored,
+        );
+        assert!(matches!(
+            UsdScene::parse_usdz(&package),
+            Err(ImportError::Malformed(_))
+        ));
+    }
+
+    /// Reads a value from a layer fragment.
+    fn value(text: &str) -> Result<Value, ImportError> {
+        Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        }
+        .value()
+    }
+
+    /// Reads a prim from a layer fragment.
+    fn prim(text: &str) -> Result<PrimSpec, ImportError> {
+        Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        }
+        .prim()
+    }
+
+    #[test]
+    fn test_parse_value_errors() {
+        assert_eq!(
+            value("[(1, 2), 3,]").unwrap().numbers(),
+            Some(vec![1.0, 2.0, 3.0])
+        );
+        assert!(matches!(value("{ 1: 2 }"), Ok(Value::Other)));
+        for text in ["[1, 2", "[1 2]", "(1, ]", "= 1", "", "\"open", "@asset.png"] {
+            assert!(
+                matches!(value(text), Err(ImportError::Malformed(_))),
+                "{text:?} should be malformed"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_prim_errors() {
+        let spec = prim("over \"A\" { def \"B\" {} }").unwrap();
+        assert_eq!((spec.specifier.as_str(), spec.children.len()), ("over", 1));
+        for text in [
+            "Xform \"A\" {}",
+            "def Xform {}",
+            "def Xform \"A\"",
+            "def Xform \"A\" (kind = ) {}",
+            "def Xform \"A\" { float size = }",
+            "def Xform \"A\" { def Mesh \"B\" {}",
+        ] {
+            assert!(
+                matches!(prim(text), Err(ImportError::Malformed(_))),
+                "{text:?} should be malformed"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_mesh_errors() {
+        let layer = |attributes: &str| format!("#usda 1.0\ndef Mesh \"M\" {{\n{attributes}\n}}");
+        let error = |attributes: &str| match UsdScene::parse_usda(layer(attributes).as_bytes()) {
+            Err(ImportError::Malformed(message)) => message,
+            result => panic!("expected a malformed mesh, got {:?}", result.map(|_| ())),
+        };
+        assert_eq!(
+            error("int[] faceVertexCounts = [3]\nint[] faceVertexIndices = [0, 1, 2]"),
+            "mesh \"M\" has faces but no points"
+        );
+        assert_eq!(
+            error("point3f[] points = [(0, 0, 0)]\nint[] faceVertexIndices = [0, 0, 0]"),
+            "mesh \"M\" has faces but no faceVertexCounts"
+        );
+        let point = "point3f[] points = [(0, 0, 0)]";
+        assert_eq!(
+            error(&format!(
+                "{point}\nint[] faceVertexCounts = [3]\nint[] faceVertexIndices = [0, 1, 2]"
+            )),
+            "mesh \"M\" indexes missing point 1"
+        );
+        assert_eq!(
+            error(&format!(
+                "{point}\nint[] faceVertexCounts = [4]\nint[] faceVertexIndices = [0]"
+            )),
+            "mesh \"M\" has face counts that don't match its indices"
+        );
+
+        // A mesh without faces is empty rather than malformed
+        let scene = UsdScene::parse_usda(layer("").as_bytes()).unwrap();
+        assert!(scene.meshes[0].data.vertices.is_empty());
+    }
+}
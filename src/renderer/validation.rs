@@ -0,0 +1,336 @@
+//! Draw validation module for the renderer.
+//!
+//! This module checks draws before they are submitted to the backend, so that
+//! malformed geometry, broken transforms and dangling handles are reported
+//! with the offending draw, scene node, mesh and material instead of failing
+//! silently or crashing inside Metal.
+//!
+//! Issues are either fatal, in which case the renderer skips the draw, or
+//! warnings, which are reported but still drawn.
+
+use super::{
+    common::PrimitiveType,
+    material_manager::MaterialId,
+    render_queue::{DrawItem, GeometryHandle, GeometryView},
+    scene_graph::NodeId,
+};
+use glam::{Mat4, Vec3};
+use std::fmt;
+
+/// Matrices with a smaller absolute determinant are treated as singular.
+const SINGULAR_DETERMINANT: f32 = 1e-12;
+
+/// A problem found in a draw.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationIssue {
+    /// The mesh handle does not refer to a mesh in storage.
+    MissingMesh(usize),
+    /// The transient geometry handle does not refer to geometry in the queue.
+    MissingTransientGeometry(u32),
+    /// The material handle does not refer to a registered material.
+    MissingMaterial(MaterialId),
+    /// An index refers to a vertex past the end of the vertex buffer.
+    IndexOutOfRange {
+        position: usize,
+        index: u32,
+        vertex_count: usize,
+    },
+    /// The vertex or index count is not a whole number of primitives.
+    IncompletePrimitive {
+        count: usize,
+        primitive_type: PrimitiveType,
+    },
+    /// Triangles with zero area, which rasterize to nothing.
+    DegenerateTriangles { count: usize, first: usize },
+    /// A transform contains NaN or infinite values.
+    NonFiniteTransform { instance: Option<usize> },
+    /// A transform cannot be inverted, e.g. because of a zero scale.
+    SingularTransform { instance: Option<usize> },
+}
+
+impl ValidationIssue {
+    /// Returns `true` if the draw must be skipped.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            ValidationIssue::IncompletePrimitive { .. }
+                | ValidationIssue::DegenerateTriangles { .. }
+                | ValidationIssue::SingularTransform { .. }
+        )
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let transform_name = |instance: &Option<usize>| match instance {
+            Some(instance) => format!("instance {instance} transform"),
+            None => "transform".to_string(),
+        };
+        match self {
+            ValidationIssue::MissingMesh(id) => write!(f, "mesh {id} does not exist"),
+            ValidationIssue::MissingTransientGeometry(id) => {
+                write!(f, "transient geometry {id} does not exist")
+            }
+            ValidationIssue::MissingMaterial(id) => {
+                write!(f, "material {} does not exist", id.0)
+            }
+            ValidationIssue::IndexOutOfRange {
+                position,
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "index {index} at position {position} is out of range for {vertex_count} vertices"
+            ),
+            ValidationIssue::IncompletePrimitive {
+                count,
+                primitive_type,
+            } => write!(
+                f,
+                "{count} vertices do not form whole {primitive_type:?} primitives"
+            ),
+            ValidationIssue::DegenerateTriangles { count, first } => {
+                write!(
+                    f,
+                    "{count} zero-area triangles, the first at triangle {first}"
+                )
+            }
+            ValidationIssue::NonFiniteTransform { instance } => {
+                write!(
+                    f,
+                    "{} contains NaN or infinite values",
+                    transform_name(instance)
+                )
+            }
+            ValidationIssue::SingularTransform { instance } => {
+                write!(f, "{} is singular", transform_name(instance))
+            }
+        }
+    }
+}
+
+/// A validation issue together with the draw it was found in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    /// The position of the draw in the frame's draw order.
+    pub draw_index: usize,
+    pub node: Option<NodeId>,
+    pub geometry: GeometryHandle,
+    pub material_id: MaterialId,
+    pub issue: ValidationIssue,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Draw {} (", self.draw_index)?;
+        if let Some(node) = self.node {
+            write!(f, "node {}, ", node.0)?;
+        }
+        match self.geometry {
+            GeometryHandle::Mesh(id) => write!(f, "mesh {id}")?,
+            GeometryHandle::Transient(id) => write!(f, "transient geometry {id}")?,
+        }
+        write!(f, ", material {}): {}", self.material_id.0, self.issue)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Checks a draw for problems before it is submitted.
+///
+/// # Arguments
+///
+/// * `item` - The draw to check.
+/// * `geometry` - The resolved geometry of the draw, or `None` if its handle is dangling.
+/// * `material_exists` - Whether the draw's material is registered.
+///
+/// # Returns
+///
+/// Every issue found, fatal ones included.
+pub fn validate_draw(
+    item: &DrawItem,
+    geometry: Option<&GeometryView>,
+    material_exists: bool,
+) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    match geometry {
+        Some(geometry) => validate_geometry(geometry, &mut issues),
+        None => issues.push(match item.geometry {
+            GeometryHandle::Mesh(id) => ValidationIssue::MissingMesh(id),
+            GeometryHandle::Transient(id) => ValidationIssue::MissingTransientGeometry(id),
+        }),
+    }
+    if !material_exists {
+        issues.push(ValidationIssue::MissingMaterial(item.material_id));
+    }
+
+    validate_transform(item.transform, None, &mut issues);
+    for (instance, data) in item.instances.unwrap_or_default().iter().enumerate() {
+        validate_transform(&data.model_matrix, Some(instance), &mut issues);
+    }
+
+    issues
+}
+
+fn validate_geometry(geometry: &GeometryView, issues: &mut Vec<ValidationIssue>) {
+    let vertex_count = geometry.vertices.len();
+    if let Some(indices) = geometry.indices {
+        if let Some((position, &index)) = indices
+            .iter()
+            .enumerate()
+            .find(|(_, &index)| index as usize >= vertex_count)
+        {
+            issues.push(ValidationIssue::IndexOutOfRange {
+                position,
+                index,
+                vertex_count,
+            });
+            return;
+        }
+    }
+
+    let count = geometry
+        .indices
+        .map_or(vertex_count, |indices| indices.len());
+    let incomplete = match geometry.primitive_type {
+        PrimitiveType::Triangle => !count.is_multiple_of(3),
+        PrimitiveType::Line => !count.is_multiple_of(2),
+        _ => false,
+    };
+    if incomplete {
+        issues.push(ValidationIssue::IncompletePrimitive {
+            count,
+            primitive_type: geometry.primitive_type,
+        });
+    }
+
+    if geometry.primitive_type == PrimitiveType::Triangle {
+        let index = |i: usize| geometry.indices.map_or(i, |indices| indices[i] as usize);
+        let position = |i: usize| Vec3::from(geometry.vertices[index(i)].position);
+        let mut degenerate = (0..count / 3).filter(|&triangle| {
+            let (a, b, c) = (
+                position(3 * triangle),
+                position(3 * triangle + 1),
+                position(3 * triangle + 2),
+            );
+            (b - a).cross(c - a).length_squared() == 0.0
+        });
+        if let Some(first) = degenerate.next() {
+            issues.push(ValidationIssue::DegenerateTriangles {
+                count: 1 + degenerate.count(),
+                first,
+            });
+        }
+    }
+}
+
+fn validate_transform(matrix: &Mat4, instance: Option<usize>, issues: &mut Vec<ValidationIssue>) {
+    if !matrix.is_finite() {
+        issues.push(ValidationIssue::NonFiniteTransform { instance });
+    } else if matrix.determinant().abs() < SINGULAR_DETERMINANT {
+        issues.push(ValidationIssue::SingularTransform { instance });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_draw, ValidationError, ValidationIssue};
+    use crate::renderer::{
+        common::{PrimitiveType, Vertex},
+        material_manager::MaterialId,
+        render_queue::{DrawCommandBuilder, GeometryHandle, RenderQueue},
+        scene_graph::NodeId,
+    };
+    use glam::{Mat4, Vec3};
+
+    fn triangle() -> Vec<Vertex> {
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+            .into_iter()
+            .map(|position| Vertex {
+                position,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_geometry() {
+        let vertices = triangle();
+        let out_of_range = [0, 1, 3];
+        let degenerate = [0, 1, 1, 0, 1, 2];
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(DrawCommandBuilder::new_primitive(
+            &vertices,
+            Some(&out_of_range),
+            PrimitiveType::Triangle,
+        ));
+        queue.add_draw_command(DrawCommandBuilder::new_primitive(
+            &vertices,
+            Some(&degenerate),
+            PrimitiveType::Triangle,
+        ));
+
+        let item = queue.draw_item(0);
+        let issues = validate_draw(&item, queue.transient_geometry(0).as_ref(), true);
+        assert_eq!(
+            issues,
+            vec![ValidationIssue::IndexOutOfRange {
+                position: 2,
+                index: 3,
+                vertex_count: 3,
+            }]
+        );
+        assert!(issues[0].is_fatal());
+
+        let item = queue.draw_item(1);
+        let issues = validate_draw(&item, queue.transient_geometry(1).as_ref(), true);
+        assert_eq!(
+            issues,
+            vec![ValidationIssue::DegenerateTriangles { count: 1, first: 0 }]
+        );
+        assert!(!issues[0].is_fatal());
+    }
+
+    #[test]
+    fn test_validate_handles_and_transforms() {
+        let mut queue = RenderQueue::new();
+        queue.add_draw_command(
+            DrawCommandBuilder::new_mesh(7)
+                .with_transform(Mat4::from_scale(Vec3::new(1.0, 0.0, 1.0)))
+                .with_material(MaterialId(3))
+                .with_node(NodeId(2)),
+        );
+        queue.add_draw_command(
+            DrawCommandBuilder::new_mesh(7).with_transform(Mat4::from_translation(Vec3::NAN)),
+        );
+
+        let item = queue.draw_item(0);
+        let issues = validate_draw(&item, None, false);
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::MissingMesh(7),
+                ValidationIssue::MissingMaterial(MaterialId(3)),
+                ValidationIssue::SingularTransform { instance: None },
+            ]
+        );
+
+        let error = ValidationError {
+            draw_index: 0,
+            node: item.node,
+            geometry: item.geometry,
+            material_id: item.material_id,
+            issue: issues[0].clone(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Draw 0 (node 2, mesh 7, material 3): mesh 7 does not exist"
+        );
+        assert_eq!(error.geometry, GeometryHandle::Mesh(7));
+
+        let item = queue.draw_item(1);
+        let issues = validate_draw(&item, None, true);
+        assert!(issues.contains(&ValidationIssue::NonFiniteTransform { instance: None }));
+    }
+}
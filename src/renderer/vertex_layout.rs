@@ -0,0 +1,571 @@
+//! Vertex layout module for the renderer.
+//!
+//! This module provides `VertexLayout`, which describes the attributes of a
+//! mesh's vertices and how they are interleaved in its vertex buffer. A
+//! layout packs a mesh's attribute streams into bytes, and the backend builds
+//! the pipeline's vertex descriptor from the same layout, so meshes only pay
+//! for the attributes they use. The default layout matches `Vertex`.
+//!
+//! Every semantic is read from a fixed shader attribute index: positions
+//! from 0, colors from 1, normals from 2, texture coordinates from 3, and
+//! custom attribute `n` from `4 + n`. The default shaders read positions,
+//! colors, normals and texture coordinates, and substitute white, zero and
+//! zero when a layout has no colors, normals or texture coordinates. Custom
+//! attributes are only read by custom shaders.
+
+use super::common::Vertex;
+use log::warn;
+
+/// The number of custom attributes a layout can hold.
+pub const MAX_CUSTOM_ATTRIBUTES: u8 = 4;
+
+/// The number of shader attribute indices a layout can use.
+const MAX_ATTRIBUTES: usize = 4 + MAX_CUSTOM_ATTRIBUTES as usize;
+
+/// What a vertex attribute holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexSemantic {
+    Position,
+    Color,
+    Normal,
+    TexCoord,
+    /// An attribute only custom shaders read, up to `MAX_CUSTOM_ATTRIBUTES`.
+    Custom(u8),
+}
+
+impl VertexSemantic {
+    /// Returns the shader attribute index the semantic is read from.
+    pub fn attribute_index(self) -> usize {
+        match self {
+            VertexSemantic::Position => 0,
+            VertexSemantic::Color => 1,
+            VertexSemantic::Normal => 2,
+            VertexSemantic::TexCoord => 3,
+            VertexSemantic::Custom(index) => 4 + index as usize,
+        }
+    }
+
+    fn from_attribute_index(index: usize) -> Self {
+        match index {
+            0 => VertexSemantic::Position,
+            1 => VertexSemantic::Color,
+            2 => VertexSemantic::Normal,
+            3 => VertexSemantic::TexCoord,
+            _ => VertexSemantic::Custom((index - 4) as u8),
+        }
+    }
+
+    /// The value of the attribute for vertices without a stream for it.
+    pub fn default_value(self) -> [f32; 4] {
+        match self {
+            VertexSemantic::Color => [1.0; 4],
+            _ => [0.0; 4],
+        }
+    }
+}
+
+/// The format of a vertex attribute in the vertex buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexFormat {
+    Float,
+    Float2,
+    Float3,
+    Float4,
+    /// Four bytes, each mapping [0, 255] to [0, 1], e.g. for compact colors.
+    UChar4Normalized,
+    /// Four signed bytes, each mapping [-127, 127] to [-1, 1], e.g. for compact normals.
+    Char4Normalized,
+    /// Two half-precision floats, e.g. for compact texture coordinates.
+    Half2,
+    /// Four half-precision floats.
+    Half4,
+}
+
+impl VertexFormat {
+    /// Returns the size of the attribute in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            VertexFormat::Float => 4,
+            VertexFormat::Float2 => 8,
+            VertexFormat::Float3 => 12,
+            VertexFormat::Float4 => 16,
+            VertexFormat::UChar4Normalized | VertexFormat::Char4Normalized => 4,
+            VertexFormat::Half2 => 4,
+            VertexFormat::Half4 => 8,
+        }
+    }
+
+    /// Returns the compact format an attribute of a semantic is quantized to.
+    ///
+    /// Colors are clamped to [0, 1] and normals to [-1, 1], so HDR colors and
+    /// unnormalized normals should not be quantized. Positions and custom
+    /// attributes keep their format.
+    fn quantized(self, semantic: VertexSemantic) -> Self {
+        match (semantic, self) {
+            (VertexSemantic::Color, VertexFormat::Float4 | VertexFormat::Half4) => {
+                VertexFormat::UChar4Normalized
+            }
+            (VertexSemantic::Normal, VertexFormat::Float3 | VertexFormat::Float4) => {
+                VertexFormat::Char4Normalized
+            }
+            (VertexSemantic::TexCoord, VertexFormat::Float2) => VertexFormat::Half2,
+            _ => self,
+        }
+    }
+
+    /// Writes the leading components of a value in this format.
+    fn write(self, value: [f32; 4], bytes: &mut [u8]) {
+        match self {
+            VertexFormat::UChar4Normalized => {
+                for (byte, component) in bytes.iter_mut().zip(value) {
+                    *byte = (component.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+            VertexFormat::Char4Normalized => {
+                for (byte, component) in bytes.iter_mut().zip(value) {
+                    *byte = (component.clamp(-1.0, 1.0) * 127.0).round() as i8 as u8;
+                }
+            }
+            VertexFormat::Half2 | VertexFormat::Half4 => {
+                for (chunk, component) in bytes.chunks_exact_mut(2).zip(value) {
+                    chunk.copy_from_slice(&f32_to_f16(component).to_ne_bytes());
+                }
+            }
+            _ => {
+                for (chunk, component) in bytes.chunks_exact_mut(4).zip(value) {
+                    chunk.copy_from_slice(&component.to_ne_bytes());
+                }
+            }
+        }
+    }
+
+    /// Reads a value written in this format, with the components it doesn't hold zero.
+    fn read(self, bytes: &[u8]) -> [f32; 4] {
+        let mut value = [0.0; 4];
+        match self {
+            VertexFormat::UChar4Normalized => {
+                for (component, &byte) in value.iter_mut().zip(bytes) {
+                    *component = byte as f32 / 255.0;
+                }
+            }
+            VertexFormat::Char4Normalized => {
+                for (component, &byte) in value.iter_mut().zip(bytes) {
+                    *component = (byte as i8 as f32 / 127.0).max(-1.0);
+                }
+            }
+            VertexFormat::Half2 | VertexFormat::Half4 => {
+                for (component, chunk) in value.iter_mut().zip(bytes.chunks_exact(2)) {
+                    *component = f16_to_f32(u16::from_ne_bytes([chunk[0], chunk[1]]));
+                }
+            }
+            _ => {
+                for (component, chunk) in value.iter_mut().zip(bytes.chunks_exact(4)) {
+                    *component = f32::from_ne_bytes(chunk.try_into().unwrap());
+                }
+            }
+        }
+        value
+    }
+}
+
+/// The format and byte offset of an attribute within a vertex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexAttribute {
+    pub format: VertexFormat,
+    pub offset: usize,
+}
+
+/// The values of one attribute for every vertex of a mesh.
+///
+/// Values have up to four components; the ones a format does not hold are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexStream {
+    pub semantic: VertexSemantic,
+    pub values: Vec<[f32; 4]>,
+}
+
+/// The attributes of a vertex and how they are interleaved.
+///
+/// Layouts are small and `Copy`, so they can be compared and hashed per draw
+/// to select a pipeline without allocating.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    /// The attributes by shader attribute index.
+    attributes: [Option<VertexAttribute>; MAX_ATTRIBUTES],
+    stride: usize,
+}
+
+impl Default for VertexLayout {
+    /// The layout of `Vertex`: a position followed by a color.
+    fn default() -> Self {
+        Self::position_color()
+    }
+}
+
+impl VertexLayout {
+    /// Creates a new `VertexLayout` holding only a `Float3` position.
+    pub fn new() -> Self {
+        let mut attributes = [None; MAX_ATTRIBUTES];
+        attributes[0] = Some(VertexAttribute {
+            format: VertexFormat::Float3,
+            offset: 0,
+        });
+        Self {
+            attributes,
+            stride: VertexFormat::Float3.size(),
+        }
+    }
+
+    /// The layout of vertices with only a position, e.g. for depth-only geometry.
+    pub fn position() -> Self {
+        Self::new()
+    }
+
+    /// The layout of `Vertex`: a position followed by a `Float4` color.
+    pub fn position_color() -> Self {
+        Self::new().with_attribute(VertexSemantic::Color, VertexFormat::Float4)
+    }
+
+    /// The layout of lit, textured vertices: a position, a normal and texture coordinates.
+    pub fn position_normal_uv() -> Self {
+        Self::new()
+            .with_attribute(VertexSemantic::Normal, VertexFormat::Float3)
+            .with_attribute(VertexSemantic::TexCoord, VertexFormat::Float2)
+    }
+
+    /// Appends an attribute after the existing ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout already has the semantic, or the semantic is a
+    /// custom attribute beyond `MAX_CUSTOM_ATTRIBUTES`.
+    pub fn with_attribute(mut self, semantic: VertexSemantic, format: VertexFormat) -> Self {
+        let index = semantic.attribute_index();
+        assert!(
+            index < MAX_ATTRIBUTES,
+            "{semantic:?} exceeds the {MAX_CUSTOM_ATTRIBUTES} custom attributes"
+        );
+        assert!(
+            self.attributes[index].is_none(),
+            "Vertex layout already has a {semantic:?} attribute"
+        );
+        self.attributes[index] = Some(VertexAttribute {
+            format,
+            offset: self.stride,
+        });
+        self.stride += format.size();
+        self
+    }
+
+    /// Returns the layout with colors, normals and texture coordinates in compact formats.
+    ///
+    /// Colors become `UChar4Normalized`, normals `Char4Normalized` and
+    /// texture coordinates `Half2`, shrinking the default layout from 28 to
+    /// 16 bytes per vertex. Positions keep full precision.
+    pub fn quantized(&self) -> Self {
+        self.attributes()
+            .filter(|(semantic, _)| *semantic != VertexSemantic::Position)
+            .fold(VertexLayout::new(), |layout, (semantic, attribute)| {
+                layout.with_attribute(semantic, attribute.format.quantized(semantic))
+            })
+    }
+
+    /// Returns the attribute of a semantic, if the layout has it.
+    pub fn attribute(&self, semantic: VertexSemantic) -> Option<VertexAttribute> {
+        self.attributes
+            .get(semantic.attribute_index())
+            .copied()
+            .flatten()
+    }
+
+    /// Returns `true` if the layout has an attribute for the semantic.
+    pub fn has(&self, semantic: VertexSemantic) -> bool {
+        self.attribute(semantic).is_some()
+    }
+
+    /// Returns every attribute with its semantic, in shader attribute order.
+    pub fn attributes(&self) -> impl Iterator<Item = (VertexSemantic, VertexAttribute)> + '_ {
+        self.attributes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, attribute)| {
+                attribute.map(|attribute| (VertexSemantic::from_attribute_index(index), attribute))
+            })
+    }
+
+    /// Returns the size of one vertex in bytes.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Interleaves vertices and attribute streams into a vertex buffer of this layout.
+    ///
+    /// Positions and colors come from `vertices`, unless a stream provides
+    /// them. Attributes without a stream, and streams shorter than
+    /// `vertices`, are filled with white colors and zeros otherwise; both
+    /// are logged, as they usually mean the layout and streams disagree.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertices` - The mesh's vertices, giving the vertex count.
+    /// * `streams` - The values of attributes other than position and color.
+    pub fn pack(&self, vertices: &[Vertex], streams: &[VertexStream]) -> Vec<u8> {
+        let mut bytes = vec![0; vertices.len() * self.stride];
+        for (semantic, attribute) in self.attributes() {
+            let stream = streams.iter().find(|stream| stream.semantic == semantic);
+            match (stream, semantic) {
+                (Some(stream), _) if stream.values.len() < vertices.len() => warn!(
+                    "{semantic:?} stream has {} values for {} vertices",
+                    stream.values.len(),
+                    vertices.len()
+                ),
+                (None, VertexSemantic::Position | VertexSemantic::Color) => {}
+                (None, _) => warn!("Vertex layout has {semantic:?}, but no stream provides it"),
+                _ => {}
+            }
+
+            let size = attribute.format.size();
+            for (index, vertex) in vertices.iter().enumerate() {
+                let value = match (stream, semantic) {
+                    (Some(stream), _) => stream
+                        .values
+                        .get(index)
+                        .copied()
+                        .unwrap_or(semantic.default_value()),
+                    (None, VertexSemantic::Position) => {
+                        let [x, y, z] = vertex.position;
+                        [x, y, z, 1.0]
+                    }
+                    (None, VertexSemantic::Color) => vertex.color,
+                    (None, _) => semantic.default_value(),
+                };
+                let start = index * self.stride + attribute.offset;
+                attribute
+                    .format
+                    .write(value, &mut bytes[start..start + size]);
+            }
+        }
+        bytes
+    }
+}
+
+/// Converts a float to the bits of the nearest half-precision float.
+///
+/// Values beyond the half range become infinite, and values below it zero.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity keeps an empty mantissa, NaN a quiet one
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal halves shift the implicit leading bit into the mantissa
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+    // Rounding up may carry into the exponent, which is the correctly rounded result
+    let round = (mantissa >> 12) & 1;
+    sign | ((((exponent as u32) << 10) | (mantissa >> 13)) + round) as u16
+}
+
+/// Converts the bits of a half-precision float to a float.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Vertices packed into a vertex buffer of a custom layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedVertices {
+    pub layout: VertexLayout,
+    pub data: Vec<u8>,
+}
+
+impl PackedVertices {
+    /// Returns the number of packed vertices.
+    pub fn len(&self) -> usize {
+        self.data.len() / self.layout.stride()
+    }
+
+    /// Returns `true` if no vertices are packed.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Reads back the values of an attribute, e.g. to export the mesh.
+    ///
+    /// Quantized attributes come back at the precision they were stored in.
+    ///
+    /// # Returns
+    ///
+    /// One value per vertex, or `None` if the layout doesn't have the attribute.
+    pub fn unpack(&self, semantic: VertexSemantic) -> Option<Vec<[f32; 4]>> {
+        let attribute = self.layout.attribute(semantic)?;
+        let size = attribute.format.size();
+        Some(
+            self.data
+                .chunks_exact(self.layout.stride())
+                .map(|vertex| {
+                    attribute
+                        .format
+                        .read(&vertex[attribute.offset..attribute.offset + size])
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        f16_to_f32, f32_to_f16, PackedVertices, VertexFormat, VertexLayout, VertexSemantic,
+        VertexStream,
+    };
+    use crate::renderer::common::Vertex;
+
+    #[test]
+    fn test_default_layout_matches_vertex() {
+        let layout = VertexLayout::default();
+        assert_eq!(layout.stride(), std::mem::size_of::<Vertex>());
+        assert_eq!(layout.attribute(VertexSemantic::Color).unwrap().offset, 12);
+
+        let vertices = [Vertex {
+            position: [1.0, 2.0, 3.0],
+            color: [0.1, 0.2, 0.3, 0.4],
+        }];
+        let bytes = layout.pack(&vertices, &[]);
+        let expected = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(&vertices),
+            )
+        };
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn test_custom_layout_packing() {
+        let layout = VertexLayout::new()
+            .with_attribute(VertexSemantic::Color, VertexFormat::UChar4Normalized)
+            .with_attribute(VertexSemantic::TexCoord, VertexFormat::Float2);
+        assert_eq!(layout.stride(), 24);
+        assert!(!layout.has(VertexSemantic::Normal));
+        assert_eq!(
+            layout
+                .attributes()
+                .map(|(semantic, attribute)| (semantic, attribute.offset))
+                .collect::<Vec<_>>(),
+            [
+                (VertexSemantic::Position, 0),
+                (VertexSemantic::Color, 12),
+                (VertexSemantic::TexCoord, 16)
+            ]
+        );
+
+        let vertices = [Vertex {
+            position: [1.0, 2.0, 3.0],
+            color: [1.0, 0.0, 0.5, 1.0],
+        }; 2];
+        let streams = [VertexStream {
+            semantic: VertexSemantic::TexCoord,
+            values: vec![[0.25, 0.75, 0.0, 0.0]],
+        }];
+        let bytes = layout.pack(&vertices, &streams);
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(&bytes[12..16], &[255, 0, 128, 255]);
+        assert_eq!(&bytes[16..20], &0.25f32.to_ne_bytes());
+        assert_eq!(&bytes[20..24], &0.75f32.to_ne_bytes());
+        // The short stream leaves the second vertex at the default
+        assert_eq!(&bytes[40..48], &[0; 8]);
+    }
+
+    #[test]
+    fn test_quantized_layout() {
+        let layout = VertexLayout::position_color()
+            .with_attribute(VertexSemantic::Normal, VertexFormat::Float3)
+            .with_attribute(VertexSemantic::TexCoord, VertexFormat::Float2)
+            .quantized();
+        assert_eq!(layout.stride(), 24);
+        assert_eq!(
+            layout.attribute(VertexSemantic::Position).unwrap().format,
+            VertexFormat::Float3
+        );
+        assert_eq!(
+            layout.attribute(VertexSemantic::Normal).unwrap().format,
+            VertexFormat::Char4Normalized
+        );
+
+        let vertices = [Vertex::default()];
+        let streams = [
+            VertexStream {
+                semantic: VertexSemantic::Normal,
+                values: vec![[0.0, -1.0, 0.5, 0.0]],
+            },
+            VertexStream {
+                semantic: VertexSemantic::TexCoord,
+                values: vec![[0.5, 1.0, 0.0, 0.0]],
+            },
+        ];
+        let bytes = layout.pack(&vertices, &streams);
+        assert_eq!(&bytes[16..20], &[0, (-127i8) as u8, 64, 0]);
+        assert_eq!(&bytes[20..22], &0x3800u16.to_ne_bytes());
+        assert_eq!(&bytes[22..24], &0x3c00u16.to_ne_bytes());
+
+        // Unpacking gives back the values at their stored precision
+        let packed = PackedVertices {
+            layout,
+            data: bytes,
+        };
+        assert_eq!(
+            packed.unpack(VertexSemantic::Normal),
+            Some(vec![[0.0, -1.0, 64.0 / 127.0, 0.0]])
+        );
+        assert_eq!(
+            packed.unpack(VertexSemantic::TexCoord),
+            Some(vec![[0.5, 1.0, 0.0, 0.0]])
+        );
+        assert_eq!(packed.unpack(VertexSemantic::Custom(0)), None);
+    }
+
+    #[test]
+    fn test_f32_to_f16() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        // The smallest subnormal half
+        assert_eq!(f32_to_f16(5.96e-8), 1);
+        for value in [0.0, 1.0, -2.0, 65504.0, 0.333, 2f32.powi(-24)] {
+            let half = f16_to_f32(f32_to_f16(value));
+            assert!((half - value).abs() <= value.abs() / 1024.0);
+        }
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "already has")]
+    fn test_duplicate_attribute_panics() {
+        VertexLayout::new().with_attribute(VertexSemantic::Position, VertexFormat::Float3);
+    }
+}
@@ -0,0 +1,251 @@
+//! Viewport module for the renderer.
+//!
+//! This module provides split-screen views: extra cameras that draw the same
+//! scene into rectangles of the frame beside the main camera, e.g. for
+//! side-by-side comparisons or two players. A `Viewport` is a rectangle in
+//! fractions of the frame, which the backend turns into the viewport and
+//! scissor rectangle of its render target. Every view draws the whole render
+//! queue with its own camera's view projection and cull mask within the same
+//! frame. The sky, atmosphere, lens flares, labels and temporal
+//! anti-aliasing follow the main camera, and lighting uses its position.
+//!
+//! `Views` holds the main camera's viewport alongside the views, keeping
+//! every view camera's aspect ratio in step with its rectangle of the frame.
+
+use super::{camera::Camera, common::LayerMask};
+use glam::{Mat4, UVec2, Vec2};
+
+/// A rectangle of the frame, in fractions of its width and height with y pointing down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl Viewport {
+    /// The whole frame.
+    pub const FULL: Viewport = Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// Creates a new `Viewport` from its top-left corner and size, clamped to the frame.
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        let (x, y) = (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
+        Viewport {
+            x,
+            y,
+            width: width.clamp(0.0, 1.0 - x),
+            height: height.clamp(0.0, 1.0 - y),
+        }
+    }
+
+    /// Returns a cell of the frame split into a grid, counting left to right, then top to bottom.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Side by side
+    /// let (left, right) = (Viewport::grid(2, 1, 0), Viewport::grid(2, 1, 1));
+    /// ```
+    pub fn grid(columns: u32, rows: u32, index: u32) -> Self {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        let (width, height) = (1.0 / columns as f32, 1.0 / rows as f32);
+        Self::new(
+            (index % columns) as f32 * width,
+            (index / columns % rows) as f32 * height,
+            width,
+            height,
+        )
+    }
+
+    /// Returns the top-left corner and size of the rectangle in pixels of a frame.
+    ///
+    /// Edges are rounded to whole pixels, so adjacent viewports share their
+    /// edges without gaps or overlaps.
+    pub fn to_pixels(&self, frame_size: UVec2) -> (UVec2, UVec2) {
+        let frame_size = frame_size.as_vec2();
+        let min = (Vec2::new(self.x, self.y) * frame_size).round();
+        let max = (Vec2::new(self.x + self.width, self.y + self.height) * frame_size)
+            .round()
+            .min(frame_size);
+        (min.as_uvec2(), (max - min).max(Vec2::ZERO).as_uvec2())
+    }
+
+    /// Returns the aspect ratio of the rectangle in a frame of the given size.
+    pub fn aspect_ratio(&self, frame_size: UVec2) -> f32 {
+        let size = self.to_pixels(frame_size).1.max(UVec2::ONE).as_vec2();
+        size.x / size.y
+    }
+}
+
+/// Identifies a view added to the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ViewId(pub usize);
+
+/// A camera drawing the scene into a rectangle of the frame.
+pub struct View {
+    pub camera: Camera,
+    pub viewport: Viewport,
+}
+
+impl View {
+    /// Creates a new `View`, giving the camera the rectangle's aspect ratio.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The camera the view draws from.
+    /// * `viewport` - The rectangle of the frame the view draws into.
+    /// * `frame_size` - The size of the frame in pixels.
+    pub fn new(mut camera: Camera, viewport: Viewport, frame_size: UVec2) -> Self {
+        camera.set_aspect_ratio(viewport.aspect_ratio(frame_size));
+        View { camera, viewport }
+    }
+}
+
+/// The split-screen views of a renderer, indexed by `ViewId`, and the
+/// rectangle of the frame the main camera draws into.
+#[derive(Default)]
+pub struct Views {
+    views: Vec<Option<View>>,
+    main: Viewport,
+}
+
+impl Views {
+    /// Returns the rectangle of the frame the main camera draws into.
+    pub fn main_viewport(&self) -> Viewport {
+        self.main
+    }
+
+    /// Sets the rectangle of the frame the main camera draws into.
+    pub fn set_main_viewport(&mut self, viewport: Viewport) {
+        self.main = viewport;
+    }
+
+    /// Returns `true` if the frame is split, so the main camera doesn't
+    /// draw into the whole frame alone.
+    pub fn is_split(&self) -> bool {
+        self.main != Viewport::FULL || !self.is_empty()
+    }
+
+    /// Returns the rectangle, view projection matrix and cull mask of every
+    /// view, in the order they are drawn after the main camera.
+    pub fn passes(&self) -> Vec<(Viewport, Mat4, LayerMask)> {
+        self.iter()
+            .map(|view| {
+                let camera = &view.camera;
+                let view_projection = camera.get_projection_matrix() * camera.get_view_matrix();
+                (view.viewport, view_projection, camera.cull_mask())
+            })
+            .collect()
+    }
+
+    /// Gives every view's camera the aspect ratio of its rectangle in a
+    /// frame of a new size.
+    pub fn resize(&mut self, frame_size: UVec2) {
+        for view in self.iter_mut() {
+            view.camera
+                .set_aspect_ratio(view.viewport.aspect_ratio(frame_size));
+        }
+    }
+
+    /// Adds a view and returns its handle.
+    pub fn add(&mut self, view: View) -> ViewId {
+        self.views.push(Some(view));
+        ViewId(self.views.len() - 1)
+    }
+
+    /// Removes a view, returning `true` if it existed.
+    pub fn remove(&mut self, id: ViewId) -> bool {
+        self.views
+            .get_mut(id.0)
+            .is_some_and(|view| view.take().is_some())
+    }
+
+    /// Returns a view for modification.
+    pub fn get_mut(&mut self, id: ViewId) -> Option<&mut View> {
+        self.views.get_mut(id.0)?.as_mut()
+    }
+
+    /// Returns `true` if there are no views.
+    pub fn is_empty(&self) -> bool {
+        self.views.iter().all(Option::is_none)
+    }
+
+    /// Returns the views in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &View> {
+        self.views.iter().flatten()
+    }
+
+    /// Returns the views for modification, in the order they were added.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut View> {
+        self.views.iter_mut().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{View, Viewport, Views};
+    use crate::renderer::{common::LayerMask, Camera};
+    use glam::{UVec2, Vec3};
+
+    #[test]
+    fn test_viewport_pixels() {
+        let size = UVec2::new(801, 600);
+        let (left, right) = (Viewport::grid(2, 1, 0), Viewport::grid(2, 1, 1));
+        assert_eq!(Viewport::FULL.to_pixels(size), (UVec2::ZERO, size));
+
+        // Halves of an odd width meet without a gap
+        let (left_min, left_size) = left.to_pixels(size);
+        let (right_min, right_size) = right.to_pixels(size);
+        assert_eq!(left_min, UVec2::ZERO);
+        assert_eq!(left_size.x, right_min.x);
+        assert_eq!(left_size.x + right_size.x, size.x);
+        assert!((right.aspect_ratio(size) - 400.0 / 600.0).abs() < 1e-6);
+
+        assert_eq!(
+            Viewport::grid(2, 2, 3).to_pixels(UVec2::new(800, 600)),
+            (UVec2::new(400, 300), UVec2::new(400, 300))
+        );
+        assert_eq!(Viewport::new(0.75, 0.0, 0.5, 2.0).width, 0.25);
+    }
+
+    #[test]
+    fn test_views() {
+        let size = UVec2::new(800, 600);
+        let mut views = Views::default();
+        assert!(!views.is_split());
+        assert!(views.passes().is_empty());
+        views.set_main_viewport(Viewport::grid(2, 1, 0));
+        assert!(views.is_split());
+
+        let mut camera = Camera::new(Vec3::new(0.0, 0.0, 5.0), 45.0, 1.0, 0.1, 100.0);
+        camera.set_cull_mask(LayerMask::SHADOW_CASTER);
+        let id = views.add(View::new(camera, Viewport::grid(2, 1, 1), size));
+        let passes = views.passes();
+        assert_eq!(passes.len(), 1);
+        assert_eq!(passes[0].0, Viewport::grid(2, 1, 1));
+        assert_eq!(passes[0].2, LayerMask::SHADOW_CASTER);
+
+        // Views keep their rectangle's aspect ratio as the frame is resized
+        let camera = &views.get_mut(id).unwrap().camera;
+        assert!((camera.aspect_ratio() - 400.0 / 600.0).abs() < 1e-6);
+        views.resize(UVec2::new(1600, 600));
+        let camera = &views.get_mut(id).unwrap().camera;
+        assert!((camera.aspect_ratio() - 800.0 / 600.0).abs() < 1e-6);
+
+        assert!(views.remove(id));
+        views.set_main_viewport(Viewport::FULL);
+        assert!(!views.is_split());
+    }
+}